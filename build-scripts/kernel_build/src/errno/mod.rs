@@ -0,0 +1,177 @@
+use std::{fs, path::PathBuf};
+
+use crate::utils::cargo_handler::CargoHandler;
+
+/// 错误码的定义所在的文件
+const SYSTEM_ERROR_SRC: &str = "crates/system_error/src/lib.rs";
+/// 内核C代码使用的错误码头文件
+const KERNEL_ERRNO_H: &str = "src/common/errno.h";
+/// 用户态libc使用的错误信息表
+const USER_STRERROR_H: &str = "../user/sysconfig/usr/include/dragonos/strerror.h";
+
+/// 大于等于这个值的错误码只在内核内部使用，不会返回给用户态
+const KERNEL_INTERNAL_ERRNO: i32 = 512;
+
+const GENERATED_NOTICE: &str =
+    "/* 由build-scripts/kernel_build根据kernel/crates/system_error自动生成，请勿手动修改 */\n";
+
+/// `SystemError`中的一个错误码
+#[derive(Debug)]
+struct Errno {
+    /// 错误码的名字。`EAGAIN_OR_EWOULDBLOCK`这样的名字会被拆分为多个
+    names: Vec<String>,
+    value: i32,
+    /// 文档注释的第一行
+    doc: Option<String>,
+}
+
+impl Errno {
+    /// 错误信息，取文档注释中的英文描述，没有英文描述时使用错误码的名字
+    fn message(&self) -> String {
+        let doc = match &self.doc {
+            Some(doc) => doc.as_str(),
+            None => return self.names[0].clone(),
+        };
+        // 文档注释的格式为“中文描述 English description.”
+        let english = match doc.rfind(|c: char| !c.is_ascii()) {
+            Some(pos) => &doc[pos + doc[pos..].chars().next().unwrap().len_utf8()..],
+            None => doc,
+        };
+        // 去掉“(may be the same value as [EAGAIN])”这样的补充说明
+        let english = english.split('(').next().unwrap();
+        let english = english.trim().trim_end_matches('.').trim();
+        if !english.contains(' ') {
+            return self.names[0].clone();
+        }
+        english.to_string()
+    }
+}
+
+/// 根据`SystemError`生成内核和用户态共用的错误码定义
+///
+/// 保证内核的C代码、用户态的`strerror`与内核实际返回的错误码一致
+pub struct ErrnoBuilder;
+
+impl ErrnoBuilder {
+    pub fn build() {
+        let src = PathBuf::from(SYSTEM_ERROR_SRC);
+        CargoHandler::emit_rerun_if_files_changed(&[src.clone()]);
+        let content = fs::read_to_string(&src)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", SYSTEM_ERROR_SRC, e));
+        let errnos = Self::parse(&content);
+
+        Self::write(&PathBuf::from(KERNEL_ERRNO_H), &Self::make_errno_h(&errnos));
+        Self::write(
+            &PathBuf::from(USER_STRERROR_H),
+            &Self::make_strerror_h(&errnos),
+        );
+    }
+
+    /// 解析`enum SystemError`中的每一项
+    fn parse(content: &str) -> Vec<Errno> {
+        let mut errnos = Vec::new();
+        let mut in_enum = false;
+        let mut doc: Option<String> = None;
+        for line in content.lines() {
+            let line = line.trim();
+            if !in_enum {
+                in_enum = line.starts_with("pub enum SystemError");
+                continue;
+            }
+            if line.starts_with('}') {
+                break;
+            }
+
+            if let Some(d) = line.strip_prefix("///") {
+                // 只保留第一行
+                if doc.is_none() && !d.trim().is_empty() {
+                    doc = Some(d.trim().to_string());
+                }
+                continue;
+            }
+            if line.starts_with("//") {
+                continue;
+            }
+
+            let Some((name, value)) = line.trim_end_matches(',').split_once('=') else {
+                continue;
+            };
+            let name = name.trim();
+            let value: i32 = value
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid errno value: {}", line));
+            if name != "MAXERRNO" {
+                errnos.push(Errno {
+                    names: name.split("_OR_").map(String::from).collect(),
+                    value,
+                    doc,
+                });
+            }
+            doc = None;
+        }
+        assert!(!errnos.is_empty(), "No errno found in {}", SYSTEM_ERROR_SRC);
+        errnos
+    }
+
+    /// 生成内核C代码使用的`errno.h`
+    fn make_errno_h(errnos: &[Errno]) -> String {
+        let mut s = String::from(GENERATED_NOTICE);
+        s.push_str("#pragma once\n");
+        for e in errnos {
+            let comment = e
+                .doc
+                .as_ref()
+                .map(|d| format!(" /* {} */", d))
+                .unwrap_or_default();
+            s.push_str(&format!("#define {} {}{}\n", e.names[0], e.value, comment));
+            for alias in &e.names[1..] {
+                s.push_str(&format!("#define {} {}\n", alias, e.names[0]));
+            }
+        }
+        s
+    }
+
+    /// 生成用户态使用的错误信息表
+    fn make_strerror_h(errnos: &[Errno]) -> String {
+        let mut s = String::from(GENERATED_NOTICE);
+        s.push_str("#pragma once\n\n");
+        s.push_str("static const char *const __dragonos_errlist[] = {\n");
+        s.push_str("    [0] = \"Success\",\n");
+        for e in errnos
+            .iter()
+            .filter(|e| e.value > 0 && e.value < KERNEL_INTERNAL_ERRNO)
+        {
+            s.push_str(&format!(
+                "    [{}] = \"{}\", /* {} */\n",
+                e.value,
+                e.message(),
+                e.names.join(", ")
+            ));
+        }
+        s.push_str("};\n\n");
+        s.push_str(
+            "static inline const char *dragonos_strerror(int errnum)\n\
+             {\n\
+             \x20   if (errnum < 0 || errnum >= (int)(sizeof(__dragonos_errlist) / sizeof(__dragonos_errlist[0])) ||\n\
+             \x20       __dragonos_errlist[errnum] == 0)\n\
+             \x20       return \"Unknown error\";\n\
+             \x20   return __dragonos_errlist[errnum];\n\
+             }\n",
+        );
+        s
+    }
+
+    /// 内容有变化时才写入，避免触发不必要的重新编译
+    fn write(path: &PathBuf, content: &str) {
+        if fs::read_to_string(path).is_ok_and(|old| old == content) {
+            return;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .unwrap_or_else(|e| panic!("Failed to create {}: {}", dir.display(), e));
+        }
+        fs::write(path, content)
+            .unwrap_or_else(|e| panic!("Failed to write {}: {}", path.display(), e));
+    }
+}
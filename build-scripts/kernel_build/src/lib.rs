@@ -5,6 +5,7 @@ extern crate cc;
 mod bindgen;
 mod cfiles;
 mod constant;
+mod errno;
 mod kconfig;
 mod utils;
 
@@ -12,6 +13,9 @@ mod utils;
 pub fn run() {
     println!("cargo:rustc-link-search=src");
 
+    // 其他C代码会用到生成的errno.h，因此需要最先生成
+    crate::errno::ErrnoBuilder::build();
+
     crate::bindgen::generate_bindings();
     crate::cfiles::CFilesBuilder::build();
     crate::kconfig::KConfigBuilder::build();
@@ -0,0 +1,43 @@
+//! * Normal little-endian 32-bit CRC calculation.
+//!
+//! Taken from Linux Kernel 6.1.9
+//!
+//! The polynomial is the one used by Ethernet, zlib and the EFI GPT header,
+//!
+//! x^32 + x^26 + x^23 + x^22 + x^16 + x^12 + x^11 + x^10 + x^8 + x^7 + x^5 +
+//! x^4 + x^2 + x + 1
+
+use crate::tables::crc32::CRC32_LE_TABLE;
+
+/// crc32_le - Calculate bitwise little-endian Ethernet AUTODIN II CRC32
+///
+/// ## 参数
+///
+/// - `crc`: seed value for computation. ~0 for Ethernet, sometimes 0 for
+///            other uses, or the previous crc32 value if computing incrementally.
+/// - `buf`: pointer to buffer over which CRC32 is run
+pub fn crc32_le(mut crc: u32, buf: &[u8]) -> u32 {
+    for &byte in buf {
+        crc = (crc >> 8) ^ CRC32_LE_TABLE[((crc as u8) ^ byte) as usize];
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_le_check() {
+        let buf = b"123456789";
+        let crc = crc32_le(!0, buf) ^ !0;
+        assert_eq!(crc, 0xcbf43926);
+    }
+
+    #[test]
+    fn crc32_le_incremental() {
+        let buf = b"0123456789";
+        let crc = crc32_le(crc32_le(!0, &buf[..4]), &buf[4..]);
+        assert_eq!(crc, crc32_le(!0, buf));
+    }
+}
@@ -6,5 +6,6 @@
 #[cfg(test)]
 extern crate std;
 
+pub mod crc32;
 pub mod crc64;
 pub mod tables;
@@ -0,0 +1,25 @@
+/// crc32_le使用的多项式（反转后的0x04C11DB7）
+pub const CRC32_LE_POLY: u32 = 0xEDB88320;
+
+/// crc32_le的查找表，编译期生成
+pub const CRC32_LE_TABLE: [u32; 256] = gen_crc32_le_table();
+
+const fn gen_crc32_le_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32_LE_POLY
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
@@ -1,12 +1,12 @@
 #![no_std]
-#![feature(const_size_of_val)]
 #![allow(clippy::needless_return)]
 
 extern crate alloc;
-use core::{fmt::Debug, mem::size_of_val};
+use core::fmt::Debug;
 
 use alloc::format;
-use kdepends::{memoffset::offset_of, thingbuf::StaticThingBuf};
+use kdepends::memoffset::offset_of;
+use ring_buffer::{record_size, RingMode, StaticRingBuffer, RECORD_ALIGN, RECORD_HEADER_SIZE};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -166,33 +166,21 @@ pub enum LogSource {
     Slab = 3,
 }
 
-pub struct MMLogCycle;
+/// 日志通道中每条记录占用的字节数
+pub const MM_LOG_SLOT_SIZE: usize = record_size(core::mem::size_of::<AllocatorLog>());
 
-impl MMLogCycle {
-    pub const fn new() -> Self {
-        Self {}
-    }
-}
-
-impl Default for MMLogCycle {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl kdepends::thingbuf::Recycle<AllocatorLog> for MMLogCycle {
-    fn new_element(&self) -> AllocatorLog {
-        AllocatorLog::zeroed()
-    }
-
-    fn recycle(&self, element: &mut AllocatorLog) {
-        *element = AllocatorLog::zeroed();
-    }
+/// 容量为`capacity`条日志的通道的数据区长度（以u64为单位），作为[`MMLogChannel`]的泛型参数
+pub const fn mm_log_channel_words(capacity: usize) -> usize {
+    capacity * MM_LOG_SLOT_SIZE / RECORD_ALIGN
 }
 
 /// 内存分配器日志通道
+///
+/// 日志存放在覆盖模式的环形缓冲区中，每条记录的长度相同，因此记录总是位于固定的槽中：
+/// 第i个槽的日志位于`slots_offset + i * slot_size`处，调试器可以直接扫描所有的槽，
+/// 通过校验和过滤掉无效的日志。
 #[repr(C)]
-pub struct MMLogChannel<const CAP: usize> {
+pub struct MMLogChannel<const WORDS: usize> {
     pub magic: u32,
     /// 日志元素的大小
     pub element_size: u32,
@@ -200,49 +188,53 @@ pub struct MMLogChannel<const CAP: usize> {
     pub slot_size: u32,
     pub capacity: u64,
     pub slots_offset: u64,
-    pub buf: StaticThingBuf<AllocatorLog, CAP, MMLogCycle>,
+    pub buf: StaticRingBuffer<WORDS>,
 }
 
-impl<const CAP: usize> Debug for MMLogChannel<CAP> {
+impl<const WORDS: usize> Debug for MMLogChannel<WORDS> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("MMLogChannel")
             .field("magic", &format!("{:#x}", self.magic))
             .field("element_size", &self.element_size)
             .field("capacity", &self.capacity)
             .field("slots_offset", &self.slots_offset)
-            .field(
-                "buf",
-                &format!(
-                    "StaticThingBuf<AllocatorLog, {}, MMLogCycle>",
-                    self.capacity
-                ),
-            )
+            .field("buf", &self.buf)
             .finish()
     }
 }
 
-impl<const CAP: usize> MMLogChannel<CAP> {
+impl<const WORDS: usize> MMLogChannel<WORDS> {
     /// 日志通道的魔数
     pub const MM_LOG_CHANNEL_MAGIC: u32 = 0x4d4c4348;
 
     /// 创建一个大小为`capacity`日志通道
+    ///
+    /// `WORDS`必须等于`mm_log_channel_words(capacity)`
     pub const fn new(capacity: usize) -> Self {
-        let buffer = StaticThingBuf::with_recycle(MMLogCycle::new());
-        assert!(buffer.offset_of_slots() != 0);
-        let slot_total_size = size_of_val(&buffer) - buffer.offset_of_slots();
-        let slot_size = slot_total_size / capacity;
-        assert!(slot_size != 0);
-        assert!(slot_size > size_of_val(&AllocatorLog::zeroed()));
-
-        let r = Self {
+        assert!(capacity != 0);
+        assert!(WORDS == mm_log_channel_words(capacity));
+
+        return Self {
             magic: Self::MM_LOG_CHANNEL_MAGIC,
             element_size: core::mem::size_of::<AllocatorLog>() as u32,
             capacity: capacity as u64,
-            slot_size: slot_size as u32,
-            slots_offset: (offset_of!(MMLogChannel<CAP>, buf) + buffer.offset_of_slots()) as u64,
-            buf: buffer,
+            slot_size: MM_LOG_SLOT_SIZE as u32,
+            // 跳过每条记录开头的记录头
+            slots_offset: (offset_of!(MMLogChannel<WORDS>, buf)
+                + StaticRingBuffer::<WORDS>::data_offset()
+                + RECORD_HEADER_SIZE) as u64,
+            buf: StaticRingBuffer::new(RingMode::Overwrite),
         };
+    }
 
-        return r;
+    /// 记录一条日志，通道满时覆盖最旧的日志
+    pub fn push(&self, log: &AllocatorLog) {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                log as *const AllocatorLog as *const u8,
+                core::mem::size_of::<AllocatorLog>(),
+            )
+        };
+        self.buf.ring().write(0, 0, &[bytes]).ok();
     }
 }
@@ -0,0 +1,57 @@
+use alloc::vec::Vec;
+
+use crate::md5::md5;
+
+/// HMAC-MD5（RFC 2104）
+pub fn hmac_md5(key: &[u8], data: &[u8]) -> [u8; 16] {
+    let mut k = [0u8; 64];
+    if key.len() > 64 {
+        k[..16].copy_from_slice(&md5(key));
+    } else {
+        k[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Vec::with_capacity(64 + data.len());
+    inner.extend(k.iter().map(|b| b ^ 0x36));
+    inner.extend_from_slice(data);
+
+    let mut outer = Vec::with_capacity(64 + 16);
+    outer.extend(k.iter().map(|b| b ^ 0x5c));
+    outer.extend_from_slice(&md5(&inner));
+    md5(&outer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    /// RFC 2202 第2节中的测试用例
+    #[test]
+    fn hmac_md5_rfc2202() {
+        assert_eq!(
+            hex(&hmac_md5(&[0x0b; 16], b"Hi There")),
+            "9294727a3638bb1c13f48ef8158bfc9d"
+        );
+        assert_eq!(
+            hex(&hmac_md5(b"Jefe", b"what do ya want for nothing?")),
+            "750c783e6ab0b503eaa86e310a5db738"
+        );
+        assert_eq!(
+            hex(&hmac_md5(&[0xaa; 16], &[0xdd; 50])),
+            "56be34521d144c88dbb8c733f0e8b3f6"
+        );
+    }
+
+    /// 长于块大小的密钥先被哈希
+    #[test]
+    fn hmac_md5_long_key() {
+        assert_eq!(
+            hex(&hmac_md5(
+                &[0xaa; 80],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "6b1ab7fe4bd7bf8f0b62e6ce61b9d0cd"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+#![cfg_attr(not(test), no_std)]
+#![allow(clippy::needless_return)]
+
+//! MD4、MD5与HMAC-MD5
+//!
+//! 这些算法已经不再安全，只应用于兼容要求使用它们的旧协议（如NTLM认证）
+
+extern crate alloc;
+
+pub mod hmac;
+pub mod md4;
+pub mod md5;
+
+pub use hmac::hmac_md5;
+pub use md4::md4;
+pub use md5::md5;
+
+use alloc::vec::Vec;
+
+/// MD4与MD5共同的初始状态
+const MD_INIT: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+/// 按照MD4/MD5的规则填充消息
+fn md_pad(data: &[u8]) -> Vec<u8> {
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_le_bytes());
+    msg
+}
+
+/// 把一个64字节的块按小端序拆成16个字
+fn md_words(block: &[u8]) -> [u32; 16] {
+    let mut x = [0u32; 16];
+    for (i, w) in x.iter_mut().enumerate() {
+        *w = u32::from_le_bytes(block[i * 4..i * 4 + 4].try_into().unwrap());
+    }
+    x
+}
+
+fn md_digest(state: [u32; 4]) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    for (i, s) in state.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&s.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+pub(crate) fn hex(digest: &[u8]) -> std::string::String {
+    digest.iter().map(|b| std::format!("{:02x}", b)).collect()
+}
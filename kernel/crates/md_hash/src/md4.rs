@@ -0,0 +1,72 @@
+use crate::{md_digest, md_pad, md_words, MD_INIT};
+
+/// MD4摘要（RFC 1320）
+pub fn md4(data: &[u8]) -> [u8; 16] {
+    const ORDER: [[usize; 16]; 3] = [
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+        [0, 4, 8, 12, 1, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15],
+        [0, 8, 4, 12, 2, 10, 6, 14, 1, 9, 5, 13, 3, 11, 7, 15],
+    ];
+    const SHIFT: [[u32; 4]; 3] = [[3, 7, 11, 19], [3, 5, 9, 13], [3, 9, 11, 15]];
+    const K: [u32; 3] = [0, 0x5a827999, 0x6ed9eba1];
+    // 每一步更新的寄存器依次为a, d, c, b
+    const TARGET: [usize; 4] = [0, 3, 2, 1];
+
+    let mut state = MD_INIT;
+    for block in md_pad(data).chunks_exact(64) {
+        let x = md_words(block);
+        let mut h = state;
+        for round in 0..3 {
+            for step in 0..16 {
+                let t = TARGET[step % 4];
+                let (b, c, d) = (h[(t + 1) % 4], h[(t + 2) % 4], h[(t + 3) % 4]);
+                let f = match round {
+                    0 => (b & c) | (!b & d),
+                    1 => (b & c) | (b & d) | (c & d),
+                    _ => b ^ c ^ d,
+                };
+                h[t] = h[t]
+                    .wrapping_add(f)
+                    .wrapping_add(x[ORDER[round][step]])
+                    .wrapping_add(K[round])
+                    .rotate_left(SHIFT[round][step % 4]);
+            }
+        }
+        for (s, v) in state.iter_mut().zip(h.iter()) {
+            *s = s.wrapping_add(*v);
+        }
+    }
+    md_digest(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    /// RFC 1320 附录A.5中的测试集
+    #[test]
+    fn md4_rfc1320_suite() {
+        let cases: [(&[u8], &str); 7] = [
+            (b"", "31d6cfe0d16ae931b73c59d7e0c089c0"),
+            (b"a", "bde52cb31de33e46245e05fbdbd6fb24"),
+            (b"abc", "a448017aaf21d8525fc10ae87aa6729d"),
+            (b"message digest", "d9130a8164549fe818874806e1c7014b"),
+            (
+                b"abcdefghijklmnopqrstuvwxyz",
+                "d79e1c308aa5bbcdeea8ed63df412da9",
+            ),
+            (
+                b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "043f8582f241db351ce627e153e7f0e4",
+            ),
+            (
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "e33b4ddc9c38f2199c3e7b164fcc0536",
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(hex(&md4(input)), expected);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use core::mem::size_of;
+
+/// 符号表
+pub const SHT_SYMTAB: u32 = 2;
+/// 字符串表
+pub const SHT_STRTAB: u32 = 3;
+
+/// 函数符号
+pub const STT_FUNC: u8 = 2;
+
+/// ELF64的节头，与`Elf64_Shdr`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElfSectionHeader {
+    pub sh_name: u32,
+    pub sh_type: u32,
+    pub sh_flags: u64,
+    /// 节在内存中的地址。不需要加载的节（如符号表），引导程序会把它加载到内存中并填写这个字段
+    pub sh_addr: u64,
+    pub sh_offset: u64,
+    pub sh_size: u64,
+    /// 符号表所使用的字符串表的下标
+    pub sh_link: u32,
+    pub sh_info: u32,
+    pub sh_addralign: u64,
+    pub sh_entsize: u64,
+}
+
+/// ELF64的符号，与`Elf64_Sym`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElfSymbol {
+    /// 符号名在字符串表中的偏移量
+    pub st_name: u32,
+    pub st_info: u8,
+    pub st_other: u8,
+    pub st_shndx: u16,
+    pub st_value: u64,
+    pub st_size: u64,
+}
+
+impl ElfSymbol {
+    pub const fn symbol_type(&self) -> u8 {
+        self.st_info & 0xf
+    }
+
+    pub const fn is_function(&self) -> bool {
+        self.symbol_type() == STT_FUNC
+    }
+
+    /// 从字符串表中取出符号名
+    pub fn name<'a>(&self, strtab: &'a [u8]) -> Option<&'a str> {
+        let start = self.st_name as usize;
+        let rest = strtab.get(start..)?;
+        let len = rest.iter().position(|&c| c == 0)?;
+        core::str::from_utf8(&rest[..len]).ok()
+    }
+}
+
+/// 引导程序提供的ELF节头表
+///
+/// 对应multiboot info中`syms`字段的ELF格式，以及multiboot2的ELF-Symbols tag
+#[derive(Debug, Clone, Copy)]
+pub struct ElfSections {
+    num: usize,
+    entsize: usize,
+    shndx: usize,
+    headers: *const u8,
+}
+
+impl ElfSections {
+    /// # 创建节头表的访问器
+    ///
+    /// 只支持ELF64的节头，`entsize`不是`Elf64_Shdr`的大小时返回None
+    ///
+    /// ## Safety
+    ///
+    /// `headers`必须指向`num * entsize`字节可读的节头，并且在返回值使用期间有效
+    pub unsafe fn new(num: u32, entsize: u32, shndx: u32, headers: *const u8) -> Option<Self> {
+        if entsize as usize != size_of::<ElfSectionHeader>() || headers.is_null() {
+            return None;
+        }
+        Some(Self {
+            num: num as usize,
+            entsize: entsize as usize,
+            shndx: shndx as usize,
+            headers,
+        })
+    }
+
+    /// 节的数量
+    pub fn len(&self) -> usize {
+        self.num
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num == 0
+    }
+
+    /// 节名字符串表所在节的下标
+    pub fn shstrndx(&self) -> usize {
+        self.shndx
+    }
+
+    pub fn get(&self, index: usize) -> Option<ElfSectionHeader> {
+        if index >= self.num {
+            return None;
+        }
+        // 引导程序不保证节头按8字节对齐
+        Some(unsafe {
+            core::ptr::read_unaligned(
+                self.headers.add(index * self.entsize) as *const ElfSectionHeader
+            )
+        })
+    }
+
+    pub fn iter(&self) -> ElfSectionIter {
+        ElfSectionIter {
+            sections: *self,
+            index: 0,
+        }
+    }
+
+    /// 查找符号表，以及它所使用的字符串表
+    pub fn symbol_table(&self) -> Option<(ElfSectionHeader, ElfSectionHeader)> {
+        let symtab = self
+            .iter()
+            .find(|sh| sh.sh_type == SHT_SYMTAB && sh.sh_addr != 0)?;
+        let strtab = self
+            .get(symtab.sh_link as usize)
+            .filter(|sh| sh.sh_type == SHT_STRTAB && sh.sh_addr != 0)?;
+        Some((symtab, strtab))
+    }
+}
+
+/// 按顺序遍历节头
+#[derive(Debug, Clone)]
+pub struct ElfSectionIter {
+    sections: ElfSections,
+    index: usize,
+}
+
+impl Iterator for ElfSectionIter {
+    type Item = ElfSectionHeader;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sh = self.sections.get(self.index)?;
+        self.index += 1;
+        Some(sh)
+    }
+}
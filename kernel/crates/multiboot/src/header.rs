@@ -0,0 +1,228 @@
+use core::fmt;
+
+use bitflags::bitflags;
+
+use crate::HEADER_MAGIC;
+
+bitflags! {
+    /// multiboot header的flags字段
+    pub struct HeaderFlags: u32 {
+        /// 引导模块按4K对齐加载
+        const PAGE_ALIGN = 1 << 0;
+        /// 要求引导程序提供内存信息（mem_*和mmap_*）
+        const MEMORY_INFO = 1 << 1;
+        /// 要求引导程序设置视频模式，header中的mode_type、width、height、depth字段有效
+        const VIDEO_MODE = 1 << 2;
+        /// header中的地址字段有效，用于加载非ELF格式的镜像
+        const ADDRESS = 1 << 16;
+    }
+}
+
+/// 请求的视频模式类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum VideoModeType {
+    /// 线性帧缓冲区
+    LinearFramebuffer = 0,
+    /// EGA文本模式
+    Text = 1,
+}
+
+/// 请求的视频模式
+///
+/// 宽度、高度和色深为0表示没有偏好，由引导程序决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoMode {
+    pub mode_type: VideoModeType,
+    /// 图形模式下以像素为单位，文本模式下以字符为单位
+    pub width: u32,
+    pub height: u32,
+    /// 每个像素的位数，文本模式下必须为0
+    pub depth: u32,
+}
+
+impl VideoMode {
+    /// 请求一个图形帧缓冲区
+    pub const fn framebuffer(width: u32, height: u32, depth: u32) -> Self {
+        Self {
+            mode_type: VideoModeType::LinearFramebuffer,
+            width,
+            height,
+            depth,
+        }
+    }
+
+    /// 请求文本模式
+    pub const fn text(width: u32, height: u32) -> Self {
+        Self {
+            mode_type: VideoModeType::Text,
+            width,
+            height,
+            depth: 0,
+        }
+    }
+}
+
+/// 非ELF镜像的加载地址（物理地址）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderAddress {
+    /// multiboot header自身所在的地址
+    pub header_addr: u32,
+    /// 代码段的起始地址
+    pub load_addr: u32,
+    /// 数据段的结束地址，为0表示加载到镜像末尾
+    pub load_end_addr: u32,
+    /// bss段的结束地址，为0表示没有bss段
+    pub bss_end_addr: u32,
+    /// 内核入口地址
+    pub entry_addr: u32,
+}
+
+/// 内核镜像中的multiboot header
+///
+/// 使用[`HeaderBuilder`]构造，通过[`Header::words`]或者`Display`输出到启动汇编代码中
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    flags: HeaderFlags,
+    address: HeaderAddress,
+    video_mode: Option<VideoMode>,
+}
+
+impl Header {
+    /// header最多占用的32位字的数量
+    pub const MAX_WORDS: usize = 12;
+
+    pub const fn builder() -> HeaderBuilder {
+        HeaderBuilder::new()
+    }
+
+    pub const fn flags(&self) -> HeaderFlags {
+        self.flags
+    }
+
+    pub const fn video_mode(&self) -> Option<VideoMode> {
+        self.video_mode
+    }
+
+    /// 使magic、flags和checksum三者之和为0的校验和
+    pub const fn checksum(&self) -> u32 {
+        0u32.wrapping_sub(HEADER_MAGIC)
+            .wrapping_sub(self.flags.bits())
+    }
+
+    /// header实际占用的32位字的数量
+    ///
+    /// 没有设置视频模式时，不需要输出末尾的视频模式字段；地址字段在视频模式字段之前，需要一并输出
+    pub const fn len(&self) -> usize {
+        if self.flags.contains(HeaderFlags::VIDEO_MODE) {
+            12
+        } else if self.flags.contains(HeaderFlags::ADDRESS) {
+            8
+        } else {
+            3
+        }
+    }
+
+    /// header的字节数
+    pub const fn size(&self) -> usize {
+        self.len() * core::mem::size_of::<u32>()
+    }
+
+    /// 按照规范中的布局，返回header的所有字段。只有前[`Header::len`]个字有效
+    pub const fn words(&self) -> [u32; Self::MAX_WORDS] {
+        let (mode_type, width, height, depth) = match self.video_mode {
+            Some(mode) => (mode.mode_type as u32, mode.width, mode.height, mode.depth),
+            None => (0, 0, 0, 0),
+        };
+        [
+            HEADER_MAGIC,
+            self.flags.bits(),
+            self.checksum(),
+            self.address.header_addr,
+            self.address.load_addr,
+            self.address.load_end_addr,
+            self.address.bss_end_addr,
+            self.address.entry_addr,
+            mode_type,
+            width,
+            height,
+            depth,
+        ]
+    }
+}
+
+impl fmt::Display for Header {
+    /// 输出为GNU as的`.long`伪指令，可以直接写入启动汇编代码
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for word in &self.words()[..self.len()] {
+            writeln!(f, ".long {:#010x}", word)?;
+        }
+        Ok(())
+    }
+}
+
+/// [`Header`]的构造器
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderBuilder {
+    flags: HeaderFlags,
+    address: HeaderAddress,
+    video_mode: Option<VideoMode>,
+}
+
+impl HeaderBuilder {
+    pub const fn new() -> Self {
+        Self {
+            flags: HeaderFlags::empty(),
+            address: HeaderAddress {
+                header_addr: 0,
+                load_addr: 0,
+                load_end_addr: 0,
+                bss_end_addr: 0,
+                entry_addr: 0,
+            },
+            video_mode: None,
+        }
+    }
+
+    /// 要求引导模块按页对齐
+    pub const fn page_align(mut self) -> Self {
+        self.flags = self.flags.union(HeaderFlags::PAGE_ALIGN);
+        self
+    }
+
+    /// 要求引导程序提供内存大小和内存布局
+    pub const fn memory_info(mut self) -> Self {
+        self.flags = self.flags.union(HeaderFlags::MEMORY_INFO);
+        self
+    }
+
+    /// 要求引导程序设置视频模式
+    ///
+    /// 传统BIOS引导时，请求[`VideoMode::framebuffer`]可以得到图形帧缓冲区，而不是VGA文本模式
+    pub const fn video_mode(mut self, mode: VideoMode) -> Self {
+        self.flags = self.flags.union(HeaderFlags::VIDEO_MODE);
+        self.video_mode = Some(mode);
+        self
+    }
+
+    /// 设置加载地址，用于非ELF格式的镜像
+    pub const fn address(mut self, address: HeaderAddress) -> Self {
+        self.flags = self.flags.union(HeaderFlags::ADDRESS);
+        self.address = address;
+        self
+    }
+
+    pub const fn build(self) -> Header {
+        Header {
+            flags: self.flags,
+            address: self.address,
+            video_mode: self.video_mode,
+        }
+    }
+}
+
+impl Default for HeaderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,109 @@
+use bitflags::bitflags;
+
+use crate::elf::ElfSections;
+
+bitflags! {
+    /// multiboot info的flags字段，表示哪些字段有效
+    pub struct InfoFlags: u32 {
+        const MEMORY = 1 << 0;
+        const BOOTDEV = 1 << 1;
+        const CMDLINE = 1 << 2;
+        const MODULES = 1 << 3;
+        /// `syms`为a.out格式的符号表
+        const AOUT_SYMS = 1 << 4;
+        /// `syms`为ELF节头表
+        const ELF_SHDR = 1 << 5;
+        const MEM_MAP = 1 << 6;
+        const DRIVE_INFO = 1 << 7;
+        const CONFIG_TABLE = 1 << 8;
+        const BOOT_LOADER_NAME = 1 << 9;
+        const APM_TABLE = 1 << 10;
+        const VBE_INFO = 1 << 11;
+        const FRAMEBUFFER_INFO = 1 << 12;
+    }
+}
+
+/// a.out格式的符号表
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AoutSymbols {
+    pub tabsize: u32,
+    pub strsize: u32,
+    pub addr: u32,
+    _reserved: u32,
+}
+
+/// ELF格式的节头表
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ElfSectionsInfo {
+    pub num: u32,
+    pub size: u32,
+    pub addr: u32,
+    pub shndx: u32,
+}
+
+/// 引导程序传给内核的multiboot info的开头部分，到`syms`字段为止
+///
+/// 地址字段都是物理地址
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Info {
+    flags: u32,
+    pub mem_lower: u32,
+    pub mem_upper: u32,
+    pub boot_device: u32,
+    pub cmdline: u32,
+    pub mods_count: u32,
+    pub mods_addr: u32,
+    syms: [u32; 4],
+}
+
+impl Info {
+    pub fn flags(&self) -> InfoFlags {
+        InfoFlags::from_bits_truncate(self.flags)
+    }
+
+    /// a.out格式的符号表，引导程序没有提供时返回None
+    pub fn aout_symbols(&self) -> Option<AoutSymbols> {
+        if !self.flags().contains(InfoFlags::AOUT_SYMS) {
+            return None;
+        }
+        Some(AoutSymbols {
+            tabsize: self.syms[0],
+            strsize: self.syms[1],
+            addr: self.syms[2],
+            _reserved: 0,
+        })
+    }
+
+    /// ELF格式的节头表信息，引导程序没有提供时返回None
+    pub fn elf_sections_info(&self) -> Option<ElfSectionsInfo> {
+        if !self.flags().contains(InfoFlags::ELF_SHDR) {
+            return None;
+        }
+        Some(ElfSectionsInfo {
+            num: self.syms[0],
+            size: self.syms[1],
+            addr: self.syms[2],
+            shndx: self.syms[3],
+        })
+    }
+
+    /// # 访问ELF节头表
+    ///
+    /// `phys_to_virt`把物理地址转换为内核可以访问的虚拟地址
+    ///
+    /// ## Safety
+    ///
+    /// `phys_to_virt`返回的地址必须已经映射，并且在返回值使用期间有效
+    pub unsafe fn elf_sections(&self, phys_to_virt: impl Fn(u64) -> usize) -> Option<ElfSections> {
+        let info = self.elf_sections_info()?;
+        ElfSections::new(
+            info.num,
+            info.size,
+            info.shndx,
+            phys_to_virt(info.addr as u64) as *const u8,
+        )
+    }
+}
@@ -0,0 +1,23 @@
+//! Multiboot（第一版）规范的定义
+//!
+//! 规范见 https://www.gnu.org/software/grub/manual/multiboot/multiboot.html
+#![no_std]
+
+mod elf;
+mod header;
+mod info;
+
+pub use elf::{
+    ElfSectionHeader, ElfSectionIter, ElfSections, ElfSymbol, SHT_STRTAB, SHT_SYMTAB, STT_FUNC,
+};
+pub use header::{Header, HeaderAddress, HeaderBuilder, HeaderFlags, VideoMode, VideoModeType};
+pub use info::{AoutSymbols, ElfSectionsInfo, Info, InfoFlags};
+
+/// 内核镜像中的multiboot header的魔数
+pub const HEADER_MAGIC: u32 = 0x1BADB002;
+/// 引导程序跳转到内核时，eax中的魔数
+pub const BOOTLOADER_MAGIC: u32 = 0x2BADB002;
+/// multiboot header必须位于内核镜像的前`HEADER_SEARCH`字节内
+pub const HEADER_SEARCH: usize = 8192;
+/// multiboot header的对齐要求
+pub const HEADER_ALIGN: usize = 4;
@@ -0,0 +1,93 @@
+use core::mem::size_of;
+
+use multiboot::{
+    ElfSectionHeader, ElfSections, ElfSymbol, Info, InfoFlags, SHT_STRTAB, SHT_SYMTAB,
+};
+
+fn section(sh_type: u32, sh_addr: u64, sh_size: u64, sh_link: u32) -> ElfSectionHeader {
+    ElfSectionHeader {
+        sh_name: 0,
+        sh_type,
+        sh_flags: 0,
+        sh_addr,
+        sh_offset: 0,
+        sh_size,
+        sh_link,
+        sh_info: 0,
+        sh_addralign: 8,
+        sh_entsize: 0,
+    }
+}
+
+#[test]
+fn test_symbol_table() {
+    let headers = [
+        section(0, 0, 0, 0),
+        section(SHT_STRTAB, 0x3000, 0x100, 0),
+        section(SHT_SYMTAB, 0x2000, 0x30, 1),
+    ];
+    let sections = unsafe {
+        ElfSections::new(
+            headers.len() as u32,
+            size_of::<ElfSectionHeader>() as u32,
+            1,
+            headers.as_ptr() as *const u8,
+        )
+    }
+    .unwrap();
+    assert_eq!(sections.len(), 3);
+    assert_eq!(sections.iter().count(), 3);
+    assert!(sections.get(3).is_none());
+
+    let (symtab, strtab) = sections.symbol_table().unwrap();
+    assert_eq!(symtab.sh_addr, 0x2000);
+    assert_eq!(strtab.sh_addr, 0x3000);
+}
+
+#[test]
+fn test_reject_elf32() {
+    let headers = [0u8; 40];
+    assert!(unsafe { ElfSections::new(1, 40, 0, headers.as_ptr()) }.is_none());
+}
+
+#[test]
+fn test_symbol_name() {
+    let strtab = b"\0main\0foo\0";
+    let sym = ElfSymbol {
+        st_name: 6,
+        st_info: 0x12,
+        st_other: 0,
+        st_shndx: 1,
+        st_value: 0x1000,
+        st_size: 0x10,
+    };
+    assert!(sym.is_function());
+    assert_eq!(sym.name(strtab), Some("foo"));
+    assert_eq!(
+        ElfSymbol {
+            st_name: 100,
+            ..sym
+        }
+        .name(strtab),
+        None
+    );
+}
+
+#[test]
+fn test_info_elf_sections() {
+    let headers = [section(SHT_SYMTAB, 0x2000, 0x30, 0)];
+    let mut words = [0u32; 11];
+    words[0] = (InfoFlags::MEMORY | InfoFlags::ELF_SHDR).bits();
+    words[7] = 1;
+    words[8] = size_of::<ElfSectionHeader>() as u32;
+    words[9] = 0x1234;
+    words[10] = 0;
+    let info = unsafe { &*(words.as_ptr() as *const Info) };
+    assert!(info.aout_symbols().is_none());
+    let esi = info.elf_sections_info().unwrap();
+    assert_eq!(esi.addr, 0x1234);
+
+    let base = headers.as_ptr() as usize;
+    let sections = unsafe { info.elf_sections(|paddr| base + paddr as usize - 0x1234) }.unwrap();
+    assert_eq!(sections.get(0), Some(headers[0]));
+}
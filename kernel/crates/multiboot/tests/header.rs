@@ -0,0 +1,54 @@
+use multiboot::{Header, HeaderAddress, HeaderFlags, VideoMode, HEADER_MAGIC};
+
+fn sum(header: &Header) -> u32 {
+    header.words()[..3]
+        .iter()
+        .fold(0u32, |acc, w| acc.wrapping_add(*w))
+}
+
+#[test]
+fn test_minimal_header() {
+    let header = Header::builder().page_align().memory_info().build();
+    assert_eq!(header.len(), 3);
+    assert_eq!(header.words()[0], HEADER_MAGIC);
+    assert_eq!(header.words()[1], 0x3);
+    assert_eq!(sum(&header), 0);
+    assert_eq!(
+        header.to_string(),
+        ".long 0x1badb002\n.long 0x00000003\n.long 0xe4524ffb\n"
+    );
+}
+
+#[test]
+fn test_framebuffer_header() {
+    let header = Header::builder()
+        .memory_info()
+        .video_mode(VideoMode::framebuffer(1024, 768, 32))
+        .build();
+    assert_eq!(header.len(), 12);
+    assert_eq!(header.size(), 48);
+    assert_eq!(
+        header.flags(),
+        HeaderFlags::MEMORY_INFO | HeaderFlags::VIDEO_MODE
+    );
+    assert_eq!(sum(&header), 0);
+    // 没有设置地址时，地址字段为0
+    assert_eq!(&header.words()[3..8], &[0; 5]);
+    assert_eq!(&header.words()[8..], &[0, 1024, 768, 32]);
+}
+
+#[test]
+fn test_address_header() {
+    let address = HeaderAddress {
+        header_addr: 0x100000,
+        load_addr: 0x100000,
+        load_end_addr: 0,
+        bss_end_addr: 0,
+        entry_addr: 0x100040,
+    };
+    let header = Header::builder().address(address).build();
+    assert_eq!(header.len(), 8);
+    assert_eq!(sum(&header), 0);
+    assert_eq!(header.words()[3], 0x100000);
+    assert_eq!(header.words()[7], 0x100040);
+}
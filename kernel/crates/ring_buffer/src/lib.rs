@@ -0,0 +1,28 @@
+//! 内核通用的环形缓冲区
+//!
+//! 内核日志、内存分配器日志与perf采样都需要一个"多个生产者、一个消费者"的记录缓冲区，
+//! 这个crate为它们提供统一的实现：
+//!
+//! - 无锁：生产者通过CAS预留空间，写完后提交，可以在中断上下文中嵌套写入；
+//! - 两种模式：覆盖模式下新记录会挤掉最旧的记录，非覆盖模式下缓冲区满时丢弃新记录并计数；
+//! - 可映射的布局：读写位置（`data_head`/`data_tail`）与数据区可以位于外部内存，
+//!   例如perf的mmap页，用户态直接读取数据区并更新`data_tail`；
+//! - 按CPU分段：[`PerCpuRingBuffer`]为每个CPU提供一段独立的缓冲区，避免CPU之间的竞争。
+//!
+//! 缓冲区中的每条记录都以8字节的[`RecordHeader`]开头，其布局与`perf_event_header`相同，
+//! 记录的长度按8字节对齐。
+
+#![no_std]
+#![allow(clippy::needless_return)]
+
+extern crate alloc;
+
+mod percpu;
+mod record;
+mod ring;
+
+pub use percpu::PerCpuRingBuffer;
+pub use record::{record_size, RecordHeader, RECORD_ALIGN, RECORD_HEADER_SIZE, RECORD_MAX_SIZE};
+pub use ring::{
+    Ring, RingBuffer, RingControl, RingCore, RingError, RingMode, RingWriter, StaticRingBuffer,
+};
@@ -0,0 +1,107 @@
+use alloc::vec::Vec;
+
+use crate::{
+    record::RecordHeader,
+    ring::{Ring, RingBuffer, RingError, RingMode},
+};
+
+/// 按CPU分段的环形缓冲区
+///
+/// 每个CPU写入自己的段，生产者之间只会因为中断嵌套而竞争。
+/// 段之间没有全局的顺序，需要按顺序读取时，由使用者在记录中保存序号或时间戳，
+/// 通过[`PerCpuRingBuffer::read_ordered`]合并。
+#[derive(Debug)]
+pub struct PerCpuRingBuffer {
+    segments: Vec<RingBuffer>,
+}
+
+impl PerCpuRingBuffer {
+    /// 创建`nr_cpus`个段，每个段的数据区为`size`字节
+    pub fn new(nr_cpus: usize, size: usize, mode: RingMode) -> Self {
+        Self {
+            segments: (0..nr_cpus).map(|_| RingBuffer::new(size, mode)).collect(),
+        }
+    }
+
+    pub fn nr_segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// 第`cpu`个CPU的段
+    pub fn segment(&self, cpu: usize) -> Option<Ring<'_>> {
+        self.segments.get(cpu).map(|s| s.ring())
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = Ring<'_>> {
+        self.segments.iter().map(|s| s.ring())
+    }
+
+    /// 向第`cpu`个CPU的段写入一条记录
+    ///
+    /// `cpu`超出段的数量时panic
+    pub fn write(
+        &self,
+        cpu: usize,
+        kind: u32,
+        misc: u16,
+        parts: &[&[u8]],
+    ) -> Result<(), RingError> {
+        self.segments[cpu].ring().write(kind, misc, parts)
+    }
+
+    /// 所有段中未被读取的数据的长度之和
+    pub fn len(&self) -> usize {
+        self.segments().map(|s| s.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments().all(|s| s.is_empty())
+    }
+
+    /// 所有段中被丢弃的记录数之和
+    pub fn lost(&self) -> u64 {
+        self.segments().map(|s| s.lost()).sum()
+    }
+
+    pub fn clear(&self) {
+        self.segments().for_each(|s| s.clear());
+    }
+
+    /// 读取并移除所有段中`key`最小的记录
+    ///
+    /// `key`根据记录头与记录的内容计算排序的键，例如记录中保存的序号。
+    /// 返回记录头与记录所在的段。
+    pub fn read_ordered(
+        &self,
+        buf: &mut [u8],
+        key: impl Fn(&RecordHeader, &[u8]) -> u64,
+    ) -> Result<Option<(RecordHeader, usize)>, RingError> {
+        loop {
+            let mut oldest: Option<(usize, u64, u64)> = None;
+            for (cpu, seg) in self.segments().enumerate() {
+                let tail = seg.tail();
+                let header = match seg.read_at(tail, buf) {
+                    Ok(Some((header, _))) => header,
+                    Ok(None) | Err(RingError::Overwritten) => continue,
+                    Err(e) => return Err(e),
+                };
+                let k = key(&header, &buf[..header.payload_len()]);
+                if oldest.is_none_or(|(_, _, oldest_key)| k < oldest_key) {
+                    oldest = Some((cpu, tail, k));
+                }
+            }
+
+            let Some((cpu, tail, _)) = oldest else {
+                return Ok(None);
+            };
+            // 选出的记录在比较期间可能已经被覆盖，此时重新比较
+            let seg = self.segments[cpu].ring();
+            if seg.tail() != tail {
+                continue;
+            }
+            if let Some(header) = seg.read(buf)? {
+                return Ok(Some((header, cpu)));
+            }
+        }
+    }
+}
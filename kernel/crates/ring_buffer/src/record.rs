@@ -0,0 +1,38 @@
+/// 记录的对齐要求（字节），数据区的长度必须是它的整数倍
+pub const RECORD_ALIGN: usize = 8;
+/// 记录头的长度
+pub const RECORD_HEADER_SIZE: usize = core::mem::size_of::<RecordHeader>();
+/// 单条记录的最大长度（包括记录头），受限于`RecordHeader::size`的位宽
+pub const RECORD_MAX_SIZE: usize = u16::MAX as usize & !(RECORD_ALIGN - 1);
+
+/// 记录头
+///
+/// 布局与`perf_event_header`相同，因此perf的采样记录可以直接存放在缓冲区中
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordHeader {
+    /// 记录的类型，由使用者定义
+    pub kind: u32,
+    /// 附加信息，由使用者定义
+    pub misc: u16,
+    /// 记录的总长度（包括记录头与对齐填充）
+    pub size: u16,
+}
+
+impl RecordHeader {
+    /// 记录的内容的长度（包括对齐填充）
+    pub fn payload_len(&self) -> usize {
+        (self.size as usize).saturating_sub(RECORD_HEADER_SIZE)
+    }
+
+    /// 记录头是否可能有效。读到被覆盖的数据时，记录头可能是任意值
+    pub(crate) fn is_sane(&self) -> bool {
+        let size = self.size as usize;
+        size >= RECORD_HEADER_SIZE && size % RECORD_ALIGN == 0
+    }
+}
+
+/// 内容长度为`payload_len`的记录在缓冲区中占用的字节数
+pub const fn record_size(payload_len: usize) -> usize {
+    (RECORD_HEADER_SIZE + payload_len + RECORD_ALIGN - 1) & !(RECORD_ALIGN - 1)
+}
@@ -0,0 +1,534 @@
+use alloc::boxed::Box;
+use core::{
+    cell::UnsafeCell,
+    fmt::Debug,
+    mem::offset_of,
+    sync::atomic::{fence, AtomicU64, Ordering},
+};
+
+use crate::record::{record_size, RecordHeader, RECORD_ALIGN, RECORD_HEADER_SIZE, RECORD_MAX_SIZE};
+
+/// 缓冲区满时的行为
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingMode {
+    /// 丢弃最旧的记录，为新记录腾出空间
+    Overwrite,
+    /// 丢弃新记录，并计入丢失的记录数
+    NoOverwrite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingError {
+    /// 缓冲区中没有足够的空间，记录被丢弃
+    Full,
+    /// 记录比整个缓冲区或者记录的最大长度还大
+    TooLarge,
+    /// 输出缓冲区太小，参数为记录内容的长度
+    BufferTooSmall(usize),
+    /// 要读取的记录已经被覆盖
+    Overwritten,
+}
+
+/// 读写位置
+///
+/// 位置是单调递增的字节数，对数据区长度取模后得到在数据区中的偏移量。
+/// 布局与`perf_event_mmap_page`中的`data_head`、`data_tail`两个字段相同，
+/// 因此可以放在与用户态共享的页中。
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct RingControl {
+    /// 已经提交的数据的结束位置，由生产者更新
+    pub data_head: AtomicU64,
+    /// 最旧的未被读取的数据的位置，由消费者更新；覆盖模式下生产者也会推进它
+    pub data_tail: AtomicU64,
+}
+
+impl RingControl {
+    pub const fn new() -> Self {
+        Self {
+            data_head: AtomicU64::new(0),
+            data_tail: AtomicU64::new(0),
+        }
+    }
+}
+
+/// 生产者之间共享的状态，不对用户态可见
+#[derive(Debug)]
+pub struct RingCore {
+    mode: RingMode,
+    /// 生产者已经预留到的位置，不小于`data_head`
+    reserve: AtomicU64,
+    /// 正在写入的生产者的数量，只有最后一个完成的生产者会推进`data_head`
+    nest: AtomicU64,
+    /// 因为缓冲区满而被丢弃的记录数
+    lost: AtomicU64,
+}
+
+impl RingCore {
+    pub const fn new(mode: RingMode) -> Self {
+        Self {
+            mode,
+            reserve: AtomicU64::new(0),
+            nest: AtomicU64::new(0),
+            lost: AtomicU64::new(0),
+        }
+    }
+
+    pub fn mode(&self) -> RingMode {
+        self.mode
+    }
+}
+
+/// 环形缓冲区的视图，所有的读写操作都通过它进行
+///
+/// 生产者之间无锁，可以在中断上下文中嵌套写入；同一时刻只能有一个消费者。
+#[derive(Debug, Clone, Copy)]
+pub struct Ring<'a> {
+    core: &'a RingCore,
+    control: &'a RingControl,
+    data: *mut u8,
+    size: usize,
+}
+
+impl<'a> Ring<'a> {
+    /// 在外部内存上构造环形缓冲区
+    ///
+    /// # Safety
+    ///
+    /// - `data`指向长度为`size`字节、按`RECORD_ALIGN`对齐的内存，在`'a`内有效
+    /// - 对同一块数据区，所有的视图都使用同一个`core`和`control`
+    pub unsafe fn from_raw(
+        core: &'a RingCore,
+        control: &'a RingControl,
+        data: *mut u8,
+        size: usize,
+    ) -> Self {
+        assert!(size >= RECORD_ALIGN && size % RECORD_ALIGN == 0);
+        assert!(data as usize % RECORD_ALIGN == 0);
+        Self {
+            core,
+            control,
+            data,
+            size,
+        }
+    }
+
+    /// 数据区的长度（字节）
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    pub fn mode(&self) -> RingMode {
+        self.core.mode
+    }
+
+    /// 已经提交的数据的结束位置
+    pub fn head(&self) -> u64 {
+        self.control.data_head.load(Ordering::Acquire)
+    }
+
+    /// 最旧的未被读取的数据的位置
+    pub fn tail(&self) -> u64 {
+        self.control.data_tail.load(Ordering::Acquire)
+    }
+
+    /// 未被读取的数据的长度（字节）
+    pub fn len(&self) -> usize {
+        let tail = self.tail();
+        self.head().saturating_sub(tail) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 因为缓冲区满而被丢弃的记录数
+    pub fn lost(&self) -> u64 {
+        self.core.lost.load(Ordering::Relaxed)
+    }
+
+    /// 预留一条内容长度为`len`的记录
+    ///
+    /// 通过返回的[`RingWriter`]写入内容，`RingWriter`被drop时提交记录。
+    /// 缓冲区满时返回`Full`，并计入丢失的记录数。
+    pub fn reserve(&self, kind: u32, misc: u16, len: usize) -> Result<RingWriter<'a>, RingError> {
+        let size = record_size(len);
+        let pos = self.do_reserve(size).inspect_err(|e| {
+            if *e == RingError::Full {
+                self.core.lost.fetch_add(1, Ordering::Relaxed);
+            }
+        })?;
+
+        let header = RecordHeader {
+            kind,
+            misc,
+            size: size as u16,
+        };
+        unsafe { self.write_header(pos, header) };
+        Ok(RingWriter {
+            ring: *self,
+            pos: pos + RECORD_HEADER_SIZE as u64,
+            len: size - RECORD_HEADER_SIZE,
+            written: 0,
+        })
+    }
+
+    /// 写入一条记录，内容为`parts`依次拼接
+    pub fn write(&self, kind: u32, misc: u16, parts: &[&[u8]]) -> Result<(), RingError> {
+        let len = parts.iter().map(|p| p.len()).sum();
+        let mut writer = self.reserve(kind, misc, len)?;
+        for part in parts {
+            writer.write(part);
+        }
+        Ok(())
+    }
+
+    /// 如果之前有记录被丢弃，写入一条丢失记录
+    ///
+    /// `payload`根据丢弃的记录数生成丢失记录的内容。丢失记录本身写不下时返回`Full`，
+    /// 计数被保留到下一次。返回是否写入了丢失记录。
+    pub fn write_lost<const N: usize>(
+        &self,
+        kind: u32,
+        misc: u16,
+        payload: impl FnOnce(u64) -> [u8; N],
+    ) -> Result<bool, RingError> {
+        let lost = self.lost();
+        if lost == 0 {
+            return Ok(false);
+        }
+        let size = record_size(N);
+        let pos = self.do_reserve(size)?;
+        self.core.lost.fetch_sub(lost, Ordering::Relaxed);
+
+        let header = RecordHeader {
+            kind,
+            misc,
+            size: size as u16,
+        };
+        unsafe { self.write_header(pos, header) };
+        let mut writer = RingWriter {
+            ring: *self,
+            pos: pos + RECORD_HEADER_SIZE as u64,
+            len: size - RECORD_HEADER_SIZE,
+            written: 0,
+        };
+        writer.write(&payload(lost));
+        Ok(true)
+    }
+
+    /// 读取位于`pos`的记录的内容到`buf`，不移除记录
+    ///
+    /// 返回记录头与下一条记录的位置；`pos`处还没有记录时返回`None`。
+    /// 覆盖模式下，记录可能在读取的过程中被覆盖，此时返回`Overwritten`，
+    /// 调用者应当从新的[`Ring::tail`]重新开始读取。
+    pub fn read_at(
+        &self,
+        pos: u64,
+        buf: &mut [u8],
+    ) -> Result<Option<(RecordHeader, u64)>, RingError> {
+        if pos < self.tail() {
+            return Err(RingError::Overwritten);
+        }
+        let head = self.head();
+        if pos >= head {
+            return Ok(None);
+        }
+
+        let header = unsafe { self.read_header(pos) };
+        if !header.is_sane() || header.size as u64 > head - pos {
+            return Err(RingError::Overwritten);
+        }
+        let len = header.payload_len();
+        if buf.len() < len {
+            return Err(RingError::BufferTooSmall(len));
+        }
+        unsafe { self.copy_out(pos + RECORD_HEADER_SIZE as u64, &mut buf[..len]) };
+
+        // 拷贝完成后，如果生产者已经越过了这条记录，拷贝出来的数据可能是不完整的
+        fence(Ordering::Acquire);
+        if self.control.data_tail.load(Ordering::Relaxed) > pos {
+            return Err(RingError::Overwritten);
+        }
+        Ok(Some((header, pos + header.size as u64)))
+    }
+
+    /// 读取并移除最旧的记录，内容被拷贝到`buf`中
+    ///
+    /// 缓冲区为空时返回`None`
+    pub fn read(&self, buf: &mut [u8]) -> Result<Option<RecordHeader>, RingError> {
+        loop {
+            let tail = self.tail();
+            match self.read_at(tail, buf) {
+                Ok(Some((header, next))) => {
+                    if self
+                        .control
+                        .data_tail
+                        .compare_exchange(tail, next, Ordering::AcqRel, Ordering::Relaxed)
+                        .is_ok()
+                    {
+                        return Ok(Some(header));
+                    }
+                }
+                Ok(None) => return Ok(None),
+                Err(RingError::Overwritten) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// 移除所有已经提交的记录
+    pub fn clear(&self) {
+        loop {
+            let tail = self.tail();
+            let head = self.head();
+            if tail >= head
+                || self
+                    .control
+                    .data_tail
+                    .compare_exchange(tail, head, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    /// 预留`size`字节，返回预留的起始位置
+    fn do_reserve(&self, size: usize) -> Result<u64, RingError> {
+        if size > RECORD_MAX_SIZE || size > self.size {
+            return Err(RingError::TooLarge);
+        }
+
+        self.core.nest.fetch_add(1, Ordering::Acquire);
+        loop {
+            let start = self.core.reserve.load(Ordering::Relaxed);
+            let end = start + size as u64;
+            let tail = self.control.data_tail.load(Ordering::Acquire);
+            if end.saturating_sub(tail) > self.size as u64 {
+                if self.core.mode == RingMode::NoOverwrite
+                    || !self.drop_oldest(tail, end - self.size as u64)
+                {
+                    self.end_write();
+                    return Err(RingError::Full);
+                }
+                continue;
+            }
+            if self
+                .core
+                .reserve
+                .compare_exchange(start, end, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                // 读者拷贝完数据后检查`data_tail`，这里保证它看到新数据时也能看到新的`data_tail`
+                fence(Ordering::Release);
+                return Ok(start);
+            }
+        }
+    }
+
+    /// 覆盖模式下，丢弃最旧的记录，直到`data_tail`不小于`need`
+    ///
+    /// 只能丢弃已经提交的记录。被嵌套的生产者预留、但还没有提交的空间不能被覆盖，
+    /// 此时返回false。
+    fn drop_oldest(&self, tail: u64, need: u64) -> bool {
+        let committed = self.head();
+        let mut pos = tail;
+        while pos < need {
+            if pos >= committed {
+                return false;
+            }
+            let header = unsafe { self.read_header(pos) };
+            if !header.is_sane() {
+                // 读到了被并发覆盖的数据，说明tail已经被其他生产者推进了
+                return self.tail() != tail;
+            }
+            pos += header.size as u64;
+        }
+        // 失败说明其他生产者或消费者推进了tail，由调用者重新检查
+        let _ =
+            self.control
+                .data_tail
+                .compare_exchange(tail, pos, Ordering::AcqRel, Ordering::Relaxed);
+        true
+    }
+
+    /// 生产者完成写入
+    ///
+    /// 与Linux perf的`perf_output_put_handle`相同：只有最后一个完成的生产者推进`data_head`，
+    /// 因此读者看到的数据总是完整的。
+    fn end_write(&self) {
+        loop {
+            let head = self.core.reserve.load(Ordering::Acquire);
+            if self.core.nest.load(Ordering::Acquire) == 1 {
+                self.control.data_head.store(head, Ordering::Release);
+            }
+            if self.core.nest.fetch_sub(1, Ordering::AcqRel) != 1 {
+                return;
+            }
+            // 在推进data_head之后、离开之前，又有生产者完成了预留
+            if self.core.reserve.load(Ordering::Acquire) == head {
+                return;
+            }
+            self.core.nest.fetch_add(1, Ordering::Acquire);
+        }
+    }
+
+    #[inline]
+    fn offset(&self, pos: u64) -> usize {
+        (pos % self.size as u64) as usize
+    }
+
+    /// 记录头按8字节对齐，数据区的长度是8的整数倍，因此记录头不会跨越数据区的结尾
+    unsafe fn read_header(&self, pos: u64) -> RecordHeader {
+        core::ptr::read_volatile(self.data.add(self.offset(pos)) as *const RecordHeader)
+    }
+
+    unsafe fn write_header(&self, pos: u64, header: RecordHeader) {
+        core::ptr::write_volatile(self.data.add(self.offset(pos)) as *mut RecordHeader, header)
+    }
+
+    unsafe fn copy_in(&self, pos: u64, src: &[u8]) {
+        let offset = self.offset(pos);
+        let first = src.len().min(self.size - offset);
+        core::ptr::copy_nonoverlapping(src.as_ptr(), self.data.add(offset), first);
+        core::ptr::copy_nonoverlapping(src[first..].as_ptr(), self.data, src.len() - first);
+    }
+
+    unsafe fn copy_out(&self, pos: u64, dst: &mut [u8]) {
+        let offset = self.offset(pos);
+        let first = dst.len().min(self.size - offset);
+        core::ptr::copy_nonoverlapping(self.data.add(offset), dst.as_mut_ptr(), first);
+        core::ptr::copy_nonoverlapping(self.data, dst[first..].as_mut_ptr(), dst.len() - first);
+    }
+}
+
+/// 已经预留、尚未提交的记录
+///
+/// 被drop时提交记录，没有写满的部分被填充为0
+#[derive(Debug)]
+pub struct RingWriter<'a> {
+    ring: Ring<'a>,
+    /// 记录内容的起始位置
+    pos: u64,
+    /// 记录内容的长度（包括对齐填充）
+    len: usize,
+    written: usize,
+}
+
+impl RingWriter<'_> {
+    /// 在已写入的内容之后追加`buf`，返回实际写入的字节数
+    pub fn write(&mut self, buf: &[u8]) -> usize {
+        let n = buf.len().min(self.len - self.written);
+        unsafe { self.ring.copy_in(self.pos + self.written as u64, &buf[..n]) };
+        self.written += n;
+        n
+    }
+
+    /// 提交记录
+    pub fn commit(self) {}
+}
+
+impl Drop for RingWriter<'_> {
+    fn drop(&mut self) {
+        const ZEROS: [u8; RECORD_ALIGN] = [0; RECORD_ALIGN];
+        while self.written < self.len {
+            let n = (self.len - self.written).min(ZEROS.len());
+            self.write(&ZEROS[..n]);
+        }
+        self.ring.end_write();
+    }
+}
+
+/// 数据区在堆上分配的环形缓冲区
+pub struct RingBuffer {
+    core: RingCore,
+    control: RingControl,
+    data: Box<[UnsafeCell<u64>]>,
+}
+
+unsafe impl Send for RingBuffer {}
+unsafe impl Sync for RingBuffer {}
+
+impl RingBuffer {
+    /// 创建数据区为`size`字节（向上对齐到8字节）的环形缓冲区
+    pub fn new(size: usize, mode: RingMode) -> Self {
+        let words = size.div_ceil(RECORD_ALIGN).max(1);
+        Self {
+            core: RingCore::new(mode),
+            control: RingControl::new(),
+            data: (0..words).map(|_| UnsafeCell::new(0)).collect(),
+        }
+    }
+
+    pub fn ring(&self) -> Ring<'_> {
+        unsafe {
+            Ring::from_raw(
+                &self.core,
+                &self.control,
+                self.data.as_ptr() as *mut u8,
+                self.data.len() * RECORD_ALIGN,
+            )
+        }
+    }
+}
+
+impl Debug for RingBuffer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RingBuffer")
+            .field("core", &self.core)
+            .field("control", &self.control)
+            .field("size", &(self.data.len() * RECORD_ALIGN))
+            .finish()
+    }
+}
+
+/// 数据区内嵌在结构体中的环形缓冲区，可以用于`static`变量
+///
+/// 数据区的长度为`WORDS * 8`字节
+#[repr(C)]
+pub struct StaticRingBuffer<const WORDS: usize> {
+    control: RingControl,
+    core: RingCore,
+    data: UnsafeCell<[u64; WORDS]>,
+}
+
+unsafe impl<const WORDS: usize> Send for StaticRingBuffer<WORDS> {}
+unsafe impl<const WORDS: usize> Sync for StaticRingBuffer<WORDS> {}
+
+impl<const WORDS: usize> StaticRingBuffer<WORDS> {
+    pub const fn new(mode: RingMode) -> Self {
+        assert!(WORDS > 0);
+        Self {
+            control: RingControl::new(),
+            core: RingCore::new(mode),
+            data: UnsafeCell::new([0; WORDS]),
+        }
+    }
+
+    /// 数据区在结构体中的偏移量，供调试器等外部工具直接读取数据区
+    pub const fn data_offset() -> usize {
+        offset_of!(Self, data)
+    }
+
+    pub fn ring(&self) -> Ring<'_> {
+        unsafe {
+            Ring::from_raw(
+                &self.core,
+                &self.control,
+                self.data.get() as *mut u8,
+                WORDS * RECORD_ALIGN,
+            )
+        }
+    }
+}
+
+impl<const WORDS: usize> Debug for StaticRingBuffer<WORDS> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StaticRingBuffer")
+            .field("core", &self.core)
+            .field("control", &self.control)
+            .field("size", &(WORDS * RECORD_ALIGN))
+            .finish()
+    }
+}
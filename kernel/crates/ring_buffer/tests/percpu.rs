@@ -0,0 +1,44 @@
+//! 按CPU分段的环形缓冲区的集成测试
+
+use ring_buffer::{PerCpuRingBuffer, RingMode};
+
+/// 按记录中的序号合并各个段
+#[test]
+fn test_read_ordered() {
+    let buf = PerCpuRingBuffer::new(3, 256, RingMode::Overwrite);
+    assert_eq!(buf.nr_segments(), 3);
+    for seq in 0..12u64 {
+        let cpu = (seq * 7 % 3) as usize;
+        buf.write(cpu, 0, 0, &[&seq.to_ne_bytes()]).unwrap();
+    }
+    assert_eq!(buf.segment(0).unwrap().len(), 4 * 16);
+
+    let mut out = [0u8; 8];
+    for seq in 0..12u64 {
+        let (_, cpu) = buf
+            .read_ordered(&mut out, |_, data| {
+                u64::from_ne_bytes(data[..8].try_into().unwrap())
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(u64::from_ne_bytes(out), seq);
+        assert_eq!(cpu, (seq * 7 % 3) as usize);
+    }
+    assert!(buf.read_ordered(&mut out, |_, _| 0).unwrap().is_none());
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_lost_and_clear() {
+    let buf = PerCpuRingBuffer::new(2, 32, RingMode::NoOverwrite);
+    for _ in 0..3 {
+        buf.write(0, 0, 0, &[&[0; 8]]).ok();
+        buf.write(1, 0, 0, &[&[1; 8]]).ok();
+    }
+    assert_eq!(buf.lost(), 2);
+    assert_eq!(buf.len(), 64);
+    assert!(buf.segment(2).is_none());
+
+    buf.clear();
+    assert!(buf.is_empty());
+}
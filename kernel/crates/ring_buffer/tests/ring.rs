@@ -0,0 +1,231 @@
+//! 环形缓冲区的集成测试
+
+use std::{sync::Arc, thread};
+
+use ring_buffer::{
+    record_size, RingBuffer, RingError, RingMode, StaticRingBuffer, RECORD_HEADER_SIZE,
+};
+
+/// 写入后按顺序读出
+#[test]
+fn test_write_read() {
+    let buf = RingBuffer::new(256, RingMode::NoOverwrite);
+    let ring = buf.ring();
+    assert!(ring.is_empty());
+
+    ring.write(1, 0, &[b"hello"]).unwrap();
+    ring.write(2, 3, &[b"wor", b"ld!"]).unwrap();
+    assert_eq!(ring.len(), record_size(5) + record_size(6));
+
+    let mut out = [0u8; 64];
+    let header = ring.read(&mut out).unwrap().unwrap();
+    assert_eq!((header.kind, header.misc), (1, 0));
+    assert_eq!(header.size as usize, record_size(5));
+    assert_eq!(&out[..5], b"hello");
+    // 对齐填充为0
+    assert_eq!(&out[5..header.payload_len()], &[0, 0, 0]);
+
+    let header = ring.read(&mut out).unwrap().unwrap();
+    assert_eq!((header.kind, header.misc), (2, 3));
+    assert_eq!(&out[..6], b"world!");
+
+    assert_eq!(ring.read(&mut out), Ok(None));
+    assert!(ring.is_empty());
+}
+
+/// 非覆盖模式下缓冲区满时丢弃新记录并计数
+#[test]
+fn test_no_overwrite_full() {
+    let buf = RingBuffer::new(64, RingMode::NoOverwrite);
+    let ring = buf.ring();
+    // 每条记录占16字节
+    for i in 0..4u8 {
+        ring.write(0, 0, &[&[i; 8]]).unwrap();
+    }
+    assert_eq!(ring.write(0, 0, &[&[4; 8]]), Err(RingError::Full));
+    assert_eq!(ring.write(0, 0, &[&[5; 8]]), Err(RingError::Full));
+    assert_eq!(ring.lost(), 2);
+
+    let mut out = [0u8; 8];
+    ring.read(&mut out).unwrap().unwrap();
+    assert_eq!(out, [0; 8]);
+
+    // 先写入丢失记录，再写入新记录
+    assert_eq!(ring.write_lost(9, 0, |n| n.to_ne_bytes()), Ok(true));
+    assert_eq!(ring.lost(), 0);
+    assert_eq!(ring.write_lost(9, 0, |n| n.to_ne_bytes()), Ok(false));
+    for i in 1..4u8 {
+        ring.read(&mut out).unwrap().unwrap();
+        assert_eq!(out, [i; 8]);
+    }
+    let header = ring.read(&mut out).unwrap().unwrap();
+    assert_eq!(header.kind, 9);
+    assert_eq!(u64::from_ne_bytes(out), 2);
+}
+
+/// 覆盖模式下新记录挤掉最旧的记录
+#[test]
+fn test_overwrite() {
+    let buf = RingBuffer::new(64, RingMode::Overwrite);
+    let ring = buf.ring();
+    for i in 0..10u8 {
+        ring.write(0, 0, &[&[i; 8]]).unwrap();
+    }
+    assert_eq!(ring.lost(), 0);
+    assert_eq!(ring.len(), 64);
+
+    let mut out = [0u8; 8];
+    for i in 6..10u8 {
+        ring.read(&mut out).unwrap().unwrap();
+        assert_eq!(out, [i; 8]);
+    }
+    assert_eq!(ring.read(&mut out), Ok(None));
+}
+
+/// 覆盖模式下，不同长度的记录跨越数据区的结尾
+#[test]
+fn test_overwrite_wrap() {
+    let buf = RingBuffer::new(96, RingMode::Overwrite);
+    let ring = buf.ring();
+    for i in 0..50u8 {
+        let data = vec![i; (i as usize % 20) + 1];
+        ring.write(i as u32, 0, &[&data]).unwrap();
+    }
+
+    let mut out = [0u8; 32];
+    let mut last = None;
+    while let Some(header) = ring.read(&mut out).unwrap() {
+        let len = header.kind as usize % 20 + 1;
+        assert!(out[..len].iter().all(|&b| b == header.kind as u8));
+        if let Some(last) = last {
+            assert_eq!(header.kind, last + 1);
+        }
+        last = Some(header.kind);
+    }
+    assert_eq!(last, Some(49));
+}
+
+/// 不移除记录的读取，以及被覆盖后的检测
+#[test]
+fn test_read_at() {
+    let buf = RingBuffer::new(64, RingMode::Overwrite);
+    let ring = buf.ring();
+    ring.write(0, 0, &[&[0; 8]]).unwrap();
+    ring.write(1, 0, &[&[1; 8]]).unwrap();
+
+    let mut out = [0u8; 8];
+    let pos = ring.tail();
+    let (header, next) = ring.read_at(pos, &mut out).unwrap().unwrap();
+    assert_eq!(header.kind, 0);
+    let (header, end) = ring.read_at(next, &mut out).unwrap().unwrap();
+    assert_eq!(header.kind, 1);
+    assert_eq!(ring.read_at(end, &mut out), Ok(None));
+    assert_eq!(ring.len(), 32);
+
+    for i in 2..6 {
+        ring.write(i, 0, &[&[i as u8; 8]]).unwrap();
+    }
+    assert_eq!(ring.read_at(pos, &mut out), Err(RingError::Overwritten));
+}
+
+#[test]
+fn test_errors() {
+    let buf = RingBuffer::new(64, RingMode::Overwrite);
+    let ring = buf.ring();
+    assert_eq!(
+        ring.write(0, 0, &[&[0; 64]]).err(),
+        Some(RingError::TooLarge)
+    );
+
+    ring.write(0, 0, &[&[0; 16]]).unwrap();
+    let mut out = [0u8; 8];
+    assert_eq!(ring.read(&mut out), Err(RingError::BufferTooSmall(16)));
+
+    ring.clear();
+    assert!(ring.is_empty());
+    assert_eq!(ring.read(&mut out), Ok(None));
+}
+
+/// 预留的记录在提交之前对读者不可见，嵌套写入的记录在外层提交后一起可见
+#[test]
+fn test_nested_reserve() {
+    let buf = RingBuffer::new(256, RingMode::NoOverwrite);
+    let ring = buf.ring();
+    let mut outer = ring.reserve(1, 0, 8).unwrap();
+    outer.write(&[1; 4]);
+
+    ring.write(2, 0, &[&[2; 8]]).unwrap();
+    assert!(ring.is_empty());
+
+    outer.write(&[1; 4]);
+    outer.commit();
+    assert_eq!(ring.len(), 2 * record_size(8));
+
+    let mut out = [0u8; 8];
+    assert_eq!(ring.read(&mut out).unwrap().unwrap().kind, 1);
+    assert_eq!(out, [1; 8]);
+    assert_eq!(ring.read(&mut out).unwrap().unwrap().kind, 2);
+}
+
+/// 外部内存上的缓冲区
+#[test]
+fn test_static_ring_buffer() {
+    static BUF: StaticRingBuffer<8> = StaticRingBuffer::new(RingMode::Overwrite);
+    assert!(StaticRingBuffer::<8>::data_offset() > 0);
+    let ring = BUF.ring();
+    assert_eq!(ring.capacity(), 64);
+    ring.write(7, 0, &[b"static"]).unwrap();
+
+    let mut out = [0u8; 8];
+    assert_eq!(ring.read(&mut out).unwrap().unwrap().kind, 7);
+    assert_eq!(&out[..6], b"static");
+    assert_eq!(RECORD_HEADER_SIZE, 8);
+}
+
+/// 多个生产者并发写入，消费者读到的每条记录都是完整的
+#[test]
+fn test_mpsc() {
+    const PRODUCERS: u64 = 4;
+    const RECORDS: u64 = 2000;
+
+    let buf = Arc::new(RingBuffer::new(1024, RingMode::NoOverwrite));
+    let producers: Vec<_> = (0..PRODUCERS)
+        .map(|p| {
+            let buf = buf.clone();
+            thread::spawn(move || {
+                for i in 0..RECORDS {
+                    let value = p * RECORDS + i;
+                    while buf
+                        .ring()
+                        .write(p as u32, 0, &[&value.to_ne_bytes()[..]; 3])
+                        .is_err()
+                    {
+                        thread::yield_now();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let mut next = [0u64; PRODUCERS as usize];
+    let mut out = [0u8; 24];
+    let mut count = 0;
+    while count < PRODUCERS * RECORDS {
+        let Some(header) = buf.ring().read(&mut out).unwrap() else {
+            thread::yield_now();
+            continue;
+        };
+        let p = header.kind as usize;
+        let value = u64::from_ne_bytes(out[..8].try_into().unwrap());
+        assert_eq!(&out[8..16], &out[..8]);
+        assert_eq!(&out[16..24], &out[..8]);
+        // 同一个生产者的记录保持写入的顺序
+        assert_eq!(value, p as u64 * RECORDS + next[p]);
+        next[p] += 1;
+        count += 1;
+    }
+    for p in producers {
+        p.join().unwrap();
+    }
+    assert!(buf.ring().is_empty());
+}
@@ -103,6 +103,26 @@ impl<'a, P: AllocablePage> SCAllocator<'a, P> {
         self.size
     }
 
+    /// 统计当前scallocator的使用情况
+    pub fn info(&mut self) -> SlabInfo {
+        let mut free_objs = self.empty_slabs.elements * self.obj_per_page;
+        for slab_page in self.slabs.iter_mut() {
+            free_objs += slab_page.free_obj_count();
+        }
+        let active_slabs = self.slabs.elements + self.full_slabs.elements;
+        let num_slabs = active_slabs + self.empty_slabs.elements;
+        let num_objs = num_slabs * self.obj_per_page;
+
+        SlabInfo {
+            obj_size: self.size,
+            obj_per_page: self.obj_per_page,
+            active_objs: num_objs.saturating_sub(free_objs),
+            num_objs,
+            active_slabs,
+            num_slabs,
+        }
+    }
+
     /// Add a new ObjectPage.
     fn insert_partial_slab(&mut self, new_head: &'a mut P) {
         self.slabs.insert_front(new_head);
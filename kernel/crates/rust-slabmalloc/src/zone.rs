@@ -152,6 +152,15 @@ impl<'a> ZoneAllocator<'a> {
         let free_num = self.free_space();
         SlabUsage::new(self.total, free_num)
     }
+
+    /// 获取每个scallocator的统计信息（用于/proc/slabinfo）
+    pub fn slab_infos(&mut self) -> [SlabInfo; ZoneAllocator::MAX_BASE_SIZE_CLASSES] {
+        let mut infos = [SlabInfo::default(); ZoneAllocator::MAX_BASE_SIZE_CLASSES];
+        for (info, scallocator) in infos.iter_mut().zip(self.small_slabs.iter_mut()) {
+            *info = scallocator.info();
+        }
+        infos
+    }
 }
 
 unsafe impl<'a> crate::Allocator<'a> for ZoneAllocator<'a> {
@@ -229,3 +238,20 @@ impl SlabUsage {
         self.free
     }
 }
+
+/// 单个size class的slab统计信息
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SlabInfo {
+    /// 对象大小
+    pub obj_size: usize,
+    /// 每个page能容纳的对象数
+    pub obj_per_page: usize,
+    /// 正在使用的对象数
+    pub active_objs: usize,
+    /// 总对象数（包括空闲对象）
+    pub num_objs: usize,
+    /// 至少分配出去一个对象的page数
+    pub active_slabs: usize,
+    /// 总page数
+    pub num_slabs: usize,
+}
@@ -25,20 +25,89 @@ macro_rules! wq_wait_event_interruptible {
 #[allow(clippy::crate_in_macro_def)]
 macro_rules! _wq_wait_event_interruptible {
     ($wq:expr, $condition: expr, $cmd: expr) => {{
-        wait_queue_macros::__wq_wait_event!($wq, $condition, true, Ok(()), {
+        wait_queue_macros::__wq_wait_event!($wq, $condition, true, false, Ok(()), {
             $cmd;
             crate::sched::schedule(SchedMode::SM_NONE)
         })
     }};
 }
 
+/// Same as [`wq_wait_event_interruptible`], but the waiter is exclusive.
+///
+/// A wakeup wakes every non-exclusive waiter but at most `nr` exclusive ones (one for
+/// `WaitQueue::wakeup`), so use this when several waiters compete for the same resource
+/// (e.g. readers of a pipe) to avoid a thundering herd.
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)]
+macro_rules! wq_wait_event_interruptible_exclusive {
+    ($wq:expr, $condition: expr, $cmd: expr) => {{
+        let mut retval = Ok(());
+        if !$condition {
+            retval = wait_queue_macros::__wq_wait_event!($wq, $condition, true, true, Ok(()), {
+                $cmd;
+                crate::sched::schedule(SchedMode::SM_NONE)
+            });
+        }
+
+        retval
+    }};
+}
+
+/// Wait for a condition to become true, without being interrupted by signals.
+///
+/// ## Parameters
+///
+/// - `$wq`: The wait queue to wait on.
+/// - `$condition`: The condition to wait for. (you can pass a function or a boolean expression)
+/// - `$cmd`: The command to execute while waiting.
+#[macro_export]
+macro_rules! wq_wait_event_uninterruptible {
+    ($wq:expr, $condition: expr, $cmd: expr) => {{
+        if !$condition {
+            wait_queue_macros::_wq_wait_event_uninterruptible!($wq, $condition, $cmd);
+        }
+    }};
+}
+
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)]
+macro_rules! _wq_wait_event_uninterruptible {
+    ($wq:expr, $condition: expr, $cmd: expr) => {{
+        let _ = wait_queue_macros::__wq_wait_event!($wq, $condition, false, false, Ok(()), {
+            $cmd;
+            crate::sched::schedule(SchedMode::SM_NONE)
+        });
+    }};
+}
+
+/// Same as [`wq_wait_event_uninterruptible`], but the waiter is exclusive.
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)]
+macro_rules! wq_wait_event_uninterruptible_exclusive {
+    ($wq:expr, $condition: expr, $cmd: expr) => {{
+        if !$condition {
+            let _ = wait_queue_macros::__wq_wait_event!($wq, $condition, false, true, Ok(()), {
+                $cmd;
+                crate::sched::schedule(SchedMode::SM_NONE)
+            });
+        }
+    }};
+}
+
+/// The common loop behind all the `wq_wait_event_*` macros.
+///
+/// ## Parameters
+///
+/// - `$interruptible`: Whether the wait can be interrupted by signals.
+/// - `$exclusive`: Whether to wait as an exclusive waiter.
+/// - `$ret`: The value returned when the condition becomes true.
 #[macro_export]
 macro_rules! __wq_wait_event(
-    ($wq:expr, $condition: expr, $interruptible: expr, $ret: expr, $cmd:expr) => {{
+    ($wq:expr, $condition: expr, $interruptible: expr, $exclusive: expr, $ret: expr, $cmd:expr) => {{
         let mut retval = $ret;
         let mut exec_finish_wait = true;
         loop {
-            let x = $wq.prepare_to_wait_event($interruptible);
+            let x = $wq.prepare_to_wait_event($interruptible, $exclusive);
             if $condition {
                 break;
             }
@@ -60,6 +60,12 @@ pub(super) struct LocalContext {
     pub kernel_sp: usize,
     // 当前进程的用户栈指针（暂存，当进入中断处理程序的时候需要保存到pcb，进程切换的时候需要重新设置这个值）
     pub user_sp: usize,
+    /// 当前进程的内核栈的最低地址，为0表示不检查栈溢出（进程切换时设置，不随进程保存）
+    pub kstack_bottom: usize,
+    /// 本CPU的溢出栈的栈顶
+    pub overflow_sp: usize,
+    /// 陷入入口检查栈溢出时暂存寄存器
+    pub scratch: usize,
 }
 
 #[allow(dead_code)]
@@ -69,6 +75,9 @@ impl LocalContext {
             current_cpu: cpu,
             kernel_sp: 0,
             user_sp: 0,
+            kstack_bottom: 0,
+            overflow_sp: 0,
+            scratch: 0,
         }
     }
     pub fn current_cpu(&self) -> ProcessorId {
@@ -95,6 +104,14 @@ impl LocalContext {
         self.user_sp = sp;
     }
 
+    pub fn set_kstack_bottom(&mut self, bottom: usize) {
+        self.kstack_bottom = bottom;
+    }
+
+    pub fn set_overflow_sp(&mut self, sp: usize) {
+        self.overflow_sp = sp;
+    }
+
     fn sync_to_cpu(&self) {
         let ptr = self as *const Self as usize;
         riscv::register::sscratch::write(0);
@@ -12,10 +12,14 @@ use crate::{
     init::{boot_params, init::start_kernel},
     mm::{memblock::mem_block_manager, PhysAddr, VirtAddr},
     print, println,
-    smp::cpu::ProcessorId,
+    process::stack_overflow::{overflow_stack_top, stack_overflow_init},
+    smp::cpu::{smp_cpu_manager, ProcessorId},
 };
 
-use super::{cpu::init_local_context, interrupt::entry::handle_exception};
+use super::{
+    cpu::{init_local_context, local_context},
+    interrupt::entry::handle_exception,
+};
 
 mod boot;
 mod dragonstub;
@@ -152,6 +156,10 @@ pub fn setup_arch() -> Result<(), SystemError> {
 
 #[inline(never)]
 pub fn setup_arch_post() -> Result<(), SystemError> {
-    // todo
+    stack_overflow_init()?;
+    for cpu in smp_cpu_manager().possible_cpus().iter_cpu() {
+        let stack_top = overflow_stack_top(cpu).ok_or(SystemError::ENOMEM)?;
+        unsafe { local_context().force_get_mut(cpu) }.set_overflow_sp(stack_top.data());
+    }
     return Ok(());
 }
@@ -42,10 +42,29 @@ unsafe extern "C" fn _restore_kernel_tpsp() -> ! {
             // 把当前的sp寄存器的值保存到当前cpu的上下文的kernel_sp字段
             sd sp, {lc_off_kernel_sp}(tp)
 
+            // 栈指针离当前内核栈的底部已经不足一个中断栈帧，说明内核栈溢出了，
+            // 不能再往原来的栈上保存现场，改用本cpu的溢出栈
+            sd t0, {lc_off_scratch}(tp)
+            ld t0, {lc_off_kstack_bottom}(tp)
+            beqz t0, 1f
+            addi t0, t0, {trap_frame_size_on_stack}
+            bgeu sp, t0, 1f
+            ld t0, {lc_off_overflow_sp}(tp)
+            beqz t0, 1f
+            sd t0, {lc_off_kernel_sp}(tp)
+            // 之后不再检查，避免处理溢出的过程中再次陷入时把溢出栈重置
+            sd zero, {lc_off_kstack_bottom}(tp)
+        1:
+            ld t0, {lc_off_scratch}(tp)
+
             j {_save_context}
         "),
         csr_scratch = const CSR_SSCRATCH,
         lc_off_kernel_sp = const offset_of!(LocalContext, kernel_sp),
+        lc_off_kstack_bottom = const offset_of!(LocalContext, kstack_bottom),
+        lc_off_overflow_sp = const offset_of!(LocalContext, overflow_sp),
+        lc_off_scratch = const offset_of!(LocalContext, scratch),
+        trap_frame_size_on_stack = const TrapFrame::SIZE_ON_STACK,
         _save_context = sym _save_context
     )
 }
@@ -7,7 +7,12 @@ use log::{error, trace};
 use system_error::SystemError;
 
 use super::TrapFrame;
+use crate::arch::mm::extable::fixup_exception;
+use crate::debug::latency_tracer::{
+    trace_enter_from_user, trace_hardirq_enter, trace_hardirq_exit,
+};
 use crate::exception::ebreak::EBreak;
+use crate::process::stack_overflow::{kernel_stack_overflow, on_overflow_stack};
 use crate::{arch::syscall::syscall_handler, driver::irqchip::riscv_intc::riscv_intc_irq};
 
 type ExceptionHandler = fn(&mut TrapFrame) -> Result<(), SystemError>;
@@ -33,6 +38,10 @@ static EXCEPTION_HANDLERS: [ExceptionHandler; 16] = [
 
 #[no_mangle]
 unsafe extern "C" fn riscv64_do_irq(trap_frame: &mut TrapFrame) {
+    // 陷入入口发现内核栈溢出时会切换到溢出栈
+    if let Some(stack_bottom) = on_overflow_stack() {
+        kernel_stack_overflow(stack_bottom, trap_frame.sp, trap_frame.epc);
+    }
     if trap_frame.cause.is_interrupt() {
         riscv64_do_interrupt(trap_frame);
     } else if trap_frame.cause.is_exception() {
@@ -42,7 +51,9 @@ unsafe extern "C" fn riscv64_do_irq(trap_frame: &mut TrapFrame) {
 
 /// 处理中断
 fn riscv64_do_interrupt(trap_frame: &mut TrapFrame) {
+    trace_hardirq_enter(trap_frame.is_from_user());
     riscv_intc_irq(trap_frame);
+    trace_hardirq_exit();
 }
 
 /// 处理异常
@@ -108,7 +119,10 @@ fn do_trap_load_misaligned(_trap_frame: &mut TrapFrame) -> Result<(), SystemErro
 }
 
 /// 处理加载访问异常 #5
-fn do_trap_load_access_fault(_trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
+fn do_trap_load_access_fault(trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
+    if !trap_frame.is_from_user() && fixup_exception(trap_frame) {
+        return Ok(());
+    }
     error!("riscv64_do_irq: do_trap_load_access_fault");
     loop {
         spin_loop();
@@ -124,7 +138,10 @@ fn do_trap_store_misaligned(_trap_frame: &mut TrapFrame) -> Result<(), SystemErr
 }
 
 /// 处理存储访问异常 #7
-fn do_trap_store_access_fault(_trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
+fn do_trap_store_access_fault(trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
+    if !trap_frame.is_from_user() && fixup_exception(trap_frame) {
+        return Ok(());
+    }
     error!("riscv64_do_irq: do_trap_store_access_fault");
     loop {
         spin_loop();
@@ -137,6 +154,7 @@ fn do_trap_user_env_call(trap_frame: &mut TrapFrame) -> Result<(), SystemError>
         let syscall_num = trap_frame.a7;
         trap_frame.epc += 4;
         trap_frame.origin_a0 = trap_frame.a0;
+        trace_enter_from_user();
         syscall_handler(syscall_num, trap_frame);
     } else {
         panic!("do_trap_user_env_call: not from user mode")
@@ -162,6 +180,9 @@ fn do_trap_insn_page_fault(trap_frame: &mut TrapFrame) -> Result<(), SystemError
 
 /// 处理页加载错误异常 #13
 fn do_trap_load_page_fault(trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
+    if !trap_frame.is_from_user() && fixup_exception(trap_frame) {
+        return Ok(());
+    }
     let vaddr = trap_frame.badaddr;
     let cause = trap_frame.cause;
     let epc = trap_frame.epc;
@@ -179,6 +200,9 @@ fn do_trap_load_page_fault(trap_frame: &mut TrapFrame) -> Result<(), SystemError
 
 /// 处理页存储错误异常 #15
 fn do_trap_store_page_fault(trap_frame: &mut TrapFrame) -> Result<(), SystemError> {
+    if !trap_frame.is_from_user() && fixup_exception(trap_frame) {
+        return Ok(());
+    }
     error!(
         "riscv64_do_irq: do_trap_store_page_fault: epc: {:#x}, vaddr={:#x}, cause={:?}",
         trap_frame.epc, trap_frame.badaddr, trap_frame.cause
@@ -4,6 +4,7 @@ use riscv::register::{scause::Scause, sstatus::Sstatus};
 use system_error::SystemError;
 
 use crate::{
+    debug::latency_tracer::{trace_irqs_off, trace_irqs_on},
     driver::irqchip::{riscv_intc::riscv_intc_init, riscv_sifive_plic::riscv_sifive_plic_init},
     exception::{InterruptArch, IrqFlags, IrqFlagsGuard, IrqNumber},
     libs::align::align_up,
@@ -27,11 +28,13 @@ impl InterruptArch for RiscV64InterruptArch {
         Ok(())
     }
     unsafe fn interrupt_enable() {
+        trace_irqs_on();
         riscv::interrupt::enable();
     }
 
     unsafe fn interrupt_disable() {
         riscv::interrupt::disable();
+        trace_irqs_off();
     }
 
     fn is_irq_enabled() -> bool {
@@ -41,12 +44,14 @@ impl InterruptArch for RiscV64InterruptArch {
     unsafe fn save_and_disable_irq() -> IrqFlagsGuard {
         let sie = riscv::register::sstatus::read().sie();
         riscv::register::sstatus::clear_sie();
+        trace_irqs_off();
         IrqFlagsGuard::new(IrqFlags::new(sie.into()))
     }
 
     unsafe fn restore_irq(flags: IrqFlags) {
         let sie: bool = flags.flags() != 0;
         if sie {
+            trace_irqs_on();
             riscv::register::sstatus::set_sie();
         } else {
             riscv::register::sstatus::clear_sie();
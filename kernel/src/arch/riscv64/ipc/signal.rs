@@ -187,6 +187,8 @@ pub enum SigCode {
     AsyncIO = -4,
     /// sent by queued SIGIO
     SigIO = -5,
+    /// seccomp过滤器返回SECCOMP_RET_TRAP时发送的SIGSYS
+    SysSeccomp = 1,
 }
 
 impl SigCode {
@@ -201,6 +203,7 @@ impl SigCode {
             -3 => Self::Mesgq,
             -4 => Self::AsyncIO,
             -5 => Self::SigIO,
+            1 => Self::SysSeccomp,
             _ => panic!("signal code not valid"),
         }
     }
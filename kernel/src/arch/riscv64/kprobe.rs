@@ -45,6 +45,13 @@ pub struct KProbeContext {
     pub t6: usize,
 }
 
+impl KProbeContext {
+    /// 被探测的指令地址与当时的帧指针，用于回溯调用栈
+    pub fn pc_fp(&self) -> (usize, usize) {
+        (self.pc, self.s0)
+    }
+}
+
 impl From<&TrapFrame> for KProbeContext {
     fn from(trap_frame: &TrapFrame) -> Self {
         Self {
@@ -0,0 +1,67 @@
+//! 在用户空间与内核空间之间拷贝数据的例程，以及缺页时的修复
+//!
+//! 拷贝逐字节进行，a2中始终是还未处理的字节数，出错时修复代码把它作为返回值。
+
+use crate::{arch::interrupt::TrapFrame, mm::extable::search_exception_table};
+
+core::arch::global_asm!(
+    r#"
+    .pushsection .text.__copy_user, "ax"
+    .global __copy_user
+__copy_user:
+    beqz a2, .Lcopy_user_fixup
+.Lcopy_user_load:
+    lb t0, 0(a1)
+.Lcopy_user_store:
+    sb t0, 0(a0)
+    addi a0, a0, 1
+    addi a1, a1, 1
+    addi a2, a2, -1
+    bnez a2, .Lcopy_user_load
+.Lcopy_user_fixup:
+    mv a0, a2
+    ret
+    .popsection
+
+    .pushsection .text.__clear_user, "ax"
+    .global __clear_user
+__clear_user:
+    beqz a1, .Lclear_user_fixup
+.Lclear_user_store:
+    sb zero, 0(a0)
+    addi a0, a0, 1
+    addi a1, a1, -1
+    bnez a1, .Lclear_user_store
+.Lclear_user_fixup:
+    mv a0, a1
+    ret
+    .popsection
+
+    .pushsection __ex_table, "a"
+    .balign 8
+    .dword .Lcopy_user_load, .Lcopy_user_fixup
+    .dword .Lcopy_user_store, .Lcopy_user_fixup
+    .dword .Lclear_user_store, .Lclear_user_fixup
+    .popsection
+"#
+);
+
+extern "C" {
+    /// 从`src`拷贝`len`字节到`dst`，返回因缺页而未能拷贝的字节数
+    pub fn __copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    /// 将`dst`开始的`len`字节清零，返回因缺页而未能清零的字节数
+    pub fn __clear_user(dst: *mut u8, len: usize) -> usize;
+}
+
+/// 内核态访问用户地址出错时，如果出错的指令登记在异常表中，就把返回地址改为修复地址
+///
+/// ## 返回值
+///
+/// 找到修复地址时返回true，此时异常处理函数直接返回即可
+pub fn fixup_exception(regs: &mut TrapFrame) -> bool {
+    if let Some(fixup) = search_exception_table(regs.epc) {
+        regs.epc = fixup;
+        return true;
+    }
+    false
+}
@@ -17,6 +17,7 @@ pub mod sched;
 pub mod smp;
 pub mod syscall;
 pub mod time;
+pub mod unwind;
 
 pub use self::interrupt::RiscV64InterruptArch as CurrentIrqArch;
 pub use self::kvm::RiscV64KVMArch as KVMArch;
@@ -32,3 +33,5 @@ pub use self::ipc::signal::RiscV64SignalArch as CurrentSignalArch;
 pub use crate::arch::smp::RiscV64SMPArch as CurrentSMPArch;
 
 pub use crate::arch::sched::RiscV64SchedArch as CurrentSchedArch;
+
+pub use self::unwind::RiscV64UnwindArch as CurrentUnwindArch;
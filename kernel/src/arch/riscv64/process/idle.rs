@@ -2,12 +2,17 @@ use core::hint::spin_loop;
 
 use log::error;
 
-use crate::{arch::CurrentIrqArch, exception::InterruptArch, process::ProcessManager};
+use crate::{
+    arch::CurrentIrqArch, exception::InterruptArch, process::ProcessManager,
+    smp::cpu::smp_cpu_manager,
+};
 
 impl ProcessManager {
     /// 每个核的idle进程
     pub fn arch_idle_func() -> ! {
         loop {
+            // 当前cpu需要离线时，停靠在这里直到重新上线
+            smp_cpu_manager().cpuhp_idle_park();
             if CurrentIrqArch::is_irq_enabled() {
                 riscv::asm::wfi();
             } else {
@@ -20,8 +20,8 @@ use crate::{
     mm::VirtAddr,
     process::{
         fork::{CloneFlags, KernelCloneArgs},
-        switch_finish_hook, KernelStack, ProcessControlBlock, ProcessFlags, ProcessManager,
-        PROCESS_SWITCH_RESULT,
+        stack_overflow, switch_finish_hook, KernelStack, ProcessControlBlock, ProcessFlags,
+        ProcessManager, PROCESS_SWITCH_RESULT,
     },
     smp::cpu::ProcessorId,
 };
@@ -210,6 +210,12 @@ impl ProcessManager {
         local_context()
             .get_mut()
             .restore(&next.arch_info_irqsave().local_context);
+        // 陷入入口根据它判断内核栈是否溢出
+        let kstack_bottom = unsafe { next.kernel_stack_force_ref() }.start_address();
+        local_context()
+            .get_mut()
+            .set_kstack_bottom(kstack_bottom.data());
+        unsafe { stack_overflow::set_current_task_stacks(next) };
     }
 
     unsafe fn task_trapframe(task: &Arc<ProcessControlBlock>) -> &mut TrapFrame {
@@ -403,6 +409,10 @@ impl ArchPCBInfo {
     pub fn set_stack(&mut self, stack: VirtAddr) {
         self.ksp = stack.data();
     }
+
+    pub fn s0(&self) -> usize {
+        self.s0
+    }
 }
 
 #[repr(C)]
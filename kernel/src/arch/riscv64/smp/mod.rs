@@ -1,9 +1,14 @@
 use log::warn;
 use system_error::SystemError;
 
-use crate::smp::{
-    cpu::{CpuHpCpuState, ProcessorId},
-    SMPArch,
+use crate::{
+    arch::CurrentIrqArch,
+    driver::clocksource::timer_riscv::{riscv_sbi_timer_start_local, riscv_sbi_timer_stop_local},
+    exception::InterruptArch,
+    smp::{
+        cpu::{CpuHpCpuState, ProcessorId},
+        SMPArch,
+    },
 };
 
 pub struct RiscV64SMPArch;
@@ -19,4 +24,21 @@ impl SMPArch for RiscV64SMPArch {
         warn!("RiscV64SMPArch::start_cpu() is not implemented");
         Ok(())
     }
+
+    fn cpu_disable() {
+        riscv_sbi_timer_stop_local();
+    }
+
+    fn cpu_enable() {
+        riscv_sbi_timer_start_local();
+    }
+
+    fn cpu_park_wait() {
+        // 关中断时，wfi仍然会在有中断等待处理时返回，随后短暂地开中断来处理它
+        riscv::asm::wfi();
+        unsafe {
+            CurrentIrqArch::interrupt_enable();
+            CurrentIrqArch::interrupt_disable();
+        }
+    }
 }
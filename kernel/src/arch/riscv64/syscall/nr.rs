@@ -304,3 +304,8 @@ pub const SYS_WRITEV: usize = 66;
 // ===以下是为了代码一致性，才定义的调用号===
 
 pub const SYS_GETDENTS: usize = SYS_GETDENTS64;
+
+// ===以下是DragonOS私有的系统调用===
+
+pub const SYS_DRAGONOS_CHANNEL_CREATE: usize = 1000;
+pub const SYS_DRAGONOS_CHANNEL_CTL: usize = 1001;
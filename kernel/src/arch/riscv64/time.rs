@@ -1,7 +1,10 @@
 use log::{debug, info};
 
 use crate::{
-    driver::open_firmware::fdt::open_firmware_fdt_driver,
+    driver::{
+        clocksource::timer_riscv::riscv_clocksource_init,
+        open_firmware::fdt::open_firmware_fdt_driver,
+    },
     time::{clocksource::HZ, TimeArch},
 };
 pub struct RiscV64TimeArch;
@@ -46,6 +49,7 @@ fn init_time_freq() {
 pub fn time_init() {
     // 初始化cpu time register频率
     init_time_freq();
+    riscv_clocksource_init().expect("riscv clocksource init failed");
 }
 
 impl TimeArch for RiscV64TimeArch {
@@ -0,0 +1,46 @@
+use crate::{
+    arch::{interrupt::TrapFrame, process::ArchPCBInfo},
+    debug::traceback::UnwindArch,
+};
+
+use super::interrupt::entry::ret_from_exception;
+
+pub struct RiscV64UnwindArch;
+
+impl UnwindArch for RiscV64UnwindArch {
+    #[inline(always)]
+    fn current_frame_pointer() -> usize {
+        let fp: usize;
+        unsafe {
+            core::arch::asm!("mv {}, s0", out(reg) fp, options(nomem, nostack, preserves_flags));
+        }
+        fp
+    }
+
+    /// riscv64的栈帧布局：`[fp-8]`为返回地址，`[fp-16]`为上一级fp
+    unsafe fn read_frame(fp: usize) -> (usize, usize) {
+        let frame = fp as *const usize;
+        (frame.sub(2).read(), frame.sub(1).read())
+    }
+
+    /// 中断入口把sp设为TrapFrame的地址，然后以`ret_from_exception`为返回地址跳转到riscv64_do_irq，
+    /// 因此riscv64_do_irq的帧指针（即进入函数时的sp）就是TrapFrame的地址。
+    ///
+    /// 入口代码会把s0改为用户栈指针，所以普通的帧指针链在这里是断开的，必须从TrapFrame中恢复。
+    unsafe fn trap_frame(fp: usize, ret_addr: usize) -> Option<&'static TrapFrame> {
+        if ret_addr != ret_from_exception as usize {
+            return None;
+        }
+
+        return Some(&*(fp as *const TrapFrame));
+    }
+
+    fn trap_frame_pc_fp(trap_frame: &TrapFrame) -> (usize, usize) {
+        (trap_frame.epc, trap_frame.s0)
+    }
+
+    /// switch_to_inner把调用者的s0（帧指针）保存在ArchPCBInfo中
+    fn task_frame_pointer(arch_info: &ArchPCBInfo) -> usize {
+        arch_info.s0()
+    }
+}
@@ -89,6 +89,16 @@ pub fn apic_timer_init() {
     LocalApicTimerIntrController.enable();
 }
 
+/// 停止当前CPU的APIC定时器，用于CPU离线
+pub fn apic_timer_stop_local() {
+    LocalApicTimerIntrController.disable();
+}
+
+/// 重新开启当前CPU的APIC定时器，用于CPU重新上线
+pub fn apic_timer_start_local() {
+    LocalApicTimerIntrController.enable();
+}
+
 /// 初始化本地APIC定时器的中断描述符
 #[inline(never)]
 pub(super) fn local_apic_timer_irq_desc_init() {
@@ -156,7 +166,6 @@ impl LocalApicTimerIntrController {
         local_apic_timer.start_current();
     }
 
-    #[allow(dead_code)]
     pub(super) fn disable(&self) {
         let cpu_id = smp_get_processor_id();
         let local_apic_timer = local_apic_timer_instance_mut(cpu_id);
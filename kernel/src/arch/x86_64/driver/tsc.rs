@@ -2,7 +2,15 @@ use crate::{
     arch::{io::PortIOArch, CurrentIrqArch, CurrentPortIOArch, CurrentTimeArch},
     driver::acpi::pmtmr::{acpi_pm_read_early, ACPI_PM_OVERRUN, PMTMR_TICKS_PER_SEC},
     exception::InterruptArch,
-    time::{TimeArch, PIT_TICK_RATE},
+    libs::spinlock::SpinLock,
+    time::{
+        clocksource::{Clocksource, ClocksourceData, ClocksourceFlags, ClocksourceMask, CycleNum},
+        TimeArch, PIT_TICK_RATE,
+    },
+};
+use alloc::{
+    string::ToString,
+    sync::{Arc, Weak},
 };
 use core::{
     cmp::{max, min},
@@ -43,7 +51,9 @@ impl TSCManager {
             }
         }
 
-        // todo: register TSC as clock source and deal with unstable clock source
+        // todo: deal with unstable clock source
+        let tsc = ClocksourceTsc::new();
+        (tsc as Arc<dyn Clocksource>).register(1000, Self::tsc_khz() as u32)?;
 
         return Ok(());
     }
@@ -389,3 +399,64 @@ impl TSCManager {
         }
     }
 }
+
+/// TSC时钟源
+#[derive(Debug)]
+pub struct ClocksourceTsc(SpinLock<InnerClocksourceTsc>);
+
+#[derive(Debug)]
+struct InnerClocksourceTsc {
+    data: ClocksourceData,
+    self_ref: Weak<ClocksourceTsc>,
+}
+
+impl ClocksourceTsc {
+    /// TSC的精度高于acpi_pm（200）
+    const RATING: i32 = 300;
+
+    fn new() -> Arc<Self> {
+        let data = ClocksourceData {
+            name: "tsc".to_string(),
+            rating: Self::RATING,
+            mask: ClocksourceMask::new(u64::MAX),
+            mult: 0,
+            shift: 0,
+            max_idle_ns: Default::default(),
+            flags: ClocksourceFlags::CLOCK_SOURCE_IS_CONTINUOUS,
+            watchdog_last: CycleNum::new(0),
+            cs_last: CycleNum::new(0),
+            uncertainty_margin: 0,
+            maxadj: 0,
+            cycle_last: CycleNum::new(0),
+        };
+        let tsc = Arc::new(ClocksourceTsc(SpinLock::new(InnerClocksourceTsc {
+            data,
+            self_ref: Default::default(),
+        })));
+        tsc.0.lock().self_ref = Arc::downgrade(&tsc);
+        return tsc;
+    }
+}
+
+impl Clocksource for ClocksourceTsc {
+    fn read(&self) -> CycleNum {
+        CycleNum::new(CurrentTimeArch::get_cycles() as u64)
+    }
+
+    fn enable(&self) -> Result<i32, SystemError> {
+        return Ok(0);
+    }
+
+    fn clocksource_data(&self) -> ClocksourceData {
+        self.0.lock_irqsave().data.clone()
+    }
+
+    fn clocksource(&self) -> Arc<dyn Clocksource> {
+        self.0.lock_irqsave().self_ref.upgrade().unwrap()
+    }
+
+    fn update_clocksource_data(&self, data: ClocksourceData) -> Result<(), SystemError> {
+        self.0.lock_irqsave().data = data;
+        return Ok(());
+    }
+}
@@ -0,0 +1,157 @@
+//! gdbstub在x86_64上的体系结构相关部分
+//!
+//! 寄存器的编号与gdb的`i386:x86-64`目标描述一致：
+//! 0-15为通用寄存器，16为rip，它们各占8字节；17为eflags，18-23为段寄存器，各占4字节。
+//! 浮点与SSE寄存器不在`g`包中返回，gdb会把它们当作不可用。
+
+use crate::{
+    arch::{interrupt::TrapFrame, MMArch},
+    debug::gdbstub::GdbArch,
+    mm::{MemoryManagementArch, PageTableKind, PhysAddr, VirtAddr},
+};
+
+core::arch::global_asm!(
+    r#"
+    .pushsection .text.gdbstub_arch_breakpoint, "ax"
+    .global gdbstub_arch_breakpoint
+gdbstub_arch_breakpoint:
+    int3
+    ret
+    .popsection
+"#
+);
+
+extern "C" {
+    /// 执行一条int3，让当前cpu进入调试器
+    fn gdbstub_arch_breakpoint();
+}
+
+/// 单步执行标志
+const RFLAGS_TF: u64 = 1 << 8;
+/// int3指令
+const INT3_INSN: u8 = 0xcc;
+
+pub struct X86_64GdbArch;
+
+impl GdbArch for X86_64GdbArch {
+    const NUM_REGS: usize = 24;
+    const BREAKPOINT_INSN: u8 = INT3_INSN;
+
+    fn register(frame: &TrapFrame, regno: usize) -> Option<(u64, usize)> {
+        let r = match regno {
+            0 => (frame.rax, 8),
+            1 => (frame.rbx, 8),
+            2 => (frame.rcx, 8),
+            3 => (frame.rdx, 8),
+            4 => (frame.rsi, 8),
+            5 => (frame.rdi, 8),
+            6 => (frame.rbp, 8),
+            7 => (frame.rsp, 8),
+            8 => (frame.r8, 8),
+            9 => (frame.r9, 8),
+            10 => (frame.r10, 8),
+            11 => (frame.r11, 8),
+            12 => (frame.r12, 8),
+            13 => (frame.r13, 8),
+            14 => (frame.r14, 8),
+            15 => (frame.r15, 8),
+            16 => (frame.rip, 8),
+            17 => (frame.rflags, 4),
+            18 => (frame.cs, 4),
+            19 => (frame.ss, 4),
+            20 => (frame.ds, 4),
+            21 => (frame.es, 4),
+            // 内核不使用fs/gs选择子，直接报告为0
+            22 | 23 => (0, 4),
+            _ => return None,
+        };
+        Some(r)
+    }
+
+    fn set_register(frame: &mut TrapFrame, regno: usize, value: u64) -> bool {
+        let reg = match regno {
+            0 => &mut frame.rax,
+            1 => &mut frame.rbx,
+            2 => &mut frame.rcx,
+            3 => &mut frame.rdx,
+            4 => &mut frame.rsi,
+            5 => &mut frame.rdi,
+            6 => &mut frame.rbp,
+            7 => &mut frame.rsp,
+            8 => &mut frame.r8,
+            9 => &mut frame.r9,
+            10 => &mut frame.r10,
+            11 => &mut frame.r11,
+            12 => &mut frame.r12,
+            13 => &mut frame.r13,
+            14 => &mut frame.r14,
+            15 => &mut frame.r15,
+            16 => &mut frame.rip,
+            17 => &mut frame.rflags,
+            // 修改段寄存器会让iretq失败，忽略这些写入
+            18..=23 => return true,
+            _ => return false,
+        };
+        *reg = value;
+        true
+    }
+
+    fn pc(frame: &TrapFrame) -> usize {
+        frame.rip as usize
+    }
+
+    fn set_pc(frame: &mut TrapFrame, pc: usize) {
+        frame.set_pc(pc);
+    }
+
+    fn breakpoint_address(frame: &TrapFrame) -> usize {
+        // int3是陷阱，rip已经指向下一条指令
+        (frame.rip - 1) as usize
+    }
+
+    fn set_single_step(frame: &mut TrapFrame, enable: bool) {
+        if enable {
+            frame.rflags |= RFLAGS_TF;
+        } else {
+            frame.rflags &= !RFLAGS_TF;
+        }
+    }
+
+    fn is_arch_breakpoint(addr: usize) -> bool {
+        addr == gdbstub_arch_breakpoint as usize
+    }
+
+    fn breakpoint() {
+        unsafe { gdbstub_arch_breakpoint() };
+    }
+
+    fn addr_accessible(addr: usize, write: bool) -> bool {
+        if !VirtAddr::new(addr).is_canonical() {
+            return false;
+        }
+
+        // 调试器可能在任何时候运行，因此不使用PageMapper，而是直接查找当前的页表，
+        // 并且遇到大页时提前结束
+        let cr3 = unsafe { MMArch::table(PageTableKind::Kernel) };
+        let mut table = PhysAddr::new(cr3.data() & MMArch::ENTRY_ADDRESS_MASK);
+        for level in (0..MMArch::PAGE_LEVELS).rev() {
+            let shift = MMArch::PAGE_SHIFT + level * MMArch::PAGE_ENTRY_SHIFT;
+            let index = (addr >> shift) & MMArch::PAGE_ENTRY_MASK;
+            let Some(vaddr) = (unsafe { MMArch::phys_2_virt(table) }) else {
+                return false;
+            };
+            let entry: usize = unsafe { MMArch::read(vaddr + index * MMArch::PAGE_ENTRY_SIZE) };
+            if entry & MMArch::ENTRY_FLAG_PRESENT == 0 {
+                return false;
+            }
+            if write && entry & MMArch::ENTRY_FLAG_READWRITE == 0 {
+                return false;
+            }
+            if level == 0 || (level <= 2 && entry & MMArch::ENTRY_FLAG_HUGE_PAGE != 0) {
+                return true;
+            }
+            table = PhysAddr::new(entry & MMArch::ENTRY_ADDRESS_MASK);
+        }
+        false
+    }
+}
@@ -8,10 +8,14 @@ use system_error::SystemError;
 use x86::dtables::DescriptorTablePointer;
 
 use crate::{
-    arch::{interrupt::trap::arch_trap_init, process::table::TSSManager},
+    arch::{
+        interrupt::trap::{arch_double_fault_stack_init, arch_trap_init},
+        process::table::TSSManager,
+    },
     driver::clocksource::acpi_pm::init_acpi_pm_clocksource,
     init::init::start_kernel,
     mm::{MemoryManagementArch, PhysAddr},
+    process::stack_overflow::stack_overflow_init,
 };
 
 use self::boot::early_boot_init;
@@ -117,6 +121,9 @@ pub fn setup_arch_post() -> Result<(), SystemError> {
     }
     TSCManager::init().expect("tsc init failed");
 
+    stack_overflow_init()?;
+    arch_double_fault_stack_init()?;
+
     return Ok(());
 }
 
@@ -2,11 +2,13 @@ use core::hint::spin_loop;
 
 use acpi::rsdp::Rsdp;
 use alloc::string::{String, ToString};
+use multiboot::{ElfSections, ElfSymbol};
 use multiboot2::{BootInformation, BootInformationHeader, MemoryAreaType, RsdpV1Tag};
 use system_error::SystemError;
 
 use crate::{
-    arch::mm::x86_64_set_kernel_load_base_paddr,
+    arch::{mm::x86_64_set_kernel_load_base_paddr, MMArch},
+    debug::kallsyms::register_boot_symtab,
     driver::{
         serial::serial8250::send_to_default_serial8250_port,
         video::fbdev::{
@@ -19,7 +21,7 @@ use crate::{
         boot_params,
     },
     libs::lazy_init::Lazy,
-    mm::{memblock::mem_block_manager, PhysAddr},
+    mm::{memblock::mem_block_manager, MemoryManagementArch, PhysAddr},
 };
 
 pub(super) const MULTIBOOT2_ENTRY_MAGIC: u32 = multiboot2::MAGIC;
@@ -206,6 +208,8 @@ impl BootCallbacks for Mb2Callback {
                 });
         }
 
+        self.reserve_boot_symtab();
+
         // setup kernel load base
         self.setup_kernel_load_base();
 
@@ -223,6 +227,92 @@ impl Mb2Callback {
         let loadbase = PhysAddr::new(kernel_start as usize);
         x86_64_set_kernel_load_base_paddr(loadbase);
     }
+
+    /// 保留引导程序加载的符号表和字符串表，并注册给kallsyms
+    fn reserve_boot_symtab(&self) {
+        let Some(sections) = mb2_elf_sections() else {
+            return;
+        };
+        let Some((symtab, strtab)) = sections.symbol_table() else {
+            return;
+        };
+        if symtab.sh_entsize as usize != core::mem::size_of::<ElfSymbol>() {
+            log::warn!("MB2: unsupported symbol entry size {}", symtab.sh_entsize);
+            return;
+        }
+
+        for sh in [&symtab, &strtab] {
+            mem_block_manager()
+                .reserve_block(PhysAddr::new(sh.sh_addr as usize), sh.sh_size as usize)
+                .unwrap_or_else(|e| {
+                    log::warn!(
+                        "Failed to reserve memory block for boot symtab: base={:#x}, size={:#x}, error={:?}",
+                        sh.sh_addr,
+                        sh.sh_size,
+                        e
+                    );
+                });
+        }
+
+        unsafe {
+            let (Some(symtab_vaddr), Some(strtab_vaddr)) = (
+                MMArch::phys_2_virt(PhysAddr::new(symtab.sh_addr as usize)),
+                MMArch::phys_2_virt(PhysAddr::new(strtab.sh_addr as usize)),
+            ) else {
+                return;
+            };
+            let symbols = core::slice::from_raw_parts(
+                symtab_vaddr.data() as *const ElfSymbol,
+                symtab.sh_size as usize / core::mem::size_of::<ElfSymbol>(),
+            );
+            let strtab = core::slice::from_raw_parts(
+                strtab_vaddr.data() as *const u8,
+                strtab.sh_size as usize,
+            );
+            register_boot_symtab(symbols, strtab);
+        }
+    }
+}
+
+/// ELF-Symbols tag的类型
+const MB2_TAG_TYPE_ELF_SECTIONS: u32 = 9;
+/// 结束tag的类型
+const MB2_TAG_TYPE_END: u32 = 0;
+
+/// # 从原始的multiboot2信息中找到ELF-Symbols tag
+///
+/// tag的布局为：type(u32), size(u32), num(u32), entsize(u32), shndx(u32)，后面紧跟节头表。
+/// 节头表已经随整个信息结构一起被复制到`MB2_RAW_INFO`中
+fn mb2_elf_sections() -> Option<ElfSections> {
+    let raw = unsafe { &*core::ptr::addr_of!(MB2_RAW_INFO) };
+    let read_u32 = |off: usize| -> Option<u32> {
+        let bytes = raw.get(off..off + 4)?;
+        Some(u32::from_ne_bytes(bytes.try_into().unwrap()))
+    };
+
+    let total_size = (read_u32(0)? as usize).min(MB2_RAW_INFO_MAX_SIZE);
+    // 跳过total_size和reserved字段
+    let mut off = 8;
+    while off + 8 <= total_size {
+        let typ = read_u32(off)?;
+        let size = read_u32(off + 4)? as usize;
+        if typ == MB2_TAG_TYPE_END || size < 8 {
+            break;
+        }
+        if typ == MB2_TAG_TYPE_ELF_SECTIONS {
+            let num = read_u32(off + 8)?;
+            let entsize = read_u32(off + 12)?;
+            let shndx = read_u32(off + 16)?;
+            let headers = off + 20;
+            if headers + num as usize * entsize as usize > off + size {
+                return None;
+            }
+            return unsafe { ElfSections::new(num, entsize, shndx, raw[headers..].as_ptr()) };
+        }
+        // tag按8字节对齐
+        off += (size + 7) & !7;
+    }
+    None
 }
 pub(super) fn early_multiboot2_init(boot_magic: u32, boot_info: u64) -> Result<(), SystemError> {
     assert_eq!(boot_magic, MULTIBOOT2_ENTRY_MAGIC);
@@ -15,6 +15,7 @@ use system_error::SystemError;
 
 use crate::{
     arch::CurrentIrqArch,
+    debug::latency_tracer::{trace_irqs_off, trace_irqs_on},
     exception::{InterruptArch, IrqFlags, IrqFlagsGuard, IrqNumber},
 };
 
@@ -49,11 +50,13 @@ impl InterruptArch for X86_64InterruptArch {
         return Ok(());
     }
     unsafe fn interrupt_enable() {
+        trace_irqs_on();
         sti();
     }
 
     unsafe fn interrupt_disable() {
         cli();
+        trace_irqs_off();
     }
 
     fn is_irq_enabled() -> bool {
@@ -67,6 +70,7 @@ impl InterruptArch for X86_64InterruptArch {
     unsafe fn save_and_disable_irq() -> IrqFlagsGuard {
         compiler_fence(Ordering::SeqCst);
         let rflags = local_irq_save();
+        trace_irqs_off();
         let flags = IrqFlags::new(rflags);
         let guard = IrqFlagsGuard::new(flags);
         compiler_fence(Ordering::SeqCst);
@@ -75,6 +79,9 @@ impl InterruptArch for X86_64InterruptArch {
 
     unsafe fn restore_irq(flags: IrqFlags) {
         compiler_fence(Ordering::SeqCst);
+        if flags.flags() & (1 << 9) != 0 {
+            trace_irqs_on();
+        }
         local_irq_restore(flags.flags());
         compiler_fence(Ordering::SeqCst);
     }
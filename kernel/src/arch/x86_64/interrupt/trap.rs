@@ -8,11 +8,18 @@ use super::{
 use crate::exception::debug::DebugException;
 use crate::exception::ebreak::EBreak;
 use crate::{
-    arch::{CurrentIrqArch, MMArch},
+    arch::{process::table::TSSManager, CurrentIrqArch, MMArch},
+    debug::gdbstub::{gdbstub_handle_breakpoint, gdbstub_handle_debug},
     exception::InterruptArch,
     mm::VirtAddr,
-    process::ProcessManager,
-    smp::core::smp_get_processor_id,
+    process::{
+        stack_overflow::{
+            current_kernel_stack_bottom, inherit_pcb_slot, kernel_stack_overflow,
+            overflow_stack_top, stack_guard_hit,
+        },
+        ProcessManager,
+    },
+    smp::{core::smp_get_processor_id, cpu::smp_cpu_manager},
 };
 
 extern "C" {
@@ -78,6 +85,12 @@ bitflags! {
     }
 }
 
+/// 双重错误使用的中断栈表项
+///
+/// 内核栈溢出时，CPU往保护区域压入异常现场失败，缺页异常会升级为双重错误，
+/// 此时必须切换到一个确定可用的栈上才能处理
+const IST_DOUBLE_FAULT: u8 = 1;
+
 #[inline(never)]
 pub fn arch_trap_init() -> Result<(), SystemError> {
     unsafe {
@@ -110,6 +123,24 @@ pub fn arch_trap_init() -> Result<(), SystemError> {
     return Ok(());
 }
 
+/// 让双重错误通过IST切换到各个CPU的溢出栈
+///
+/// 需要在溢出栈分配好之后、启动AP之前调用
+pub fn arch_double_fault_stack_init() -> Result<(), SystemError> {
+    for cpu in smp_cpu_manager().possible_cpus().iter_cpu() {
+        let stack_top = overflow_stack_top(cpu).ok_or(SystemError::ENOMEM)?;
+        unsafe { TSSManager::set_ist(cpu, IST_DOUBLE_FAULT, stack_top) };
+    }
+    unsafe {
+        set_intr_gate(
+            8,
+            IST_DOUBLE_FAULT,
+            VirtAddr::new(trap_double_fault as usize),
+        )
+    };
+    return Ok(());
+}
+
 /// 处理除法错误 0 #DE
 #[no_mangle]
 unsafe extern "C" fn do_divide_error(regs: &'static TrapFrame, error_code: u64) {
@@ -135,6 +166,9 @@ unsafe extern "C" fn do_debug(regs: &'static mut TrapFrame, error_code: u64) {
         smp_get_processor_id().data(),
         ProcessManager::current_pid()
     );
+    if gdbstub_handle_debug(regs) {
+        return;
+    }
     DebugException::handle(regs).unwrap();
 }
 
@@ -163,6 +197,9 @@ unsafe extern "C" fn do_int3(regs: &'static mut TrapFrame, error_code: u64) {
         smp_get_processor_id().data(),
         ProcessManager::current_pid()
     );
+    if gdbstub_handle_breakpoint(regs) {
+        return;
+    }
     EBreak::handle(regs).unwrap();
 }
 
@@ -225,6 +262,15 @@ unsafe extern "C" fn do_dev_not_avaliable(regs: &'static TrapFrame, error_code:
 /// 处理双重错误 8 #DF
 #[no_mangle]
 unsafe extern "C" fn do_double_fault(regs: &'static TrapFrame, error_code: u64) {
+    if let Some(stack_bottom) = stack_guard_hit(regs.rsp as usize) {
+        kernel_stack_overflow(stack_bottom, regs.rsp as usize, regs.rip as usize);
+    }
+
+    // 运行在IST栈上，需要先复制pcb指针才能获取当前进程
+    let stack_bottom = current_kernel_stack_bottom();
+    if stack_bottom != 0 {
+        inherit_pcb_slot(stack_bottom);
+    }
     error!(
         "do_double_fault(8), \tError code: {:#x},\trsp: {:#x},\trip: {:#x},\t CPU: {}, \tpid: {:?}",
         error_code,
@@ -363,7 +409,7 @@ Segment Selector Index: {:#x}\n
 
 /// 处理页错误 14 #PF
 #[no_mangle]
-unsafe extern "C" fn do_page_fault(regs: &'static TrapFrame, error_code: u64) {
+unsafe extern "C" fn do_page_fault(regs: &'static mut TrapFrame, error_code: u64) {
     // error!(
     //     "do_page_fault(14), \tError code: {:#x},\trsp: {:#x},\trip: {:#x},\t CPU: {}, \tpid: {:?}, \nFault Address: {:#x}",
     //     error_code,
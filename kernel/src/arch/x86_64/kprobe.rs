@@ -36,6 +36,13 @@ pub struct KProbeContext {
     pub ss: ::core::ffi::c_ulong,
 }
 
+impl KProbeContext {
+    /// 被探测的指令地址与当时的帧指针，用于回溯调用栈
+    pub fn pc_fp(&self) -> (usize, usize) {
+        (self.rip as usize, self.rbp as usize)
+    }
+}
+
 impl From<&TrapFrame> for KProbeContext {
     fn from(trap_frame: &TrapFrame) -> Self {
         Self {
@@ -0,0 +1,58 @@
+//! 在用户空间与内核空间之间拷贝数据的例程，以及缺页时的修复
+//!
+//! 拷贝用`rep movsb`/`rep stosb`完成，出错时rcx中就是还未处理的字节数，修复代码把它作为返回值。
+
+use crate::{arch::interrupt::TrapFrame, mm::extable::search_exception_table};
+
+core::arch::global_asm!(
+    r#"
+    .pushsection .text.__copy_user, "ax"
+    .global __copy_user
+__copy_user:
+    mov rcx, rdx
+.Lcopy_user_insn:
+    rep movsb
+.Lcopy_user_fixup:
+    mov rax, rcx
+    ret
+    .popsection
+
+    .pushsection .text.__clear_user, "ax"
+    .global __clear_user
+__clear_user:
+    mov rcx, rsi
+    xor eax, eax
+.Lclear_user_insn:
+    rep stosb
+.Lclear_user_fixup:
+    mov rax, rcx
+    ret
+    .popsection
+
+    .pushsection __ex_table, "a"
+    .balign 8
+    .quad .Lcopy_user_insn, .Lcopy_user_fixup
+    .quad .Lclear_user_insn, .Lclear_user_fixup
+    .popsection
+"#
+);
+
+extern "C" {
+    /// 从`src`拷贝`len`字节到`dst`，返回因缺页而未能拷贝的字节数
+    pub fn __copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    /// 将`dst`开始的`len`字节清零，返回因缺页而未能清零的字节数
+    pub fn __clear_user(dst: *mut u8, len: usize) -> usize;
+}
+
+/// 内核态访问用户地址出错时，如果出错的指令登记在异常表中，就把返回地址改为修复地址
+///
+/// ## 返回值
+///
+/// 找到修复地址时返回true，此时缺页异常处理函数直接返回即可
+pub fn fixup_exception(regs: &mut TrapFrame) -> bool {
+    if let Some(fixup) = search_exception_table(regs.rip as usize) {
+        regs.set_pc(fixup);
+        return true;
+    }
+    false
+}
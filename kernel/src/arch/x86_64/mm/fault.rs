@@ -11,7 +11,7 @@ use crate::{
     arch::{
         interrupt::{trap::X86PfErrorCode, TrapFrame},
         ipc::signal::{SigCode, Signal},
-        mm::{MemoryManagementArch, X86_64MMArch},
+        mm::{extable::fixup_exception, MemoryManagementArch, X86_64MMArch},
         CurrentIrqArch, MMArch,
     },
     exception::InterruptArch,
@@ -21,7 +21,10 @@ use crate::{
         ucontext::{AddressSpace, LockedVMA},
         VirtAddr, VmFaultReason, VmFlags,
     },
-    process::ProcessManager,
+    process::{
+        stack_overflow::{kernel_stack_overflow, stack_guard_hit},
+        ProcessManager,
+    },
 };
 
 use super::LockedFrameAllocator;
@@ -67,11 +70,7 @@ impl X86_64MMArch {
         false
     }
 
-    pub fn show_fault_oops(
-        regs: &'static TrapFrame,
-        error_code: X86PfErrorCode,
-        address: VirtAddr,
-    ) {
+    pub fn show_fault_oops(regs: &TrapFrame, error_code: X86PfErrorCode, address: VirtAddr) {
         let mapper =
             unsafe { PageMapper::current(crate::mm::PageTableKind::User, LockedFrameAllocator) };
         if let Some(entry) = mapper.get_entry(address, 0) {
@@ -132,17 +131,28 @@ impl X86_64MMArch {
         );
     }
 
-    pub fn page_fault_oops(
-        regs: &'static TrapFrame,
-        error_code: X86PfErrorCode,
-        address: VirtAddr,
-    ) {
+    pub fn page_fault_oops(regs: &TrapFrame, error_code: X86PfErrorCode, address: VirtAddr) {
         if regs.is_from_user() {
             Self::show_fault_oops(regs, error_code, address);
         }
         panic!()
     }
 
+    /// 访问的用户地址无效
+    ///
+    /// 内核态的访问如果登记在异常表中，就跳到修复地址，由拷贝函数返回EFAULT；
+    /// 其余情况向当前进程发送SIGSEGV
+    fn bad_area(regs: &mut TrapFrame) {
+        if !regs.is_from_user() && fixup_exception(regs) {
+            return;
+        }
+        let pid = ProcessManager::current_pid();
+        let mut info = SigInfo::new(Signal::SIGSEGV, 0, SigCode::User, SigType::Kill(pid));
+        Signal::SIGSEGV
+            .send_signal_info(Some(&mut info), pid)
+            .expect("failed to send SIGSEGV to process");
+    }
+
     /// 内核态缺页异常处理
     /// ## 参数
     ///
@@ -150,10 +160,17 @@ impl X86_64MMArch {
     /// - `error_code`: 错误标志
     /// - `address`: 发生缺页异常的虚拟地址
     pub fn do_kern_addr_fault(
-        _regs: &'static TrapFrame,
+        regs: &'static mut TrapFrame,
         error_code: X86PfErrorCode,
         address: VirtAddr,
     ) {
+        if fixup_exception(regs) {
+            return;
+        }
+        // 访问了内核栈下方的保护区域
+        if let Some(stack_bottom) = stack_guard_hit(address.data()) {
+            unsafe { kernel_stack_overflow(stack_bottom, regs.rsp as usize, regs.rip as usize) };
+        }
         panic!(
             "do_kern_addr_fault has not yet been implemented, 
         fault address: {:#x}, 
@@ -173,7 +190,7 @@ impl X86_64MMArch {
     /// - `error_code`: 错误标志
     /// - `address`: 发生缺页异常的虚拟地址
     pub unsafe fn do_user_addr_fault(
-        regs: &'static TrapFrame,
+        regs: &'static mut TrapFrame,
         error_code: X86PfErrorCode,
         address: VirtAddr,
     ) {
@@ -240,12 +257,7 @@ impl X86_64MMArch {
                         error_code,
                         address.data(),
                     );
-                    let pid = ProcessManager::current_pid();
-                    let mut info =
-                        SigInfo::new(Signal::SIGSEGV, 0, SigCode::User, SigType::Kill(pid));
-                    Signal::SIGSEGV
-                        .send_signal_info(Some(&mut info), pid)
-                        .expect("failed to send SIGSEGV to process");
+                    Self::bad_area(regs);
                     return;
                 }
             };
@@ -256,15 +268,16 @@ impl X86_64MMArch {
 
             if !region.contains(address) {
                 if vm_flags.contains(VmFlags::VM_GROWSDOWN) {
-                    space_guard
-                        .extend_stack(region.start() - address)
-                        .unwrap_or_else(|_| {
-                            panic!(
-                                "user stack extend failed, error_code: {:#b}, address: {:#x}",
-                                error_code,
-                                address.data(),
-                            )
-                        });
+                    if space_guard.extend_stack(region.start() - address).is_err() {
+                        log::error!(
+                            "user stack extend failed, error_code: {:#b}, address: {:#x}",
+                            error_code,
+                            address.data(),
+                        );
+                        drop(space_guard);
+                        Self::bad_area(regs);
+                        return;
+                    }
                 } else {
                     log::error!(
                         "No mapped vma, error_code: {:#b}, address: {:#x}, flags: {:?}",
@@ -272,22 +285,20 @@ impl X86_64MMArch {
                         address.data(),
                         flags
                     );
-                    let pid = ProcessManager::current_pid();
-                    let mut info =
-                        SigInfo::new(Signal::SIGSEGV, 0, SigCode::User, SigType::Kill(pid));
-                    Signal::SIGSEGV
-                        .send_signal_info(Some(&mut info), pid)
-                        .expect("failed to send SIGSEGV to process");
+                    Self::bad_area(regs);
                     return;
                 }
             }
 
             if unlikely(Self::vma_access_error(vma.clone(), error_code)) {
-                panic!(
+                log::error!(
                     "vma access error, error_code: {:#b}, address: {:#x}",
                     error_code,
                     address.data(),
                 );
+                drop(space_guard);
+                Self::bad_area(regs);
+                return;
             }
             let mapper = &mut space_guard.user_mapper.utable;
             let message = PageFaultMessage::new(vma.clone(), address, flags, mapper);
@@ -312,8 +323,11 @@ impl X86_64MMArch {
             | VmFaultReason::VM_FAULT_HWPOISON_LARGE
             | VmFaultReason::VM_FAULT_FALLBACK;
 
-        if likely(!fault.contains(vm_fault_error)) {
+        if likely(!fault.intersects(vm_fault_error)) {
             panic!("fault error: {:?}", fault)
         }
+        // 缺页无法被满足
+        drop(space_guard);
+        Self::bad_area(regs);
     }
 }
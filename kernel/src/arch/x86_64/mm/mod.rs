@@ -1,5 +1,6 @@
 pub mod barrier;
 pub mod bump;
+pub mod extable;
 pub mod fault;
 pub mod pkru;
 
@@ -5,6 +5,7 @@ pub mod cpu;
 pub mod driver;
 pub mod elf;
 pub mod fpu;
+pub mod gdbstub;
 pub mod init;
 pub mod interrupt;
 pub mod ipc;
@@ -20,6 +21,7 @@ pub mod sched;
 pub mod smp;
 pub mod syscall;
 pub mod time;
+pub mod unwind;
 
 pub use self::pci::pci::X86_64PciArch as PciArch;
 
@@ -40,3 +42,7 @@ pub use crate::arch::elf::X86_64ElfArch as CurrentElfArch;
 pub use crate::arch::smp::X86_64SMPArch as CurrentSMPArch;
 
 pub use crate::arch::sched::X86_64SchedArch as CurrentSchedArch;
+
+pub use crate::arch::unwind::X86_64UnwindArch as CurrentUnwindArch;
+
+pub use crate::arch::gdbstub::X86_64GdbArch as CurrentGdbArch;
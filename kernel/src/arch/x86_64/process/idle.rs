@@ -7,12 +7,15 @@ use crate::{
     exception::InterruptArch,
     process::{ProcessFlags, ProcessManager},
     sched::{SchedMode, __schedule},
+    smp::cpu::smp_cpu_manager,
 };
 
 impl ProcessManager {
     /// 每个核的idle进程
     pub fn arch_idle_func() -> ! {
         loop {
+            // 当前cpu需要离线时，停靠在这里直到重新上线
+            smp_cpu_manager().cpuhp_idle_park();
             let pcb = ProcessManager::current_pcb();
             if pcb.flags().contains(ProcessFlags::NEED_SCHEDULE) {
                 __schedule(SchedMode::SM_NONE);
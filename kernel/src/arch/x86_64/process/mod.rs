@@ -19,7 +19,8 @@ use crate::{
     mm::VirtAddr,
     process::{
         fork::{CloneFlags, KernelCloneArgs},
-        KernelStack, ProcessControlBlock, ProcessFlags, ProcessManager, PROCESS_SWITCH_RESULT,
+        stack_overflow, KernelStack, ProcessControlBlock, ProcessFlags, ProcessManager,
+        PROCESS_SWITCH_RESULT,
     },
     syscall::Syscall,
 };
@@ -404,6 +405,7 @@ impl ProcessManager {
             x86::Ring::Ring0,
             next.kernel_stack().stack_max_address().data() as u64,
         );
+        stack_overflow::set_current_task_stacks(&next);
         PROCESS_SWITCH_RESULT.as_mut().unwrap().get_mut().prev_pcb = Some(prev);
         PROCESS_SWITCH_RESULT.as_mut().unwrap().get_mut().next_pcb = Some(next);
         // debug!("switch tss ok");
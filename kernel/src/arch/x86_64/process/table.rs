@@ -2,7 +2,7 @@ use x86::{current::task::TaskStateSegment, segmentation::SegmentSelector, Ring};
 
 use crate::{
     mm::{percpu::PerCpu, VirtAddr},
-    smp::core::smp_get_processor_id,
+    smp::{core::smp_get_processor_id, cpu::ProcessorId},
 };
 
 // === 段选择子在GDT中的索引 ===
@@ -47,6 +47,19 @@ impl TSSManager {
         &mut TSS_MANAGER.tss[smp_get_processor_id().data() as usize]
     }
 
+    /// 设置指定CPU的TSS中的中断栈表项
+    ///
+    /// ## 参数
+    ///
+    /// - `cpu`: CPU号
+    /// - `ist`: 中断栈表的下标，从1开始，与中断门中的IST字段一致
+    /// - `stack_top`: 栈顶（高地址）
+    #[allow(static_mut_refs)]
+    pub unsafe fn set_ist(cpu: ProcessorId, ist: u8, stack_top: VirtAddr) {
+        assert!((1..=7).contains(&ist));
+        TSS_MANAGER.tss[cpu.data() as usize].set_ist(ist as usize - 1, stack_top.data() as u64);
+    }
+
     /// 加载当前CPU的TSS
     pub unsafe fn load_tr() {
         let index = (10 + smp_get_processor_id().data() * 2) as u16;
@@ -1,4 +1,5 @@
 use core::{
+    arch::asm,
     hint::spin_loop,
     sync::atomic::{compiler_fence, fence, AtomicBool, Ordering},
 };
@@ -23,6 +24,7 @@ use crate::{
 
 use super::{
     acpi::early_acpi_boot_init,
+    driver::apic::apic_timer::{apic_timer_start_local, apic_timer_stop_local},
     interrupt::ipi::{ipi_send_smp_init, ipi_send_smp_startup},
     CurrentIrqArch,
 };
@@ -227,6 +229,19 @@ impl SMPArch for X86_64SMPArch {
 
         return Ok(());
     }
+
+    fn cpu_disable() {
+        apic_timer_stop_local();
+    }
+
+    fn cpu_enable() {
+        apic_timer_start_local();
+    }
+
+    fn cpu_park_wait() {
+        // sti的下一条指令执行完之前不会响应中断，因此检查条件与hlt之间到来的中断不会被错过
+        unsafe { asm!("sti; hlt; cli") };
+    }
 }
 
 impl X86_64SMPArch {
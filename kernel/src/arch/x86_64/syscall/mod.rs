@@ -4,11 +4,12 @@ use crate::{
         syscall::nr::{SYS_ARCH_PRCTL, SYS_RT_SIGRETURN},
         CurrentIrqArch,
     },
+    debug::latency_tracer::trace_enter_from_user,
     exception::InterruptArch,
     ipc::signal_types::SignalArch,
     libs::align::SafeForZero,
     mm::VirtAddr,
-    process::ProcessManager,
+    process::{seccomp::secure_computing, ProcessManager},
     syscall::{Syscall, SYS_SCHED},
 };
 use log::debug;
@@ -68,6 +69,7 @@ pub extern "sysv64" fn syscall_handler(frame: &mut TrapFrame) {
     // 系统调用进入时，把系统调用号存入errcode字段，以便在syscall_handler退出后，仍能获取到系统调用号
     frame.errcode = frame.rax;
     let syscall_num = frame.rax as usize;
+    trace_enter_from_user();
     // 防止sys_sched由于超时无法退出导致的死锁
     if syscall_num == SYS_SCHED {
         unsafe {
@@ -100,6 +102,11 @@ pub extern "sysv64" fn syscall_handler(frame: &mut TrapFrame) {
         debug!("syscall: pid: {:?}, num={:?}\n", pid, syscall_num);
     }
 
+    // 被seccomp拒绝的系统调用不会执行
+    if let Some(ret) = secure_computing(syscall_num, &args, frame.rip as usize) {
+        syscall_return!(ret, frame, show);
+    }
+
     // Arch specific syscall
     match syscall_num {
         SYS_RT_SIGRETURN => {
@@ -355,3 +355,8 @@ pub const SYS_WAIT4: usize = 61;
 pub const SYS_WAITID: usize = 247;
 pub const SYS_WRITE: usize = 1;
 pub const SYS_WRITEV: usize = 20;
+
+// ===以下是DragonOS私有的系统调用===
+
+pub const SYS_DRAGONOS_CHANNEL_CREATE: usize = 1000;
+pub const SYS_DRAGONOS_CHANNEL_CTL: usize = 1001;
@@ -0,0 +1,52 @@
+use crate::{
+    arch::{interrupt::TrapFrame, process::ArchPCBInfo},
+    debug::traceback::UnwindArch,
+};
+
+extern "C" {
+    /// 中断处理函数返回后继续执行的位置（见interrupt/entry.rs）
+    fn ret_from_intr();
+    /// 异常处理函数返回后继续执行的位置（见asm/entry.S）
+    fn __entry_err_code_to_ret_from_exception();
+}
+
+pub struct X86_64UnwindArch;
+
+impl UnwindArch for X86_64UnwindArch {
+    #[inline(always)]
+    fn current_frame_pointer() -> usize {
+        let fp: usize;
+        unsafe {
+            core::arch::asm!("mov {}, rbp", out(reg) fp, options(nomem, nostack, preserves_flags));
+        }
+        fp
+    }
+
+    /// x86_64的栈帧布局：`[rbp]`为上一级rbp，`[rbp+8]`为返回地址
+    unsafe fn read_frame(fp: usize) -> (usize, usize) {
+        let frame = fp as *const usize;
+        (frame.read(), frame.add(1).read())
+    }
+
+    /// 中断入口在调用处理函数之前，栈顶就是TrapFrame，
+    /// 因此TrapFrame紧挨着处理函数栈帧中的返回地址
+    unsafe fn trap_frame(fp: usize, ret_addr: usize) -> Option<&'static TrapFrame> {
+        if ret_addr != ret_from_intr as usize
+            && ret_addr != __entry_err_code_to_ret_from_exception as usize
+        {
+            return None;
+        }
+
+        let trap_frame = (fp + 2 * core::mem::size_of::<usize>()) as *const TrapFrame;
+        return Some(&*trap_frame);
+    }
+
+    fn trap_frame_pc_fp(trap_frame: &TrapFrame) -> (usize, usize) {
+        (trap_frame.rip as usize, trap_frame.rbp as usize)
+    }
+
+    /// switch_to_inner把调用者的rbp保存在ArchPCBInfo中
+    fn task_frame_pointer(arch_info: &ArchPCBInfo) -> usize {
+        arch_info.rbp()
+    }
+}
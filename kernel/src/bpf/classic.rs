@@ -0,0 +1,284 @@
+//! 经典BPF（cBPF）程序
+//!
+//! seccomp等接口使用经典BPF描述过滤规则。这里检查经典BPF程序，并把它转换为eBPF程序，
+//! 交给内核中已有的eBPF虚拟机执行。转换的方式与Linux的`bpf_convert_filter`相同：
+//! - A寄存器对应r0，X寄存器对应r7，上下文指针保存在r6中
+//! - 暂存区M[0..16]位于eBPF栈上
+//! - 运算都是32位的，条件跳转使用JMP32类指令，立即数不会被符号扩展
+//!
+//! 目前只支持seccomp允许使用的指令。`BPF_LD | BPF_ABS`按本机字节序读取上下文中的32位数据。
+
+use alloc::vec::Vec;
+use rbpf::ebpf::{self, Insn};
+use system_error::SystemError;
+
+/// 一个经典BPF程序最多的指令数
+pub const BPF_MAXINSNS: usize = 4096;
+/// 暂存区M[]的大小（32位字）
+const BPF_MEMWORDS: u32 = 16;
+
+// 指令类别
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+
+// 寻址方式
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+
+// ALU运算
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_XOR: u16 = 0xa0;
+
+// 跳转
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+
+// 操作数来源
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+
+// BPF_MISC
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+/// 累加器A
+const REG_A: u8 = 0;
+/// 上下文指针，eBPF程序开始时位于r1
+const REG_ARG: u8 = 1;
+/// 保存上下文指针
+const REG_CTX: u8 = 6;
+/// 索引寄存器X
+const REG_X: u8 = 7;
+/// 栈指针
+const REG_FP: u8 = 10;
+
+/// struct sock_filter
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// struct sock_fprog
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockFprog {
+    pub len: u16,
+    pub filter: *const SockFilter,
+}
+
+/// 生成eBPF指令，并在最后填写跳转的偏移
+struct Emitter {
+    insns: Vec<Insn>,
+    /// 需要填写偏移的跳转指令：(eBPF指令的下标, 目标经典BPF指令的下标)
+    fixups: Vec<(usize, usize)>,
+}
+
+impl Emitter {
+    fn emit(&mut self, opc: u8, dst: u8, src: u8, off: i16, imm: i32) {
+        self.insns.push(Insn {
+            opc,
+            dst,
+            src,
+            off,
+            imm,
+        });
+    }
+
+    /// 生成跳转到经典BPF指令`target`的跳转指令
+    fn emit_jump(&mut self, opc: u8, dst: u8, src: u8, imm: i32, target: usize) {
+        self.fixups.push((self.insns.len(), target));
+        self.emit(opc, dst, src, 0, imm);
+    }
+}
+
+/// 暂存区M[k]在eBPF栈上的偏移
+fn mem_offset(k: u32) -> i16 {
+    -(((BPF_MEMWORDS - k) * 4) as i16)
+}
+
+/// # 检查经典BPF程序，并转换为eBPF程序
+///
+/// ## 参数
+/// - `insns`: 经典BPF指令
+/// - `ctx_len`: 上下文的长度。`BPF_LD | BPF_ABS`只能读取上下文中4字节对齐的位置，`BPF_LEN`得到这个长度
+///
+/// ## 返回值
+/// - `Ok(Vec<u8>)`: eBPF程序的字节码，执行时以上下文作为内存
+/// - `Err(SystemError::EINVAL)`: 程序不合法，或者使用了不支持的指令
+pub fn convert_classic(insns: &[SockFilter], ctx_len: u32) -> Result<Vec<u8>, SystemError> {
+    let len = insns.len();
+    if len == 0 || len > BPF_MAXINSNS {
+        return Err(SystemError::EINVAL);
+    }
+    // 程序必须以返回指令结束，不能执行到程序之外
+    if insns[len - 1].code & 0x07 != BPF_RET {
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut e = Emitter {
+        insns: Vec::with_capacity(len * 2 + 3),
+        fixups: Vec::new(),
+    };
+    let mut addrs = Vec::with_capacity(len);
+
+    e.emit(ebpf::MOV64_REG, REG_CTX, REG_ARG, 0, 0);
+    e.emit(ebpf::MOV32_IMM, REG_A, 0, 0, 0);
+    e.emit(ebpf::MOV32_IMM, REG_X, 0, 0, 0);
+
+    for (i, insn) in insns.iter().enumerate() {
+        addrs.push(e.insns.len());
+        let code = insn.code;
+        let k = insn.k;
+        if code > 0xff {
+            return Err(SystemError::EINVAL);
+        }
+        match code & 0x07 {
+            BPF_LD | BPF_LDX => {
+                let dst = if code & 0x07 == BPF_LD { REG_A } else { REG_X };
+                // 只支持32位的读取，BPF_W为0
+                match code & !0x07 {
+                    BPF_ABS if dst == REG_A => {
+                        if k % 4 != 0 || k.checked_add(4).map_or(true, |end| end > ctx_len) {
+                            return Err(SystemError::EINVAL);
+                        }
+                        e.emit(ebpf::LD_W_REG, dst, REG_CTX, k as i16, 0);
+                    }
+                    BPF_LEN => e.emit(ebpf::MOV32_IMM, dst, 0, 0, ctx_len as i32),
+                    BPF_IMM => e.emit(ebpf::MOV32_IMM, dst, 0, 0, k as i32),
+                    BPF_MEM => {
+                        if k >= BPF_MEMWORDS {
+                            return Err(SystemError::EINVAL);
+                        }
+                        e.emit(ebpf::LD_W_REG, dst, REG_FP, mem_offset(k), 0);
+                    }
+                    _ => return Err(SystemError::EINVAL),
+                }
+            }
+            BPF_ST | BPF_STX => {
+                if code & !0x07 != 0 || k >= BPF_MEMWORDS {
+                    return Err(SystemError::EINVAL);
+                }
+                let src = if code & 0x07 == BPF_ST { REG_A } else { REG_X };
+                e.emit(ebpf::ST_W_REG, REG_FP, src, mem_offset(k), 0);
+            }
+            BPF_ALU => {
+                let op = code & 0xf0;
+                let from_x = code & BPF_X != 0;
+                match op {
+                    BPF_NEG => {
+                        if code != BPF_ALU | BPF_NEG {
+                            return Err(SystemError::EINVAL);
+                        }
+                        e.emit(ebpf::NEG32, REG_A, 0, 0, 0);
+                        continue;
+                    }
+                    BPF_ADD | BPF_SUB | BPF_MUL | BPF_OR | BPF_AND | BPF_XOR => {}
+                    BPF_DIV => {
+                        if !from_x && k == 0 {
+                            return Err(SystemError::EINVAL);
+                        }
+                        if from_x {
+                            // 除数为0时，与Linux相同，过滤器返回0
+                            e.emit(ebpf::JNE_IMM32, REG_X, 0, 2, 0);
+                            e.emit(ebpf::MOV32_IMM, REG_A, 0, 0, 0);
+                            e.emit(ebpf::EXIT, 0, 0, 0, 0);
+                        }
+                    }
+                    BPF_LSH | BPF_RSH => {
+                        if !from_x && k >= 32 {
+                            return Err(SystemError::EINVAL);
+                        }
+                    }
+                    _ => return Err(SystemError::EINVAL),
+                }
+                // 经典BPF的ALU操作码与eBPF的32位ALU操作码相同
+                if from_x {
+                    e.emit(code as u8, REG_A, REG_X, 0, 0);
+                } else {
+                    e.emit(code as u8, REG_A, 0, 0, k as i32);
+                }
+            }
+            BPF_JMP => {
+                let op = code & 0xf0;
+                if op == BPF_JA {
+                    if code != BPF_JMP | BPF_JA {
+                        return Err(SystemError::EINVAL);
+                    }
+                    let target = (i + 1)
+                        .checked_add(k as usize)
+                        .filter(|t| *t < len)
+                        .ok_or(SystemError::EINVAL)?;
+                    e.emit_jump(ebpf::JA, 0, 0, 0, target);
+                    continue;
+                }
+                if !matches!(op, BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET) {
+                    return Err(SystemError::EINVAL);
+                }
+                let jt = i + 1 + insn.jt as usize;
+                let jf = i + 1 + insn.jf as usize;
+                if jt >= len || jf >= len {
+                    return Err(SystemError::EINVAL);
+                }
+                let opc = ebpf::BPF_JMP32 | (code & 0xf8) as u8;
+                if code & BPF_X != 0 {
+                    e.emit_jump(opc, REG_A, REG_X, 0, jt);
+                } else {
+                    e.emit_jump(opc, REG_A, 0, k as i32, jt);
+                }
+                // 条件不成立时执行下一条指令，否则还需要一条无条件跳转
+                if insn.jf != 0 {
+                    e.emit_jump(ebpf::JA, 0, 0, 0, jf);
+                }
+            }
+            BPF_RET => match code & !0x07 {
+                BPF_K => {
+                    e.emit(ebpf::MOV32_IMM, REG_A, 0, 0, k as i32);
+                    e.emit(ebpf::EXIT, 0, 0, 0, 0);
+                }
+                BPF_A => e.emit(ebpf::EXIT, 0, 0, 0, 0),
+                _ => return Err(SystemError::EINVAL),
+            },
+            BPF_MISC => match code & !0x07 {
+                BPF_TAX => e.emit(ebpf::MOV32_REG, REG_X, REG_A, 0, 0),
+                BPF_TXA => e.emit(ebpf::MOV32_REG, REG_A, REG_X, 0, 0),
+                _ => return Err(SystemError::EINVAL),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    for (at, target) in e.fixups.iter() {
+        let off = addrs[*target] as isize - (*at as isize + 1);
+        e.insns[*at].off = i16::try_from(off).map_err(|_| SystemError::EINVAL)?;
+    }
+
+    let mut prog = Vec::with_capacity(e.insns.len() * ebpf::INSN_SIZE);
+    for insn in e.insns.iter() {
+        prog.extend_from_slice(&insn.to_array());
+    }
+    return Ok(prog);
+}
@@ -1,12 +1,14 @@
 mod consts;
 mod print;
 
+use crate::arch::kprobe::KProbeContext;
 use crate::bpf::helper::print::trace_printf;
 use crate::bpf::map::{BpfCallBackFn, BpfMap};
-use crate::include::bindings::linux_bpf::BPF_F_CURRENT_CPU;
+use crate::debug::traceback::StackUnwinder;
+use crate::include::bindings::linux_bpf::{BPF_F_CURRENT_CPU, BPF_F_SKIP_FIELD_MASK};
 use crate::libs::lazy_init::Lazy;
 use crate::smp::core::smp_get_processor_id;
-use crate::time::Instant;
+use crate::time::timekeeping::ktime_get_ns;
 use alloc::{collections::BTreeMap, sync::Arc};
 use core::ffi::c_void;
 use system_error::SystemError;
@@ -301,8 +303,45 @@ pub fn map_peek_elem(map: &Arc<BpfMap>, value: &mut [u8]) -> Result<()> {
     value
 }
 
+/// See https://ebpf-docs.dylanreimerink.nl/linux/helper-function/bpf_get_stack/
+unsafe fn raw_bpf_get_stack(ctx: *mut c_void, buf: *mut c_void, size: u32, flags: u64) -> i64 {
+    let ctx = &*(ctx as *const KProbeContext);
+    let buf = core::slice::from_raw_parts_mut(buf as *mut u8, size as usize);
+    match bpf_get_stack(ctx, buf, flags) {
+        Ok(len) => len as i64,
+        Err(e) => e.to_posix_errno() as i64,
+    }
+}
+
+/// 从kprobe命中时的寄存器现场回溯内核调用栈，把各级返回地址（u64）写入`buf`
+///
+/// flags的低8位表示跳过的栈帧数。目前只支持内核栈
+///
+/// ## 返回值
+///
+/// 写入`buf`的字节数
+pub fn bpf_get_stack(ctx: &KProbeContext, buf: &mut [u8], flags: u64) -> Result<usize> {
+    // 除了跳过的栈帧数之外，其他标志（包括BPF_F_USER_STACK）都不支持
+    if flags & !(BPF_F_SKIP_FIELD_MASK as u64) != 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let skip = (flags & BPF_F_SKIP_FIELD_MASK as u64) as usize;
+    let (pc, fp) = ctx.pc_fp();
+    let mut len = 0;
+    for (slot, frame) in buf
+        .chunks_exact_mut(core::mem::size_of::<u64>())
+        .zip(StackUnwinder::from_regs(pc, fp).skip(skip))
+    {
+        slot.copy_from_slice(&(frame.pc as u64).to_ne_bytes());
+        len += slot.len();
+    }
+    // 与Linux一致，剩余的部分清零
+    buf[len..].fill(0);
+    Ok(len)
+}
+
 pub fn bpf_ktime_get_ns() -> u64 {
-    (Instant::now().total_micros() * 1000) as u64
+    ktime_get_ns()
 }
 
 pub static BPF_HELPER_FUN_SET: Lazy<BTreeMap<u32, RawBPFHelperFn>> = Lazy::new();
@@ -336,6 +375,8 @@ pub fn init_helper_functions() {
         map.insert(HELPER_BPF_PROBE_READ, define_func!(raw_bpf_probe_read));
         // Print helpers
         map.insert(HELPER_TRACE_PRINTF, define_func!(trace_printf));
+        // Probe and trace helpers::Stack helpers
+        map.insert(HELPER_GET_STACK, define_func!(raw_bpf_get_stack));
 
         // Map helpers::Queue and stack helpers
         map.insert(HELPER_MAP_PUSH_ELEM, define_func!(raw_map_push_elem));
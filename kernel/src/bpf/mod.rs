@@ -1,7 +1,9 @@
+pub mod classic;
 pub mod helper;
 pub mod map;
 pub mod prog;
 use crate::include::bindings::linux_bpf::{bpf_attr, bpf_cmd};
+use crate::process::{cred::CAPFlags, ProcessManager};
 use crate::syscall::user_access::UserBufferReader;
 use crate::syscall::Syscall;
 use log::error;
@@ -12,6 +14,11 @@ type Result<T> = core::result::Result<T, SystemError>;
 
 impl Syscall {
     pub fn sys_bpf(cmd: u32, attr: *mut u8, size: u32) -> Result<usize> {
+        let cred = ProcessManager::current_pcb().cred();
+        if !cred.has_capability(CAPFlags::CAP_BPF) && !cred.has_capability(CAPFlags::CAP_SYS_ADMIN)
+        {
+            return Err(SystemError::EPERM);
+        }
         let buf = UserBufferReader::new(attr, size as usize, true)?;
         let attr = buf.read_one_from_user::<bpf_attr>(0)?;
         let cmd = bpf_cmd::from_u32(cmd).ok_or(SystemError::EINVAL)?;
@@ -0,0 +1,531 @@
+//! cgroup2文件系统
+//!
+//! 每个目录对应一个cgroup，目录中的接口文件用于查看和修改cgroup的状态。
+//! 整个系统只有一棵cgroup树，每次挂载得到的都是同一个实例。
+
+use core::any::Any;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use log::info;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::MMArch,
+    driver::base::{device::device_number::DeviceNumber, kobject::KObject, kset::KSet},
+    filesystem::vfs::{
+        core::generate_inode_id, file::FileMode, syscall::ModeType, utils::DName, FilePrivateData,
+        FileSystem, FileSystemMaker, FileSystemMakerData, FileType, FsInfo, IndexNode, InodeId,
+        Magic, Metadata, SuperBlock, FSMAKER, ROOT_INODE,
+    },
+    init::initcall::INITCALL_FS,
+    libs::spinlock::SpinLockGuard,
+    mm::MemoryManagementArch,
+    process::Pid,
+    time::NSEC_PER_USEC,
+};
+
+use super::{cgroup_root, cpu, freezer, memory, Cgroup, CgroupControllers, CGROUP_MAX_NAMELEN};
+
+const CGROUP_BLOCK_SIZE: u64 = 4096;
+
+/// cgroup目录中的接口文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupFileType {
+    Procs,
+    Controllers,
+    SubtreeControl,
+    Events,
+    Freeze,
+    CpuStat,
+    CpuWeight,
+    CpuMax,
+    MemoryCurrent,
+    MemoryMax,
+    MemoryEvents,
+}
+
+impl CgroupFileType {
+    pub const ALL: &'static [CgroupFileType] = &[
+        CgroupFileType::Procs,
+        CgroupFileType::Controllers,
+        CgroupFileType::SubtreeControl,
+        CgroupFileType::Events,
+        CgroupFileType::Freeze,
+        CgroupFileType::CpuStat,
+        CgroupFileType::CpuWeight,
+        CgroupFileType::CpuMax,
+        CgroupFileType::MemoryCurrent,
+        CgroupFileType::MemoryMax,
+        CgroupFileType::MemoryEvents,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            CgroupFileType::Procs => "cgroup.procs",
+            CgroupFileType::Controllers => "cgroup.controllers",
+            CgroupFileType::SubtreeControl => "cgroup.subtree_control",
+            CgroupFileType::Events => "cgroup.events",
+            CgroupFileType::Freeze => "cgroup.freeze",
+            CgroupFileType::CpuStat => "cpu.stat",
+            CgroupFileType::CpuWeight => "cpu.weight",
+            CgroupFileType::CpuMax => "cpu.max",
+            CgroupFileType::MemoryCurrent => "memory.current",
+            CgroupFileType::MemoryMax => "memory.max",
+            CgroupFileType::MemoryEvents => "memory.events",
+        }
+    }
+
+    fn writable(&self) -> bool {
+        !matches!(
+            self,
+            CgroupFileType::Controllers
+                | CgroupFileType::Events
+                | CgroupFileType::CpuStat
+                | CgroupFileType::MemoryCurrent
+                | CgroupFileType::MemoryEvents
+        )
+    }
+
+    /// 文件是否出现在`cgroup`的目录中
+    fn visible(&self, cgroup: &Cgroup) -> bool {
+        match self {
+            CgroupFileType::Events | CgroupFileType::Freeze => !cgroup.is_root(),
+            CgroupFileType::CpuWeight | CgroupFileType::CpuMax => cgroup.cpu_enabled(),
+            CgroupFileType::MemoryCurrent
+            | CgroupFileType::MemoryMax
+            | CgroupFileType::MemoryEvents => cgroup.mem_enabled(),
+            _ => true,
+        }
+    }
+}
+
+/// cgroup目录中的一个接口文件
+#[derive(Debug)]
+pub struct CgroupFile {
+    cgroup: Weak<Cgroup>,
+    file_type: CgroupFileType,
+    inode_id: InodeId,
+}
+
+impl CgroupFile {
+    pub(super) fn new(cgroup: Weak<Cgroup>, file_type: CgroupFileType) -> Self {
+        CgroupFile {
+            cgroup,
+            file_type,
+            inode_id: generate_inode_id(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.file_type.name()
+    }
+
+    fn cgroup(&self) -> Result<Arc<Cgroup>, SystemError> {
+        self.cgroup.upgrade().ok_or(SystemError::ENODEV)
+    }
+
+    fn content(&self) -> Result<String, SystemError> {
+        let cgroup = self.cgroup()?;
+        let content = match self.file_type {
+            CgroupFileType::Procs => cgroup
+                .procs()
+                .iter()
+                .map(|pid| alloc::format!("{}\n", pid.data()))
+                .collect(),
+            CgroupFileType::Controllers => line(cgroup.controllers().to_names()),
+            CgroupFileType::SubtreeControl => line(cgroup.subtree_control().to_names()),
+            CgroupFileType::Events => freezer::events_string(&cgroup),
+            CgroupFileType::Freeze => freezer::freeze_string(&cgroup),
+            CgroupFileType::CpuStat => {
+                let mut s = alloc::format!(
+                    "usage_usec {}\n",
+                    cgroup.cpu().usage() / NSEC_PER_USEC as u64
+                );
+                if cgroup.cpu_enabled() {
+                    let (nr_periods, nr_throttled, throttled_usec) = cgroup.cpu().bandwidth_stat();
+                    s.push_str(&alloc::format!(
+                        "nr_periods {}\nnr_throttled {}\nthrottled_usec {}\n",
+                        nr_periods,
+                        nr_throttled,
+                        throttled_usec
+                    ));
+                }
+                s
+            }
+            CgroupFileType::CpuWeight => alloc::format!("{}\n", cgroup.cpu().weight()),
+            CgroupFileType::CpuMax => cgroup.cpu().max_string(),
+            CgroupFileType::MemoryCurrent => {
+                alloc::format!("{}\n", cgroup.memory().usage() * MMArch::PAGE_SIZE)
+            }
+            CgroupFileType::MemoryMax => cgroup.memory().max_string(),
+            CgroupFileType::MemoryEvents => cgroup.memory().events_string(),
+        };
+        return Ok(content);
+    }
+
+    fn write(&self, buf: &str) -> Result<(), SystemError> {
+        let cgroup = self.cgroup()?;
+        match self.file_type {
+            CgroupFileType::Procs => {
+                let pid = buf
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| SystemError::EINVAL)?;
+                cgroup.attach(Pid::new(pid))
+            }
+            CgroupFileType::SubtreeControl => {
+                let mut enable = CgroupControllers::empty();
+                let mut disable = CgroupControllers::empty();
+                for token in buf.split_whitespace() {
+                    let (enabling, name) = if let Some(name) = token.strip_prefix('+') {
+                        (true, name)
+                    } else if let Some(name) = token.strip_prefix('-') {
+                        (false, name)
+                    } else {
+                        return Err(SystemError::EINVAL);
+                    };
+                    let controller =
+                        CgroupControllers::from_name(name).ok_or(SystemError::EINVAL)?;
+                    if enabling {
+                        enable.insert(controller);
+                        disable.remove(controller);
+                    } else {
+                        disable.insert(controller);
+                        enable.remove(controller);
+                    }
+                }
+                cgroup.update_subtree_control(enable, disable)
+            }
+            CgroupFileType::Freeze => freezer::write_freeze(&cgroup, buf),
+            CgroupFileType::CpuWeight => cpu::write_weight(&cgroup, buf),
+            CgroupFileType::CpuMax => cpu::write_max(&cgroup, buf),
+            CgroupFileType::MemoryMax => memory::write_max(&cgroup, buf),
+            CgroupFileType::Controllers
+            | CgroupFileType::Events
+            | CgroupFileType::CpuStat
+            | CgroupFileType::MemoryCurrent
+            | CgroupFileType::MemoryEvents => Err(SystemError::EACCES),
+        }
+    }
+}
+
+/// 以换行结尾的一行内容
+fn line(s: String) -> String {
+    s + "\n"
+}
+
+impl IndexNode for CgroupFile {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        if mode.accmode() != FileMode::O_RDONLY.bits() && !self.file_type.writable() {
+            return Err(SystemError::EACCES);
+        }
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let content = self.content()?;
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return Ok(0);
+        }
+        let len = len.min(buf.len()).min(content.len() - offset);
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        return Ok(len);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let len = len.min(buf.len());
+        let s = core::str::from_utf8(&buf[..len]).map_err(|_| SystemError::EINVAL)?;
+        self.write(s)?;
+        return Ok(len);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let cgroup = self.cgroup()?;
+        let mode = if self.file_type.writable() {
+            0o644
+        } else {
+            0o444
+        };
+        Ok(Metadata {
+            dev_id: 0,
+            inode_id: self.inode_id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: cgroup.ctime,
+            mtime: cgroup.ctime,
+            ctime: cgroup.ctime,
+            file_type: FileType::File,
+            mode: ModeType::from_bits_truncate(mode),
+            nlinks: 1,
+            uid: 0,
+            gid: 0,
+            raw_dev: DeviceNumber::default(),
+        })
+    }
+
+    fn set_metadata(&self, _metadata: &Metadata) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn resize(&self, _len: usize) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn truncate(&self, _len: usize) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        cgroupfs().clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.name().into())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        Ok(self.cgroup()?)
+    }
+}
+
+impl IndexNode for Cgroup {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EISDIR)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EISDIR)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(Metadata {
+            dev_id: 0,
+            inode_id: self.inode_id,
+            size: 0,
+            blk_size: 0,
+            blocks: 0,
+            atime: self.ctime,
+            mtime: self.ctime,
+            ctime: self.ctime,
+            file_type: FileType::Dir,
+            mode: ModeType::from_bits_truncate(0o755),
+            nlinks: 2 + self.inner().children.len(),
+            uid: 0,
+            gid: 0,
+            raw_dev: DeviceNumber::default(),
+        })
+    }
+
+    fn set_metadata(&self, _metadata: &Metadata) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// 在cgroup中只能创建目录，即子cgroup
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        _mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if file_type != FileType::Dir {
+            return Err(SystemError::EPERM);
+        }
+        Ok(self.create_child(name)?)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        match name {
+            "" | "." => return Ok(self.self_arc()),
+            ".." => return Ok(self.parent_cgroup().unwrap_or_else(|| self.self_arc())),
+            _ => {}
+        }
+        if let Some(file) = self
+            .files
+            .iter()
+            .find(|f| f.name() == name && f.file_type.visible(self))
+        {
+            return Ok(file.clone());
+        }
+        self.child(name)
+            .map(|c| c as Arc<dyn IndexNode>)
+            .ok_or(SystemError::ENOENT)
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        self.remove_child(name)
+    }
+
+    fn unlink(&self, _name: &str) -> Result<(), SystemError> {
+        Err(SystemError::EPERM)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        cgroupfs().clone()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let mut keys = vec![String::from("."), String::from("..")];
+        keys.extend(
+            self.files
+                .iter()
+                .filter(|f| f.file_type.visible(self))
+                .map(|f| f.name().to_string()),
+        );
+        keys.extend(self.children_names());
+        Ok(keys)
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.name.as_str().into())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        Ok(self.parent_cgroup().unwrap_or_else(|| self.self_arc()))
+    }
+}
+
+/// cgroup2文件系统
+#[derive(Debug)]
+pub struct CgroupFS;
+
+impl CgroupFS {
+    /// 同一个系统中只有一棵cgroup树，每次挂载得到的都是同一个实例
+    pub fn make_cgroupfs(
+        _data: Option<&dyn FileSystemMakerData>,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        Ok(cgroupfs().clone())
+    }
+}
+
+impl FileSystem for CgroupFS {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        cgroup_root().clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            blk_dev_id: 0,
+            max_name_len: CGROUP_MAX_NAMELEN,
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "cgroup2"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        SuperBlock::new(
+            Magic::CGROUP2_MAGIC,
+            CGROUP_BLOCK_SIZE,
+            CGROUP_MAX_NAMELEN as u64,
+        )
+    }
+}
+
+#[distributed_slice(FSMAKER)]
+static CGROUPFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "cgroup2",
+    &(CgroupFS::make_cgroupfs
+        as fn(
+            Option<&dyn FileSystemMakerData>,
+        ) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+lazy_static! {
+    static ref CGROUP_FS: Arc<CgroupFS> = Arc::new(CgroupFS);
+}
+
+/// 系统中唯一的cgroup2文件系统
+pub fn cgroupfs() -> &'static Arc<CgroupFS> {
+    &CGROUP_FS
+}
+
+/// 创建`/sys/fs/cgroup`并把cgroup2文件系统挂载在这里
+#[unified_init(INITCALL_FS)]
+#[inline(never)]
+pub fn cgroup_init() -> Result<(), SystemError> {
+    let fs_kset = KSet::new("fs".to_string());
+    fs_kset.register(None).expect("register fs kset failed");
+    fs_kset.inode().ok_or(SystemError::ENOENT)?.add_dir(
+        "cgroup".to_string(),
+        ModeType::from_bits_truncate(0o755),
+        None,
+        None,
+    )?;
+
+    ROOT_INODE()
+        .lookup("/sys/fs/cgroup")?
+        .mount(cgroupfs().clone())
+        .expect("Failed to mount cgroup2");
+    info!("cgroup2 mounted.");
+    Ok(())
+}
@@ -0,0 +1,441 @@
+//! cgroup v2的cpu控制器
+//!
+//! - `cpu.weight`: 同一父cgroup下，各个子cgroup按照权重分配cpu时间。
+//!   由于CFS中所有调度实体的负载权重都相同，这里通过放大cgroup中进程的虚拟运行时间的增长速度来实现：
+//!   进程所在的cgroup中可运行的进程越多、权重越小，它的vruntime增长得越快。
+//! - `cpu.max`: 每个周期内，cgroup（包括后代）中的进程最多能运行quota的时间。
+//!   超出之后，cgroup被限流，其中的进程在返回用户态之前睡眠，直到下一个周期开始。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sched/fair.c
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use alloc::{
+    boxed::Box,
+    string::String,
+    sync::{Arc, Weak},
+};
+use system_error::SystemError;
+
+use crate::{
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    process::{ProcessControlBlock, ProcessFlags, ProcessManager},
+    time::{
+        timekeeping::ktime_get_ns,
+        timer::{next_n_us_timer_jiffies, Timer, TimerFunction},
+        NSEC_PER_USEC,
+    },
+};
+
+use super::Cgroup;
+
+/// `cpu.weight`的默认值
+pub const CGROUP_WEIGHT_DFL: u64 = 100;
+pub const CGROUP_WEIGHT_MIN: u64 = 1;
+pub const CGROUP_WEIGHT_MAX: u64 = 10000;
+
+/// `cpu.max`的默认周期（us）
+const CPU_MAX_PERIOD_DFL_US: u64 = 100_000;
+/// 周期的最小值（us）
+const CPU_MAX_PERIOD_MIN_US: u64 = 1_000;
+/// 周期的最大值（us）
+const CPU_MAX_PERIOD_MAX_US: u64 = 1_000_000;
+/// quota的最小值（us）
+const CPU_MAX_QUOTA_MIN_US: u64 = 1_000;
+
+#[derive(Debug)]
+struct CpuBandwidth {
+    /// 每个周期内可以运行的时间（ns），None表示不限制
+    quota: Option<u64>,
+    /// 周期（ns）
+    period: u64,
+    /// 当前周期内已经运行的时间（ns）
+    runtime: u64,
+    throttled: bool,
+    /// 开始限流的时间（ns）
+    throttled_at: u64,
+    nr_periods: u64,
+    nr_throttled: u64,
+    /// 被限流的总时长（ns）
+    throttled_time: u64,
+    /// 周期定时器，cgroup空闲时会停止
+    timer: Option<Arc<Timer>>,
+    /// 每次修改`cpu.max`都会加一，用于让旧的定时器失效
+    generation: u64,
+}
+
+impl CpuBandwidth {
+    const fn new() -> Self {
+        CpuBandwidth {
+            quota: None,
+            period: CPU_MAX_PERIOD_DFL_US * NSEC_PER_USEC as u64,
+            runtime: 0,
+            throttled: false,
+            throttled_at: 0,
+            nr_periods: 0,
+            nr_throttled: 0,
+            throttled_time: 0,
+            timer: None,
+            generation: 0,
+        }
+    }
+
+    fn stop_timer(&mut self) {
+        self.generation += 1;
+        if let Some(timer) = self.timer.take() {
+            timer.cancel();
+        }
+    }
+
+    fn start_timer(&mut self, cgroup: Weak<Cgroup>) {
+        if self.timer.is_some() {
+            return;
+        }
+        let timer = Timer::new(
+            CpuPeriodTimerFunc::new(cgroup, self.generation),
+            next_n_us_timer_jiffies(self.period / NSEC_PER_USEC as u64),
+        );
+        timer.activate();
+        self.timer = Some(timer);
+    }
+
+    /// 解除限流，返回是否需要唤醒等待的进程
+    fn unthrottle(&mut self) -> bool {
+        if !self.throttled {
+            return false;
+        }
+        self.throttled = false;
+        self.throttled_time += ktime_get_ns().saturating_sub(self.throttled_at);
+        return true;
+    }
+}
+
+/// cgroup中与cpu控制器相关的状态
+#[derive(Debug)]
+pub struct CpuCgroup {
+    weight: AtomicU64,
+    /// cgroup（包括后代）中可运行的进程数
+    nr_running: AtomicUsize,
+    /// 有可运行进程的子cgroup的权重之和
+    active_weight: AtomicU64,
+    /// cgroup（包括后代）中的进程运行的总时长（ns）
+    usage: AtomicU64,
+    bandwidth: SpinLock<CpuBandwidth>,
+    /// 因限流而睡眠的进程
+    throttle_wait: WaitQueue,
+}
+
+impl CpuCgroup {
+    pub fn new() -> Self {
+        CpuCgroup {
+            weight: AtomicU64::new(CGROUP_WEIGHT_DFL),
+            nr_running: AtomicUsize::new(0),
+            active_weight: AtomicU64::new(0),
+            usage: AtomicU64::new(0),
+            bandwidth: SpinLock::new(CpuBandwidth::new()),
+            throttle_wait: WaitQueue::default(),
+        }
+    }
+
+    pub fn weight(&self) -> u64 {
+        self.weight.load(Ordering::SeqCst)
+    }
+
+    pub fn usage(&self) -> u64 {
+        self.usage.load(Ordering::SeqCst)
+    }
+
+    /// `cpu.max`的内容
+    pub fn max_string(&self) -> String {
+        let bw = self.bandwidth.lock_irqsave();
+        let period = bw.period / NSEC_PER_USEC as u64;
+        match bw.quota {
+            Some(quota) => alloc::format!("{} {}\n", quota / NSEC_PER_USEC as u64, period),
+            None => alloc::format!("max {}\n", period),
+        }
+    }
+
+    /// `cpu.stat`中与限流相关的内容：(nr_periods, nr_throttled, throttled_usec)
+    pub fn bandwidth_stat(&self) -> (u64, u64, u64) {
+        let bw = self.bandwidth.lock_irqsave();
+        let mut throttled_time = bw.throttled_time;
+        if bw.throttled {
+            throttled_time += ktime_get_ns().saturating_sub(bw.throttled_at);
+        }
+        (
+            bw.nr_periods,
+            bw.nr_throttled,
+            throttled_time / NSEC_PER_USEC as u64,
+        )
+    }
+}
+
+impl Default for CpuCgroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 修改cgroup的权重，同时更新父cgroup中有可运行进程的子cgroup的权重之和
+fn set_weight(cgroup: &Cgroup, weight: u64) {
+    let old = cgroup.cpu.weight.swap(weight, Ordering::SeqCst);
+    if old == weight || cgroup.cpu.nr_running.load(Ordering::SeqCst) == 0 {
+        return;
+    }
+    if let Some(parent) = cgroup.parent_cgroup() {
+        let active = &parent.cpu.active_weight;
+        if weight > old {
+            active.fetch_add(weight - old, Ordering::SeqCst);
+        } else {
+            active
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |w| {
+                    Some(w.saturating_sub(old - weight))
+                })
+                .ok();
+        }
+    }
+}
+
+/// 恢复默认的`cpu.weight`和`cpu.max`
+pub(super) fn reset(cgroup: &Cgroup) {
+    set_weight(cgroup, CGROUP_WEIGHT_DFL);
+    let mut bw = cgroup.cpu.bandwidth.lock_irqsave();
+    bw.stop_timer();
+    bw.quota = None;
+    bw.period = CPU_MAX_PERIOD_DFL_US * NSEC_PER_USEC as u64;
+    bw.runtime = 0;
+    let wake = bw.unthrottle();
+    drop(bw);
+    if wake {
+        cgroup.cpu.throttle_wait.wakeup_all(None);
+    }
+}
+
+/// # 写入`cpu.weight`
+pub fn write_weight(cgroup: &Cgroup, buf: &str) -> Result<(), SystemError> {
+    let weight = buf.trim().parse::<u64>().map_err(|_| SystemError::EINVAL)?;
+    if !(CGROUP_WEIGHT_MIN..=CGROUP_WEIGHT_MAX).contains(&weight) {
+        return Err(SystemError::ERANGE);
+    }
+    set_weight(cgroup, weight);
+    return Ok(());
+}
+
+/// # 写入`cpu.max`
+///
+/// 格式为`$MAX $PERIOD`，`$MAX`为`max`表示不限制，`$PERIOD`可以省略，单位都是us
+pub fn write_max(cgroup: &Cgroup, buf: &str) -> Result<(), SystemError> {
+    let mut tokens = buf.split_whitespace();
+    let max = tokens.next().ok_or(SystemError::EINVAL)?;
+    let period = tokens.next();
+    if tokens.next().is_some() {
+        return Err(SystemError::EINVAL);
+    }
+
+    let quota = if max == "max" {
+        None
+    } else {
+        let quota = max.parse::<u64>().map_err(|_| SystemError::EINVAL)?;
+        if quota < CPU_MAX_QUOTA_MIN_US {
+            return Err(SystemError::EINVAL);
+        }
+        Some(quota)
+    };
+
+    let mut bw = cgroup.cpu.bandwidth.lock_irqsave();
+    let period = match period {
+        Some(p) => p.parse::<u64>().map_err(|_| SystemError::EINVAL)?,
+        None => bw.period / NSEC_PER_USEC as u64,
+    };
+    if !(CPU_MAX_PERIOD_MIN_US..=CPU_MAX_PERIOD_MAX_US).contains(&period) {
+        return Err(SystemError::EINVAL);
+    }
+
+    bw.stop_timer();
+    bw.quota = quota.map(|q| q * NSEC_PER_USEC as u64);
+    bw.period = period * NSEC_PER_USEC as u64;
+    bw.runtime = 0;
+    let wake = bw.unthrottle();
+    drop(bw);
+
+    if wake {
+        cgroup.cpu.throttle_wait.wakeup_all(None);
+    }
+    return Ok(());
+}
+
+/// cgroup及其祖先的可运行进程数加一
+pub(super) fn inc_nr_running(cgroup: &Arc<Cgroup>) {
+    let mut cg = Some(cgroup.clone());
+    while let Some(c) = cg {
+        let parent = c.parent_cgroup();
+        let prev = c.cpu.nr_running.fetch_add(1, Ordering::SeqCst);
+        if prev == 0 {
+            if let Some(parent) = parent.as_ref() {
+                parent
+                    .cpu
+                    .active_weight
+                    .fetch_add(c.cpu.weight(), Ordering::SeqCst);
+            }
+        }
+        cg = parent;
+    }
+}
+
+/// cgroup及其祖先的可运行进程数减一
+pub(super) fn dec_nr_running(cgroup: &Arc<Cgroup>) {
+    let mut cg = Some(cgroup.clone());
+    while let Some(c) = cg {
+        let parent = c.parent_cgroup();
+        let prev = c
+            .cpu
+            .nr_running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                Some(n.saturating_sub(1))
+            })
+            .unwrap();
+        if prev == 1 {
+            if let Some(parent) = parent.as_ref() {
+                let weight = c.cpu.weight();
+                parent
+                    .cpu
+                    .active_weight
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |w| {
+                        Some(w.saturating_sub(weight))
+                    })
+                    .ok();
+            }
+        }
+        cg = parent;
+    }
+}
+
+/// # 根据进程所在cgroup的权重，缩放进程的虚拟运行时间的增量
+///
+/// 对于启用了cpu控制器的最深的cgroup C，其中的进程平分C的cpu时间；
+/// 每一层cgroup按照自身权重占有效兄弟cgroup权重之和的比例，分得父cgroup的cpu时间；
+/// 顶层cgroup与根cgroup中的进程竞争，根cgroup中的每个进程相当于权重为默认值的cgroup。
+pub fn scale_delta_fair(pcb: &Arc<ProcessControlBlock>, delta: u64) -> u64 {
+    let mut cg = pcb.task_cgroup().cgroup();
+    while !cg.cpu_enabled() {
+        match cg.parent_cgroup() {
+            Some(parent) => cg = parent,
+            None => return delta,
+        }
+    }
+
+    let mut delta = delta as u128 * cg.cpu.nr_running.load(Ordering::SeqCst).max(1) as u128;
+    loop {
+        let parent = cg.parent_cgroup().unwrap();
+        let weight = cg.cpu.weight() as u128;
+        if parent.is_root() {
+            delta = delta * CGROUP_WEIGHT_DFL as u128 / weight;
+            break;
+        }
+        let active = (parent.cpu.active_weight.load(Ordering::SeqCst) as u128).max(weight);
+        delta = delta * active / weight;
+        cg = parent;
+    }
+
+    return delta.min(u64::MAX as u128) as u64;
+}
+
+/// # 把进程的运行时间计入它所在的cgroup及其祖先
+///
+/// 如果某个cgroup在本周期内的运行时间超过了quota，就限流这个cgroup，
+/// 并让当前进程在返回用户态之前睡眠
+pub fn account_runtime(pcb: &Arc<ProcessControlBlock>, delta: u64) {
+    let mut cg = Some(pcb.task_cgroup().cgroup());
+    let mut throttled = false;
+    while let Some(c) = cg {
+        c.cpu.usage.fetch_add(delta, Ordering::SeqCst);
+        if c.cpu_enabled() {
+            let mut bw = c.cpu.bandwidth.lock_irqsave();
+            if let Some(quota) = bw.quota {
+                bw.runtime += delta;
+                if bw.runtime >= quota && !bw.throttled {
+                    bw.throttled = true;
+                    bw.throttled_at = ktime_get_ns();
+                    bw.nr_throttled += 1;
+                }
+                throttled |= bw.throttled;
+                bw.start_timer(Arc::downgrade(&c));
+            }
+        }
+        cg = c.parent_cgroup();
+    }
+
+    if throttled && !pcb.flags().contains(ProcessFlags::KTHREAD) {
+        pcb.flags().insert(ProcessFlags::CPU_THROTTLED);
+    }
+}
+
+/// # 返回用户态之前，如果当前进程所在的cgroup被限流，就睡眠直到限流解除
+pub fn throttle_current() {
+    let pcb = ProcessManager::current_pcb();
+    pcb.flags().remove(ProcessFlags::CPU_THROTTLED);
+
+    'retry: loop {
+        let mut cg = Some(pcb.task_cgroup().cgroup());
+        while let Some(c) = cg {
+            if c.cpu_enabled() {
+                let bw = c.cpu.bandwidth.lock_irqsave();
+                if bw.throttled {
+                    c.cpu
+                        .throttle_wait
+                        .sleep_uninterruptible_unlock_spinlock(bw);
+                    continue 'retry;
+                }
+            }
+            cg = c.parent_cgroup();
+        }
+        break;
+    }
+}
+
+/// `cpu.max`的周期定时器：补充运行时间，解除限流
+#[derive(Debug)]
+struct CpuPeriodTimerFunc {
+    cgroup: Weak<Cgroup>,
+    generation: u64,
+}
+
+impl CpuPeriodTimerFunc {
+    fn new(cgroup: Weak<Cgroup>, generation: u64) -> Box<Self> {
+        Box::new(CpuPeriodTimerFunc { cgroup, generation })
+    }
+}
+
+impl TimerFunction for CpuPeriodTimerFunc {
+    fn run(&mut self) -> Result<(), SystemError> {
+        let cgroup = match self.cgroup.upgrade() {
+            Some(cgroup) => cgroup,
+            None => return Ok(()),
+        };
+        let mut bw = cgroup.cpu.bandwidth.lock_irqsave();
+        if bw.generation != self.generation {
+            return Ok(());
+        }
+        let quota = match bw.quota {
+            Some(quota) => quota,
+            None => return Ok(()),
+        };
+        bw.timer = None;
+
+        let idle = bw.runtime == 0 && cgroup.cpu.nr_running.load(Ordering::SeqCst) == 0;
+        bw.nr_periods += 1;
+        // 上个周期超出的部分从本周期扣除
+        bw.runtime = bw.runtime.saturating_sub(quota);
+        let wake = bw.runtime < quota && bw.unthrottle();
+        // cgroup空闲时停止定时器，直到再次有进程运行
+        if !idle || bw.throttled {
+            bw.start_timer(self.cgroup.clone());
+        }
+        drop(bw);
+
+        if wake {
+            cgroup.cpu.throttle_wait.wakeup_all(None);
+        }
+        return Ok(());
+    }
+}
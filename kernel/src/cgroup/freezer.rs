@@ -0,0 +1,87 @@
+//! cgroup v2的冻结
+//!
+//! 向非根cgroup的`cgroup.freeze`写入1，冻结cgroup及其后代中的所有进程；写入0解冻。
+//! 进程被冻结的方式见[`crate::process::freezer`]。在冻结期间迁入的进程和fork出的进程同样会被冻结，
+//! 迁出的进程则被解冻。`cgroup.events`中的`frozen`在所有进程都停下之后变为1。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/cgroup/freezer.c
+
+use core::sync::atomic::Ordering;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::process::{freezer, ProcessControlBlock};
+
+use super::Cgroup;
+
+/// cgroup及其祖先中是否有cgroup设置了`cgroup.freeze`
+pub fn cgroup_freezing(cgroup: &Arc<Cgroup>) -> bool {
+    let mut cg = Some(cgroup.clone());
+    while let Some(c) = cg {
+        if c.freeze.load(Ordering::SeqCst) {
+            return true;
+        }
+        cg = c.parent_cgroup();
+    }
+    return false;
+}
+
+/// cgroup及其后代中的所有进程
+fn subtree_tasks(cgroup: &Arc<Cgroup>) -> Vec<Arc<ProcessControlBlock>> {
+    let mut tasks = Vec::new();
+    let mut stack = alloc::vec![cgroup.clone()];
+    while let Some(c) = stack.pop() {
+        let inner = c.inner();
+        tasks.extend(inner.tasks.values().filter_map(|t| t.upgrade()));
+        stack.extend(inner.children.values().cloned());
+    }
+    return tasks;
+}
+
+/// `cgroup.freeze`文件的内容
+pub fn freeze_string(cgroup: &Cgroup) -> String {
+    alloc::format!("{}\n", cgroup.freeze.load(Ordering::SeqCst) as u8)
+}
+
+/// # 写入`cgroup.freeze`
+///
+/// 只接受0和1
+pub fn write_freeze(cgroup: &Arc<Cgroup>, buf: &str) -> Result<(), SystemError> {
+    let freeze = match buf.trim() {
+        "0" => false,
+        "1" => true,
+        _ => return Err(SystemError::EINVAL),
+    };
+    if cgroup.freeze.swap(freeze, Ordering::SeqCst) == freeze {
+        return Ok(());
+    }
+
+    if freeze {
+        for task in subtree_tasks(cgroup) {
+            freezer::freeze_task(&task);
+        }
+    } else {
+        // 祖先仍然被冻结的进程会继续睡眠
+        freezer::thaw();
+    }
+    return Ok(());
+}
+
+/// `cgroup.events`文件的内容
+pub fn events_string(cgroup: &Arc<Cgroup>) -> String {
+    let tasks = subtree_tasks(cgroup);
+    let frozen = cgroup_freezing(cgroup) && tasks.iter().all(freezer::frozen);
+    alloc::format!(
+        "populated {}\nfrozen {}\n",
+        !tasks.is_empty() as u8,
+        frozen as u8
+    )
+}
+
+/// 进程迁入`dst`或者fork出来之后，使它的冻结状态与所在的cgroup一致
+pub(super) fn sync_task(pcb: &Arc<ProcessControlBlock>) {
+    if !freezer::freeze_task(pcb) && freezer::frozen(pcb) {
+        freezer::thaw();
+    }
+}
@@ -1,6 +0,0 @@
-use super::CgroupSubsysState;
-
-struct MemCgroup {
-    css: CgroupSubsysState,
-    id: u32,
-}
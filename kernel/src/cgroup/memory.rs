@@ -0,0 +1,319 @@
+//! cgroup v2的memory控制器
+//!
+//! 非kthread进程创建的物理页（包括匿名页、写时复制的页和页缓存）都计入它所在的cgroup及其祖先的`memory.current`，
+//! 页面记住计费的cgroup，释放时再从这些cgroup中减去。进程迁移之后，已经分配的页面仍然计入原来的cgroup。
+//!
+//! 页面是在持有页面管理器的锁时创建的，不能在那里回收内存，所以超出`memory.max`时分配并不会失败，
+//! 而是标记当前进程，由它在返回用户态之前处理：先回收cgroup中干净并且没有被映射的页缓存，
+//! 如果仍然超出限制，就杀死cgroup中占用内存最多的进程。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/mm/memcontrol.c
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use alloc::{collections::BTreeSet, string::String, sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    arch::{
+        ipc::signal::{SigCode, Signal},
+        MMArch,
+    },
+    ipc::signal_types::{SigInfo, SigType},
+    mm::{page::page_reclaimer_lock_irqsave, MemoryManagementArch},
+    process::{ProcessControlBlock, ProcessFlags, ProcessManager},
+    time::{sleep::nanosleep, PosixTimeSpec},
+};
+
+use super::Cgroup;
+
+/// 等待被杀死的进程释放内存时，每次睡眠的时间（ns）
+const OOM_WAIT_NS: i64 = 10_000_000;
+
+/// cgroup中与memory控制器相关的状态，以页为单位
+#[derive(Debug)]
+pub struct MemCgroup {
+    /// cgroup（包括后代）中的页面数
+    usage: AtomicUsize,
+    /// `memory.max`，usize::MAX表示不限制
+    max: AtomicUsize,
+    /// 使用量超出`memory.max`的次数
+    nr_max: AtomicU64,
+    /// 回收之后仍然超出`memory.max`的次数
+    nr_oom: AtomicU64,
+    /// 因为超出`memory.max`而被杀死的进程数
+    nr_oom_kill: AtomicU64,
+}
+
+impl MemCgroup {
+    pub fn new() -> Self {
+        MemCgroup {
+            usage: AtomicUsize::new(0),
+            max: AtomicUsize::new(usize::MAX),
+            nr_max: AtomicU64::new(0),
+            nr_oom: AtomicU64::new(0),
+            nr_oom_kill: AtomicU64::new(0),
+        }
+    }
+
+    /// 已经使用的页数
+    #[inline]
+    pub fn usage(&self) -> usize {
+        self.usage.load(Ordering::SeqCst)
+    }
+
+    /// 最多可以使用的页数
+    #[inline]
+    pub fn max(&self) -> usize {
+        self.max.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    fn over_limit(&self) -> bool {
+        self.usage() > self.max()
+    }
+
+    /// `memory.max`文件的内容
+    pub fn max_string(&self) -> String {
+        match self.max() {
+            usize::MAX => String::from("max\n"),
+            max => alloc::format!("{}\n", max * MMArch::PAGE_SIZE),
+        }
+    }
+
+    /// `memory.events`文件的内容
+    pub fn events_string(&self) -> String {
+        alloc::format!(
+            "max {}\noom {}\noom_kill {}\n",
+            self.nr_max.load(Ordering::SeqCst),
+            self.nr_oom.load(Ordering::SeqCst),
+            self.nr_oom_kill.load(Ordering::SeqCst)
+        )
+    }
+}
+
+impl Default for MemCgroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 禁用memory控制器时，恢复默认的限制
+pub(super) fn reset(cgroup: &Cgroup) {
+    cgroup.memory.max.store(usize::MAX, Ordering::SeqCst);
+}
+
+/// # 写入`memory.max`
+///
+/// 格式为`max`或者字节数，字节数向上对齐到页大小
+pub fn write_max(cgroup: &Cgroup, buf: &str) -> Result<(), SystemError> {
+    let buf = buf.trim();
+    let max = if buf == "max" {
+        usize::MAX
+    } else {
+        let bytes = buf.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+        bytes.div_ceil(MMArch::PAGE_SIZE)
+    };
+    cgroup.memory.max.store(max, Ordering::SeqCst);
+
+    // 调小限制之后，由下一次分配页面的进程负责回收
+    return Ok(());
+}
+
+/// # 把一个新创建的页面计入当前进程所在的cgroup
+///
+/// ## 返回值
+///
+/// 计费的cgroup，页面释放时需要用它调用[`uncharge`]。内核线程和根cgroup中的进程不计费，返回None
+pub fn charge_current() -> Option<Arc<Cgroup>> {
+    if !ProcessManager::initialized() {
+        return None;
+    }
+    let pcb = ProcessManager::current_pcb();
+    if pcb.flags().contains(ProcessFlags::KTHREAD) {
+        return None;
+    }
+    let cgroup = pcb.task_cgroup().cgroup();
+    if cgroup.is_root() {
+        return None;
+    }
+
+    let mut over = false;
+    let mut cg = Some(cgroup.clone());
+    while let Some(c) = cg {
+        let parent = c.parent_cgroup();
+        // 根cgroup不计费
+        if parent.is_none() {
+            break;
+        }
+        let usage = c.memory.usage.fetch_add(1, Ordering::SeqCst) + 1;
+        if c.mem_enabled() && usage > c.memory.max() {
+            c.memory.nr_max.fetch_add(1, Ordering::SeqCst);
+            over = true;
+        }
+        cg = parent;
+    }
+
+    if over {
+        pcb.flags().insert(ProcessFlags::MEMCG_OVER_LIMIT);
+    }
+    return Some(cgroup);
+}
+
+/// 页面释放时，从计费的cgroup及其祖先中减去
+pub fn uncharge(cgroup: &Arc<Cgroup>) {
+    let mut cg = Some(cgroup.clone());
+    while let Some(c) = cg {
+        let parent = c.parent_cgroup();
+        if parent.is_none() {
+            break;
+        }
+        c.memory.usage.fetch_sub(1, Ordering::SeqCst);
+        cg = parent;
+    }
+}
+
+/// `cgroup`是否为`ancestor`或者它的后代
+pub fn is_descendant_of(cgroup: &Arc<Cgroup>, ancestor: &Cgroup) -> bool {
+    let mut cg = Some(cgroup.clone());
+    while let Some(c) = cg {
+        if core::ptr::eq(c.as_ref(), ancestor) {
+            return true;
+        }
+        cg = c.parent_cgroup();
+    }
+    return false;
+}
+
+/// 找到`cgroup`及其祖先中超出了`memory.max`的cgroup
+fn over_limit_cgroup(cgroup: Arc<Cgroup>) -> Option<Arc<Cgroup>> {
+    let mut cg = Some(cgroup);
+    while let Some(c) = cg {
+        if c.mem_enabled() && c.memory.over_limit() {
+            return Some(c);
+        }
+        cg = c.parent_cgroup();
+    }
+    return None;
+}
+
+enum OomVictim {
+    Kill(Arc<ProcessControlBlock>),
+    /// 有进程已经在退出，等待它释放内存
+    Wait,
+    None,
+}
+
+/// 进程的用户地址空间中已经映射了物理页的页数
+fn resident_pages(pcb: &Arc<ProcessControlBlock>) -> usize {
+    let Some(vm) = pcb.basic().user_vm() else {
+        return 0;
+    };
+    let guard = vm.read();
+    let mut count = 0;
+    for vma in guard.mappings.iter_vmas() {
+        let region = *vma.lock_irqsave().region();
+        count += region
+            .pages()
+            .filter(|page| {
+                guard
+                    .user_mapper
+                    .utable
+                    .translate(page.virt_address())
+                    .is_some()
+            })
+            .count();
+    }
+    return count;
+}
+
+/// # 在`memcg`（包括后代）中选择要杀死的进程
+///
+/// 选择占用物理页最多的线程组
+fn select_victim(memcg: &Arc<Cgroup>) -> OomVictim {
+    let mut tasks = Vec::new();
+    let mut stack = alloc::vec![memcg.clone()];
+    while let Some(c) = stack.pop() {
+        let inner = c.inner();
+        tasks.extend(inner.tasks.values().filter_map(|t| t.upgrade()));
+        stack.extend(inner.children.values().cloned());
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut dying = false;
+    let mut victim: Option<(usize, Arc<ProcessControlBlock>)> = None;
+    for task in tasks {
+        if task.flags().contains(ProcessFlags::KTHREAD) {
+            continue;
+        }
+        let exited = task
+            .sched_info()
+            .inner_lock_read_irqsave()
+            .state()
+            .is_exited();
+        if exited || Signal::fatal_signal_pending(&task) {
+            dying = true;
+            continue;
+        }
+        if !seen.insert(task.tgid()) {
+            continue;
+        }
+        let pages = resident_pages(&task);
+        if victim.as_ref().is_none_or(|(max, _)| pages > *max) {
+            victim = Some((pages, task));
+        }
+    }
+
+    match victim {
+        Some((_, pcb)) => OomVictim::Kill(pcb),
+        None if dying => OomVictim::Wait,
+        None => OomVictim::None,
+    }
+}
+
+/// # 返回用户态之前，如果当前进程所在的cgroup超出了`memory.max`，就回收内存，必要时杀死进程
+pub fn handle_over_limit() {
+    let pcb = ProcessManager::current_pcb();
+    pcb.flags().remove(ProcessFlags::MEMCG_OVER_LIMIT);
+
+    loop {
+        let Some(memcg) = over_limit_cgroup(pcb.task_cgroup().cgroup()) else {
+            return;
+        };
+        let excess = memcg.memory.usage().saturating_sub(memcg.memory.max());
+        page_reclaimer_lock_irqsave().shrink_memcg(&memcg, excess);
+        if !memcg.memory.over_limit() {
+            continue;
+        }
+        if Signal::fatal_signal_pending(&pcb) {
+            return;
+        }
+
+        memcg.memory.nr_oom.fetch_add(1, Ordering::SeqCst);
+        match select_victim(&memcg) {
+            OomVictim::Kill(victim) => {
+                let tgid = victim.tgid();
+                log::warn!(
+                    "Memory cgroup out of memory: killed process {} ({})",
+                    tgid.data(),
+                    victim.basic().name()
+                );
+                let mut info = SigInfo::new(
+                    Signal::SIGKILL,
+                    0,
+                    SigCode::Kernel,
+                    SigType::Kill(ProcessManager::current_pid()),
+                );
+                let _ = Signal::SIGKILL.send_signal_info(Some(&mut info), tgid);
+                memcg.memory.nr_oom_kill.fetch_add(1, Ordering::SeqCst);
+                if tgid == pcb.tgid() {
+                    return;
+                }
+            }
+            OomVictim::Wait => {}
+            // cgroup中的内存不属于任何进程（例如共享内存），无法通过杀死进程释放
+            OomVictim::None => return,
+        }
+        let _ = nanosleep(PosixTimeSpec::new(0, OOM_WAIT_NS));
+    }
+}
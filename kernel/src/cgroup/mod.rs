@@ -1,48 +1,450 @@
-#![allow(dead_code, unused_variables, unused_imports)]
-pub mod mem_cgroup;
+//! cgroup v2
+//!
+//! 所有的cgroup组成一棵树，每个进程属于且只属于其中的一个cgroup，fork出的子进程与父进程在同一个cgroup中。
+//! 这棵树通过cgroup2文件系统呈现给用户态：目录对应cgroup，`cgroup.procs`用于迁移进程，
+//! `cgroup.subtree_control`用于为子cgroup启用控制器。
+//!
+//! 目前实现了cpu控制器和memory控制器，分别见[`cpu`]和[`memory`]；
+//! 非根cgroup还可以通过`cgroup.freeze`冻结其中的所有进程，见[`freezer`]。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/cgroup/cgroup.c
 
-use alloc::{collections::LinkedList, rc::Weak, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
 
-use alloc::boxed::Box;
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
 
-use crate::filesystem::vfs::IndexNode;
+use crate::{
+    filesystem::vfs::{core::generate_inode_id, InodeId},
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    process::{cred::CAPFlags, Pid, ProcessControlBlock, ProcessManager},
+    time::PosixTimeSpec,
+};
 
+use self::{
+    cgroupfs::{CgroupFile, CgroupFileType},
+    cpu::CpuCgroup,
+    memory::MemCgroup,
+};
+
+pub mod cgroupfs;
+pub mod cpu;
+pub mod freezer;
+pub mod memory;
+
+/// cgroup名称的最大长度
+const CGROUP_MAX_NAMELEN: usize = 255;
+
+bitflags! {
+    /// cgroup v2的控制器
+    pub struct CgroupControllers: u32 {
+        const CPU = 1 << 0;
+        const MEMORY = 1 << 1;
+    }
+}
+
+impl CgroupControllers {
+    /// 控制器的名称，与`cgroup.controllers`中的名称相同
+    const NAMES: &'static [(CgroupControllers, &'static str)] = &[
+        (CgroupControllers::CPU, "cpu"),
+        (CgroupControllers::MEMORY, "memory"),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(c, _)| *c)
+    }
+
+    /// 以空格分隔的控制器名称列表
+    fn to_names(self) -> String {
+        Self::NAMES
+            .iter()
+            .filter(|(c, _)| self.contains(*c))
+            .map(|(_, n)| *n)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Debug)]
+struct InnerCgroup {
+    children: BTreeMap<String, Arc<Cgroup>>,
+    /// 为子cgroup启用的控制器
+    subtree_control: CgroupControllers,
+    /// 属于此cgroup的进程（包括线程），不包括子cgroup中的进程
+    tasks: BTreeMap<Pid, Weak<ProcessControlBlock>>,
+}
+
+/// 一个cgroup，同时也是cgroup2文件系统中的一个目录
+#[derive(Debug)]
 pub struct Cgroup {
-    css: Weak<CgroupSubsysState>,
-    /// 当前所在的深度
-    level: u32,
-    /// 支持的最大深度
-    max_depth: u32,
-    /// 可见后代数量
-    nr_descendants: u32,
-    /// 正在死亡后代数量
-    nr_dying_descendants: u32,
-    /// 允许的最大后代数量
-    max_descendants: u32,
-    /// css_set的数量
-    nr_populated_csets: u32,
-    /// 子group中有任务的记数
-    nr_populated_domain_children: u32,
-    /// 线程子group中有任务的记数
-    nr_populated_threaded_children: u32,
-    /// 活跃线程子cgroup数量
-    nr_threaded_children: u32,
-    /// 关联cgroup的inode
-    kernfs_node: Box<dyn IndexNode>,
-}
-
-/// 控制资源的统计信息
-pub struct CgroupSubsysState {
+    name: String,
+    parent: Weak<Cgroup>,
+    inner: SpinLock<InnerCgroup>,
+    /// 父cgroup是否为此cgroup启用了cpu控制器
+    cpu_enabled: AtomicBool,
+    /// 父cgroup是否为此cgroup启用了memory控制器
+    mem_enabled: AtomicBool,
+    /// 已经被rmdir删除
+    dead: AtomicBool,
+    /// `cgroup.freeze`
+    freeze: AtomicBool,
+    cpu: CpuCgroup,
+    memory: MemCgroup,
+    /// 目录中的接口文件
+    files: Vec<Arc<CgroupFile>>,
+    inode_id: InodeId,
+    ctime: PosixTimeSpec,
+    self_ref: Weak<Cgroup>,
+}
+
+impl Cgroup {
+    fn new(name: &str, parent: Option<&Arc<Cgroup>>) -> Arc<Self> {
+        Arc::new_cyclic(|self_ref| Cgroup {
+            name: name.to_string(),
+            parent: parent.map(Arc::downgrade).unwrap_or_default(),
+            inner: SpinLock::new(InnerCgroup {
+                children: BTreeMap::new(),
+                subtree_control: CgroupControllers::empty(),
+                tasks: BTreeMap::new(),
+            }),
+            cpu_enabled: AtomicBool::new(false),
+            mem_enabled: AtomicBool::new(false),
+            dead: AtomicBool::new(false),
+            freeze: AtomicBool::new(false),
+            cpu: CpuCgroup::new(),
+            memory: MemCgroup::new(),
+            files: CgroupFileType::ALL
+                .iter()
+                .map(|t| Arc::new(CgroupFile::new(self_ref.clone(), *t)))
+                .collect(),
+            inode_id: generate_inode_id(),
+            ctime: PosixTimeSpec::now(),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    pub fn self_arc(&self) -> Arc<Cgroup> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    pub fn parent_cgroup(&self) -> Option<Arc<Cgroup>> {
+        self.parent.upgrade()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.parent.upgrade().is_none()
+    }
+
+    pub fn cpu(&self) -> &CpuCgroup {
+        &self.cpu
+    }
+
+    pub fn memory(&self) -> &MemCgroup {
+        &self.memory
+    }
+
+    /// 父cgroup是否为此cgroup启用了cpu控制器，根cgroup总是返回false
+    #[inline]
+    pub fn cpu_enabled(&self) -> bool {
+        self.cpu_enabled.load(Ordering::SeqCst)
+    }
+
+    /// 父cgroup是否为此cgroup启用了memory控制器，根cgroup总是返回false
+    #[inline]
+    pub fn mem_enabled(&self) -> bool {
+        self.mem_enabled.load(Ordering::SeqCst)
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerCgroup> {
+        self.inner.lock_irqsave()
+    }
+
+    pub fn subtree_control(&self) -> CgroupControllers {
+        self.inner().subtree_control
+    }
+
+    /// 此cgroup中可以使用的控制器，即父cgroup的`cgroup.subtree_control`
+    pub fn controllers(&self) -> CgroupControllers {
+        match self.parent_cgroup() {
+            Some(parent) => parent.subtree_control(),
+            None => CgroupControllers::all(),
+        }
+    }
+
+    fn child(&self, name: &str) -> Option<Arc<Cgroup>> {
+        self.inner().children.get(name).cloned()
+    }
+
+    fn children_names(&self) -> Vec<String> {
+        self.inner().children.keys().cloned().collect()
+    }
+
+    /// 此cgroup中进程的线程组id，按从小到大的顺序排列
+    pub fn procs(&self) -> Vec<Pid> {
+        let mut procs = self
+            .inner()
+            .tasks
+            .values()
+            .filter_map(|t| t.upgrade())
+            .map(|t| t.tgid())
+            .collect::<Vec<_>>();
+        procs.sort();
+        procs.dedup();
+        procs
+    }
+
+    fn has_tasks(&self) -> bool {
+        self.inner().tasks.values().any(|t| t.strong_count() > 0)
+    }
+
+    /// # 创建子cgroup
+    pub fn create_child(&self, name: &str) -> Result<Arc<Cgroup>, SystemError> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(SystemError::EINVAL);
+        }
+        if name.len() > CGROUP_MAX_NAMELEN {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+        if self.dead.load(Ordering::SeqCst) {
+            return Err(SystemError::ENOENT);
+        }
+        // 与接口文件重名
+        if self.files.iter().any(|f| f.name() == name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        let child = Cgroup::new(name, Some(&self.self_arc()));
+        let mut inner = self.inner();
+        if inner.children.contains_key(name) {
+            return Err(SystemError::EEXIST);
+        }
+        child.cpu_enabled.store(
+            inner.subtree_control.contains(CgroupControllers::CPU),
+            Ordering::SeqCst,
+        );
+        child.mem_enabled.store(
+            inner.subtree_control.contains(CgroupControllers::MEMORY),
+            Ordering::SeqCst,
+        );
+        inner.children.insert(name.to_string(), child.clone());
+        return Ok(child);
+    }
+
+    /// # 删除子cgroup
+    ///
+    /// 只有没有子cgroup并且没有进程的cgroup可以被删除
+    pub fn remove_child(&self, name: &str) -> Result<(), SystemError> {
+        let mut inner = self.inner();
+        let child = inner.children.get(name).ok_or(SystemError::ENOENT)?.clone();
+        if !child.inner().children.is_empty() || child.has_tasks() {
+            return Err(SystemError::EBUSY);
+        }
+        inner.children.remove(name);
+        drop(inner);
+
+        child.dead.store(true, Ordering::SeqCst);
+        cpu::reset(&child);
+        memory::reset(&child);
+        return Ok(());
+    }
+
+    /// # 修改`cgroup.subtree_control`
+    ///
+    /// - `enable`: 要为子cgroup启用的控制器，必须在此cgroup的`cgroup.controllers`中
+    /// - `disable`: 要为子cgroup禁用的控制器，子cgroup还在为后代使用的控制器不能被禁用
+    ///
+    /// 非根cgroup中有进程时不能启用控制器（no internal process约束）
+    pub fn update_subtree_control(
+        &self,
+        enable: CgroupControllers,
+        disable: CgroupControllers,
+    ) -> Result<(), SystemError> {
+        if !self.controllers().contains(enable) {
+            return Err(SystemError::ENOENT);
+        }
+
+        let mut inner = self.inner();
+        let new = (inner.subtree_control | enable) - disable;
+        if new == inner.subtree_control {
+            return Ok(());
+        }
+        let enabling = new - inner.subtree_control;
+        let disabling = inner.subtree_control - new;
+        if !enabling.is_empty()
+            && !self.is_root()
+            && inner.tasks.values().any(|t| t.strong_count() > 0)
+        {
+            return Err(SystemError::EBUSY);
+        }
+        if inner
+            .children
+            .values()
+            .any(|c| c.subtree_control().intersects(disabling))
+        {
+            return Err(SystemError::EBUSY);
+        }
+        inner.subtree_control = new;
+
+        if enabling.contains(CgroupControllers::CPU) || disabling.contains(CgroupControllers::CPU) {
+            let enabled = new.contains(CgroupControllers::CPU);
+            for child in inner.children.values() {
+                child.cpu_enabled.store(enabled, Ordering::SeqCst);
+                if !enabled {
+                    // 禁用控制器之后，再次启用时使用默认的配置
+                    cpu::reset(child);
+                }
+            }
+        }
+        if enabling.contains(CgroupControllers::MEMORY)
+            || disabling.contains(CgroupControllers::MEMORY)
+        {
+            let enabled = new.contains(CgroupControllers::MEMORY);
+            for child in inner.children.values() {
+                child.mem_enabled.store(enabled, Ordering::SeqCst);
+                if !enabled {
+                    memory::reset(child);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    /// # 把线程组`pid`中的所有线程迁移到此cgroup
+    ///
+    /// `pid`为0时迁移当前进程
+    pub fn attach(&self, pid: Pid) -> Result<(), SystemError> {
+        let current = ProcessManager::current_pcb();
+        let target = if pid.data() == 0 {
+            current.clone()
+        } else {
+            ProcessManager::find(pid).ok_or(SystemError::ESRCH)?
+        };
+        if target.is_kthread() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let cred = current.cred();
+        let tcred = target.cred();
+        if !cred.has_capability(CAPFlags::CAP_SYS_ADMIN)
+            && cred.euid != tcred.uid
+            && cred.euid != tcred.suid
+        {
+            return Err(SystemError::EACCES);
+        }
+
+        if self.dead.load(Ordering::SeqCst) {
+            return Err(SystemError::ENODEV);
+        }
+        if !self.is_root() && !self.subtree_control().is_empty() {
+            return Err(SystemError::EBUSY);
+        }
+
+        let tgid = target.tgid();
+        let src = target.task_cgroup().cgroup();
+        let mut threads = src
+            .inner()
+            .tasks
+            .values()
+            .filter_map(|t| t.upgrade())
+            .filter(|t| t.tgid() == tgid)
+            .collect::<Vec<_>>();
+        if !threads.iter().any(|t| Arc::ptr_eq(t, &target)) {
+            threads.push(target);
+        }
+
+        let dst = self.self_arc();
+        for thread in threads {
+            thread.task_cgroup().migrate(&thread, &dst);
+            freezer::sync_task(&thread);
+        }
+        return Ok(());
+    }
+}
+
+/// 进程所属的cgroup
+#[derive(Debug)]
+pub struct TaskCgroup {
     cgroup: Arc<Cgroup>,
-    /// 兄弟节点
-    sibling: LinkedList<Arc<Cgroup>>,
-    /// 孩子节点
-    children: LinkedList<Arc<Cgroup>>,
+    /// 是否已经计入cgroup（及其祖先）的可运行进程数
+    runnable: bool,
+    /// 进程已经退出，不再属于任何cgroup的进程列表
+    exited: bool,
+}
+
+impl TaskCgroup {
+    pub fn new(cgroup: Arc<Cgroup>) -> Self {
+        TaskCgroup {
+            cgroup,
+            runnable: false,
+            exited: false,
+        }
+    }
+
+    pub fn cgroup(&self) -> Arc<Cgroup> {
+        self.cgroup.clone()
+    }
+
+    /// 进程进入或者离开CFS运行队列时调用，维护cgroup的可运行进程数
+    pub fn set_runnable(&mut self, runnable: bool) {
+        if self.runnable == runnable {
+            return;
+        }
+        self.runnable = runnable;
+        if runnable {
+            cpu::inc_nr_running(&self.cgroup);
+        } else {
+            cpu::dec_nr_running(&self.cgroup);
+        }
+    }
+
+    fn migrate(&mut self, pcb: &Arc<ProcessControlBlock>, dst: &Arc<Cgroup>) {
+        if self.exited || Arc::ptr_eq(&self.cgroup, dst) {
+            return;
+        }
+        if self.runnable {
+            cpu::dec_nr_running(&self.cgroup);
+            cpu::inc_nr_running(dst);
+        }
+        self.cgroup.inner().tasks.remove(&pcb.pid());
+        dst.inner().tasks.insert(pcb.pid(), Arc::downgrade(pcb));
+        self.cgroup = dst.clone();
+    }
 }
 
-pub struct CgroupSubsys {}
+lazy_static! {
+    static ref CGROUP_ROOT: Arc<Cgroup> = Cgroup::new("", None);
+}
+
+/// 根cgroup
+pub fn cgroup_root() -> &'static Arc<Cgroup> {
+    &CGROUP_ROOT
+}
+
+/// fork完成之后，把新进程加入它所在cgroup的进程列表
+pub fn cgroup_post_fork(pcb: &Arc<ProcessControlBlock>) {
+    let task_cgroup = pcb.task_cgroup();
+    task_cgroup
+        .cgroup
+        .inner()
+        .tasks
+        .insert(pcb.pid(), Arc::downgrade(pcb));
+    drop(task_cgroup);
+
+    // 在被冻结的cgroup中fork出的进程同样被冻结
+    freezer::sync_task(pcb);
+}
 
-/// cgroup_sub_state 的集合
-pub struct CssSet {
-    subsys: Vec<Arc<CgroupSubsysState>>,
+/// 进程退出时，把它从所在cgroup的进程列表中删除
+pub fn cgroup_exit(pcb: &Arc<ProcessControlBlock>) {
+    let mut task_cgroup = pcb.task_cgroup();
+    task_cgroup.exited = true;
+    task_cgroup.cgroup.inner().tasks.remove(&pcb.pid());
 }
@@ -0,0 +1,149 @@
+//! execve时的文件描述符泄漏审计
+//!
+//! 没有设置O_CLOEXEC的fd会在execve之后被新程序继承。如果这不是有意为之，
+//! 被继承的socket会一直占用着端口，被继承的管道写端会让读端永远等不到EOF。
+//!
+//! 打开审计后，每次execve关闭了close-on-exec的fd之后，都会把仍然保留下来的fd
+//! （标准输入、输出和错误除外）打印到内核日志中，并记录到报告里。
+//!
+//! 控制文件位于debugfs的`/sys/kernel/debug/exec_fd_audit/`下：
+//!
+//! - `enable`: 是否开启审计（0/1）
+//! - `report`: 最近的审计记录（只读），向`enable`写入1时清空
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+};
+use log::warn;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    filesystem::{
+        debugfs::{debugfs_create_dir, debugfs_create_file, DEBUGFS_MODE_RO, DEBUGFS_MODE_RW},
+        vfs::{
+            file::{File, FileDescriptorVec},
+            FileType,
+        },
+    },
+    init::initcall::INITCALL_POSTCORE,
+    libs::spinlock::SpinLock,
+    net::socket::SocketInode,
+    process::ProcessManager,
+};
+
+/// 报告中最多保留的记录条数，超出后丢弃最旧的记录
+const EXEC_FD_AUDIT_MAX_RECORDS: usize = 128;
+
+/// 小于该值的fd（标准输入、输出和错误）通常是有意继承的，不做审计
+const EXEC_FD_AUDIT_FIRST_FD: i32 = 3;
+
+static EXEC_FD_AUDIT_ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref EXEC_FD_AUDIT_RECORDS: SpinLock<VecDeque<String>> = SpinLock::new(VecDeque::new());
+}
+
+/// 审计execve之后仍然保留的fd
+///
+/// 应当在关闭了close-on-exec的fd之后调用。审计未开启时直接返回
+pub fn exec_fd_audit(fd_table: &FileDescriptorVec) {
+    if !EXEC_FD_AUDIT_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let pcb = ProcessManager::current_pcb();
+    let pid = pcb.pid();
+    let name = pcb.basic().name().to_string();
+
+    for (fd, file) in fd_table.iter() {
+        if fd < EXEC_FD_AUDIT_FIRST_FD {
+            continue;
+        }
+        let record = format!(
+            "pid {:?} ({}): fd {} inherited across exec: {}",
+            pid,
+            name,
+            fd,
+            describe_file(&file)
+        );
+        warn!("exec_fd_audit: {}", record);
+
+        let mut records = EXEC_FD_AUDIT_RECORDS.lock_irqsave();
+        if records.len() >= EXEC_FD_AUDIT_MAX_RECORDS {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+}
+
+/// 生成fd所指向文件的描述。socket会附带本端和对端的地址，其他文件附带路径
+fn describe_file(file: &Arc<File>) -> String {
+    let file_type = file.file_type();
+    if file_type == FileType::Socket {
+        if let Some(socket) = file.inode().as_any_ref().downcast_ref::<SocketInode>() {
+            let socket = socket.inner();
+            return format!(
+                "socket {:?} local={:?} peer={:?}",
+                socket.metadata().socket_type,
+                socket.endpoint(),
+                socket.peer_endpoint()
+            );
+        }
+    }
+
+    match file.inode().absolute_path() {
+        Ok(path) => format!("{:?} {}", file_type, path),
+        Err(_) => format!("{:?}", file_type),
+    }
+}
+
+/// 在debugfs中创建审计的控制文件
+#[unified_init(INITCALL_POSTCORE)]
+fn exec_fd_audit_init() -> Result<(), SystemError> {
+    let dir = debugfs_create_dir("exec_fd_audit", None)?;
+    let dir = Some(&dir);
+
+    debugfs_create_file(
+        "enable",
+        DEBUGFS_MODE_RW,
+        dir,
+        Some(Box::new(|| {
+            Ok(format!(
+                "{}\n",
+                EXEC_FD_AUDIT_ENABLED.load(Ordering::Relaxed) as usize
+            ))
+        })),
+        Some(Box::new(|s: &str| {
+            let v = s.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+            if v != 0 {
+                EXEC_FD_AUDIT_RECORDS.lock_irqsave().clear();
+            }
+            EXEC_FD_AUDIT_ENABLED.store(v != 0, Ordering::Relaxed);
+            Ok(())
+        })),
+    )?;
+    debugfs_create_file(
+        "report",
+        DEBUGFS_MODE_RO,
+        dir,
+        Some(Box::new(|| {
+            let records = EXEC_FD_AUDIT_RECORDS.lock_irqsave();
+            let mut s = String::new();
+            for record in records.iter() {
+                s.push_str(record);
+                s.push('\n');
+            }
+            Ok(s)
+        })),
+        None,
+    )?;
+
+    return Ok(());
+}
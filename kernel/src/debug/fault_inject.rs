@@ -0,0 +1,379 @@
+//! 故障注入框架
+//!
+//! 让kmalloc、块设备IO等操作按照配置的概率失败，用于测试各个子系统的错误处理路径。
+//!
+//! 每个故障点在debugfs的`/sys/kernel/debug/<name>/`下有一组控制文件：
+//!
+//! - `probability`: 注入概率（百分比，0表示关闭）
+//! - `interval`: 每interval次调用才考虑注入一次
+//! - `times`: 剩余可注入次数，-1表示不限次数
+//! - `space`: 在开始注入之前允许通过的"额度"（kmalloc为字节数，块设备IO为块数）
+//! - `verbose`: 注入时是否打印日志
+//! - `filter`: 调用栈过滤，只有调用栈中存在名字包含该字符串的函数时才会注入
+//! - `injected`: 已注入的次数（只读）
+//!
+//! kmalloc（`failslab`）由GlobalAlloc注入，而大部分分配（如`Box::new`、`Vec::push`）失败时
+//! 会直接panic，所以`failslab`必须设置`filter`才会注入：只应让能够处理分配失败的调用点
+//! （如使用`try_reserve`的函数）失败。`filter`为空时`failslab`不注入。
+//!
+//! 也可以通过内核命令行参数`failslab=<interval>,<probability>,<space>,<times>`以及
+//! `fail_make_request=<interval>,<probability>,<space>,<times>`在启动时配置。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/lib/fault-inject.c
+
+use core::{
+    intrinsics::likely,
+    sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering},
+};
+
+use alloc::{boxed::Box, format};
+use log::{info, warn};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::rand::rand,
+    debug::{kallsyms::lookup_symbol, traceback::StackUnwinder},
+    filesystem::debugfs::{
+        debugfs_create_dir, debugfs_create_file, DEBUGFS_MODE_RO, DEBUGFS_MODE_RW,
+    },
+    init::initcall::INITCALL_POSTCORE,
+    libs::spinlock::SpinLock,
+};
+
+/// 调用栈过滤字符串的最大长度
+const FAULT_FILTER_MAX_LEN: usize = 64;
+/// 匹配调用栈过滤时最多回溯的栈帧数
+const FAULT_STACKTRACE_DEPTH: usize = 32;
+
+kernel_cmdline_param_kv!(FAILSLAB_PARAM, failslab, "");
+kernel_cmdline_param_kv!(FAIL_MAKE_REQUEST_PARAM, fail_make_request, "");
+
+/// kmalloc的故障点，只在调用栈匹配`filter`时注入
+pub static FAIL_KMALLOC: FaultAttr = FaultAttr::new_filtered("failslab");
+/// 块设备IO的故障点
+pub static FAIL_BLOCK_IO: FaultAttr = FaultAttr::new("fail_make_request");
+
+/// 正在打印注入信息（打印时可能分配内存，此时不再注入，避免递归）
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug)]
+struct FaultFilter {
+    buf: [u8; FAULT_FILTER_MAX_LEN],
+    len: usize,
+}
+
+impl FaultFilter {
+    const fn new() -> Self {
+        Self {
+            buf: [0; FAULT_FILTER_MAX_LEN],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+/// 一个故障点的配置以及统计信息
+#[derive(Debug)]
+pub struct FaultAttr {
+    name: &'static str,
+    probability: AtomicUsize,
+    interval: AtomicUsize,
+    times: AtomicIsize,
+    space: AtomicIsize,
+    verbose: AtomicBool,
+    /// 经过概率判断之前的调用次数
+    count: AtomicUsize,
+    /// 已注入的次数
+    injected: AtomicUsize,
+    filter: SpinLock<FaultFilter>,
+    /// 为true时，`filter`为空则不注入
+    require_filter: bool,
+}
+
+impl FaultAttr {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            probability: AtomicUsize::new(0),
+            interval: AtomicUsize::new(1),
+            times: AtomicIsize::new(-1),
+            space: AtomicIsize::new(0),
+            verbose: AtomicBool::new(false),
+            count: AtomicUsize::new(0),
+            injected: AtomicUsize::new(0),
+            filter: SpinLock::new(FaultFilter::new()),
+            require_filter: false,
+        }
+    }
+
+    /// 创建一个只在调用栈匹配`filter`时才注入的故障点，用于调用者不一定能处理失败的地方
+    pub const fn new_filtered(name: &'static str) -> Self {
+        let mut attr = Self::new(name);
+        attr.require_filter = true;
+        attr
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// 判断本次调用是否应当失败
+    ///
+    /// ## 参数
+    ///
+    /// - `size`: 本次操作的大小，用于消耗`space`额度
+    #[inline]
+    pub fn should_fail(&self, size: usize) -> bool {
+        // 快速路径：故障点未开启
+        if likely(self.probability.load(Ordering::Relaxed) == 0) {
+            return false;
+        }
+        self.should_fail_slow(size)
+    }
+
+    #[inline(never)]
+    fn should_fail_slow(&self, size: usize) -> bool {
+        if REPORTING.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        if self.times.load(Ordering::Relaxed) == 0 {
+            return false;
+        }
+
+        let size = size as isize;
+        if self.space.load(Ordering::Relaxed) > size {
+            self.space.fetch_sub(size, Ordering::Relaxed);
+            return false;
+        }
+
+        let interval = self.interval.load(Ordering::Relaxed);
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if interval > 1 && count % interval != 0 {
+            return false;
+        }
+
+        if self.probability.load(Ordering::Relaxed) <= rand() % 100 {
+            return false;
+        }
+
+        if !self.stacktrace_match() {
+            return false;
+        }
+
+        // times为-1时不限次数
+        if self
+            .times
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |t| match t {
+                0 => None,
+                -1 => Some(-1),
+                t => Some(t - 1),
+            })
+            .is_err()
+        {
+            return false;
+        }
+
+        self.injected.fetch_add(1, Ordering::Relaxed);
+        if self.verbose.load(Ordering::Relaxed)
+            && REPORTING
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        {
+            warn!("FAULT_INJECTION: forcing a failure at '{}'", self.name);
+            REPORTING.store(false, Ordering::Release);
+        }
+
+        return true;
+    }
+
+    /// 检查当前调用栈是否满足过滤条件
+    fn stacktrace_match(&self) -> bool {
+        let mut filter = FaultFilter::new();
+        {
+            let guard = self.filter.lock_irqsave();
+            filter.buf = guard.buf;
+            filter.len = guard.len;
+        }
+
+        if filter.len == 0 {
+            return !self.require_filter;
+        }
+
+        return stacktrace_contains(filter.as_str());
+    }
+
+    fn set_filter(&self, s: &str) -> Result<(), SystemError> {
+        let s = s.trim();
+        if s.len() > FAULT_FILTER_MAX_LEN {
+            return Err(SystemError::EINVAL);
+        }
+        let mut guard = self.filter.lock_irqsave();
+        guard.buf[..s.len()].copy_from_slice(s.as_bytes());
+        guard.len = s.len();
+        return Ok(());
+    }
+
+    /// 解析`<interval>,<probability>,<space>,<times>`格式的配置
+    fn setup(&self, s: &str) -> Result<(), SystemError> {
+        let mut it = s.split(',').map(|x| x.trim());
+        let mut next = || it.next().ok_or(SystemError::EINVAL);
+        let interval = next()?.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+        let probability = next()?.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+        let space = next()?.parse::<isize>().map_err(|_| SystemError::EINVAL)?;
+        let times = next()?.parse::<isize>().map_err(|_| SystemError::EINVAL)?;
+        if probability > 100 {
+            return Err(SystemError::EINVAL);
+        }
+
+        self.interval.store(interval.max(1), Ordering::Relaxed);
+        self.space.store(space, Ordering::Relaxed);
+        self.times.store(times, Ordering::Relaxed);
+        self.probability.store(probability, Ordering::Relaxed);
+        return Ok(());
+    }
+}
+
+fn stacktrace_contains(filter: &str) -> bool {
+    StackUnwinder::from_current()
+        .take(FAULT_STACKTRACE_DEPTH)
+        .any(|frame| lookup_symbol(frame.pc).is_some_and(|(name, _)| name.contains(filter)))
+}
+
+/// 在`/sys/kernel/debug/<name>/`下创建故障点的控制文件
+fn fault_create_debugfs_attr(attr: &'static FaultAttr) -> Result<(), SystemError> {
+    let dir = debugfs_create_dir(attr.name(), None)?;
+    let dir = Some(&dir);
+
+    debugfs_create_file(
+        "probability",
+        DEBUGFS_MODE_RW,
+        dir,
+        Some(Box::new(move || {
+            Ok(format!("{}\n", attr.probability.load(Ordering::Relaxed)))
+        })),
+        Some(Box::new(move |s: &str| {
+            let v = s.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+            if v > 100 {
+                return Err(SystemError::EINVAL);
+            }
+            attr.probability.store(v, Ordering::Relaxed);
+            Ok(())
+        })),
+    )?;
+    debugfs_create_file(
+        "interval",
+        DEBUGFS_MODE_RW,
+        dir,
+        Some(Box::new(move || {
+            Ok(format!("{}\n", attr.interval.load(Ordering::Relaxed)))
+        })),
+        Some(Box::new(move |s: &str| {
+            let v = s.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+            attr.interval.store(v.max(1), Ordering::Relaxed);
+            Ok(())
+        })),
+    )?;
+    debugfs_create_file(
+        "times",
+        DEBUGFS_MODE_RW,
+        dir,
+        Some(Box::new(move || {
+            Ok(format!("{}\n", attr.times.load(Ordering::Relaxed)))
+        })),
+        Some(Box::new(move |s: &str| {
+            let v = s.parse::<isize>().map_err(|_| SystemError::EINVAL)?;
+            attr.times.store(v, Ordering::Relaxed);
+            Ok(())
+        })),
+    )?;
+    debugfs_create_file(
+        "space",
+        DEBUGFS_MODE_RW,
+        dir,
+        Some(Box::new(move || {
+            Ok(format!("{}\n", attr.space.load(Ordering::Relaxed)))
+        })),
+        Some(Box::new(move |s: &str| {
+            let v = s.parse::<isize>().map_err(|_| SystemError::EINVAL)?;
+            attr.space.store(v, Ordering::Relaxed);
+            Ok(())
+        })),
+    )?;
+    debugfs_create_file(
+        "verbose",
+        DEBUGFS_MODE_RW,
+        dir,
+        Some(Box::new(move || {
+            Ok(format!(
+                "{}\n",
+                attr.verbose.load(Ordering::Relaxed) as usize
+            ))
+        })),
+        Some(Box::new(move |s: &str| {
+            let v = s.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+            attr.verbose.store(v != 0, Ordering::Relaxed);
+            Ok(())
+        })),
+    )?;
+    debugfs_create_file(
+        "filter",
+        DEBUGFS_MODE_RW,
+        dir,
+        Some(Box::new(move || {
+            Ok(format!("{}\n", attr.filter.lock_irqsave().as_str()))
+        })),
+        Some(Box::new(move |s: &str| attr.set_filter(s))),
+    )?;
+    debugfs_create_file(
+        "injected",
+        DEBUGFS_MODE_RO,
+        dir,
+        Some(Box::new(move || {
+            Ok(format!("{}\n", attr.injected.load(Ordering::Relaxed)))
+        })),
+        None,
+    )?;
+
+    return Ok(());
+}
+
+/// 初始化故障注入框架：解析命令行参数，并在debugfs中创建控制文件
+#[unified_init(INITCALL_POSTCORE)]
+fn fault_inject_init() -> Result<(), SystemError> {
+    for (param, attr) in [
+        (&FAILSLAB_PARAM, &FAIL_KMALLOC),
+        (&FAIL_MAKE_REQUEST_PARAM, &FAIL_BLOCK_IO),
+    ] {
+        if let Some(s) = param.value_str() {
+            if s.is_empty() {
+                continue;
+            }
+            if attr.setup(s).is_err() {
+                warn!(
+                    "Invalid fault injection parameter for '{}': {}",
+                    attr.name(),
+                    s
+                );
+            } else {
+                info!("Fault injection '{}' enabled: {}", attr.name(), s);
+            }
+        }
+    }
+
+    for attr in [&FAIL_KMALLOC, &FAIL_BLOCK_IO] {
+        fault_create_debugfs_attr(attr).inspect_err(|e| {
+            warn!(
+                "Failed to create fault injection debugfs files for '{}': {:?}",
+                attr.name(),
+                e
+            );
+        })?;
+    }
+
+    return Ok(());
+}
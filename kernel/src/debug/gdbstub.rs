@@ -0,0 +1,798 @@
+//! gdbstub：内核中的gdb远程串行协议（RSP）调试桩
+//!
+//! 用于在没有QEMU gdbserver的真实硬件上调试内核。调试桩独占一个PIO串口，可以通过以下方式启用：
+//!
+//! - 内核命令行参数`kgdboc=ttyS1[,115200]`：指定调试用的串口与波特率；
+//! - 内核命令行参数`kgdbwait`：与`kgdboc`一起使用，初始化完成后立即停下，等待gdb连接；
+//! - 向`/proc/sysrq-trigger`写入`g`：进入调试器，未配置`kgdboc`时使用ttyS1。
+//!
+//! gdb中使用`target remote /dev/ttyUSB0`连接。内核运行时收到gdb发来的Ctrl-C或数据包，
+//! 也会停下并进入调试器。
+//!
+//! 支持读写寄存器与内存、软件断点（`Z0`/`z0`）、单步执行与继续执行。调试器运行时关闭中断，
+//! 以轮询方式收发串口数据，并且不分配内存。
+//!
+//! 目前不会让其他cpu停下：它们会继续运行，遇到断点时等待当前的调试会话结束。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/debug/gdbstub.c
+
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{string::ToString, sync::Arc};
+use log::{info, warn};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::{interrupt::TrapFrame, CurrentGdbArch},
+    driver::{
+        base::device::DeviceId,
+        serial::{
+            serial8250::serial8250_pio::{serial8250_pio_port, Serial8250PIOPort},
+            BaudRate, UartPort,
+        },
+        tty::sysrq::{register_sysrq_key, SysrqKeyOp},
+    },
+    exception::{
+        irqdata::IrqHandlerData,
+        irqdesc::{IrqHandleFlags, IrqHandler, IrqReturn},
+        manage::irq_manager,
+        IrqNumber,
+    },
+    init::initcall::INITCALL_LATE,
+    libs::spinlock::SpinLock,
+    smp::{core::smp_get_processor_id, cpu::ProcessorId},
+};
+
+kernel_cmdline_param_kv!(KGDBOC_PARAM, kgdboc, "");
+kernel_cmdline_param_arg!(KGDBWAIT_PARAM, kgdbwait, false, false);
+
+/// 体系结构相关的调试操作
+pub trait GdbArch {
+    /// `g`包中寄存器的个数
+    const NUM_REGS: usize;
+    /// 软件断点指令
+    const BREAKPOINT_INSN: u8;
+
+    /// 读取寄存器，返回寄存器的值与宽度（字节）
+    fn register(frame: &TrapFrame, regno: usize) -> Option<(u64, usize)>;
+    /// 写入寄存器，寄存器不存在时返回false
+    fn set_register(frame: &mut TrapFrame, regno: usize, value: u64) -> bool;
+    fn pc(frame: &TrapFrame) -> usize;
+    fn set_pc(frame: &mut TrapFrame, pc: usize);
+    /// 断点异常发生后，由trap frame得到断点指令的地址
+    fn breakpoint_address(frame: &TrapFrame) -> usize;
+    fn set_single_step(frame: &mut TrapFrame, enable: bool);
+    /// 地址是否是`breakpoint()`执行的断点指令
+    fn is_arch_breakpoint(addr: usize) -> bool;
+    /// 执行一条断点指令，进入调试器
+    fn breakpoint();
+    /// 地址在当前页表中是否已映射（`write`为true时还要求可写），调试器只访问这样的地址
+    fn addr_accessible(addr: usize, write: bool) -> bool;
+}
+
+/// 数据包缓冲区的大小，也是通过qSupported告诉gdb的PacketSize
+const GDB_BUF_SIZE: usize = 4096;
+/// 软件断点的最大个数
+const GDB_MAX_BREAKPOINTS: usize = 32;
+/// 通过sysrq启用时默认使用的串口（ttyS1）
+const GDB_DEFAULT_PORT: usize = 1;
+/// 控制台所在的串口（ttyS0），不能用于调试
+const GDB_CONSOLE_PORT: usize = 0;
+
+/// gdb发送的中断请求（Ctrl-C）
+const GDB_INTERRUPT_CHAR: u8 = 0x03;
+
+/// 调试桩是否已经启用
+static GDBSTUB_ENABLED: AtomicBool = AtomicBool::new(false);
+/// 串口中断中已经读走了数据包的起始字符`$`
+static PACKET_START_PENDING: AtomicBool = AtomicBool::new(false);
+
+static GDB_STATE: SpinLock<GdbState> = SpinLock::new(GdbState::new());
+/// 收发数据包的缓冲区，只在持有`GDB_STATE`的锁时使用
+static GDB_BUFFERS: SpinLock<GdbBuffers> = SpinLock::new(GdbBuffers::new());
+
+static SYSRQ_GDB_OP: SysrqKeyOp = SysrqKeyOp {
+    handler: sysrq_handle_dbg,
+    help_msg: "debug(g)",
+    action_msg: "DEBUG",
+};
+
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    addr: usize,
+    /// 被断点指令覆盖的原始字节，只在`inserted`为true时有效
+    orig: u8,
+    inserted: bool,
+}
+
+struct GdbState {
+    port: Option<&'static Serial8250PIOPort>,
+    /// gdb已经连接，停下时需要主动发送停止原因
+    connected: bool,
+    /// 正在执行单步的cpu
+    step_cpu: Option<ProcessorId>,
+    /// 单步是gdb请求的，完成后进入调试器
+    stepping: bool,
+    /// 为了越过该地址的断点而单步，完成后重新插入断点
+    step_over: Option<usize>,
+    breakpoints: [Option<Breakpoint>; GDB_MAX_BREAKPOINTS],
+}
+
+struct GdbBuffers {
+    input: [u8; GDB_BUF_SIZE],
+    output: [u8; GDB_BUF_SIZE],
+}
+
+impl GdbBuffers {
+    const fn new() -> Self {
+        Self {
+            input: [0; GDB_BUF_SIZE],
+            output: [0; GDB_BUF_SIZE],
+        }
+    }
+}
+
+/// 处理完一个数据包后要做的事
+enum GdbAction {
+    /// 发送回复，继续等待下一个数据包
+    Reply,
+    /// 离开调试器，继续执行
+    Resume { step: bool },
+    /// gdb断开连接
+    Detach,
+}
+
+impl GdbState {
+    const fn new() -> Self {
+        Self {
+            port: None,
+            connected: false,
+            step_cpu: None,
+            stepping: false,
+            step_over: None,
+            breakpoints: [None; GDB_MAX_BREAKPOINTS],
+        }
+    }
+
+    fn find_breakpoint(&self, addr: usize) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .position(|bp| bp.is_some_and(|bp| bp.addr == addr))
+    }
+
+    fn breakpoint_inserted(&self, addr: usize) -> bool {
+        self.find_breakpoint(addr)
+            .is_some_and(|i| self.breakpoints[i].unwrap().inserted)
+    }
+
+    fn add_breakpoint(&mut self, addr: usize) -> Result<(), SystemError> {
+        if self.find_breakpoint(addr).is_some() {
+            return Ok(());
+        }
+        if !CurrentGdbArch::addr_accessible(addr, true) {
+            return Err(SystemError::EFAULT);
+        }
+        let slot = self
+            .breakpoints
+            .iter_mut()
+            .find(|bp| bp.is_none())
+            .ok_or(SystemError::ENOSPC)?;
+        *slot = Some(Breakpoint {
+            addr,
+            orig: 0,
+            inserted: false,
+        });
+        return Ok(());
+    }
+
+    fn remove_breakpoint(&mut self, addr: usize) {
+        if let Some(i) = self.find_breakpoint(addr) {
+            // 停下时断点已经全部从内存中移除
+            debug_assert!(!self.breakpoints[i].unwrap().inserted);
+            self.breakpoints[i] = None;
+        }
+    }
+
+    /// 把断点指令写入内存，`skip`处的断点除外
+    fn insert_breakpoints(&mut self, skip: Option<usize>) {
+        for bp in self.breakpoints.iter_mut().flatten() {
+            if bp.inserted || Some(bp.addr) == skip {
+                continue;
+            }
+            // 断点登记后映射可能发生了变化
+            if !CurrentGdbArch::addr_accessible(bp.addr, true) {
+                continue;
+            }
+            unsafe {
+                bp.orig = core::ptr::read_volatile(bp.addr as *const u8);
+                core::ptr::write_volatile(bp.addr as *mut u8, CurrentGdbArch::BREAKPOINT_INSN);
+            }
+            bp.inserted = true;
+        }
+    }
+
+    /// 恢复所有断点处的原始指令，使调试器看到的内存与没有断点时一致
+    fn remove_breakpoints(&mut self) {
+        for bp in self.breakpoints.iter_mut().flatten() {
+            if bp.inserted {
+                unsafe { core::ptr::write_volatile(bp.addr as *mut u8, bp.orig) };
+                bp.inserted = false;
+            }
+        }
+    }
+
+    /// 调试会话：停在`frame`处，处理gdb的请求，直到gdb要求继续执行
+    fn enter(&mut self, frame: &mut TrapFrame) {
+        let Some(port) = self.port else {
+            return;
+        };
+        self.remove_breakpoints();
+        self.step_cpu = None;
+        self.stepping = false;
+        self.step_over = None;
+        CurrentGdbArch::set_single_step(frame, false);
+
+        let mut buffers = GDB_BUFFERS.lock();
+        let GdbBuffers { input, output } = &mut *buffers;
+        if self.connected {
+            put_packet(port, b"S05");
+        }
+
+        loop {
+            let len = get_packet(port, input);
+            self.connected = true;
+
+            let mut out = PacketWriter::new(output);
+            match self.handle_packet(frame, &input[..len], &mut out) {
+                GdbAction::Reply => {
+                    let len = out.len;
+                    put_packet(port, &output[..len]);
+                }
+                GdbAction::Resume { step } => {
+                    self.resume(frame, step);
+                    return;
+                }
+                GdbAction::Detach => {
+                    self.breakpoints = [None; GDB_MAX_BREAKPOINTS];
+                    self.connected = false;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn resume(&mut self, frame: &mut TrapFrame, step: bool) {
+        // 当前指令上有断点时，先不插入它，单步越过之后再插入
+        let pc = CurrentGdbArch::pc(frame);
+        let step_over = self.find_breakpoint(pc).map(|_| pc);
+        self.insert_breakpoints(step_over);
+        self.stepping = step;
+        self.step_over = step_over;
+        if step || step_over.is_some() {
+            self.step_cpu = Some(smp_get_processor_id());
+            CurrentGdbArch::set_single_step(frame, true);
+        }
+    }
+
+    fn handle_packet(
+        &mut self,
+        frame: &mut TrapFrame,
+        pkt: &[u8],
+        out: &mut PacketWriter,
+    ) -> GdbAction {
+        let Some((&cmd, args)) = pkt.split_first() else {
+            return GdbAction::Reply;
+        };
+        match cmd {
+            b'?' => out.push_bytes(b"S05"),
+            b'g' => {
+                for regno in 0..CurrentGdbArch::NUM_REGS {
+                    if let Some((value, size)) = CurrentGdbArch::register(frame, regno) {
+                        out.push_hex_le(value, size);
+                    }
+                }
+            }
+            b'G' => {
+                let mut rest = args;
+                for regno in 0..CurrentGdbArch::NUM_REGS {
+                    let Some((_, size)) = CurrentGdbArch::register(frame, regno) else {
+                        continue;
+                    };
+                    if rest.len() < size * 2 {
+                        break;
+                    }
+                    let (hex, tail) = rest.split_at(size * 2);
+                    rest = tail;
+                    if let Some(value) = decode_hex_le(hex) {
+                        CurrentGdbArch::set_register(frame, regno, value);
+                    }
+                }
+                out.push_bytes(b"OK");
+            }
+            b'p' => match parse_hex(args).and_then(|n| CurrentGdbArch::register(frame, n)) {
+                Some((value, size)) => out.push_hex_le(value, size),
+                None => out.push_error(SystemError::EINVAL),
+            },
+            b'P' => {
+                let ok = split_at_byte(args, b'=')
+                    .and_then(|(n, v)| Some((parse_hex(n)?, decode_hex_le(v)?)))
+                    .is_some_and(|(n, v)| CurrentGdbArch::set_register(frame, n, v));
+                if ok {
+                    out.push_bytes(b"OK");
+                } else {
+                    out.push_error(SystemError::EINVAL);
+                }
+            }
+            b'm' => match parse_addr_len(args) {
+                Some((addr, len)) => read_memory(addr, len.min(out.remaining() / 2), out),
+                None => out.push_error(SystemError::EINVAL),
+            },
+            b'M' => {
+                let r = split_at_byte(args, b':')
+                    .and_then(|(al, data)| Some((parse_addr_len(al)?, data)))
+                    .ok_or(SystemError::EINVAL)
+                    .and_then(|((addr, len), data)| write_memory(addr, len, data));
+                match r {
+                    Ok(()) => out.push_bytes(b"OK"),
+                    Err(e) => out.push_error(e),
+                }
+            }
+            b'c' | b's' => {
+                if !args.is_empty() {
+                    match parse_hex(args) {
+                        Some(addr) => CurrentGdbArch::set_pc(frame, addr),
+                        None => {
+                            out.push_error(SystemError::EINVAL);
+                            return GdbAction::Reply;
+                        }
+                    }
+                }
+                return GdbAction::Resume { step: cmd == b's' };
+            }
+            b'Z' | b'z' => {
+                // 只支持软件断点，其他类型回复空包，gdb会改用其他方式
+                let Some(args) = args.strip_prefix(b"0,") else {
+                    return GdbAction::Reply;
+                };
+                let Some(addr) = split_at_byte(args, b',').and_then(|(a, _)| parse_hex(a)) else {
+                    out.push_error(SystemError::EINVAL);
+                    return GdbAction::Reply;
+                };
+                let r = if cmd == b'Z' {
+                    self.add_breakpoint(addr)
+                } else {
+                    self.remove_breakpoint(addr);
+                    Ok(())
+                };
+                match r {
+                    Ok(()) => out.push_bytes(b"OK"),
+                    Err(e) => out.push_error(e),
+                }
+            }
+            b'D' => {
+                if let Some(port) = self.port {
+                    put_packet(port, b"OK");
+                }
+                return GdbAction::Detach;
+            }
+            b'k' => return GdbAction::Detach,
+            b'H' | b'T' => out.push_bytes(b"OK"),
+            b'q' => {
+                if pkt.starts_with(b"qSupported") {
+                    out.push_bytes(b"PacketSize=");
+                    out.push_hex(GDB_BUF_SIZE);
+                } else if pkt.starts_with(b"qAttached") {
+                    out.push_bytes(b"1");
+                }
+            }
+            // 不支持的请求回复空包
+            _ => {}
+        }
+        GdbAction::Reply
+    }
+}
+
+/// 构造回复数据包的内容
+struct PacketWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> PacketWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.len
+    }
+
+    fn push(&mut self, c: u8) {
+        if self.len < self.buf.len() {
+            self.buf[self.len] = c;
+            self.len += 1;
+        }
+    }
+
+    fn push_bytes(&mut self, s: &[u8]) {
+        for &c in s {
+            self.push(c);
+        }
+    }
+
+    fn push_hex_byte(&mut self, b: u8) {
+        self.push(HEX_CHARS[(b >> 4) as usize]);
+        self.push(HEX_CHARS[(b & 0xf) as usize]);
+    }
+
+    /// 按小端序输出`size`字节的值，寄存器使用这种格式
+    fn push_hex_le(&mut self, value: u64, size: usize) {
+        for b in &value.to_le_bytes()[..size] {
+            self.push_hex_byte(*b);
+        }
+    }
+
+    /// 输出不含前导零的十六进制数
+    fn push_hex(&mut self, value: usize) {
+        let digits = (usize::BITS - value.leading_zeros()).div_ceil(4).max(1);
+        for i in (0..digits).rev() {
+            self.push(HEX_CHARS[(value >> (i * 4)) & 0xf]);
+        }
+    }
+
+    fn push_error(&mut self, e: SystemError) {
+        self.push(b'E');
+        self.push_hex_byte(e.to_posix_errno().unsigned_abs() as u8);
+    }
+}
+
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// 解析大端序的十六进制数，如地址与长度
+fn parse_hex(s: &[u8]) -> Option<usize> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    s.iter()
+        .try_fold(0usize, |acc, &c| Some((acc << 4) | hex_digit(c)? as usize))
+}
+
+fn decode_hex_byte(s: &[u8]) -> Option<u8> {
+    Some((hex_digit(s[0])? << 4) | hex_digit(s[1])?)
+}
+
+/// 解析小端序的十六进制字节串，如寄存器的值
+fn decode_hex_le(s: &[u8]) -> Option<u64> {
+    if s.is_empty() || s.len() % 2 != 0 || s.len() > 16 {
+        return None;
+    }
+    let mut value = 0u64;
+    for (i, pair) in s.chunks(2).enumerate() {
+        value |= (decode_hex_byte(pair)? as u64) << (i * 8);
+    }
+    Some(value)
+}
+
+fn split_at_byte(s: &[u8], sep: u8) -> Option<(&[u8], &[u8])> {
+    let pos = s.iter().position(|&c| c == sep)?;
+    Some((&s[..pos], &s[pos + 1..]))
+}
+
+/// 解析`addr,length`
+fn parse_addr_len(s: &[u8]) -> Option<(usize, usize)> {
+    let (addr, len) = split_at_byte(s, b',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+/// 检查`[addr, addr + len)`中的每一页是否都可以访问
+fn range_accessible(addr: usize, len: usize, write: bool) -> bool {
+    const PAGE_SIZE: usize = 4096;
+    let Some(end) = addr.checked_add(len) else {
+        return false;
+    };
+    let mut page = addr & !(PAGE_SIZE - 1);
+    while page < end {
+        if !CurrentGdbArch::addr_accessible(page.max(addr), write) {
+            return false;
+        }
+        page += PAGE_SIZE;
+    }
+    true
+}
+
+fn read_memory(addr: usize, len: usize, out: &mut PacketWriter) {
+    if !range_accessible(addr, len, false) {
+        out.push_error(SystemError::EFAULT);
+        return;
+    }
+    for i in 0..len {
+        let b = unsafe { core::ptr::read_volatile((addr + i) as *const u8) };
+        out.push_hex_byte(b);
+    }
+}
+
+fn write_memory(addr: usize, len: usize, data: &[u8]) -> Result<(), SystemError> {
+    if data.len() != len * 2 {
+        return Err(SystemError::EINVAL);
+    }
+    if !range_accessible(addr, len, true) {
+        return Err(SystemError::EFAULT);
+    }
+    for (i, pair) in data.chunks(2).enumerate() {
+        let b = decode_hex_byte(pair).ok_or(SystemError::EINVAL)?;
+        unsafe { core::ptr::write_volatile((addr + i) as *mut u8, b) };
+    }
+    Ok(())
+}
+
+fn get_char(port: &Serial8250PIOPort) -> u8 {
+    loop {
+        if let Some(c) = port.poll_get_char() {
+            return c;
+        }
+        spin_loop();
+    }
+}
+
+/// 接收一个数据包，把内容放到`buf`中，返回内容的长度
+fn get_packet(port: &Serial8250PIOPort, buf: &mut [u8]) -> usize {
+    loop {
+        if !PACKET_START_PENDING.swap(false, Ordering::SeqCst) {
+            while get_char(port) != b'$' {}
+        }
+
+        let mut len = 0;
+        let mut sum = 0u8;
+        let mut overflow = false;
+        loop {
+            let c = get_char(port);
+            match c {
+                b'#' => break,
+                // 数据包被打断，重新开始接收
+                b'$' => {
+                    len = 0;
+                    sum = 0;
+                    overflow = false;
+                }
+                _ => {
+                    sum = sum.wrapping_add(c);
+                    if len < buf.len() {
+                        buf[len] = c;
+                        len += 1;
+                    } else {
+                        overflow = true;
+                    }
+                }
+            }
+        }
+
+        let checksum = [get_char(port), get_char(port)];
+        if !overflow && decode_hex_byte(&checksum) == Some(sum) {
+            port.poll_put_char(b'+');
+            return len;
+        }
+        port.poll_put_char(b'-');
+    }
+}
+
+/// 发送一个数据包，直到gdb确认收到
+fn put_packet(port: &Serial8250PIOPort, data: &[u8]) {
+    loop {
+        port.poll_put_char(b'$');
+        let mut sum = 0u8;
+        for &c in data {
+            port.poll_put_char(c);
+            sum = sum.wrapping_add(c);
+        }
+        port.poll_put_char(b'#');
+        port.poll_put_char(HEX_CHARS[(sum >> 4) as usize]);
+        port.poll_put_char(HEX_CHARS[(sum & 0xf) as usize]);
+
+        loop {
+            match get_char(port) {
+                b'+' => return,
+                b'-' => break,
+                // gdb没有等待确认就发来了新的数据包
+                b'$' => {
+                    PACKET_START_PENDING.store(true, Ordering::SeqCst);
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// 处理断点异常，异常由调试器处理时返回true
+pub fn gdbstub_handle_breakpoint(frame: &mut TrapFrame) -> bool {
+    if !GDBSTUB_ENABLED.load(Ordering::SeqCst) || frame.is_from_user() {
+        return false;
+    }
+    let addr = CurrentGdbArch::breakpoint_address(frame);
+    let mut state = GDB_STATE.lock_irqsave();
+    if CurrentGdbArch::is_arch_breakpoint(addr) {
+        state.enter(frame);
+        return true;
+    }
+    if state.breakpoint_inserted(addr) {
+        // 回到断点处，继续执行时执行的是原来的指令
+        CurrentGdbArch::set_pc(frame, addr);
+        state.enter(frame);
+        return true;
+    }
+    // 等待锁的时候，断点可能已经被其他cpu上的调试会话移除，此时重新执行原来的指令即可
+    if state.connected
+        && CurrentGdbArch::addr_accessible(addr, false)
+        && unsafe { core::ptr::read_volatile(addr as *const u8) } != CurrentGdbArch::BREAKPOINT_INSN
+    {
+        CurrentGdbArch::set_pc(frame, addr);
+        return true;
+    }
+    false
+}
+
+/// 处理调试异常，单步由调试器发起时返回true
+pub fn gdbstub_handle_debug(frame: &mut TrapFrame) -> bool {
+    if !GDBSTUB_ENABLED.load(Ordering::SeqCst) || frame.is_from_user() {
+        return false;
+    }
+    let mut state = GDB_STATE.lock_irqsave();
+    if state.step_cpu != Some(smp_get_processor_id()) {
+        return false;
+    }
+    state.step_cpu = None;
+    CurrentGdbArch::set_single_step(frame, false);
+    if state.step_over.take().is_some() {
+        state.insert_breakpoints(None);
+    }
+    if state.stepping {
+        state.enter(frame);
+    }
+    true
+}
+
+/// 让当前cpu进入调试器，调试桩未启用时什么也不做
+pub fn gdbstub_breakpoint() {
+    if GDBSTUB_ENABLED.load(Ordering::SeqCst) {
+        CurrentGdbArch::breakpoint();
+    }
+}
+
+/// 启用调试桩，使用第`index`个PIO串口
+///
+/// ## 参数
+///
+/// - `index`：串口的编号，即ttyS后面的数字
+/// - `baud`：波特率，为None时保持串口当前的设置
+pub fn gdbstub_enable(index: usize, baud: Option<BaudRate>) -> Result<(), SystemError> {
+    if index == GDB_CONSOLE_PORT {
+        return Err(SystemError::EBUSY);
+    }
+    let port = serial8250_pio_port(index).ok_or(SystemError::ENODEV)?;
+    let mut state = GDB_STATE.lock_irqsave();
+    if state.port.is_some() {
+        return Err(SystemError::EBUSY);
+    }
+    if let Some(baud) = baud {
+        port.set_divisor(baud)?;
+    }
+    port.set_reserved(true);
+    state.port = Some(port);
+    drop(state);
+
+    // 没有中断时仍然可以通过断点与sysrq进入调试器，只是无法用Ctrl-C打断内核
+    irq_manager()
+        .request_irq(
+            port.irq(),
+            "gdbstub".to_string(),
+            &GdbIrqHandler,
+            IrqHandleFlags::IRQF_SHARED | IrqHandleFlags::IRQF_TRIGGER_RISING,
+            Some(DeviceId::new(Some("gdbstub"), None).unwrap()),
+        )
+        .inspect_err(|e| warn!("gdbstub: failed to request irq: {:?}", e))
+        .ok();
+
+    GDBSTUB_ENABLED.store(true, Ordering::SeqCst);
+    info!("gdbstub: ready on ttyS{}", index);
+    return Ok(());
+}
+
+/// 调试串口的中断：gdb发来Ctrl-C或数据包时进入调试器
+#[derive(Debug)]
+struct GdbIrqHandler;
+
+impl IrqHandler for GdbIrqHandler {
+    fn handle(
+        &self,
+        _irq: IrqNumber,
+        _static_data: Option<&dyn IrqHandlerData>,
+        _dynamic_data: Option<Arc<dyn IrqHandlerData>>,
+    ) -> Result<IrqReturn, SystemError> {
+        // 调试会话进行中，由会话自己读取串口
+        let Ok(state) = GDB_STATE.try_lock_irqsave() else {
+            return Ok(IrqReturn::Handled);
+        };
+        let Some(port) = state.port else {
+            return Ok(IrqReturn::NotHandled);
+        };
+
+        let mut enter = false;
+        while let Some(c) = port.poll_get_char() {
+            if c == GDB_INTERRUPT_CHAR {
+                enter = true;
+                break;
+            }
+            if c == b'$' {
+                PACKET_START_PENDING.store(true, Ordering::SeqCst);
+                enter = true;
+                break;
+            }
+            // 其他字符（如连接前残留的确认字符）直接丢弃
+        }
+        drop(state);
+
+        if enter {
+            gdbstub_breakpoint();
+        }
+        Ok(IrqReturn::Handled)
+    }
+}
+
+fn sysrq_handle_dbg(_key: u8) {
+    if !GDBSTUB_ENABLED.load(Ordering::SeqCst) {
+        if let Err(e) = gdbstub_enable(GDB_DEFAULT_PORT, None) {
+            warn!(
+                "gdbstub: failed to enable on ttyS{}: {:?}",
+                GDB_DEFAULT_PORT, e
+            );
+            return;
+        }
+    }
+    gdbstub_breakpoint();
+}
+
+/// 解析`kgdboc=ttyS<n>[,<baud>]`
+fn parse_kgdboc(s: &str) -> Option<(usize, Option<BaudRate>)> {
+    let (dev, baud) = match s.split_once(',') {
+        Some((dev, baud)) => (dev, Some(BaudRate::new(baud.parse().ok()?))),
+        None => (s, None),
+    };
+    let index = dev.strip_prefix("ttyS")?.parse().ok()?;
+    Some((index, baud))
+}
+
+#[unified_init(INITCALL_LATE)]
+fn gdbstub_init() -> Result<(), SystemError> {
+    register_sysrq_key(b'g', &SYSRQ_GDB_OP)?;
+
+    let Some(param) = KGDBOC_PARAM.value_str().filter(|s| !s.is_empty()) else {
+        return Ok(());
+    };
+    let Some((index, baud)) = parse_kgdboc(param) else {
+        warn!("gdbstub: invalid kgdboc parameter: {}", param);
+        return Ok(());
+    };
+    if let Err(e) = gdbstub_enable(index, baud) {
+        warn!("gdbstub: failed to enable on ttyS{}: {:?}", index, e);
+        return Ok(());
+    }
+
+    if KGDBWAIT_PARAM.value_bool().unwrap_or(false) {
+        info!("gdbstub: waiting for connection from remote gdb...");
+        gdbstub_breakpoint();
+    }
+    Ok(())
+}
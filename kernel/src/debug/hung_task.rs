@@ -0,0 +1,137 @@
+//! 检测长时间处于不可中断睡眠（D状态）的进程
+//!
+//! 检测线程`khungtaskd`每隔`hung_task_timeout_secs`秒扫描一次所有进程，
+//! 如果某个进程在两次扫描之间一直处于不可中断睡眠，而且没有被调度运行过，
+//! 就认为它挂起了，打印它的信息与内核调用栈。
+//!
+//! 控制文件位于debugfs的`/sys/kernel/debug/`下：
+//!
+//! - `hung_task_timeout_secs`: 超时时间（秒），写入0关闭检测
+//! - `hung_task_warnings`: 剩余的报告次数，减到0之后不再报告，写入新的值可以重新打开
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/hung_task.c
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use alloc::{boxed::Box, collections::BTreeMap, string::ToString};
+use log::error;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    debug::traceback::{print_stack_trace, StackUnwinder},
+    filesystem::debugfs::{debugfs_create_file, DEBUGFS_MODE_RW},
+    init::initcall::INITCALL_LATE,
+    process::{
+        kthread::{KernelThreadClosure, KernelThreadMechanism},
+        Pid, ProcessControlBlock, ProcessManager, ProcessState,
+    },
+    time::{sleep::nanosleep, PosixTimeSpec},
+};
+
+/// 默认的超时时间（秒）
+const DEFAULT_TIMEOUT_SECS: u64 = 120;
+/// 默认最多报告的次数
+const DEFAULT_WARNINGS: usize = 10;
+/// 检测关闭时，检测线程检查设置是否改变的间隔（秒）
+const IDLE_CHECK_SECS: u64 = 5;
+
+static HUNG_TASK_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT_SECS);
+static HUNG_TASK_WARNINGS: AtomicUsize = AtomicUsize::new(DEFAULT_WARNINGS);
+
+/// 进程被调度运行的次数，两次扫描之间没有变化说明它一直没有运行
+fn switch_count(pcb: &ProcessControlBlock) -> usize {
+    pcb.sched_info().sched_stat.read_irqsave().pcount
+}
+
+/// 报告一个挂起的进程
+fn report_hung_task(pcb: &ProcessControlBlock, timeout: u64) {
+    // 报告次数用完之后不再报告，避免刷屏
+    if HUNG_TASK_WARNINGS
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+        .is_err()
+    {
+        return;
+    }
+
+    error!(
+        "INFO: task {}:{} blocked for more than {} seconds.",
+        pcb.basic().name(),
+        pcb.pid().data(),
+        timeout
+    );
+    print_stack_trace(StackUnwinder::from_task(pcb));
+}
+
+/// 扫描一遍所有进程
+///
+/// `last_switch`记录上一次扫描时处于不可中断睡眠的进程的调度次数，扫描之后会被更新
+fn check_hung_tasks(last_switch: &mut BTreeMap<Pid, usize>, timeout: u64) {
+    let mut current = BTreeMap::new();
+    for pcb in ProcessManager::all_processes() {
+        if pcb.sched_info().inner_lock_read_irqsave().state() != ProcessState::Blocked(false) {
+            continue;
+        }
+        let count = switch_count(&pcb);
+        if last_switch.get(&pcb.pid()) == Some(&count) {
+            report_hung_task(&pcb, timeout);
+        }
+        current.insert(pcb.pid(), count);
+    }
+    *last_switch = current;
+}
+
+fn hung_task_thread() -> i32 {
+    let mut last_switch = BTreeMap::new();
+    loop {
+        let timeout = HUNG_TASK_TIMEOUT_SECS.load(Ordering::Relaxed);
+        if timeout == 0 {
+            last_switch.clear();
+            let _ = nanosleep(PosixTimeSpec::new(IDLE_CHECK_SECS as i64, 0));
+            continue;
+        }
+
+        let _ = nanosleep(PosixTimeSpec::new(timeout as i64, 0));
+        // 睡眠期间超时时间可能被修改，此时上一次扫描的结果已经不能说明问题
+        if HUNG_TASK_TIMEOUT_SECS.load(Ordering::Relaxed) != timeout {
+            last_switch.clear();
+            continue;
+        }
+        check_hung_tasks(&mut last_switch, timeout);
+    }
+}
+
+#[unified_init(INITCALL_LATE)]
+fn hung_task_init() -> Result<(), SystemError> {
+    debugfs_create_file(
+        "hung_task_timeout_secs",
+        DEBUGFS_MODE_RW,
+        None,
+        Some(Box::new(|| {
+            Ok(HUNG_TASK_TIMEOUT_SECS.load(Ordering::Relaxed).to_string() + "\n")
+        })),
+        Some(Box::new(|s: &str| {
+            let v = s.parse::<u64>().map_err(|_| SystemError::EINVAL)?;
+            HUNG_TASK_TIMEOUT_SECS.store(v, Ordering::Relaxed);
+            Ok(())
+        })),
+    )?;
+    debugfs_create_file(
+        "hung_task_warnings",
+        DEBUGFS_MODE_RW,
+        None,
+        Some(Box::new(|| {
+            Ok(HUNG_TASK_WARNINGS.load(Ordering::Relaxed).to_string() + "\n")
+        })),
+        Some(Box::new(|s: &str| {
+            let v = s.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+            HUNG_TASK_WARNINGS.store(v, Ordering::Relaxed);
+            Ok(())
+        })),
+    )?;
+
+    let closure = KernelThreadClosure::StaticEmptyClosure((&(hung_task_thread as fn() -> i32), ()));
+    KernelThreadMechanism::create_and_run(closure, "khungtaskd".to_string())
+        .ok_or(SystemError::ENOMEM)?;
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use core::ffi::{c_char, CStr};
+
+use multiboot::ElfSymbol;
+
+use crate::libs::lazy_init::Lazy;
+
+extern "C" {
+    fn kallsyms_lookup_symbol(addr: u64, offset: *mut u64) -> *const c_char;
+}
+
+/// 引导程序随内核一起加载的ELF符号表
+///
+/// 内核还没有链接kallsyms时（第一遍链接），用它来解析地址
+#[derive(Debug)]
+struct BootSymtab {
+    symbols: &'static [ElfSymbol],
+    strtab: &'static [u8],
+}
+
+static BOOT_SYMTAB: Lazy<BootSymtab> = Lazy::new();
+
+/// # 注册引导程序提供的符号表
+///
+/// 只有第一次注册生效
+///
+/// ## Safety
+///
+/// 两个切片所在的内存必须已经被保留，并且在内核运行期间一直可以访问
+pub unsafe fn register_boot_symtab(symbols: &'static [ElfSymbol], strtab: &'static [u8]) {
+    if BOOT_SYMTAB.initialized() {
+        return;
+    }
+    BOOT_SYMTAB.init(BootSymtab { symbols, strtab });
+}
+
+/// 在引导程序提供的符号表中查找包含`addr`的函数
+fn lookup_boot_symbol(addr: usize) -> Option<(&'static str, usize)> {
+    let symtab = BOOT_SYMTAB.try_get()?;
+    let addr = addr as u64;
+    let sym = symtab.symbols.iter().find(|sym| {
+        sym.is_function()
+            && sym.st_value <= addr
+            && addr < sym.st_value.saturating_add(sym.st_size.max(1))
+    })?;
+    let name = sym.name(symtab.strtab)?;
+    return Some((name, (addr - sym.st_value) as usize));
+}
+
+/// 查找地址所在的内核函数
+///
+/// ## 返回值
+///
+/// - `Some((name, offset))`: 函数名，以及地址相对于函数起始处的偏移量
+/// - `None`: 地址不在内核text段内，或者内核中和引导程序提供的符号表中都没有这个地址
+pub fn lookup_symbol(addr: usize) -> Option<(&'static str, usize)> {
+    let mut offset: u64 = 0;
+    let name = unsafe { kallsyms_lookup_symbol(addr as u64, &mut offset) };
+    if name.is_null() {
+        return lookup_boot_symbol(addr);
+    }
+
+    let name = unsafe { CStr::from_ptr(name) }.to_str().ok()?;
+    return Some((name, offset as usize));
+}
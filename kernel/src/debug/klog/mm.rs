@@ -1,8 +1,6 @@
 extern crate klog_types;
 
-use core::sync::atomic::{compiler_fence, Ordering};
-
-use klog_types::{AllocatorLog, AllocatorLogType, LogSource, MMLogChannel};
+use klog_types::{mm_log_channel_words, AllocatorLog, AllocatorLogType, LogSource, MMLogChannel};
 
 use crate::{arch::CurrentTimeArch, libs::spinlock::SpinLock, process::Pid, time::TimeArch};
 
@@ -10,8 +8,9 @@ use crate::{arch::CurrentTimeArch, libs::spinlock::SpinLock, process::Pid, time:
 ///
 /// 标记为`no_mangle`是为了让调试器能够找到这个变量
 #[no_mangle]
-static __MM_ALLOCATOR_LOG_CHANNEL: MMLogChannel<{ MMDebugLogManager::MAX_ALLOC_LOG_NUM }> =
-    MMLogChannel::new(MMDebugLogManager::MAX_ALLOC_LOG_NUM);
+static __MM_ALLOCATOR_LOG_CHANNEL: MMLogChannel<
+    { mm_log_channel_words(MMDebugLogManager::MAX_ALLOC_LOG_NUM) },
+> = MMLogChannel::new(MMDebugLogManager::MAX_ALLOC_LOG_NUM);
 
 /// 全局的内存分配器日志id分配器
 ///
@@ -60,23 +59,7 @@ impl MMDebugLogManager {
             CurrentTimeArch::get_cycles() as u64,
         );
 
-        let mut log = log;
-        loop {
-            compiler_fence(Ordering::SeqCst);
-            let r = __MM_ALLOCATOR_LOG_CHANNEL.buf.push(log);
-            compiler_fence(Ordering::SeqCst);
-            if let Err(r) = r {
-                // 如果日志通道满了，就把最早的日志丢弃
-                if __MM_ALLOCATOR_LOG_CHANNEL.buf.remaining() == 0 {
-                    compiler_fence(Ordering::SeqCst);
-                    __MM_ALLOCATOR_LOG_CHANNEL.buf.pop();
-                    compiler_fence(Ordering::SeqCst);
-                }
-                log = r.into_inner();
-                compiler_fence(Ordering::SeqCst);
-            } else {
-                break;
-            }
-        }
+        // 日志通道满时，环形缓冲区会丢弃最早的日志
+        __MM_ALLOCATOR_LOG_CHANNEL.push(&log);
     }
 }
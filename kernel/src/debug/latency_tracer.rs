@@ -0,0 +1,476 @@
+//! 中断/抢占延迟追踪器（irqsoff/preemptoff）
+//!
+//! 记录每个CPU上关中断、关抢占区间的长度，并保存最长区间的开始与结束调用栈，
+//! 用于定位键盘、tty等场景下的延迟尖峰。
+//!
+//! 控制文件位于debugfs的`/sys/kernel/debug/tracing/`下：
+//!
+//! - `current_tracer`: 当前的追踪器，可选`nop`、`irqsoff`、`preemptoff`、`preemptirqsoff`
+//! - `tracing_max_latency`: 目前记录到的最大延迟（微秒），写入0清空记录
+//! - `trace`: 最大延迟区间的详细信息，包括开始与结束时的调用栈（只读）
+//!
+//! 追踪器为`nop`时，各个钩子函数只有一次原子读的开销。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/trace/trace_irqsoff.c
+
+use core::{
+    cell::UnsafeCell,
+    fmt::Write,
+    intrinsics::likely,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::CurrentTimeArch,
+    debug::{
+        kallsyms::lookup_symbol,
+        traceback::{save_stack_trace, StackUnwinder},
+    },
+    filesystem::debugfs::{
+        debugfs_create_dir, debugfs_create_file, DEBUGFS_MODE_RO, DEBUGFS_MODE_RW,
+    },
+    init::initcall::INITCALL_POSTCORE,
+    mm::percpu::PerCpu,
+    process::ProcessManager,
+    smp::core::smp_get_processor_id,
+    time::TimeArch,
+};
+
+/// 保存的调用栈深度
+const LATENCY_STACK_DEPTH: usize = 16;
+/// `trace`文件中每个调用栈最多显示的栈帧数
+const LATENCY_SHOW_DEPTH: usize = 8;
+
+/// 追踪关中断区间
+const TRACE_IRQS_OFF: u8 = 1 << 0;
+/// 追踪关抢占区间
+const TRACE_PREEMPT_OFF: u8 = 1 << 1;
+
+/// 当前追踪器，为`TRACE_*`的组合，0表示`nop`
+static CURRENT_TRACER: AtomicU8 = AtomicU8::new(0);
+/// 每次切换追踪器或清空记录时加一，使各个CPU上已经开始的区间失效
+static TRACER_GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// 目前记录到的最大延迟（纳秒），用于在加锁之前快速判断
+static MAX_LATENCY_NS: AtomicU64 = AtomicU64::new(0);
+
+static MAX_RECORD: MaxRecord = MaxRecord::new();
+static CPU_STATES: [CpuLatencyState; PerCpu::MAX_CPU_NUM as usize] =
+    [const { CpuLatencyState::new() }; PerCpu::MAX_CPU_NUM as usize];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LatencyEvent {
+    IrqsOff,
+    IrqsOn,
+    PreemptOff,
+    PreemptOn,
+}
+
+/// 一个调用栈
+#[derive(Debug, Clone, Copy)]
+struct LatencyStack {
+    pcs: [usize; LATENCY_STACK_DEPTH],
+    len: usize,
+}
+
+impl LatencyStack {
+    const fn new() -> Self {
+        Self {
+            pcs: [0; LATENCY_STACK_DEPTH],
+            len: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn save(&mut self) {
+        self.len = save_stack_trace(StackUnwinder::from_current(), &mut self.pcs);
+    }
+
+    /// 符号化的调用栈，跳过追踪器自身的栈帧
+    fn frames(&self) -> impl Iterator<Item = (usize, Option<(String, usize)>)> + '_ {
+        self.pcs[..self.len]
+            .iter()
+            .map(|pc| (*pc, lookup_symbol(*pc)))
+            .skip_while(|(_, sym)| {
+                sym.as_ref()
+                    .is_some_and(|(name, _)| name.contains("latency_tracer"))
+            })
+            .take(LATENCY_SHOW_DEPTH)
+    }
+
+    /// 调用栈中第一个不属于追踪器的函数名
+    fn site(&self) -> String {
+        self.frames()
+            .next()
+            .and_then(|(_, sym)| sym)
+            .map(|(name, _)| name)
+            .unwrap_or_else(|| "?".to_string())
+    }
+}
+
+/// 每个CPU上的追踪状态
+///
+/// 只会被所在的CPU访问，`in_tracer`防止中断嵌套时重入
+#[derive(Debug)]
+struct CpuLatencyState {
+    in_tracer: AtomicBool,
+    inner: UnsafeCell<CpuLatencyInner>,
+}
+
+#[derive(Debug)]
+struct CpuLatencyInner {
+    generation: usize,
+    irqs_off: bool,
+    preempt_off: bool,
+    /// 是否处于被追踪的区间中
+    active: bool,
+    /// 区间开始时的时钟周期数
+    start: usize,
+    /// 区间的开始原因
+    start_event: LatencyEvent,
+    start_stack: LatencyStack,
+}
+
+unsafe impl Sync for CpuLatencyState {}
+
+impl CpuLatencyState {
+    const fn new() -> Self {
+        Self {
+            in_tracer: AtomicBool::new(false),
+            inner: UnsafeCell::new(CpuLatencyInner {
+                generation: 0,
+                irqs_off: false,
+                preempt_off: false,
+                active: false,
+                start: 0,
+                start_event: LatencyEvent::IrqsOff,
+                start_stack: LatencyStack::new(),
+            }),
+        }
+    }
+}
+
+/// 最大延迟区间的记录
+#[derive(Debug, Clone, Copy)]
+struct LatencyRecord {
+    latency_ns: u64,
+    cpu: usize,
+    pid: usize,
+    tracer: u8,
+    start_event: LatencyEvent,
+    start_stack: LatencyStack,
+    end_stack: LatencyStack,
+}
+
+impl LatencyRecord {
+    const fn new() -> Self {
+        Self {
+            latency_ns: 0,
+            cpu: 0,
+            pid: 0,
+            tracer: 0,
+            start_event: LatencyEvent::IrqsOff,
+            start_stack: LatencyStack::new(),
+            end_stack: LatencyStack::new(),
+        }
+    }
+}
+
+/// 保护最大延迟记录的锁
+///
+/// 追踪器的钩子位于关中断、关抢占的路径上，不能使用SpinLock（会再次触发钩子）。
+/// 记录时只尝试加锁一次，失败则放弃本次记录，因此不会在中断上下文中死锁。
+#[derive(Debug)]
+struct MaxRecord {
+    locked: AtomicBool,
+    record: UnsafeCell<LatencyRecord>,
+}
+
+unsafe impl Sync for MaxRecord {}
+
+impl MaxRecord {
+    const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            record: UnsafeCell::new(LatencyRecord::new()),
+        }
+    }
+
+    fn try_with<R>(&self, f: impl FnOnce(&mut LatencyRecord) -> R) -> Option<R> {
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+        let r = f(unsafe { &mut *self.record.get() });
+        self.locked.store(false, Ordering::Release);
+        return Some(r);
+    }
+
+    /// 在进程上下文中访问记录，等待其它CPU完成记录
+    fn with<R>(&self, f: impl FnOnce(&mut LatencyRecord) -> R) -> R {
+        let mut f = Some(f);
+        loop {
+            if let Some(r) = self.try_with(|record| (f.take().unwrap())(record)) {
+                return r;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// 关中断时调用
+#[inline(always)]
+pub fn trace_irqs_off() {
+    if likely(CURRENT_TRACER.load(Ordering::Relaxed) == 0) {
+        return;
+    }
+    latency_event(LatencyEvent::IrqsOff, false);
+}
+
+/// 开中断时调用，必须在中断真正被打开之前调用
+#[inline(always)]
+pub fn trace_irqs_on() {
+    if likely(CURRENT_TRACER.load(Ordering::Relaxed) == 0) {
+        return;
+    }
+    latency_event(LatencyEvent::IrqsOn, false);
+}
+
+/// 抢占计数从0变为1之后调用
+#[inline(always)]
+pub fn trace_preempt_off() {
+    if likely(CURRENT_TRACER.load(Ordering::Relaxed) == 0) {
+        return;
+    }
+    latency_event(LatencyEvent::PreemptOff, false);
+}
+
+/// 抢占计数从1变为0之前调用
+#[inline(always)]
+pub fn trace_preempt_on() {
+    if likely(CURRENT_TRACER.load(Ordering::Relaxed) == 0) {
+        return;
+    }
+    latency_event(LatencyEvent::PreemptOn, false);
+}
+
+/// 硬件中断处理函数入口处调用（此时中断已被硬件关闭）
+///
+/// ## 参数
+///
+/// - `from_user`: 中断是否发生在用户态。用户态下中断一定是打开的，抢占也一定是允许的，
+///   因此可以丢弃本CPU上遗留的状态（例如新进程通过`iret`返回用户态时没有经过钩子）
+#[inline(always)]
+pub fn trace_hardirq_enter(from_user: bool) {
+    if likely(CURRENT_TRACER.load(Ordering::Relaxed) == 0) {
+        return;
+    }
+    latency_event(LatencyEvent::IrqsOff, from_user);
+}
+
+/// 硬件中断处理函数返回之前调用（返回后中断会被硬件重新打开）
+#[inline(always)]
+pub fn trace_hardirq_exit() {
+    trace_irqs_on();
+}
+
+/// 从用户态进入内核时调用（例如系统调用入口）
+#[inline(always)]
+pub fn trace_enter_from_user() {
+    if likely(CURRENT_TRACER.load(Ordering::Relaxed) == 0) {
+        return;
+    }
+    latency_event(LatencyEvent::IrqsOn, true);
+}
+
+#[inline(never)]
+fn latency_event(event: LatencyEvent, reset: bool) {
+    let cpu = smp_get_processor_id().data() as usize;
+    let Some(state) = CPU_STATES.get(cpu) else {
+        return;
+    };
+    if state.in_tracer.swap(true, Ordering::Acquire) {
+        return;
+    }
+    let inner = unsafe { &mut *state.inner.get() };
+
+    let tracer = CURRENT_TRACER.load(Ordering::Relaxed);
+    let generation = TRACER_GENERATION.load(Ordering::Relaxed);
+    if reset || inner.generation != generation {
+        inner.generation = generation;
+        inner.irqs_off = false;
+        inner.preempt_off = false;
+        inner.active = false;
+    }
+
+    match event {
+        LatencyEvent::IrqsOff => inner.irqs_off = true,
+        LatencyEvent::IrqsOn => inner.irqs_off = false,
+        LatencyEvent::PreemptOff => inner.preempt_off = true,
+        LatencyEvent::PreemptOn => inner.preempt_off = false,
+    }
+
+    let critical = (tracer & TRACE_IRQS_OFF != 0 && inner.irqs_off)
+        || (tracer & TRACE_PREEMPT_OFF != 0 && inner.preempt_off);
+
+    if critical && !inner.active {
+        inner.active = true;
+        inner.start_event = event;
+        inner.start_stack.save();
+        inner.start = CurrentTimeArch::get_cycles();
+    } else if !critical && inner.active {
+        inner.active = false;
+        let cycles = CurrentTimeArch::get_cycles().saturating_sub(inner.start);
+        let latency_ns = CurrentTimeArch::cycles2ns(cycles) as u64;
+        if latency_ns > MAX_LATENCY_NS.load(Ordering::Relaxed) {
+            let mut end_stack = LatencyStack::new();
+            end_stack.save();
+            MAX_RECORD.try_with(|record| {
+                // 加锁期间可能有其它CPU记录了更大的延迟，或者追踪器已被切换
+                if latency_ns <= record.latency_ns
+                    || TRACER_GENERATION.load(Ordering::Relaxed) != generation
+                {
+                    return;
+                }
+                *record = LatencyRecord {
+                    latency_ns,
+                    cpu,
+                    pid: ProcessManager::current_pid().data(),
+                    tracer,
+                    start_event: inner.start_event,
+                    start_stack: inner.start_stack,
+                    end_stack,
+                };
+                MAX_LATENCY_NS.store(latency_ns, Ordering::Relaxed);
+            });
+        }
+    }
+
+    state.in_tracer.store(false, Ordering::Release);
+}
+
+fn tracer_name(tracer: u8) -> &'static str {
+    match tracer {
+        TRACE_IRQS_OFF => "irqsoff",
+        TRACE_PREEMPT_OFF => "preemptoff",
+        t if t == TRACE_IRQS_OFF | TRACE_PREEMPT_OFF => "preemptirqsoff",
+        _ => "nop",
+    }
+}
+
+fn tracer_from_name(name: &str) -> Option<u8> {
+    match name {
+        "nop" => Some(0),
+        "irqsoff" => Some(TRACE_IRQS_OFF),
+        "preemptoff" => Some(TRACE_PREEMPT_OFF),
+        "preemptirqsoff" => Some(TRACE_IRQS_OFF | TRACE_PREEMPT_OFF),
+        _ => None,
+    }
+}
+
+/// 清空最大延迟记录，并使各个CPU上已经开始的区间失效
+fn reset_max_latency() {
+    MAX_RECORD.with(|record| {
+        TRACER_GENERATION.fetch_add(1, Ordering::Relaxed);
+        *record = LatencyRecord::new();
+        MAX_LATENCY_NS.store(0, Ordering::Relaxed);
+    });
+}
+
+fn set_current_tracer(tracer: u8) {
+    CURRENT_TRACER.store(tracer, Ordering::Relaxed);
+    reset_max_latency();
+}
+
+/// 生成`trace`文件的内容
+fn format_trace() -> String {
+    let record = MAX_RECORD.with(|record| *record);
+    let mut s = String::new();
+    writeln!(s, "# tracer: {}", tracer_name(record.tracer)).ok();
+    if record.latency_ns == 0 {
+        return s;
+    }
+
+    let cause = match record.start_event {
+        LatencyEvent::IrqsOff => "irqs off",
+        _ => "preempt off",
+    };
+    writeln!(s, "#").ok();
+    writeln!(
+        s,
+        "# latency: {} us, CPU#{} | (Pid: {}) | started by: {}",
+        record.latency_ns / 1000,
+        record.cpu,
+        record.pid,
+        cause
+    )
+    .ok();
+    writeln!(s, "#  => started at: {}", record.start_stack.site()).ok();
+    writeln!(s, "#  => ended at:   {}", record.end_stack.site()).ok();
+
+    for (title, stack) in [("start", &record.start_stack), ("end", &record.end_stack)] {
+        writeln!(s, "#\n# {} call trace:", title).ok();
+        for (pc, sym) in stack.frames() {
+            match sym {
+                Some((name, offset)) => writeln!(s, "#  [{:#018x}] {}+{:#x}", pc, name, offset),
+                None => writeln!(s, "#  [{:#018x}] ?", pc),
+            }
+            .ok();
+        }
+    }
+    return s;
+}
+
+/// 在`/sys/kernel/debug/tracing`下创建延迟追踪器的控制文件
+#[unified_init(INITCALL_POSTCORE)]
+fn latency_tracer_init() -> Result<(), SystemError> {
+    let dir = debugfs_create_dir("tracing", None)?;
+    debugfs_create_file(
+        "current_tracer",
+        DEBUGFS_MODE_RW,
+        Some(&dir),
+        Some(Box::new(|| {
+            Ok(tracer_name(CURRENT_TRACER.load(Ordering::Relaxed)).to_string() + "\n")
+        })),
+        Some(Box::new(|s: &str| {
+            let tracer = tracer_from_name(s).ok_or(SystemError::EINVAL)?;
+            set_current_tracer(tracer);
+            Ok(())
+        })),
+    )?;
+    debugfs_create_file(
+        "tracing_max_latency",
+        DEBUGFS_MODE_RW,
+        Some(&dir),
+        Some(Box::new(|| {
+            Ok((MAX_LATENCY_NS.load(Ordering::Relaxed) / 1000).to_string() + "\n")
+        })),
+        Some(Box::new(|s: &str| {
+            // 只能写入0，用于清空记录
+            let v = s.parse::<u64>().map_err(|_| SystemError::EINVAL)?;
+            if v != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            reset_max_latency();
+            Ok(())
+        })),
+    )?;
+    debugfs_create_file(
+        "trace",
+        DEBUGFS_MODE_RO,
+        Some(&dir),
+        Some(Box::new(|| Ok(format_trace()))),
+        None,
+    )?;
+
+    return Ok(());
+}
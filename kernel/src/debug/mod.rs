@@ -1,4 +1,13 @@
+pub mod exec_fd_audit;
+pub mod fault_inject;
+#[cfg(target_arch = "x86_64")]
+pub mod gdbstub;
+pub mod hung_task;
 pub mod jump_label;
+pub mod kallsyms;
 pub mod klog;
+pub mod latency_tracer;
+pub mod obj_lifetime;
 pub mod kprobe;
 pub mod panic;
+pub mod traceback;
@@ -0,0 +1,327 @@
+//! 基于Arc的内核对象生命周期调试
+//!
+//! 开启`obj_lifetime_debug`特性后，inode、socket、设备等对象在创建时被登记，
+//! 通过保存的Weak引用观察它们的引用计数变化，从而发现：
+//!
+//! - 已经被释放（例如socket被关闭、设备被移除）但仍然存活的对象，通常是引用环导致的泄漏
+//! - 释放之后强引用计数反而增加的对象，通常意味着有人通过残留的Weak引用"复活"了它，
+//!   存在释放后使用的风险
+//!
+//! 控制文件位于debugfs的`/sys/kernel/debug/obj_lifetime/`下：
+//!
+//! - `stacktrace`: 是否在登记与释放时保存调用栈（0/1）
+//! - `report`: 当前所有被登记对象的状态（只读）
+//!
+//! 系统重启前也会把报告打印到内核日志中。
+//!
+//! 未开启该特性时，所有接口都是空函数。
+
+use alloc::sync::Arc;
+
+/// 被追踪对象的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjKind {
+    Inode,
+    Socket,
+    Device,
+}
+
+impl ObjKind {
+    #[allow(dead_code)]
+    fn name(&self) -> &'static str {
+        match self {
+            ObjKind::Inode => "inode",
+            ObjKind::Socket => "socket",
+            ObjKind::Device => "device",
+        }
+    }
+}
+
+/// 登记一个对象。同一个对象重复登记时只会记录一次
+#[inline(always)]
+pub fn obj_lifetime_track<T: ?Sized + Send + Sync + 'static>(kind: ObjKind, obj: &Arc<T>) {
+    #[cfg(feature = "obj_lifetime_debug")]
+    tracker::track(kind, obj);
+    #[cfg(not(feature = "obj_lifetime_debug"))]
+    let _ = (kind, obj);
+}
+
+/// 标记对象已被释放，此后它的强引用应当很快归零
+///
+/// `obj`为Arc所指向的对象本身，因此也可以在`&self`方法中调用
+#[inline(always)]
+pub fn obj_lifetime_release<T: ?Sized>(obj: &T) {
+    #[cfg(feature = "obj_lifetime_debug")]
+    tracker::release(obj);
+    #[cfg(not(feature = "obj_lifetime_debug"))]
+    let _ = obj;
+}
+
+/// 把所有可疑对象打印到内核日志中
+#[inline(always)]
+pub fn obj_lifetime_report() {
+    #[cfg(feature = "obj_lifetime_debug")]
+    tracker::report();
+}
+
+#[cfg(feature = "obj_lifetime_debug")]
+mod tracker {
+    use core::{
+        fmt::Write,
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    };
+
+    use alloc::{
+        boxed::Box,
+        collections::BTreeMap,
+        string::{String, ToString},
+        sync::Arc,
+    };
+    use log::{info, warn};
+    use system_error::SystemError;
+    use unified_init::macros::unified_init;
+
+    use crate::{
+        debug::{
+            kallsyms::lookup_symbol,
+            traceback::{save_stack_trace, StackUnwinder},
+        },
+        filesystem::debugfs::{
+            debugfs_create_dir, debugfs_create_file, DEBUGFS_MODE_RO, DEBUGFS_MODE_RW,
+        },
+        init::initcall::INITCALL_POSTCORE,
+        libs::spinlock::SpinLock,
+    };
+
+    use super::ObjKind;
+
+    /// 保存的调用栈深度
+    const OBJ_STACK_DEPTH: usize = 8;
+    /// 每登记这么多个对象，清理一次已经销毁的对象
+    const OBJ_PRUNE_INTERVAL: usize = 256;
+
+    static STACKTRACE: AtomicBool = AtomicBool::new(false);
+    static TRACK_COUNT: AtomicUsize = AtomicUsize::new(0);
+    /// 已经销毁的对象数量，按种类统计
+    static DROPPED: [AtomicUsize; 3] = [const { AtomicUsize::new(0) }; 3];
+
+    static OBJECTS: SpinLock<BTreeMap<usize, TrackedObj>> = SpinLock::new(BTreeMap::new());
+
+    #[derive(Debug, Clone, Copy)]
+    struct ObjStack {
+        pcs: [usize; OBJ_STACK_DEPTH],
+        len: usize,
+    }
+
+    impl ObjStack {
+        fn capture() -> Option<Self> {
+            if !STACKTRACE.load(Ordering::Relaxed) {
+                return None;
+            }
+            let mut stack = Self {
+                pcs: [0; OBJ_STACK_DEPTH],
+                len: 0,
+            };
+            stack.len = save_stack_trace(StackUnwinder::from_current(), &mut stack.pcs);
+            Some(stack)
+        }
+
+        fn format(&self, s: &mut String) {
+            let frames = self.pcs[..self.len]
+                .iter()
+                .map(|pc| (*pc, lookup_symbol(*pc)));
+            for (pc, sym) in frames.skip_while(|(_, sym)| {
+                sym.as_ref()
+                    .is_some_and(|(name, _)| name.contains("obj_lifetime"))
+            }) {
+                match sym {
+                    Some((name, offset)) => writeln!(s, "      {}+{:#x}", name, offset),
+                    None => writeln!(s, "      [{:#018x}] ?", pc),
+                }
+                .ok();
+            }
+        }
+    }
+
+    struct TrackedObj {
+        kind: ObjKind,
+        type_name: &'static str,
+        /// 通过Weak引用读取(强引用计数, 弱引用计数)
+        counts: Box<dyn Fn() -> (usize, usize) + Send + Sync>,
+        /// 观察到的最大强引用计数
+        max_strong: usize,
+        created: Option<ObjStack>,
+        /// 被标记释放时的强引用计数以及调用栈
+        released: Option<(usize, Option<ObjStack>)>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum ObjState {
+        Alive,
+        /// 已被释放但仍然存活
+        Leaked,
+        /// 释放之后强引用计数增加
+        Resurrected,
+    }
+
+    impl TrackedObj {
+        fn state(&self, strong: usize) -> ObjState {
+            match self.released {
+                None => ObjState::Alive,
+                Some((at_release, _)) if strong > at_release => ObjState::Resurrected,
+                Some(_) => ObjState::Leaked,
+            }
+        }
+    }
+
+    fn obj_key<T: ?Sized>(obj: &Arc<T>) -> usize {
+        Arc::as_ptr(obj) as *const () as usize
+    }
+
+    /// 清理已经销毁的对象，并更新观察到的最大强引用计数
+    fn prune(objects: &mut BTreeMap<usize, TrackedObj>) {
+        objects.retain(|_, obj| {
+            let (strong, _) = (obj.counts)();
+            if strong == 0 {
+                DROPPED[obj.kind as usize].fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+            obj.max_strong = obj.max_strong.max(strong);
+            true
+        });
+    }
+
+    pub(super) fn track<T: ?Sized + Send + Sync + 'static>(kind: ObjKind, obj: &Arc<T>) {
+        let key = obj_key(obj);
+        let weak = Arc::downgrade(obj);
+        let created = ObjStack::capture();
+
+        let mut objects = OBJECTS.lock_irqsave();
+        if let Some(old) = objects.get(&key) {
+            // 地址被复用说明旧对象已经销毁
+            if (old.counts)().0 != 0 {
+                return;
+            }
+            DROPPED[old.kind as usize].fetch_add(1, Ordering::Relaxed);
+        }
+        objects.insert(
+            key,
+            TrackedObj {
+                kind,
+                type_name: core::any::type_name::<T>(),
+                counts: Box::new(move || (weak.strong_count(), weak.weak_count())),
+                max_strong: Arc::strong_count(obj),
+                created,
+                released: None,
+            },
+        );
+
+        if TRACK_COUNT.fetch_add(1, Ordering::Relaxed) % OBJ_PRUNE_INTERVAL == 0 {
+            prune(&mut objects);
+        }
+    }
+
+    pub(super) fn release<T: ?Sized>(obj: &T) {
+        let key = obj as *const T as *const () as usize;
+        let stack = ObjStack::capture();
+        let mut objects = OBJECTS.lock_irqsave();
+        if let Some(tracked) = objects.get_mut(&key) {
+            if tracked.released.is_none() {
+                tracked.released = Some(((tracked.counts)().0, stack));
+            }
+        }
+    }
+
+    /// 生成报告
+    ///
+    /// ## 参数
+    ///
+    /// - `all`: 是否包括未被释放的对象
+    fn format_report(all: bool) -> String {
+        let mut objects = OBJECTS.lock_irqsave();
+        prune(&mut objects);
+
+        let mut s = String::new();
+        for kind in [ObjKind::Inode, ObjKind::Socket, ObjKind::Device] {
+            let live = objects.values().filter(|obj| obj.kind == kind).count();
+            writeln!(
+                s,
+                "{}: {} live, {} dropped",
+                kind.name(),
+                live,
+                DROPPED[kind as usize].load(Ordering::Relaxed)
+            )
+            .ok();
+        }
+
+        for (key, obj) in objects.iter() {
+            let (strong, weak) = (obj.counts)();
+            let state = obj.state(strong);
+            let desc = match state {
+                ObjState::Alive if !all => continue,
+                ObjState::Alive => "alive",
+                ObjState::Leaked => "LEAKED after release",
+                ObjState::Resurrected => "REFCOUNT GREW after release",
+            };
+            writeln!(
+                s,
+                "{} {:#x} <{}>: {}, strong={} (max {}), weak={}",
+                obj.kind.name(),
+                key,
+                obj.type_name,
+                desc,
+                strong,
+                obj.max_strong,
+                weak
+            )
+            .ok();
+            if let Some(created) = &obj.created {
+                writeln!(s, "    created at:").ok();
+                created.format(&mut s);
+            }
+            if let Some((at_release, stack)) = &obj.released {
+                writeln!(s, "    released with strong={} at:", at_release).ok();
+                if let Some(stack) = stack {
+                    stack.format(&mut s);
+                }
+            }
+        }
+        return s;
+    }
+
+    pub(super) fn report() {
+        let report = format_report(false);
+        for line in report.lines() {
+            warn!("obj_lifetime: {}", line);
+        }
+    }
+
+    /// 在`/sys/kernel/debug/obj_lifetime`下创建控制文件
+    #[unified_init(INITCALL_POSTCORE)]
+    fn obj_lifetime_init() -> Result<(), SystemError> {
+        let dir = debugfs_create_dir("obj_lifetime", None)?;
+        debugfs_create_file(
+            "stacktrace",
+            DEBUGFS_MODE_RW,
+            Some(&dir),
+            Some(Box::new(|| {
+                Ok((STACKTRACE.load(Ordering::Relaxed) as usize).to_string() + "\n")
+            })),
+            Some(Box::new(|s: &str| {
+                let v = s.parse::<usize>().map_err(|_| SystemError::EINVAL)?;
+                STACKTRACE.store(v != 0, Ordering::Relaxed);
+                Ok(())
+            })),
+        )?;
+        debugfs_create_file(
+            "report",
+            DEBUGFS_MODE_RO,
+            Some(&dir),
+            Some(Box::new(|| Ok(format_report(true)))),
+            None,
+        )?;
+
+        info!("Object lifetime debugging enabled");
+        return Ok(());
+    }
+}
@@ -1,5 +1,5 @@
-#[cfg(feature = "backtrace")]
-mod hook;
+mod upload;
+
 use core::panic::PanicInfo;
 
 /// 全局的panic处理函数
@@ -28,19 +28,15 @@ pub fn panic(info: &PanicInfo) -> ! {
         }
     }
     println!("Message:\n\t{}", info.message());
+    crate::debug::traceback::dump_stack();
+    // 调用栈已经由上面的帧指针回溯打印过了，这里只负责展开，
+    // 让系统调用入口处的catch_unwind有机会接住panic
     #[cfg(feature = "backtrace")]
-    {
-        let mut data = hook::CallbackData { counter: 0 };
-        println!("Rust Panic Backtrace:");
-        let _res = unwinding::panic::begin_panic_with_hook::<hook::Tracer>(
-            alloc::boxed::Box::new(()),
-            &mut data,
-        );
-        // log::error!("panic unreachable: {:?}", res.0);
-    }
+    let _ = unwinding::panic::begin_panic(alloc::boxed::Box::new(()));
     println!(
         "Current PCB:\n\t{:?}",
         process::ProcessManager::current_pcb()
     );
+    upload::upload_crash_record(info);
     process::ProcessManager::exit(usize::MAX);
 }
@@ -0,0 +1,231 @@
+//! panic后把崩溃记录通过HTTP上传到指定的服务器
+//!
+//! 通过内核命令行参数`crashdump_url=http://<ipv4>[:port]/<path>`启用，
+//! 供无人值守的CI机器主动上报自己的崩溃现场。
+//!
+//! panic时系统的状态不可信，因此这里不经过socket层和全局的SOCKET_SET，
+//! 而是使用私有的SocketSet直接轮询第一张网卡。所有的锁都只尝试获取，
+//! 一旦发生竞争就放弃上传，避免在panic路径上死锁。
+
+use core::{
+    fmt::Write,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{format, string::String, vec, vec::Vec};
+use smoltcp::{
+    iface::SocketSet,
+    socket::tcp,
+    wire::{IpAddress, IpEndpoint, Ipv4Address},
+};
+use system_error::SystemError;
+
+use crate::{
+    debug::{
+        kallsyms::lookup_symbol,
+        traceback::{StackFrameKind, StackUnwinder},
+    },
+    filesystem::procfs::kmsg::KMSG,
+    net::NET_DEVICES,
+};
+
+kernel_cmdline_param_kv!(CRASHDUMP_URL_PARAM, crashdump_url, "");
+
+/// 上传时使用的本地端口（不经过PORT_MANAGER分配，以免在panic时加锁）
+const CRASHDUMP_LOCAL_PORT: u16 = 61000;
+/// 崩溃记录中附带的内核日志的最大长度
+const CRASHDUMP_KMSG_MAX_LEN: usize = 32 * 1024;
+const CRASHDUMP_TX_BUF_SIZE: usize = 64 * 1024;
+const CRASHDUMP_RX_BUF_SIZE: usize = 4096;
+/// 轮询网卡的最大次数
+///
+/// panic时时钟中断可能已经停止，因此用轮询次数而不是时间来限制上传的耗时
+const CRASHDUMP_MAX_POLLS: usize = 1_000_000;
+/// 两次轮询之间的自旋次数
+const CRASHDUMP_POLL_SPIN: usize = 1000;
+
+/// 防止上传过程中再次panic时重入
+static UPLOADING: AtomicBool = AtomicBool::new(false);
+
+/// 解析后的上传地址
+#[derive(Debug)]
+struct CrashDumpUrl<'a> {
+    endpoint: IpEndpoint,
+    host: &'a str,
+    path: &'a str,
+}
+
+impl<'a> CrashDumpUrl<'a> {
+    /// 解析`http://<ipv4>[:port]/<path>`形式的地址
+    ///
+    /// panic时无法进行域名解析，因此只支持IPv4地址
+    fn parse(url: &'a str) -> Option<Self> {
+        let rest = url.strip_prefix("http://")?;
+        let (host, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (addr, port) = match host.split_once(':') {
+            Some((addr, port)) => (addr, port.parse::<u16>().ok()?),
+            None => (host, 80),
+        };
+
+        let mut octets = [0u8; 4];
+        let mut parts = addr.split('.');
+        for octet in octets.iter_mut() {
+            *octet = parts.next()?.parse::<u8>().ok()?;
+        }
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            endpoint: IpEndpoint::new(IpAddress::Ipv4(Ipv4Address(octets)), port),
+            host,
+            path,
+        })
+    }
+}
+
+/// 如果配置了`crashdump_url`，则把本次panic的崩溃记录上传到该地址
+pub fn upload_crash_record(info: &PanicInfo) {
+    let Some(url) = CRASHDUMP_URL_PARAM.value_str().filter(|s| !s.is_empty()) else {
+        return;
+    };
+    if UPLOADING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let Some(url) = CrashDumpUrl::parse(url) else {
+        println!("crashdump: invalid url: {}", url);
+        return;
+    };
+
+    println!("crashdump: uploading crash record to {:?}", url.endpoint);
+    let record = build_record(info);
+    match http_post(&url, record.as_bytes()) {
+        Ok(()) => println!("crashdump: upload done, {} bytes", record.len()),
+        Err(e) => println!("crashdump: upload failed: {:?}", e),
+    }
+}
+
+/// 生成崩溃记录：panic的位置与信息、内核调用栈，以及最近的内核日志
+fn build_record(info: &PanicInfo) -> String {
+    let mut record = String::new();
+    writeln!(record, "DragonOS crash record").ok();
+    match info.location() {
+        Some(loc) => writeln!(
+            record,
+            "Location: {}:{}:{}",
+            loc.file(),
+            loc.line(),
+            loc.column()
+        ),
+        None => writeln!(record, "Location: unknown"),
+    }
+    .ok();
+    writeln!(record, "Message: {}", info.message()).ok();
+
+    writeln!(record, "\nKernel Stack Trace:").ok();
+    for (i, frame) in StackUnwinder::from_current().enumerate() {
+        if frame.kind == StackFrameKind::Trap {
+            writeln!(record, "  <interrupted>").ok();
+        }
+        match lookup_symbol(frame.pc) {
+            Some((name, offset)) => {
+                writeln!(
+                    record,
+                    "  #{:<2} [{:#018x}] {}+{:#x}",
+                    i, frame.pc, name, offset
+                )
+            }
+            None => writeln!(record, "  #{:<2} [{:#018x}] ?", i, frame.pc),
+        }
+        .ok();
+    }
+
+    writeln!(record, "\nKernel Log:").ok();
+    if let Some(kmsg) = unsafe { KMSG.as_ref() } {
+        record.push_str(&kmsg.recent_text(CRASHDUMP_KMSG_MAX_LEN));
+    }
+
+    return record;
+}
+
+/// 通过私有的TCP连接，把`body`以HTTP POST的方式发送出去
+fn http_post(url: &CrashDumpUrl, body: &[u8]) -> Result<(), SystemError> {
+    let iface = NET_DEVICES
+        .try_read_irqsave()
+        .ok_or(SystemError::EBUSY)?
+        .values()
+        .next()
+        .cloned()
+        .ok_or(SystemError::ENODEV)?;
+
+    let mut request: Vec<u8> = format!(
+        "POST {} HTTP/1.0\r\nHost: {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        url.path,
+        url.host,
+        body.len()
+    )
+    .into_bytes();
+    request.extend_from_slice(body);
+
+    let mut sockets = SocketSet::new(vec![]);
+    let handle = sockets.add(tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; CRASHDUMP_RX_BUF_SIZE]),
+        tcp::SocketBuffer::new(vec![0; CRASHDUMP_TX_BUF_SIZE]),
+    ));
+    {
+        let mut inner_iface = iface
+            .inner_iface()
+            .try_lock_irqsave()
+            .map_err(|_| SystemError::EBUSY)?;
+        sockets
+            .get_mut::<tcp::Socket>(handle)
+            .connect(inner_iface.context(), url.endpoint, CRASHDUMP_LOCAL_PORT)
+            .map_err(|_| SystemError::ECONNREFUSED)?;
+    }
+
+    let mut sent = 0;
+    let mut closing = false;
+    for _ in 0..CRASHDUMP_MAX_POLLS {
+        for _ in 0..CRASHDUMP_POLL_SPIN {
+            core::hint::spin_loop();
+        }
+        // 网卡的poll内部会阻塞地加锁，所以只在网卡空闲时轮询
+        if iface.inner_iface().is_locked() {
+            continue;
+        }
+        iface.poll(&mut sockets).ok();
+
+        let socket = sockets.get_mut::<tcp::Socket>(handle);
+        // 丢弃服务端的响应，避免接收窗口被占满
+        if socket.can_recv() {
+            socket.recv(|buf| (buf.len(), ())).ok();
+        }
+        match socket.state() {
+            tcp::State::SynSent | tcp::State::SynReceived => {}
+            tcp::State::Established | tcp::State::CloseWait => {
+                if sent < request.len() && socket.can_send() {
+                    sent += socket
+                        .send_slice(&request[sent..])
+                        .map_err(|_| SystemError::ECONNRESET)?;
+                }
+                if sent == request.len() && socket.send_queue() == 0 && !closing {
+                    socket.close();
+                    closing = true;
+                }
+            }
+            // 数据都已被对端确认，不必等待对端关闭连接
+            tcp::State::FinWait2 | tcp::State::TimeWait | tcp::State::Closed if closing => {
+                return Ok(())
+            }
+            tcp::State::Closed => return Err(SystemError::ECONNREFUSED),
+            _ => {}
+        }
+    }
+
+    return Err(SystemError::ETIMEDOUT);
+}
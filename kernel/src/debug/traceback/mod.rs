@@ -0,0 +1,261 @@
+//! 基于帧指针的内核栈回溯
+//!
+//! 与DWARF回溯不同，帧指针回溯能够穿过中断/异常入口：
+//! 当发现某个栈帧的返回地址是中断入口调用处理函数后的返回点时，
+//! 从栈上的TrapFrame中恢复被中断现场的pc和帧指针，继续向上回溯。
+
+use crate::{
+    arch::{interrupt::TrapFrame, process::ArchPCBInfo, CurrentUnwindArch},
+    process::{stack_overflow::is_cpu_stack, KernelStack, ProcessControlBlock},
+};
+
+use super::kallsyms::lookup_symbol;
+
+/// 最大回溯深度
+const MAX_UNWIND_DEPTH: usize = 64;
+
+/// 栈回溯的体系结构相关部分
+pub trait UnwindArch {
+    /// 获取调用者函数的帧指针
+    ///
+    /// 该函数必须被内联，否则得到的是它自己的帧指针
+    fn current_frame_pointer() -> usize;
+
+    /// 读取帧指针`fp`对应的栈帧中保存的(上一级帧指针, 返回地址)
+    ///
+    /// ## Safety
+    ///
+    /// `fp`必须是一个有效的内核栈帧指针
+    unsafe fn read_frame(fp: usize) -> (usize, usize);
+
+    /// 若`ret_addr`是中断/异常入口调用处理函数后的返回点，返回被中断现场的TrapFrame
+    ///
+    /// ## 参数
+    ///
+    /// - `fp`: 中断处理函数的帧指针
+    /// - `ret_addr`: 中断处理函数的返回地址
+    ///
+    /// ## Safety
+    ///
+    /// `fp`必须是一个有效的内核栈帧指针
+    unsafe fn trap_frame(fp: usize, ret_addr: usize) -> Option<&'static TrapFrame>;
+
+    /// 获取被中断现场的(pc, 帧指针)
+    fn trap_frame_pc_fp(trap_frame: &TrapFrame) -> (usize, usize);
+
+    /// 获取进程被切换出去时保存的帧指针
+    fn task_frame_pointer(arch_info: &ArchPCBInfo) -> usize;
+}
+
+/// 栈帧的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackFrameKind {
+    /// 普通的函数调用栈帧
+    Normal,
+    /// 被中断/异常打断的现场
+    Trap,
+}
+
+/// 回溯得到的一个栈帧
+#[derive(Debug, Clone, Copy)]
+pub struct StackFrame {
+    pub pc: usize,
+    pub kind: StackFrameKind,
+}
+
+/// 内核栈回溯器
+///
+/// 迭代返回从当前位置开始，逐级向上的栈帧
+#[derive(Debug)]
+pub struct StackUnwinder {
+    pc: usize,
+    fp: usize,
+    kind: StackFrameKind,
+    /// 当前所在内核栈的范围
+    stack_range: (usize, usize),
+    depth: usize,
+    finished: bool,
+}
+
+impl StackUnwinder {
+    /// 从调用者开始回溯
+    #[inline(never)]
+    pub fn from_current() -> Self {
+        let fp = CurrentUnwindArch::current_frame_pointer();
+        let mut unwinder = Self::new(0, fp, StackFrameKind::Normal);
+        // 跳过from_current自身的栈帧
+        unwinder.step();
+        unwinder
+    }
+
+    /// 从被中断的现场开始回溯
+    pub fn from_trap_frame(trap_frame: &TrapFrame) -> Self {
+        let (pc, fp) = CurrentUnwindArch::trap_frame_pc_fp(trap_frame);
+        let mut unwinder = Self::new(pc, fp, StackFrameKind::Trap);
+        if trap_frame.is_from_user() {
+            unwinder.finished = true;
+        }
+        unwinder
+    }
+
+    /// 回溯一个没有在运行的进程的内核栈，从它调用调度器的位置开始
+    ///
+    /// 调用者需要保证回溯期间该进程不会被调度运行（例如它正处于阻塞状态），
+    /// 否则只能得到不完整的结果
+    pub fn from_task(pcb: &ProcessControlBlock) -> Self {
+        let fp = CurrentUnwindArch::task_frame_pointer(&pcb.arch_info_irqsave());
+        let mut unwinder = Self::new(0, fp, StackFrameKind::Normal);
+        // 栈的范围不能根据帧指针推算：保存的帧指针可能已经失效，必须限制在该进程自己的栈上
+        let stacks = unsafe { [pcb.kernel_stack_force_ref(), pcb.syscall_stack_force_ref()] };
+        let range = stacks
+            .iter()
+            .map(|stack| {
+                let base = stack.start_address().data();
+                (base, base + KernelStack::SIZE)
+            })
+            .find(|&(base, top)| base != 0 && fp > base && fp < top);
+        match range {
+            Some(range) => {
+                unwinder.stack_range = range;
+                // 起始的pc为0，先走一步得到调用调度器的位置
+                unwinder.step();
+            }
+            None => unwinder.finished = true,
+        }
+        unwinder
+    }
+
+    /// 从给定的寄存器现场开始回溯（例如kprobe交给BPF程序的寄存器副本）
+    pub fn from_regs(pc: usize, fp: usize) -> Self {
+        Self::new(pc, fp, StackFrameKind::Trap)
+    }
+
+    fn new(pc: usize, fp: usize, kind: StackFrameKind) -> Self {
+        // 内核栈按照其大小对齐，因此可以直接根据帧指针算出栈的范围
+        let base = fp & !(KernelStack::SIZE - 1);
+        Self {
+            pc,
+            fp,
+            kind,
+            stack_range: (base, base + KernelStack::SIZE),
+            depth: 0,
+            finished: false,
+        }
+    }
+
+    /// 判断帧指针是否可以安全地解引用
+    fn fp_valid(&self, fp: usize) -> bool {
+        fp % core::mem::size_of::<usize>() == 0
+            && fp > self.stack_range.0
+            && fp < self.stack_range.1
+    }
+
+    /// 越过TrapFrame之后，被中断的现场可能在另一个栈上
+    /// （例如溢出栈上的双重错误打断的是原来的内核栈），需要重新确定栈的范围
+    ///
+    /// 只接受当前CPU上已知的栈，否则返回false
+    fn switch_stack(&mut self, fp: usize) -> bool {
+        let base = fp & !(KernelStack::ALIGN - 1);
+        if base == self.stack_range.0 {
+            return true;
+        }
+        if !is_cpu_stack(base) {
+            return false;
+        }
+        self.stack_range = (base, base + KernelStack::SIZE);
+        return true;
+    }
+
+    /// 向上回溯一级
+    fn step(&mut self) {
+        if !self.fp_valid(self.fp) {
+            self.finished = true;
+            return;
+        }
+
+        let (prev_fp, ret_addr) = unsafe { CurrentUnwindArch::read_frame(self.fp) };
+        if ret_addr == 0 {
+            self.finished = true;
+            return;
+        }
+
+        if let Some(trap_frame) = unsafe { CurrentUnwindArch::trap_frame(self.fp, ret_addr) } {
+            if trap_frame.is_from_user() {
+                self.finished = true;
+                return;
+            }
+            let (pc, fp) = CurrentUnwindArch::trap_frame_pc_fp(trap_frame);
+            if !self.switch_stack(fp) {
+                self.finished = true;
+                return;
+            }
+            self.pc = pc;
+            self.fp = fp;
+            self.kind = StackFrameKind::Trap;
+            return;
+        }
+
+        // 栈向低地址增长，调用者的栈帧一定在更高的地址上
+        if prev_fp <= self.fp {
+            self.finished = true;
+            return;
+        }
+        self.pc = ret_addr;
+        self.fp = prev_fp;
+        self.kind = StackFrameKind::Normal;
+    }
+}
+
+impl Iterator for StackUnwinder {
+    type Item = StackFrame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished || self.depth >= MAX_UNWIND_DEPTH || self.pc == 0 {
+            return None;
+        }
+        self.depth += 1;
+
+        let frame = StackFrame {
+            pc: self.pc,
+            kind: self.kind,
+        };
+        self.step();
+        return Some(frame);
+    }
+}
+
+/// 把调用栈的返回地址保存到`buf`中（供perf callchain等使用）
+///
+/// ## 返回值
+///
+/// 保存的地址数量
+pub fn save_stack_trace(unwinder: StackUnwinder, buf: &mut [usize]) -> usize {
+    let mut n = 0;
+    for (slot, frame) in buf.iter_mut().zip(unwinder) {
+        *slot = frame.pc;
+        n += 1;
+    }
+    return n;
+}
+
+/// 打印符号化的调用栈
+pub fn print_stack_trace(unwinder: StackUnwinder) {
+    println!("Kernel Stack Trace:");
+    for (i, frame) in unwinder.enumerate() {
+        if frame.kind == StackFrameKind::Trap {
+            println!("  <interrupted>");
+        }
+        match lookup_symbol(frame.pc) {
+            Some((name, offset)) => {
+                println!("  #{:<2} [{:#018x}] {}+{:#x}", i, frame.pc, name, offset)
+            }
+            None => println!("  #{:<2} [{:#018x}] ?", i, frame.pc),
+        }
+    }
+}
+
+/// 打印当前的内核调用栈
+#[inline(never)]
+pub fn dump_stack() {
+    print_stack_trace(StackUnwinder::from_current());
+}
@@ -27,6 +27,39 @@ int lookup_kallsyms(uint64_t addr, int level)
         return -1;
 }
 
+/**
+ * @brief 查找地址所在的函数符号
+ *
+ * @param addr 要查找的地址
+ * @param offset 返回addr相对于函数起始地址的偏移量
+ * @return const char* 符号名称，找不到时返回NULL
+ */
+const char *kallsyms_lookup_symbol(uint64_t addr, uint64_t *offset)
+{
+    // 第一次链接时，kallsyms还不存在
+    if (&kallsyms_num == NULL || kallsyms_num == 0)
+        return NULL;
+
+    if (addr < kallsyms_address[0] || addr > kallsyms_address[kallsyms_num - 1])
+        return NULL;
+
+    // 符号表按照地址升序排列，二分查找最后一个起始地址不大于addr的符号
+    uint64_t left = 0, right = kallsyms_num - 1;
+    while (left < right)
+    {
+        uint64_t mid = left + (right - left + 1) / 2;
+        if (kallsyms_address[mid] <= addr)
+            left = mid;
+        else
+            right = mid - 1;
+    }
+
+    const char *str = (const char *)&kallsyms_names;
+    if (offset != NULL)
+        *offset = addr - kallsyms_address[left];
+    return &str[kallsyms_names_index[left]];
+}
+
 uint64_t addr_from_symbol(const char *symbol)
 {
     const char *str = (const char *)&kallsyms_names;
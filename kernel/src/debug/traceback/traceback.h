@@ -15,4 +15,5 @@ extern const char *kallsyms_names __attribute__((weak));
  * @param regs 内核栈结构体
  */
 void traceback(struct pt_regs *regs);
-uint64_t addr_from_symbol(const char *symbol);
\ No newline at end of file
+uint64_t addr_from_symbol(const char *symbol);
+const char *kallsyms_lookup_symbol(uint64_t addr, uint64_t *offset);
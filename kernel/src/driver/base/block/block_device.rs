@@ -1,20 +1,23 @@
 /// 引入Module
-use crate::driver::{
-    base::{
-        device::{
-            device_number::{DeviceNumber, Major},
-            Device, DeviceError, IdTable, BLOCKDEVS,
-        },
-        map::{
-            DeviceStruct, DEV_MAJOR_DYN_END, DEV_MAJOR_DYN_EXT_END, DEV_MAJOR_DYN_EXT_START,
-            DEV_MAJOR_HASH_SIZE, DEV_MAJOR_MAX,
+use crate::{
+    debug::fault_inject::FAIL_BLOCK_IO,
+    driver::{
+        base::{
+            device::{
+                device_number::{DeviceNumber, Major},
+                Device, DeviceError, IdTable, BLOCKDEVS,
+            },
+            map::{
+                DeviceStruct, DEV_MAJOR_DYN_END, DEV_MAJOR_DYN_EXT_END, DEV_MAJOR_DYN_EXT_START,
+                DEV_MAJOR_HASH_SIZE, DEV_MAJOR_MAX,
+            },
         },
+        block::cache::{cached_block_device::BlockCache, BlockCacheError, BLOCK_SIZE},
     },
-    block::cache::{cached_block_device::BlockCache, BlockCacheError, BLOCK_SIZE},
 };
 
 use alloc::{string::String, sync::Arc, vec::Vec};
-use core::{any::Any, fmt::Display, ops::Deref};
+use core::{any::Any, fmt::Display, intrinsics::unlikely, ops::Deref};
 use log::error;
 use system_error::SystemError;
 
@@ -356,6 +359,15 @@ pub trait BlockDevice: Device {
         count: usize,
         buf: &mut [u8],
     ) -> Result<usize, SystemError> {
+        if unlikely(FAIL_BLOCK_IO.should_fail(count)) {
+            return Err(SystemError::EIO);
+        }
+        // 先下发与本次读取重叠的、暂存在请求队列中的写请求
+        self.blkdev_meta()
+            .queue()
+            .flush_range(lba_id_start, count, &|lba, count, buf| {
+                self.write_at_sync(lba, count, buf)
+            })?;
         self.cache_read(lba_id_start, count, buf)
     }
 
@@ -367,6 +379,9 @@ pub trait BlockDevice: Device {
         count: usize,
         buf: &[u8],
     ) -> Result<usize, SystemError> {
+        if unlikely(FAIL_BLOCK_IO.should_fail(count)) {
+            return Err(SystemError::EIO);
+        }
         self.cache_write(lba_id_start, count, buf)
     }
 
@@ -378,21 +393,29 @@ pub trait BlockDevice: Device {
         count: usize,
         buf: &mut [u8],
     ) -> Result<usize, SystemError> {
+        // 没有命中缓存时，经由请求队列按IO优先级下发
+        let read_at_queued = |buf: &mut [u8]| {
+            self.blkdev_meta()
+                .queue()
+                .submit_read(lba_id_start, count, buf, &|lba, count, buf| {
+                    self.read_at_sync(lba, count, buf)
+                })
+        };
         let cache_response = BlockCache::read(lba_id_start, count, buf);
         if let Err(e) = cache_response {
             match e {
                 BlockCacheError::StaticParameterError => {
                     BlockCache::init();
-                    let ans = self.read_at_sync(lba_id_start, count, buf)?;
+                    let ans = read_at_queued(buf)?;
                     return Ok(ans);
                 }
                 BlockCacheError::BlockFaultError(fail_vec) => {
-                    let ans = self.read_at_sync(lba_id_start, count, buf)?;
+                    let ans = read_at_queued(buf)?;
                     let _ = BlockCache::insert(fail_vec, buf);
                     return Ok(ans);
                 }
                 _ => {
-                    let ans = self.read_at_sync(lba_id_start, count, buf)?;
+                    let ans = read_at_queued(buf)?;
                     return Ok(ans);
                 }
             }
@@ -410,7 +433,11 @@ pub trait BlockDevice: Device {
         buf: &[u8],
     ) -> Result<usize, SystemError> {
         let _cache_response = BlockCache::immediate_write(lba_id_start, count, buf);
-        self.write_at_sync(lba_id_start, count, buf)
+        self.blkdev_meta()
+            .queue()
+            .submit_write(lba_id_start, count, buf, &|lba, count, buf| {
+                self.write_at_sync(lba, count, buf)
+            })
     }
 
     fn write_at_bytes(&self, offset: usize, len: usize, buf: &[u8]) -> Result<usize, SystemError> {
@@ -487,10 +514,42 @@ pub trait BlockDevice: Device {
         return Ok(len);
     }
 
+    /// # 丢弃一段扇区
+    ///
+    /// 告诉设备从`lba_id_start`开始的`count`个块不再保存有用的数据，设备可以回收它们的空间。
+    /// 之后读取这些块得到的数据是不确定的。
+    ///
+    /// 调用者需要保证请求队列与块缓存中没有这段范围内的数据，见[`GenDisk::discard`]。
+    /// 默认实现返回EOPNOTSUPP，表示设备不支持丢弃
+    fn discard(&self, _lba_id_start: BlockId, _count: usize) -> Result<(), SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    /// # 将一段扇区清零
+    ///
+    /// 调用者需要保证请求队列与块缓存中没有这段范围内的数据，见[`GenDisk::write_zeroes`]。
+    /// 默认实现逐批写入全0的块，支持清零命令的设备应当覆盖它，避免传输数据
+    fn write_zeroes(&self, lba_id_start: BlockId, count: usize) -> Result<(), SystemError> {
+        const BATCH_BLOCKS: usize = 64;
+        let zeroes = vec![0u8; BATCH_BLOCKS << self.blk_size_log2()];
+        let mut done = 0;
+        while done < count {
+            let n = (count - done).min(BATCH_BLOCKS);
+            self.write_at_sync(lba_id_start + done, n, &zeroes[..n << self.blk_size_log2()])?;
+            done += n;
+        }
+        Ok(())
+    }
+
     /// # gendisk注册成功的回调函数
     fn callback_gendisk_registered(&self, _gendisk: &Arc<GenDisk>) -> Result<(), SystemError> {
         Ok(())
     }
+
+    /// 注册磁盘时是否扫描分区表，返回false时整个磁盘被注册为一个gendisk
+    fn partition_scan(&self) -> bool {
+        true
+    }
 }
 
 /// @brief 块设备框架函数集
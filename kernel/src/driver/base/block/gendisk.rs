@@ -7,7 +7,12 @@ use alloc::sync::{Arc, Weak};
 use hashbrown::HashMap;
 use system_error::SystemError;
 
-use super::block_device::{BlockDevice, BlockId, GeneralBlockRange, LBA_SIZE};
+use crate::driver::block::cache::cached_block_device::BlockCache;
+
+use super::{
+    block_device::{BlockDevice, BlockId, GeneralBlockRange, LBA_SIZE},
+    request_queue::BlkPlug,
+};
 
 #[derive(Debug)]
 pub struct GenDisk {
@@ -119,6 +124,53 @@ impl GenDisk {
         return self.block_device().write_at(lba, blocks, buf);
     }
 
+    /// # discard
+    ///
+    /// 丢弃分区内从`start_block_offset`开始的`count`个块，供文件系统在释放空间（如fstrim、打洞）时调用
+    ///
+    /// 设备不支持丢弃时返回EOPNOTSUPP
+    pub fn discard(&self, start_block_offset: BlockId, count: usize) -> Result<(), SystemError> {
+        let lba = self.prepare_range(start_block_offset, count)?;
+        self.block_device().discard(lba, count)
+    }
+
+    /// # write_zeroes
+    ///
+    /// 将分区内从`start_block_offset`开始的`count`个块清零
+    pub fn write_zeroes(
+        &self,
+        start_block_offset: BlockId,
+        count: usize,
+    ) -> Result<(), SystemError> {
+        let lba = self.prepare_range(start_block_offset, count)?;
+        self.block_device().write_zeroes(lba, count)
+    }
+
+    /// 检查分区内的一段块是否越界，并让请求队列与块缓存中不再有这段范围内的数据
+    ///
+    /// 返回这段块在磁盘上的起始块号
+    fn prepare_range(
+        &self,
+        start_block_offset: BlockId,
+        count: usize,
+    ) -> Result<BlockId, SystemError> {
+        if start_block_offset
+            .checked_add(count)
+            .is_none_or(|end| end > self.range.len())
+        {
+            return Err(SystemError::EINVAL);
+        }
+        let lba = self.block_offset_2_disk_blkid(start_block_offset);
+        let dev = self.block_device();
+        dev.blkdev_meta()
+            .queue()
+            .flush_range(lba, count, &|lba, count, buf| {
+                dev.write_at_sync(lba, count, buf)
+            })?;
+        BlockCache::immediate_write(lba, count, &[]).ok();
+        Ok(lba)
+    }
+
     #[inline]
     fn block_offset_2_disk_blkid(&self, block_offset: BlockId) -> BlockId {
         self.range.lba_start + block_offset
@@ -140,9 +192,19 @@ impl GenDisk {
     }
 
     /// # sync
-    /// 同步磁盘
+    /// 同步磁盘，先下发请求队列中暂存的写请求
     pub fn sync(&self) -> Result<(), SystemError> {
-        self.block_device().sync()
+        let dev = self.block_device();
+        dev.blkdev_meta()
+            .queue()
+            .flush(&|lba, count, buf| dev.write_at_sync(lba, count, buf))?;
+        dev.sync()
+    }
+
+    /// # plug
+    /// 塞住磁盘的请求队列，返回的`BlkPlug`被释放时疏通，期间的写请求会被合并、排序后再下发
+    pub fn plug(&self) -> BlkPlug {
+        BlkPlug::new(self.block_device())
     }
 }
 
@@ -171,6 +233,12 @@ impl GenDiskMap {
         self.max_idx.fetch_add(1, Ordering::SeqCst)
     }
 
+    /// 删除所有的gendisk，并重新从1开始分配分区号
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.max_idx.store(1, Ordering::SeqCst);
+    }
+
     pub fn intersects(&self, range: &GeneralBlockRange) -> bool {
         for (_, v) in self.iter() {
             if range.intersects_with(&v.range).is_some() {
@@ -0,0 +1,230 @@
+//! 进程的IO优先级
+//!
+//! IO优先级由调度类与类内的等级组成，编码为`(class << 13) | level`：
+//! - 实时（RT）：总是优先下发，等级0..7，设置需要CAP_SYS_NICE或CAP_SYS_ADMIN
+//! - 尽力而为（BE）：等级0..7，等级越小越优先
+//! - 空闲（IDLE）：只有在磁盘空闲了一段时间之后才下发
+//! - NONE：没有设置，由进程的调度策略与nice值决定实际的调度类与等级
+//!
+//! 块设备的请求队列按照提交请求的进程的IO优先级决定下发的顺序，见`request_queue.rs`。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/block/ioprio.c
+
+use alloc::{sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    process::{cred::CAPFlags, Pid, ProcessControlBlock, ProcessManager},
+    sched::{prio::PrioUtil, FairPolicy},
+    syscall::Syscall,
+};
+
+const IOPRIO_CLASS_SHIFT: u16 = 13;
+const IOPRIO_PRIO_MASK: u16 = (1 << IOPRIO_CLASS_SHIFT) - 1;
+/// RT与BE的等级数
+pub const IOPRIO_NR_LEVELS: u16 = 8;
+/// 没有设置IO优先级的进程，nice为0时的BE等级
+const IOPRIO_BE_NORM: u16 = 4;
+
+/// ioprio_set、ioprio_get的which参数
+const IOPRIO_WHO_PROCESS: i32 = 1;
+const IOPRIO_WHO_PGRP: i32 = 2;
+const IOPRIO_WHO_USER: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPrioClass {
+    None = 0,
+    RealTime = 1,
+    BestEffort = 2,
+    Idle = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoPrio(u16);
+
+impl IoPrio {
+    /// 没有设置IO优先级
+    pub const DEFAULT: IoPrio = IoPrio(0);
+
+    pub const fn new(class: IoPrioClass, level: u16) -> Self {
+        Self(((class as u16) << IOPRIO_CLASS_SHIFT) | (level & IOPRIO_PRIO_MASK))
+    }
+
+    #[inline]
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    #[inline]
+    pub const fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn class(&self) -> IoPrioClass {
+        match self.0 >> IOPRIO_CLASS_SHIFT {
+            1 => IoPrioClass::RealTime,
+            2 => IoPrioClass::BestEffort,
+            3 => IoPrioClass::Idle,
+            _ => IoPrioClass::None,
+        }
+    }
+
+    #[inline]
+    pub fn level(&self) -> u16 {
+        self.0 & IOPRIO_PRIO_MASK
+    }
+
+    /// # 检查用户传入的IO优先级
+    ///
+    /// 调度类或者等级不合法时返回EINVAL，没有权限设置实时调度类时返回EPERM
+    fn from_user(ioprio: i32) -> Result<Self, SystemError> {
+        if !(0..=u16::MAX as i32).contains(&ioprio) {
+            return Err(SystemError::EINVAL);
+        }
+        let ioprio = IoPrio(ioprio as u16);
+        if ioprio.0 >> IOPRIO_CLASS_SHIFT > IoPrioClass::Idle as u16 {
+            return Err(SystemError::EINVAL);
+        }
+        if ioprio.class() == IoPrioClass::RealTime {
+            let cred = ProcessManager::current_pcb().cred();
+            if !cred.has_capability(CAPFlags::CAP_SYS_NICE)
+                && !cred.has_capability(CAPFlags::CAP_SYS_ADMIN)
+            {
+                return Err(SystemError::EPERM);
+            }
+        }
+        match ioprio.class() {
+            IoPrioClass::RealTime | IoPrioClass::BestEffort
+                if ioprio.level() >= IOPRIO_NR_LEVELS =>
+            {
+                return Err(SystemError::EINVAL)
+            }
+            IoPrioClass::None if ioprio.level() != 0 => return Err(SystemError::EINVAL),
+            _ => {}
+        }
+        Ok(ioprio)
+    }
+
+    /// # 进程实际使用的IO优先级
+    ///
+    /// 没有设置IO优先级时，SCHED_IDLE的进程属于IDLE类，实时进程属于RT类，其他进程属于BE类，
+    /// 等级由nice值决定：nice -20..19 对应等级 0..7
+    pub fn effective(pcb: &Arc<ProcessControlBlock>) -> Self {
+        let ioprio = pcb.ioprio();
+        if ioprio.class() != IoPrioClass::None {
+            return ioprio;
+        }
+
+        let sched_info = pcb.sched_info();
+        let prio_data = sched_info.prio_data.read_irqsave();
+        let level = (PrioUtil::prio_to_nice(prio_data.static_prio) + 20) as u16 / 5;
+        if PrioUtil::rt_prio(prio_data.normal_prio) {
+            IoPrio::new(IoPrioClass::RealTime, level)
+        } else if sched_info.fair_policy() == FairPolicy::Idle {
+            IoPrio::new(IoPrioClass::Idle, 0)
+        } else {
+            IoPrio::new(IoPrioClass::BestEffort, level)
+        }
+    }
+
+    /// # 请求调度使用的序号，越小越优先
+    ///
+    /// RT的等级在前，然后是BE的等级，IDLE排在最后
+    pub fn rank(&self) -> usize {
+        let level = self.level().min(IOPRIO_NR_LEVELS - 1) as usize;
+        match self.class() {
+            IoPrioClass::RealTime => level,
+            IoPrioClass::BestEffort => IOPRIO_NR_LEVELS as usize + level,
+            IoPrioClass::None => IOPRIO_NR_LEVELS as usize + IOPRIO_BE_NORM as usize,
+            IoPrioClass::Idle => 2 * IOPRIO_NR_LEVELS as usize,
+        }
+    }
+}
+
+/// 根据which与who找到ioprio_set、ioprio_get的目标进程，没有找到时返回ESRCH
+fn ioprio_targets(which: i32, who: i32) -> Result<Vec<Arc<ProcessControlBlock>>, SystemError> {
+    if who < 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let current = ProcessManager::current_pcb();
+    let targets = match which {
+        IOPRIO_WHO_PROCESS => {
+            if who == 0 {
+                alloc::vec![current]
+            } else {
+                ProcessManager::find(Pid::new(who as usize))
+                    .into_iter()
+                    .collect()
+            }
+        }
+        IOPRIO_WHO_PGRP => {
+            let pgid = if who == 0 {
+                current.basic().pgid()
+            } else {
+                Pid::new(who as usize)
+            };
+            ProcessManager::all_processes()
+                .into_iter()
+                .filter(|p| p.basic().pgid() == pgid)
+                .collect()
+        }
+        IOPRIO_WHO_USER => {
+            let uid = if who == 0 {
+                current.cred().uid.data()
+            } else {
+                who as usize
+            };
+            ProcessManager::all_processes()
+                .into_iter()
+                .filter(|p| p.cred().uid.data() == uid)
+                .collect()
+        }
+        _ => return Err(SystemError::EINVAL),
+    };
+    if targets.is_empty() {
+        return Err(SystemError::ESRCH);
+    }
+    Ok(targets)
+}
+
+/// 只能修改uid与当前进程的uid或有效uid相同的进程，除非有CAP_SYS_NICE
+fn set_task_ioprio(pcb: &Arc<ProcessControlBlock>, ioprio: IoPrio) -> Result<(), SystemError> {
+    let cred = ProcessManager::current_pcb().cred();
+    let target_uid = pcb.cred().uid;
+    if target_uid != cred.euid
+        && target_uid != cred.uid
+        && !cred.has_capability(CAPFlags::CAP_SYS_NICE)
+    {
+        return Err(SystemError::EPERM);
+    }
+    pcb.set_ioprio(ioprio);
+    Ok(())
+}
+
+impl Syscall {
+    /// # 设置进程、进程组或者用户的所有进程的IO优先级
+    ///
+    /// 对多个进程设置时，某个进程失败仍然设置剩下的进程，返回最后一个错误
+    pub fn ioprio_set(which: i32, who: i32, ioprio: i32) -> Result<usize, SystemError> {
+        let ioprio = IoPrio::from_user(ioprio)?;
+        let mut result = Ok(0);
+        for pcb in ioprio_targets(which, who)? {
+            if let Err(e) = set_task_ioprio(&pcb, ioprio) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// # 获取IO优先级
+    ///
+    /// 目标是多个进程时，返回其中最高的IO优先级
+    pub fn ioprio_get(which: i32, who: i32) -> Result<usize, SystemError> {
+        let best = ioprio_targets(which, who)?
+            .iter()
+            .map(|pcb| pcb.ioprio())
+            .min_by_key(|ioprio| ioprio.rank())
+            .unwrap();
+        Ok(best.bits() as usize)
+    }
+}
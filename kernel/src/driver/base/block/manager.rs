@@ -1,20 +1,22 @@
 use core::fmt::Formatter;
 
-use alloc::sync::Arc;
+use alloc::{string::ToString, sync::Arc, vec::Vec};
 use hashbrown::HashMap;
 use system_error::SystemError;
 use unified_init::macros::unified_init;
 
 use crate::{
-    driver::base::block::gendisk::GenDisk,
-    filesystem::mbr::MbrDiskPartionTable,
+    driver::base::{block::gendisk::GenDisk, device::device_number::DeviceNumber},
+    filesystem::{gpt::GptDiskPartitionTable, mbr::MbrDiskPartionTable},
     init::initcall::INITCALL_POSTCORE,
     libs::spinlock::{SpinLock, SpinLockGuard},
+    mm::writeback::BackingDevInfo,
 };
 
 use super::{
     block_device::{BlockDevName, BlockDevice, GeneralBlockRange},
     gendisk::GenDiskMap,
+    request_queue::RequestQueue,
 };
 
 static mut BLOCK_DEV_MANAGER: Option<BlockDevManager> = None;
@@ -71,9 +73,19 @@ impl BlockDevManager {
         Ok(())
     }
 
+    /// 把所有磁盘设备的缓存写回设备
+    pub fn sync_all(&self) {
+        let disks = self.inner().disks.values().cloned().collect::<Vec<_>>();
+        for disk in disks {
+            if let Err(e) = disk.sync() {
+                log::warn!("Failed to sync {}: {:?}", disk.dev_name(), e);
+            }
+        }
+    }
+
     /// 检测分区表，并创建gendisk
     fn check_partitions(&self, dev: &Arc<dyn BlockDevice>) -> Result<(), SystemError> {
-        if self.check_mbr(dev).is_ok() {
+        if dev.partition_scan() && (self.check_gpt(dev).is_ok() || self.check_mbr(dev).is_ok()) {
             return Ok(());
         }
 
@@ -81,8 +93,22 @@ impl BlockDevManager {
         self.register_entire_disk_as_gendisk(dev)
     }
 
+    /// 检测GPT分区表，分区号与分区表项的下标对应
+    fn check_gpt(&self, dev: &Arc<dyn BlockDevice>) -> Result<(), SystemError> {
+        let gpt = GptDiskPartitionTable::from_disk(dev.clone())?;
+        for p in gpt.partitions_raw() {
+            let partno = p.partno as u32;
+            self.register_gendisk_with_idx(dev, p.try_into()?, partno)?;
+        }
+        Ok(())
+    }
+
     fn check_mbr(&self, dev: &Arc<dyn BlockDevice>) -> Result<(), SystemError> {
         let mbr = MbrDiskPartionTable::from_disk(dev.clone())?;
+        // GPT分区表无效时，不把保护性MBR中的分区当作普通分区
+        if mbr.is_protective() {
+            return Err(SystemError::EINVAL);
+        }
         let piter = mbr.partitions_raw();
         for p in piter {
             self.register_gendisk_with_range(dev, p.try_into()?)?;
@@ -113,6 +139,17 @@ impl BlockDevManager {
         self.register_gendisk(dev, gendisk)
     }
 
+    fn register_gendisk_with_idx(
+        &self,
+        dev: &Arc<dyn BlockDevice>,
+        range: GeneralBlockRange,
+        idx: u32,
+    ) -> Result<(), SystemError> {
+        let weak_dev = Arc::downgrade(dev);
+        let gendisk = GenDisk::new(weak_dev, range, Some(idx));
+        self.register_gendisk(dev, gendisk)
+    }
+
     fn register_gendisk(
         &self,
         dev: &Arc<dyn BlockDevice>,
@@ -134,22 +171,60 @@ impl BlockDevManager {
     }
 
     /// 卸载磁盘设备
-    #[allow(dead_code)]
-    pub fn unregister(&self, dev: &Arc<dyn BlockDevice>) {
+    ///
+    /// 磁盘上的gendisk仍在被使用（例如已经被挂载）时，返回`EBUSY`
+    pub fn unregister(&self, dev: &Arc<dyn BlockDevice>) -> Result<(), SystemError> {
         let mut inner = self.inner();
+        let mut meta_inner = dev.blkdev_meta().inner();
+        if meta_inner
+            .gendisks
+            .values()
+            .any(|gendisk| Arc::strong_count(gendisk) > 1)
+        {
+            return Err(SystemError::EBUSY);
+        }
+        meta_inner.gendisks.clear();
+        drop(meta_inner);
+
         inner.disks.remove(dev.dev_name());
-        // todo: 这里应该callback一下磁盘设备，但是现在还没实现热插拔，所以暂时没做这里
-        todo!("BlockDevManager: unregister disk")
+        Ok(())
     }
 
     /// 通过路径查找gendisk
     ///
+    /// 名字以数字结尾的磁盘（如`loop0`），其分区的名字需要加上`p`，如`loop0p1`
+    ///
     /// # 参数
     ///
     /// - `path`: 分区路径 `/dev/sda1` 或者 `sda1`，或者是`/dev/sda`
     pub fn lookup_gendisk_by_path(&self, path: &str) -> Option<Arc<GenDisk>> {
-        let (devname, partno) = self.path2devname(path)?;
+        let path = path.strip_prefix("/dev/").unwrap_or(path);
         let inner = self.inner();
+        if let Some(dev) = inner
+            .disks
+            .values()
+            .find(|dev| dev.dev_name().as_str() == path)
+        {
+            // 没有分区表的磁盘，整个磁盘被注册为一个gendisk
+            let disk_range = dev.disk_range();
+            let meta = dev.blkdev_meta().inner();
+            return meta
+                .gendisks
+                .get(&GenDisk::ENTIRE_DISK_IDX)
+                .or_else(|| {
+                    meta.gendisks.values().find(|gendisk| {
+                        gendisk.range().lba_start == disk_range.lba_start
+                            && gendisk.range().lba_end == disk_range.lba_end
+                    })
+                })
+                .cloned();
+        }
+
+        let (devname, partno) = self.path2devname(path)?;
+        let devname = devname
+            .strip_suffix('p')
+            .filter(|name| name.ends_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or(devname);
         for dev in inner.disks.values() {
             if dev.dev_name().as_str() == devname {
                 return dev.blkdev_meta().inner().gendisks.get(&partno).cloned();
@@ -158,6 +233,24 @@ impl BlockDevManager {
         None
     }
 
+    /// 通过设备号查找整个磁盘对应的gendisk
+    pub fn lookup_gendisk_by_devt(&self, devt: DeviceNumber) -> Option<Arc<GenDisk>> {
+        let inner = self.inner();
+        let dev = inner
+            .disks
+            .values()
+            .find(|dev| dev.device().id_table().device_number() == devt)?;
+        let disk_range = dev.disk_range();
+        let meta = dev.blkdev_meta().inner();
+        meta.gendisks
+            .values()
+            .find(|gendisk| {
+                gendisk.range().lba_start == disk_range.lba_start
+                    && gendisk.range().lba_end == disk_range.lba_end
+            })
+            .cloned()
+    }
+
     /// 打印所有的gendisk的路径
     pub fn print_gendisks(&self) {
         let mut disks = alloc::vec::Vec::new();
@@ -168,6 +261,8 @@ impl BlockDevManager {
             for idx in meta.gendisks.keys() {
                 if idx == &GenDisk::ENTIRE_DISK_IDX {
                     disks.push(format!("/dev/{}", dev.dev_name()));
+                } else if dev.dev_name().ends_with(|c: char| c.is_ascii_digit()) {
+                    disks.push(format!("/dev/{}p{}", dev.dev_name(), idx));
                 } else {
                     disks.push(format!("/dev/{}{}", dev.dev_name(), idx));
                 }
@@ -209,6 +304,8 @@ impl BlockDevManager {
 pub struct BlockDevMeta {
     pub devname: BlockDevName,
     inner: SpinLock<InnerBlockDevMeta>,
+    queue: RequestQueue,
+    bdi: Arc<BackingDevInfo>,
 }
 
 pub struct InnerBlockDevMeta {
@@ -218,13 +315,25 @@ pub struct InnerBlockDevMeta {
 impl BlockDevMeta {
     pub fn new(devname: BlockDevName) -> Self {
         BlockDevMeta {
+            bdi: BackingDevInfo::new(devname.to_string()),
             devname,
             inner: SpinLock::new(InnerBlockDevMeta {
                 gendisks: GenDiskMap::new(),
             }),
+            queue: RequestQueue::new(),
         }
     }
 
+    /// 块设备的后备设备信息，统计页面缓存中属于这个设备的脏页
+    pub fn bdi(&self) -> &Arc<BackingDevInfo> {
+        &self.bdi
+    }
+
+    /// 块设备的请求队列
+    pub fn queue(&self) -> &RequestQueue {
+        &self.queue
+    }
+
     fn inner(&self) -> SpinLockGuard<InnerBlockDevMeta> {
         self.inner.lock()
     }
@@ -1,7 +1,9 @@
 pub mod block_device;
 pub mod disk_info;
 pub mod gendisk;
+pub mod ioprio;
 pub mod manager;
+pub mod request_queue;
 
 #[derive(Debug)]
 #[allow(dead_code)]
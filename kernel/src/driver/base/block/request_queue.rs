@@ -0,0 +1,353 @@
+//! 块设备的请求队列
+//!
+//! 每个块设备有一个请求队列。队列没有被塞住（plug）时，写请求直接下发给驱动，与原来的行为相同；
+//! 队列被塞住期间，写请求被暂存在队列中：相邻或重叠的请求被合并成一个更大的请求，
+//! 所有请求按起始扇区排序。队列被疏通（unplug）时，按扇区从小到大的顺序（简单的电梯算法）
+//! 一次性下发，从而把文件系统零散的小写入变成少量连续的大写入。
+//!
+//! 与早期的Linux一样，塞住的是整个队列而不是单个进程。读请求与暂存的写请求重叠时，
+//! 先下发暂存的写请求，再读取，保证读到的是最新的数据。
+//!
+//! 同一时刻只有一个进程向驱动下发请求。下发之前，进程按照自己的IO优先级（见`ioprio.rs`）
+//! 排队：有更高优先级的进程在等待时，让它们先下发；IDLE类的进程只有在其他进程的请求完成
+//! 一段时间（`IOPRIO_IDLE_DELAY`）之后才能下发，使得后台的IO不会影响交互进程的延迟。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-2.6.39/block/blk-core.c
+//! 参考 https://code.dragonos.org.cn/xref/linux-2.6.39/block/cfq-iosched.c
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use log::warn;
+use system_error::SystemError;
+
+use crate::{
+    libs::{mutex::Mutex, spinlock::SpinLock, wait_queue::WaitQueue},
+    process::ProcessManager,
+    time::{
+        clocksource::HZ,
+        timer::{clock, schedule_timeout},
+    },
+};
+
+use super::{
+    block_device::{BlockDevice, BlockId, LBA_SIZE},
+    ioprio::{IoPrio, IoPrioClass, IOPRIO_NR_LEVELS},
+};
+
+/// 合并后的单个请求的最大长度（扇区）
+pub const BLK_MAX_SECTORS: usize = 256;
+/// 队列中最多暂存的请求数，超过后即使仍被塞住也会下发
+pub const BLK_MAX_REQUEST_COUNT: usize = 32;
+
+/// IDLE类的请求在其他类的请求完成之后至少等待的时间（jiffies），与CFQ相同为200ms
+const IOPRIO_IDLE_DELAY: u64 = HZ / 5;
+/// 不同的IO优先级序号的个数，见`IoPrio::rank`
+const IOPRIO_NR_RANKS: usize = 2 * IOPRIO_NR_LEVELS as usize + 1;
+
+/// 下发写请求的函数，参数为`(起始扇区, 扇区数, 数据)`
+pub type BlockWriteFn<'a> = &'a dyn Fn(BlockId, usize, &[u8]) -> Result<usize, SystemError>;
+/// 下发读请求的函数，参数为`(起始扇区, 扇区数, 缓冲区)`
+pub type BlockReadFn<'a> = &'a dyn Fn(BlockId, usize, &mut [u8]) -> Result<usize, SystemError>;
+
+#[derive(Debug)]
+pub struct RequestQueue {
+    inner: Mutex<InnerRequestQueue>,
+    /// 按IO优先级决定由哪个进程下发请求
+    dispatch_slot: SpinLock<DispatchSlot>,
+    slot_wait: WaitQueue,
+}
+
+#[derive(Debug)]
+struct DispatchSlot {
+    /// 是否有进程正在下发请求
+    busy: bool,
+    /// 每个IO优先级序号上等待下发的进程数
+    waiting: [usize; IOPRIO_NR_RANKS],
+    /// 最近一次非IDLE类的下发结束的时刻（jiffies）
+    last_busy: Option<u64>,
+}
+
+/// 持有期间可以向驱动下发请求，drop时让出
+struct DispatchGuard<'a> {
+    queue: &'a RequestQueue,
+    idle: bool,
+}
+
+impl Drop for DispatchGuard<'_> {
+    fn drop(&mut self) {
+        let mut slot = self.queue.dispatch_slot.lock_irqsave();
+        slot.busy = false;
+        if !self.idle {
+            slot.last_busy = Some(clock());
+        }
+        drop(slot);
+        self.queue.slot_wait.wakeup_all(None);
+    }
+}
+
+#[derive(Debug, Default)]
+struct InnerRequestQueue {
+    /// 塞住的层数，为0时队列是通畅的
+    plug_depth: usize,
+    /// 暂存的写请求，以起始扇区为键，互不重叠
+    pending: BTreeMap<BlockId, Vec<u8>>,
+    /// 被合并掉的请求数
+    merged: usize,
+    /// 下发给驱动的请求数
+    dispatched: usize,
+}
+
+impl InnerRequestQueue {
+    /// 按扇区从小到大的顺序下发所有暂存的请求
+    ///
+    /// 某个请求失败时仍然下发剩下的请求，返回第一个错误
+    fn dispatch(&mut self, write: BlockWriteFn) -> Result<(), SystemError> {
+        let pending = core::mem::take(&mut self.pending);
+        let mut result = Ok(());
+        for (lba, data) in pending {
+            self.dispatched += 1;
+            if let Err(e) = write(lba, data.len() / LBA_SIZE, &data) {
+                warn!(
+                    "request queue: write {} sectors at {} failed: {:?}",
+                    data.len() / LBA_SIZE,
+                    lba,
+                    e
+                );
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    /// 把写请求放入队列，与重叠或相邻的请求合并
+    fn insert(
+        &mut self,
+        lba: BlockId,
+        data: &[u8],
+        write: BlockWriteFn,
+    ) -> Result<(), SystemError> {
+        let end = lba + data.len() / LBA_SIZE;
+
+        // 暂存的请求互不重叠，因此从起始扇区不超过end的请求往前找，直到遇到结束扇区小于lba的请求
+        let neighbours: Vec<BlockId> = self
+            .pending
+            .range(..=end)
+            .rev()
+            .take_while(|(start, buf)| *start + buf.len() / LBA_SIZE >= lba)
+            .map(|(start, _)| *start)
+            .collect();
+
+        let merge_start = neighbours.last().map_or(lba, |s| (*s).min(lba));
+        let merge_end = neighbours
+            .first()
+            .map_or(end, |s| (s + self.pending[s].len() / LBA_SIZE).max(end));
+
+        if merge_end - merge_start > BLK_MAX_SECTORS && !neighbours.is_empty() {
+            // 合并后太大，先把暂存的请求下发（重叠的旧数据会被新数据覆盖）
+            self.dispatch(write)?;
+            self.pending.insert(lba, data.to_vec());
+            return Ok(());
+        }
+
+        // 先放旧数据，再用新数据覆盖重叠的部分
+        let mut merged = vec![0u8; (merge_end - merge_start) * LBA_SIZE];
+        for start in neighbours.iter() {
+            let old = self.pending.remove(start).unwrap();
+            let offset = (start - merge_start) * LBA_SIZE;
+            merged[offset..offset + old.len()].copy_from_slice(&old);
+        }
+        let offset = (lba - merge_start) * LBA_SIZE;
+        merged[offset..offset + data.len()].copy_from_slice(data);
+        self.merged += neighbours.len();
+        self.pending.insert(merge_start, merged);
+
+        if self.pending.len() >= BLK_MAX_REQUEST_COUNT {
+            self.dispatch(write)?;
+        }
+        Ok(())
+    }
+
+    /// `[lba, lba + count)`是否与暂存的请求重叠
+    fn overlaps(&self, lba: BlockId, count: usize) -> bool {
+        self.pending
+            .range(..lba + count)
+            .next_back()
+            .is_some_and(|(start, buf)| start + buf.len() / LBA_SIZE > lba)
+    }
+}
+
+impl Default for RequestQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestQueue {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(InnerRequestQueue::default()),
+            dispatch_slot: SpinLock::new(DispatchSlot {
+                busy: false,
+                waiting: [0; IOPRIO_NR_RANKS],
+                last_busy: None,
+            }),
+            slot_wait: WaitQueue::default(),
+        }
+    }
+
+    /// # 按照当前进程的IO优先级，等待轮到自己下发请求
+    ///
+    /// 没有进程正在下发、也没有更高优先级的进程在等待时才能下发；
+    /// IDLE类的进程还要等到其他类的下发结束`IOPRIO_IDLE_DELAY`之后
+    fn wait_dispatch(&self) -> DispatchGuard<'_> {
+        let ioprio = IoPrio::effective(&ProcessManager::current_pcb());
+        let rank = ioprio.rank();
+        let idle = ioprio.class() == IoPrioClass::Idle;
+
+        let mut slot = self.dispatch_slot.lock_irqsave();
+        slot.waiting[rank] += 1;
+        loop {
+            if slot.busy || slot.waiting[..rank].iter().any(|n| *n > 0) {
+                self.slot_wait.sleep_uninterruptible_unlock_spinlock(slot);
+                slot = self.dispatch_slot.lock_irqsave();
+                continue;
+            }
+            if let Some(last_busy) = slot.last_busy.filter(|_| idle) {
+                let now = clock();
+                if now < last_busy + IOPRIO_IDLE_DELAY {
+                    drop(slot);
+                    schedule_timeout((last_busy + IOPRIO_IDLE_DELAY - now) as i64).ok();
+                    slot = self.dispatch_slot.lock_irqsave();
+                    continue;
+                }
+            }
+            break;
+        }
+        slot.waiting[rank] -= 1;
+        slot.busy = true;
+        DispatchGuard { queue: self, idle }
+    }
+
+    /// 塞住队列，可以嵌套
+    pub fn plug(&self) {
+        self.inner.lock().plug_depth += 1;
+    }
+
+    /// 疏通队列，最外层的疏通会下发所有暂存的请求
+    pub fn unplug(&self, write: BlockWriteFn) -> Result<(), SystemError> {
+        let mut inner = self.inner.lock();
+        inner.plug_depth = inner.plug_depth.saturating_sub(1);
+        if inner.plug_depth != 0 || inner.pending.is_empty() {
+            return Ok(());
+        }
+        drop(inner);
+
+        let _dispatch = self.wait_dispatch();
+        let mut inner = self.inner.lock();
+        // 等待期间队列可能又被塞住
+        if inner.plug_depth != 0 {
+            return Ok(());
+        }
+        inner.dispatch(write)
+    }
+
+    /// 提交写请求
+    ///
+    /// 队列通畅时直接下发，否则暂存在队列中，返回写入的字节数
+    pub fn submit_write(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+        write: BlockWriteFn,
+    ) -> Result<usize, SystemError> {
+        let _dispatch = self.wait_dispatch();
+        let mut inner = self.inner.lock();
+        if inner.plug_depth == 0 {
+            inner.dispatched += 1;
+            return write(lba_id_start, count, buf);
+        }
+        inner.insert(lba_id_start, &buf[..count * LBA_SIZE], write)?;
+        Ok(count * LBA_SIZE)
+    }
+
+    /// 读取`[lba, lba + count)`之前调用，如果与暂存的写请求重叠，则先下发暂存的请求
+    pub fn flush_range(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        write: BlockWriteFn,
+    ) -> Result<(), SystemError> {
+        if self.inner.lock().overlaps(lba_id_start, count) {
+            return self.flush(write);
+        }
+        Ok(())
+    }
+
+    /// 下发所有暂存的请求，不改变队列是否被塞住
+    pub fn flush(&self, write: BlockWriteFn) -> Result<(), SystemError> {
+        if self.inner.lock().pending.is_empty() {
+            return Ok(());
+        }
+        let _dispatch = self.wait_dispatch();
+        self.inner.lock().dispatch(write)
+    }
+
+    /// # 提交读请求
+    ///
+    /// 读请求不经过暂存，轮到当前进程下发时直接下发，返回读取的字节数
+    pub fn submit_read(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+        read: BlockReadFn,
+    ) -> Result<usize, SystemError> {
+        let _dispatch = self.wait_dispatch();
+        self.inner.lock().dispatched += 1;
+        read(lba_id_start, count, buf)
+    }
+
+    /// 返回`(被合并掉的请求数, 下发给驱动的请求数)`
+    pub fn stats(&self) -> (usize, usize) {
+        let inner = self.inner.lock();
+        (inner.merged, inner.dispatched)
+    }
+}
+
+/// 在作用域内塞住块设备的请求队列，离开作用域时疏通
+///
+/// 需要得知下发的结果时，使用`finish`代替drop
+#[derive(Debug)]
+pub struct BlkPlug {
+    dev: Option<Arc<dyn BlockDevice>>,
+}
+
+impl BlkPlug {
+    pub fn new(dev: Arc<dyn BlockDevice>) -> Self {
+        dev.blkdev_meta().queue().plug();
+        Self { dev: Some(dev) }
+    }
+
+    /// 疏通队列，返回下发暂存请求的结果
+    pub fn finish(mut self) -> Result<(), SystemError> {
+        self.do_unplug()
+    }
+
+    fn do_unplug(&mut self) -> Result<(), SystemError> {
+        match self.dev.take() {
+            Some(dev) => dev
+                .blkdev_meta()
+                .queue()
+                .unplug(&|lba, count, buf| dev.write_at_sync(lba, count, buf)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for BlkPlug {
+    fn drop(&mut self) {
+        self.do_unplug().ok();
+    }
+}
@@ -3,18 +3,21 @@ use core::any::Any;
 use alloc::{
     string::{String, ToString},
     sync::{Arc, Weak},
+    vec::Vec,
 };
 
 use crate::{
     driver::acpi::acpi_manager,
     filesystem::kernfs::KernFSInode,
     libs::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    smp::cpu::{smp_cpu_manager, ProcessorId},
 };
 
 use super::{
     class::Class,
     device::{
         bus::{subsystem_manager, Bus},
+        device_manager,
         driver::Driver,
         Device, DeviceCommonData, DeviceType, IdTable,
     },
@@ -33,6 +36,7 @@ static CPU_DEVICE_MANAGER: Lazy<CpuDeviceManager> = Lazy::new();
 #[derive(Debug)]
 pub struct CpuDeviceManager {
     _root_device: Arc<CpuSubSystemFakeRootDevice>,
+    _cpu_devices: Vec<Arc<CpuDevice>>,
 }
 
 impl CpuDeviceManager {
@@ -44,12 +48,24 @@ impl CpuDeviceManager {
         let root_device = CpuSubSystemFakeRootDevice::new();
         subsystem_manager()
             .subsys_system_register(
-                &(cpu_subsys as Arc<dyn Bus>),
+                &(cpu_subsys.clone() as Arc<dyn Bus>),
                 &(root_device.clone() as Arc<dyn Device>),
             )
             .expect("register cpu subsys failed");
+
+        // 为每个CPU注册/sys/devices/system/cpu/cpuN
+        let mut cpu_devices = Vec::new();
+        for cpu in smp_cpu_manager().present_cpus().iter_cpu() {
+            let device = CpuDevice::new(cpu);
+            device.set_bus(Some(Arc::downgrade(&(cpu_subsys.clone() as Arc<dyn Bus>))));
+            device_manager().device_default_initialize(&(device.clone() as Arc<dyn Device>));
+            device_manager().add_device(device.clone() as Arc<dyn Device>)?;
+            cpu_devices.push(device);
+        }
+
         let manager = Self {
             _root_device: root_device,
+            _cpu_devices: cpu_devices,
         };
         CPU_DEVICE_MANAGER.init(manager);
         return Ok(());
@@ -62,12 +78,14 @@ impl CpuDeviceManager {
 #[derive(Debug)]
 struct CpuSubSystem {
     subsys_private: SubSysPrivate,
+    root_device: RwLock<Option<Weak<dyn Device>>>,
 }
 
 impl CpuSubSystem {
     pub fn new() -> Arc<Self> {
         let bus = Arc::new(Self {
             subsys_private: SubSysPrivate::new("cpu".to_string(), None, None, &[]),
+            root_device: RwLock::new(None),
         });
         bus.subsystem()
             .set_bus(Some(Arc::downgrade(&(bus.clone() as Arc<dyn Bus>))));
@@ -108,6 +126,14 @@ impl Bus for CpuSubSystem {
     fn subsystem(&self) -> &SubSysPrivate {
         &self.subsys_private
     }
+
+    fn root_device(&self) -> Option<Weak<dyn Device>> {
+        self.root_device.read().clone()
+    }
+
+    fn set_root_device(&self, dev: Option<Weak<dyn Device>>) {
+        *self.root_device.write() = dev;
+    }
 }
 
 #[derive(Debug)]
@@ -319,9 +345,216 @@ impl Attribute for AttrCpuOnline {
     }
 
     fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
-        let cpu_manager = crate::smp::cpu::smp_cpu_manager();
-        let cpus = cpu_manager.present_cpus_count();
-        let data = format!("0-{}", cpus - 1);
+        let data = smp_cpu_manager().online_cpus().to_list_string();
         sysfs_emit_str(buf, &data)
     }
 }
+
+/// 一个CPU在sysfs中对应的设备，即/sys/devices/system/cpu/cpuN
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/cpu.c#378
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct CpuDevice {
+    cpu: ProcessorId,
+    inner: RwLock<InnerCpuDevice>,
+    kobj_state: LockedKObjectState,
+}
+
+#[derive(Debug)]
+struct InnerCpuDevice {
+    kobject_common: KObjectCommonData,
+    device_common: DeviceCommonData,
+}
+
+impl CpuDevice {
+    pub fn new(cpu: ProcessorId) -> Arc<Self> {
+        return Arc::new(Self {
+            cpu,
+            inner: RwLock::new(InnerCpuDevice {
+                kobject_common: KObjectCommonData::default(),
+                device_common: DeviceCommonData::default(),
+            }),
+            kobj_state: LockedKObjectState::new(None),
+        });
+    }
+
+    pub fn cpu(&self) -> ProcessorId {
+        self.cpu
+    }
+}
+
+impl Device for CpuDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Other
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(self.name(), None)
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner.write().device_common.bus = bus;
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner.read().device_common.bus.clone()
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        None
+    }
+
+    fn set_driver(&self, _driver: Option<Weak<dyn Driver>>) {}
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        false
+    }
+
+    fn set_can_match(&self, _can_match: bool) {}
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn set_class(&self, _class: Option<Weak<dyn Class>>) {}
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner.read().device_common.parent.clone()
+    }
+
+    fn set_dev_parent(&self, dev_parent: Option<Weak<dyn Device>>) {
+        self.inner.write().device_common.parent = dev_parent;
+    }
+
+    fn attribute_groups(&self) -> Option<&'static [&'static dyn AttributeGroup]> {
+        Some(&[&AttrGroupCpuDevice])
+    }
+}
+
+impl KObject for CpuDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner.write().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner.read().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner.read().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner.write().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner.read().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner.write().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner.read().kobject_common.kobj_type
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner.write().kobject_common.kobj_type = ktype;
+    }
+
+    fn name(&self) -> String {
+        format!("cpu{}", self.cpu.data())
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.kobj_state_mut() = state;
+    }
+}
+
+#[derive(Debug)]
+pub struct AttrGroupCpuDevice;
+
+impl AttributeGroup for AttrGroupCpuDevice {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn attrs(&self) -> &[&'static dyn Attribute] {
+        &[&AttrCpuDeviceOnline]
+    }
+
+    fn is_visible(&self, kobj: Arc<dyn KObject>, attr: &'static dyn Attribute) -> Option<ModeType> {
+        let device = kobj.arc_any().downcast::<CpuDevice>().ok()?;
+        // CPU0负责更新jiffies，不能离线，因此没有online文件
+        if device.cpu() == ProcessorId::new(0) {
+            return Some(ModeType::empty());
+        }
+        Some(attr.mode())
+    }
+}
+
+/// /sys/devices/system/cpu/cpuN/online，写入0让CPU离线，写入1让CPU上线
+#[derive(Debug)]
+pub struct AttrCpuDeviceOnline;
+
+impl Attribute for AttrCpuDeviceOnline {
+    fn name(&self) -> &str {
+        "online"
+    }
+
+    fn mode(&self) -> ModeType {
+        ModeType::S_IRUGO | ModeType::S_IWUSR
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW | SysFSOpsSupport::ATTR_STORE
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let device = kobj
+            .arc_any()
+            .downcast::<CpuDevice>()
+            .map_err(|_| SystemError::EINVAL)?;
+        let online = smp_cpu_manager().cpu_online(device.cpu());
+        sysfs_emit_str(buf, if online { "1" } else { "0" })
+    }
+
+    fn store(&self, kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        let device = kobj
+            .arc_any()
+            .downcast::<CpuDevice>()
+            .map_err(|_| SystemError::EINVAL)?;
+        let value = core::str::from_utf8(buf)
+            .map_err(|_| SystemError::EINVAL)?
+            .trim_matches(|c: char| c.is_whitespace() || c == '\0');
+        match value {
+            "1" => smp_cpu_manager().cpu_device_up(device.cpu())?,
+            "0" => smp_cpu_manager().cpu_device_down(device.cpu())?,
+            _ => return Err(SystemError::EINVAL),
+        }
+        Ok(buf.len())
+    }
+}
@@ -21,6 +21,7 @@ use crate::{
 use alloc::{
     string::{String, ToString},
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use core::{ffi::CStr, fmt::Debug, intrinsics::unlikely};
 use hashbrown::HashMap;
@@ -81,7 +82,7 @@ impl From<DeviceState> for BusState {
         match state {
             DeviceState::Initialized => BusState::Initialized,
             DeviceState::NotInitialized => BusState::NotInitialized,
-            DeviceState::UnDefined => BusState::UnDefined,
+            DeviceState::UnDefined | DeviceState::Removed => BusState::UnDefined,
         }
     }
 }
@@ -289,6 +290,70 @@ impl BusManager {
         return Ok(());
     }
 
+    /// 把一个设备从总线上移除（与`add_device`相反）
+    ///
+    /// ## 描述
+    ///
+    /// - 通知总线的接口，设备将被移除
+    /// - 删除在bus和设备文件夹下创建的软链接
+    /// - 删除设备的与bus相关的属性
+    /// - 把设备从它的总线的设备列表中移除
+    /// - 把设备与它的驱动解绑
+    ///
+    /// 参考： https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/bus.c?fi=bus_remove_device#519
+    ///
+    /// ## 参数
+    ///
+    /// - `dev` - 要被移除的设备
+    pub fn remove_device(&self, dev: &Arc<dyn Device>) {
+        let bus = dev.bus().and_then(|bus| bus.upgrade());
+        if let Some(bus) = bus {
+            for interface in bus.subsystem().interfaces() {
+                interface.remove_device(dev);
+            }
+
+            let dev_kobj = dev.clone() as Arc<dyn KObject>;
+            sysfs_instance().remove_link(&dev_kobj, "subsystem".to_string());
+            if let Some(bus_devices_kset) = bus.subsystem().devices_kset() {
+                sysfs_instance().remove_link(&bus_devices_kset.as_kobject(), dev.name());
+            }
+
+            device_manager().remove_groups(dev, bus.dev_groups());
+            bus.subsystem().remove_device_from_vec(dev);
+            device_manager().device_release_driver(dev);
+        }
+    }
+
+    /// 在所有总线上，查找父设备为`dev`的设备
+    ///
+    /// 删除设备之前，需要先删除它的子设备
+    pub fn children_of(&self, dev: &Arc<dyn Device>) -> Vec<Arc<dyn Device>> {
+        let buses = self
+            .kset_bus_map
+            .read()
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut children = Vec::new();
+        for bus in buses {
+            let subsys = bus.subsystem();
+            let devices = subsys.devices();
+            children.extend(
+                devices
+                    .iter()
+                    .filter(|child| {
+                        child
+                            .dev_parent()
+                            .and_then(|parent| parent.upgrade())
+                            .is_some_and(|parent| Arc::ptr_eq(&parent, dev))
+                    })
+                    .cloned(),
+            );
+        }
+        return children;
+    }
+
     /// 在总线上添加一个驱动
     ///
     /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/bus.c?fi=bus_add_driver#590
@@ -576,6 +641,17 @@ pub fn bus_add_device(dev: &Arc<dyn Device>) -> Result<(), SystemError> {
     return bus_manager().add_device(dev);
 }
 
+/// 把一个设备从总线上移除
+///
+/// 参考： https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/bus.c?fi=bus_remove_device#519
+///
+/// ## 参数
+///
+/// - `dev` - 要被移除的设备
+pub fn bus_remove_device(dev: &Arc<dyn Device>) {
+    bus_manager().remove_device(dev);
+}
+
 /// 自动为设备在总线上寻找可用的驱动程序
 ///
 /// Automatically probe for a driver if the bus allows it.
@@ -5,7 +5,10 @@ use intertrait::cast::CastArc;
 use log::{debug, error, warn};
 
 use crate::{
-    driver::base::kobject::KObject,
+    driver::base::{
+        kobject::KObject,
+        uevent::{kobject_uevent, KObjectAction},
+    },
     filesystem::{
         sysfs::{
             file::sysfs_emit_str, sysfs_instance, Attribute, SysFSOpsSupport, SYSFS_ATTR_MODE_WO,
@@ -226,6 +229,46 @@ impl DeviceManager {
         return r;
     }
 
+    /// 把设备与它的驱动解绑
+    ///
+    /// 如果设备没有绑定驱动，则什么也不做
+    ///
+    /// ## 参数
+    ///
+    /// - `dev`: 设备
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/dd.c#1216
+    pub fn device_release_driver(&self, dev: &Arc<dyn Device>) {
+        let driver = dev.driver();
+        if driver.is_none() {
+            return;
+        }
+        let driver = driver.unwrap();
+
+        let bus = dev.bus().and_then(|bus| bus.upgrade());
+        if let Some(bus) = bus.as_ref() {
+            bus.subsystem().bus_notifier().call_chain(
+                BusNotifyEvent::UnbindDriver,
+                Some(dev),
+                None,
+            );
+        }
+
+        driver_manager().remove_from_sysfs(dev);
+        driver.delete_device(dev);
+        self.unbind_cleanup(dev);
+
+        if let Some(bus) = bus {
+            bus.subsystem().bus_notifier().call_chain(
+                BusNotifyEvent::UnboundDriver,
+                Some(dev),
+                None,
+            );
+        }
+
+        kobject_uevent(&(dev.clone() as Arc<dyn KObject>), KObjectAction::Unbind).ok();
+    }
+
     /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=driver_attach#528
     fn unbind_cleanup(&self, dev: &Arc<dyn Device>) {
         dev.set_driver(None);
@@ -500,8 +543,14 @@ impl DriverManager {
     }
 
     /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=driver_attach#469
-    fn remove_from_sysfs(&self, _device: &Arc<dyn Device>) {
-        todo!("remove_from_sysfs")
+    fn remove_from_sysfs(&self, device: &Arc<dyn Device>) {
+        let device_kobj = device.clone() as Arc<dyn KObject>;
+        if let Some(driver) = device.driver() {
+            let driver_kobj = driver as Arc<dyn KObject>;
+            sysfs_instance().remove_link(&driver_kobj, device.name());
+        }
+        sysfs_instance().remove_link(&device_kobj, "driver".to_string());
+        device_manager().remove_file(device, &DeviceAttrCoredump);
     }
 
     fn call_driver_probe(
@@ -570,7 +619,7 @@ impl DriverManager {
             );
         }
 
-        // todo: 发送kobj bind的uevent
+        kobject_uevent(&(device.clone() as Arc<dyn KObject>), KObjectAction::Bind).ok();
     }
 
     fn driver_is_bound(&self, device: &Arc<dyn Device>) -> bool {
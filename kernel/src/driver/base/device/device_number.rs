@@ -13,6 +13,9 @@ impl Major {
     pub const TTY_MAJOR: Self = Self::new(4);
     pub const TTYAUX_MAJOR: Self = Self::new(5);
     pub const HD_MAJOR: Self = Self::IDE0_MAJOR;
+    /// 回环设备
+    pub const LOOP_MAJOR: Self = Self::new(7);
+    pub const MISC_MAJOR: Self = Self::new(10);
 
     pub const INPUT_MAJOR: Self = Self::new(13);
     /// /dev/fb* framebuffers
@@ -24,6 +27,9 @@ impl Major {
     pub const UNIX98_PTY_SLAVE_MAJOR: Self =
         Self::new(Self::UNIX98_PTY_MASTER_MAJOR.0 + Self::UNIX98_PTY_MAJOR_COUNT.0);
 
+    /// device-mapper，Linux中是动态分配的，这里固定使用253
+    pub const DM_MAJOR: Self = Self::new(253);
+
     pub const fn new(x: u32) -> Self {
         Major(x)
     }
@@ -7,11 +7,13 @@ use intertrait::cast::CastArc;
 use log::{error, warn};
 
 use crate::{
+    arch::CurrentIrqArch,
+    debug::obj_lifetime::{obj_lifetime_release, obj_lifetime_track, ObjKind},
     driver::{
         acpi::glue::acpi_device_notify,
         base::map::{LockedDevsMap, LockedKObjMap},
     },
-    exception::irqdata::IrqHandlerData,
+    exception::{irqdata::IrqHandlerData, InterruptArch},
     filesystem::{
         kernfs::KernFSInode,
         sysfs::{
@@ -24,6 +26,8 @@ use crate::{
         rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
         spinlock::{SpinLock, SpinLockGuard},
     },
+    process::ProcessManager,
+    syscall::Syscall,
 };
 
 use core::intrinsics::unlikely;
@@ -31,7 +35,7 @@ use core::{any::Any, fmt::Debug};
 use system_error::SystemError;
 
 use self::{
-    bus::{bus_add_device, bus_probe_device, Bus},
+    bus::{bus_add_device, bus_manager, bus_probe_device, bus_remove_device, Bus},
     device_number::{DeviceNumber, Major},
     driver::Driver,
 };
@@ -43,6 +47,9 @@ use super::{
     },
     kset::KSet,
     swnode::software_node_notify,
+    uevent::{
+        device_uevent_vars, kobject_synth_uevent, kobject_uevent, KObjUEventEnv, KObjectAction,
+    },
 };
 
 pub mod bus;
@@ -184,6 +191,35 @@ pub trait Device: KObject {
     /// 当前设备是否已经挂掉了
     fn is_dead(&self) -> bool;
 
+    /// 获取设备当前的状态
+    ///
+    /// 设备被删除之后，状态为`DeviceState::Removed`
+    fn device_state(&self) -> DeviceState {
+        let state = *self.kobj_state();
+        if state.contains(KObjectState::REMOVED) {
+            DeviceState::Removed
+        } else if state.contains(KObjectState::IN_SYSFS) {
+            DeviceState::Initialized
+        } else {
+            DeviceState::NotInitialized
+        }
+    }
+
+    /// 检查设备是否仍然可用
+    ///
+    /// 设备被删除（例如virtio设备被热拔出）之后，仍然持有设备引用的操作应当先调用这个函数，
+    /// 而不是继续访问已经被拆除的结构。
+    ///
+    /// ## 返回值
+    ///
+    /// - `Err(SystemError::ENODEV)`: 设备已经被删除或者已经挂掉了
+    fn check_alive(&self) -> Result<(), SystemError> {
+        if self.is_dead() || self.device_state() == DeviceState::Removed {
+            return Err(SystemError::ENODEV);
+        }
+        return Ok(());
+    }
+
     /// 当前设备是否处于可以被匹配的状态
     ///
     /// The device has matched with a driver at least once or it is in
@@ -360,6 +396,8 @@ pub enum DeviceState {
     NotInitialized = 0,
     Initialized = 1,
     UnDefined = 2,
+    /// 设备已经被删除，对它的后续操作都应返回`ENODEV`
+    Removed = 3,
 }
 
 /// @brief: 设备错误类型
@@ -397,6 +435,8 @@ impl From<u32> for DeviceState {
         match state {
             0 => DeviceState::NotInitialized,
             1 => DeviceState::Initialized,
+            2 => DeviceState::UnDefined,
+            3 => DeviceState::Removed,
             _ => todo!(),
         }
     }
@@ -409,6 +449,7 @@ impl From<DeviceState> for u32 {
             DeviceState::NotInitialized => 0,
             DeviceState::Initialized => 1,
             DeviceState::UnDefined => 2,
+            DeviceState::Removed => 3,
         }
     }
 }
@@ -481,6 +522,7 @@ impl DeviceManager {
     }
 
     pub fn register(&self, device: Arc<dyn Device>) -> Result<(), SystemError> {
+        obj_lifetime_track(ObjKind::Device, &device);
         self.device_default_initialize(&device);
         return self.add_device(device);
     }
@@ -524,6 +566,8 @@ impl DeviceManager {
             e
         })?;
 
+        self.create_file(&device, &DeviceAttrUevent)?;
+
         self.device_platform_notify(&device);
 
         self.add_class_symlinks(&device)?;
@@ -547,7 +591,7 @@ impl DeviceManager {
             );
         }
 
-        // todo: 发送uevent: KOBJ_ADD
+        kobject_uevent(&(device.clone() as Arc<dyn KObject>), KObjectAction::Add).ok();
 
         // probe drivers for a new device
         bus_probe_device(&device);
@@ -660,9 +704,71 @@ impl DeviceManager {
         todo!()
     }
 
-    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/dd.c?fi=driver_attach#542
-    pub fn remove(&self, _dev: &Arc<dyn Device>) {
-        todo!("DeviceManager::remove")
+    /// 删除设备（与`add_device`相反）
+    ///
+    /// 设备会先被标记为`DeviceState::Removed`，使并发的操作尽早返回`ENODEV`，
+    /// 然后依次删除它的子设备、解绑驱动、从总线和类中移除，并拆除它在sysfs中的目录。
+    ///
+    /// 当前函数只释放设备模型持有的引用，设备对象本身在最后一个引用被释放时才会被销毁。
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/core.c#3680
+    pub fn remove(&self, dev: &Arc<dyn Device>) {
+        {
+            let mut state = dev.kobj_state_mut();
+            if state.contains(KObjectState::REMOVED) {
+                return;
+            }
+            state.insert(KObjectState::REMOVED);
+        }
+
+        let bus = dev.bus().and_then(|bus| bus.upgrade());
+        // 通知总线上的其他组件，设备将要被删除，让它们释放与该设备相关的资源
+        if let Some(bus) = bus.as_ref() {
+            bus.subsystem().bus_notifier().call_chain(
+                bus::BusNotifyEvent::DelDevice,
+                Some(dev),
+                None,
+            );
+        }
+
+        // 子设备依赖于父设备，必须先被删除
+        for child in bus_manager().children_of(dev) {
+            self.remove(&child);
+        }
+
+        if let Some(class) = dev.class() {
+            for class_interface in class.subsystem().interfaces() {
+                class_interface.remove_device(dev);
+            }
+            class.subsystem().remove_device_from_vec(dev);
+        }
+
+        if dev.is_registered() {
+            if dev.id_table().device_number().major() != Major::UNNAMED_MAJOR {
+                self.remove_sys_dev_entry(dev);
+                self.remove_file(dev, &DeviceAttrDev);
+            }
+            self.remove_class_symlinks(dev);
+        }
+
+        bus_remove_device(dev);
+
+        if dev.is_registered() {
+            self.remove_attrs(dev);
+            self.remove_file(dev, &DeviceAttrUevent);
+            kobject_uevent(&(dev.clone() as Arc<dyn KObject>), KObjectAction::Remove).ok();
+            KObjectManager::remove_kobj(dev.clone() as Arc<dyn KObject>);
+        }
+
+        if let Some(bus) = bus {
+            bus.subsystem().bus_notifier().call_chain(
+                bus::BusNotifyEvent::RemovedDevice,
+                Some(dev),
+                None,
+            );
+        }
+
+        obj_lifetime_release(dev.as_ref());
     }
 
     /// @brief: 获取设备
@@ -720,6 +826,23 @@ impl DeviceManager {
         return Ok(());
     }
 
+    // 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/core.c#3266
+    fn remove_class_symlinks(&self, dev: &Arc<dyn Device>) {
+        let class = dev.class();
+        if class.is_none() {
+            return;
+        }
+
+        let class = class.unwrap();
+        let dev_kobj = dev.clone() as Arc<dyn KObject>;
+        let subsys_kobj = class.subsystem().subsys() as Arc<dyn KObject>;
+        if dev.dev_parent().and_then(|x| x.upgrade()).is_some() {
+            sysfs_instance().remove_link(&dev_kobj, "device".to_string());
+        }
+        sysfs_instance().remove_link(&dev_kobj, "subsystem".to_string());
+        sysfs_instance().remove_link(&subsys_kobj, dev.name());
+    }
+
     /// 在sysfs中，为指定的设备创建属性文件
     ///
     /// ## 参数
@@ -767,6 +890,23 @@ impl DeviceManager {
         return Ok(());
     }
 
+    /// 在sysfs中，删除由`add_attrs`为指定的设备创建的属性文件
+    ///
+    /// ## 参数
+    ///
+    /// - `dev`: 设备
+    fn remove_attrs(&self, dev: &Arc<dyn Device>) {
+        self.remove_groups(dev, dev.attribute_groups().unwrap_or(&[]));
+
+        if let Some(kobj_type) = dev.kobj_type() {
+            self.remove_groups(dev, kobj_type.attribute_groups().unwrap_or(&[]));
+        }
+
+        if let Some(class) = dev.class() {
+            self.remove_groups(dev, class.dev_groups());
+        }
+    }
+
     /// 在sysfs中，为指定的设备创建属性组，以及属性组中的属性文件
     ///
     /// ## 参数
@@ -832,6 +972,17 @@ impl DeviceManager {
         return sysfs_instance().create_file(&kobj, attr);
     }
 
+    /// 删除设备在sysfs中的属性文件
+    ///
+    /// ## 参数
+    ///
+    /// - `dev`: 设备
+    /// - `attr`: 属性
+    pub fn remove_file(&self, dev: &Arc<dyn Device>, attr: &'static dyn Attribute) {
+        let kobj = dev.clone() as Arc<dyn KObject>;
+        sysfs_instance().remove_file(&kobj, attr);
+    }
+
     /// 在/sys/dev下，或者设备所属的class下，为指定的设备创建链接
     fn create_sys_dev_entry(&self, dev: &Arc<dyn Device>) -> Result<(), SystemError> {
         let target_kobj = self.device_to_dev_kobj(dev);
@@ -841,7 +992,6 @@ impl DeviceManager {
     }
 
     /// Delete symlink for device in `/sys/dev` or `/sys/class/<class_name>`
-    #[allow(dead_code)]
     fn remove_sys_dev_entry(&self, dev: &Arc<dyn Device>) {
         let kobj = self.device_to_dev_kobj(dev);
         let name = dev.id_table().name();
@@ -885,8 +1035,8 @@ impl DeviceManager {
     }
 
     /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/drivers/base/dd.c?r=&mo=35401&fi=1313#1313
-    pub fn device_driver_detach(&self, _dev: &Arc<dyn Device>) {
-        todo!("device_driver_detach")
+    pub fn device_driver_detach(&self, dev: &Arc<dyn Device>) {
+        self.device_release_driver(dev);
     }
 }
 
@@ -897,19 +1047,36 @@ pub fn device_register<T: Device>(device: Arc<T>) -> Result<(), SystemError> {
     return device_manager().register(device);
 }
 
+/// 等待设备引用释放时，最多让出CPU的次数
+const DEVICE_DRAIN_MAX_YIELDS: usize = 64;
+
 /// @brief: 设备卸载
-/// @parameter: name: 设备名
-/// @return: 操作成功，返回()，操作失败，返回错误码
-pub fn device_unregister<T: Device>(_device: Arc<T>) {
-    // DEVICE_MANAGER.add_device(device.id_table(), device.clone());
-    // match sys_device_unregister(&device.id_table().name()) {
-    //     Ok(_) => {
-    //         device.set_inode(None);
-    //         return Ok(());
-    //     }
-    //     Err(_) => Err(DeviceError::RegisterError),
-    // }
-    todo!("device_unregister")
+/// @parameter: device: 要卸载的设备
+///
+/// 删除设备之后，等待正在进行中的操作释放它们持有的设备引用。
+/// 如果等待超时，仍未释放引用的持有者会在下一次操作时，通过`check_alive`得到`ENODEV`。
+pub fn device_unregister<T: Device>(device: Arc<T>) {
+    let device = device as Arc<dyn Device>;
+    device_manager().remove(&device);
+
+    // 当前函数自身持有一个引用
+    let mut yields = 0;
+    while Arc::strong_count(&device) > 1 && yields < DEVICE_DRAIN_MAX_YIELDS {
+        if !CurrentIrqArch::is_irq_enabled() || ProcessManager::current_pcb().preempt_count() != 0 {
+            break;
+        }
+        Syscall::do_sched_yield().ok();
+        yields += 1;
+    }
+
+    let refs = Arc::strong_count(&device);
+    if refs > 1 {
+        warn!(
+            "device_unregister: device '{}' is still referenced by {} holder(s)",
+            device.name(),
+            refs - 1
+        );
+    }
 }
 
 /// 设备文件夹下的`dev`文件的属性
@@ -950,6 +1117,52 @@ impl Attribute for DeviceAttrDev {
     }
 }
 
+/// 设备文件夹下的`uevent`文件的属性
+///
+/// 读取时得到设备特有的uevent环境变量；写入`add`等动作时，为设备重新发出一个uevent
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceAttrUevent;
+
+impl Attribute for DeviceAttrUevent {
+    fn mode(&self) -> ModeType {
+        // 0o644
+        return ModeType::S_IRUGO | ModeType::S_IWUSR;
+    }
+
+    fn name(&self) -> &str {
+        "uevent"
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let dev = kobj.cast::<dyn Device>().map_err(|kobj| {
+            error!(
+                "Intertrait casting not implemented for kobj: {}",
+                kobj.name()
+            );
+            SystemError::ENOSYS
+        })?;
+
+        let mut env = KObjUEventEnv::default();
+        device_uevent_vars(&dev, &mut env)?;
+        let mut s = String::new();
+        for var in env.vars() {
+            s.push_str(var);
+            s.push('\n');
+        }
+
+        return sysfs_emit_str(buf, &s);
+    }
+
+    fn store(&self, kobj: Arc<dyn KObject>, buf: &[u8]) -> Result<usize, SystemError> {
+        kobject_synth_uevent(&kobj, buf)?;
+        return Ok(buf.len());
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW | SysFSOpsSupport::ATTR_STORE
+    }
+}
+
 /// 设备匹配器
 ///
 /// 用于匹配设备是否符合某个条件
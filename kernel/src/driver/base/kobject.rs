@@ -21,7 +21,10 @@ use crate::{
 
 use system_error::SystemError;
 
-use super::kset::KSet;
+use super::{
+    kset::KSet,
+    uevent::{kobject_uevent, KObjectAction},
+};
 
 pub trait KObject: Any + Send + Sync + Debug + CastFromSync {
     fn as_any_ref(&self) -> &dyn core::any::Any;
@@ -103,6 +106,7 @@ bitflags! {
         const ADD_UEVENT_SENT = 1 << 1;
         const REMOVE_UEVENT_SENT = 1 << 2;
         const INITIALIZED = 1 << 3;
+        const REMOVED = 1 << 4;
     }
 
 }
@@ -250,7 +254,13 @@ impl KObjectManager {
             }
         }
 
-        // todo: 发送uevent: KOBJ_REMOVE
+        // 发出过add事件，但还没有发出remove事件的kobject，需要补发remove事件
+        let state = *kobj.kobj_state();
+        if state.contains(KObjectState::ADD_UEVENT_SENT)
+            && !state.contains(KObjectState::REMOVE_UEVENT_SENT)
+        {
+            kobject_uevent(&kobj, KObjectAction::Remove).ok();
+        }
 
         sysfs_instance().remove_dir(&kobj);
         kobj.update_kobj_state(None, Some(KObjectState::IN_SYSFS));
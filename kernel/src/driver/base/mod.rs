@@ -12,3 +12,4 @@ pub mod map;
 pub mod platform;
 pub mod subsys;
 pub mod swnode;
+pub mod uevent;
@@ -0,0 +1,263 @@
+//! kobject的uevent
+//!
+//! kobject被添加、删除，或者设备绑定、解绑驱动时，内核通过`NETLINK_KOBJECT_UEVENT`多播一条消息。
+//! 消息的格式与Linux相同：`ACTION@DEVPATH\0`之后是以`\0`分隔的`KEY=VALUE`，
+//! 至少包含`ACTION`、`DEVPATH`、`SUBSYSTEM`与`SEQNUM`，有设备号的设备还包含`MAJOR`、`MINOR`与`DEVNAME`。
+//!
+//! 守护进程启动时，向`/sys/devices/**/uevent`写入`add`，可以让内核为已经存在的设备重新发出事件（冷插拔）。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/lib/kobject_uevent.c
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use intertrait::cast::CastArc;
+use system_error::SystemError;
+
+use crate::net::socket::netlink::netlink_broadcast_uevent;
+
+use super::{
+    device::{device_number::Major, Device},
+    kobject::{KObject, KObjectState},
+};
+
+/// uevent环境变量的最大数量
+const UEVENT_NUM_ENVP: usize = 64;
+/// uevent环境变量的最大总长度
+const UEVENT_BUFFER_SIZE: usize = 2048;
+
+/// 最近一次发出的uevent的序号，通过`/sys/kernel/uevent_seqnum`导出
+static UEVENT_SEQNUM: AtomicU64 = AtomicU64::new(0);
+
+pub fn uevent_seqnum() -> u64 {
+    UEVENT_SEQNUM.load(Ordering::SeqCst)
+}
+
+/// uevent的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KObjectAction {
+    Add,
+    Remove,
+    Change,
+    Move,
+    Online,
+    Offline,
+    Bind,
+    Unbind,
+}
+
+impl KObjectAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KObjectAction::Add => "add",
+            KObjectAction::Remove => "remove",
+            KObjectAction::Change => "change",
+            KObjectAction::Move => "move",
+            KObjectAction::Online => "online",
+            KObjectAction::Offline => "offline",
+            KObjectAction::Bind => "bind",
+            KObjectAction::Unbind => "unbind",
+        }
+    }
+}
+
+impl TryFrom<&str> for KObjectAction {
+    type Error = SystemError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "add" => Ok(KObjectAction::Add),
+            "remove" => Ok(KObjectAction::Remove),
+            "change" => Ok(KObjectAction::Change),
+            "move" => Ok(KObjectAction::Move),
+            "online" => Ok(KObjectAction::Online),
+            "offline" => Ok(KObjectAction::Offline),
+            "bind" => Ok(KObjectAction::Bind),
+            "unbind" => Ok(KObjectAction::Unbind),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
+
+/// uevent的环境变量
+#[derive(Debug, Default)]
+pub struct KObjUEventEnv {
+    envp: Vec<String>,
+    buflen: usize,
+}
+
+impl KObjUEventEnv {
+    /// 添加一个`KEY=VALUE`形式的环境变量
+    pub fn add_var(&mut self, var: String) -> Result<(), SystemError> {
+        if self.envp.len() >= UEVENT_NUM_ENVP || self.buflen + var.len() + 1 > UEVENT_BUFFER_SIZE {
+            return Err(SystemError::ENOMEM);
+        }
+        self.buflen += var.len() + 1;
+        self.envp.push(var);
+        Ok(())
+    }
+
+    pub fn vars(&self) -> &[String] {
+        &self.envp
+    }
+}
+
+/// 获取kobject在sysfs中的路径（不含`/sys`），如`/devices/platform/serial8250`
+pub fn kobject_get_path(kobj: &Arc<dyn KObject>) -> String {
+    let mut names = Vec::new();
+    let mut cur = Some(kobj.clone());
+    while let Some(k) = cur {
+        names.push(k.name());
+        cur = k.parent().and_then(|p| p.upgrade());
+    }
+
+    let mut path = String::new();
+    for name in names.iter().rev() {
+        path.push('/');
+        path.push_str(name);
+    }
+    path
+}
+
+/// 获取kobject所属的子系统
+///
+/// 设备的子系统是它所在的总线，没有总线时是它所属的类；
+/// 其他kobject的子系统是离它最近的kset的名字
+fn kobject_subsystem(kobj: &Arc<dyn KObject>) -> Option<String> {
+    if let Ok(dev) = kobj.clone().cast::<dyn Device>() {
+        if let Some(bus) = dev.bus().and_then(|bus| bus.upgrade()) {
+            return Some(bus.name());
+        }
+        if let Some(class) = dev.class() {
+            return Some(class.name().to_string());
+        }
+    }
+
+    let mut cur = Some(kobj.clone());
+    while let Some(k) = cur {
+        if let Some(kset) = k.kset() {
+            return Some(kset.name());
+        }
+        cur = k.parent().and_then(|p| p.upgrade());
+    }
+    None
+}
+
+/// 设备特有的环境变量，也是读取设备的`uevent`文件时得到的内容
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/base/core.c#2576
+pub fn device_uevent_vars(
+    dev: &Arc<dyn Device>,
+    env: &mut KObjUEventEnv,
+) -> Result<(), SystemError> {
+    let devt = dev.id_table().device_number();
+    if devt.major() != Major::UNNAMED_MAJOR {
+        env.add_var(format!("MAJOR={}", devt.major().data()))?;
+        env.add_var(format!("MINOR={}", devt.minor()))?;
+        env.add_var(format!("DEVNAME={}", dev.name()))?;
+    }
+
+    if let Some(driver) = dev.driver() {
+        env.add_var(format!("DRIVER={}", driver.name()))?;
+    }
+    Ok(())
+}
+
+/// 发出uevent
+pub fn kobject_uevent(kobj: &Arc<dyn KObject>, action: KObjectAction) -> Result<(), SystemError> {
+    kobject_uevent_env(kobj, action, &[])
+}
+
+/// 发出uevent，并附带额外的环境变量
+///
+/// ## 参数
+///
+/// - `kobj`: 发生事件的kobject
+/// - `action`: 事件的动作
+/// - `extra`: 额外的`KEY=VALUE`形式的环境变量
+pub fn kobject_uevent_env(
+    kobj: &Arc<dyn KObject>,
+    action: KObjectAction,
+    extra: &[String],
+) -> Result<(), SystemError> {
+    let subsystem = kobject_subsystem(kobj).ok_or(SystemError::EINVAL)?;
+    let devpath = kobject_get_path(kobj);
+
+    let mut env = KObjUEventEnv::default();
+    env.add_var(format!("ACTION={}", action.as_str()))?;
+    env.add_var(format!("DEVPATH={}", devpath))?;
+    env.add_var(format!("SUBSYSTEM={}", subsystem))?;
+    for var in extra {
+        env.add_var(var.clone())?;
+    }
+    if let Ok(dev) = kobj.clone().cast::<dyn Device>() {
+        device_uevent_vars(&dev, &mut env)?;
+    }
+
+    match action {
+        KObjectAction::Add => kobj.update_kobj_state(Some(KObjectState::ADD_UEVENT_SENT), None),
+        KObjectAction::Remove => {
+            kobj.update_kobj_state(Some(KObjectState::REMOVE_UEVENT_SENT), None)
+        }
+        _ => {}
+    }
+
+    // 序号在最后分配，使得用户态看到的序号是连续的
+    let seqnum = UEVENT_SEQNUM.fetch_add(1, Ordering::SeqCst) + 1;
+    env.add_var(format!("SEQNUM={}", seqnum))?;
+
+    let mut msg = format!("{}@{}", action.as_str(), devpath).into_bytes();
+    msg.push(0);
+    for var in env.vars() {
+        msg.extend_from_slice(var.as_bytes());
+        msg.push(0);
+    }
+    netlink_broadcast_uevent(&msg);
+    Ok(())
+}
+
+/// 处理向`uevent`文件写入的内容，发出一个合成的uevent
+///
+/// 格式为`ACTION [UUID [KEY=VALUE ...]]`，UUID与参数分别以`SYNTH_UUID`和`SYNTH_ARG_KEY`的形式
+/// 附加在环境变量中，使守护进程能够区分自己触发的事件。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/lib/kobject_uevent.c#192
+pub fn kobject_synth_uevent(kobj: &Arc<dyn KObject>, buf: &[u8]) -> Result<(), SystemError> {
+    let s = core::str::from_utf8(buf).map_err(|_| SystemError::EINVAL)?;
+    let mut tokens = s.split_whitespace();
+    let action = KObjectAction::try_from(tokens.next().ok_or(SystemError::EINVAL)?)?;
+
+    let mut extra = Vec::new();
+    match tokens.next() {
+        None => extra.push("SYNTH_UUID=0".to_string()),
+        Some(uuid) => {
+            if !is_uuid(uuid) {
+                return Err(SystemError::EINVAL);
+            }
+            extra.push(format!("SYNTH_UUID={}", uuid));
+        }
+    }
+
+    for arg in tokens {
+        let (key, value) = arg.split_once('=').ok_or(SystemError::EINVAL)?;
+        if key.is_empty() || !key.bytes().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(SystemError::EINVAL);
+        }
+        extra.push(format!("SYNTH_ARG_{}={}", key, value));
+    }
+
+    kobject_uevent_env(kobj, action, &extra)
+}
+
+/// 检查是否为`xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`形式的UUID
+fn is_uuid(s: &str) -> bool {
+    s.len() == 36
+        && s.bytes().enumerate().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == b'-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
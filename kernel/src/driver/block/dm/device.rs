@@ -0,0 +1,599 @@
+use core::{
+    any::Any,
+    intrinsics::unlikely,
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering},
+};
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use log::warn;
+use system_error::SystemError;
+
+use crate::{
+    debug::fault_inject::FAIL_BLOCK_IO,
+    driver::base::{
+        block::{
+            block_device::{BlockDevName, BlockDevice, BlockId, GeneralBlockRange, LBA_SIZE},
+            disk_info::Partition,
+            manager::{block_dev_manager, BlockDevMeta},
+        },
+        class::Class,
+        device::{
+            bus::Bus,
+            device_number::{DeviceNumber, Major},
+            driver::Driver,
+            Device, DeviceCommonData, DeviceType, IdTable,
+        },
+        kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::{
+        devfs::{DevFS, DeviceINode},
+        kernfs::KernFSInode,
+        vfs::{
+            core::generate_inode_id, file::FileMode, syscall::ModeType, FilePrivateData,
+            FileSystem, FileType, IndexNode, Metadata,
+        },
+    },
+    libs::{
+        mutex::Mutex,
+        rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    time::PosixTimeSpec,
+};
+
+use super::{DmTable, DM_BASENAME};
+
+/// 映射设备
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct DmDevice {
+    blkdev_meta: BlockDevMeta,
+    minor: u32,
+    name: SpinLock<String>,
+    uuid: String,
+    /// 活动表，所有的I/O都通过它转发
+    live: RwLock<Option<Arc<DmTable>>>,
+    /// 已加载但尚未生效的表
+    inactive: SpinLock<Option<Arc<DmTable>>>,
+    suspended: AtomicBool,
+    open_count: AtomicI32,
+    event_nr: AtomicU32,
+    /// 串行化加载、切换映射表与删除设备的操作
+    ctl_lock: Mutex<()>,
+    inner: SpinLock<InnerDmDevice>,
+    locked_kobj_state: LockedKObjectState,
+    self_ref: Weak<Self>,
+}
+
+#[derive(Debug)]
+struct InnerDmDevice {
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+}
+
+impl DmDevice {
+    fn new(minor: u32, name: String, uuid: String) -> Arc<Self> {
+        Arc::new_cyclic(|self_ref| Self {
+            blkdev_meta: BlockDevMeta::new(BlockDevName::new(
+                format!("{}{}", DM_BASENAME, minor),
+                minor as usize,
+            )),
+            minor,
+            name: SpinLock::new(name),
+            uuid,
+            live: RwLock::new(None),
+            inactive: SpinLock::new(None),
+            suspended: AtomicBool::new(false),
+            open_count: AtomicI32::new(0),
+            event_nr: AtomicU32::new(0),
+            ctl_lock: Mutex::new(()),
+            inner: SpinLock::new(InnerDmDevice {
+                device_common: DeviceCommonData::default(),
+                kobject_common: KObjectCommonData::default(),
+            }),
+            locked_kobj_state: LockedKObjectState::default(),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerDmDevice> {
+        self.inner.lock()
+    }
+
+    fn as_block_device(&self) -> Arc<dyn BlockDevice> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    pub fn devt(&self) -> DeviceNumber {
+        DeviceNumber::new(Major::DM_MAJOR, self.minor)
+    }
+
+    /// 映射设备的名字，与块设备名`dm-N`不同
+    pub fn dm_name(&self) -> String {
+        self.name.lock().clone()
+    }
+
+    pub fn set_dm_name(&self, name: String) {
+        *self.name.lock() = name;
+    }
+
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    pub fn live_table(&self) -> Option<Arc<DmTable>> {
+        self.live.read().clone()
+    }
+
+    pub fn inactive_table(&self) -> Option<Arc<DmTable>> {
+        self.inactive.lock().clone()
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::SeqCst)
+    }
+
+    pub fn open_count(&self) -> i32 {
+        self.open_count.load(Ordering::SeqCst)
+    }
+
+    pub fn event_nr(&self) -> u32 {
+        self.event_nr.load(Ordering::SeqCst)
+    }
+
+    /// 设备的容量（扇区）
+    fn nr_sectors(&self) -> usize {
+        self.live
+            .read()
+            .as_ref()
+            .map(|table| table.nr_sectors())
+            .unwrap_or(0)
+    }
+
+    /// 加载非活动表，替换掉之前加载的非活动表
+    pub fn load_table(&self, table: DmTable) -> Result<(), SystemError> {
+        if table.nr_targets() == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let _guard = self.ctl_lock.lock();
+        *self.inactive.lock() = Some(Arc::new(table));
+        Ok(())
+    }
+
+    /// 丢弃非活动表
+    pub fn clear_table(&self) {
+        let _guard = self.ctl_lock.lock();
+        *self.inactive.lock() = None;
+    }
+
+    /// 挂起设备
+    ///
+    /// 挂起时把数据同步到下层设备，但不会阻塞新的I/O
+    pub fn suspend(&self) -> Result<(), SystemError> {
+        let _guard = self.ctl_lock.lock();
+        if let Some(table) = self.live_table() {
+            table.sync()?;
+        }
+        self.suspended.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// 恢复设备，如果有非活动表，则用它替换活动表
+    pub fn resume(&self) -> Result<(), SystemError> {
+        let _guard = self.ctl_lock.lock();
+        let new = self.inactive.lock().take();
+        if let Some(new) = new {
+            self.swap_table(new.clone())
+                .inspect_err(|_| *self.inactive.lock() = Some(new))?;
+            self.event_nr.fetch_add(1, Ordering::SeqCst);
+        }
+        self.suspended.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// 替换活动表
+    ///
+    /// 第一次加载表时把设备注册到块设备管理器；容量发生变化时需要重新注册，
+    /// 此时设备被挂载着的话返回`EBUSY`
+    fn swap_table(&self, new: Arc<DmTable>) -> Result<(), SystemError> {
+        let dev = self.as_block_device();
+        let old = self.live_table();
+        match old {
+            None => {
+                *self.live.write() = Some(new);
+                block_dev_manager()
+                    .register(dev)
+                    .inspect_err(|_| *self.live.write() = None)
+            }
+            Some(old) if old.nr_sectors() == new.nr_sectors() => {
+                *self.live.write() = Some(new);
+                Ok(())
+            }
+            Some(old) => {
+                block_dev_manager().unregister(&dev)?;
+                *self.live.write() = Some(new);
+                if let Err(e) = block_dev_manager().register(dev.clone()) {
+                    warn!(
+                        "dm: failed to re-register {} with the new table: {:?}",
+                        self.dev_name(),
+                        e
+                    );
+                    *self.live.write() = Some(old);
+                    block_dev_manager().register(dev)?;
+                    return Err(e);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// 删除设备前，从块设备管理器中注销并释放映射表
+    pub(super) fn destroy(&self) -> Result<(), SystemError> {
+        let _guard = self.ctl_lock.lock();
+        if self.open_count() > 0 {
+            return Err(SystemError::EBUSY);
+        }
+        if let Some(table) = self.live_table() {
+            block_dev_manager().unregister(&self.as_block_device())?;
+            table.sync().ok();
+        }
+        *self.live.write() = None;
+        *self.inactive.lock() = None;
+        Ok(())
+    }
+}
+
+impl BlockDevice for DmDevice {
+    fn dev_name(&self) -> &BlockDevName {
+        &self.blkdev_meta.devname
+    }
+
+    fn blkdev_meta(&self) -> &BlockDevMeta {
+        &self.blkdev_meta
+    }
+
+    fn disk_range(&self) -> GeneralBlockRange {
+        GeneralBlockRange {
+            lba_start: 0,
+            lba_end: self.nr_sectors(),
+        }
+    }
+
+    fn read_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let table = self.live_table().ok_or(SystemError::ENXIO)?;
+        table.read(lba_id_start, count, buf)
+    }
+
+    fn write_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        let table = self.live_table().ok_or(SystemError::ENXIO)?;
+        table.write(lba_id_start, count, buf)
+    }
+
+    /// 不经过全局的块缓存：块缓存只以LBA为键，不区分磁盘，
+    /// 而下层设备自己会使用块缓存
+    fn read_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if unlikely(FAIL_BLOCK_IO.should_fail(count)) {
+            return Err(SystemError::EIO);
+        }
+        self.read_at_sync(lba_id_start, count, buf)
+    }
+
+    fn write_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        if unlikely(FAIL_BLOCK_IO.should_fail(count)) {
+            return Err(SystemError::EIO);
+        }
+        self.write_at_sync(lba_id_start, count, buf)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        match self.live_table() {
+            Some(table) => table.sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn partition_scan(&self) -> bool {
+        false
+    }
+
+    fn blk_size_log2(&self) -> u8 {
+        9
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn device(&self) -> Arc<dyn Device> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    fn block_size(&self) -> usize {
+        LBA_SIZE
+    }
+
+    fn partitions(&self) -> Vec<Arc<Partition>> {
+        Vec::new()
+    }
+}
+
+impl Device for DmDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(DM_BASENAME.to_string(), Some(self.devt()))
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for DmDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.dev_name().to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
+
+/// /dev/dm-N
+#[derive(Debug)]
+pub(super) struct DmInode {
+    dev: Arc<DmDevice>,
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl DmInode {
+    pub(super) fn new(minor: u32, name: String, uuid: String) -> Arc<Self> {
+        Arc::new(Self {
+            dev: DmDevice::new(minor, name, uuid),
+            fs: SpinLock::new(Weak::default()),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: LBA_SIZE,
+                blocks: 0,
+                atime: PosixTimeSpec::default(),
+                mtime: PosixTimeSpec::default(),
+                ctime: PosixTimeSpec::default(),
+                file_type: FileType::BlockDevice,
+                mode: ModeType::from_bits_truncate(0o660),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::new(Major::DM_MAJOR, minor),
+            },
+        })
+    }
+
+    pub(super) fn dev(&self) -> &Arc<DmDevice> {
+        &self.dev
+    }
+
+    pub(super) fn devname(&self) -> String {
+        self.dev.dev_name().to_string()
+    }
+
+    /// 把读写请求限制在设备的容量之内
+    fn clamp(&self, offset: usize, len: usize) -> usize {
+        len.min((self.dev.nr_sectors() * LBA_SIZE).saturating_sub(offset))
+    }
+}
+
+impl DeviceINode for DmInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for DmInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        self.dev.open_count.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        self.dev.open_count.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if self.dev.live_table().is_none() {
+            return Err(SystemError::ENXIO);
+        }
+        let len = self.clamp(offset, len);
+        if len == 0 {
+            return Ok(0);
+        }
+        self.dev.read_at_bytes(offset, len, buf)
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if self.dev.live_table().is_none() {
+            return Err(SystemError::ENXIO);
+        }
+        let len = self.clamp(offset, len);
+        if len == 0 {
+            return Err(SystemError::ENOSPC);
+        }
+        self.dev.write_at_bytes(offset, len, buf)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let mut metadata = self.metadata.clone();
+        metadata.size = (self.dev.nr_sectors() * LBA_SIZE) as i64;
+        metadata.blocks = self.dev.nr_sectors();
+        Ok(metadata)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        self.dev.sync()
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.lock().upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+}
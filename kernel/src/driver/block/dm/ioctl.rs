@@ -0,0 +1,550 @@
+//! /dev/mapper/control
+//!
+//! 实现了Linux dm-ioctl接口（版本4）的一个子集，足够`dmsetup create/remove/load/resume/table/ls`使用。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/md/dm-ioctl.c
+
+use core::any::Any;
+
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::base::device::device_number::{DeviceNumber, Major},
+    filesystem::{
+        devfs::{DevFS, DeviceINode},
+        vfs::{
+            core::generate_inode_id, file::FileMode, syscall::ModeType, FilePrivateData,
+            FileSystem, FileType, IndexNode, Metadata,
+        },
+    },
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+    time::PosixTimeSpec,
+};
+
+use super::{
+    dm_create, dm_devices, dm_lookup_by_minor, dm_lookup_by_name, dm_lookup_by_uuid, dm_remove,
+    DmDevice, DmTable, DM_NAME_LEN, DM_UUID_LEN,
+};
+
+/// 控制设备在devfs中的名字，会被放在/dev/mapper/control
+pub(super) const DM_CONTROL_NAME: &str = "device-mapper";
+/// /dev/mapper/control的次设备号
+const MAPPER_CTRL_MINOR: u32 = 236;
+
+const DM_IOCTL_TYPE: u32 = 0xfd;
+const DM_VERSION_MAJOR: u32 = 4;
+const DM_VERSION_MINOR: u32 = 0;
+const DM_VERSION_PATCHLEVEL: u32 = 0;
+/// ioctl参数（包括数据区）的最大长度
+const DM_MAX_IOCTL_SIZE: usize = 64 * 1024;
+/// 目标类型名的最大长度
+const DM_MAX_TYPE_NAME: usize = 16;
+/// `struct dm_target_spec`的大小，之后紧跟着以`\0`结尾的参数
+const DM_TARGET_SPEC_SIZE: usize = 40;
+/// `struct dm_name_list`中名字的偏移量
+const DM_NAME_LIST_NAME_OFFSET: usize = 12;
+
+// ioctl命令号，即`_IOWR(DM_IOCTL_TYPE, nr, struct dm_ioctl)`中的nr
+const DM_VERSION_CMD: u32 = 0;
+const DM_REMOVE_ALL_CMD: u32 = 1;
+const DM_LIST_DEVICES_CMD: u32 = 2;
+const DM_DEV_CREATE_CMD: u32 = 3;
+const DM_DEV_REMOVE_CMD: u32 = 4;
+const DM_DEV_RENAME_CMD: u32 = 5;
+const DM_DEV_SUSPEND_CMD: u32 = 6;
+const DM_DEV_STATUS_CMD: u32 = 7;
+const DM_TABLE_LOAD_CMD: u32 = 9;
+const DM_TABLE_CLEAR_CMD: u32 = 10;
+const DM_TABLE_STATUS_CMD: u32 = 12;
+
+bitflags! {
+    pub struct DmIoctlFlags: u32 {
+        const READONLY = 1 << 0;
+        const SUSPEND = 1 << 1;
+        const PERSISTENT_DEV = 1 << 3;
+        const STATUS_TABLE = 1 << 4;
+        const ACTIVE_PRESENT = 1 << 5;
+        const INACTIVE_PRESENT = 1 << 6;
+        const BUFFER_FULL = 1 << 8;
+        const QUERY_INACTIVE_TABLE = 1 << 12;
+        const UUID = 1 << 14;
+        const DATA_OUT = 1 << 16;
+    }
+}
+
+const _: () = assert!(core::mem::size_of::<DmIoctl>() == 312);
+
+/// 所有dm ioctl的参数，对应`struct dm_ioctl`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DmIoctl {
+    pub version: [u32; 3],
+    /// 参数的总长度，包括本结构体
+    pub data_size: u32,
+    /// 数据区相对于本结构体起始处的偏移量
+    pub data_start: u32,
+    pub target_count: u32,
+    pub open_count: i32,
+    pub flags: u32,
+    pub event_nr: u32,
+    pub padding: u32,
+    pub dev: u64,
+    pub name: [u8; DM_NAME_LEN],
+    pub uuid: [u8; DM_UUID_LEN],
+    pub data: [u8; 7],
+}
+
+impl DmIoctl {
+    fn flags(&self) -> DmIoctlFlags {
+        DmIoctlFlags::from_bits_truncate(self.flags)
+    }
+
+    fn set_flags(&mut self, flags: DmIoctlFlags) {
+        self.flags = flags.bits();
+    }
+}
+
+/// 与Linux的`huge_encode_dev`相同的设备号编码
+fn encode_dev(devt: DeviceNumber) -> u64 {
+    let major = devt.major().data() as u64;
+    let minor = devt.minor() as u64;
+    (minor & 0xff) | (major << 8) | ((minor & !0xff) << 12)
+}
+
+fn decode_dev(dev: u64) -> DeviceNumber {
+    let major = ((dev >> 8) & 0xfff) as u32;
+    let minor = ((dev & 0xff) | ((dev >> 12) & 0xfff00)) as u32;
+    DeviceNumber::new(Major::new(major), minor)
+}
+
+/// 取出以`\0`结尾的字符串
+fn cstr(buf: &[u8]) -> Result<&str, SystemError> {
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    core::str::from_utf8(&buf[..len]).map_err(|_| SystemError::EINVAL)
+}
+
+fn fill_cstr(dst: &mut [u8], s: &str) {
+    dst.fill(0);
+    let len = s.len().min(dst.len() - 1);
+    dst[..len].copy_from_slice(&s.as_bytes()[..len]);
+}
+
+/// 把输出数据填充到8字节对齐
+fn align_output(out: &mut Vec<u8>) {
+    out.resize(out.len().next_multiple_of(8), 0);
+}
+
+/// 一次ioctl调用的输入与输出
+struct DmIoctlParam {
+    header: DmIoctl,
+    /// 数据区的内容
+    input: Vec<u8>,
+    /// 要写回数据区的内容
+    output: Vec<u8>,
+}
+
+impl DmIoctlParam {
+    /// 根据名字、UUID或设备号（按此优先级）查找映射设备
+    fn find_device(&self) -> Result<Arc<DmDevice>, SystemError> {
+        let name = cstr(&self.header.name)?;
+        if !name.is_empty() {
+            return dm_lookup_by_name(name).ok_or(SystemError::ENXIO);
+        }
+        let uuid = cstr(&self.header.uuid)?;
+        if !uuid.is_empty() {
+            return dm_lookup_by_uuid(uuid).ok_or(SystemError::ENXIO);
+        }
+        let devt = decode_dev(self.header.dev);
+        if devt.major() != Major::DM_MAJOR {
+            return Err(SystemError::ENXIO);
+        }
+        dm_lookup_by_minor(devt.minor()).ok_or(SystemError::ENXIO)
+    }
+
+    /// 把设备的状态写回参数
+    fn dev_status(&mut self, dev: &Arc<DmDevice>) {
+        let mut flags = self.header.flags()
+            - (DmIoctlFlags::SUSPEND
+                | DmIoctlFlags::READONLY
+                | DmIoctlFlags::ACTIVE_PRESENT
+                | DmIoctlFlags::INACTIVE_PRESENT);
+        if dev.is_suspended() {
+            flags.insert(DmIoctlFlags::SUSPEND);
+        }
+
+        let live = dev.live_table();
+        let inactive = dev.inactive_table();
+        if live.is_some() {
+            flags.insert(DmIoctlFlags::ACTIVE_PRESENT);
+        }
+        if inactive.is_some() {
+            flags.insert(DmIoctlFlags::INACTIVE_PRESENT);
+        }
+        let table = if flags.contains(DmIoctlFlags::QUERY_INACTIVE_TABLE) {
+            inactive
+        } else {
+            live
+        };
+
+        self.header.set_flags(flags);
+        self.header.target_count = table.map(|t| t.nr_targets() as u32).unwrap_or(0);
+        self.header.open_count = dev.open_count();
+        self.header.event_nr = dev.event_nr();
+        self.header.dev = encode_dev(dev.devt());
+        fill_cstr(&mut self.header.name, &dev.dm_name());
+        fill_cstr(&mut self.header.uuid, dev.uuid());
+    }
+
+    /// 数据区中的内容（不包括本结构体）
+    fn data(&self) -> Result<&[u8], SystemError> {
+        let start = self.header.data_start as usize;
+        self.input.get(start..).ok_or(SystemError::EINVAL)
+    }
+
+    fn remove_all(&mut self) -> Result<(), SystemError> {
+        // 仍在使用中的设备被保留
+        for dev in dm_devices() {
+            dm_remove(&dev).ok();
+        }
+        Ok(())
+    }
+
+    fn list_devices(&mut self) -> Result<(), SystemError> {
+        let devices = dm_devices();
+        let mut out = Vec::new();
+        for (i, dev) in devices.iter().enumerate() {
+            let entry_start = out.len();
+            out.extend_from_slice(&encode_dev(dev.devt()).to_ne_bytes());
+            out.extend_from_slice(&0u32.to_ne_bytes());
+            out.extend_from_slice(dev.dm_name().as_bytes());
+            out.push(0);
+            align_output(&mut out);
+            if i + 1 < devices.len() {
+                let next = (out.len() - entry_start) as u32;
+                out[entry_start + 8..entry_start + DM_NAME_LIST_NAME_OFFSET]
+                    .copy_from_slice(&next.to_ne_bytes());
+            }
+        }
+        // 没有设备时，输出一个dev为0的空项
+        if out.is_empty() {
+            out.resize(DM_NAME_LIST_NAME_OFFSET + 4, 0);
+        }
+        self.output = out;
+        Ok(())
+    }
+
+    fn dev_create(&mut self) -> Result<(), SystemError> {
+        let name = cstr(&self.header.name)?;
+        let uuid = cstr(&self.header.uuid)?;
+        let minor = if self.header.flags().contains(DmIoctlFlags::PERSISTENT_DEV) {
+            Some(decode_dev(self.header.dev).minor())
+        } else {
+            None
+        };
+
+        let dev = dm_create(name, uuid, minor)?;
+        self.dev_status(&dev);
+        Ok(())
+    }
+
+    fn dev_remove(&mut self) -> Result<(), SystemError> {
+        let dev = self.find_device()?;
+        dm_remove(&dev)?;
+        self.header.set_flags(DmIoctlFlags::empty());
+        Ok(())
+    }
+
+    /// 新名字以`\0`结尾的字符串的形式放在数据区中
+    fn dev_rename(&mut self) -> Result<(), SystemError> {
+        if self.header.flags().contains(DmIoctlFlags::UUID) {
+            return Err(SystemError::EINVAL);
+        }
+        let new_name = String::from(cstr(self.data()?)?);
+        if new_name.is_empty() || new_name.len() >= DM_NAME_LEN || new_name.contains('/') {
+            return Err(SystemError::EINVAL);
+        }
+        if dm_lookup_by_name(&new_name).is_some() {
+            return Err(SystemError::EBUSY);
+        }
+
+        let dev = self.find_device()?;
+        dev.set_dm_name(new_name);
+        self.dev_status(&dev);
+        Ok(())
+    }
+
+    fn dev_suspend(&mut self) -> Result<(), SystemError> {
+        let dev = self.find_device()?;
+        if self.header.flags().contains(DmIoctlFlags::SUSPEND) {
+            dev.suspend()?;
+        } else {
+            dev.resume()?;
+        }
+        self.dev_status(&dev);
+        Ok(())
+    }
+
+    fn dev_status_cmd(&mut self) -> Result<(), SystemError> {
+        let dev = self.find_device()?;
+        self.dev_status(&dev);
+        Ok(())
+    }
+
+    /// 数据区中是`target_count`个`struct dm_target_spec`，
+    /// 每个之后紧跟着参数，`next`是下一个目标相对于当前目标的偏移量
+    fn table_load(&mut self) -> Result<(), SystemError> {
+        let dev = self.find_device()?;
+        let data = self.data()?;
+
+        let mut table = DmTable::new();
+        let mut offset = 0;
+        for i in 0..self.header.target_count {
+            let spec = data
+                .get(offset..offset + DM_TARGET_SPEC_SIZE)
+                .ok_or(SystemError::EINVAL)?;
+            let sector_start = u64::from_ne_bytes(spec[0..8].try_into().unwrap());
+            let length = u64::from_ne_bytes(spec[8..16].try_into().unwrap());
+            let next = u32::from_ne_bytes(spec[20..24].try_into().unwrap()) as usize;
+            let target_type = cstr(&spec[24..24 + DM_MAX_TYPE_NAME])?;
+            let params_end = if i + 1 < self.header.target_count {
+                offset + next
+            } else {
+                data.len()
+            };
+            let params = cstr(
+                data.get(offset + DM_TARGET_SPEC_SIZE..params_end)
+                    .ok_or(SystemError::EINVAL)?,
+            )?;
+
+            table.add_target(sector_start as usize, length as usize, target_type, params)?;
+            offset += next;
+        }
+
+        dev.load_table(table)?;
+        self.dev_status(&dev);
+        Ok(())
+    }
+
+    fn table_clear(&mut self) -> Result<(), SystemError> {
+        let dev = self.find_device()?;
+        dev.clear_table();
+        self.dev_status(&dev);
+        Ok(())
+    }
+
+    /// 输出的`next`是下一个目标相对于数据区起始处的偏移量
+    ///
+    /// 设置了`STATUS_TABLE`时输出构造参数，否则输出目标的状态（linear目标没有状态）
+    fn table_status(&mut self) -> Result<(), SystemError> {
+        let dev = self.find_device()?;
+        self.dev_status(&dev);
+
+        let flags = self.header.flags();
+        let table = if flags.contains(DmIoctlFlags::QUERY_INACTIVE_TABLE) {
+            dev.inactive_table()
+        } else {
+            dev.live_table()
+        };
+        let Some(table) = table else {
+            return Ok(());
+        };
+
+        let mut out = Vec::new();
+        for (start, len, target_type, params) in table.entries() {
+            let spec_start = out.len();
+            out.extend_from_slice(&(start as u64).to_ne_bytes());
+            out.extend_from_slice(&(len as u64).to_ne_bytes());
+            out.extend_from_slice(&0i32.to_ne_bytes());
+            out.extend_from_slice(&0u32.to_ne_bytes());
+            let mut type_name = [0u8; DM_MAX_TYPE_NAME];
+            fill_cstr(&mut type_name, target_type);
+            out.extend_from_slice(&type_name);
+            if flags.contains(DmIoctlFlags::STATUS_TABLE) {
+                out.extend_from_slice(params.as_bytes());
+            }
+            out.push(0);
+            align_output(&mut out);
+
+            let next = out.len() as u32;
+            out[spec_start + 20..spec_start + 24].copy_from_slice(&next.to_ne_bytes());
+        }
+        self.output = out;
+        Ok(())
+    }
+
+    fn dispatch(&mut self, nr: u32) -> Result<(), SystemError> {
+        match nr {
+            DM_VERSION_CMD => Ok(()),
+            DM_REMOVE_ALL_CMD => self.remove_all(),
+            DM_LIST_DEVICES_CMD => self.list_devices(),
+            DM_DEV_CREATE_CMD => self.dev_create(),
+            DM_DEV_REMOVE_CMD => self.dev_remove(),
+            DM_DEV_RENAME_CMD => self.dev_rename(),
+            DM_DEV_SUSPEND_CMD => self.dev_suspend(),
+            DM_DEV_STATUS_CMD => self.dev_status_cmd(),
+            DM_TABLE_LOAD_CMD => self.table_load(),
+            DM_TABLE_CLEAR_CMD => self.table_clear(),
+            DM_TABLE_STATUS_CMD => self.table_status(),
+            _ => Err(SystemError::ENOTTY),
+        }
+    }
+}
+
+/// 处理一次dm ioctl
+fn dm_ctl_ioctl(cmd: u32, data: usize) -> Result<usize, SystemError> {
+    if (cmd >> 8) & 0xff != DM_IOCTL_TYPE {
+        return Err(SystemError::ENOTTY);
+    }
+    let header_size = core::mem::size_of::<DmIoctl>();
+    let header = *UserBufferReader::new(data as *const DmIoctl, header_size, true)?
+        .read_one_from_user::<DmIoctl>(0)?;
+
+    let mut out_header = header;
+    out_header.version = [DM_VERSION_MAJOR, DM_VERSION_MINOR, DM_VERSION_PATCHLEVEL];
+    if header.version[0] != DM_VERSION_MAJOR {
+        UserBufferWriter::new(data as *mut DmIoctl, header_size, true)?
+            .copy_one_to_user(&out_header, 0)?;
+        return Err(SystemError::EINVAL);
+    }
+
+    let data_size = header.data_size as usize;
+    if data_size < header_size
+        || data_size > DM_MAX_IOCTL_SIZE
+        || (header.data_start as usize) < header_size
+    {
+        return Err(SystemError::EINVAL);
+    }
+    let input = UserBufferReader::new(data as *const u8, data_size, true)?
+        .read_from_user::<u8>(0)?
+        .to_vec();
+
+    let mut param = DmIoctlParam {
+        header,
+        input,
+        output: Vec::new(),
+    };
+    let mut flags = param.header.flags() - (DmIoctlFlags::BUFFER_FULL | DmIoctlFlags::DATA_OUT);
+    param.header.set_flags(flags);
+    param.dispatch(cmd & 0xff)?;
+
+    let mut out_header = param.header;
+    out_header.version = [DM_VERSION_MAJOR, DM_VERSION_MINOR, DM_VERSION_PATCHLEVEL];
+    flags = out_header.flags();
+    let data_start = header.data_start as usize;
+    if !param.output.is_empty() {
+        if data_start + param.output.len() > data_size {
+            flags.insert(DmIoctlFlags::BUFFER_FULL);
+        } else {
+            UserBufferWriter::new((data + data_start) as *mut u8, param.output.len(), true)?
+                .copy_to_user(&param.output, 0)?;
+            out_header.data_size = (data_start + param.output.len()) as u32;
+            flags.insert(DmIoctlFlags::DATA_OUT);
+        }
+    }
+    out_header.set_flags(flags);
+    UserBufferWriter::new(data as *mut DmIoctl, header_size, true)?
+        .copy_one_to_user(&out_header, 0)?;
+    Ok(0)
+}
+
+/// /dev/mapper/control
+#[derive(Debug)]
+pub(super) struct DmControlInode {
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl DmControlInode {
+    pub(super) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            fs: SpinLock::new(Weak::default()),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: PosixTimeSpec::default(),
+                mtime: PosixTimeSpec::default(),
+                ctime: PosixTimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o600),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::new(Major::MISC_MAJOR, MAPPER_CTRL_MINOR),
+            },
+        })
+    }
+}
+
+impl DeviceINode for DmControlInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for DmControlInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        dm_ctl_ioctl(cmd, data)
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.lock().upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+}
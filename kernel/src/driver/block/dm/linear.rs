@@ -0,0 +1,71 @@
+//! linear目标：把映射设备上的一段扇区线性地映射到另一个块设备上
+//!
+//! 参数为`<设备> <起始扇区>`，设备可以是`/dev/sda1`这样的路径，也可以是`主设备号:次设备号`。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/md/dm-linear.c
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+};
+use linkme::distributed_slice;
+use system_error::SystemError;
+
+use crate::driver::base::block::{
+    block_device::{BlockId, LBA_SIZE},
+    gendisk::GenDisk,
+};
+
+use super::{dm_get_device, DmTarget, DmTargetType, DM_TARGET_TYPES};
+
+#[derive(Debug)]
+pub struct LinearTarget {
+    /// 下层设备，持有它使得下层设备在映射表被释放之前不能被卸载
+    gendisk: Arc<GenDisk>,
+    /// 构造时传入的设备
+    path: String,
+    /// 在下层设备上的起始扇区
+    start: BlockId,
+}
+
+impl LinearTarget {
+    fn create(len: usize, args: &[&str]) -> Result<Arc<dyn DmTarget>, SystemError> {
+        let [path, start] = args else {
+            return Err(SystemError::EINVAL);
+        };
+        let start: BlockId = start.parse().map_err(|_| SystemError::EINVAL)?;
+        let gendisk = dm_get_device(path)?;
+        if start.checked_add(len).ok_or(SystemError::EINVAL)? > gendisk.range().len() {
+            return Err(SystemError::EINVAL);
+        }
+
+        Ok(Arc::new(Self {
+            gendisk,
+            path: path.to_string(),
+            start,
+        }))
+    }
+}
+
+impl DmTarget for LinearTarget {
+    fn read(&self, offset: BlockId, count: usize, buf: &mut [u8]) -> Result<usize, SystemError> {
+        self.gendisk
+            .read_at(&mut buf[..count * LBA_SIZE], self.start + offset)
+    }
+
+    fn write(&self, offset: BlockId, count: usize, buf: &[u8]) -> Result<usize, SystemError> {
+        self.gendisk
+            .write_at(&buf[..count * LBA_SIZE], self.start + offset)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        self.gendisk.sync()
+    }
+
+    fn params(&self) -> String {
+        format!("{} {}", self.path, self.start)
+    }
+}
+
+#[distributed_slice(DM_TARGET_TYPES)]
+static LINEAR_TARGET: DmTargetType = DmTargetType::new("linear", LinearTarget::create);
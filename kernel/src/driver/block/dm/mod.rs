@@ -0,0 +1,342 @@
+//! device-mapper
+//!
+//! 把若干个块设备的片段按映射表组合成一个新的块设备`/dev/dm-N`。
+//! 映射表由若干个首尾相接的目标（target）组成，每个目标负责一段连续的扇区，
+//! 由目标类型决定如何把请求转发给下层设备。目前只实现了`linear`目标。
+//!
+//! 与Linux一样，新的映射表先被加载为非活动表，恢复（resume）设备时才替换活动表；
+//! 第一次恢复时设备被注册到块设备管理器，之后就可以像普通磁盘一样挂载。
+//! 映射设备本身不扫描分区表。
+//!
+//! 用户态通过`/dev/mapper/control`的ioctl（与Linux的dm-ioctl兼容的子集）管理映射设备。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/md/dm.c
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt::Debug;
+use linkme::distributed_slice;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    driver::base::{
+        block::{
+            block_device::{BlockId, LBA_SIZE},
+            gendisk::GenDisk,
+            manager::block_dev_manager,
+        },
+        device::device_number::{DeviceNumber, Major},
+    },
+    filesystem::devfs::{devfs_register, devfs_unregister},
+    init::initcall::INITCALL_DEVICE,
+    libs::mutex::Mutex,
+};
+
+use self::{device::DmInode, ioctl::DmControlInode};
+
+mod device;
+mod ioctl;
+pub mod linear;
+
+pub use self::device::DmDevice;
+
+const DM_BASENAME: &str = "dm-";
+/// 映射设备的最大数量
+pub const DM_MAX_DEVICES: u32 = 256;
+/// 映射设备名字的最大长度（包括结尾的`\0`）
+pub const DM_NAME_LEN: usize = 128;
+/// 映射设备UUID的最大长度（包括结尾的`\0`）
+pub const DM_UUID_LEN: usize = 129;
+
+/// 已经创建的映射设备，以次设备号为键
+static DM_DEVICES: Mutex<BTreeMap<u32, Arc<DmInode>>> = Mutex::new(BTreeMap::new());
+
+/// 映射表中的一个目标
+pub trait DmTarget: Debug + Send + Sync {
+    /// 读取目标内的扇区
+    ///
+    /// ## 参数
+    ///
+    /// - `offset`: 目标内的起始扇区
+    /// - `count`: 扇区数
+    /// - `buf`: 输出缓冲区，长度为`count * LBA_SIZE`
+    fn read(&self, offset: BlockId, count: usize, buf: &mut [u8]) -> Result<usize, SystemError>;
+
+    /// 写入目标内的扇区，参数与`read`相同
+    fn write(&self, offset: BlockId, count: usize, buf: &[u8]) -> Result<usize, SystemError>;
+
+    /// 把数据同步到下层设备
+    fn sync(&self) -> Result<(), SystemError>;
+
+    /// 构造目标时使用的参数，用于导出映射表
+    fn params(&self) -> String;
+}
+
+/// 目标的构造函数
+///
+/// ## 参数
+///
+/// - `len`: 目标的长度（扇区）
+/// - `args`: 以空白分隔的参数
+pub type DmTargetCtr = fn(len: usize, args: &[&str]) -> Result<Arc<dyn DmTarget>, SystemError>;
+
+/// 目标类型
+pub struct DmTargetType {
+    name: &'static str,
+    ctr: DmTargetCtr,
+}
+
+impl Debug for DmTargetType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DmTargetType")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl DmTargetType {
+    pub const fn new(name: &'static str, ctr: DmTargetCtr) -> Self {
+        Self { name, ctr }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// 所有的目标类型，新的目标类型通过`#[distributed_slice(DM_TARGET_TYPES)]`注册
+#[distributed_slice]
+pub static DM_TARGET_TYPES: [DmTargetType] = [..];
+
+fn dm_find_target_type(name: &str) -> Option<&'static DmTargetType> {
+    DM_TARGET_TYPES.iter().find(|t| t.name == name)
+}
+
+/// 查找目标使用的下层设备
+///
+/// `path`可以是`/dev/sda1`这样的路径，也可以是`主设备号:次设备号`（只能指定整个磁盘）
+pub fn dm_get_device(path: &str) -> Result<Arc<GenDisk>, SystemError> {
+    if let Some((major, minor)) = path.split_once(':') {
+        let major = major.parse().map_err(|_| SystemError::EINVAL)?;
+        let minor = minor.parse().map_err(|_| SystemError::EINVAL)?;
+        return block_dev_manager()
+            .lookup_gendisk_by_devt(DeviceNumber::new(Major::new(major), minor))
+            .ok_or(SystemError::ENODEV);
+    }
+    block_dev_manager()
+        .lookup_gendisk_by_path(path)
+        .ok_or(SystemError::ENODEV)
+}
+
+#[derive(Debug)]
+struct DmTableEntry {
+    start: BlockId,
+    len: usize,
+    target_type: &'static DmTargetType,
+    target: Arc<dyn DmTarget>,
+}
+
+/// 映射表
+#[derive(Debug, Default)]
+pub struct DmTable {
+    targets: Vec<DmTableEntry>,
+}
+
+impl DmTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 在映射表末尾添加一个目标
+    ///
+    /// 目标必须紧接着前一个目标，且长度不能为0
+    pub fn add_target(
+        &mut self,
+        start: BlockId,
+        len: usize,
+        target_type: &str,
+        params: &str,
+    ) -> Result<(), SystemError> {
+        if len == 0 || start != self.nr_sectors() {
+            return Err(SystemError::EINVAL);
+        }
+        let target_type = dm_find_target_type(target_type).ok_or(SystemError::EINVAL)?;
+        let args: Vec<&str> = params.split_whitespace().collect();
+        let target = (target_type.ctr)(len, &args)?;
+
+        self.targets.push(DmTableEntry {
+            start,
+            len,
+            target_type,
+            target,
+        });
+        Ok(())
+    }
+
+    /// 映射表的总长度（扇区）
+    pub fn nr_sectors(&self) -> usize {
+        self.targets.last().map(|t| t.start + t.len).unwrap_or(0)
+    }
+
+    pub fn nr_targets(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// 依次返回每个目标的`(起始扇区, 长度, 目标类型, 参数)`
+    pub fn entries(&self) -> impl Iterator<Item = (BlockId, usize, &'static str, String)> + '_ {
+        self.targets
+            .iter()
+            .map(|t| (t.start, t.len, t.target_type.name, t.target.params()))
+    }
+
+    /// 把请求按目标的边界拆分，对每一段调用`f(目标, 目标内的起始扇区, 扇区数, 在请求内的扇区偏移)`
+    fn split_io<F>(&self, lba_id_start: BlockId, count: usize, mut f: F) -> Result<(), SystemError>
+    where
+        F: FnMut(&Arc<dyn DmTarget>, BlockId, usize, usize) -> Result<(), SystemError>,
+    {
+        if lba_id_start + count > self.nr_sectors() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut done = 0;
+        while done < count {
+            let lba = lba_id_start + done;
+            let idx = self.targets.partition_point(|t| t.start + t.len <= lba);
+            let entry = &self.targets[idx];
+            let offset = lba - entry.start;
+            let n = (entry.len - offset).min(count - done);
+            f(&entry.target, offset, n, done)?;
+            done += n;
+        }
+        Ok(())
+    }
+
+    pub fn read(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        self.split_io(lba_id_start, count, |target, offset, n, done| {
+            target
+                .read(offset, n, &mut buf[done * LBA_SIZE..(done + n) * LBA_SIZE])
+                .map(|_| ())
+        })?;
+        Ok(count)
+    }
+
+    pub fn write(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        self.split_io(lba_id_start, count, |target, offset, n, done| {
+            target
+                .write(offset, n, &buf[done * LBA_SIZE..(done + n) * LBA_SIZE])
+                .map(|_| ())
+        })?;
+        Ok(count)
+    }
+
+    pub fn sync(&self) -> Result<(), SystemError> {
+        for entry in self.targets.iter() {
+            entry.target.sync()?;
+        }
+        Ok(())
+    }
+}
+
+/// 创建映射设备
+///
+/// ## 参数
+///
+/// - `name`: 设备的名字，不能与已有的设备重复
+/// - `uuid`: 设备的UUID，可以为空，不为空时不能与已有的设备重复
+/// - `minor`: 次设备号，为None时使用最小的未被使用的次设备号
+pub fn dm_create(name: &str, uuid: &str, minor: Option<u32>) -> Result<Arc<DmDevice>, SystemError> {
+    if name.is_empty() || name.len() >= DM_NAME_LEN || name.contains('/') {
+        return Err(SystemError::EINVAL);
+    }
+    if uuid.len() >= DM_UUID_LEN {
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut devices = DM_DEVICES.lock();
+    if devices.values().any(|inode| {
+        inode.dev().dm_name() == name || (!uuid.is_empty() && inode.dev().uuid() == uuid)
+    }) {
+        return Err(SystemError::EBUSY);
+    }
+    let minor = match minor {
+        Some(minor) if minor >= DM_MAX_DEVICES => return Err(SystemError::EINVAL),
+        Some(minor) if devices.contains_key(&minor) => return Err(SystemError::EBUSY),
+        Some(minor) => minor,
+        None => (0..DM_MAX_DEVICES)
+            .find(|minor| !devices.contains_key(minor))
+            .ok_or(SystemError::ENOSPC)?,
+    };
+
+    let inode = DmInode::new(minor, name.to_string(), uuid.to_string());
+    devfs_register(&inode.devname(), inode.clone())?;
+    let dev = inode.dev().clone();
+    devices.insert(minor, inode);
+    Ok(dev)
+}
+
+/// 删除映射设备
+///
+/// 设备仍被打开或者仍被挂载时返回`EBUSY`
+pub fn dm_remove(dev: &Arc<DmDevice>) -> Result<(), SystemError> {
+    let mut devices = DM_DEVICES.lock();
+    let inode = devices.get(&dev.minor()).ok_or(SystemError::ENXIO)?.clone();
+    dev.destroy()?;
+    devfs_unregister(&inode.devname(), inode.clone())?;
+    devices.remove(&dev.minor());
+    Ok(())
+}
+
+/// 通过名字查找映射设备
+pub fn dm_lookup_by_name(name: &str) -> Option<Arc<DmDevice>> {
+    DM_DEVICES
+        .lock()
+        .values()
+        .find(|inode| inode.dev().dm_name() == name)
+        .map(|inode| inode.dev().clone())
+}
+
+/// 通过UUID查找映射设备
+pub fn dm_lookup_by_uuid(uuid: &str) -> Option<Arc<DmDevice>> {
+    DM_DEVICES
+        .lock()
+        .values()
+        .find(|inode| inode.dev().uuid() == uuid)
+        .map(|inode| inode.dev().clone())
+}
+
+/// 通过次设备号查找映射设备
+pub fn dm_lookup_by_minor(minor: u32) -> Option<Arc<DmDevice>> {
+    DM_DEVICES
+        .lock()
+        .get(&minor)
+        .map(|inode| inode.dev().clone())
+}
+
+/// 所有的映射设备，按次设备号排序
+pub fn dm_devices() -> Vec<Arc<DmDevice>> {
+    DM_DEVICES
+        .lock()
+        .values()
+        .map(|inode| inode.dev().clone())
+        .collect()
+}
+
+#[unified_init(INITCALL_DEVICE)]
+fn dm_init() -> Result<(), SystemError> {
+    devfs_register(ioctl::DM_CONTROL_NAME, DmControlInode::new())
+}
@@ -0,0 +1,970 @@
+//! 回环块设备（loop）
+//!
+//! 把一个普通文件映射为块设备，从而可以像挂载磁盘一样挂载文件系统镜像。
+//!
+//! - `/dev/loop-control`: `LOOP_CTL_GET_FREE`返回一个空闲的设备号（没有空闲设备时会创建一个新设备），
+//!   `LOOP_CTL_ADD`/`LOOP_CTL_REMOVE`创建/删除指定编号的设备
+//! - `/dev/loopN`: `LOOP_SET_FD`把文件绑定到设备上，之后设备会被注册到块设备管理器；
+//!   `LOOP_CLR_FD`解除绑定；`LOOP_SET_STATUS64`/`LOOP_GET_STATUS64`设置/获取偏移量、大小限制与标志
+//!
+//! 由于设备名以数字结尾，设备上的分区被命名为`loopNpM`，如`/dev/loop0p1`。
+//!
+//! 只有设置了`LO_FLAGS_PARTSCAN`时才会扫描分区表，否则整个设备就是一个gendisk，
+//! 这样FAT等本身带有0x55AA签名的文件系统镜像不会被误认为MBR。
+//! `LO_FLAGS_AUTOCLEAR`只被记录下来，最后一个用户关闭设备时并不会自动解除绑定。
+
+use core::{any::Any, intrinsics::unlikely};
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use log::warn;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    debug::fault_inject::FAIL_BLOCK_IO,
+    driver::base::{
+        block::{
+            block_device::{BlockDevName, BlockDevice, BlockId, GeneralBlockRange, LBA_SIZE},
+            disk_info::Partition,
+            manager::{block_dev_manager, BlockDevMeta},
+        },
+        class::Class,
+        device::{
+            bus::Bus,
+            device_number::{DeviceNumber, Major},
+            driver::Driver,
+            Device, DeviceCommonData, DeviceType, IdTable,
+        },
+        kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::{
+        devfs::{devfs_register, devfs_unregister, DevFS, DeviceINode},
+        kernfs::KernFSInode,
+        mbr::MbrDiskPartionTable,
+        vfs::{
+            core::generate_inode_id,
+            file::{File, FileMode},
+            syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, IndexNode, Metadata,
+        },
+    },
+    init::initcall::INITCALL_DEVICE,
+    libs::{
+        mutex::Mutex,
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    process::ProcessManager,
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+    time::PosixTimeSpec,
+};
+
+const LOOP_BASENAME: &str = "loop";
+const LOOP_CONTROL_NAME: &str = "loop-control";
+
+/// loop设备的最大数量
+pub const LOOP_MAX_DEVICES: usize = 256;
+/// 启动时创建的loop设备数量
+const LOOP_DEFAULT_DEVICES: usize = 8;
+/// /dev/loop-control的次设备号
+const LOOP_CTRL_MINOR: u32 = 237;
+
+/// /dev/loopN的ioctl：绑定文件，参数为文件描述符
+pub const LOOP_SET_FD: u32 = 0x4C00;
+/// /dev/loopN的ioctl：解除绑定
+pub const LOOP_CLR_FD: u32 = 0x4C01;
+/// /dev/loopN的ioctl：设置LoopInfo64
+pub const LOOP_SET_STATUS64: u32 = 0x4C04;
+/// /dev/loopN的ioctl：获取LoopInfo64
+pub const LOOP_GET_STATUS64: u32 = 0x4C05;
+/// /dev/loopN的ioctl：根据文件的当前大小重新计算容量
+pub const LOOP_SET_CAPACITY: u32 = 0x4C07;
+
+/// /dev/loop-control的ioctl：创建指定编号的设备，参数为负数时自动分配编号
+pub const LOOP_CTL_ADD: u32 = 0x4C80;
+/// /dev/loop-control的ioctl：删除指定编号的设备
+pub const LOOP_CTL_REMOVE: u32 = 0x4C81;
+/// /dev/loop-control的ioctl：获取一个空闲的设备号
+pub const LOOP_CTL_GET_FREE: u32 = 0x4C82;
+
+const LO_NAME_SIZE: usize = 64;
+const LO_KEY_SIZE: usize = 32;
+
+const _: () = assert!(core::mem::size_of::<LoopInfo64>() == 232);
+
+bitflags! {
+    pub struct LoopFlags: u32 {
+        const READ_ONLY = 1;
+        const AUTOCLEAR = 4;
+        const PARTSCAN = 8;
+        const DIRECT_IO = 16;
+
+        /// 可以通过LOOP_SET_STATUS64设置的标志
+        const SETTABLE = Self::AUTOCLEAR.bits | Self::PARTSCAN.bits;
+        /// 可以通过LOOP_SET_STATUS64清除的标志
+        const CLEARABLE = Self::AUTOCLEAR.bits;
+    }
+}
+
+/// LOOP_SET_STATUS64/LOOP_GET_STATUS64的参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LoopInfo64 {
+    /// 输出：后备文件所在的设备
+    pub lo_device: u64,
+    /// 输出：后备文件的inode号
+    pub lo_inode: u64,
+    /// 输出：loop设备自身的设备号
+    pub lo_rdevice: u64,
+    /// 数据在后备文件中的起始偏移量（字节）
+    pub lo_offset: u64,
+    /// 设备大小的上限（字节），为0时表示不限制
+    pub lo_sizelimit: u64,
+    /// 输出：设备编号
+    pub lo_number: u32,
+    /// 不支持加密，必须为0
+    pub lo_encrypt_type: u32,
+    pub lo_encrypt_key_size: u32,
+    /// LoopFlags
+    pub lo_flags: u32,
+    pub lo_file_name: [u8; LO_NAME_SIZE],
+    pub lo_crypt_name: [u8; LO_NAME_SIZE],
+    pub lo_encrypt_key: [u8; LO_KEY_SIZE],
+    pub lo_init: [u64; 2],
+}
+
+impl LoopInfo64 {
+    fn zeroed() -> Self {
+        Self {
+            lo_device: 0,
+            lo_inode: 0,
+            lo_rdevice: 0,
+            lo_offset: 0,
+            lo_sizelimit: 0,
+            lo_number: 0,
+            lo_encrypt_type: 0,
+            lo_encrypt_key_size: 0,
+            lo_flags: 0,
+            lo_file_name: [0; LO_NAME_SIZE],
+            lo_crypt_name: [0; LO_NAME_SIZE],
+            lo_encrypt_key: [0; LO_KEY_SIZE],
+            lo_init: [0; 2],
+        }
+    }
+}
+
+/// 已经创建的loop设备，以设备编号为键
+static LOOP_DEVICES: Mutex<BTreeMap<usize, Arc<LoopInode>>> = Mutex::new(BTreeMap::new());
+
+/// 设备与后备文件的绑定关系
+#[derive(Debug, Clone)]
+struct LoopBinding {
+    file: Arc<File>,
+    offset: u64,
+    sizelimit: u64,
+    /// 设备的容量（512字节的扇区数）
+    nr_sectors: u64,
+    flags: LoopFlags,
+    file_name: [u8; LO_NAME_SIZE],
+}
+
+impl LoopBinding {
+    /// 根据后备文件的当前大小计算设备的容量
+    fn compute_sectors(file: &Arc<File>, offset: u64, sizelimit: u64) -> Result<u64, SystemError> {
+        let file_size = file.inode().metadata()?.size.max(0) as u64;
+        let mut size = file_size.saturating_sub(offset);
+        if sizelimit != 0 {
+            size = size.min(sizelimit);
+        }
+        Ok(size / LBA_SIZE as u64)
+    }
+}
+
+/// loop块设备
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct LoopDevice {
+    blkdev_meta: BlockDevMeta,
+    id: usize,
+    binding: SpinLock<Option<LoopBinding>>,
+    /// 串行化绑定、解绑与修改参数的操作
+    ctl_lock: Mutex<()>,
+    inner: SpinLock<InnerLoopDevice>,
+    locked_kobj_state: LockedKObjectState,
+    self_ref: Weak<Self>,
+}
+
+#[derive(Debug)]
+struct InnerLoopDevice {
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+}
+
+impl LoopDevice {
+    fn new(id: usize) -> Arc<Self> {
+        Arc::new_cyclic(|self_ref| Self {
+            blkdev_meta: BlockDevMeta::new(BlockDevName::new(
+                format!("{}{}", LOOP_BASENAME, id),
+                id,
+            )),
+            id,
+            binding: SpinLock::new(None),
+            ctl_lock: Mutex::new(()),
+            inner: SpinLock::new(InnerLoopDevice {
+                device_common: DeviceCommonData::default(),
+                kobject_common: KObjectCommonData::default(),
+            }),
+            locked_kobj_state: LockedKObjectState::default(),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerLoopDevice> {
+        self.inner.lock()
+    }
+
+    fn binding(&self) -> Option<LoopBinding> {
+        self.binding.lock().clone()
+    }
+
+    fn is_bound(&self) -> bool {
+        self.binding.lock().is_some()
+    }
+
+    /// 设备的容量（字节）
+    fn capacity(&self) -> usize {
+        self.binding
+            .lock()
+            .as_ref()
+            .map(|b| b.nr_sectors as usize * LBA_SIZE)
+            .unwrap_or(0)
+    }
+
+    fn as_block_device(&self) -> Arc<dyn BlockDevice> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    /// 绑定后备文件
+    fn set_fd(&self, fd: i32) -> Result<(), SystemError> {
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        if file.file_type() != FileType::File {
+            return Err(SystemError::EINVAL);
+        }
+
+        let _guard = self.ctl_lock.lock();
+        if self.is_bound() {
+            return Err(SystemError::EBUSY);
+        }
+
+        let nr_sectors = LoopBinding::compute_sectors(&file, 0, 0)?;
+        if nr_sectors == 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut flags = LoopFlags::empty();
+        if file.mode().accmode() == FileMode::O_RDONLY.accmode() {
+            flags.insert(LoopFlags::READ_ONLY);
+        }
+
+        let mut file_name = [0u8; LO_NAME_SIZE];
+        let path = file.inode().absolute_path().unwrap_or_default();
+        let len = path.len().min(LO_NAME_SIZE - 1);
+        file_name[..len].copy_from_slice(&path.as_bytes()[..len]);
+
+        *self.binding.lock() = Some(LoopBinding {
+            file,
+            offset: 0,
+            sizelimit: 0,
+            nr_sectors,
+            flags,
+            file_name,
+        });
+
+        block_dev_manager()
+            .register(self.as_block_device())
+            .inspect_err(|_| *self.binding.lock() = None)
+    }
+
+    /// 解除绑定
+    ///
+    /// 设备上的文件系统仍被挂载时返回`EBUSY`
+    fn clr_fd(&self) -> Result<(), SystemError> {
+        let _guard = self.ctl_lock.lock();
+        let binding = self.binding().ok_or(SystemError::ENXIO)?;
+        block_dev_manager().unregister(&self.as_block_device())?;
+        *self.binding.lock() = None;
+
+        if !binding.flags.contains(LoopFlags::READ_ONLY) {
+            binding.file.inode().sync().ok();
+        }
+        Ok(())
+    }
+
+    /// 修改设备的参数
+    ///
+    /// 容量或PARTSCAN标志发生变化时，需要先从块设备管理器中注销，再重新注册，以便重新扫描分区表
+    fn reconfigure(&self, new: LoopBinding) -> Result<(), SystemError> {
+        let old = self.binding().ok_or(SystemError::ENXIO)?;
+        if old.nr_sectors == new.nr_sectors
+            && old.offset == new.offset
+            && old.flags.contains(LoopFlags::PARTSCAN) == new.flags.contains(LoopFlags::PARTSCAN)
+        {
+            *self.binding.lock() = Some(new);
+            return Ok(());
+        }
+
+        let dev = self.as_block_device();
+        block_dev_manager().unregister(&dev)?;
+        *self.binding.lock() = Some(new);
+        if let Err(e) = block_dev_manager().register(dev.clone()) {
+            warn!(
+                "loop: failed to re-register {} with the new geometry: {:?}",
+                self.dev_name(),
+                e
+            );
+            *self.binding.lock() = Some(old);
+            block_dev_manager().register(dev)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn set_status64(&self, info: &LoopInfo64) -> Result<(), SystemError> {
+        if info.lo_encrypt_type != 0 || info.lo_encrypt_key_size != 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let _guard = self.ctl_lock.lock();
+        let mut binding = self.binding().ok_or(SystemError::ENXIO)?;
+
+        let prev = binding.flags;
+        let mut flags = LoopFlags::from_bits_truncate(info.lo_flags) & LoopFlags::SETTABLE;
+        // 不能设置的标志保持原值，不能清除的标志也保持原值
+        flags |= prev - LoopFlags::SETTABLE;
+        flags |= prev - LoopFlags::CLEARABLE;
+        binding.flags = flags;
+
+        binding.file_name = info.lo_file_name;
+        binding.file_name[LO_NAME_SIZE - 1] = 0;
+
+        if info.lo_offset != binding.offset || info.lo_sizelimit != binding.sizelimit {
+            if info.lo_offset % LBA_SIZE as u64 != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let nr_sectors =
+                LoopBinding::compute_sectors(&binding.file, info.lo_offset, info.lo_sizelimit)?;
+            if nr_sectors == 0 {
+                return Err(SystemError::EINVAL);
+            }
+            binding.offset = info.lo_offset;
+            binding.sizelimit = info.lo_sizelimit;
+            binding.nr_sectors = nr_sectors;
+        }
+
+        self.reconfigure(binding)
+    }
+
+    fn get_status64(&self) -> Result<LoopInfo64, SystemError> {
+        let binding = self.binding().ok_or(SystemError::ENXIO)?;
+        let metadata = binding.file.inode().metadata()?;
+
+        let mut info = LoopInfo64::zeroed();
+        info.lo_device = metadata.dev_id as u64;
+        let ino: usize = metadata.inode_id.into();
+        info.lo_inode = ino as u64;
+        info.lo_rdevice = DeviceNumber::new(Major::LOOP_MAJOR, self.id as u32).data() as u64;
+        info.lo_offset = binding.offset;
+        info.lo_sizelimit = binding.sizelimit;
+        info.lo_number = self.id as u32;
+        info.lo_flags = binding.flags.bits();
+        info.lo_file_name = binding.file_name;
+        Ok(info)
+    }
+
+    /// 后备文件的大小发生变化后，重新计算容量
+    fn set_capacity(&self) -> Result<(), SystemError> {
+        let _guard = self.ctl_lock.lock();
+        let mut binding = self.binding().ok_or(SystemError::ENXIO)?;
+        let nr_sectors =
+            LoopBinding::compute_sectors(&binding.file, binding.offset, binding.sizelimit)?;
+        if nr_sectors == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        binding.nr_sectors = nr_sectors;
+        self.reconfigure(binding)
+    }
+
+    /// 检查请求是否越界，返回请求在后备文件中的偏移量
+    fn check_range(
+        binding: &LoopBinding,
+        lba_id_start: BlockId,
+        count: usize,
+    ) -> Result<usize, SystemError> {
+        if (lba_id_start + count) as u64 > binding.nr_sectors {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(binding.offset as usize + lba_id_start * LBA_SIZE)
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn dev_name(&self) -> &BlockDevName {
+        &self.blkdev_meta.devname
+    }
+
+    fn blkdev_meta(&self) -> &BlockDevMeta {
+        &self.blkdev_meta
+    }
+
+    fn disk_range(&self) -> GeneralBlockRange {
+        GeneralBlockRange {
+            lba_start: 0,
+            lba_end: self.capacity() / LBA_SIZE,
+        }
+    }
+
+    fn read_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let binding = self.binding().ok_or(SystemError::ENXIO)?;
+        let offset = Self::check_range(&binding, lba_id_start, count)?;
+        let len = count * LBA_SIZE;
+        let n = binding.file.pread(offset, len, &mut buf[..len])?;
+        // 后备文件被截断时，超出文件末尾的部分读出为0
+        buf[n..len].fill(0);
+        Ok(count)
+    }
+
+    fn write_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        let binding = self.binding().ok_or(SystemError::ENXIO)?;
+        if binding.flags.contains(LoopFlags::READ_ONLY) {
+            return Err(SystemError::EROFS);
+        }
+        let offset = Self::check_range(&binding, lba_id_start, count)?;
+        let len = count * LBA_SIZE;
+        if binding.file.pwrite(offset, len, &buf[..len])? != len {
+            return Err(SystemError::EIO);
+        }
+        Ok(count)
+    }
+
+    /// 不经过全局的块缓存：块缓存只以LBA为键，不区分磁盘，
+    /// 而后备文件本身已经有页缓存
+    fn read_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if unlikely(FAIL_BLOCK_IO.should_fail(count)) {
+            return Err(SystemError::EIO);
+        }
+        self.read_at_sync(lba_id_start, count, buf)
+    }
+
+    fn write_at(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        if unlikely(FAIL_BLOCK_IO.should_fail(count)) {
+            return Err(SystemError::EIO);
+        }
+        self.write_at_sync(lba_id_start, count, buf)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        match self.binding() {
+            Some(binding) => binding.file.inode().sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn partition_scan(&self) -> bool {
+        self.binding
+            .lock()
+            .as_ref()
+            .is_some_and(|b| b.flags.contains(LoopFlags::PARTSCAN))
+    }
+
+    fn blk_size_log2(&self) -> u8 {
+        9
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn device(&self) -> Arc<dyn Device> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    fn block_size(&self) -> usize {
+        LBA_SIZE
+    }
+
+    fn partitions(&self) -> Vec<Arc<Partition>> {
+        let device = self.as_block_device();
+        MbrDiskPartionTable::from_disk(device.clone())
+            .map(|mbr_table| mbr_table.partitions(Arc::downgrade(&device)))
+            .unwrap_or_default()
+    }
+}
+
+impl Device for LoopDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(
+            LOOP_BASENAME.to_string(),
+            Some(DeviceNumber::new(Major::LOOP_MAJOR, self.id as u32)),
+        )
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for LoopDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.dev_name().to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
+
+/// /dev/loopN
+#[derive(Debug)]
+pub struct LoopInode {
+    dev: Arc<LoopDevice>,
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl LoopInode {
+    fn new(id: usize) -> Arc<Self> {
+        Arc::new(Self {
+            dev: LoopDevice::new(id),
+            fs: SpinLock::new(Weak::default()),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: LBA_SIZE,
+                blocks: 0,
+                atime: PosixTimeSpec::default(),
+                mtime: PosixTimeSpec::default(),
+                ctime: PosixTimeSpec::default(),
+                file_type: FileType::BlockDevice,
+                mode: ModeType::from_bits_truncate(0o660),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::new(Major::LOOP_MAJOR, id as u32),
+            },
+        })
+    }
+
+    fn name(&self) -> String {
+        self.dev.dev_name().to_string()
+    }
+
+    /// 把读写请求限制在设备的容量之内
+    fn clamp(&self, offset: usize, len: usize) -> usize {
+        len.min(self.dev.capacity().saturating_sub(offset))
+    }
+}
+
+impl DeviceINode for LoopInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for LoopInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        match cmd {
+            LOOP_SET_FD => self.dev.set_fd(data as i32).map(|_| 0),
+            LOOP_CLR_FD => self.dev.clr_fd().map(|_| 0),
+            LOOP_SET_STATUS64 => {
+                let info = *UserBufferReader::new(
+                    data as *const LoopInfo64,
+                    core::mem::size_of::<LoopInfo64>(),
+                    true,
+                )?
+                .read_one_from_user::<LoopInfo64>(0)?;
+                self.dev.set_status64(&info).map(|_| 0)
+            }
+            LOOP_GET_STATUS64 => {
+                let info = self.dev.get_status64()?;
+                let mut writer = UserBufferWriter::new(
+                    data as *mut LoopInfo64,
+                    core::mem::size_of::<LoopInfo64>(),
+                    true,
+                )?;
+                writer.copy_one_to_user(&info, 0)?;
+                Ok(0)
+            }
+            LOOP_SET_CAPACITY => self.dev.set_capacity().map(|_| 0),
+            _ => Err(SystemError::ENOSYS),
+        }
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if !self.dev.is_bound() {
+            return Err(SystemError::ENXIO);
+        }
+        let len = self.clamp(offset, len);
+        if len == 0 {
+            return Ok(0);
+        }
+        self.dev.read_at_bytes(offset, len, buf)
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if !self.dev.is_bound() {
+            return Err(SystemError::ENXIO);
+        }
+        let len = self.clamp(offset, len);
+        if len == 0 {
+            return Err(SystemError::ENOSPC);
+        }
+        self.dev.write_at_bytes(offset, len, buf)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let mut metadata = self.metadata.clone();
+        metadata.size = self.dev.capacity() as i64;
+        metadata.blocks = self.dev.capacity() / LBA_SIZE;
+        Ok(metadata)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        self.dev.sync()
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.lock().upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+}
+
+/// 创建编号为`id`的loop设备，`id`为None时使用最小的未被使用的编号
+fn loop_add(id: Option<usize>) -> Result<usize, SystemError> {
+    let mut devices = LOOP_DEVICES.lock();
+    let id = match id {
+        Some(id) if id >= LOOP_MAX_DEVICES => return Err(SystemError::EINVAL),
+        Some(id) if devices.contains_key(&id) => return Err(SystemError::EEXIST),
+        Some(id) => id,
+        None => (0..LOOP_MAX_DEVICES)
+            .find(|id| !devices.contains_key(id))
+            .ok_or(SystemError::ENOSPC)?,
+    };
+
+    let inode = LoopInode::new(id);
+    devfs_register(&inode.name(), inode.clone())?;
+    devices.insert(id, inode);
+    Ok(id)
+}
+
+/// 删除编号为`id`的loop设备，设备仍绑定着文件时返回`EBUSY`
+fn loop_remove(id: usize) -> Result<(), SystemError> {
+    let mut devices = LOOP_DEVICES.lock();
+    let inode = devices.get(&id).ok_or(SystemError::ENODEV)?.clone();
+    let _guard = inode.dev.ctl_lock.lock();
+    if inode.dev.is_bound() {
+        return Err(SystemError::EBUSY);
+    }
+    devfs_unregister(&inode.name(), inode.clone())?;
+    devices.remove(&id);
+    Ok(())
+}
+
+/// 返回一个未绑定文件的loop设备的编号，没有时创建一个新设备
+fn loop_get_free() -> Result<usize, SystemError> {
+    let free = LOOP_DEVICES
+        .lock()
+        .iter()
+        .find(|(_, inode)| !inode.dev.is_bound())
+        .map(|(id, _)| *id);
+    match free {
+        Some(id) => Ok(id),
+        None => loop_add(None),
+    }
+}
+
+/// /dev/loop-control
+#[derive(Debug)]
+pub struct LoopControlInode {
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl LoopControlInode {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            fs: SpinLock::new(Weak::default()),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: PosixTimeSpec::default(),
+                mtime: PosixTimeSpec::default(),
+                ctime: PosixTimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o660),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::new(Major::MISC_MAJOR, LOOP_CTRL_MINOR),
+            },
+        })
+    }
+}
+
+impl DeviceINode for LoopControlInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for LoopControlInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        match cmd {
+            LOOP_CTL_GET_FREE => loop_get_free(),
+            LOOP_CTL_ADD => {
+                let id = data as isize;
+                loop_add(if id < 0 { None } else { Some(id as usize) })
+            }
+            LOOP_CTL_REMOVE => loop_remove(data).map(|_| data),
+            _ => Err(SystemError::ENOSYS),
+        }
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.lock().upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+}
+
+#[unified_init(INITCALL_DEVICE)]
+fn loop_init() -> Result<(), SystemError> {
+    devfs_register(LOOP_CONTROL_NAME, LoopControlInode::new())?;
+    for id in 0..LOOP_DEFAULT_DEVICES {
+        loop_add(Some(id))?;
+    }
+    Ok(())
+}
@@ -1,2 +1,6 @@
 pub mod cache;
+pub mod dm;
+pub mod loop_device;
+pub mod ublk;
 pub mod virtio_blk;
+mod virtio_blk_queue;
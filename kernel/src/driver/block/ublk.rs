@@ -0,0 +1,1158 @@
+//! 用户态块设备（ublk）
+//!
+//! 让用户态的守护进程为内核中注册的块设备处理I/O请求，
+//! 从而无需修改内核就能实现压缩盘、加密盘、网络盘等原型。
+//!
+//! - 守护进程打开`/dev/ublk-control`，通过`UBLK_CTRL_ADD_DEV`创建设备，得到一个守护进程fd
+//! - 守护进程把该fd mmap到自己的地址空间，请求队列、完成队列和数据缓冲区都位于这块共享内存中
+//! - 内核把请求的tag放入请求队列并唤醒守护进程；守护进程处理完毕后把结果放入完成队列，
+//!   然后通过`UBLK_CMD_COMMIT`（门铃）通知内核
+//! - `UBLK_CMD_START_DEV`把设备注册到块设备管理器，之后就可以像其他磁盘一样挂载
+//!
+//! 详细的用户态协议见`docs/kernel/filesystem/ublk.md`。
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU8, AtomicUsize, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use bitmap::traits::BitMapOps;
+use log::warn;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::{mm::LockedFrameAllocator, MMArch},
+    driver::base::{
+        block::{
+            block_device::{BlockDevName, BlockDevice, BlockId, GeneralBlockRange, LBA_SIZE},
+            disk_info::Partition,
+            manager::{block_dev_manager, BlockDevMeta},
+        },
+        class::Class,
+        device::{
+            bus::Bus, device_number::DeviceNumber, driver::Driver, Device, DeviceCommonData,
+            DeviceType, IdTable,
+        },
+        kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::{
+        devfs::{devfs_register, DevFS, DeviceINode},
+        kernfs::KernFSInode,
+        mbr::MbrDiskPartionTable,
+        page_cache::PageCache,
+        vfs::{
+            core::generate_inode_id,
+            file::{File, FileMode},
+            syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, FsInfo, IndexNode, Metadata, SuperBlock,
+        },
+    },
+    init::initcall::INITCALL_DEVICE,
+    libs::{
+        align::page_align_up,
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::WaitQueue,
+    },
+    mm::{
+        allocator::page_frame::{PageFrameCount, PhysPageFrame},
+        fault::{PageFaultHandler, PageFaultMessage},
+        page::{page_manager_lock_irqsave, Page, PageFlags, PageType},
+        MemoryManagementArch, PhysAddr, VirtAddr, VmFaultReason,
+    },
+    net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll},
+    process::{
+        kthread::{KernelThreadClosure, KernelThreadMechanism},
+        ProcessManager,
+    },
+    sched::SchedMode,
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+    time::PosixTimeSpec,
+};
+
+const UBLK_BASENAME: &str = "ublk";
+const UBLK_CONTROL_NAME: &str = "ublk-control";
+
+/// ublk设备的最大数量
+pub const UBLK_MAX_DEVICES: usize = 16;
+/// 每个设备最多同时处理的请求数
+pub const UBLK_MAX_QUEUE_DEPTH: u32 = 64;
+/// 单个请求的最大字节数
+pub const UBLK_MAX_IO_BYTES: u32 = 256 * 1024;
+/// 未指定时，单个请求的默认字节数
+const UBLK_DEFAULT_IO_BYTES: u32 = 64 * 1024;
+
+/// /dev/ublk-control的ioctl：创建设备，返回守护进程fd
+pub const UBLK_CTRL_ADD_DEV: u32 = 0x7500;
+/// 守护进程fd的ioctl：把设备注册到块设备管理器
+pub const UBLK_CMD_START_DEV: u32 = 0x7510;
+/// 守护进程fd的ioctl：睡眠，直到请求队列中有新的请求，返回待处理的请求数
+pub const UBLK_CMD_WAIT: u32 = 0x7511;
+/// 守护进程fd的ioctl：门铃，通知内核完成队列中有新的结果，返回本次收割的请求数
+pub const UBLK_CMD_COMMIT: u32 = 0x7512;
+
+/// 读请求：守护进程把数据写入该tag的缓冲区
+pub const UBLK_IO_OP_READ: u8 = 0;
+/// 写请求：数据已经位于该tag的缓冲区中
+pub const UBLK_IO_OP_WRITE: u8 = 1;
+/// 把守护进程缓存的数据写回存储，nr_sectors为0
+pub const UBLK_IO_OP_FLUSH: u8 = 2;
+
+const _: () = assert!(core::mem::size_of::<UblkDevInfo>() == 48);
+const _: () = assert!(core::mem::size_of::<UblkCtrlHeader>() == 64);
+const _: () = assert!(core::mem::size_of::<UblkIoDesc>() == 16);
+const _: () = assert!(core::mem::size_of::<UblkCqe>() == 8);
+// 控制结构必须能放进共享内存的第一页
+const _: () = assert!(
+    core::mem::size_of::<UblkCtrlHeader>()
+        + UBLK_MAX_QUEUE_DEPTH as usize
+            * (4 + core::mem::size_of::<UblkCqe>() + core::mem::size_of::<UblkIoDesc>())
+        <= MMArch::PAGE_SIZE
+);
+
+/// UBLK_CTRL_ADD_DEV的参数
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UblkDevInfo {
+    /// 输入：设备的容量（512字节的扇区数）
+    pub nr_sectors: u64,
+    /// 输入：队列深度，会被向上取整为2的幂；输出：实际的队列深度
+    pub queue_depth: u32,
+    /// 输入：单个请求的最大字节数，为0时使用默认值；输出：实际值（扇区大小的倍数）
+    pub max_io_bytes: u32,
+    /// 输出：设备号，块设备的名字为`ublk`加上对应的字母（0为`ublka`）
+    pub dev_id: u32,
+    /// 输出：mmap时需要映射的共享内存长度
+    pub region_size: u32,
+    /// 输出：请求队列（u32的tag数组）在共享内存中的偏移量
+    pub sq_off: u32,
+    /// 输出：完成队列（UblkCqe数组）在共享内存中的偏移量
+    pub cq_off: u32,
+    /// 输出：请求描述符（UblkIoDesc数组，以tag为下标）在共享内存中的偏移量
+    pub desc_off: u32,
+    /// 输出：数据缓冲区在共享内存中的偏移量，tag为i的缓冲区位于`data_off + i * max_io_bytes`
+    pub data_off: u32,
+    pub resv: [u32; 2],
+}
+
+/// 共享内存开头的控制头
+#[repr(C)]
+#[derive(Debug)]
+pub struct UblkCtrlHeader {
+    /// 请求队列的读位置，只由守护进程修改
+    pub sq_head: AtomicU32,
+    /// 请求队列的写位置，只由内核修改
+    pub sq_tail: AtomicU32,
+    /// 完成队列的读位置，只由内核修改
+    pub cq_head: AtomicU32,
+    /// 完成队列的写位置，只由守护进程修改
+    pub cq_tail: AtomicU32,
+    pub queue_depth: u32,
+    pub max_io_bytes: u32,
+    resv: [u32; 10],
+}
+
+/// 请求描述符
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UblkIoDesc {
+    /// UBLK_IO_OP_*
+    pub op: u8,
+    resv: [u8; 3],
+    pub nr_sectors: u32,
+    pub start_sector: u64,
+}
+
+/// 完成队列的表项
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UblkCqe {
+    pub tag: u32,
+    /// 成功时为处理的字节数，失败时为负的错误码
+    pub result: i32,
+}
+
+/// tag的状态
+const TAG_FREE: u8 = 0;
+/// 已放入请求队列，等待守护进程完成
+const TAG_INFLIGHT: u8 = 1;
+/// 守护进程已经完成
+const TAG_DONE: u8 = 2;
+
+static UBLK_IDS: SpinLock<bitmap::StaticBitmap<UBLK_MAX_DEVICES>> =
+    SpinLock::new(bitmap::StaticBitmap::new());
+
+/// 设备与守护进程共享的内存
+#[derive(Debug)]
+struct UblkRegion {
+    phys_addr: PhysAddr,
+    vaddr: VirtAddr,
+    page_count: PageFrameCount,
+    pages: Vec<Arc<Page>>,
+}
+
+impl UblkRegion {
+    fn new(len: usize) -> Result<Self, SystemError> {
+        let page_count = PageFrameCount::new(page_align_up(len) / MMArch::PAGE_SIZE);
+        let mut page_manager_guard = page_manager_lock_irqsave();
+        let (phys_addr, pages) = page_manager_guard.create_pages(
+            PageType::Normal,
+            PageFlags::PG_UNEVICTABLE,
+            &mut LockedFrameAllocator,
+            page_count,
+        )?;
+        drop(page_manager_guard);
+
+        let vaddr = unsafe { MMArch::phys_2_virt(phys_addr) }.ok_or(SystemError::EFAULT)?;
+        Ok(Self {
+            phys_addr,
+            vaddr,
+            page_count,
+            pages,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.page_count.data() * MMArch::PAGE_SIZE
+    }
+}
+
+impl Drop for UblkRegion {
+    fn drop(&mut self) {
+        let mut page_manager_guard = page_manager_lock_irqsave();
+        let mut cur_phys = PhysPageFrame::new(self.phys_addr);
+        for _ in 0..self.page_count.data() {
+            page_manager_guard.remove_page(&cur_phys.phys_address());
+            cur_phys = cur_phys.next();
+        }
+    }
+}
+
+/// 共享内存的布局
+#[derive(Debug, Clone, Copy)]
+struct UblkLayout {
+    sq_off: usize,
+    cq_off: usize,
+    desc_off: usize,
+    data_off: usize,
+    len: usize,
+}
+
+impl UblkLayout {
+    fn new(queue_depth: u32, max_io_bytes: u32) -> Self {
+        let depth = queue_depth as usize;
+        let sq_off = core::mem::size_of::<UblkCtrlHeader>();
+        let cq_off = sq_off + depth * core::mem::size_of::<u32>();
+        let desc_off = cq_off + depth * core::mem::size_of::<UblkCqe>();
+        let data_off = MMArch::PAGE_SIZE;
+        Self {
+            sq_off,
+            cq_off,
+            desc_off,
+            data_off,
+            len: data_off + depth * max_io_bytes as usize,
+        }
+    }
+}
+
+/// ublk块设备
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct UblkDevice {
+    blkdev_meta: BlockDevMeta,
+    id: usize,
+    nr_sectors: u64,
+    queue_depth: u32,
+    max_io_bytes: u32,
+    region: UblkRegion,
+    layout: UblkLayout,
+    /// 空闲tag的位图
+    free_tags: SpinLock<u64>,
+    tag_state: Vec<AtomicU8>,
+    tag_result: Vec<AtomicI32>,
+    /// 请求队列的写位置（共享内存中的副本可能被用户态改写，内核只信任这里的值）
+    sq_tail: SpinLock<u32>,
+    /// 完成队列的读位置
+    cq_head: SpinLock<u32>,
+    /// 守护进程已经退出
+    dead: AtomicBool,
+    started: AtomicBool,
+    /// 等待空闲tag的进程
+    tag_wait: WaitQueue,
+    /// 等待请求完成的进程
+    io_wait: WaitQueue,
+    /// 等待新请求的守护进程
+    daemon_wait: WaitQueue,
+    epitems: EPollItems,
+    inner: SpinLock<InnerUblkDevice>,
+    locked_kobj_state: LockedKObjectState,
+    self_ref: Weak<Self>,
+}
+
+#[derive(Debug)]
+struct InnerUblkDevice {
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+}
+
+impl UblkDevice {
+    fn new(id: usize, info: &UblkDevInfo) -> Result<Arc<Self>, SystemError> {
+        let layout = UblkLayout::new(info.queue_depth, info.max_io_bytes);
+        let region = UblkRegion::new(layout.len)?;
+
+        let dev = Arc::new_cyclic(|self_ref| Self {
+            blkdev_meta: BlockDevMeta::new(Self::format_name(id)),
+            id,
+            nr_sectors: info.nr_sectors,
+            queue_depth: info.queue_depth,
+            max_io_bytes: info.max_io_bytes,
+            region,
+            layout,
+            free_tags: SpinLock::new(u64::MAX >> (64 - info.queue_depth)),
+            tag_state: (0..info.queue_depth)
+                .map(|_| AtomicU8::new(TAG_FREE))
+                .collect(),
+            tag_result: (0..info.queue_depth).map(|_| AtomicI32::new(0)).collect(),
+            sq_tail: SpinLock::new(0),
+            cq_head: SpinLock::new(0),
+            dead: AtomicBool::new(false),
+            started: AtomicBool::new(false),
+            tag_wait: WaitQueue::default(),
+            io_wait: WaitQueue::default(),
+            daemon_wait: WaitQueue::default(),
+            epitems: EPollItems::new(),
+            inner: SpinLock::new(InnerUblkDevice {
+                device_common: DeviceCommonData::default(),
+                kobject_common: KObjectCommonData::default(),
+            }),
+            locked_kobj_state: LockedKObjectState::default(),
+            self_ref: self_ref.clone(),
+        });
+
+        let header = dev.header_mut();
+        header.queue_depth = info.queue_depth;
+        header.max_io_bytes = info.max_io_bytes;
+        Ok(dev)
+    }
+
+    /// 生成设备名，如`ublka`、`ublkb`
+    ///
+    /// 不使用数字后缀，因为块设备管理器会把名字末尾的数字解析为分区号
+    fn format_name(id: usize) -> BlockDevName {
+        let x = (b'a' + id as u8) as char;
+        BlockDevName::new(format!("{}{}", UBLK_BASENAME, x), id)
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerUblkDevice> {
+        self.inner.lock()
+    }
+
+    fn header(&self) -> &UblkCtrlHeader {
+        unsafe { &*(self.region.vaddr.data() as *const UblkCtrlHeader) }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn header_mut(&self) -> &mut UblkCtrlHeader {
+        unsafe { &mut *(self.region.vaddr.data() as *mut UblkCtrlHeader) }
+    }
+
+    fn sq_entry(&self, index: u32) -> *mut u32 {
+        let slot = (index & (self.queue_depth - 1)) as usize;
+        (self.region.vaddr.data() + self.layout.sq_off + slot * core::mem::size_of::<u32>())
+            as *mut u32
+    }
+
+    fn cqe(&self, index: u32) -> *const UblkCqe {
+        let slot = (index & (self.queue_depth - 1)) as usize;
+        (self.region.vaddr.data() + self.layout.cq_off + slot * core::mem::size_of::<UblkCqe>())
+            as *const UblkCqe
+    }
+
+    fn desc(&self, tag: usize) -> *mut UblkIoDesc {
+        (self.region.vaddr.data() + self.layout.desc_off + tag * core::mem::size_of::<UblkIoDesc>())
+            as *mut UblkIoDesc
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn tag_buffer(&self, tag: usize, len: usize) -> &mut [u8] {
+        let start =
+            self.region.vaddr.data() + self.layout.data_off + tag * self.max_io_bytes as usize;
+        unsafe { core::slice::from_raw_parts_mut(start as *mut u8, len) }
+    }
+
+    fn daemon_exited(&self) -> bool {
+        self.dead.load(Ordering::SeqCst)
+    }
+
+    /// 请求队列中还有守护进程没有取走的请求
+    fn has_pending(&self) -> bool {
+        *self.sq_tail.lock_irqsave() != self.header().sq_head.load(Ordering::Acquire)
+    }
+
+    fn poll_events(&self) -> EPollEventType {
+        let mut events = EPollEventType::empty();
+        if self.has_pending() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        events
+    }
+
+    fn wake_daemon(&self) {
+        self.daemon_wait.wakeup_all(None);
+        self.epitems.wakeup(Some(self.poll_events())).ok();
+    }
+
+    fn try_get_tag(&self) -> Option<usize> {
+        let mut free_tags = self.free_tags.lock_irqsave();
+        if *free_tags == 0 {
+            return None;
+        }
+        let tag = free_tags.trailing_zeros() as usize;
+        *free_tags &= !(1 << tag);
+        Some(tag)
+    }
+
+    fn put_tag(&self, tag: usize) {
+        self.tag_state[tag].store(TAG_FREE, Ordering::SeqCst);
+        *self.free_tags.lock_irqsave() |= 1 << tag;
+        self.tag_wait.wakeup_all(None);
+    }
+
+    /// 把一个请求交给守护进程，并等待它完成
+    ///
+    /// 块设备的读写不能被信号打断，因此这里的睡眠都是不可中断的；
+    /// 守护进程退出时，所有未完成的请求都会以EIO结束。
+    fn queue_rq(
+        &self,
+        op: u8,
+        start_sector: u64,
+        nr_sectors: u32,
+        write_buf: Option<&[u8]>,
+        read_buf: Option<&mut [u8]>,
+    ) -> Result<(), SystemError> {
+        let mut tag = None;
+        wq_wait_event_uninterruptible!(
+            self.tag_wait,
+            self.daemon_exited() || {
+                tag = self.try_get_tag();
+                tag.is_some()
+            },
+            {}
+        );
+        let Some(tag) = tag else {
+            return Err(SystemError::EIO);
+        };
+
+        let len = nr_sectors as usize * LBA_SIZE;
+        if let Some(buf) = write_buf {
+            self.tag_buffer(tag, len).copy_from_slice(&buf[..len]);
+        }
+
+        {
+            let mut sq_tail = self.sq_tail.lock_irqsave();
+            // 与守护进程退出时的清理互斥：要么请求在清理前入队并被清理，要么在这里看到dead
+            if self.daemon_exited() {
+                drop(sq_tail);
+                self.put_tag(tag);
+                return Err(SystemError::EIO);
+            }
+            unsafe {
+                self.desc(tag).write_volatile(UblkIoDesc {
+                    op,
+                    resv: [0; 3],
+                    nr_sectors,
+                    start_sector,
+                });
+                self.sq_entry(*sq_tail).write_volatile(tag as u32);
+            }
+            self.tag_state[tag].store(TAG_INFLIGHT, Ordering::SeqCst);
+            *sq_tail = sq_tail.wrapping_add(1);
+            self.header().sq_tail.store(*sq_tail, Ordering::Release);
+        }
+        self.wake_daemon();
+
+        wq_wait_event_uninterruptible!(
+            self.io_wait,
+            self.tag_state[tag].load(Ordering::SeqCst) == TAG_DONE,
+            {}
+        );
+
+        let result = self.tag_result[tag].load(Ordering::SeqCst);
+        let r = if result < 0 || (op != UBLK_IO_OP_FLUSH && result as usize != len) {
+            Err(SystemError::EIO)
+        } else {
+            if let Some(buf) = read_buf {
+                buf[..len].copy_from_slice(self.tag_buffer(tag, len));
+            }
+            Ok(())
+        };
+        self.put_tag(tag);
+        r
+    }
+
+    /// 把请求拆分成不超过max_io_bytes的若干个请求
+    fn do_rw(
+        &self,
+        op: u8,
+        lba_id_start: BlockId,
+        count: usize,
+        write_buf: Option<&[u8]>,
+        mut read_buf: Option<&mut [u8]>,
+    ) -> Result<usize, SystemError> {
+        if (lba_id_start + count) as u64 > self.nr_sectors {
+            return Err(SystemError::EINVAL);
+        }
+        let max_sectors = self.max_io_bytes as usize / LBA_SIZE;
+        let mut done = 0;
+        while done < count {
+            let n = (count - done).min(max_sectors);
+            let range = done * LBA_SIZE..(done + n) * LBA_SIZE;
+            self.queue_rq(
+                op,
+                (lba_id_start + done) as u64,
+                n as u32,
+                write_buf.map(|buf| &buf[range.clone()]),
+                read_buf.as_deref_mut().map(|buf| &mut buf[range]),
+            )?;
+            done += n;
+        }
+        Ok(count)
+    }
+
+    /// 门铃：收割完成队列中的结果
+    fn commit(&self) -> Result<usize, SystemError> {
+        let mut cq_head = self.cq_head.lock_irqsave();
+        let cq_tail = self.header().cq_tail.load(Ordering::Acquire);
+        if cq_tail.wrapping_sub(*cq_head) > self.queue_depth {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut reaped = 0;
+        while *cq_head != cq_tail {
+            let cqe = unsafe { self.cqe(*cq_head).read_volatile() };
+            *cq_head = cq_head.wrapping_add(1);
+
+            let tag = cqe.tag as usize;
+            // 忽略守护进程写入的无效tag，不能让它影响其他请求
+            if tag >= self.queue_depth as usize
+                || self.tag_state[tag].load(Ordering::SeqCst) != TAG_INFLIGHT
+            {
+                continue;
+            }
+            self.tag_result[tag].store(cqe.result, Ordering::SeqCst);
+            self.tag_state[tag].store(TAG_DONE, Ordering::SeqCst);
+            reaped += 1;
+        }
+        self.header().cq_head.store(*cq_head, Ordering::Release);
+        drop(cq_head);
+
+        if reaped > 0 {
+            self.io_wait.wakeup_all(None);
+        }
+        Ok(reaped)
+    }
+
+    /// 等待新的请求，返回待处理的请求数
+    ///
+    /// 不希望阻塞的守护进程可以改用poll/epoll
+    fn wait(&self) -> Result<usize, SystemError> {
+        loop {
+            let pending = self.pending_count();
+            if pending > 0 {
+                return Ok(pending);
+            }
+            wq_wait_event_interruptible!(self.daemon_wait, self.has_pending(), {})?;
+        }
+    }
+
+    fn pending_count(&self) -> usize {
+        let sq_tail = *self.sq_tail.lock_irqsave();
+        let pending = sq_tail.wrapping_sub(self.header().sq_head.load(Ordering::Acquire));
+        // sq_head由用户态维护，不可信
+        pending.min(self.queue_depth) as usize
+    }
+
+    /// 把设备注册到块设备管理器
+    ///
+    /// 注册时会读取分区表，而分区表只能由守护进程提供。
+    /// 为了让单线程的守护进程也能使用，注册在内核线程中异步进行。
+    fn start(&self) -> Result<(), SystemError> {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return Err(SystemError::EBUSY);
+        }
+        let dev = self.self_ref.upgrade().unwrap();
+        let closure = KernelThreadClosure::EmptyClosure((
+            Box::new(move || {
+                if let Err(e) = block_dev_manager().register(dev.clone() as Arc<dyn BlockDevice>) {
+                    warn!("ublk: failed to register {}: {:?}", dev.dev_name(), e);
+                }
+                0
+            }),
+            (),
+        ));
+        KernelThreadMechanism::create_and_run(closure, format!("ublk_start_{}", self.id))
+            .ok_or(SystemError::ENOMEM)
+            .inspect_err(|_| self.started.store(false, Ordering::SeqCst))?;
+        Ok(())
+    }
+
+    /// 守护进程退出：以EIO结束所有未完成的请求
+    fn shutdown(&self) {
+        {
+            let _sq_tail = self.sq_tail.lock_irqsave();
+            self.dead.store(true, Ordering::SeqCst);
+        }
+        for tag in 0..self.queue_depth as usize {
+            if self.tag_state[tag]
+                .compare_exchange(TAG_INFLIGHT, TAG_DONE, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                self.tag_result[tag].store(SystemError::EIO.to_posix_errno(), Ordering::SeqCst);
+            }
+        }
+        self.io_wait.wakeup_all(None);
+        self.tag_wait.wakeup_all(None);
+
+        if self.started.load(Ordering::SeqCst) {
+            // TODO: 块设备管理器还不支持注销磁盘，设备会以“死盘”的形式保留，所有I/O返回EIO
+            warn!(
+                "ublk: daemon of {} exited, the disk stays registered but is dead",
+                self.dev_name()
+            );
+        } else {
+            UBLK_IDS.lock().set(self.id, false);
+        }
+    }
+}
+
+impl BlockDevice for UblkDevice {
+    fn dev_name(&self) -> &BlockDevName {
+        &self.blkdev_meta.devname
+    }
+
+    fn blkdev_meta(&self) -> &BlockDevMeta {
+        &self.blkdev_meta
+    }
+
+    fn disk_range(&self) -> GeneralBlockRange {
+        GeneralBlockRange::new(0, self.nr_sectors as usize).unwrap()
+    }
+
+    fn read_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        self.do_rw(UBLK_IO_OP_READ, lba_id_start, count, None, Some(buf))
+    }
+
+    fn write_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        self.do_rw(UBLK_IO_OP_WRITE, lba_id_start, count, Some(buf), None)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        self.queue_rq(UBLK_IO_OP_FLUSH, 0, 0, None, None)
+    }
+
+    fn blk_size_log2(&self) -> u8 {
+        9
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn device(&self) -> Arc<dyn Device> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    fn block_size(&self) -> usize {
+        LBA_SIZE
+    }
+
+    fn partitions(&self) -> Vec<Arc<Partition>> {
+        let device = self.self_ref.upgrade().unwrap() as Arc<dyn BlockDevice>;
+        MbrDiskPartionTable::from_disk(device.clone())
+            .map(|mbr_table| mbr_table.partitions(Arc::downgrade(&device)))
+            .unwrap_or_default()
+    }
+}
+
+impl Device for UblkDevice {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(UBLK_BASENAME.to_string(), None)
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        self.dead.load(Ordering::SeqCst)
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for UblkDevice {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.dev_name().to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
+
+/// 守护进程fd对应的inode
+#[derive(Debug)]
+pub struct UblkDaemonInode {
+    dev: Arc<UblkDevice>,
+    page_cache: Arc<PageCache>,
+    /// 打开了本inode的文件对象数
+    open_count: AtomicUsize,
+}
+
+impl UblkDaemonInode {
+    fn new(dev: Arc<UblkDevice>) -> Result<Arc<Self>, SystemError> {
+        let page_cache = PageCache::new(None);
+        let mut cache_guard = page_cache.lock_irqsave();
+        for (i, page) in dev.region.pages.iter().enumerate() {
+            cache_guard.add_page(i, page);
+        }
+        drop(cache_guard);
+
+        let inode = Arc::new(Self {
+            dev,
+            page_cache,
+            open_count: AtomicUsize::new(0),
+        });
+        inode
+            .page_cache
+            .set_inode(Arc::downgrade(&(inode.clone() as Arc<dyn IndexNode>)))?;
+        Ok(inode)
+    }
+}
+
+impl IndexNode for UblkDaemonInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        self.open_count.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        if self.open_count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.dev.shutdown();
+        }
+        Ok(())
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        _data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        match cmd {
+            UBLK_CMD_START_DEV => self.dev.start().map(|_| 0),
+            UBLK_CMD_WAIT => self.dev.wait(),
+            UBLK_CMD_COMMIT => self.dev.commit(),
+            _ => Err(SystemError::ENOSYS),
+        }
+    }
+
+    fn mmap(&self, _start: usize, len: usize, offset: usize) -> Result<(), SystemError> {
+        if offset != 0 || len > self.dev.region.len() {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        Ok(self.dev.poll_events().bits() as usize)
+    }
+
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.dev.epitems.add(epitem);
+        Ok(())
+    }
+
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.dev.epitems.remove(epoll)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            size: self.dev.region.len() as i64,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn resize(&self, _len: usize) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        Arc::new(UblkFakeFs)
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        Some(self.page_cache.clone())
+    }
+}
+
+/// 用于处理共享内存缺页的伪文件系统
+#[derive(Debug)]
+struct UblkFakeFs;
+
+impl FileSystem for UblkFakeFs {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        panic!("UblkFakeFs does not have a root inode")
+    }
+
+    fn info(&self) -> FsInfo {
+        panic!("UblkFakeFs does not have a filesystem info")
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "ublk"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        panic!("UblkFakeFs does not have a super block")
+    }
+
+    unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
+        PageFaultHandler::filemap_fault(pfm)
+    }
+
+    unsafe fn map_pages(
+        &self,
+        pfm: &mut PageFaultMessage,
+        start_pgoff: usize,
+        end_pgoff: usize,
+    ) -> VmFaultReason {
+        PageFaultHandler::filemap_map_pages(pfm, start_pgoff, end_pgoff)
+    }
+}
+
+/// /dev/ublk-control
+#[derive(Debug)]
+pub struct UblkControlInode {
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl UblkControlInode {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            fs: SpinLock::new(Weak::default()),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: PosixTimeSpec::default(),
+                mtime: PosixTimeSpec::default(),
+                ctime: PosixTimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o600),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::default(),
+            },
+        })
+    }
+
+    /// 创建一个ublk设备，返回守护进程fd
+    fn add_dev(&self, arg: usize) -> Result<usize, SystemError> {
+        let mut info = *UserBufferReader::new(
+            arg as *const UblkDevInfo,
+            core::mem::size_of::<UblkDevInfo>(),
+            true,
+        )?
+        .read_one_from_user::<UblkDevInfo>(0)?;
+
+        if info.nr_sectors == 0
+            || info.queue_depth == 0
+            || info.queue_depth > UBLK_MAX_QUEUE_DEPTH
+            || info.max_io_bytes > UBLK_MAX_IO_BYTES
+            || info.resv != [0; 2]
+        {
+            return Err(SystemError::EINVAL);
+        }
+        info.queue_depth = info.queue_depth.next_power_of_two();
+        info.max_io_bytes = match info.max_io_bytes {
+            0 => UBLK_DEFAULT_IO_BYTES,
+            n => n.next_multiple_of(LBA_SIZE as u32),
+        };
+
+        let id = {
+            let mut ids = UBLK_IDS.lock();
+            let id = ids.first_false_index().ok_or(SystemError::ENOSPC)?;
+            ids.set(id, true);
+            id
+        };
+        let dev = UblkDevice::new(id, &info).inspect_err(|_| UBLK_IDS.lock().set(id, false))?;
+        // 此后设备的编号由UblkDevice::shutdown释放
+        let inode = UblkDaemonInode::new(dev.clone())?;
+        let file = File::new(inode, FileMode::O_RDWR | FileMode::O_CLOEXEC)?;
+
+        info.dev_id = id as u32;
+        info.region_size = dev.region.len() as u32;
+        info.sq_off = dev.layout.sq_off as u32;
+        info.cq_off = dev.layout.cq_off as u32;
+        info.desc_off = dev.layout.desc_off as u32;
+        info.data_off = dev.layout.data_off as u32;
+
+        let mut writer = UserBufferWriter::new(
+            arg as *mut UblkDevInfo,
+            core::mem::size_of::<UblkDevInfo>(),
+            true,
+        )?;
+        writer.copy_one_to_user(&info, 0)?;
+
+        let fd = ProcessManager::current_pcb()
+            .fd_table()
+            .write()
+            .alloc_fd(file, None)?;
+        Ok(fd as usize)
+    }
+}
+
+impl DeviceINode for UblkControlInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for UblkControlInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        _private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        match cmd {
+            UBLK_CTRL_ADD_DEV => self.add_dev(data),
+            _ => Err(SystemError::ENOSYS),
+        }
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.lock().upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+}
+
+#[unified_init(INITCALL_DEVICE)]
+fn ublk_init() -> Result<(), SystemError> {
+    devfs_register(UBLK_CONTROL_NAME, UblkControlInode::new())
+}
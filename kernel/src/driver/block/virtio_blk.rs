@@ -10,7 +10,6 @@ use bitmap::traits::BitMapOps;
 use log::error;
 use system_error::SystemError;
 use unified_init::macros::unified_init;
-use virtio_drivers::device::blk::{VirtIOBlk, SECTOR_SIZE};
 
 use crate::{
     driver::{
@@ -32,13 +31,12 @@ use crate::{
         virtio::{
             sysfs::{virtio_bus, virtio_device_manager, virtio_driver_manager},
             transport::VirtIOTransport,
-            virtio_impl::HalImpl,
             VirtIODevice, VirtIODeviceIndex, VirtIODriver, VirtIODriverCommonData, VirtioDeviceId,
             VIRTIO_VENDOR_ID,
         },
     },
     exception::{irqdesc::IrqReturn, IrqNumber},
-    filesystem::{kernfs::KernFSInode, mbr::MbrDiskPartionTable},
+    filesystem::{gpt::GptDiskPartitionTable, kernfs::KernFSInode, mbr::MbrDiskPartionTable},
     init::initcall::INITCALL_POSTCORE,
     libs::{
         rwlock::{RwLockReadGuard, RwLockWriteGuard},
@@ -46,6 +44,8 @@ use crate::{
     },
 };
 
+use super::virtio_blk_queue::{VirtIOBlkQueue, SECTOR_SIZE};
+
 const VIRTIO_BLK_BASENAME: &str = "virtio_blk";
 
 static mut VIRTIO_BLK_DRIVER: Option<Arc<VirtIOBlkDriver>> = None;
@@ -172,14 +172,13 @@ impl VirtIOBlkDevice {
 
         let devname = virtioblk_manager().alloc_id()?;
         let irq = Some(transport.irq());
-        let device_inner = VirtIOBlk::<HalImpl, VirtIOTransport>::new(transport);
+        let device_inner = VirtIOBlkQueue::new(transport);
         if let Err(e) = device_inner {
             error!("VirtIOBlkDevice '{dev_id:?}' create failed: {:?}", e);
             return None;
         }
 
-        let mut device_inner: VirtIOBlk<HalImpl, VirtIOTransport> = device_inner.unwrap();
-        device_inner.enable_interrupts();
+        let device_inner = device_inner.unwrap();
         let dev = Arc::new_cyclic(|self_ref| Self {
             blkdev_meta: BlockDevMeta::new(devname),
             self_ref: self_ref.clone(),
@@ -230,6 +229,7 @@ impl BlockDevice for VirtIOBlkDevice {
         count: usize,
         buf: &mut [u8],
     ) -> Result<usize, SystemError> {
+        self.check_alive()?;
         let mut inner = self.inner();
 
         inner
@@ -252,17 +252,69 @@ impl BlockDevice for VirtIOBlkDevice {
         count: usize,
         buf: &[u8],
     ) -> Result<usize, SystemError> {
+        self.check_alive()?;
         self.inner()
             .device_inner
-            .write_blocks(lba_id_start, &buf[..count * LBA_SIZE])
-            .map_err(|_| SystemError::EIO)?;
+            .write_blocks(lba_id_start, &buf[..count * LBA_SIZE])?;
         Ok(count)
     }
 
-    fn sync(&self) -> Result<(), SystemError> {
+    fn discard(&self, lba_id_start: BlockId, count: usize) -> Result<(), SystemError> {
+        self.check_alive()?;
+        let max_sectors = {
+            let inner = self.inner();
+            if !inner.device_inner.support_discard() {
+                return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+            }
+            inner.device_inner.max_discard_sectors()
+        };
+        // 每个请求不超过设备允许的扇区数，请求之间释放锁，不让其他读写等待太久
+        let mut done = 0;
+        while done < count {
+            let n = (count - done).min(max_sectors);
+            self.inner().device_inner.discard(lba_id_start + done, n)?;
+            done += n;
+        }
         Ok(())
     }
 
+    fn write_zeroes(&self, lba_id_start: BlockId, count: usize) -> Result<(), SystemError> {
+        self.check_alive()?;
+        let max_sectors = {
+            let inner = self.inner();
+            inner
+                .device_inner
+                .support_write_zeroes()
+                .then(|| inner.device_inner.max_write_zeroes_sectors())
+        };
+        let Some(max_sectors) = max_sectors else {
+            // 设备不支持清零请求，逐批写入全0的块
+            const BATCH_BLOCKS: usize = 64;
+            let zeroes = vec![0u8; BATCH_BLOCKS * LBA_SIZE];
+            let mut done = 0;
+            while done < count {
+                let n = (count - done).min(BATCH_BLOCKS);
+                self.write_at_sync(lba_id_start + done, n, &zeroes[..n * LBA_SIZE])?;
+                done += n;
+            }
+            return Ok(());
+        };
+
+        let mut done = 0;
+        while done < count {
+            let n = (count - done).min(max_sectors);
+            self.inner()
+                .device_inner
+                .write_zeroes(lba_id_start + done, n)?;
+            done += n;
+        }
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        self.check_alive()
+    }
+
     fn blk_size_log2(&self) -> u8 {
         9
     }
@@ -281,14 +333,17 @@ impl BlockDevice for VirtIOBlkDevice {
 
     fn partitions(&self) -> Vec<Arc<Partition>> {
         let device = self.self_ref.upgrade().unwrap() as Arc<dyn BlockDevice>;
-        let mbr_table = MbrDiskPartionTable::from_disk(device.clone())
-            .expect("Failed to get MBR partition table");
-        mbr_table.partitions(Arc::downgrade(&device))
+        if let Ok(gpt_table) = GptDiskPartitionTable::from_disk(device.clone()) {
+            return gpt_table.partitions(Arc::downgrade(&device));
+        }
+        MbrDiskPartionTable::from_disk(device.clone())
+            .map(|mbr_table| mbr_table.partitions(Arc::downgrade(&device)))
+            .unwrap_or_default()
     }
 }
 
 struct InnerVirtIOBlkDevice {
-    device_inner: VirtIOBlk<HalImpl, VirtIOTransport>,
+    device_inner: VirtIOBlkQueue,
     name: Option<String>,
     virtio_index: Option<VirtIODeviceIndex>,
     device_common: DeviceCommonData,
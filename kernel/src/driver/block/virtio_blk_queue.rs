@@ -0,0 +1,499 @@
+//! virtio块设备的请求队列
+//!
+//! virtio-drivers中的`VirtIOBlk`只协商固定的几个特性，也没有提供提交其他类型请求的接口，
+//! 因此这里直接在[`Transport`]上实现一个分离式（split）virtqueue，
+//! 协商`VIRTIO_BLK_F_DISCARD`与`VIRTIO_BLK_F_WRITE_ZEROES`，并支持对应的请求类型。
+//!
+//! 请求是同步执行的：调用者持有设备的锁，提交一条描述符链之后轮询已用环，
+//! 因此队列中同一时刻只有一个请求，固定使用前三个描述符。
+
+use core::{
+    hint::spin_loop,
+    mem::size_of,
+    ptr::{addr_of, addr_of_mut, NonNull},
+    sync::atomic::{fence, Ordering},
+};
+
+use log::{error, info};
+use system_error::SystemError;
+use virtio_drivers::{
+    transport::{DeviceStatus, Transport},
+    BufferDirection, Hal, PAGE_SIZE,
+};
+
+use crate::driver::virtio::{transport::VirtIOTransport, virtio_impl::HalImpl};
+
+/// virtio块设备的扇区大小，与块设备的LBA大小无关，总是512字节
+pub const SECTOR_SIZE: usize = 512;
+
+/// 设备的特性位
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+const VIRTIO_BLK_F_DISCARD: u64 = 1 << 13;
+const VIRTIO_BLK_F_WRITE_ZEROES: u64 = 1 << 14;
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// 驱动支持的特性
+const SUPPORTED_FEATURES: u64 = VIRTIO_BLK_F_RO
+    | VIRTIO_BLK_F_FLUSH
+    | VIRTIO_BLK_F_DISCARD
+    | VIRTIO_BLK_F_WRITE_ZEROES
+    | VIRTIO_F_VERSION_1;
+
+/// 请求类型
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+const VIRTIO_BLK_T_DISCARD: u32 = 11;
+const VIRTIO_BLK_T_WRITE_ZEROES: u32 = 13;
+
+/// 请求的完成状态
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+/// 描述符的标志
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// 队列的长度。请求是同步执行的，只会用到前三个描述符
+const QUEUE_SIZE: u16 = 4;
+/// 使用的virtqueue编号
+const QUEUE_IDX: u16 = 0;
+
+/// 设备的配置空间（virtio规范5.2.4节）
+#[repr(C)]
+#[allow(dead_code)]
+struct VirtIOBlkConfig {
+    /// 容量（以512字节扇区为单位）的低32位与高32位，分开读取以兼容只支持32位访问的传输层
+    capacity_low: u32,
+    capacity_high: u32,
+    size_max: u32,
+    seg_max: u32,
+    geometry: u32,
+    blk_size: u32,
+    topology: [u32; 2],
+    writeback: u8,
+    unused0: u8,
+    num_queues: u16,
+    max_discard_sectors: u32,
+    max_discard_seg: u32,
+    discard_sector_alignment: u32,
+    max_write_zeroes_sectors: u32,
+    max_write_zeroes_seg: u32,
+    write_zeroes_may_unmap: u8,
+    unused1: [u8; 3],
+}
+
+/// 请求头，由设备读取
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct VirtIOBlkReqHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// 丢弃与清零请求的数据段，描述一段扇区
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct VirtIOBlkDiscardWriteZeroes {
+    sector: u64,
+    num_sectors: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct VirtqDesc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct VirtqAvail {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE as usize],
+    used_event: u16,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct VirtqUsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+struct VirtqUsed {
+    flags: u16,
+    idx: u16,
+    ring: [VirtqUsedElem; QUEUE_SIZE as usize],
+    avail_event: u16,
+}
+
+/// 请求头、数据段与状态字节所在的DMA页的布局
+#[repr(C)]
+struct VirtIOBlkReqArea {
+    header: VirtIOBlkReqHeader,
+    range: VirtIOBlkDiscardWriteZeroes,
+    status: u8,
+}
+
+/// 请求的数据段
+enum ReqData<'a> {
+    None,
+    /// 设备读取的数据
+    Out(&'a [u8]),
+    /// 设备写入的数据
+    In(&'a mut [u8]),
+    /// 丢弃或者清零的范围，放在请求区里
+    Range,
+}
+
+/// virtio块设备的请求队列
+pub struct VirtIOBlkQueue {
+    transport: VirtIOTransport,
+    /// 协商后的特性
+    features: u64,
+    /// 容量（以512字节扇区为单位）
+    capacity: u64,
+    /// 一个丢弃请求最多包含的扇区数
+    max_discard_sectors: u32,
+    /// 一个清零请求最多包含的扇区数
+    max_write_zeroes_sectors: u32,
+    /// 描述符表、可用环与已用环所在的DMA内存
+    ring_paddr: usize,
+    ring_vaddr: NonNull<u8>,
+    ring_pages: usize,
+    /// 请求头、数据段与状态字节所在的DMA内存
+    req_paddr: usize,
+    req_vaddr: NonNull<VirtIOBlkReqArea>,
+    /// 下一个要放入可用环的位置
+    avail_idx: u16,
+    /// 已经处理过的已用环位置
+    last_used_idx: u16,
+}
+
+unsafe impl Send for VirtIOBlkQueue {}
+unsafe impl Sync for VirtIOBlkQueue {}
+
+impl VirtIOBlkQueue {
+    /// 初始化设备：协商特性、读取配置空间并设置virtqueue
+    pub fn new(mut transport: VirtIOTransport) -> Result<Self, SystemError> {
+        transport.set_status(DeviceStatus::empty());
+        transport.set_status(DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER);
+        let features = transport.read_device_features() & SUPPORTED_FEATURES;
+        transport.write_driver_features(features);
+        transport.set_status(
+            DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER | DeviceStatus::FEATURES_OK,
+        );
+        if !transport.get_status().contains(DeviceStatus::FEATURES_OK) {
+            transport.set_status(DeviceStatus::FAILED);
+            return Err(SystemError::ENODEV);
+        }
+        transport.set_guest_page_size(PAGE_SIZE as u32);
+
+        let config = transport.config_space::<VirtIOBlkConfig>().map_err(|e| {
+            error!("virtio_blk: failed to map config space: {:?}", e);
+            SystemError::ENODEV
+        })?;
+        // 安全性：配置空间由传输层映射，只进行按字段的volatile读取
+        let (capacity, max_discard_sectors, max_write_zeroes_sectors) = unsafe {
+            let config = config.as_ptr();
+            let low = addr_of!((*config).capacity_low).read_volatile() as u64;
+            let high = addr_of!((*config).capacity_high).read_volatile() as u64;
+            (
+                (high << 32) | low,
+                addr_of!((*config).max_discard_sectors).read_volatile(),
+                addr_of!((*config).max_write_zeroes_sectors).read_volatile(),
+            )
+        };
+
+        if transport.max_queue_size(QUEUE_IDX) < QUEUE_SIZE as u32
+            || transport.queue_used(QUEUE_IDX)
+        {
+            transport.set_status(DeviceStatus::FAILED);
+            return Err(SystemError::ENODEV);
+        }
+
+        let (driver_offset, device_offset) = Self::ring_layout();
+        let ring_pages = (device_offset + size_of::<VirtqUsed>()).div_ceil(PAGE_SIZE);
+        let (ring_paddr, ring_vaddr) = HalImpl::dma_alloc(ring_pages, BufferDirection::Both);
+        let (req_paddr, req_vaddr) = HalImpl::dma_alloc(1, BufferDirection::Both);
+        transport.queue_set(
+            QUEUE_IDX,
+            QUEUE_SIZE as u32,
+            ring_paddr,
+            ring_paddr + driver_offset,
+            ring_paddr + device_offset,
+        );
+        transport.finish_init();
+
+        info!(
+            "virtio_blk: capacity {} sectors, features {:#x}",
+            capacity, features
+        );
+
+        Ok(Self {
+            transport,
+            features,
+            capacity,
+            max_discard_sectors,
+            max_write_zeroes_sectors,
+            ring_paddr,
+            ring_vaddr,
+            ring_pages,
+            req_paddr,
+            req_vaddr: req_vaddr.cast(),
+            avail_idx: 0,
+            last_used_idx: 0,
+        })
+    }
+
+    /// 可用环与已用环在队列内存中的偏移。按照旧版传输层要求的布局：
+    /// 描述符表与可用环在前，已用环从下一页开始
+    fn ring_layout() -> (usize, usize) {
+        let driver_offset = size_of::<VirtqDesc>() * QUEUE_SIZE as usize;
+        let device_offset = (driver_offset + size_of::<VirtqAvail>()).next_multiple_of(PAGE_SIZE);
+        (driver_offset, device_offset)
+    }
+
+    /// 容量（以512字节扇区为单位）
+    #[inline]
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    #[inline]
+    pub fn readonly(&self) -> bool {
+        self.features & VIRTIO_BLK_F_RO != 0
+    }
+
+    #[inline]
+    pub fn support_discard(&self) -> bool {
+        self.features & VIRTIO_BLK_F_DISCARD != 0
+    }
+
+    #[inline]
+    pub fn support_write_zeroes(&self) -> bool {
+        self.features & VIRTIO_BLK_F_WRITE_ZEROES != 0
+    }
+
+    /// 一个丢弃请求最多包含的扇区数
+    #[inline]
+    pub fn max_discard_sectors(&self) -> usize {
+        self.max_discard_sectors.max(1) as usize
+    }
+
+    /// 一个清零请求最多包含的扇区数
+    #[inline]
+    pub fn max_write_zeroes_sectors(&self) -> usize {
+        self.max_write_zeroes_sectors.max(1) as usize
+    }
+
+    /// 从`sector`开始读取，`buf`的长度为扇区大小的整数倍
+    pub fn read_blocks(&mut self, sector: usize, buf: &mut [u8]) -> Result<(), SystemError> {
+        self.request(VIRTIO_BLK_T_IN, sector as u64, ReqData::In(buf))
+    }
+
+    /// 从`sector`开始写入，`buf`的长度为扇区大小的整数倍
+    pub fn write_blocks(&mut self, sector: usize, buf: &[u8]) -> Result<(), SystemError> {
+        if self.readonly() {
+            return Err(SystemError::EROFS);
+        }
+        self.request(VIRTIO_BLK_T_OUT, sector as u64, ReqData::Out(buf))
+    }
+
+    /// 把设备的写缓存刷到存储介质上，设备没有写缓存时什么都不做
+    pub fn flush(&mut self) -> Result<(), SystemError> {
+        if self.features & VIRTIO_BLK_F_FLUSH == 0 {
+            return Ok(());
+        }
+        self.request(VIRTIO_BLK_T_FLUSH, 0, ReqData::None)
+    }
+
+    /// 丢弃从`sector`开始的`count`个扇区，`count`不能超过[`Self::max_discard_sectors`]
+    pub fn discard(&mut self, sector: usize, count: usize) -> Result<(), SystemError> {
+        if !self.support_discard() {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        self.range_request(VIRTIO_BLK_T_DISCARD, sector, count)
+    }
+
+    /// 将从`sector`开始的`count`个扇区清零，`count`不能超过[`Self::max_write_zeroes_sectors`]
+    pub fn write_zeroes(&mut self, sector: usize, count: usize) -> Result<(), SystemError> {
+        if !self.support_write_zeroes() {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        self.range_request(VIRTIO_BLK_T_WRITE_ZEROES, sector, count)
+    }
+
+    fn range_request(
+        &mut self,
+        req_type: u32,
+        sector: usize,
+        count: usize,
+    ) -> Result<(), SystemError> {
+        if self.readonly() {
+            return Err(SystemError::EROFS);
+        }
+        // 安全性：请求区只在持有队列的可变引用时访问，此时设备没有在处理请求
+        unsafe {
+            addr_of_mut!((*self.req_vaddr.as_ptr()).range).write_volatile(
+                VirtIOBlkDiscardWriteZeroes {
+                    sector: sector as u64,
+                    num_sectors: count as u32,
+                    flags: 0,
+                },
+            );
+        }
+        self.request(req_type, 0, ReqData::Range)
+    }
+
+    /// 提交一个请求并等待设备完成
+    fn request(
+        &mut self,
+        req_type: u32,
+        sector: u64,
+        data: ReqData<'_>,
+    ) -> Result<(), SystemError> {
+        let req = self.req_vaddr.as_ptr();
+        // 安全性：同上
+        unsafe {
+            addr_of_mut!((*req).header).write_volatile(VirtIOBlkReqHeader {
+                req_type,
+                reserved: 0,
+                sector,
+            });
+            addr_of_mut!((*req).status).write_volatile(u8::MAX);
+        }
+
+        let header_paddr = self.req_paddr + core::mem::offset_of!(VirtIOBlkReqArea, header);
+        let range_paddr = self.req_paddr + core::mem::offset_of!(VirtIOBlkReqArea, range);
+        let status_paddr = self.req_paddr + core::mem::offset_of!(VirtIOBlkReqArea, status);
+
+        let mut descs = [VirtqDesc::default(); 3];
+        let mut n = 0;
+        descs[n] = VirtqDesc {
+            addr: header_paddr as u64,
+            len: size_of::<VirtIOBlkReqHeader>() as u32,
+            flags: 0,
+            next: 0,
+        };
+        n += 1;
+        // 数据段的物理地址，在设备完成请求后停止共享
+        let mut shared = None;
+        match data {
+            ReqData::None => {}
+            ReqData::Out(buf) => {
+                let buf = NonNull::from(buf);
+                // 安全性：缓冲区在请求完成之前一直被借用
+                let paddr = unsafe { HalImpl::share(buf, BufferDirection::DriverToDevice) };
+                shared = Some((paddr, buf, BufferDirection::DriverToDevice));
+                descs[n] = VirtqDesc {
+                    addr: paddr as u64,
+                    len: buf.len() as u32,
+                    flags: 0,
+                    next: 0,
+                };
+                n += 1;
+            }
+            ReqData::In(buf) => {
+                let buf = NonNull::from(buf);
+                // 安全性：同上
+                let paddr = unsafe { HalImpl::share(buf, BufferDirection::DeviceToDriver) };
+                shared = Some((paddr, buf, BufferDirection::DeviceToDriver));
+                descs[n] = VirtqDesc {
+                    addr: paddr as u64,
+                    len: buf.len() as u32,
+                    flags: VIRTQ_DESC_F_WRITE,
+                    next: 0,
+                };
+                n += 1;
+            }
+            ReqData::Range => {
+                descs[n] = VirtqDesc {
+                    addr: range_paddr as u64,
+                    len: size_of::<VirtIOBlkDiscardWriteZeroes>() as u32,
+                    flags: 0,
+                    next: 0,
+                };
+                n += 1;
+            }
+        }
+        descs[n] = VirtqDesc {
+            addr: status_paddr as u64,
+            len: 1,
+            flags: VIRTQ_DESC_F_WRITE,
+            next: 0,
+        };
+        n += 1;
+        for (i, desc) in descs[..n - 1].iter_mut().enumerate() {
+            desc.flags |= VIRTQ_DESC_F_NEXT;
+            desc.next = i as u16 + 1;
+        }
+
+        self.submit_and_wait(&descs[..n]);
+
+        if let Some((paddr, buf, direction)) = shared {
+            // 安全性：设备已经完成请求，不会再访问缓冲区
+            unsafe { HalImpl::unshare(paddr, buf, direction) };
+        }
+
+        // 安全性：同上
+        let status = unsafe { addr_of!((*req).status).read_volatile() };
+        match status {
+            VIRTIO_BLK_S_OK => Ok(()),
+            VIRTIO_BLK_S_UNSUPP => Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+            _ => Err(SystemError::EIO),
+        }
+    }
+
+    /// 把描述符链放进队列，通知设备，然后轮询已用环直到设备处理完它
+    fn submit_and_wait(&mut self, chain: &[VirtqDesc]) {
+        let (driver_offset, device_offset) = Self::ring_layout();
+        let desc_table = self.ring_vaddr.as_ptr() as *mut VirtqDesc;
+        let avail = unsafe { self.ring_vaddr.as_ptr().add(driver_offset) } as *mut VirtqAvail;
+        let used = unsafe { self.ring_vaddr.as_ptr().add(device_offset) } as *const VirtqUsed;
+
+        // 安全性：这些内存在创建队列时分配，队列中没有其他请求，设备只会读取可用环中的描述符链
+        unsafe {
+            for (i, desc) in chain.iter().enumerate() {
+                desc_table.add(i).write_volatile(*desc);
+            }
+            let slot = (self.avail_idx % QUEUE_SIZE) as usize;
+            addr_of_mut!((*avail).ring[slot]).write_volatile(0);
+            // 描述符必须在更新可用环的索引之前对设备可见
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            addr_of_mut!((*avail).idx).write_volatile(self.avail_idx);
+            fence(Ordering::SeqCst);
+        }
+        self.transport.notify(QUEUE_IDX);
+
+        // 安全性：同上
+        unsafe {
+            while addr_of!((*used).idx).read_volatile() == self.last_used_idx {
+                spin_loop();
+            }
+            fence(Ordering::SeqCst);
+        }
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+    }
+}
+
+impl Drop for VirtIOBlkQueue {
+    fn drop(&mut self) {
+        self.transport.queue_unset(QUEUE_IDX);
+        // 安全性：队列已经从设备上解除，这些内存不再被访问
+        unsafe {
+            HalImpl::dma_dealloc(self.ring_paddr, self.ring_vaddr, self.ring_pages);
+            HalImpl::dma_dealloc(self.req_paddr, self.req_vaddr.cast(), 1);
+        }
+    }
+}
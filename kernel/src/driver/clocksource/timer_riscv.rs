@@ -1,6 +1,9 @@
 use core::sync::atomic::{compiler_fence, fence, Ordering};
 
-use alloc::{string::ToString, sync::Arc};
+use alloc::{
+    string::ToString,
+    sync::{Arc, Weak},
+};
 use bitmap::{traits::BitMapOps, StaticBitmap};
 use system_error::SystemError;
 
@@ -22,7 +25,11 @@ use crate::{
     mm::percpu::PerCpu,
     smp::core::smp_get_processor_id,
     time::{
-        clocksource::HZ, tick_common::tick_handle_periodic, timer::try_raise_timer_softirq,
+        clocksource::{
+            Clocksource, ClocksourceData, ClocksourceFlags, ClocksourceMask, CycleNum, HZ,
+        },
+        tick_common::tick_handle_periodic,
+        timer::try_raise_timer_softirq,
         TimeArch,
     },
 };
@@ -54,12 +61,22 @@ impl RiscVSbiTimer {
         unsafe { riscv::register::sie::set_stimer() };
     }
 
-    #[allow(dead_code)]
     fn disable() {
         unsafe { riscv::register::sie::clear_stimer() };
     }
 }
 
+/// 停止当前CPU的SBI定时器中断，用于CPU离线
+pub fn riscv_sbi_timer_stop_local() {
+    RiscVSbiTimer::disable();
+}
+
+/// 重新开启当前CPU的SBI定时器中断，用于CPU重新上线
+pub fn riscv_sbi_timer_start_local() {
+    sbi_rt::set_timer(CurrentTimeArch::get_cycles() as u64 + unsafe { INTERVAL_CNT } as u64);
+    RiscVSbiTimer::enable();
+}
+
 /// riscv 初始化本地调度时钟源
 #[inline(never)]
 pub fn riscv_sbi_timer_init_local() {
@@ -136,3 +153,70 @@ impl IrqFlowHandler for RiscvSbiTimerIrqFlowHandler {
         fence(Ordering::SeqCst)
     }
 }
+
+/// 以time寄存器为计数器的时钟源
+#[derive(Debug)]
+pub struct RiscVClocksource(SpinLock<InnerRiscVClocksource>);
+
+#[derive(Debug)]
+struct InnerRiscVClocksource {
+    data: ClocksourceData,
+    self_ref: Weak<RiscVClocksource>,
+}
+
+impl RiscVClocksource {
+    fn new() -> Arc<Self> {
+        let data = ClocksourceData {
+            name: "riscv_clocksource".to_string(),
+            rating: 300,
+            mask: ClocksourceMask::new(u64::MAX),
+            mult: 0,
+            shift: 0,
+            max_idle_ns: Default::default(),
+            flags: ClocksourceFlags::CLOCK_SOURCE_IS_CONTINUOUS,
+            watchdog_last: CycleNum::new(0),
+            cs_last: CycleNum::new(0),
+            uncertainty_margin: 0,
+            maxadj: 0,
+            cycle_last: CycleNum::new(0),
+        };
+        let cs = Arc::new(RiscVClocksource(SpinLock::new(InnerRiscVClocksource {
+            data,
+            self_ref: Default::default(),
+        })));
+        cs.0.lock().self_ref = Arc::downgrade(&cs);
+        return cs;
+    }
+}
+
+impl Clocksource for RiscVClocksource {
+    fn read(&self) -> CycleNum {
+        CycleNum::new(CurrentTimeArch::get_cycles() as u64)
+    }
+
+    fn enable(&self) -> Result<i32, SystemError> {
+        return Ok(0);
+    }
+
+    fn clocksource_data(&self) -> ClocksourceData {
+        self.0.lock_irqsave().data.clone()
+    }
+
+    fn clocksource(&self) -> Arc<dyn Clocksource> {
+        self.0.lock_irqsave().self_ref.upgrade().unwrap()
+    }
+
+    fn update_clocksource_data(&self, data: ClocksourceData) -> Result<(), SystemError> {
+        self.0.lock_irqsave().data = data;
+        return Ok(());
+    }
+}
+
+/// 注册time寄存器时钟源，需要在获取了timebase-frequency之后调用
+pub fn riscv_clocksource_init() -> Result<(), SystemError> {
+    let freq = riscv_time_base_freq();
+    if freq == 0 {
+        return Err(SystemError::ENODEV);
+    }
+    (RiscVClocksource::new() as Arc<dyn Clocksource>).register(1, freq as u32)
+}
@@ -1,2 +1,3 @@
 #[cfg(target_arch = "x86_64")]
 pub mod ahci;
+pub mod nvme;
@@ -0,0 +1,530 @@
+//! PCIe NVMe驱动
+//!
+//! 控制器初始化时建立管理队列，识别控制器与其上的所有活动命名空间，
+//! 然后为每个CPU（最多`NVME_MAX_IO_QUEUES`个）创建一对I/O队列。
+//! 所有完成队列共用MSI-X的第0个中断向量，中断处理函数取出完成队列的条目并唤醒等待的进程；
+//! 中断安装失败时退回到轮询模式。
+//!
+//! 每个命名空间被注册为一个块设备，名字形如`nvme0n1`，其分区为`nvme0n1p1`。
+//!
+//! 参考 NVM Express Base Specification 2.0 与
+//! https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/nvme/host/pci.c
+
+pub mod namespace;
+pub mod queue;
+
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use log::{info, warn};
+use system_error::SystemError;
+
+use crate::{
+    driver::{
+        base::{
+            block::{block_device::BlockDevice, manager::block_dev_manager},
+            device::DeviceId,
+        },
+        pci::{
+            pci::{
+                get_pci_device_structure_mut, PciDeviceStructure, PciDeviceStructureGeneralDevice,
+                PCI_DEVICE_LINKEDLIST,
+            },
+            pci_irq::{IrqCommonMsg, IrqSpecificMsg, PciInterrupt, PciIrqMsg, IRQ},
+        },
+    },
+    exception::{
+        irqdata::IrqHandlerData,
+        irqdesc::{IrqHandler, IrqReturn},
+        IrqNumber,
+    },
+    libs::{rwlock::RwLock, spinlock::SpinLock},
+    mm::VirtAddr,
+    smp::{core::smp_get_processor_id, cpu::smp_cpu_manager},
+    time::{Duration, Instant},
+};
+
+use self::{
+    namespace::NvmeNamespace,
+    queue::{NvmeCommand, NvmeQueue, NVME_CQ_ENTRY_SIZE, NVME_SQ_ENTRY_SIZE},
+};
+
+const NVME_CLASS: u8 = 0x1;
+const NVME_SUBCLASS: u8 = 0x8;
+const NVME_PROG_IF: u8 = 0x2;
+
+/// 控制器的内存页大小（CC.MPS = 0）
+pub const NVME_PAGE_SIZE: usize = 4096;
+/// 管理队列的深度
+const NVME_ADMIN_QUEUE_DEPTH: u16 = 32;
+/// I/O队列的深度
+const NVME_IO_QUEUE_DEPTH: u16 = 64;
+/// 每个I/O队列能同时执行的命令数
+const NVME_IO_QUEUE_SLOTS: usize = 8;
+/// 最多创建的I/O队列数
+const NVME_MAX_IO_QUEUES: usize = 4;
+/// 单个命令最多传输的字节数（受控制器的MDTS进一步限制）
+const NVME_MAX_TRANSFER: usize = 32 * 1024;
+
+/// 目前缺少对PCI设备中断号的统一管理，所以这里需要指定一个中断号。不能与其他中断重复
+const NVME_IRQ_VECTOR: IrqNumber = IrqNumber::new(58);
+
+// 控制器寄存器的偏移量
+const NVME_REG_CAP: usize = 0x00;
+const NVME_REG_VS: usize = 0x08;
+const NVME_REG_CC: usize = 0x14;
+const NVME_REG_CSTS: usize = 0x1c;
+const NVME_REG_AQA: usize = 0x24;
+const NVME_REG_ASQ: usize = 0x28;
+const NVME_REG_ACQ: usize = 0x30;
+const NVME_REG_DOORBELL: usize = 0x1000;
+
+const NVME_CC_ENABLE: u32 = 1 << 0;
+const NVME_CSTS_RDY: u32 = 1 << 0;
+const NVME_CSTS_CFS: u32 = 1 << 1;
+
+// 管理命令
+const NVME_ADMIN_CREATE_SQ: u8 = 0x01;
+const NVME_ADMIN_CREATE_CQ: u8 = 0x05;
+const NVME_ADMIN_IDENTIFY: u8 = 0x06;
+const NVME_ADMIN_SET_FEATURES: u8 = 0x09;
+
+const NVME_IDENTIFY_NAMESPACE: u32 = 0x00;
+const NVME_IDENTIFY_CONTROLLER: u32 = 0x01;
+const NVME_IDENTIFY_ACTIVE_NS_LIST: u32 = 0x02;
+
+const NVME_FEATURE_NUM_QUEUES: u32 = 0x07;
+
+// NVM命令
+pub const NVME_CMD_FLUSH: u8 = 0x00;
+pub const NVME_CMD_WRITE: u8 = 0x01;
+pub const NVME_CMD_READ: u8 = 0x02;
+pub const NVME_CMD_WRITE_ZEROES: u8 = 0x08;
+pub const NVME_CMD_DSM: u8 = 0x09;
+
+/// Identify Controller中ONCS字段的位：支持Dataset Management命令
+const NVME_ONCS_DSM: u16 = 1 << 2;
+/// Identify Controller中ONCS字段的位：支持Write Zeroes命令
+const NVME_ONCS_WRITE_ZEROES: u16 = 1 << 3;
+
+/// 已初始化的控制器
+static NVME_CONTROLLERS: SpinLock<Vec<Arc<NvmeController>>> = SpinLock::new(Vec::new());
+/// 下一个控制器的编号
+static NVME_NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// 控制器的寄存器（BAR0）
+#[derive(Debug, Clone, Copy)]
+pub struct NvmeRegs {
+    base: VirtAddr,
+    /// 门铃寄存器的间距（字节）
+    doorbell_stride: usize,
+}
+
+impl NvmeRegs {
+    #[inline]
+    fn read32(&self, offset: usize) -> u32 {
+        unsafe { ((self.base.data() + offset) as *const u32).read_volatile() }
+    }
+
+    #[inline]
+    fn write32(&self, offset: usize, value: u32) {
+        unsafe { ((self.base.data() + offset) as *mut u32).write_volatile(value) }
+    }
+
+    #[inline]
+    fn read64(&self, offset: usize) -> u64 {
+        self.read32(offset) as u64 | ((self.read32(offset + 4) as u64) << 32)
+    }
+
+    #[inline]
+    fn write64(&self, offset: usize, value: u64) {
+        self.write32(offset, value as u32);
+        self.write32(offset + 4, (value >> 32) as u32);
+    }
+
+    #[inline]
+    pub fn write_sq_doorbell(&self, qid: u16, tail: u16) {
+        self.write32(
+            NVME_REG_DOORBELL + (2 * qid as usize) * self.doorbell_stride,
+            tail as u32,
+        );
+    }
+
+    #[inline]
+    pub fn write_cq_doorbell(&self, qid: u16, head: u16) {
+        self.write32(
+            NVME_REG_DOORBELL + (2 * qid as usize + 1) * self.doorbell_stride,
+            head as u32,
+        );
+    }
+}
+
+/// NVMe控制器
+#[derive(Debug)]
+pub struct NvmeController {
+    id: usize,
+    dev_id: Arc<DeviceId>,
+    regs: NvmeRegs,
+    admin_queue: NvmeQueue,
+    io_queues: RwLock<Vec<Arc<NvmeQueue>>>,
+    /// 单个命令最多传输的字节数
+    max_transfer: usize,
+    /// 完成中断是否可用，不可用时轮询完成队列
+    irq_enabled: bool,
+    /// 可选的NVM命令（Identify Controller中的ONCS字段）
+    oncs: u16,
+}
+
+impl NvmeController {
+    fn new(device: &Arc<PciDeviceStructureGeneralDevice>) -> Result<Arc<Self>, SystemError> {
+        device
+            .bar_ioremap()
+            .ok_or(SystemError::ENODEV)?
+            .map_err(|_| SystemError::ENODEV)?;
+        device.enable_master();
+        let base = device
+            .bar()
+            .ok_or(SystemError::ENODEV)?
+            .read()
+            .get_bar(0)
+            .map_err(|_| SystemError::ENODEV)?
+            .virtual_address()
+            .ok_or(SystemError::ENODEV)?;
+
+        let mut regs = NvmeRegs {
+            base,
+            doorbell_stride: 4,
+        };
+        let cap = regs.read64(NVME_REG_CAP);
+        regs.doorbell_stride = 4 << ((cap >> 32) & 0xf);
+        // CAP.TO的单位为500毫秒
+        let timeout = Duration::from_millis(((cap >> 24) & 0xff).max(1) * 500);
+        let max_entries = (cap & 0xffff) as u16 + 1;
+        let vs = regs.read32(NVME_REG_VS);
+
+        let id = NVME_NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let dev_id = DeviceId::new(None, Some(format!("nvme{}", id))).unwrap();
+
+        // 关闭控制器，设置管理队列
+        regs.write32(NVME_REG_CC, 0);
+        Self::wait_ready(&regs, false, timeout)?;
+
+        let admin_depth = NVME_ADMIN_QUEUE_DEPTH.min(max_entries);
+        let admin_queue = NvmeQueue::new(regs, 0, admin_depth, 1, NVME_PAGE_SIZE);
+        regs.write32(
+            NVME_REG_AQA,
+            ((admin_depth as u32 - 1) << 16) | (admin_depth as u32 - 1),
+        );
+        regs.write64(NVME_REG_ASQ, admin_queue.sq_paddr() as u64);
+        regs.write64(NVME_REG_ACQ, admin_queue.cq_paddr() as u64);
+
+        // I/O提交队列条目为2^6字节，完成队列条目为2^4字节，使用NVM命令集，页大小4K
+        let cc = ((NVME_CQ_ENTRY_SIZE.trailing_zeros() << 20)
+            | (NVME_SQ_ENTRY_SIZE.trailing_zeros() << 16)) as u32
+            | NVME_CC_ENABLE;
+        regs.write32(NVME_REG_CC, cc);
+        Self::wait_ready(&regs, true, timeout)?;
+
+        let irq_enabled = Self::setup_irq(device, dev_id.clone())
+            .inspect_err(|e| {
+                warn!(
+                    "nvme{}: MSI-X setup failed ({:?}), fall back to polling",
+                    id, e
+                )
+            })
+            .is_ok();
+
+        let mut identify = vec![0u8; NVME_PAGE_SIZE];
+        let mut cmd = NvmeCommand::new(NVME_ADMIN_IDENTIFY, 0);
+        cmd.cdw10 = NVME_IDENTIFY_CONTROLLER;
+        admin_queue.execute(cmd, None, Some(&mut identify), true)?;
+        let serial = Self::identify_string(&identify[4..24]);
+        let model = Self::identify_string(&identify[24..64]);
+        // MDTS以最小页大小为单位，为0表示没有限制
+        let mdts = identify[77];
+        let max_transfer = if mdts == 0 {
+            NVME_MAX_TRANSFER
+        } else {
+            NVME_MAX_TRANSFER.min(NVME_PAGE_SIZE << mdts)
+        };
+        let oncs = u16::from_le_bytes(identify[520..522].try_into().unwrap());
+
+        info!(
+            "nvme{}: {} (serial {}), NVMe {}.{}",
+            id,
+            model,
+            serial,
+            vs >> 16,
+            (vs >> 8) & 0xff
+        );
+
+        let ctrl = Arc::new(Self {
+            id,
+            dev_id,
+            regs,
+            admin_queue,
+            io_queues: RwLock::new(Vec::new()),
+            max_transfer,
+            irq_enabled,
+            oncs,
+        });
+        ctrl.create_io_queues(max_entries)?;
+        Ok(ctrl)
+    }
+
+    fn wait_ready(regs: &NvmeRegs, ready: bool, timeout: Duration) -> Result<(), SystemError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let csts = regs.read32(NVME_REG_CSTS);
+            if csts & NVME_CSTS_CFS != 0 && ready {
+                return Err(SystemError::EIO);
+            }
+            if (csts & NVME_CSTS_RDY != 0) == ready {
+                return Ok(());
+            }
+            if Instant::now() > deadline {
+                return Err(SystemError::ETIMEDOUT);
+            }
+            spin_loop();
+        }
+    }
+
+    fn setup_irq(
+        device: &Arc<PciDeviceStructureGeneralDevice>,
+        dev_id: Arc<DeviceId>,
+    ) -> Result<(), SystemError> {
+        device
+            .irq_vector_mut()
+            .ok_or(SystemError::ENOSYS)?
+            .write()
+            .push(NVME_IRQ_VECTOR);
+        device
+            .irq_init(IRQ::PCI_IRQ_MSIX)
+            .ok_or(SystemError::ENOSYS)?;
+        let msg = PciIrqMsg {
+            irq_common_message: IrqCommonMsg::init_from(
+                0,
+                "NVME_IRQ".to_string(),
+                &NvmeIrqHandler,
+                dev_id,
+            ),
+            irq_specific_message: IrqSpecificMsg::msi_default(),
+        };
+        device.irq_install(msg).map_err(|_| SystemError::EIO)?;
+        device.irq_enable(true).map_err(|_| SystemError::EIO)?;
+        Ok(())
+    }
+
+    /// 取出Identify数据中以空格填充的ASCII字符串
+    fn identify_string(raw: &[u8]) -> String {
+        String::from_utf8_lossy(raw).trim().to_string()
+    }
+
+    /// 为每个CPU创建一对I/O队列
+    fn create_io_queues(&self, max_entries: u16) -> Result<(), SystemError> {
+        let wanted = (smp_cpu_manager().present_cpus_count() as usize).clamp(1, NVME_MAX_IO_QUEUES);
+
+        // 协商队列数，返回值的低16位为控制器分配的提交队列数减1，高16位为完成队列数减1
+        let mut cmd = NvmeCommand::new(NVME_ADMIN_SET_FEATURES, 0);
+        cmd.cdw10 = NVME_FEATURE_NUM_QUEUES;
+        cmd.cdw11 = ((wanted as u32 - 1) << 16) | (wanted as u32 - 1);
+        let result = self.admin_queue.execute(cmd, None, None, true)?;
+        let granted = ((result & 0xffff).min(result >> 16) as usize + 1).min(wanted);
+
+        let depth = NVME_IO_QUEUE_DEPTH.min(max_entries);
+        let mut queues = Vec::with_capacity(granted);
+        for qid in 1..=granted as u16 {
+            let queue = NvmeQueue::new(
+                self.regs,
+                qid,
+                depth,
+                NVME_IO_QUEUE_SLOTS.min(depth as usize - 1),
+                self.max_transfer,
+            );
+
+            // 完成队列：物理连续，使用第0个中断向量，按需开启中断
+            let mut cmd = NvmeCommand::new(NVME_ADMIN_CREATE_CQ, 0);
+            cmd.prp1 = queue.cq_paddr() as u64;
+            cmd.cdw10 = ((depth as u32 - 1) << 16) | qid as u32;
+            cmd.cdw11 = if self.irq_enabled { 0b11 } else { 0b01 };
+            self.admin_queue.execute(cmd, None, None, true)?;
+
+            // 提交队列：物理连续，对应同号的完成队列
+            let mut cmd = NvmeCommand::new(NVME_ADMIN_CREATE_SQ, 0);
+            cmd.prp1 = queue.sq_paddr() as u64;
+            cmd.cdw10 = ((depth as u32 - 1) << 16) | qid as u32;
+            cmd.cdw11 = ((qid as u32) << 16) | 0b01;
+            self.admin_queue.execute(cmd, None, None, true)?;
+
+            queues.push(Arc::new(queue));
+        }
+        *self.io_queues.write() = queues;
+        Ok(())
+    }
+
+    /// 识别控制器上的活动命名空间
+    fn namespaces(self: &Arc<Self>) -> Result<Vec<Arc<NvmeNamespace>>, SystemError> {
+        let mut list = vec![0u8; NVME_PAGE_SIZE];
+        let mut cmd = NvmeCommand::new(NVME_ADMIN_IDENTIFY, 0);
+        cmd.cdw10 = NVME_IDENTIFY_ACTIVE_NS_LIST;
+        self.admin_queue.execute(cmd, None, Some(&mut list), true)?;
+
+        let mut namespaces = Vec::new();
+        for nsid in list
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .take_while(|nsid| *nsid != 0)
+        {
+            let mut identify = vec![0u8; NVME_PAGE_SIZE];
+            let mut cmd = NvmeCommand::new(NVME_ADMIN_IDENTIFY, nsid);
+            cmd.cdw10 = NVME_IDENTIFY_NAMESPACE;
+            self.admin_queue
+                .execute(cmd, None, Some(&mut identify), true)?;
+
+            let nsze = u64::from_le_bytes(identify[0..8].try_into().unwrap());
+            let flbas = (identify[26] & 0xf) as usize;
+            let lbaf = u32::from_le_bytes(
+                identify[128 + flbas * 4..132 + flbas * 4]
+                    .try_into()
+                    .unwrap(),
+            );
+            let lba_shift = ((lbaf >> 16) & 0xff) as u8;
+            if nsze == 0 {
+                continue;
+            }
+            if lba_shift != 9 {
+                warn!(
+                    "nvme{}n{}: LBA size {} is not supported yet",
+                    self.id,
+                    nsid,
+                    1usize << lba_shift
+                );
+                continue;
+            }
+            namespaces.push(NvmeNamespace::new(self.clone(), nsid, nsze));
+        }
+        Ok(namespaces)
+    }
+
+    #[inline]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    #[inline]
+    pub fn max_transfer(&self) -> usize {
+        self.max_transfer
+    }
+
+    /// 是否支持Dataset Management命令（用于丢弃扇区）
+    #[inline]
+    pub fn support_dsm(&self) -> bool {
+        self.oncs & NVME_ONCS_DSM != 0
+    }
+
+    /// 是否支持Write Zeroes命令
+    #[inline]
+    pub fn support_write_zeroes(&self) -> bool {
+        self.oncs & NVME_ONCS_WRITE_ZEROES != 0
+    }
+
+    /// 在当前CPU对应的I/O队列上执行命令
+    pub fn execute_io(
+        &self,
+        cmd: NvmeCommand,
+        write_buf: Option<&[u8]>,
+        read_buf: Option<&mut [u8]>,
+    ) -> Result<(), SystemError> {
+        let queue = {
+            let queues = self.io_queues.read();
+            if queues.is_empty() {
+                return Err(SystemError::ENODEV);
+            }
+            queues[smp_get_processor_id().data() as usize % queues.len()].clone()
+        };
+        queue.execute(cmd, write_buf, read_buf, !self.irq_enabled)?;
+        Ok(())
+    }
+
+    fn handle_irq(&self) -> IrqReturn {
+        let mut handled = self.admin_queue.process_completions() > 0;
+        for queue in self.io_queues.read().iter() {
+            handled |= queue.process_completions() > 0;
+        }
+        if handled {
+            IrqReturn::Handled
+        } else {
+            IrqReturn::NotHandled
+        }
+    }
+}
+
+/// NVMe完成中断的处理函数
+#[derive(Debug)]
+struct NvmeIrqHandler;
+
+impl IrqHandler for NvmeIrqHandler {
+    fn handle(
+        &self,
+        _irq: IrqNumber,
+        _static_data: Option<&dyn IrqHandlerData>,
+        dev_id: Option<Arc<dyn IrqHandlerData>>,
+    ) -> Result<IrqReturn, SystemError> {
+        let dev_id = dev_id
+            .ok_or(SystemError::EINVAL)?
+            .arc_any()
+            .downcast::<DeviceId>()
+            .map_err(|_| SystemError::EINVAL)?;
+        let ctrl = NVME_CONTROLLERS
+            .lock_irqsave()
+            .iter()
+            .find(|ctrl| ctrl.dev_id == dev_id)
+            .cloned();
+        Ok(ctrl.map_or(IrqReturn::NotHandled, |ctrl| ctrl.handle_irq()))
+    }
+}
+
+/// @brief 寻找所有的NVMe控制器
+fn nvme_device_search() -> Vec<Arc<PciDeviceStructureGeneralDevice>> {
+    get_pci_device_structure_mut(&PCI_DEVICE_LINKEDLIST, NVME_CLASS, NVME_SUBCLASS)
+        .into_iter()
+        .filter(|dev| dev.common_header().prog_if == NVME_PROG_IF)
+        .filter_map(|dev| dev.as_standard_device())
+        .collect()
+}
+
+/// @brief: 初始化所有NVMe控制器，并把其上的命名空间注册为块设备
+///
+/// 没有NVMe控制器时什么也不做
+pub fn nvme_init() -> Result<(), SystemError> {
+    for device in nvme_device_search() {
+        let ctrl = match NvmeController::new(&device) {
+            Ok(ctrl) => ctrl,
+            Err(e) => {
+                warn!(
+                    "nvme: failed to initialize controller {:?}: {:?}",
+                    device.common_header().bus_device_function,
+                    e
+                );
+                continue;
+            }
+        };
+        NVME_CONTROLLERS.lock_irqsave().push(ctrl.clone());
+
+        for ns in ctrl.namespaces()? {
+            block_dev_manager()
+                .register(ns.clone() as Arc<dyn BlockDevice>)
+                .inspect_err(|e| warn!("nvme: failed to register {}: {:?}", ns.dev_name(), e))
+                .ok();
+        }
+    }
+    return Ok(());
+}
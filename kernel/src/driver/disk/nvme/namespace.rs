@@ -0,0 +1,367 @@
+use core::any::Any;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::base::{
+        block::{
+            block_device::{BlockDevName, BlockDevice, BlockId, GeneralBlockRange, LBA_SIZE},
+            disk_info::Partition,
+            manager::BlockDevMeta,
+        },
+        class::Class,
+        device::{bus::Bus, driver::Driver, Device, DeviceCommonData, DeviceType, IdTable},
+        kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
+        kset::KSet,
+    },
+    filesystem::{gpt::GptDiskPartitionTable, kernfs::KernFSInode, mbr::MbrDiskPartionTable},
+    libs::{
+        rwlock::{RwLockReadGuard, RwLockWriteGuard},
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+};
+
+use super::{
+    queue::NvmeCommand, NvmeController, NVME_CMD_DSM, NVME_CMD_FLUSH, NVME_CMD_READ,
+    NVME_CMD_WRITE, NVME_CMD_WRITE_ZEROES,
+};
+
+const NVME_BASENAME: &str = "nvme";
+/// Dataset Management命令中一个范围描述符的大小（字节）
+const NVME_DSM_RANGE_SIZE: usize = 16;
+/// 一条Dataset Management命令最多携带的范围数
+const NVME_DSM_MAX_RANGES: usize = 256;
+/// Dataset Management命令的属性：释放（deallocate）这些范围
+const NVME_DSM_ATTR_DEALLOCATE: u32 = 1 << 2;
+/// 一条Write Zeroes命令最多清零的扇区数
+const NVME_WRITE_ZEROES_MAX_SECTORS: usize = 1 << 16;
+
+/// NVMe命名空间，作为一个块设备注册
+#[derive(Debug)]
+#[cast_to([sync] Device)]
+pub struct NvmeNamespace {
+    blkdev_meta: BlockDevMeta,
+    ctrl: Arc<NvmeController>,
+    nsid: u32,
+    /// 命名空间的大小（扇区）
+    nr_sectors: u64,
+    inner: SpinLock<InnerNvmeNamespace>,
+    locked_kobj_state: LockedKObjectState,
+    self_ref: Weak<Self>,
+}
+
+#[derive(Debug)]
+struct InnerNvmeNamespace {
+    device_common: DeviceCommonData,
+    kobject_common: KObjectCommonData,
+}
+
+impl NvmeNamespace {
+    pub fn new(ctrl: Arc<NvmeController>, nsid: u32, nr_sectors: u64) -> Arc<Self> {
+        let name = BlockDevName::new(format!("nvme{}n{}", ctrl.id(), nsid), ctrl.id());
+        Arc::new_cyclic(|self_ref| Self {
+            blkdev_meta: BlockDevMeta::new(name),
+            ctrl,
+            nsid,
+            nr_sectors,
+            inner: SpinLock::new(InnerNvmeNamespace {
+                device_common: DeviceCommonData::default(),
+                kobject_common: KObjectCommonData::default(),
+            }),
+            locked_kobj_state: LockedKObjectState::default(),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerNvmeNamespace> {
+        self.inner.lock()
+    }
+
+    fn check_range(&self, lba_id_start: BlockId, count: usize) -> Result<(), SystemError> {
+        if (lba_id_start + count) as u64 > self.nr_sectors {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(())
+    }
+
+    /// 构造读写命令，`count`为扇区数
+    fn rw_command(&self, opcode: u8, lba: BlockId, count: usize) -> NvmeCommand {
+        let mut cmd = NvmeCommand::new(opcode, self.nsid);
+        cmd.cdw10 = lba as u32;
+        cmd.cdw11 = (lba as u64 >> 32) as u32;
+        // NLB从0开始计数
+        cmd.cdw12 = count as u32 - 1;
+        cmd
+    }
+}
+
+impl BlockDevice for NvmeNamespace {
+    fn dev_name(&self) -> &BlockDevName {
+        &self.blkdev_meta.devname
+    }
+
+    fn blkdev_meta(&self) -> &BlockDevMeta {
+        &self.blkdev_meta
+    }
+
+    fn disk_range(&self) -> GeneralBlockRange {
+        GeneralBlockRange::new(0, self.nr_sectors as usize).unwrap()
+    }
+
+    fn read_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        self.check_range(lba_id_start, count)?;
+        let max_sectors = self.ctrl.max_transfer() / LBA_SIZE;
+        let mut done = 0;
+        while done < count {
+            let n = (count - done).min(max_sectors);
+            let cmd = self.rw_command(NVME_CMD_READ, lba_id_start + done, n);
+            self.ctrl.execute_io(
+                cmd,
+                None,
+                Some(&mut buf[done * LBA_SIZE..(done + n) * LBA_SIZE]),
+            )?;
+            done += n;
+        }
+        Ok(count)
+    }
+
+    fn write_at_sync(
+        &self,
+        lba_id_start: BlockId,
+        count: usize,
+        buf: &[u8],
+    ) -> Result<usize, SystemError> {
+        self.check_range(lba_id_start, count)?;
+        let max_sectors = self.ctrl.max_transfer() / LBA_SIZE;
+        let mut done = 0;
+        while done < count {
+            let n = (count - done).min(max_sectors);
+            let cmd = self.rw_command(NVME_CMD_WRITE, lba_id_start + done, n);
+            self.ctrl.execute_io(
+                cmd,
+                Some(&buf[done * LBA_SIZE..(done + n) * LBA_SIZE]),
+                None,
+            )?;
+            done += n;
+        }
+        Ok(count)
+    }
+
+    fn sync(&self) -> Result<(), SystemError> {
+        self.ctrl
+            .execute_io(NvmeCommand::new(NVME_CMD_FLUSH, self.nsid), None, None)
+    }
+
+    fn discard(&self, lba_id_start: BlockId, count: usize) -> Result<(), SystemError> {
+        if !self.ctrl.support_dsm() {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        self.check_range(lba_id_start, count)?;
+        // 每个范围最多描述u32::MAX个扇区，一条命令的范围列表不能超过槽的DMA缓冲区
+        let max_ranges = NVME_DSM_MAX_RANGES.min(self.ctrl.max_transfer() / NVME_DSM_RANGE_SIZE);
+        let mut lba = lba_id_start;
+        let end = lba_id_start + count;
+        while lba < end {
+            let mut ranges = Vec::with_capacity(max_ranges * NVME_DSM_RANGE_SIZE);
+            while lba < end && ranges.len() < max_ranges * NVME_DSM_RANGE_SIZE {
+                let n = (end - lba).min(u32::MAX as usize);
+                ranges.extend_from_slice(&0u32.to_le_bytes());
+                ranges.extend_from_slice(&(n as u32).to_le_bytes());
+                ranges.extend_from_slice(&(lba as u64).to_le_bytes());
+                lba += n;
+            }
+            let mut cmd = NvmeCommand::new(NVME_CMD_DSM, self.nsid);
+            cmd.cdw10 = (ranges.len() / NVME_DSM_RANGE_SIZE) as u32 - 1;
+            cmd.cdw11 = NVME_DSM_ATTR_DEALLOCATE;
+            self.ctrl.execute_io(cmd, Some(&ranges), None)?;
+        }
+        Ok(())
+    }
+
+    fn write_zeroes(&self, lba_id_start: BlockId, count: usize) -> Result<(), SystemError> {
+        self.check_range(lba_id_start, count)?;
+        if !self.ctrl.support_write_zeroes() {
+            let max_sectors = self.ctrl.max_transfer() / LBA_SIZE;
+            let zeroes = vec![0u8; max_sectors * LBA_SIZE];
+            let mut done = 0;
+            while done < count {
+                let n = (count - done).min(max_sectors);
+                self.write_at_sync(lba_id_start + done, n, &zeroes[..n * LBA_SIZE])?;
+                done += n;
+            }
+            return Ok(());
+        }
+
+        let mut done = 0;
+        while done < count {
+            let n = (count - done).min(NVME_WRITE_ZEROES_MAX_SECTORS);
+            let cmd = self.rw_command(NVME_CMD_WRITE_ZEROES, lba_id_start + done, n);
+            self.ctrl.execute_io(cmd, None, None)?;
+            done += n;
+        }
+        Ok(())
+    }
+
+    fn blk_size_log2(&self) -> u8 {
+        9
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn device(&self) -> Arc<dyn Device> {
+        self.self_ref.upgrade().unwrap()
+    }
+
+    fn block_size(&self) -> usize {
+        LBA_SIZE
+    }
+
+    fn partitions(&self) -> Vec<Arc<Partition>> {
+        let device = self.self_ref.upgrade().unwrap() as Arc<dyn BlockDevice>;
+        if let Ok(gpt_table) = GptDiskPartitionTable::from_disk(device.clone()) {
+            return gpt_table.partitions(Arc::downgrade(&device));
+        }
+        MbrDiskPartionTable::from_disk(device.clone())
+            .map(|mbr_table| mbr_table.partitions(Arc::downgrade(&device)))
+            .unwrap_or_default()
+    }
+}
+
+impl Device for NvmeNamespace {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Block
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(NVME_BASENAME.to_string(), None)
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl KObject for NvmeNamespace {
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobject_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobject_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobject_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobject_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobject_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobject_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobject_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.dev_name().to_string()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobject_common.kobj_type = ktype;
+    }
+}
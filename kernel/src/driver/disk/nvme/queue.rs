@@ -0,0 +1,368 @@
+use core::{
+    hint::spin_loop,
+    ptr::NonNull,
+    sync::atomic::{fence, AtomicU16, AtomicU32, AtomicU8, Ordering},
+};
+
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+use crate::{
+    driver::net::dma::dma_alloc,
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+};
+
+use super::{NvmeRegs, NVME_PAGE_SIZE};
+
+/// 提交队列条目的大小（字节）
+pub const NVME_SQ_ENTRY_SIZE: usize = 64;
+/// 完成队列条目的大小（字节）
+pub const NVME_CQ_ENTRY_SIZE: usize = 16;
+
+const SLOT_FREE: u8 = 0;
+const SLOT_BUSY: u8 = 1;
+const SLOT_DONE: u8 = 2;
+
+/// 提交队列条目
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NvmeCommand {
+    /// 操作码（0-7位）、命令标识符（16-31位）
+    pub cdw0: u32,
+    pub nsid: u32,
+    pub cdw2: u32,
+    pub cdw3: u32,
+    pub mptr: u64,
+    pub prp1: u64,
+    pub prp2: u64,
+    pub cdw10: u32,
+    pub cdw11: u32,
+    pub cdw12: u32,
+    pub cdw13: u32,
+    pub cdw14: u32,
+    pub cdw15: u32,
+}
+
+impl NvmeCommand {
+    pub fn new(opcode: u8, nsid: u32) -> Self {
+        Self {
+            cdw0: opcode as u32,
+            nsid,
+            ..Default::default()
+        }
+    }
+
+    fn set_cid(&mut self, cid: u16) {
+        self.cdw0 = (self.cdw0 & 0xffff) | ((cid as u32) << 16);
+    }
+}
+
+/// 完成队列条目
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NvmeCompletion {
+    pub result: u32,
+    pub reserved: u32,
+    pub sq_head: u16,
+    pub sq_id: u16,
+    pub cid: u16,
+    /// 相位标志（第0位）与状态域（1-15位）
+    pub status: u16,
+}
+
+impl NvmeCompletion {
+    #[inline]
+    fn phase(&self) -> bool {
+        self.status & 1 != 0
+    }
+
+    /// 状态码类型与状态码，为0表示成功
+    #[inline]
+    pub fn status_code(&self) -> u16 {
+        (self.status >> 1) & 0x7ff
+    }
+}
+
+#[derive(Debug)]
+struct CqState {
+    head: u16,
+    phase: bool,
+}
+
+/// 一对提交队列与完成队列
+///
+/// 每个命令占用一个槽（slot），槽的下标就是命令标识符。每个槽有自己的DMA缓冲区，
+/// 读写的数据经由它与调用者的缓冲区交换；缓冲区在物理上连续，所以PRP列表在创建队列时就可以填好。
+#[derive(Debug)]
+pub struct NvmeQueue {
+    qid: u16,
+    depth: u16,
+    regs: NvmeRegs,
+    sq_vaddr: NonNull<NvmeCommand>,
+    sq_paddr: usize,
+    cq_vaddr: NonNull<NvmeCompletion>,
+    cq_paddr: usize,
+    sq_tail: SpinLock<u16>,
+    cq: SpinLock<CqState>,
+    /// 每个槽的DMA缓冲区大小（字节）
+    slot_size: usize,
+    buf_vaddr: NonNull<u8>,
+    buf_paddr: usize,
+    prp_paddr: usize,
+    free_slots: SpinLock<u64>,
+    slot_state: Vec<AtomicU8>,
+    slot_status: Vec<AtomicU16>,
+    slot_result: Vec<AtomicU32>,
+    /// 等待空闲槽的进程
+    slot_wait: WaitQueue,
+    /// 等待命令完成的进程
+    io_wait: WaitQueue,
+}
+
+unsafe impl Send for NvmeQueue {}
+unsafe impl Sync for NvmeQueue {}
+
+impl NvmeQueue {
+    /// 分配队列所需的内存
+    ///
+    /// ## 参数
+    ///
+    /// - `qid`: 队列号，0为管理队列
+    /// - `depth`: 队列的条目数
+    /// - `nr_slots`: 能同时执行的命令数，不超过64
+    /// - `slot_size`: 每个槽的DMA缓冲区大小，为页大小的整数倍
+    pub fn new(regs: NvmeRegs, qid: u16, depth: u16, nr_slots: usize, slot_size: usize) -> Self {
+        let (sq_paddr, sq_vaddr) =
+            dma_alloc((depth as usize * NVME_SQ_ENTRY_SIZE).div_ceil(NVME_PAGE_SIZE));
+        let (cq_paddr, cq_vaddr) =
+            dma_alloc((depth as usize * NVME_CQ_ENTRY_SIZE).div_ceil(NVME_PAGE_SIZE));
+        let (buf_paddr, buf_vaddr) = dma_alloc(nr_slots * slot_size / NVME_PAGE_SIZE);
+
+        // 所有槽的PRP列表放在同一个页中，每个槽的列表依次描述其缓冲区第1页之后的各页
+        let pages_per_slot = slot_size / NVME_PAGE_SIZE;
+        let (prp_paddr, prp_vaddr) = dma_alloc(1);
+        let prp_list = prp_vaddr.as_ptr() as *mut u64;
+        for slot in 0..nr_slots {
+            for page in 1..pages_per_slot {
+                let paddr = buf_paddr + slot * slot_size + page * NVME_PAGE_SIZE;
+                unsafe {
+                    prp_list
+                        .add(slot * pages_per_slot + page - 1)
+                        .write_volatile(paddr as u64)
+                };
+            }
+        }
+
+        Self {
+            qid,
+            depth,
+            regs,
+            sq_vaddr: sq_vaddr.cast(),
+            sq_paddr,
+            cq_vaddr: cq_vaddr.cast(),
+            cq_paddr,
+            sq_tail: SpinLock::new(0),
+            cq: SpinLock::new(CqState {
+                head: 0,
+                phase: true,
+            }),
+            slot_size,
+            buf_vaddr,
+            buf_paddr,
+            prp_paddr,
+            free_slots: SpinLock::new(u64::MAX >> (64 - nr_slots)),
+            slot_state: (0..nr_slots).map(|_| AtomicU8::new(SLOT_FREE)).collect(),
+            slot_status: (0..nr_slots).map(|_| AtomicU16::new(0)).collect(),
+            slot_result: (0..nr_slots).map(|_| AtomicU32::new(0)).collect(),
+            slot_wait: WaitQueue::default(),
+            io_wait: WaitQueue::default(),
+        }
+    }
+
+    #[inline]
+    pub fn sq_paddr(&self) -> usize {
+        self.sq_paddr
+    }
+
+    #[inline]
+    pub fn cq_paddr(&self) -> usize {
+        self.cq_paddr
+    }
+
+    fn try_get_slot(&self) -> Option<usize> {
+        let mut free = self.free_slots.lock_irqsave();
+        if *free == 0 {
+            return None;
+        }
+        let slot = free.trailing_zeros() as usize;
+        *free &= !(1 << slot);
+        self.slot_state[slot].store(SLOT_BUSY, Ordering::SeqCst);
+        Some(slot)
+    }
+
+    fn put_slot(&self, slot: usize) {
+        self.slot_state[slot].store(SLOT_FREE, Ordering::SeqCst);
+        *self.free_slots.lock_irqsave() |= 1 << slot;
+        self.slot_wait.wakeup_all(None);
+    }
+
+    fn slot_done(&self, slot: usize) -> bool {
+        self.slot_state[slot].load(Ordering::SeqCst) == SLOT_DONE
+    }
+
+    /// 槽的DMA缓冲区
+    fn slot_buf(&self, slot: usize) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.buf_vaddr.as_ptr().add(slot * self.slot_size),
+                self.slot_size,
+            )
+        }
+    }
+
+    /// 为传输`len`字节的命令填写PRP1和PRP2
+    fn fill_prp(&self, cmd: &mut NvmeCommand, slot: usize, len: usize) {
+        let paddr = self.buf_paddr + slot * self.slot_size;
+        cmd.prp1 = paddr as u64;
+        cmd.prp2 = if len <= NVME_PAGE_SIZE {
+            0
+        } else if len <= 2 * NVME_PAGE_SIZE {
+            (paddr + NVME_PAGE_SIZE) as u64
+        } else {
+            let pages_per_slot = self.slot_size / NVME_PAGE_SIZE;
+            (self.prp_paddr + slot * pages_per_slot * core::mem::size_of::<u64>()) as u64
+        };
+    }
+
+    /// 把命令写入提交队列并敲门铃
+    fn submit(&self, mut cmd: NvmeCommand, slot: usize) {
+        cmd.set_cid(slot as u16);
+        let mut tail = self.sq_tail.lock_irqsave();
+        unsafe {
+            self.sq_vaddr
+                .as_ptr()
+                .add(*tail as usize)
+                .write_volatile(cmd)
+        };
+        *tail = (*tail + 1) % self.depth;
+        fence(Ordering::SeqCst);
+        self.regs.write_sq_doorbell(self.qid, *tail);
+    }
+
+    /// 取出完成队列中所有新的条目，标记对应的命令已完成
+    ///
+    /// 返回取出的条目数。中断处理函数与轮询的进程都会调用
+    pub fn process_completions(&self) -> usize {
+        let mut cq = self.cq.lock_irqsave();
+        let mut count = 0;
+        loop {
+            let entry = unsafe { self.cq_vaddr.as_ptr().add(cq.head as usize).read_volatile() };
+            if entry.phase() != cq.phase {
+                break;
+            }
+            fence(Ordering::SeqCst);
+            let slot = entry.cid as usize;
+            if let Some(state) = self.slot_state.get(slot) {
+                self.slot_status[slot].store(entry.status_code(), Ordering::SeqCst);
+                self.slot_result[slot].store(entry.result, Ordering::SeqCst);
+                state.store(SLOT_DONE, Ordering::SeqCst);
+            }
+            cq.head += 1;
+            if cq.head == self.depth {
+                cq.head = 0;
+                cq.phase = !cq.phase;
+            }
+            count += 1;
+        }
+
+        if count > 0 {
+            self.regs.write_cq_doorbell(self.qid, cq.head);
+            drop(cq);
+            self.io_wait.wakeup_all(None);
+        }
+        count
+    }
+
+    /// 执行一条命令并等待它完成
+    ///
+    /// ## 参数
+    ///
+    /// - `cmd`: 命令，PRP与命令标识符由这里填写
+    /// - `write_buf`: 需要传给设备的数据
+    /// - `read_buf`: 存放设备返回的数据
+    /// - `poll`: 为true时轮询完成队列，否则睡眠等待中断唤醒
+    ///
+    /// ## 返回值
+    ///
+    /// 完成队列条目中的命令结果（DW0）
+    pub fn execute(
+        &self,
+        mut cmd: NvmeCommand,
+        write_buf: Option<&[u8]>,
+        read_buf: Option<&mut [u8]>,
+        poll: bool,
+    ) -> Result<u32, SystemError> {
+        let len = write_buf
+            .map(|b| b.len())
+            .or(read_buf.as_ref().map(|b| b.len()))
+            .unwrap_or(0);
+        if len > self.slot_size {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut slot = None;
+        if poll {
+            while slot.is_none() {
+                slot = self.try_get_slot();
+                spin_loop();
+            }
+        } else {
+            wq_wait_event_uninterruptible!(
+                self.slot_wait,
+                {
+                    slot = self.try_get_slot();
+                    slot.is_some()
+                },
+                {}
+            );
+        }
+        let slot = slot.unwrap();
+
+        if let Some(buf) = write_buf {
+            self.slot_buf(slot)[..len].copy_from_slice(buf);
+        }
+        if len > 0 {
+            self.fill_prp(&mut cmd, slot, len);
+        }
+        self.submit(cmd, slot);
+
+        if poll {
+            while !self.slot_done(slot) {
+                self.process_completions();
+                spin_loop();
+            }
+        } else {
+            wq_wait_event_uninterruptible!(self.io_wait, self.slot_done(slot), {});
+        }
+
+        let status = self.slot_status[slot].load(Ordering::SeqCst);
+        let result = self.slot_result[slot].load(Ordering::SeqCst);
+        if status == 0 {
+            if let Some(buf) = read_buf {
+                buf.copy_from_slice(&self.slot_buf(slot)[..len]);
+            }
+        }
+        self.put_slot(slot);
+
+        if status != 0 {
+            log::warn!(
+                "nvme: command {:#x} on queue {} failed, status {:#x}",
+                cmd.cdw0 & 0xff,
+                self.qid,
+                status
+            );
+            return Err(SystemError::EIO);
+        }
+        Ok(result)
+    }
+}
@@ -15,7 +15,7 @@ use crate::{
         HardwareIrqNumber, IrqNumber,
     },
     libs::spinlock::{SpinLock, SpinLockGuard},
-    sched::{SchedMode, __schedule},
+    sched::preempt::irq_exit_resched,
 };
 
 use super::riscv_sifive_plic::do_plic_irq;
@@ -224,7 +224,5 @@ pub fn riscv_intc_irq(trap_frame: &mut TrapFrame) {
         .ok();
     }
     do_softirq();
-    if hwirq.data() == RiscVSbiTimer::TIMER_IRQ.data() {
-        __schedule(SchedMode::SM_PREEMPT);
-    }
+    irq_exit_resched(hwirq.data() == RiscVSbiTimer::TIMER_IRQ.data());
 }
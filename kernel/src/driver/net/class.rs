@@ -15,7 +15,7 @@ use alloc::{
 use system_error::SystemError;
 use unified_init::macros::unified_init;
 
-use super::sysfs::NetAttrGroup;
+use super::sysfs::{NetAttrGroup, NetStatAttrGroup};
 
 /// `/sys/class/net` 的 class 实例
 static mut CLASS_NET_INSTANCE: Option<Arc<NetClass>> = None;
@@ -78,6 +78,6 @@ impl Class for NetClass {
     }
 
     fn dev_groups(&self) -> &'static [&'static dyn AttributeGroup] {
-        return &[&NetAttrGroup];
+        return &[&NetAttrGroup, &NetStatAttrGroup];
     }
 }
@@ -14,6 +14,7 @@ use crate::exception::IrqNumber;
 use alloc::string::ToString;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::hint::spin_loop;
 use core::intrinsics::unlikely;
 use core::mem::size_of;
 use core::ptr::NonNull;
@@ -43,6 +44,20 @@ const E1000E_DEVICE_ID: [u16; 14] = [
     0x1503, // 82579V
     0x150c, // 82583V
 ];
+// igb系列（82575之后）网卡的device id列表。它们的收发队列0的寄存器兼容e1000e的地址，
+// 中断相关的寄存器则位于0x01500
+const IGB_DEVICE_ID: [u16; 10] = [
+    0x10c9, // 82576, qemu `-device igb`
+    0x10e6, // 82576 Fiber
+    0x10e7, // 82576 SerDes
+    0x1533, // I210 Copper
+    0x1536, // I210 Fiber
+    0x1537, // I210 SerDes
+    0x1538, // I210 SGMII
+    0x157b, // I210 Copper (Flashless)
+    0x157c, // I210 SerDes (Flashless)
+    0x1539, // I211 Copper
+];
 
 // e1000e网卡与BAR有关的常量
 // BAR0空间大小(128KB)
@@ -59,6 +74,10 @@ const E1000E_DMA_PAGES: usize = 1;
 
 // 中断相关
 const E1000E_RECV_VECTOR: IrqNumber = IrqNumber::new(57);
+// 中断节流：每秒最多产生的中断数，与Linux e1000e驱动的默认值相同
+const E1000E_INTR_THROTTLE_RATE: u32 = 20000;
+// 等待设备复位完成的最大轮询次数
+const E1000E_RESET_POLL_TIMES: usize = 100000;
 
 // napi队列中暂时存储的buffer个数
 const E1000E_RECV_NAPI: usize = 1024;
@@ -160,12 +179,95 @@ impl E1000EBuffer {
     }
 }
 
+/// 网卡所属的系列，决定部分寄存器的位置与初始化步骤
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E1000EModel {
+    /// 8257x/8258x等e1000e网卡
+    E1000E,
+    /// 82576/I210/I211等igb网卡
+    Igb,
+}
+
+impl E1000EModel {
+    fn from_device_id(device_id: u16) -> Option<Self> {
+        if E1000E_DEVICE_ID.contains(&device_id) {
+            Some(Self::E1000E)
+        } else if IGB_DEVICE_ID.contains(&device_id) {
+            Some(Self::Igb)
+        } else {
+            None
+        }
+    }
+}
+
+/// 中断相关的寄存器
+///
+/// e1000e的位于0x000c0，igb的位于0x01500，且排列不同
+enum InterruptRegsPtr {
+    E1000E(NonNull<InterruptRegs>),
+    Igb(NonNull<IgbInterruptRegs>),
+}
+
+impl InterruptRegsPtr {
+    fn new(vaddress: u64, model: E1000EModel) -> Self {
+        match model {
+            E1000EModel::E1000E => {
+                Self::E1000E(get_register_ptr(vaddress, E1000E_INTERRRUPT_REGS_OFFSET))
+            }
+            E1000EModel::Igb => Self::Igb(get_register_ptr(vaddress, IGB_INTERRUPT_REGS_OFFSET)),
+        }
+    }
+
+    unsafe fn read_icr(&self) -> u32 {
+        match self {
+            Self::E1000E(regs) => volread!(regs, icr),
+            Self::Igb(regs) => volread!(regs, icr),
+        }
+    }
+
+    unsafe fn write_icr(&self, value: u32) {
+        match self {
+            Self::E1000E(regs) => volwrite!(regs, icr, value),
+            Self::Igb(regs) => volwrite!(regs, icr, value),
+        }
+    }
+
+    unsafe fn read_ims(&self) -> u32 {
+        match self {
+            Self::E1000E(regs) => volread!(regs, ims),
+            Self::Igb(regs) => volread!(regs, ims),
+        }
+    }
+
+    unsafe fn write_ims(&self, value: u32) {
+        match self {
+            Self::E1000E(regs) => volwrite!(regs, ims, value),
+            Self::Igb(regs) => volwrite!(regs, ims, value),
+        }
+    }
+
+    /// 只有e1000e有ITR寄存器，igb使用EITR
+    unsafe fn write_itr(&self, value: u32) {
+        if let Self::E1000E(regs) = self {
+            volwrite!(regs, itr, value);
+        }
+    }
+
+    unsafe fn write_imc(&self, value: u32) {
+        match self {
+            Self::E1000E(regs) => volwrite!(regs, imc, value),
+            Self::Igb(regs) => volwrite!(regs, imc, value),
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct E1000EDevice {
+    model: E1000EModel,
     // 设备寄存器
     // device registers
     general_regs: NonNull<GeneralRegs>,
-    interrupt_regs: NonNull<InterruptRegs>,
+    interrupt_regs: InterruptRegsPtr,
     rctl_regs: NonNull<ReceiveCtrlRegs>,
     receive_regs: NonNull<ReceiveRegs>,
     tctl_regs: NonNull<TransmitCtrlRegs>,
@@ -201,6 +303,7 @@ impl E1000EDevice {
     pub fn new(
         device: Arc<PciDeviceStructureGeneralDevice>,
         device_id: Arc<DeviceId>,
+        model: E1000EModel,
     ) -> Result<Self, E1000EPciError> {
         // 从BAR0获取我们需要的寄存器
         // Build registers sturcts from BAR0
@@ -241,8 +344,7 @@ impl E1000EDevice {
 
         let general_regs: NonNull<GeneralRegs> =
             get_register_ptr(vaddress, E1000E_GENERAL_REGS_OFFSET);
-        let interrupt_regs: NonNull<InterruptRegs> =
-            get_register_ptr(vaddress, E1000E_INTERRRUPT_REGS_OFFSET);
+        let interrupt_regs = InterruptRegsPtr::new(vaddress, model);
         let rctl_regs: NonNull<ReceiveCtrlRegs> =
             get_register_ptr(vaddress, E1000E_RECEIVE_CTRL_REG_OFFSET);
         let receive_regs: NonNull<ReceiveRegs> =
@@ -260,20 +362,30 @@ impl E1000EDevice {
             let mut ctrl = volread!(general_regs, ctrl);
             // 关闭中断
             // close the interrupt
-            volwrite!(interrupt_regs, imc, E1000E_IMC_CLEAR);
+            interrupt_regs.write_imc(E1000E_IMC_CLEAR);
             //SW RESET
             volwrite!(general_regs, ctrl, ctrl | E1000E_CTRL_RST);
             compiler_fence(Ordering::AcqRel);
+            // 复位完成后RST位会被硬件清除
+            // the RST bit is self-clearing
+            for _ in 0..E1000E_RESET_POLL_TIMES {
+                if volread!(general_regs, ctrl) & E1000E_CTRL_RST == 0 {
+                    break;
+                }
+                spin_loop();
+            }
             // PHY RESET
             ctrl = volread!(general_regs, ctrl);
             volwrite!(general_regs, ctrl, ctrl | E1000E_CTRL_PHY_RST);
             volwrite!(general_regs, ctrl, ctrl);
             // 关闭中断
             // close the interrupt
-            volwrite!(interrupt_regs, imc, E1000E_IMC_CLEAR);
-            let mut gcr = volread!(pcie_regs, gcr);
-            gcr |= 1 << 22;
-            volwrite!(pcie_regs, gcr, gcr);
+            interrupt_regs.write_imc(E1000E_IMC_CLEAR);
+            if model == E1000EModel::E1000E {
+                let mut gcr = volread!(pcie_regs, gcr);
+                gcr |= 1 << 22;
+                volwrite!(pcie_regs, gcr, gcr);
+            }
             compiler_fence(Ordering::AcqRel);
             // PHY Initialization 14.8.1
             // MAC/PHY Link Setup 14.8.2
@@ -368,11 +480,13 @@ impl E1000EDevice {
             // Transmit Initialization 14.7
             // 开启发包descriptor的回写功能
             // Program the TXDCTL register with the desired TX descriptor write-back policy
-            volwrite!(
-                transimit_regs,
-                txdctl,
-                E1000E_TXDCTL_WTHRESH | E1000E_TXDCTL_GRAN
-            );
+            let txdctl = match model {
+                E1000EModel::E1000E => E1000E_TXDCTL_WTHRESH | E1000E_TXDCTL_GRAN,
+                // igb的队列需要显式使能
+                // queues of igb must be enabled explicitly
+                E1000EModel::Igb => E1000E_TXDCTL_WTHRESH | IGB_XDCTL_ENABLE,
+            };
+            volwrite!(transimit_regs, txdctl, txdctl);
             // 设置descriptor环形队列的基地址，长度与首尾指针
             // Program the descriptor base address with the address of the region
             volwrite!(transimit_regs, tdbal0, trans_ring_pa as u32);
@@ -395,15 +509,50 @@ impl E1000EDevice {
                 E1000E_TCTL_EN | E1000E_TCTL_PSP | E1000E_TCTL_CT_VAL | E1000E_TCTL_COLD_VAL
             );
 
-            let icr = volread!(interrupt_regs, icr);
-            volwrite!(interrupt_regs, icr, icr);
+            if model == E1000EModel::Igb {
+                volwrite!(receive_regs, rxdctl, IGB_XDCTL_ENABLE);
+                for _ in 0..E1000E_RESET_POLL_TIMES {
+                    if volread!(receive_regs, rxdctl) & IGB_XDCTL_ENABLE != 0
+                        && volread!(transimit_regs, txdctl) & IGB_XDCTL_ENABLE != 0
+                    {
+                        break;
+                    }
+                    spin_loop();
+                }
+                // 队列使能后才能设置尾指针
+                // the tail pointer can only be written after the queue is enabled
+                volwrite!(receive_regs, rdt0, (recv_ring_length - 1) as u32);
+            }
+
+            // 中断节流，避免收包时每个分组都产生一次中断
+            // Interrupt moderation
+            match model {
+                E1000EModel::E1000E => {
+                    // ITR的单位为256ns
+                    // the interval of ITR is in 256ns increments
+                    interrupt_regs.write_itr(1_000_000_000 / (E1000E_INTR_THROTTLE_RATE * 256));
+                }
+                E1000EModel::Igb => {
+                    // EITR的间隔位于2-14位，单位为1us
+                    // the interval of EITR is in bits 14:2, in 1us increments
+                    let eitr: NonNull<IgbEitrRegs> =
+                        get_register_ptr(vaddress, IGB_EITR_REGS_OFFSET);
+                    volwrite!(eitr, eitr0, (1_000_000 / E1000E_INTR_THROTTLE_RATE) << 2);
+                }
+            }
+
+            let icr = interrupt_regs.read_icr();
+            interrupt_regs.write_icr(icr);
             // 开启收包相关的中断
             // Enable receive interrupts
-            let mut ims = volread!(interrupt_regs, ims);
-            ims = E1000E_IMS_LSC | E1000E_IMS_RXT0 | E1000E_IMS_RXDMT0 | E1000E_IMS_OTHER;
-            volwrite!(interrupt_regs, ims, ims);
+            let mut ims = E1000E_IMS_LSC | E1000E_IMS_RXT0 | E1000E_IMS_RXDMT0;
+            if model == E1000EModel::E1000E {
+                ims |= E1000E_IMS_OTHER;
+            }
+            interrupt_regs.write_ims(ims);
         }
         return Ok(E1000EDevice {
+            model,
             general_regs,
             interrupt_regs,
             rctl_regs,
@@ -474,12 +623,16 @@ impl E1000EDevice {
     pub fn mac_address(&self) -> [u8; 6] {
         return self.mac;
     }
+
+    pub fn model(&self) -> E1000EModel {
+        return self.model;
+    }
     // 向ICR寄存器中的某一bit写入1b表示该中断已经被接收，同时会清空该位
     // we need to clear ICR to tell e1000e we have read the interrupt
     pub fn e1000e_intr(&mut self) {
-        let icr = unsafe { volread!(self.interrupt_regs, icr) };
+        let icr = unsafe { self.interrupt_regs.read_icr() };
         // write 1b to any bit in ICR will clear the bit
-        unsafe { volwrite!(self.interrupt_regs, icr, icr) };
+        unsafe { self.interrupt_regs.write_icr(icr) };
     }
 
     // 切换是否接受分组到达的中断
@@ -487,12 +640,12 @@ impl E1000EDevice {
     // Note: this method is not completely implemented and not used in the current version
     #[allow(dead_code)]
     pub fn e1000e_intr_set(&mut self, state: bool) {
-        let mut ims = unsafe { volread!(self.interrupt_regs, ims) };
+        let mut ims = unsafe { self.interrupt_regs.read_ims() };
         match state {
             true => ims |= E1000E_IMS_RXT0,
             false => ims &= !E1000E_IMS_RXT0,
         }
-        unsafe { volwrite!(self.interrupt_regs, ims, ims) };
+        unsafe { self.interrupt_regs.write_ims(ims) };
     }
 
     // 实现了一部分napi机制的收包函数, 现在还没有投入使用
@@ -606,10 +759,12 @@ pub fn e1000e_probe() -> Result<u64, E1000EPciError> {
         let standard_device = device.as_standard_device().unwrap();
         if standard_device.common_header.vendor_id == 0x8086 {
             // intel
-            if E1000E_DEVICE_ID.contains(&standard_device.common_header.device_id) {
+            if let Some(model) =
+                E1000EModel::from_device_id(standard_device.common_header.device_id)
+            {
                 debug!(
-                    "Detected e1000e PCI device with device id {:#x}",
-                    standard_device.common_header.device_id
+                    "Detected {:?} PCI device with device id {:#x}",
+                    model, standard_device.common_header.device_id
                 );
 
                 // todo: 根据pci的path来生成device id
@@ -623,6 +778,7 @@ pub fn e1000e_probe() -> Result<u64, E1000EPciError> {
                         )),
                     )
                     .unwrap(),
+                    model,
                 )?;
                 e1000e_driver_init(e1000e);
             }
@@ -658,6 +814,18 @@ struct InterruptRegs {
     ims_align: ReadOnly<u32>, //0x000d4
     imc: WriteOnly<u32>, //0x000d8
 }
+// igb的中断控制
+// pp.451, I210 datasheet Table 8-8
+struct IgbInterruptRegs {
+    icr: Volatile<u32>,  //0x01500
+    ics: WriteOnly<u32>, //0x01504
+    ims: Volatile<u32>,  //0x01508
+    imc: WriteOnly<u32>, //0x0150c
+}
+// igb的中断节流，MSI/INTx模式下使用EITR0
+struct IgbEitrRegs {
+    eitr0: Volatile<u32>, //0x01680
+}
 // 收包功能控制
 struct ReceiveCtrlRegs {
     rctl: Volatile<u32>, //0x00100
@@ -723,6 +891,8 @@ struct MTARegs {
 
 const E1000E_GENERAL_REGS_OFFSET: u64 = 0x00000;
 const E1000E_INTERRRUPT_REGS_OFFSET: u64 = 0x000c0;
+const IGB_INTERRUPT_REGS_OFFSET: u64 = 0x01500;
+const IGB_EITR_REGS_OFFSET: u64 = 0x01680;
 const E1000E_RECEIVE_CTRL_REG_OFFSET: u64 = 0x00100;
 const E1000E_RECEIVE_REGS_OFFSET: u64 = 0x02800;
 const E1000E_TRANSMIT_CTRL_REG_OFFSET: u64 = 0x00400;
@@ -771,6 +941,8 @@ const E1000E_TCTL_COLD_VAL: u32 = 0x03f << 12; // suggested 64 byte time for Ful
                                                // TXDCTL
 const E1000E_TXDCTL_WTHRESH: u32 = 1 << 16;
 const E1000E_TXDCTL_GRAN: u32 = 1 << 24;
+// RXDCTL/TXDCTL (igb)
+const IGB_XDCTL_ENABLE: u32 = 1 << 25;
 // TIPG
 const E1000E_TIPG_IPGT: u32 = 8;
 const E1000E_TIPG_IPGR1: u32 = 2 << 10;
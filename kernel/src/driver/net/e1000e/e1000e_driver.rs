@@ -8,7 +8,10 @@ use crate::{
             device::{bus::Bus, driver::Driver, Device, DeviceCommonData, DeviceType, IdTable},
             kobject::{KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState},
         },
-        net::{register_netdevice, NetDeivceState, NetDevice, NetDeviceCommonData, Operstate},
+        net::{
+            ipv6_link_local, packet_tap::PacketTap, register_netdevice, replace_ip_addr,
+            NetDeivceState, NetDevice, NetDeviceCommonData, Operstate,
+        },
     },
     libs::{
         rwlock::{RwLockReadGuard, RwLockWriteGuard},
@@ -115,8 +118,8 @@ impl phy::TxToken for E1000ETxToken {
         let mut buffer = E1000EBuffer::new(4096);
         let result = f(buffer.as_mut_slice());
         let mut device = self.driver.inner.lock();
+        // 缓冲区由发送队列持有，在描述符被复用时释放
         device.e1000e_transmit(buffer);
-        buffer.free_buffer();
         return result;
     }
 }
@@ -193,13 +196,13 @@ impl phy::Device for E1000EDriver {
 impl E1000EInterface {
     pub fn new(mut driver: E1000EDriver) -> Arc<Self> {
         let iface_id = generate_iface_id();
-        let mut iface_config = smoltcp::iface::Config::new(HardwareAddress::Ethernet(
-            smoltcp::wire::EthernetAddress(driver.inner.lock().mac_address()),
-        ));
+        let mac = smoltcp::wire::EthernetAddress(driver.inner.lock().mac_address());
+        let mut iface_config = smoltcp::iface::Config::new(HardwareAddress::Ethernet(mac));
         iface_config.random_seed = rand() as u64;
 
-        let iface =
+        let mut iface =
             smoltcp::iface::Interface::new(iface_config, &mut driver, Instant::now().into());
+        replace_ip_addr(&mut iface, ipv6_link_local(mac)).ok();
 
         let driver: E1000EDriverWrapper = E1000EDriverWrapper(UnsafeCell::new(driver));
         let result = Arc::new(E1000EInterface {
@@ -221,6 +224,11 @@ impl E1000EInterface {
     pub fn inner(&self) -> SpinLockGuard<InnerE1000EInterface> {
         return self.inner.lock();
     }
+
+    /// 把驱动包装为抓包设备，再交给smoltcp收发
+    fn packet_tap(&self) -> PacketTap<'_, E1000EDriver> {
+        PacketTap::new(self.driver.force_get_mut(), self.iface_id, self.mac())
+    }
 }
 
 impl Debug for E1000EInterface {
@@ -323,28 +331,53 @@ impl NetDevice for E1000EInterface {
             return Err(SystemError::EINVAL);
         }
 
-        self.iface.lock().update_ip_addrs(|addrs| {
-            let dest = addrs.iter_mut().next();
-
-            if let Some(dest) = dest {
-                *dest = ip_addrs[0];
-            } else {
-                addrs.push(ip_addrs[0]).expect("Push ipCidr failed: full");
-            }
-        });
-        return Ok(());
+        return replace_ip_addr(&mut self.iface.lock(), ip_addrs[0]);
     }
 
     fn poll(&self, sockets: &mut smoltcp::iface::SocketSet) -> Result<(), SystemError> {
         let timestamp: smoltcp::time::Instant = Instant::now().into();
         let mut guard = self.iface.lock();
-        let poll_res = guard.poll(timestamp, self.driver.force_get_mut(), sockets);
+        let poll_res = guard.poll(timestamp, &mut self.packet_tap(), sockets);
         if poll_res {
             return Ok(());
         }
         return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
     }
 
+    fn join_multicast_group(&self, addr: wire::IpAddress) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        self.iface
+            .lock()
+            .join_multicast_group(&mut self.packet_tap(), addr, timestamp)
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOBUFS)
+    }
+
+    fn leave_multicast_group(&self, addr: wire::IpAddress) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        self.iface
+            .lock()
+            .leave_multicast_group(&mut self.packet_tap(), addr, timestamp)
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOBUFS)
+    }
+
+    fn poll_xmit(&self, frame: &[u8]) -> Result<(), SystemError> {
+        let mut device = self
+            .driver
+            .inner
+            .try_lock_irqsave()
+            .map_err(|_| SystemError::EAGAIN_OR_EWOULDBLOCK)?;
+        if !device.e1000e_can_transmit() {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+        let mut buffer = E1000EBuffer::new(frame.len());
+        buffer.as_mut_slice().copy_from_slice(frame);
+        // 缓冲区由发送队列持有，在描述符被复用时释放
+        device.e1000e_transmit(buffer);
+        Ok(())
+    }
+
     #[inline(always)]
     fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
         return &self.iface;
@@ -436,6 +469,7 @@ impl KObject for E1000EInterface {
 
 pub fn e1000e_driver_init(device: E1000EDevice) {
     let mac = smoltcp::wire::EthernetAddress::from_bytes(&device.mac_address());
+    let model = device.model();
     let driver = E1000EDriver::new(device);
     let iface = E1000EInterface::new(driver);
     // 标识网络设备已经启动
@@ -445,7 +479,7 @@ pub fn e1000e_driver_init(device: E1000EDevice) {
     NET_DEVICES
         .write_irqsave()
         .insert(iface.nic_id(), iface.clone());
-    info!("e1000e driver init successfully!\tMAC: [{}]", mac);
+    info!("{:?} driver init successfully!\tMAC: [{}]", model, mac);
 
     register_netdevice(iface.clone()).expect("register lo device failed");
 }
@@ -20,17 +20,27 @@ use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
+use smoltcp::iface::Route;
 use smoltcp::wire::HardwareAddress;
 use smoltcp::{
     phy::{self},
-    wire::{IpAddress, IpCidr},
+    wire::{
+        ArpOperation, ArpPacket, ArpRepr, EthernetFrame, EthernetProtocol, EthernetRepr, IpAddress,
+        IpCidr, Ipv4Address, Ipv4Cidr,
+    },
 };
 use system_error::SystemError;
 use unified_init::macros::unified_init;
 
-use super::{register_netdevice, NetDeivceState, NetDevice, NetDeviceCommonData, Operstate};
+use super::packet_tap::PacketTap;
+use super::{
+    register_netdevice, replace_ip_addr, NetDeivceState, NetDevice, NetDeviceCommonData, Operstate,
+};
 
 const DEVICE_NAME: &str = "loopback";
+/// smoltcp的以太网接口需要一个硬件地址，lo网卡收发的帧都使用这个地址
+const LOOPBACK_HW_ADDR: smoltcp::wire::EthernetAddress =
+    smoltcp::wire::EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
 
 /// ## 环回接收令牌
 /// 用于储存lo网卡接收到的数据
@@ -128,8 +138,52 @@ impl Loopback {
     /// - buffer：需要发送的数据包
     pub fn loopback_transmit(&mut self, buffer: Vec<u8>) {
         //debug!("lo transmit!");
+        let buffer = Self::arp_reply(&buffer).unwrap_or(buffer);
         self.queue.push_back(buffer)
     }
+
+    /// ## 应答ARP请求
+    /// 环回网卡上的任何邻居都是本机自己，因此ARP请求不需要交给协议栈处理，
+    /// 直接以lo的硬件地址应答。这样发往127.0.0.0/8中任意地址的数据包都能完成邻居解析
+    ///
+    /// ## 返回值
+    /// - Some(reply)：`frame`是ARP请求，返回对应的应答帧
+    /// - None：`frame`不是ARP请求
+    fn arp_reply(frame: &[u8]) -> Option<Vec<u8>> {
+        let eth = EthernetFrame::new_checked(frame).ok()?;
+        if eth.ethertype() != EthernetProtocol::Arp {
+            return None;
+        }
+        let packet = ArpPacket::new_checked(eth.payload()).ok()?;
+        let ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Request,
+            source_hardware_addr,
+            source_protocol_addr,
+            target_protocol_addr,
+            ..
+        } = ArpRepr::parse(&packet).ok()?
+        else {
+            return None;
+        };
+        let reply = ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Reply,
+            source_hardware_addr: LOOPBACK_HW_ADDR,
+            source_protocol_addr: target_protocol_addr,
+            target_hardware_addr: source_hardware_addr,
+            target_protocol_addr: source_protocol_addr,
+        };
+
+        let mut buf = vec![0; EthernetFrame::<&[u8]>::header_len() + reply.buffer_len()];
+        let mut frame = EthernetFrame::new_unchecked(buf.as_mut_slice());
+        EthernetRepr {
+            src_addr: LOOPBACK_HW_ADDR,
+            dst_addr: source_hardware_addr,
+            ethertype: EthernetProtocol::Arp,
+        }
+        .emit(&mut frame);
+        reply.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+        return Some(buf);
+    }
 }
 
 /// ## driver的包裹器
@@ -277,18 +331,33 @@ impl LoopbackInterface {
     /// 返回一个 `Arc<Self>`，即一个指向新创建的 `LoopbackInterface` 实例的智能指针。
     pub fn new(mut driver: LoopbackDriver) -> Arc<Self> {
         let iface_id = generate_iface_id();
-        let mut iface_config = smoltcp::iface::Config::new(HardwareAddress::Ethernet(
-            smoltcp::wire::EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]),
-        ));
+        let mut iface_config =
+            smoltcp::iface::Config::new(HardwareAddress::Ethernet(LOOPBACK_HW_ADDR));
         iface_config.random_seed = rand() as u64;
 
         let mut iface =
             smoltcp::iface::Interface::new(iface_config, &mut driver, Instant::now().into());
-        //设置网卡地址为127.0.0.1
+        //设置网卡地址为127.0.0.1和::1
         iface.update_ip_addrs(|ip_addrs| {
             ip_addrs
                 .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
                 .unwrap();
+            ip_addrs
+                .push(IpCidr::new(IpAddress::v6(0, 0, 0, 0, 0, 0, 0, 1), 128))
+                .unwrap();
+        });
+        // 127.0.0.0/8中的所有地址都属于本机：经由127.0.0.1的路由配合any_ip，
+        // 使lo接收目的地址为其中任意地址的数据包，而不仅仅是127.0.0.1
+        iface.set_any_ip(true);
+        iface.routes_mut().update(|routes| {
+            routes
+                .push(Route {
+                    cidr: IpCidr::Ipv4(Ipv4Cidr::new(Ipv4Address::new(127, 0, 0, 0), 8)),
+                    via_router: IpAddress::v4(127, 0, 0, 1),
+                    preferred_until: None,
+                    expires_at: None,
+                })
+                .ok();
         });
         let driver = LoopbackDriverWapper(UnsafeCell::new(driver));
         Arc::new(LoopbackInterface {
@@ -308,6 +377,11 @@ impl LoopbackInterface {
     fn inner(&self) -> SpinLockGuard<InnerLoopbackInterface> {
         return self.inner.lock();
     }
+
+    /// 把驱动包装为抓包设备，再交给smoltcp收发
+    fn packet_tap(&self) -> PacketTap<'_, LoopbackDriver> {
+        PacketTap::new(self.driver.force_get_mut(), self.iface_id, LOOPBACK_HW_ADDR)
+    }
 }
 
 impl Debug for LoopbackInterface {
@@ -480,16 +554,7 @@ impl NetDevice for LoopbackInterface {
             return Err(SystemError::EINVAL);
         }
 
-        self.iface.lock().update_ip_addrs(|addrs| {
-            let dest = addrs.iter_mut().next();
-
-            if let Some(dest) = dest {
-                *dest = ip_addrs[0];
-            } else {
-                addrs.push(ip_addrs[0]).expect("Push ipCidr failed: full");
-            }
-        });
-        return Ok(());
+        return replace_ip_addr(&mut self.iface.lock(), ip_addrs[0]);
     }
     /// ## `poll` 用于轮询接口的状态。
     ///
@@ -503,13 +568,41 @@ impl NetDevice for LoopbackInterface {
     fn poll(&self, sockets: &mut smoltcp::iface::SocketSet) -> Result<(), SystemError> {
         let timestamp: smoltcp::time::Instant = Instant::now().into();
         let mut guard = self.iface.lock();
-        let poll_res = guard.poll(timestamp, self.driver.force_get_mut(), sockets);
+        let poll_res = guard.poll(timestamp, &mut self.packet_tap(), sockets);
         if poll_res {
             return Ok(());
         }
         return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
     }
 
+    fn poll_xmit(&self, frame: &[u8]) -> Result<(), SystemError> {
+        self.driver
+            .force_get_mut()
+            .inner
+            .try_lock_irqsave()
+            .map_err(|_| SystemError::EAGAIN_OR_EWOULDBLOCK)?
+            .loopback_transmit(frame.to_vec());
+        return Ok(());
+    }
+
+    fn join_multicast_group(&self, addr: smoltcp::wire::IpAddress) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        self.iface
+            .lock()
+            .join_multicast_group(&mut self.packet_tap(), addr, timestamp)
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOBUFS)
+    }
+
+    fn leave_multicast_group(&self, addr: smoltcp::wire::IpAddress) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        self.iface
+            .lock()
+            .leave_multicast_group(&mut self.packet_tap(), addr, timestamp)
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOBUFS)
+    }
+
     #[inline(always)]
     fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
         return &self.iface;
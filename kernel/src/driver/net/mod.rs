@@ -5,8 +5,8 @@ use smoltcp::{
 };
 use sysfs::netdev_register_kobject;
 
-use super::base::device::Device;
-use crate::libs::spinlock::SpinLock;
+use super::base::device::{device_manager, Device};
+use crate::{libs::spinlock::SpinLock, net::NET_DEVICES};
 use system_error::SystemError;
 
 pub mod class;
@@ -14,7 +14,12 @@ mod dma;
 pub mod e1000e;
 pub mod irq_handle;
 pub mod loopback;
+pub mod netconsole;
+mod offload;
+pub mod packet_tap;
+pub mod stats;
 pub mod sysfs;
+pub mod tun;
 pub mod virtio_net;
 
 bitflags! {
@@ -63,8 +68,27 @@ pub trait NetDevice: Device {
 
     fn poll(&self, sockets: &mut iface::SocketSet) -> Result<(), SystemError>;
 
+    /// @brief 绕过协议栈，直接发送一个完整的以太网帧（供netconsole等使用）
+    ///
+    /// 该函数可能在中断上下文中被调用，只能尝试加锁，设备忙时返回`EAGAIN_OR_EWOULDBLOCK`
+    fn poll_xmit(&self, _frame: &[u8]) -> Result<(), SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
     fn update_ip_addrs(&self, ip_addrs: &[wire::IpCidr]) -> Result<(), SystemError>;
 
+    /// @brief 加入组播组，并立即发送一个IGMP成员报告
+    ///
+    /// 组播组的引用计数由`net_core`维护，这里只负责操作smoltcp的网卡接口
+    fn join_multicast_group(&self, _addr: wire::IpAddress) -> Result<(), SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    /// @brief 离开组播组，并发送一个IGMP离开报告
+    fn leave_multicast_group(&self, _addr: wire::IpAddress) -> Result<(), SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
     /// @brief 获取smoltcp的网卡接口类型
     fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface>;
     // fn as_any_ref(&'static self) -> &'static dyn core::any::Any;
@@ -117,3 +141,48 @@ fn register_netdevice(dev: Arc<dyn NetDevice>) -> Result<(), SystemError> {
 
     return Ok(());
 }
+
+/// 注销网络设备（与`register_netdevice`相反），从全局的网卡表和sysfs中删除它
+fn unregister_netdevice(dev: Arc<dyn NetDevice>) {
+    NET_DEVICES.write_irqsave().remove(&dev.nic_id());
+    stats::netdev_stats_remove(dev.nic_id());
+    device_manager().remove(&(dev as Arc<dyn Device>));
+}
+
+/// 用`cidr`替换网卡中第一个同一协议族的地址，没有这样的地址时添加它
+///
+/// 各个网卡的`update_ip_addrs`通过它设置地址，这样设置IPv4地址时不会覆盖IPv6的链路本地地址
+fn replace_ip_addr(iface: &mut iface::Interface, cidr: wire::IpCidr) -> Result<(), SystemError> {
+    let mut result = Ok(());
+    iface.update_ip_addrs(|addrs| {
+        let version = cidr.address().version();
+        match addrs.iter_mut().find(|a| a.address().version() == version) {
+            Some(dest) => *dest = cidr,
+            None => {
+                if addrs.push(cidr).is_err() {
+                    result = Err(SystemError::ENOSPC);
+                }
+            }
+        }
+    });
+    return result;
+}
+
+/// 由MAC地址生成网卡的IPv6链路本地地址（fe80::/64），接口标识为修改后的EUI-64
+///
+/// 网卡有了IPv6地址之后，smoltcp才会响应和发送邻居发现报文
+fn ipv6_link_local(mac: EthernetAddress) -> wire::IpCidr {
+    let mac = mac.as_bytes();
+    let mut addr = [0u8; 16];
+    addr[0] = 0xfe;
+    addr[1] = 0x80;
+    addr[8] = mac[0] ^ 0x02;
+    addr[9..11].copy_from_slice(&mac[1..3]);
+    addr[11] = 0xff;
+    addr[12] = 0xfe;
+    addr[13..16].copy_from_slice(&mac[3..6]);
+    wire::IpCidr::new(
+        wire::IpAddress::Ipv6(wire::Ipv6Address::from_bytes(&addr)),
+        64,
+    )
+}
@@ -0,0 +1,270 @@
+//! netconsole：通过UDP把内核日志发送到远程主机
+//!
+//! 通过内核命令行参数配置，格式与Linux相同：
+//!
+//! `netconsole=[src-port]@[src-ip]/[<dev>],[tgt-port]@<tgt-ip>/[tgt-macaddr]`
+//!
+//! 例如`netconsole=6665@10.0.2.15/eth0,6666@10.0.2.2/52:54:00:12:34:56`。
+//! 省略时源端口为6665，目标端口为6666，网卡为eth0，目标MAC为广播地址。
+//!
+//! 日志可能在中断上下文、甚至在协议栈持有锁的时候输出，因此这里不经过socket层和smoltcp，
+//! 而是自己构造以太网帧，通过网卡的`poll_xmit`直接发送。所有的锁都只尝试获取，
+//! 发生竞争时丢弃这一条日志，而不是等待。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/net/netconsole.c
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use log::{info, warn};
+use smoltcp::{
+    phy::ChecksumCapabilities,
+    wire::{
+        EthernetAddress, EthernetFrame, EthernetProtocol, IpAddress, IpProtocol, Ipv4Address,
+        Ipv4Packet, Ipv4Repr, UdpPacket, UdpRepr,
+    },
+};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    init::initcall::INITCALL_LATE,
+    libs::{
+        console::{register_console, Console, ConsoleFlags},
+        spinlock::SpinLock,
+    },
+    net::NET_DEVICES,
+};
+
+use super::NetDevice;
+
+kernel_cmdline_param_kv!(NETCONSOLE_PARAM, netconsole, "");
+
+const NETCONSOLE_DEFAULT_SRC_PORT: u16 = 6665;
+const NETCONSOLE_DEFAULT_TGT_PORT: u16 = 6666;
+const NETCONSOLE_DEFAULT_DEV: &str = "eth0";
+/// 以太网帧的最大长度（不含FCS）
+const NETCONSOLE_FRAME_SIZE: usize = 1514;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+const ETHERNET_HEADER_LEN: usize = 14;
+/// 一个UDP报文能携带的最大日志长度，更长的日志会被拆成多个报文
+const NETCONSOLE_MAX_PAYLOAD: usize =
+    NETCONSOLE_FRAME_SIZE - ETHERNET_HEADER_LEN - IPV4_HEADER_LEN - UDP_HEADER_LEN;
+
+/// 解析后的netconsole配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NetConsoleConfig<'a> {
+    src_port: u16,
+    src_ip: Option<Ipv4Address>,
+    dev: &'a str,
+    tgt_port: u16,
+    tgt_ip: Ipv4Address,
+    tgt_mac: EthernetAddress,
+}
+
+impl<'a> NetConsoleConfig<'a> {
+    fn parse(s: &'a str) -> Option<Self> {
+        let (src, tgt) = s.split_once(',')?;
+
+        let (src_port, rest) = src.split_once('@')?;
+        let src_port = parse_port(src_port, NETCONSOLE_DEFAULT_SRC_PORT)?;
+        let (src_ip, dev) = rest.split_once('/').unwrap_or((rest, ""));
+        let src_ip = match src_ip {
+            "" => None,
+            ip => Some(parse_ipv4(ip)?),
+        };
+        let dev = if dev.is_empty() {
+            NETCONSOLE_DEFAULT_DEV
+        } else {
+            dev
+        };
+
+        let (tgt_port, rest) = tgt.split_once('@')?;
+        let tgt_port = parse_port(tgt_port, NETCONSOLE_DEFAULT_TGT_PORT)?;
+        let (tgt_ip, tgt_mac) = rest.split_once('/').unwrap_or((rest, ""));
+        let tgt_ip = parse_ipv4(tgt_ip)?;
+        let tgt_mac = match tgt_mac {
+            "" => EthernetAddress::BROADCAST,
+            mac => parse_mac(mac)?,
+        };
+
+        Some(Self {
+            src_port,
+            src_ip,
+            dev,
+            tgt_port,
+            tgt_ip,
+            tgt_mac,
+        })
+    }
+}
+
+fn parse_port(s: &str, default: u16) -> Option<u16> {
+    if s.is_empty() {
+        return Some(default);
+    }
+    s.parse().ok()
+}
+
+fn parse_ipv4(s: &str) -> Option<Ipv4Address> {
+    let mut octets = [0u8; 4];
+    let mut parts = s.split('.');
+    for octet in octets.iter_mut() {
+        *octet = parts.next()?.parse().ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(Ipv4Address(octets))
+}
+
+fn parse_mac(s: &str) -> Option<EthernetAddress> {
+    let mut bytes = [0u8; 6];
+    let mut parts = s.split(':');
+    for byte in bytes.iter_mut() {
+        *byte = u8::from_str_radix(parts.next()?, 16).ok()?;
+    }
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(EthernetAddress(bytes))
+}
+
+/// 发送日志所需的全部状态，在初始化时确定下来
+#[derive(Debug)]
+struct NetConsoleTarget {
+    dev: Arc<dyn NetDevice>,
+    src_mac: EthernetAddress,
+    src_ip: Ipv4Address,
+    src_port: u16,
+    tgt_mac: EthernetAddress,
+    tgt_ip: Ipv4Address,
+    tgt_port: u16,
+    /// 预先分配的帧缓冲区，避免在输出日志时分配内存
+    frame: Vec<u8>,
+}
+
+impl NetConsoleTarget {
+    /// 构造一个携带`payload`的UDP帧，返回帧的长度
+    fn build_frame(&mut self, payload: &[u8]) -> usize {
+        let udp_len = UDP_HEADER_LEN + payload.len();
+        let ip_repr = Ipv4Repr {
+            src_addr: self.src_ip,
+            dst_addr: self.tgt_ip,
+            next_header: IpProtocol::Udp,
+            payload_len: udp_len,
+            hop_limit: 64,
+        };
+        let udp_repr = UdpRepr {
+            src_port: self.src_port,
+            dst_port: self.tgt_port,
+        };
+        let checksum = ChecksumCapabilities::default();
+        let len = ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + udp_len;
+
+        let mut eth = EthernetFrame::new_unchecked(&mut self.frame[..len]);
+        eth.set_src_addr(self.src_mac);
+        eth.set_dst_addr(self.tgt_mac);
+        eth.set_ethertype(EthernetProtocol::Ipv4);
+
+        let mut ip = Ipv4Packet::new_unchecked(eth.payload_mut());
+        ip_repr.emit(&mut ip, &checksum);
+
+        let mut udp = UdpPacket::new_unchecked(ip.payload_mut());
+        udp_repr.emit(
+            &mut udp,
+            &IpAddress::Ipv4(self.src_ip),
+            &IpAddress::Ipv4(self.tgt_ip),
+            payload.len(),
+            |buf| buf.copy_from_slice(payload),
+            &checksum,
+        );
+
+        len
+    }
+
+    fn send(&mut self, s: &str) {
+        for chunk in s.as_bytes().chunks(NETCONSOLE_MAX_PAYLOAD) {
+            let len = self.build_frame(chunk);
+            // 网卡忙时丢弃这条日志，不能在这里等待
+            if self.dev.poll_xmit(&self.frame[..len]).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct NetConsole {
+    target: SpinLock<Option<NetConsoleTarget>>,
+}
+
+static NETCONSOLE: NetConsole = NetConsole {
+    target: SpinLock::new(None),
+};
+
+impl Console for NetConsole {
+    fn name(&self) -> &'static str {
+        "netcon0"
+    }
+
+    fn write(&self, s: &str) {
+        // 发送过程中网卡驱动打印日志时，会重入到这里，此时获取锁失败，日志被丢弃
+        if let Ok(mut target) = self.target.try_lock_irqsave() {
+            if let Some(target) = target.as_mut() {
+                target.send(s);
+            }
+        }
+    }
+}
+
+#[unified_init(INITCALL_LATE)]
+fn netconsole_init() -> Result<(), SystemError> {
+    let Some(param) = NETCONSOLE_PARAM.value_str().filter(|s| !s.is_empty()) else {
+        return Ok(());
+    };
+    let Some(config) = NetConsoleConfig::parse(param) else {
+        warn!("netconsole: invalid parameter: {}", param);
+        return Err(SystemError::EINVAL);
+    };
+
+    let dev = NET_DEVICES
+        .read_irqsave()
+        .values()
+        .find(|dev| dev.iface_name() == config.dev)
+        .cloned()
+        .ok_or_else(|| {
+            warn!("netconsole: network device {} not found", config.dev);
+            SystemError::ENODEV
+        })?;
+
+    // 没有指定源地址时，使用网卡的IPv4地址
+    let src_ip = match config.src_ip {
+        Some(ip) => ip,
+        None => dev
+            .inner_iface()
+            .lock_irqsave()
+            .ipv4_addr()
+            .ok_or_else(|| {
+                warn!("netconsole: {} has no IPv4 address", config.dev);
+                SystemError::EADDRNOTAVAIL
+            })?,
+    };
+
+    *NETCONSOLE.target.lock_irqsave() = Some(NetConsoleTarget {
+        src_mac: dev.mac(),
+        dev,
+        src_ip,
+        src_port: config.src_port,
+        tgt_mac: config.tgt_mac,
+        tgt_ip: config.tgt_ip,
+        tgt_port: config.tgt_port,
+        frame: vec![0; NETCONSOLE_FRAME_SIZE],
+    });
+    register_console(&NETCONSOLE, ConsoleFlags::empty())?;
+
+    info!(
+        "netconsole: sending kernel log from {}:{} to {}:{} ({}) via {}",
+        src_ip, config.src_port, config.tgt_ip, config.tgt_port, config.tgt_mac, config.dev
+    );
+    Ok(())
+}
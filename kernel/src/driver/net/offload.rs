@@ -0,0 +1,365 @@
+//! 软件实现的GRO（接收合并）与GSO（发送分段）
+//!
+//! QEMU中的网卡MTU很小，批量传输时协议栈要为每个小包走一遍完整的处理流程。
+//! [`super::packet_tap::PacketTap`]在网卡与协议栈之间做两件事：
+//!
+//! - 接收时把同一TCP连接中连续到达的数据段合并为一个大的数据段再交给协议栈（[`GroHead`]）；
+//! - 向协议栈声明一个很大的MTU，发送时再把超过网卡MTU的帧切分为多个帧（[`gso_segment`]）。
+//!
+//! smoltcp按照对端通告的MSS决定数据段的大小，因此接收到的SYN中的MSS被改大，
+//! 原来的值记录在[`GSO_FLOWS`]中，发送时按照它切分。只有IPv4的TCP数据段会被合并和切分，
+//! 其他超过网卡MTU的IPv4数据包被分片，不能分片的数据包被丢弃。
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, IpAddress, IpProtocol, Ipv4Packet, TcpPacket,
+};
+
+use crate::libs::spinlock::SpinLock;
+
+/// 合并之后以及交给[`gso_segment`]的IP数据包的最大长度
+pub const GSO_MAX_SIZE: usize = 65535;
+
+/// 同时记录MSS的TCP连接数的上限，超过之后新连接不再改写MSS
+const GSO_FLOWS_MAX: usize = 1024;
+
+const ETH_HLEN: usize = 14;
+const IPV4_HLEN: usize = 20;
+const TCP_HLEN: usize = 20;
+/// TCP的MSS选项
+const TCP_OPT_MSS: u8 = 2;
+
+/// 网卡的MTU小于这个值时才启用GRO/GSO，回环网卡等MTU足够大的设备不需要
+pub fn offload_enabled(mtu: usize) -> bool {
+    mtu < GSO_MAX_SIZE
+}
+
+/// 网卡MTU下TCP数据段能携带的最大数据量
+pub fn offload_mss(mtu: usize) -> u16 {
+    mtu.saturating_sub(ETH_HLEN + IPV4_HLEN + TCP_HLEN) as u16
+}
+
+/// 改写过MSS的TCP连接，以本端的视角表示：（网卡id，本端地址，本端端口，对端地址，对端端口）
+type GsoFlowKey = (usize, u32, u16, u32, u16);
+
+#[derive(Debug, Clone, Copy)]
+struct GsoFlow {
+    /// 对端原本通告的MSS
+    mss: u16,
+    fin_rx: bool,
+    fin_tx: bool,
+}
+
+static GSO_FLOWS: SpinLock<BTreeMap<GsoFlowKey, GsoFlow>> = SpinLock::new(BTreeMap::new());
+
+/// 解析以太网帧中的IPv4 TCP数据段，不是时返回None
+fn tcp_view(frame: &[u8]) -> Option<(Ipv4Packet<&[u8]>, TcpPacket<&[u8]>)> {
+    let eth = EthernetFrame::new_checked(frame).ok()?;
+    if eth.ethertype() != EthernetProtocol::Ipv4 {
+        return None;
+    }
+    let ip = Ipv4Packet::new_checked(&frame[ETH_HLEN..]).ok()?;
+    if ip.next_header() != IpProtocol::Tcp || ip.more_frags() || ip.frag_offset() != 0 {
+        return None;
+    }
+    let tcp = TcpPacket::new_checked(ip.payload()).ok()?;
+    Some((ip, tcp))
+}
+
+fn tcp_checksum_ok(ip: &Ipv4Packet<&[u8]>, tcp: &TcpPacket<&[u8]>) -> bool {
+    ip.verify_checksum() && tcp.verify_checksum(&ip.src_addr().into(), &ip.dst_addr().into())
+}
+
+/// 重新计算帧中IPv4 TCP数据段的校验和
+fn tcp_fill_checksums(frame: &mut [u8]) {
+    let mut ip = Ipv4Packet::new_unchecked(&mut frame[ETH_HLEN..]);
+    let (src, dst) = (
+        IpAddress::Ipv4(ip.src_addr()),
+        IpAddress::Ipv4(ip.dst_addr()),
+    );
+    TcpPacket::new_unchecked(ip.payload_mut()).fill_checksum(&src, &dst);
+    ip.fill_checksum();
+}
+
+/// 在TCP头部的选项中查找MSS选项，返回它的值在TCP头部中的偏移
+fn tcp_mss_offset(tcp: &TcpPacket<&[u8]>) -> Option<usize> {
+    let options = tcp.options();
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            0 => return None,
+            1 => i += 1,
+            kind => {
+                let len = *options.get(i + 1)? as usize;
+                if len < 2 || i + len > options.len() {
+                    return None;
+                }
+                if kind == TCP_OPT_MSS && len == 4 {
+                    return Some(TCP_HLEN + i + 2);
+                }
+                i += len;
+            }
+        }
+    }
+    None
+}
+
+fn gso_flow_key(
+    nic_id: usize,
+    ip: &Ipv4Packet<&[u8]>,
+    tcp: &TcpPacket<&[u8]>,
+    rx: bool,
+) -> GsoFlowKey {
+    let src = (u32::from_be_bytes(ip.src_addr().0), tcp.src_port());
+    let dst = (u32::from_be_bytes(ip.dst_addr().0), tcp.dst_port());
+    let (local, remote) = if rx { (dst, src) } else { (src, dst) };
+    (nic_id, local.0, local.1, remote.0, remote.1)
+}
+
+/// 跟踪经过网卡的TCP连接的MSS，收发的每一帧都要经过这里
+///
+/// - 收到带MSS选项的SYN时，记录对端的MSS，并把它改为[`GSO_MAX_SIZE`]允许的最大值，
+///   让协议栈发出大的数据段；
+/// - 发出的SYN中的MSS不超过网卡MTU所允许的值，使对端发来的帧不会超过MTU；
+/// - 连接被重置或者双方都关闭之后删除记录。
+///
+/// 发送的帧要在地址转换之前、接收的帧要在地址还原之后经过这里，这样连接总是以本机的地址表示
+pub fn gso_track(nic_id: usize, frame: &mut [u8], mtu: usize, rx: bool) {
+    let Some((ip, tcp)) = tcp_view(frame) else {
+        return;
+    };
+    if !(tcp.syn() || tcp.fin() || tcp.rst()) {
+        return;
+    }
+    let key = gso_flow_key(nic_id, &ip, &tcp, rx);
+    let dev_mss = offload_mss(mtu);
+
+    if tcp.rst() {
+        GSO_FLOWS.lock_irqsave().remove(&key);
+        return;
+    }
+    if tcp.fin() {
+        let mut flows = GSO_FLOWS.lock_irqsave();
+        if let Some(flow) = flows.get_mut(&key) {
+            if rx {
+                flow.fin_rx = true;
+            } else {
+                flow.fin_tx = true;
+            }
+            if flow.fin_rx && flow.fin_tx {
+                flows.remove(&key);
+            }
+        }
+        return;
+    }
+
+    // 改写之后要重新计算校验和，校验和原本就不对的数据段保持原样，交给协议栈丢弃
+    let Some(offset) = tcp_mss_offset(&tcp) else {
+        return;
+    };
+    if !tcp_checksum_ok(&ip, &tcp) {
+        return;
+    }
+    let start = ETH_HLEN + ip.header_len() as usize + offset;
+    let mss = u16::from_be_bytes([frame[start], frame[start + 1]]);
+    let new_mss = if rx {
+        let mut flows = GSO_FLOWS.lock_irqsave();
+        if flows.len() >= GSO_FLOWS_MAX && !flows.contains_key(&key) {
+            return;
+        }
+        flows.insert(
+            key,
+            GsoFlow {
+                mss,
+                fin_rx: false,
+                fin_tx: false,
+            },
+        );
+        (GSO_MAX_SIZE - IPV4_HLEN - TCP_HLEN) as u16
+    } else {
+        mss.min(dev_mss)
+    };
+    if new_mss == mss {
+        return;
+    }
+    frame[start..start + 2].copy_from_slice(&new_mss.to_be_bytes());
+    tcp_fill_checksums(frame);
+}
+
+/// 发送`frame`时每个数据段携带的最大数据量
+///
+/// 连接的MSS没有被改写过时使用网卡MTU所允许的值
+pub fn gso_mss(nic_id: usize, frame: &[u8], mtu: usize) -> u16 {
+    let dev_mss = offload_mss(mtu);
+    let Some((ip, tcp)) = tcp_view(frame) else {
+        return dev_mss;
+    };
+    let key = gso_flow_key(nic_id, &ip, &tcp, false);
+    GSO_FLOWS
+        .lock_irqsave()
+        .get(&key)
+        .map_or(dev_mss, |flow| flow.mss.min(dev_mss))
+}
+
+/// 把超过网卡MTU的帧切分为多个不超过MTU的帧
+///
+/// IPv4 TCP数据段按照`mss`切分，其他IPv4数据包被分片。设置了DF标志的非TCP数据包以及其他协议的帧不能切分，
+/// 此时返回空的数组，帧被丢弃
+pub fn gso_segment(frame: &[u8], mss: u16, mtu: usize) -> Vec<Vec<u8>> {
+    if let Some((ip, tcp)) = tcp_view(frame) {
+        return gso_segment_tcp(frame, &ip, &tcp, mss, mtu);
+    }
+    let Ok(eth) = EthernetFrame::new_checked(frame) else {
+        return Vec::new();
+    };
+    if eth.ethertype() != EthernetProtocol::Ipv4 {
+        return Vec::new();
+    }
+    match Ipv4Packet::new_checked(&frame[ETH_HLEN..]) {
+        Ok(ip) if !ip.dont_frag() => gso_fragment(frame, &ip, mtu),
+        _ => Vec::new(),
+    }
+}
+
+fn gso_segment_tcp(
+    frame: &[u8],
+    ip: &Ipv4Packet<&[u8]>,
+    tcp: &TcpPacket<&[u8]>,
+    mss: u16,
+    mtu: usize,
+) -> Vec<Vec<u8>> {
+    let hdr_len = ETH_HLEN + ip.header_len() as usize + tcp.header_len() as usize;
+    let seg_size = (mss as usize).min(mtu.saturating_sub(hdr_len));
+    let payload = tcp.payload();
+    if seg_size == 0 {
+        return Vec::new();
+    }
+
+    let count = payload.len().div_ceil(seg_size);
+    let mut segments = Vec::with_capacity(count);
+    for (i, chunk) in payload.chunks(seg_size).enumerate() {
+        let mut seg = Vec::with_capacity(hdr_len + chunk.len());
+        seg.extend_from_slice(&frame[..hdr_len]);
+        seg.extend_from_slice(chunk);
+
+        let mut seg_ip = Ipv4Packet::new_unchecked(&mut seg[ETH_HLEN..]);
+        seg_ip.set_total_len((hdr_len - ETH_HLEN + chunk.len()) as u16);
+        seg_ip.set_ident(ip.ident().wrapping_add(i as u16));
+        let mut seg_tcp = TcpPacket::new_unchecked(seg_ip.payload_mut());
+        seg_tcp.set_seq_number(tcp.seq_number() + i * seg_size);
+        // FIN和PSH只出现在最后一个数据段中
+        if i + 1 != count {
+            seg_tcp.set_fin(false);
+            seg_tcp.set_psh(false);
+        }
+        tcp_fill_checksums(&mut seg);
+        segments.push(seg);
+    }
+    segments
+}
+
+fn gso_fragment(frame: &[u8], ip: &Ipv4Packet<&[u8]>, mtu: usize) -> Vec<Vec<u8>> {
+    let hdr_len = ETH_HLEN + ip.header_len() as usize;
+    // 除最后一片以外，每一片的长度必须是8的整数倍
+    let frag_size = mtu.saturating_sub(hdr_len) & !7;
+    let payload = ip.payload();
+    if frag_size == 0 {
+        return Vec::new();
+    }
+
+    let count = payload.len().div_ceil(frag_size);
+    let mut frags = Vec::with_capacity(count);
+    for (i, chunk) in payload.chunks(frag_size).enumerate() {
+        let mut frag = Vec::with_capacity(hdr_len + chunk.len());
+        frag.extend_from_slice(&frame[..hdr_len]);
+        frag.extend_from_slice(chunk);
+
+        let mut frag_ip = Ipv4Packet::new_unchecked(&mut frag[ETH_HLEN..]);
+        frag_ip.set_total_len((ip.header_len() as usize + chunk.len()) as u16);
+        // 被切分的数据包本身可能就是一个分片
+        frag_ip.set_frag_offset(ip.frag_offset() + (i * frag_size) as u16);
+        frag_ip.set_more_frags(i + 1 != count || ip.more_frags());
+        frag_ip.fill_checksum();
+        frags.push(frag);
+    }
+    frags
+}
+
+/// 正在合并的TCP数据段
+///
+/// 可以合并的数据段属于同一个连接、序号连续、确认号窗口和选项都相同、只带有ACK（最后一个可以带PSH）标志，
+/// 并且校验和正确。合并之后的数据段带有第一个数据段的头部
+pub struct GroHead {
+    frame: Vec<u8>,
+    merged: bool,
+}
+
+impl GroHead {
+    /// 以`frame`作为合并的第一个数据段，它不能参与合并时原样返回
+    pub fn new(frame: Vec<u8>) -> Result<Self, Vec<u8>> {
+        match Self::candidate(&frame) {
+            Some(_) => Ok(Self {
+                frame,
+                merged: false,
+            }),
+            None => Err(frame),
+        }
+    }
+
+    /// 检查帧能否参与合并，能时返回它的IP和TCP头部
+    fn candidate(frame: &[u8]) -> Option<(Ipv4Packet<&[u8]>, TcpPacket<&[u8]>)> {
+        let (ip, tcp) = tcp_view(frame)?;
+        let plain = ip.header_len() as usize == IPV4_HLEN
+            && tcp.ack()
+            && !(tcp.syn() || tcp.fin() || tcp.rst() || tcp.urg() || tcp.ece() || tcp.cwr())
+            && !tcp.payload().is_empty();
+        (plain && tcp_checksum_ok(&ip, &tcp)).then_some((ip, tcp))
+    }
+
+    /// 尝试把`frame`合并到已有的数据段之后，不能合并时原样返回
+    pub fn try_merge(&mut self, frame: Vec<u8>) -> Result<(), Vec<u8>> {
+        let Some((ip, tcp)) = Self::candidate(&frame) else {
+            return Err(frame);
+        };
+        let Some((head_ip, head_tcp)) = tcp_view(&self.frame) else {
+            return Err(frame);
+        };
+        let mergeable = !head_tcp.psh()
+            && head_ip.src_addr() == ip.src_addr()
+            && head_ip.dst_addr() == ip.dst_addr()
+            && head_ip.hop_limit() == ip.hop_limit()
+            && head_ip.dscp() == ip.dscp()
+            && head_ip.ecn() == ip.ecn()
+            && head_tcp.src_port() == tcp.src_port()
+            && head_tcp.dst_port() == tcp.dst_port()
+            && head_tcp.ack_number() == tcp.ack_number()
+            && head_tcp.window_len() == tcp.window_len()
+            && head_tcp.options() == tcp.options()
+            && head_tcp.seq_number() + head_tcp.payload().len() == tcp.seq_number()
+            && head_ip.total_len() as usize + tcp.payload().len() <= GSO_MAX_SIZE;
+        if !mergeable {
+            return Err(frame);
+        }
+
+        let (data, psh) = (tcp.payload(), tcp.psh());
+        // 去掉以太网帧末尾可能存在的填充
+        let total_len = head_ip.total_len() as usize;
+        self.frame.truncate(ETH_HLEN + total_len);
+        self.frame.extend_from_slice(data);
+        let mut head_ip = Ipv4Packet::new_unchecked(&mut self.frame[ETH_HLEN..]);
+        head_ip.set_total_len((total_len + data.len()) as u16);
+        if psh {
+            TcpPacket::new_unchecked(head_ip.payload_mut()).set_psh(true);
+        }
+        self.merged = true;
+        Ok(())
+    }
+
+    /// 结束合并，返回合并之后的帧
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.merged {
+            tcp_fill_checksums(&mut self.frame);
+        }
+        self.frame
+    }
+}
@@ -0,0 +1,269 @@
+//! 网卡收发路径上的抓包点
+//!
+//! 驱动把自己的smoltcp设备包装为[`PacketTap`]之后再交给协议栈，
+//! 这样协议栈收到和发出的每一个以太网帧都会先交给AF_PACKET套接字（见`net::socket::packet`）。
+//!
+//! netfilter的钩子也在这里执行（见`net::netfilter`）。与Linux相同，
+//! AF_PACKET套接字能看到被防火墙丢弃的接收帧，但看不到被丢弃的发送帧；
+//! 接收帧在地址还原之前、发送帧在地址转换之后交给AF_PACKET套接字。
+//!
+//! 收发的TCP报文段也在这里统计，供TCP_INFO使用（见`net::socket::tcp_info`）。
+//! 网卡的收发计数同样在这里更新（见[`super::stats`]）。
+//!
+//! 属于绑定了其他网卡（SO_BINDTODEVICE）的socket的帧也在这里丢弃，见`PortManager::device_allows_frame`。
+//!
+//! MTU较小的网卡在这里做GRO和GSO（见[`super::offload`]）：抓包点和防火墙看到的接收帧是合并之前的，
+//! 发送帧则是切分之前的大帧经过防火墙，切分之后的每一帧分别交给AF_PACKET套接字。
+//!
+//! 网卡绑定了AF_XDP套接字时，收到的帧在最开始就交给它（见`net::socket::xdp`），
+//! 不再经过抓包点、防火墙和协议栈，只计入网卡的接收统计。
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use smoltcp::{
+    phy::{self, DeviceCapabilities, PacketMeta},
+    time::Instant,
+    wire::EthernetAddress,
+};
+
+use crate::{
+    libs::spinlock::SpinLock,
+    net::{
+        netfilter::{nf_hook_rx, nf_hook_tx, nf_tx_hooks_active},
+        socket::{packet::packet_rcv, tcp_info::tcp_info_rcv, xdp::xsk_rcv, PORT_MANAGER},
+    },
+};
+
+use super::{
+    offload::{gso_mss, gso_segment, gso_track, offload_enabled, GroHead, GSO_MAX_SIZE},
+    stats::{netdev_stats_rx, netdev_stats_rx_dropped, netdev_stats_tx, netdev_stats_tx_dropped},
+};
+
+/// 合并时多读出的、不能与前面的数据段合并的帧，key为网卡id
+///
+/// [`PacketTap`]在每次轮询网卡时重新创建，因此放在这里，下次接收时最先交给协议栈
+static GRO_HELD: SpinLock<BTreeMap<usize, Vec<u8>>> = SpinLock::new(BTreeMap::new());
+
+/// 把收发的帧交给AF_PACKET套接字，并统计网卡的收发以及其中的TCP报文段
+fn tap(nic_id: usize, mac: EthernetAddress, frame: &[u8], outgoing: bool) {
+    if outgoing {
+        netdev_stats_tx(nic_id, frame.len());
+    } else {
+        netdev_stats_rx(nic_id, frame);
+    }
+    packet_rcv(nic_id, mac, frame, outgoing);
+    tcp_info_rcv(frame, outgoing);
+}
+
+/// 包装一个smoltcp设备，在收发的帧经过时调用[`tap`]以及netfilter的钩子
+pub struct PacketTap<'a, D: phy::Device> {
+    inner: &'a mut D,
+    nic_id: usize,
+    mac: EthernetAddress,
+}
+
+impl<'a, D: phy::Device> PacketTap<'a, D> {
+    /// ## 参数
+    ///
+    /// - `inner`: 网卡驱动的smoltcp设备
+    /// - `nic_id`: 网卡的id
+    /// - `mac`: 网卡的MAC地址
+    pub fn new(inner: &'a mut D, nic_id: usize, mac: EthernetAddress) -> Self {
+        Self { inner, nic_id, mac }
+    }
+
+    /// 从网卡读出下一个通过了防火墙的帧
+    fn next_frame(&mut self, timestamp: Instant) -> Option<Vec<u8>> {
+        if let Some(frame) = GRO_HELD.lock_irqsave().remove(&self.nic_id) {
+            return Some(frame);
+        }
+        let (nic_id, mac) = (self.nic_id, self.mac);
+        let mtu = self.inner.capabilities().max_transmission_unit;
+        loop {
+            let (rx, _) = self.inner.receive(timestamp)?;
+            let mut redirected = false;
+            let frame = phy::RxToken::consume(rx, |frame| {
+                if xsk_rcv(nic_id, frame) {
+                    netdev_stats_rx(nic_id, frame);
+                    redirected = true;
+                    return None;
+                }
+                tap(nic_id, mac, frame, false);
+                let accepted = nf_hook_rx(nic_id, frame)
+                    && PORT_MANAGER.device_allows_frame(nic_id, frame, false);
+                accepted.then(|| frame.to_vec())
+            });
+            if redirected {
+                continue;
+            }
+            // 被丢弃的帧不交给协议栈，继续读下一个
+            let Some(mut frame) = frame else {
+                netdev_stats_rx_dropped(nic_id);
+                continue;
+            };
+            if offload_enabled(mtu) {
+                gso_track(nic_id, &mut frame, mtu, true);
+            }
+            return Some(frame);
+        }
+    }
+
+    /// 把网卡中紧接着`frame`到达的、可以合并的TCP数据段合并到它之后
+    fn gro_receive(&mut self, timestamp: Instant, frame: Vec<u8>) -> Vec<u8> {
+        let mut head = match GroHead::new(frame) {
+            Ok(head) => head,
+            Err(frame) => return frame,
+        };
+        while let Some(next) = self.next_frame(timestamp) {
+            if let Err(next) = head.try_merge(next) {
+                GRO_HELD.lock_irqsave().insert(self.nic_id, next);
+                break;
+            }
+        }
+        head.finish()
+    }
+
+    fn tx_token(&mut self, timestamp: Instant) -> PacketTapTxToken<'_, D> {
+        PacketTapTxToken {
+            device: &mut *self.inner,
+            nic_id: self.nic_id,
+            mac: self.mac,
+            timestamp,
+            meta: PacketMeta::default(),
+        }
+    }
+}
+
+impl<'a, D: phy::Device> phy::Device for PacketTap<'a, D> {
+    type RxToken<'b>
+        = PacketTapRxToken
+    where
+        Self: 'b;
+    type TxToken<'b>
+        = PacketTapTxToken<'b, D>
+    where
+        Self: 'b;
+
+    fn receive(&mut self, timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let mut frame = self.next_frame(timestamp)?;
+        if offload_enabled(self.inner.capabilities().max_transmission_unit) {
+            frame = self.gro_receive(timestamp, frame);
+        }
+        Some((PacketTapRxToken { frame }, self.tx_token(timestamp)))
+    }
+
+    fn transmit(&mut self, timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        // 真正的发送token在消费时才获取，这里先确认网卡此时可以发送
+        self.inner.transmit(timestamp)?;
+        Some(self.tx_token(timestamp))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = self.inner.capabilities();
+        if offload_enabled(caps.max_transmission_unit) {
+            // 以太网设备的MTU包含以太网头部
+            caps.max_transmission_unit = 14 + GSO_MAX_SIZE;
+        }
+        caps
+    }
+}
+
+/// 接收到的帧，已经经过了抓包点、防火墙和GRO
+pub struct PacketTapRxToken {
+    frame: Vec<u8>,
+}
+
+impl phy::RxToken for PacketTapRxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.frame)
+    }
+
+    fn meta(&self) -> PacketMeta {
+        PacketMeta::default()
+    }
+}
+
+/// 在消费时才从网卡获取发送token，这样一个超过网卡MTU的帧可以被切分为多个帧发送
+pub struct PacketTapTxToken<'a, D: phy::Device> {
+    device: &'a mut D,
+    nic_id: usize,
+    mac: EthernetAddress,
+    timestamp: Instant,
+    meta: PacketMeta,
+}
+
+impl<'a, D: phy::Device> PacketTapTxToken<'a, D> {
+    /// 把构造好的帧交给网卡，网卡无法发送时返回false
+    fn send(&mut self, buf: &[u8]) -> bool {
+        let (nic_id, mac) = (self.nic_id, self.mac);
+        let Some(mut tx) = self.device.transmit(self.timestamp) else {
+            netdev_stats_tx_dropped(nic_id);
+            return false;
+        };
+        tx.set_meta(self.meta);
+        phy::TxToken::consume(tx, buf.len(), |frame| {
+            frame.copy_from_slice(buf);
+            tap(nic_id, mac, frame, true);
+        });
+        true
+    }
+}
+
+impl<'a, D: phy::Device> phy::TxToken for PacketTapTxToken<'a, D> {
+    fn consume<R, F>(mut self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let (nic_id, mac) = (self.nic_id, self.mac);
+        let mtu = self.device.capabilities().max_transmission_unit;
+        let offload = offload_enabled(mtu);
+        if len <= mtu && !nf_tx_hooks_active() && !PORT_MANAGER.has_bound_devices() {
+            if let Some(mut tx) = self.device.transmit(self.timestamp) {
+                tx.set_meta(self.meta);
+                return phy::TxToken::consume(tx, len, |frame| {
+                    let result = f(frame);
+                    if offload {
+                        gso_track(nic_id, frame, mtu, false);
+                    }
+                    tap(nic_id, mac, frame, true);
+                    result
+                });
+            }
+        }
+
+        // 先在临时缓冲区里构造帧，经过防火墙之后再交给网卡，被丢弃时直接丢掉。
+        // 地址转换会修改源端口，因此先检查socket绑定的网卡，并按照转换之前的连接找到切分时使用的MSS
+        let mut buf = vec![0u8; len];
+        let result = f(&mut buf);
+        if !PORT_MANAGER.device_allows_frame(nic_id, &buf, true) {
+            return result;
+        }
+        if offload {
+            gso_track(nic_id, &mut buf, mtu, false);
+        }
+        let mss = (len > mtu).then(|| gso_mss(nic_id, &buf, mtu));
+        if !nf_hook_tx(nic_id, &mut buf) {
+            return result;
+        }
+        match mss {
+            None => {
+                self.send(&buf);
+            }
+            Some(mss) => {
+                // 网卡发送队列满时丢弃剩下的数据段，由TCP重传
+                for seg in gso_segment(&buf, mss, mtu) {
+                    if !self.send(&seg) {
+                        break;
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn set_meta(&mut self, meta: PacketMeta) {
+        self.meta = meta;
+    }
+}
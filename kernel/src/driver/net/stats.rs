@@ -0,0 +1,152 @@
+//! 网卡的收发统计
+//!
+//! 经过[`super::packet_tap::PacketTap`]以及`poll_xmit`收发的每一个帧都在这里计数，
+//! 统计结果通过`/proc/net/dev`、`/sys/class/net/<网卡>/statistics/`和rtnetlink的
+//! `IFLA_STATS`/`IFLA_STATS64`导出，供`ifconfig`、`ip -s link`和`getifaddrs`使用。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/include/uapi/linux/if_link.h#rtnl_link_stats64
+
+use alloc::{collections::BTreeMap, string::String};
+use core::fmt::Write;
+
+use smoltcp::wire::EthernetFrame;
+
+use crate::{libs::spinlock::SpinLock, net::NET_DEVICES};
+
+/// 一个网卡的收发计数
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NetDeviceStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// 不完整的帧
+    pub rx_errors: u64,
+    /// 网卡发送失败的帧
+    pub tx_errors: u64,
+    /// 收到之后在交给协议栈之前被丢弃（防火墙、SO_BINDTODEVICE）的帧
+    pub rx_dropped: u64,
+    /// 网卡发送队列已满而丢弃的帧
+    pub tx_dropped: u64,
+    /// 收到的组播和广播帧
+    pub multicast: u64,
+}
+
+impl NetDeviceStats {
+    /// 按照`struct rtnl_link_stats64`的布局输出，没有统计的字段为0
+    pub fn to_stats64(&self) -> [u64; 24] {
+        let mut s = [0u64; 24];
+        s[..9].copy_from_slice(&[
+            self.rx_packets,
+            self.tx_packets,
+            self.rx_bytes,
+            self.tx_bytes,
+            self.rx_errors,
+            self.tx_errors,
+            self.rx_dropped,
+            self.tx_dropped,
+            self.multicast,
+        ]);
+        s
+    }
+
+    /// 按照`struct rtnl_link_stats`的布局输出，计数按32位截断
+    pub fn to_stats32(&self) -> [u32; 24] {
+        self.to_stats64().map(|v| v as u32)
+    }
+}
+
+/// 各个网卡的计数，key为网卡id
+static NETDEV_STATS: SpinLock<BTreeMap<usize, NetDeviceStats>> = SpinLock::new(BTreeMap::new());
+
+fn netdev_stats_update(nic_id: usize, f: impl FnOnce(&mut NetDeviceStats)) {
+    f(NETDEV_STATS.lock_irqsave().entry(nic_id).or_default());
+}
+
+/// 获取网卡当前的计数
+pub fn netdev_stats(nic_id: usize) -> NetDeviceStats {
+    NETDEV_STATS
+        .lock_irqsave()
+        .get(&nic_id)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// 网卡被注销时删除它的计数，之后注册的网卡可能复用这个id
+pub(super) fn netdev_stats_remove(nic_id: usize) {
+    NETDEV_STATS.lock_irqsave().remove(&nic_id);
+}
+
+/// 记录网卡收到的一个帧，连以太网头部都不完整的帧记为错误
+pub fn netdev_stats_rx(nic_id: usize, frame: &[u8]) {
+    let eth = EthernetFrame::new_checked(frame);
+    netdev_stats_update(nic_id, |s| {
+        s.rx_packets += 1;
+        s.rx_bytes += frame.len() as u64;
+        match eth {
+            Ok(eth) if eth.dst_addr().is_multicast() || eth.dst_addr().is_broadcast() => {
+                s.multicast += 1
+            }
+            Ok(_) => {}
+            Err(_) => s.rx_errors += 1,
+        }
+    });
+}
+
+/// 记录网卡发出的一个帧
+pub fn netdev_stats_tx(nic_id: usize, len: usize) {
+    netdev_stats_update(nic_id, |s| {
+        s.tx_packets += 1;
+        s.tx_bytes += len as u64;
+    });
+}
+
+/// 记录一个收到之后没有交给协议栈的帧
+pub fn netdev_stats_rx_dropped(nic_id: usize) {
+    netdev_stats_update(nic_id, |s| s.rx_dropped += 1);
+}
+
+/// 记录一个因为网卡忙而没有发出的帧
+pub fn netdev_stats_tx_dropped(nic_id: usize) {
+    netdev_stats_update(nic_id, |s| s.tx_dropped += 1);
+}
+
+/// 记录一个网卡发送失败的帧
+pub fn netdev_stats_tx_error(nic_id: usize) {
+    netdev_stats_update(nic_id, |s| s.tx_errors += 1);
+}
+
+/// 生成`/proc/net/dev`的内容，格式与Linux相同
+pub fn netdev_procfs_show() -> String {
+    let mut s = String::new();
+    s.push_str("Inter-|   Receive                                                |  Transmit\n");
+    s.push_str(
+        " face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n",
+    );
+    for dev in NET_DEVICES.read_irqsave().values() {
+        let st = netdev_stats(dev.nic_id());
+        writeln!(
+            s,
+            "{:>6}: {:>7} {:>7} {:>4} {:>4} {:>4} {:>5} {:>10} {:>9} {:>8} {:>7} {:>4} {:>4} {:>4} {:>5} {:>7} {:>10}",
+            dev.iface_name(),
+            st.rx_bytes,
+            st.rx_packets,
+            st.rx_errors,
+            st.rx_dropped,
+            0,
+            0,
+            0,
+            st.multicast,
+            st.tx_bytes,
+            st.tx_packets,
+            st.tx_errors,
+            st.tx_dropped,
+            0,
+            0,
+            0,
+            0
+        )
+        .ok();
+    }
+    s
+}
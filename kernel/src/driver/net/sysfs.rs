@@ -17,7 +17,11 @@ use intertrait::cast::CastArc;
 use log::error;
 use system_error::SystemError;
 
-use super::{class::sys_class_net_instance, NetDeivceState, NetDevice, Operstate};
+use super::{
+    class::sys_class_net_instance,
+    stats::{netdev_stats, NetDeviceStats},
+    NetDeivceState, NetDevice, Operstate,
+};
 
 /// 将设备注册到`/sys/class/net`目录下
 /// 参考：https://code.dragonos.org.cn/xref/linux-2.6.39/net/core/net-sysfs.c?fi=netdev_register_kobject#1311
@@ -221,8 +225,12 @@ impl Attribute for AttrIfindex {
         SysFSOpsSupport::ATTR_SHOW
     }
 
-    fn show(&self, _kobj: Arc<dyn KObject>, _buf: &mut [u8]) -> Result<usize, SystemError> {
-        todo!("AttrIfindex::show")
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let net_device = kobj.cast::<dyn NetDevice>().map_err(|_| {
+            error!("AttrIfindex::show() failed: kobj is not a NetDevice");
+            SystemError::EINVAL
+        })?;
+        sysfs_emit_str(buf, &format!("{}\n", net_device.nic_id()))
     }
 }
 
@@ -633,3 +641,96 @@ impl Attribute for AttrNetdevGroup {
         todo!("AttrNetdevGroup::store")
     }
 }
+
+/// `/sys/class/net/<网卡>/statistics`目录下的收发计数
+///
+/// 参考：https://code.dragonos.org.cn/xref/linux-6.6.21/net/core/net-sysfs.c#netstat_group
+#[derive(Debug)]
+pub struct NetStatAttrGroup;
+
+impl AttributeGroup for NetStatAttrGroup {
+    fn name(&self) -> Option<&str> {
+        Some("statistics")
+    }
+
+    fn attrs(&self) -> &[&'static dyn Attribute] {
+        &[
+            &ATTR_RX_PACKETS,
+            &ATTR_TX_PACKETS,
+            &ATTR_RX_BYTES,
+            &ATTR_TX_BYTES,
+            &ATTR_RX_ERRORS,
+            &ATTR_TX_ERRORS,
+            &ATTR_RX_DROPPED,
+            &ATTR_TX_DROPPED,
+            &ATTR_MULTICAST,
+        ]
+    }
+}
+
+/// # 网卡的一项收发计数
+#[derive(Debug)]
+struct AttrStat {
+    name: &'static str,
+    get: fn(&NetDeviceStats) -> u64,
+}
+
+static ATTR_RX_PACKETS: AttrStat = AttrStat {
+    name: "rx_packets",
+    get: |s| s.rx_packets,
+};
+static ATTR_TX_PACKETS: AttrStat = AttrStat {
+    name: "tx_packets",
+    get: |s| s.tx_packets,
+};
+static ATTR_RX_BYTES: AttrStat = AttrStat {
+    name: "rx_bytes",
+    get: |s| s.rx_bytes,
+};
+static ATTR_TX_BYTES: AttrStat = AttrStat {
+    name: "tx_bytes",
+    get: |s| s.tx_bytes,
+};
+static ATTR_RX_ERRORS: AttrStat = AttrStat {
+    name: "rx_errors",
+    get: |s| s.rx_errors,
+};
+static ATTR_TX_ERRORS: AttrStat = AttrStat {
+    name: "tx_errors",
+    get: |s| s.tx_errors,
+};
+static ATTR_RX_DROPPED: AttrStat = AttrStat {
+    name: "rx_dropped",
+    get: |s| s.rx_dropped,
+};
+static ATTR_TX_DROPPED: AttrStat = AttrStat {
+    name: "tx_dropped",
+    get: |s| s.tx_dropped,
+};
+static ATTR_MULTICAST: AttrStat = AttrStat {
+    name: "multicast",
+    get: |s| s.multicast,
+};
+
+impl Attribute for AttrStat {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn mode(&self) -> ModeType {
+        SYSFS_ATTR_MODE_RO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW
+    }
+
+    fn show(&self, kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let net_device = kobj.cast::<dyn NetDevice>().map_err(|_| {
+            error!("AttrStat::show() failed: kobj is not a NetDevice");
+            SystemError::EINVAL
+        })?;
+        let stats = netdev_stats(net_device.nic_id());
+        sysfs_emit_str(buf, &format!("{}\n", (self.get)(&stats)))
+    }
+}
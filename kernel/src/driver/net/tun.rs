@@ -0,0 +1,1019 @@
+//! TUN/TAP设备
+//!
+//! 打开/dev/net/tun之后用TUNSETIFF创建一个网卡，此后从文件读出的是协议栈经这个网卡发出的包，
+//! 写入文件的包则被当作这个网卡收到的包交给协议栈。文件关闭时网卡随之删除。
+//!
+//! - TAP网卡收发以太网帧
+//! - TUN网卡收发IP报文。smoltcp只启用了以太网介质，因此TUN网卡在内部仍然使用以太网帧：
+//!   写入的IP报文被加上以太网头，发出的帧被去掉以太网头。协议栈对邻居的ARP请求和IPv6邻居请求
+//!   由网卡直接以一个虚拟的对端MAC地址应答，不会交给用户态。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/net/tun.c
+
+use core::any::Any;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+use alloc::collections::VecDeque;
+use alloc::fmt::Debug;
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use smoltcp::phy::{self, ChecksumCapabilities};
+use smoltcp::wire::{
+    ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+    EthernetRepr, HardwareAddress, Icmpv6Packet, Icmpv6Repr, IpAddress, IpProtocol, Ipv6Packet,
+    Ipv6Repr, NdiscNeighborFlags, NdiscRepr, RawHardwareAddress,
+};
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::arch::rand::rand;
+use crate::driver::base::class::Class;
+use crate::driver::base::device::bus::Bus;
+use crate::driver::base::device::device_number::{DeviceNumber, Major};
+use crate::driver::base::device::driver::Driver;
+use crate::driver::base::device::{Device, DeviceCommonData, DeviceType, IdTable};
+use crate::driver::base::kobject::{
+    KObjType, KObject, KObjectCommonData, KObjectState, LockedKObjectState,
+};
+use crate::driver::base::kset::KSet;
+use crate::filesystem::devfs::{devfs_register, DevFS, DeviceINode};
+use crate::filesystem::kernfs::KernFSInode;
+use crate::filesystem::vfs::core::generate_inode_id;
+use crate::filesystem::vfs::file::FileMode;
+use crate::filesystem::vfs::syscall::ModeType;
+use crate::filesystem::vfs::{FilePrivateData, FileSystem, FileType, IndexNode, Metadata};
+use crate::init::initcall::INITCALL_DEVICE;
+use crate::libs::rwlock::{RwLockReadGuard, RwLockWriteGuard};
+use crate::libs::spinlock::{SpinLock, SpinLockGuard};
+use crate::libs::wait_queue::WaitQueue;
+use crate::net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll};
+use crate::net::net_core::poll_ifaces;
+use crate::net::{generate_iface_id, NET_DEVICES};
+use crate::process::cred::CAPFlags;
+use crate::process::{ProcessFlags, ProcessManager};
+use crate::syscall::user_access::{UserBufferReader, UserBufferWriter};
+use crate::time::{Instant, PosixTimeSpec};
+
+use super::packet_tap::PacketTap;
+use super::{
+    ipv6_link_local, register_netdevice, replace_ip_addr, unregister_netdevice, NetDeivceState,
+    NetDevice, NetDeviceCommonData, Operstate,
+};
+
+/// 控制设备在devfs中的名字，会被放在/dev/net/tun
+pub const TUN_CONTROL_NAME: &str = "tun";
+/// /dev/net/tun的次设备号
+const TUN_MINOR: u32 = 200;
+
+const TUNSETIFF: u32 = 0x400454ca;
+const TUNGETIFF: u32 = 0x800454d2;
+
+/// 网卡名字的最大长度（包括结尾的`\0`）
+const IFNAMSIZ: usize = 16;
+/// 用户态传入的`struct ifreq`的大小
+const IFREQ_SIZE: usize = 40;
+/// 包信息头（`struct tun_pi`）的大小
+const TUN_PI_SIZE: usize = 4;
+/// 等待用户态读取的包的最大数量，超过时丢弃新发出的包（与Linux默认的txqueuelen相同）
+const TUN_TX_QUEUE_LEN: usize = 500;
+/// TUN网卡的MTU
+const TUN_MTU: usize = 1500;
+
+/// TUN网卡内部使用的虚拟对端MAC地址，写入的IP报文以它为源地址
+const TUN_PEER_HW_ADDR: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x02]);
+
+/// `ARPHRD_ETHER`
+const ARPHRD_ETHER: u16 = 1;
+/// `ARPHRD_NONE`，没有链路层头的网卡
+const ARPHRD_NONE: u16 = 0xfffe;
+
+bitflags! {
+    /// `struct ifreq`中的`ifr_flags`
+    pub struct TunFlags: u16 {
+        /// 收发IP报文
+        const IFF_TUN = 0x0001;
+        /// 收发以太网帧
+        const IFF_TAP = 0x0002;
+        /// 收发的包前面不带`struct tun_pi`
+        const IFF_NO_PI = 0x1000;
+    }
+}
+
+/// 网卡与打开它的文件共享的收发队列
+#[derive(Debug)]
+struct TunQueue {
+    flags: TunFlags,
+    mac: EthernetAddress,
+    /// 用户态写入、等待交给协议栈的帧
+    rx: SpinLock<VecDeque<Vec<u8>>>,
+    /// 协议栈发出、等待用户态读取的帧
+    tx: SpinLock<VecDeque<Vec<u8>>>,
+    wait_queue: WaitQueue,
+    epitems: EPollItems,
+}
+
+impl TunQueue {
+    fn is_tun(&self) -> bool {
+        self.flags.contains(TunFlags::IFF_TUN)
+    }
+
+    fn readable(&self) -> bool {
+        !self.tx.lock_irqsave().is_empty()
+    }
+
+    fn poll_events(&self) -> EPollEventType {
+        let mut events = EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        if self.readable() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        events
+    }
+
+    /// 把一个帧交给协议栈
+    fn push_rx(&self, frame: Vec<u8>) {
+        self.rx.lock_irqsave().push_back(frame);
+    }
+
+    /// 协议栈发出一个帧
+    ///
+    /// TUN网卡在这里应答ARP请求和IPv6邻居请求，其他非IP帧被丢弃
+    fn transmit(&self, frame: Vec<u8>) {
+        if self.is_tun() {
+            let Ok(eth) = EthernetFrame::new_checked(frame.as_slice()) else {
+                return;
+            };
+            match eth.ethertype() {
+                EthernetProtocol::Arp => {
+                    if let Some(reply) = self.arp_reply(eth.payload()) {
+                        self.push_rx(reply);
+                    }
+                    return;
+                }
+                EthernetProtocol::Ipv6 => {
+                    if let Some(reply) = self.ndisc_reply(eth.payload()) {
+                        self.push_rx(reply);
+                        return;
+                    }
+                }
+                EthernetProtocol::Ipv4 => {}
+                _ => return,
+            }
+        }
+
+        let mut tx = self.tx.lock_irqsave();
+        if tx.len() >= TUN_TX_QUEUE_LEN {
+            return;
+        }
+        tx.push_back(frame);
+        drop(tx);
+
+        self.wait_queue.wakeup_all(None);
+        self.epitems.wakeup(Some(self.poll_events())).ok();
+    }
+
+    /// 构造一个发给本网卡的以太网帧，`payload_len`为帧中负载的长度
+    fn new_frame(
+        &self,
+        ethertype: EthernetProtocol,
+        payload_len: usize,
+        emit: impl FnOnce(&mut [u8]),
+    ) -> Vec<u8> {
+        let mut buf = vec![0; EthernetFrame::<&[u8]>::header_len() + payload_len];
+        let mut frame = EthernetFrame::new_unchecked(buf.as_mut_slice());
+        EthernetRepr {
+            src_addr: TUN_PEER_HW_ADDR,
+            dst_addr: self.mac,
+            ethertype,
+        }
+        .emit(&mut frame);
+        emit(frame.payload_mut());
+        buf
+    }
+
+    /// 以虚拟的对端MAC地址应答ARP请求
+    fn arp_reply(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let packet = ArpPacket::new_checked(payload).ok()?;
+        let ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Request,
+            source_hardware_addr,
+            source_protocol_addr,
+            target_protocol_addr,
+            ..
+        } = ArpRepr::parse(&packet).ok()?
+        else {
+            return None;
+        };
+        let reply = ArpRepr::EthernetIpv4 {
+            operation: ArpOperation::Reply,
+            source_hardware_addr: TUN_PEER_HW_ADDR,
+            source_protocol_addr: target_protocol_addr,
+            target_hardware_addr: source_hardware_addr,
+            target_protocol_addr: source_protocol_addr,
+        };
+        Some(
+            self.new_frame(EthernetProtocol::Arp, reply.buffer_len(), |buf| {
+                reply.emit(&mut ArpPacket::new_unchecked(buf))
+            }),
+        )
+    }
+
+    /// 以虚拟的对端MAC地址应答IPv6邻居请求，不是邻居请求时返回`None`
+    fn ndisc_reply(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let ip = Ipv6Packet::new_checked(payload).ok()?;
+        if ip.next_header() != IpProtocol::Icmpv6 {
+            return None;
+        }
+        let (src, dst) = (ip.src_addr(), ip.dst_addr());
+        let icmp = Icmpv6Packet::new_checked(ip.payload()).ok()?;
+        let caps = ChecksumCapabilities::default();
+        let Icmpv6Repr::Ndisc(NdiscRepr::NeighborSolicit { target_addr, .. }) =
+            Icmpv6Repr::parse(&IpAddress::Ipv6(src), &IpAddress::Ipv6(dst), &icmp, &caps).ok()?
+        else {
+            return None;
+        };
+
+        let advert = Icmpv6Repr::Ndisc(NdiscRepr::NeighborAdvert {
+            flags: NdiscNeighborFlags::SOLICITED | NdiscNeighborFlags::OVERRIDE,
+            target_addr,
+            lladdr: Some(RawHardwareAddress::from_bytes(TUN_PEER_HW_ADDR.as_bytes())),
+        });
+        let ip_repr = Ipv6Repr {
+            src_addr: target_addr,
+            dst_addr: src,
+            next_header: IpProtocol::Icmpv6,
+            payload_len: advert.buffer_len(),
+            hop_limit: 255,
+        };
+        let len = ip_repr.buffer_len() + advert.buffer_len();
+        Some(self.new_frame(EthernetProtocol::Ipv6, len, |buf| {
+            let mut packet = Ipv6Packet::new_unchecked(buf);
+            ip_repr.emit(&mut packet);
+            advert.emit(
+                &IpAddress::Ipv6(target_addr),
+                &IpAddress::Ipv6(src),
+                &mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+                &caps,
+            );
+        }))
+    }
+
+    /// 把用户态写入的包转换为以太网帧
+    fn frame_from_user(&self, buf: &[u8]) -> Result<Vec<u8>, SystemError> {
+        let (proto, packet) = if self.flags.contains(TunFlags::IFF_NO_PI) {
+            (None, buf)
+        } else {
+            if buf.len() < TUN_PI_SIZE {
+                return Err(SystemError::EINVAL);
+            }
+            let proto = u16::from_be_bytes([buf[2], buf[3]]);
+            (Some(proto), &buf[TUN_PI_SIZE..])
+        };
+
+        if !self.is_tun() {
+            if packet.len() < EthernetFrame::<&[u8]>::header_len() {
+                return Err(SystemError::EINVAL);
+            }
+            return Ok(packet.to_vec());
+        }
+
+        // 没有包信息头时由IP版本号确定协议
+        let ethertype = match proto {
+            Some(proto) => EthernetProtocol::from(proto),
+            None => match packet.first().map(|b| b >> 4) {
+                Some(4) => EthernetProtocol::Ipv4,
+                Some(6) => EthernetProtocol::Ipv6,
+                _ => return Err(SystemError::EINVAL),
+            },
+        };
+        if !matches!(ethertype, EthernetProtocol::Ipv4 | EthernetProtocol::Ipv6) {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(self.new_frame(ethertype, packet.len(), |buf| buf.copy_from_slice(packet)))
+    }
+
+    /// 把协议栈发出的帧转换为交给用户态的包，超出`buf`的部分被截断
+    fn frame_to_user(&self, frame: &[u8], buf: &mut [u8]) -> usize {
+        let eth = EthernetFrame::new_unchecked(frame);
+        let packet = if self.is_tun() { eth.payload() } else { frame };
+
+        let mut len = 0;
+        if !self.flags.contains(TunFlags::IFF_NO_PI) {
+            let mut pi = [0u8; TUN_PI_SIZE];
+            pi[2..].copy_from_slice(&u16::from(eth.ethertype()).to_be_bytes());
+            len = TUN_PI_SIZE.min(buf.len());
+            buf[..len].copy_from_slice(&pi[..len]);
+        }
+        let n = packet.len().min(buf.len() - len);
+        buf[len..len + n].copy_from_slice(&packet[..n]);
+        len + n
+    }
+}
+
+/// TUN/TAP网卡的接收令牌
+pub struct TunRxToken {
+    buffer: Vec<u8>,
+}
+
+impl phy::RxToken for TunRxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(self.buffer.as_mut_slice())
+    }
+}
+
+/// TUN/TAP网卡的发送令牌，发出的帧放入等待用户态读取的队列
+pub struct TunTxToken {
+    queue: Arc<TunQueue>,
+}
+
+impl phy::TxToken for TunTxToken {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buffer = vec![0; len];
+        let result = f(buffer.as_mut_slice());
+        self.queue.transmit(buffer);
+        result
+    }
+}
+
+/// TUN/TAP网卡的驱动
+#[derive(Debug, Clone)]
+pub struct TunDriver {
+    queue: Arc<TunQueue>,
+}
+
+impl phy::Device for TunDriver {
+    type RxToken<'a>
+        = TunRxToken
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TunTxToken
+    where
+        Self: 'a;
+
+    fn capabilities(&self) -> phy::DeviceCapabilities {
+        let mut result = phy::DeviceCapabilities::default();
+        result.max_transmission_unit = TUN_MTU + EthernetFrame::<&[u8]>::header_len();
+        result.max_burst_size = Some(1);
+        result.medium = smoltcp::phy::Medium::Ethernet;
+        return result;
+    }
+
+    fn receive(
+        &mut self,
+        _timestamp: smoltcp::time::Instant,
+    ) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let buffer = self.queue.rx.lock_irqsave().pop_front()?;
+        let rx = TunRxToken { buffer };
+        let tx = TunTxToken {
+            queue: self.queue.clone(),
+        };
+        return Some((rx, tx));
+    }
+
+    fn transmit(&mut self, _timestamp: smoltcp::time::Instant) -> Option<Self::TxToken<'_>> {
+        Some(TunTxToken {
+            queue: self.queue.clone(),
+        })
+    }
+}
+
+/// ## driver的包裹器
+/// 为实现获得不可变引用的Interface的内部可变性，故为Driver提供UnsafeCell包裹器
+///
+/// 参考loopback.rs
+struct TunDriverWrapper(UnsafeCell<TunDriver>);
+unsafe impl Send for TunDriverWrapper {}
+unsafe impl Sync for TunDriverWrapper {}
+
+impl Deref for TunDriverWrapper {
+    type Target = TunDriver;
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.0.get() }
+    }
+}
+
+impl DerefMut for TunDriverWrapper {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.0.get() }
+    }
+}
+
+impl TunDriverWrapper {
+    #[allow(clippy::mut_from_ref)]
+    fn force_get_mut(&self) -> &mut TunDriver {
+        unsafe { &mut *self.0.get() }
+    }
+}
+
+/// TUN/TAP网卡
+#[cast_to([sync] NetDevice)]
+#[cast_to([sync] Device)]
+pub struct TunInterface {
+    driver: TunDriverWrapper,
+    iface_id: usize,
+    iface: SpinLock<smoltcp::iface::Interface>,
+    name: String,
+    inner: SpinLock<InnerTunInterface>,
+    locked_kobj_state: LockedKObjectState,
+}
+
+#[derive(Debug)]
+pub struct InnerTunInterface {
+    netdevice_common: NetDeviceCommonData,
+    device_common: DeviceCommonData,
+    kobj_common: KObjectCommonData,
+}
+
+impl TunInterface {
+    fn new(name: String, flags: TunFlags) -> Arc<Self> {
+        // 随机生成一个本地管理的单播地址
+        let mut mac = [0u8; 6];
+        for b in mac.iter_mut() {
+            *b = rand() as u8;
+        }
+        mac[0] = (mac[0] & 0xfe) | 0x02;
+        let mac = EthernetAddress(mac);
+
+        let mut driver = TunDriver {
+            queue: Arc::new(TunQueue {
+                flags,
+                mac,
+                rx: SpinLock::new(VecDeque::new()),
+                tx: SpinLock::new(VecDeque::new()),
+                wait_queue: WaitQueue::default(),
+                epitems: EPollItems::new(),
+            }),
+        };
+
+        let iface_id = generate_iface_id();
+        let mut iface_config = smoltcp::iface::Config::new(HardwareAddress::Ethernet(mac));
+        iface_config.random_seed = rand() as u64;
+        let mut iface =
+            smoltcp::iface::Interface::new(iface_config, &mut driver, Instant::now().into());
+        if flags.contains(TunFlags::IFF_TAP) {
+            replace_ip_addr(&mut iface, ipv6_link_local(mac)).ok();
+        }
+
+        let mut netdevice_common = NetDeviceCommonData::default();
+        netdevice_common.net_device_type = if flags.contains(TunFlags::IFF_TUN) {
+            ARPHRD_NONE
+        } else {
+            ARPHRD_ETHER
+        };
+        Arc::new(TunInterface {
+            driver: TunDriverWrapper(UnsafeCell::new(driver)),
+            iface_id,
+            iface: SpinLock::new(iface),
+            name,
+            inner: SpinLock::new(InnerTunInterface {
+                netdevice_common,
+                device_common: DeviceCommonData::default(),
+                kobj_common: KObjectCommonData::default(),
+            }),
+            locked_kobj_state: LockedKObjectState::default(),
+        })
+    }
+
+    fn inner(&self) -> SpinLockGuard<InnerTunInterface> {
+        return self.inner.lock();
+    }
+
+    fn queue(&self) -> &Arc<TunQueue> {
+        &self.driver.queue
+    }
+
+    pub fn flags(&self) -> TunFlags {
+        self.queue().flags
+    }
+
+    /// 把驱动包装为抓包设备，再交给smoltcp收发
+    fn packet_tap(&self) -> PacketTap<'_, TunDriver> {
+        PacketTap::new(self.driver.force_get_mut(), self.iface_id, self.queue().mac)
+    }
+
+    /// 读出一个协议栈发出的包
+    fn read(&self, buf: &mut [u8], nonblock: bool) -> Result<usize, SystemError> {
+        loop {
+            if let Some(frame) = self.queue().tx.lock_irqsave().pop_front() {
+                return Ok(self.queue().frame_to_user(&frame, buf));
+            }
+            if nonblock {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if ProcessManager::current_pcb().has_pending_signal_fast() {
+                return Err(SystemError::ERESTARTSYS);
+            }
+            let r =
+                wq_wait_event_interruptible!(self.queue().wait_queue, self.queue().readable(), {});
+            if r.is_err() {
+                ProcessManager::current_pcb()
+                    .flags()
+                    .insert(ProcessFlags::HAS_PENDING_SIGNAL);
+                return Err(SystemError::ERESTARTSYS);
+            }
+        }
+    }
+
+    /// 把用户态写入的包交给协议栈
+    fn write(&self, buf: &[u8]) -> Result<usize, SystemError> {
+        let frame = self.queue().frame_from_user(buf)?;
+        self.queue().push_rx(frame);
+        poll_ifaces();
+        return Ok(buf.len());
+    }
+}
+
+impl Debug for TunInterface {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TunInterface")
+            .field("iface_id", &self.iface_id)
+            .field("iface", &"smtoltcp::iface::Interface")
+            .field("name", &self.name)
+            .field("flags", &self.flags())
+            .finish()
+    }
+}
+
+impl KObject for TunInterface {
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn set_inode(&self, inode: Option<Arc<KernFSInode>>) {
+        self.inner().kobj_common.kern_inode = inode;
+    }
+
+    fn inode(&self) -> Option<Arc<KernFSInode>> {
+        self.inner().kobj_common.kern_inode.clone()
+    }
+
+    fn parent(&self) -> Option<Weak<dyn KObject>> {
+        self.inner().kobj_common.parent.clone()
+    }
+
+    fn set_parent(&self, parent: Option<Weak<dyn KObject>>) {
+        self.inner().kobj_common.parent = parent;
+    }
+
+    fn kset(&self) -> Option<Arc<KSet>> {
+        self.inner().kobj_common.kset.clone()
+    }
+
+    fn set_kset(&self, kset: Option<Arc<KSet>>) {
+        self.inner().kobj_common.kset = kset;
+    }
+
+    fn kobj_type(&self) -> Option<&'static dyn KObjType> {
+        self.inner().kobj_common.kobj_type
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn set_name(&self, _name: String) {
+        // do nothing
+    }
+
+    fn kobj_state(&self) -> RwLockReadGuard<KObjectState> {
+        self.locked_kobj_state.read()
+    }
+
+    fn kobj_state_mut(&self) -> RwLockWriteGuard<KObjectState> {
+        self.locked_kobj_state.write()
+    }
+
+    fn set_kobj_state(&self, state: KObjectState) {
+        *self.locked_kobj_state.write() = state;
+    }
+
+    fn set_kobj_type(&self, ktype: Option<&'static dyn KObjType>) {
+        self.inner().kobj_common.kobj_type = ktype;
+    }
+}
+
+impl Device for TunInterface {
+    fn dev_type(&self) -> DeviceType {
+        DeviceType::Net
+    }
+
+    fn id_table(&self) -> IdTable {
+        IdTable::new(TUN_CONTROL_NAME.to_string(), None)
+    }
+
+    fn bus(&self) -> Option<Weak<dyn Bus>> {
+        self.inner().device_common.bus.clone()
+    }
+
+    fn set_bus(&self, bus: Option<Weak<dyn Bus>>) {
+        self.inner().device_common.bus = bus;
+    }
+
+    fn class(&self) -> Option<Arc<dyn Class>> {
+        let mut guard = self.inner();
+        let r = guard.device_common.class.clone()?.upgrade();
+        if r.is_none() {
+            guard.device_common.class = None;
+        }
+
+        return r;
+    }
+
+    fn set_class(&self, class: Option<Weak<dyn Class>>) {
+        self.inner().device_common.class = class;
+    }
+
+    fn driver(&self) -> Option<Arc<dyn Driver>> {
+        let r = self.inner().device_common.driver.clone()?.upgrade();
+        if r.is_none() {
+            self.inner().device_common.driver = None;
+        }
+
+        return r;
+    }
+
+    fn set_driver(&self, driver: Option<Weak<dyn Driver>>) {
+        self.inner().device_common.driver = driver;
+    }
+
+    fn is_dead(&self) -> bool {
+        false
+    }
+
+    fn can_match(&self) -> bool {
+        self.inner().device_common.can_match
+    }
+
+    fn set_can_match(&self, can_match: bool) {
+        self.inner().device_common.can_match = can_match;
+    }
+
+    fn state_synced(&self) -> bool {
+        true
+    }
+
+    fn dev_parent(&self) -> Option<Weak<dyn Device>> {
+        self.inner().device_common.get_parent_weak_or_clear()
+    }
+
+    fn set_dev_parent(&self, parent: Option<Weak<dyn Device>>) {
+        self.inner().device_common.parent = parent;
+    }
+}
+
+impl NetDevice for TunInterface {
+    fn mac(&self) -> EthernetAddress {
+        self.queue().mac
+    }
+
+    #[inline]
+    fn nic_id(&self) -> usize {
+        self.iface_id
+    }
+
+    #[inline]
+    fn iface_name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn update_ip_addrs(&self, ip_addrs: &[smoltcp::wire::IpCidr]) -> Result<(), SystemError> {
+        if ip_addrs.len() != 1 {
+            return Err(SystemError::EINVAL);
+        }
+
+        return replace_ip_addr(&mut self.iface.lock(), ip_addrs[0]);
+    }
+
+    fn poll(&self, sockets: &mut smoltcp::iface::SocketSet) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        let mut guard = self.iface.lock();
+        let poll_res = guard.poll(timestamp, &mut self.packet_tap(), sockets);
+        if poll_res {
+            return Ok(());
+        }
+        return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+    }
+
+    fn poll_xmit(&self, frame: &[u8]) -> Result<(), SystemError> {
+        self.queue().transmit(frame.to_vec());
+        return Ok(());
+    }
+
+    fn join_multicast_group(&self, addr: IpAddress) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        self.iface
+            .lock()
+            .join_multicast_group(&mut self.packet_tap(), addr, timestamp)
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOBUFS)
+    }
+
+    fn leave_multicast_group(&self, addr: IpAddress) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        self.iface
+            .lock()
+            .leave_multicast_group(&mut self.packet_tap(), addr, timestamp)
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOBUFS)
+    }
+
+    #[inline(always)]
+    fn inner_iface(&self) -> &SpinLock<smoltcp::iface::Interface> {
+        return &self.iface;
+    }
+
+    fn addr_assign_type(&self) -> u8 {
+        return self.inner().netdevice_common.addr_assign_type;
+    }
+
+    fn net_device_type(&self) -> u16 {
+        return self.inner().netdevice_common.net_device_type;
+    }
+
+    fn net_state(&self) -> NetDeivceState {
+        return self.inner().netdevice_common.state;
+    }
+
+    fn set_net_state(&self, state: NetDeivceState) {
+        self.inner().netdevice_common.state |= state;
+    }
+
+    fn operstate(&self) -> Operstate {
+        return self.inner().netdevice_common.operstate;
+    }
+
+    fn set_operstate(&self, state: Operstate) {
+        self.inner().netdevice_common.operstate = state;
+    }
+}
+
+/// 打开/dev/net/tun的文件的私有信息
+#[derive(Debug, Clone)]
+pub struct TunFilePrivateData {
+    /// 用TUNSETIFF创建的网卡
+    dev: Arc<SpinLock<Option<Arc<TunInterface>>>>,
+    mode: FileMode,
+}
+
+impl TunFilePrivateData {
+    pub fn set_mode(&mut self, mode: FileMode) {
+        self.mode = mode;
+    }
+
+    fn dev(&self) -> Result<Arc<TunInterface>, SystemError> {
+        self.dev.lock().clone().ok_or(SystemError::EBADFD)
+    }
+}
+
+/// 由用户给出的名字确定网卡名，名字中的`%d`被替换为第一个未被使用的编号
+fn tun_alloc_name(name: &str, flags: TunFlags) -> Result<String, SystemError> {
+    let template = if name.is_empty() {
+        if flags.contains(TunFlags::IFF_TUN) {
+            "tun%d"
+        } else {
+            "tap%d"
+        }
+    } else {
+        name
+    };
+
+    let devices = NET_DEVICES.read_irqsave();
+    let used = |name: &str| devices.values().any(|d| d.iface_name() == name);
+    if !template.contains("%d") {
+        if let Some(dev) = devices.values().find(|d| d.iface_name() == template) {
+            // 网卡在文件关闭时删除，同名的TUN/TAP网卡一定正在被使用
+            return Err(
+                if dev.as_any_ref().downcast_ref::<TunInterface>().is_some() {
+                    SystemError::EBUSY
+                } else {
+                    SystemError::EINVAL
+                },
+            );
+        }
+        return Ok(template.to_string());
+    }
+    (0..)
+        .map(|i| template.replacen("%d", &i.to_string(), 1))
+        .take_while(|name| name.len() < IFNAMSIZ)
+        .find(|name| !used(name))
+        .ok_or(SystemError::ENFILE)
+}
+
+/// /dev/net/tun
+#[derive(Debug)]
+pub struct TunCharInode {
+    fs: SpinLock<Weak<DevFS>>,
+    metadata: Metadata,
+}
+
+impl TunCharInode {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            fs: SpinLock::new(Weak::default()),
+            metadata: Metadata {
+                dev_id: 1,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: PosixTimeSpec::default(),
+                mtime: PosixTimeSpec::default(),
+                ctime: PosixTimeSpec::default(),
+                file_type: FileType::CharDevice,
+                mode: ModeType::from_bits_truncate(0o666),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::new(Major::MISC_MAJOR, TUN_MINOR),
+            },
+        })
+    }
+
+    fn private_data(data: &FilePrivateData) -> Result<&TunFilePrivateData, SystemError> {
+        match data {
+            FilePrivateData::Tun(pdata) => Ok(pdata),
+            _ => Err(SystemError::EBADFD),
+        }
+    }
+
+    /// TUNSETIFF：创建一个网卡并与当前文件绑定
+    fn set_iff(&self, pdata: &TunFilePrivateData, arg: usize) -> Result<usize, SystemError> {
+        let reader = UserBufferReader::new(arg as *const u8, IFREQ_SIZE, true)?;
+        let mut ifreq = [0u8; IFREQ_SIZE];
+        reader.copy_from_user(&mut ifreq, 0)?;
+
+        let flags = TunFlags::from_bits(u16::from_ne_bytes([ifreq[IFNAMSIZ], ifreq[IFNAMSIZ + 1]]))
+            .ok_or(SystemError::EINVAL)?;
+        if flags.contains(TunFlags::IFF_TUN) == flags.contains(TunFlags::IFF_TAP) {
+            return Err(SystemError::EINVAL);
+        }
+        let name_len = ifreq[..IFNAMSIZ]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(SystemError::EINVAL)?;
+        let name = core::str::from_utf8(&ifreq[..name_len]).map_err(|_| SystemError::EINVAL)?;
+
+        if !ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_NET_ADMIN)
+        {
+            return Err(SystemError::EPERM);
+        }
+
+        let mut dev = pdata.dev.lock();
+        if dev.is_some() {
+            return Err(SystemError::EEXIST);
+        }
+        let name = tun_alloc_name(name, flags)?;
+        let iface = TunInterface::new(name, flags);
+        iface.set_net_state(NetDeivceState::__LINK_STATE_START);
+        NET_DEVICES
+            .write_irqsave()
+            .insert(iface.iface_id, iface.clone());
+        if let Err(e) = register_netdevice(iface.clone()) {
+            NET_DEVICES.write_irqsave().remove(&iface.iface_id);
+            return Err(e);
+        }
+        *dev = Some(iface.clone());
+        drop(dev);
+
+        Self::write_ifreq(&iface, arg)
+    }
+
+    /// TUNGETIFF：获取与当前文件绑定的网卡的名字和标志
+    fn get_iff(&self, pdata: &TunFilePrivateData, arg: usize) -> Result<usize, SystemError> {
+        Self::write_ifreq(&pdata.dev()?, arg)
+    }
+
+    fn write_ifreq(iface: &TunInterface, arg: usize) -> Result<usize, SystemError> {
+        let mut ifreq = [0u8; IFNAMSIZ + 2];
+        ifreq[..iface.name.len()].copy_from_slice(iface.name.as_bytes());
+        ifreq[IFNAMSIZ..].copy_from_slice(&iface.flags().bits().to_ne_bytes());
+        let mut writer = UserBufferWriter::new(arg as *mut u8, ifreq.len(), true)?;
+        writer.copy_to_user(&ifreq, 0)?;
+        Ok(0)
+    }
+}
+
+impl DeviceINode for TunCharInode {
+    fn set_fs(&self, fs: Weak<DevFS>) {
+        *self.fs.lock() = fs;
+    }
+}
+
+impl IndexNode for TunCharInode {
+    fn open(
+        &self,
+        mut data: SpinLockGuard<FilePrivateData>,
+        mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        *data = FilePrivateData::Tun(TunFilePrivateData {
+            dev: Arc::new(SpinLock::new(None)),
+            mode: *mode,
+        });
+        Ok(())
+    }
+
+    fn close(&self, data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        let dev = Self::private_data(&data)?.dev.lock().take();
+        drop(data);
+        if let Some(dev) = dev {
+            unregister_netdevice(dev);
+        }
+        Ok(())
+    }
+
+    fn ioctl(
+        &self,
+        cmd: u32,
+        data: usize,
+        private_data: &FilePrivateData,
+    ) -> Result<usize, SystemError> {
+        let pdata = Self::private_data(private_data)?;
+        match cmd {
+            TUNSETIFF => self.set_iff(pdata, data),
+            TUNGETIFF => self.get_iff(pdata, data),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        buf: &mut [u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let pdata = Self::private_data(&data)?;
+        let (dev, nonblock) = (pdata.dev()?, pdata.mode.contains(FileMode::O_NONBLOCK));
+        drop(data);
+        dev.read(buf, nonblock)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &[u8],
+        data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let dev = Self::private_data(&data)?.dev()?;
+        drop(data);
+        dev.write(&buf[..len])
+    }
+
+    fn poll(&self, private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        match Self::private_data(private_data)?.dev() {
+            Ok(dev) => Ok(dev.queue().poll_events().bits() as usize),
+            Err(_) => Ok(EPollEventType::EPOLLERR.bits() as usize),
+        }
+    }
+
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        let dev = Self::private_data(private_data)?.dev()?;
+        dev.queue().epitems.add(epitem);
+        Ok(())
+    }
+
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        let dev = Self::private_data(private_data)?.dev()?;
+        dev.queue().epitems.remove(epoll)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.lock().upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+}
+
+#[unified_init(INITCALL_DEVICE)]
+fn tun_init() -> Result<(), SystemError> {
+    devfs_register(TUN_CONTROL_NAME, TunCharInode::new())
+}
@@ -16,7 +16,10 @@ use smoltcp::{iface, phy, wire};
 use unified_init::macros::unified_init;
 use virtio_drivers::device::net::VirtIONet;
 
-use super::{NetDeivceState, NetDevice, NetDeviceCommonData, Operstate};
+use super::{
+    ipv6_link_local, packet_tap::PacketTap, replace_ip_addr, NetDeivceState, NetDevice,
+    NetDeviceCommonData, Operstate,
+};
 use crate::{
     arch::rand::rand,
     driver::{
@@ -400,12 +403,13 @@ impl core::fmt::Debug for VirtioInterface {
 impl VirtioInterface {
     pub fn new(mut device_inner: VirtIONicDeviceInner) -> Arc<Self> {
         let iface_id = generate_iface_id();
-        let mut iface_config = iface::Config::new(wire::HardwareAddress::Ethernet(
-            wire::EthernetAddress(device_inner.inner.lock().mac_address()),
-        ));
+        let mac = wire::EthernetAddress(device_inner.inner.lock().mac_address());
+        let mut iface_config = iface::Config::new(wire::HardwareAddress::Ethernet(mac));
         iface_config.random_seed = rand() as u64;
 
-        let iface = iface::Interface::new(iface_config, &mut device_inner, Instant::now().into());
+        let mut iface =
+            iface::Interface::new(iface_config, &mut device_inner, Instant::now().into());
+        replace_ip_addr(&mut iface, ipv6_link_local(mac)).ok();
 
         let result = Arc::new(VirtioInterface {
             device_inner: VirtIONicDeviceInnerWrapper(UnsafeCell::new(device_inner)),
@@ -427,6 +431,11 @@ impl VirtioInterface {
         return self.inner.lock();
     }
 
+    /// 把驱动包装为抓包设备，再交给smoltcp收发
+    fn packet_tap(&self) -> PacketTap<'_, VirtIONicDeviceInner> {
+        PacketTap::new(self.device_inner.force_get_mut(), self.iface_id, self.mac())
+    }
+
     /// 获取网卡接口的名称
     #[allow(dead_code)]
     pub fn iface_name(&self) -> String {
@@ -657,24 +666,14 @@ impl NetDevice for VirtioInterface {
             return Err(SystemError::EINVAL);
         }
 
-        self.iface.lock().update_ip_addrs(|addrs| {
-            let dest = addrs.iter_mut().next();
-
-            if let Some(dest) = dest {
-                *dest = ip_addrs[0];
-            } else {
-                addrs
-                    .push(ip_addrs[0])
-                    .expect("Push wire::IpCidr failed: full");
-            }
-        });
-        return Ok(());
+        return replace_ip_addr(&mut self.iface.lock(), ip_addrs[0]);
     }
 
     fn poll(&self, sockets: &mut iface::SocketSet) -> Result<(), SystemError> {
+        self.check_alive()?;
         let timestamp: smoltcp::time::Instant = Instant::now().into();
         let mut guard = self.iface.lock();
-        let poll_res = guard.poll(timestamp, self.device_inner.force_get_mut(), sockets);
+        let poll_res = guard.poll(timestamp, &mut self.packet_tap(), sockets);
         // todo: notify!!!
         // debug!("Virtio Interface poll:{poll_res}");
         if poll_res {
@@ -683,6 +682,38 @@ impl NetDevice for VirtioInterface {
         return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
     }
 
+    fn join_multicast_group(&self, addr: wire::IpAddress) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        self.iface
+            .lock()
+            .join_multicast_group(&mut self.packet_tap(), addr, timestamp)
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOBUFS)
+    }
+
+    fn leave_multicast_group(&self, addr: wire::IpAddress) -> Result<(), SystemError> {
+        let timestamp: smoltcp::time::Instant = Instant::now().into();
+        self.iface
+            .lock()
+            .leave_multicast_group(&mut self.packet_tap(), addr, timestamp)
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOBUFS)
+    }
+
+    fn poll_xmit(&self, frame: &[u8]) -> Result<(), SystemError> {
+        let mut driver_net = self
+            .device_inner
+            .inner
+            .try_lock_irqsave()
+            .map_err(|_| SystemError::EAGAIN_OR_EWOULDBLOCK)?;
+        if !driver_net.can_send() {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+        let mut tx_buf = driver_net.new_tx_buffer(frame.len());
+        tx_buf.packet_mut().copy_from_slice(frame);
+        driver_net.send(tx_buf).map_err(|_| SystemError::EIO)
+    }
+
     #[inline(always)]
     fn inner_iface(&self) -> &SpinLock<iface::Interface> {
         return &self.iface;
@@ -3,9 +3,12 @@ use core::{fmt::Debug, sync::atomic::AtomicU32};
 use alloc::sync::Arc;
 use system_error::SystemError;
 
-use crate::mm::VirtAddr;
+use crate::{
+    libs::console::{register_console, ConsoleFlags},
+    mm::VirtAddr,
+};
 
-use self::serial8250::serial8250_manager;
+use self::serial8250::{serial8250_manager, Serial8250Console};
 
 use super::tty::{
     termios::{ControlMode, InputMode, LocalMode, OutputMode, Termios, INIT_CONTORL_CHARACTERS},
@@ -94,6 +97,7 @@ impl UartManager {
 
 pub fn serial_early_init() -> Result<(), SystemError> {
     serial8250_manager().early_init()?;
+    register_console(&Serial8250Console, ConsoleFlags::empty())?;
     return Ok(());
 }
 
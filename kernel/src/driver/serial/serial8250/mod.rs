@@ -29,7 +29,10 @@ use crate::{
         tty::tty_driver::{TtyDriver, TtyDriverManager, TtyDriverType},
     },
     filesystem::kernfs::KernFSInode,
-    libs::rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    libs::{
+        console::Console,
+        rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    },
 };
 
 #[cfg(target_arch = "x86_64")]
@@ -41,7 +44,7 @@ use self::serial8250_pio::{
 use super::{uart_manager, UartDriver, UartManager, UartPort, TTY_SERIAL_DEFAULT_TERMIOS};
 
 #[cfg(target_arch = "x86_64")]
-mod serial8250_pio;
+pub mod serial8250_pio;
 
 static mut SERIAL8250_ISA_DEVICES: Option<Arc<Serial8250ISADevices>> = None;
 static mut SERIAL8250_ISA_DRIVER: Option<Arc<Serial8250ISADriver>> = None;
@@ -565,3 +568,23 @@ pub fn send_to_default_serial8250_port(s: &[u8]) {
         crate::arch::driver::sbi::console_putstr(s);
     }
 }
+
+/// 默认串口对应的内核控制台
+#[derive(Debug)]
+pub struct Serial8250Console;
+
+impl Console for Serial8250Console {
+    fn name(&self) -> &'static str {
+        "ttyS0"
+    }
+
+    fn write(&self, s: &str) {
+        for line in s.split_inclusive('\n') {
+            send_to_default_serial8250_port(line.as_bytes());
+            // 换行时还需要输出\r
+            if line.ends_with('\n') {
+                send_to_default_serial8250_port(b"\r");
+            }
+        }
+    }
+}
@@ -2,7 +2,7 @@
 
 use core::{
     hint::spin_loop,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
 };
 
 use alloc::{
@@ -21,6 +21,7 @@ use crate::{
         tty::{
             console::ConsoleSwitch,
             kthread::send_to_tty_refresh_thread,
+            sysrq::handle_sysrq,
             termios::WindowSize,
             tty_core::{TtyCore, TtyCoreData},
             tty_driver::{TtyDriver, TtyDriverManager, TtyOperation},
@@ -35,6 +36,7 @@ use crate::{
         IrqNumber,
     },
     libs::{rwlock::RwLock, spinlock::SpinLock},
+    time::{clocksource::HZ, timer::clock},
 };
 use system_error::SystemError;
 
@@ -45,6 +47,19 @@ static mut PIO_PORTS: [Option<Serial8250PIOPort>; 8] =
 
 const SERIAL_8250_PIO_IRQ: IrqNumber = IrqNumber::new(IoApic::VECTOR_BASE as u32 + 4);
 
+/// 线路状态寄存器：有数据可读
+const UART_LSR_DR: u32 = 0x01;
+/// 线路状态寄存器：收到BREAK
+const UART_LSR_BI: u32 = 0x10;
+/// 收到BREAK之后，等待sysrq按键的时间（秒）
+const SYSRQ_TIMEOUT_SECS: u64 = 5;
+
+/// 获取第`index`个（从0开始）PIO串口，端口不存在时返回None
+#[allow(static_mut_refs)]
+pub fn serial8250_pio_port(index: usize) -> Option<&'static Serial8250PIOPort> {
+    unsafe { PIO_PORTS.get(index)?.as_ref() }
+}
+
 impl Serial8250Manager {
     #[allow(static_mut_refs)]
     pub(super) fn bind_pio_ports(
@@ -104,6 +119,10 @@ pub struct Serial8250PIOPort {
     iobase: Serial8250PortBase,
     baudrate: AtomicBaudRate,
     initialized: AtomicBool,
+    /// 端口被其他模块（如gdbstub）独占，收到的数据不再送往tty
+    reserved: AtomicBool,
+    /// 收到BREAK之后，在这个时刻（jiffies）之前收到的字符作为sysrq按键，为0表示没有收到BREAK
+    sysrq_deadline: AtomicU64,
     inner: RwLock<Serial8250PIOPortInner>,
 }
 
@@ -114,6 +133,8 @@ impl Serial8250PIOPort {
             iobase,
             baudrate: AtomicBaudRate::new(baudrate),
             initialized: AtomicBool::new(false),
+            reserved: AtomicBool::new(false),
+            sysrq_deadline: AtomicU64::new(0),
             inner: RwLock::new(Serial8250PIOPortInner::new()),
         };
 
@@ -208,6 +229,60 @@ impl Serial8250PIOPort {
         }
         return Some(self.serial_in(0) as u8);
     }
+
+    /// # 处理收到的一个字符
+    ///
+    /// 如果收到了BREAK，就记录下来，它后面的一个字符会被当作sysrq按键，而不是送往tty
+    ///
+    /// ## 返回值
+    ///
+    /// 字符已经被处理，不需要送往tty时返回true
+    fn handle_sysrq_char(&self, lsr: u32, c: u8) -> bool {
+        if lsr & UART_LSR_BI != 0 {
+            // BREAK会附带一个0字符，丢弃它
+            self.sysrq_deadline
+                .store(clock() + SYSRQ_TIMEOUT_SECS * HZ, Ordering::SeqCst);
+            return true;
+        }
+        let deadline = self.sysrq_deadline.swap(0, Ordering::SeqCst);
+        if deadline != 0 && clock() < deadline {
+            handle_sysrq(c);
+            return true;
+        }
+        return false;
+    }
+
+    /// 以轮询方式读取一个字节，不依赖中断，可以在关中断的上下文中使用
+    pub fn poll_get_char(&self) -> Option<u8> {
+        self.read_one_byte()
+    }
+
+    /// 以轮询方式发送一个字节
+    pub fn poll_put_char(&self, c: u8) {
+        while !self.is_transmit_empty() {
+            spin_loop();
+        }
+        self.serial_out(0, c.into());
+    }
+
+    /// 设置端口是否被独占
+    pub fn set_reserved(&self, reserved: bool) {
+        self.reserved.store(reserved, Ordering::SeqCst);
+    }
+
+    pub fn reserved(&self) -> bool {
+        self.reserved.load(Ordering::SeqCst)
+    }
+
+    /// 端口在ISA总线上对应的中断号
+    pub fn irq(&self) -> IrqNumber {
+        match self.iobase {
+            Serial8250PortBase::COM2 | Serial8250PortBase::COM4 => {
+                IrqNumber::new(IoApic::VECTOR_BASE as u32 + 3)
+            }
+            _ => SERIAL_8250_PIO_IRQ,
+        }
+    }
 }
 
 impl Serial8250Port for Serial8250PIOPort {
@@ -272,12 +347,16 @@ impl UartPort for Serial8250PIOPort {
 
         // Read up to the size of the buffer
         while index < buf.len() {
-            if let Some(c) = self.read_one_byte() {
-                buf[index] = c;
-                index += 1;
-            } else {
+            let lsr = self.serial_in(5);
+            if lsr & UART_LSR_DR == 0 {
                 break; // No more bytes to read
             }
+            let c = self.serial_in(0) as u8;
+            if self.handle_sysrq_char(lsr, c) {
+                continue;
+            }
+            buf[index] = c;
+            index += 1;
         }
 
         send_to_tty_refresh_thread(&buf[0..index]);
@@ -472,6 +551,9 @@ impl IrqHandler for Serial8250IrqHandler {
         _dynamic_data: Option<Arc<dyn IrqHandlerData>>,
     ) -> Result<IrqReturn, SystemError> {
         for port in unsafe { PIO_PORTS.iter() }.flatten() {
+            if port.reserved() {
+                continue;
+            }
             port.handle_irq()?;
         }
 
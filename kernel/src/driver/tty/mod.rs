@@ -3,6 +3,7 @@ use alloc::vec::Vec;
 pub mod console;
 pub mod kthread;
 pub mod pty;
+pub mod sysrq;
 mod sysfs;
 pub mod termios;
 pub mod tty_core;
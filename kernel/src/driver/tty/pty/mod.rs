@@ -186,7 +186,8 @@ impl PtyCommon {
     pub fn pty_get_lock(tty: &TtyCoreData, arg: VirtAddr) -> Result<(), SystemError> {
         let mut user_writer =
             UserBufferWriter::new(arg.as_ptr::<i32>(), core::mem::size_of::<i32>(), true)?;
-        user_writer.copy_one_to_user(&tty.flags().contains(TtyFlag::PTY_LOCK), 0)?;
+        let locked = tty.flags().contains(TtyFlag::PTY_LOCK) as i32;
+        user_writer.copy_one_to_user(&locked, 0)?;
         Ok(())
     }
 
@@ -210,7 +211,8 @@ impl PtyCommon {
     pub fn pty_get_packet_mode(tty: &TtyCoreData, arg: VirtAddr) -> Result<(), SystemError> {
         let mut user_writer =
             UserBufferWriter::new(arg.as_ptr::<i32>(), core::mem::size_of::<i32>(), true)?;
-        user_writer.copy_one_to_user(&tty.contorl_info_irqsave().packet, 0)?;
+        let packet = tty.contorl_info_irqsave().packet as i32;
+        user_writer.copy_one_to_user(&packet, 0)?;
         Ok(())
     }
 
@@ -2,6 +2,7 @@ use alloc::{string::ToString, sync::Arc};
 use system_error::SystemError;
 
 use crate::{
+    arch::ipc::signal::Signal,
     driver::tty::{
         termios::{ControlCharIndex, ControlMode, InputMode, LocalMode, Termios},
         tty_core::{TtyCore, TtyCoreData, TtyFlag, TtyIoctlCmd, TtyPacketStatus},
@@ -65,10 +66,14 @@ impl TtyOperation for Unix98PtyDriverInner {
     fn flush_buffer(&self, tty: &TtyCoreData) -> Result<(), SystemError> {
         let to = tty.checked_link()?;
 
-        let mut ctrl = to.core().contorl_info_irqsave();
-        ctrl.pktstatus.insert(TtyPacketStatus::TIOCPKT_FLUSHWRITE);
+        // packet模式下，通知对端本端的写缓冲区已被清空
+        if to.core().contorl_info_irqsave().packet {
+            tty.contorl_info_irqsave()
+                .pktstatus
+                .insert(TtyPacketStatus::TIOCPKT_FLUSHWRITE);
 
-        to.core().read_wq().wakeup_all();
+            to.core().read_wq().wakeup_all();
+        }
 
         Ok(())
     }
@@ -233,14 +238,22 @@ impl TtyOperation for Unix98PtyDriverInner {
         winsize: crate::driver::tty::termios::WindowSize,
     ) -> Result<(), SystemError> {
         let core = tty.core();
-        if *core.window_size() == winsize {
+        let link = core.checked_link()?;
+        let mut cur_winsize = core.window_size_write();
+        if *cur_winsize == winsize {
             return Ok(());
         }
-
-        // TODO：向进程发送SIGWINCH信号
-
-        *core.window_size_write() = winsize;
-        *core.link().unwrap().core().window_size_write() = winsize;
+        *cur_winsize = winsize;
+        drop(cur_winsize);
+        *link.core().window_size_write() = winsize;
+
+        // 向两端的前台进程组发送SIGWINCH信号
+        let pgrp = core.contorl_info_irqsave().pgid;
+        let rpgrp = link.core().contorl_info_irqsave().pgid;
+        core.kill_pgrp(Signal::SIGWINCH);
+        if rpgrp != pgrp {
+            link.core().kill_pgrp(Signal::SIGWINCH);
+        }
 
         Ok(())
     }
@@ -0,0 +1,203 @@
+//! Magic SysRq：在系统失去响应时，通过特殊的按键触发内核中登记的调试动作
+//!
+//! 触发方式：
+//! - 向`/proc/sysrq-trigger`写入一个字符；
+//! - 在串口控制台上发送BREAK，然后在5秒内发送一个字符；
+//! - 在PS/2键盘上按住Alt+SysRq，再按下一个键。
+//!
+//! 内置的按键：`b`重启、`c`触发崩溃、`m`打印内存信息、`s`紧急同步、`t`打印所有进程、
+//! `w`打印不可中断睡眠的进程。其他模块可以通过`register_sysrq_key`登记自己的按键，
+//! 例如gdbstub登记了`g`，用于进入内核调试器。
+//!
+//! 按键处理函数可能在中断上下文中执行，不能睡眠，需要睡眠的操作（如紧急同步）应当交给工作队列。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/drivers/tty/sysrq.c
+
+use alloc::{boxed::Box, string::String, sync::Arc};
+use log::info;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::cpu::cpu_reset,
+    driver::base::block::manager::block_dev_manager,
+    init::initcall::INITCALL_CORE,
+    libs::{
+        lazy_init::Lazy,
+        spinlock::SpinLock,
+        workqueue::{schedule_work, Work, WorkFunction},
+    },
+    mm::{page::page_reclaimer_lock_irqsave, vmstat::show_mem},
+    process::{ProcessManager, ProcessState},
+};
+
+/// 一个sysrq按键对应的操作
+#[derive(Debug)]
+pub struct SysrqKeyOp {
+    /// 按键被触发时调用，参数为按键
+    pub handler: fn(u8),
+    /// 帮助信息中显示的说明，如`debug(g)`
+    pub help_msg: &'static str,
+    /// 触发时打印的提示
+    pub action_msg: &'static str,
+}
+
+/// 按键只允许`0-9`和`a-z`
+const SYSRQ_KEY_TABLE_SIZE: usize = 36;
+
+static SYSRQ_KEY_TABLE: SpinLock<[Option<&'static SysrqKeyOp>; SYSRQ_KEY_TABLE_SIZE]> =
+    SpinLock::new([None; SYSRQ_KEY_TABLE_SIZE]);
+
+fn sysrq_key_index(key: u8) -> Option<usize> {
+    match key {
+        b'0'..=b'9' => Some((key - b'0') as usize),
+        b'a'..=b'z' => Some((key - b'a') as usize + 10),
+        _ => None,
+    }
+}
+
+/// 登记一个sysrq按键
+///
+/// ## 返回值
+///
+/// - `EINVAL`：按键不是数字或小写字母
+/// - `EBUSY`：按键已经被登记
+pub fn register_sysrq_key(key: u8, op: &'static SysrqKeyOp) -> Result<(), SystemError> {
+    let index = sysrq_key_index(key).ok_or(SystemError::EINVAL)?;
+    let mut table = SYSRQ_KEY_TABLE.lock_irqsave();
+    if table[index].is_some() {
+        return Err(SystemError::EBUSY);
+    }
+    table[index] = Some(op);
+    return Ok(());
+}
+
+/// 注销一个sysrq按键，只有登记时使用的操作才能注销
+pub fn unregister_sysrq_key(key: u8, op: &'static SysrqKeyOp) -> Result<(), SystemError> {
+    let index = sysrq_key_index(key).ok_or(SystemError::EINVAL)?;
+    let mut table = SYSRQ_KEY_TABLE.lock_irqsave();
+    match table[index] {
+        Some(cur) if core::ptr::eq(cur, op) => {
+            table[index] = None;
+            Ok(())
+        }
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+/// 触发一个sysrq按键，未登记的按键会打印帮助信息
+pub fn handle_sysrq(key: u8) {
+    let key = key.to_ascii_lowercase();
+    // 操作可能不会返回（如进入调试器），因此先释放锁
+    let op = sysrq_key_index(key).and_then(|index| SYSRQ_KEY_TABLE.lock_irqsave()[index]);
+    if let Some(op) = op {
+        info!("sysrq: {}", op.action_msg);
+        (op.handler)(key);
+        return;
+    }
+
+    let mut help = String::from("sysrq: HELP :");
+    for op in SYSRQ_KEY_TABLE.lock_irqsave().iter().flatten() {
+        help.push(' ');
+        help.push_str(op.help_msg);
+    }
+    info!("{}", help);
+}
+
+/// 处理对`/proc/sysrq-trigger`的写入，只有第一个字符有效
+pub fn write_sysrq_trigger(buf: &[u8]) -> Result<usize, SystemError> {
+    if let Some(&key) = buf.first() {
+        handle_sysrq(key);
+    }
+    return Ok(buf.len());
+}
+
+fn sysrq_handle_reboot(_key: u8) {
+    unsafe { cpu_reset() };
+}
+
+static SYSRQ_REBOOT_OP: SysrqKeyOp = SysrqKeyOp {
+    handler: sysrq_handle_reboot,
+    help_msg: "reboot(b)",
+    action_msg: "Resetting",
+};
+
+fn sysrq_handle_crash(_key: u8) {
+    panic!("sysrq triggered crash");
+}
+
+static SYSRQ_CRASH_OP: SysrqKeyOp = SysrqKeyOp {
+    handler: sysrq_handle_crash,
+    help_msg: "crash(c)",
+    action_msg: "Trigger a crash",
+};
+
+fn sysrq_handle_showmem(_key: u8) {
+    show_mem();
+}
+
+static SYSRQ_SHOWMEM_OP: SysrqKeyOp = SysrqKeyOp {
+    handler: sysrq_handle_showmem,
+    help_msg: "show-memory-usage(m)",
+    action_msg: "Show Memory",
+};
+
+/// 紧急同步：把所有的脏页和磁盘设备的缓存写回设备
+#[derive(Debug)]
+struct EmergencySyncWork;
+
+impl WorkFunction for EmergencySyncWork {
+    fn run(&self) -> Result<(), SystemError> {
+        page_reclaimer_lock_irqsave().flush_dirty_pages();
+        block_dev_manager().sync_all();
+        info!("Emergency Sync complete");
+        Ok(())
+    }
+}
+
+static EMERGENCY_SYNC_WORK: Lazy<Arc<Work>> = Lazy::new();
+
+fn sysrq_handle_sync(_key: u8) {
+    // 回写需要睡眠，交给工作队列执行
+    schedule_work(EMERGENCY_SYNC_WORK.get());
+}
+
+static SYSRQ_SYNC_OP: SysrqKeyOp = SysrqKeyOp {
+    handler: sysrq_handle_sync,
+    help_msg: "sync(s)",
+    action_msg: "Emergency Sync",
+};
+
+fn sysrq_handle_showstate(_key: u8) {
+    ProcessManager::show_state_filter(|_| true);
+}
+
+static SYSRQ_SHOWSTATE_OP: SysrqKeyOp = SysrqKeyOp {
+    handler: sysrq_handle_showstate,
+    help_msg: "show-task-states(t)",
+    action_msg: "Show State",
+};
+
+fn sysrq_handle_showstate_blocked(_key: u8) {
+    ProcessManager::show_state_filter(|state| *state == ProcessState::Blocked(false));
+}
+
+static SYSRQ_SHOWSTATE_BLOCKED_OP: SysrqKeyOp = SysrqKeyOp {
+    handler: sysrq_handle_showstate_blocked,
+    help_msg: "show-blocked-tasks(w)",
+    action_msg: "Show Blocked State",
+};
+
+/// 登记内置的sysrq按键
+#[unified_init(INITCALL_CORE)]
+fn sysrq_init() -> Result<(), SystemError> {
+    EMERGENCY_SYNC_WORK.init(Work::new(Box::new(EmergencySyncWork)));
+
+    register_sysrq_key(b'b', &SYSRQ_REBOOT_OP)?;
+    register_sysrq_key(b'c', &SYSRQ_CRASH_OP)?;
+    register_sysrq_key(b'm', &SYSRQ_SHOWMEM_OP)?;
+    register_sysrq_key(b's', &SYSRQ_SYNC_OP)?;
+    register_sysrq_key(b't', &SYSRQ_SHOWSTATE_OP)?;
+    register_sysrq_key(b'w', &SYSRQ_SHOWSTATE_BLOCKED_OP)?;
+    Ok(())
+}
@@ -4,13 +4,13 @@ use core::{
 };
 
 use alloc::{
-    collections::LinkedList,
     string::String,
     sync::{Arc, Weak},
 };
 use system_error::SystemError;
 
 use crate::{
+    arch::ipc::signal::Signal,
     driver::{base::device::device_number::DeviceNumber, tty::pty::ptm_driver},
     libs::{
         rwlock::{RwLock, RwLockReadGuard, RwLockUpgradableGuard, RwLockWriteGuard},
@@ -18,9 +18,12 @@ use crate::{
         wait_queue::EventWaitQueue,
     },
     mm::VirtAddr,
-    net::event_poll::{EPollEventType, EPollItem},
+    net::event_poll::{EPollEventType, EPollItems},
     process::Pid,
-    syscall::user_access::{UserBufferReader, UserBufferWriter},
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
 };
 
 use super::{
@@ -72,7 +75,7 @@ impl TtyCore {
             closing: AtomicBool::new(false),
             flow: SpinLock::new(TtyFlowState::default()),
             link: RwLock::default(),
-            epitems: SpinLock::new(LinkedList::new()),
+            epitems: EPollItems::new(),
             device_number,
             privete_fields: SpinLock::new(None),
         };
@@ -272,10 +275,28 @@ impl TtyCore {
     }
 
     pub fn tty_do_resize(&self, windowsize: WindowSize) -> Result<(), SystemError> {
-        // TODO: 向前台进程发送信号
-        *self.core.window_size_write() = windowsize;
+        let mut winsize = self.core.window_size_write();
+        if *winsize == windowsize {
+            return Ok(());
+        }
+        *winsize = windowsize;
+        drop(winsize);
+
+        self.core.kill_pgrp(Signal::SIGWINCH);
         Ok(())
     }
+
+    /// ### 获取真正处理ioctl的tty
+    ///
+    /// 对pty master的窗口大小等操作实际作用于对应的slave端
+    pub fn pair_tty(self: &Arc<Self>) -> Arc<TtyCore> {
+        if self.core.driver().tty_driver_sub_type() == TtyDriverSubType::PtyMaster {
+            if let Some(link) = self.core.link() {
+                return link;
+            }
+        }
+        self.clone()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -285,8 +306,9 @@ pub struct TtyContorlInfo {
     /// 前台进程组id
     pub pgid: Option<Pid>,
 
-    /// packet模式下使用，目前未用到
+    /// 尚未被对端以packet模式读取的状态变化
     pub pktstatus: TtyPacketStatus,
+    /// 是否处于packet模式（仅pty master）
     pub packet: bool,
 }
 
@@ -325,7 +347,7 @@ pub struct TtyCoreData {
     /// 链接tty
     link: RwLock<Weak<TtyCore>>,
     /// epitems
-    epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+    epitems: EPollItems,
     /// 设备号
     device_number: DeviceNumber,
 
@@ -456,6 +478,14 @@ impl TtyCoreData {
         self.link.read().upgrade()
     }
 
+    /// 向终端的前台进程组发送信号
+    pub fn kill_pgrp(&self, sig: Signal) {
+        let pgid = self.contorl_info_irqsave().pgid;
+        if let Some(pgid) = pgid {
+            let _ = Syscall::kill(pgid, sig as i32);
+        }
+    }
+
     pub fn checked_link(&self) -> Result<Arc<TtyCore>, SystemError> {
         if let Some(link) = self.link() {
             return Ok(link);
@@ -486,11 +516,7 @@ impl TtyCoreData {
     }
 
     #[inline]
-    pub fn add_epitem(&self, epitem: Arc<EPollItem>) {
-        self.epitems.lock().push_back(epitem)
-    }
-
-    pub fn eptiems(&self) -> &SpinLock<LinkedList<Arc<EPollItem>>> {
+    pub fn epitems(&self) -> &EPollItems {
         &self.epitems
     }
 
@@ -31,10 +31,10 @@ use crate::{
     init::initcall::INITCALL_DEVICE,
     libs::{
         rwlock::{RwLock, RwLockWriteGuard},
-        spinlock::SpinLockGuard,
+        spinlock::{SpinLock, SpinLockGuard},
     },
     mm::VirtAddr,
-    net::event_poll::{EPollItem, KernelIoctlData},
+    net::event_poll::{EPollItem, EventPoll},
     process::ProcessManager,
     syscall::user_access::{UserBufferReader, UserBufferWriter},
 };
@@ -312,33 +312,33 @@ impl IndexNode for TtyDevice {
         Ok(())
     }
 
-    fn kernel_ioctl(
+    fn add_epitem(
         &self,
-        arg: Arc<dyn KernelIoctlData>,
-        data: &FilePrivateData,
-    ) -> Result<usize, SystemError> {
-        let epitem = arg
-            .arc_any()
-            .downcast::<EPollItem>()
-            .map_err(|_| SystemError::EFAULT)?;
-
-        let _ = UserBufferReader::new(
-            &epitem as *const Arc<EPollItem>,
-            core::mem::size_of::<Arc<EPollItem>>(),
-            false,
-        )?;
-
-        let (tty, _) = if let FilePrivateData::Tty(tty_priv) = data {
-            (tty_priv.tty(), tty_priv.mode)
+        epitem: Arc<EPollItem>,
+        private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        let tty = if let FilePrivateData::Tty(tty_priv) = private_data {
+            tty_priv.tty()
         } else {
             return Err(SystemError::EIO);
         };
 
-        let core = tty.core();
+        tty.core().epitems().add(epitem);
+        return Ok(());
+    }
 
-        core.add_epitem(epitem.clone());
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        let tty = if let FilePrivateData::Tty(tty_priv) = private_data {
+            tty_priv.tty()
+        } else {
+            return Err(SystemError::EIO);
+        };
 
-        return Ok(0);
+        tty.core().epitems().remove(epoll)
     }
 
     fn ioctl(&self, cmd: u32, arg: usize, data: &FilePrivateData) -> Result<usize, SystemError> {
@@ -364,7 +364,8 @@ impl IndexNode for TtyDevice {
 
         match cmd {
             TtyIoctlCmd::TIOCGWINSZ => {
-                let core = tty.core();
+                let real_tty = tty.pair_tty();
+                let core = real_tty.core();
                 let winsize = *core.window_size();
 
                 let mut user_writer = UserBufferWriter::new(
@@ -388,12 +389,13 @@ impl IndexNode for TtyDevice {
 
                 let user_winsize = reader.read_one_from_user::<WindowSize>(0)?;
 
-                let ret = tty.resize(tty.clone(), *user_winsize);
+                let real_tty = tty.pair_tty();
+                let ret = real_tty.resize(real_tty.clone(), *user_winsize);
 
                 if ret != Err(SystemError::ENOSYS) {
                     return ret.map(|_| 0);
                 } else {
-                    return tty.tty_do_resize(*user_winsize).map(|_| 0);
+                    return real_tty.tty_do_resize(*user_winsize).map(|_| 0);
                 }
             }
             _ => match TtyJobCtrlManager::job_ctrl_ioctl(tty.clone(), cmd, arg) {
@@ -1640,7 +1640,7 @@ impl TtyLineDiscipline for NTtyLinediscipline {
         let tail = ldata.read_tail;
         drop(ldata);
         while nr != 0 {
-            // todo: 处理packet模式
+            // packet模式下，优先返回对端的状态变化
             if packet {
                 let link = core.link().unwrap();
                 let link = link.core();
@@ -1710,7 +1710,7 @@ impl TtyLineDiscipline for NTtyLinediscipline {
                 }
             } else {
                 // 非标准模式
-                // todo: 处理packet模式
+                // packet模式下，数据之前需要加上TIOCPKT_DATA
                 if packet && offset == 0 {
                     buf[offset] = TtyPacketStatus::TIOCPKT_DATA.bits();
                     offset += 1;
@@ -4,10 +4,7 @@ use alloc::sync::{Arc, Weak};
 use kdepends::thingbuf::mpsc;
 use system_error::SystemError;
 
-use crate::{
-    libs::spinlock::{SpinLock, SpinLockGuard},
-    net::event_poll::EventPoll,
-};
+use crate::libs::spinlock::{SpinLock, SpinLockGuard};
 
 use super::tty_core::TtyCore;
 
@@ -88,7 +85,7 @@ pub trait TtyPort: Sync + Send + Debug {
             return ld.receive_buf(tty, buf, None, count);
         }
 
-        EventPoll::wakeup_epoll(tty.core().eptiems(), None)?;
+        tty.core().epitems().wakeup(None)?;
 
         ret
     }
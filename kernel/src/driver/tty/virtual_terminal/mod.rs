@@ -19,7 +19,13 @@ use crate::{
     },
     filesystem::devfs::{devfs_register, devfs_unregister},
     init::initcall::INITCALL_LATE,
-    libs::{lazy_init::Lazy, rwlock::RwLock, spinlock::SpinLock},
+    libs::{
+        console::{register_console, Console, ConsoleFlags},
+        lazy_init::Lazy,
+        lib_ui::textui::{textui_putstr, FontColor},
+        rwlock::RwLock,
+        spinlock::SpinLock,
+    },
 };
 
 use self::virtual_console::VirtualConsoleData;
@@ -475,5 +481,34 @@ fn vty_late_init() -> Result<(), SystemError> {
     console_driver.init_tty_device(None).ok();
 
     vc_manager().setup_default_vc();
+    // 接替textui启动控制台
+    register_console(&VtConsole, ConsoleFlags::empty())?;
     Ok(())
 }
+
+/// 当前虚拟终端对应的内核控制台
+#[derive(Debug)]
+struct VtConsole;
+
+impl Console for VtConsole {
+    fn name(&self) -> &'static str {
+        "tty0"
+    }
+
+    /// 直接写入虚拟终端，不经过tty驱动，以免日志被重复输出到串口
+    fn write(&self, s: &str) {
+        let tty = vc_manager()
+            .current_vc()
+            .and_then(|vc| vc.port().port_data().internal_tty());
+        let Some(tty) = tty else {
+            textui_putstr(s, FontColor::WHITE, FontColor::BLACK).ok();
+            return;
+        };
+
+        let core = tty.core();
+        core.do_write(s.as_bytes(), s.len()).ok();
+        if let Some(vc_data) = core.vc_data() {
+            vc_data.lock_irqsave().set_cursor();
+        }
+    }
+}
@@ -4,7 +4,7 @@ use system_error::SystemError;
 use unified_init::macros::unified_init;
 
 use crate::{
-    driver::base::device::DeviceId,
+    driver::base::device::{Device, DeviceId},
     exception::{
         irqdata::IrqHandlerData,
         irqdesc::{IrqHandler, IrqReturn},
@@ -116,6 +116,10 @@ impl IrqHandler for DefaultVirtioIrqHandler {
             .map_err(|_| SystemError::EINVAL)?;
 
         if let Some(dev) = virtio_irq_manager().lookup_device(&dev_id) {
+            // 设备可能在查找之后被热拔出，此时不能再访问它的传输层
+            if dev.check_alive().is_err() {
+                return Ok(IrqReturn::NotHandled);
+            }
             return dev.handle_irq(irq);
         } else {
             // 未绑定具体设备，因此无法处理中断
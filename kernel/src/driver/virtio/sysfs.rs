@@ -240,9 +240,17 @@ impl VirtIODeviceManager {
         return Ok(());
     }
 
+    /// # device_remove - 删除virtio设备
+    ///
+    /// 用于virtio设备被热拔出的场景。先停止向设备分发中断，再从设备模型中删除设备，
+    /// 此后对该设备的操作都会返回`ENODEV`。
     #[allow(dead_code)]
     pub fn device_remove(&self, dev: &Arc<dyn VirtIODevice>) -> Result<(), SystemError> {
+        virtio_irq_manager().unregister_device(dev.dev_id());
         device_manager().remove(&(dev.clone() as Arc<dyn Device>));
+        if let Some(index) = dev.virtio_device_index() {
+            VIRTIO_DEVICE_INDEX_MANAGER.free(index);
+        }
         return Ok(());
     }
 }
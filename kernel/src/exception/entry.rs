@@ -1,7 +1,9 @@
 use crate::{
     arch::{interrupt::TrapFrame, CurrentSignalArch},
+    cgroup,
     ipc::signal_types::SignalArch,
-    process::{ProcessFlags, ProcessManager},
+    process::{freezer, ProcessFlags, ProcessManager},
+    sched,
 };
 
 #[no_mangle]
@@ -41,6 +43,18 @@ unsafe fn exit_to_user_mode_loop(frame: &mut TrapFrame, mut process_flags_work:
         if process_flags_work.contains(ProcessFlags::HAS_PENDING_SIGNAL) {
             unsafe { CurrentSignalArch::do_signal_or_restart(frame) };
         }
+        if process_flags_work.contains(ProcessFlags::CPU_THROTTLED) {
+            cgroup::cpu::throttle_current();
+        }
+        if process_flags_work.contains(ProcessFlags::MEMCG_OVER_LIMIT) {
+            cgroup::memory::handle_over_limit();
+        }
+        if process_flags_work.contains(ProcessFlags::FREEZE_PENDING) {
+            freezer::refrigerator();
+        }
+        if process_flags_work.contains(ProcessFlags::NEED_MIGRATE) {
+            sched::migrate_current();
+        }
         process_flags_work = *ProcessManager::current_pcb().flags();
     }
 }
@@ -7,7 +7,7 @@ use crate::arch::driver::apic::{CurrentApic, LocalAPIC};
 use crate::{
     arch::MMArch,
     mm::MemoryManagementArch,
-    sched::{SchedMode, __schedule},
+    process::{ProcessFlags, ProcessManager},
     smp::cpu::ProcessorId,
 };
 
@@ -54,8 +54,10 @@ impl IrqHandler for KickCpuIpiHandler {
         #[cfg(target_arch = "x86_64")]
         CurrentApic.send_eoi();
 
-        // 被其他cpu kick时应该是抢占调度
-        __schedule(SchedMode::SM_PREEMPT);
+        // 被其他cpu kick时应该是抢占调度，在中断返回时进行
+        ProcessManager::current_pcb()
+            .flags()
+            .insert(ProcessFlags::NEED_SCHEDULE);
         Ok(IrqReturn::Handled)
     }
 }
@@ -19,10 +19,12 @@ use crate::{
     process::ProcessManager,
     sched::cputime::IrqTime,
     smp::{core::smp_get_processor_id, cpu::ProcessorId},
-    time::timer::clock,
+    time::{jiffies::msecs_to_jiffies, timer::clock},
 };
 
 const MAX_SOFTIRQ_NUM: u64 = 64;
+/// 一次处理软中断最多持续的时间（毫秒），超出后剩余的软中断留到下次处理
+const MAX_SOFTIRQ_TIME_MS: u64 = 2;
 const MAX_SOFTIRQ_RESTART: i32 = 20;
 
 static mut __CPU_PENDING: Option<Box<[VecStatus; PerCpu::MAX_CPU_NUM as usize]>> = None;
@@ -190,9 +192,11 @@ impl Softirq {
         }
         // 创建一个RunningCountGuard，当退出作用域时，会自动将cpu_running_count减1
         let _count_guard = RunningCountGuard::new(self.cpu_running_count());
+        // 软中断处理函数运行时中断是开启的，但不能被抢占
+        ProcessManager::preempt_disable();
 
         // TODO pcb的flags未修改
-        let end = clock() + 500 * 2;
+        let end = clock() + msecs_to_jiffies(MAX_SOFTIRQ_TIME_MS);
         let cpu_id = smp_get_processor_id();
         let mut max_restart = MAX_SOFTIRQ_RESTART;
         loop {
@@ -244,6 +248,8 @@ impl Softirq {
                 break;
             }
         }
+        // 此时中断是关闭的，返回被中断的现场之前会检查是否需要调度
+        ProcessManager::preempt_enable_no_resched();
     }
 
     pub fn raise_softirq(&self, softirq_num: SoftirqNumber) {
@@ -0,0 +1,192 @@
+//! debugfs：用于内核调试的伪文件系统
+//!
+//! 各个子系统可以在这里创建临时性的调试文件，而不必为它们设计稳定的sysfs/procfs接口。
+//! 文件的内容由创建时传入的闭包提供：读文件时调用读回调生成完整的内容，
+//! 写文件时把写入的字符串交给写回调处理。
+//! 回调在持有kernfs inode的自旋锁时被调用，不能睡眠。
+//!
+//! debugfs基于kernfs实现，挂载在`/sys/kernel/debug`下。在挂载之前就可以创建文件。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/fs/debugfs/inode.c
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::fmt::Debug;
+use log::info;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    driver::base::kobject::KObject,
+    filesystem::{
+        kernfs::{
+            callback::{KernCallbackData, KernFSCallback, KernInodePrivateData},
+            KernFS, KernFSInode,
+        },
+        vfs::{syscall::ModeType, FileSystem, IndexNode, PollStatus, ROOT_INODE},
+    },
+    init::initcall::INITCALL_FS,
+    libs::casting::DowncastArc,
+    misc::ksysfs::sys_kernel_kset,
+};
+
+/// debugfs文件的读回调，返回文件的完整内容
+pub type DebugFSReadFn = Box<dyn Fn() -> Result<String, SystemError> + Send + Sync>;
+/// debugfs文件的写回调，参数为写入的内容（已去掉首尾的空白）
+pub type DebugFSWriteFn = Box<dyn Fn(&str) -> Result<(), SystemError> + Send + Sync>;
+
+/// debugfs只读文件的权限
+pub const DEBUGFS_MODE_RO: ModeType = ModeType::from_bits_truncate(0o444);
+/// debugfs读写文件的权限
+pub const DEBUGFS_MODE_RW: ModeType = ModeType::from_bits_truncate(0o644);
+/// debugfs目录的权限
+const DEBUGFS_DIR_MODE: ModeType = ModeType::from_bits_truncate(0o755);
+
+lazy_static! {
+    static ref DEBUGFS: Arc<KernFS> = KernFS::new();
+}
+
+/// debugfs的根目录
+pub fn debugfs_root() -> Arc<KernFSInode> {
+    DEBUGFS.root_inode().downcast_arc::<KernFSInode>().unwrap()
+}
+
+/// debugfs文件在kernfs inode中的私有数据
+pub struct DebugFSFile {
+    read: Option<DebugFSReadFn>,
+    write: Option<DebugFSWriteFn>,
+}
+
+impl Debug for DebugFSFile {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DebugFSFile")
+            .field("read", &self.read.is_some())
+            .field("write", &self.write.is_some())
+            .finish()
+    }
+}
+
+impl DebugFSFile {
+    pub fn callback_read(&self, buf: &mut [u8], offset: usize) -> Result<usize, SystemError> {
+        let read = self.read.as_ref().ok_or(SystemError::EPERM)?;
+        let content = read()?;
+        let content = content.as_bytes();
+        if offset >= content.len() {
+            return Ok(0);
+        }
+        let len = (content.len() - offset).min(buf.len());
+        buf[..len].copy_from_slice(&content[offset..offset + len]);
+        return Ok(len);
+    }
+
+    pub fn callback_write(&self, buf: &[u8], _offset: usize) -> Result<usize, SystemError> {
+        let write = self.write.as_ref().ok_or(SystemError::EPERM)?;
+        let s = core::str::from_utf8(buf)
+            .map_err(|_| SystemError::EINVAL)?
+            .trim_end_matches('\0')
+            .trim();
+        write(s)?;
+        return Ok(buf.len());
+    }
+}
+
+#[derive(Debug)]
+struct DebugFSCallback;
+
+impl KernFSCallback for DebugFSCallback {
+    fn open(&self, _data: KernCallbackData) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn read(
+        &self,
+        data: KernCallbackData,
+        buf: &mut [u8],
+        offset: usize,
+    ) -> Result<usize, SystemError> {
+        return data.callback_read(buf, offset);
+    }
+
+    fn write(
+        &self,
+        data: KernCallbackData,
+        buf: &[u8],
+        offset: usize,
+    ) -> Result<usize, SystemError> {
+        return data.callback_write(buf, offset);
+    }
+
+    fn poll(&self, _data: KernCallbackData) -> Result<PollStatus, SystemError> {
+        return Ok(PollStatus::READ | PollStatus::WRITE);
+    }
+}
+
+/// 在debugfs中创建目录
+///
+/// ## 参数
+///
+/// - `name`: 目录名
+/// - `parent`: 父目录，为None时在debugfs的根目录下创建
+pub fn debugfs_create_dir(
+    name: &str,
+    parent: Option<&Arc<KernFSInode>>,
+) -> Result<Arc<KernFSInode>, SystemError> {
+    let parent = parent.cloned().unwrap_or_else(debugfs_root);
+    if parent.find(name).is_ok() {
+        return Err(SystemError::EEXIST);
+    }
+    parent.add_dir(name.to_string(), DEBUGFS_DIR_MODE, None, None)
+}
+
+/// 在debugfs中创建文件
+///
+/// ## 参数
+///
+/// - `name`: 文件名
+/// - `mode`: 文件权限
+/// - `parent`: 父目录，为None时在debugfs的根目录下创建
+/// - `read`: 读回调，为None时文件不可读
+/// - `write`: 写回调，为None时文件不可写
+pub fn debugfs_create_file(
+    name: &str,
+    mode: ModeType,
+    parent: Option<&Arc<KernFSInode>>,
+    read: Option<DebugFSReadFn>,
+    write: Option<DebugFSWriteFn>,
+) -> Result<Arc<KernFSInode>, SystemError> {
+    let parent = parent.cloned().unwrap_or_else(debugfs_root);
+    if parent.find(name).is_ok() {
+        return Err(SystemError::EEXIST);
+    }
+    parent.add_file(
+        name.to_string(),
+        mode,
+        None,
+        Some(KernInodePrivateData::DebugFS(DebugFSFile { read, write })),
+        Some(&DebugFSCallback),
+    )
+}
+
+/// 删除debugfs中的文件或目录（包括目录下的所有内容）
+pub fn debugfs_remove(inode: &Arc<KernFSInode>) {
+    inode.remove_inode_include_self();
+}
+
+/// 创建挂载点`/sys/kernel/debug`并挂载debugfs
+#[unified_init(INITCALL_FS)]
+fn debugfs_init() -> Result<(), SystemError> {
+    let kernel_dir = sys_kernel_kset()
+        .as_kobject()
+        .inode()
+        .ok_or(SystemError::ENOENT)?;
+    kernel_dir.add_dir("debug".to_string(), DEBUGFS_DIR_MODE, None, None)?;
+
+    ROOT_INODE()
+        .lookup("/sys/kernel/debug")?
+        .mount(DEBUGFS.clone())?;
+    info!("DebugFS mounted.");
+    return Ok(());
+}
@@ -148,10 +148,34 @@ impl DevFS {
                 if name.starts_with("tty") && name.len() > 3 {
                     dev_root_inode.add_dev(name, device.clone())?;
                 }
-                // ptmx设备
-                if name == "ptmx" {
+                // ptmx设备、ublk控制设备、回环设备的控制设备
+                if name == "ptmx" || name == "ublk-control" || name == "loop-control" {
                     dev_root_inode.add_dev(name, device.clone())?;
                 }
+                // device-mapper的控制设备，挂载在 /dev/mapper/control
+                if name == "device-mapper" {
+                    if dev_root_inode.find("mapper").is_err() {
+                        dev_root_inode.add_dir("mapper")?;
+                    }
+                    let any_mapper_inode = dev_root_inode.find("mapper")?;
+                    let dev_mapper_inode: &LockedDevFSInode = any_mapper_inode
+                        .as_any_ref()
+                        .downcast_ref::<LockedDevFSInode>()
+                        .unwrap();
+                    dev_mapper_inode.add_dev("control", device.clone())?;
+                }
+                // TUN/TAP设备的控制设备，挂载在 /dev/net/tun
+                if name == "tun" {
+                    if dev_root_inode.find("net").is_err() {
+                        dev_root_inode.add_dir("net")?;
+                    }
+                    let any_net_inode = dev_root_inode.find("net")?;
+                    let dev_net_inode: &LockedDevFSInode = any_net_inode
+                        .as_any_ref()
+                        .downcast_ref::<LockedDevFSInode>()
+                        .unwrap();
+                    dev_net_inode.add_dev("tun", device.clone())?;
+                }
                 device.set_fs(dev_char_inode.0.lock().fs.clone());
             }
             FileType::BlockDevice => {
@@ -170,6 +194,10 @@ impl DevFS {
                     .unwrap();
 
                 dev_block_inode.add_dev(name, device.clone())?;
+                // 回环设备与映射设备，挂载在 /dev 下
+                if name.starts_with("loop") || name.starts_with("dm-") {
+                    dev_root_inode.add_dev(name, device.clone())?;
+                }
                 device.set_fs(dev_block_inode.0.lock().fs.clone());
             }
             FileType::KvmDevice => {
@@ -224,6 +252,9 @@ impl DevFS {
                     .unwrap();
 
                 dev_block_inode.remove(name)?;
+                if name.starts_with("loop") || name.starts_with("dm-") {
+                    dev_root_inode.remove(name)?;
+                }
             }
             _ => {
                 return Err(SystemError::ENOSYS);
@@ -602,7 +633,6 @@ pub fn devfs_register<T: DeviceINode>(name: &str, device: Arc<T>) -> Result<(),
 }
 
 /// @brief devfs的设备卸载函数
-#[allow(dead_code)]
 pub fn devfs_unregister<T: DeviceINode>(name: &str, device: Arc<T>) -> Result<(), SystemError> {
     return devfs_exact_ref!().unregister_device(name, device);
 }
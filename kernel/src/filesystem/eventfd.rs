@@ -3,11 +3,10 @@ use crate::filesystem::vfs::syscall::ModeType;
 use crate::filesystem::vfs::{FilePrivateData, FileSystem, FileType, IndexNode, Metadata};
 use crate::libs::spinlock::{SpinLock, SpinLockGuard};
 use crate::libs::wait_queue::WaitQueue;
-use crate::net::event_poll::{EPollEventType, EPollItem, EventPoll, KernelIoctlData};
+use crate::net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll};
 use crate::process::{ProcessFlags, ProcessManager};
 use crate::sched::SchedMode;
 use crate::syscall::Syscall;
-use alloc::collections::LinkedList;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::sync::Weak;
@@ -52,7 +51,7 @@ impl EventFd {
 pub struct EventFdInode {
     eventfd: SpinLock<EventFd>,
     wait_queue: WaitQueue,
-    epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+    epitems: EPollItems,
 }
 
 impl EventFdInode {
@@ -60,23 +59,9 @@ impl EventFdInode {
         EventFdInode {
             eventfd: SpinLock::new(eventfd),
             wait_queue: WaitQueue::default(),
-            epitems: SpinLock::new(LinkedList::new()),
+            epitems: EPollItems::new(),
         }
     }
-    pub fn remove_epoll(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
-        let is_remove = !self
-            .epitems
-            .lock_irqsave()
-            .extract_if(|x| x.epoll().ptr_eq(epoll))
-            .collect::<Vec<_>>()
-            .is_empty();
-
-        if is_remove {
-            return Ok(());
-        }
-
-        Err(SystemError::ENOENT)
-    }
 
     fn readable(&self) -> bool {
         let count = self.eventfd.lock().count;
@@ -170,8 +155,10 @@ impl IndexNode for EventFdInode {
         let pollflag = EPollEventType::from_bits_truncate(self.do_poll(&data, &eventfd)? as u32);
         drop(eventfd);
 
+        // 计数减少之后，因为计数将要溢出而阻塞的写者可以继续写入
+        self.wait_queue.wakeup_all(None);
         // 唤醒epoll中等待的进程
-        EventPoll::wakeup_epoll(&self.epitems, Some(pollflag))?;
+        self.epitems.wakeup(Some(pollflag))?;
 
         return Ok(8);
     }
@@ -225,7 +212,7 @@ impl IndexNode for EventFdInode {
         drop(eventfd);
 
         // 唤醒epoll中等待的进程
-        EventPoll::wakeup_epoll(&self.epitems, Some(pollflag))?;
+        self.epitems.wakeup(Some(pollflag))?;
         return Ok(8);
     }
 
@@ -250,17 +237,20 @@ impl IndexNode for EventFdInode {
     fn resize(&self, _len: usize) -> Result<(), SystemError> {
         Ok(())
     }
-    fn kernel_ioctl(
+    fn add_epitem(
         &self,
-        arg: Arc<dyn KernelIoctlData>,
-        _data: &FilePrivateData,
-    ) -> Result<usize, SystemError> {
-        let epitem = arg
-            .arc_any()
-            .downcast::<EPollItem>()
-            .map_err(|_| SystemError::EFAULT)?;
-        self.epitems.lock().push_back(epitem);
-        Ok(0)
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.add(epitem);
+        Ok(())
+    }
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.remove(epoll)
     }
     fn fs(&self) -> Arc<dyn FileSystem> {
         panic!("EventFd does not have a filesystem")
@@ -293,11 +283,13 @@ impl Syscall {
             .ok_or(SystemError::ENOMEM)? as u32;
         let eventfd = EventFd::new(init_val as u64, flags, id);
         let inode = Arc::new(EventFdInode::new(eventfd));
-        let filemode = if flags.contains(EventFdFlags::EFD_CLOEXEC) {
-            FileMode::O_RDWR | FileMode::O_CLOEXEC
-        } else {
-            FileMode::O_RDWR
-        };
+        let mut filemode = FileMode::O_RDWR;
+        if flags.contains(EventFdFlags::EFD_CLOEXEC) {
+            filemode |= FileMode::O_CLOEXEC;
+        }
+        if flags.contains(EventFdFlags::EFD_NONBLOCK) {
+            filemode |= FileMode::O_NONBLOCK;
+        }
         let file = File::new(inode, filemode)?;
         let binding = ProcessManager::current_pcb().fd_table();
         let mut fd_table_guard = binding.write();
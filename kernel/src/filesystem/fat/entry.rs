@@ -6,6 +6,7 @@ use system_error::SystemError;
 use crate::{
     driver::base::block::{block_device::LBA_SIZE, SeekFrom},
     libs::vec_cursor::VecCursor,
+    time::{Instant, PosixTimeSpec},
 };
 use alloc::{
     string::{String, ToString},
@@ -177,6 +178,9 @@ impl FATFile {
         let mut start: usize = 0;
         let mut write_ok: usize = 0;
 
+        // 塞住请求队列，让相邻簇的写入合并成连续的大请求
+        let plug = fs.gendisk.plug();
+
         // 循环写入数据
         loop {
             if in_cluster_bytes_offset >= fs.bytes_per_cluster() {
@@ -209,6 +213,7 @@ impl FATFile {
                 break;
             }
         }
+        plug.finish()?;
         // todo: 更新时间信息
         return Ok(write_ok);
     }
@@ -719,7 +724,11 @@ impl FATDir {
         short_dentry.name = *short_name;
         short_dentry.attributes = attrs;
 
-        // todo: 设置创建时间、修改时间
+        // 新建的目录项记录创建时间，重命名时保留原来的创建时间
+        // todo: 设置修改时间
+        if short_dentry.crt_date == 0 {
+            short_dentry.set_create_time(PosixTimeSpec::now());
+        }
 
         let mut long_name_gen: LongNameEntryGenerator =
             LongNameEntryGenerator::new(long_name, short_dentry.checksum());
@@ -1201,6 +1210,56 @@ impl ShortDirEntry {
             && self.attributes.contains(FileAttributes::VOLUME_ID);
     }
 
+    /// # 获取文件的创建时间
+    ///
+    /// FAT以本地时间记录时间，内核没有时区信息，因此按照UTC解释。创建日期为0时表示没有记录创建时间
+    pub fn create_time(&self) -> Option<PosixTimeSpec> {
+        if self.crt_date == 0 {
+            return None;
+        }
+        let year = 1980 + (self.crt_date >> 9) as u32;
+        let month = ((self.crt_date >> 5) & 0xf) as u32;
+        let day = (self.crt_date & 0x1f) as u32;
+        let hour = (self.crt_time >> 11) as u32;
+        let min = ((self.crt_time >> 5) & 0x3f) as u32;
+        // crt_time中的秒以2秒为单位，crt_time_tenth以10毫秒为单位，取值0..199
+        let sec = (self.crt_time & 0x1f) as u32 * 2 + self.crt_time_tenth as u32 / 100;
+        let nsec = (self.crt_time_tenth % 100) as i64 * 10_000_000;
+        let secs = Instant::mktime64(year, month, day, hour, min, sec).secs();
+        Some(PosixTimeSpec::new(secs, nsec))
+    }
+
+    /// # 设置文件的创建时间
+    ///
+    /// 早于1980年的时间无法表示，此时不记录创建时间
+    pub fn set_create_time(&mut self, time: PosixTimeSpec) {
+        const SECS_PER_DAY: i64 = 24 * 60 * 60;
+        let days = time.tv_sec.div_euclid(SECS_PER_DAY);
+        let secs = time.tv_sec.rem_euclid(SECS_PER_DAY);
+
+        // 把1970-01-01以来的天数转换为年月日
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        if !(1980..=2107).contains(&year) {
+            self.crt_date = 0;
+            self.crt_time = 0;
+            self.crt_time_tenth = 0;
+            return;
+        }
+        self.crt_date = (((year - 1980) << 9) | (month << 5) | day) as u16;
+        self.crt_time =
+            (((secs / 3600) << 11) | (((secs / 60) % 60) << 5) | ((secs % 60) / 2)) as u16;
+        self.crt_time_tenth = ((secs % 2) * 100 + time.tv_nsec / 10_000_000) as u8;
+    }
+
     /// @brief 将短目录项的名字转换为String
     fn name_to_string(&self) -> String {
         // 计算基础名的长度
@@ -3,6 +3,7 @@ use core::cmp::Ordering;
 use core::intrinsics::unlikely;
 use core::{any::Any, fmt::Debug};
 use hashbrown::HashMap;
+use linkme::distributed_slice;
 use log::error;
 use system_error::SystemError;
 
@@ -13,13 +14,19 @@ use alloc::{
 };
 
 use crate::driver::base::block::gendisk::GenDisk;
+use crate::driver::base::block::manager::block_dev_manager;
 use crate::driver::base::device::device_number::DeviceNumber;
 use crate::filesystem::page_cache::PageCache;
+use crate::filesystem::vfs::mount::fs_error_remount_ro;
 use crate::filesystem::vfs::utils::DName;
-use crate::filesystem::vfs::{Magic, SpecialNodeData, SuperBlock};
+use crate::filesystem::vfs::{
+    FileSystemMaker, FileSystemMakerData, Magic, SpecialNodeData, SuperBlock, FSMAKER, MAX_PATHLEN,
+};
 use crate::ipc::pipe::LockedPipeInode;
 use crate::mm::fault::{PageFaultHandler, PageFaultMessage};
+use crate::mm::writeback::balance_dirty_pages_ratelimited;
 use crate::mm::VmFaultReason;
+use crate::syscall::user_access::check_and_clone_cstr;
 use crate::{
     driver::base::block::{block_device::LBA_SIZE, disk_info::Partition, SeekFrom},
     filesystem::vfs::{
@@ -241,6 +248,8 @@ impl LockedFATInode {
 
         if !inode.0.lock().inode_type.is_dir() {
             let page_cache = PageCache::new(Some(Arc::downgrade(&inode) as Weak<dyn IndexNode>));
+            let bdi = fs.gendisk.block_device().blkdev_meta().bdi().clone();
+            page_cache.set_bdi(bdi).ok();
             inode.0.lock().page_cache = Some(page_cache);
         }
 
@@ -313,6 +322,55 @@ impl FileSystem for FATFileSystem {
     }
 }
 
+#[distributed_slice(FSMAKER)]
+static FATFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "vfat",
+    &(FATFileSystem::make_fatfs
+        as fn(
+            Option<&dyn FileSystemMakerData>,
+        ) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+/// 挂载参数：挂载源对应的gendisk
+#[derive(Debug)]
+pub struct FatMountData {
+    gendisk: Arc<GenDisk>,
+}
+
+impl FileSystemMakerData for FatMountData {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl FatMountData {
+    /// 根据挂载源的路径（如`/dev/loop0`）查找gendisk
+    pub fn from_source(source: *const u8) -> Result<Self, SystemError> {
+        if source.is_null() {
+            return Err(SystemError::EINVAL);
+        }
+        let path = check_and_clone_cstr(source, Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+        let gendisk = block_dev_manager()
+            .lookup_gendisk_by_path(&path)
+            .ok_or(SystemError::ENODEV)?;
+        Ok(Self { gendisk })
+    }
+}
+
+impl FATFileSystem {
+    pub fn make_fatfs(
+        data: Option<&dyn FileSystemMakerData>,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        let data = data
+            .and_then(|d| d.as_any().downcast_ref::<FatMountData>())
+            .ok_or(SystemError::EINVAL)?;
+        let fs = FATFileSystem::new(data.gendisk.clone())?;
+        return Ok(fs);
+    }
+}
+
 impl FATFileSystem {
     /// FAT12允许的最大簇号
     pub const FAT12_MAX_CLUSTER: u32 = 0xFF5;
@@ -1084,10 +1142,19 @@ impl FATFileSystem {
     }
 
     /// @brief 在FAT表中，设置指定的簇的信息。
+    /// 写入失败时FAT表可能已经不一致，文件系统会被改为只读，避免继续损坏磁盘上的数据
     ///
     /// @param cluster 目标簇
     /// @param fat_entry 这个簇在FAT表中，存储的信息（下一个簇的簇号）
     pub fn set_entry(&self, cluster: Cluster, fat_entry: FATEntry) -> Result<(), SystemError> {
+        let r = self.do_set_entry(cluster, fat_entry);
+        if r.is_err() {
+            fs_error_remount_ro(self);
+        }
+        return r;
+    }
+
+    fn do_set_entry(&self, cluster: Cluster, fat_entry: FATEntry) -> Result<(), SystemError> {
         // fat表项在分区上的字节偏移量
         let fat_part_bytes_offset: u64 = self.bpb.fat_type.get_fat_bytes_offset(
             cluster,
@@ -1473,6 +1540,8 @@ impl IndexNode for LockedFATInode {
             let mut guard = self.0.lock();
             let old_size = guard.metadata.size;
             guard.update_metadata(Some(core::cmp::max(old_size, (offset + write_len) as i64)));
+            drop(guard);
+            balance_dirty_pages_ratelimited(&page_cache);
             return Ok(write_len);
         } else {
             return self.write_direct(offset, len, buf, data);
@@ -1925,6 +1994,14 @@ impl IndexNode for LockedFATInode {
     fn page_cache(&self) -> Option<Arc<PageCache>> {
         self.0.lock().page_cache.clone()
     }
+
+    fn btime(&self) -> Option<PosixTimeSpec> {
+        self.0
+            .lock()
+            .inode_type
+            .short_dir_entry()
+            .and_then(|entry| entry.create_time())
+    }
 }
 
 impl Default for FATFsInfo {
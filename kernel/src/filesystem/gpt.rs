@@ -0,0 +1,258 @@
+//! GUID分区表（GPT）
+//!
+//! 磁盘的第0个扇区是保护性MBR（含有一个类型为0xEE的分区），第1个扇区是GPT头，
+//! 最后一个扇区是备份的GPT头。GPT头与分区表项数组都带有CRC32校验，
+//! 主GPT头无效时使用备份GPT头。
+//!
+//! 分区号与分区表项在数组中的下标对应（从1开始），与Linux相同。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/block/partitions/efi.c
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use kdepends::crc::crc32::crc32_le;
+use log::{debug, warn};
+use system_error::SystemError;
+
+use crate::{
+    driver::base::block::{
+        block_device::{BlockDevice, BlockId, LBA_SIZE},
+        disk_info::Partition,
+        SeekFrom,
+    },
+    libs::vec_cursor::VecCursor,
+};
+
+use super::mbr::MbrDiskPartionTable;
+
+/// GPT头的签名"EFI PART"
+const GPT_HEADER_SIGNATURE: u64 = 0x5452415020494645;
+/// GPT头的最小长度（UEFI 2.x）
+const GPT_HEADER_MIN_SIZE: usize = 92;
+/// GPT头中CRC32字段的偏移量
+const GPT_HEADER_CRC_OFFSET: usize = 16;
+/// 分区表项的长度
+const GPT_ENTRY_SIZE: usize = 128;
+/// 分区表项数组的最大长度，防止损坏的GPT头导致分配过多内存
+const GPT_MAX_ENTRIES_SIZE: usize = 128 * 1024;
+
+/// GPT头
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GptHeader {
+    pub signature: u64,
+    pub revision: u32,
+    pub header_size: u32,
+    pub header_crc32: u32,
+    pub my_lba: u64,
+    pub alternate_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: [u8; 16],
+    pub partition_entry_lba: u64,
+    pub num_partition_entries: u32,
+    pub sizeof_partition_entry: u32,
+    pub partition_entry_array_crc32: u32,
+}
+
+/// GPT分区表项
+#[derive(Debug, Clone, Copy)]
+pub struct GptPartitionEntry {
+    pub partition_type_guid: [u8; 16],
+    pub unique_partition_guid: [u8; 16],
+    pub starting_lba: u64,
+    pub ending_lba: u64,
+    pub attributes: u64,
+    /// UTF-16LE编码的分区名
+    pub partition_name: [u16; 36],
+}
+
+impl GptPartitionEntry {
+    /// 分区类型GUID为全0的表项没有被使用
+    pub fn is_used(&self) -> bool {
+        self.partition_type_guid.iter().any(|&b| b != 0)
+    }
+
+    pub fn sectors_num(&self) -> u64 {
+        self.ending_lba - self.starting_lba + 1
+    }
+}
+
+/// GPT磁盘分区表
+#[derive(Debug)]
+pub struct GptDiskPartitionTable {
+    pub header: GptHeader,
+    /// 被使用的分区表项，以及它在数组中的下标
+    entries: Vec<(usize, GptPartitionEntry)>,
+}
+
+impl GptDiskPartitionTable {
+    /// # 从磁盘读取GPT分区表
+    ///
+    /// 磁盘上没有保护性MBR，或者主、备GPT头都无效时，返回`EINVAL`
+    pub fn from_disk(disk: Arc<dyn BlockDevice>) -> Result<GptDiskPartitionTable, SystemError> {
+        let mbr = MbrDiskPartionTable::from_disk(disk.clone())?;
+        if !mbr.is_protective() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let last_lba = disk
+            .disk_range()
+            .lba_end
+            .checked_sub(1)
+            .ok_or(SystemError::EINVAL)? as u64;
+        let (header, entries) = match Self::read_gpt(&disk, 1, last_lba) {
+            Ok(r) => r,
+            Err(e) => {
+                warn!(
+                    "GPT: primary header of {} is invalid ({:?}), trying the backup",
+                    disk.dev_name(),
+                    e
+                );
+                Self::read_gpt(&disk, last_lba, last_lba)?
+            }
+        };
+
+        let entries = entries
+            .into_iter()
+            .enumerate()
+            .filter(|(i, entry)| {
+                let valid = entry.is_used()
+                    && entry.starting_lba <= entry.ending_lba
+                    && entry.starting_lba >= header.first_usable_lba
+                    && entry.ending_lba <= header.last_usable_lba;
+                if entry.is_used() && !valid {
+                    warn!("GPT: entry {} of {} is out of range", i, disk.dev_name());
+                }
+                valid
+            })
+            .collect();
+
+        Ok(GptDiskPartitionTable { header, entries })
+    }
+
+    /// 读取并校验位于`lba`的GPT头及其分区表项数组
+    fn read_gpt(
+        disk: &Arc<dyn BlockDevice>,
+        lba: u64,
+        last_lba: u64,
+    ) -> Result<(GptHeader, Vec<GptPartitionEntry>), SystemError> {
+        let mut buf = vec![0u8; LBA_SIZE];
+        disk.read_at_sync(lba as BlockId, 1, &mut buf)?;
+        let header = Self::parse_header(&buf)?;
+
+        // 计算CRC时，CRC字段本身视为0
+        let header_size = header.header_size as usize;
+        if !(GPT_HEADER_MIN_SIZE..=LBA_SIZE).contains(&header_size) {
+            return Err(SystemError::EINVAL);
+        }
+        let mut raw = buf[..header_size].to_vec();
+        raw[GPT_HEADER_CRC_OFFSET..GPT_HEADER_CRC_OFFSET + 4].fill(0);
+        if crc32_le(!0, &raw) ^ !0 != header.header_crc32 {
+            return Err(SystemError::EINVAL);
+        }
+
+        if header.my_lba != lba
+            || header.first_usable_lba > header.last_usable_lba
+            || header.last_usable_lba > last_lba
+            || header.sizeof_partition_entry as usize != GPT_ENTRY_SIZE
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let entries_size = header.num_partition_entries as usize * GPT_ENTRY_SIZE;
+        if entries_size == 0 || entries_size > GPT_MAX_ENTRIES_SIZE {
+            return Err(SystemError::EINVAL);
+        }
+        let count = entries_size.div_ceil(LBA_SIZE);
+        let mut buf = vec![0u8; count * LBA_SIZE];
+        disk.read_at_sync(header.partition_entry_lba as BlockId, count, &mut buf)?;
+        buf.truncate(entries_size);
+        if crc32_le(!0, &buf) ^ !0 != header.partition_entry_array_crc32 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut cursor = VecCursor::new(buf);
+        let mut entries = Vec::with_capacity(header.num_partition_entries as usize);
+        for _ in 0..header.num_partition_entries {
+            entries.push(Self::parse_entry(&mut cursor)?);
+        }
+
+        debug!("GPT header = {:?}", header);
+        Ok((header, entries))
+    }
+
+    fn parse_header(buf: &[u8]) -> Result<GptHeader, SystemError> {
+        let mut cursor = VecCursor::new(buf.to_vec());
+        let mut header = GptHeader {
+            signature: cursor.read_u64()?,
+            ..Default::default()
+        };
+        if header.signature != GPT_HEADER_SIGNATURE {
+            return Err(SystemError::EINVAL);
+        }
+        header.revision = cursor.read_u32()?;
+        header.header_size = cursor.read_u32()?;
+        header.header_crc32 = cursor.read_u32()?;
+        cursor.seek(SeekFrom::SeekCurrent(4))?;
+        header.my_lba = cursor.read_u64()?;
+        header.alternate_lba = cursor.read_u64()?;
+        header.first_usable_lba = cursor.read_u64()?;
+        header.last_usable_lba = cursor.read_u64()?;
+        cursor.read_exact(&mut header.disk_guid)?;
+        header.partition_entry_lba = cursor.read_u64()?;
+        header.num_partition_entries = cursor.read_u32()?;
+        header.sizeof_partition_entry = cursor.read_u32()?;
+        header.partition_entry_array_crc32 = cursor.read_u32()?;
+        Ok(header)
+    }
+
+    fn parse_entry(cursor: &mut VecCursor) -> Result<GptPartitionEntry, SystemError> {
+        let mut entry = GptPartitionEntry {
+            partition_type_guid: [0; 16],
+            unique_partition_guid: [0; 16],
+            starting_lba: 0,
+            ending_lba: 0,
+            attributes: 0,
+            partition_name: [0; 36],
+        };
+        cursor.read_exact(&mut entry.partition_type_guid)?;
+        cursor.read_exact(&mut entry.unique_partition_guid)?;
+        entry.starting_lba = cursor.read_u64()?;
+        entry.ending_lba = cursor.read_u64()?;
+        entry.attributes = cursor.read_u64()?;
+        cursor.read_u16_into(&mut entry.partition_name)?;
+        Ok(entry)
+    }
+
+    /// # partitions - 获取磁盘的分区信息
+    pub fn partitions(&self, disk: Weak<dyn BlockDevice>) -> Vec<Arc<Partition>> {
+        self.entries
+            .iter()
+            .map(|(i, entry)| {
+                Partition::new(
+                    entry.starting_lba,
+                    entry.starting_lba,
+                    entry.sectors_num(),
+                    disk.clone(),
+                    (*i + 1) as u16,
+                )
+            })
+            .collect()
+    }
+
+    /// # partitions_raw - 获取磁盘的分区信息，不包含磁盘设备信息
+    ///
+    /// 分区号为分区表项的下标加1
+    pub fn partitions_raw(&self) -> impl Iterator<Item = Partition> + '_ {
+        self.entries.iter().map(|(i, entry)| {
+            Partition::new_raw(
+                entry.starting_lba,
+                entry.starting_lba,
+                entry.sectors_num(),
+                (*i + 1) as u16,
+            )
+        })
+    }
+}
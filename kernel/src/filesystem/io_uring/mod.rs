@@ -0,0 +1,826 @@
+//! io_uring 异步IO接口
+//!
+//! 目前实现了一个最小可用的子集：
+//! - SQ/CQ 环形队列与 SQE 数组通过 mmap 与用户态共享
+//! - 支持 NOP/READ/WRITE/FSYNC 操作码，除 NOP 外均由内核工作线程异步执行
+//! - 支持 IORING_REGISTER_FILES 注册固定文件
+//!
+//! WRITE 在提交时把用户缓冲区拷贝到内核；READ 由工作线程临时切换到提交者的地址空间，
+//! 把读到的数据拷贝回用户缓冲区。请求完成后工作线程直接写入CQE，CQ已满时暂存在内核中，
+//! 等用户态消费CQE后再写入。
+
+pub mod syscall;
+mod worker;
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec::Vec};
+use num_traits::FromPrimitive;
+use system_error::SystemError;
+
+use crate::{
+    arch::{mm::LockedFrameAllocator, MMArch},
+    filesystem::{
+        page_cache::PageCache,
+        vfs::{
+            file::{File, FileMode},
+            syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, FsInfo, IndexNode, Metadata, SuperBlock,
+        },
+    },
+    libs::{
+        align::page_align_up,
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::WaitQueue,
+    },
+    mm::{
+        allocator::page_frame::PageFrameCount,
+        fault::{PageFaultHandler, PageFaultMessage},
+        page::{page_manager_lock_irqsave, Page, PageFlags, PageType},
+        MemoryManagementArch, VirtAddr, VmFaultReason,
+    },
+    net::event_poll::EPollEventType,
+    process::ProcessManager,
+    sched::SchedMode,
+    syscall::user_access::{UserPod, UserSlice},
+};
+
+/// SQ的最大长度
+pub const IORING_MAX_ENTRIES: u32 = 4096;
+/// CQ的最大长度
+pub const IORING_MAX_CQ_ENTRIES: u32 = 2 * IORING_MAX_ENTRIES;
+
+/// mmap时，SQ环形队列对应的偏移量
+pub const IORING_OFF_SQ_RING: usize = 0;
+/// mmap时，CQ环形队列对应的偏移量
+pub const IORING_OFF_CQ_RING: usize = 0x8000000;
+/// mmap时，SQE数组对应的偏移量
+pub const IORING_OFF_SQES: usize = 0x10000000;
+
+/// 对偏移量为-1的读写，使用文件当前的读写位置
+const IORING_RW_CUR_POS: u64 = u64::MAX;
+/// 单次读写的最大长度，与Linux的MAX_RW_COUNT相同
+const MAX_RW_COUNT: usize = i32::MAX as usize & !(MMArch::PAGE_SIZE - 1);
+
+const _: () = assert!(core::mem::size_of::<IoUringSqe>() == 64);
+const _: () = assert!(core::mem::size_of::<IoUringCqe>() == 16);
+const _: () = assert!(core::mem::size_of::<IoUringParams>() == 120);
+
+bitflags! {
+    /// io_uring_setup 的标志
+    pub struct IoUringSetupFlags: u32 {
+        const IORING_SETUP_IOPOLL = 1 << 0;
+        const IORING_SETUP_SQPOLL = 1 << 1;
+        const IORING_SETUP_SQ_AFF = 1 << 2;
+        const IORING_SETUP_CQSIZE = 1 << 3;
+        const IORING_SETUP_CLAMP = 1 << 4;
+    }
+
+    /// 内核支持的io_uring特性
+    pub struct IoUringFeatures: u32 {
+        const IORING_FEAT_SINGLE_MMAP = 1 << 0;
+        const IORING_FEAT_NODROP = 1 << 1;
+        const IORING_FEAT_RW_CUR_POS = 1 << 3;
+    }
+
+    /// io_uring_enter 的标志
+    pub struct IoUringEnterFlags: u32 {
+        const IORING_ENTER_GETEVENTS = 1 << 0;
+        const IORING_ENTER_SQ_WAKEUP = 1 << 1;
+        const IORING_ENTER_SQ_WAIT = 1 << 2;
+    }
+
+    /// SQE 的标志
+    pub struct IoSqeFlags: u8 {
+        const IOSQE_FIXED_FILE = 1 << 0;
+        const IOSQE_IO_DRAIN = 1 << 1;
+        const IOSQE_IO_LINK = 1 << 2;
+        const IOSQE_IO_HARDLINK = 1 << 3;
+        const IOSQE_ASYNC = 1 << 4;
+    }
+}
+
+/// 支持的操作码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum IoUringOp {
+    Nop = 0,
+    Fsync = 3,
+    Read = 22,
+    Write = 23,
+}
+
+/// io_uring_register 的操作码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum IoUringRegisterOp {
+    RegisterBuffers = 0,
+    UnregisterBuffers = 1,
+    RegisterFiles = 2,
+    UnregisterFiles = 3,
+}
+
+/// 提交队列项，与Linux的 struct io_uring_sqe 布局一致
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IoUringSqe {
+    pub opcode: u8,
+    pub flags: u8,
+    pub ioprio: u16,
+    pub fd: i32,
+    pub off: u64,
+    pub addr: u64,
+    pub len: u32,
+    pub rw_flags: u32,
+    pub user_data: u64,
+    pub buf_index: u16,
+    pub personality: u16,
+    pub splice_fd_in: i32,
+    pub addr3: u64,
+    pub __pad2: u64,
+}
+
+/// 完成队列项，与Linux的 struct io_uring_cqe 布局一致
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IoUringCqe {
+    pub user_data: u64,
+    pub res: i32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IoSqringOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub flags: u32,
+    pub dropped: u32,
+    pub array: u32,
+    pub resv1: u32,
+    pub user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IoCqringOffsets {
+    pub head: u32,
+    pub tail: u32,
+    pub ring_mask: u32,
+    pub ring_entries: u32,
+    pub overflow: u32,
+    pub cqes: u32,
+    pub flags: u32,
+    pub resv1: u32,
+    pub user_addr: u64,
+}
+
+/// io_uring_setup 的参数，与Linux的 struct io_uring_params 布局一致
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IoUringParams {
+    pub sq_entries: u32,
+    pub cq_entries: u32,
+    pub flags: u32,
+    pub sq_thread_cpu: u32,
+    pub sq_thread_idle: u32,
+    pub features: u32,
+    pub wq_fd: u32,
+    pub resv: [u32; 3],
+    pub sq_off: IoSqringOffsets,
+    pub cq_off: IoCqringOffsets,
+}
+
+unsafe impl UserPod for IoSqringOffsets {}
+unsafe impl UserPod for IoCqringOffsets {}
+unsafe impl UserPod for IoUringParams {}
+
+/// 与用户态共享的环形队列头部
+///
+/// SQ与CQ共用同一块内存（IORING_FEAT_SINGLE_MMAP），头部之后依次是SQ索引数组和CQE数组
+#[repr(C)]
+struct IoRings {
+    sq_head: AtomicU32,
+    sq_tail: AtomicU32,
+    sq_ring_mask: u32,
+    sq_ring_entries: u32,
+    sq_flags: AtomicU32,
+    sq_dropped: AtomicU32,
+    cq_head: AtomicU32,
+    cq_tail: AtomicU32,
+    cq_ring_mask: u32,
+    cq_ring_entries: u32,
+    cq_overflow: AtomicU32,
+    cq_flags: AtomicU32,
+}
+
+impl IoRings {
+    /// SQ索引数组在共享内存中的偏移量
+    const SQ_ARRAY_OFFSET: usize = 64;
+
+    fn cqes_offset(sq_entries: u32) -> usize {
+        let end = Self::SQ_ARRAY_OFFSET + sq_entries as usize * core::mem::size_of::<u32>();
+        (end + core::mem::align_of::<IoUringCqe>() - 1) & !(core::mem::align_of::<IoUringCqe>() - 1)
+    }
+
+    fn size(sq_entries: u32, cq_entries: u32) -> usize {
+        Self::cqes_offset(sq_entries) + cq_entries as usize * core::mem::size_of::<IoUringCqe>()
+    }
+}
+
+/// 一段供mmap到用户态的连续物理页
+///
+/// 页面由区域、页缓存与页面管理器共同引用，物理页在最后一个引用消失时才释放
+#[derive(Debug)]
+struct IoUringRegion {
+    vaddr: VirtAddr,
+    pages: Vec<Arc<Page>>,
+}
+
+impl IoUringRegion {
+    /// 分配`len`字节的内存，并以`pgoffs`中的每个页偏移量加入页缓存
+    fn new(page_cache: &Arc<PageCache>, len: usize, pgoffs: &[usize]) -> Result<Self, SystemError> {
+        let page_count = PageFrameCount::new(page_align_up(len) / MMArch::PAGE_SIZE);
+        let mut page_manager_guard = page_manager_lock_irqsave();
+        let (phys_addr, pages) = page_manager_guard.create_pages(
+            PageType::Normal,
+            PageFlags::PG_UNEVICTABLE,
+            &mut LockedFrameAllocator,
+            page_count,
+        )?;
+        drop(page_manager_guard);
+
+        let mut cache_guard = page_cache.lock_irqsave();
+        for pgoff in pgoffs {
+            for (i, page) in pages.iter().enumerate() {
+                cache_guard.add_page(pgoff + i, page);
+            }
+        }
+        drop(cache_guard);
+
+        let vaddr = unsafe { MMArch::phys_2_virt(phys_addr) }.ok_or(SystemError::EFAULT)?;
+        Ok(Self { vaddr, pages })
+    }
+}
+
+impl Drop for IoUringRegion {
+    fn drop(&mut self) {
+        let mut page_manager_guard = page_manager_lock_irqsave();
+        for page in self.pages.iter() {
+            let mut page_guard = page.write_irqsave();
+            if page_guard.map_count() == 0 {
+                page_manager_guard.remove_page(&page.phys_address());
+            } else {
+                // 仍被映射的页面交给解除映射的流程从页面管理器中移除，
+                // 否则解除映射时会找不到页面
+                page_guard.remove_flags(PageFlags::PG_UNEVICTABLE);
+            }
+        }
+    }
+}
+
+/// 一个已完成、等待写入CQ的请求
+#[derive(Debug)]
+struct IoUringCompletion {
+    user_data: u64,
+    result: Result<usize, SystemError>,
+}
+
+/// io_uring 实例
+#[derive(Debug)]
+pub struct IoUringCtx {
+    sq_entries: u32,
+    cq_entries: u32,
+    rings: IoUringRegion,
+    sqes: IoUringRegion,
+    page_cache: Arc<PageCache>,
+    /// 已完成但因CQ已满尚未写入CQ的请求
+    completions: SpinLock<VecDeque<IoUringCompletion>>,
+    /// 已提交但尚未完成的请求数
+    inflight: AtomicU32,
+    /// 通过IORING_REGISTER_FILES注册的固定文件
+    files: SpinLock<Vec<Option<Arc<File>>>>,
+    wait_queue: WaitQueue,
+}
+
+impl IoUringCtx {
+    fn new(sq_entries: u32, cq_entries: u32) -> Result<Self, SystemError> {
+        let page_cache = PageCache::new(None);
+        let rings = IoUringRegion::new(
+            &page_cache,
+            IoRings::size(sq_entries, cq_entries),
+            &[
+                IORING_OFF_SQ_RING >> MMArch::PAGE_SHIFT,
+                IORING_OFF_CQ_RING >> MMArch::PAGE_SHIFT,
+            ],
+        )?;
+        let sqes = IoUringRegion::new(
+            &page_cache,
+            sq_entries as usize * core::mem::size_of::<IoUringSqe>(),
+            &[IORING_OFF_SQES >> MMArch::PAGE_SHIFT],
+        )?;
+
+        let ctx = Self {
+            sq_entries,
+            cq_entries,
+            rings,
+            sqes,
+            page_cache,
+            completions: SpinLock::new(VecDeque::new()),
+            inflight: AtomicU32::new(0),
+            files: SpinLock::new(Vec::new()),
+            wait_queue: WaitQueue::default(),
+        };
+
+        let rings = unsafe { &mut *(ctx.rings.vaddr.data() as *mut IoRings) };
+        rings.sq_ring_mask = sq_entries - 1;
+        rings.sq_ring_entries = sq_entries;
+        rings.cq_ring_mask = cq_entries - 1;
+        rings.cq_ring_entries = cq_entries;
+        Ok(ctx)
+    }
+
+    fn rings(&self) -> &IoRings {
+        unsafe { &*(self.rings.vaddr.data() as *const IoRings) }
+    }
+
+    fn sq_array(&self) -> &[AtomicU32] {
+        unsafe {
+            core::slice::from_raw_parts(
+                (self.rings.vaddr.data() + IoRings::SQ_ARRAY_OFFSET) as *const AtomicU32,
+                self.sq_entries as usize,
+            )
+        }
+    }
+
+    fn cqes(&self) -> *mut IoUringCqe {
+        (self.rings.vaddr.data() + IoRings::cqes_offset(self.sq_entries)) as *mut IoUringCqe
+    }
+
+    fn sqes(&self) -> *const IoUringSqe {
+        self.sqes.vaddr.data() as *const IoUringSqe
+    }
+
+    /// 填写返回给用户态的各项偏移量
+    fn fill_offsets(&self, params: &mut IoUringParams) {
+        params.sq_entries = self.sq_entries;
+        params.cq_entries = self.cq_entries;
+        params.features = (IoUringFeatures::IORING_FEAT_SINGLE_MMAP
+            | IoUringFeatures::IORING_FEAT_NODROP
+            | IoUringFeatures::IORING_FEAT_RW_CUR_POS)
+            .bits();
+
+        params.sq_off = IoSqringOffsets {
+            head: core::mem::offset_of!(IoRings, sq_head) as u32,
+            tail: core::mem::offset_of!(IoRings, sq_tail) as u32,
+            ring_mask: core::mem::offset_of!(IoRings, sq_ring_mask) as u32,
+            ring_entries: core::mem::offset_of!(IoRings, sq_ring_entries) as u32,
+            flags: core::mem::offset_of!(IoRings, sq_flags) as u32,
+            dropped: core::mem::offset_of!(IoRings, sq_dropped) as u32,
+            array: IoRings::SQ_ARRAY_OFFSET as u32,
+            ..Default::default()
+        };
+        params.cq_off = IoCqringOffsets {
+            head: core::mem::offset_of!(IoRings, cq_head) as u32,
+            tail: core::mem::offset_of!(IoRings, cq_tail) as u32,
+            ring_mask: core::mem::offset_of!(IoRings, cq_ring_mask) as u32,
+            ring_entries: core::mem::offset_of!(IoRings, cq_ring_entries) as u32,
+            overflow: core::mem::offset_of!(IoRings, cq_overflow) as u32,
+            cqes: IoRings::cqes_offset(self.sq_entries) as u32,
+            flags: core::mem::offset_of!(IoRings, cq_flags) as u32,
+            ..Default::default()
+        };
+    }
+
+    /// 从SQ中取出至多`to_submit`个请求并提交
+    ///
+    /// ## 返回值
+    ///
+    /// 成功消费的SQE数量
+    fn submit(self: &Arc<Self>, to_submit: u32) -> u32 {
+        let rings = self.rings();
+        let mask = self.sq_entries - 1;
+        let mut head = rings.sq_head.load(Ordering::Relaxed);
+        let tail = rings.sq_tail.load(Ordering::Acquire);
+
+        let mut submitted = 0;
+        while submitted < to_submit && head != tail {
+            let idx = self.sq_array()[(head & mask) as usize].load(Ordering::Relaxed);
+            head = head.wrapping_add(1);
+            if idx >= self.sq_entries {
+                rings.sq_dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            let sqe = unsafe { core::ptr::read_volatile(self.sqes().add(idx as usize)) };
+            if let Err(e) = self.submit_sqe(&sqe) {
+                self.complete(IoUringCompletion {
+                    user_data: sqe.user_data,
+                    result: Err(e),
+                });
+            }
+            submitted += 1;
+        }
+        rings.sq_head.store(head, Ordering::Release);
+        submitted
+    }
+
+    fn submit_sqe(self: &Arc<Self>, sqe: &IoUringSqe) -> Result<(), SystemError> {
+        let op = IoUringOp::from_u8(sqe.opcode).ok_or(SystemError::EINVAL)?;
+        if op == IoUringOp::Nop {
+            self.complete(IoUringCompletion {
+                user_data: sqe.user_data,
+                result: Ok(0),
+            });
+            return Ok(());
+        }
+
+        let file = self.get_file(sqe)?;
+        let offset = if sqe.off == IORING_RW_CUR_POS {
+            None
+        } else {
+            Some(sqe.off as usize)
+        };
+        // 与read/write系统调用一样，单次读写的长度不超过MAX_RW_COUNT，
+        // 这样内核缓冲区的大小有上限，结果也能放进CQE的i32中
+        let len = (sqe.len as usize).min(MAX_RW_COUNT);
+        let work = match op {
+            IoUringOp::Read => {
+                file.readable()?;
+                let buf = VirtAddr::new(sqe.addr as usize);
+                // 先检查缓冲区的范围，拷贝时的缺页由工作线程在提交者的地址空间中处理
+                UserSlice::new(buf, len)?;
+                worker::IoUringWorkKind::Read {
+                    offset,
+                    buf,
+                    len,
+                    user_vm: ProcessManager::current_pcb()
+                        .basic()
+                        .user_vm()
+                        .ok_or(SystemError::EFAULT)?,
+                }
+            }
+            IoUringOp::Write => {
+                file.writeable()?;
+                let mut data = worker::alloc_buf(len)?;
+                UserSlice::new(VirtAddr::new(sqe.addr as usize), len)?
+                    .reader()
+                    .read_raw(&mut data)?;
+                worker::IoUringWorkKind::Write { offset, data }
+            }
+            IoUringOp::Fsync => worker::IoUringWorkKind::Fsync,
+            IoUringOp::Nop => unreachable!(),
+        };
+
+        // 每个未完成的请求都可能占用一个工作线程，限制在CQ的大小之内
+        if self
+            .inflight
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.cq_entries).then_some(n + 1)
+            })
+            .is_err()
+        {
+            return Err(SystemError::EBUSY);
+        }
+        worker::queue_work(worker::IoUringWork {
+            ctx: self.clone(),
+            file,
+            user_data: sqe.user_data,
+            kind: work,
+        });
+        Ok(())
+    }
+
+    fn get_file(&self, sqe: &IoUringSqe) -> Result<Arc<File>, SystemError> {
+        let flags = IoSqeFlags::from_bits_truncate(sqe.flags);
+        if flags.contains(IoSqeFlags::IOSQE_FIXED_FILE) {
+            return self
+                .files
+                .lock()
+                .get(sqe.fd as usize)
+                .cloned()
+                .flatten()
+                .ok_or(SystemError::EBADF);
+        }
+
+        ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(sqe.fd)
+            .ok_or(SystemError::EBADF)
+    }
+
+    /// 记录一个已完成的请求，并唤醒等待完成事件的进程
+    fn complete(&self, completion: IoUringCompletion) {
+        self.post_completion(completion);
+        self.wait_queue.wakeup_all(None);
+    }
+
+    /// 工作线程完成一个请求
+    ///
+    /// 先写入CQE再减少正在执行的请求数，等待者看到没有正在执行的请求时，CQE一定已经可见
+    fn complete_work(&self, completion: IoUringCompletion) {
+        self.post_completion(completion);
+        self.inflight.fetch_sub(1, Ordering::SeqCst);
+        self.wait_queue.wakeup_all(None);
+    }
+
+    fn post_completion(&self, completion: IoUringCompletion) {
+        self.completions.lock_irqsave().push_back(completion);
+        self.flush_completions();
+    }
+
+    fn has_completions(&self) -> bool {
+        !self.completions.lock_irqsave().is_empty()
+    }
+
+    /// 把已完成的请求写入CQ
+    ///
+    /// CQ已满时，剩余的请求保留在内核中，等待用户态消费CQE后再写入。
+    /// 写入CQ的过程持有`completions`的锁，多个工作线程与提交者不会写同一个CQE
+    fn flush_completions(&self) {
+        let rings = self.rings();
+        let mask = self.cq_entries - 1;
+        let mut completions = self.completions.lock_irqsave();
+        loop {
+            let head = rings.cq_head.load(Ordering::Acquire);
+            let tail = rings.cq_tail.load(Ordering::Relaxed);
+            if tail.wrapping_sub(head) >= self.cq_entries {
+                break;
+            }
+
+            let Some(completion) = completions.pop_front() else {
+                break;
+            };
+
+            let res = match completion.result {
+                Ok(n) => n.min(i32::MAX as usize) as i32,
+                Err(e) => e.to_posix_errno(),
+            };
+            unsafe {
+                core::ptr::write_volatile(
+                    self.cqes().add((tail & mask) as usize),
+                    IoUringCqe {
+                        user_data: completion.user_data,
+                        res,
+                        flags: 0,
+                    },
+                );
+            }
+            rings.cq_tail.store(tail.wrapping_add(1), Ordering::Release);
+        }
+    }
+
+    /// CQ中尚未被用户态消费的CQE数量
+    fn cq_ready(&self) -> u32 {
+        let rings = self.rings();
+        rings
+            .cq_tail
+            .load(Ordering::Acquire)
+            .wrapping_sub(rings.cq_head.load(Ordering::Acquire))
+    }
+
+    /// 等待CQ中至少有`min_complete`个CQE
+    fn wait_completions(&self, min_complete: u32) -> Result<(), SystemError> {
+        loop {
+            self.flush_completions();
+            if self.cq_ready() >= min_complete {
+                return Ok(());
+            }
+            if self.inflight.load(Ordering::SeqCst) == 0 && !self.has_completions() {
+                // 没有正在执行的请求，继续等待也不会有新的CQE
+                return Ok(());
+            }
+            wq_wait_event_interruptible!(
+                self.wait_queue,
+                self.cq_ready() >= min_complete
+                    || self.has_completions()
+                    || self.inflight.load(Ordering::SeqCst) == 0,
+                {}
+            )?;
+        }
+    }
+
+    fn register_files(&self, fds: &[i32]) -> Result<(), SystemError> {
+        let mut files = self.files.lock();
+        if !files.is_empty() {
+            return Err(SystemError::EBUSY);
+        }
+
+        let fd_table = ProcessManager::current_pcb().fd_table();
+        let fd_table_guard = fd_table.read();
+        // fd为-1的表项表示空位
+        let new_files = fds
+            .iter()
+            .map(|&fd| {
+                if fd == -1 {
+                    return Ok(None);
+                }
+                let file = fd_table_guard
+                    .get_file_by_fd(fd)
+                    .ok_or(SystemError::EBADF)?;
+                // 注册io_uring自身（或者其他io_uring）会形成引用环，实例永远不会被释放
+                if file
+                    .inode()
+                    .as_any_ref()
+                    .downcast_ref::<IoUringInode>()
+                    .is_some()
+                {
+                    return Err(SystemError::EBADF);
+                }
+                Ok(Some(file))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        *files = new_files;
+        Ok(())
+    }
+
+    fn unregister_files(&self) -> Result<(), SystemError> {
+        let mut files = self.files.lock();
+        if files.is_empty() {
+            return Err(SystemError::ENXIO);
+        }
+        files.clear();
+        Ok(())
+    }
+}
+
+/// io_uring 文件描述符对应的inode
+#[derive(Debug)]
+pub struct IoUringInode {
+    ctx: Arc<IoUringCtx>,
+}
+
+impl IoUringInode {
+    pub fn new(ctx: Arc<IoUringCtx>) -> Self {
+        Self { ctx }
+    }
+
+    pub fn ctx(&self) -> &Arc<IoUringCtx> {
+        &self.ctx
+    }
+}
+
+impl IndexNode for IoUringInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        // 正在执行的请求还会引用实例，注册的文件在文件描述符关闭时就释放
+        self.ctx.files.lock().clear();
+        Ok(())
+    }
+
+    fn mmap(&self, _start: usize, _len: usize, offset: usize) -> Result<(), SystemError> {
+        match offset {
+            IORING_OFF_SQ_RING | IORING_OFF_CQ_RING | IORING_OFF_SQES => Ok(()),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let mut events = EPollEventType::empty();
+        if self.ctx.cq_ready() > 0 || self.ctx.has_completions() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        Ok(events.bits() as usize)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn resize(&self, _len: usize) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        Arc::new(IoUringFakeFs)
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        Some(self.ctx.page_cache.clone())
+    }
+}
+
+/// 用于处理io_uring共享内存缺页的伪文件系统
+#[derive(Debug)]
+struct IoUringFakeFs;
+
+impl FileSystem for IoUringFakeFs {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        panic!("IoUringFakeFs does not have a root inode")
+    }
+
+    fn info(&self) -> FsInfo {
+        panic!("IoUringFakeFs does not have a filesystem info")
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "io_uring"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        panic!("IoUringFakeFs does not have a super block")
+    }
+
+    unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
+        PageFaultHandler::filemap_fault(pfm)
+    }
+
+    unsafe fn map_pages(
+        &self,
+        pfm: &mut PageFaultMessage,
+        start_pgoff: usize,
+        end_pgoff: usize,
+    ) -> VmFaultReason {
+        PageFaultHandler::filemap_map_pages(pfm, start_pgoff, end_pgoff)
+    }
+}
+
+/// 创建一个io_uring实例，返回对应的inode
+fn io_uring_create(params: &mut IoUringParams) -> Result<Arc<IoUringInode>, SystemError> {
+    let sq_entries = params.sq_entries.next_power_of_two();
+    let cq_entries = if IoUringSetupFlags::from_bits_truncate(params.flags)
+        .contains(IoUringSetupFlags::IORING_SETUP_CQSIZE)
+    {
+        if params.cq_entries == 0 || params.cq_entries > IORING_MAX_CQ_ENTRIES {
+            return Err(SystemError::EINVAL);
+        }
+        let cq_entries = params.cq_entries.next_power_of_two();
+        if cq_entries < sq_entries {
+            return Err(SystemError::EINVAL);
+        }
+        cq_entries
+    } else {
+        2 * sq_entries
+    };
+
+    let ctx = Arc::new(IoUringCtx::new(sq_entries, cq_entries)?);
+    ctx.fill_offsets(params);
+
+    let inode = Arc::new(IoUringInode::new(ctx.clone()));
+    ctx.page_cache
+        .set_inode(Arc::downgrade(&(inode.clone() as Arc<dyn IndexNode>)))?;
+    Ok(inode)
+}
+
+/// 获取fd对应的io_uring实例
+fn io_uring_get_ctx(fd: i32) -> Result<Arc<IoUringCtx>, SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    let inode = file.inode();
+    let io_uring = inode
+        .as_any_ref()
+        .downcast_ref::<IoUringInode>()
+        .ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP)?;
+    Ok(io_uring.ctx().clone())
+}
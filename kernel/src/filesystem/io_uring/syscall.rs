@@ -0,0 +1,143 @@
+use num_traits::FromPrimitive;
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::file::{File, FileMode},
+    mm::VirtAddr,
+    process::ProcessManager,
+    syscall::{
+        user_access::{UserPtr, UserSlice},
+        Syscall,
+    },
+};
+
+use super::{
+    io_uring_create, io_uring_get_ctx, IoUringEnterFlags, IoUringParams, IoUringRegisterOp,
+    IoUringSetupFlags, IORING_MAX_ENTRIES,
+};
+
+impl Syscall {
+    /// # 创建一个 io_uring 实例
+    ///
+    /// ## 参数
+    /// - `entries`: SQ的长度，会被向上取整为2的幂
+    /// - `params`: 用户态的 io_uring_params，返回时填入各个环形队列的偏移量
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: io_uring 实例的文件描述符
+    /// - `Err(SystemError)`: 创建失败
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/io_uring_setup.2.html
+    pub fn sys_io_uring_setup(
+        entries: u32,
+        params: *mut IoUringParams,
+    ) -> Result<usize, SystemError> {
+        let params = UserPtr::from_ptr(params);
+        let mut p = params.read()?;
+        if p.resv.iter().any(|&x| x != 0) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let flags = IoUringSetupFlags::from_bits(p.flags).ok_or(SystemError::EINVAL)?;
+        // 暂不支持轮询模式与SQ内核线程
+        if flags.intersects(
+            IoUringSetupFlags::IORING_SETUP_IOPOLL
+                | IoUringSetupFlags::IORING_SETUP_SQPOLL
+                | IoUringSetupFlags::IORING_SETUP_SQ_AFF,
+        ) {
+            return Err(SystemError::EINVAL);
+        }
+
+        if entries == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        p.sq_entries = if entries > IORING_MAX_ENTRIES {
+            if !flags.contains(IoUringSetupFlags::IORING_SETUP_CLAMP) {
+                return Err(SystemError::EINVAL);
+            }
+            IORING_MAX_ENTRIES
+        } else {
+            entries
+        };
+
+        let inode = io_uring_create(&mut p)?;
+        let file = File::new(inode, FileMode::O_RDWR | FileMode::O_CLOEXEC)?;
+        let fd = ProcessManager::current_pcb()
+            .fd_table()
+            .write()
+            .alloc_fd(file, None)?;
+
+        params.write(&p)?;
+        Ok(fd as usize)
+    }
+
+    /// # 提交请求并等待完成事件
+    ///
+    /// ## 参数
+    /// - `fd`: io_uring 实例的文件描述符
+    /// - `to_submit`: 要从SQ中提交的请求数
+    /// - `min_complete`: 设置了IORING_ENTER_GETEVENTS时，至少等待的完成事件数
+    /// - `flags`: IoUringEnterFlags
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 成功提交的请求数
+    /// - `Err(SystemError)`: 错误码
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/io_uring_enter.2.html
+    pub fn sys_io_uring_enter(
+        fd: i32,
+        to_submit: u32,
+        min_complete: u32,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        let flags = IoUringEnterFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let ctx = io_uring_get_ctx(fd)?;
+
+        let submitted = ctx.submit(to_submit);
+        if flags.contains(IoUringEnterFlags::IORING_ENTER_GETEVENTS) {
+            ctx.wait_completions(min_complete.min(ctx.cq_entries))?;
+        } else {
+            ctx.flush_completions();
+        }
+        Ok(submitted as usize)
+    }
+
+    /// # 向 io_uring 实例注册资源
+    ///
+    /// 目前只支持注册/注销固定文件
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/io_uring_register.2.html
+    pub fn sys_io_uring_register(
+        fd: i32,
+        opcode: u32,
+        arg: usize,
+        nr_args: u32,
+    ) -> Result<usize, SystemError> {
+        let ctx = io_uring_get_ctx(fd)?;
+        let op = IoUringRegisterOp::from_u32(opcode).ok_or(SystemError::EINVAL)?;
+        match op {
+            IoUringRegisterOp::RegisterFiles => {
+                if nr_args == 0 || nr_args > IORING_MAX_ENTRIES {
+                    return Err(SystemError::EINVAL);
+                }
+                let fds = UserSlice::new(
+                    VirtAddr::new(arg),
+                    nr_args as usize * core::mem::size_of::<i32>(),
+                )?
+                .reader()
+                .read_vec::<i32>(nr_args as usize)?;
+                ctx.register_files(&fds)?;
+            }
+            IoUringRegisterOp::UnregisterFiles => {
+                if arg != 0 || nr_args != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                ctx.unregister_files()?;
+            }
+            IoUringRegisterOp::RegisterBuffers | IoUringRegisterOp::UnregisterBuffers => {
+                return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+            }
+        }
+        Ok(0)
+    }
+}
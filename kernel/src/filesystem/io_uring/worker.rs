@@ -0,0 +1,207 @@
+//! io_uring 的内核工作线程
+
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use log::warn;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::CurrentIrqArch,
+    exception::InterruptArch,
+    filesystem::vfs::file::File,
+    init::initcall::INITCALL_SUBSYS,
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    mm::{ucontext::AddressSpace, VirtAddr},
+    process::{
+        kthread::{KernelThreadClosure, KernelThreadMechanism},
+        ProcessManager,
+    },
+    syscall::user_access::UserSlice,
+};
+
+use super::{IoUringCompletion, IoUringCtx};
+
+/// 常驻的空闲工作线程的数量
+///
+/// 请求可能长时间阻塞（例如读一个空的管道），所以入队时没有空闲的工作线程就新建一个，
+/// 空闲的工作线程超过这个数量时多出来的会退出
+const IO_URING_WORKER_NUM: usize = 2;
+
+struct IoUringWorkQueue {
+    /// 等待工作线程执行的请求
+    works: VecDeque<IoUringWork>,
+    /// 正在等待新请求、且还没有被分配请求的工作线程的数量
+    idle: usize,
+    /// 用于给新的工作线程命名
+    next_id: usize,
+}
+
+static IO_URING_WORKS: SpinLock<IoUringWorkQueue> = SpinLock::new(IoUringWorkQueue {
+    works: VecDeque::new(),
+    idle: 0,
+    next_id: 0,
+});
+/// 工作线程在此等待新的请求
+static IO_URING_WORKER_WAIT: WaitQueue = WaitQueue::default();
+
+#[derive(Debug)]
+pub(super) enum IoUringWorkKind {
+    Read {
+        /// 为`None`时使用文件当前的读写位置
+        offset: Option<usize>,
+        buf: VirtAddr,
+        len: usize,
+        /// 提交者的地址空间，`buf`位于其中
+        user_vm: Arc<AddressSpace>,
+    },
+    Write {
+        offset: Option<usize>,
+        data: Vec<u8>,
+    },
+    Fsync,
+}
+
+/// 交给工作线程执行的请求
+#[derive(Debug)]
+pub(super) struct IoUringWork {
+    pub ctx: Arc<IoUringCtx>,
+    pub file: Arc<File>,
+    pub user_data: u64,
+    pub kind: IoUringWorkKind,
+}
+
+impl IoUringWork {
+    fn run(self) {
+        let result = match self.kind {
+            IoUringWorkKind::Read {
+                offset,
+                buf,
+                len,
+                user_vm,
+            } => {
+                let mut data = match alloc_buf(len) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        self.ctx.complete_work(IoUringCompletion {
+                            user_data: self.user_data,
+                            result: Err(e),
+                        });
+                        return;
+                    }
+                };
+                match offset {
+                    Some(offset) => self.file.pread(offset, len, &mut data),
+                    None => self.file.read(len, &mut data),
+                }
+                .and_then(|n| {
+                    with_user_vm(&user_vm, || {
+                        UserSlice::new(buf, n)?.writer().write_raw(&data[..n])
+                    })
+                    .map(|_| n)
+                })
+            }
+            IoUringWorkKind::Write { offset, data } => match offset {
+                Some(offset) => self.file.pwrite(offset, data.len(), &data),
+                None => self.file.write(data.len(), &data),
+            },
+            IoUringWorkKind::Fsync => self.file.inode().sync().map(|_| 0),
+        };
+
+        self.ctx.complete_work(IoUringCompletion {
+            user_data: self.user_data,
+            result,
+        });
+    }
+}
+
+/// 分配`len`字节的缓冲区，内存不足时返回`ENOMEM`而不是panic
+pub(super) fn alloc_buf(len: usize) -> Result<Vec<u8>, SystemError> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len)
+        .map_err(|_| SystemError::ENOMEM)?;
+    buf.resize(len, 0);
+    Ok(buf)
+}
+
+/// 临时切换到`user_vm`执行`f`，用于访问提交者的用户缓冲区
+///
+/// 切换方式与execve切换地址空间相同：同时更新PCB中的地址空间与页表，
+/// 这样`f`中的缺页会在`user_vm`中处理，期间被调度出去再回来时也会切换到`user_vm`
+fn with_user_vm<R>(user_vm: &Arc<AddressSpace>, f: impl FnOnce() -> R) -> R {
+    let pcb = ProcessManager::current_pcb();
+    let switch = |vm: Option<Arc<AddressSpace>>| {
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        let mut basic_info = pcb.basic_mut();
+        let old = basic_info.user_vm();
+        unsafe { basic_info.set_user_vm(vm.clone()) };
+        drop(basic_info);
+        if let Some(vm) = vm {
+            unsafe { vm.read().user_mapper.utable.make_current() };
+        }
+        drop(irq_guard);
+        old
+    };
+
+    let old = switch(Some(user_vm.clone()));
+    let r = f();
+    switch(old);
+    r
+}
+
+/// 把请求加入工作队列，唤醒一个空闲的工作线程，没有空闲的工作线程时新建一个
+pub(super) fn queue_work(work: IoUringWork) {
+    let mut queue = IO_URING_WORKS.lock_irqsave();
+    queue.works.push_back(work);
+    if queue.idle > 0 {
+        // 被唤醒的工作线程不再算作空闲，避免后续的请求都指望同一个线程
+        queue.idle -= 1;
+        drop(queue);
+        IO_URING_WORKER_WAIT.wakeup(None);
+        return;
+    }
+    let id = queue.next_id;
+    queue.next_id += 1;
+    drop(queue);
+
+    if spawn_worker(id).is_err() {
+        // 请求仍在队列中，等某个工作线程空闲下来之后执行
+        warn!("io_uring: failed to create worker io_uring-wq/{}", id);
+    }
+}
+
+fn spawn_worker(id: usize) -> Result<(), SystemError> {
+    let closure = KernelThreadClosure::StaticEmptyClosure((&(io_uring_worker as fn() -> i32), ()));
+    KernelThreadMechanism::create_and_run(closure, format!("io_uring-wq/{}", id))
+        .ok_or(SystemError::ENOMEM)?;
+    Ok(())
+}
+
+fn io_uring_worker() -> i32 {
+    loop {
+        let mut queue = IO_URING_WORKS.lock_irqsave();
+        match queue.works.pop_front() {
+            Some(work) => {
+                drop(queue);
+                work.run();
+            }
+            None if queue.idle >= IO_URING_WORKER_NUM => return 0,
+            None => {
+                queue.idle += 1;
+                IO_URING_WORKER_WAIT.sleep_uninterruptible_unlock_spinlock(queue);
+            }
+        }
+    }
+}
+
+#[unified_init(INITCALL_SUBSYS)]
+fn io_uring_worker_init() -> Result<(), SystemError> {
+    for _ in 0..IO_URING_WORKER_NUM {
+        let id = {
+            let mut queue = IO_URING_WORKS.lock_irqsave();
+            queue.next_id += 1;
+            queue.next_id - 1
+        };
+        spawn_worker(id)?;
+    }
+    Ok(())
+}
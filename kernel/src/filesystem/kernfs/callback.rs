@@ -1,5 +1,5 @@
 use crate::{
-    filesystem::{sysfs::SysFSKernPrivateData, vfs::PollStatus},
+    filesystem::{debugfs::DebugFSFile, sysfs::SysFSKernPrivateData, vfs::PollStatus},
     libs::spinlock::SpinLockGuard,
 };
 use alloc::sync::Arc;
@@ -86,6 +86,7 @@ impl<'a> KernCallbackData<'a> {
 #[derive(Debug)]
 pub enum KernInodePrivateData {
     SysFS(SysFSKernPrivateData),
+    DebugFS(DebugFSFile),
 }
 
 impl KernInodePrivateData {
@@ -95,6 +96,9 @@ impl KernInodePrivateData {
             KernInodePrivateData::SysFS(private_data) => {
                 return private_data.callback_read(buf, offset);
             }
+            KernInodePrivateData::DebugFS(file) => {
+                return file.callback_read(buf, offset);
+            }
         }
     }
 
@@ -104,6 +108,9 @@ impl KernInodePrivateData {
             KernInodePrivateData::SysFS(private_data) => {
                 return private_data.callback_write(buf, offset);
             }
+            KernInodePrivateData::DebugFS(file) => {
+                return file.callback_write(buf, offset);
+            }
         }
     }
 }
@@ -12,6 +12,9 @@ use crate::{
     libs::vec_cursor::VecCursor,
 };
 
+/// GPT磁盘的保护性MBR中的分区类型
+pub const EFI_PMBR_OSTYPE_EFI_GPT: u8 = 0xEE;
+
 /// @brief MBR硬盘分区表项的结构
 #[repr(packed)]
 #[derive(Debug, Clone, Copy, Default)]
@@ -156,6 +159,13 @@ impl MbrDiskPartionTable {
     pub fn is_valid(&self) -> bool {
         self.bs_trailsig == 0xAA55
     }
+
+    /// 是否为GPT磁盘的保护性MBR（含有一个类型为0xEE、从LBA 1开始的分区）
+    pub fn is_protective(&self) -> bool {
+        let dpte = self.dpte;
+        dpte.iter()
+            .any(|entry| entry.part_type == EFI_PMBR_OSTYPE_EFI_GPT && entry.starting_lba == 1)
+    }
 }
 
 pub struct MbrPartitionIter<'a> {
@@ -1,12 +1,20 @@
+pub mod debugfs;
 pub mod devfs;
 pub mod devpts;
 pub mod eventfd;
 pub mod fat;
+pub mod gpt;
+pub mod io_uring;
 pub mod kernfs;
 pub mod mbr;
 pub mod overlayfs;
 pub mod page_cache;
+pub mod pidfd;
 pub mod procfs;
 pub mod ramfs;
+pub mod signalfd;
+pub mod smb;
 pub mod sysfs;
+pub mod timerfd;
+pub mod tmpfs;
 pub mod vfs;
@@ -9,6 +9,7 @@ use crate::driver::base::device::device_number::DeviceNumber;
 use crate::driver::base::device::device_number::Major;
 use crate::filesystem::vfs::{FileSystemMaker, FileSystemMakerData};
 use crate::libs::spinlock::SpinLock;
+use crate::time::PosixTimeSpec;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::sync::Weak;
@@ -303,6 +304,13 @@ impl IndexNode for OvlInode {
         Ok(Metadata::default())
     }
 
+    fn btime(&self) -> Option<PosixTimeSpec> {
+        if let Some(ref upper_inode) = *self.upper_inode.lock() {
+            return upper_inode.btime();
+        }
+        self.lower_inode.as_ref().and_then(|inode| inode.btime())
+    }
+
     fn as_any_ref(&self) -> &dyn core::any::Any {
         self
     }
@@ -16,16 +16,104 @@ use crate::{
     libs::spinlock::SpinLock,
     mm::{
         page::{page_manager_lock_irqsave, page_reclaimer_lock_irqsave, Page, PageFlags},
+        writeback::BackingDevInfo,
         MemoryManagementArch,
     },
 };
 use crate::{libs::align::page_align_up, mm::page::PageType};
 
+/// 预读窗口的初始大小（页数）
+const READAHEAD_INIT_PAGES: usize = 4;
+/// 预读窗口的最大大小（页数）
+const READAHEAD_MAX_PAGES: usize = 32;
+
+/// 每个打开的文件的预读状态
+///
+/// 顺序访问命中预读窗口的后半部分时，预读下一个窗口并把窗口大小翻倍；
+/// 发生随机访问时，窗口大小减半，减小到初始大小以下时停止预读。
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/mm/readahead.c
+#[derive(Debug, Default, Clone)]
+pub struct FileReadAhead {
+    /// 当前预读窗口的起始页号
+    start: usize,
+    /// 当前预读窗口的大小（页数），0表示没有预读窗口
+    size: usize,
+    /// 上一次访问的最后一页
+    prev_index: Option<usize>,
+}
+
+impl FileReadAhead {
+    pub const fn new() -> Self {
+        Self {
+            start: 0,
+            size: 0,
+            prev_index: None,
+        }
+    }
+
+    /// 根据本次访问更新预读状态
+    ///
+    /// ## 参数
+    ///
+    /// - `index` 本次访问的起始页号
+    /// - `nr_pages` 本次访问的页数
+    ///
+    /// ## 返回值
+    ///
+    /// 需要读入页面缓存的范围(起始页号, 页数)，不需要预读时返回None
+    fn on_access(&mut self, index: usize, nr_pages: usize) -> Option<(usize, usize)> {
+        let nr_pages = nr_pages.max(1);
+        let last = index + nr_pages - 1;
+        let follows_prev = match self.prev_index {
+            Some(prev) => index == prev || index == prev + 1,
+            None => index == 0,
+        };
+        let in_window = self.size != 0 && index >= self.start && index < self.start + self.size;
+        let sequential = follows_prev || in_window;
+        self.prev_index = Some(last);
+
+        if !sequential {
+            // 随机访问，缩小窗口
+            self.size /= 2;
+            if self.size < READAHEAD_INIT_PAGES {
+                self.size = 0;
+                return None;
+            }
+            self.start = index;
+            return Some((index, self.size.max(nr_pages)));
+        }
+
+        if self.size == 0 {
+            // 开始顺序访问，建立初始窗口
+            self.start = index;
+            self.size = (nr_pages.next_power_of_two() * 2)
+                .clamp(READAHEAD_INIT_PAGES, READAHEAD_MAX_PAGES)
+                .max(nr_pages);
+            return Some((self.start, self.size));
+        }
+
+        // 访问到了窗口的后半部分，预读下一个窗口并把窗口翻倍
+        if last >= self.start + self.size / 2 {
+            self.start = (self.start + self.size).max(index);
+            self.size = (self.size * 2).min(READAHEAD_MAX_PAGES);
+            if self.start + self.size <= last {
+                self.size = last + 1 - self.start;
+            }
+            return Some((self.start, self.size));
+        }
+
+        None
+    }
+}
+
 /// 页面缓存
 #[derive(Debug)]
 pub struct PageCache {
     inner: SpinLock<InnerPageCache>,
     inode: Lazy<Weak<dyn IndexNode>>,
+    /// 脏页写回的后备设备，用于脏页统计与写回限流
+    bdi: Lazy<Arc<BackingDevInfo>>,
 }
 
 #[derive(Debug)]
@@ -70,6 +158,11 @@ impl InnerPageCache {
             let buf_offset = i * MMArch::PAGE_SIZE;
             let page_index = start_page_index + i;
 
+            // 页面可能已经在读入数据期间被其它路径加入缓存
+            if self.pages.contains_key(&page_index) {
+                continue;
+            }
+
             let page = page_manager_guard.create_one_page(
                 PageType::File(FileMapInfo {
                     page_cache: self
@@ -264,7 +357,7 @@ impl InnerPageCache {
                     page_guard.as_slice_mut()[page_offset..page_offset + sub_len]
                         .copy_from_slice(sub_buf);
                 }
-                page_guard.add_flags(PageFlags::PG_DIRTY);
+                page_guard.mark_dirty();
 
                 ret += sub_len;
 
@@ -325,6 +418,7 @@ impl PageCache {
                 }
                 v
             },
+            bdi: Lazy::new(),
         })
     }
 
@@ -340,7 +434,78 @@ impl PageCache {
         Ok(())
     }
 
+    pub fn bdi(&self) -> Option<Arc<BackingDevInfo>> {
+        self.bdi.try_get().cloned()
+    }
+
+    /// 设置后备设备，需要在缓存中出现脏页之前设置
+    pub fn set_bdi(&self, bdi: Arc<BackingDevInfo>) -> Result<(), SystemError> {
+        if self.bdi.initialized() {
+            return Err(SystemError::EINVAL);
+        }
+        self.bdi.init(bdi);
+        Ok(())
+    }
+
     pub fn lock_irqsave(&self) -> SpinLockGuard<InnerPageCache> {
         self.inner.lock_irqsave()
     }
+
+    /// 根据文件的访问模式进行预读
+    ///
+    /// ## 参数
+    ///
+    /// - `ra` 文件的预读状态
+    /// - `offset` 本次访问的偏移量
+    /// - `len` 本次访问的长度
+    pub fn readahead(
+        &self,
+        ra: &SpinLock<FileReadAhead>,
+        offset: usize,
+        len: usize,
+    ) -> Result<(), SystemError> {
+        let index = offset >> MMArch::PAGE_SHIFT;
+        let nr_pages = (page_align_up(offset + len) >> MMArch::PAGE_SHIFT).saturating_sub(index);
+        let window = ra.lock().on_access(index, nr_pages);
+        if let Some((start, count)) = window {
+            self.populate(start, count)?;
+        }
+        Ok(())
+    }
+
+    /// 把[start, start + count)范围内不在缓存中的页面读入缓存
+    ///
+    /// 连续缺失的页面通过一次读取完成，读取期间不持有页面缓存的锁
+    fn populate(&self, start: usize, count: usize) -> Result<(), SystemError> {
+        let inode = match self.inode().and_then(|inode| inode.upgrade()) {
+            Some(inode) => inode,
+            None => return Ok(()),
+        };
+        let file_size = inode.metadata()?.size as usize;
+        let end = min(
+            start + count,
+            page_align_up(file_size) >> MMArch::PAGE_SHIFT,
+        );
+
+        let mut not_exist: Vec<(usize, usize)> = Vec::new();
+        {
+            let inner = self.lock_irqsave();
+            for page_index in start..end {
+                if inner.get_page(page_index).is_some() {
+                    continue;
+                }
+                match not_exist.last_mut() {
+                    Some((index, count)) if *index + *count == page_index => *count += 1,
+                    _ => not_exist.push((page_index, 1)),
+                }
+            }
+        }
+
+        for (page_index, count) in not_exist {
+            let mut page_buf = vec![0u8; MMArch::PAGE_SIZE * count];
+            inode.read_sync(page_index * MMArch::PAGE_SIZE, page_buf.as_mut())?;
+            self.lock_irqsave().create_pages(page_index, &page_buf)?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,282 @@
+//! pidfd：指向进程的文件描述符
+//!
+//! pidfd持有目标进程的pcb，而不是进程号，因此进程退出、进程号被复用之后，
+//! 通过pidfd发送信号或者等待也不会作用到无关的进程上。进程退出时pidfd变为可读，
+//! 可以通过poll/epoll等待子进程退出。
+//!
+//! 参考 https://man7.org/linux/man-pages/man2/pidfd_open.2.html
+
+use crate::arch::ipc::signal::{SigCode, Signal};
+use crate::filesystem::vfs::file::{File, FileMode};
+use crate::filesystem::vfs::syscall::ModeType;
+use crate::filesystem::vfs::{FilePrivateData, FileSystem, FileType, IndexNode, Metadata};
+use crate::ipc::signal_types::{SigInfo, SigType};
+use crate::libs::spinlock::{SpinLock, SpinLockGuard};
+use crate::net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll};
+use crate::process::{Pid, ProcessControlBlock, ProcessManager};
+use crate::syscall::user_access::UserBufferReader;
+use crate::syscall::Syscall;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::ffi::c_void;
+use core::mem::size_of;
+use system_error::SystemError;
+
+/// pidfd_open的标志位，与O_NONBLOCK相同
+pub const PIDFD_NONBLOCK: u32 = FileMode::O_NONBLOCK.bits();
+
+/// tgkill发出的信号的si_code
+const SI_TKILL: i32 = -6;
+
+/// 所有的pidfd，进程退出时据此唤醒通过epoll等待的进程。已经释放的pidfd在遍历时清理
+static PIDFDS: SpinLock<Vec<Weak<PidFdInode>>> = SpinLock::new(Vec::new());
+
+#[derive(Debug)]
+pub struct PidFdInode {
+    pcb: Arc<ProcessControlBlock>,
+    epitems: EPollItems,
+}
+
+impl PidFdInode {
+    pub fn new(pcb: Arc<ProcessControlBlock>) -> Arc<Self> {
+        let inode = Arc::new(PidFdInode {
+            pcb,
+            epitems: EPollItems::new(),
+        });
+        let mut pidfds = PIDFDS.lock_irqsave();
+        pidfds.retain(|w| w.strong_count() > 0);
+        pidfds.push(Arc::downgrade(&inode));
+        inode
+    }
+
+    /// pidfd指向的进程
+    pub fn pcb(&self) -> &Arc<ProcessControlBlock> {
+        &self.pcb
+    }
+
+    /// 进程是否已经退出
+    fn exited(&self) -> bool {
+        self.pcb
+            .sched_info()
+            .inner_lock_read_irqsave()
+            .state()
+            .is_exited()
+    }
+}
+
+impl IndexNode for PidFdInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    /// 进程退出后可读
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let mut events = EPollEventType::empty();
+        if self.exited() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        return Ok(events.bits() as usize);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn resize(&self, _len: usize) -> Result<(), SystemError> {
+        Ok(())
+    }
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.add(epitem);
+        Ok(())
+    }
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.remove(epoll)
+    }
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        panic!("PidFd does not have a filesystem")
+    }
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+}
+
+/// # 通知指向进程`pid`的pidfd
+///
+/// 进程状态被设置为退出之后调用，唤醒通过epoll等待这些pidfd的进程
+pub fn pidfd_notify_exit(pid: Pid) {
+    let mut pidfds = PIDFDS.lock_irqsave();
+    pidfds.retain(|w| w.strong_count() > 0);
+    for pidfd in pidfds.iter().filter_map(|w| w.upgrade()) {
+        if pidfd.pcb.pid() != pid {
+            continue;
+        }
+        pidfd
+            .epitems
+            .wakeup(Some(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM))
+            .ok();
+    }
+}
+
+/// # 为进程创建pidfd，并分配文件描述符
+///
+/// pidfd总是带有O_CLOEXEC标志
+pub fn pidfd_create(pcb: Arc<ProcessControlBlock>, flags: u32) -> Result<usize, SystemError> {
+    let mut filemode = FileMode::O_RDWR | FileMode::O_CLOEXEC;
+    if flags & PIDFD_NONBLOCK != 0 {
+        filemode |= FileMode::O_NONBLOCK;
+    }
+    let file = File::new(PidFdInode::new(pcb), filemode)?;
+    let binding = ProcessManager::current_pcb().fd_table();
+    let mut fd_table_guard = binding.write();
+    let fd = fd_table_guard.alloc_fd(file, None).map(|x| x as usize);
+    return fd;
+}
+
+/// # 获取当前进程的文件描述符`fd`对应的pidfd
+///
+/// ## 返回值
+/// - `Ok((pcb, mode))`: pidfd指向的进程，以及文件的打开模式
+/// - `Err(SystemError::EBADF)`: 文件描述符不存在
+/// - `Err(SystemError::EINVAL)`: 文件描述符不是pidfd
+pub fn pidfd_get_pcb(fd: i32) -> Result<(Arc<ProcessControlBlock>, FileMode), SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    let inode = file.inode();
+    let pidfd = inode
+        .as_any_ref()
+        .downcast_ref::<PidFdInode>()
+        .ok_or(SystemError::EINVAL)?;
+    return Ok((pidfd.pcb().clone(), file.mode()));
+}
+
+impl Syscall {
+    /// # 获取指向进程`pid`的pidfd
+    ///
+    /// ## 参数
+    /// - `pid`: 目标进程，必须是线程组的组长
+    /// - `flags`: 0 或者 PIDFD_NONBLOCK
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: pidfd 的文件描述符
+    /// - `Err(SystemError)`: 失败
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/pidfd_open.2.html
+    pub fn sys_pidfd_open(pid: i32, flags: u32) -> Result<usize, SystemError> {
+        if flags & !PIDFD_NONBLOCK != 0 || pid <= 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = ProcessManager::find(Pid::new(pid as usize)).ok_or(SystemError::ESRCH)?;
+        if pcb.pid() != pcb.tgid() {
+            return Err(SystemError::EINVAL);
+        }
+        pidfd_create(pcb, flags)
+    }
+
+    /// # 向pidfd指向的进程发送信号
+    ///
+    /// ## 参数
+    /// - `pidfd`: 目标进程的 pidfd
+    /// - `sig`: 要发送的信号，为0时只检查进程是否存在
+    /// - `info`: 用户空间的 siginfo_t，可以为空。不为空时，其中的信号必须与`sig`相同，
+    ///   并且向其他进程发送时，si_code 只能是 SI_QUEUE 等用户态使用的负值
+    /// - `flags`: 保留，必须为0
+    ///
+    /// ## 返回值
+    /// - `Ok(0)`: 成功
+    /// - `Err(SystemError::ESRCH)`: 目标进程已经退出
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/pidfd_send_signal.2.html
+    pub fn sys_pidfd_send_signal(
+        pidfd: i32,
+        sig: i32,
+        info: *const c_void,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        if flags != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let (pcb, _) = pidfd_get_pcb(pidfd)?;
+        if pcb
+            .sched_info()
+            .inner_lock_read_irqsave()
+            .state()
+            .is_exited()
+        {
+            return Err(SystemError::ESRCH);
+        }
+
+        let current_pid = ProcessManager::current_pid();
+        if !info.is_null() {
+            // siginfo_t 的前三个字段：si_signo、si_errno、si_code
+            let reader = UserBufferReader::new(info as *const i32, 3 * size_of::<i32>(), true)?;
+            let header = reader.read_from_user::<i32>(0)?;
+            if header[0] != sig {
+                return Err(SystemError::EINVAL);
+            }
+            // 与rt_sigqueueinfo相同，不允许伪装成内核或者kill发出的信号
+            if (header[2] >= 0 || header[2] == SI_TKILL) && pcb.pid() != current_pid {
+                return Err(SystemError::EPERM);
+            }
+        }
+
+        if sig == 0 {
+            return Ok(0);
+        }
+        let sig = Signal::from(sig);
+        if sig == Signal::INVALID {
+            return Err(SystemError::EINVAL);
+        }
+        let mut info = SigInfo::new(sig, 0, SigCode::User, SigType::Kill(current_pid));
+        sig.send_signal_info_to_pcb(Some(&mut info), pcb).map(|_| 0)
+    }
+}
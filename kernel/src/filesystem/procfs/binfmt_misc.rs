@@ -0,0 +1,74 @@
+//! /proc/sys/fs/binfmt_misc目录
+//!
+//! 目录下的register、status以及每个已注册格式对应的文件都不保存在目录中，每次查找时重新创建
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::{syscall::ModeType, FileType},
+    process::binfmt_misc::{
+        binfmt_misc_names, binfmt_misc_register, binfmt_misc_show, binfmt_misc_status,
+        binfmt_misc_write_entry, binfmt_misc_write_status,
+    },
+};
+
+use super::{LockedProcFSInode, ProcFSInode, ProcFileType, ProcfsFilePrivateData};
+
+impl ProcFSInode {
+    /// 在binfmt_misc目录下查找文件
+    pub(super) fn find_binfmt_misc(
+        &self,
+        name: &str,
+    ) -> Result<Arc<LockedProcFSInode>, SystemError> {
+        let (mode, ftype) = match name {
+            "register" => (0o200, ProcFileType::ProcBinfmtMiscRegister),
+            "status" => (0o644, ProcFileType::ProcBinfmtMiscStatus),
+            name if binfmt_misc_names().iter().any(|n| n == name) => {
+                (0o644, ProcFileType::ProcBinfmtMiscEntry)
+            }
+            _ => return Err(SystemError::ENOENT),
+        };
+
+        let inode = self.new_child(name, FileType::File, ModeType::from_bits_truncate(mode), 0);
+        inode.0.lock().fdata.ftype = ftype;
+        return Ok(inode);
+    }
+
+    /// 列出binfmt_misc目录下的文件
+    pub(super) fn list_binfmt_misc(&self) -> Vec<String> {
+        let mut names = vec!["register".to_string(), "status".to_string()];
+        names.append(&mut binfmt_misc_names());
+        return names;
+    }
+
+    /// 打开binfmt_misc目录下的status文件或者格式文件
+    pub(super) fn open_binfmt_misc(
+        &self,
+        pdata: &mut ProcfsFilePrivateData,
+    ) -> Result<i64, SystemError> {
+        let content = match self.fdata.ftype {
+            ProcFileType::ProcBinfmtMiscStatus => binfmt_misc_status(),
+            ProcFileType::ProcBinfmtMiscEntry => binfmt_misc_show(self.dname.as_ref())?,
+            _ => String::new(),
+        };
+        pdata.data = content.into_bytes();
+        return Ok(pdata.data.len() as i64);
+    }
+
+    /// 写binfmt_misc目录下的文件，每次写入都是一条完整的命令
+    pub(super) fn write_binfmt_misc(&self, buf: &[u8]) -> Result<usize, SystemError> {
+        match self.fdata.ftype {
+            ProcFileType::ProcBinfmtMiscRegister => binfmt_misc_register(buf)?,
+            ProcFileType::ProcBinfmtMiscStatus => binfmt_misc_write_status(buf)?,
+            ProcFileType::ProcBinfmtMiscEntry => binfmt_misc_write_entry(self.dname.as_ref(), buf)?,
+            _ => return Err(SystemError::EINVAL),
+        }
+        return Ok(buf.len());
+    }
+}
@@ -2,25 +2,35 @@ use core::sync::atomic::{compiler_fence, Ordering};
 
 use super::log::{LogLevel, LogMessage};
 
-use crate::libs::spinlock::SpinLock;
+use crate::{libs::spinlock::SpinLock, time::PosixTimeSpec};
 
-use alloc::{borrow::ToOwned, string::ToString, vec::Vec};
-
-use kdepends::ringbuffer::{AllocRingBuffer, RingBuffer};
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 
 use log::info;
+use ring_buffer::{RingBuffer, RingError, RingMode, RECORD_HEADER_SIZE, RECORD_MAX_SIZE};
 use system_error::SystemError;
 
-/// 缓冲区容量
-const KMSG_BUFFER_CAPACITY: usize = 1024;
+/// 缓冲区容量（字节）
+const KMSG_BUFFER_SIZE: usize = 128 * 1024;
+/// 日志记录的类型
+const KMSG_RECORD_LOG: u32 = 1;
+/// 日志记录中，消息文本之前的固定部分：时间戳的秒与纳秒、文本的长度
+const KMSG_RECORD_FIXED_LEN: usize = 8 + 8 + 4;
+/// 单条日志消息文本的最大长度，更长的消息被截断
+const KMSG_MAX_TEXT_LEN: usize = RECORD_MAX_SIZE - RECORD_HEADER_SIZE - KMSG_RECORD_FIXED_LEN;
 
 /// 全局环形缓冲区
-pub static mut KMSG: Option<SpinLock<Kmsg>> = None;
+pub static mut KMSG: Option<Kmsg> = None;
 
 /// 初始化KMSG
 pub fn kmsg_init() {
     info!("kmsg_init");
-    let kmsg = SpinLock::new(Kmsg::new());
+    let kmsg = Kmsg::new();
 
     compiler_fence(Ordering::SeqCst);
     unsafe { KMSG = Some(kmsg) };
@@ -29,60 +39,119 @@ pub fn kmsg_init() {
 }
 
 /// 日志
+///
+/// 写入日志不需要加锁，可以在中断上下文中进行；读取日志的状态由`reader`保护
 pub struct Kmsg {
-    /// 环形缓冲区
-    buffer: AllocRingBuffer<LogMessage>,
+    /// 环形缓冲区，满了之后覆盖最旧的日志
+    buffer: RingBuffer,
+    reader: SpinLock<KmsgReader>,
+}
+
+struct KmsgReader {
     /// 缓冲区字节数组
     data: Vec<u8>,
     /// 能够输出到控制台的日志级别，当console_loglevel为DEFAULT时，表示可以打印所有级别的日志消息到控制台
     console_loglevel: LogLevel,
-    /// 判断buffer在上一次转成字节数组之后是否发生变动
-    is_changed: bool,
+    /// 上一次转成字节数组时缓冲区的`(tail, head)`，用于判断buffer是否发生变动
+    snapshot: Option<(u64, u64)>,
 }
 
 impl Kmsg {
     pub fn new() -> Self {
         Kmsg {
-            buffer: AllocRingBuffer::new(KMSG_BUFFER_CAPACITY),
-            data: Vec::new(),
-            console_loglevel: LogLevel::DEFAULT,
-            is_changed: false,
+            buffer: RingBuffer::new(KMSG_BUFFER_SIZE, RingMode::Overwrite),
+            reader: SpinLock::new(KmsgReader {
+                data: Vec::new(),
+                console_loglevel: LogLevel::DEFAULT,
+                snapshot: None,
+            }),
         }
     }
 
     /// 添加日志消息
-    pub fn push(&mut self, msg: LogMessage) {
-        self.buffer.push(msg);
-        self.is_changed = true;
+    pub fn push(&self, timestamp: PosixTimeSpec, level: LogLevel, message: &str) {
+        let text = &message.as_bytes()[..message.len().min(KMSG_MAX_TEXT_LEN)];
+        self.buffer
+            .ring()
+            .write(
+                KMSG_RECORD_LOG,
+                level as u16,
+                &[
+                    &timestamp.tv_sec.to_ne_bytes(),
+                    &timestamp.tv_nsec.to_ne_bytes(),
+                    &(text.len() as u32).to_ne_bytes(),
+                    text,
+                ],
+            )
+            .ok();
+    }
+
+    /// 按时间顺序取出缓冲区中的所有日志消息，不修改缓冲区
+    fn messages(&self) -> Vec<LogMessage> {
+        let ring = self.buffer.ring();
+        let mut buf = vec![0u8; RECORD_MAX_SIZE];
+        let mut msgs = Vec::new();
+        let mut pos = ring.tail();
+        loop {
+            match ring.read_at(pos, &mut buf) {
+                Ok(Some((header, next))) => {
+                    if let Some(msg) = Self::decode(header.misc, &buf[..header.payload_len()]) {
+                        msgs.push(msg);
+                    }
+                    pos = next;
+                }
+                Ok(None) => return msgs,
+                // 读取期间最旧的日志被覆盖了，从头重新读取
+                Err(RingError::Overwritten) => {
+                    msgs.clear();
+                    pos = ring.tail();
+                }
+                Err(_) => return msgs,
+            }
+        }
+    }
+
+    fn decode(level: u16, payload: &[u8]) -> Option<LogMessage> {
+        let fixed = payload.get(..KMSG_RECORD_FIXED_LEN)?;
+        let tv_sec = i64::from_ne_bytes(fixed[0..8].try_into().unwrap());
+        let tv_nsec = i64::from_ne_bytes(fixed[8..16].try_into().unwrap());
+        let len = u32::from_ne_bytes(fixed[16..20].try_into().unwrap()) as usize;
+        let text = payload.get(KMSG_RECORD_FIXED_LEN..KMSG_RECORD_FIXED_LEN + len)?;
+        Some(LogMessage::new(
+            PosixTimeSpec { tv_sec, tv_nsec },
+            LogLevel::from(level as usize),
+            String::from_utf8_lossy(text).into_owned(),
+        ))
     }
 
     /// 读取缓冲区
-    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, SystemError> {
-        self.tobytes();
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let mut reader = self.reader.lock_irqsave();
+        self.tobytes(&mut reader);
 
-        match self.console_loglevel {
-            LogLevel::DEFAULT => self.read_all(buf),
-            _ => self.read_level(buf),
+        match reader.console_loglevel {
+            LogLevel::DEFAULT => Self::read_all(&reader, buf),
+            _ => self.read_level(&mut reader, buf),
         }
     }
 
     /// 读取缓冲区所有日志消息
-    fn read_all(&mut self, buf: &mut [u8]) -> Result<usize, SystemError> {
-        let len = self.data.len().min(buf.len());
+    fn read_all(reader: &KmsgReader, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let len = reader.data.len().min(buf.len());
 
         // 拷贝数据
-        let src = &self.data[0..len];
+        let src = &reader.data[0..len];
         buf[0..len].copy_from_slice(src);
 
         return Ok(len);
     }
 
     /// 读取缓冲区特定level的日志消息
-    fn read_level(&mut self, buf: &mut [u8]) -> Result<usize, SystemError> {
+    fn read_level(&self, reader: &mut KmsgReader, buf: &mut [u8]) -> Result<usize, SystemError> {
         let mut data_level: Vec<u8> = Vec::new();
 
-        for msg in self.buffer.iter() {
-            if msg.level() == self.console_loglevel {
+        for msg in self.messages() {
+            if msg.level() == reader.console_loglevel {
                 data_level.append(&mut msg.to_string().as_bytes().to_owned());
             }
         }
@@ -94,32 +163,38 @@ impl Kmsg {
         buf[0..len].copy_from_slice(src);
 
         // 将控制台输出日志level改回默认，否则之后都是打印特定level的日志消息
-        self.console_loglevel = LogLevel::DEFAULT;
+        reader.console_loglevel = LogLevel::DEFAULT;
 
         return Ok(data_level.len());
     }
 
     /// 读取并清空缓冲区
-    pub fn read_clear(&mut self, buf: &mut [u8]) -> Result<usize, SystemError> {
-        let r = self.read_all(buf);
+    pub fn read_clear(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        let r = {
+            let mut reader = self.reader.lock_irqsave();
+            self.tobytes(&mut reader);
+            Self::read_all(&reader, buf)
+        };
         self.clear()?;
 
         return r;
     }
 
     /// 清空缓冲区
-    pub fn clear(&mut self) -> Result<usize, SystemError> {
-        self.buffer.clear();
-        self.data.clear();
+    pub fn clear(&self) -> Result<usize, SystemError> {
+        let mut reader = self.reader.lock_irqsave();
+        self.buffer.ring().clear();
+        reader.data.clear();
+        reader.snapshot = None;
 
         return Ok(0);
     }
 
     /// 设置输出到控制台的日志级别
-    pub fn set_level(&mut self, log_level: usize) -> Result<usize, SystemError> {
+    pub fn set_level(&self, log_level: usize) -> Result<usize, SystemError> {
         let log_level = log_level - 1;
 
-        self.console_loglevel = match log_level {
+        self.reader.lock_irqsave().console_loglevel = match log_level {
             0 => LogLevel::EMERG,
             1 => LogLevel::ALERT,
             2 => LogLevel::CRIT,
@@ -136,24 +211,47 @@ impl Kmsg {
     }
 
     /// 将环形缓冲区的日志消息转成字节数组以拷入用户buf
-    fn tobytes(&mut self) -> usize {
-        if self.is_changed {
-            self.data.clear();
-
-            if self.console_loglevel == LogLevel::DEFAULT {
-                for msg in self.buffer.iter() {
-                    self.data.append(&mut msg.to_string().as_bytes().to_owned());
+    fn tobytes(&self, reader: &mut KmsgReader) -> usize {
+        let ring = self.buffer.ring();
+        let snapshot = (ring.tail(), ring.head());
+        if reader.snapshot != Some(snapshot) {
+            reader.data.clear();
+
+            if reader.console_loglevel == LogLevel::DEFAULT {
+                for msg in self.messages() {
+                    reader
+                        .data
+                        .append(&mut msg.to_string().as_bytes().to_owned());
                 }
+                reader.snapshot = Some(snapshot);
             }
-
-            self.is_changed = false;
         }
 
-        return self.data.len();
+        return reader.data.len();
     }
 
     // 返回内核缓冲区所占字节数
-    pub fn data_size(&mut self) -> Result<usize, SystemError> {
-        return Ok(self.tobytes());
+    pub fn data_size(&self) -> Result<usize, SystemError> {
+        let mut reader = self.reader.lock_irqsave();
+        return Ok(self.tobytes(&mut reader));
+    }
+
+    /// 以文本形式返回最近的若干条日志，总长度不超过`max_len`字节
+    ///
+    /// 不加锁，也不修改缓冲区状态，可在panic等场景下只读地取出日志
+    pub fn recent_text(&self, max_len: usize) -> String {
+        let msgs: Vec<String> = self.messages().iter().map(|msg| msg.to_string()).collect();
+        let mut total = 0;
+        let mut start = msgs.len();
+        while start > 0 && total + msgs[start - 1].len() <= max_len {
+            start -= 1;
+            total += msgs[start].len();
+        }
+
+        let mut text = String::with_capacity(total);
+        for msg in &msgs[start..] {
+            text.push_str(msg);
+        }
+        return text;
     }
 }
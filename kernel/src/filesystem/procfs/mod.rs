@@ -12,19 +12,41 @@ use alloc::{
 use system_error::SystemError;
 
 use crate::{
-    arch::mm::LockedFrameAllocator,
-    driver::base::device::device_number::DeviceNumber,
+    arch::{mm::LockedFrameAllocator, MMArch},
+    driver::{
+        base::device::device_number::DeviceNumber, net::stats::netdev_procfs_show,
+        tty::sysrq::write_sysrq_trigger,
+    },
     filesystem::vfs::{
         core::{generate_inode_id, ROOT_INODE},
         FileType,
     },
+    ipc::{msg::msg_procfs_show, sem::sem_procfs_show, shm::shm_manager_lock},
     libs::{
         once::Once,
         rwlock::RwLock,
         spinlock::{SpinLock, SpinLockGuard},
     },
-    mm::allocator::page_frame::FrameAllocator,
+    mm::{
+        allocator::{
+            page_frame::FrameAllocator,
+            slab::{slab_info, slab_usage},
+        },
+        page::page_reclaimer_lock_irqsave,
+        vmstat::{vm_event_count, VmEvent},
+        writeback::{dirty_thresholds, global_dirty_pages},
+        MemoryManagementArch,
+    },
+    net::socket::proc::{tcp_procfs_show, udp_procfs_show, unix_procfs_show},
     process::{Pid, ProcessManager},
+    sched::{
+        rt::{
+            sched_rt_period_show, sched_rt_runtime_show, write_sched_rt_period,
+            write_sched_rt_runtime,
+        },
+        stats::{sched_procfs_show, schedstat_procfs_show},
+    },
+    smp::cpu::smp_cpu_manager,
     time::PosixTimeSpec,
 };
 
@@ -35,8 +57,10 @@ use super::vfs::{
     FileSystem, FsInfo, IndexNode, InodeId, Magic, Metadata, SuperBlock,
 };
 
+mod binfmt_misc;
 pub mod kmsg;
 pub mod log;
+mod pid;
 mod syscall;
 
 /// @brief 进程文件类型
@@ -50,6 +74,62 @@ pub enum ProcFileType {
     ProcMeminfo = 1,
     /// kmsg
     ProcKmsg = 2,
+    /// vmstat
+    ProcVmstat = 3,
+    /// slabinfo
+    ProcSlabinfo = 4,
+    /// 进程的虚拟内存区域
+    ProcMaps = 5,
+    /// 进程的虚拟页到物理页帧的映射
+    ProcPagemap = 6,
+    /// 进程打开的文件描述符目录
+    ProcFdDir = 7,
+    /// 指向文件描述符对应文件的符号链接
+    ProcFdLink = 8,
+    /// 进程的文件描述符信息目录
+    ProcFdInfoDir = 9,
+    /// 文件描述符的偏移量、打开标志等信息
+    ProcFdInfo = 10,
+    /// /proc/sys/fs/binfmt_misc目录
+    ProcBinfmtMiscDir = 11,
+    /// 注册binfmt_misc格式的文件
+    ProcBinfmtMiscRegister = 12,
+    /// binfmt_misc的启用状态
+    ProcBinfmtMiscStatus = 13,
+    /// 一个已注册的binfmt_misc格式
+    ProcBinfmtMiscEntry = 14,
+    /// 各个网卡的收发统计
+    ProcNetDev = 15,
+    /// IPv4的TCP socket列表
+    ProcNetTcp = 16,
+    /// IPv6的TCP socket列表
+    ProcNetTcp6 = 17,
+    /// IPv4的UDP socket列表
+    ProcNetUdp = 18,
+    /// IPv6的UDP socket列表
+    ProcNetUdp6 = 19,
+    /// unix域socket列表
+    ProcNetUnix = 20,
+    /// System V共享内存段列表
+    ProcSysvipcShm = 21,
+    /// System V信号量集合列表
+    ProcSysvipcSem = 22,
+    /// System V消息队列列表
+    ProcSysvipcMsg = 23,
+    /// 各cpu运行队列的调度统计
+    ProcSchedstat = 24,
+    /// 进程的调度统计
+    ProcPidSched = 25,
+    /// 进程所在user namespace的uid映射
+    ProcUidMap = 26,
+    /// 进程所在user namespace的gid映射
+    ProcGidMap = 27,
+    /// 触发magic sysrq
+    ProcSysrqTrigger = 28,
+    /// 实时进程限流的周期
+    ProcSchedRtPeriod = 29,
+    /// 每个周期内实时进程最多运行的时间
+    ProcSchedRtRuntime = 30,
     //todo: 其他文件类型
     ///默认文件类型
     Default,
@@ -61,6 +141,34 @@ impl From<u8> for ProcFileType {
             0 => ProcFileType::ProcStatus,
             1 => ProcFileType::ProcMeminfo,
             2 => ProcFileType::ProcKmsg,
+            3 => ProcFileType::ProcVmstat,
+            4 => ProcFileType::ProcSlabinfo,
+            5 => ProcFileType::ProcMaps,
+            6 => ProcFileType::ProcPagemap,
+            7 => ProcFileType::ProcFdDir,
+            8 => ProcFileType::ProcFdLink,
+            9 => ProcFileType::ProcFdInfoDir,
+            10 => ProcFileType::ProcFdInfo,
+            11 => ProcFileType::ProcBinfmtMiscDir,
+            12 => ProcFileType::ProcBinfmtMiscRegister,
+            13 => ProcFileType::ProcBinfmtMiscStatus,
+            14 => ProcFileType::ProcBinfmtMiscEntry,
+            15 => ProcFileType::ProcNetDev,
+            16 => ProcFileType::ProcNetTcp,
+            17 => ProcFileType::ProcNetTcp6,
+            18 => ProcFileType::ProcNetUdp,
+            19 => ProcFileType::ProcNetUdp6,
+            20 => ProcFileType::ProcNetUnix,
+            21 => ProcFileType::ProcSysvipcShm,
+            22 => ProcFileType::ProcSysvipcSem,
+            23 => ProcFileType::ProcSysvipcMsg,
+            24 => ProcFileType::ProcSchedstat,
+            25 => ProcFileType::ProcPidSched,
+            26 => ProcFileType::ProcUidMap,
+            27 => ProcFileType::ProcGidMap,
+            28 => ProcFileType::ProcSysrqTrigger,
+            29 => ProcFileType::ProcSchedRtPeriod,
+            30 => ProcFileType::ProcSchedRtRuntime,
             _ => ProcFileType::Default,
         }
     }
@@ -73,6 +181,8 @@ pub struct InodeInfo {
     pid: Pid,
     ///文件类型
     ftype: ProcFileType,
+    /// fd与fdinfo目录下的文件所对应的文件描述符
+    fd: i32,
     //其他需要传入的信息在此定义
 }
 
@@ -138,6 +248,51 @@ impl ProcFSInode {
 
         data.push(0);
     }
+
+    /// 创建一个以当前inode为父目录的inode，但不把它加入当前inode的子目录项中
+    fn new_child(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        data: usize,
+    ) -> Arc<LockedProcFSInode> {
+        let result: Arc<LockedProcFSInode> =
+            Arc::new(LockedProcFSInode(SpinLock::new(ProcFSInode {
+                parent: self.self_ref.clone(),
+                self_ref: Weak::default(),
+                children: BTreeMap::new(),
+                data: Vec::new(),
+                metadata: Metadata {
+                    dev_id: 0,
+                    inode_id: generate_inode_id(),
+                    size: 0,
+                    blk_size: 0,
+                    blocks: 0,
+                    atime: PosixTimeSpec::default(),
+                    mtime: PosixTimeSpec::default(),
+                    ctime: PosixTimeSpec::default(),
+                    file_type,
+                    mode,
+                    nlinks: 1,
+                    uid: 0,
+                    gid: 0,
+                    raw_dev: DeviceNumber::from(data as u32),
+                },
+                fs: self.fs.clone(),
+                fdata: InodeInfo {
+                    pid: Pid::new(0),
+                    ftype: ProcFileType::Default,
+                    fd: -1,
+                },
+                dname: DName::from(name),
+            })));
+
+        // 初始化inode的自引用的weak指针
+        result.0.lock().self_ref = Arc::downgrade(&result);
+
+        return result;
+    }
     // todo:其他数据获取函数实现
 
     /// @brief 打开status文件
@@ -228,6 +383,17 @@ impl ProcFSInode {
             pdata.append(&mut format!("\nVmExe:\t{} kB", text).as_bytes().to_owned());
         }
 
+        let cpus_allowed = &pcb.sched_info().cpus_allowed() & smp_cpu_manager().possible_cpus();
+        pdata.append(
+            &mut format!(
+                "\nCpus_allowed:\t{}",
+                cpus_allowed.to_hex_string(smp_cpu_manager().nr_cpu_ids())
+            )
+            .into(),
+        );
+        pdata
+            .append(&mut format!("\nCpus_allowed_list:\t{}", cpus_allowed.to_list_string()).into());
+
         pdata.append(
             &mut format!("\nflags: {:?}\n", pcb.flags().clone())
                 .as_bytes()
@@ -244,6 +410,10 @@ impl ProcFSInode {
     fn open_meminfo(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
         // 获取内存信息
         let usage = unsafe { LockedFrameAllocator.usage() };
+        let slab = unsafe { slab_usage() };
+        let cached = page_reclaimer_lock_irqsave().nr_pages();
+        let dirty = global_dirty_pages();
+        let page_kb = MMArch::PAGE_SIZE >> 10;
 
         // 传入数据
         let data: &mut Vec<u8> = &mut pdata.data;
@@ -260,12 +430,192 @@ impl ProcFSInode {
                 .to_owned(),
         );
 
+        // 空闲页、slab中的空闲对象以及干净的页缓存都可以被回收利用
+        let available = (usage.free().bytes() >> 10)
+            + (slab.free() >> 10) as usize
+            + (cached - dirty) * page_kb;
+        data.append(&mut format!("MemAvailable:\t{} kB\n", available).into());
+        data.append(&mut format!("Cached:\t{} kB\n", cached * page_kb).into());
+        data.append(&mut format!("Dirty:\t{} kB\n", dirty * page_kb).into());
+        data.append(&mut format!("Slab:\t{} kB\n", slab.total() >> 10).into());
+        data.append(&mut format!("SlabUsed:\t{} kB\n", slab.used() >> 10).into());
+        data.append(&mut format!("SlabFree:\t{} kB\n", slab.free() >> 10).into());
+
         // 去除多余的\0
         self.trim_string(data);
 
         return Ok((data.len() * size_of::<u8>()) as i64);
     }
 
+    /// 打开 vmstat 文件
+    fn open_vmstat(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let usage = unsafe { LockedFrameAllocator.usage() };
+        let slab = unsafe { slab_usage() };
+        let file_pages = page_reclaimer_lock_irqsave().nr_pages();
+        let (bg_thresh, thresh) = dirty_thresholds();
+
+        let data: &mut Vec<u8> = &mut pdata.data;
+
+        data.append(&mut format!("nr_free_pages {}\n", usage.free().data()).into());
+        data.append(&mut format!("nr_file_pages {}\n", file_pages).into());
+        data.append(&mut format!("nr_dirty {}\n", global_dirty_pages()).into());
+        data.append(&mut format!("nr_dirty_threshold {}\n", thresh).into());
+        data.append(&mut format!("nr_dirty_background_threshold {}\n", bg_thresh).into());
+        data.append(
+            &mut format!(
+                "nr_slab_unreclaimable {}\n",
+                slab.total() as usize / MMArch::PAGE_SIZE
+            )
+            .into(),
+        );
+        for event in VmEvent::iter() {
+            data.append(&mut format!("{} {}\n", event.name(), vm_event_count(event)).into());
+        }
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 slabinfo 文件
+    fn open_slabinfo(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let infos = unsafe { slab_info() };
+
+        let data: &mut Vec<u8> = &mut pdata.data;
+
+        data.append(&mut "slabinfo - version: 2.1\n".into());
+        data.append(
+            &mut "# name            <active_objs> <num_objs> <objsize> <objperslab> <pagesperslab> : slabdata <active_slabs> <num_slabs> <sharedavail>\n"
+                .into(),
+        );
+        for info in infos.iter() {
+            let name = format!("kmalloc-{}", info.obj_size);
+            data.append(
+                &mut format!(
+                    "{:<17} {:>6} {:>6} {:>6} {:>4} {:>4} : slabdata {:>6} {:>6} {:>6}\n",
+                    name,
+                    info.active_objs,
+                    info.num_objs,
+                    info.obj_size,
+                    info.obj_per_page,
+                    1,
+                    info.active_slabs,
+                    info.num_slabs,
+                    0
+                )
+                .into(),
+            );
+        }
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 net/dev 文件
+    fn open_net_dev(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut netdev_procfs_show().into());
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 net/tcp、net/udp、net/unix 等socket列表
+    fn open_net_sockets(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let content = match self.fdata.ftype {
+            ProcFileType::ProcNetTcp => tcp_procfs_show(false),
+            ProcFileType::ProcNetTcp6 => tcp_procfs_show(true),
+            ProcFileType::ProcNetUdp => udp_procfs_show(false),
+            ProcFileType::ProcNetUdp6 => udp_procfs_show(true),
+            ProcFileType::ProcNetUnix => unix_procfs_show(),
+            _ => return Err(SystemError::EINVAL),
+        };
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut content.into());
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 sysvipc 目录下的shm、sem、msg文件
+    fn open_sysvipc(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let content = match self.fdata.ftype {
+            ProcFileType::ProcSysvipcShm => shm_manager_lock().procfs_show(),
+            ProcFileType::ProcSysvipcSem => sem_procfs_show(),
+            ProcFileType::ProcSysvipcMsg => msg_procfs_show(),
+            _ => return Err(SystemError::EINVAL),
+        };
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut content.into());
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 schedstat 文件
+    fn open_schedstat(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut schedstat_procfs_show().into());
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 /proc/sys/kernel/sched_rt_period_us 或 sched_rt_runtime_us 文件
+    fn open_sched_rt(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let content = match self.fdata.ftype {
+            ProcFileType::ProcSchedRtPeriod => sched_rt_period_show(),
+            _ => sched_rt_runtime_show(),
+        };
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut content.into());
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 /proc/[pid]/sched 文件
+    fn open_pid_sched(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let pcb = ProcessManager::find(self.fdata.pid).ok_or(SystemError::ESRCH)?;
+        let data: &mut Vec<u8> = &mut pdata.data;
+        data.append(&mut sched_procfs_show(&pcb).into());
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 打开 /proc/[pid]/uid_map 或 /proc/[pid]/gid_map 文件
+    fn open_id_map(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let pcb = ProcessManager::find(self.fdata.pid).ok_or(SystemError::ESRCH)?;
+        let user_ns = pcb.cred().user_ns;
+        let content = match self.fdata.ftype {
+            ProcFileType::ProcUidMap => user_ns.show_uid_map(),
+            _ => user_ns.show_gid_map(),
+        };
+        pdata.data = content.into_bytes();
+
+        return Ok(pdata.data.len() as i64);
+    }
+
+    /// 写 /proc/[pid]/uid_map 或 /proc/[pid]/gid_map 文件，必须从头一次写入全部映射
+    fn write_id_map(&self, offset: usize, buf: &[u8]) -> Result<usize, SystemError> {
+        if offset != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = ProcessManager::find(self.fdata.pid).ok_or(SystemError::ESRCH)?;
+        let user_ns = pcb.cred().user_ns;
+        let cred = ProcessManager::current_pcb().cred();
+        match self.fdata.ftype {
+            ProcFileType::ProcUidMap => user_ns.write_uid_map(buf, &cred),
+            _ => user_ns.write_gid_map(buf, &cred),
+        }
+    }
+
     /// proc文件系统读取函数
     fn proc_read(
         &self,
@@ -347,6 +697,7 @@ impl ProcFS {
                 fdata: InodeInfo {
                     pid: Pid::new(0),
                     ftype: ProcFileType::Default,
+                    fd: -1,
                 },
                 dname: DName::default(),
             })));
@@ -382,6 +733,40 @@ impl ProcFS {
             panic!("create meminfo error");
         }
 
+        // 创建vmstat文件
+        let binding = inode.create(
+            "vmstat",
+            FileType::File,
+            ModeType::from_bits_truncate(0o444),
+        );
+        if let Ok(vmstat) = binding {
+            let vmstat_file = vmstat
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            vmstat_file.0.lock().fdata.pid = Pid::new(0);
+            vmstat_file.0.lock().fdata.ftype = ProcFileType::ProcVmstat;
+        } else {
+            panic!("create vmstat error");
+        }
+
+        // 创建slabinfo文件
+        let binding = inode.create(
+            "slabinfo",
+            FileType::File,
+            ModeType::from_bits_truncate(0o444),
+        );
+        if let Ok(slabinfo) = binding {
+            let slabinfo_file = slabinfo
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            slabinfo_file.0.lock().fdata.pid = Pid::new(0);
+            slabinfo_file.0.lock().fdata.ftype = ProcFileType::ProcSlabinfo;
+        } else {
+            panic!("create slabinfo error");
+        }
+
         // 创建kmsg文件
         let binding = inode.create("kmsg", FileType::File, ModeType::from_bits_truncate(0o444));
         if let Ok(kmsg) = binding {
@@ -394,6 +779,141 @@ impl ProcFS {
         } else {
             panic!("create ksmg error");
         }
+        // 创建net目录及其中的dev文件
+        let binding = inode.create("net", FileType::Dir, ModeType::from_bits_truncate(0o555));
+        if let Ok(net) = binding {
+            let binding = net.create("dev", FileType::File, ModeType::from_bits_truncate(0o444));
+            if let Ok(dev) = binding {
+                let dev_file = dev
+                    .as_any_ref()
+                    .downcast_ref::<LockedProcFSInode>()
+                    .unwrap();
+                dev_file.0.lock().fdata.pid = Pid::new(0);
+                dev_file.0.lock().fdata.ftype = ProcFileType::ProcNetDev;
+            } else {
+                panic!("create net/dev error");
+            }
+            for (name, ftype) in [
+                ("tcp", ProcFileType::ProcNetTcp),
+                ("tcp6", ProcFileType::ProcNetTcp6),
+                ("udp", ProcFileType::ProcNetUdp),
+                ("udp6", ProcFileType::ProcNetUdp6),
+                ("unix", ProcFileType::ProcNetUnix),
+            ] {
+                let file = net
+                    .create(name, FileType::File, ModeType::from_bits_truncate(0o444))
+                    .unwrap_or_else(|_| panic!("create net/{} error", name));
+                let file = file
+                    .as_any_ref()
+                    .downcast_ref::<LockedProcFSInode>()
+                    .unwrap();
+                file.0.lock().fdata.pid = Pid::new(0);
+                file.0.lock().fdata.ftype = ftype;
+            }
+        } else {
+            panic!("create net error");
+        }
+
+        // 创建sysvipc目录及其中的shm、sem、msg文件
+        let sysvipc = inode
+            .create(
+                "sysvipc",
+                FileType::Dir,
+                ModeType::from_bits_truncate(0o555),
+            )
+            .expect("create sysvipc error");
+        for (name, ftype) in [
+            ("shm", ProcFileType::ProcSysvipcShm),
+            ("sem", ProcFileType::ProcSysvipcSem),
+            ("msg", ProcFileType::ProcSysvipcMsg),
+        ] {
+            let file = sysvipc
+                .create(name, FileType::File, ModeType::from_bits_truncate(0o444))
+                .unwrap_or_else(|_| panic!("create sysvipc/{} error", name));
+            let file = file
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            file.0.lock().fdata.pid = Pid::new(0);
+            file.0.lock().fdata.ftype = ftype;
+        }
+
+        // 创建schedstat文件
+        let schedstat = inode
+            .create(
+                "schedstat",
+                FileType::File,
+                ModeType::from_bits_truncate(0o444),
+            )
+            .expect("create schedstat error");
+        let schedstat_file = schedstat
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap();
+        schedstat_file.0.lock().fdata.pid = Pid::new(0);
+        schedstat_file.0.lock().fdata.ftype = ProcFileType::ProcSchedstat;
+
+        // 创建sysrq-trigger文件
+        let sysrq_trigger = inode
+            .create(
+                "sysrq-trigger",
+                FileType::File,
+                ModeType::from_bits_truncate(0o200),
+            )
+            .expect("create sysrq-trigger error");
+        sysrq_trigger
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap()
+            .0
+            .lock()
+            .fdata
+            .ftype = ProcFileType::ProcSysrqTrigger;
+
+        let sys = inode
+            .create("sys", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .expect("create sys error");
+
+        // 创建sys/kernel目录下的实时调度限流参数
+        let kernel = sys
+            .create("kernel", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .expect("create sys/kernel error");
+        for (name, ftype) in [
+            ("sched_rt_period_us", ProcFileType::ProcSchedRtPeriod),
+            ("sched_rt_runtime_us", ProcFileType::ProcSchedRtRuntime),
+        ] {
+            let file = kernel
+                .create(name, FileType::File, ModeType::from_bits_truncate(0o644))
+                .unwrap_or_else(|_| panic!("create sys/kernel/{} error", name));
+            file.as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap()
+                .0
+                .lock()
+                .fdata
+                .ftype = ftype;
+        }
+
+        // 创建sys/fs/binfmt_misc目录
+        let binfmt_misc = sys
+            .create("fs", FileType::Dir, ModeType::from_bits_truncate(0o555))
+            .and_then(|fs| {
+                fs.create(
+                    "binfmt_misc",
+                    FileType::Dir,
+                    ModeType::from_bits_truncate(0o755),
+                )
+            })
+            .expect("create sys/fs/binfmt_misc error");
+        binfmt_misc
+            .as_any_ref()
+            .downcast_ref::<LockedProcFSInode>()
+            .unwrap()
+            .0
+            .lock()
+            .fdata
+            .ftype = ProcFileType::ProcBinfmtMiscDir;
+
         // 这个文件是用来欺骗Aya框架识别内核版本
         /* On Ubuntu LINUX_VERSION_CODE doesn't correspond to info.release,
          * but Ubuntu provides /proc/version_signature file, as described at
@@ -434,18 +954,26 @@ impl ProcFS {
             ModeType::from_bits_truncate(0o555),
         )?;
         // 创建相关文件
-        // status文件
-        let binding: Arc<dyn IndexNode> = pid_dir.create(
-            "status",
-            FileType::File,
-            ModeType::from_bits_truncate(0o444),
-        )?;
-        let status_file: &LockedProcFSInode = binding
-            .as_any_ref()
-            .downcast_ref::<LockedProcFSInode>()
-            .unwrap();
-        status_file.0.lock().fdata.pid = pid;
-        status_file.0.lock().fdata.ftype = ProcFileType::ProcStatus;
+        let files = [
+            ("status", FileType::File, 0o444, ProcFileType::ProcStatus),
+            ("maps", FileType::File, 0o444, ProcFileType::ProcMaps),
+            ("pagemap", FileType::File, 0o400, ProcFileType::ProcPagemap),
+            ("sched", FileType::File, 0o444, ProcFileType::ProcPidSched),
+            ("uid_map", FileType::File, 0o644, ProcFileType::ProcUidMap),
+            ("gid_map", FileType::File, 0o644, ProcFileType::ProcGidMap),
+            ("fd", FileType::Dir, 0o500, ProcFileType::ProcFdDir),
+            ("fdinfo", FileType::Dir, 0o555, ProcFileType::ProcFdInfoDir),
+        ];
+        for (name, file_type, mode, ftype) in files {
+            let binding: Arc<dyn IndexNode> =
+                pid_dir.create(name, file_type, ModeType::from_bits_truncate(mode))?;
+            let file: &LockedProcFSInode = binding
+                .as_any_ref()
+                .downcast_ref::<LockedProcFSInode>()
+                .unwrap();
+            file.0.lock().fdata.pid = pid;
+            file.0.lock().fdata.ftype = ftype;
+        }
 
         //todo: 创建其他文件
 
@@ -460,7 +988,11 @@ impl ProcFS {
         // 获取进程文件夹
         let pid_dir: Arc<dyn IndexNode> = proc.find(&pid.to_string())?;
         // 删除进程文件夹下文件
-        pid_dir.unlink("status")?;
+        for name in [
+            "status", "maps", "pagemap", "sched", "uid_map", "gid_map", "fd", "fdinfo",
+        ] {
+            pid_dir.unlink(name)?;
+        }
 
         // 查看进程文件是否还存在
         // let pf= pid_dir.find("status").expect("Cannot find status");
@@ -472,6 +1004,13 @@ impl ProcFS {
     }
 }
 
+impl LockedProcFSInode {
+    /// 是否为直接指向内核对象的“魔法链接”（如`/proc/[pid]/fd/*`）
+    pub fn is_magic_link(&self) -> bool {
+        return matches!(self.0.lock().fdata.ftype, ProcFileType::ProcFdLink);
+    }
+}
+
 impl IndexNode for LockedProcFSInode {
     fn open(
         &self,
@@ -490,6 +1029,34 @@ impl IndexNode for LockedProcFSInode {
         let file_size = match inode.fdata.ftype {
             ProcFileType::ProcStatus => inode.open_status(&mut private_data)?,
             ProcFileType::ProcMeminfo => inode.open_meminfo(&mut private_data)?,
+            ProcFileType::ProcVmstat => inode.open_vmstat(&mut private_data)?,
+            ProcFileType::ProcSlabinfo => inode.open_slabinfo(&mut private_data)?,
+            ProcFileType::ProcMaps => inode.open_maps(&mut private_data)?,
+            ProcFileType::ProcFdInfo => inode.open_fdinfo(&mut private_data)?,
+            ProcFileType::ProcNetDev => inode.open_net_dev(&mut private_data)?,
+            ProcFileType::ProcNetTcp
+            | ProcFileType::ProcNetTcp6
+            | ProcFileType::ProcNetUdp
+            | ProcFileType::ProcNetUdp6
+            | ProcFileType::ProcNetUnix => inode.open_net_sockets(&mut private_data)?,
+            ProcFileType::ProcSysvipcShm
+            | ProcFileType::ProcSysvipcSem
+            | ProcFileType::ProcSysvipcMsg => inode.open_sysvipc(&mut private_data)?,
+            ProcFileType::ProcSchedstat => inode.open_schedstat(&mut private_data)?,
+            ProcFileType::ProcPidSched => inode.open_pid_sched(&mut private_data)?,
+            ProcFileType::ProcUidMap | ProcFileType::ProcGidMap => {
+                inode.open_id_map(&mut private_data)?
+            }
+            ProcFileType::ProcBinfmtMiscRegister
+            | ProcFileType::ProcBinfmtMiscStatus
+            | ProcFileType::ProcBinfmtMiscEntry => inode.open_binfmt_misc(&mut private_data)?,
+            ProcFileType::ProcSchedRtPeriod | ProcFileType::ProcSchedRtRuntime => {
+                inode.open_sched_rt(&mut private_data)?
+            }
+            // 按需生成内容，不需要在打开时准备数据
+            ProcFileType::ProcPagemap
+            | ProcFileType::ProcFdLink
+            | ProcFileType::ProcSysrqTrigger => 0,
             ProcFileType::Default => inode.data.len() as i64,
             _ => {
                 todo!()
@@ -532,6 +1099,13 @@ impl IndexNode for LockedProcFSInode {
             return Err(SystemError::EISDIR);
         }
 
+        // 跟随符号链接时不会打开文件，因此这些文件不依赖于打开时准备的数据
+        match inode.fdata.ftype {
+            ProcFileType::ProcPagemap => return inode.read_pagemap(offset, len, buf),
+            ProcFileType::ProcFdLink => return inode.read_fd_link(offset, len, buf),
+            _ => (),
+        }
+
         // 获取数据信息
         let mut private_data = match &*data {
             FilePrivateData::Procfs(p) => p.clone(),
@@ -548,8 +1122,34 @@ impl IndexNode for LockedProcFSInode {
             ProcFileType::ProcMeminfo => {
                 return inode.proc_read(offset, len, buf, &mut private_data)
             }
-            ProcFileType::ProcKmsg => (),
-            ProcFileType::Default => (),
+            ProcFileType::ProcVmstat | ProcFileType::ProcSlabinfo => {
+                return inode.proc_read(offset, len, buf, &mut private_data)
+            }
+            ProcFileType::ProcMaps
+            | ProcFileType::ProcFdInfo
+            | ProcFileType::ProcNetDev
+            | ProcFileType::ProcNetTcp
+            | ProcFileType::ProcNetTcp6
+            | ProcFileType::ProcNetUdp
+            | ProcFileType::ProcNetUdp6
+            | ProcFileType::ProcNetUnix
+            | ProcFileType::ProcSysvipcShm
+            | ProcFileType::ProcSysvipcSem
+            | ProcFileType::ProcSysvipcMsg
+            | ProcFileType::ProcSchedstat
+            | ProcFileType::ProcPidSched
+            | ProcFileType::ProcUidMap
+            | ProcFileType::ProcGidMap
+            | ProcFileType::ProcSchedRtPeriod
+            | ProcFileType::ProcSchedRtRuntime => {
+                return inode.proc_read(offset, len, buf, &mut private_data)
+            }
+            ProcFileType::ProcBinfmtMiscRegister
+            | ProcFileType::ProcBinfmtMiscStatus
+            | ProcFileType::ProcBinfmtMiscEntry => {
+                return inode.proc_read(offset, len, buf, &mut private_data)
+            }
+            _ => (),
         };
 
         // 默认读取
@@ -569,12 +1169,31 @@ impl IndexNode for LockedProcFSInode {
 
     fn write_at(
         &self,
-        _offset: usize,
-        _len: usize,
-        _buf: &[u8],
+        offset: usize,
+        len: usize,
+        buf: &[u8],
         _data: SpinLockGuard<FilePrivateData>,
     ) -> Result<usize, SystemError> {
-        return Err(SystemError::ENOSYS);
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let inode: SpinLockGuard<ProcFSInode> = self.0.lock();
+        if let ProcFileType::ProcSysrqTrigger = inode.fdata.ftype {
+            // sysrq的操作可能不会立即返回（如进入调试器），不能持有inode的锁
+            drop(inode);
+            return write_sysrq_trigger(&buf[..len]);
+        }
+        match inode.fdata.ftype {
+            ProcFileType::ProcBinfmtMiscRegister
+            | ProcFileType::ProcBinfmtMiscStatus
+            | ProcFileType::ProcBinfmtMiscEntry => inode.write_binfmt_misc(&buf[..len]),
+            ProcFileType::ProcUidMap | ProcFileType::ProcGidMap => {
+                inode.write_id_map(offset, &buf[..len])
+            }
+            ProcFileType::ProcSchedRtPeriod => write_sched_rt_period(&buf[..len]),
+            ProcFileType::ProcSchedRtRuntime => write_sched_rt_runtime(&buf[..len]),
+            _ => Err(SystemError::ENOSYS),
+        }
     }
 
     fn fs(&self) -> Arc<dyn FileSystem> {
@@ -634,38 +1253,7 @@ impl IndexNode for LockedProcFSInode {
         }
 
         // 创建inode
-        let result: Arc<LockedProcFSInode> =
-            Arc::new(LockedProcFSInode(SpinLock::new(ProcFSInode {
-                parent: inode.self_ref.clone(),
-                self_ref: Weak::default(),
-                children: BTreeMap::new(),
-                data: Vec::new(),
-                metadata: Metadata {
-                    dev_id: 0,
-                    inode_id: generate_inode_id(),
-                    size: 0,
-                    blk_size: 0,
-                    blocks: 0,
-                    atime: PosixTimeSpec::default(),
-                    mtime: PosixTimeSpec::default(),
-                    ctime: PosixTimeSpec::default(),
-                    file_type,
-                    mode,
-                    nlinks: 1,
-                    uid: 0,
-                    gid: 0,
-                    raw_dev: DeviceNumber::from(data as u32),
-                },
-                fs: inode.fs.clone(),
-                fdata: InodeInfo {
-                    pid: Pid::new(0),
-                    ftype: ProcFileType::Default,
-                },
-                dname: name.clone(),
-            })));
-
-        // 初始化inode的自引用的weak指针
-        result.0.lock().self_ref = Arc::downgrade(&result);
+        let result = inode.new_child(name.as_ref(), file_type, mode, data);
 
         // 将子inode插入父inode的B树中
         inode.children.insert(name, result.clone());
@@ -752,6 +1340,16 @@ impl IndexNode for LockedProcFSInode {
             ".." => {
                 return Ok(inode.parent.upgrade().ok_or(SystemError::ENOENT)?);
             }
+            name if matches!(
+                inode.fdata.ftype,
+                ProcFileType::ProcFdDir | ProcFileType::ProcFdInfoDir
+            ) =>
+            {
+                return Ok(inode.find_fd(name)?);
+            }
+            name if matches!(inode.fdata.ftype, ProcFileType::ProcBinfmtMiscDir) => {
+                return Ok(inode.find_binfmt_misc(name)?);
+            }
             name => {
                 // 在子目录项中查找
                 return Ok(inode
@@ -809,6 +1407,19 @@ impl IndexNode for LockedProcFSInode {
         let mut keys: Vec<String> = Vec::new();
         keys.push(String::from("."));
         keys.push(String::from(".."));
+        let inode = self.0.lock();
+        if matches!(
+            inode.fdata.ftype,
+            ProcFileType::ProcFdDir | ProcFileType::ProcFdInfoDir
+        ) {
+            keys.append(&mut inode.list_fds()?);
+            return Ok(keys);
+        }
+        if matches!(inode.fdata.ftype, ProcFileType::ProcBinfmtMiscDir) {
+            keys.append(&mut inode.list_binfmt_misc());
+            return Ok(keys);
+        }
+        drop(inode);
         keys.append(
             &mut self
                 .0
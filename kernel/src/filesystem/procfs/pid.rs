@@ -0,0 +1,285 @@
+//! /proc/[pid]下用于进程检查点（checkpoint/restore）的文件
+//!
+//! - `maps`: 进程的虚拟内存区域
+//! - `pagemap`: 每个虚拟页对应一个64位的表项，描述该页是否在内存中以及对应的物理页帧
+//! - `fd/`: 进程打开的文件，每个文件描述符对应一个指向该文件的符号链接
+//! - `fdinfo/`: 每个文件描述符的偏移量、打开标志等信息
+//!
+//! 恢复时，通过`lseek`恢复文件偏移量，通过`mmap`的`MAP_FIXED`把内存镜像映射回原来的地址，
+//! 再通过`prctl(PR_SET_MM)`恢复代码段、数据段与堆的边界。
+//!
+//! 与process_vm_readv相同，只有满足[`may_access`]的进程才能访问这些文件，否则返回 EACCES 。
+//! pagemap中的物理页帧号只对有CAP_SYS_ADMIN权限的进程可见，其他进程读到的页帧号为0
+
+use core::mem::size_of;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    arch::MMArch,
+    filesystem::vfs::{file::File, syscall::ModeType, FileType},
+    mm::process_vm::may_access,
+    mm::{
+        page::{page_manager_lock_irqsave, PageType},
+        MemoryManagementArch, VirtAddr, VmFlags,
+    },
+    process::{cred::CAPFlags, ProcessControlBlock, ProcessManager},
+};
+
+use super::{LockedProcFSInode, ProcFSInode, ProcFileType, ProcfsFilePrivateData};
+
+/// pagemap表项：页面在内存中
+const PM_PRESENT: u64 = 1 << 63;
+/// pagemap表项：页面是文件页或共享内存页
+const PM_FILE: u64 = 1 << 61;
+/// pagemap表项：页面只被一个VMA映射
+const PM_MMAP_EXCLUSIVE: u64 = 1 << 56;
+/// pagemap表项中物理页帧号所占的位
+const PM_PFRAME_MASK: u64 = (1 << 55) - 1;
+/// pagemap每个表项的大小
+const PM_ENTRY_BYTES: usize = size_of::<u64>();
+
+impl ProcFSInode {
+    fn pcb(&self) -> Result<Arc<ProcessControlBlock>, SystemError> {
+        return ProcessManager::find(self.fdata.pid).ok_or(SystemError::ESRCH);
+    }
+
+    /// 获取目标进程，当前进程没有权限检查它时返回 EACCES
+    fn accessible_pcb(&self) -> Result<Arc<ProcessControlBlock>, SystemError> {
+        let pcb = self.pcb()?;
+        if !may_access(&pcb) {
+            return Err(SystemError::EACCES);
+        }
+        return Ok(pcb);
+    }
+
+    fn file_by_fd(&self, fd: i32) -> Result<Arc<File>, SystemError> {
+        return self
+            .accessible_pcb()?
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::ENOENT);
+    }
+
+    /// 打开maps文件
+    pub(super) fn open_maps(&self, pdata: &mut ProcfsFilePrivateData) -> Result<i64, SystemError> {
+        let pcb = self.accessible_pcb()?;
+        let data: &mut Vec<u8> = &mut pdata.data;
+
+        if let Some(user_vm) = pcb.basic().user_vm() {
+            let guard = user_vm.read();
+            let mut vmas = guard.mappings.iter_vmas().cloned().collect::<Vec<_>>();
+            vmas.sort_by_key(|vma| vma.lock_irqsave().region().start());
+
+            for vma in vmas {
+                let vma = vma.lock_irqsave();
+                let region = *vma.region();
+                let vm_flags = *vma.vm_flags();
+                let perm = |flag: VmFlags, c: char| if vm_flags.contains(flag) { c } else { '-' };
+                let perms = format!(
+                    "{}{}{}{}",
+                    perm(VmFlags::VM_READ, 'r'),
+                    perm(VmFlags::VM_WRITE, 'w'),
+                    perm(VmFlags::VM_EXEC, 'x'),
+                    if vm_flags.contains(VmFlags::VM_SHARED) {
+                        's'
+                    } else {
+                        'p'
+                    },
+                );
+                let offset = vma.file_page_offset().unwrap_or(0) * MMArch::PAGE_SIZE;
+
+                let (ino, name) = match vma.vm_file() {
+                    Some(file) => {
+                        let inode = file.inode();
+                        let ino: usize = inode.metadata().map(|m| m.inode_id.into()).unwrap_or(0);
+                        (ino, inode.absolute_path().unwrap_or_default())
+                    }
+                    None if vm_flags.contains(VmFlags::VM_GROWSDOWN) => (0, "[stack]".to_string()),
+                    None if region.start() >= guard.brk_start && region.end() <= guard.brk => {
+                        (0, "[heap]".to_string())
+                    }
+                    None => (0, String::new()),
+                };
+
+                data.append(
+                    &mut format!(
+                        "{:08x}-{:08x} {} {:08x} 00:00 {} {}\n",
+                        region.start().data(),
+                        region.end().data(),
+                        perms,
+                        offset,
+                        ino,
+                        name
+                    )
+                    .into(),
+                );
+            }
+        }
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+
+    /// 读取pagemap文件
+    ///
+    /// 文件偏移量除以表项大小即为虚拟页号，因此偏移量与长度都必须是表项大小的整数倍
+    pub(super) fn read_pagemap(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if offset % PM_ENTRY_BYTES != 0 || len % PM_ENTRY_BYTES != 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let pcb = self.accessible_pcb()?;
+        let Some(user_vm) = pcb.basic().user_vm() else {
+            return Ok(0);
+        };
+        // 物理地址可以被用来发动Rowhammer等攻击，与Linux相同，只对特权进程显示
+        let pfn_mask = if ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_SYS_ADMIN)
+        {
+            PM_PFRAME_MASK
+        } else {
+            0
+        };
+
+        let start_page = offset / PM_ENTRY_BYTES;
+        let end_page = MMArch::USER_END_VADDR.data() >> MMArch::PAGE_SHIFT;
+        let count = (len / PM_ENTRY_BYTES).min(end_page.saturating_sub(start_page));
+
+        let mut entries: Vec<u64> = Vec::with_capacity(count);
+        {
+            let guard = user_vm.read();
+            for i in 0..count {
+                let vaddr = VirtAddr::new((start_page + i) << MMArch::PAGE_SHIFT);
+                let entry = match guard.user_mapper.utable.translate(vaddr) {
+                    Some((paddr, flags)) if flags.present() => {
+                        let mut entry =
+                            PM_PRESENT | ((paddr.data() >> MMArch::PAGE_SHIFT) as u64 & pfn_mask);
+                        if let Some(page) = page_manager_lock_irqsave().get(&paddr) {
+                            let page_guard = page.read_irqsave();
+                            if !matches!(page_guard.page_type(), PageType::Normal) {
+                                entry |= PM_FILE;
+                            }
+                            if page_guard.map_count() == 1 {
+                                entry |= PM_MMAP_EXCLUSIVE;
+                            }
+                        }
+                        entry
+                    }
+                    _ => 0,
+                };
+                entries.push(entry);
+            }
+        }
+
+        for (chunk, entry) in buf.chunks_exact_mut(PM_ENTRY_BYTES).zip(entries.iter()) {
+            chunk.copy_from_slice(&entry.to_ne_bytes());
+        }
+        return Ok(entries.len() * PM_ENTRY_BYTES);
+    }
+
+    /// 列出fd或fdinfo目录下的文件，即进程当前打开的所有文件描述符
+    pub(super) fn list_fds(&self) -> Result<Vec<String>, SystemError> {
+        let pcb = self.accessible_pcb()?;
+        let fd_table = pcb.fd_table();
+        let fds = fd_table
+            .read()
+            .iter()
+            .map(|(fd, _)| fd.to_string())
+            .collect();
+        return Ok(fds);
+    }
+
+    /// 在fd或fdinfo目录下查找文件描述符对应的文件
+    ///
+    /// 这些文件不会被保存在目录中，每次查找时都根据进程当前的文件描述符表重新创建
+    pub(super) fn find_fd(&self, name: &str) -> Result<Arc<LockedProcFSInode>, SystemError> {
+        let fd: i32 = name.parse().map_err(|_| SystemError::ENOENT)?;
+        self.file_by_fd(fd)?;
+
+        let (file_type, mode, ftype) = match self.fdata.ftype {
+            ProcFileType::ProcFdDir => (
+                FileType::SymLink,
+                ModeType::from_bits_truncate(0o700),
+                ProcFileType::ProcFdLink,
+            ),
+            ProcFileType::ProcFdInfoDir => (
+                FileType::File,
+                ModeType::from_bits_truncate(0o444),
+                ProcFileType::ProcFdInfo,
+            ),
+            _ => return Err(SystemError::ENOTDIR),
+        };
+
+        let inode = self.new_child(name, file_type, mode, 0);
+        let mut guard = inode.0.lock();
+        guard.fdata.pid = self.fdata.pid;
+        guard.fdata.ftype = ftype;
+        guard.fdata.fd = fd;
+        drop(guard);
+        return Ok(inode);
+    }
+
+    /// 读取fd目录下的符号链接，得到文件描述符对应的文件路径
+    ///
+    /// 不在文件系统中的文件（如管道、socket）以`类型:[inode号]`的形式表示
+    pub(super) fn read_fd_link(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let file = self.file_by_fd(self.fdata.fd)?;
+        let inode = file.inode();
+        let target = match inode.absolute_path() {
+            Ok(path) => path,
+            Err(_) => {
+                let ino: usize = inode.metadata()?.inode_id.into();
+                match file.file_type() {
+                    FileType::Pipe => format!("pipe:[{}]", ino),
+                    FileType::Socket => format!("socket:[{}]", ino),
+                    _ => format!("anon_inode:[{}]", ino),
+                }
+            }
+        };
+
+        let target = target.as_bytes();
+        let start = target.len().min(offset);
+        let end = target.len().min(offset + len);
+        let src = &target[start..end];
+        buf[..src.len()].copy_from_slice(src);
+        return Ok(src.len());
+    }
+
+    /// 打开fdinfo目录下的文件
+    pub(super) fn open_fdinfo(
+        &self,
+        pdata: &mut ProcfsFilePrivateData,
+    ) -> Result<i64, SystemError> {
+        let file = self.file_by_fd(self.fdata.fd)?;
+        let ino: usize = file.inode().metadata()?.inode_id.into();
+        let data: &mut Vec<u8> = &mut pdata.data;
+
+        data.append(&mut format!("pos:\t{}\n", file.pos()).into());
+        data.append(&mut format!("flags:\t0{:o}\n", file.mode().bits()).into());
+        data.append(&mut format!("ino:\t{}\n", ino).into());
+
+        self.trim_string(data);
+
+        return Ok((data.len() * size_of::<u8>()) as i64);
+    }
+}
@@ -58,16 +58,16 @@ impl Syscall {
     ) -> Result<usize, SystemError> {
         let syslog_action = SyslogAction::from(syslog_action_type);
 
-        let mut kmsg_guard = unsafe { KMSG.as_ref().unwrap().lock_irqsave() };
+        let kmsg = unsafe { KMSG.as_ref().unwrap() };
 
         match syslog_action {
             SyslogAction::Close => Ok(0),
             SyslogAction::Open => Ok(0),
-            SyslogAction::Read => kmsg_guard.read(buf),
-            SyslogAction::ReadClear => kmsg_guard.read_clear(buf),
-            SyslogAction::Clear => kmsg_guard.clear(),
-            SyslogAction::SizeBuffer => kmsg_guard.data_size(),
-            SyslogAction::ConsoleLevel => kmsg_guard.set_level(len),
+            SyslogAction::Read => kmsg.read(buf),
+            SyslogAction::ReadClear => kmsg.read_clear(buf),
+            SyslogAction::Clear => kmsg.clear(),
+            SyslogAction::SizeBuffer => kmsg.data_size(),
+            SyslogAction::ConsoleLevel => kmsg.set_level(len),
             SyslogAction::Inval => return Err(SystemError::EINVAL),
         }
     }
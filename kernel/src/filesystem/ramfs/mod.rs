@@ -124,6 +124,10 @@ impl FileSystem for RamFS {
     fn super_block(&self) -> SuperBlock {
         self.super_block.read().clone()
     }
+
+    fn dcache_enabled(&self) -> bool {
+        true
+    }
 }
 
 impl RamFS {
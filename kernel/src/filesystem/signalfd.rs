@@ -0,0 +1,348 @@
+//! signalfd：通过文件描述符接收信号
+//!
+//! 读取signalfd时，会从当前进程的pending信号中取出属于掩码的信号，以`struct signalfd_siginfo`
+//! 的形式返回。这些信号一般已经被`sigprocmask`屏蔽，因此不会再按照sigaction处理。
+//!
+//! 参考 https://man7.org/linux/man-pages/man2/signalfd.2.html
+
+use crate::arch::ipc::signal::{SigSet, Signal};
+use crate::filesystem::vfs::file::{File, FileMode};
+use crate::filesystem::vfs::syscall::ModeType;
+use crate::filesystem::vfs::{FilePrivateData, FileSystem, FileType, IndexNode, Metadata};
+use crate::ipc::signal_types::{SigInfo, SigType};
+use crate::libs::spinlock::{SpinLock, SpinLockGuard};
+use crate::libs::wait_queue::WaitQueue;
+use crate::net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll};
+use crate::process::{ProcessFlags, ProcessManager};
+use crate::syscall::user_access::UserBufferReader;
+use crate::syscall::Syscall;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::mem::size_of;
+use system_error::SystemError;
+
+bitflags! {
+    pub struct SignalFdFlags: u32 {
+        /// Set the close-on-exec (FD_CLOEXEC) flag on the new file
+        /// descriptor
+        const SFD_CLOEXEC = 0o2000000;
+        /// Set the O_NONBLOCK file status flag on the open file
+        /// description referred to by the new file descriptor
+        const SFD_NONBLOCK = 0o0004000;
+    }
+}
+
+/// 读取signalfd得到的信号信息，与Linux的`struct signalfd_siginfo`布局相同，大小为128字节
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PosixSignalfdSiginfo {
+    ssi_signo: u32,
+    ssi_errno: i32,
+    ssi_code: i32,
+    ssi_pid: u32,
+    ssi_uid: u32,
+    ssi_fd: i32,
+    ssi_tid: u32,
+    ssi_band: u32,
+    ssi_overrun: u32,
+    ssi_trapno: u32,
+    ssi_status: i32,
+    ssi_int: i32,
+    ssi_ptr: u64,
+    ssi_utime: u64,
+    ssi_stime: u64,
+    ssi_addr: u64,
+    ssi_addr_lsb: u16,
+    __pad2: u16,
+    ssi_syscall: i32,
+    ssi_call_addr: u64,
+    ssi_arch: u32,
+    __pad: [u8; 28],
+}
+
+impl From<&SigInfo> for PosixSignalfdSiginfo {
+    fn from(info: &SigInfo) -> Self {
+        let pid = match info.sig_type() {
+            SigType::Kill(pid) | SigType::Alarm(pid) => pid.data() as u32,
+        };
+        PosixSignalfdSiginfo {
+            ssi_signo: info.sig_no() as u32,
+            ssi_errno: info.errno(),
+            ssi_code: info.sig_code() as i32,
+            ssi_pid: pid,
+            ssi_uid: 0,
+            ssi_fd: 0,
+            ssi_tid: 0,
+            ssi_band: 0,
+            ssi_overrun: 0,
+            ssi_trapno: 0,
+            ssi_status: 0,
+            ssi_int: 0,
+            ssi_ptr: 0,
+            ssi_utime: 0,
+            ssi_stime: 0,
+            ssi_addr: 0,
+            ssi_addr_lsb: 0,
+            __pad2: 0,
+            ssi_syscall: 0,
+            ssi_call_addr: 0,
+            ssi_arch: 0,
+            __pad: [0; 28],
+        }
+    }
+}
+
+/// 所有的signalfd，发送信号时据此唤醒在signalfd上等待的进程。已经释放的signalfd在遍历时清理
+static SIGNALFDS: SpinLock<Vec<Weak<SignalFdInode>>> = SpinLock::new(Vec::new());
+
+#[derive(Debug)]
+pub struct SignalFdInode {
+    /// 要通过这个signalfd接收的信号
+    mask: SpinLock<SigSet>,
+    flags: SignalFdFlags,
+    wait_queue: WaitQueue,
+    epitems: EPollItems,
+}
+
+impl SignalFdInode {
+    pub fn new(mask: SigSet, flags: SignalFdFlags) -> Arc<Self> {
+        let inode = Arc::new(SignalFdInode {
+            mask: SpinLock::new(mask),
+            flags,
+            wait_queue: WaitQueue::default(),
+            epitems: EPollItems::new(),
+        });
+        let mut signalfds = SIGNALFDS.lock_irqsave();
+        signalfds.retain(|w| w.strong_count() > 0);
+        signalfds.push(Arc::downgrade(&inode));
+        inode
+    }
+
+    fn set_mask(&self, mask: SigSet) {
+        *self.mask.lock_irqsave() = mask;
+    }
+
+    /// 当前进程是否有属于掩码的pending信号
+    fn readable(&self) -> bool {
+        let mask = *self.mask.lock_irqsave();
+        let pcb = ProcessManager::current_pcb();
+        let sig_info = pcb.sig_info_irqsave();
+        let pending = sig_info.sig_pending().signal() | sig_info.sig_shared_pending().signal();
+        !(pending & mask).is_empty()
+    }
+
+    /// 从当前进程取出一个属于掩码的信号
+    fn dequeue(&self) -> Option<SigInfo> {
+        let mask = *self.mask.lock_irqsave();
+        let pcb = ProcessManager::current_pcb();
+        let mut sig_info = pcb.sig_info_mut();
+        let (sig, info) = sig_info.dequeue_signal(&!mask, &pcb);
+        if sig == Signal::INVALID {
+            return None;
+        }
+        info
+    }
+}
+
+impl IndexNode for SignalFdInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// # 读取属于掩码的pending信号
+    ///
+    /// - 缓冲区至少要能容纳一个`struct signalfd_siginfo`，否则以 EINVAL 失败
+    /// - 一次读取尽可能多的信号，直到缓冲区放不下或者没有更多的信号
+    /// - 没有信号时，SFD_NONBLOCK 被设置则以 EAGAIN 失败，否则阻塞直到有信号到来
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data_guard: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        drop(data_guard);
+        let record_size = size_of::<PosixSignalfdSiginfo>();
+        if len < record_size {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut read = 0;
+        while read + record_size <= len {
+            let Some(info) = self.dequeue() else {
+                if read > 0 {
+                    break;
+                }
+                if self.flags.contains(SignalFdFlags::SFD_NONBLOCK) {
+                    return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+                }
+                let r = wq_wait_event_interruptible!(self.wait_queue, self.readable(), {});
+                if r.is_err() {
+                    ProcessManager::current_pcb()
+                        .flags()
+                        .insert(ProcessFlags::HAS_PENDING_SIGNAL);
+                    return Err(SystemError::ERESTARTSYS);
+                }
+                continue;
+            };
+
+            let record = PosixSignalfdSiginfo::from(&info);
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    &record as *const PosixSignalfdSiginfo as *const u8,
+                    record_size,
+                )
+            };
+            buf[read..read + record_size].copy_from_slice(bytes);
+            read += record_size;
+        }
+
+        return Ok(read);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    /// 当前进程有属于掩码的pending信号时可读
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let mut events = EPollEventType::empty();
+        if self.readable() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        return Ok(events.bits() as usize);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn resize(&self, _len: usize) -> Result<(), SystemError> {
+        Ok(())
+    }
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.add(epitem);
+        Ok(())
+    }
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.remove(epoll)
+    }
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        panic!("SignalFd does not have a filesystem")
+    }
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+}
+
+/// # 通知监听了信号`sig`的signalfd
+///
+/// 信号加入目标进程的pending队列之后调用，唤醒在signalfd上阻塞读取或者通过epoll等待的进程
+pub fn signalfd_notify(sig: Signal) {
+    let mut signalfds = SIGNALFDS.lock_irqsave();
+    signalfds.retain(|w| w.strong_count() > 0);
+    for signalfd in signalfds.iter().filter_map(|w| w.upgrade()) {
+        if !signalfd.mask.lock_irqsave().contains(sig.into()) {
+            continue;
+        }
+        signalfd.wait_queue.wakeup_all(None);
+        signalfd
+            .epitems
+            .wakeup(Some(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM))
+            .ok();
+    }
+}
+
+impl Syscall {
+    /// # 创建一个 signalfd，或者修改已有 signalfd 的信号掩码
+    ///
+    /// ## 参数
+    /// - `fd`: -1 表示创建新的 signalfd，否则为要修改的 signalfd
+    /// - `mask`: 用户空间的信号掩码
+    /// - `sizemask`: 信号掩码的大小，必须为8
+    /// - `flags`: SFD_CLOEXEC 和 SFD_NONBLOCK 的组合，修改已有的 signalfd 时忽略
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: signalfd 的文件描述符
+    /// - `Err(SystemError)`: 失败
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/signalfd.2.html
+    pub fn sys_signalfd4(
+        fd: i32,
+        mask: *const u64,
+        sizemask: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        let flags = SignalFdFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        if sizemask != size_of::<SigSet>() {
+            return Err(SystemError::EINVAL);
+        }
+        let reader = UserBufferReader::new(mask, size_of::<u64>(), true)?;
+        let mut mask = SigSet::from_bits_truncate(*reader.read_one_from_user::<u64>(0)?);
+        // SIGKILL和SIGSTOP不能通过signalfd接收
+        let to_remove: SigSet =
+            <Signal as Into<SigSet>>::into(Signal::SIGKILL) | Signal::SIGSTOP.into();
+        mask.remove(to_remove);
+
+        let binding = ProcessManager::current_pcb().fd_table();
+        if fd != -1 {
+            let file = binding
+                .read()
+                .get_file_by_fd(fd)
+                .ok_or(SystemError::EBADF)?;
+            let inode = file.inode();
+            let signalfd = inode
+                .as_any_ref()
+                .downcast_ref::<SignalFdInode>()
+                .ok_or(SystemError::EINVAL)?;
+            signalfd.set_mask(mask);
+            return Ok(fd as usize);
+        }
+
+        let inode = SignalFdInode::new(mask, flags);
+        let mut filemode = FileMode::O_RDONLY;
+        if flags.contains(SignalFdFlags::SFD_CLOEXEC) {
+            filemode |= FileMode::O_CLOEXEC;
+        }
+        if flags.contains(SignalFdFlags::SFD_NONBLOCK) {
+            filemode |= FileMode::O_NONBLOCK;
+        }
+        let file = File::new(inode, filemode)?;
+        let mut fd_table_guard = binding.write();
+        let fd = fd_table_guard.alloc_fd(file, None).map(|x| x as usize);
+        return fd;
+    }
+}
@@ -0,0 +1,526 @@
+//! SMB2 客户端连接
+//!
+//! 每个挂载点持有一条到服务端的TCP连接，所有请求串行地在这条连接上收发，
+//! 每次只有一个请求在途，因此一个credit就足够了。
+
+use alloc::{string::String, vec::Vec};
+use log::warn;
+use smoltcp::wire::{IpAddress, IpEndpoint};
+use system_error::SystemError;
+
+use crate::{
+    arch::rand::rand,
+    filesystem::vfs::file::{File, FileMode},
+    net::{
+        net_core::poll_ifaces,
+        socket::{new_socket, AddressFamily, PosixSocketType, SocketInode, SocketOptions},
+        Endpoint, Protocol,
+    },
+    sched::{schedule, SchedMode},
+    time::PosixTimeSpec,
+};
+
+use super::{
+    ntlm,
+    proto::{
+        from_utf16le, le16, le32, status::*, status_to_errno, timespec_to_filetime, utf16le,
+        CreateDisposition, Smb2Command, Smb2Header, SmbDirEntry, SmbFileId, SmbFileInfo,
+        FILE_DIRECTORY_INFORMATION, FILE_SHARE_ALL, SMB2_DIALECTS, SMB2_FLAGS_ASYNC_COMMAND,
+        SMB2_FLAGS_SERVER_TO_REDIR, SMB2_HEADER_SIZE, SMB2_IMPERSONATION_LEVEL, SMB2_MAX_IO_SIZE,
+        SMB2_NEGOTIATE_SIGNING_ENABLED, SMB2_NEGOTIATE_SIGNING_REQUIRED, SMB2_RESTART_SCANS,
+    },
+    spnego,
+};
+
+/// NetBIOS会话报文的最大长度（长度字段为24位）
+const NBSS_MAX_LEN: usize = 0x00ff_ffff;
+
+/// SET_INFO的信息类型: 文件信息
+const SMB2_0_INFO_FILE: u8 = 0x01;
+/// SET_INFO的信息类别: FileEndOfFileInformation
+const FILE_END_OF_FILE_INFORMATION: u8 = 20;
+
+/// 认证所需的凭据
+#[derive(Debug, Clone)]
+pub struct SmbCredentials {
+    pub username: String,
+    pub password: String,
+    pub domain: String,
+}
+
+/// 一个打开的服务端文件
+#[derive(Debug)]
+pub struct SmbOpenFile {
+    pub file_id: SmbFileId,
+    pub info: SmbFileInfo,
+}
+
+/// 到服务端的连接，已经完成认证并连接到一个共享
+#[derive(Debug)]
+pub struct SmbConnection {
+    socket: File,
+    message_id: u64,
+    session_id: u64,
+    tree_id: u32,
+    max_read: u32,
+    max_write: u32,
+    max_transact: u32,
+}
+
+impl SmbConnection {
+    /// 连接到服务端，完成协商、认证，并连接到指定的共享
+    pub fn connect(
+        addr: IpAddress,
+        port: u16,
+        share: &str,
+        cred: &SmbCredentials,
+    ) -> Result<Self, SystemError> {
+        let socket = new_socket(
+            AddressFamily::INet,
+            PosixSocketType::Stream,
+            u8::from(Protocol::Tcp) as usize,
+            SocketOptions::BLOCK,
+        )?;
+        let inode = SocketInode::new(socket);
+        let socket = File::new(inode.clone(), FileMode::O_RDWR)?;
+        unsafe { inode.inner_no_preempt() }
+            .connect(Endpoint::Ip(Some(IpEndpoint::new(addr, port))))?;
+
+        let mut conn = Self {
+            socket,
+            message_id: 0,
+            session_id: 0,
+            tree_id: 0,
+            max_read: SMB2_MAX_IO_SIZE,
+            max_write: SMB2_MAX_IO_SIZE,
+            max_transact: SMB2_MAX_IO_SIZE,
+        };
+        conn.negotiate()?;
+        conn.session_setup(cred)?;
+        conn.tree_connect(&format!("\\\\{}\\{}", addr, share))?;
+        Ok(conn)
+    }
+
+    pub fn max_read(&self) -> usize {
+        self.max_read as usize
+    }
+
+    pub fn max_write(&self) -> usize {
+        self.max_write as usize
+    }
+
+    fn send_all(&self, mut buf: &[u8]) -> Result<(), SystemError> {
+        while !buf.is_empty() {
+            match self.socket.write(buf.len(), buf) {
+                Ok(0) => return Err(SystemError::ECONNRESET),
+                Ok(n) => buf = &buf[n..],
+                // 发送缓冲区已满，等待对端确认后再发送
+                Err(SystemError::ENOBUFS) => {
+                    poll_ifaces();
+                    schedule(SchedMode::SM_NONE);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn recv_exact(&self, buf: &mut [u8]) -> Result<(), SystemError> {
+        let mut off = 0;
+        while off < buf.len() {
+            let len = buf.len() - off;
+            match self.socket.read(len, &mut buf[off..])? {
+                0 => return Err(SystemError::ECONNRESET),
+                n => off += n,
+            }
+        }
+        Ok(())
+    }
+
+    /// 收取一个完整的NetBIOS会话报文
+    fn recv_frame(&self) -> Result<Vec<u8>, SystemError> {
+        let mut nbss = [0u8; 4];
+        self.recv_exact(&mut nbss)?;
+        let len = u32::from_be_bytes(nbss) as usize & NBSS_MAX_LEN;
+        let mut msg = vec![0u8; len];
+        self.recv_exact(&mut msg)?;
+        Ok(msg)
+    }
+
+    /// 发送一个请求，并等待对应的响应
+    ///
+    /// ## 返回值
+    ///
+    /// 响应的报文头，以及包括报文头在内的整个报文（响应中的各个偏移量都是相对于报文头起始处的）
+    fn transact(
+        &mut self,
+        command: u16,
+        body: &[u8],
+    ) -> Result<(Smb2Header, Vec<u8>), SystemError> {
+        let message_id = self.message_id;
+        self.message_id += 1;
+
+        let len = SMB2_HEADER_SIZE + body.len();
+        if len > NBSS_MAX_LEN {
+            return Err(SystemError::E2BIG);
+        }
+        let mut msg = Vec::with_capacity(4 + len);
+        msg.extend_from_slice(&(len as u32).to_be_bytes());
+        Smb2Header {
+            status: 0,
+            command,
+            flags: 0,
+            message_id,
+            tree_id: self.tree_id,
+            session_id: self.session_id,
+        }
+        .encode(&mut msg);
+        msg.extend_from_slice(body);
+        self.send_all(&msg)?;
+
+        loop {
+            let resp = self.recv_frame()?;
+            let hdr = Smb2Header::decode(&resp)?;
+            if hdr.flags & SMB2_FLAGS_SERVER_TO_REDIR == 0
+                || hdr.message_id != message_id
+                || hdr.command != command
+            {
+                warn!("smb: unexpected response {:?}", hdr);
+                continue;
+            }
+            // 服务端先回复一个中间响应，最终结果稍后以异步响应的形式到达
+            if hdr.status == STATUS_PENDING && hdr.flags & SMB2_FLAGS_ASYNC_COMMAND != 0 {
+                continue;
+            }
+            return Ok((hdr, resp));
+        }
+    }
+
+    /// 发送请求，并在状态码不为成功时返回对应的错误码
+    fn transact_ok(&mut self, command: Smb2Command, body: &[u8]) -> Result<Vec<u8>, SystemError> {
+        let (hdr, resp) = self.transact(command as u16, body)?;
+        if hdr.status != STATUS_SUCCESS {
+            return Err(status_to_errno(hdr.status));
+        }
+        Ok(resp)
+    }
+
+    fn negotiate(&mut self) -> Result<(), SystemError> {
+        let mut body = Vec::with_capacity(36 + 2 * SMB2_DIALECTS.len());
+        body.extend_from_slice(&36u16.to_le_bytes());
+        body.extend_from_slice(&(SMB2_DIALECTS.len() as u16).to_le_bytes());
+        body.extend_from_slice(&SMB2_NEGOTIATE_SIGNING_ENABLED.to_le_bytes());
+        // Reserved, Capabilities
+        body.extend_from_slice(&[0u8; 6]);
+        // ClientGuid
+        for _ in 0..2 {
+            body.extend_from_slice(&(rand() as u64).to_le_bytes());
+        }
+        // ClientStartTime
+        body.extend_from_slice(&[0u8; 8]);
+        for dialect in SMB2_DIALECTS.iter() {
+            body.extend_from_slice(&dialect.to_le_bytes());
+        }
+
+        let resp = self.transact_ok(Smb2Command::Negotiate, &body)?;
+        let r = resp.get(SMB2_HEADER_SIZE..).ok_or(SystemError::EPROTO)?;
+        if r.len() < 64 {
+            return Err(SystemError::EPROTO);
+        }
+        let dialect = le16(r, 4);
+        if !SMB2_DIALECTS.contains(&dialect) {
+            warn!("smb: server chose unsupported dialect {:#x}", dialect);
+            return Err(SystemError::EPROTONOSUPPORT);
+        }
+        // 尚未实现报文签名
+        if le16(r, 2) & SMB2_NEGOTIATE_SIGNING_REQUIRED != 0 {
+            warn!("smb: server requires message signing, which is not supported");
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        self.max_transact = le32(r, 28).min(SMB2_MAX_IO_SIZE);
+        self.max_read = le32(r, 32).min(SMB2_MAX_IO_SIZE);
+        self.max_write = le32(r, 36).min(SMB2_MAX_IO_SIZE);
+        Ok(())
+    }
+
+    /// 发送一轮SESSION_SETUP，返回状态码与服务端的安全令牌
+    fn session_setup_round(&mut self, token: &[u8]) -> Result<(u32, Vec<u8>), SystemError> {
+        let mut body = Vec::with_capacity(24 + token.len());
+        body.extend_from_slice(&25u16.to_le_bytes());
+        // Flags
+        body.push(0);
+        body.push(SMB2_NEGOTIATE_SIGNING_ENABLED as u8);
+        // Capabilities, Channel
+        body.extend_from_slice(&[0u8; 8]);
+        body.extend_from_slice(&((SMB2_HEADER_SIZE + 24) as u16).to_le_bytes());
+        body.extend_from_slice(&(token.len() as u16).to_le_bytes());
+        // PreviousSessionId
+        body.extend_from_slice(&[0u8; 8]);
+        body.extend_from_slice(token);
+
+        let (hdr, resp) = self.transact(Smb2Command::SessionSetup as u16, &body)?;
+        if hdr.status != STATUS_SUCCESS && hdr.status != STATUS_MORE_PROCESSING_REQUIRED {
+            return Err(status_to_errno(hdr.status));
+        }
+        self.session_id = hdr.session_id;
+
+        let r = resp.get(SMB2_HEADER_SIZE..).ok_or(SystemError::EPROTO)?;
+        if r.len() < 8 {
+            return Err(SystemError::EPROTO);
+        }
+        let off = le16(r, 4) as usize;
+        let len = le16(r, 6) as usize;
+        let token = resp.get(off..off + len).ok_or(SystemError::EPROTO)?;
+        Ok((hdr.status, token.to_vec()))
+    }
+
+    /// 使用NTLMv2完成认证
+    fn session_setup(&mut self, cred: &SmbCredentials) -> Result<(), SystemError> {
+        let (status, token) =
+            self.session_setup_round(&spnego::wrap_init(&ntlm::negotiate_message()))?;
+        if status != STATUS_MORE_PROCESSING_REQUIRED {
+            return Err(SystemError::EPROTO);
+        }
+        let challenge = ntlm::parse_challenge(spnego::unwrap_response(&token)?)?;
+
+        let mut client_challenge = [0u8; 8];
+        client_challenge.copy_from_slice(&(rand() as u64).to_le_bytes());
+        let auth = ntlm::authenticate_message(
+            &challenge,
+            &cred.username,
+            &cred.password,
+            &cred.domain,
+            timespec_to_filetime(PosixTimeSpec::now()),
+            client_challenge,
+        );
+
+        let (status, _) = self.session_setup_round(&spnego::wrap_response(&auth))?;
+        if status != STATUS_SUCCESS {
+            return Err(status_to_errno(status));
+        }
+        Ok(())
+    }
+
+    fn tree_connect(&mut self, path: &str) -> Result<(), SystemError> {
+        let path = utf16le(path);
+        let mut body = Vec::with_capacity(8 + path.len());
+        body.extend_from_slice(&9u16.to_le_bytes());
+        // Reserved
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&((SMB2_HEADER_SIZE + 8) as u16).to_le_bytes());
+        body.extend_from_slice(&(path.len() as u16).to_le_bytes());
+        body.extend_from_slice(&path);
+
+        let resp = self.transact_ok(Smb2Command::TreeConnect, &body)?;
+        self.tree_id = Smb2Header::decode(&resp)?.tree_id;
+        Ok(())
+    }
+
+    /// 打开或创建一个文件
+    ///
+    /// ## 参数
+    ///
+    /// - `path`: 相对于共享根目录的路径，以`\`分隔，空字符串表示共享根目录
+    /// - `access`: DesiredAccess
+    /// - `options`: CreateOptions
+    pub fn create(
+        &mut self,
+        path: &str,
+        access: u32,
+        disposition: CreateDisposition,
+        options: u32,
+    ) -> Result<SmbOpenFile, SystemError> {
+        let name = utf16le(path);
+        let mut body = Vec::with_capacity(56 + name.len().max(1));
+        body.extend_from_slice(&57u16.to_le_bytes());
+        // SecurityFlags, RequestedOplockLevel
+        body.extend_from_slice(&[0u8; 2]);
+        body.extend_from_slice(&SMB2_IMPERSONATION_LEVEL.to_le_bytes());
+        // SmbCreateFlags, Reserved
+        body.extend_from_slice(&[0u8; 16]);
+        body.extend_from_slice(&access.to_le_bytes());
+        // FileAttributes
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&FILE_SHARE_ALL.to_le_bytes());
+        body.extend_from_slice(&(disposition as u32).to_le_bytes());
+        body.extend_from_slice(&options.to_le_bytes());
+        body.extend_from_slice(&((SMB2_HEADER_SIZE + 56) as u16).to_le_bytes());
+        body.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        // CreateContextsOffset, CreateContextsLength
+        body.extend_from_slice(&[0u8; 8]);
+        body.extend_from_slice(&name);
+        // 缓冲区至少要有一个字节
+        if name.is_empty() {
+            body.push(0);
+        }
+
+        let resp = self.transact_ok(Smb2Command::Create, &body)?;
+        let r = resp.get(SMB2_HEADER_SIZE..).ok_or(SystemError::EPROTO)?;
+        if r.len() < 88 {
+            return Err(SystemError::EPROTO);
+        }
+        let mut file_id = SmbFileId::default();
+        file_id.copy_from_slice(&r[64..80]);
+        Ok(SmbOpenFile {
+            file_id,
+            info: SmbFileInfo::parse(r, [8, 16, 24, 32], 48, 56),
+        })
+    }
+
+    pub fn close(&mut self, file_id: &SmbFileId) -> Result<(), SystemError> {
+        let mut body = Vec::with_capacity(24);
+        body.extend_from_slice(&24u16.to_le_bytes());
+        // Flags, Reserved
+        body.extend_from_slice(&[0u8; 6]);
+        body.extend_from_slice(file_id);
+        self.transact_ok(Smb2Command::Close, &body)?;
+        Ok(())
+    }
+
+    /// 从文件中读取至多`buf.len()`字节（不超过`max_read`），返回读取的字节数
+    pub fn read(
+        &mut self,
+        file_id: &SmbFileId,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let len = buf.len().min(self.max_read()) as u32;
+        let mut body = Vec::with_capacity(49);
+        body.extend_from_slice(&49u16.to_le_bytes());
+        // Padding: 希望数据紧跟在响应体的固定部分之后
+        body.push((SMB2_HEADER_SIZE + 16) as u8);
+        // Flags
+        body.push(0);
+        body.extend_from_slice(&len.to_le_bytes());
+        body.extend_from_slice(&offset.to_le_bytes());
+        body.extend_from_slice(file_id);
+        // MinimumCount, Channel, RemainingBytes, ReadChannelInfoOffset/Length
+        body.extend_from_slice(&[0u8; 16]);
+        // 缓冲区至少要有一个字节
+        body.push(0);
+
+        let (hdr, resp) = self.transact(Smb2Command::Read as u16, &body)?;
+        match hdr.status {
+            STATUS_SUCCESS => {}
+            STATUS_END_OF_FILE => return Ok(0),
+            s => return Err(status_to_errno(s)),
+        }
+        let r = resp.get(SMB2_HEADER_SIZE..).ok_or(SystemError::EPROTO)?;
+        if r.len() < 16 {
+            return Err(SystemError::EPROTO);
+        }
+        let data_off = r[2] as usize;
+        let data_len = (le32(r, 4) as usize).min(buf.len());
+        let data = resp
+            .get(data_off..data_off + data_len)
+            .ok_or(SystemError::EPROTO)?;
+        buf[..data_len].copy_from_slice(data);
+        Ok(data_len)
+    }
+
+    /// 向文件写入至多`data.len()`字节（不超过`max_write`），返回写入的字节数
+    pub fn write(
+        &mut self,
+        file_id: &SmbFileId,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<usize, SystemError> {
+        let data = &data[..data.len().min(self.max_write())];
+        let mut body = Vec::with_capacity(48 + data.len());
+        body.extend_from_slice(&49u16.to_le_bytes());
+        body.extend_from_slice(&((SMB2_HEADER_SIZE + 48) as u16).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&offset.to_le_bytes());
+        body.extend_from_slice(file_id);
+        // Channel, RemainingBytes, WriteChannelInfoOffset/Length, Flags
+        body.extend_from_slice(&[0u8; 16]);
+        body.extend_from_slice(data);
+        // 缓冲区至少要有一个字节
+        if data.is_empty() {
+            body.push(0);
+        }
+
+        let resp = self.transact_ok(Smb2Command::Write, &body)?;
+        let r = resp.get(SMB2_HEADER_SIZE..).ok_or(SystemError::EPROTO)?;
+        if r.len() < 8 {
+            return Err(SystemError::EPROTO);
+        }
+        Ok(le32(r, 4) as usize)
+    }
+
+    /// 设置文件的长度
+    pub fn set_end_of_file(&mut self, file_id: &SmbFileId, len: u64) -> Result<(), SystemError> {
+        let mut body = Vec::with_capacity(32 + 8);
+        body.extend_from_slice(&33u16.to_le_bytes());
+        body.push(SMB2_0_INFO_FILE);
+        body.push(FILE_END_OF_FILE_INFORMATION);
+        body.extend_from_slice(&8u32.to_le_bytes());
+        body.extend_from_slice(&((SMB2_HEADER_SIZE + 32) as u16).to_le_bytes());
+        // Reserved, AdditionalInformation
+        body.extend_from_slice(&[0u8; 6]);
+        body.extend_from_slice(file_id);
+        body.extend_from_slice(&len.to_le_bytes());
+
+        self.transact_ok(Smb2Command::SetInfo, &body)?;
+        Ok(())
+    }
+
+    /// 列出目录中的所有目录项（不包括`.`与`..`）
+    pub fn query_directory(&mut self, dir_id: &SmbFileId) -> Result<Vec<SmbDirEntry>, SystemError> {
+        let pattern = utf16le("*");
+        let mut entries = Vec::new();
+        let mut flags = SMB2_RESTART_SCANS;
+        loop {
+            let mut body = Vec::with_capacity(32 + pattern.len());
+            body.extend_from_slice(&33u16.to_le_bytes());
+            body.push(FILE_DIRECTORY_INFORMATION);
+            body.push(flags);
+            // FileIndex
+            body.extend_from_slice(&0u32.to_le_bytes());
+            body.extend_from_slice(dir_id);
+            body.extend_from_slice(&((SMB2_HEADER_SIZE + 32) as u16).to_le_bytes());
+            body.extend_from_slice(&(pattern.len() as u16).to_le_bytes());
+            body.extend_from_slice(&self.max_transact.to_le_bytes());
+            body.extend_from_slice(&pattern);
+            flags = 0;
+
+            let (hdr, resp) = self.transact(Smb2Command::QueryDirectory as u16, &body)?;
+            match hdr.status {
+                STATUS_SUCCESS => {}
+                STATUS_NO_MORE_FILES => return Ok(entries),
+                s => return Err(status_to_errno(s)),
+            }
+            let r = resp.get(SMB2_HEADER_SIZE..).ok_or(SystemError::EPROTO)?;
+            if r.len() < 8 {
+                return Err(SystemError::EPROTO);
+            }
+            let off = le16(r, 2) as usize;
+            let len = le32(r, 4) as usize;
+            let buf = resp.get(off..off + len).ok_or(SystemError::EPROTO)?;
+            Self::parse_directory_info(buf, &mut entries)?;
+        }
+    }
+
+    /// 解析一串FILE_DIRECTORY_INFORMATION
+    fn parse_directory_info(
+        mut buf: &[u8],
+        entries: &mut Vec<SmbDirEntry>,
+    ) -> Result<(), SystemError> {
+        while buf.len() >= 64 {
+            let next = le32(buf, 0) as usize;
+            let name_len = le32(buf, 60) as usize;
+            let name = from_utf16le(buf.get(64..64 + name_len).ok_or(SystemError::EPROTO)?)?;
+            if name != "." && name != ".." {
+                entries.push(SmbDirEntry {
+                    name,
+                    info: SmbFileInfo::parse(buf, [8, 16, 24, 32], 40, 56),
+                });
+            }
+            if next == 0 {
+                break;
+            }
+            buf = buf.get(next..).ok_or(SystemError::EPROTO)?;
+        }
+        Ok(())
+    }
+}
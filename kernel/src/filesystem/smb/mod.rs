@@ -0,0 +1,619 @@
+//! SMB2/CIFS 客户端文件系统
+//!
+//! 通过SMB 2.0.2/2.1协议挂载Windows或Samba的共享目录，使用NTLMv2认证。
+//! 目前不支持报文签名与加密，因此服务端不能强制要求签名。
+//!
+//! 挂载参数形如`addr=10.0.2.2,share=public,username=user,password=pass`，
+//! 可选的参数有`port`（默认445）与`domain`。
+
+pub mod conn;
+mod ntlm;
+pub mod proto;
+mod spnego;
+
+use core::any::Any;
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use smoltcp::wire::IpAddress;
+use system_error::SystemError;
+
+use crate::{
+    driver::base::device::device_number::DeviceNumber,
+    filesystem::vfs::{
+        core::generate_inode_id, file::FilePrivateData, syscall::ModeType, utils::DName,
+        FileSystem, FileSystemMaker, FileSystemMakerData, FileType, FsInfo, IndexNode, Magic,
+        Metadata, SuperBlock, FSMAKER,
+    },
+    libs::{
+        mutex::Mutex,
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    time::PosixTimeSpec,
+};
+
+use self::{
+    conn::{SmbConnection, SmbCredentials, SmbOpenFile},
+    proto::{access, create_options, CreateDisposition, SmbFileInfo},
+};
+
+/// 文件名的最大长度
+const SMB_MAX_NAMELEN: usize = 255;
+/// 向用户态报告的块大小
+const SMB_BLOCK_SIZE: u64 = 4096;
+/// SMB over TCP的默认端口
+const SMB_DEFAULT_PORT: u16 = 445;
+
+#[distributed_slice(FSMAKER)]
+static SMBFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "cifs",
+    &(SmbFs::make_smbfs
+        as fn(
+            Option<&dyn FileSystemMakerData>,
+        ) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+/// 挂载参数
+#[derive(Debug)]
+pub struct SmbMountData {
+    addr: IpAddress,
+    port: u16,
+    share: String,
+    cred: SmbCredentials,
+}
+
+impl SmbMountData {
+    pub fn from_row(raw_data: *const u8) -> Result<Self, SystemError> {
+        if raw_data.is_null() {
+            return Err(SystemError::EINVAL);
+        }
+        let len = (0..)
+            .find(|&i| unsafe { raw_data.add(i).read() } == 0)
+            .ok_or(SystemError::EINVAL)?;
+        let slice = unsafe { core::slice::from_raw_parts(raw_data, len) };
+        let raw_str = core::str::from_utf8(slice).map_err(|_| SystemError::EINVAL)?;
+
+        let mut addr = None;
+        let mut port = SMB_DEFAULT_PORT;
+        let mut share = None;
+        let mut cred = SmbCredentials {
+            username: String::new(),
+            password: String::new(),
+            domain: String::new(),
+        };
+
+        for pair in raw_str.split(',') {
+            // 密码中可能含有'='，因此只在第一个'='处分割
+            let (key, value) = pair.split_once('=').ok_or(SystemError::EINVAL)?;
+            match key {
+                "addr" | "ip" => {
+                    addr = Some(
+                        value
+                            .parse::<IpAddress>()
+                            .map_err(|_| SystemError::EINVAL)?,
+                    )
+                }
+                "port" => port = value.parse().map_err(|_| SystemError::EINVAL)?,
+                "share" => share = Some(value.trim_matches(|c| c == '/' || c == '\\').into()),
+                "username" | "user" => cred.username = value.into(),
+                "password" | "pass" => cred.password = value.into(),
+                "domain" | "dom" => cred.domain = value.into(),
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+
+        Ok(SmbMountData {
+            addr: addr.ok_or(SystemError::EINVAL)?,
+            port,
+            share: share.ok_or(SystemError::EINVAL)?,
+            cred,
+        })
+    }
+}
+
+impl FileSystemMakerData for SmbMountData {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// SMB文件系统
+#[derive(Debug)]
+pub struct SmbFs {
+    /// 到服务端的连接，请求在连接上串行执行
+    conn: Mutex<SmbConnection>,
+    root_inode: Arc<LockedSmbInode>,
+    super_block: SuperBlock,
+}
+
+impl FileSystem for SmbFs {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        self.root_inode.clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            blk_dev_id: 0,
+            max_name_len: SMB_MAX_NAMELEN,
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "cifs"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        self.super_block.clone()
+    }
+}
+
+impl SmbFs {
+    pub fn make_smbfs(
+        data: Option<&dyn FileSystemMakerData>,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        let mount_data = data
+            .and_then(|d| d.as_any().downcast_ref::<SmbMountData>())
+            .ok_or(SystemError::EINVAL)?;
+
+        let mut conn = SmbConnection::connect(
+            mount_data.addr,
+            mount_data.port,
+            &mount_data.share,
+            &mount_data.cred,
+        )?;
+        let root = conn.create(
+            "",
+            access::FILE_READ_ATTRIBUTES,
+            CreateDisposition::Open,
+            create_options::FILE_DIRECTORY_FILE,
+        )?;
+        conn.close(&root.file_id)?;
+
+        let root_inode = Arc::new(LockedSmbInode(SpinLock::new(SmbInode::new(
+            String::new(),
+            DName::default(),
+            &root.info,
+        ))));
+        let fs = Arc::new(SmbFs {
+            conn: Mutex::new(conn),
+            root_inode,
+            super_block: SuperBlock::new(Magic::SMB2_MAGIC, SMB_BLOCK_SIZE, SMB_MAX_NAMELEN as u64),
+        });
+
+        let mut root_guard = fs.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&fs.root_inode);
+        root_guard.self_ref = Arc::downgrade(&fs.root_inode);
+        root_guard.fs = Arc::downgrade(&fs);
+        drop(root_guard);
+
+        Ok(fs)
+    }
+
+    /// 打开服务端的文件，执行`f`后关闭它
+    fn with_open<R>(
+        &self,
+        path: &str,
+        access: u32,
+        disposition: CreateDisposition,
+        options: u32,
+        f: impl FnOnce(&mut SmbConnection, &SmbOpenFile) -> Result<R, SystemError>,
+    ) -> Result<R, SystemError> {
+        let mut conn = self.conn.lock();
+        let file = conn.create(path, access, disposition, options)?;
+        let r = f(&mut conn, &file);
+        let closed = conn.close(&file.file_id);
+        let r = r?;
+        closed?;
+        Ok(r)
+    }
+}
+
+#[derive(Debug)]
+pub struct LockedSmbInode(SpinLock<SmbInode>);
+
+#[derive(Debug)]
+pub struct SmbInode {
+    /// 指向父Inode的弱引用
+    parent: Weak<LockedSmbInode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedSmbInode>,
+    /// 已经查找过的子Inode
+    children: BTreeMap<DName, Arc<LockedSmbInode>>,
+    /// 相对于共享根目录的路径，以`\`分隔
+    path: String,
+    metadata: Metadata,
+    /// 服务端记录的创建时间
+    btime: PosixTimeSpec,
+    fs: Weak<SmbFs>,
+    name: DName,
+}
+
+impl SmbInode {
+    fn new(path: String, name: DName, info: &SmbFileInfo) -> Self {
+        let mut inode = Self {
+            parent: Weak::default(),
+            self_ref: Weak::default(),
+            children: BTreeMap::new(),
+            path,
+            metadata: Metadata {
+                dev_id: 0,
+                inode_id: generate_inode_id(),
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::default(),
+                ..Default::default()
+            },
+            btime: PosixTimeSpec::default(),
+            fs: Weak::default(),
+            name,
+        };
+        inode.update(info);
+        inode
+    }
+
+    /// 用服务端返回的属性更新元数据
+    fn update(&mut self, info: &SmbFileInfo) {
+        let md = &mut self.metadata;
+        md.file_type = file_type_of(info);
+        md.mode = if info.is_dir() {
+            ModeType::from_bits_truncate(0o755)
+        } else {
+            ModeType::from_bits_truncate(0o644)
+        };
+        md.size = info.size as i64;
+        md.blk_size = SMB_BLOCK_SIZE as usize;
+        md.blocks = info.size.div_ceil(SMB_BLOCK_SIZE) as usize;
+        md.atime = info.atime;
+        md.mtime = info.mtime;
+        md.ctime = info.ctime;
+        self.btime = info.btime;
+    }
+
+    /// 在当前目录下构造名为`name`的子Inode
+    fn new_child(&self, name: &str, info: &SmbFileInfo) -> Arc<LockedSmbInode> {
+        let path = if self.path.is_empty() {
+            String::from(name)
+        } else {
+            format!("{}\\{}", self.path, name)
+        };
+        let mut child = SmbInode::new(path, DName::from(name), info);
+        child.parent = self.self_ref.clone();
+        child.fs = self.fs.clone();
+
+        let child = Arc::new(LockedSmbInode(SpinLock::new(child)));
+        child.0.lock().self_ref = Arc::downgrade(&child);
+        child
+    }
+
+    fn child_path(&self, name: &str) -> Result<String, SystemError> {
+        if name.len() > SMB_MAX_NAMELEN {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+        if name.contains(['\\', '/']) {
+            return Err(SystemError::EINVAL);
+        }
+        if self.path.is_empty() {
+            Ok(String::from(name))
+        } else {
+            Ok(format!("{}\\{}", self.path, name))
+        }
+    }
+
+    fn fs(&self) -> Arc<SmbFs> {
+        self.fs.upgrade().unwrap()
+    }
+}
+
+impl LockedSmbInode {
+    /// 获取路径与文件系统，之后的网络请求不应在持有Inode锁的情况下进行
+    fn snapshot(&self) -> (String, FileType, Arc<SmbFs>) {
+        let inode = self.0.lock();
+        (inode.path.clone(), inode.metadata.file_type, inode.fs())
+    }
+
+    fn dir_snapshot(&self, name: &str) -> Result<(String, Arc<SmbFs>), SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        Ok((inode.child_path(name)?, inode.fs()))
+    }
+
+    /// 删除名为`name`的子文件或子目录
+    fn remove(&self, name: &str, options: u32) -> Result<(), SystemError> {
+        let (path, fs) = self.dir_snapshot(name)?;
+        fs.with_open(
+            &path,
+            access::DELETE,
+            CreateDisposition::Open,
+            options | create_options::FILE_DELETE_ON_CLOSE,
+            |_, _| Ok(()),
+        )?;
+        self.0.lock().children.remove(&DName::from(name));
+        Ok(())
+    }
+}
+
+impl IndexNode for LockedSmbInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &crate::filesystem::vfs::file::FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let (path, file_type, fs) = self.snapshot();
+        if file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+
+        fs.with_open(
+            &path,
+            access::FILE_READ_DATA | access::SYNCHRONIZE,
+            CreateDisposition::Open,
+            create_options::FILE_NON_DIRECTORY_FILE,
+            |conn, file| {
+                let mut total = 0;
+                while total < len {
+                    let want = (len - total).min(conn.max_read());
+                    let n = conn.read(
+                        &file.file_id,
+                        (offset + total) as u64,
+                        &mut buf[total..total + want],
+                    )?;
+                    total += n;
+                    // 读到了文件末尾
+                    if n < want {
+                        break;
+                    }
+                }
+                Ok(total)
+            },
+        )
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let (path, file_type, fs) = self.snapshot();
+        if file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+
+        let (written, info) = fs.with_open(
+            &path,
+            access::FILE_WRITE_DATA | access::FILE_READ_ATTRIBUTES | access::SYNCHRONIZE,
+            CreateDisposition::Open,
+            create_options::FILE_NON_DIRECTORY_FILE,
+            |conn, file| {
+                let mut total = 0;
+                while total < len {
+                    let end = len.min(total + conn.max_write());
+                    let n = conn.write(&file.file_id, (offset + total) as u64, &buf[total..end])?;
+                    if n == 0 {
+                        break;
+                    }
+                    total += n;
+                }
+                Ok((total, file.info))
+            },
+        )?;
+
+        let mut inode = self.0.lock();
+        inode.update(&info);
+        let size = inode.metadata.size.max((offset + written) as i64);
+        inode.metadata.size = size;
+        Ok(written)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.0.lock().fs()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(self.0.lock().metadata.clone())
+    }
+
+    fn btime(&self) -> Option<PosixTimeSpec> {
+        Some(self.0.lock().btime)
+    }
+
+    /// 元数据只在打开、读写文件与列出目录时更新，这里重新向服务端查询
+    fn revalidate(&self) -> Result<(), SystemError> {
+        let (path, file_type, fs) = self.snapshot();
+        let options = if file_type == FileType::Dir {
+            create_options::FILE_DIRECTORY_FILE
+        } else {
+            create_options::FILE_NON_DIRECTORY_FILE
+        };
+        let info = fs.with_open(
+            &path,
+            access::FILE_READ_ATTRIBUTES,
+            CreateDisposition::Open,
+            options,
+            |_, file| Ok(file.info),
+        )?;
+        self.0.lock().update(&info);
+        Ok(())
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        let (path, file_type, fs) = self.snapshot();
+        if file_type != FileType::File {
+            return Err(SystemError::EINVAL);
+        }
+
+        fs.with_open(
+            &path,
+            access::FILE_WRITE_DATA | access::SYNCHRONIZE,
+            CreateDisposition::Open,
+            create_options::FILE_NON_DIRECTORY_FILE,
+            |conn, file| conn.set_end_of_file(&file.file_id, len as u64),
+        )?;
+        self.0.lock().metadata.size = len as i64;
+        Ok(())
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        _mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let options = match file_type {
+            FileType::File => create_options::FILE_NON_DIRECTORY_FILE,
+            FileType::Dir => create_options::FILE_DIRECTORY_FILE,
+            // 没有UNIX扩展时，无法在服务端创建特殊文件
+            _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+        };
+        let (path, fs) = self.dir_snapshot(name)?;
+        let info = fs.with_open(
+            &path,
+            access::FILE_READ_ATTRIBUTES,
+            CreateDisposition::Create,
+            options,
+            |_, file| Ok(file.info),
+        )?;
+
+        let mut inode = self.0.lock();
+        let child = inode.new_child(name, &info);
+        inode.children.insert(DName::from(name), child.clone());
+        Ok(child)
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        self.remove(name, create_options::FILE_NON_DIRECTORY_FILE)
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        self.remove(name, create_options::FILE_DIRECTORY_FILE)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        {
+            let inode = self.0.lock();
+            if inode.metadata.file_type != FileType::Dir {
+                return Err(SystemError::ENOTDIR);
+            }
+            match name {
+                "" | "." => return Ok(inode.self_ref.upgrade().ok_or(SystemError::ENOENT)?),
+                ".." => return Ok(inode.parent.upgrade().ok_or(SystemError::ENOENT)?),
+                name => {
+                    if let Some(child) = inode.children.get(&DName::from(name)) {
+                        return Ok(child.clone());
+                    }
+                }
+            }
+        }
+
+        let (path, fs) = self.dir_snapshot(name)?;
+        let info = fs.with_open(
+            &path,
+            access::FILE_READ_ATTRIBUTES,
+            CreateDisposition::Open,
+            0,
+            |_, file| Ok(file.info),
+        )?;
+
+        let mut inode = self.0.lock();
+        let child = inode.new_child(name, &info);
+        // 查找期间可能有其他进程已经插入了同名的子Inode
+        Ok(inode
+            .children
+            .entry(DName::from(name))
+            .or_insert(child)
+            .clone())
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let (path, file_type, fs) = self.snapshot();
+        if file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        let entries = fs.with_open(
+            &path,
+            access::FILE_LIST_DIRECTORY | access::FILE_READ_ATTRIBUTES | access::SYNCHRONIZE,
+            CreateDisposition::Open,
+            create_options::FILE_DIRECTORY_FILE,
+            |conn, file| conn.query_directory(&file.file_id),
+        )?;
+
+        // 用服务端的目录内容刷新缓存：保留仍然存在的子Inode，丢弃已被删除的
+        let mut inode = self.0.lock();
+        let mut children = BTreeMap::new();
+        for entry in entries.iter() {
+            let name = DName::from(entry.name.as_str());
+            let child = match inode.children.remove(&name) {
+                Some(child) if child.0.lock().metadata.file_type == file_type_of(&entry.info) => {
+                    child.0.lock().update(&entry.info);
+                    child
+                }
+                _ => inode.new_child(&entry.name, &entry.info),
+            };
+            children.insert(name, child);
+        }
+        inode.children = children;
+
+        let mut keys: Vec<String> = vec![String::from("."), String::from("..")];
+        keys.extend(entries.into_iter().map(|e| e.name));
+        Ok(keys)
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.0.lock().name.clone())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.0
+            .lock()
+            .parent
+            .upgrade()
+            .map(|item| item as Arc<dyn IndexNode>)
+            .ok_or(SystemError::EINVAL)
+    }
+}
+
+fn file_type_of(info: &SmbFileInfo) -> FileType {
+    if info.is_dir() {
+        FileType::Dir
+    } else {
+        FileType::File
+    }
+}
@@ -0,0 +1,182 @@
+//! NTLMv2 认证
+//!
+//! 参考: [MS-NLMP] NT LAN Manager (NTLM) Authentication Protocol
+
+use alloc::vec::Vec;
+use md_hash::{hmac_md5, md4};
+use system_error::SystemError;
+
+use super::proto::{le16, le32, utf16le};
+
+const NTLMSSP_SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+const NTLMSSP_NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const NTLMSSP_REQUEST_TARGET: u32 = 0x0000_0004;
+const NTLMSSP_NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NTLMSSP_NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NTLMSSP_NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+const NTLMSSP_NEGOTIATE_TARGET_INFO: u32 = 0x0080_0000;
+const NTLMSSP_NEGOTIATE_128: u32 = 0x2000_0000;
+const NTLMSSP_NEGOTIATE_56: u32 = 0x8000_0000;
+
+const NTLM_NEGOTIATE_FLAGS: u32 = NTLMSSP_NEGOTIATE_UNICODE
+    | NTLMSSP_REQUEST_TARGET
+    | NTLMSSP_NEGOTIATE_NTLM
+    | NTLMSSP_NEGOTIATE_ALWAYS_SIGN
+    | NTLMSSP_NEGOTIATE_EXTENDED_SESSIONSECURITY
+    | NTLMSSP_NEGOTIATE_TARGET_INFO
+    | NTLMSSP_NEGOTIATE_128
+    | NTLMSSP_NEGOTIATE_56;
+
+/// AV_PAIR中时间戳的AvId
+const MSV_AV_TIMESTAMP: u16 = 7;
+/// AV_PAIR列表的结束标记
+const MSV_AV_EOL: u16 = 0;
+
+/// 服务端发来的CHALLENGE_MESSAGE
+#[derive(Debug)]
+pub struct NtlmChallenge {
+    server_challenge: [u8; 8],
+    target_info: Vec<u8>,
+    flags: u32,
+}
+
+/// 构造NEGOTIATE_MESSAGE
+pub fn negotiate_message() -> Vec<u8> {
+    let mut msg = Vec::with_capacity(32);
+    msg.extend_from_slice(NTLMSSP_SIGNATURE);
+    msg.extend_from_slice(&1u32.to_le_bytes());
+    msg.extend_from_slice(&NTLM_NEGOTIATE_FLAGS.to_le_bytes());
+    // DomainNameFields与WorkstationFields均为空
+    msg.extend_from_slice(&[0u8; 16]);
+    msg
+}
+
+/// 解析CHALLENGE_MESSAGE
+pub fn parse_challenge(msg: &[u8]) -> Result<NtlmChallenge, SystemError> {
+    if msg.len() < 48 || &msg[0..8] != NTLMSSP_SIGNATURE || le32(msg, 8) != 2 {
+        return Err(SystemError::EPROTO);
+    }
+    let flags = le32(msg, 20);
+    let mut server_challenge = [0u8; 8];
+    server_challenge.copy_from_slice(&msg[24..32]);
+
+    let info_len = le16(msg, 40) as usize;
+    let info_off = le32(msg, 44) as usize;
+    let target_info = msg
+        .get(info_off..info_off + info_len)
+        .ok_or(SystemError::EPROTO)?
+        .to_vec();
+
+    Ok(NtlmChallenge {
+        server_challenge,
+        target_info,
+        flags,
+    })
+}
+
+/// 从AV_PAIR列表中查找时间戳
+fn find_timestamp(target_info: &[u8]) -> Option<u64> {
+    let mut off = 0;
+    while off + 4 <= target_info.len() {
+        let id = le16(target_info, off);
+        let len = le16(target_info, off + 2) as usize;
+        if id == MSV_AV_EOL {
+            break;
+        }
+        if id == MSV_AV_TIMESTAMP && len == 8 && off + 12 <= target_info.len() {
+            return Some(u64::from_le_bytes(
+                target_info[off + 4..off + 12].try_into().unwrap(),
+            ));
+        }
+        off += 4 + len;
+    }
+    None
+}
+
+/// NTOWFv2(password, user, domain)
+pub fn ntowf_v2(password: &str, user: &str, domain: &str) -> [u8; 16] {
+    let nt_hash = md4(&utf16le(password));
+    let mut identity = user.to_uppercase();
+    identity.push_str(domain);
+    hmac_md5(&nt_hash, &utf16le(&identity))
+}
+
+/// 构造AUTHENTICATE_MESSAGE
+///
+/// ## 参数
+///
+/// - `now`: 当前时间（FILETIME格式），服务端没有提供时间戳时使用
+/// - `client_challenge`: 客户端随机数
+pub fn authenticate_message(
+    challenge: &NtlmChallenge,
+    user: &str,
+    password: &str,
+    domain: &str,
+    now: u64,
+    client_challenge: [u8; 8],
+) -> Vec<u8> {
+    let response_key = ntowf_v2(password, user, domain);
+    let timestamp = find_timestamp(&challenge.target_info);
+
+    // NTLMv2_CLIENT_CHALLENGE
+    let mut temp = Vec::with_capacity(32 + challenge.target_info.len());
+    temp.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 0]);
+    temp.extend_from_slice(&timestamp.unwrap_or(now).to_le_bytes());
+    temp.extend_from_slice(&client_challenge);
+    temp.extend_from_slice(&[0u8; 4]);
+    temp.extend_from_slice(&challenge.target_info);
+    temp.extend_from_slice(&[0u8; 4]);
+
+    let mut proof_input = Vec::with_capacity(8 + temp.len());
+    proof_input.extend_from_slice(&challenge.server_challenge);
+    proof_input.extend_from_slice(&temp);
+    let nt_proof = hmac_md5(&response_key, &proof_input);
+
+    let mut nt_response = Vec::with_capacity(16 + temp.len());
+    nt_response.extend_from_slice(&nt_proof);
+    nt_response.extend_from_slice(&temp);
+
+    // 服务端提供了时间戳时，LMv2响应必须为全0
+    let lm_response = if timestamp.is_some() {
+        [0u8; 24].to_vec()
+    } else {
+        let mut input = [0u8; 16];
+        input[..8].copy_from_slice(&challenge.server_challenge);
+        input[8..].copy_from_slice(&client_challenge);
+        let mut r = hmac_md5(&response_key, &input).to_vec();
+        r.extend_from_slice(&client_challenge);
+        r
+    };
+
+    let domain = utf16le(domain);
+    let user = utf16le(user);
+    let workstation = utf16le("DRAGONOS");
+
+    // 不携带Version与MIC字段，因此载荷从64字节处开始
+    const HEADER_LEN: usize = 64;
+    let payloads: [&[u8]; 6] = [
+        &lm_response,
+        &nt_response,
+        &domain,
+        &user,
+        &workstation,
+        &[],
+    ];
+
+    let mut msg = Vec::with_capacity(HEADER_LEN + payloads.iter().map(|p| p.len()).sum::<usize>());
+    msg.extend_from_slice(NTLMSSP_SIGNATURE);
+    msg.extend_from_slice(&3u32.to_le_bytes());
+    let mut off = HEADER_LEN;
+    for p in payloads.iter() {
+        msg.extend_from_slice(&(p.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(p.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(off as u32).to_le_bytes());
+        off += p.len();
+    }
+    msg.extend_from_slice(&(challenge.flags & NTLM_NEGOTIATE_FLAGS).to_le_bytes());
+    for p in payloads.iter() {
+        msg.extend_from_slice(p);
+    }
+    msg
+}
@@ -0,0 +1,281 @@
+//! SMB2 协议的报文格式与常量
+//!
+//! 参考: [MS-SMB2] Server Message Block (SMB) Protocol Versions 2 and 3
+
+use alloc::{string::String, vec::Vec};
+use system_error::SystemError;
+
+use crate::time::PosixTimeSpec;
+
+/// SMB2报文头的长度
+pub const SMB2_HEADER_SIZE: usize = 64;
+/// SMB2报文头的协议标识: 0xFE 'S' 'M' 'B'
+pub const SMB2_PROTOCOL_ID: [u8; 4] = [0xfe, b'S', b'M', b'B'];
+
+/// 报文头Flags: 服务端发出的响应
+pub const SMB2_FLAGS_SERVER_TO_REDIR: u32 = 0x0000_0001;
+/// 报文头Flags: 异步报文
+pub const SMB2_FLAGS_ASYNC_COMMAND: u32 = 0x0000_0002;
+
+/// NEGOTIATE请求中的SecurityMode: 支持签名
+pub const SMB2_NEGOTIATE_SIGNING_ENABLED: u16 = 0x0001;
+/// NEGOTIATE响应中的SecurityMode: 服务端要求签名
+pub const SMB2_NEGOTIATE_SIGNING_REQUIRED: u16 = 0x0002;
+
+/// 客户端支持的方言: SMB 2.0.2与SMB 2.1（都不要求加密与预认证完整性校验）
+pub const SMB2_DIALECTS: [u16; 2] = [0x0202, 0x0210];
+
+/// 单次READ/WRITE的最大长度（SMB 2.0.2不支持multi-credit，上限为64KiB）
+pub const SMB2_MAX_IO_SIZE: u32 = 65536;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Smb2Command {
+    Negotiate = 0x0000,
+    SessionSetup = 0x0001,
+    Logoff = 0x0002,
+    TreeConnect = 0x0003,
+    TreeDisconnect = 0x0004,
+    Create = 0x0005,
+    Close = 0x0006,
+    Flush = 0x0007,
+    Read = 0x0008,
+    Write = 0x0009,
+    QueryDirectory = 0x000e,
+    SetInfo = 0x0011,
+}
+
+/// NT状态码
+///
+/// 参考: [MS-ERREF] 2.3.1 NTSTATUS Values
+pub mod status {
+    pub const STATUS_SUCCESS: u32 = 0x0000_0000;
+    pub const STATUS_PENDING: u32 = 0x0000_0103;
+    pub const STATUS_NO_MORE_FILES: u32 = 0x8000_0006;
+    pub const STATUS_INVALID_PARAMETER: u32 = 0xc000_000d;
+    pub const STATUS_NO_SUCH_FILE: u32 = 0xc000_000f;
+    pub const STATUS_END_OF_FILE: u32 = 0xc000_0011;
+    pub const STATUS_MORE_PROCESSING_REQUIRED: u32 = 0xc000_0016;
+    pub const STATUS_ACCESS_DENIED: u32 = 0xc000_0022;
+    pub const STATUS_OBJECT_NAME_INVALID: u32 = 0xc000_0033;
+    pub const STATUS_OBJECT_NAME_NOT_FOUND: u32 = 0xc000_0034;
+    pub const STATUS_OBJECT_NAME_COLLISION: u32 = 0xc000_0035;
+    pub const STATUS_OBJECT_PATH_NOT_FOUND: u32 = 0xc000_003a;
+    pub const STATUS_SHARING_VIOLATION: u32 = 0xc000_0043;
+    pub const STATUS_DELETE_PENDING: u32 = 0xc000_0056;
+    pub const STATUS_LOGON_FAILURE: u32 = 0xc000_006d;
+    pub const STATUS_DISK_FULL: u32 = 0xc000_007f;
+    pub const STATUS_FILE_IS_A_DIRECTORY: u32 = 0xc000_00ba;
+    pub const STATUS_NOT_SUPPORTED: u32 = 0xc000_00bb;
+    pub const STATUS_BAD_NETWORK_NAME: u32 = 0xc000_00cc;
+    pub const STATUS_DIRECTORY_NOT_EMPTY: u32 = 0xc000_0101;
+    pub const STATUS_NOT_A_DIRECTORY: u32 = 0xc000_0103;
+}
+
+/// 把NT状态码转换为错误码
+pub fn status_to_errno(status: u32) -> SystemError {
+    use status::*;
+    match status {
+        STATUS_NO_SUCH_FILE
+        | STATUS_OBJECT_NAME_NOT_FOUND
+        | STATUS_OBJECT_PATH_NOT_FOUND
+        | STATUS_BAD_NETWORK_NAME
+        | STATUS_DELETE_PENDING => SystemError::ENOENT,
+        STATUS_ACCESS_DENIED | STATUS_LOGON_FAILURE => SystemError::EACCES,
+        STATUS_OBJECT_NAME_COLLISION => SystemError::EEXIST,
+        STATUS_OBJECT_NAME_INVALID | STATUS_INVALID_PARAMETER => SystemError::EINVAL,
+        STATUS_NOT_A_DIRECTORY => SystemError::ENOTDIR,
+        STATUS_FILE_IS_A_DIRECTORY => SystemError::EISDIR,
+        STATUS_DIRECTORY_NOT_EMPTY => SystemError::ENOTEMPTY,
+        STATUS_SHARING_VIOLATION => SystemError::EBUSY,
+        STATUS_DISK_FULL => SystemError::ENOSPC,
+        STATUS_NOT_SUPPORTED => SystemError::EOPNOTSUPP_OR_ENOTSUP,
+        _ => SystemError::EIO,
+    }
+}
+
+/// 文件属性: 目录
+pub const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x0000_0010;
+
+/// CREATE请求中的DesiredAccess
+pub mod access {
+    pub const FILE_READ_DATA: u32 = 0x0000_0001;
+    pub const FILE_WRITE_DATA: u32 = 0x0000_0002;
+    pub const FILE_APPEND_DATA: u32 = 0x0000_0004;
+    pub const FILE_READ_ATTRIBUTES: u32 = 0x0000_0080;
+    pub const FILE_WRITE_ATTRIBUTES: u32 = 0x0000_0100;
+    pub const DELETE: u32 = 0x0001_0000;
+    pub const SYNCHRONIZE: u32 = 0x0010_0000;
+    pub const FILE_LIST_DIRECTORY: u32 = FILE_READ_DATA;
+}
+
+/// CREATE请求中的CreateDisposition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum CreateDisposition {
+    /// 文件存在则打开，否则失败
+    Open = 1,
+    /// 文件存在则失败，否则创建
+    Create = 2,
+}
+
+/// CREATE请求中的CreateOptions
+pub mod create_options {
+    pub const FILE_DIRECTORY_FILE: u32 = 0x0000_0001;
+    pub const FILE_NON_DIRECTORY_FILE: u32 = 0x0000_0040;
+    pub const FILE_DELETE_ON_CLOSE: u32 = 0x0000_1000;
+}
+
+/// 共享模式: 允许其他打开者读、写、删除
+pub const FILE_SHARE_ALL: u32 = 0x0000_0007;
+/// 模拟级别: Impersonation
+pub const SMB2_IMPERSONATION_LEVEL: u32 = 2;
+
+/// QUERY_DIRECTORY的信息类别: FileDirectoryInformation
+pub const FILE_DIRECTORY_INFORMATION: u8 = 0x01;
+/// QUERY_DIRECTORY的标志: 从头开始列举
+pub const SMB2_RESTART_SCANS: u8 = 0x01;
+
+/// 服务端分配的文件句柄
+pub type SmbFileId = [u8; 16];
+
+/// SMB2报文头中客户端关心的字段
+#[derive(Debug, Clone, Copy)]
+pub struct Smb2Header {
+    pub status: u32,
+    pub command: u16,
+    pub flags: u32,
+    pub message_id: u64,
+    pub tree_id: u32,
+    pub session_id: u64,
+}
+
+impl Smb2Header {
+    /// 把报文头序列化到`buf`的末尾
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&SMB2_PROTOCOL_ID);
+        buf.extend_from_slice(&(SMB2_HEADER_SIZE as u16).to_le_bytes());
+        // CreditCharge
+        buf.extend_from_slice(&1u16.to_le_bytes());
+        buf.extend_from_slice(&self.status.to_le_bytes());
+        buf.extend_from_slice(&self.command.to_le_bytes());
+        // CreditRequest
+        buf.extend_from_slice(&1u16.to_le_bytes());
+        buf.extend_from_slice(&self.flags.to_le_bytes());
+        // NextCommand
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&self.message_id.to_le_bytes());
+        // Reserved(ProcessId)
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&self.tree_id.to_le_bytes());
+        buf.extend_from_slice(&self.session_id.to_le_bytes());
+        // Signature
+        buf.extend_from_slice(&[0u8; 16]);
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, SystemError> {
+        if buf.len() < SMB2_HEADER_SIZE
+            || buf[0..4] != SMB2_PROTOCOL_ID
+            || le16(buf, 4) as usize != SMB2_HEADER_SIZE
+        {
+            return Err(SystemError::EPROTO);
+        }
+        Ok(Self {
+            status: le32(buf, 8),
+            command: le16(buf, 12),
+            flags: le32(buf, 16),
+            message_id: le64(buf, 24),
+            tree_id: le32(buf, 36),
+            session_id: le64(buf, 40),
+        })
+    }
+}
+
+/// 目录项
+#[derive(Debug, Clone)]
+pub struct SmbDirEntry {
+    pub name: String,
+    pub info: SmbFileInfo,
+}
+
+/// 服务端返回的文件属性
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmbFileInfo {
+    pub btime: PosixTimeSpec,
+    pub atime: PosixTimeSpec,
+    pub mtime: PosixTimeSpec,
+    pub ctime: PosixTimeSpec,
+    pub size: u64,
+    pub attributes: u32,
+}
+
+impl SmbFileInfo {
+    pub fn is_dir(&self) -> bool {
+        self.attributes & FILE_ATTRIBUTE_DIRECTORY != 0
+    }
+
+    /// 从FILE_DIRECTORY_INFORMATION或CREATE响应中解析
+    ///
+    /// ## 参数
+    ///
+    /// - `times`: CreationTime、LastAccessTime、LastWriteTime、ChangeTime依次所在的偏移量
+    pub fn parse(buf: &[u8], times: [usize; 4], eof: usize, attrs: usize) -> Self {
+        Self {
+            btime: filetime_to_timespec(le64(buf, times[0])),
+            atime: filetime_to_timespec(le64(buf, times[1])),
+            mtime: filetime_to_timespec(le64(buf, times[2])),
+            ctime: filetime_to_timespec(le64(buf, times[3])),
+            size: le64(buf, eof),
+            attributes: le32(buf, attrs),
+        }
+    }
+}
+
+/// 1601-01-01与1970-01-01之间相差的秒数
+const FILETIME_UNIX_EPOCH_DIFF: i64 = 11_644_473_600;
+/// FILETIME的单位是100纳秒
+const FILETIME_TICKS_PER_SEC: i64 = 10_000_000;
+
+/// 把FILETIME转换为Unix时间
+pub fn filetime_to_timespec(filetime: u64) -> PosixTimeSpec {
+    let ticks = filetime as i64;
+    PosixTimeSpec::new(
+        ticks / FILETIME_TICKS_PER_SEC - FILETIME_UNIX_EPOCH_DIFF,
+        (ticks % FILETIME_TICKS_PER_SEC) * 100,
+    )
+}
+
+/// 把Unix时间转换为FILETIME
+pub fn timespec_to_filetime(ts: PosixTimeSpec) -> u64 {
+    ((ts.tv_sec + FILETIME_UNIX_EPOCH_DIFF) * FILETIME_TICKS_PER_SEC + ts.tv_nsec / 100) as u64
+}
+
+#[inline]
+pub fn le16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+#[inline]
+pub fn le32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+#[inline]
+pub fn le64(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off + 8].try_into().unwrap())
+}
+
+/// 把字符串编码为UTF-16LE
+pub fn utf16le(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+/// 解码UTF-16LE字符串
+pub fn from_utf16le(buf: &[u8]) -> Result<String, SystemError> {
+    let units = buf
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]));
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map_err(|_| SystemError::EILSEQ)
+}
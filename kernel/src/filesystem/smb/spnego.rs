@@ -0,0 +1,113 @@
+//! SPNEGO封装
+//!
+//! SESSION_SETUP中的安全令牌是GSS-API令牌，这里只实现了承载NTLMSSP所需的最小子集。
+//!
+//! 参考: RFC 4178
+
+use alloc::vec::Vec;
+use system_error::SystemError;
+
+/// SPNEGO的OID: 1.3.6.1.5.5.2
+const SPNEGO_OID: [u8; 6] = [0x2b, 0x06, 0x01, 0x05, 0x05, 0x02];
+/// NTLMSSP的OID: 1.3.6.1.4.1.311.2.2.10
+const NTLMSSP_OID: [u8; 10] = [0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0a];
+
+const DER_OCTET_STRING: u8 = 0x04;
+const DER_OID: u8 = 0x06;
+const DER_SEQUENCE: u8 = 0x30;
+const DER_APPLICATION_0: u8 = 0x60;
+
+/// 上下文相关标签[n]
+const fn der_context(n: u8) -> u8 {
+    0xa0 | n
+}
+
+/// 以DER编码输出一个TLV
+fn der_push(out: &mut Vec<u8>, tag: u8, content: &[u8]) {
+    out.push(tag);
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else if len <= 0xff {
+        out.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+    }
+    out.extend_from_slice(content);
+}
+
+fn der_wrap(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len() + 4);
+    der_push(&mut out, tag, content);
+    out
+}
+
+/// 读取`buf[*off..]`处的一个TLV，返回其标签与内容，并把`off`移动到下一个TLV
+fn der_read<'a>(buf: &'a [u8], off: &mut usize) -> Result<(u8, &'a [u8]), SystemError> {
+    let tag = *buf.get(*off).ok_or(SystemError::EPROTO)?;
+    let first = *buf.get(*off + 1).ok_or(SystemError::EPROTO)? as usize;
+    let mut pos = *off + 2;
+    let len = if first < 0x80 {
+        first
+    } else {
+        let n = first & 0x7f;
+        if n == 0 || n > 2 {
+            return Err(SystemError::EPROTO);
+        }
+        let bytes = buf.get(pos..pos + n).ok_or(SystemError::EPROTO)?;
+        pos += n;
+        bytes.iter().fold(0, |acc, &b| (acc << 8) | b as usize)
+    };
+    let content = buf.get(pos..pos + len).ok_or(SystemError::EPROTO)?;
+    *off = pos + len;
+    Ok((tag, content))
+}
+
+/// 把NTLM的NEGOTIATE_MESSAGE封装为NegTokenInit
+pub fn wrap_init(ntlm_token: &[u8]) -> Vec<u8> {
+    let mech_types = der_wrap(DER_SEQUENCE, &der_wrap(DER_OID, &NTLMSSP_OID));
+
+    let mut init = der_wrap(der_context(0), &mech_types);
+    init.extend(der_wrap(
+        der_context(2),
+        &der_wrap(DER_OCTET_STRING, ntlm_token),
+    ));
+
+    let mut body = der_wrap(DER_OID, &SPNEGO_OID);
+    body.extend(der_wrap(der_context(0), &der_wrap(DER_SEQUENCE, &init)));
+    der_wrap(DER_APPLICATION_0, &body)
+}
+
+/// 把NTLM的AUTHENTICATE_MESSAGE封装为NegTokenResp
+pub fn wrap_response(ntlm_token: &[u8]) -> Vec<u8> {
+    let resp = der_wrap(der_context(2), &der_wrap(DER_OCTET_STRING, ntlm_token));
+    der_wrap(der_context(1), &der_wrap(DER_SEQUENCE, &resp))
+}
+
+/// 从服务端的NegTokenResp中取出responseToken
+pub fn unwrap_response(token: &[u8]) -> Result<&[u8], SystemError> {
+    let mut off = 0;
+    let (tag, resp) = der_read(token, &mut off)?;
+    if tag != der_context(1) {
+        return Err(SystemError::EPROTO);
+    }
+    off = 0;
+    let (tag, seq) = der_read(resp, &mut off)?;
+    if tag != DER_SEQUENCE {
+        return Err(SystemError::EPROTO);
+    }
+
+    off = 0;
+    while off < seq.len() {
+        let (tag, field) = der_read(seq, &mut off)?;
+        if tag == der_context(2) {
+            let mut inner = 0;
+            let (tag, data) = der_read(field, &mut inner)?;
+            if tag != DER_OCTET_STRING {
+                return Err(SystemError::EPROTO);
+            }
+            return Ok(data);
+        }
+    }
+    Err(SystemError::EPROTO)
+}
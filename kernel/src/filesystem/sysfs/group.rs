@@ -131,7 +131,7 @@ impl SysFS {
         kobj: &Arc<dyn KObject>,
         group: &'static dyn AttributeGroup,
     ) -> Result<(), SystemError> {
-        let inode = kobj.inode().unwrap();
+        let inode = kobj.inode().ok_or(SystemError::ENOENT)?;
         let parent_inode: Arc<KernFSInode>;
         if let Some(name) = group.name() {
             parent_inode = inode
@@ -148,7 +148,7 @@ impl SysFS {
         self.group_remove_files(&parent_inode, group);
 
         if group.name().is_some() {
-            parent_inode.remove_recursive();
+            parent_inode.remove_inode_include_self();
         }
 
         return Ok(());
@@ -214,7 +214,12 @@ impl SysFS {
         return Ok(());
     }
 
-    fn group_remove_files(&self, _parent: &Arc<KernFSInode>, _group: &'static dyn AttributeGroup) {
-        todo!("group_remove_files")
+    /// 删除属性组的文件
+    ///
+    /// https://code.dragonos.org.cn/xref/linux-6.1.9/fs/sysfs/group.c#21
+    fn group_remove_files(&self, parent: &Arc<KernFSInode>, group: &'static dyn AttributeGroup) {
+        for attr in group.attrs() {
+            parent.remove(attr.name()).ok();
+        }
     }
 }
@@ -38,8 +38,11 @@ impl SysFS {
     ///
     ///
     /// 参考：https://code.dragonos.org.cn/xref/linux-6.1.9/fs/sysfs/symlink.c#143
-    pub fn remove_link(&self, _kobj: &Arc<dyn KObject>, _name: String) {
-        todo!("sysfs remove link")
+    pub fn remove_link(&self, kobj: &Arc<dyn KObject>, name: String) {
+        if let Some(parent) = kobj.inode() {
+            // 符号链接不存在时，与Linux一样直接忽略
+            parent.remove(&name).ok();
+        }
     }
 
     fn do_create_link(
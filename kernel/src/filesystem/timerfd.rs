@@ -0,0 +1,429 @@
+//! timerfd：通过文件描述符通知定时器到期
+//!
+//! 定时器每到期一次，计数加一。读取timerfd得到自上次读取以来的到期次数，并将计数清零。
+//! 计数不为0时timerfd可读，因此可以和其他文件一起用epoll等待。
+//!
+//! 参考 https://man7.org/linux/man-pages/man2/timerfd_create.2.html
+
+use crate::filesystem::vfs::file::{File, FileMode};
+use crate::filesystem::vfs::syscall::ModeType;
+use crate::filesystem::vfs::{FilePrivateData, FileSystem, FileType, IndexNode, Metadata};
+use crate::libs::spinlock::{SpinLock, SpinLockGuard};
+use crate::libs::wait_queue::WaitQueue;
+use crate::net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll};
+use crate::process::{ProcessFlags, ProcessManager};
+use crate::syscall::user_access::{UserBufferReader, UserBufferWriter};
+use crate::syscall::Syscall;
+use crate::time::syscall::PosixClockID;
+use crate::time::timekeep::{ktime_t, ktime_to_timespec};
+use crate::time::timekeeping::{ktime_get, ktime_get_boottime, ktime_get_real};
+use crate::time::timer::{Jiffies, Timer, TimerFunction};
+use crate::time::PosixTimeSpec;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::mem::size_of;
+use core::time::Duration;
+use system_error::SystemError;
+
+bitflags! {
+    pub struct TimerFdFlags: u32 {
+        /// Set the close-on-exec (FD_CLOEXEC) flag on the new file
+        /// descriptor
+        const TFD_CLOEXEC = 0o2000000;
+        /// Set the O_NONBLOCK file status flag on the open file
+        /// description referred to by the new file descriptor
+        const TFD_NONBLOCK = 0o0004000;
+    }
+
+    pub struct TimerFdSetFlags: u32 {
+        /// `new_value.it_value`为时钟上的绝对时间
+        const TFD_TIMER_ABSTIME = 1 << 0;
+        /// 墙上时间被修改时取消定时器，暂不支持，仅接受这个标志
+        const TFD_TIMER_CANCEL_ON_SET = 1 << 1;
+    }
+}
+
+/// 与Linux的`struct itimerspec`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixItimerspec {
+    /// 周期，为0时定时器只到期一次
+    pub it_interval: PosixTimeSpec,
+    /// 距离下一次到期的时间，为0时定时器停止
+    pub it_value: PosixTimeSpec,
+}
+
+#[derive(Debug, Default)]
+struct TimerFdState {
+    /// 下一次到期的时刻（所在时钟上的纳秒数），定时器停止时为None
+    expires: Option<ktime_t>,
+    /// 周期（纳秒），为0时定时器只到期一次
+    interval: ktime_t,
+    /// 尚未被读取的到期次数
+    ticks: u64,
+    /// 每次设置定时器时加一，用来忽略已经被替换的定时器
+    generation: u64,
+    timer: Option<Arc<Timer>>,
+}
+
+#[derive(Debug)]
+pub struct TimerFdInode {
+    clock: PosixClockID,
+    flags: TimerFdFlags,
+    state: SpinLock<TimerFdState>,
+    wait_queue: WaitQueue,
+    epitems: EPollItems,
+    self_ref: Weak<TimerFdInode>,
+}
+
+/// timerfd的定时器到期时执行的函数
+#[derive(Debug)]
+struct TimerFdTimerFunc {
+    inode: Weak<TimerFdInode>,
+    generation: u64,
+}
+
+impl TimerFunction for TimerFdTimerFunc {
+    fn run(&mut self) -> Result<(), SystemError> {
+        if let Some(inode) = self.inode.upgrade() {
+            inode.expire(self.generation);
+        }
+        return Ok(());
+    }
+}
+
+fn timespec_to_ns(ts: &PosixTimeSpec) -> ktime_t {
+    ts.tv_sec * 1_000_000_000 + ts.tv_nsec
+}
+
+fn valid_timespec(ts: &PosixTimeSpec) -> bool {
+    ts.tv_sec >= 0 && ts.tv_nsec >= 0 && ts.tv_nsec < 1_000_000_000
+}
+
+impl TimerFdInode {
+    pub fn new(clock: PosixClockID, flags: TimerFdFlags) -> Arc<Self> {
+        Arc::new_cyclic(|self_ref| TimerFdInode {
+            clock,
+            flags,
+            state: SpinLock::new(TimerFdState::default()),
+            wait_queue: WaitQueue::default(),
+            epitems: EPollItems::new(),
+            self_ref: self_ref.clone(),
+        })
+    }
+
+    /// 定时器所在时钟的当前时间（纳秒）
+    fn now(&self) -> ktime_t {
+        match self.clock {
+            PosixClockID::Realtime => ktime_get_real(),
+            PosixClockID::Boottime => ktime_get_boottime(),
+            _ => ktime_get(),
+        }
+    }
+
+    fn readable(&self) -> bool {
+        self.state.lock_irqsave().ticks > 0
+    }
+
+    /// 在`expires`时刻触发定时器。调用者应当已经把旧的定时器取走
+    fn arm(&self, state: &mut TimerFdState, expires: ktime_t) {
+        let delay = (expires - self.now()).max(0) as u64;
+        let timer = Timer::new(
+            Box::new(TimerFdTimerFunc {
+                inode: self.self_ref.clone(),
+                generation: state.generation,
+            }),
+            <Jiffies as From<Duration>>::from(Duration::from_nanos(delay)).timer_jiffies(),
+        );
+        timer.activate();
+        state.expires = Some(expires);
+        state.timer = Some(timer);
+    }
+
+    /// 定时器到期。jiffies的精度比时钟低，提前到期时重新等待剩余的时间
+    fn expire(&self, generation: u64) {
+        let mut state = self.state.lock_irqsave();
+        if state.generation != generation {
+            return;
+        }
+        let Some(expires) = state.expires else {
+            return;
+        };
+        let now = self.now();
+        if now < expires {
+            self.arm(&mut state, expires);
+            return;
+        }
+
+        if state.interval > 0 {
+            // 错过的周期也计入到期次数
+            let overrun = ((now - expires) / state.interval + 1) as u64;
+            state.ticks += overrun;
+            let next = expires + overrun as ktime_t * state.interval;
+            self.arm(&mut state, next);
+        } else {
+            state.ticks += 1;
+            state.expires = None;
+            state.timer = None;
+        }
+        drop(state);
+
+        self.wait_queue.wakeup_all(None);
+        self.epitems
+            .wakeup(Some(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM))
+            .ok();
+    }
+
+    /// 当前的设置，`it_value`为距离下一次到期的剩余时间
+    fn get(&self, state: &TimerFdState) -> PosixItimerspec {
+        let remain = state
+            .expires
+            .map(|expires| (expires - self.now()).max(1))
+            .unwrap_or(0);
+        PosixItimerspec {
+            it_interval: ktime_to_timespec(state.interval),
+            it_value: ktime_to_timespec(remain),
+        }
+    }
+
+    /// 设置定时器，返回之前的设置
+    fn set(&self, new: &PosixItimerspec, flags: TimerFdSetFlags) -> PosixItimerspec {
+        let mut state = self.state.lock_irqsave();
+        let old = self.get(&state);
+        let old_timer = state.timer.take();
+        state.generation += 1;
+        state.ticks = 0;
+        state.expires = None;
+        state.interval = timespec_to_ns(&new.it_interval);
+
+        let value = timespec_to_ns(&new.it_value);
+        if value != 0 {
+            let expires = if flags.contains(TimerFdSetFlags::TFD_TIMER_ABSTIME) {
+                value
+            } else {
+                self.now() + value
+            };
+            self.arm(&mut state, expires);
+        }
+        drop(state);
+
+        if let Some(timer) = old_timer {
+            timer.cancel();
+        }
+        old
+    }
+}
+
+impl IndexNode for TimerFdInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        let timer = self.state.lock_irqsave().timer.take();
+        if let Some(timer) = timer {
+            timer.cancel();
+        }
+        Ok(())
+    }
+
+    /// # 读取定时器的到期次数
+    ///
+    /// - 返回一个 8 字节的到期次数，并将计数清零
+    /// - 计数为0时，TFD_NONBLOCK 被设置则以 EAGAIN 失败，否则阻塞直到定时器到期
+    fn read_at(
+        &self,
+        _offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        data_guard: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        drop(data_guard);
+        if len < 8 {
+            return Err(SystemError::EINVAL);
+        }
+        loop {
+            let mut state = self.state.lock_irqsave();
+            if state.ticks > 0 {
+                let ticks = core::mem::take(&mut state.ticks);
+                drop(state);
+                buf[..8].copy_from_slice(&ticks.to_ne_bytes());
+                return Ok(8);
+            }
+            drop(state);
+
+            if self.flags.contains(TimerFdFlags::TFD_NONBLOCK) {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            let r = wq_wait_event_interruptible!(self.wait_queue, self.readable(), {});
+            if r.is_err() {
+                ProcessManager::current_pcb()
+                    .flags()
+                    .insert(ProcessFlags::HAS_PENDING_SIGNAL);
+                return Err(SystemError::ERESTARTSYS);
+            }
+        }
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    /// 到期次数不为0时可读
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        let mut events = EPollEventType::empty();
+        if self.readable() {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        return Ok(events.bits() as usize);
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn resize(&self, _len: usize) -> Result<(), SystemError> {
+        Ok(())
+    }
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.add(epitem);
+        Ok(())
+    }
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.remove(epoll)
+    }
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        panic!("TimerFd does not have a filesystem")
+    }
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+}
+
+/// 获取fd对应的timerfd
+fn timerfd_get(fd: i32) -> Result<Arc<dyn IndexNode>, SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    let inode = file.inode();
+    if inode.as_any_ref().downcast_ref::<TimerFdInode>().is_none() {
+        return Err(SystemError::EINVAL);
+    }
+    Ok(inode)
+}
+
+impl Syscall {
+    /// # 创建一个 timerfd
+    ///
+    /// ## 参数
+    /// - `clockid`: 定时器使用的时钟，支持 CLOCK_REALTIME、CLOCK_MONOTONIC 和 CLOCK_BOOTTIME
+    /// - `flags`: TFD_CLOEXEC 和 TFD_NONBLOCK 的组合
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 成功创建的文件描述符
+    /// - `Err(SystemError)`: 创建失败
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/timerfd_create.2.html
+    pub fn sys_timerfd_create(clockid: i32, flags: u32) -> Result<usize, SystemError> {
+        let flags = TimerFdFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let clock = PosixClockID::try_from(clockid)?;
+        if !matches!(
+            clock,
+            PosixClockID::Realtime | PosixClockID::Monotonic | PosixClockID::Boottime
+        ) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let inode = TimerFdInode::new(clock, flags);
+        let mut filemode = FileMode::O_RDONLY;
+        if flags.contains(TimerFdFlags::TFD_CLOEXEC) {
+            filemode |= FileMode::O_CLOEXEC;
+        }
+        if flags.contains(TimerFdFlags::TFD_NONBLOCK) {
+            filemode |= FileMode::O_NONBLOCK;
+        }
+        let file = File::new(inode, filemode)?;
+        let binding = ProcessManager::current_pcb().fd_table();
+        let mut fd_table_guard = binding.write();
+        let fd = fd_table_guard.alloc_fd(file, None).map(|x| x as usize);
+        return fd;
+    }
+
+    /// # 启动或者停止 timerfd 的定时器
+    ///
+    /// ## 参数
+    /// - `fd`: timerfd
+    /// - `flags`: 设置了 TFD_TIMER_ABSTIME 时，`new_value.it_value` 为绝对时间
+    /// - `new_value`: 新的设置，`it_value` 为0时停止定时器
+    /// - `old_value`: 用于返回之前的设置，可以为空
+    pub fn sys_timerfd_settime(
+        fd: i32,
+        flags: u32,
+        new_value: *const PosixItimerspec,
+        old_value: *mut PosixItimerspec,
+    ) -> Result<usize, SystemError> {
+        let flags = TimerFdSetFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+        let new = *UserBufferReader::new(new_value, size_of::<PosixItimerspec>(), true)?
+            .read_one_from_user::<PosixItimerspec>(0)?;
+        if !valid_timespec(&new.it_interval) || !valid_timespec(&new.it_value) {
+            return Err(SystemError::EINVAL);
+        }
+
+        let inode = timerfd_get(fd)?;
+        let timerfd = inode.as_any_ref().downcast_ref::<TimerFdInode>().unwrap();
+        let old = timerfd.set(&new, flags);
+
+        if !old_value.is_null() {
+            UserBufferWriter::new(old_value, size_of::<PosixItimerspec>(), true)?
+                .copy_one_to_user(&old, 0)?;
+        }
+        return Ok(0);
+    }
+
+    /// # 获取 timerfd 的当前设置
+    ///
+    /// `curr_value.it_value` 为距离下一次到期的剩余时间，定时器停止时为0
+    pub fn sys_timerfd_gettime(
+        fd: i32,
+        curr_value: *mut PosixItimerspec,
+    ) -> Result<usize, SystemError> {
+        let inode = timerfd_get(fd)?;
+        let timerfd = inode.as_any_ref().downcast_ref::<TimerFdInode>().unwrap();
+        let curr = timerfd.get(&timerfd.state.lock_irqsave());
+        UserBufferWriter::new(curr_value, size_of::<PosixItimerspec>(), true)?
+            .copy_one_to_user(&curr, 0)?;
+        return Ok(0);
+    }
+}
@@ -0,0 +1,816 @@
+//! tmpfs: 带容量限制的内存文件系统
+//!
+//! 与ramfs不同，tmpfs按页保存文件数据，并对每个挂载点分别统计已使用的页数与inode数，
+//! 超过`size=`/`nr_inodes=`挂载选项给出的上限时返回ENOSPC，避免失控的进程耗尽内存。
+//!
+//! 支持的挂载选项（以逗号分隔）：
+//! - `size=<bytes>[k|m|g|%]`: 容量上限，`%`表示占物理内存的百分比，0表示不限制。默认为物理内存的一半
+//! - `nr_inodes=<n>[k|m|g]`: inode数量上限，0表示不限制。默认为物理内存页数的一半
+//! - `mode=<octal>`: 根目录的权限
+
+use core::{
+    any::Any,
+    intrinsics::unlikely,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use system_error::SystemError;
+
+use crate::{
+    arch::{mm::LockedFrameAllocator, MMArch},
+    driver::base::device::device_number::DeviceNumber,
+    ipc::pipe::LockedPipeInode,
+    libs::{
+        casting::DowncastArc,
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    mm::{allocator::page_frame::FrameAllocator, MemoryManagementArch},
+    time::PosixTimeSpec,
+};
+
+use super::vfs::{
+    core::generate_inode_id, file::FilePrivateData, syscall::ModeType, utils::DName, FileSystem,
+    FileSystemMaker, FileSystemMakerData, FileType, FsInfo, IndexNode, InodeId, Magic, Metadata,
+    SpecialNodeData, SuperBlock, FSMAKER,
+};
+
+/// tmpfs的inode名称的最大长度
+const TMPFS_MAX_NAMELEN: usize = 255;
+const TMPFS_PAGE_SIZE: usize = MMArch::PAGE_SIZE;
+
+/// tmpfs的挂载选项
+#[derive(Debug, Default)]
+pub struct TmpfsMountData {
+    /// 容量上限（字节）
+    size: Option<usize>,
+    /// 容量上限（占物理内存的百分比）
+    size_percent: Option<usize>,
+    nr_inodes: Option<usize>,
+    mode: Option<ModeType>,
+}
+
+impl TmpfsMountData {
+    pub fn from_row(raw_data: *const u8) -> Result<Self, SystemError> {
+        if raw_data.is_null() {
+            return Err(SystemError::EINVAL);
+        }
+        let len = (0..)
+            .find(|&i| unsafe { raw_data.add(i).read() } == 0)
+            .ok_or(SystemError::EINVAL)?;
+        let slice = unsafe { core::slice::from_raw_parts(raw_data, len) };
+        let raw_str = core::str::from_utf8(slice).map_err(|_| SystemError::EINVAL)?;
+
+        let mut data = Self::default();
+        for pair in raw_str.split(',').filter(|s| !s.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or(SystemError::EINVAL)?;
+            match key {
+                "size" => {
+                    if let Some(percent) = value.strip_suffix('%') {
+                        let percent: usize = percent.parse().map_err(|_| SystemError::EINVAL)?;
+                        if percent > 100 {
+                            return Err(SystemError::EINVAL);
+                        }
+                        data.size_percent = Some(percent);
+                    } else {
+                        data.size = Some(parse_size(value)?);
+                    }
+                }
+                "nr_inodes" => data.nr_inodes = Some(parse_size(value)?),
+                "mode" => {
+                    let mode = u32::from_str_radix(value, 8).map_err(|_| SystemError::EINVAL)?;
+                    data.mode = Some(ModeType::from_bits_truncate(mode) & ModeType::S_IALLUGO);
+                }
+                _ => return Err(SystemError::EINVAL),
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl FileSystemMakerData for TmpfsMountData {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// 解析带有k/m/g后缀的数值
+fn parse_size(value: &str) -> Result<usize, SystemError> {
+    let (num, shift) = match value.as_bytes().last() {
+        Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+        Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+        Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let num: usize = num.parse().map_err(|_| SystemError::EINVAL)?;
+    num.checked_mul(1 << shift).ok_or(SystemError::EINVAL)
+}
+
+/// 物理内存的总页数
+fn total_ram_pages() -> usize {
+    unsafe { LockedFrameAllocator.usage() }.total().data()
+}
+
+/// 一种按挂载点统计的资源（页或inode）
+#[derive(Debug)]
+struct TmpfsQuota {
+    /// 上限，0表示不限制
+    max: usize,
+    used: AtomicUsize,
+}
+
+impl TmpfsQuota {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    fn charge(&self, n: usize) -> Result<(), SystemError> {
+        if n == 0 {
+            return Ok(());
+        }
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                let new = used.checked_add(n)?;
+                if self.max != 0 && new > self.max {
+                    None
+                } else {
+                    Some(new)
+                }
+            })
+            .map(|_| ())
+            .map_err(|_| SystemError::ENOSPC)
+    }
+
+    fn uncharge(&self, n: usize) {
+        self.used.fetch_sub(n, Ordering::SeqCst);
+    }
+
+    fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+}
+
+/// tmpfs文件系统结构体
+#[derive(Debug)]
+pub struct Tmpfs {
+    root_inode: Arc<LockedTmpfsInode>,
+    /// 已使用的数据页
+    pages: TmpfsQuota,
+    /// 已使用的inode
+    inodes: TmpfsQuota,
+}
+
+/// tmpfs的inode结构体
+#[derive(Debug)]
+pub struct LockedTmpfsInode(pub SpinLock<TmpfsInode>);
+
+/// tmpfs的inode结构体(不包含锁)
+#[derive(Debug)]
+pub struct TmpfsInode {
+    /// 指向父Inode的弱引用（只对目录有意义）
+    parent: Weak<LockedTmpfsInode>,
+    /// 指向自身的弱引用
+    self_ref: Weak<LockedTmpfsInode>,
+    /// 子Inode的B树
+    children: BTreeMap<DName, Arc<LockedTmpfsInode>>,
+    /// 文件数据，以页号为键。没有分配的页是空洞，读出为0
+    pages: BTreeMap<usize, Box<[u8]>>,
+    /// 当前inode的元数据
+    metadata: Metadata,
+    /// 创建时间
+    btime: PosixTimeSpec,
+    /// 指向inode所在的文件系统对象的指针
+    fs: Weak<Tmpfs>,
+    /// 指向特殊节点
+    special_node: Option<SpecialNodeData>,
+    name: DName,
+}
+
+impl TmpfsInode {
+    fn new(
+        parent: Weak<LockedTmpfsInode>,
+        fs: Weak<Tmpfs>,
+        name: DName,
+        file_type: FileType,
+        mode: ModeType,
+        raw_dev: DeviceNumber,
+    ) -> Self {
+        let now = PosixTimeSpec::now();
+        Self {
+            parent,
+            self_ref: Weak::default(),
+            children: BTreeMap::new(),
+            pages: BTreeMap::new(),
+            metadata: Metadata {
+                dev_id: 0,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: TMPFS_PAGE_SIZE,
+                blocks: 0,
+                atime: now,
+                mtime: now,
+                ctime: now,
+                file_type,
+                mode,
+                nlinks: 1,
+                uid: 0,
+                gid: 0,
+                raw_dev,
+            },
+            btime: now,
+            fs,
+            special_node: None,
+            name,
+        }
+    }
+
+    /// 把文件截断或扩展到`len`字节，释放超出部分的页
+    fn set_size(&mut self, len: usize) {
+        let keep = len.div_ceil(TMPFS_PAGE_SIZE);
+        let removed = self.pages.split_off(&keep).len();
+        if removed != 0 {
+            if let Some(fs) = self.fs.upgrade() {
+                fs.pages.uncharge(removed);
+            }
+        }
+        // 把最后一页中超出文件末尾的部分清零，以免以后扩展文件时读到旧数据
+        if len % TMPFS_PAGE_SIZE != 0 {
+            if let Some(page) = self.pages.get_mut(&(len / TMPFS_PAGE_SIZE)) {
+                page[len % TMPFS_PAGE_SIZE..].fill(0);
+            }
+        }
+        self.metadata.size = len as i64;
+    }
+}
+
+impl Drop for TmpfsInode {
+    fn drop(&mut self) {
+        // inode的最后一个引用（目录项与打开的文件）消失时，才归还它占用的资源
+        if let Some(fs) = self.fs.upgrade() {
+            fs.pages.uncharge(self.pages.len());
+            fs.inodes.uncharge(1);
+        }
+    }
+}
+
+impl Tmpfs {
+    pub fn new(data: Option<&TmpfsMountData>) -> Result<Arc<Self>, SystemError> {
+        let default = TmpfsMountData::default();
+        let data = data.unwrap_or(&default);
+        let total_pages = total_ram_pages();
+
+        let max_pages = if let Some(percent) = data.size_percent {
+            total_pages * percent / 100
+        } else if let Some(size) = data.size {
+            size.div_ceil(TMPFS_PAGE_SIZE)
+        } else {
+            total_pages / 2
+        };
+        let max_inodes = data.nr_inodes.unwrap_or(total_pages / 2);
+        let mode = data.mode.unwrap_or(ModeType::from_bits_truncate(0o1777));
+
+        let result = Arc::new_cyclic(|fs: &Weak<Tmpfs>| Tmpfs {
+            root_inode: Arc::new(LockedTmpfsInode(SpinLock::new(TmpfsInode::new(
+                Weak::default(),
+                fs.clone(),
+                DName::default(),
+                FileType::Dir,
+                mode,
+                DeviceNumber::default(),
+            )))),
+            pages: TmpfsQuota::new(max_pages),
+            inodes: TmpfsQuota::new(max_inodes),
+        });
+        result.inodes.charge(1)?;
+
+        let mut root_guard = result.root_inode.0.lock();
+        root_guard.parent = Arc::downgrade(&result.root_inode);
+        root_guard.self_ref = Arc::downgrade(&result.root_inode);
+        drop(root_guard);
+
+        return Ok(result);
+    }
+
+    pub fn make_tmpfs(
+        data: Option<&dyn FileSystemMakerData>,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        let data = data.and_then(|d| d.as_any().downcast_ref::<TmpfsMountData>());
+        let fs = Tmpfs::new(data)?;
+        return Ok(fs);
+    }
+}
+
+#[distributed_slice(FSMAKER)]
+static TMPFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "tmpfs",
+    &(Tmpfs::make_tmpfs
+        as fn(
+            Option<&dyn FileSystemMakerData>,
+        ) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+impl FileSystem for Tmpfs {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        return self.root_inode.clone();
+    }
+
+    fn info(&self) -> FsInfo {
+        return FsInfo {
+            blk_dev_id: 0,
+            max_name_len: TMPFS_MAX_NAMELEN,
+        };
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "tmpfs"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        let mut sb = SuperBlock::new(
+            Magic::TMPFS_MAGIC,
+            TMPFS_PAGE_SIZE as u64,
+            TMPFS_MAX_NAMELEN as u64,
+        );
+        // 不限制容量时，以物理内存的大小作为总容量
+        let max_pages = match self.pages.max {
+            0 => total_ram_pages(),
+            max => max,
+        };
+        sb.blocks = max_pages as u64;
+        sb.bfree = max_pages.saturating_sub(self.pages.used()) as u64;
+        sb.bavail = sb.bfree;
+        if self.inodes.max != 0 {
+            sb.files = self.inodes.max as u64;
+            sb.ffree = self.inodes.max.saturating_sub(self.inodes.used()) as u64;
+        }
+        sb.frsize = TMPFS_PAGE_SIZE as u64;
+        sb
+    }
+
+    fn dcache_enabled(&self) -> bool {
+        true
+    }
+}
+
+impl LockedTmpfsInode {
+    /// 在当前目录下创建一个新的inode，并计入inode配额
+    fn new_child(
+        &self,
+        inode: &mut TmpfsInode,
+        name: DName,
+        file_type: FileType,
+        mode: ModeType,
+        raw_dev: DeviceNumber,
+    ) -> Result<Arc<LockedTmpfsInode>, SystemError> {
+        let fs = inode.fs.upgrade().ok_or(SystemError::ENOENT)?;
+        fs.inodes.charge(1)?;
+
+        let result = Arc::new(LockedTmpfsInode(SpinLock::new(TmpfsInode::new(
+            inode.self_ref.clone(),
+            inode.fs.clone(),
+            name.clone(),
+            file_type,
+            mode,
+            raw_dev,
+        ))));
+        result.0.lock().self_ref = Arc::downgrade(&result);
+
+        if file_type == FileType::Dir {
+            inode.metadata.nlinks += 1;
+        }
+        inode.metadata.mtime = PosixTimeSpec::now();
+        inode.children.insert(name, result.clone());
+        Ok(result)
+    }
+}
+
+impl IndexNode for LockedTmpfsInode {
+    fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EINVAL);
+        }
+        if (inode.metadata.size as usize) > len {
+            inode.set_size(len);
+        }
+        return Ok(());
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &super::vfs::file::FileMode,
+    ) -> Result<(), SystemError> {
+        return Ok(());
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let inode = self.0.lock();
+        if inode.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+
+        let size = inode.metadata.size as usize;
+        let start = size.min(offset);
+        let end = size.min(offset.saturating_add(len));
+
+        let mut pos = start;
+        while pos < end {
+            let page_off = pos % TMPFS_PAGE_SIZE;
+            let n = (TMPFS_PAGE_SIZE - page_off).min(end - pos);
+            let dst = &mut buf[pos - start..pos - start + n];
+            match inode.pages.get(&(pos / TMPFS_PAGE_SIZE)) {
+                Some(page) => dst.copy_from_slice(&page[page_off..page_off + n]),
+                None => dst.fill(0),
+            }
+            pos += n;
+        }
+        return Ok(end - start);
+    }
+
+    fn write_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        if buf.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+        let end = offset.checked_add(len).ok_or(SystemError::EFBIG)?;
+
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+
+        // 先统计需要新分配的页，一次性计入配额
+        let first = offset / TMPFS_PAGE_SIZE;
+        let last = (end - 1) / TMPFS_PAGE_SIZE;
+        let new_pages = (first..=last)
+            .filter(|idx| !inode.pages.contains_key(idx))
+            .count();
+        let fs = inode.fs.upgrade().ok_or(SystemError::ENOENT)?;
+        fs.pages.charge(new_pages)?;
+
+        let mut pos = offset;
+        while pos < end {
+            let page_off = pos % TMPFS_PAGE_SIZE;
+            let n = (TMPFS_PAGE_SIZE - page_off).min(end - pos);
+            let page = inode
+                .pages
+                .entry(pos / TMPFS_PAGE_SIZE)
+                .or_insert_with(|| vec![0u8; TMPFS_PAGE_SIZE].into_boxed_slice());
+            page[page_off..page_off + n].copy_from_slice(&buf[pos - offset..pos - offset + n]);
+            pos += n;
+        }
+
+        if end > inode.metadata.size as usize {
+            inode.metadata.size = end as i64;
+        }
+        inode.metadata.mtime = PosixTimeSpec::now();
+        return Ok(len);
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        return self.0.lock().fs.upgrade().unwrap();
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let inode = self.0.lock();
+        let mut metadata = inode.metadata.clone();
+        metadata.blocks = inode.pages.len() * (TMPFS_PAGE_SIZE / 512);
+        return Ok(metadata);
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        inode.metadata.atime = metadata.atime;
+        inode.metadata.mtime = metadata.mtime;
+        inode.metadata.ctime = metadata.ctime;
+        inode.metadata.mode = metadata.mode;
+        inode.metadata.uid = metadata.uid;
+        inode.metadata.gid = metadata.gid;
+        return Ok(());
+    }
+
+    fn resize(&self, len: usize) -> Result<(), SystemError> {
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type == FileType::File {
+            inode.set_size(len);
+            return Ok(());
+        } else {
+            return Err(SystemError::EINVAL);
+        }
+    }
+
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if name.len() > TMPFS_MAX_NAMELEN {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+        let name = DName::from(name);
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if inode.children.contains_key(&name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        let result = self.new_child(
+            &mut inode,
+            name,
+            file_type,
+            mode,
+            DeviceNumber::from(data as u32),
+        )?;
+        return Ok(result);
+    }
+
+    fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+        let other: &LockedTmpfsInode = other
+            .downcast_ref::<LockedTmpfsInode>()
+            .ok_or(SystemError::EXDEV)?;
+        let name = DName::from(name);
+        let mut inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        let mut other_locked: SpinLockGuard<TmpfsInode> = other.0.lock();
+
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if other_locked.metadata.file_type == FileType::Dir {
+            return Err(SystemError::EISDIR);
+        }
+        if inode.children.contains_key(&name) {
+            return Err(SystemError::EEXIST);
+        }
+
+        inode
+            .children
+            .insert(name, other_locked.self_ref.upgrade().unwrap());
+        other_locked.metadata.nlinks += 1;
+        return Ok(());
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        let mut inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if name == "." || name == ".." {
+            return Err(SystemError::ENOTEMPTY);
+        }
+
+        let name = DName::from(name);
+        let to_delete = inode.children.get(&name).ok_or(SystemError::ENOENT)?;
+        if to_delete.0.lock().metadata.file_type == FileType::Dir {
+            return Err(SystemError::EPERM);
+        }
+        to_delete.0.lock().metadata.nlinks -= 1;
+        // 删除目录项后，数据页在inode的最后一个引用消失时才被释放
+        inode.children.remove(&name);
+        inode.metadata.mtime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        let name = DName::from(name);
+        let mut inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        let to_delete = inode.children.get(&name).ok_or(SystemError::ENOENT)?;
+        let mut to_delete_guard = to_delete.0.lock();
+        if to_delete_guard.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if !to_delete_guard.children.is_empty() {
+            return Err(SystemError::ENOTEMPTY);
+        }
+        to_delete_guard.metadata.nlinks -= 1;
+        drop(to_delete_guard);
+
+        inode.children.remove(&name);
+        inode.metadata.nlinks -= 1;
+        inode.metadata.mtime = PosixTimeSpec::now();
+        return Ok(());
+    }
+
+    fn move_to(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn IndexNode>,
+        new_name: &str,
+    ) -> Result<(), SystemError> {
+        let inode_to_move = self
+            .find(old_name)?
+            .downcast_arc::<LockedTmpfsInode>()
+            .ok_or(SystemError::EINVAL)?;
+        let target = target
+            .clone()
+            .downcast_arc::<LockedTmpfsInode>()
+            .ok_or(SystemError::EXDEV)?;
+        let new_name = DName::from(new_name);
+        let old_name = DName::from(old_name);
+
+        let target_id = target.0.lock().metadata.inode_id;
+        let mut self_inode = self.0.lock();
+        if target_id == self_inode.metadata.inode_id {
+            // 同一目录下的重命名
+            self_inode.children.remove(&old_name);
+            if let Some(replaced) = self_inode
+                .children
+                .insert(new_name.clone(), inode_to_move.clone())
+            {
+                replaced.0.lock().metadata.nlinks -= 1;
+            }
+            drop(self_inode);
+            inode_to_move.0.lock().name = new_name;
+            return Ok(());
+        }
+
+        // 跨目录移动：先从原目录摘下，再挂到目标目录
+        self_inode.children.remove(&old_name);
+        let is_dir = inode_to_move.0.lock().metadata.file_type == FileType::Dir;
+        if is_dir {
+            self_inode.metadata.nlinks -= 1;
+        }
+        drop(self_inode);
+
+        let mut target_inode = target.0.lock();
+        if let Some(replaced) = target_inode
+            .children
+            .insert(new_name.clone(), inode_to_move.clone())
+        {
+            replaced.0.lock().metadata.nlinks -= 1;
+        } else if is_dir {
+            target_inode.metadata.nlinks += 1;
+        }
+        drop(target_inode);
+
+        let mut moved = inode_to_move.0.lock();
+        moved.name = new_name;
+        moved.parent = Arc::downgrade(&target);
+        return Ok(());
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        match name {
+            "" | "." => {
+                return Ok(inode.self_ref.upgrade().ok_or(SystemError::ENOENT)?);
+            }
+            ".." => {
+                return Ok(inode.parent.upgrade().ok_or(SystemError::ENOENT)?);
+            }
+            name => {
+                let name = DName::from(name);
+                return Ok(inode
+                    .children
+                    .get(&name)
+                    .ok_or(SystemError::ENOENT)?
+                    .clone());
+            }
+        }
+    }
+
+    fn get_entry_name(&self, ino: InodeId) -> Result<String, SystemError> {
+        let inode: SpinLockGuard<TmpfsInode> = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        match ino.into() {
+            0 => {
+                return Ok(String::from("."));
+            }
+            1 => {
+                return Ok(String::from(".."));
+            }
+            ino => {
+                return inode
+                    .children
+                    .iter()
+                    .find(|(_, v)| v.0.lock().metadata.inode_id.into() == ino)
+                    .map(|(k, _)| k.to_string())
+                    .ok_or(SystemError::ENOENT);
+            }
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let mut keys: Vec<String> = Vec::new();
+        keys.push(String::from("."));
+        keys.push(String::from(".."));
+        keys.extend(inode.children.keys().map(|k| k.to_string()));
+        return Ok(keys);
+    }
+
+    fn mknod(
+        &self,
+        filename: &str,
+        mode: ModeType,
+        dev_t: DeviceNumber,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if unlikely(mode.contains(ModeType::S_IFREG)) {
+            return self.create(filename, FileType::File, mode);
+        }
+
+        let file_type = if mode.contains(ModeType::S_IFIFO) {
+            FileType::Pipe
+        } else if mode.contains(ModeType::S_IFBLK) {
+            FileType::BlockDevice
+        } else if mode.contains(ModeType::S_IFCHR) {
+            FileType::CharDevice
+        } else {
+            return Err(SystemError::EINVAL);
+        };
+
+        let filename = DName::from(filename);
+        let mut inode = self.0.lock();
+        if inode.metadata.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+        if inode.children.contains_key(&filename) {
+            return Err(SystemError::EEXIST);
+        }
+
+        let nod = self.new_child(&mut inode, filename, file_type, mode, dev_t)?;
+        if file_type == FileType::Pipe {
+            nod.0.lock().special_node = Some(SpecialNodeData::Pipe(LockedPipeInode::new()));
+        }
+        Ok(nod)
+    }
+
+    fn special_node(&self) -> Option<SpecialNodeData> {
+        return self.0.lock().special_node.clone();
+    }
+
+    fn btime(&self) -> Option<PosixTimeSpec> {
+        Some(self.0.lock().btime)
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.0.lock().name.clone())
+    }
+
+    fn parent(&self) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.0
+            .lock()
+            .parent
+            .upgrade()
+            .map(|item| item as Arc<dyn IndexNode>)
+            .ok_or(SystemError::EINVAL)
+    }
+}
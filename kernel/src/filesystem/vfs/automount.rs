@@ -0,0 +1,204 @@
+//! 自动挂载：目录被访问时才挂载文件系统，空闲一段时间后自动卸载
+//!
+//! 通过[`register_automount`]把一个目录标记为自动挂载点之后，路径查找第一次经过这个目录时，
+//! 调用注册的挂载函数创建文件系统并挂载到这个目录上，之后的查找直接进入已挂载的文件系统。
+//!
+//! 设置了过期时间的自动挂载点，在过期时间内没有被路径查找经过，并且挂载的文件系统中没有被打开的文件、
+//! 工作目录、子挂载等引用时，会被系统工作队列中的过期检查卸载，下次访问时重新挂载。
+//!
+//! 用于/proc/sys/fs/binfmt_misc、可移动介质等按需挂载的伪文件系统（相当于内核中的简化版autofs）。
+
+use alloc::{
+    boxed::Box,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    libs::{
+        casting::DowncastArc,
+        mutex::Mutex,
+        spinlock::SpinLock,
+        workqueue::{schedule_delayed_work, DelayedWork, WorkFunction},
+    },
+    time::{jiffies::msecs_to_jiffies, timer::clock},
+};
+
+use super::{
+    mount::{MountFS, MountFSInode, MOUNT_LIST},
+    FileSystem, FileType, IndexNode,
+};
+
+/// 过期检查的间隔（毫秒）
+const AUTOMOUNT_EXPIRE_INTERVAL_MS: u64 = 1000;
+
+/// 自动挂载时调用的函数，返回要挂载的文件系统
+pub type AutomountFn = fn() -> Result<Arc<dyn FileSystem>, SystemError>;
+
+/// 所有设置了过期时间的自动挂载点，过期检查时遍历。已经被注销的挂载点在遍历时清理
+static EXPIRABLE_AUTOMOUNTS: SpinLock<Vec<Weak<AutomountPoint>>> = SpinLock::new(Vec::new());
+
+/// 过期检查的延迟工作，在第一个设置了过期时间的挂载点注册时创建
+static EXPIRE_WORK: SpinLock<Option<Arc<DelayedWork>>> = SpinLock::new(None);
+
+/// 自动挂载点
+#[derive(Debug)]
+pub struct AutomountPoint {
+    mount: AutomountFn,
+    /// 空闲多少个jiffies之后卸载，为None时不会自动卸载
+    expire: Option<u64>,
+    inner: Mutex<AutomountInner>,
+}
+
+#[derive(Debug)]
+struct AutomountInner {
+    /// 最近一次被路径查找经过的时刻（jiffies）
+    last_used: u64,
+    /// 由自动挂载挂上的文件系统，以及它在挂载列表中的路径
+    mounted: Option<(String, Weak<MountFS>)>,
+}
+
+impl AutomountPoint {
+    fn new(mount: AutomountFn, expire: Option<u64>) -> Arc<Self> {
+        Arc::new(Self {
+            mount,
+            expire,
+            inner: Mutex::new(AutomountInner {
+                last_used: clock(),
+                mounted: None,
+            }),
+        })
+    }
+
+    /// # 路径查找经过自动挂载点
+    ///
+    /// 刷新最近使用的时刻。目录上还没有挂载文件系统时，调用挂载函数并挂载
+    pub(super) fn follow(&self, dir: &MountFSInode) -> Result<(), SystemError> {
+        // 持有锁完成挂载，并发的查找会等待挂载结束，而不是重复挂载
+        let mut inner = self.inner.lock();
+        inner.last_used = clock();
+        if dir.sub_mount()?.is_some() {
+            return Ok(());
+        }
+
+        let fs = (self.mount)()?;
+        let path = dir.absolute_path()?;
+        let mount_fs = dir.mount(fs)?;
+        inner.mounted = Some((path, Arc::downgrade(&mount_fs)));
+        return Ok(());
+    }
+
+    /// # 空闲超过过期时间时卸载自动挂载的文件系统
+    ///
+    /// 文件系统仍在使用时（有inode引用它）不卸载
+    fn try_expire(&self, now: u64) {
+        let Some(expire) = self.expire else {
+            return;
+        };
+        let mut inner = self.inner.lock();
+        if now.saturating_sub(inner.last_used) < expire {
+            return;
+        }
+        let Some((path, weak)) = inner.mounted.take() else {
+            return;
+        };
+        let Some(mount_fs) = weak.upgrade() else {
+            // 已经被手动卸载
+            return;
+        };
+        // 除了这里的引用，只被父挂载的挂载点表和挂载列表引用时，说明没有在使用
+        if Arc::strong_count(&mount_fs) > 3 {
+            inner.mounted = Some((path, weak));
+            return;
+        }
+        let removed = MOUNT_LIST().remove(path.as_str());
+        if let Err(e) = mount_fs.umount() {
+            log::warn!("automount: failed to expire {}: {:?}", path, e);
+            if let Some(removed) = removed {
+                MOUNT_LIST().insert(path.as_str(), removed);
+            }
+        }
+    }
+}
+
+/// 过期检查的工作函数
+#[derive(Debug)]
+struct AutomountExpireWork;
+
+impl WorkFunction for AutomountExpireWork {
+    fn run(&self) -> Result<(), SystemError> {
+        let points: Vec<Arc<AutomountPoint>> = {
+            let mut list = EXPIRABLE_AUTOMOUNTS.lock();
+            list.retain(|w| w.strong_count() > 0);
+            list.iter().filter_map(|w| w.upgrade()).collect()
+        };
+        let now = clock();
+        for point in points.iter() {
+            point.try_expire(now);
+        }
+        if !points.is_empty() {
+            schedule_expire_work();
+        }
+        return Ok(());
+    }
+}
+
+/// 安排下一次过期检查
+fn schedule_expire_work() {
+    let mut work = EXPIRE_WORK.lock();
+    let work = work.get_or_insert_with(|| DelayedWork::new(Box::new(AutomountExpireWork)));
+    let delay = msecs_to_jiffies(AUTOMOUNT_EXPIRE_INTERVAL_MS);
+    schedule_delayed_work(work, delay, delay / 4);
+}
+
+/// 把路径查找得到的inode转换为MountFSInode，并检查它是否为目录
+fn automount_dir(dir: &Arc<dyn IndexNode>) -> Result<Arc<MountFSInode>, SystemError> {
+    if dir.metadata()?.file_type != FileType::Dir {
+        return Err(SystemError::ENOTDIR);
+    }
+    dir.clone()
+        .downcast_arc::<MountFSInode>()
+        .ok_or(SystemError::EINVAL)
+}
+
+/// # 把目录标记为自动挂载点
+///
+/// 挂载函数在路径查找的过程中调用，不能再经过同一个自动挂载点查找路径
+///
+/// ## 参数
+/// - `dir`: 作为挂载点的目录
+/// - `mount`: 挂载时调用的函数，返回要挂载的文件系统
+/// - `expire_ms`: 空闲多少毫秒之后自动卸载，为None时挂载之后不会自动卸载
+///
+/// ## 返回值
+/// - `Ok(())`: 成功
+/// - `Err(SystemError::ENOTDIR)`: `dir`不是目录
+/// - `Err(SystemError::EBUSY)`: `dir`已经是自动挂载点
+pub fn register_automount(
+    dir: &Arc<dyn IndexNode>,
+    mount: AutomountFn,
+    expire_ms: Option<u64>,
+) -> Result<(), SystemError> {
+    let dir = automount_dir(dir)?;
+    let point = AutomountPoint::new(mount, expire_ms.map(msecs_to_jiffies));
+    dir.set_automount(Some(point.clone()))?;
+
+    if point.expire.is_some() {
+        EXPIRABLE_AUTOMOUNTS.lock().push(Arc::downgrade(&point));
+        schedule_expire_work();
+    }
+    return Ok(());
+}
+
+/// # 取消目录的自动挂载
+///
+/// 已经自动挂载的文件系统不会被卸载
+///
+/// ## 返回值
+/// - `Ok(())`: 成功
+/// - `Err(SystemError::ENOENT)`: `dir`不是自动挂载点
+pub fn unregister_automount(dir: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
+    automount_dir(dir)?.set_automount(None)
+}
@@ -13,10 +13,12 @@ use crate::{
         ramfs::RamFS,
         sysfs::sysfs_init,
         vfs::{
-            mount::MountFS, syscall::ModeType, AtomicInodeId, FileSystem, FileType, MAX_PATHLEN,
+            mount::{MountFS, MountFSInode},
+            syscall::{ModeType, MountFlags},
+            AtomicInodeId, FileSystem, FileType, MAX_PATHLEN,
         },
     },
-    libs::spinlock::SpinLock,
+    libs::{casting::DowncastArc, spinlock::SpinLock},
     process::ProcessManager,
     syscall::user_access::check_and_clone_cstr,
 };
@@ -31,7 +33,14 @@ use super::{
 };
 
 /// 当没有指定根文件系统时，尝试的根文件系统列表
-const ROOTFS_TRY_LIST: [&str; 4] = ["/dev/sda1", "/dev/sda", "/dev/vda1", "/dev/vda"];
+const ROOTFS_TRY_LIST: [&str; 6] = [
+    "/dev/sda1",
+    "/dev/sda",
+    "/dev/vda1",
+    "/dev/vda",
+    "/dev/nvme0n1p1",
+    "/dev/nvme0n1",
+];
 kernel_cmdline_param_kv!(ROOTFS_PATH_PARAM, root, "");
 
 /// @brief 原子地生成新的Inode号。
@@ -222,18 +231,12 @@ pub fn do_unlink_at(dirfd: i32, path: &str) -> Result<u64, SystemError> {
 
     let pcb = ProcessManager::current_pcb();
     let (inode_begin, remain_path) = user_path_at(&pcb, dirfd, path)?;
-    let inode: Result<Arc<dyn IndexNode>, SystemError> =
-        inode_begin.lookup_follow_symlink(&remain_path, VFS_MAX_FOLLOW_SYMLINK_TIMES);
-
-    if inode.is_err() {
-        let errno = inode.clone().unwrap_err();
-        // 文件不存在，且需要创建
-        if errno == SystemError::ENOENT {
-            return Err(SystemError::ENOENT);
-        }
-    }
+    // unlink删除的是符号链接本身，不跟随最后一级的符号链接
+    let inode: Arc<dyn IndexNode> =
+        inode_begin.lookup_follow_symlink2(&remain_path, VFS_MAX_FOLLOW_SYMLINK_TIMES, false)?;
+
     // 禁止在目录上unlink
-    if inode.unwrap().metadata()?.file_type == FileType::Dir {
+    if inode.metadata()?.file_type == FileType::Dir {
         return Err(SystemError::EPERM);
     }
 
@@ -357,6 +360,32 @@ pub fn do_mount_mkdir(
     return inode.mount(fs);
 }
 
+/// # do_remount - 修改已有挂载的标志位
+///
+/// ## 参数
+///
+/// - `mount_point`: &str，挂载点路径，必须是某个挂载的根目录。
+/// - `flags`: MountFlags，新的挂载标志位，会替换原有的标志位。
+///
+/// ## 返回值
+///
+/// - `Ok(Arc<MountFS>)`: 被修改的挂载。
+/// - `Err(SystemError::EINVAL)`: 挂载点路径不是挂载的根目录。
+pub fn do_remount(mount_point: &str, flags: MountFlags) -> Result<Arc<MountFS>, SystemError> {
+    let (current_node, rest_path) = user_path_at(
+        &ProcessManager::current_pcb(),
+        AtFlags::AT_FDCWD.bits(),
+        mount_point,
+    )?;
+    let inode = current_node.lookup_follow_symlink(&rest_path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    let mount_fs = inode
+        .downcast_arc::<MountFSInode>()
+        .ok_or(SystemError::EINVAL)?
+        .mount_root_fs()?;
+    mount_fs.set_flags(flags);
+    return Ok(mount_fs);
+}
+
 /// # do_umount2 - 执行卸载文件系统的函数
 ///
 /// 这个函数用于卸载指定的文件系统。
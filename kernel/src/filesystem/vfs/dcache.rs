@@ -0,0 +1,265 @@
+//! 目录项缓存（dcache）
+//!
+//! 以（文件系统, 父目录inode, 文件名）为键，缓存具体文件系统的`find`结果，
+//! 避免每次路径查找都重新遍历目录。查找失败（`ENOENT`）的结果同样会被缓存为负目录项。
+//!
+//! 缓存的是具体文件系统的inode，而不是`MountFSInode`，因此挂载点的替换仍然在每次查找时进行，
+//! 挂载与卸载不会使缓存失效。
+//!
+//! 只有`FileSystem::dcache_enabled`返回true的文件系统才会被缓存：
+//! 这些文件系统的目录只会经由VFS修改，因此可以在`MountFSInode`的create、unlink、rmdir、rename等
+//! 操作中使相关的目录项失效。
+//!
+//! 此外，这里还提供了重命名序列号（参考Linux的`rename_lock`）：unlink、rmdir、rename在修改目录结构期间
+//! 持有写锁，路径查找结束时如果发现期间目录结构被修改过，就重新查找，而不是返回基于过期父目录的结果。
+
+use core::{
+    num::NonZeroUsize,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{sync::Arc, vec::Vec};
+use lru::LruCache;
+use system_error::SystemError;
+
+use crate::{
+    libs::{
+        mutex::{Mutex, MutexGuard},
+        spinlock::SpinLock,
+    },
+    process::ProcessManager,
+};
+
+use super::{utils::DName, FileSystem, IndexNode, InodeId};
+
+/// dcache中最多缓存的目录项数量
+const DCACHE_MAX_ENTRIES: usize = 8192;
+
+static DCACHE: SpinLock<Option<LruCache<DentryKey, Dentry>>> = SpinLock::new(None);
+/// 目录项失效的次数，只在持有`DCACHE`的锁时修改
+///
+/// `lookup`在锁外调用具体文件系统的`find`，插入结果之前比较这个值：
+/// 期间有目录项失效时不插入，以免把失效之前查到的过期结果放回缓存
+static DCACHE_GEN: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DentryKey {
+    /// 具体文件系统对象的地址，用于区分不同的文件系统实例
+    fs: usize,
+    parent: InodeId,
+    name: DName,
+}
+
+impl DentryKey {
+    fn new(parent: &Arc<dyn IndexNode>, name: &str) -> Result<Option<Self>, SystemError> {
+        let fs = parent.fs();
+        if !fs.dcache_enabled() {
+            return Ok(None);
+        }
+        return Ok(Some(Self {
+            fs: fs_key(&fs),
+            parent: parent.metadata()?.inode_id,
+            name: DName::from(name),
+        }));
+    }
+}
+
+/// 目录项。为None时表示负目录项，即该文件不存在
+type Dentry = Option<Arc<dyn IndexNode>>;
+
+fn fs_key(fs: &Arc<dyn FileSystem>) -> usize {
+    Arc::as_ptr(fs) as *const () as usize
+}
+
+/// 在`parent`目录下查找`name`，优先使用dcache中的结果
+///
+/// `parent`应当是具体文件系统的inode
+pub fn lookup(parent: &Arc<dyn IndexNode>, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+    let Some(key) = DentryKey::new(parent, name)? else {
+        return parent.find(name);
+    };
+
+    let gen = {
+        let mut guard = DCACHE.lock_irqsave();
+        if let Some(dentry) = guard.as_mut().and_then(|cache| cache.get(&key).cloned()) {
+            return dentry.ok_or(SystemError::ENOENT);
+        }
+        DCACHE_GEN.load(Ordering::Relaxed)
+    };
+
+    let result = parent.find(name);
+    let dentry = match &result {
+        Ok(inode) => Some(inode.clone()),
+        Err(SystemError::ENOENT) => None,
+        // 其他错误可能是暂时的，不缓存
+        Err(_) => return result,
+    };
+
+    let mut guard = DCACHE.lock_irqsave();
+    // 查找期间有目录项失效，查到的结果可能已经过期，不缓存
+    if DCACHE_GEN.load(Ordering::Relaxed) != gen {
+        return result;
+    }
+    let evicted = guard
+        .get_or_insert_with(|| LruCache::new(NonZeroUsize::new(DCACHE_MAX_ENTRIES).unwrap()))
+        .push(key, dentry);
+    drop(guard);
+    // 被替换或淘汰的目录项在锁外释放
+    drop(evicted);
+    return result;
+}
+
+/// 使`parent`目录下名为`name`的目录项失效
+///
+/// 在目录项被创建、删除或重命名之后调用
+pub fn invalidate(parent: &Arc<dyn IndexNode>, name: &str) {
+    let Ok(Some(key)) = DentryKey::new(parent, name) else {
+        return;
+    };
+    let removed = {
+        let mut guard = DCACHE.lock_irqsave();
+        DCACHE_GEN.fetch_add(1, Ordering::Relaxed);
+        guard.as_mut().and_then(|cache| cache.pop(&key))
+    };
+    drop(removed);
+}
+
+/// 使以`dir`为父目录的所有目录项失效
+///
+/// 在目录被删除之后调用，以免inode号被复用时命中过期的目录项
+pub fn invalidate_children(fs: &Arc<dyn FileSystem>, dir: InodeId) {
+    let fs = fs_key(fs);
+    remove_if(|key| key.fs == fs && key.parent == dir);
+}
+
+/// 使属于文件系统`fs`的所有目录项失效
+///
+/// 在文件系统被卸载时调用
+pub fn invalidate_fs(fs: &Arc<dyn FileSystem>) {
+    let fs = fs_key(fs);
+    remove_if(|key| key.fs == fs);
+}
+
+fn remove_if(pred: impl Fn(&DentryKey) -> bool) {
+    let mut removed = Vec::new();
+    {
+        let mut guard = DCACHE.lock_irqsave();
+        DCACHE_GEN.fetch_add(1, Ordering::Relaxed);
+        let Some(cache) = guard.as_mut() else {
+            return;
+        };
+        let keys: Vec<DentryKey> = cache
+            .iter()
+            .filter(|(key, _)| pred(key))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            removed.push(cache.pop(&key));
+        }
+    }
+}
+
+/// 在内存紧张时回收最近最少使用的目录项
+///
+/// ## 参数
+///
+/// - `count`: 最多回收的目录项数量
+///
+/// ## 返回值
+///
+/// 实际回收的目录项数量
+pub fn shrink(count: usize) -> usize {
+    // 在锁外释放inode，以免在持有dcache的锁时进入具体文件系统
+    let mut freed = Vec::new();
+    {
+        let mut guard = DCACHE.lock_irqsave();
+        let Some(cache) = guard.as_mut() else {
+            return 0;
+        };
+        while freed.len() < count {
+            match cache.pop_lru() {
+                Some((_, dentry)) => freed.push(dentry),
+                None => break,
+            }
+        }
+    }
+    return freed.len();
+}
+
+/// 重命名序列号。修改目录结构期间为奇数，每次修改完成后比开始前大2
+static RENAME_SEQ: AtomicUsize = AtomicUsize::new(0);
+/// 串行化目录结构的修改
+static RENAME_LOCK: Mutex<()> = Mutex::new(());
+/// 持有`RENAME_LOCK`的进程的pid，没有进程持有时为`usize::MAX`
+static RENAME_OWNER: AtomicUsize = AtomicUsize::new(usize::MAX);
+/// 路径查找无锁重试的最大次数
+const RENAME_READ_MAX_RETRIES: usize = 4;
+
+/// 修改目录结构的写锁，释放时结束修改
+pub struct RenameWriteGuard {
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl Drop for RenameWriteGuard {
+    fn drop(&mut self) {
+        RENAME_SEQ.fetch_add(1, Ordering::Release);
+        RENAME_OWNER.store(usize::MAX, Ordering::Release);
+    }
+}
+
+/// 开始修改目录结构
+///
+/// 在unlink、rmdir、rename修改具体文件系统的目录之前调用，返回的guard被释放时修改结束
+pub fn rename_write_begin() -> RenameWriteGuard {
+    let guard = RENAME_LOCK.lock();
+    RENAME_OWNER.store(ProcessManager::current_pid().data(), Ordering::Release);
+    RENAME_SEQ.fetch_add(1, Ordering::Acquire);
+    return RenameWriteGuard { _guard: guard };
+}
+
+/// 当前进程是否正在修改目录结构
+fn rename_owned_by_current() -> bool {
+    RENAME_OWNER.load(Ordering::Acquire) == ProcessManager::current_pid().data()
+}
+
+/// 开始一次路径查找，返回当前的序列号
+///
+/// 如果有修改正在进行，先等待它完成，以免查找看到修改了一半的目录结构
+pub fn rename_read_begin() -> usize {
+    loop {
+        let seq = RENAME_SEQ.load(Ordering::Acquire);
+        // 正在修改目录结构的进程自己进行查找时不能等待
+        if seq & 1 == 0 || rename_owned_by_current() {
+            return seq;
+        }
+        drop(RENAME_LOCK.lock());
+    }
+}
+
+/// 路径查找结束时调用，返回true表示查找期间目录结构被修改过，需要重新查找
+pub fn rename_read_retry(seq: usize) -> bool {
+    RENAME_SEQ.load(Ordering::Acquire) != seq
+}
+
+/// 进行一次不会与目录结构的修改冲突的路径查找
+///
+/// 先无锁地查找，期间目录结构被修改时重试。重试`RENAME_READ_MAX_RETRIES`次之后，
+/// 持有写锁进行查找，以免在频繁的重命名中一直无法完成
+pub fn rename_read_consistent<T>(
+    mut f: impl FnMut() -> Result<T, SystemError>,
+) -> Result<T, SystemError> {
+    for _ in 0..RENAME_READ_MAX_RETRIES {
+        let seq = rename_read_begin();
+        let result = f();
+        if !rename_read_retry(seq) {
+            return result;
+        }
+    }
+
+    if rename_owned_by_current() {
+        return f();
+    }
+    // 与修改目录结构一样记录持有者，查找中嵌套的路径查找（如跟随符号链接）才不会等待自己
+    let _guard = rename_write_begin();
+    return f();
+}
@@ -9,20 +9,18 @@ use log::error;
 use system_error::SystemError;
 
 use super::{Dirent, FileType, IndexNode, InodeId, Metadata, SpecialNodeData};
-use crate::filesystem::eventfd::EventFdInode;
-use crate::perf::PerfEventInode;
+use crate::driver::net::tun::TunFilePrivateData;
+use crate::filesystem::page_cache::FileReadAhead;
 use crate::{
+    debug::obj_lifetime::{obj_lifetime_track, ObjKind},
     driver::{
         base::{block::SeekFrom, device::DevicePrivateData},
         tty::tty_device::TtyFilePrivateData,
     },
     filesystem::procfs::ProcfsFilePrivateData,
-    ipc::pipe::{LockedPipeInode, PipeFsPrivateData},
+    ipc::pipe::PipeFsPrivateData,
     libs::{rwlock::RwLock, spinlock::SpinLock},
-    net::{
-        event_poll::{EPollItem, EPollPrivateData, EventPoll},
-        socket::SocketInode,
-    },
+    net::event_poll::{EPollItem, EPollPrivateData, EventPoll},
     process::{cred::Cred, ProcessManager},
 };
 
@@ -40,6 +38,8 @@ pub enum FilePrivateData {
     Tty(TtyFilePrivateData),
     /// epoll私有信息
     EPoll(EPollPrivateData),
+    /// /dev/net/tun的私有信息
+    Tun(TunFilePrivateData),
     /// 不需要文件私有信息
     Unused,
 }
@@ -52,8 +52,10 @@ impl Default for FilePrivateData {
 
 impl FilePrivateData {
     pub fn update_mode(&mut self, mode: FileMode) {
-        if let FilePrivateData::Pipefs(pdata) = self {
-            pdata.set_mode(mode);
+        match self {
+            FilePrivateData::Pipefs(pdata) => pdata.set_mode(mode),
+            FilePrivateData::Tun(pdata) => pdata.set_mode(mode),
+            _ => {}
         }
     }
 }
@@ -132,6 +134,8 @@ pub struct File {
     file_type: FileType,
     /// readdir时候用的，暂存的本次循环中，所有子目录项的名字的数组
     readdir_subdirs_name: SpinLock<Vec<String>>,
+    /// 预读状态
+    ra_state: SpinLock<FileReadAhead>,
     pub private_data: SpinLock<FilePrivateData>,
     /// 文件的凭证
     cred: Cred,
@@ -151,12 +155,14 @@ impl File {
             }
         }
 
+        obj_lifetime_track(ObjKind::Inode, &inode);
         let f = File {
             inode,
             offset: AtomicUsize::new(0),
             mode: RwLock::new(mode),
             file_type,
             readdir_subdirs_name: SpinLock::new(Vec::default()),
+            ra_state: SpinLock::new(FileReadAhead::new()),
             private_data: SpinLock::new(FilePrivateData::default()),
             cred: ProcessManager::current_pcb().cred(),
         };
@@ -246,6 +252,7 @@ impl File {
             self.inode
                 .read_direct(offset, len, buf, self.private_data.lock())
         } else {
+            self.readahead(offset, len);
             self.inode
                 .read_at(offset, len, buf, self.private_data.lock())
         }?;
@@ -258,6 +265,18 @@ impl File {
         Ok(len)
     }
 
+    /// 根据本次访问对页面缓存进行预读
+    ///
+    /// 预读失败不影响本次读取，真正需要的页面会在读取时被同步读入
+    pub fn readahead(&self, offset: usize, len: usize) {
+        if self.file_type != FileType::File {
+            return;
+        }
+        if let Some(page_cache) = self.inode.page_cache() {
+            let _ = page_cache.readahead(&self.ra_state, offset, len);
+        }
+    }
+
     fn do_write(
         &self,
         offset: usize,
@@ -352,14 +371,21 @@ impl File {
         return Ok(());
     }
 
-    /// @biref 充填dirent结构体
-    /// @return 返回dirent结构体的大小
-    pub fn readdir(&self, dirent: &mut Dirent) -> Result<u64, SystemError> {
+    /// 从当前偏移量开始读取目录项，按照getdents64的格式依次填入`buf`，直到`buf`放不下下一个目录项
+    ///
+    /// 目录的偏移量是目录项的序号，每个目录项的`d_off`为下一个目录项的序号，可以直接用于lseek
+    ///
+    /// ## 返回值
+    ///
+    /// - `Ok(0)`: 已经读到目录末尾
+    /// - `Ok(n)`: 写入`buf`的字节数
+    /// - `Err(SystemError::EINVAL)`: `buf`连一个目录项都放不下
+    pub fn readdir(&self, buf: &mut [u8]) -> Result<usize, SystemError> {
         let inode: &Arc<dyn IndexNode> = &self.inode;
         let mut readdir_subdirs_name = self.readdir_subdirs_name.lock();
-        let offset = self.offset.load(Ordering::SeqCst);
-        // 如果偏移量为0
-        if offset == 0 {
+        let mut offset = self.offset.load(Ordering::SeqCst);
+        // 如果偏移量为0，或者偏移量是通过lseek直接设置的（如恢复检查点时）
+        if offset == 0 || readdir_subdirs_name.is_empty() {
             // 通过list更新readdir_subdirs_name
             *readdir_subdirs_name = inode.list()?;
             readdir_subdirs_name.sort();
@@ -367,51 +393,73 @@ impl File {
         // debug!("sub_entries={sub_entries:?}");
 
         // 已经读到末尾
-        if offset == readdir_subdirs_name.len() {
+        if offset >= readdir_subdirs_name.len() {
             self.offset.store(0, Ordering::SeqCst);
             return Ok(0);
         }
-        let name = &readdir_subdirs_name[offset];
-        let sub_inode: Arc<dyn IndexNode> = match inode.find(name) {
-            Ok(i) => i,
-            Err(e) => {
-                error!(
-                    "Readdir error: Failed to find sub inode:{name:?}, file={self:?}, error={e:?}"
-                );
-                return Err(e);
-            }
-        };
 
-        let name_bytes: &[u8] = name.as_bytes();
+        let mut written = 0;
+        while offset < readdir_subdirs_name.len() {
+            let name = &readdir_subdirs_name[offset];
+            let metadata = match inode.find(name).and_then(|i| i.metadata()) {
+                Ok(m) => m,
+                // 目录项在list之后被删除了，跳过
+                Err(SystemError::ENOENT) => {
+                    offset += 1;
+                    continue;
+                }
+                Err(e) => {
+                    error!(
+                        "Readdir error: Failed to get sub inode:{name:?}, file={self:?}, error={e:?}"
+                    );
+                    self.offset.store(offset, Ordering::SeqCst);
+                    // 已经填入的目录项仍然返回给用户，下一次调用再从出错的目录项开始
+                    return if written > 0 { Ok(written) } else { Err(e) };
+                }
+            };
 
-        // 根据posix的规定，dirent中的d_name是一个不定长的数组，因此需要unsafe来拷贝数据
-        unsafe {
-            let ptr = &mut dirent.d_name as *mut u8;
+            let name_bytes: &[u8] = name.as_bytes();
+            let reclen = Dirent::reclen(name_bytes.len());
+            if written + reclen > buf.len() {
+                if written == 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                break;
+            }
 
-            let buf: &mut [u8] =
-                ::core::slice::from_raw_parts_mut::<'static, u8>(ptr, name_bytes.len() + 1);
-            buf[0..name_bytes.len()].copy_from_slice(name_bytes);
-            buf[name_bytes.len()] = 0;
+            let record = &mut buf[written..written + reclen];
+            let dirent = Dirent {
+                d_ino: metadata.inode_id.into() as u64,
+                d_off: (offset + 1) as i64,
+                d_reclen: reclen as u16,
+                d_type: metadata.file_type.get_file_type_num() as u8,
+                d_name: 0,
+            };
+            // 用户缓冲区不一定按Dirent对齐
+            unsafe { core::ptr::write_unaligned(record.as_mut_ptr() as *mut Dirent, dirent) };
+            // 根据posix的规定，dirent中的d_name是一个不定长的数组，紧跟在结构体的固定部分之后
+            let name_start = Dirent::NAME_OFFSET;
+            record[name_start..name_start + name_bytes.len()].copy_from_slice(name_bytes);
+            record[name_start + name_bytes.len()..].fill(0);
+
+            offset += 1;
+            written += reclen;
         }
 
-        self.offset.fetch_add(1, Ordering::SeqCst);
-        dirent.d_ino = sub_inode.metadata().unwrap().inode_id.into() as u64;
-        dirent.d_type = sub_inode.metadata().unwrap().file_type.get_file_type_num() as u8;
-
-        // 计算dirent结构体的大小
-        let size = (name_bytes.len() + ::core::mem::size_of::<Dirent>()
-            - ::core::mem::size_of_val(&dirent.d_name)) as u64;
-
-        dirent.d_reclen = size as u16;
-        dirent.d_off += dirent.d_reclen as i64;
-
-        return Ok(size);
+        self.offset.store(offset, Ordering::SeqCst);
+        return Ok(written);
     }
 
     pub fn inode(&self) -> Arc<dyn IndexNode> {
         return self.inode.clone();
     }
 
+    /// 获取文件当前的偏移量
+    #[inline]
+    pub fn pos(&self) -> usize {
+        return self.offset.load(Ordering::SeqCst);
+    }
+
     /// @brief 尝试克隆一个文件
     ///
     /// @return Option<File> 克隆后的文件结构体。如果克隆失败，返回None
@@ -422,6 +470,7 @@ impl File {
             mode: RwLock::new(self.mode()),
             file_type: self.file_type,
             readdir_subdirs_name: SpinLock::new(self.readdir_subdirs_name.lock().clone()),
+            ra_state: SpinLock::new(self.ra_state.lock().clone()),
             private_data: SpinLock::new(self.private_data.lock().clone()),
             cred: self.cred.clone(),
         };
@@ -496,53 +545,12 @@ impl File {
     ///
     /// 在文件状态发生变化时，需要向epoll通知
     pub fn add_epoll(&self, epitem: Arc<EPollItem>) -> Result<(), SystemError> {
-        match self.file_type {
-            FileType::Socket => {
-                let inode = self.inode.downcast_ref::<SocketInode>().unwrap();
-                let mut socket = inode.inner();
-
-                return socket.add_epoll(epitem);
-            }
-            FileType::Pipe => {
-                let inode = self.inode.downcast_ref::<LockedPipeInode>().unwrap();
-                return inode.inner().lock().add_epoll(epitem);
-            }
-            _ => {
-                let r = self.inode.kernel_ioctl(epitem, &self.private_data.lock());
-                if r.is_err() {
-                    return Err(SystemError::ENOSYS);
-                }
-
-                Ok(())
-            }
-        }
+        self.inode.add_epitem(epitem, &self.private_data.lock())
     }
 
     /// ## 删除一个绑定的epoll
     pub fn remove_epoll(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
-        match self.file_type {
-            FileType::Socket => {
-                let inode = self.inode.downcast_ref::<SocketInode>().unwrap();
-                let mut socket = inode.inner();
-
-                socket.remove_epoll(epoll)
-            }
-            FileType::Pipe => {
-                let inode = self.inode.downcast_ref::<LockedPipeInode>().unwrap();
-                inode.inner().lock().remove_epoll(epoll)
-            }
-            _ => {
-                let inode = self.inode.downcast_ref::<EventFdInode>();
-                if let Some(inode) = inode {
-                    return inode.remove_epoll(epoll);
-                }
-                let inode = self
-                    .inode
-                    .downcast_ref::<PerfEventInode>()
-                    .ok_or(SystemError::ENOSYS)?;
-                return inode.remove_epoll(epoll);
-            }
-        }
+        self.inode.remove_epitem(epoll, &self.private_data.lock())
     }
 
     pub fn poll(&self) -> Result<usize, SystemError> {
@@ -1,13 +1,21 @@
+pub mod automount;
 pub mod core;
+pub mod dcache;
 pub mod fcntl;
 pub mod file;
 pub mod mount;
 pub mod open;
+mod resolve;
+pub mod splice;
 pub mod syscall;
 pub mod utils;
 
 use ::core::{any::Any, fmt::Debug, sync::atomic::AtomicUsize};
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use intertrait::CastFromSync;
 use system_error::SystemError;
 
@@ -21,6 +29,7 @@ use crate::{
         spinlock::{SpinLock, SpinLockGuard},
     },
     mm::{fault::PageFaultMessage, VmFaultReason},
+    net::event_poll::{EPollItem, EventPoll},
     time::PosixTimeSpec,
 };
 
@@ -93,8 +102,8 @@ pub const DT_WHT: u16 = 14;
 #[allow(dead_code)]
 pub const DT_MAX: u16 = 16;
 
-/// vfs容许的最大的符号链接跳转次数
-pub const VFS_MAX_FOLLOW_SYMLINK_TIMES: usize = 8;
+/// vfs容许的最大的符号链接跳转次数（与Linux的MAXSYMLINKS相同），超过时返回ELOOP
+pub const VFS_MAX_FOLLOW_SYMLINK_TIMES: usize = 40;
 
 impl FileType {
     pub fn get_file_type_num(&self) -> u16 {
@@ -411,11 +420,24 @@ pub trait IndexNode: Any + Sync + Send + Debug + CastFromSync {
         return Err(SystemError::ENOSYS);
     }
 
-    fn kernel_ioctl(
+    /// @brief 把epitem挂到文件的等待队列上，文件有事件发生时唤醒epitem所属的epoll
+    ///
+    /// 支持epoll的文件需要同时实现本方法和`remove_epitem`，通常是把epitem交给自己持有的
+    /// `EPollItems`，并在`poll`中返回当前的事件
+    fn add_epitem(
         &self,
-        _arg: Arc<dyn crate::net::event_poll::KernelIoctlData>,
-        _data: &FilePrivateData,
-    ) -> Result<usize, SystemError> {
+        _epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        return Err(SystemError::ENOSYS);
+    }
+
+    /// @brief 从文件的等待队列上删除属于`epoll`的epitem
+    fn remove_epitem(
+        &self,
+        _epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
         return Err(SystemError::ENOSYS);
     }
 
@@ -618,13 +640,22 @@ pub trait IndexNode: Any + Sync + Send + Debug + CastFromSync {
         return self.find("..");
     }
 
+    /// 获取inode的页面缓存，没有页面缓存的inode返回None
     fn page_cache(&self) -> Option<Arc<PageCache>> {
-        log::error!(
-            "function page_cache() has not yet been implemented for inode:{}",
-            crate::libs::name::get_type_name(&self)
-        );
         None
     }
+
+    /// 获取文件的创建时间，文件系统没有记录创建时间时返回None
+    fn btime(&self) -> Option<PosixTimeSpec> {
+        None
+    }
+
+    /// # 重新从后备存储获取inode的元数据
+    ///
+    /// 网络文件系统等缓存了元数据的文件系统需要实现，statx指定AT_STATX_FORCE_SYNC时调用
+    fn revalidate(&self) -> Result<(), SystemError> {
+        Ok(())
+    }
 }
 
 impl DowncastArc for dyn IndexNode {
@@ -640,14 +671,16 @@ impl dyn IndexNode {
         return self.as_any_ref().downcast_ref::<T>();
     }
 
-    /// @brief 查找文件（不考虑符号链接）
+    /// @brief 查找文件（不跟随符号链接）
+    ///
+    /// 路径的最后一级是符号链接时，返回符号链接本身；中间某一级是符号链接时，返回ELOOP
     ///
     /// @param path 文件路径
     ///
     /// @return Ok(Arc<dyn IndexNode>) 要寻找的目录项的inode
     /// @return Err(SystemError) 错误码
     pub fn lookup(&self, path: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
-        return self.lookup_follow_symlink(path, 0);
+        return self.do_lookup_follow_symlink(path, 0, false);
     }
 
     pub fn lookup_follow_symlink(
@@ -672,21 +705,36 @@ impl dyn IndexNode {
     ///
     /// ## 参数
     /// - `path`: 文件路径
-    /// - `max_follow_times`: 最大经过的符号链接的数量
+    /// - `max_follow_times`: 最大经过的符号链接的数量，为0时不允许跟随任何符号链接
     /// - `follow_final_symlink`: 是否跟随最后的符号链接
     ///
     /// ## 返回值
     /// - `Ok(Arc<dyn IndexNode>)`: 要寻找的目录项的inode
+    /// - `Err(SystemError::ELOOP)`: 需要跟随的符号链接数量超过了`max_follow_times`
     /// - `Err(SystemError)`: 错误码，表示查找过程中遇到的错误
     ///
     /// ## Safety
     /// 此函数在处理符号链接时可能会遇到循环引用的情况，`max_follow_times` 参数用于限制符号链接的跟随次数以避免无限循环。
+    ///
+    /// 查找期间有其他进程unlink、rmdir或者rename时，查找会重新进行，见`dcache::rename_read_consistent`
     #[inline(never)]
     pub fn do_lookup_follow_symlink(
         &self,
         path: &str,
         max_follow_times: usize,
         follow_final_symlink: bool,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        return dcache::rename_read_consistent(|| {
+            self.walk_path(path, max_follow_times, follow_final_symlink)
+        });
+    }
+
+    /// 逐级查找路径，参数与`do_lookup_follow_symlink`相同
+    fn walk_path(
+        &self,
+        path: &str,
+        max_follow_times: usize,
+        follow_final_symlink: bool,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
         if self.metadata()?.file_type != FileType::Dir {
             return Err(SystemError::ENOTDIR);
@@ -729,39 +777,34 @@ impl dyn IndexNode {
 
             let inode = result.find(&name)?;
             let file_type = inode.metadata()?.file_type;
-            // 如果已经是路径的最后一个部分，并且不希望跟随最后的符号链接
-            if rest_path.is_empty() && !follow_final_symlink && file_type == FileType::SymLink {
-                // 返回符号链接本身
-                return Ok(inode);
+            // 不是符号链接，或者已经是路径的最后一个部分并且不希望跟随最后的符号链接
+            if file_type != FileType::SymLink || (rest_path.is_empty() && !follow_final_symlink) {
+                result = inode;
+                continue;
             }
 
-            // 跟随符号链接跳转
-            if file_type == FileType::SymLink && max_follow_times > 0 {
-                let mut content = [0u8; 256];
-                // 读取符号链接
-
-                let len = inode.read_at(
-                    0,
-                    256,
-                    &mut content,
-                    SpinLock::new(FilePrivateData::Unused).lock(),
-                )?;
-
-                // 将读到的数据转换为utf8字符串（先转为str，再转为String）
-                let link_path = String::from(
-                    ::core::str::from_utf8(&content[..len]).map_err(|_| SystemError::EINVAL)?,
-                );
-                let new_path = link_path + "/" + &rest_path;
-
-                // 继续查找符号链接
-                return result.lookup_follow_symlink2(
-                    &new_path,
-                    max_follow_times - 1,
-                    follow_final_symlink,
-                );
-            } else {
-                result = inode;
+            // 需要跟随的符号链接过多，认为出现了循环
+            if max_follow_times == 0 {
+                return Err(SystemError::ELOOP);
             }
+
+            // 读取符号链接
+            let mut content = vec![0u8; MAX_PATHLEN];
+            let len = inode.read_at(
+                0,
+                MAX_PATHLEN,
+                &mut content,
+                SpinLock::new(FilePrivateData::Unused).lock(),
+            )?;
+
+            // 将读到的数据转换为utf8字符串（先转为str，再转为String）
+            let link_path = String::from(
+                ::core::str::from_utf8(&content[..len]).map_err(|_| SystemError::EINVAL)?,
+            );
+            let new_path = link_path + "/" + &rest_path;
+
+            // 继续查找符号链接
+            return result.walk_path(&new_path, max_follow_times - 1, follow_final_symlink);
         }
 
         return Ok(result);
@@ -890,6 +933,10 @@ bitflags! {
         const PROC_MAGIC = 0x9fa0;
         const RAMFS_MAGIC = 0x858458f6;
         const MOUNT_MAGIC = 61267;
+        const SMB2_MAGIC = 0xfe534d42;
+        const TMPFS_MAGIC = 0x01021994;
+        const MQUEUE_MAGIC = 0x19800202;
+        const CGROUP2_MAGIC = 0x63677270;
     }
 }
 
@@ -909,6 +956,14 @@ pub trait FileSystem: Any + Sync + Send + Debug {
 
     fn super_block(&self) -> SuperBlock;
 
+    /// @brief 本文件系统的目录项能否被dcache缓存
+    ///
+    /// 只有目录内容只会经由VFS修改的文件系统才能被缓存，否则会命中过期的目录项。
+    /// 因此默认不缓存。
+    fn dcache_enabled(&self) -> bool {
+        false
+    }
+
     unsafe fn fault(&self, _pfm: &mut PageFaultMessage) -> VmFaultReason {
         panic!(
             "fault() has not yet been implemented for filesystem: {}",
@@ -954,6 +1009,16 @@ pub struct Dirent {
     d_name: u8,    // 文件entry的名字(是一个零长数组)， 本字段仅用于占位
 }
 
+impl Dirent {
+    /// d_name在结构体中的偏移量
+    const NAME_OFFSET: usize = core::mem::offset_of!(Dirent, d_name);
+
+    /// 名字长度为`name_len`的目录项所占的字节数（包括结尾的'\0'），按8字节对齐
+    fn reclen(name_len: usize) -> usize {
+        (Self::NAME_OFFSET + name_len + 1).next_multiple_of(core::mem::align_of::<Dirent>())
+    }
+}
+
 impl Metadata {
     pub fn new(file_type: FileType, mode: ModeType) -> Self {
         Metadata {
@@ -1016,15 +1081,26 @@ macro_rules! define_filesystem_maker_slice {
 /// 调用指定数组中的所有初始化器
 #[macro_export]
 macro_rules! producefs {
-    ($initializer_slice:ident,$filesystem:ident,$raw_data : ident) => {
+    ($initializer_slice:ident,$filesystem:ident,$source:ident,$raw_data : ident) => {
         match $initializer_slice.iter().find(|&m| m.name == $filesystem) {
             Some(maker) => {
-                let mount_data = match $filesystem {
-                    "overlay" => OverlayMountData::from_row($raw_data).ok(),
-                    _ => None,
-                };
-                let data: Option<&dyn FileSystemMakerData> =
-                    mount_data.as_ref().map(|d| d as &dyn FileSystemMakerData);
+                let mount_data: Option<alloc::boxed::Box<dyn FileSystemMakerData>> =
+                    match $filesystem {
+                        "overlay" => OverlayMountData::from_row($raw_data)
+                            .ok()
+                            .map(|d| alloc::boxed::Box::new(d) as _),
+                        "cifs" => SmbMountData::from_row($raw_data)
+                            .ok()
+                            .map(|d| alloc::boxed::Box::new(d) as _),
+                        "tmpfs" => TmpfsMountData::from_row($raw_data)
+                            .ok()
+                            .map(|d| alloc::boxed::Box::new(d) as _),
+                        "vfat" => FatMountData::from_source($source)
+                            .ok()
+                            .map(|d| alloc::boxed::Box::new(d) as _),
+                        _ => None,
+                    };
+                let data: Option<&dyn FileSystemMakerData> = mount_data.as_deref();
 
                 maker.call(data)
             }
@@ -21,15 +21,32 @@ use crate::{
         spinlock::{SpinLock, SpinLockGuard},
     },
     mm::{fault::PageFaultMessage, VmFaultReason},
+    time::PosixTimeSpec,
 };
 
 use super::{
-    file::FileMode, syscall::ModeType, utils::DName, FilePrivateData, FileSystem, FileType,
-    IndexNode, InodeId, Magic, SuperBlock,
+    automount::AutomountPoint,
+    dcache,
+    file::FileMode,
+    syscall::{ModeType, MountFlags},
+    utils::DName,
+    FilePrivateData, FileSystem, FileType, IndexNode, InodeId, Magic, SuperBlock,
 };
 
 const MOUNTFS_BLOCK_SIZE: u64 = 512;
 const MOUNTFS_MAX_NAMELEN: u64 = 64;
+
+/// statfs返回的f_flags，见Linux的`ST_*`
+const ST_RDONLY: u64 = 1;
+const ST_NOSUID: u64 = 2;
+const ST_NODEV: u64 = 4;
+const ST_NOEXEC: u64 = 8;
+const ST_SYNCHRONOUS: u64 = 16;
+const ST_MANDLOCK: u64 = 64;
+const ST_NOATIME: u64 = 1024;
+const ST_NODIRATIME: u64 = 2048;
+const ST_RELATIME: u64 = 4096;
+
 /// @brief 挂载文件系统
 /// 挂载文件系统的时候，套了MountFS这一层，以实现文件系统的递归挂载
 #[derive(Debug)]
@@ -42,6 +59,10 @@ pub struct MountFS {
     self_mountpoint: Option<Arc<MountFSInode>>,
     /// 指向当前MountFS的弱引用
     self_ref: Weak<MountFS>,
+    /// 挂载标志位，只包含[`MountFlags::MS_PER_MOUNT`]中的位
+    flags: SpinLock<MountFlags>,
+    /// 用来存储InodeID->自动挂载点的B树
+    automounts: SpinLock<BTreeMap<InodeId, Arc<AutomountPoint>>>,
 }
 
 /// @brief MountFS的Index Node 注意，这个IndexNode只是一个中间层。它的目的是将具体文件系统的Inode与挂载机制连接在一起。
@@ -66,6 +87,8 @@ impl MountFS {
             mountpoints: SpinLock::new(BTreeMap::new()),
             self_mountpoint,
             self_ref: self_ref.clone(),
+            flags: SpinLock::new(MountFlags::empty()),
+            automounts: SpinLock::new(BTreeMap::new()),
         });
     }
 
@@ -106,6 +129,44 @@ impl MountFS {
         self.self_ref.upgrade().unwrap()
     }
 
+    /// 当前挂载的标志位
+    pub fn flags(&self) -> MountFlags {
+        *self.flags.lock()
+    }
+
+    /// 设置挂载的标志位，不属于单个挂载的位会被忽略
+    pub fn set_flags(&self, flags: MountFlags) {
+        *self.flags.lock() = flags & MountFlags::MS_PER_MOUNT;
+    }
+
+    /// 是否为只读挂载
+    pub fn is_readonly(&self) -> bool {
+        self.flags().contains(MountFlags::MS_RDONLY)
+    }
+
+    /// 只读挂载时返回`EROFS`，在所有会修改文件系统的操作之前调用
+    fn check_writable(&self) -> Result<(), SystemError> {
+        if self.is_readonly() {
+            return Err(SystemError::EROFS);
+        }
+        return Ok(());
+    }
+
+    /// # 文件系统出错时把它强制改为只读
+    ///
+    /// 底层设备写入失败时，继续写入只会让磁盘上的数据更加不一致，因此之后的修改都返回`EROFS`，
+    /// 已经写入的数据仍然可以读取
+    pub fn emergency_remount_ro(&self) {
+        let mut flags = self.flags.lock();
+        if !flags.contains(MountFlags::MS_RDONLY) {
+            flags.insert(MountFlags::MS_RDONLY);
+            log::warn!(
+                "{}: error detected, remounting filesystem read-only",
+                self.inner_filesystem.name()
+            );
+        }
+    }
+
     /// 卸载文件系统
     /// # Errors
     /// 如果当前文件系统是根文件系统，那么将会返回`EINVAL`
@@ -142,11 +203,80 @@ impl MountFSInode {
     }
 
     /// @brief 判断当前inode是否为它所在的文件系统的root inode
-    fn is_mountpoint_root(&self) -> Result<bool, SystemError> {
+    pub fn is_mountpoint_root(&self) -> Result<bool, SystemError> {
         return Ok(self.inner_inode.fs().root_inode().metadata()?.inode_id
             == self.inner_inode.metadata()?.inode_id);
     }
 
+    /// # 检查能否修改当前inode的内容
+    ///
+    /// 只读挂载上的设备文件、管道等不保存在文件系统中，仍然可以写入
+    fn check_content_writable(&self) -> Result<(), SystemError> {
+        if !self.mount_fs.is_readonly() {
+            return Ok(());
+        }
+        match self.inner_inode.metadata()?.file_type {
+            FileType::File | FileType::Dir | FileType::SymLink => Err(SystemError::EROFS),
+            _ => Ok(()),
+        }
+    }
+
+    /// 获取挂载在当前inode上的文件系统
+    pub(super) fn sub_mount(&self) -> Result<Option<Arc<MountFS>>, SystemError> {
+        let inode_id = self.inner_inode.metadata()?.inode_id;
+        return Ok(self.mount_fs.mountpoints.lock().get(&inode_id).cloned());
+    }
+
+    /// # 设置或者取消当前目录的自动挂载点
+    ///
+    /// ## 返回值
+    /// - `Err(SystemError::EBUSY)`: 设置时，当前目录已经是自动挂载点
+    /// - `Err(SystemError::ENOENT)`: 取消时，当前目录不是自动挂载点
+    pub(super) fn set_automount(
+        &self,
+        point: Option<Arc<AutomountPoint>>,
+    ) -> Result<(), SystemError> {
+        let inode_id = self.inner_inode.metadata()?.inode_id;
+        let mut automounts = self.mount_fs.automounts.lock();
+        match point {
+            Some(point) => {
+                if automounts.contains_key(&inode_id) {
+                    return Err(SystemError::EBUSY);
+                }
+                automounts.insert(inode_id, point);
+            }
+            None => {
+                automounts.remove(&inode_id).ok_or(SystemError::ENOENT)?;
+            }
+        }
+        return Ok(());
+    }
+
+    /// 当前inode是自动挂载点时，确保文件系统已经挂载上去
+    fn follow_automount(&self) -> Result<(), SystemError> {
+        if self.mount_fs.automounts.lock().is_empty() {
+            return Ok(());
+        }
+        let inode_id = self.inner_inode.metadata()?.inode_id;
+        let point = self.mount_fs.automounts.lock().get(&inode_id).cloned();
+        match point {
+            Some(point) => point.follow(self),
+            None => Ok(()),
+        }
+    }
+
+    /// # 获取以当前inode为根的挂载
+    ///
+    /// ## 返回值
+    /// - `Ok(Arc<MountFS>)`: 当前inode所在的挂载
+    /// - `Err(SystemError::EINVAL)`: 当前inode不是挂载点的根
+    pub fn mount_root_fs(&self) -> Result<Arc<MountFS>, SystemError> {
+        if !self.is_mountpoint_root()? {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(self.mount_fs.clone());
+    }
+
     /// @brief 在挂载树上进行inode替换。
     /// 如果当前inode是父MountFS内的一个挂载点，那么，本函数将会返回挂载到这个挂载点下的文件系统的root inode.
     /// 如果当前inode在父MountFS内，但不是挂载点，那么说明在这里不需要进行inode替换，因此直接返回当前inode。
@@ -165,13 +295,14 @@ impl MountFSInode {
     fn do_find(&self, name: &str) -> Result<Arc<MountFSInode>, SystemError> {
         // 直接调用当前inode所在的文件系统的find方法进行查找
         // 由于向下查找可能会跨越文件系统的边界，因此需要尝试替换inode
-        let inner_inode = self.inner_inode.find(name)?;
-        return Ok(Arc::new_cyclic(|self_ref| MountFSInode {
+        let inner_inode = dcache::lookup(&self.inner_inode, name)?;
+        let inode = Arc::new_cyclic(|self_ref| MountFSInode {
             inner_inode,
             mount_fs: self.mount_fs.clone(),
             self_ref: self_ref.clone(),
-        })
-        .overlaid_inode());
+        });
+        inode.follow_automount()?;
+        return Ok(inode.overlaid_inode());
     }
 
     pub(super) fn do_parent(&self) -> Result<Arc<MountFSInode>, SystemError> {
@@ -206,12 +337,14 @@ impl MountFSInode {
         if self.metadata()?.file_type != FileType::Dir {
             return Err(SystemError::ENOTDIR);
         }
-        return self
+        let mount_fs = self
             .mount_fs
             .mountpoints
             .lock()
             .remove(&self.inner_inode.metadata()?.inode_id)
-            .ok_or(SystemError::ENOENT);
+            .ok_or(SystemError::ENOENT)?;
+        dcache::invalidate_fs(&mount_fs.inner_filesystem());
+        return Ok(mount_fs);
     }
 
     fn do_absolute_path(&self) -> Result<String, SystemError> {
@@ -246,6 +379,9 @@ impl IndexNode for MountFSInode {
         data: SpinLockGuard<FilePrivateData>,
         mode: &FileMode,
     ) -> Result<(), SystemError> {
+        if mode.accmode() != FileMode::O_RDONLY.bits() {
+            self.check_content_writable()?;
+        }
         return self.inner_inode.open(data, mode);
     }
 
@@ -260,9 +396,11 @@ impl IndexNode for MountFSInode {
         mode: ModeType,
         data: usize,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.mount_fs.check_writable()?;
         let inner_inode = self
             .inner_inode
             .create_with_data(name, file_type, mode, data)?;
+        dcache::invalidate(&self.inner_inode, name);
         return Ok(Arc::new_cyclic(|self_ref| MountFSInode {
             inner_inode,
             mount_fs: self.mount_fs.clone(),
@@ -271,6 +409,7 @@ impl IndexNode for MountFSInode {
     }
 
     fn truncate(&self, len: usize) -> Result<(), SystemError> {
+        self.mount_fs.check_writable()?;
         return self.inner_inode.truncate(len);
     }
 
@@ -291,6 +430,7 @@ impl IndexNode for MountFSInode {
         buf: &[u8],
         data: SpinLockGuard<FilePrivateData>,
     ) -> Result<usize, SystemError> {
+        self.check_content_writable()?;
         return self.inner_inode.write_at(offset, len, buf, data);
     }
 
@@ -311,6 +451,7 @@ impl IndexNode for MountFSInode {
         buf: &[u8],
         data: SpinLockGuard<FilePrivateData>,
     ) -> Result<usize, SystemError> {
+        self.check_content_writable()?;
         self.inner_inode.write_direct(offset, len, buf, data)
     }
 
@@ -331,11 +472,13 @@ impl IndexNode for MountFSInode {
 
     #[inline]
     fn set_metadata(&self, metadata: &super::Metadata) -> Result<(), SystemError> {
+        self.mount_fs.check_writable()?;
         return self.inner_inode.set_metadata(metadata);
     }
 
     #[inline]
     fn resize(&self, len: usize) -> Result<(), SystemError> {
+        self.check_content_writable()?;
         return self.inner_inode.resize(len);
     }
 
@@ -346,7 +489,9 @@ impl IndexNode for MountFSInode {
         file_type: FileType,
         mode: ModeType,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.mount_fs.check_writable()?;
         let inner_inode = self.inner_inode.create(name, file_type, mode)?;
+        dcache::invalidate(&self.inner_inode, name);
         return Ok(Arc::new_cyclic(|self_ref| MountFSInode {
             inner_inode,
             mount_fs: self.mount_fs.clone(),
@@ -355,12 +500,16 @@ impl IndexNode for MountFSInode {
     }
 
     fn link(&self, name: &str, other: &Arc<dyn IndexNode>) -> Result<(), SystemError> {
-        return self.inner_inode.link(name, other);
+        self.mount_fs.check_writable()?;
+        self.inner_inode.link(name, other)?;
+        dcache::invalidate(&self.inner_inode, name);
+        return Ok(());
     }
 
     /// @brief 在挂载文件系统中删除文件/文件夹
     #[inline]
     fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        self.mount_fs.check_writable()?;
         let inode_id = self.inner_inode.find(name)?.metadata()?.inode_id;
 
         // 先检查这个inode是否为一个挂载点，如果当前inode是一个挂载点，那么就不能删除这个inode
@@ -368,11 +517,15 @@ impl IndexNode for MountFSInode {
             return Err(SystemError::EBUSY);
         }
         // 调用内层的inode的方法来删除这个inode
-        return self.inner_inode.unlink(name);
+        let _rename_guard = dcache::rename_write_begin();
+        self.inner_inode.unlink(name)?;
+        dcache::invalidate(&self.inner_inode, name);
+        return Ok(());
     }
 
     #[inline]
     fn rmdir(&self, name: &str) -> Result<(), SystemError> {
+        self.mount_fs.check_writable()?;
         let inode_id = self.inner_inode.find(name)?.metadata()?.inode_id;
 
         // 先检查这个inode是否为一个挂载点，如果当前inode是一个挂载点，那么就不能删除这个inode
@@ -380,9 +533,12 @@ impl IndexNode for MountFSInode {
             return Err(SystemError::EBUSY);
         }
         // 调用内层的rmdir的方法来删除这个inode
-        let r = self.inner_inode.rmdir(name);
-
-        return r;
+        let _rename_guard = dcache::rename_write_begin();
+        self.inner_inode.rmdir(name)?;
+        self.mount_fs.automounts.lock().remove(&inode_id);
+        dcache::invalidate(&self.inner_inode, name);
+        dcache::invalidate_children(&self.inner_inode.fs(), inode_id);
+        return Ok(());
     }
 
     #[inline]
@@ -392,7 +548,29 @@ impl IndexNode for MountFSInode {
         target: &Arc<dyn IndexNode>,
         new_name: &str,
     ) -> Result<(), SystemError> {
-        return self.inner_inode.move_to(old_name, target, new_name);
+        self.mount_fs.check_writable()?;
+        let target_inner = target
+            .clone()
+            .downcast_arc::<MountFSInode>()
+            .map(|inode| inode.inner_inode.clone())
+            .unwrap_or_else(|| target.clone());
+        // 被覆盖的目录的inode号可能被复用，需要清除以它为父目录的目录项
+        let replaced = target_inner
+            .find(new_name)
+            .and_then(|inode| inode.metadata())
+            .ok()
+            .filter(|md| md.file_type == FileType::Dir)
+            .map(|md| md.inode_id);
+
+        let _rename_guard = dcache::rename_write_begin();
+        self.inner_inode.move_to(old_name, target, new_name)?;
+
+        dcache::invalidate(&self.inner_inode, old_name);
+        dcache::invalidate(&target_inner, new_name);
+        if let Some(inode_id) = replaced {
+            dcache::invalidate_children(&target_inner.fs(), inode_id);
+        }
+        return Ok(());
     }
 
     fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
@@ -436,12 +614,21 @@ impl IndexNode for MountFSInode {
     }
 
     #[inline]
-    fn kernel_ioctl(
+    fn add_epitem(
         &self,
-        arg: Arc<dyn crate::net::event_poll::KernelIoctlData>,
-        data: &FilePrivateData,
-    ) -> Result<usize, SystemError> {
-        return self.inner_inode.kernel_ioctl(arg, data);
+        epitem: Arc<crate::net::event_poll::EPollItem>,
+        private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        return self.inner_inode.add_epitem(epitem, private_data);
+    }
+
+    #[inline]
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<crate::net::event_poll::EventPoll>>,
+        private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        return self.inner_inode.remove_epitem(epoll, private_data);
     }
 
     #[inline]
@@ -515,7 +702,9 @@ impl IndexNode for MountFSInode {
         mode: ModeType,
         dev_t: DeviceNumber,
     ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.mount_fs.check_writable()?;
         let inner_inode = self.inner_inode.mknod(filename, mode, dev_t)?;
+        dcache::invalidate(&self.inner_inode, filename);
         return Ok(Arc::new_cyclic(|self_ref| MountFSInode {
             inner_inode,
             mount_fs: self.mount_fs.clone(),
@@ -553,6 +742,16 @@ impl IndexNode for MountFSInode {
     fn page_cache(&self) -> Option<Arc<PageCache>> {
         self.inner_inode.page_cache()
     }
+
+    #[inline]
+    fn btime(&self) -> Option<PosixTimeSpec> {
+        self.inner_inode.btime()
+    }
+
+    #[inline]
+    fn revalidate(&self) -> Result<(), SystemError> {
+        self.inner_inode.revalidate()
+    }
 }
 
 impl FileSystem for MountFS {
@@ -578,7 +777,24 @@ impl FileSystem for MountFS {
         "mountfs"
     }
     fn super_block(&self) -> SuperBlock {
-        SuperBlock::new(Magic::MOUNT_MAGIC, MOUNTFS_BLOCK_SIZE, MOUNTFS_MAX_NAMELEN)
+        let mut sb = SuperBlock::new(Magic::MOUNT_MAGIC, MOUNTFS_BLOCK_SIZE, MOUNTFS_MAX_NAMELEN);
+        let flags = self.flags();
+        for (mount_flag, st_flag) in [
+            (MountFlags::MS_RDONLY, ST_RDONLY),
+            (MountFlags::MS_NOSUID, ST_NOSUID),
+            (MountFlags::MS_NODEV, ST_NODEV),
+            (MountFlags::MS_NOEXEC, ST_NOEXEC),
+            (MountFlags::MS_SYNCHRONOUS, ST_SYNCHRONOUS),
+            (MountFlags::MS_MANDLOCK, ST_MANDLOCK),
+            (MountFlags::MS_NOATIME, ST_NOATIME),
+            (MountFlags::MS_NODIRATIME, ST_NODIRATIME),
+            (MountFlags::MS_RELATIME, ST_RELATIME),
+        ] {
+            if flags.contains(mount_flag) {
+                sb.flags |= st_flag;
+            }
+        }
+        sb
     }
 
     unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
@@ -752,6 +968,41 @@ impl MountList {
     pub fn remove<T: Into<MountPath>>(&self, path: T) -> Option<Arc<MountFS>> {
         self.0.write().remove(&path.into())
     }
+
+    /// # find_by_inner_fs - 查找包装了指定文件系统的挂载
+    ///
+    /// ## 参数
+    ///
+    /// - `fs: &dyn FileSystem`: 具体的文件系统（不是MountFS）
+    ///
+    /// ## 返回值
+    ///
+    /// - `Vec<Arc<MountFS>>`: 所有内部文件系统为`fs`的挂载
+    pub fn find_by_inner_fs(&self, fs: &dyn FileSystem) -> Vec<Arc<MountFS>> {
+        let fs_ptr = fs as *const dyn FileSystem as *const u8;
+        self.0
+            .read()
+            .values()
+            .filter(|mount_fs| Arc::as_ptr(&mount_fs.inner_filesystem) as *const u8 == fs_ptr)
+            .cloned()
+            .collect()
+    }
+}
+
+/// # fs_error_remount_ro - 文件系统出错时把它的所有挂载改为只读
+///
+/// 供具体文件系统在写入元数据失败等无法恢复的错误时调用，避免继续写入损坏磁盘上的数据
+pub fn fs_error_remount_ro(fs: &dyn FileSystem) {
+    let fs_ptr = fs as *const dyn FileSystem as *const u8;
+    // 根文件系统的挂载不在挂载列表中
+    if let Some(root_fs) = ROOT_INODE().fs().downcast_arc::<MountFS>() {
+        if Arc::as_ptr(&root_fs.inner_filesystem) as *const u8 == fs_ptr {
+            root_fs.emergency_remount_ro();
+        }
+    }
+    for mount_fs in MOUNT_LIST().find_by_inner_fs(fs) {
+        mount_fs.emergency_remount_ro();
+    }
 }
 
 impl Debug for MountList {
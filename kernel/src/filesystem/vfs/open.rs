@@ -5,20 +5,24 @@ use system_error::SystemError;
 use super::{
     fcntl::AtFlags,
     file::{File, FileMode},
+    resolve::{lookup_resolve, resolve_start_dir},
     syscall::{ModeType, OpenHow, OpenHowResolve},
     utils::{rsplit_path, user_path_at},
-    FileType, IndexNode, MAX_PATHLEN, ROOT_INODE, VFS_MAX_FOLLOW_SYMLINK_TIMES,
+    FileType, IndexNode, MAX_PATHLEN, VFS_MAX_FOLLOW_SYMLINK_TIMES,
 };
 use crate::{
     driver::base::block::SeekFrom, process::ProcessManager,
     syscall::user_access::check_and_clone_cstr,
 };
-use crate::{filesystem::vfs::syscall::UtimensFlags, process::cred::Kgid};
+use crate::{
+    filesystem::vfs::syscall::UtimensFlags,
+    process::cred::{CAPFlags, Kgid},
+};
 use crate::{
     process::cred::GroupInfo,
     time::{syscall::PosixTimeval, PosixTimeSpec},
 };
-use alloc::string::String;
+use alloc::{format, string::String};
 
 pub(super) fn do_faccessat(
     dirfd: i32,
@@ -38,7 +42,7 @@ pub(super) fn do_faccessat(
         return Err(SystemError::EINVAL);
     }
 
-    // let follow_symlink = flags & AtFlags::AT_SYMLINK_NOFOLLOW.bits() as u32 == 0;
+    let follow_symlink = flags & AtFlags::AT_SYMLINK_NOFOLLOW.bits() as u32 == 0;
 
     let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?;
     let path = path.to_str().map_err(|_| SystemError::EINVAL)?;
@@ -46,7 +50,11 @@ pub(super) fn do_faccessat(
     let (inode, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, path)?;
 
     // 如果找不到文件，则返回错误码ENOENT
-    let _inode = inode.lookup_follow_symlink(path.as_str(), VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
+    let _inode = inode.lookup_follow_symlink2(
+        path.as_str(),
+        VFS_MAX_FOLLOW_SYMLINK_TIMES,
+        follow_symlink,
+    )?;
 
     // todo: 接着完善（可以借鉴linux 6.1.9的do_faccessat）
     return Ok(0);
@@ -75,36 +83,43 @@ pub fn do_fchownat(
     flag: AtFlags,
 ) -> Result<usize, SystemError> {
     // 检查flag是否合法
-    if flag.contains(!(AtFlags::AT_SYMLINK_NOFOLLOW | AtFlags::AT_EMPTY_PATH)) {
+    if !(AtFlags::AT_SYMLINK_NOFOLLOW | AtFlags::AT_EMPTY_PATH).contains(flag) {
         return Err(SystemError::EINVAL);
     }
 
-    let follow_symlink = flag.contains(!AtFlags::AT_SYMLINK_NOFOLLOW);
+    let follow_symlink = !flag.contains(AtFlags::AT_SYMLINK_NOFOLLOW);
     let (inode, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, path)?;
 
     // 如果找不到文件，则返回错误码ENOENT
-    let inode = if follow_symlink {
-        inode.lookup_follow_symlink2(path.as_str(), VFS_MAX_FOLLOW_SYMLINK_TIMES, false)
-    } else {
-        inode.lookup(path.as_str())
-    };
-
-    if inode.is_err() {
-        let errno = inode.clone().unwrap_err();
-        // 文件不存在
-        if errno == SystemError::ENOENT {
-            return Err(SystemError::ENOENT);
-        }
-    }
-
-    let inode = inode.unwrap();
+    let inode = inode.lookup_follow_symlink2(
+        path.as_str(),
+        VFS_MAX_FOLLOW_SYMLINK_TIMES,
+        follow_symlink,
+    )?;
 
     return chown_common(inode, uid, gid);
 }
 
+/// 修改文件的uid与gid。`uid`、`gid`是当前user namespace中的id，为-1时不修改
 fn chown_common(inode: Arc<dyn IndexNode>, uid: usize, gid: usize) -> Result<usize, SystemError> {
     let mut meta = inode.metadata()?;
     let cred = ProcessManager::current_pcb().cred();
+    let uid = if uid as u32 == u32::MAX {
+        meta.uid
+    } else {
+        cred.user_ns
+            .make_kuid(uid)
+            .ok_or(SystemError::EINVAL)?
+            .data()
+    };
+    let gid = if gid as u32 == u32::MAX {
+        meta.gid
+    } else {
+        cred.user_ns
+            .make_kgid(gid)
+            .ok_or(SystemError::EINVAL)?
+            .data()
+    };
     let current_uid = cred.uid.data();
     let current_gid = cred.gid.data();
     let mut group_info = GroupInfo::default();
@@ -113,21 +128,18 @@ fn chown_common(inode: Arc<dyn IndexNode>, uid: usize, gid: usize) -> Result<usi
     }
 
     // 检查权限
-    match current_uid {
-        0 => {
-            meta.uid = uid;
-            meta.gid = gid;
+    if cred.capable_wrt_inode_uidgid(meta.uid, meta.gid, CAPFlags::CAP_CHOWN) {
+        meta.uid = uid;
+        meta.gid = gid;
+    } else {
+        // 非文件所有者不能更改信息，且不能更改uid
+        if current_uid != meta.uid || uid != meta.uid {
+            return Err(SystemError::EPERM);
         }
-        _ => {
-            // 非文件所有者不能更改信息，且不能更改uid
-            if current_uid != meta.uid || uid != meta.uid {
-                return Err(SystemError::EPERM);
-            }
-            if gid != current_gid && !group_info.gids.contains(&Kgid::from(gid)) {
-                return Err(SystemError::EPERM);
-            }
-            meta.gid = gid;
+        if gid != current_gid && !group_info.gids.contains(&Kgid::from(gid)) {
+            return Err(SystemError::EPERM);
         }
+        meta.gid = gid;
     }
 
     meta.mode.remove(ModeType::S_ISUID | ModeType::S_ISGID);
@@ -160,7 +172,7 @@ pub(super) fn do_sys_open(
     return do_sys_openat2(dfd, path, how, follow_symlink);
 }
 
-fn do_sys_openat2(
+pub(super) fn do_sys_openat2(
     dirfd: i32,
     path: &str,
     how: OpenHow,
@@ -169,15 +181,44 @@ fn do_sys_openat2(
     // debug!("open path: {}, how: {:?}", path, how);
     let path = path.trim();
 
-    let (inode_begin, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, path)?;
-    let inode: Result<Arc<dyn IndexNode>, SystemError> = inode_begin.lookup_follow_symlink(
-        &path,
-        if follow_symlink {
-            VFS_MAX_FOLLOW_SYMLINK_TIMES
-        } else {
-            0
-        },
+    // RESOLVE_NO_SYMLINKS: 路径中出现任何需要跟随的符号链接都返回ELOOP
+    let max_follow_times = if how.resolve.contains(OpenHowResolve::RESOLVE_NO_SYMLINKS) {
+        0
+    } else {
+        VFS_MAX_FOLLOW_SYMLINK_TIMES
+    };
+    let follow_final_symlink = follow_symlink && !how.o_flags.contains(FileMode::O_NOFOLLOW);
+
+    // 需要限制路径解析的范围时，从dirfd对应的目录开始逐级解析，而不是拼接出绝对路径
+    let restricted = how.resolve.intersects(
+        OpenHowResolve::RESOLVE_BENEATH
+            | OpenHowResolve::RESOLVE_IN_ROOT
+            | OpenHowResolve::RESOLVE_NO_MAGICLINKS
+            | OpenHowResolve::RESOLVE_NO_XDEV,
     );
+    let (inode_begin, path) = if restricted {
+        (
+            resolve_start_dir(&ProcessManager::current_pcb(), dirfd)?,
+            String::from(path),
+        )
+    } else {
+        user_path_at(&ProcessManager::current_pcb(), dirfd, path)?
+    };
+    let lookup = |path: &str, follow_final_symlink: bool| {
+        if restricted {
+            lookup_resolve(
+                &inode_begin,
+                path,
+                how.resolve,
+                max_follow_times,
+                follow_final_symlink,
+            )
+        } else {
+            inode_begin.lookup_follow_symlink2(path, max_follow_times, follow_final_symlink)
+        }
+    };
+    let inode: Result<Arc<dyn IndexNode>, SystemError> =
+        lookup(path.as_str(), follow_final_symlink);
 
     let inode: Arc<dyn IndexNode> = match inode {
         Ok(inode) => inode,
@@ -189,8 +230,15 @@ fn do_sys_openat2(
             {
                 let (filename, parent_path) = rsplit_path(&path);
                 // 查找父目录
-                let parent_inode: Arc<dyn IndexNode> =
-                    ROOT_INODE().lookup(parent_path.unwrap_or("/"))?;
+                // rsplit_path会去掉开头的“/”，绝对路径的父目录需要重新补上
+                let parent_inode: Arc<dyn IndexNode> = match (parent_path, path.starts_with('/')) {
+                    (Some(parent_path), true) => {
+                        lookup(format!("/{}", parent_path).as_str(), true)?
+                    }
+                    (Some(parent_path), false) => lookup(parent_path, true)?,
+                    (None, true) => lookup("/", true)?,
+                    (None, false) => inode_begin.clone(),
+                };
                 // 创建文件
                 let inode: Arc<dyn IndexNode> = parent_inode.create(
                     filename,
@@ -206,6 +254,11 @@ fn do_sys_openat2(
     };
 
     let file_type: FileType = inode.metadata()?.file_type;
+    // O_NOFOLLOW: 路径的最后一级是符号链接时，除非同时指定了O_PATH，否则返回ELOOP
+    if follow_symlink && file_type == FileType::SymLink && !how.o_flags.contains(FileMode::O_PATH) {
+        return Err(SystemError::ELOOP);
+    }
+
     // 如果要打开的是文件夹，而目标不是文件夹
     if how.o_flags.contains(FileMode::O_DIRECTORY) && file_type != FileType::Dir {
         return Err(SystemError::ENOTDIR);
@@ -256,12 +309,11 @@ pub fn do_utimensat(
         Some(path) => {
             let (inode_begin, path) =
                 user_path_at(&ProcessManager::current_pcb(), dirfd, path.as_str())?;
-            let inode = if flags.contains(UtimensFlags::AT_SYMLINK_NOFOLLOW) {
-                inode_begin.lookup(path.as_str())?
-            } else {
-                inode_begin.lookup_follow_symlink(path.as_str(), VFS_MAX_FOLLOW_SYMLINK_TIMES)?
-            };
-            inode
+            inode_begin.lookup_follow_symlink2(
+                path.as_str(),
+                VFS_MAX_FOLLOW_SYMLINK_TIMES,
+                !flags.contains(UtimensFlags::AT_SYMLINK_NOFOLLOW),
+            )?
         }
         None => {
             let binding = ProcessManager::current_pcb().fd_table();
@@ -286,11 +338,11 @@ pub fn do_utimensat(
         } else if mtime.tv_nsec != UTIME_OMIT {
             meta.mtime = mtime;
         }
-        inode.set_metadata(&meta).unwrap();
+        inode.set_metadata(&meta)?;
     } else {
         meta.atime = now;
         meta.mtime = now;
-        inode.set_metadata(&meta).unwrap();
+        inode.set_metadata(&meta)?;
     }
     return Ok(0);
 }
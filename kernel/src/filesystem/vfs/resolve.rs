@@ -0,0 +1,217 @@
+//! 受`RESOLVE_*`标志约束的路径解析，供openat2使用
+//!
+//! 与`lookup_follow_symlink`不同，这里记录了从起始目录出发所经过的目录，
+//! 从而能够判断`..`与符号链接是否会越出起始目录：
+//!
+//! - `RESOLVE_BENEATH`: 绝对路径、绝对路径的符号链接以及越出起始目录的`..`都返回`EXDEV`
+//! - `RESOLVE_IN_ROOT`: 把起始目录当作根目录，`/`与根目录下的`..`都停留在起始目录
+//! - `RESOLVE_NO_MAGICLINKS`: 不允许跟随procfs中的“魔法链接”，返回`ELOOP`
+//! - `RESOLVE_NO_XDEV`: 解析过程中经过的目录与结果都必须与起始目录位于同一挂载点，否则返回`EXDEV`
+
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    filesystem::procfs::LockedProcFSInode, libs::spinlock::SpinLock, process::ProcessControlBlock,
+};
+
+use super::{
+    dcache, fcntl::AtFlags, file::FilePrivateData, syscall::OpenHowResolve, FileType, IndexNode,
+    MAX_PATHLEN, ROOT_INODE,
+};
+
+/// 根据dirfd获取路径解析的起始目录
+///
+/// 与`user_path_at`不同，相对于当前工作目录的解析也会返回当前工作目录的inode，
+/// 而不是把路径拼接到工作目录之后，以便确定`RESOLVE_BENEATH`与`RESOLVE_IN_ROOT`的边界。
+pub(super) fn resolve_start_dir(
+    pcb: &Arc<ProcessControlBlock>,
+    dirfd: i32,
+) -> Result<Arc<dyn IndexNode>, SystemError> {
+    if dirfd == AtFlags::AT_FDCWD.bits() {
+        let cwd = pcb.basic().cwd();
+        return ROOT_INODE().lookup(&cwd);
+    }
+
+    let file = pcb
+        .fd_table()
+        .read()
+        .get_file_by_fd(dirfd)
+        .ok_or(SystemError::EBADF)?;
+    if file.file_type() != FileType::Dir {
+        return Err(SystemError::ENOTDIR);
+    }
+    return Ok(file.inode());
+}
+
+/// 从`start`开始解析`path`
+///
+/// ## 参数
+///
+/// - `start`: 起始目录。指定了`RESOLVE_BENEATH`或`RESOLVE_IN_ROOT`时，同时也是解析的边界
+/// - `path`: 要解析的路径
+/// - `resolve`: RESOLVE_*标志
+/// - `max_follow_times`: 整个解析过程中最多跟随的符号链接数量
+/// - `follow_final_symlink`: 是否跟随最后的符号链接
+pub(super) fn lookup_resolve(
+    start: &Arc<dyn IndexNode>,
+    path: &str,
+    resolve: OpenHowResolve,
+    max_follow_times: usize,
+    follow_final_symlink: bool,
+) -> Result<Arc<dyn IndexNode>, SystemError> {
+    return dcache::rename_read_consistent(|| {
+        do_lookup_resolve(start, path, resolve, max_follow_times, follow_final_symlink)
+    });
+}
+
+fn do_lookup_resolve(
+    start: &Arc<dyn IndexNode>,
+    path: &str,
+    resolve: OpenHowResolve,
+    max_follow_times: usize,
+    follow_final_symlink: bool,
+) -> Result<Arc<dyn IndexNode>, SystemError> {
+    let scoped =
+        resolve.intersects(OpenHowResolve::RESOLVE_BENEATH | OpenHowResolve::RESOLVE_IN_ROOT);
+    // 从起始目录（或根目录）到当前目录所经过的目录，栈底为解析的边界
+    let mut stack: Vec<Arc<dyn IndexNode>> = vec![start.clone()];
+    let mut follow_left = max_follow_times;
+
+    let mut rest_path = match path.strip_prefix('/') {
+        Some(rest) => {
+            jump_root(&mut stack, resolve)?;
+            String::from(rest)
+        }
+        None => String::from(path),
+    };
+
+    while !rest_path.is_empty() {
+        let current = stack.last().unwrap().clone();
+        check_xdev(start, &current, resolve)?;
+        if current.metadata()?.file_type != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
+
+        let name = match rest_path.find('/') {
+            Some(pos) => {
+                let name = String::from(&rest_path[..pos]);
+                rest_path = String::from(&rest_path[pos + 1..]);
+                name
+            }
+            None => core::mem::take(&mut rest_path),
+        };
+
+        match name.as_str() {
+            "" | "." => continue,
+            ".." => {
+                if stack.len() > 1 {
+                    stack.pop();
+                } else if resolve.contains(OpenHowResolve::RESOLVE_BENEATH) {
+                    return Err(SystemError::EXDEV);
+                } else if !scoped {
+                    stack[0] = current.find("..")?;
+                }
+                // RESOLVE_IN_ROOT: 停留在起始目录
+                continue;
+            }
+            _ => {}
+        }
+
+        let inode = current.find(&name)?;
+        if inode.metadata()?.file_type != FileType::SymLink
+            || (rest_path.is_empty() && !follow_final_symlink)
+        {
+            stack.push(inode);
+            continue;
+        }
+
+        if is_magic_link(&inode) {
+            if resolve.contains(OpenHowResolve::RESOLVE_NO_MAGICLINKS) {
+                return Err(SystemError::ELOOP);
+            }
+            if scoped {
+                return Err(SystemError::EXDEV);
+            }
+        }
+
+        // 需要跟随的符号链接过多，认为出现了循环
+        if follow_left == 0 {
+            return Err(SystemError::ELOOP);
+        }
+        follow_left -= 1;
+
+        let link_path = read_link(&inode)?;
+        let link_path = match link_path.strip_prefix('/') {
+            Some(rest) => {
+                jump_root(&mut stack, resolve)?;
+                String::from(rest)
+            }
+            None => link_path,
+        };
+        rest_path = link_path + "/" + &rest_path;
+    }
+
+    let result = stack.pop().unwrap();
+    check_xdev(start, &result, resolve)?;
+    return Ok(result);
+}
+
+/// 指定了`RESOLVE_NO_XDEV`时，检查`inode`是否与起始目录位于同一挂载点
+fn check_xdev(
+    start: &Arc<dyn IndexNode>,
+    inode: &Arc<dyn IndexNode>,
+    resolve: OpenHowResolve,
+) -> Result<(), SystemError> {
+    if !resolve.contains(OpenHowResolve::RESOLVE_NO_XDEV) {
+        return Ok(());
+    }
+    // 只比较数据指针，同一个挂载点的不同Arc<dyn FileSystem>的虚表指针可能不同
+    if Arc::as_ptr(&start.fs()) as *const () != Arc::as_ptr(&inode.fs()) as *const () {
+        return Err(SystemError::EXDEV);
+    }
+    return Ok(());
+}
+
+/// 处理解析过程中遇到的绝对路径
+fn jump_root(
+    stack: &mut Vec<Arc<dyn IndexNode>>,
+    resolve: OpenHowResolve,
+) -> Result<(), SystemError> {
+    if resolve.contains(OpenHowResolve::RESOLVE_BENEATH) {
+        return Err(SystemError::EXDEV);
+    }
+
+    if resolve.contains(OpenHowResolve::RESOLVE_IN_ROOT) {
+        stack.truncate(1);
+    } else {
+        stack.clear();
+        stack.push(ROOT_INODE());
+    }
+    return Ok(());
+}
+
+fn read_link(inode: &Arc<dyn IndexNode>) -> Result<String, SystemError> {
+    let mut content = vec![0u8; MAX_PATHLEN];
+    let len = inode.read_at(
+        0,
+        MAX_PATHLEN,
+        &mut content,
+        SpinLock::new(FilePrivateData::Unused).lock(),
+    )?;
+    return core::str::from_utf8(&content[..len])
+        .map(String::from)
+        .map_err(|_| SystemError::EINVAL);
+}
+
+/// 判断符号链接是否为“魔法链接”
+///
+/// `/proc/[pid]/fd/*`这类链接并不保存路径，而是直接指向内核对象，
+/// 跟随它们可以绕过路径解析的限制。`/proc/self`等保存普通路径的符号链接不属于魔法链接。
+fn is_magic_link(inode: &Arc<dyn IndexNode>) -> bool {
+    // MountFSInode的as_any_ref()返回的是被包裹的inode
+    return inode
+        .as_any_ref()
+        .downcast_ref::<LockedProcFSInode>()
+        .is_some_and(|inode| inode.is_magic_link());
+}
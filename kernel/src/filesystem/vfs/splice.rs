@@ -0,0 +1,277 @@
+//! sendfile/splice 系统调用
+//!
+//! 数据在内核中经由一个中转缓冲区从输入文件搬运到输出文件，不需要拷贝到用户态
+
+use alloc::{sync::Arc, vec::Vec};
+use log::warn;
+use system_error::SystemError;
+
+use crate::{
+    arch::MMArch,
+    driver::base::block::SeekFrom,
+    ipc::pipe::{LockedPipeInode, PIPE_BUFF_SIZE},
+    mm::MemoryManagementArch,
+    process::ProcessManager,
+    syscall::{user_access::UserPtr, Syscall},
+};
+
+use super::{file::File, FileType};
+
+bitflags! {
+    pub struct SpliceFlags: u32 {
+        /// 尝试移动页面而不是拷贝（仅作为提示）
+        const SPLICE_F_MOVE = 0x01;
+        /// 不阻塞在管道上（目前被忽略，是否阻塞取决于管道自身的O_NONBLOCK）
+        const SPLICE_F_NONBLOCK = 0x02;
+        /// 后续还有更多数据（仅作为提示）
+        const SPLICE_F_MORE = 0x04;
+        /// 仅用于vmsplice
+        const SPLICE_F_GIFT = 0x08;
+    }
+}
+
+/// splice/sendfile的一端
+struct SpliceEnd {
+    file: Arc<File>,
+    /// 为`None`时使用并更新文件自身的读写位置
+    offset: Option<usize>,
+}
+
+impl SpliceEnd {
+    fn new(fd: i32, offset: Option<usize>) -> Result<Self, SystemError> {
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        Ok(Self { file, offset })
+    }
+
+    fn is_pipe(&self) -> bool {
+        self.file.file_type() == FileType::Pipe
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, SystemError> {
+        match self.offset.as_mut() {
+            Some(offset) => {
+                let n = self.file.pread(*offset, buf.len(), buf)?;
+                *offset += n;
+                Ok(n)
+            }
+            None => self.file.read(buf.len(), buf),
+        }
+    }
+
+    /// 写入`buf`中的全部数据
+    ///
+    /// 写入了一部分之后出错时，返回已写入的字节数，不返回错误
+    fn write_all(&mut self, buf: &[u8]) -> Result<usize, SystemError> {
+        let mut written = 0;
+        while written < buf.len() {
+            let remain = &buf[written..];
+            let r = match self.offset.as_mut() {
+                Some(offset) => self.file.pwrite(*offset, remain.len(), remain).map(|n| {
+                    *offset += n;
+                    n
+                }),
+                None => self.file.write(remain.len(), remain),
+            };
+            let n = match r {
+                Ok(n) => n,
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            };
+            if n == 0 {
+                break;
+            }
+            written += n;
+        }
+        Ok(written)
+    }
+
+    /// 刚读出的`buf`没有被写出去，退回输入端，使输入端只前进实际写出的字节数
+    fn unread(&mut self, buf: &[u8]) -> Result<(), SystemError> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        if let Some(offset) = self.offset.as_mut() {
+            *offset -= buf.len();
+            return Ok(());
+        }
+        if self.is_pipe() {
+            let inode = self.file.inode();
+            let pipe = inode
+                .as_any_ref()
+                .downcast_ref::<LockedPipeInode>()
+                .ok_or(SystemError::EINVAL)?;
+            return pipe.unread(buf);
+        }
+        self.file
+            .lseek(SeekFrom::SeekCurrent(-(buf.len() as i64)))
+            .map(|_| ())
+    }
+}
+
+/// 在两个文件之间搬运至多`len`字节的数据
+///
+/// ## 参数
+///
+/// - `single_read`: 为true时只从输入端读取一次。输入端为管道时，
+///   读到数据后就返回，而不是阻塞等待写端填满`len`字节
+///
+/// ## 返回值
+///
+/// 成功搬运的字节数。若已经搬运了部分数据后出错，返回已搬运的字节数。
+/// 输入端（偏移量、文件位置或者管道中的数据）只前进实际写出的字节数
+fn do_splice(
+    input: &mut SpliceEnd,
+    output: &mut SpliceEnd,
+    len: usize,
+    single_read: bool,
+) -> Result<usize, SystemError> {
+    // 管道的写操作不能超过管道缓冲区的大小
+    let chunk = if input.is_pipe() || output.is_pipe() {
+        PIPE_BUFF_SIZE
+    } else {
+        MMArch::PAGE_SIZE
+    };
+    let mut buf: Vec<u8> = vec![0; chunk.min(len)];
+
+    let mut total = 0;
+    while total < len {
+        let to_read = (len - total).min(buf.len());
+        let n = match input.read(&mut buf[..to_read]) {
+            Ok(n) => n,
+            Err(e) if total == 0 => return Err(e),
+            Err(_) => break,
+        };
+        let r = output.write_all(&buf[..n]);
+        let written = r.as_ref().map_or(0, |&w| w);
+        // 没写出去的数据还给输入端，输入端只前进实际写出的字节数
+        if let Err(e) = input.unread(&buf[written..n]) {
+            warn!(
+                "splice: failed to give back {} bytes to the input: {:?}",
+                n - written,
+                e
+            );
+        }
+        let written = match r {
+            Ok(written) => written,
+            Err(e) if total == 0 => return Err(e),
+            Err(_) => break,
+        };
+
+        total += written;
+        if n == 0 || written < n || single_read {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// 从用户态读取偏移量
+fn read_user_offset(ptr: *const i64) -> Result<Option<usize>, SystemError> {
+    match UserPtr::from_ptr(ptr).read_opt()? {
+        Some(offset) if offset < 0 => Err(SystemError::EINVAL),
+        offset => Ok(offset.map(|offset| offset as usize)),
+    }
+}
+
+/// 把更新后的偏移量写回用户态
+fn write_user_offset(ptr: *mut i64, offset: Option<usize>) -> Result<(), SystemError> {
+    if let Some(offset) = offset {
+        UserPtr::from_ptr(ptr).write(&(offset as i64))?;
+    }
+    Ok(())
+}
+
+impl Syscall {
+    /// # 在两个文件描述符之间传输数据
+    ///
+    /// ## 参数
+    /// - `out_fd`: 输出文件描述符，可以是任意可写的文件（包括socket）
+    /// - `in_fd`: 输入文件描述符，不能是管道或socket
+    /// - `offset`: 不为空时，从`*offset`处开始读取，且不改变输入文件的读写位置，
+    ///   返回时`*offset`被更新为最后读取的字节之后的位置
+    /// - `count`: 要传输的字节数
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 成功传输的字节数
+    /// - `Err(SystemError)`: 错误码
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/sendfile.2.html
+    pub fn sys_sendfile(
+        out_fd: i32,
+        in_fd: i32,
+        offset: *mut i64,
+        count: usize,
+    ) -> Result<usize, SystemError> {
+        let mut input = SpliceEnd::new(in_fd, read_user_offset(offset)?)?;
+        let mut output = SpliceEnd::new(out_fd, None)?;
+        if matches!(input.file.file_type(), FileType::Pipe | FileType::Socket) {
+            return Err(SystemError::EINVAL);
+        }
+        input.file.readable()?;
+        output.file.writeable()?;
+
+        let r = do_splice(&mut input, &mut output, count, false)?;
+        write_user_offset(offset, input.offset)?;
+        Ok(r)
+    }
+
+    /// # 在文件与管道之间搬运数据
+    ///
+    /// `fd_in`与`fd_out`中至少有一个必须是管道
+    ///
+    /// ## 参数
+    /// - `fd_in`: 输入文件描述符
+    /// - `off_in`: 输入端的偏移量，输入端为管道时必须为空
+    /// - `fd_out`: 输出文件描述符
+    /// - `off_out`: 输出端的偏移量，输出端为管道时必须为空
+    /// - `len`: 要搬运的字节数
+    /// - `flags`: SpliceFlags
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 成功搬运的字节数
+    /// - `Err(SystemError)`: 错误码
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/splice.2.html
+    pub fn sys_splice(
+        fd_in: i32,
+        off_in: *mut i64,
+        fd_out: i32,
+        off_out: *mut i64,
+        len: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        SpliceFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
+
+        let mut input = SpliceEnd::new(fd_in, None)?;
+        let mut output = SpliceEnd::new(fd_out, None)?;
+        if !input.is_pipe() && !output.is_pipe() {
+            return Err(SystemError::EINVAL);
+        }
+        if (input.is_pipe() && !off_in.is_null()) || (output.is_pipe() && !off_out.is_null()) {
+            return Err(SystemError::ESPIPE);
+        }
+        if Arc::as_ptr(&input.file.inode()) as *const ()
+            == Arc::as_ptr(&output.file.inode()) as *const ()
+        {
+            return Err(SystemError::EINVAL);
+        }
+        input.offset = read_user_offset(off_in)?;
+        output.offset = read_user_offset(off_out)?;
+        input.file.readable()?;
+        output.file.writeable()?;
+
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let single_read = input.is_pipe();
+        let r = do_splice(&mut input, &mut output, len, single_read)?;
+        write_user_offset(off_in, input.offset)?;
+        write_user_offset(off_out, output.offset)?;
+        Ok(r)
+    }
+}
@@ -1,4 +1,7 @@
+use crate::filesystem::fat::fs::FatMountData;
 use crate::filesystem::overlayfs::OverlayMountData;
+use crate::filesystem::smb::SmbMountData;
+use crate::filesystem::tmpfs::TmpfsMountData;
 use crate::filesystem::vfs::FileSystemMakerData;
 use core::mem::size_of;
 
@@ -9,13 +12,17 @@ use system_error::SystemError;
 use crate::producefs;
 use crate::syscall::user_access::UserBufferReader;
 use crate::{
+    arch::MMArch,
     driver::base::{block::SeekFrom, device::device_number::DeviceNumber},
     filesystem::vfs::{core as Vcore, file::FileDescriptorVec},
-    libs::rwlock::RwLockWriteGuard,
-    mm::{verify_area, VirtAddr},
-    process::ProcessManager,
+    libs::{casting::DowncastArc, rwlock::RwLockWriteGuard},
+    mm::{verify_area, MemoryManagementArch, VirtAddr},
+    process::{
+        cred::{CAPFlags, Kgid, Kuid},
+        ProcessManager,
+    },
     syscall::{
-        user_access::{self, check_and_clone_cstr, UserBufferWriter},
+        user_access::{self, check_and_clone_cstr, UserBufferWriter, UserPod, UserSlice},
         Syscall,
     },
     time::{syscall::PosixTimeval, PosixTimeSpec},
@@ -26,11 +33,13 @@ use super::{
     core::{do_mkdir_at, do_remove_dir, do_unlink_at},
     fcntl::{AtFlags, FcntlCommand, FD_CLOEXEC},
     file::{File, FileMode},
+    mount::MountFSInode,
     open::{
-        do_faccessat, do_fchmodat, do_fchownat, do_sys_open, do_utimensat, do_utimes, ksys_fchown,
+        do_faccessat, do_fchmodat, do_fchownat, do_sys_open, do_sys_openat2, do_utimensat,
+        do_utimes, ksys_fchown,
     },
     utils::{rsplit_path, user_path_at},
-    Dirent, FileType, IndexNode, SuperBlock, FSMAKER, MAX_PATHLEN, ROOT_INODE,
+    FileType, IndexNode, SuperBlock, FSMAKER, MAX_PATHLEN, ROOT_INODE,
     VFS_MAX_FOLLOW_SYMLINK_TIMES,
 };
 
@@ -205,13 +214,16 @@ pub struct PosixStatx {
     stx_mnt_id: u64,
     stx_dio_mem_align: u32,
     stx_dio_offset_align: u32,
+
+    /* 0xa0 */
+    stx_spare: [u64; 12],
 }
 impl PosixStatx {
     fn new() -> Self {
         Self {
             stx_mask: PosixStatxMask::STATX_BASIC_STATS,
             stx_blksize: 0,
-            stx_attributes: StxAttributes::STATX_ATTR_APPEND,
+            stx_attributes: StxAttributes::empty(),
             stx_nlink: 0,
             stx_uid: 0,
             stx_gid: 0,
@@ -219,7 +231,7 @@ impl PosixStatx {
             stx_inode: 0,
             stx_size: 0,
             stx_blocks: 0,
-            stx_attributes_mask: StxAttributes::STATX_ATTR_APPEND,
+            stx_attributes_mask: StxAttributes::empty(),
             stx_atime: PosixTimeSpec {
                 tv_sec: 0,
                 tv_nsec: 0,
@@ -243,10 +255,40 @@ impl PosixStatx {
             stx_mnt_id: 0,
             stx_dio_mem_align: 0,
             stx_dio_offset_align: 0,
+            stx_spare: [0; 12],
         }
     }
 }
 
+/// # 查找statx的目标文件
+///
+/// 路径为空时需要指定AT_EMPTY_PATH，此时目标是`dirfd`指向的文件（可以不是目录）或者当前工作目录
+fn statx_lookup(dirfd: i32, path: &str, flags: AtFlags) -> Result<Arc<dyn IndexNode>, SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    if path.is_empty() {
+        if !flags.contains(AtFlags::AT_EMPTY_PATH) {
+            return Err(SystemError::ENOENT);
+        }
+        if dirfd == AtFlags::AT_FDCWD.bits() {
+            return ROOT_INODE()
+                .lookup_follow_symlink(&pcb.basic().cwd(), VFS_MAX_FOLLOW_SYMLINK_TIMES);
+        }
+        let file = pcb
+            .fd_table()
+            .read()
+            .get_file_by_fd(dirfd)
+            .ok_or(SystemError::EBADF)?;
+        return Ok(file.inode());
+    }
+
+    let (inode_begin, path) = user_path_at(&pcb, dirfd, path)?;
+    inode_begin.lookup_follow_symlink2(
+        &path,
+        VFS_MAX_FOLLOW_SYMLINK_TIMES,
+        !flags.contains(AtFlags::AT_SYMLINK_NOFOLLOW),
+    )
+}
+
 bitflags! {
     pub struct PosixStatxMask: u32{
         ///  Want stx_mode & S_IFMT
@@ -390,6 +432,8 @@ pub struct PosixOpenHow {
     pub resolve: u64,
 }
 
+unsafe impl UserPod for PosixOpenHow {}
+
 impl PosixOpenHow {
     #[allow(dead_code)]
     pub fn new(flags: u64, mode: u64, resolve: u64) -> Self {
@@ -464,6 +508,53 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// mount系统调用的标志位，与Linux的MS_*相同
+    pub struct MountFlags: u32 {
+        /// 只读挂载
+        const MS_RDONLY = 1;
+        /// 忽略suid和sgid位
+        const MS_NOSUID = 2;
+        /// 不允许访问设备文件
+        const MS_NODEV = 4;
+        /// 不允许执行程序
+        const MS_NOEXEC = 8;
+        /// 同步写入
+        const MS_SYNCHRONOUS = 16;
+        /// 修改已有挂载的标志位
+        const MS_REMOUNT = 32;
+        /// 允许强制锁
+        const MS_MANDLOCK = 64;
+        /// 目录的修改同步写入
+        const MS_DIRSYNC = 128;
+        /// 不更新访问时间
+        const MS_NOATIME = 1024;
+        /// 不更新目录的访问时间
+        const MS_NODIRATIME = 2048;
+        const MS_BIND = 4096;
+        const MS_MOVE = 8192;
+        const MS_REC = 16384;
+        const MS_SILENT = 32768;
+        const MS_RELATIME = 1 << 21;
+        const MS_STRICTATIME = 1 << 24;
+        const MS_LAZYTIME = 1 << 25;
+
+        /// 属于单个挂载、会被记录下来的标志位，其余的标志位只描述本次mount操作
+        const MS_PER_MOUNT = Self::MS_RDONLY.bits
+            | Self::MS_NOSUID.bits
+            | Self::MS_NODEV.bits
+            | Self::MS_NOEXEC.bits
+            | Self::MS_SYNCHRONOUS.bits
+            | Self::MS_MANDLOCK.bits
+            | Self::MS_DIRSYNC.bits
+            | Self::MS_NOATIME.bits
+            | Self::MS_NODIRATIME.bits
+            | Self::MS_RELATIME.bits
+            | Self::MS_STRICTATIME.bits
+            | Self::MS_LAZYTIME.bits;
+    }
+}
+
 bitflags! {
     pub struct UmountFlag: i32 {
         const DEFAULT = 0;          /* Default call to umount. */
@@ -518,6 +609,69 @@ impl Syscall {
         return do_sys_open(dirfd, &path, open_flags, mode, follow_symlink);
     }
 
+    /// openat2: 按照`open_how`打开文件
+    ///
+    /// ## 参数
+    ///
+    /// - `dirfd`: 相对路径的起始目录
+    /// - `path`: 文件路径
+    /// - `how`: 用户空间的`struct open_how`
+    /// - `size`: 用户空间的`struct open_how`的大小，用于兼容以后扩展的字段
+    ///
+    /// 与openat不同，未知的标志位都会返回EINVAL，而不是被忽略
+    pub fn openat2(
+        dirfd: i32,
+        path: *const u8,
+        how: *const PosixOpenHow,
+        size: usize,
+    ) -> Result<usize, SystemError> {
+        if size < size_of::<PosixOpenHow>() {
+            return Err(SystemError::EINVAL);
+        }
+        if size > MMArch::PAGE_SIZE {
+            return Err(SystemError::E2BIG);
+        }
+        let mut reader = UserSlice::new(VirtAddr::new(how as usize), size)?.reader();
+        let how = reader.read::<PosixOpenHow>()?;
+        // 新版本用户程序传入的更大的结构体中，本内核不认识的字段必须为0
+        if !reader.rest_is_zeroed()? {
+            return Err(SystemError::E2BIG);
+        }
+
+        let path = check_and_clone_cstr(path, Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+
+        let o_flags = u32::try_from(how.flags)
+            .ok()
+            .and_then(FileMode::from_bits)
+            .ok_or(SystemError::EINVAL)?;
+        let mode = u32::try_from(how.mode)
+            .ok()
+            .and_then(ModeType::from_bits)
+            .ok_or(SystemError::EINVAL)?;
+        if !mode.difference(ModeType::S_IALLUGO).is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+        // 不创建文件时不允许指定mode
+        if !o_flags.contains(FileMode::O_CREAT) && !mode.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let resolve = OpenHowResolve::from_bits(how.resolve).ok_or(SystemError::EINVAL)?;
+        if resolve.contains(OpenHowResolve::RESOLVE_BENEATH | OpenHowResolve::RESOLVE_IN_ROOT) {
+            return Err(SystemError::EINVAL);
+        }
+        // 创建或截断文件无法只通过缓存完成
+        if resolve.contains(OpenHowResolve::RESOLVE_CACHED)
+            && o_flags.intersects(FileMode::O_CREAT | FileMode::O_TRUNC)
+        {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+
+        return do_sys_openat2(dirfd, &path, OpenHow::new(o_flags, mode, resolve), true);
+    }
+
     /// @brief 关闭文件
     ///
     /// @param fd 文件描述符编号
@@ -780,16 +934,13 @@ impl Syscall {
 
     /// @brief 获取目录中的数据
     ///
-    /// TODO: 这个函数的语义与Linux不一致，需要修改！！！
+    /// 每次调用会填入尽可能多的目录项，直到缓冲区放不下下一个目录项
     ///
     /// @param fd 文件描述符号
     /// @param buf 输出缓冲区
     ///
-    /// @return 成功返回读取的字节数，失败返回错误码
+    /// @return 成功返回读取的字节数（0表示已经读到目录末尾），失败返回错误码
     pub fn getdents(fd: i32, buf: &mut [u8]) -> Result<usize, SystemError> {
-        let dirent =
-            unsafe { (buf.as_mut_ptr() as *mut Dirent).as_mut() }.ok_or(SystemError::EFAULT)?;
-
         if fd < 0 || fd as usize > FileDescriptorVec::PROCESS_MAX_FD {
             return Err(SystemError::EBADF);
         }
@@ -804,9 +955,11 @@ impl Syscall {
         // drop guard 以避免无法调度的问题
         drop(fd_table_guard);
 
-        let res = file.readdir(dirent).map(|x| x as usize);
+        if file.file_type() != FileType::Dir {
+            return Err(SystemError::ENOTDIR);
+        }
 
-        return res;
+        return file.readdir(buf);
     }
 
     /// @brief 创建文件夹
@@ -1100,7 +1253,8 @@ impl Syscall {
     }
 
     pub fn dup3(oldfd: i32, newfd: i32, flags: u32) -> Result<usize, SystemError> {
-        let flags = FileMode::from_bits_truncate(flags);
+        // dup3只接受O_CLOEXEC，未知的标志位也要报错，而不是被静默截断
+        let flags = FileMode::from_bits(flags).ok_or(SystemError::EINVAL)?;
         if (flags.bits() & !FileMode::O_CLOEXEC.bits()) != 0 {
             return Err(SystemError::EINVAL);
         }
@@ -1137,6 +1291,14 @@ impl Syscall {
             // 若oldfd与newfd相等
             return Ok(newfd as usize);
         }
+        // 先确认oldfd存在并准备好新的文件对象（包括close-on-exec标志），再关闭newfd，
+        // 这样oldfd无效时不会误关newfd，且newfd一旦可见就带有正确的标志
+        let old_file = fd_table_guard
+            .get_file_by_fd(oldfd)
+            .ok_or(SystemError::EBADF)?;
+        let new_file = old_file.try_clone().ok_or(SystemError::EBADF)?;
+        new_file.set_close_on_exec(flags.contains(FileMode::O_CLOEXEC));
+
         let new_exists = fd_table_guard.get_file_by_fd(newfd).is_some();
         if new_exists {
             // close newfd
@@ -1146,16 +1308,6 @@ impl Syscall {
             }
         }
 
-        let old_file = fd_table_guard
-            .get_file_by_fd(oldfd)
-            .ok_or(SystemError::EBADF)?;
-        let new_file = old_file.try_clone().ok_or(SystemError::EBADF)?;
-
-        if flags.contains(FileMode::O_CLOEXEC) {
-            new_file.set_close_on_exec(true);
-        } else {
-            new_file.set_close_on_exec(false);
-        }
         // 申请文件描述符，并把文件对象存入其中
         let res = fd_table_guard
             .alloc_fd(new_file, Some(newfd))
@@ -1174,27 +1326,25 @@ impl Syscall {
         // debug!("fcntl ({cmd:?}) fd: {fd}, arg={arg}");
         match cmd {
             FcntlCommand::DupFd | FcntlCommand::DupFdCloexec => {
+                // 与Linux相同，超出范围的最小fd返回EINVAL
                 if arg < 0 || arg as usize >= FileDescriptorVec::PROCESS_MAX_FD {
-                    return Err(SystemError::EBADF);
+                    return Err(SystemError::EINVAL);
                 }
-                let arg = arg as usize;
-                for i in arg..FileDescriptorVec::PROCESS_MAX_FD {
-                    let binding = ProcessManager::current_pcb().fd_table();
-                    let mut fd_table_guard = binding.write();
-                    if fd_table_guard.get_file_by_fd(i as i32).is_none() {
-                        if cmd == FcntlCommand::DupFd {
-                            return Self::do_dup2(fd, i as i32, &mut fd_table_guard);
-                        } else {
-                            return Self::do_dup3(
-                                fd,
-                                i as i32,
-                                FileMode::O_CLOEXEC,
-                                &mut fd_table_guard,
-                            );
-                        }
-                    }
+                // 在同一次加锁中查找空闲的fd并复制，避免找到的fd在复制之前被其他线程占用
+                let binding = ProcessManager::current_pcb().fd_table();
+                let mut fd_table_guard = binding.write();
+                if fd_table_guard.get_file_by_fd(fd).is_none() {
+                    return Err(SystemError::EBADF);
                 }
-                return Err(SystemError::EMFILE);
+                let newfd = (arg..FileDescriptorVec::PROCESS_MAX_FD as i32)
+                    .find(|&i| fd_table_guard.get_file_by_fd(i).is_none())
+                    .ok_or(SystemError::EMFILE)?;
+                let flags = if cmd == FcntlCommand::DupFdCloexec {
+                    FileMode::O_CLOEXEC
+                } else {
+                    FileMode::empty()
+                };
+                return Self::do_dup3(fd, newfd, flags, &mut fd_table_guard);
             }
             FcntlCommand::GetFd => {
                 // Get file descriptor flags.
@@ -1240,7 +1390,9 @@ impl Syscall {
                 if let Some(file) = fd_table_guard.get_file_by_fd(fd) {
                     // drop guard 以避免无法调度的问题
                     drop(fd_table_guard);
-                    return Ok(file.mode().bits() as usize);
+                    // O_CLOEXEC是文件描述符标志，不属于文件状态标志，由F_GETFD返回
+                    let mode = file.mode() - FileMode::O_CLOEXEC;
+                    return Ok(mode.bits() as usize);
                 }
 
                 return Err(SystemError::EBADF);
@@ -1251,10 +1403,17 @@ impl Syscall {
                 let fd_table_guard = binding.write();
 
                 if let Some(file) = fd_table_guard.get_file_by_fd(fd) {
-                    let arg = arg as u32;
-                    let mode = FileMode::from_bits(arg).ok_or(SystemError::EINVAL)?;
+                    // 只允许修改这些状态标志。访问模式和O_CLOEXEC等标志必须保持不变，
+                    // 否则fcntl(fd, F_SETFL, O_NONBLOCK)会顺带清除close-on-exec标志，导致fd泄漏到exec之后
+                    let setfl_mask = FileMode::O_APPEND
+                        | FileMode::O_NONBLOCK
+                        | FileMode::O_DIRECT
+                        | FileMode::O_NOATIME
+                        | FileMode::FASYNC;
+                    let arg = FileMode::from_bits_truncate(arg as u32) & setfl_mask;
                     // drop guard 以避免无法调度的问题
                     drop(fd_table_guard);
+                    let mode = (file.mode() - setfl_mask) | arg;
                     file.set_mode(mode)?;
                     return Ok(0);
                 }
@@ -1327,8 +1486,10 @@ impl Syscall {
         kstat.ctime.tv_nsec = metadata.ctime.tv_nsec;
 
         kstat.nlink = metadata.nlinks as u64;
-        kstat.uid = metadata.uid as i32;
-        kstat.gid = metadata.gid as i32;
+        // 转换为当前user namespace中的uid、gid
+        let cred = ProcessManager::current_pcb().cred();
+        kstat.uid = cred.user_ns.from_kuid_munged(Kuid::new(metadata.uid)) as i32;
+        kstat.gid = cred.user_ns.from_kgid_munged(Kgid::new(metadata.gid)) as i32;
         kstat.rdev = metadata.raw_dev.data() as i64;
         kstat.mode = metadata.mode;
         match file.file_type() {
@@ -1392,7 +1553,8 @@ impl Syscall {
             .map_err(|_| SystemError::EINVAL)?;
         let pcb = ProcessManager::current_pcb();
         let (_inode_begin, remain_path) = user_path_at(&pcb, fd as i32, &path)?;
-        let inode = ROOT_INODE().lookup_follow_symlink(&remain_path, MAX_PATHLEN)?;
+        let inode =
+            ROOT_INODE().lookup_follow_symlink(&remain_path, VFS_MAX_FOLLOW_SYMLINK_TIMES)?;
         let statfs = PosixStatfs::from(inode.fs().super_block());
         writer.copy_one_to_user(&statfs, 0)?;
         return Ok(0);
@@ -1411,8 +1573,15 @@ impl Syscall {
         return Ok(0);
     }
 
+    /// # 获取文件的扩展状态
+    ///
+    /// 总是返回基本属性；创建时间只在请求了STATX_BTIME并且文件系统记录了创建时间时返回，
+    /// `stx_mask`中报告实际返回了的属性。
+    ///
+    /// `flags`中的同步类型：AT_STATX_FORCE_SYNC先从后备存储重新获取元数据，
+    /// AT_STATX_DONT_SYNC与AT_STATX_SYNC_AS_STAT直接使用缓存的元数据
     pub fn do_statx(
-        fd: i32,
+        dirfd: i32,
         path: *const u8,
         flags: u32,
         mask: u32,
@@ -1423,86 +1592,38 @@ impl Syscall {
         }
 
         let mask = PosixStatxMask::from_bits_truncate(mask);
-
         if mask.contains(PosixStatxMask::STATX_RESERVED) {
-            return Err(SystemError::ENAVAIL);
+            return Err(SystemError::EINVAL);
         }
 
-        let flags = FileMode::from_bits_truncate(flags);
-        let ofd = Self::open(path, flags.bits(), ModeType::empty().bits, true)?;
-
-        let binding = ProcessManager::current_pcb().fd_table();
-        let fd_table_guard = binding.read();
-        let file = fd_table_guard
-            .get_file_by_fd(ofd as i32)
-            .ok_or(SystemError::EBADF)?;
-        // drop guard 以避免无法调度的问题
-        drop(fd_table_guard);
-        let mut writer = UserBufferWriter::new(usr_kstat, size_of::<PosixStatx>(), true)?;
-        let mut tmp: PosixStatx = PosixStatx::new();
-        // 获取文件信息
-        let metadata = file.metadata()?;
-
-        tmp.stx_mask |= PosixStatxMask::STATX_BASIC_STATS;
-        tmp.stx_blksize = metadata.blk_size as u32;
-        if mask.contains(PosixStatxMask::STATX_MODE) || mask.contains(PosixStatxMask::STATX_TYPE) {
-            tmp.stx_mode = metadata.mode;
-        }
-        if mask.contains(PosixStatxMask::STATX_NLINK) {
-            tmp.stx_nlink = metadata.nlinks as u32;
-        }
-        if mask.contains(PosixStatxMask::STATX_UID) {
-            tmp.stx_uid = metadata.uid as u32;
-        }
-        if mask.contains(PosixStatxMask::STATX_GID) {
-            tmp.stx_gid = metadata.gid as u32;
-        }
-        if mask.contains(PosixStatxMask::STATX_ATIME) {
-            tmp.stx_atime.tv_sec = metadata.atime.tv_sec;
-            tmp.stx_atime.tv_nsec = metadata.atime.tv_nsec;
-        }
-        if mask.contains(PosixStatxMask::STATX_MTIME) {
-            tmp.stx_mtime.tv_sec = metadata.ctime.tv_sec;
-            tmp.stx_mtime.tv_nsec = metadata.ctime.tv_nsec;
-        }
-        if mask.contains(PosixStatxMask::STATX_CTIME) {
-            // ctime是文件上次修改状态的时间
-            tmp.stx_ctime.tv_sec = metadata.mtime.tv_sec;
-            tmp.stx_ctime.tv_nsec = metadata.mtime.tv_nsec;
-        }
-        if mask.contains(PosixStatxMask::STATX_INO) {
-            tmp.stx_inode = metadata.inode_id.into() as u64;
-        }
-        if mask.contains(PosixStatxMask::STATX_SIZE) {
-            tmp.stx_size = metadata.size;
-        }
-        if mask.contains(PosixStatxMask::STATX_BLOCKS) {
-            tmp.stx_blocks = metadata.blocks as u64;
+        let flags = AtFlags::from_bits(flags as i32).ok_or(SystemError::EINVAL)?;
+        let valid = AtFlags::AT_SYMLINK_NOFOLLOW
+            | AtFlags::AT_NO_AUTOMOUNT
+            | AtFlags::AT_EMPTY_PATH
+            | AtFlags::AT_STATX_SYNC_TYPE;
+        if !valid.contains(flags) || flags.contains(AtFlags::AT_STATX_SYNC_TYPE) {
+            return Err(SystemError::EINVAL);
         }
 
-        if mask.contains(PosixStatxMask::STATX_BTIME) {
-            // btime是文件创建时间
-            tmp.stx_btime.tv_sec = metadata.ctime.tv_sec;
-            tmp.stx_btime.tv_nsec = metadata.ctime.tv_nsec;
-        }
-        if mask.contains(PosixStatxMask::STATX_ALL) {
-            tmp.stx_attributes = StxAttributes::STATX_ATTR_APPEND;
-            tmp.stx_attributes_mask |=
-                StxAttributes::STATX_ATTR_AUTOMOUNT | StxAttributes::STATX_ATTR_DAX;
-            tmp.stx_dev_major = metadata.dev_id as u32;
-            tmp.stx_dev_minor = metadata.dev_id as u32; //
-            tmp.stx_rdev_major = metadata.raw_dev.data();
-            tmp.stx_rdev_minor = metadata.raw_dev.data();
-        }
-        if mask.contains(PosixStatxMask::STATX_MNT_ID) {
-            tmp.stx_mnt_id = 0;
-        }
-        if mask.contains(PosixStatxMask::STATX_DIOALIGN) {
-            tmp.stx_dio_mem_align = 0;
-            tmp.stx_dio_offset_align = 0;
+        let path = if path.is_null() && flags.contains(AtFlags::AT_EMPTY_PATH) {
+            String::new()
+        } else {
+            check_and_clone_cstr(path, Some(MAX_PATHLEN))?
+                .into_string()
+                .map_err(|_| SystemError::EINVAL)?
+        };
+        let inode = statx_lookup(dirfd, &path, flags)?;
+
+        if flags.contains(AtFlags::AT_STATX_FORCE_SYNC) {
+            inode.revalidate()?;
         }
+        let metadata = inode.metadata()?;
 
-        match file.file_type() {
+        let mut tmp: PosixStatx = PosixStatx::new();
+        tmp.stx_mask = PosixStatxMask::STATX_BASIC_STATS;
+        tmp.stx_blksize = metadata.blk_size as u32;
+        tmp.stx_mode = metadata.mode;
+        match metadata.file_type {
             FileType::File => tmp.stx_mode.insert(ModeType::S_IFREG),
             FileType::Dir => tmp.stx_mode.insert(ModeType::S_IFDIR),
             FileType::BlockDevice => tmp.stx_mode.insert(ModeType::S_IFBLK),
@@ -1513,9 +1634,42 @@ impl Syscall {
             FileType::KvmDevice => tmp.stx_mode.insert(ModeType::S_IFCHR),
             FileType::FramebufferDevice => tmp.stx_mode.insert(ModeType::S_IFCHR),
         }
+        tmp.stx_nlink = metadata.nlinks as u32;
+        let cred = ProcessManager::current_pcb().cred();
+        tmp.stx_uid = cred.user_ns.from_kuid_munged(Kuid::new(metadata.uid)) as u32;
+        tmp.stx_gid = cred.user_ns.from_kgid_munged(Kgid::new(metadata.gid)) as u32;
+        tmp.stx_atime = metadata.atime;
+        tmp.stx_mtime = metadata.mtime;
+        tmp.stx_ctime = metadata.ctime;
+        tmp.stx_inode = metadata.inode_id.into() as u64;
+        tmp.stx_size = metadata.size;
+        tmp.stx_blocks = metadata.blocks as u64;
+
+        if mask.contains(PosixStatxMask::STATX_BTIME) {
+            if let Some(btime) = inode.btime() {
+                tmp.stx_mask |= PosixStatxMask::STATX_BTIME;
+                tmp.stx_btime = btime;
+            }
+        }
+
+        tmp.stx_attributes_mask = StxAttributes::STATX_ATTR_MOUNT_ROOT;
+        let is_mount_root = inode
+            .downcast_arc::<MountFSInode>()
+            .map(|inode| inode.is_mountpoint_root())
+            .transpose()?
+            .unwrap_or(false);
+        if is_mount_root {
+            tmp.stx_attributes |= StxAttributes::STATX_ATTR_MOUNT_ROOT;
+        }
 
+        let dev = DeviceNumber::from(metadata.dev_id as u32);
+        tmp.stx_dev_major = dev.major().data();
+        tmp.stx_dev_minor = dev.minor();
+        tmp.stx_rdev_major = metadata.raw_dev.major().data();
+        tmp.stx_rdev_minor = metadata.raw_dev.minor();
+
+        let mut writer = UserBufferWriter::new(usr_kstat, size_of::<PosixStatx>(), true)?;
         writer.copy_one_to_user(&tmp, 0)?;
-        Self::close(fd as usize).ok();
         return Ok(0);
     }
 
@@ -1529,6 +1683,16 @@ impl Syscall {
             .map_err(|_| SystemError::EINVAL)?;
         let path = path.as_str().trim();
 
+        // 创建设备文件需要CAP_MKNOD
+        let file_type = mode & ModeType::S_IFMT;
+        if (file_type == ModeType::S_IFCHR || file_type == ModeType::S_IFBLK)
+            && !ProcessManager::current_pcb()
+                .cred()
+                .has_capability(CAPFlags::CAP_MKNOD)
+        {
+            return Err(SystemError::EPERM);
+        }
+
         let inode: Result<Arc<dyn IndexNode>, SystemError> =
             ROOT_INODE().lookup_follow_symlink(path, VFS_MAX_FOLLOW_SYMLINK_TIMES);
 
@@ -1569,6 +1733,68 @@ impl Syscall {
         return Ok(len);
     }
 
+    /// # sys_preadv 系统调用的实际执行函数
+    ///
+    /// 从文件的`offset`处读取数据，分散写入到iovec中，不改变文件的偏移量
+    pub fn preadv(fd: i32, iov: usize, count: usize, offset: usize) -> Result<usize, SystemError> {
+        // IoVecs会进行用户态检验
+        let mut iovecs = unsafe { IoVecs::from_user(iov as *const IoVec, count, true) }?;
+
+        let mut data = iovecs.new_buf(true);
+        let len = data.len();
+        let len = Self::pread(fd, &mut data, len, offset)?;
+
+        iovecs.scatter(&data[..len]);
+
+        return Ok(len);
+    }
+
+    /// # sys_pwritev 系统调用的实际执行函数
+    ///
+    /// 把iovec中的数据聚合后写入到文件的`offset`处，不改变文件的偏移量
+    pub fn pwritev(fd: i32, iov: usize, count: usize, offset: usize) -> Result<usize, SystemError> {
+        // IoVecs会进行用户态检验
+        let iovecs = unsafe { IoVecs::from_user(iov as *const IoVec, count, false) }?;
+
+        let data = iovecs.gather();
+
+        Self::pwrite(fd, &data, data.len(), offset)
+    }
+
+    /// # sys_fsync/sys_fdatasync 系统调用的实际执行函数
+    ///
+    /// 把文件的内容同步到设备上。元数据总是与数据一起同步，因此两者的实现相同
+    pub fn fsync(fd: i32) -> Result<usize, SystemError> {
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        file.inode().sync()?;
+        return Ok(0);
+    }
+
+    /// # sys_fadvise64 系统调用的实际执行函数
+    ///
+    /// 文件的访问模式只是给内核的建议，目前只检查参数，不做任何处理
+    pub fn fadvise64(fd: i32, offset: i64, len: i64, advice: i32) -> Result<usize, SystemError> {
+        /// 最大的advice值（POSIX_FADV_NOREUSE）
+        const POSIX_FADV_MAX: i32 = 5;
+
+        let file = ProcessManager::current_pcb()
+            .fd_table()
+            .read()
+            .get_file_by_fd(fd)
+            .ok_or(SystemError::EBADF)?;
+        if file.file_type() == FileType::Pipe {
+            return Err(SystemError::ESPIPE);
+        }
+        if !(0..=POSIX_FADV_MAX).contains(&advice) || offset < 0 || len < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        return Ok(0);
+    }
+
     pub fn readlink_at(
         dirfd: i32,
         path: *const u8,
@@ -1583,7 +1809,8 @@ impl Syscall {
 
         let (inode, path) = user_path_at(&ProcessManager::current_pcb(), dirfd, path)?;
 
-        let inode = inode.lookup(path.as_str())?;
+        let inode =
+            inode.lookup_follow_symlink2(path.as_str(), VFS_MAX_FOLLOW_SYMLINK_TIMES, false)?;
         if inode.metadata()?.file_type != FileType::SymLink {
             return Err(SystemError::EINVAL);
         }
@@ -1705,36 +1932,50 @@ impl Syscall {
 
     /// #挂载文件系统
     ///
-    /// 用于挂载文件系统,目前仅支持ramfs挂载
+    /// 用于挂载文件系统
     ///
     /// ## 参数:
     ///
-    /// - source       挂载设备(暂时不支持)
+    /// - source       挂载设备(仅基于块设备的文件系统使用，如vfat)
     /// - target       挂载目录
     /// - filesystemtype   文件系统
-    /// - mountflags     挂载选项（暂未实现）
+    /// - mountflags     挂载选项，见[`MountFlags`]。带有MS_REMOUNT时只修改target处已有挂载的标志位
     /// - data        带数据挂载
     ///
     /// ## 返回值
     /// - Ok(0): 挂载成功
+    /// - Err(SystemError::EPERM): 没有CAP_SYS_ADMIN
     /// - Err(SystemError) :挂载过程中出错
     pub fn mount(
-        _source: *const u8,
+        source: *const u8,
         target: *const u8,
         filesystemtype: *const u8,
-        _mountflags: usize,
+        mountflags: usize,
         data: *const u8,
     ) -> Result<usize, SystemError> {
+        if !ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_SYS_ADMIN)
+        {
+            return Err(SystemError::EPERM);
+        }
         let target = user_access::check_and_clone_cstr(target, Some(MAX_PATHLEN))?
             .into_string()
             .map_err(|_| SystemError::EINVAL)?;
+        let flags = MountFlags::from_bits_truncate(mountflags as u32);
+
+        if flags.contains(MountFlags::MS_REMOUNT) {
+            Vcore::do_remount(&target, flags)?;
+            return Ok(0);
+        }
 
         let fstype_str = user_access::check_and_clone_cstr(filesystemtype, Some(MAX_PATHLEN))?;
         let fstype_str = fstype_str.to_str().map_err(|_| SystemError::EINVAL)?;
 
-        let fstype = producefs!(FSMAKER, fstype_str, data)?;
+        let fstype = producefs!(FSMAKER, fstype_str, source, data)?;
 
-        Vcore::do_mount(fstype, &target)?;
+        let mount_fs = Vcore::do_mount(fstype, &target)?;
+        mount_fs.set_flags(flags);
 
         return Ok(0);
     }
@@ -1748,6 +1989,12 @@ impl Syscall {
     ///
     /// [umount(2) — Linux manual page](https://www.man7.org/linux/man-pages/man2/umount.2.html)
     pub fn umount2(target: *const u8, flags: i32) -> Result<(), SystemError> {
+        if !ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_SYS_ADMIN)
+        {
+            return Err(SystemError::EPERM);
+        }
         let target = user_access::check_and_clone_cstr(target, Some(MAX_PATHLEN))?
             .into_string()
             .map_err(|_| SystemError::EINVAL)?;
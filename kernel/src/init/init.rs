@@ -21,7 +21,7 @@ use crate::{
     },
     mm::init::mm_init,
     process::{kthread::kthread_init, process_init, ProcessManager},
-    sched::SchedArch,
+    sched::{preempt::might_sleep_check_enable, SchedArch},
     smp::{early_smp_init, SMPArch},
     syscall::Syscall,
     time::{
@@ -47,6 +47,7 @@ pub fn start_kernel() -> ! {
     CurrentSchedArch::initial_setup_sched_local();
 
     CurrentSchedArch::enable_sched_local();
+    might_sleep_check_enable();
 
     ProcessManager::arch_idle_func();
 }
@@ -40,6 +40,9 @@ fn kernel_init() -> Result<(), SystemError> {
     crate::driver::disk::ahci::ahci_init()
         .inspect_err(|e| log::error!("ahci_init failed: {:?}", e))
         .ok();
+    crate::driver::disk::nvme::nvme_init()
+        .inspect_err(|e| log::error!("nvme_init failed: {:?}", e))
+        .ok();
     virtio_probe();
     mount_root_fs().expect("Failed to mount root fs");
     e1000e_init();
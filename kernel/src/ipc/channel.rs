@@ -0,0 +1,812 @@
+//! DragonOS原生的快速IPC通道（channel）
+//!
+//! 为将来的用户态驱动/服务架构设计的进程间通信原语：
+//! - 一个通道有两个端点，各自对应一个文件描述符，可以像其他fd一样被继承、dup和epoll
+//! - 消息通过共享内存中的两个单生产者/单消费者环形队列传递（每个方向一个），
+//!   收发消息本身不需要陷入内核
+//! - 只有在需要睡眠或唤醒对端时，才通过`channel_ctl`进入内核
+//! - 文件描述符（句柄）通过内核传递，并且可以在传递时收窄访问权限（能力式的句柄传递）
+//!
+//! 详细的用户态协议见`docs/kernel/ipc/channel.md`。
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    arch::{mm::LockedFrameAllocator, MMArch},
+    filesystem::{
+        page_cache::PageCache,
+        vfs::{
+            file::{File, FileDescriptorVec, FileMode},
+            syscall::ModeType,
+            FilePrivateData, FileSystem, FileType, FsInfo, IndexNode, Metadata, SuperBlock,
+        },
+    },
+    libs::{
+        align::page_align_up,
+        casting::DowncastArc,
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::WaitQueue,
+    },
+    mm::{
+        allocator::page_frame::{PageFrameCount, PhysPageFrame},
+        fault::{PageFaultHandler, PageFaultMessage},
+        page::{page_manager_lock_irqsave, Page, PageFlags, PageType},
+        MemoryManagementArch, PhysAddr, VirtAddr, VmFaultReason,
+    },
+    net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll},
+    process::{cred::CAPFlags, ProcessManager},
+    sched::SchedMode,
+    syscall::user_access::UserPod,
+};
+
+/// 环形队列的最大槽数
+pub const CHANNEL_MAX_ENTRIES: u32 = 4096;
+/// 槽的最小长度（字节）
+pub const CHANNEL_MIN_SLOT_SIZE: u32 = 64;
+/// 槽的最大长度（字节）
+pub const CHANNEL_MAX_SLOT_SIZE: u32 = 16384;
+/// 每个方向上最多积压的、尚未被接收的句柄数
+pub const CHANNEL_MAX_PENDING_HANDLES: usize = 256;
+/// 一次channel_ctl最多收发的句柄数
+pub const CHANNEL_MAX_HANDLES_PER_CALL: usize = 16;
+/// 没有CAP_IPC_LOCK权限时，每个用户的所有通道最多占用的共享内存（字节）
+///
+/// 共享内存在通道存在期间一直被锁定在内存中，不会被换出
+pub const CHANNEL_MAX_LOCKED_BYTES: usize = 64 * 1024 * 1024;
+
+/// 每个用户（以euid区分）的通道占用的共享内存页数
+static USER_LOCKED_PAGES: SpinLock<BTreeMap<usize, usize>> = SpinLock::new(BTreeMap::new());
+/// 发送通道端点时，检查与更新[`Channel::inflight`]、[`Channel::queued_endpoints`]需要持有的锁，
+/// 保证检查的结果在入队之前不会被并发的发送改变
+static CHANNEL_GRAPH_LOCK: SpinLock<()> = SpinLock::new(());
+
+const _: () = assert!(core::mem::size_of::<ChannelRingHeader>() == 64);
+const _: () = assert!(core::mem::size_of::<ChannelMsgHeader>() == 8);
+const _: () = assert!(core::mem::size_of::<ChannelParams>() == 32);
+
+bitflags! {
+    /// channel_create的标志
+    pub struct ChannelFlags: u32 {
+        const CHANNEL_NONBLOCK = 0o0004000;
+        const CHANNEL_CLOEXEC = 0o2000000;
+    }
+
+    /// 通过通道传递的句柄所具有的权限
+    pub struct ChannelRights: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+    }
+}
+
+/// channel_ctl的操作码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum ChannelCtlOp {
+    /// 唤醒对端在channel_ctl(WAIT)中睡眠的进程
+    Notify = 0,
+    /// 睡眠，直到`arg`(ChannelWaitFor)指定的条件满足
+    Wait = 1,
+    /// 把`arg`指向的`len`个ChannelHandle发送给对端
+    SendHandles = 2,
+    /// 接收对端发来的句柄，写入`arg`指向的、容量为`len`的ChannelHandle数组
+    RecvHandles = 3,
+}
+
+bitflags! {
+    /// channel_ctl(WAIT)等待的条件
+    pub struct ChannelWaitFor: u32 {
+        /// 接收队列中有消息，或者有待接收的句柄
+        const READABLE = 1 << 0;
+        /// 发送队列中有空槽
+        const WRITABLE = 1 << 1;
+    }
+}
+
+/// channel_create的参数
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChannelParams {
+    /// 输入：每个方向的槽数，会被向上取整为2的幂；输出：实际的槽数
+    pub entries: u32,
+    /// 输入：每个槽的字节数，会被向上取整为64的倍数；输出：实际的槽长度
+    pub slot_size: u32,
+    /// 输入：ChannelFlags
+    pub flags: u32,
+    /// 输出：mmap时需要映射的共享内存长度
+    pub region_size: u32,
+    /// 输出：`fds[0]`的发送队列在共享内存中的偏移量（也就是`fds[1]`的接收队列）
+    pub ring0_off: u32,
+    /// 输出：`fds[0]`的接收队列在共享内存中的偏移量（也就是`fds[1]`的发送队列）
+    pub ring1_off: u32,
+    pub resv: [u32; 2],
+}
+
+unsafe impl UserPod for ChannelParams {}
+
+/// 共享内存中每个环形队列的头部
+///
+/// 头部之后紧跟着`entries`个长度为`slot_size`的槽
+#[repr(C)]
+#[derive(Debug)]
+pub struct ChannelRingHeader {
+    /// 消费者的读位置，只由消费者修改
+    pub head: AtomicU32,
+    /// 生产者的写位置，只由生产者修改
+    pub tail: AtomicU32,
+    pub entries: u32,
+    pub slot_size: u32,
+    /// 消费者在等待消息时置位。生产者发布消息后若看到该标志，需要调用NOTIFY
+    pub rx_waiting: AtomicU32,
+    /// 生产者在等待空槽时置位。消费者取走消息后若看到该标志，需要调用NOTIFY
+    pub tx_waiting: AtomicU32,
+    resv: [u32; 10],
+}
+
+/// 每个槽开头的消息头
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelMsgHeader {
+    /// 消息负载的长度，不超过`slot_size - 8`
+    pub len: u32,
+    /// 随这条消息一起通过SEND_HANDLES发送的句柄数
+    pub handles: u32,
+}
+
+/// 在用户态与内核之间描述一个句柄
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChannelHandle {
+    /// 文件描述符
+    pub fd: i32,
+    /// ChannelRights
+    pub rights: u32,
+}
+
+unsafe impl UserPod for ChannelHandle {}
+
+/// 通道的共享内存
+#[derive(Debug)]
+struct ChannelRegion {
+    phys_addr: PhysAddr,
+    vaddr: VirtAddr,
+    page_count: PageFrameCount,
+    pages: Vec<Arc<Page>>,
+    /// 创建者的euid，共享内存的页数记在这个用户上
+    owner: usize,
+}
+
+impl ChannelRegion {
+    fn new(len: usize) -> Result<Self, SystemError> {
+        let page_count = PageFrameCount::new(page_align_up(len) / MMArch::PAGE_SIZE);
+        let owner = Self::charge(page_count.data())?;
+        let mut page_manager_guard = page_manager_lock_irqsave();
+        let r = page_manager_guard.create_pages(
+            PageType::Normal,
+            PageFlags::PG_UNEVICTABLE,
+            &mut LockedFrameAllocator,
+            page_count,
+        );
+        drop(page_manager_guard);
+        let (phys_addr, pages) = r.inspect_err(|_| Self::uncharge(owner, page_count.data()))?;
+
+        // 从这里开始，出错时由drop归还页面与计数
+        let mut region = Self {
+            phys_addr,
+            vaddr: VirtAddr::new(0),
+            page_count,
+            pages,
+            owner,
+        };
+        region.vaddr = unsafe { MMArch::phys_2_virt(phys_addr) }.ok_or(SystemError::EFAULT)?;
+        Ok(region)
+    }
+
+    /// 把`pages`页记在当前用户上，超出[`CHANNEL_MAX_LOCKED_BYTES`]时以 ENOMEM 失败。
+    /// 有CAP_IPC_LOCK权限的进程只记录、不受限制
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 当前用户的euid
+    fn charge(pages: usize) -> Result<usize, SystemError> {
+        let cred = ProcessManager::current_pcb().cred();
+        let owner = cred.euid.data();
+        let mut locked = USER_LOCKED_PAGES.lock_irqsave();
+        let used = locked.get(&owner).copied().unwrap_or(0);
+        if (used + pages) * MMArch::PAGE_SIZE > CHANNEL_MAX_LOCKED_BYTES
+            && !cred.has_capability(CAPFlags::CAP_IPC_LOCK)
+        {
+            return Err(SystemError::ENOMEM);
+        }
+        locked.insert(owner, used + pages);
+        Ok(owner)
+    }
+
+    fn uncharge(owner: usize, pages: usize) {
+        let mut locked = USER_LOCKED_PAGES.lock_irqsave();
+        if let Some(used) = locked.get_mut(&owner) {
+            *used -= pages;
+            if *used == 0 {
+                locked.remove(&owner);
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.page_count.data() * MMArch::PAGE_SIZE
+    }
+}
+
+impl Drop for ChannelRegion {
+    fn drop(&mut self) {
+        let mut page_manager_guard = page_manager_lock_irqsave();
+        let mut cur_phys = PhysPageFrame::new(self.phys_addr);
+        for _ in 0..self.page_count.data() {
+            page_manager_guard.remove_page(&cur_phys.phys_address());
+            cur_phys = cur_phys.next();
+        }
+        drop(page_manager_guard);
+        Self::uncharge(self.owner, self.page_count.data());
+    }
+}
+
+/// 通道的一端
+#[derive(Debug)]
+struct ChannelSide {
+    /// 本端发送队列在共享内存中的偏移量
+    tx_off: usize,
+    /// 本端已发送、对端尚未接收的句柄
+    tx_handles: SpinLock<VecDeque<File>>,
+    /// 打开了本端的文件对象数
+    open_count: AtomicUsize,
+    closed: AtomicBool,
+    /// 在本端等待的进程
+    wait_queue: WaitQueue,
+    epitems: EPollItems,
+}
+
+impl ChannelSide {
+    fn new(tx_off: usize) -> Self {
+        Self {
+            tx_off,
+            tx_handles: SpinLock::new(VecDeque::new()),
+            open_count: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            wait_queue: WaitQueue::default(),
+            epitems: EPollItems::new(),
+        }
+    }
+}
+
+/// 通道实例，由两个端点共享
+#[derive(Debug)]
+pub struct Channel {
+    region: ChannelRegion,
+    sides: [ChannelSide; 2],
+    /// 每个方向的槽数（共享内存中的副本可能被用户态改写，内核只信任这里的值）
+    entries: u32,
+    /// 本通道的端点有多少个正在某个通道的句柄队列中等待接收
+    inflight: AtomicUsize,
+    /// 本通道的句柄队列中有多少个通道端点
+    queued_endpoints: AtomicUsize,
+}
+
+impl Channel {
+    fn new(entries: u32, slot_size: u32) -> Result<Self, SystemError> {
+        let ring_len = core::mem::size_of::<ChannelRingHeader>() + (entries * slot_size) as usize;
+        // 两个队列都按缓存行对齐
+        let ring1_off = (ring_len + 63) & !63;
+        let region = ChannelRegion::new(ring1_off + ring_len)?;
+
+        let channel = Self {
+            region,
+            sides: [ChannelSide::new(0), ChannelSide::new(ring1_off)],
+            entries,
+            inflight: AtomicUsize::new(0),
+            queued_endpoints: AtomicUsize::new(0),
+        };
+        for side in channel.sides.iter() {
+            let ring = unsafe { &mut *(channel.ring_ptr(side.tx_off)) };
+            ring.entries = entries;
+            ring.slot_size = slot_size;
+        }
+        Ok(channel)
+    }
+
+    fn ring_ptr(&self, off: usize) -> *mut ChannelRingHeader {
+        (self.region.vaddr.data() + off) as *mut ChannelRingHeader
+    }
+
+    /// `side`端的发送队列
+    fn tx_ring(&self, side: usize) -> &ChannelRingHeader {
+        unsafe { &*self.ring_ptr(self.sides[side].tx_off) }
+    }
+
+    /// `side`端的接收队列（即对端的发送队列）
+    fn rx_ring(&self, side: usize) -> &ChannelRingHeader {
+        self.tx_ring(side ^ 1)
+    }
+
+    fn readable(&self, side: usize) -> bool {
+        let ring = self.rx_ring(side);
+        ring.tail.load(Ordering::Acquire) != ring.head.load(Ordering::Acquire)
+            || !self.sides[side ^ 1].tx_handles.lock().is_empty()
+    }
+
+    fn writable(&self, side: usize) -> bool {
+        let ring = self.tx_ring(side);
+        ring.tail
+            .load(Ordering::Acquire)
+            .wrapping_sub(ring.head.load(Ordering::Acquire))
+            < self.entries
+    }
+
+    fn peer_closed(&self, side: usize) -> bool {
+        self.sides[side ^ 1].closed.load(Ordering::SeqCst)
+    }
+
+    fn poll_events(&self, side: usize) -> EPollEventType {
+        let mut events = EPollEventType::empty();
+        if self.readable(side) {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        if self.peer_closed(side) {
+            events |= EPollEventType::EPOLLHUP;
+        } else if self.writable(side) {
+            events |= EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        }
+        events
+    }
+
+    /// 一个句柄离开了本通道的句柄队列（被接收或者被丢弃）
+    fn handle_dequeued(&self, file: &File) {
+        if let Some(endpoint) = file
+            .inode()
+            .as_any_ref()
+            .downcast_ref::<ChannelEndpointInode>()
+        {
+            endpoint.channel.inflight.fetch_sub(1, Ordering::SeqCst);
+            self.queued_endpoints.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// 丢弃`side`端已发送、尚未被接收的句柄
+    fn drop_pending(&self, side: usize) {
+        let pending = core::mem::take(&mut *self.sides[side].tx_handles.lock());
+        for file in pending.iter() {
+            self.handle_dequeued(file);
+        }
+        drop(pending);
+    }
+
+    /// 唤醒在`side`端等待的进程
+    fn wake(&self, side: usize) {
+        self.sides[side].wait_queue.wakeup_all(None);
+        self.sides[side]
+            .epitems
+            .wakeup(Some(self.poll_events(side)))
+            .ok();
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.drop_pending(0);
+        self.drop_pending(1);
+    }
+}
+
+/// 通道端点对应的inode
+#[derive(Debug)]
+pub struct ChannelEndpointInode {
+    channel: Arc<Channel>,
+    side: usize,
+    page_cache: Arc<PageCache>,
+}
+
+impl ChannelEndpointInode {
+    fn new(channel: Arc<Channel>, side: usize) -> Result<Arc<Self>, SystemError> {
+        let page_cache = PageCache::new(None);
+        let mut cache_guard = page_cache.lock_irqsave();
+        for (i, page) in channel.region.pages.iter().enumerate() {
+            cache_guard.add_page(i, page);
+        }
+        drop(cache_guard);
+
+        let inode = Arc::new(Self {
+            channel,
+            side,
+            page_cache,
+        });
+        inode
+            .page_cache
+            .set_inode(Arc::downgrade(&(inode.clone() as Arc<dyn IndexNode>)))?;
+        Ok(inode)
+    }
+
+    /// 唤醒对端
+    pub fn notify(&self) {
+        self.channel.wake(self.side ^ 1);
+    }
+
+    /// 等待`what`中的任意一个条件满足
+    ///
+    /// ## 返回值
+    /// - `Ok(ChannelWaitFor)`: 已满足的条件
+    /// - `Err(EPIPE)`: 对端已关闭，并且没有剩余的消息可读
+    pub fn wait(
+        &self,
+        what: ChannelWaitFor,
+        nonblock: bool,
+    ) -> Result<ChannelWaitFor, SystemError> {
+        let channel = &self.channel;
+        let side = self.side;
+        let check = || {
+            let mut ready = ChannelWaitFor::empty();
+            if what.contains(ChannelWaitFor::READABLE) && channel.readable(side) {
+                ready |= ChannelWaitFor::READABLE;
+            }
+            if what.contains(ChannelWaitFor::WRITABLE) && channel.writable(side) {
+                ready |= ChannelWaitFor::WRITABLE;
+            }
+            ready
+        };
+
+        // 先置位等待标志再检查条件，与用户态“先发布、后检查标志”的顺序配合，避免丢失唤醒
+        if what.contains(ChannelWaitFor::READABLE) {
+            channel.rx_ring(side).rx_waiting.store(1, Ordering::SeqCst);
+        }
+        if what.contains(ChannelWaitFor::WRITABLE) {
+            channel.tx_ring(side).tx_waiting.store(1, Ordering::SeqCst);
+        }
+
+        let r = loop {
+            let ready = check();
+            if !ready.is_empty() {
+                break Ok(ready);
+            }
+            if channel.peer_closed(side) {
+                break Err(SystemError::EPIPE);
+            }
+            if nonblock {
+                break Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if let Err(e) = wq_wait_event_interruptible!(
+                channel.sides[side].wait_queue,
+                !check().is_empty() || channel.peer_closed(side),
+                {}
+            ) {
+                break Err(e);
+            }
+        };
+
+        channel.rx_ring(side).rx_waiting.store(0, Ordering::SeqCst);
+        channel.tx_ring(side).tx_waiting.store(0, Ordering::SeqCst);
+        r
+    }
+
+    /// 把当前进程的若干个文件描述符发送给对端
+    ///
+    /// 句柄的权限只能收窄：请求的权限必须是原文件打开模式的子集。
+    ///
+    /// 通道端点在队列中时持有通道的引用，为了不形成引用环（例如A的端点在B的队列中，
+    /// B的端点又在A的队列中，两个通道都无法释放），发送通道端点时要求：
+    /// - 不能发送到通道自身
+    /// - 被发送的端点所在的通道，队列中没有通道端点
+    /// - 用于发送的通道，它的端点没有在任何队列中
+    ///
+    /// 这样在队列中的通道的队列里不会有通道端点，也就不会有环。不满足时以 ETOOMANYREFS 失败
+    pub fn send_handles(&self, handles: &[ChannelHandle]) -> Result<(), SystemError> {
+        let fd_table = ProcessManager::current_pcb().fd_table();
+        let fd_table_guard = fd_table.read();
+        let files = handles
+            .iter()
+            .map(|handle| {
+                let file = fd_table_guard
+                    .get_file_by_fd(handle.fd)
+                    .ok_or(SystemError::EBADF)?;
+                if let Some(endpoint) = file.inode().as_any_ref().downcast_ref::<Self>() {
+                    if Arc::ptr_eq(&endpoint.channel, &self.channel) {
+                        return Err(SystemError::EINVAL);
+                    }
+                }
+
+                let rights = ChannelRights::from_bits(handle.rights).ok_or(SystemError::EINVAL)?;
+                let mode = file.mode();
+                if !rights_of(mode).contains(rights) {
+                    return Err(SystemError::EPERM);
+                }
+
+                let new_file = file.try_clone().ok_or(SystemError::EBADF)?;
+                new_file.set_mode(
+                    (mode - FileMode::O_ACCMODE - FileMode::O_CLOEXEC) | accmode_of(rights),
+                )?;
+                Ok(new_file)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(fd_table_guard);
+
+        if self.channel.peer_closed(self.side) {
+            return Err(SystemError::EPIPE);
+        }
+        let endpoints = files
+            .iter()
+            .filter_map(|file| {
+                file.inode()
+                    .as_any_ref()
+                    .downcast_ref::<Self>()
+                    .map(|endpoint| endpoint.channel.clone())
+            })
+            .collect::<Vec<_>>();
+        let graph_guard = CHANNEL_GRAPH_LOCK.lock_irqsave();
+        if !endpoints.is_empty()
+            && (self.channel.inflight.load(Ordering::SeqCst) != 0
+                || endpoints
+                    .iter()
+                    .any(|channel| channel.queued_endpoints.load(Ordering::SeqCst) != 0))
+        {
+            return Err(SystemError::ETOOMANYREFS);
+        }
+        let mut tx_handles = self.channel.sides[self.side].tx_handles.lock();
+        if tx_handles.len() + files.len() > CHANNEL_MAX_PENDING_HANDLES {
+            return Err(SystemError::ENOBUFS);
+        }
+        for channel in endpoints.iter() {
+            channel.inflight.fetch_add(1, Ordering::SeqCst);
+            self.channel.queued_endpoints.fetch_add(1, Ordering::SeqCst);
+        }
+        tx_handles.extend(files);
+        drop(tx_handles);
+        drop(graph_guard);
+
+        self.notify();
+        Ok(())
+    }
+
+    /// 接收对端发来的句柄，最多`max`个，按发送的顺序安装到当前进程的文件描述符表中
+    ///
+    /// 文件描述符不够时只接收能安装的部分，其余的留在队列中；一个也装不下时返回 EMFILE
+    pub fn recv_handles(&self, max: usize) -> Result<Vec<ChannelHandle>, SystemError> {
+        let fd_table = ProcessManager::current_pcb().fd_table();
+        let mut fd_table_guard = fd_table.write();
+        let mut rx_handles = self.channel.sides[self.side ^ 1].tx_handles.lock();
+
+        // alloc_fd失败时会丢弃文件，所以先算好能安装多少个，只从队列中取出这么多
+        let free = FileDescriptorVec::PROCESS_MAX_FD - fd_table_guard.fd_open_count();
+        let count = max.min(rx_handles.len());
+        if count > 0 && free == 0 {
+            return Err(SystemError::EMFILE);
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..count.min(free) {
+            let file = rx_handles.pop_front().unwrap();
+            self.channel.handle_dequeued(&file);
+            let rights = rights_of(file.mode()).bits();
+            let fd = fd_table_guard.alloc_fd(file, None)?;
+            received.push(ChannelHandle { fd, rights });
+        }
+        drop(rx_handles);
+        drop(fd_table_guard);
+
+        // 接收队列腾出了空间，对端可能在等待可写
+        self.notify();
+        Ok(received)
+    }
+}
+
+/// 文件打开模式对应的句柄权限
+fn rights_of(mode: FileMode) -> ChannelRights {
+    match mode.accmode() {
+        x if x == FileMode::O_WRONLY.bits() => ChannelRights::WRITE,
+        x if x == FileMode::O_RDWR.bits() => ChannelRights::READ | ChannelRights::WRITE,
+        _ => ChannelRights::READ,
+    }
+}
+
+/// 句柄权限对应的文件访问模式
+fn accmode_of(rights: ChannelRights) -> FileMode {
+    if rights.contains(ChannelRights::READ | ChannelRights::WRITE) {
+        FileMode::O_RDWR
+    } else if rights.contains(ChannelRights::WRITE) {
+        FileMode::O_WRONLY
+    } else {
+        FileMode::O_RDONLY
+    }
+}
+
+impl IndexNode for ChannelEndpointInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        self.channel.sides[self.side]
+            .open_count
+            .fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        let side = &self.channel.sides[self.side];
+        if side.open_count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            side.closed.store(true, Ordering::SeqCst);
+            // 本端不会再接收了，丢弃对端发来、尚未被接收的句柄
+            self.channel.drop_pending(self.side ^ 1);
+            self.notify();
+        }
+        Ok(())
+    }
+
+    fn mmap(&self, _start: usize, len: usize, offset: usize) -> Result<(), SystemError> {
+        if offset != 0 || len > self.channel.region.len() {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        Ok(self.channel.poll_events(self.side).bits() as usize)
+    }
+
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.channel.sides[self.side].epitems.add(epitem);
+        Ok(())
+    }
+
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.channel.sides[self.side].epitems.remove(epoll)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        let meta = Metadata {
+            mode: ModeType::from_bits_truncate(0o600),
+            file_type: FileType::File,
+            size: self.channel.region.len() as i64,
+            ..Default::default()
+        };
+        Ok(meta)
+    }
+
+    fn resize(&self, _len: usize) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        Arc::new(ChannelFakeFs)
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        Some(self.page_cache.clone())
+    }
+}
+
+/// 用于处理通道共享内存缺页的伪文件系统
+#[derive(Debug)]
+struct ChannelFakeFs;
+
+impl FileSystem for ChannelFakeFs {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        panic!("ChannelFakeFs does not have a root inode")
+    }
+
+    fn info(&self) -> FsInfo {
+        panic!("ChannelFakeFs does not have a filesystem info")
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "channel"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        panic!("ChannelFakeFs does not have a super block")
+    }
+
+    unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
+        PageFaultHandler::filemap_fault(pfm)
+    }
+
+    unsafe fn map_pages(
+        &self,
+        pfm: &mut PageFaultMessage,
+        start_pgoff: usize,
+        end_pgoff: usize,
+    ) -> VmFaultReason {
+        PageFaultHandler::filemap_map_pages(pfm, start_pgoff, end_pgoff)
+    }
+}
+
+/// 创建一个通道，返回它的两个端点
+pub fn channel_create(
+    params: &mut ChannelParams,
+) -> Result<(Arc<ChannelEndpointInode>, Arc<ChannelEndpointInode>), SystemError> {
+    if params.entries == 0 || params.entries > CHANNEL_MAX_ENTRIES {
+        return Err(SystemError::EINVAL);
+    }
+    if params.slot_size < core::mem::size_of::<ChannelMsgHeader>() as u32
+        || params.slot_size > CHANNEL_MAX_SLOT_SIZE
+    {
+        return Err(SystemError::EINVAL);
+    }
+    let entries = params.entries.next_power_of_two();
+    let slot_size = params
+        .slot_size
+        .max(CHANNEL_MIN_SLOT_SIZE)
+        .next_multiple_of(64);
+
+    let channel = Arc::new(Channel::new(entries, slot_size)?);
+    params.entries = entries;
+    params.slot_size = slot_size;
+    params.region_size = channel.region.len() as u32;
+    params.ring0_off = channel.sides[0].tx_off as u32;
+    params.ring1_off = channel.sides[1].tx_off as u32;
+
+    Ok((
+        ChannelEndpointInode::new(channel.clone(), 0)?,
+        ChannelEndpointInode::new(channel, 1)?,
+    ))
+}
+
+/// 获取fd对应的文件及通道端点
+pub fn channel_get_endpoint(
+    fd: i32,
+) -> Result<(Arc<File>, Arc<ChannelEndpointInode>), SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    let endpoint = file
+        .inode()
+        .downcast_arc::<ChannelEndpointInode>()
+        .ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP)?;
+    Ok((file, endpoint))
+}
@@ -0,0 +1,300 @@
+use alloc::collections::BTreeMap;
+use hashbrown::HashMap;
+use ida::IdAllocator;
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::syscall::ModeType,
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    process::{cred::CAPFlags, ProcessManager},
+    time::timer::{clock, next_n_us_timer_jiffies, Timer, WakeUpHelper},
+};
+
+use super::shm::{PosixIpcPerm, IPC_PRIVATE};
+
+/// 每个IPC命名空间中，每种IPC对象的最大数量，同时也是id中下标部分的上限
+pub const IPCMNI: usize = 32768;
+
+/// 对象不存在时创建
+pub const IPC_CREAT: u32 = 0o1000;
+/// 与IPC_CREAT一起使用，对象已经存在时失败
+pub const IPC_EXCL: u32 = 0o2000;
+/// 操作不能立即完成时不等待
+pub const IPC_NOWAIT: u32 = 0o4000;
+
+/// 删除对象
+pub const IPC_RMID: i32 = 0;
+/// 设置对象的权限信息
+pub const IPC_SET: i32 = 1;
+/// 获取对象的信息
+pub const IPC_STAT: i32 = 2;
+/// 获取系统的限制
+pub const IPC_INFO: i32 = 3;
+/// libc在ctl命令中加上的标志，表示使用64位的结构体，本身没有含义
+pub const IPC_64: i32 = 0x100;
+
+/// 读权限，用于[`IpcPerm::check`]
+pub const S_IRUGO: u32 = 0o444;
+/// 写权限，用于[`IpcPerm::check`]
+pub const S_IWUGO: u32 = 0o222;
+
+/// IPC对象的权限信息，对应Linux的`struct kern_ipc_perm`
+#[derive(Debug, Clone, Copy)]
+pub struct IpcPerm {
+    pub key: i32,
+    pub uid: u32,
+    pub gid: u32,
+    pub cuid: u32,
+    pub cgid: u32,
+    /// 只有低9位的权限位有意义
+    pub mode: u32,
+}
+
+impl IpcPerm {
+    /// 以当前进程的身份创建权限信息
+    pub fn new(key: i32, flags: u32) -> Self {
+        let cred = ProcessManager::current_pcb().cred();
+        let uid = cred.euid.data() as u32;
+        let gid = cred.egid.data() as u32;
+        IpcPerm {
+            key,
+            uid,
+            gid,
+            cuid: uid,
+            cgid: gid,
+            mode: flags & ModeType::S_IRWXUGO.bits(),
+        }
+    }
+
+    pub fn to_posix(self) -> PosixIpcPerm {
+        PosixIpcPerm::new(
+            self.key, self.uid, self.gid, self.cuid, self.cgid, self.mode,
+        )
+    }
+
+    /// # 检查当前进程是否可以按照`flag`访问对象，对应Linux的`ipcperms()`
+    ///
+    /// `flag`中的权限位（如0o400、0o200）表示需要的权限，为0时不检查。
+    /// 有CAP_IPC_OWNER权限的进程不受限制
+    pub fn check(&self, flag: u32) -> Result<(), SystemError> {
+        let requested = (flag >> 6 | flag >> 3 | flag) & 0o7;
+        if requested == 0 {
+            return Ok(());
+        }
+        let cred = ProcessManager::current_pcb().cred();
+        let euid = cred.euid.data() as u32;
+        let egid = cred.egid.data() as u32;
+        let in_group = |gid: u32| {
+            egid == gid
+                || cred
+                    .group_info
+                    .as_ref()
+                    .is_some_and(|info| info.gids.iter().any(|g| g.data() as u32 == gid))
+        };
+        let granted = if euid == self.uid || euid == self.cuid {
+            self.mode >> 6
+        } else if in_group(self.gid) || in_group(self.cgid) {
+            self.mode >> 3
+        } else {
+            self.mode
+        };
+        if requested & !granted & 0o7 != 0 && !cred.has_capability(CAPFlags::CAP_IPC_OWNER) {
+            return Err(SystemError::EACCES);
+        }
+        Ok(())
+    }
+
+    /// IPC_RMID、IPC_SET只允许所有者、创建者或者有CAP_SYS_ADMIN权限的进程执行
+    pub fn check_owner(&self) -> Result<(), SystemError> {
+        let cred = ProcessManager::current_pcb().cred();
+        let euid = cred.euid.data() as u32;
+        if euid == self.uid || euid == self.cuid || cred.has_capability(CAPFlags::CAP_SYS_ADMIN) {
+            return Ok(());
+        }
+        Err(SystemError::EPERM)
+    }
+
+    /// IPC_SET：只能修改所有者和权限位
+    pub fn set(&mut self, perm: &PosixIpcPerm) {
+        self.uid = perm.uid();
+        self.gid = perm.gid();
+        self.mode = perm.mode() & ModeType::S_IRWXUGO.bits();
+    }
+}
+
+#[derive(Debug)]
+struct IpcEntry<T> {
+    id: usize,
+    key: i32,
+    object: T,
+}
+
+/// 一种IPC对象的id表
+///
+/// 对象的id由序号和下标组成（`seq * IPCMNI + index`）。对象删除后下标会被复用，
+/// 但序号会递增，这样已经删除的对象的id不会指向新创建的对象
+#[derive(Debug)]
+pub struct IpcIds<T> {
+    index_allocator: IdAllocator,
+    /// 下一个对象使用的序号
+    seq: usize,
+    entries: BTreeMap<usize, IpcEntry<T>>,
+    key2id: HashMap<i32, usize>,
+}
+
+impl<T> IpcIds<T> {
+    pub fn new() -> Self {
+        IpcIds {
+            index_allocator: IdAllocator::new(0, IPCMNI).unwrap(),
+            seq: 0,
+            entries: BTreeMap::new(),
+            key2id: HashMap::new(),
+        }
+    }
+
+    /// 对象的数量
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 已经使用的最大下标，IPC_INFO命令返回这个值
+    pub fn max_index(&self) -> usize {
+        self.entries.keys().next_back().copied().unwrap_or(0)
+    }
+
+    /// # 按照`key`查找或者创建对象，实现semget、msgget的公共逻辑
+    ///
+    /// ## 参数
+    /// - `check`: 对象已经存在时，检查调用者的参数是否与对象匹配
+    /// - `create`: 创建新的对象
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 对象的id
+    pub fn get_or_create(
+        &mut self,
+        key: i32,
+        flags: u32,
+        check: impl FnOnce(&T) -> Result<(), SystemError>,
+        create: impl FnOnce(IpcPerm) -> Result<T, SystemError>,
+    ) -> Result<usize, SystemError> {
+        if key != IPC_PRIVATE.data() as i32 {
+            if let Some(&id) = self.key2id.get(&key) {
+                if flags & IPC_CREAT != 0 && flags & IPC_EXCL != 0 {
+                    return Err(SystemError::EEXIST);
+                }
+                check(self.get(id).unwrap())?;
+                return Ok(id);
+            }
+            if flags & IPC_CREAT == 0 {
+                return Err(SystemError::ENOENT);
+            }
+        }
+
+        let object = create(IpcPerm::new(key, flags))?;
+        self.insert(key, object)
+    }
+
+    fn insert(&mut self, key: i32, object: T) -> Result<usize, SystemError> {
+        let index = self.index_allocator.alloc().ok_or(SystemError::ENOSPC)?;
+        let id = self.seq * IPCMNI + index;
+        // 保证id是非负的int
+        self.seq = (self.seq + 1) % (i32::MAX as usize / IPCMNI);
+        self.entries.insert(index, IpcEntry { id, key, object });
+        if key != IPC_PRIVATE.data() as i32 {
+            self.key2id.insert(key, id);
+        }
+        Ok(id)
+    }
+
+    /// 按照id获取对象，对象已经被删除时返回None
+    pub fn get(&self, id: usize) -> Option<&T> {
+        self.entries
+            .get(&(id % IPCMNI))
+            .filter(|entry| entry.id == id)
+            .map(|entry| &entry.object)
+    }
+
+    /// 按照下标获取对象和它的id，用于SEM_STAT、MSG_STAT等以下标为参数的命令
+    pub fn get_by_index(&self, index: usize) -> Option<(usize, &T)> {
+        self.entries
+            .get(&index)
+            .map(|entry| (entry.id, &entry.object))
+    }
+
+    /// 删除对象，之后不能再通过key找到它
+    pub fn remove(&mut self, id: usize) -> Option<T> {
+        let index = id % IPCMNI;
+        if self.entries.get(&index)?.id != id {
+            return None;
+        }
+        let entry = self.entries.remove(&index).unwrap();
+        if entry.key != IPC_PRIVATE.data() as i32 {
+            self.key2id.remove(&entry.key);
+        }
+        self.index_allocator.free(index);
+        Some(entry.object)
+    }
+
+    /// 按照下标的顺序遍历所有对象
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.entries.values().map(|entry| (entry.id, &entry.object))
+    }
+}
+
+impl<T> Default for IpcIds<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// # 等待一个IPC操作完成
+///
+/// 每次被唤醒后，在持有`lock`的情况下调用`f`：
+/// - 返回`Ok(Some(r))`表示操作完成
+/// - 返回`Ok(None)`表示需要继续等待
+/// - 返回`Err`表示操作失败，如IPC_NOWAIT时的EAGAIN、对象被删除时的EIDRM
+///
+/// `timeout`为相对的等待时间（微秒），为None时一直等待，超时返回 EAGAIN 。被信号打断时返回 EINTR ，不会重启系统调用
+pub fn ipc_wait<T, R>(
+    lock: &SpinLock<T>,
+    wait_queue: &WaitQueue,
+    timeout: Option<u64>,
+    mut f: impl FnMut(&mut T) -> Result<Option<R>, SystemError>,
+) -> Result<R, SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    // 截止时间，以jiffies计
+    let deadline = timeout.map(next_n_us_timer_jiffies);
+    loop {
+        let mut guard = lock.lock_irqsave();
+        if let Some(r) = f(&mut guard)? {
+            return Ok(r);
+        }
+        if pcb.has_pending_signal() {
+            return Err(SystemError::EINTR);
+        }
+
+        let timer = match deadline {
+            Some(deadline) => {
+                if clock() >= deadline {
+                    return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+                }
+                let timer = Timer::new(WakeUpHelper::new(pcb.clone()), deadline);
+                timer.activate();
+                Some(timer)
+            }
+            None => None,
+        };
+
+        wait_queue.sleep_unlock_spinlock(guard)?;
+
+        if let Some(timer) = timer {
+            if !timer.timeout() {
+                timer.cancel();
+            }
+        }
+    }
+}
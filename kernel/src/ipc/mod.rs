@@ -1,4 +1,9 @@
+pub mod channel;
+pub mod ids;
+pub mod mqueue;
+pub mod msg;
 pub mod pipe;
+pub mod sem;
 pub mod shm;
 pub mod signal;
 pub mod signal_types;
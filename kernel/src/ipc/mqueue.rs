@@ -0,0 +1,830 @@
+//! POSIX消息队列
+//!
+//! 消息队列保存在mqueuefs中。mqueuefs只有一个实例，默认挂载在`/dev/mqueue`，
+//! 可以通过`ls /dev/mqueue`查看系统中的消息队列，读取其中的文件可以得到队列的状态。
+//!
+//! 消息按照优先级从高到低出队，相同优先级的消息先进先出。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/ipc/mqueue.c
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use linkme::distributed_slice;
+use log::info;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    arch::ipc::signal::{SigCode, Signal},
+    driver::base::device::device_number::DeviceNumber,
+    filesystem::vfs::{
+        core::{do_mount_mkdir, generate_inode_id},
+        file::{File, FileMode},
+        syscall::ModeType,
+        utils::DName,
+        FilePrivateData, FileSystem, FileSystemMaker, FileSystemMakerData, FileType, FsInfo,
+        IndexNode, Magic, Metadata, SuperBlock, FSMAKER,
+    },
+    init::initcall::INITCALL_FS,
+    libs::{
+        casting::DowncastArc,
+        rwlock::RwLock,
+        spinlock::{SpinLock, SpinLockGuard},
+        wait_queue::WaitQueue,
+    },
+    net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll},
+    process::{cred::CAPFlags, Pid, ProcessManager},
+    time::{
+        timer::{next_n_us_timer_jiffies, Timer, WakeUpHelper},
+        PosixTimeSpec,
+    },
+};
+
+use super::signal_types::{SigInfo, SigType};
+
+/// 消息优先级的上限（不含）
+pub const MQ_PRIO_MAX: u32 = 32768;
+/// 未指定属性时，队列中最多的消息数
+const DFLT_MSGMAX: usize = 10;
+/// 未指定属性时，每条消息的最大长度
+const DFLT_MSGSIZEMAX: usize = 8192;
+/// 队列中最多的消息数的上限
+const HARD_MSGMAX: usize = 65536;
+/// 每条消息的最大长度的上限
+const HARD_MSGSIZEMAX: usize = 16 * 1024 * 1024;
+/// 没有CAP_SYS_RESOURCE权限时，队列中最多的消息数的上限（Linux的`msg_max`）
+const MSG_MAX: usize = 10;
+/// 没有CAP_SYS_RESOURCE权限时，每条消息的最大长度的上限（Linux的`msgsize_max`）
+const MSGSIZE_MAX: usize = 8192;
+/// 没有CAP_SYS_RESOURCE权限时，系统中最多的队列数（Linux的`queues_max`）
+const QUEUES_MAX: usize = 256;
+/// 每个用户创建的队列最多占用的字节数，与Linux中RLIMIT_MSGQUEUE的默认值相同
+pub const MQ_BYTES_MAX: usize = 819200;
+
+/// 系统中队列的数量，包括已经unlink但是仍然打开着的队列
+static QUEUES_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// 每个用户（以euid区分）创建的队列占用的字节数
+static USER_MQ_BYTES: SpinLock<BTreeMap<usize, usize>> = SpinLock::new(BTreeMap::new());
+
+const MQUEUE_MAX_NAMELEN: usize = 255;
+const MQUEUE_BLOCK_SIZE: u64 = 4096;
+
+/// 与Linux的`struct mq_attr`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixMqAttr {
+    /// 只有O_NONBLOCK有意义
+    pub mq_flags: i64,
+    /// 队列中最多的消息数
+    pub mq_maxmsg: i64,
+    /// 每条消息的最大长度
+    pub mq_msgsize: i64,
+    /// 队列中当前的消息数
+    pub mq_curmsgs: i64,
+    __reserved: [i64; 4],
+}
+
+/// 通过信号通知
+pub const SIGEV_SIGNAL: i32 = 0;
+/// 不通知
+pub const SIGEV_NONE: i32 = 1;
+/// 在新线程中调用函数，需要libc通过netlink实现，暂不支持
+pub const SIGEV_THREAD: i32 = 2;
+
+/// 与Linux的`struct sigevent`相同，大小为64字节
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PosixSigevent {
+    pub sigev_value: u64,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    _pad: [i32; 12],
+}
+
+/// `mq_notify`注册的通知
+#[derive(Debug)]
+struct MqNotify {
+    pid: Pid,
+    /// 为None时（SIGEV_NONE）只占用注册，不发送信号
+    signo: Option<Signal>,
+}
+
+#[derive(Debug)]
+struct InnerMqueue {
+    /// 按照优先级保存的消息，同一优先级内先进先出
+    messages: BTreeMap<u32, VecDeque<Vec<u8>>>,
+    curmsgs: usize,
+    /// 所有消息的总长度
+    qsize: usize,
+    notify: Option<MqNotify>,
+    metadata: Metadata,
+}
+
+/// 一个消息队列
+#[derive(Debug)]
+pub struct MqueueInode {
+    name: String,
+    maxmsg: usize,
+    msgsize: usize,
+    inner: SpinLock<InnerMqueue>,
+    /// 等待消息到来的进程
+    recv_wait: WaitQueue,
+    /// 等待队列有空位的进程
+    send_wait: WaitQueue,
+    epitems: EPollItems,
+    fs: Weak<MqueueFS>,
+    /// 创建者的euid，队列占用的字节数记在这个用户上
+    owner: usize,
+    /// 记在`owner`上的字节数，队列释放时归还
+    charge: usize,
+}
+
+impl MqueueInode {
+    fn new(
+        name: &str,
+        maxmsg: usize,
+        msgsize: usize,
+        mode: ModeType,
+        fs: Weak<MqueueFS>,
+        owner: usize,
+        charge: usize,
+    ) -> Self {
+        let now = PosixTimeSpec::now();
+        MqueueInode {
+            name: name.to_string(),
+            maxmsg,
+            msgsize,
+            owner,
+            charge,
+            inner: SpinLock::new(InnerMqueue {
+                messages: BTreeMap::new(),
+                curmsgs: 0,
+                qsize: 0,
+                notify: None,
+                metadata: Metadata {
+                    dev_id: 0,
+                    inode_id: generate_inode_id(),
+                    size: 0,
+                    blk_size: 0,
+                    blocks: 0,
+                    atime: now,
+                    mtime: now,
+                    ctime: now,
+                    file_type: FileType::File,
+                    mode: mode & ModeType::S_IRWXUGO,
+                    nlinks: 1,
+                    uid: 0,
+                    gid: 0,
+                    raw_dev: DeviceNumber::default(),
+                },
+            }),
+            recv_wait: WaitQueue::default(),
+            send_wait: WaitQueue::default(),
+            epitems: EPollItems::new(),
+            fs,
+        }
+    }
+
+    /// 按照Linux的方式估算一个队列最多占用的内存：消息本身加上每条消息的管理开销
+    fn mq_bytes(maxmsg: usize, msgsize: usize) -> usize {
+        maxmsg * (msgsize + core::mem::size_of::<Vec<u8>>())
+    }
+
+    /// 队列当前的属性，`mq_flags`由调用者根据文件的打开模式填写
+    pub fn attr(&self) -> PosixMqAttr {
+        PosixMqAttr {
+            mq_maxmsg: self.maxmsg as i64,
+            mq_msgsize: self.msgsize as i64,
+            mq_curmsgs: self.inner.lock_irqsave().curmsgs as i64,
+            ..Default::default()
+        }
+    }
+
+    /// 等待`cond`成立，返回持有的锁
+    ///
+    /// `timeout`为CLOCK_REALTIME上的绝对时间，为None时一直等待
+    fn wait_for(
+        &self,
+        wait_queue: &WaitQueue,
+        nonblock: bool,
+        timeout: Option<PosixTimeSpec>,
+        cond: impl Fn(&InnerMqueue) -> bool,
+    ) -> Result<SpinLockGuard<InnerMqueue>, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let mut timed_out = false;
+        loop {
+            let guard = self.inner.lock_irqsave();
+            if cond(&guard) {
+                return Ok(guard);
+            }
+            if nonblock {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            if timed_out {
+                return Err(SystemError::ETIMEDOUT);
+            }
+            if pcb.has_pending_signal() {
+                return Err(SystemError::ERESTARTSYS);
+            }
+
+            let timer = match timeout {
+                Some(timeout) => {
+                    let now = PosixTimeSpec::now();
+                    if timeout.total_nanos() <= now.total_nanos() {
+                        return Err(SystemError::ETIMEDOUT);
+                    }
+                    let us = (timeout.total_nanos() - now.total_nanos()) / 1000;
+                    let timer = Timer::new(
+                        WakeUpHelper::new(pcb.clone()),
+                        next_n_us_timer_jiffies(us as u64),
+                    );
+                    timer.activate();
+                    Some(timer)
+                }
+                None => None,
+            };
+
+            wait_queue.sleep_unlock_spinlock(guard)?;
+
+            if let Some(timer) = timer {
+                if timer.timeout() {
+                    timed_out = true;
+                } else {
+                    timer.cancel();
+                }
+            }
+        }
+    }
+
+    /// # 发送一条消息
+    ///
+    /// 队列满时，`nonblock`为true则以 EAGAIN 失败，否则阻塞直到队列有空位或者超时
+    pub fn send(
+        &self,
+        msg: &[u8],
+        prio: u32,
+        nonblock: bool,
+        timeout: Option<PosixTimeSpec>,
+    ) -> Result<(), SystemError> {
+        if msg.len() > self.msgsize {
+            return Err(SystemError::EMSGSIZE);
+        }
+        if prio >= MQ_PRIO_MAX {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut guard = self.wait_for(&self.send_wait, nonblock, timeout, |inner| {
+            inner.curmsgs < self.maxmsg
+        })?;
+        let was_empty = guard.curmsgs == 0;
+        guard
+            .messages
+            .entry(prio)
+            .or_default()
+            .push_back(msg.to_vec());
+        guard.curmsgs += 1;
+        guard.qsize += msg.len();
+        guard.metadata.size = guard.qsize as i64;
+        guard.metadata.mtime = PosixTimeSpec::now();
+
+        // 空队列收到消息，并且没有进程在等待接收时，通知注册了mq_notify的进程。通知只发送一次
+        let notify = if was_empty && self.recv_wait.len() == 0 {
+            guard.notify.take()
+        } else {
+            None
+        };
+        drop(guard);
+
+        if let Some(MqNotify {
+            pid,
+            signo: Some(sig),
+        }) = notify
+        {
+            let mut info = SigInfo::new(
+                sig,
+                0,
+                SigCode::Mesgq,
+                SigType::Kill(ProcessManager::current_pid()),
+            );
+            sig.send_signal_info(Some(&mut info), pid).ok();
+        }
+
+        self.recv_wait.wakeup_all(None);
+        self.epitems
+            .wakeup(Some(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM))?;
+        return Ok(());
+    }
+
+    /// # 接收优先级最高的消息
+    ///
+    /// 返回消息的长度和优先级。队列为空时，`nonblock`为true则以 EAGAIN 失败，否则阻塞直到有消息或者超时
+    pub fn receive(
+        &self,
+        buf: &mut [u8],
+        nonblock: bool,
+        timeout: Option<PosixTimeSpec>,
+    ) -> Result<(usize, u32), SystemError> {
+        if buf.len() < self.msgsize {
+            return Err(SystemError::EMSGSIZE);
+        }
+
+        let mut guard = self.wait_for(&self.recv_wait, nonblock, timeout, |inner| {
+            inner.curmsgs > 0
+        })?;
+        let mut entry = guard.messages.last_entry().unwrap();
+        let prio = *entry.key();
+        let msg = entry.get_mut().pop_front().unwrap();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        guard.curmsgs -= 1;
+        guard.qsize -= msg.len();
+        guard.metadata.size = guard.qsize as i64;
+        guard.metadata.atime = PosixTimeSpec::now();
+        drop(guard);
+
+        buf[..msg.len()].copy_from_slice(&msg);
+
+        self.send_wait.wakeup_all(None);
+        self.epitems
+            .wakeup(Some(EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM))?;
+        return Ok((msg.len(), prio));
+    }
+
+    /// # 注册或者取消消息到达的通知
+    ///
+    /// - `sigevent`为None时，取消当前进程注册的通知
+    /// - 同一时间只能有一个进程注册通知，否则以 EBUSY 失败
+    pub fn set_notify(&self, sigevent: Option<&PosixSigevent>) -> Result<(), SystemError> {
+        let pid = ProcessManager::current_pid();
+        let mut guard = self.inner.lock_irqsave();
+        let Some(sigevent) = sigevent else {
+            if guard.notify.as_ref().is_some_and(|n| n.pid == pid) {
+                guard.notify = None;
+            }
+            return Ok(());
+        };
+
+        let signo = match sigevent.sigev_notify {
+            SIGEV_NONE => None,
+            SIGEV_SIGNAL => {
+                let sig = Signal::from(sigevent.sigev_signo);
+                if sig == Signal::INVALID {
+                    return Err(SystemError::EINVAL);
+                }
+                Some(sig)
+            }
+            SIGEV_THREAD => return Err(SystemError::ENOSYS),
+            _ => return Err(SystemError::EINVAL),
+        };
+        if guard.notify.is_some() {
+            return Err(SystemError::EBUSY);
+        }
+        guard.notify = Some(MqNotify { pid, signo });
+        return Ok(());
+    }
+
+    /// 读取队列文件时得到的状态，格式与Linux相同
+    fn status(&self) -> String {
+        let guard = self.inner.lock_irqsave();
+        let (notify, signo, pid) = match &guard.notify {
+            Some(MqNotify { pid, signo }) => (
+                if signo.is_some() {
+                    SIGEV_SIGNAL
+                } else {
+                    SIGEV_NONE
+                },
+                signo.map(|s| s as i32).unwrap_or(0),
+                pid.data(),
+            ),
+            None => (0, 0, 0),
+        };
+        format!(
+            "QSIZE:{:<10} NOTIFY:{:<5} SIGNO:{:<5} NOTIFY_PID:{:<6}\n",
+            guard.qsize, notify, signo, pid
+        )
+    }
+
+    fn do_poll(&self) -> EPollEventType {
+        let guard = self.inner.lock_irqsave();
+        let mut events = EPollEventType::empty();
+        if guard.curmsgs > 0 {
+            events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+        }
+        if guard.curmsgs < self.maxmsg {
+            events |= EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        }
+        events
+    }
+}
+
+impl IndexNode for MqueueInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    /// 关闭时取消当前进程注册的通知
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        self.set_notify(None)
+    }
+
+    fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        let status = self.status();
+        let status = status.as_bytes();
+        if offset >= status.len() {
+            return Ok(0);
+        }
+        let len = len.min(status.len() - offset);
+        buf[..len].copy_from_slice(&status[offset..offset + len]);
+        return Ok(len);
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EINVAL)
+    }
+
+    fn poll(&self, _private_data: &FilePrivateData) -> Result<usize, SystemError> {
+        Ok(self.do_poll().bits() as usize)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(self.inner.lock_irqsave().metadata.clone())
+    }
+
+    fn set_metadata(&self, metadata: &Metadata) -> Result<(), SystemError> {
+        let mut guard = self.inner.lock_irqsave();
+        guard.metadata.mode = metadata.mode;
+        guard.metadata.uid = metadata.uid;
+        guard.metadata.gid = metadata.gid;
+        guard.metadata.atime = metadata.atime;
+        guard.metadata.mtime = metadata.mtime;
+        guard.metadata.ctime = metadata.ctime;
+        Ok(())
+    }
+
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.add(epitem);
+        Ok(())
+    }
+
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.epitems.remove(epoll)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        Err(SystemError::ENOTDIR)
+    }
+
+    fn dname(&self) -> Result<DName, SystemError> {
+        Ok(self.name.as_str().into())
+    }
+}
+
+impl Drop for MqueueInode {
+    fn drop(&mut self) {
+        QUEUES_COUNT.fetch_sub(1, Ordering::SeqCst);
+        let mut user_bytes = USER_MQ_BYTES.lock_irqsave();
+        if let Some(used) = user_bytes.get_mut(&self.owner) {
+            *used -= self.charge;
+            if *used == 0 {
+                user_bytes.remove(&self.owner);
+            }
+        }
+    }
+}
+
+/// mqueuefs的根目录，其中的每一个文件是一个消息队列
+#[derive(Debug)]
+pub struct MqueueRootInode {
+    queues: SpinLock<BTreeMap<String, Arc<MqueueInode>>>,
+    metadata: Metadata,
+    fs: RwLock<Weak<MqueueFS>>,
+}
+
+impl MqueueRootInode {
+    fn new() -> Self {
+        MqueueRootInode {
+            queues: SpinLock::new(BTreeMap::new()),
+            metadata: Metadata {
+                dev_id: 0,
+                inode_id: generate_inode_id(),
+                size: 0,
+                blk_size: 0,
+                blocks: 0,
+                atime: PosixTimeSpec::default(),
+                mtime: PosixTimeSpec::default(),
+                ctime: PosixTimeSpec::default(),
+                file_type: FileType::Dir,
+                mode: ModeType::from_bits_truncate(0o1777),
+                nlinks: 2,
+                uid: 0,
+                gid: 0,
+                raw_dev: DeviceNumber::default(),
+            },
+            fs: RwLock::new(Weak::new()),
+        }
+    }
+
+    /// # 打开一个消息队列
+    ///
+    /// - `create`为true时，队列不存在则用`attr`指定的属性创建，`attr`为None时使用默认属性
+    /// - `exclusive`为true时，队列已经存在则以 EEXIST 失败
+    pub fn open_queue(
+        &self,
+        name: &str,
+        create: bool,
+        exclusive: bool,
+        mode: ModeType,
+        attr: Option<&PosixMqAttr>,
+    ) -> Result<Arc<MqueueInode>, SystemError> {
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(SystemError::EINVAL);
+        }
+        if name.contains('/') {
+            return Err(SystemError::EACCES);
+        }
+        if name.len() > MQUEUE_MAX_NAMELEN {
+            return Err(SystemError::ENAMETOOLONG);
+        }
+
+        let mut queues = self.queues.lock_irqsave();
+        if let Some(queue) = queues.get(name) {
+            if create && exclusive {
+                return Err(SystemError::EEXIST);
+            }
+            return Ok(queue.clone());
+        }
+        if !create {
+            return Err(SystemError::ENOENT);
+        }
+
+        let cred = ProcessManager::current_pcb().cred();
+        let privileged = cred.has_capability(CAPFlags::CAP_SYS_RESOURCE);
+        let (maxmsg, msgsize) = match attr {
+            Some(attr) => {
+                if attr.mq_maxmsg <= 0
+                    || attr.mq_msgsize <= 0
+                    || attr.mq_maxmsg as usize > HARD_MSGMAX
+                    || attr.mq_msgsize as usize > HARD_MSGSIZEMAX
+                {
+                    return Err(SystemError::EINVAL);
+                }
+                if !privileged
+                    && (attr.mq_maxmsg as usize > MSG_MAX || attr.mq_msgsize as usize > MSGSIZE_MAX)
+                {
+                    return Err(SystemError::EINVAL);
+                }
+                (attr.mq_maxmsg as usize, attr.mq_msgsize as usize)
+            }
+            None => (DFLT_MSGMAX, DFLT_MSGSIZEMAX),
+        };
+        if !privileged && QUEUES_COUNT.load(Ordering::SeqCst) >= QUEUES_MAX {
+            return Err(SystemError::ENOSPC);
+        }
+
+        // 与Linux相同，超出每个用户的字节数限制时返回 EMFILE
+        let owner = cred.euid.data();
+        let charge = MqueueInode::mq_bytes(maxmsg, msgsize);
+        let mut user_bytes = USER_MQ_BYTES.lock_irqsave();
+        let used = user_bytes.get(&owner).copied().unwrap_or(0);
+        if used + charge > MQ_BYTES_MAX {
+            return Err(SystemError::EMFILE);
+        }
+        user_bytes.insert(owner, used + charge);
+        drop(user_bytes);
+        QUEUES_COUNT.fetch_add(1, Ordering::SeqCst);
+
+        let queue = Arc::new(MqueueInode::new(
+            name,
+            maxmsg,
+            msgsize,
+            mode,
+            self.fs.read().clone(),
+            owner,
+            charge,
+        ));
+        queues.insert(name.to_string(), queue.clone());
+        return Ok(queue);
+    }
+}
+
+impl IndexNode for MqueueRootInode {
+    fn open(
+        &self,
+        _data: SpinLockGuard<FilePrivateData>,
+        _mode: &FileMode,
+    ) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn close(&self, _data: SpinLockGuard<FilePrivateData>) -> Result<(), SystemError> {
+        Ok(())
+    }
+
+    fn read_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &mut [u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EISDIR)
+    }
+
+    fn write_at(
+        &self,
+        _offset: usize,
+        _len: usize,
+        _buf: &[u8],
+        _data: SpinLockGuard<FilePrivateData>,
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::EISDIR)
+    }
+
+    fn metadata(&self) -> Result<Metadata, SystemError> {
+        Ok(self.metadata.clone())
+    }
+
+    /// 在目录中创建普通文件，相当于以默认属性调用mq_open
+    fn create_with_data(
+        &self,
+        name: &str,
+        file_type: FileType,
+        mode: ModeType,
+        _data: usize,
+    ) -> Result<Arc<dyn IndexNode>, SystemError> {
+        if file_type != FileType::File {
+            return Err(SystemError::EPERM);
+        }
+        Ok(self.open_queue(name, true, true, mode, None)?)
+    }
+
+    fn find(&self, name: &str) -> Result<Arc<dyn IndexNode>, SystemError> {
+        self.queues
+            .lock_irqsave()
+            .get(name)
+            .map(|q| q.clone() as Arc<dyn IndexNode>)
+            .ok_or(SystemError::ENOENT)
+    }
+
+    /// 删除消息队列。已经打开的队列在关闭之前仍然可以使用
+    fn unlink(&self, name: &str) -> Result<(), SystemError> {
+        self.queues
+            .lock_irqsave()
+            .remove(name)
+            .map(|_| ())
+            .ok_or(SystemError::ENOENT)
+    }
+
+    fn fs(&self) -> Arc<dyn FileSystem> {
+        self.fs.read().upgrade().unwrap()
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn list(&self) -> Result<Vec<String>, SystemError> {
+        let mut keys = vec![String::from("."), String::from("..")];
+        keys.extend(self.queues.lock_irqsave().keys().cloned());
+        Ok(keys)
+    }
+}
+
+/// 保存消息队列的文件系统
+#[derive(Debug)]
+pub struct MqueueFS {
+    root_inode: Arc<MqueueRootInode>,
+}
+
+impl MqueueFS {
+    fn new() -> Arc<Self> {
+        let fs = Arc::new(MqueueFS {
+            root_inode: Arc::new(MqueueRootInode::new()),
+        });
+        *fs.root_inode.fs.write() = Arc::downgrade(&fs);
+        fs
+    }
+
+    pub fn root(&self) -> &Arc<MqueueRootInode> {
+        &self.root_inode
+    }
+
+    /// 同一个系统中只有一个mqueuefs，每次挂载得到的都是同一个实例
+    pub fn make_mqueuefs(
+        _data: Option<&dyn FileSystemMakerData>,
+    ) -> Result<Arc<dyn FileSystem + 'static>, SystemError> {
+        Ok(mqueuefs().clone())
+    }
+}
+
+impl FileSystem for MqueueFS {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        self.root_inode.clone()
+    }
+
+    fn info(&self) -> FsInfo {
+        FsInfo {
+            blk_dev_id: 0,
+            max_name_len: MQUEUE_MAX_NAMELEN,
+        }
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "mqueue"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        SuperBlock::new(
+            Magic::MQUEUE_MAGIC,
+            MQUEUE_BLOCK_SIZE,
+            MQUEUE_MAX_NAMELEN as u64,
+        )
+    }
+}
+
+#[distributed_slice(FSMAKER)]
+static MQUEUEFSMAKER: FileSystemMaker = FileSystemMaker::new(
+    "mqueue",
+    &(MqueueFS::make_mqueuefs
+        as fn(
+            Option<&dyn FileSystemMakerData>,
+        ) -> Result<Arc<dyn FileSystem + 'static>, SystemError>),
+);
+
+/// 获取fd对应的消息队列
+pub fn mqueue_get(fd: i32) -> Result<(Arc<File>, Arc<MqueueInode>), SystemError> {
+    let file = ProcessManager::current_pcb()
+        .fd_table()
+        .read()
+        .get_file_by_fd(fd)
+        .ok_or(SystemError::EBADF)?;
+    let queue = file
+        .inode()
+        .downcast_arc::<MqueueInode>()
+        .ok_or(SystemError::EBADF)?;
+    Ok((file, queue))
+}
+
+lazy_static! {
+    static ref MQUEUE_FS: Arc<MqueueFS> = MqueueFS::new();
+}
+
+/// 系统中唯一的mqueuefs
+pub fn mqueuefs() -> &'static Arc<MqueueFS> {
+    &MQUEUE_FS
+}
+
+#[unified_init(INITCALL_FS)]
+#[inline(never)]
+pub fn mqueue_init() -> Result<(), SystemError> {
+    do_mount_mkdir(mqueuefs().clone(), "/dev/mqueue").expect("Failed to mount mqueuefs");
+    info!("mqueuefs mounted.");
+    Ok(())
+}
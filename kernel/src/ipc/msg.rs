@@ -0,0 +1,442 @@
+use alloc::{
+    collections::VecDeque,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt::Write;
+use system_error::SystemError;
+
+use crate::{
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    namespaces::ipc_namespace::IpcNamespace,
+    process::{cred::CAPFlags, ProcessManager},
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+    time::PosixTimeSpec,
+};
+
+use super::{
+    ids::{
+        ipc_wait, IpcIds, IpcPerm, IPCMNI, IPC_64, IPC_INFO, IPC_NOWAIT, IPC_RMID, IPC_SET,
+        IPC_STAT, S_IRUGO, S_IWUGO,
+    },
+    shm::PosixIpcPerm,
+};
+
+/// 单条消息的最大长度
+pub const MSGMAX: usize = 8192;
+/// 一个消息队列默认的最大字节数
+pub const MSGMNB: usize = 16384;
+/// 消息队列的最大数量
+pub const MSGMNI: usize = IPCMNI;
+
+/// 接收时，消息过长则截断而不是失败
+pub const MSG_NOERROR: u32 = 0o10000;
+/// 接收第一条类型不等于msgtyp的消息
+pub const MSG_EXCEPT: u32 = 0o20000;
+/// 复制消息而不从队列中删除。暂不支持
+pub const MSG_COPY: u32 = 0o40000;
+
+const MSG_STAT: i32 = 11;
+const MSG_INFO: i32 = 12;
+const MSG_STAT_ANY: i32 = 13;
+
+pub type MsgIds = IpcIds<Arc<MsgQueue>>;
+
+/// 消息队列的信息，与Linux的`struct msqid64_ds`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixMsqidDs {
+    msg_perm: PosixIpcPerm,
+    msg_stime: i64,
+    msg_rtime: i64,
+    msg_ctime: i64,
+    msg_cbytes: u64,
+    msg_qnum: u64,
+    msg_qbytes: u64,
+    msg_lspid: i32,
+    msg_lrpid: i32,
+    _unused4: u64,
+    _unused5: u64,
+}
+
+/// 消息队列的系统限制，与Linux的`struct msginfo`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixMsginfo {
+    msgpool: i32,
+    msgmap: i32,
+    msgmax: i32,
+    msgmnb: i32,
+    msgmni: i32,
+    msgssz: i32,
+    msgtql: i32,
+    msgseg: u16,
+}
+
+#[derive(Debug)]
+struct Msg {
+    mtype: i64,
+    text: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct MsgQueueInner {
+    perm: IpcPerm,
+    messages: VecDeque<Msg>,
+    /// 队列中所有消息的总字节数
+    cbytes: usize,
+    /// 队列允许的最大字节数
+    qbytes: usize,
+    /// 最后一次msgsnd的时间和进程
+    stime: i64,
+    lspid: usize,
+    /// 最后一次msgrcv的时间和进程
+    rtime: i64,
+    lrpid: usize,
+    ctime: i64,
+    /// 已经被IPC_RMID删除
+    removed: bool,
+}
+
+/// 一个System V消息队列
+#[derive(Debug)]
+pub struct MsgQueue {
+    inner: SpinLock<MsgQueueInner>,
+    /// 等待队列有空间的发送者
+    send_wait: WaitQueue,
+    /// 等待消息的接收者
+    recv_wait: WaitQueue,
+}
+
+impl MsgQueue {
+    fn new(perm: IpcPerm) -> Self {
+        MsgQueue {
+            inner: SpinLock::new(MsgQueueInner {
+                perm,
+                messages: VecDeque::new(),
+                cbytes: 0,
+                qbytes: MSGMNB,
+                stime: 0,
+                lspid: 0,
+                rtime: 0,
+                lrpid: 0,
+                ctime: PosixTimeSpec::now().tv_sec,
+                removed: false,
+            }),
+            send_wait: WaitQueue::default(),
+            recv_wait: WaitQueue::default(),
+        }
+    }
+
+    fn stat(&self) -> PosixMsqidDs {
+        let inner = self.inner.lock_irqsave();
+        PosixMsqidDs {
+            msg_perm: inner.perm.to_posix(),
+            msg_stime: inner.stime,
+            msg_rtime: inner.rtime,
+            msg_ctime: inner.ctime,
+            msg_cbytes: inner.cbytes as u64,
+            msg_qnum: inner.messages.len() as u64,
+            msg_qbytes: inner.qbytes as u64,
+            msg_lspid: inner.lspid as i32,
+            msg_lrpid: inner.lrpid as i32,
+            ..Default::default()
+        }
+    }
+
+    /// 删除消息队列，唤醒所有等待的进程，它们会以 EIDRM 失败
+    fn remove(&self) {
+        self.inner.lock_irqsave().removed = true;
+        self.send_wait.wakeup_all(None);
+        self.recv_wait.wakeup_all(None);
+    }
+
+    /// 按照`msgtyp`选择要接收的消息
+    fn find_message(messages: &VecDeque<Msg>, msgtyp: i64, msgflg: u32) -> Option<usize> {
+        if msgtyp == 0 {
+            return if messages.is_empty() { None } else { Some(0) };
+        }
+        if msgtyp > 0 {
+            let except = msgflg & MSG_EXCEPT != 0;
+            return messages
+                .iter()
+                .position(|msg| (msg.mtype == msgtyp) != except);
+        }
+        // msgtyp小于0时，接收类型不大于|msgtyp|的消息中类型最小的第一条
+        let mut found: Option<usize> = None;
+        for (i, msg) in messages.iter().enumerate() {
+            if msg.mtype <= msgtyp.saturating_neg()
+                && found.map_or(true, |f| msg.mtype < messages[f].mtype)
+            {
+                found = Some(i);
+            }
+        }
+        found
+    }
+}
+
+/// # 获取或者创建消息队列
+///
+/// ## 参数
+/// - `key`: 消息队列的键值，IPC_PRIVATE表示创建新的队列
+/// - `msgflg`: IPC_CREAT、IPC_EXCL和权限位
+pub fn msgget(key: i32, msgflg: u32) -> Result<usize, SystemError> {
+    let ns = IpcNamespace::current();
+    let mut ids = ns.msg_ids.lock_irqsave();
+    ids.get_or_create(
+        key,
+        msgflg,
+        |queue| queue.inner.lock_irqsave().perm.check(msgflg),
+        |perm| Ok(Arc::new(MsgQueue::new(perm))),
+    )
+}
+
+fn find_msg_queue(msqid: i32) -> Result<Arc<MsgQueue>, SystemError> {
+    if msqid < 0 {
+        return Err(SystemError::EINVAL);
+    }
+    IpcNamespace::current()
+        .msg_ids
+        .lock_irqsave()
+        .get(msqid as usize)
+        .cloned()
+        .ok_or(SystemError::EINVAL)
+}
+
+/// # 发送一条消息
+///
+/// 队列已满时，设置了IPC_NOWAIT则以 EAGAIN 失败，否则等待
+pub fn msgsnd(msqid: i32, mtype: i64, text: &[u8], msgflg: u32) -> Result<usize, SystemError> {
+    if mtype < 1 || text.len() > MSGMAX {
+        return Err(SystemError::EINVAL);
+    }
+    let queue = find_msg_queue(msqid)?;
+    let mut text = Some(text.to_vec());
+    ipc_wait(&queue.inner, &queue.send_wait, None, |inner| {
+        if inner.removed {
+            return Err(SystemError::EIDRM);
+        }
+        // 每次被唤醒都重新检查，等待期间权限可能被IPC_SET修改
+        inner.perm.check(S_IWUGO)?;
+        let len = text.as_ref().unwrap().len();
+        // 与Linux相同，消息的数量也不能超过qbytes，避免大量空消息占满内存
+        if inner.cbytes + len > inner.qbytes || inner.messages.len() + 1 > inner.qbytes {
+            if msgflg & IPC_NOWAIT != 0 {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            return Ok(None);
+        }
+        inner.messages.push_back(Msg {
+            mtype,
+            text: text.take().unwrap(),
+        });
+        inner.cbytes += len;
+        inner.stime = PosixTimeSpec::now().tv_sec;
+        inner.lspid = ProcessManager::current_pid().data();
+        Ok(Some(()))
+    })?;
+    queue.recv_wait.wakeup_all(None);
+    Ok(0)
+}
+
+/// # 接收一条消息
+///
+/// ## 参数
+/// - `msgtyp`: 为0时接收第一条消息；大于0时接收第一条类型为`msgtyp`的消息（MSG_EXCEPT时为类型不等于`msgtyp`）；
+///   小于0时接收类型不大于|msgtyp|的消息中类型最小的一条
+/// - `msgsz`: 缓冲区能容纳的消息长度
+///
+/// ## 返回值
+/// - `Ok((mtype, text))`: 消息的类型和内容，内容已经按照MSG_NOERROR截断
+pub fn msgrcv(
+    msqid: i32,
+    msgsz: usize,
+    msgtyp: i64,
+    msgflg: u32,
+) -> Result<(i64, Vec<u8>), SystemError> {
+    if msgflg & MSG_COPY != 0 {
+        return Err(SystemError::ENOSYS);
+    }
+    let queue = find_msg_queue(msqid)?;
+    let (mtype, mut text) = ipc_wait(&queue.inner, &queue.recv_wait, None, |inner| {
+        if inner.removed {
+            return Err(SystemError::EIDRM);
+        }
+        inner.perm.check(S_IRUGO)?;
+        let Some(i) = MsgQueue::find_message(&inner.messages, msgtyp, msgflg) else {
+            if msgflg & IPC_NOWAIT != 0 {
+                return Err(SystemError::ENOMSG);
+            }
+            return Ok(None);
+        };
+        if inner.messages[i].text.len() > msgsz && msgflg & MSG_NOERROR == 0 {
+            return Err(SystemError::E2BIG);
+        }
+        let msg = inner.messages.remove(i).unwrap();
+        inner.cbytes -= msg.text.len();
+        inner.rtime = PosixTimeSpec::now().tv_sec;
+        inner.lrpid = ProcessManager::current_pid().data();
+        Ok(Some((msg.mtype, msg.text)))
+    })?;
+    queue.send_wait.wakeup_all(None);
+    text.truncate(msgsz);
+    Ok((mtype, text))
+}
+
+/// # 管理消息队列
+///
+/// ## 参数
+/// - `msqid`: 消息队列的id。MSG_STAT和MSG_STAT_ANY命令中为下标
+/// - `cmd`: 操作码
+/// - `buf`: 用户空间的`struct msqid64_ds`或者`struct msginfo`
+pub fn msgctl(msqid: i32, cmd: i32, buf: usize) -> Result<usize, SystemError> {
+    let cmd = cmd & !IPC_64;
+    let ns = IpcNamespace::current();
+    match cmd {
+        IPC_INFO | MSG_INFO => {
+            let ids = ns.msg_ids.lock_irqsave();
+            let max_index = ids.max_index();
+            let mut info = PosixMsginfo {
+                msgpool: (MSGMNI * MSGMNB / 1024) as i32,
+                msgmap: MSGMNB as i32,
+                msgmax: MSGMAX as i32,
+                msgmnb: MSGMNB as i32,
+                msgmni: MSGMNI as i32,
+                msgssz: 16,
+                msgtql: MSGMNB as i32,
+                msgseg: 0xffff,
+            };
+            if cmd == MSG_INFO {
+                // MSG_INFO时，msgpool为队列的数量，msgmap为消息的总数，msgtql为消息的总字节数
+                info.msgpool = ids.len() as i32;
+                let (count, bytes) = ids.iter().fold((0, 0), |(count, bytes), (_, queue)| {
+                    let inner = queue.inner.lock_irqsave();
+                    (count + inner.messages.len(), bytes + inner.cbytes)
+                });
+                info.msgmap = count as i32;
+                info.msgtql = bytes as i32;
+            }
+            drop(ids);
+            UserBufferWriter::new(
+                buf as *mut PosixMsginfo,
+                core::mem::size_of::<PosixMsginfo>(),
+                true,
+            )?
+            .copy_one_to_user(&info, 0)?;
+            return Ok(max_index);
+        }
+        MSG_STAT | MSG_STAT_ANY => {
+            if msqid < 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let (id, ds) = {
+                let ids = ns.msg_ids.lock_irqsave();
+                let (id, queue) = ids
+                    .get_by_index(msqid as usize)
+                    .ok_or(SystemError::EINVAL)?;
+                if cmd == MSG_STAT {
+                    queue.inner.lock_irqsave().perm.check(S_IRUGO)?;
+                }
+                (id, queue.stat())
+            };
+            UserBufferWriter::new(
+                buf as *mut PosixMsqidDs,
+                core::mem::size_of::<PosixMsqidDs>(),
+                true,
+            )?
+            .copy_one_to_user(&ds, 0)?;
+            return Ok(id);
+        }
+        IPC_RMID => {
+            if msqid < 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let mut ids = ns.msg_ids.lock_irqsave();
+            ids.get(msqid as usize)
+                .ok_or(SystemError::EINVAL)?
+                .inner
+                .lock_irqsave()
+                .perm
+                .check_owner()?;
+            let queue = ids.remove(msqid as usize).unwrap();
+            drop(ids);
+            queue.remove();
+            return Ok(0);
+        }
+        _ => {}
+    }
+
+    let queue = find_msg_queue(msqid)?;
+    match cmd {
+        IPC_STAT => {
+            queue.inner.lock_irqsave().perm.check(S_IRUGO)?;
+            let ds = queue.stat();
+            UserBufferWriter::new(
+                buf as *mut PosixMsqidDs,
+                core::mem::size_of::<PosixMsqidDs>(),
+                true,
+            )?
+            .copy_one_to_user(&ds, 0)?;
+            Ok(0)
+        }
+        IPC_SET => {
+            let ds = *UserBufferReader::new(
+                buf as *const PosixMsqidDs,
+                core::mem::size_of::<PosixMsqidDs>(),
+                true,
+            )?
+            .read_one_from_user::<PosixMsqidDs>(0)?;
+            let mut inner = queue.inner.lock_irqsave();
+            inner.perm.check_owner()?;
+            // 只有特权进程才能把队列的容量调到系统默认值以上
+            if ds.msg_qbytes as usize > MSGMNB
+                && !ProcessManager::current_pcb()
+                    .cred()
+                    .has_capability(CAPFlags::CAP_SYS_RESOURCE)
+            {
+                return Err(SystemError::EPERM);
+            }
+            inner.perm.set(&ds.msg_perm);
+            inner.qbytes = ds.msg_qbytes as usize;
+            inner.ctime = PosixTimeSpec::now().tv_sec;
+            drop(inner);
+            // 队列变大后，等待的发送者可能可以继续发送
+            queue.send_wait.wakeup_all(None);
+            Ok(0)
+        }
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+/// /proc/sysvipc/msg的内容
+pub fn msg_procfs_show() -> String {
+    let mut s = "       key      msqid perms      cbytes       qnum lspid lrpid   uid   gid  cuid  cgid      stime      rtime      ctime\n"
+        .to_string();
+    let ns = IpcNamespace::current();
+    let ids = ns.msg_ids.lock_irqsave();
+    for (id, queue) in ids.iter() {
+        let inner = queue.inner.lock_irqsave();
+        let perm = &inner.perm;
+        writeln!(
+            s,
+            "{:>10} {:>10}  {:>4o}  {:>10} {:>10} {:>5} {:>5} {:>5} {:>5} {:>5} {:>5} {:>10} {:>10} {:>10}",
+            perm.key,
+            id,
+            perm.mode,
+            inner.cbytes,
+            inner.messages.len(),
+            inner.lspid,
+            inner.lrpid,
+            perm.uid,
+            perm.gid,
+            perm.cuid,
+            perm.cgid,
+            inner.stime,
+            inner.rtime,
+            inner.ctime
+        )
+        .ok();
+    }
+    s
+}
@@ -10,23 +10,19 @@ use crate::{
         spinlock::{SpinLock, SpinLockGuard},
         wait_queue::WaitQueue,
     },
-    net::event_poll::{EPollEventType, EPollItem, EventPoll},
+    net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll},
     process::{ProcessFlags, ProcessManager, ProcessState},
     sched::SchedMode,
     time::PosixTimeSpec,
 };
 
-use alloc::{
-    collections::LinkedList,
-    sync::{Arc, Weak},
-    vec::Vec,
-};
+use alloc::sync::{Arc, Weak};
 use system_error::SystemError;
 
 use super::signal_types::{SigInfo, SigType};
 
 /// 我们设定pipe_buff的总大小为1024字节
-const PIPE_BUFF_SIZE: usize = 1024;
+pub const PIPE_BUFF_SIZE: usize = 1024;
 
 #[derive(Debug, Clone)]
 pub struct PipeFsPrivateData {
@@ -65,7 +61,7 @@ pub struct InnerPipeInode {
     reader: u32,
     writer: u32,
     had_reader: bool,
-    epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+    epitems: EPollItems,
 }
 
 impl InnerPipeInode {
@@ -105,29 +101,9 @@ impl InnerPipeInode {
         Ok(events.bits() as usize)
     }
 
-    pub fn add_epoll(&mut self, epitem: Arc<EPollItem>) -> Result<(), SystemError> {
-        self.epitems.lock().push_back(epitem);
-        Ok(())
-    }
-
     fn buf_full(&self) -> bool {
         return self.valid_cnt as usize == PIPE_BUFF_SIZE;
     }
-
-    pub fn remove_epoll(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
-        let is_remove = !self
-            .epitems
-            .lock_irqsave()
-            .extract_if(|x| x.epoll().ptr_eq(epoll))
-            .collect::<Vec<_>>()
-            .is_empty();
-
-        if is_remove {
-            return Ok(());
-        }
-
-        Err(SystemError::ENOENT)
-    }
 }
 
 impl LockedPipeInode {
@@ -158,7 +134,7 @@ impl LockedPipeInode {
             },
             reader: 0,
             writer: 0,
-            epitems: SpinLock::new(LinkedList::new()),
+            epitems: EPollItems::new(),
         };
         let result = Arc::new(Self {
             inner: SpinLock::new(inner),
@@ -185,6 +161,28 @@ impl LockedPipeInode {
         let inode = self.inner.lock();
         return !inode.buf_full() || inode.reader == 0;
     }
+
+    /// 把刚读出的数据放回管道的头部，下一次读取时最先读到
+    ///
+    /// 用于splice：从管道读出的数据没能全部写到输出端时，把没写出去的部分还给管道。
+    /// 管道中剩余的空间不够时（读出之后又有数据写入）返回 EAGAIN
+    pub fn unread(&self, buf: &[u8]) -> Result<(), SystemError> {
+        let mut inode = self.inner.lock();
+        if inode.valid_cnt as usize + buf.len() > PIPE_BUFF_SIZE {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+        let start = (inode.read_pos as usize + PIPE_BUFF_SIZE - buf.len()) % PIPE_BUFF_SIZE;
+        for (i, &b) in buf.iter().enumerate() {
+            inode.data[(start + i) % PIPE_BUFF_SIZE] = b;
+        }
+        inode.read_pos = start as i32;
+        inode.valid_cnt += buf.len() as i32;
+        drop(inode);
+
+        self.read_wait_queue
+            .wakeup(Some(ProcessState::Blocked(true)));
+        Ok(())
+    }
 }
 
 impl IndexNode for LockedPipeInode {
@@ -230,7 +228,8 @@ impl IndexNode for LockedPipeInode {
 
             // 否则在读等待队列中睡眠，并释放锁
             drop(inode);
-            let r = wq_wait_event_interruptible!(self.read_wait_queue, self.readable(), {});
+            let r =
+                wq_wait_event_interruptible_exclusive!(self.read_wait_queue, self.readable(), {});
             if r.is_err() {
                 ProcessManager::current_pcb()
                     .flags()
@@ -277,7 +276,7 @@ impl IndexNode for LockedPipeInode {
 
         let pollflag = EPollEventType::from_bits_truncate(inode.poll(&data)? as u32);
         // 唤醒epoll中等待的进程
-        EventPoll::wakeup_epoll(&inode.epitems, Some(pollflag))?;
+        inode.epitems.wakeup(Some(pollflag))?;
 
         //返回读取的字节数
         return Ok(num);
@@ -430,7 +429,8 @@ impl IndexNode for LockedPipeInode {
 
             // 解锁并睡眠
             drop(inode);
-            let r = wq_wait_event_interruptible!(self.write_wait_queue, self.writeable(), {});
+            let r =
+                wq_wait_event_interruptible_exclusive!(self.write_wait_queue, self.writeable(), {});
             if r.is_err() {
                 return Err(SystemError::ERESTARTSYS);
             }
@@ -464,7 +464,7 @@ impl IndexNode for LockedPipeInode {
 
         let pollflag = EPollEventType::from_bits_truncate(inode.poll(&data)? as u32);
         // 唤醒epoll中等待的进程
-        EventPoll::wakeup_epoll(&inode.epitems, Some(pollflag))?;
+        inode.epitems.wakeup(Some(pollflag))?;
 
         // 返回写入的字节数
         return Ok(len);
@@ -495,4 +495,21 @@ impl IndexNode for LockedPipeInode {
     fn poll(&self, private_data: &FilePrivateData) -> Result<usize, SystemError> {
         return self.inner.lock().poll(private_data);
     }
+
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.inner.lock().epitems.add(epitem);
+        Ok(())
+    }
+
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.inner.lock().epitems.remove(epoll)
+    }
 }
@@ -0,0 +1,544 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt::Write;
+use system_error::SystemError;
+
+use crate::{
+    libs::{spinlock::SpinLock, wait_queue::WaitQueue},
+    namespaces::ipc_namespace::IpcNamespace,
+    process::ProcessManager,
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+    time::PosixTimeSpec,
+};
+
+use super::{
+    ids::{
+        ipc_wait, IpcIds, IpcPerm, IPCMNI, IPC_64, IPC_INFO, IPC_NOWAIT, IPC_RMID, IPC_SET,
+        IPC_STAT, S_IRUGO, S_IWUGO,
+    },
+    shm::PosixIpcPerm,
+};
+
+/// 一个信号量集合中最多的信号量数
+pub const SEMMSL: usize = 32000;
+/// 信号量集合的最大数量
+pub const SEMMNI: usize = IPCMNI;
+/// 所有信号量集合中信号量的总数上限
+pub const SEMMNS: usize = SEMMNI * SEMMSL;
+/// 一次semop最多的操作数
+pub const SEMOPM: usize = 500;
+/// 信号量的最大值
+pub const SEMVMX: i32 = 32767;
+
+/// 进程退出时撤销操作。暂不支持，这个标志会被忽略
+pub const SEM_UNDO: i16 = 0x1000;
+
+const GETPID: i32 = 11;
+const GETVAL: i32 = 12;
+const GETALL: i32 = 13;
+const GETNCNT: i32 = 14;
+const GETZCNT: i32 = 15;
+const SETVAL: i32 = 16;
+const SETALL: i32 = 17;
+const SEM_STAT: i32 = 18;
+const SEM_INFO: i32 = 19;
+const SEM_STAT_ANY: i32 = 20;
+
+pub type SemIds = IpcIds<Arc<SemArray>>;
+
+/// semop的一个操作，与Linux的`struct sembuf`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixSembuf {
+    pub sem_num: u16,
+    pub sem_op: i16,
+    pub sem_flg: i16,
+}
+
+/// 信号量集合的信息，与Linux的`struct semid64_ds`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixSemidDs {
+    sem_perm: PosixIpcPerm,
+    sem_otime: i64,
+    #[cfg(target_arch = "x86_64")]
+    _unused1: u64,
+    sem_ctime: i64,
+    #[cfg(target_arch = "x86_64")]
+    _unused2: u64,
+    sem_nsems: u64,
+    _unused3: u64,
+    _unused4: u64,
+}
+
+/// 信号量的系统限制，与Linux的`struct seminfo`相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixSeminfo {
+    semmap: i32,
+    semmni: i32,
+    semmns: i32,
+    semmnu: i32,
+    semmsl: i32,
+    semopm: i32,
+    semume: i32,
+    semusz: i32,
+    semvmx: i32,
+    semaem: i32,
+}
+
+#[derive(Debug, Default)]
+struct Sem {
+    val: i32,
+    /// 最后一次操作这个信号量的进程
+    pid: usize,
+    /// 等待信号量增加的进程数
+    ncnt: usize,
+    /// 等待信号量变为0的进程数
+    zcnt: usize,
+}
+
+#[derive(Debug)]
+struct SemArrayInner {
+    perm: IpcPerm,
+    sems: Vec<Sem>,
+    /// 最后一次semop的时间
+    otime: i64,
+    /// 最后一次修改的时间
+    ctime: i64,
+    /// 已经被IPC_RMID删除
+    removed: bool,
+}
+
+/// 一个信号量集合
+#[derive(Debug)]
+pub struct SemArray {
+    inner: SpinLock<SemArrayInner>,
+    /// 等待信号量变化的进程
+    wait_queue: WaitQueue,
+}
+
+impl SemArray {
+    fn new(perm: IpcPerm, nsems: usize) -> Self {
+        SemArray {
+            inner: SpinLock::new(SemArrayInner {
+                perm,
+                sems: (0..nsems).map(|_| Sem::default()).collect(),
+                otime: 0,
+                ctime: PosixTimeSpec::now().tv_sec,
+                removed: false,
+            }),
+            wait_queue: WaitQueue::default(),
+        }
+    }
+
+    fn nsems(&self) -> usize {
+        self.inner.lock_irqsave().sems.len()
+    }
+
+    fn stat(&self) -> PosixSemidDs {
+        let inner = self.inner.lock_irqsave();
+        PosixSemidDs {
+            sem_perm: inner.perm.to_posix(),
+            sem_otime: inner.otime,
+            sem_ctime: inner.ctime,
+            sem_nsems: inner.sems.len() as u64,
+            ..Default::default()
+        }
+    }
+
+    /// 删除信号量集合，唤醒所有等待的进程，它们会以 EIDRM 失败
+    fn remove(&self) {
+        self.inner.lock_irqsave().removed = true;
+        self.wait_queue.wakeup_all(None);
+    }
+
+    /// # 尝试原子地执行所有操作
+    ///
+    /// ## 返回值
+    /// - `Ok(None)`: 所有操作都已完成
+    /// - `Ok(Some((sem_num, zero)))`: 需要等待`sem_num`增加（`zero`为false）或者变为0（`zero`为true）
+    fn try_semop(
+        inner: &mut SemArrayInner,
+        sops: &[PosixSembuf],
+    ) -> Result<Option<(usize, bool)>, SystemError> {
+        // 先在副本上执行，有操作需要等待时不修改任何信号量
+        let mut vals: Vec<(usize, i32)> = Vec::with_capacity(sops.len());
+        for sop in sops {
+            let num = sop.sem_num as usize;
+            let i = match vals.iter().position(|(n, _)| *n == num) {
+                Some(i) => i,
+                None => {
+                    vals.push((num, inner.sems[num].val));
+                    vals.len() - 1
+                }
+            };
+            let val = &mut vals[i].1;
+
+            let blocked = if sop.sem_op == 0 {
+                *val != 0
+            } else {
+                let result = *val + sop.sem_op as i32;
+                if result > SEMVMX {
+                    return Err(SystemError::ERANGE);
+                }
+                if result >= 0 {
+                    *val = result;
+                }
+                result < 0
+            };
+            if blocked {
+                if sop.sem_flg as u32 & IPC_NOWAIT != 0 {
+                    return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+                }
+                return Ok(Some((num, sop.sem_op == 0)));
+            }
+        }
+
+        let pid = ProcessManager::current_pid().data();
+        for (num, val) in vals {
+            inner.sems[num].val = val;
+            inner.sems[num].pid = pid;
+        }
+        inner.otime = PosixTimeSpec::now().tv_sec;
+        Ok(None)
+    }
+}
+
+/// # 获取或者创建信号量集合
+///
+/// ## 参数
+/// - `key`: 信号量集合的键值，IPC_PRIVATE表示创建新的集合
+/// - `nsems`: 信号量的数量，获取已有的集合时可以为0
+/// - `semflg`: IPC_CREAT、IPC_EXCL和权限位
+pub fn semget(key: i32, nsems: i32, semflg: u32) -> Result<usize, SystemError> {
+    if nsems < 0 || nsems as usize > SEMMSL {
+        return Err(SystemError::EINVAL);
+    }
+    let nsems = nsems as usize;
+    let ns = IpcNamespace::current();
+    let mut ids = ns.sem_ids.lock_irqsave();
+    ids.get_or_create(
+        key,
+        semflg,
+        |sma| {
+            sma.inner.lock_irqsave().perm.check(semflg)?;
+            if nsems > sma.nsems() {
+                return Err(SystemError::EINVAL);
+            }
+            Ok(())
+        },
+        |perm| {
+            if nsems == 0 {
+                return Err(SystemError::EINVAL);
+            }
+            Ok(Arc::new(SemArray::new(perm, nsems)))
+        },
+    )
+}
+
+fn find_sem_array(semid: i32) -> Result<Arc<SemArray>, SystemError> {
+    if semid < 0 {
+        return Err(SystemError::EINVAL);
+    }
+    IpcNamespace::current()
+        .sem_ids
+        .lock_irqsave()
+        .get(semid as usize)
+        .cloned()
+        .ok_or(SystemError::EINVAL)
+}
+
+/// # 原子地执行一组信号量操作
+///
+/// 所有操作要么都完成，要么都不执行。有操作不能立即完成时，等待信号量变化或者超时
+///
+/// ## 参数
+/// - `timeout`: 相对的等待时间（微秒），为None时一直等待
+pub fn semtimedop(
+    semid: i32,
+    sops: &[PosixSembuf],
+    timeout: Option<u64>,
+) -> Result<usize, SystemError> {
+    if sops.is_empty() {
+        return Err(SystemError::EINVAL);
+    }
+    if sops.len() > SEMOPM {
+        return Err(SystemError::E2BIG);
+    }
+    let sma = find_sem_array(semid)?;
+    // 只等待信号量变为0的操作只需要读权限
+    let alter = sops.iter().any(|sop| sop.sem_op != 0);
+    let flag = if alter { S_IWUGO } else { S_IRUGO };
+
+    // 当前正在等待的信号量，用于维护semncnt和semzcnt
+    let mut waiting: Option<(usize, bool)> = None;
+    let r = ipc_wait(&sma.inner, &sma.wait_queue, timeout, |inner| {
+        if inner.removed {
+            return Err(SystemError::EIDRM);
+        }
+        if let Some((num, zero)) = waiting.take() {
+            let sem = &mut inner.sems[num];
+            if zero {
+                sem.zcnt -= 1;
+            } else {
+                sem.ncnt -= 1;
+            }
+        }
+        if sops
+            .iter()
+            .any(|sop| sop.sem_num as usize >= inner.sems.len())
+        {
+            return Err(SystemError::EFBIG);
+        }
+        // 每次被唤醒都重新检查，等待期间权限可能被IPC_SET修改
+        inner.perm.check(flag)?;
+
+        match SemArray::try_semop(inner, sops)? {
+            None => Ok(Some(())),
+            Some((num, zero)) => {
+                let sem = &mut inner.sems[num];
+                if zero {
+                    sem.zcnt += 1;
+                } else {
+                    sem.ncnt += 1;
+                }
+                waiting = Some((num, zero));
+                Ok(None)
+            }
+        }
+    });
+
+    if let Some((num, zero)) = waiting {
+        // 超时或者被信号打断，不再等待
+        let mut inner = sma.inner.lock_irqsave();
+        if !inner.removed {
+            let sem = &mut inner.sems[num];
+            if zero {
+                sem.zcnt -= 1;
+            } else {
+                sem.ncnt -= 1;
+            }
+        }
+    }
+    r?;
+
+    if alter {
+        sma.wait_queue.wakeup_all(None);
+    }
+    Ok(0)
+}
+
+/// # 管理信号量集合
+///
+/// ## 参数
+/// - `semid`: 信号量集合的id。SEM_STAT和SEM_STAT_ANY命令中为下标
+/// - `semnum`: GETVAL、SETVAL等命令操作的信号量
+/// - `cmd`: 操作码
+/// - `arg`: `union semun`，SETVAL时为信号量的值，其他命令时为用户空间的指针
+pub fn semctl(semid: i32, semnum: i32, cmd: i32, arg: usize) -> Result<usize, SystemError> {
+    let cmd = cmd & !IPC_64;
+    let ns = IpcNamespace::current();
+    match cmd {
+        IPC_INFO | SEM_INFO => {
+            let ids = ns.sem_ids.lock_irqsave();
+            let max_index = ids.max_index();
+            let mut info = PosixSeminfo {
+                semmap: SEMMNS as i32,
+                semmni: SEMMNI as i32,
+                semmns: SEMMNS as i32,
+                semmnu: SEMMNS as i32,
+                semmsl: SEMMSL as i32,
+                semopm: SEMOPM as i32,
+                semume: SEMOPM as i32,
+                semusz: 0,
+                semvmx: SEMVMX,
+                semaem: SEMVMX,
+            };
+            if cmd == SEM_INFO {
+                // SEM_INFO时，semusz为信号量集合的数量，semaem为信号量的总数
+                info.semusz = ids.len() as i32;
+                info.semaem = ids.iter().map(|(_, sma)| sma.nsems()).sum::<usize>() as i32;
+            }
+            drop(ids);
+            UserBufferWriter::new(
+                arg as *mut PosixSeminfo,
+                core::mem::size_of::<PosixSeminfo>(),
+                true,
+            )?
+            .copy_one_to_user(&info, 0)?;
+            return Ok(max_index);
+        }
+        SEM_STAT | SEM_STAT_ANY => {
+            if semid < 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let (id, ds) = {
+                let ids = ns.sem_ids.lock_irqsave();
+                let (id, sma) = ids
+                    .get_by_index(semid as usize)
+                    .ok_or(SystemError::EINVAL)?;
+                if cmd == SEM_STAT {
+                    sma.inner.lock_irqsave().perm.check(S_IRUGO)?;
+                }
+                (id, sma.stat())
+            };
+            UserBufferWriter::new(
+                arg as *mut PosixSemidDs,
+                core::mem::size_of::<PosixSemidDs>(),
+                true,
+            )?
+            .copy_one_to_user(&ds, 0)?;
+            return Ok(id);
+        }
+        IPC_RMID => {
+            if semid < 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let mut ids = ns.sem_ids.lock_irqsave();
+            ids.get(semid as usize)
+                .ok_or(SystemError::EINVAL)?
+                .inner
+                .lock_irqsave()
+                .perm
+                .check_owner()?;
+            let sma = ids.remove(semid as usize).unwrap();
+            drop(ids);
+            sma.remove();
+            return Ok(0);
+        }
+        _ => {}
+    }
+
+    let sma = find_sem_array(semid)?;
+    match cmd {
+        IPC_STAT | GETVAL | GETPID | GETNCNT | GETZCNT | GETALL => {
+            sma.inner.lock_irqsave().perm.check(S_IRUGO)?
+        }
+        SETVAL | SETALL => sma.inner.lock_irqsave().perm.check(S_IWUGO)?,
+        _ => {}
+    }
+    match cmd {
+        IPC_STAT => {
+            let ds = sma.stat();
+            UserBufferWriter::new(
+                arg as *mut PosixSemidDs,
+                core::mem::size_of::<PosixSemidDs>(),
+                true,
+            )?
+            .copy_one_to_user(&ds, 0)?;
+            Ok(0)
+        }
+        IPC_SET => {
+            let ds = *UserBufferReader::new(
+                arg as *const PosixSemidDs,
+                core::mem::size_of::<PosixSemidDs>(),
+                true,
+            )?
+            .read_one_from_user::<PosixSemidDs>(0)?;
+            let mut inner = sma.inner.lock_irqsave();
+            inner.perm.check_owner()?;
+            inner.perm.set(&ds.sem_perm);
+            inner.ctime = PosixTimeSpec::now().tv_sec;
+            Ok(0)
+        }
+        GETVAL | GETPID | GETNCNT | GETZCNT | SETVAL => {
+            let mut inner = sma.inner.lock_irqsave();
+            if semnum < 0 || semnum as usize >= inner.sems.len() {
+                return Err(SystemError::EINVAL);
+            }
+            let sem = &inner.sems[semnum as usize];
+            match cmd {
+                GETVAL => Ok(sem.val as usize),
+                GETPID => Ok(sem.pid),
+                GETNCNT => Ok(sem.ncnt),
+                GETZCNT => Ok(sem.zcnt),
+                _ => {
+                    let val = arg as i32;
+                    if !(0..=SEMVMX).contains(&val) {
+                        return Err(SystemError::ERANGE);
+                    }
+                    let pid = ProcessManager::current_pid().data();
+                    let sem = &mut inner.sems[semnum as usize];
+                    sem.val = val;
+                    sem.pid = pid;
+                    inner.ctime = PosixTimeSpec::now().tv_sec;
+                    drop(inner);
+                    sma.wait_queue.wakeup_all(None);
+                    Ok(0)
+                }
+            }
+        }
+        GETALL => {
+            let vals: Vec<u16> = sma
+                .inner
+                .lock_irqsave()
+                .sems
+                .iter()
+                .map(|sem| sem.val as u16)
+                .collect();
+            let mut writer = UserBufferWriter::new(
+                arg as *mut u16,
+                vals.len() * core::mem::size_of::<u16>(),
+                true,
+            )?;
+            writer.copy_to_user(&vals, 0)?;
+            Ok(0)
+        }
+        SETALL => {
+            let nsems = sma.nsems();
+            let reader = UserBufferReader::new(
+                arg as *const u16,
+                nsems * core::mem::size_of::<u16>(),
+                true,
+            )?;
+            let vals = reader.read_from_user::<u16>(0)?;
+            if vals.iter().any(|&val| val as i32 > SEMVMX) {
+                return Err(SystemError::ERANGE);
+            }
+            let pid = ProcessManager::current_pid().data();
+            let mut inner = sma.inner.lock_irqsave();
+            for (sem, &val) in inner.sems.iter_mut().zip(vals) {
+                sem.val = val as i32;
+                sem.pid = pid;
+            }
+            inner.ctime = PosixTimeSpec::now().tv_sec;
+            drop(inner);
+            sma.wait_queue.wakeup_all(None);
+            Ok(0)
+        }
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+/// /proc/sysvipc/sem的内容
+pub fn sem_procfs_show() -> String {
+    let mut s =
+        "       key      semid perms      nsems   uid   gid  cuid  cgid      otime      ctime\n"
+            .to_string();
+    let ns = IpcNamespace::current();
+    let ids = ns.sem_ids.lock_irqsave();
+    for (id, sma) in ids.iter() {
+        let inner = sma.inner.lock_irqsave();
+        let perm = &inner.perm;
+        writeln!(
+            s,
+            "{:>10} {:>10}  {:>4o} {:>10} {:>5} {:>5} {:>5} {:>5} {:>10} {:>10}",
+            perm.key,
+            id,
+            perm.mode,
+            inner.sems.len(),
+            perm.uid,
+            perm.gid,
+            perm.cuid,
+            perm.cgid,
+            inner.otime,
+            inner.ctime
+        )
+        .ok();
+    }
+    s
+}
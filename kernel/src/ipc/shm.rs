@@ -14,7 +14,11 @@ use crate::{
     syscall::user_access::{UserBufferReader, UserBufferWriter},
     time::PosixTimeSpec,
 };
-use core::sync::atomic::{compiler_fence, Ordering};
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::Write,
+    sync::atomic::{compiler_fence, Ordering},
+};
 use hashbrown::HashMap;
 use ida::IdAllocator;
 use log::info;
@@ -347,6 +351,39 @@ impl ShmManager {
         return Ok(0);
     }
 
+    /// /proc/sysvipc/shm的内容
+    pub fn procfs_show(&self) -> String {
+        let mut s = String::from("       key      shmid perms                  size  cpid  lpid nattch   uid   gid  cuid  cgid      atime      dtime      ctime                   rss                  swap\n");
+        let mut shms: Vec<&KernelShm> = self.id2shm.values().collect();
+        shms.sort_by_key(|shm| shm.kern_ipc_perm.id.data());
+        for shm in shms {
+            let perm = &shm.kern_ipc_perm;
+            let rss = page_align_up(shm.shm_size);
+            writeln!(
+                s,
+                "{:>10} {:>10}  {:>4o} {:>21} {:>5} {:>5}  {:>5} {:>5} {:>5} {:>5} {:>5} {:>10} {:>10} {:>10} {:>21} {:>21}",
+                perm.key.data() as i32,
+                perm.id.data(),
+                perm.mode.bits() & ModeType::S_IRWXUGO.bits(),
+                shm.shm_size,
+                shm.shm_cprid.data(),
+                shm.shm_lprid.data(),
+                shm.map_count,
+                perm.uid,
+                perm.gid,
+                perm._cuid,
+                perm._cgid,
+                shm.shm_atim.tv_sec,
+                shm.shm_dtim.tv_sec,
+                shm.shm_ctim.tv_sec,
+                rss,
+                0
+            )
+            .ok();
+        }
+        s
+    }
+
     pub fn shm_lock(&mut self, id: ShmId) -> Result<usize, SystemError> {
         let kernel_shm = self.id2shm.get_mut(&id).ok_or(SystemError::EINVAL)?;
         kernel_shm.set_mode(ShmFlags::SHM_LOCKED, true);
@@ -649,4 +686,16 @@ impl PosixIpcPerm {
             _unused2: 0,
         }
     }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
 }
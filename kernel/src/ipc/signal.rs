@@ -6,6 +6,7 @@ use system_error::SystemError;
 
 use crate::{
     arch::ipc::signal::{SigCode, SigFlags, SigSet, Signal},
+    filesystem::signalfd::signalfd_notify,
     ipc::signal_types::SigactionType,
     libs::spinlock::SpinLockGuard,
     process::{
@@ -84,9 +85,25 @@ impl Signal {
 
         let pcb = pcb.unwrap();
         // println!("Target pcb = {:?}", pcb.as_ref().unwrap());
+        retval = self.send_signal_info_to_pcb(info, pcb);
+        return retval;
+    }
+
+    /// # 向指定的pcb发送信号
+    ///
+    /// 与`send_signal_info`不同，不通过pid查找进程，用于pidfd等已经持有目标pcb的场景，
+    /// 避免pid被复用后信号发给了其他进程
+    pub fn send_signal_info_to_pcb(
+        &self,
+        info: Option<&mut SigInfo>,
+        pcb: Arc<ProcessControlBlock>,
+    ) -> Result<i32, SystemError> {
+        if !self.is_valid() {
+            return Err(SystemError::EINVAL);
+        }
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
         // 发送信号
-        retval = self.send_signal(info, pcb.clone(), PidType::PID);
+        let retval = self.send_signal(info, pcb, PidType::PID);
 
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
         return retval;
@@ -134,7 +151,6 @@ impl Signal {
         else if !self.is_rt_signal() && pending.queue().find(*self).0.is_some() {
             return Ok(0);
         } else {
-            // TODO signalfd_notify 完善 signalfd 机制
             // 如果是其他信号，则加入到sigqueue内，然后complete_signal
             let new_sig_info = match info {
                 Some(siginfo) => {
@@ -152,11 +168,13 @@ impl Signal {
                 }
             };
             drop(pcb_info);
-            pcb.sig_info_mut()
-                .sig_pending_mut()
-                .queue_mut()
-                .q
-                .push(new_sig_info);
+            let mut sig_info = pcb.sig_info_mut();
+            let pending = sig_info.sig_pending_mut();
+            pending.queue_mut().q.push(new_sig_info);
+            // 被屏蔽的信号也要记录为pending，在解除屏蔽或者通过signalfd读取时取出
+            pending.signal_mut().insert((*self).into());
+            drop(sig_info);
+            signalfd_notify(*self);
 
             // if pt == PidType::PGID || pt == PidType::SID {}
             self.complete_signal(pcb.clone(), pt);
@@ -185,7 +203,6 @@ impl Signal {
 
         // 判断目标进程是否想接收这个信号
         if self.wants_signal(pcb.clone()) {
-            // todo: 将信号产生的消息通知到正在监听这个信号的进程（引入signalfd之后，在这里调用signalfd_notify)
             // 将这个信号加到目标进程的sig_pending中
             pcb.sig_info_mut()
                 .sig_pending_mut()
@@ -245,8 +262,8 @@ impl Signal {
 
         // todo: 检查目标进程是否正在一个cpu上执行，如果是，则返回true，否则继续检查下一项
 
-        // 检查目标进程是否有信号正在等待处理，如果是，则返回false，否则返回true
-        return pcb.sig_info_irqsave().sig_pending().signal().bits() == 0;
+        // 检查目标进程是否已经被通知有信号等待处理，如果是，则返回false，否则返回true
+        return !pcb.has_pending_signal_fast();
     }
 
     /// @brief 判断signal的处理是否可能使得整个进程组退出
@@ -4,6 +4,7 @@ use core::{
 };
 
 use log::{error, warn};
+use num_traits::FromPrimitive;
 use system_error::SystemError;
 
 use crate::{
@@ -13,7 +14,8 @@ use crate::{
     },
     filesystem::vfs::{
         file::{File, FileMode},
-        FilePrivateData,
+        syscall::ModeType,
+        FilePrivateData, IndexNode, MAX_PATHLEN,
     },
     ipc::shm::{shm_manager_lock, IPC_PRIVATE},
     libs::{align::page_align_up, spinlock::SpinLock},
@@ -26,13 +28,23 @@ use crate::{
     },
     process::{Pid, ProcessManager},
     syscall::{
-        user_access::{UserBufferReader, UserBufferWriter},
+        user_access::{
+            access_ok, check_and_clone_cstr, UserBufferReader, UserBufferWriter, UserPtr, UserSlice,
+        },
         Syscall,
     },
+    time::PosixTimeSpec,
 };
 
 use super::{
+    channel::{
+        channel_create, channel_get_endpoint, ChannelCtlOp, ChannelFlags, ChannelHandle,
+        ChannelParams, ChannelWaitFor, CHANNEL_MAX_HANDLES_PER_CALL,
+    },
+    mqueue::{mqueue_get, mqueuefs, PosixMqAttr, PosixSigevent},
+    msg::{msgctl, msgget, msgrcv, msgsnd, MSGMAX},
     pipe::{LockedPipeInode, PipeFsPrivateData},
+    sem::{semctl, semget, semtimedop, PosixSembuf, SEMOPM},
     shm::{ShmCtlCmd, ShmFlags, ShmId, ShmKey},
     signal::{set_sigprocmask, SigHow},
     signal_types::{
@@ -83,7 +95,14 @@ impl Syscall {
         let fd_table_ptr = ProcessManager::current_pcb().fd_table();
         let mut fd_table_guard = fd_table_ptr.write();
         let read_fd = fd_table_guard.alloc_fd(read_file, None)?;
-        let write_fd = fd_table_guard.alloc_fd(write_file, None)?;
+        let write_fd = match fd_table_guard.alloc_fd(write_file, None) {
+            Ok(fd) => fd,
+            Err(e) => {
+                // 写端分配失败时，回收已经分配的读端，避免fd泄漏
+                fd_table_guard.drop_fd(read_fd).ok();
+                return Err(e);
+            }
+        };
 
         drop(fd_table_guard);
 
@@ -100,8 +119,13 @@ impl Syscall {
             return Err(SystemError::EINVAL);
         }
 
-        // 初始化signal info
-        let mut info = SigInfo::new(sig, 0, SigCode::User, SigType::Kill(pid));
+        // 初始化signal info，si_pid为发送者的pid
+        let mut info = SigInfo::new(
+            sig,
+            0,
+            SigCode::User,
+            SigType::Kill(ProcessManager::current_pid()),
+        );
 
         compiler_fence(core::sync::atomic::Ordering::SeqCst);
 
@@ -552,4 +576,400 @@ impl Syscall {
         unimplemented!("restart_syscall with restart block");
         // Err(SystemError::ENOSYS)
     }
+
+    /// # 创建一个IPC通道
+    ///
+    /// ## 参数
+    /// - `params`: 用户态的ChannelParams，返回时填入实际的队列参数与共享内存布局
+    /// - `fds`: 用于返回两个端点的文件描述符的数组
+    ///
+    /// ## 返回值
+    /// - `Ok(0)`: 创建成功
+    /// - `Err(SystemError)`: 创建失败
+    pub fn sys_channel_create(
+        params: *mut ChannelParams,
+        fds: *mut i32,
+    ) -> Result<usize, SystemError> {
+        let params = UserPtr::from_ptr(params);
+        let mut p = params.read()?;
+        if p.resv.iter().any(|&x| x != 0) {
+            return Err(SystemError::EINVAL);
+        }
+        let flags = ChannelFlags::from_bits(p.flags).ok_or(SystemError::EINVAL)?;
+        let fds = UserPtr::<[c_int; 2]>::new(fds as usize);
+        access_ok(fds.addr(), core::mem::size_of::<[c_int; 2]>())?;
+
+        let (ep0, ep1) = channel_create(&mut p)?;
+        let mut mode = FileMode::O_RDWR;
+        if flags.contains(ChannelFlags::CHANNEL_NONBLOCK) {
+            mode |= FileMode::O_NONBLOCK;
+        }
+        if flags.contains(ChannelFlags::CHANNEL_CLOEXEC) {
+            mode |= FileMode::O_CLOEXEC;
+        }
+        let file0 = File::new(ep0, mode)?;
+        let file1 = File::new(ep1, mode)?;
+
+        let fd_table_ptr = ProcessManager::current_pcb().fd_table();
+        let mut fd_table_guard = fd_table_ptr.write();
+        let fd0 = fd_table_guard.alloc_fd(file0, None)?;
+        let fd1 = match fd_table_guard.alloc_fd(file1, None) {
+            Ok(fd) => fd,
+            Err(e) => {
+                fd_table_guard.drop_fd(fd0).ok();
+                return Err(e);
+            }
+        };
+        drop(fd_table_guard);
+
+        params.write(&p)?;
+        fds.write(&[fd0, fd1])?;
+        Ok(0)
+    }
+
+    /// # 操作IPC通道的一个端点
+    ///
+    /// ## 参数
+    /// - `fd`: 通道端点的文件描述符
+    /// - `op`: ChannelCtlOp
+    /// - `arg`: 与操作相关的参数
+    /// - `len`: 与操作相关的长度
+    ///
+    /// ## 返回值
+    /// - NOTIFY、SEND_HANDLES: 成功时返回0
+    /// - WAIT: 已满足的ChannelWaitFor条件
+    /// - RECV_HANDLES: 收到的句柄数
+    pub fn sys_channel_ctl(fd: i32, op: u32, arg: usize, len: usize) -> Result<usize, SystemError> {
+        let op = ChannelCtlOp::from_u32(op).ok_or(SystemError::EINVAL)?;
+        let (file, endpoint) = channel_get_endpoint(fd)?;
+
+        match op {
+            ChannelCtlOp::Notify => {
+                endpoint.notify();
+                Ok(0)
+            }
+            ChannelCtlOp::Wait => {
+                let what = ChannelWaitFor::from_bits(arg as u32).ok_or(SystemError::EINVAL)?;
+                if what.is_empty() {
+                    return Err(SystemError::EINVAL);
+                }
+                let nonblock = file.mode().contains(FileMode::O_NONBLOCK);
+                drop(file);
+                endpoint
+                    .wait(what, nonblock)
+                    .map(|ready| ready.bits() as usize)
+            }
+            ChannelCtlOp::SendHandles => {
+                if len == 0 || len > CHANNEL_MAX_HANDLES_PER_CALL {
+                    return Err(SystemError::EINVAL);
+                }
+                let handles = UserSlice::new(
+                    VirtAddr::new(arg),
+                    len * core::mem::size_of::<ChannelHandle>(),
+                )?
+                .reader()
+                .read_vec::<ChannelHandle>(len)?;
+                endpoint.send_handles(&handles)?;
+                Ok(0)
+            }
+            ChannelCtlOp::RecvHandles => {
+                let len = len.min(CHANNEL_MAX_HANDLES_PER_CALL);
+                let mut writer = UserSlice::new(
+                    VirtAddr::new(arg),
+                    len * core::mem::size_of::<ChannelHandle>(),
+                )?
+                .writer();
+                let handles = endpoint.recv_handles(len)?;
+                writer.write_slice(&handles)?;
+                Ok(handles.len())
+            }
+        }
+    }
+
+    /// # 打开或者创建一个POSIX消息队列
+    ///
+    /// ## 参数
+    /// - `name`: 队列的名字，libc已经去掉了开头的`/`
+    /// - `oflag`: 打开模式，支持O_CREAT、O_EXCL、O_NONBLOCK和O_CLOEXEC
+    /// - `mode`: 创建队列时的权限
+    /// - `attr`: 创建队列时的属性，为空时使用默认属性
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 消息队列的文件描述符
+    pub fn sys_mq_open(
+        name: *const u8,
+        oflag: u32,
+        mode: u32,
+        attr: *const PosixMqAttr,
+    ) -> Result<usize, SystemError> {
+        let name = check_and_clone_cstr(name, Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+        let name = name.strip_prefix('/').unwrap_or(&name);
+        let oflag = FileMode::from_bits_truncate(oflag);
+        let create = oflag.contains(FileMode::O_CREAT);
+        let attr = if create && !attr.is_null() {
+            Some(
+                *UserBufferReader::new(attr, core::mem::size_of::<PosixMqAttr>(), true)?
+                    .read_one_from_user::<PosixMqAttr>(0)?,
+            )
+        } else {
+            None
+        };
+
+        let queue = mqueuefs().root().open_queue(
+            name,
+            create,
+            oflag.contains(FileMode::O_EXCL),
+            ModeType::from_bits_truncate(mode),
+            attr.as_ref(),
+        )?;
+
+        let mut filemode = FileMode::from_bits_truncate(oflag.accmode());
+        filemode |= oflag & (FileMode::O_NONBLOCK | FileMode::O_CLOEXEC);
+        let file = File::new(queue, filemode)?;
+        let fd_table_ptr = ProcessManager::current_pcb().fd_table();
+        let fd = fd_table_ptr.write().alloc_fd(file, None)?;
+        Ok(fd as usize)
+    }
+
+    /// # 删除一个POSIX消息队列
+    ///
+    /// 已经打开这个队列的进程在关闭之前仍然可以使用它
+    pub fn sys_mq_unlink(name: *const u8) -> Result<usize, SystemError> {
+        let name = check_and_clone_cstr(name, Some(MAX_PATHLEN))?
+            .into_string()
+            .map_err(|_| SystemError::EINVAL)?;
+        let name = name.strip_prefix('/').unwrap_or(&name);
+        mqueuefs().root().unlink(name)?;
+        Ok(0)
+    }
+
+    /// 读取mq_timedsend/mq_timedreceive的超时时间（CLOCK_REALTIME上的绝对时间）
+    fn mq_read_timeout(
+        abs_timeout: *const PosixTimeSpec,
+    ) -> Result<Option<PosixTimeSpec>, SystemError> {
+        if abs_timeout.is_null() {
+            return Ok(None);
+        }
+        let timeout =
+            *UserBufferReader::new(abs_timeout, core::mem::size_of::<PosixTimeSpec>(), true)?
+                .read_one_from_user::<PosixTimeSpec>(0)?;
+        if timeout.tv_sec < 0 || timeout.tv_nsec < 0 || timeout.tv_nsec >= 1_000_000_000 {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(Some(timeout))
+    }
+
+    /// # 向消息队列发送一条消息
+    ///
+    /// ## 参数
+    /// - `mqdes`: 消息队列的文件描述符
+    /// - `msg_ptr`、`msg_len`: 消息的内容
+    /// - `msg_prio`: 消息的优先级，数值越大越先被接收
+    /// - `abs_timeout`: 队列满时等待的截止时间，为空时一直等待
+    pub fn sys_mq_timedsend(
+        mqdes: i32,
+        msg_ptr: *const u8,
+        msg_len: usize,
+        msg_prio: u32,
+        abs_timeout: *const PosixTimeSpec,
+    ) -> Result<usize, SystemError> {
+        let timeout = Self::mq_read_timeout(abs_timeout)?;
+        let (file, queue) = mqueue_get(mqdes)?;
+        if file.mode().accmode() == FileMode::O_RDONLY.bits() {
+            return Err(SystemError::EBADF);
+        }
+        let reader = UserBufferReader::new(msg_ptr, msg_len, true)?;
+        let msg = reader.read_from_user::<u8>(0)?;
+        let nonblock = file.mode().contains(FileMode::O_NONBLOCK);
+        queue.send(msg, msg_prio, nonblock, timeout)?;
+        Ok(0)
+    }
+
+    /// # 从消息队列接收优先级最高的消息
+    ///
+    /// ## 参数
+    /// - `mqdes`: 消息队列的文件描述符
+    /// - `msg_ptr`、`msg_len`: 接收消息的缓冲区，长度不能小于队列的`mq_msgsize`
+    /// - `msg_prio`: 用于返回消息的优先级，可以为空
+    /// - `abs_timeout`: 队列空时等待的截止时间，为空时一直等待
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 消息的长度
+    pub fn sys_mq_timedreceive(
+        mqdes: i32,
+        msg_ptr: *mut u8,
+        msg_len: usize,
+        msg_prio: *mut u32,
+        abs_timeout: *const PosixTimeSpec,
+    ) -> Result<usize, SystemError> {
+        let timeout = Self::mq_read_timeout(abs_timeout)?;
+        let (file, queue) = mqueue_get(mqdes)?;
+        if file.mode().accmode() == FileMode::O_WRONLY.bits() {
+            return Err(SystemError::EBADF);
+        }
+        let mut writer = UserBufferWriter::new(msg_ptr, msg_len, true)?;
+        let nonblock = file.mode().contains(FileMode::O_NONBLOCK);
+        let (len, prio) = queue.receive(writer.buffer::<u8>(0)?, nonblock, timeout)?;
+        if !msg_prio.is_null() {
+            UserBufferWriter::new(msg_prio, core::mem::size_of::<u32>(), true)?
+                .copy_one_to_user(&prio, 0)?;
+        }
+        Ok(len)
+    }
+
+    /// # 注册或者取消消息到达的通知
+    ///
+    /// `sevp`为空时取消当前进程的注册。空队列收到消息时，向注册的进程发送`sigev_signo`信号
+    pub fn sys_mq_notify(mqdes: i32, sevp: *const PosixSigevent) -> Result<usize, SystemError> {
+        let (_file, queue) = mqueue_get(mqdes)?;
+        let sigevent = if sevp.is_null() {
+            None
+        } else {
+            Some(
+                *UserBufferReader::new(sevp, core::mem::size_of::<PosixSigevent>(), true)?
+                    .read_one_from_user::<PosixSigevent>(0)?,
+            )
+        };
+        queue.set_notify(sigevent.as_ref())?;
+        Ok(0)
+    }
+
+    /// # 获取或者修改消息队列的属性
+    ///
+    /// 只有`mq_flags`中的O_NONBLOCK可以修改，其他属性在创建之后不能改变
+    pub fn sys_mq_getsetattr(
+        mqdes: i32,
+        new_attr: *const PosixMqAttr,
+        old_attr: *mut PosixMqAttr,
+    ) -> Result<usize, SystemError> {
+        let (file, queue) = mqueue_get(mqdes)?;
+        let mode = file.mode();
+        let mut attr = queue.attr();
+        attr.mq_flags = (mode & FileMode::O_NONBLOCK).bits() as i64;
+
+        if !new_attr.is_null() {
+            let new = *UserBufferReader::new(new_attr, core::mem::size_of::<PosixMqAttr>(), true)?
+                .read_one_from_user::<PosixMqAttr>(0)?;
+            if new.mq_flags & !(FileMode::O_NONBLOCK.bits() as i64) != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let mut new_mode = mode;
+            new_mode.set(FileMode::O_NONBLOCK, new.mq_flags != 0);
+            file.set_mode(new_mode)?;
+        }
+        if !old_attr.is_null() {
+            UserBufferWriter::new(old_attr, core::mem::size_of::<PosixMqAttr>(), true)?
+                .copy_one_to_user(&attr, 0)?;
+        }
+        Ok(0)
+    }
+
+    /// # 获取或者创建System V信号量集合
+    ///
+    /// ## 参数
+    /// - `key`: 键值，IPC_PRIVATE表示创建新的集合
+    /// - `nsems`: 信号量的数量
+    /// - `semflg`: IPC_CREAT、IPC_EXCL和权限位
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 信号量集合的id
+    pub fn sys_semget(key: i32, nsems: i32, semflg: u32) -> Result<usize, SystemError> {
+        semget(key, nsems, semflg)
+    }
+
+    /// # 对System V信号量集合执行一组操作
+    ///
+    /// ## 参数
+    /// - `semid`: 信号量集合的id
+    /// - `sops`、`nsops`: 操作数组
+    /// - `timeout`: 相对的等待时间，为空时一直等待
+    pub fn sys_semtimedop(
+        semid: i32,
+        sops: *const PosixSembuf,
+        nsops: usize,
+        timeout: *const PosixTimeSpec,
+    ) -> Result<usize, SystemError> {
+        if nsops == 0 {
+            return Err(SystemError::EINVAL);
+        }
+        if nsops > SEMOPM {
+            return Err(SystemError::E2BIG);
+        }
+        let reader =
+            UserBufferReader::new(sops, nsops * core::mem::size_of::<PosixSembuf>(), true)?;
+        let sops = reader.read_from_user::<PosixSembuf>(0)?;
+
+        let timeout = if timeout.is_null() {
+            None
+        } else {
+            let timeout =
+                *UserBufferReader::new(timeout, core::mem::size_of::<PosixTimeSpec>(), true)?
+                    .read_one_from_user::<PosixTimeSpec>(0)?;
+            if timeout.tv_sec < 0 || timeout.tv_nsec < 0 || timeout.tv_nsec >= 1_000_000_000 {
+                return Err(SystemError::EINVAL);
+            }
+            Some(timeout.total_nanos() as u64 / 1000)
+        };
+        semtimedop(semid, sops, timeout)
+    }
+
+    /// # 管理System V信号量集合
+    ///
+    /// `arg`为`union semun`，按值传递
+    pub fn sys_semctl(semid: i32, semnum: i32, cmd: i32, arg: usize) -> Result<usize, SystemError> {
+        semctl(semid, semnum, cmd, arg)
+    }
+
+    /// # 获取或者创建System V消息队列
+    pub fn sys_msgget(key: i32, msgflg: u32) -> Result<usize, SystemError> {
+        msgget(key, msgflg)
+    }
+
+    /// # 向System V消息队列发送消息
+    ///
+    /// ## 参数
+    /// - `msgp`: 用户空间的`struct msgbuf`，以`long mtype`开头，后面是消息的内容
+    /// - `msgsz`: 消息内容的长度，不包括`mtype`
+    pub fn sys_msgsnd(
+        msqid: i32,
+        msgp: *const u8,
+        msgsz: usize,
+        msgflg: u32,
+    ) -> Result<usize, SystemError> {
+        if msgsz > MSGMAX {
+            return Err(SystemError::EINVAL);
+        }
+        let reader = UserBufferReader::new(msgp, core::mem::size_of::<i64>() + msgsz, true)?;
+        let mtype = *reader.read_one_from_user::<i64>(0)?;
+        let text = reader.read_from_user::<u8>(core::mem::size_of::<i64>())?;
+        msgsnd(msqid, mtype, text, msgflg)
+    }
+
+    /// # 从System V消息队列接收消息
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 复制到`msgp`中的消息内容的长度
+    pub fn sys_msgrcv(
+        msqid: i32,
+        msgp: *mut u8,
+        msgsz: usize,
+        msgtyp: i64,
+        msgflg: u32,
+    ) -> Result<usize, SystemError> {
+        if (msgsz as isize) < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let mut writer = UserBufferWriter::new(msgp, core::mem::size_of::<i64>() + msgsz, true)?;
+        let (mtype, text) = msgrcv(msqid, msgsz, msgtyp, msgflg)?;
+        writer.copy_one_to_user(&mtype, 0)?;
+        writer.copy_to_user(&text, core::mem::size_of::<i64>())?;
+        Ok(text.len())
+    }
+
+    /// # 管理System V消息队列
+    pub fn sys_msgctl(msqid: i32, cmd: i32, buf: usize) -> Result<usize, SystemError> {
+        msgctl(msqid, cmd, buf)
+    }
 }
@@ -0,0 +1,162 @@
+//! 内核控制台
+//!
+//! 串口、textui、虚拟终端以及以后的netconsole等都以控制台的形式注册到这里，
+//! 内核日志会被同时输出到所有已注册的控制台。每个控制台有自己的日志级别，
+//! 只有级别数值小于控制台日志级别的消息才会输出到该控制台。
+//!
+//! 启动早期使用的控制台带有`BOOT`标志。当同名的正式控制台注册时，
+//! 它在同一个临界区内替换掉启动控制台（并继承其日志级别），
+//! 因此交接过程中的日志既不会丢失，也不会重复输出。
+//!
+//! 注册表不需要分配内存，因此在内存管理初始化之前就可以使用。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/printk/printk.c#3360
+
+use core::fmt::Debug;
+
+use system_error::SystemError;
+
+use crate::driver::serial::serial8250::send_to_default_serial8250_port;
+
+use super::spinlock::SpinLock;
+
+/// 控制台默认的日志级别，输出包括debug在内的所有日志
+pub const CONSOLE_LOGLEVEL_DEFAULT: usize = 8;
+/// 控制台日志级别的最大值
+pub const CONSOLE_LOGLEVEL_MAX: usize = 15;
+/// 最多能同时注册的控制台数量
+const MAX_CONSOLES: usize = 8;
+
+bitflags! {
+    pub struct ConsoleFlags: u32 {
+        /// 启动控制台，会被同名的正式控制台替换
+        const BOOT = 1 << 0;
+    }
+}
+
+/// 内核控制台
+pub trait Console: Send + Sync + Debug {
+    /// 控制台的名字，如`ttyS0`、`tty0`
+    fn name(&self) -> &'static str;
+
+    /// 输出一个字符串
+    ///
+    /// 该函数可能在中断上下文中被调用，不能睡眠
+    fn write(&self, s: &str);
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ConsoleEntry {
+    console: &'static dyn Console,
+    flags: ConsoleFlags,
+    loglevel: usize,
+}
+
+static CONSOLES: SpinLock<[Option<ConsoleEntry>; MAX_CONSOLES]> =
+    SpinLock::new([None; MAX_CONSOLES]);
+
+/// 注册控制台
+///
+/// ## 参数
+///
+/// - `console`: 要注册的控制台
+/// - `flags`: 控制台的标志
+///
+/// ## 返回值
+///
+/// - `EEXIST`: 已经有同名的控制台，且不能被替换
+/// - `ENOSPC`: 控制台数量达到上限
+pub fn register_console(
+    console: &'static dyn Console,
+    flags: ConsoleFlags,
+) -> Result<(), SystemError> {
+    let mut consoles = CONSOLES.lock_irqsave();
+
+    let existing = consoles
+        .iter_mut()
+        .flatten()
+        .find(|entry| entry.console.name() == console.name());
+    if let Some(entry) = existing {
+        // 正式控制台接替同名的启动控制台
+        if entry.flags.contains(ConsoleFlags::BOOT) && !flags.contains(ConsoleFlags::BOOT) {
+            entry.console = console;
+            entry.flags = flags;
+            return Ok(());
+        }
+        return Err(SystemError::EEXIST);
+    }
+
+    let slot = consoles
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(SystemError::ENOSPC)?;
+    *slot = Some(ConsoleEntry {
+        console,
+        flags,
+        loglevel: CONSOLE_LOGLEVEL_DEFAULT,
+    });
+    Ok(())
+}
+
+/// 注销控制台
+pub fn unregister_console(name: &str) -> Result<(), SystemError> {
+    let mut consoles = CONSOLES.lock_irqsave();
+    let slot = consoles
+        .iter_mut()
+        .find(|slot| slot.is_some_and(|entry| entry.console.name() == name))
+        .ok_or(SystemError::ENODEV)?;
+    *slot = None;
+    Ok(())
+}
+
+/// 设置控制台的日志级别
+///
+/// 级别数值小于`loglevel`的消息才会输出到该控制台，设置为0时不输出任何日志
+pub fn console_set_loglevel(name: &str, loglevel: usize) -> Result<(), SystemError> {
+    if loglevel > CONSOLE_LOGLEVEL_MAX {
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut consoles = CONSOLES.lock_irqsave();
+    let entry = consoles
+        .iter_mut()
+        .flatten()
+        .find(|entry| entry.console.name() == name)
+        .ok_or(SystemError::ENODEV)?;
+    entry.loglevel = loglevel;
+    Ok(())
+}
+
+/// 获取控制台的日志级别
+pub fn console_loglevel(name: &str) -> Option<usize> {
+    CONSOLES
+        .lock_irqsave()
+        .iter()
+        .flatten()
+        .find(|entry| entry.console.name() == name)
+        .map(|entry| entry.loglevel)
+}
+
+/// 把字符串输出到所有控制台
+///
+/// ## 参数
+///
+/// - `level`: 消息的日志级别，为`None`时（如`print!`）输出到所有控制台
+/// - `s`: 要输出的字符串
+pub fn console_write(level: Option<usize>, s: &str) {
+    // 先复制一份控制台列表再输出，避免控制台在输出时打印日志造成死锁
+    let consoles = *CONSOLES.lock_irqsave();
+
+    let mut registered = false;
+    for entry in consoles.iter().flatten() {
+        registered = true;
+        if level.map_or(true, |level| level < entry.loglevel) {
+            entry.console.write(s);
+        }
+    }
+
+    // 还没有任何控制台时，直接输出到串口，以免丢失最早期的日志
+    if !registered {
+        send_to_default_serial8250_port(s.as_bytes());
+    }
+}
@@ -1,5 +1,6 @@
-use core::ops::BitAnd;
+use core::{fmt::Write, ops::BitAnd};
 
+use alloc::{string::String, vec::Vec};
 use bitmap::{traits::BitMapOps, AllocBitmap};
 
 use crate::{mm::percpu::PerCpu, smp::cpu::ProcessorId};
@@ -31,6 +32,88 @@ impl CpuMask {
         mask
     }
 
+    /// 创建所有cpu都被置位的掩码
+    pub fn new_full() -> Self {
+        let mut mask = Self::new();
+        mask.bmp.set_all(true);
+        mask
+    }
+
+    /// # 从字节数组创建CPU掩码
+    ///
+    /// 第i个字节的第j位对应第`i * 8 + j`个cpu（与Linux的`cpu_set_t`相同），超出`MAX_CPU_NUM`的位被忽略
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut mask = Self::new();
+        for cpu in 0..(bytes.len() * 8).min(PerCpu::MAX_CPU_NUM as usize) {
+            if bytes[cpu / 8] & (1 << (cpu % 8)) != 0 {
+                mask.set(ProcessorId::new(cpu as u32), true);
+            }
+        }
+        mask
+    }
+
+    /// 转换为长度为`len`的字节数组，格式与`from_bytes`相同
+    pub fn to_bytes(&self, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; len];
+        for cpu in self.iter_cpu() {
+            let cpu = cpu.data() as usize;
+            if cpu / 8 < len {
+                bytes[cpu / 8] |= 1 << (cpu % 8);
+            }
+        }
+        bytes
+    }
+
+    /// 被置位的cpu的数量
+    pub fn weight(&self) -> usize {
+        self.iter_cpu().count()
+    }
+
+    /// # 以十六进制显示前`nbits`个cpu
+    ///
+    /// 与Linux的`%*pb`相同，每32位为一组，高位在前，组之间用逗号分隔，如`ffffffff,0000000f`
+    pub fn to_hex_string(&self, nbits: usize) -> String {
+        let mut s = String::new();
+        let groups = nbits.div_ceil(32).max(1);
+        for group in (0..groups).rev() {
+            let mut value = 0u32;
+            for bit in 0..32 {
+                let cpu = group * 32 + bit;
+                if cpu < nbits && self.get(ProcessorId::new(cpu as u32)).unwrap_or(false) {
+                    value |= 1 << bit;
+                }
+            }
+            if group == groups - 1 {
+                let width = (nbits - group * 32).div_ceil(4).max(1);
+                write!(s, "{:0width$x}", value, width = width).ok();
+            } else {
+                write!(s, ",{:08x}", value).ok();
+            }
+        }
+        s
+    }
+
+    /// 以列表的形式显示被置位的cpu，连续的cpu合并为一个区间，如`0-3,5`
+    pub fn to_list_string(&self) -> String {
+        let mut s = String::new();
+        let mut iter = self.iter_cpu().map(|cpu| cpu.data()).peekable();
+        while let Some(start) = iter.next() {
+            let mut end = start;
+            while iter.peek() == Some(&(end + 1)) {
+                end = iter.next().unwrap();
+            }
+            if !s.is_empty() {
+                s.push(',');
+            }
+            if start == end {
+                write!(s, "{}", start).ok();
+            } else {
+                write!(s, "{}-{}", start, end).ok();
+            }
+        }
+        s
+    }
+
     /// 获取CpuMask中的第一个cpu
     pub fn first(&self) -> Option<ProcessorId> {
         self.bmp
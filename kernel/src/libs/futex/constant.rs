@@ -45,10 +45,21 @@ bitflags! {
     }
 }
 
-#[allow(dead_code)]
+impl FutexArg {
+    /// 命令的第四个参数是否为超时时间，其余命令的第四个参数为val2
+    pub fn has_timeout(&self) -> bool {
+        matches!(
+            *self,
+            Self::FUTEX_WAIT
+                | Self::FUTEX_WAIT_BITSET
+                | Self::FUTEX_LOCK_PI
+                | Self::FUTEX_LOCK_PI2
+                | Self::FUTEX_WAIT_REQUEUE_PI
+        )
+    }
+}
+
 pub const FUTEX_WAITERS: u32 = 0x80000000;
-#[allow(dead_code)]
 pub const FUTEX_OWNER_DIED: u32 = 0x40000000;
-#[allow(dead_code)]
 pub const FUTEX_TID_MASK: u32 = 0x3fffffff;
 pub const FUTEX_BITSET_MATCH_ANY: u32 = 0xffffffff;
@@ -1,12 +1,13 @@
 use alloc::{
     collections::LinkedList,
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use core::hash::{Hash, Hasher};
 use core::{
     intrinsics::{likely, unlikely},
     mem,
-    sync::atomic::AtomicU64,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
 };
 use log::warn;
 
@@ -19,7 +20,7 @@ use crate::{
     libs::spinlock::{SpinLock, SpinLockGuard},
     mm::{ucontext::AddressSpace, MemoryManagementArch, VirtAddr},
     process::{Pid, ProcessControlBlock, ProcessManager},
-    sched::{schedule, SchedMode},
+    sched::{rt_mutex_setprio, schedule, SchedMode},
     syscall::user_access::{UserBufferReader, UserBufferWriter},
     time::{
         timer::{next_n_us_timer_jiffies, Timer, WakeUpHelper},
@@ -43,10 +44,18 @@ impl FutexData {
     pub fn try_remove(key: &FutexKey) -> Option<FutexHashBucket> {
         unsafe {
             let mut guard = FUTEX_DATA.as_ref().unwrap().data.lock();
-            if let Some(futex) = guard.get(key) {
-                if futex.chain.is_empty() {
-                    return guard.remove(key);
-                }
+            Self::remove_if_empty(&mut guard, key)
+        }
+    }
+
+    /// 在已经持有锁的情况下，删除没有等待者的bucket
+    fn remove_if_empty(
+        map: &mut HashMap<FutexKey, FutexHashBucket>,
+        key: &FutexKey,
+    ) -> Option<FutexHashBucket> {
+        if let Some(futex) = map.get(key) {
+            if futex.chain.is_empty() {
+                return map.remove(key);
             }
         }
         None
@@ -59,16 +68,16 @@ pub struct Futex;
 pub struct FutexHashBucket {
     // 该futex维护的等待队列
     chain: LinkedList<Arc<FutexObj>>,
+    // PI futex的持有者，只在有进程等待该PI futex时记录
+    pi_owner: Option<Weak<ProcessControlBlock>>,
 }
 
 impl FutexHashBucket {
-    /// ## 判断是否在bucket里
-    pub fn contains(&self, futex_q: &FutexObj) -> bool {
-        self.chain
-            .iter()
-            .filter(|x| futex_q.pcb.ptr_eq(&x.pcb) && x.key == futex_q.key)
-            .count()
-            != 0
+    fn new() -> Self {
+        Self {
+            chain: LinkedList::new(),
+            pi_owner: None,
+        }
     }
 
     /// 让futex_q在该bucket上挂起
@@ -84,66 +93,127 @@ impl FutexHashBucket {
         Ok(())
     }
 
-    /// ## 唤醒队列中的最多nr_wake个进程
+    /// ## 唤醒队列中bitset与`bitset`有交集的最多nr_wake个进程
     ///
     /// return: 唤醒的进程数
     #[inline(always)]
-    pub fn wake_up(
-        &mut self,
-        key: FutexKey,
-        bitset: Option<u32>,
-        nr_wake: u32,
-    ) -> Result<usize, SystemError> {
+    pub fn wake_up(&mut self, bitset: u32, nr_wake: u32) -> Result<usize, SystemError> {
         let mut count = 0;
-        let mut pop_count = 0;
-        while let Some(futex_q) = self.chain.pop_front() {
-            if futex_q.key == key {
-                // TODO: 考虑优先级继承的机制
-
-                if let Some(bitset) = bitset {
-                    if futex_q.bitset != bitset {
-                        self.chain.push_back(futex_q);
-                        continue;
-                    }
+        let woken = self
+            .chain
+            .extract_if(|futex_q| {
+                if count >= nr_wake || futex_q.bitset & bitset == 0 {
+                    return false;
                 }
-
-                // 唤醒
-                if futex_q.pcb.upgrade().is_some() {
-                    self.remove(futex_q.clone());
-                    ProcessManager::wakeup(&futex_q.pcb.upgrade().unwrap())?;
-                }
-
-                // 判断唤醒数
                 count += 1;
-                if count >= nr_wake {
-                    break;
-                }
-            } else {
-                self.chain.push_back(futex_q);
-            }
-            // 判断是否循环完队列了
-            pop_count += 1;
-            if pop_count >= self.chain.len() {
-                break;
-            }
+                true
+            })
+            .collect::<Vec<_>>();
+
+        for futex_q in woken.iter() {
+            futex_q.wake()?;
         }
-        Ok(count as usize)
+        if !self.has_pi_waiters() {
+            self.pi_owner = None;
+        }
+        Ok(woken.len())
     }
 
     /// 将FutexObj从bucket中删除
-    pub fn remove(&mut self, futex: Arc<FutexObj>) {
+    pub fn remove(&mut self, futex: &Arc<FutexObj>) {
         self.chain
-            .extract_if(|x| Arc::ptr_eq(x, &futex))
+            .extract_if(|x| Arc::ptr_eq(x, futex))
             .for_each(drop);
+        if !self.has_pi_waiters() {
+            self.pi_owner = None;
+        }
     }
+
+    fn has_pi_waiters(&self) -> bool {
+        self.chain.iter().any(|futex_q| futex_q.is_pi())
+    }
+
+    /// 优先级最高的PI等待者，优先级相同时先等待的优先
+    fn top_pi_waiter(&self) -> Option<&Arc<FutexObj>> {
+        self.chain
+            .iter()
+            .filter(|futex_q| futex_q.is_pi())
+            .reduce(|top, futex_q| {
+                if futex_q.prio < top.prio {
+                    futex_q
+                } else {
+                    top
+                }
+            })
+    }
+
+    fn is_pi_owned_by(&self, pcb: &Arc<ProcessControlBlock>) -> bool {
+        self.pi_owner
+            .as_ref()
+            .is_some_and(|owner| core::ptr::eq(owner.as_ptr(), Arc::as_ptr(pcb)))
+    }
+}
+
+struct FutexObjInner {
+    // 当前所在bucket的key，requeue时会被修改
+    key: FutexKey,
+    // 是否作为PI futex的等待者
+    pi: bool,
+    // 是否已经被唤醒者从bucket中移除
+    woken: bool,
 }
 
-#[derive(Debug)]
 pub struct FutexObj {
     pcb: Weak<ProcessControlBlock>,
-    key: FutexKey,
     bitset: u32,
-    // TODO: 优先级继承
+    // 开始等待时的优先级，用于PI futex的优先级继承和选择下一个持有者
+    prio: i32,
+    // FUTEX_WAIT_REQUEUE_PI等待者被requeue的目标
+    requeue_pi_key: Option<FutexKey>,
+    inner: SpinLock<FutexObjInner>,
+}
+
+impl FutexObj {
+    fn new(
+        pcb: &Arc<ProcessControlBlock>,
+        key: FutexKey,
+        bitset: u32,
+        pi: bool,
+        requeue_pi_key: Option<FutexKey>,
+    ) -> Arc<Self> {
+        Arc::new(FutexObj {
+            pcb: Arc::downgrade(pcb),
+            bitset,
+            prio: pcb.sched_info().prio_data.read_irqsave().prio,
+            requeue_pi_key,
+            inner: SpinLock::new(FutexObjInner {
+                key,
+                pi,
+                woken: false,
+            }),
+        })
+    }
+
+    fn key(&self) -> FutexKey {
+        self.inner.lock().key.clone()
+    }
+
+    fn is_pi(&self) -> bool {
+        self.inner.lock().pi
+    }
+
+    fn is_woken(&self) -> bool {
+        self.inner.lock().woken
+    }
+
+    /// 标记为已唤醒并唤醒等待的进程，调用前需要已经将它从bucket中移除
+    fn wake(&self) -> Result<(), SystemError> {
+        self.inner.lock().woken = true;
+        if let Some(pcb) = self.pcb.upgrade() {
+            ProcessManager::wakeup(&pcb)?;
+        }
+        Ok(())
+    }
 }
 
 pub enum FutexAccess {
@@ -217,11 +287,13 @@ impl Futex {
     }
 
     /// ### 让当前进程在指定futex上等待直到futex_wake显式唤醒
+    ///
+    /// `timeout`为相对时间
     pub fn futex_wait(
         uaddr: VirtAddr,
         flags: FutexFlag,
         val: u32,
-        abs_time: Option<PosixTimeSpec>,
+        timeout: Option<PosixTimeSpec>,
         bitset: u32,
     ) -> Result<usize, SystemError> {
         if bitset == 0 {
@@ -235,115 +307,147 @@ impl Futex {
             FutexAccess::FutexRead,
         )?;
 
-        let mut futex_map_guard = FutexData::futex_map();
-        let bucket = futex_map_guard.get_mut(&key);
-        let bucket_mut = match bucket {
-            Some(bucket) => bucket,
-            None => {
-                let bucket = FutexHashBucket {
-                    chain: LinkedList::new(),
-                };
-                futex_map_guard.insert(key.clone(), bucket);
-                futex_map_guard.get_mut(&key).unwrap()
-            }
-        };
+        let pcb = ProcessManager::current_pcb();
+        let futex_q = FutexObj::new(&pcb, key, bitset, false, None);
+        Self::queue_and_wait(uaddr, val, &futex_q, timeout)?;
 
-        // 使用UserBuffer读取futex
-        let user_reader =
-            UserBufferReader::new(uaddr.as_ptr::<u32>(), core::mem::size_of::<u32>(), true)?;
+        Ok(0)
+    }
 
-        // 从用户空间读取到futex的val
-        let mut uval = 0;
+    /// ### 在uaddr上等待，被FUTEX_CMP_REQUEUE_PI转移到uaddr2后等待获得uaddr2上的PI锁
+    ///
+    /// 成功返回时，当前进程已经持有uaddr2上的锁
+    pub fn futex_wait_requeue_pi(
+        uaddr: VirtAddr,
+        flags: FutexFlag,
+        val: u32,
+        timeout: Option<PosixTimeSpec>,
+        bitset: u32,
+        uaddr2: VirtAddr,
+    ) -> Result<usize, SystemError> {
+        if bitset == 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let shared = flags.contains(FutexFlag::FLAGS_SHARED);
+        let key = Self::get_futex_key(uaddr, shared, FutexAccess::FutexRead)?;
+        let key2 = Self::get_futex_key(uaddr2, shared, FutexAccess::FutexWrite)?;
+        if key == key2 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let pcb = ProcessManager::current_pcb();
+        let tid = pcb.pid().data() as u32;
+        let futex_q = FutexObj::new(&pcb, key, bitset, false, Some(key2.clone()));
+        Self::queue_and_wait(uaddr, val, &futex_q, timeout)?;
 
-        // 读取
+        // 没有被requeue，而是在uaddr上被直接唤醒
+        if futex_q.key() != key2 {
+            return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+        }
+        if futex_q.is_woken() && Self::read_futex_value(uaddr2)? & FUTEX_TID_MASK == tid {
+            return Ok(0);
+        }
+
+        // 已经被requeue，但还没有获得锁
+        Self::futex_lock_pi(uaddr2, flags, timeout, false)
+    }
+
+    /// # 检查futex的值，然后在futex_q所在的bucket上挂起
+    ///
+    /// 返回时futex_q已经不在任何bucket中
+    ///
+    /// ## 返回值
+    /// - `Ok(())`: 被唤醒，或者虚假唤醒
+    /// - `Err(EAGAIN)`: futex的值不等于`val`
+    /// - `Err(ETIMEDOUT)`: 超时
+    /// - `Err(ERESTARTSYS)`: 被信号打断
+    fn queue_and_wait(
+        uaddr: VirtAddr,
+        val: u32,
+        futex_q: &Arc<FutexObj>,
+        timeout: Option<PosixTimeSpec>,
+    ) -> Result<(), SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let key = futex_q.key();
+        let mut futex_map_guard = FutexData::futex_map();
+
+        // 从用户空间读取到futex的val
         // 这里只尝试一种方式去读取用户空间，与linux不太一致
         // 对于linux，如果bucket被锁住时读取失败，将会将bucket解锁后重新读取
-        user_reader.copy_one_from_user::<u32>(&mut uval, 0)?;
+        let uval = Self::read_futex_value(uaddr)?;
 
         // 不满足wait条件，返回错误
         if uval != val {
             return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
         }
 
-        let pcb = ProcessManager::current_pcb();
         // 创建超时计时器任务
-        let mut timer = None;
-        if let Some(time) = abs_time {
-            let wakeup_helper = WakeUpHelper::new(pcb.clone());
-
-            let sec = time.tv_sec;
-            let nsec = time.tv_nsec;
-            let jiffies = next_n_us_timer_jiffies((nsec / 1000 + sec * 1_000_000) as u64);
-
-            let wake_up = Timer::new(wakeup_helper, jiffies);
+        let timer = Self::start_timer(&pcb, timeout);
 
-            wake_up.activate();
-            timer = Some(wake_up);
-        }
-
-        let futex_q = Arc::new(FutexObj {
-            pcb: Arc::downgrade(&pcb),
-            key: key.clone(),
-            bitset,
-        });
+        let bucket = futex_map_guard
+            .entry(key)
+            .or_insert_with(FutexHashBucket::new);
         let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
         // 满足条件则将当前进程在该bucket上挂起
-        bucket_mut.sleep_no_sched(futex_q.clone()).map_err(|e| {
+        if let Err(e) = bucket.sleep_no_sched(futex_q.clone()) {
             warn!("error:{e:?}");
-            e
-        })?;
+            bucket.remove(futex_q);
+            if let Some(timer) = timer {
+                timer.cancel();
+            }
+            return Err(e);
+        }
         drop(futex_map_guard);
         drop(irq_guard);
         schedule(SchedMode::SM_NONE);
 
-        // 被唤醒后的检查
-        let mut futex_map_guard = FutexData::futex_map();
-        let bucket = futex_map_guard.get_mut(&key);
-        let bucket_mut = match bucket {
-            // 如果该pcb不在链表里面了或者该链表已经被释放，就证明是正常的Wake操作
-            Some(bucket_mut) => {
-                if !bucket_mut.contains(&futex_q) {
-                    // 取消定时器任务
-                    if let Some(timer) = timer {
-                        timer.cancel();
-                    }
-                    return Ok(0);
-                }
-                // 非正常唤醒，返回交给下层
-                bucket_mut
+        let ret = Self::finish_wait(futex_q, timer.as_ref());
+
+        // 取消定时器任务
+        if let Some(timer) = timer {
+            if !timer.timeout() {
+                timer.cancel();
             }
-            None => {
-                // 取消定时器任务
-                if let Some(timer) = timer {
-                    timer.cancel();
-                }
-                return Ok(0);
+        }
+        ret
+    }
+
+    /// 被唤醒后的检查。如果不是被唤醒者从bucket中移除的，则需要自己从bucket中移除
+    fn finish_wait(futex_q: &Arc<FutexObj>, timer: Option<&Arc<Timer>>) -> Result<(), SystemError> {
+        let mut futex_map_guard = FutexData::futex_map();
+        // 唤醒者会在持有锁的情况下将futex_q移出bucket，这是正常的Wake操作
+        if futex_q.is_woken() {
+            return Ok(());
+        }
+
+        let key = futex_q.key();
+        let mut pi_owner = None;
+        if let Some(bucket) = futex_map_guard.get_mut(&key) {
+            if futex_q.is_pi() {
+                pi_owner = bucket.pi_owner.as_ref().and_then(|owner| owner.upgrade());
             }
-        };
+            bucket.remove(futex_q);
+        }
+        // 不再等待PI futex，持有者的优先级可能需要降低
+        if let Some(owner) = pi_owner {
+            Self::pi_adjust_prio(&futex_map_guard, &owner);
+        }
+        FutexData::remove_if_empty(&mut futex_map_guard, &key);
+        drop(futex_map_guard);
 
         // 如果是超时唤醒，则返回错误
-        if timer.is_some() && timer.clone().unwrap().timeout() {
-            bucket_mut.remove(futex_q);
-
+        if timer.is_some_and(|timer| timer.timeout()) {
             return Err(SystemError::ETIMEDOUT);
         }
 
-        // TODO: 如果没有挂起的信号，则重新判断是否满足wait要求，重新进入wait
-
-        // 经过前面的几个判断，到这里之后，
-        // 当前进程被唤醒大概率是其他进程更改了uval,需要重新去判断当前进程是否满足wait
-
-        // 到这里之后，前面的唤醒条件都不满足，则是被信号唤醒
-        // 需要处理信号然后重启futex系统调用
-
-        // 取消定时器任务
-        if let Some(timer) = timer {
-            if !timer.timeout() {
-                timer.cancel();
-            }
+        // 被信号唤醒，需要处理信号然后重启futex系统调用
+        if ProcessManager::current_pcb().has_pending_signal() {
+            return Err(SystemError::ERESTARTSYS);
         }
 
-        Ok(0)
+        // 虚假唤醒，由调用者重新判断是否满足wait要求
+        Ok(())
     }
 
     // ### 唤醒指定futex上挂起的最多nr_wake个进程
@@ -364,23 +468,22 @@ impl Futex {
             FutexAccess::FutexRead,
         )?;
         let mut binding = FutexData::futex_map();
-        let bucket_mut = binding.get_mut(&key).ok_or(SystemError::EINVAL)?;
-
-        // 确保后面的唤醒操作是有意义的
-        if bucket_mut.chain.is_empty() {
+        // 没有进程在该futex上等待
+        let Some(bucket_mut) = binding.get_mut(&key) else {
             return Ok(0);
-        }
-        // 从队列中唤醒
-        let count = bucket_mut.wake_up(key.clone(), Some(bitset), nr_wake)?;
+        };
 
-        drop(binding);
+        // 从队列中唤醒
+        let count = bucket_mut.wake_up(bitset, nr_wake)?;
 
-        FutexData::try_remove(&key);
+        FutexData::remove_if_empty(&mut binding, &key);
 
         Ok(count)
     }
 
     /// ### 唤醒制定uaddr1上的最多nr_wake个进程，然后将uaddr1最多nr_requeue个进程移动到uaddr2绑定的futex上
+    ///
+    /// 返回唤醒和移动的进程数之和
     pub fn futex_requeue(
         uaddr1: VirtAddr,
         flags: FutexFlag,
@@ -394,9 +497,9 @@ impl Futex {
             return Err(SystemError::EINVAL);
         }
 
-        // 暂时不支持优先级继承
-        if requeue_pi {
-            return Err(SystemError::ENOSYS);
+        // 被唤醒的进程需要获得uaddr2上的锁，所以只能唤醒一个
+        if requeue_pi && nr_wake != 1 {
+            return Err(SystemError::EINVAL);
         }
 
         let key1 = Self::get_futex_key(
@@ -415,42 +518,365 @@ impl Futex {
             return Err(SystemError::EINVAL);
         }
 
+        let mut futex_data_guard = FutexData::futex_map();
         if likely(cmpval.is_some()) {
-            let uval_reader =
-                UserBufferReader::new(uaddr1.as_ptr::<u32>(), core::mem::size_of::<u32>(), true)?;
-            let curval = uval_reader.read_one_from_user::<u32>(0)?;
+            let curval = Self::read_futex_value(uaddr1)?;
 
             // 判断是否满足条件
-            if *curval != cmpval.unwrap() {
+            if curval != cmpval.unwrap() {
                 return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
             }
         }
 
-        let mut futex_data_guard = FutexData::futex_map();
-        if !requeue_pi {
-            // 唤醒nr_wake个进程
-            let bucket_1_mut = futex_data_guard.get_mut(&key1).ok_or(SystemError::EINVAL)?;
-            let ret = bucket_1_mut.wake_up(key1.clone(), None, nr_wake as u32)?;
-            // 将bucket1中最多nr_requeue个任务转移到bucket2
-            for _ in 0..nr_requeue {
-                let bucket_1_mut = futex_data_guard.get_mut(&key1).ok_or(SystemError::EINVAL)?;
-                let futex_q = bucket_1_mut.chain.pop_front();
-                match futex_q {
-                    Some(futex_q) => {
-                        let bucket_2_mut =
-                            futex_data_guard.get_mut(&key2).ok_or(SystemError::EINVAL)?;
-                        bucket_2_mut.chain.push_back(futex_q);
+        if requeue_pi {
+            return Self::futex_requeue_pi(
+                &mut futex_data_guard,
+                uaddr2,
+                &key1,
+                &key2,
+                nr_requeue as usize,
+            );
+        }
+
+        let Some(bucket_1_mut) = futex_data_guard.get_mut(&key1) else {
+            return Ok(0);
+        };
+        // 唤醒nr_wake个进程
+        let ret = bucket_1_mut.wake_up(FUTEX_BITSET_MATCH_ANY, nr_wake as u32)?;
+        // 将bucket1中最多nr_requeue个任务转移到bucket2
+        let moved = (0..nr_requeue)
+            .map_while(|_| bucket_1_mut.chain.pop_front())
+            .collect::<Vec<_>>();
+        FutexData::remove_if_empty(&mut futex_data_guard, &key1);
+
+        let count = moved.len();
+        if count != 0 {
+            let bucket_2_mut = futex_data_guard
+                .entry(key2.clone())
+                .or_insert_with(FutexHashBucket::new);
+            for futex_q in moved {
+                futex_q.inner.lock().key = key2.clone();
+                bucket_2_mut.chain.push_back(futex_q);
+            }
+        }
+
+        return Ok(ret + count);
+    }
+
+    /// # 将FUTEX_WAIT_REQUEUE_PI的等待者转移到uaddr2的PI futex上
+    ///
+    /// 锁空闲时，第一个等待者直接获得锁并被唤醒，其余的成为uaddr2上的PI等待者
+    fn futex_requeue_pi(
+        futex_map: &mut HashMap<FutexKey, FutexHashBucket>,
+        uaddr2: VirtAddr,
+        key1: &FutexKey,
+        key2: &FutexKey,
+        nr_requeue: usize,
+    ) -> Result<usize, SystemError> {
+        let Some(bucket1) = futex_map.get_mut(key1) else {
+            return Ok(0);
+        };
+        // 等待者都必须是以uaddr2为目标的FUTEX_WAIT_REQUEUE_PI
+        if bucket1
+            .chain
+            .iter()
+            .any(|futex_q| futex_q.requeue_pi_key.as_ref() != Some(key2))
+        {
+            return Err(SystemError::EINVAL);
+        }
+        let waiters = (0..=nr_requeue)
+            .map_while(|_| bucket1.chain.pop_front())
+            .collect::<Vec<_>>();
+        FutexData::remove_if_empty(futex_map, key1);
+
+        let mut count = 0;
+        let mut owner = None;
+        let mut waiters = waiters.into_iter();
+        while let Some(futex_q) = waiters.next() {
+            let Some(pcb) = futex_q.pcb.upgrade() else {
+                continue;
+            };
+            {
+                let mut inner = futex_q.inner.lock();
+                inner.key = key2.clone();
+                inner.pi = true;
+            }
+
+            match Self::pi_prepare_wait(uaddr2, pcb.pid().data() as u32) {
+                Ok(None) => {
+                    futex_q.wake()?;
+                    owner = Some(pcb);
+                }
+                Ok(Some(o)) => {
+                    futex_map
+                        .entry(key2.clone())
+                        .or_insert_with(FutexHashBucket::new)
+                        .chain
+                        .push_back(futex_q);
+                    owner = Some(o);
+                }
+                Err(e) => {
+                    // 无法完成requeue，唤醒剩下的等待者，让它们回到用户态重试
+                    futex_q.wake()?;
+                    for futex_q in waiters {
+                        futex_q.wake()?;
                     }
-                    None => {
-                        break;
+                    if let Some(owner) = owner {
+                        Self::pi_set_owner(futex_map, key2, &owner);
                     }
+                    return Err(e);
                 }
             }
+            count += 1;
+        }
 
-            return Ok(ret);
+        if let Some(owner) = owner {
+            Self::pi_set_owner(futex_map, key2, &owner);
+        }
+        Ok(count)
+    }
+
+    /// # 获得PI futex
+    ///
+    /// 锁被其他进程持有时，当前进程挂起，同时持有者的优先级会被提升到不低于当前进程的优先级。
+    /// 释放锁的进程会把锁直接交给优先级最高的等待者
+    ///
+    /// ## 参数
+    /// - `timeout`: 相对的超时时间
+    /// - `trylock`: 为true时不等待，锁被持有时返回EAGAIN
+    pub fn futex_lock_pi(
+        uaddr: VirtAddr,
+        flags: FutexFlag,
+        timeout: Option<PosixTimeSpec>,
+        trylock: bool,
+    ) -> Result<usize, SystemError> {
+        let key = Self::get_futex_key(
+            uaddr,
+            flags.contains(FutexFlag::FLAGS_SHARED),
+            FutexAccess::FutexWrite,
+        )?;
+        let pcb = ProcessManager::current_pcb();
+        let timer = if trylock {
+            None
         } else {
-            // 暂时不支持优先级继承
-            todo!()
+            Self::start_timer(&pcb, timeout)
+        };
+
+        let ret = Self::do_lock_pi(uaddr, &key, &pcb, timer.as_ref(), trylock);
+
+        if let Some(timer) = timer {
+            if !timer.timeout() {
+                timer.cancel();
+            }
+        }
+        ret
+    }
+
+    fn do_lock_pi(
+        uaddr: VirtAddr,
+        key: &FutexKey,
+        pcb: &Arc<ProcessControlBlock>,
+        timer: Option<&Arc<Timer>>,
+        trylock: bool,
+    ) -> Result<usize, SystemError> {
+        let tid = pcb.pid().data() as u32;
+        loop {
+            let mut futex_map_guard = FutexData::futex_map();
+            let uval = Self::read_futex_value(uaddr)?;
+            if uval & FUTEX_TID_MASK == tid {
+                return Err(SystemError::EDEADLK_OR_EDEADLOCK);
+            }
+            if trylock && uval & FUTEX_TID_MASK != 0 {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+
+            let owner = match Self::pi_prepare_wait(uaddr, tid)? {
+                None => {
+                    Self::pi_set_owner(&mut futex_map_guard, key, pcb);
+                    return Ok(0);
+                }
+                Some(_) if trylock => return Err(SystemError::EAGAIN_OR_EWOULDBLOCK),
+                Some(owner) => owner,
+            };
+
+            let futex_q = FutexObj::new(pcb, key.clone(), FUTEX_BITSET_MATCH_ANY, true, None);
+            let bucket = futex_map_guard
+                .entry(key.clone())
+                .or_insert_with(FutexHashBucket::new);
+            bucket.pi_owner = Some(Arc::downgrade(&owner));
+            let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+            if let Err(e) = bucket.sleep_no_sched(futex_q.clone()) {
+                warn!("error:{e:?}");
+                bucket.remove(&futex_q);
+                return Err(e);
+            }
+            // 优先级继承：持有者的优先级不低于等待者
+            Self::pi_adjust_prio(&futex_map_guard, &owner);
+            drop(futex_map_guard);
+            drop(irq_guard);
+            schedule(SchedMode::SM_NONE);
+
+            let ret = Self::finish_wait(&futex_q, timer);
+            // 释放锁的进程已经把锁交给了当前进程
+            if Self::read_futex_value(uaddr)? & FUTEX_TID_MASK == tid {
+                return Ok(0);
+            }
+            ret?;
+            // 虚假唤醒，或者持有者退出时被唤醒，重新尝试获得锁
+        }
+    }
+
+    /// # 释放PI futex
+    ///
+    /// 有等待者时，把锁交给优先级最高的等待者并唤醒它，同时恢复当前进程的优先级
+    pub fn futex_unlock_pi(uaddr: VirtAddr, flags: FutexFlag) -> Result<usize, SystemError> {
+        let key = Self::get_futex_key(
+            uaddr,
+            flags.contains(FutexFlag::FLAGS_SHARED),
+            FutexAccess::FutexWrite,
+        )?;
+        let pcb = ProcessManager::current_pcb();
+        let tid = pcb.pid().data() as u32;
+
+        let mut futex_map_guard = FutexData::futex_map();
+        loop {
+            let uval = Self::read_futex_value(uaddr)?;
+            if uval & FUTEX_TID_MASK != tid {
+                return Err(SystemError::EPERM);
+            }
+
+            let next = futex_map_guard
+                .get(&key)
+                .and_then(|bucket| bucket.top_pi_waiter().cloned());
+            let Some(next) = next else {
+                // 没有等待者，直接释放
+                if Self::cmpxchg_futex_value(uaddr, uval, 0)? != uval {
+                    continue;
+                }
+                break;
+            };
+
+            let bucket = futex_map_guard.get_mut(&key).unwrap();
+            bucket.remove(&next);
+            let Some(next_pcb) = next.pcb.upgrade() else {
+                continue;
+            };
+            let mut newval = next_pcb.pid().data() as u32;
+            if bucket.has_pi_waiters() {
+                newval |= FUTEX_WAITERS;
+            }
+            if Self::cmpxchg_futex_value(uaddr, uval, newval)? != uval {
+                // futex的值在用户态被修改了，把等待者放回去重试
+                bucket.chain.push_front(next);
+                bucket.pi_owner = Some(Arc::downgrade(&pcb));
+                continue;
+            }
+
+            next.wake()?;
+            Self::pi_set_owner(&mut futex_map_guard, &key, &next_pcb);
+            break;
+        }
+
+        FutexData::remove_if_empty(&mut futex_map_guard, &key);
+        Self::pi_adjust_prio(&futex_map_guard, &pcb);
+
+        Ok(0)
+    }
+
+    /// # 为即将挂起的PI等待者设置FUTEX_WAITERS，并找到锁的持有者
+    ///
+    /// ## 返回值
+    /// - `Ok(Some(owner))`: 锁的持有者
+    /// - `Ok(None)`: 锁是空闲的，已经由`tid`获得
+    fn pi_prepare_wait(
+        uaddr: VirtAddr,
+        tid: u32,
+    ) -> Result<Option<Arc<ProcessControlBlock>>, SystemError> {
+        loop {
+            let uval = Self::read_futex_value(uaddr)?;
+            let owner_tid = uval & FUTEX_TID_MASK;
+            if owner_tid == 0 {
+                // 锁没有持有者（或者持有者已经退出），直接获得它，保留FUTEX_OWNER_DIED让用户态知道
+                let newval = tid | (uval & (FUTEX_WAITERS | FUTEX_OWNER_DIED));
+                if Self::cmpxchg_futex_value(uaddr, uval, newval)? == uval {
+                    return Ok(None);
+                }
+                continue;
+            }
+
+            // 设置FUTEX_WAITERS后，持有者只能通过FUTEX_UNLOCK_PI释放锁
+            if uval & FUTEX_WAITERS == 0
+                && Self::cmpxchg_futex_value(uaddr, uval, uval | FUTEX_WAITERS)? != uval
+            {
+                continue;
+            }
+
+            return ProcessManager::find(Pid::new(owner_tid as usize))
+                .map(Some)
+                .ok_or(SystemError::ESRCH);
+        }
+    }
+
+    /// 设置PI futex的持有者，并根据等待者的优先级调整持有者的优先级
+    fn pi_set_owner(
+        futex_map: &mut HashMap<FutexKey, FutexHashBucket>,
+        key: &FutexKey,
+        owner: &Arc<ProcessControlBlock>,
+    ) {
+        if let Some(bucket) = futex_map.get_mut(key) {
+            if bucket.has_pi_waiters() {
+                bucket.pi_owner = Some(Arc::downgrade(owner));
+            }
+        }
+        Self::pi_adjust_prio(futex_map, owner);
+    }
+
+    /// # 根据进程持有的所有PI futex上优先级最高的等待者，重新计算进程的优先级
+    ///
+    /// 只处理一层：持有者自己在等待另一个PI futex时，不会继续提升那个futex的持有者
+    fn pi_adjust_prio(
+        futex_map: &HashMap<FutexKey, FutexHashBucket>,
+        pcb: &Arc<ProcessControlBlock>,
+    ) {
+        let top_prio = futex_map
+            .values()
+            .filter(|bucket| bucket.is_pi_owned_by(pcb))
+            .filter_map(|bucket| bucket.top_pi_waiter())
+            .map(|futex_q| futex_q.prio)
+            .min();
+        rt_mutex_setprio(pcb, top_prio);
+    }
+
+    /// 创建超时计时器任务，`timeout`为相对时间
+    fn start_timer(
+        pcb: &Arc<ProcessControlBlock>,
+        timeout: Option<PosixTimeSpec>,
+    ) -> Option<Arc<Timer>> {
+        let time = timeout?;
+        let wakeup_helper = WakeUpHelper::new(pcb.clone());
+
+        let sec = time.tv_sec;
+        let nsec = time.tv_nsec;
+        let jiffies = next_n_us_timer_jiffies((nsec / 1000 + sec * 1_000_000) as u64);
+
+        let wake_up = Timer::new(wakeup_helper, jiffies);
+        wake_up.activate();
+        Some(wake_up)
+    }
+
+    fn read_futex_value(uaddr: VirtAddr) -> Result<u32, SystemError> {
+        let reader =
+            UserBufferReader::new(uaddr.as_ptr::<u32>(), core::mem::size_of::<u32>(), true)?;
+        Ok(*reader.read_one_from_user::<u32>(0)?)
+    }
+
+    /// 原子地比较并交换用户空间中futex的值，返回交换前的值
+    fn cmpxchg_futex_value(uaddr: VirtAddr, old: u32, new: u32) -> Result<u32, SystemError> {
+        // 检查地址是否可写
+        UserBufferWriter::new(uaddr.as_ptr::<u32>(), core::mem::size_of::<u32>(), true)?;
+        let atomic = unsafe { AtomicU32::from_ptr(uaddr.as_ptr::<u32>()) };
+        match atomic.compare_exchange(old, new, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(val) | Err(val) => Ok(val),
         }
     }
 
@@ -475,18 +901,20 @@ impl Futex {
         )?;
 
         let mut futex_data_guard = FutexData::futex_map();
-        let bucket1 = futex_data_guard.get_mut(&key1).ok_or(SystemError::EINVAL)?;
         let mut wake_count = 0;
 
         // 唤醒uaddr1中的进程
-        wake_count += bucket1.wake_up(key1, None, nr_wake as u32)?;
+        if let Some(bucket1) = futex_data_guard.get_mut(&key1) {
+            wake_count += bucket1.wake_up(FUTEX_BITSET_MATCH_ANY, nr_wake as u32)?;
+        }
 
         match Self::futex_atomic_op_inuser(op as u32, uaddr2) {
             Ok(ret) => {
                 // 操作成功则唤醒uaddr2中的进程
                 if ret {
-                    let bucket2 = futex_data_guard.get_mut(&key2).ok_or(SystemError::EINVAL)?;
-                    wake_count += bucket2.wake_up(key2, None, nr_wake2 as u32)?;
+                    if let Some(bucket2) = futex_data_guard.get_mut(&key2) {
+                        wake_count += bucket2.wake_up(FUTEX_BITSET_MATCH_ANY, nr_wake2 as u32)?;
+                    }
                 }
             }
             Err(e) => {
@@ -495,6 +923,9 @@ impl Futex {
             }
         }
 
+        FutexData::remove_if_empty(&mut futex_data_guard, &key1);
+        FutexData::remove_if_empty(&mut futex_data_guard, &key2);
+
         Ok(wake_count)
     }
 
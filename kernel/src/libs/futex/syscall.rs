@@ -3,7 +3,10 @@ use system_error::SystemError;
 use crate::{
     mm::{verify_area, VirtAddr},
     syscall::Syscall,
-    time::PosixTimeSpec,
+    time::{
+        timekeeping::{getnstimeofday, ktime_get_ts64},
+        PosixTimeSpec,
+    },
 };
 
 use super::{
@@ -43,6 +46,12 @@ impl Syscall {
             }
         }
 
+        // 统一转换为相对的超时时间
+        let timeout = match timeout {
+            Some(time) => Some(futex_relative_timeout(cmd, flags, time)?),
+            None => None,
+        };
+
         match cmd {
             FutexArg::FUTEX_WAIT => {
                 return Futex::futex_wait(uaddr, flags, val, timeout, FUTEX_BITSET_MATCH_ANY);
@@ -88,23 +97,28 @@ impl Syscall {
                     val3 as i32,
                 );
             }
-            FutexArg::FUTEX_LOCK_PI => {
-                todo!()
-            }
-            FutexArg::FUTEX_LOCK_PI2 => {
-                todo!()
+            FutexArg::FUTEX_LOCK_PI | FutexArg::FUTEX_LOCK_PI2 => {
+                return Futex::futex_lock_pi(uaddr, flags, timeout, false);
             }
             FutexArg::FUTEX_UNLOCK_PI => {
-                todo!()
+                return Futex::futex_unlock_pi(uaddr, flags);
             }
             FutexArg::FUTEX_TRYLOCK_PI => {
-                todo!()
+                return Futex::futex_lock_pi(uaddr, flags, None, true);
             }
             FutexArg::FUTEX_WAIT_REQUEUE_PI => {
-                todo!()
+                return Futex::futex_wait_requeue_pi(uaddr, flags, val, timeout, val3, uaddr2);
             }
             FutexArg::FUTEX_CMP_REQUEUE_PI => {
-                todo!()
+                return Futex::futex_requeue(
+                    uaddr,
+                    flags,
+                    uaddr2,
+                    val as i32,
+                    val2 as i32,
+                    Some(val3),
+                    true,
+                );
             }
             _ => {
                 return Err(SystemError::ENOSYS);
@@ -133,3 +147,27 @@ impl Syscall {
         return ret;
     }
 }
+
+/// # 把用户传入的超时时间转换为相对时间
+///
+/// FUTEX_WAIT的超时时间是相对时间，其余命令的是绝对时间：
+/// FUTEX_LOCK_PI使用CLOCK_REALTIME，其余命令设置了FUTEX_CLOCK_REALTIME时使用CLOCK_REALTIME，否则使用CLOCK_MONOTONIC
+fn futex_relative_timeout(
+    cmd: FutexArg,
+    flags: FutexFlag,
+    time: PosixTimeSpec,
+) -> Result<PosixTimeSpec, SystemError> {
+    if time.tv_sec < 0 || !(0..1_000_000_000).contains(&time.tv_nsec) {
+        return Err(SystemError::EINVAL);
+    }
+    if cmd == FutexArg::FUTEX_WAIT {
+        return Ok(time);
+    }
+
+    let now = if cmd == FutexArg::FUTEX_LOCK_PI || flags.contains(FutexFlag::FLAGS_CLOCKRT) {
+        getnstimeofday()
+    } else {
+        ktime_get_ts64()
+    };
+    Ok(PosixTimeSpec::from(time - now))
+}
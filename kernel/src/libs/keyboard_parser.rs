@@ -1,4 +1,4 @@
-use crate::driver::tty::kthread::send_to_tty_refresh_thread;
+use crate::driver::tty::{kthread::send_to_tty_refresh_thread, sysrq::handle_sysrq};
 
 #[allow(dead_code)]
 pub const NUM_SCAN_CODES: u8 = 0x80;
@@ -307,6 +307,11 @@ impl TypeOneFSMState {
                 scancode_status.alt_r = flag_make;
                 key = KeyFlag::NoneFlag;
             }
+            0x54 => {
+                // 按住Alt时按下PrintScreen，键盘发送的是SysRq
+                scancode_status.sysrq = flag_make;
+                key = KeyFlag::NoneFlag;
+            }
             0x3A => {
                 if scancode_status.caps_lock {
                     scancode_status.caps_lock = !flag_make;
@@ -339,6 +344,15 @@ impl TypeOneFSMState {
             col = !col;
         }
 
+        if key != KeyFlag::NoneFlag
+            && scancode_status.sysrq
+            && (scancode_status.alt_l || scancode_status.alt_r)
+        {
+            // Alt+SysRq+按键：触发magic sysrq，按键不送往tty
+            handle_sysrq(TYPE1_KEY_CODE_MAPTABLE[2 * index as usize]);
+            return TypeOneFSMState::Start;
+        }
+
         let mut ch = TYPE1_KEY_CODE_MAPTABLE[col as usize + 2 * index as usize];
         if key != KeyFlag::NoneFlag {
             if scancode_status.ctrl_l || scancode_status.ctrl_r {
@@ -457,6 +471,8 @@ pub struct ScanCodeStatus {
     // 回车
     kp_enter: bool,
     caps_lock: bool,
+    // SysRq（按住Alt时的PrintScreen）
+    sysrq: bool,
 }
 
 impl ScanCodeStatus {
@@ -484,6 +500,7 @@ impl ScanCodeStatus {
             kp_forward_slash: false,
             kp_enter: false,
             caps_lock: false,
+            sysrq: false,
         }
     }
 }
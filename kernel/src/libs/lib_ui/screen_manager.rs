@@ -12,12 +12,17 @@ use crate::{
         serial::serial8250::send_to_default_serial8250_port,
         video::{has_video_refresh_manager, video_refresh_manager},
     },
-    libs::{lib_ui::textui::textui_is_enable_put_to_window, rwlock::RwLock, spinlock::SpinLock},
+    libs::{
+        console::{register_console, ConsoleFlags},
+        lib_ui::textui::textui_is_enable_put_to_window,
+        rwlock::RwLock,
+        spinlock::SpinLock,
+    },
     mm::{mmio_buddy::MMIOSpaceGuard, VirtAddr},
 };
 
 use super::{
-    textui::{textui_disable_put_to_window, textui_enable_put_to_window},
+    textui::{textui_disable_put_to_window, textui_enable_put_to_window, TextuiBootConsole},
     textui_no_alloc::textui_init_no_alloc,
 };
 
@@ -290,6 +295,9 @@ pub fn scm_init(enable_put_to_window: bool) {
         textui_disable_put_to_window();
     }
     textui_init_no_alloc(enable_put_to_window);
+    if enable_put_to_window {
+        register_console(&TextuiBootConsole, ConsoleFlags::BOOT).ok();
+    }
 
     send_to_default_serial8250_port("\nfinish_scm_init\n\0".as_bytes());
 }
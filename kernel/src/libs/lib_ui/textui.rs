@@ -4,6 +4,7 @@ use crate::{
         video::video_refresh_manager,
     },
     libs::{
+        console::Console,
         lib_ui::font::FONT_8x16,
         rwlock::RwLock,
         spinlock::{SpinLock, SpinLockGuard},
@@ -811,12 +812,8 @@ impl TextuiWindow {
         if !self.flags.contains(WindowFlag::TEXTUI_CHROMATIC) {
             return Ok(());
         }
-        send_to_default_serial8250_port(&[character as u8]);
-
         //进行换行操作
         if character == '\n' {
-            // 换行时还需要输出\r
-            send_to_default_serial8250_port(b"\r");
             if is_enable_window {
                 self.textui_new_line()?;
             }
@@ -1117,6 +1114,22 @@ pub fn textui_putstr(
     return Ok(());
 }
 
+/// textui启动控制台
+///
+/// 虚拟终端初始化之前，日志通过它输出到屏幕，之后由同名的虚拟终端控制台接替
+#[derive(Debug)]
+pub struct TextuiBootConsole;
+
+impl Console for TextuiBootConsole {
+    fn name(&self) -> &'static str {
+        "tty0"
+    }
+
+    fn write(&self, s: &str) {
+        textui_putstr(s, FontColor::WHITE, FontColor::BLACK).ok();
+    }
+}
+
 /// 初始化text ui框架
 #[inline(never)]
 pub fn textui_init() -> Result<i32, SystemError> {
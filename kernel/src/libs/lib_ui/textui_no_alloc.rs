@@ -5,9 +5,7 @@ use core::{
 
 use system_error::SystemError;
 
-use crate::driver::{
-    serial::serial8250::send_to_default_serial8250_port, video::video_refresh_manager,
-};
+use crate::driver::video::video_refresh_manager;
 
 use super::textui::{
     FontColor, LineId, LineIndex, TextuiCharChromatic, TEXTUI_CHAR_HEIGHT, TEXTUI_CHAR_WIDTH,
@@ -48,13 +46,10 @@ pub fn no_init_textui_putchar_window(
     if unlikely(character == '\0') {
         return Ok(());
     }
-    send_to_default_serial8250_port(&[character as u8]);
-
     if is_put_to_window {
         match character {
             // 进行换行操作
             '\n' => {
-                send_to_default_serial8250_port(b"\r");
                 if is_put_to_window {
                     next_line();
                 }
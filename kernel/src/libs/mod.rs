@@ -1,5 +1,6 @@
 pub mod align;
 pub mod casting;
+pub mod console;
 pub mod cpumask;
 pub mod elf;
 #[macro_use]
@@ -24,6 +25,7 @@ pub mod volatile;
 pub mod futex;
 pub mod rand;
 pub mod wait_queue;
+pub mod workqueue;
 
 pub mod font;
 pub mod name;
@@ -11,7 +11,7 @@ use crate::{
     exception::InterruptArch,
     libs::spinlock::SpinLockGuard,
     process::{Pid, ProcessControlBlock, ProcessManager},
-    sched::{schedule, SchedMode},
+    sched::{preempt::might_sleep, schedule, SchedMode},
 };
 
 use super::spinlock::SpinLock;
@@ -59,7 +59,9 @@ impl<T> Mutex<T> {
     /// @return MutexGuard<T> 返回Mutex的守卫，您可以使用这个守卫来操作被保护的数据
     #[inline(always)]
     #[allow(dead_code)]
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn lock(&self) -> MutexGuard<T> {
+        might_sleep();
         loop {
             let mut inner: SpinLockGuard<MutexInner> = self.inner.lock();
             // 当前mutex已经上锁
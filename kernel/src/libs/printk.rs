@@ -3,15 +3,11 @@ use core::fmt::{self, Write};
 use alloc::string::ToString;
 use log::{info, Level, Log};
 
-use super::lib_ui::textui::{textui_putstr, FontColor};
+use super::console::console_write;
 
 use crate::{
-    driver::tty::{tty_driver::TtyOperation, virtual_terminal::vc_manager},
-    filesystem::procfs::{
-        kmsg::KMSG,
-        log::{LogLevel, LogMessage},
-    },
-    time::PosixTimeSpec,
+    filesystem::procfs::{kmsg::KMSG, log::LogLevel},
+    time::{timekeeping::ktime_get_ts64, PosixTimeSpec},
 };
 
 #[macro_export]
@@ -35,21 +31,10 @@ impl PrintkWriter {
         self.write_fmt(args).ok();
     }
 
-    /// 并输出白底黑字
+    /// 输出到所有已注册的控制台
     /// @param str: 要写入的字符
     pub fn __write_string(&mut self, s: &str) {
-        if let Some(current_vc) = vc_manager().current_vc() {
-            // tty已经初始化了之后才输出到屏幕
-            let port = current_vc.port();
-            let tty = port.port_data().internal_tty();
-            if let Some(tty) = tty {
-                let _ = tty.write(tty.core(), s.as_bytes(), s.len());
-            } else {
-                let _ = textui_putstr(s, FontColor::WHITE, FontColor::BLACK);
-            }
-        } else {
-            let _ = textui_putstr(s, FontColor::WHITE, FontColor::BLACK);
-        }
+        console_write(None, s);
     }
 }
 
@@ -61,6 +46,16 @@ impl fmt::Write for PrintkWriter {
     }
 }
 
+/// 带日志级别的输出，只输出到日志级别足够的控制台
+struct LevelWriter(usize);
+
+impl fmt::Write for LevelWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        console_write(Some(self.0), s);
+        Ok(())
+    }
+}
+
 #[doc(hidden)]
 pub fn __printk(args: fmt::Arguments) {
     PrintkWriter.write_fmt(args).unwrap();
@@ -71,12 +66,14 @@ pub struct Logger;
 impl Logger {
     pub fn log(&self, log_level: usize, message: fmt::Arguments) {
         if unsafe { KMSG.is_some() } {
-            let timestamp: PosixTimeSpec = PosixTimeSpec::now_cpu_time();
+            let timestamp: PosixTimeSpec = ktime_get_ts64();
             let log_level = LogLevel::from(log_level);
 
-            let log_message = LogMessage::new(timestamp, log_level, message.to_string());
-
-            unsafe { KMSG.as_ref().unwrap().lock_irqsave().push(log_message) };
+            unsafe {
+                KMSG.as_ref()
+                    .unwrap()
+                    .push(timestamp, log_level, &message.to_string())
+            };
         }
     }
 }
@@ -107,20 +104,21 @@ impl Log for KernelLogger {
 
 impl KernelLogger {
     fn iodisplay(record: &log::Record) {
+        let mut writer = LevelWriter(Self::loglevel(record.level()));
         match record.level() {
             Level::Debug | Level::Info | Level::Trace => {
-                write!(PrintkWriter, "[ {} ] ", record.level(),)
+                write!(writer, "[ {} ] ", record.level(),)
             }
             Level::Error => {
-                write!(PrintkWriter, "\x1B[41m[ ERROR ] \x1B[0m",)
+                write!(writer, "\x1B[41m[ ERROR ] \x1B[0m",)
             }
             Level::Warn => {
-                write!(PrintkWriter, "\x1B[1;33m[ WARN ] \x1B[0m",)
+                write!(writer, "\x1B[1;33m[ WARN ] \x1B[0m",)
             }
         }
         .unwrap();
         writeln!(
-            PrintkWriter,
+            writer,
             "({}:{})\t {}",
             record.file().unwrap_or(""),
             record.line().unwrap_or(0),
@@ -129,6 +127,16 @@ impl KernelLogger {
         .unwrap();
     }
 
+    /// 把`log`的级别转换为控制台的日志级别
+    fn loglevel(level: Level) -> usize {
+        match level {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        }
+    }
+
     fn kernel_log(record: &log::Record) {
         match record.level() {
             Level::Debug => Logger.log(
@@ -581,6 +581,8 @@ impl<T> Drop for RwLockReadGuard<'_, T> {
     fn drop(&mut self) {
         debug_assert!(self.lock.load(Ordering::Relaxed) & !(WRITER | UPGRADED) > 0);
         self.lock.fetch_sub(READER, Ordering::Release);
+        // 先恢复中断，再减少preempt count，这样才能在需要时被抢占
+        self.irq_guard.take();
         ProcessManager::preempt_enable();
     }
 }
@@ -592,6 +594,7 @@ impl<T> Drop for RwLockUpgradableGuard<'_, T> {
             UPGRADED
         );
         self.inner.lock.fetch_sub(UPGRADED, Ordering::AcqRel);
+        self.irq_guard.take();
         ProcessManager::preempt_enable();
         //这里为啥要AcqRel? Release应该就行了?
     }
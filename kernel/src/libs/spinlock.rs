@@ -9,6 +9,7 @@ use core::sync::atomic::{AtomicBool, Ordering};
 use crate::arch::CurrentIrqArch;
 use crate::exception::{InterruptArch, IrqFlagsGuard};
 use crate::process::ProcessManager;
+use crate::sched::preempt::preempt_check_resched;
 use system_error::SystemError;
 
 /// 实现了守卫的SpinLock, 能够支持内部可变性
@@ -68,6 +69,7 @@ impl<T> SpinLock<T> {
     }
 
     #[inline(always)]
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn lock(&self) -> SpinLockGuard<T> {
         loop {
             let res = self.try_lock();
@@ -89,6 +91,7 @@ impl<T> SpinLock<T> {
         }
     }
 
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn lock_irqsave(&self) -> SpinLockGuard<T> {
         loop {
             if let Ok(guard) = self.try_lock_irqsave() {
@@ -98,6 +101,7 @@ impl<T> SpinLock<T> {
         }
     }
 
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn try_lock(&self) -> Result<SpinLockGuard<T>, SystemError> {
         // 先增加自旋锁持有计数
         ProcessManager::preempt_disable();
@@ -125,6 +129,7 @@ impl<T> SpinLock<T> {
         return res;
     }
 
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn try_lock_irqsave(&self) -> Result<SpinLockGuard<T>, SystemError> {
         let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
         ProcessManager::preempt_disable();
@@ -163,9 +168,10 @@ impl<T> SpinLock<T> {
         self.lock.store(false, Ordering::SeqCst);
     }
 
-    fn unlock(&self) {
+    /// 解锁并减少preempt count，由调用者检查是否需要调度
+    fn unlock_no_resched(&self) {
         self.lock.store(false, Ordering::SeqCst);
-        ProcessManager::preempt_enable();
+        ProcessManager::preempt_enable_no_resched();
     }
 
     pub fn is_locked(&self) -> bool {
@@ -194,11 +200,16 @@ impl<T> Drop for SpinLockGuard<'_, T> {
     fn drop(&mut self) {
         if self.flags.contains(SpinLockGuardFlags::NO_PREEMPT) {
             self.unlock_no_preempt();
-        } else {
-            self.lock.unlock();
+            // restore irq
+            self.irq_flag.take();
+            return;
         }
+
+        self.lock.unlock_no_resched();
         // restore irq
         self.irq_flag.take();
+        // 中断恢复之后才能被抢占，因此最后再检查是否需要调度
+        preempt_check_resched();
     }
 }
 
@@ -9,7 +9,7 @@ use crate::{
     arch::{ipc::signal::Signal, CurrentIrqArch},
     exception::InterruptArch,
     process::{ProcessControlBlock, ProcessManager, ProcessState},
-    sched::{schedule, SchedMode},
+    sched::{preempt::might_sleep_offset, schedule, SchedMode},
 };
 
 use super::{
@@ -17,15 +17,39 @@ use super::{
     spinlock::{SpinLock, SpinLockGuard},
 };
 
+/// 唤醒时每扫描这么多个等待者，就释放一次队列的锁，避免长时间关中断
+const WAITQUEUE_WALK_BREAK_CNT: usize = 64;
+
+/// 等待队列中的一个等待者
+#[derive(Debug)]
+struct Waiter {
+    pcb: Arc<ProcessControlBlock>,
+    /// 独占的等待者：每次唤醒只会唤醒有限个，见[`WaitQueue::wakeup_nr`]
+    exclusive: bool,
+    /// 加入队列时的序号，队列中的等待者按序号递增排列
+    seq: u64,
+}
+
 #[derive(Debug)]
 struct InnerWaitQueue {
     /// 等待队列是否已经死亡, 如果已经死亡, 则不能再添加新的等待进程
     dead: bool,
     /// 等待队列的链表
-    wait_list: VecDeque<Arc<ProcessControlBlock>>,
+    wait_list: VecDeque<Waiter>,
+    /// 下一个等待者的序号
+    next_seq: u64,
 }
 
 /// 被自旋锁保护的等待队列
+///
+/// 等待者分为两种：
+///
+/// - 非独占的等待者：每次唤醒都会被唤醒，适合等待某个状态变化的场景；
+/// - 独占的等待者：每次唤醒最多唤醒指定个数，适合多个进程争抢同一资源的场景（如pipe的读写端），
+///   可以避免“惊群”。
+///
+/// `sleep`系列函数以独占的方式等待，因此[`WaitQueue::wakeup`]每次只唤醒其中一个；
+/// `wq_wait_event_*`宏默认以非独占的方式等待，`_exclusive`后缀的版本以独占的方式等待。
 #[derive(Debug)]
 pub struct WaitQueue {
     inner: SpinLock<InnerWaitQueue>,
@@ -47,19 +71,34 @@ impl WaitQueue {
         self.inner.lock()
     }
 
-    pub fn prepare_to_wait_event(&self, interruptible: bool) -> Result<(), SystemError> {
+    /// 把当前进程加入等待队列，并标记为睡眠，供`wq_wait_event_*`宏使用
+    ///
+    /// ## 参数
+    ///
+    /// - `interruptible`: 是否允许被信号打断
+    /// - `exclusive`: 是否以独占的方式等待
+    pub fn prepare_to_wait_event(
+        &self,
+        interruptible: bool,
+        exclusive: bool,
+    ) -> Result<(), SystemError> {
         let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
         let pcb = ProcessManager::current_pcb();
         if !guard.can_sleep() {
             return Err(SystemError::ESRCH);
         }
         if Signal::signal_pending_state(interruptible, false, &pcb) {
+            // 如果已经被唤醒过，调用者会重新检查条件；否则从队列中移除，
+            // 使得之后的唤醒不会选中这个即将返回错误的独占等待者
+            guard.remove(&pcb);
             return Err(SystemError::ERESTARTSYS);
         } else {
             ProcessManager::mark_sleep(interruptible).unwrap_or_else(|e| {
                 panic!("sleep error: {:?}", e);
             });
-            guard.wait_list.push_back(ProcessManager::current_pcb());
+            if !guard.contains(&pcb) {
+                guard.enqueue(pcb, exclusive);
+            }
             drop(guard);
         }
         Ok(())
@@ -73,12 +112,13 @@ impl WaitQueue {
         writer.set_state(ProcessState::Runnable);
         writer.set_wakeup();
 
-        guard.wait_list.retain(|x| !Arc::ptr_eq(x, &pcb));
+        guard.remove(&pcb);
         drop(guard);
         drop(writer);
     }
 
     /// @brief 让当前进程在等待队列上进行等待，并且，允许被信号打断
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn sleep(&self) -> Result<(), SystemError> {
         before_sleep_check(0);
         let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
@@ -88,7 +128,7 @@ impl WaitQueue {
         ProcessManager::mark_sleep(true).unwrap_or_else(|e| {
             panic!("sleep error: {:?}", e);
         });
-        guard.wait_list.push_back(ProcessManager::current_pcb());
+        guard.enqueue(ProcessManager::current_pcb(), true);
         drop(guard);
         schedule(SchedMode::SM_NONE);
         Ok(())
@@ -102,6 +142,7 @@ impl WaitQueue {
     }
 
     /// @brief 让当前进程在等待队列上进行等待，并且,在释放waitqueue的锁之前，执行f函数闭包
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn sleep_with_func<F>(&self, f: F) -> Result<(), SystemError>
     where
         F: FnOnce(),
@@ -115,7 +156,7 @@ impl WaitQueue {
         ProcessManager::mark_sleep(true).unwrap_or_else(|e| {
             panic!("sleep error: {:?}", e);
         });
-        guard.wait_list.push_back(ProcessManager::current_pcb());
+        guard.enqueue(ProcessManager::current_pcb(), true);
         f();
 
         drop(guard);
@@ -138,6 +179,7 @@ impl WaitQueue {
     ///
     /// 由于sleep_without_schedule不会调用调度函数，因此，如果开发者忘记在执行本函数之后，手动调用调度函数，
     /// 由于时钟中断到来或者‘其他cpu kick了当前cpu’，可能会导致一些未定义的行为。
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub unsafe fn sleep_without_schedule(&self) -> Result<(), SystemError> {
         before_sleep_check(1);
         // 安全检查：确保当前处于中断禁止状态
@@ -149,11 +191,12 @@ impl WaitQueue {
         ProcessManager::mark_sleep(true).unwrap_or_else(|e| {
             panic!("sleep error: {:?}", e);
         });
-        guard.wait_list.push_back(ProcessManager::current_pcb());
+        guard.enqueue(ProcessManager::current_pcb(), true);
         drop(guard);
         Ok(())
     }
 
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub unsafe fn sleep_without_schedule_uninterruptible(&self) -> Result<(), SystemError> {
         before_sleep_check(1);
         // 安全检查：确保当前处于中断禁止状态
@@ -165,11 +208,12 @@ impl WaitQueue {
         ProcessManager::mark_sleep(false).unwrap_or_else(|e| {
             panic!("sleep error: {:?}", e);
         });
-        guard.wait_list.push_back(ProcessManager::current_pcb());
+        guard.enqueue(ProcessManager::current_pcb(), true);
         drop(guard);
         Ok(())
     }
     /// @brief 让当前进程在等待队列上进行等待，并且，不允许被信号打断
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn sleep_uninterruptible(&self) -> Result<(), SystemError> {
         before_sleep_check(0);
         let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
@@ -179,7 +223,7 @@ impl WaitQueue {
         ProcessManager::mark_sleep(false).unwrap_or_else(|e| {
             panic!("sleep error: {:?}", e);
         });
-        guard.wait_list.push_back(ProcessManager::current_pcb());
+        guard.enqueue(ProcessManager::current_pcb(), true);
         drop(guard);
         schedule(SchedMode::SM_NONE);
         Ok(())
@@ -187,6 +231,7 @@ impl WaitQueue {
 
     /// @brief 让当前进程在等待队列上进行等待，并且，允许被信号打断。
     /// 在当前进程的pcb加入队列后，解锁指定的自旋锁。
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn sleep_unlock_spinlock<T>(&self, to_unlock: SpinLockGuard<T>) -> Result<(), SystemError> {
         before_sleep_check(1);
         let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
@@ -196,7 +241,7 @@ impl WaitQueue {
         ProcessManager::mark_sleep(true).unwrap_or_else(|e| {
             panic!("sleep error: {:?}", e);
         });
-        guard.wait_list.push_back(ProcessManager::current_pcb());
+        guard.enqueue(ProcessManager::current_pcb(), true);
         drop(to_unlock);
         drop(guard);
         schedule(SchedMode::SM_NONE);
@@ -205,6 +250,7 @@ impl WaitQueue {
 
     /// @brief 让当前进程在等待队列上进行等待，并且，允许被信号打断。
     /// 在当前进程的pcb加入队列后，解锁指定的Mutex。
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn sleep_unlock_mutex<T>(&self, to_unlock: MutexGuard<T>) -> Result<(), SystemError> {
         before_sleep_check(1);
         let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
@@ -215,7 +261,7 @@ impl WaitQueue {
         ProcessManager::mark_sleep(true).unwrap_or_else(|e| {
             panic!("sleep error: {:?}", e);
         });
-        guard.wait_list.push_back(ProcessManager::current_pcb());
+        guard.enqueue(ProcessManager::current_pcb(), true);
         drop(to_unlock);
         drop(guard);
         schedule(SchedMode::SM_NONE);
@@ -224,6 +270,7 @@ impl WaitQueue {
 
     /// @brief 让当前进程在等待队列上进行等待，并且，不允许被信号打断。
     /// 在当前进程的pcb加入队列后，解锁指定的自旋锁。
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn sleep_uninterruptible_unlock_spinlock<T>(&self, to_unlock: SpinLockGuard<T>) {
         before_sleep_check(1);
         let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
@@ -232,7 +279,7 @@ impl WaitQueue {
             panic!("sleep error: {:?}", e);
         });
         drop(irq_guard);
-        guard.wait_list.push_back(ProcessManager::current_pcb());
+        guard.enqueue(ProcessManager::current_pcb(), true);
         drop(to_unlock);
         drop(guard);
         schedule(SchedMode::SM_NONE);
@@ -240,6 +287,7 @@ impl WaitQueue {
 
     /// @brief 让当前进程在等待队列上进行等待，并且，不允许被信号打断。
     /// 在当前进程的pcb加入队列后，解锁指定的Mutex。
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn sleep_uninterruptible_unlock_mutex<T>(&self, to_unlock: MutexGuard<T>) {
         before_sleep_check(1);
         let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
@@ -249,80 +297,101 @@ impl WaitQueue {
         });
         drop(irq_guard);
 
-        guard.wait_list.push_back(ProcessManager::current_pcb());
+        guard.enqueue(ProcessManager::current_pcb(), true);
 
         drop(to_unlock);
         drop(guard);
         schedule(SchedMode::SM_NONE);
     }
 
-    /// @brief 唤醒在队列中等待的第一个进程。
-    /// 如果这个进程的state与给定的state进行and操作之后，结果不为0,则唤醒它。
+    /// @brief 唤醒队列中所有非独占的等待者，以及第一个独占的等待者。
+    /// `sleep`系列函数都以独占的方式等待，因此对它们来说相当于只唤醒第一个进程。
     ///
-    /// @param state 用于判断的state，如果队列第一个进程与这个state相同，或者为None(表示不进行这个判断)，则唤醒这个进程。
+    /// @param state 用于判断的state，如果进程的state与这个state相同，或者为None(表示不进行这个判断)，则唤醒这个进程。
     ///
     /// @return true 成功唤醒进程
     /// @return false 没有唤醒进程
     pub fn wakeup(&self, state: Option<ProcessState>) -> bool {
-        let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
-        // 如果队列为空，则返回
-        if guard.wait_list.is_empty() {
-            return false;
-        }
-        // 如果队列头部的pcb的state与给定的state相与，结果不为0，则唤醒
-        if let Some(state) = state {
-            if guard
-                .wait_list
-                .front()
-                .unwrap()
-                .sched_info()
-                .inner_lock_read_irqsave()
-                .state()
-                != state
-            {
-                return false;
-            }
-        }
-        let to_wakeup = guard.wait_list.pop_front().unwrap();
-        drop(guard);
-        let res = ProcessManager::wakeup(&to_wakeup).is_ok();
-        return res;
+        self.wakeup_nr(1, state) > 0
     }
 
-    /// @brief 唤醒在队列中，符合条件的所有进程。
+    /// @brief 唤醒在队列中，符合条件的所有进程，不论是否独占。
     ///
     /// @param state 用于判断的state，如果一个进程与这个state相同，或者为None(表示不进行这个判断)，则唤醒这个进程。
     pub fn wakeup_all(&self, state: Option<ProcessState>) {
-        let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
-        // 如果队列为空，则返回
-        if guard.wait_list.is_empty() {
-            return;
-        }
+        self.wakeup_nr(0, state);
+    }
 
-        let mut to_push_back: Vec<Arc<ProcessControlBlock>> = Vec::new();
-        // 如果队列头部的pcb的state与给定的state相与，结果不为0，则唤醒
-        while let Some(to_wakeup) = guard.wait_list.pop_front() {
-            let mut wake = false;
-            if let Some(state) = state {
-                if to_wakeup.sched_info().inner_lock_read_irqsave().state() == state {
-                    wake = true;
+    /// 唤醒队列中所有符合条件的非独占等待者，以及最多`nr_exclusive`个符合条件的独占等待者
+    ///
+    /// 开始唤醒之后才加入队列的进程不会被唤醒。为了避免在等待者很多时长时间关中断，
+    /// 每扫描[`WAITQUEUE_WALK_BREAK_CNT`]个等待者就释放一次锁，记下最后扫描到的序号（书签），
+    /// 重新加锁后从书签处继续。
+    ///
+    /// ## 参数
+    ///
+    /// - `nr_exclusive`: 最多唤醒的独占等待者的个数，为0表示不限制
+    /// - `state`: 用于判断的state，为None表示不进行判断
+    ///
+    /// ## 返回值
+    ///
+    /// 被唤醒的进程的个数
+    pub fn wakeup_nr(&self, nr_exclusive: usize, state: Option<ProcessState>) -> usize {
+        let mut woken = 0;
+        let mut exclusive_left = nr_exclusive;
+        let mut bookmark: Option<u64> = None;
+        let mut limit: Option<u64> = None;
+
+        loop {
+            let mut to_wakeup: Vec<Arc<ProcessControlBlock>> = Vec::new();
+            let mut finished = true;
+
+            let mut guard: SpinLockGuard<InnerWaitQueue> = self.inner_irqsave();
+            let limit = *limit.get_or_insert(guard.next_seq);
+            let mut idx = match bookmark {
+                Some(bookmark) => guard.wait_list.partition_point(|w| w.seq <= bookmark),
+                None => 0,
+            };
+            let mut scanned = 0;
+            while idx < guard.wait_list.len() {
+                let waiter = &guard.wait_list[idx];
+                if waiter.seq >= limit {
+                    break;
                 }
-            } else {
-                wake = true;
-            }
+                if scanned == WAITQUEUE_WALK_BREAK_CNT {
+                    finished = false;
+                    break;
+                }
+                scanned += 1;
+                bookmark = Some(waiter.seq);
 
-            if wake {
-                ProcessManager::wakeup(&to_wakeup).unwrap_or_else(|e| {
-                    error!("wakeup pid: {:?} error: {:?}", to_wakeup.pid(), e);
+                let quota_used = waiter.exclusive && nr_exclusive != 0 && exclusive_left == 0;
+                let state_match = state.map_or(true, |state| {
+                    waiter.pcb.sched_info().inner_lock_read_irqsave().state() == state
                 });
-                continue;
-            } else {
-                to_push_back.push(to_wakeup);
+                if quota_used || !state_match {
+                    idx += 1;
+                    continue;
+                }
+
+                let waiter = guard.wait_list.remove(idx).unwrap();
+                if waiter.exclusive && nr_exclusive != 0 {
+                    exclusive_left -= 1;
+                }
+                to_wakeup.push(waiter.pcb);
             }
-        }
+            drop(guard);
 
-        for to_wakeup in to_push_back {
-            guard.wait_list.push_back(to_wakeup);
+            for pcb in to_wakeup {
+                match ProcessManager::wakeup(&pcb) {
+                    Ok(_) => woken += 1,
+                    Err(e) => error!("wakeup pid: {:?} error: {:?}", pcb.pid(), e),
+                }
+            }
+
+            if finished {
+                return woken;
+            }
         }
     }
 
@@ -336,14 +405,35 @@ impl InnerWaitQueue {
     pub const INIT: InnerWaitQueue = InnerWaitQueue {
         wait_list: VecDeque::new(),
         dead: false,
+        next_seq: 0,
     };
 
     pub fn can_sleep(&self) -> bool {
         return !self.dead;
     }
+
+    fn enqueue(&mut self, pcb: Arc<ProcessControlBlock>, exclusive: bool) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.wait_list.push_back(Waiter {
+            pcb,
+            exclusive,
+            seq,
+        });
+    }
+
+    fn contains(&self, pcb: &Arc<ProcessControlBlock>) -> bool {
+        self.wait_list.iter().any(|w| Arc::ptr_eq(&w.pcb, pcb))
+    }
+
+    fn remove(&mut self, pcb: &Arc<ProcessControlBlock>) {
+        self.wait_list.retain(|w| !Arc::ptr_eq(&w.pcb, pcb));
+    }
 }
 
+#[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
 fn before_sleep_check(max_preempt: usize) {
+    might_sleep_offset(max_preempt);
     let pcb = ProcessManager::current_pcb();
     if unlikely(pcb.preempt_count() > max_preempt) {
         warn!(
@@ -354,10 +444,23 @@ fn before_sleep_check(max_preempt: usize) {
     }
 }
 
+/// 事件等待队列中的一个等待者
+#[derive(Debug)]
+struct EventWaiter {
+    /// 感兴趣的事件
+    events: u64,
+    pcb: Arc<ProcessControlBlock>,
+    /// 独占的等待者：同一次唤醒最多唤醒一个
+    exclusive: bool,
+}
+
 /// 事件等待队列
+///
+/// 与[`WaitQueue`]一样，等待者可以是独占的：一次唤醒会唤醒所有感兴趣的非独占等待者，
+/// 但最多只唤醒一个感兴趣的独占等待者（如多个进程在同一个socket上accept）。
 #[derive(Debug)]
 pub struct EventWaitQueue {
-    wait_list: SpinLock<Vec<(u64, Arc<ProcessControlBlock>)>>,
+    wait_list: SpinLock<Vec<EventWaiter>>,
 }
 
 impl Default for EventWaitQueue {
@@ -380,27 +483,36 @@ impl EventWaitQueue {
     /// - events: 进程感兴趣的事件，events最好是为位表示，一位表示一个事件
     ///
     /// 注意，使用前应该注意有可能其他地方定义了冲突的事件，可能会导致未定义行为
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn sleep(&self, events: u64) {
         before_sleep_check(0);
-        let mut guard = self.wait_list.lock_irqsave();
-        ProcessManager::mark_sleep(true).unwrap_or_else(|e| {
-            panic!("sleep error: {:?}", e);
-        });
-        guard.push((events, ProcessManager::current_pcb()));
-        drop(guard);
+        self.enqueue_current(events, false);
+        schedule(SchedMode::SM_NONE);
+    }
+
+    /// ## 以独占的方式等待感兴趣的事件
+    ///
+    /// 同一次唤醒最多唤醒一个独占的等待者，用于避免多个进程争抢同一资源时的“惊群”
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
+    pub fn sleep_exclusive(&self, events: u64) {
+        before_sleep_check(0);
+        self.enqueue_current(events, true);
         schedule(SchedMode::SM_NONE);
     }
 
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub unsafe fn sleep_without_schedule(&self, events: u64) {
         before_sleep_check(1);
-        let mut guard = self.wait_list.lock_irqsave();
-        ProcessManager::mark_sleep(true).unwrap_or_else(|e| {
-            panic!("sleep error: {:?}", e);
-        });
-        guard.push((events, ProcessManager::current_pcb()));
-        drop(guard);
+        self.enqueue_current(events, false);
+    }
+
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
+    pub unsafe fn sleep_without_schedule_exclusive(&self, events: u64) {
+        before_sleep_check(1);
+        self.enqueue_current(events, true);
     }
 
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn sleep_unlock_spinlock<T>(&self, events: u64, to_unlock: SpinLockGuard<T>) {
         before_sleep_check(1);
         let mut guard = self.wait_list.lock_irqsave();
@@ -409,12 +521,29 @@ impl EventWaitQueue {
             panic!("sleep error: {:?}", e);
         });
         drop(irq_guard);
-        guard.push((events, ProcessManager::current_pcb()));
+        guard.push(EventWaiter {
+            events,
+            pcb: ProcessManager::current_pcb(),
+            exclusive: false,
+        });
         drop(to_unlock);
         drop(guard);
         schedule(SchedMode::SM_NONE);
     }
 
+    fn enqueue_current(&self, events: u64, exclusive: bool) {
+        let mut guard = self.wait_list.lock_irqsave();
+        ProcessManager::mark_sleep(true).unwrap_or_else(|e| {
+            panic!("sleep error: {:?}", e);
+        });
+        guard.push(EventWaiter {
+            events,
+            pcb: ProcessManager::current_pcb(),
+            exclusive,
+        });
+        drop(guard);
+    }
+
     /// ### 唤醒该队列上等待events的进程
     ///
     ///  ### 参数
@@ -422,23 +551,7 @@ impl EventWaitQueue {
     ///
     /// 需要注意的是，只要触发了events中的任意一件事件，进程都会被唤醒
     pub fn wakeup_any(&self, events: u64) -> usize {
-        let mut ret = 0;
-
-        let mut wq_guard = self.wait_list.lock_irqsave();
-        wq_guard.retain(|(es, pcb)| {
-            if *es & events > 0 {
-                // 有感兴趣的事件
-                if ProcessManager::wakeup(pcb).is_ok() {
-                    ret += 1;
-                    return false;
-                } else {
-                    return true;
-                }
-            } else {
-                return true;
-            }
-        });
-        ret
+        self.wakeup_matching(|es| es & events > 0, 1)
     }
 
     /// ### 唤醒该队列上等待events的进程
@@ -448,25 +561,42 @@ impl EventWaitQueue {
     ///
     /// 需要注意的是，只有满足所有事件的进程才会被唤醒
     pub fn wakeup(&self, events: u64) -> usize {
+        self.wakeup_matching(|es| es == events, 1)
+    }
+
+    /// 唤醒所有等待者，不论是否独占
+    pub fn wakeup_all(&self) {
+        self.wakeup_matching(|_| true, 0);
+    }
+
+    /// 唤醒所有感兴趣的非独占等待者，以及最多`nr_exclusive`个（为0表示不限制）感兴趣的独占等待者
+    fn wakeup_matching<F>(&self, interested: F, nr_exclusive: usize) -> usize
+    where
+        F: Fn(u64) -> bool,
+    {
         let mut ret = 0;
+        let mut exclusive_left = nr_exclusive;
+
         let mut wq_guard = self.wait_list.lock_irqsave();
-        wq_guard.retain(|(es, pcb)| {
-            if *es == events {
-                // 有感兴趣的事件
-                if ProcessManager::wakeup(pcb).is_ok() {
-                    ret += 1;
-                    return false;
-                } else {
-                    return true;
+        wq_guard.retain(|waiter| {
+            if !interested(waiter.events) {
+                return true;
+            }
+            let counted = waiter.exclusive && nr_exclusive != 0;
+            if counted && exclusive_left == 0 {
+                return true;
+            }
+            // 有感兴趣的事件
+            if ProcessManager::wakeup(&waiter.pcb).is_ok() {
+                ret += 1;
+                if counted {
+                    exclusive_left -= 1;
                 }
+                return false;
             } else {
                 return true;
             }
         });
         ret
     }
-
-    pub fn wakeup_all(&self) {
-        self.wakeup_any(u64::MAX);
-    }
 }
@@ -0,0 +1,276 @@
+//! 工作队列：把工作推迟到内核线程中执行
+//!
+//! 每个工作队列有一个工作线程，按照加入的顺序执行队列中的工作。工作可以在中断上下文中加入，
+//! 在进程上下文中执行，因此工作函数可以睡眠。
+//!
+//! 延迟工作（[`DelayedWork`]）先启动一个定时器，到期之后再加入工作队列。定时器可以设置松弛时间，
+//! 使得到期时刻相近的延迟工作一起被唤醒（见`time::timer`）。需要周期性执行的工作（如链路检测、
+//! 回写、LED闪烁）可以在工作函数中重新加入自己，而不必各自创建一个内核线程。
+//!
+//! 系统默认的工作队列通过[`schedule_work`]和[`schedule_delayed_work`]使用。
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    string::{String, ToString},
+    sync::Arc,
+};
+use core::{
+    fmt::Debug,
+    intrinsics::unlikely,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use log::error;
+use system_error::SystemError;
+use unified_init::macros::unified_init;
+
+use crate::{
+    init::initcall::INITCALL_CORE,
+    libs::{lazy_init::Lazy, spinlock::SpinLock, wait_queue::WaitQueue},
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
+    time::timer::{clock, Timer, TimerFunction},
+};
+
+/// 工作要执行的函数的特征
+///
+/// 与定时器函数不同，工作可以被多次加入队列，因此函数不会在执行之后被消耗
+pub trait WorkFunction: Send + Sync + Debug {
+    fn run(&self) -> Result<(), SystemError>;
+}
+
+#[derive(Debug)]
+pub struct Work {
+    func: Box<dyn WorkFunction>,
+    /// 工作是否已经在队列中（或者作为延迟工作等待定时器到期）
+    pending: AtomicBool,
+}
+
+impl Work {
+    pub fn new(func: Box<dyn WorkFunction>) -> Arc<Self> {
+        Arc::new(Self {
+            func,
+            pending: AtomicBool::new(false),
+        })
+    }
+
+    /// 工作是否正在等待执行
+    #[inline]
+    pub fn pending(&self) -> bool {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// 标记为等待执行，已经在等待时返回false
+    #[inline]
+    fn try_set_pending(&self) -> bool {
+        self.pending
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    fn run(&self) {
+        // 先清除标记，这样工作函数可以重新加入自己
+        self.pending.store(false, Ordering::SeqCst);
+        let r = self.func.run();
+        if unlikely(r.is_err()) {
+            error!(
+                "Failed to run work function: {:?} {:?}",
+                self.func,
+                r.as_ref().err().unwrap()
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct WorkQueue {
+    name: String,
+    queue: SpinLock<VecDeque<Arc<Work>>>,
+    /// 工作线程在这里等待新的工作
+    wait_queue: WaitQueue,
+}
+
+impl WorkQueue {
+    /// 创建一个工作队列，并启动它的工作线程
+    pub fn new(name: &str) -> Result<Arc<Self>, SystemError> {
+        let wq = Arc::new(Self {
+            name: name.to_string(),
+            queue: SpinLock::new(VecDeque::new()),
+            wait_queue: WaitQueue::default(),
+        });
+
+        let worker = wq.clone();
+        let closure =
+            KernelThreadClosure::EmptyClosure((Box::new(move || worker.worker_thread()), ()));
+        KernelThreadMechanism::create_and_run(closure, name.to_string())
+            .ok_or(SystemError::ENOMEM)?;
+        Ok(wq)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 把工作加入队列
+    ///
+    /// ## 返回值
+    ///
+    /// 工作已经在等待执行时返回false
+    pub fn queue_work(&self, work: &Arc<Work>) -> bool {
+        if !work.try_set_pending() {
+            return false;
+        }
+        self.insert_work(work.clone());
+        true
+    }
+
+    /// 把已经标记为等待执行的工作放入队列，并唤醒工作线程
+    fn insert_work(&self, work: Arc<Work>) {
+        self.queue.lock_irqsave().push_back(work);
+        self.wait_queue.wakeup(None);
+    }
+
+    /// 启动一个定时器，在`delay`个jiffies之后把工作加入队列
+    ///
+    /// ## 参数
+    ///
+    /// - `dwork`: 延迟工作
+    /// - `delay`: 延迟的jiffies数，为0时立即加入队列
+    /// - `slack`: 允许额外推迟的jiffies数，用于与其他定时器合并
+    ///
+    /// ## 返回值
+    ///
+    /// 工作已经在等待执行时返回false
+    pub fn queue_delayed_work(
+        self: &Arc<Self>,
+        dwork: &Arc<DelayedWork>,
+        delay: u64,
+        slack: u64,
+    ) -> bool {
+        if delay == 0 {
+            return self.queue_work(&dwork.work);
+        }
+        if !dwork.work.try_set_pending() {
+            return false;
+        }
+
+        let timer = Timer::new(
+            Box::new(DelayedWorkTimerFunc {
+                wq: self.clone(),
+                work: dwork.work.clone(),
+            }),
+            clock() + delay,
+        );
+        timer.set_slack(slack);
+        *dwork.timer.lock_irqsave() = Some((timer.clone(), self.clone()));
+        timer.activate();
+        true
+    }
+
+    /// 把尚未开始执行的工作从队列中移除
+    ///
+    /// ## 返回值
+    ///
+    /// 工作在队列中时返回true
+    pub fn cancel_work(&self, work: &Arc<Work>) -> bool {
+        let mut queue = self.queue.lock_irqsave();
+        let Some(pos) = queue.iter().position(|w| Arc::ptr_eq(w, work)) else {
+            return false;
+        };
+        queue.remove(pos);
+        work.pending.store(false, Ordering::SeqCst);
+        true
+    }
+
+    fn worker_thread(&self) -> i32 {
+        loop {
+            let mut queue = self.queue.lock_irqsave();
+            let Some(work) = queue.pop_front() else {
+                // 在释放队列的锁之前进入等待队列，避免错过唤醒
+                self.wait_queue.sleep_uninterruptible_unlock_spinlock(queue);
+                continue;
+            };
+            drop(queue);
+            work.run();
+        }
+    }
+}
+
+/// 延迟执行的工作
+#[derive(Debug)]
+pub struct DelayedWork {
+    work: Arc<Work>,
+    /// 尚未到期的定时器，以及定时器到期后工作要加入的队列
+    timer: SpinLock<Option<(Arc<Timer>, Arc<WorkQueue>)>>,
+}
+
+impl DelayedWork {
+    pub fn new(func: Box<dyn WorkFunction>) -> Arc<Self> {
+        Arc::new(Self {
+            work: Work::new(func),
+            timer: SpinLock::new(None),
+        })
+    }
+
+    pub fn work(&self) -> &Arc<Work> {
+        &self.work
+    }
+
+    /// 取消延迟工作
+    ///
+    /// 定时器尚未到期时直接取消定时器，已经到期时把工作从队列中移除。正在执行的工作不会被打断
+    ///
+    /// ## 返回值
+    ///
+    /// 工作在执行之前被取消时返回true
+    pub fn cancel(&self) -> bool {
+        let Some((timer, wq)) = self.timer.lock_irqsave().take() else {
+            return false;
+        };
+        timer.cancel();
+        if !timer.timeout() {
+            self.work.pending.store(false, Ordering::SeqCst);
+            return true;
+        }
+        wq.cancel_work(&self.work)
+    }
+}
+
+/// 延迟工作的定时器到期时，把工作加入队列
+#[derive(Debug)]
+struct DelayedWorkTimerFunc {
+    wq: Arc<WorkQueue>,
+    work: Arc<Work>,
+}
+
+impl TimerFunction for DelayedWorkTimerFunc {
+    fn run(&mut self) -> Result<(), SystemError> {
+        self.wq.insert_work(self.work.clone());
+        Ok(())
+    }
+}
+
+/// 系统默认的工作队列
+static SYSTEM_WQ: Lazy<Arc<WorkQueue>> = Lazy::new();
+
+#[inline]
+pub fn system_wq() -> &'static Arc<WorkQueue> {
+    SYSTEM_WQ.get()
+}
+
+/// 把工作加入系统默认的工作队列，工作已经在等待执行时返回false
+pub fn schedule_work(work: &Arc<Work>) -> bool {
+    system_wq().queue_work(work)
+}
+
+/// 在`delay`个jiffies之后把工作加入系统默认的工作队列，允许额外推迟`slack`个jiffies
+///
+/// 工作已经在等待执行时返回false
+pub fn schedule_delayed_work(dwork: &Arc<DelayedWork>, delay: u64, slack: u64) -> bool {
+    system_wq().queue_delayed_work(dwork, delay, slack)
+}
+
+#[unified_init(INITCALL_CORE)]
+fn workqueue_init() -> Result<(), SystemError> {
+    SYSTEM_WQ.init(WorkQueue::new("events")?);
+    Ok(())
+}
@@ -1,12 +1,12 @@
 use crate::{
-    driver::base::{kobject::KObject, kset::KSet},
+    driver::base::{kobject::KObject, kset::KSet, uevent::uevent_seqnum},
     filesystem::{
-        sysfs::{sysfs_instance, Attribute, AttributeGroup},
+        sysfs::{file::sysfs_emit_str, sysfs_instance, Attribute, AttributeGroup, SysFSOpsSupport},
         vfs::syscall::ModeType,
     },
     init::initcall::INITCALL_CORE,
 };
-use alloc::{string::ToString, sync::Arc};
+use alloc::{format, string::ToString, sync::Arc};
 use log::error;
 use system_error::SystemError;
 use unified_init::macros::unified_init;
@@ -51,7 +51,7 @@ impl AttributeGroup for KernelAttrGroup {
     }
 
     fn attrs(&self) -> &[&'static dyn Attribute] {
-        &[]
+        &[&AttrUeventSeqnum]
     }
 
     fn is_visible(
@@ -62,3 +62,25 @@ impl AttributeGroup for KernelAttrGroup {
         Some(attr.mode())
     }
 }
+
+/// `/sys/kernel/uevent_seqnum`：最近一次发出的uevent的序号
+#[derive(Debug)]
+struct AttrUeventSeqnum;
+
+impl Attribute for AttrUeventSeqnum {
+    fn name(&self) -> &str {
+        "uevent_seqnum"
+    }
+
+    fn mode(&self) -> ModeType {
+        ModeType::S_IRUGO
+    }
+
+    fn support(&self) -> SysFSOpsSupport {
+        SysFSOpsSupport::ATTR_SHOW
+    }
+
+    fn show(&self, _kobj: Arc<dyn KObject>, buf: &mut [u8]) -> Result<usize, SystemError> {
+        sysfs_emit_str(buf, &format!("{}\n", uevent_seqnum()))
+    }
+}
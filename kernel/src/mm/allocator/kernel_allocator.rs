@@ -2,7 +2,7 @@ use klog_types::{AllocLogItem, LogSource};
 
 use crate::{
     arch::mm::LockedFrameAllocator,
-    debug::klog::mm::mm_debug_log,
+    debug::{fault_inject::FAIL_KMALLOC, klog::mm::mm_debug_log},
     libs::align::page_align_up,
     mm::{MMArch, MemoryManagementArch, VirtAddr},
 };
@@ -106,6 +106,10 @@ impl LocalAlloc for KernelAllocator {
 /// 为内核slab分配器实现GlobalAlloc特性
 unsafe impl GlobalAlloc for KernelAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // 这里返回空指针时，大部分调用者会panic，所以FAIL_KMALLOC只在调用栈匹配过滤条件时注入
+        if unlikely(FAIL_KMALLOC.should_fail(layout.size())) {
+            return core::ptr::null_mut();
+        }
         let r = self.local_alloc_zeroed(layout);
         if allocator_select_condition(layout) {
             alloc_debug_log(klog_types::LogSource::Buddy, layout, r);
@@ -116,6 +120,9 @@ unsafe impl GlobalAlloc for KernelAllocator {
     }
 
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if unlikely(FAIL_KMALLOC.should_fail(layout.size())) {
+            return core::ptr::null_mut();
+        }
         let r = self.local_alloc_zeroed(layout);
         if allocator_select_condition(layout) {
             alloc_debug_log(klog_types::LogSource::Buddy, layout, r);
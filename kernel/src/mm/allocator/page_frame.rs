@@ -5,7 +5,10 @@ use core::{
 
 use crate::{
     arch::{mm::LockedFrameAllocator, MMArch},
-    mm::{MemoryManagementArch, PhysAddr, VirtAddr},
+    mm::{
+        vmstat::{count_vm_events, VmEvent},
+        MemoryManagementArch, PhysAddr, VirtAddr,
+    },
 };
 
 /// @brief 物理页帧的表示
@@ -348,6 +351,7 @@ impl<T: FrameAllocator> FrameAllocator for &mut T {
 /// @param count 请求分配的页帧数量
 pub unsafe fn allocate_page_frames(count: PageFrameCount) -> Option<(PhysAddr, PageFrameCount)> {
     let frame = unsafe { LockedFrameAllocator.allocate(count)? };
+    count_vm_events(VmEvent::PgAlloc, frame.1.data());
     return Some(frame);
 }
 
@@ -359,4 +363,5 @@ pub unsafe fn deallocate_page_frames(frame: PhysPageFrame, count: PageFrameCount
     unsafe {
         LockedFrameAllocator.free(frame.phys_address(), count);
     };
+    count_vm_events(VmEvent::PgFree, count.data());
 }
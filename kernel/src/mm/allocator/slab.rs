@@ -85,6 +85,15 @@ pub unsafe fn slab_usage() -> SlabUsage {
     }
 }
 
+/// 获取slab各个size class的统计信息
+pub unsafe fn slab_info() -> [SlabInfo; ZoneAllocator::MAX_BASE_SIZE_CLASSES] {
+    if let Some(ref mut slab) = SLABALLOCATOR {
+        slab.zone.slab_infos()
+    } else {
+        [SlabInfo::default(); ZoneAllocator::MAX_BASE_SIZE_CLASSES]
+    }
+}
+
 /// 归还slab_page给buddy的回调
 pub struct SlabCallback;
 impl CallBack for SlabCallback {
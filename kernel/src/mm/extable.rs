@@ -0,0 +1,52 @@
+//! 异常表
+//!
+//! 内核中可能因访问用户空间地址而出错的指令，都在`__ex_table`段中登记了一个修复地址。
+//! 缺页异常无法处理时，如果出错的指令登记在异常表中，就让它跳到修复地址继续执行，
+//! 由修复代码向调用者返回错误，而不是让内核panic。
+//!
+//! 表项由各架构的汇编代码（见`arch::mm::extable`）写入，这里只负责查找。
+
+use core::mem::size_of;
+
+/// 异常表的表项
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ExceptionTableEntry {
+    /// 可能出错的指令的地址
+    pub insn: usize,
+    /// 出错后继续执行的地址
+    pub fixup: usize,
+}
+
+extern "C" {
+    fn __start___ex_table();
+    fn __stop___ex_table();
+}
+
+/// 链接脚本收集的所有表项
+fn exception_table() -> &'static [ExceptionTableEntry] {
+    let start = __start___ex_table as usize;
+    let end = __stop___ex_table as usize;
+    unsafe {
+        core::slice::from_raw_parts(
+            start as *const ExceptionTableEntry,
+            (end - start) / size_of::<ExceptionTableEntry>(),
+        )
+    }
+}
+
+/// 查找出错指令的修复地址
+///
+/// ## 参数
+///
+/// - `insn`: 出错的指令的地址
+///
+/// ## 返回值
+///
+/// 指令登记在异常表中时返回修复地址，否则返回None。表项很少，线性查找即可
+pub fn search_exception_table(insn: usize) -> Option<usize> {
+    exception_table()
+        .iter()
+        .find(|entry| entry.insn == insn)
+        .map(|entry| entry.fixup)
+}
@@ -13,6 +13,7 @@ use crate::{
     mm::{
         page::{page_manager_lock_irqsave, EntryFlags},
         ucontext::LockedVMA,
+        vmstat::{count_vm_event, VmEvent},
         VirtAddr, VmFaultReason, VmFlags,
     },
     process::{ProcessManager, ProcessState},
@@ -123,6 +124,7 @@ impl PageFaultHandler {
     /// ## 返回值
     /// - VmFaultReason: 页面错误处理信息标志
     pub unsafe fn handle_mm_fault(mut pfm: PageFaultMessage) -> VmFaultReason {
+        count_vm_event(VmEvent::PgFault);
         let flags = pfm.flags();
         let vma = pfm.vma();
         let current_pcb = ProcessManager::current_pcb();
@@ -359,7 +361,7 @@ impl PageFaultHandler {
         let cache_page = pfm.page.clone().expect("no cache_page in PageFaultMessage");
 
         // 将pagecache页设为脏页，以便回收时能够回写
-        cache_page.write_irqsave().add_flags(PageFlags::PG_DIRTY);
+        cache_page.write_irqsave().mark_dirty();
         ret = ret.union(Self::finish_fault(pfm));
 
         ret
@@ -434,7 +436,7 @@ impl PageFaultHandler {
             entry.set_flags(new_flags);
             table.set_entry(i, entry);
 
-            old_page.write_irqsave().add_flags(PageFlags::PG_DIRTY);
+            old_page.write_irqsave().mark_dirty();
 
             VmFaultReason::VM_FAULT_COMPLETED
         } else if vma.is_anonymous() {
@@ -589,18 +591,26 @@ impl PageFaultHandler {
                     .expect("file_page_offset is none"))
                 << MMArch::PAGE_SHIFT);
 
+        let shared = vma_guard.vm_flags().contains(VmFlags::VM_SHARED);
         for pgoff in start_pgoff..=end_pgoff {
             if let Some(page) = page_cache.lock_irqsave().get_page(pgoff) {
-                let page_guard = page.read_irqsave();
+                let mut page_guard = page.write_irqsave();
                 if page_guard.flags().contains(PageFlags::PG_UPTODATE) {
                     let phys = page.phys_address();
 
                     let address =
                         VirtAddr::new(addr.data() + ((pgoff - start_pgoff) << MMArch::PAGE_SHIFT));
-                    mapper
-                        .map_phys(address, phys, vma_guard.flags())
-                        .unwrap()
-                        .flush();
+                    if mapper.translate(address).is_some() {
+                        continue;
+                    }
+                    // 预读映射的页面没有被写入，共享映射中的干净页同样映射为只读
+                    let mut entry_flags = vma_guard.flags();
+                    if shared && !page_guard.flags().contains(PageFlags::PG_DIRTY) {
+                        entry_flags = entry_flags.set_write(false);
+                    }
+                    mapper.map_phys(address, phys, entry_flags).unwrap().flush();
+                    // 记录映射了该页的VMA，回写时据此重新设置写保护
+                    page_guard.insert_vma(vma.clone());
                 }
             }
         }
@@ -624,15 +634,21 @@ impl PageFaultHandler {
         let mapper = &mut pfm.mapper;
         let mut ret = VmFaultReason::empty();
 
-        if let Some(page) = page_cache.lock_irqsave().get_page(file_pgoff) {
-            // TODO 异步从磁盘中预读页面进PageCache
+        // 根据缺页的访问模式预读后续页面，页面由预读从磁盘读入时同样属于major fault
+        let cached = page_cache.lock_irqsave().get_page(file_pgoff).is_some();
+        file.readahead(file_pgoff * MMArch::PAGE_SIZE, MMArch::PAGE_SIZE);
+        if !cached && page_cache.lock_irqsave().get_page(file_pgoff).is_some() {
+            ret = VmFaultReason::VM_FAULT_MAJOR;
+            count_vm_event(VmEvent::PgMajFault);
+        }
 
+        if let Some(page) = page_cache.lock_irqsave().get_page(file_pgoff) {
             // 直接将PageCache中的页面作为要映射的页面
             pfm.page = Some(page.clone());
         } else {
-            // TODO 同步预读
             //涉及磁盘IO，返回标志为VM_FAULT_MAJOR
             ret = VmFaultReason::VM_FAULT_MAJOR;
+            count_vm_event(VmEvent::PgMajFault);
             // let mut buf: Vec<u8> = vec![0; MMArch::PAGE_SIZE];
 
             let allocator = mapper.allocator_mut();
@@ -697,7 +713,19 @@ impl PageFaultHandler {
 
         let page_phys = page_to_map.phys_address();
 
-        mapper.map_phys(address, page_phys, vma_guard.flags());
+        // 共享文件映射因读操作缺页时映射为只读，第一次写入时通过写保护异常标记脏页
+        let mut entry_flags = vma_guard.flags();
+        if !flags.contains(FaultFlags::FAULT_FLAG_WRITE)
+            && vma_guard.vm_flags().contains(VmFlags::VM_SHARED)
+            && !page_to_map
+                .read_irqsave()
+                .flags()
+                .contains(PageFlags::PG_DIRTY)
+        {
+            entry_flags = entry_flags.set_write(false);
+        }
+
+        mapper.map_phys(address, page_phys, entry_flags);
         page_to_map.write_irqsave().insert_vma(pfm.vma());
         VmFaultReason::VM_FAULT_COMPLETED
     }
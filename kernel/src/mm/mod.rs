@@ -23,6 +23,7 @@ use self::{
 pub mod allocator;
 pub mod c_adapter;
 pub mod early_ioremap;
+pub mod extable;
 pub mod fault;
 pub mod init;
 pub mod kernel_mapper;
@@ -32,8 +33,11 @@ pub mod mmio_buddy;
 pub mod no_init;
 pub mod page;
 pub mod percpu;
+pub mod process_vm;
 pub mod syscall;
 pub mod ucontext;
+pub mod vmstat;
+pub mod writeback;
 
 /// 内核INIT进程的用户地址空间结构体（仅在process_init中初始化）
 static mut __IDLE_PROCESS_ADDRESS_SPACE: Option<Arc<AddressSpace>> = None;
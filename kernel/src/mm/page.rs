@@ -16,8 +16,12 @@ use lru::LruCache;
 
 use crate::{
     arch::{interrupt::ipi::send_ipi, mm::LockedFrameAllocator, MMArch},
+    cgroup::{memory, Cgroup},
     exception::ipi::{IpiKind, IpiTarget},
-    filesystem::{page_cache::PageCache, vfs::FilePrivateData},
+    filesystem::{
+        page_cache::PageCache,
+        vfs::{dcache, FilePrivateData},
+    },
     init::initcall::INITCALL_CORE,
     ipc::shm::ShmId,
     libs::{
@@ -34,6 +38,8 @@ use super::{
     },
     syscall::ProtFlags,
     ucontext::LockedVMA,
+    vmstat::{count_vm_event, VmEvent},
+    writeback::{account_page_cleaned, account_page_dirtied, BackingDevInfo},
     MemoryManagementArch, PageTableKind, PhysAddr, VirtAddr,
 };
 
@@ -224,6 +230,9 @@ pub fn page_reclaimer_init() {
     info!("page_reclaimer_init done");
 }
 
+/// 内存紧张时，每轮从dcache中回收的目录项数量
+const DCACHE_SHRINK_COUNT: usize = 1024;
+
 /// 页面回收线程
 static mut PAGE_RECLAIMER_THREAD: Option<Arc<ProcessControlBlock>> = None;
 
@@ -256,6 +265,8 @@ fn page_reclaim_thread() -> i32 {
         if usage.free().data() < 4096 {
             let page_to_free = 4096;
             page_reclaimer_lock_irqsave().shrink_list(PageFrameCount::new(page_to_free));
+            // 同时回收dcache中最近最少使用的目录项，释放被它们引用的inode
+            dcache::shrink(DCACHE_SHRINK_COUNT);
         } else {
             //TODO 暂时让页面回收线程负责脏页回写任务，后续需要分离
             page_reclaimer_lock_irqsave().flush_dirty_pages();
@@ -316,6 +327,51 @@ impl PageReclaimer {
                 page_cache.lock_irqsave().remove_page(page_index);
                 page_manager_lock_irqsave().remove_page(&paddr);
                 self.remove_page(&paddr);
+                count_vm_event(VmEvent::PgSteal);
+            }
+        }
+    }
+
+    /// # 回收计入`memcg`（包括后代）的页缓存
+    ///
+    /// 只回收干净并且没有被映射的页面，它们不需要回写，也不需要修改页表
+    ///
+    /// ## 参数
+    ///
+    /// - `memcg`: 超出`memory.max`的cgroup
+    /// - `count`: 最多回收的页数
+    pub fn shrink_memcg(&mut self, memcg: &Cgroup, count: usize) {
+        let reclaimable = |guard: &InnerPage| {
+            matches!(guard.page_type(), PageType::File(_))
+                && !guard.flags().contains(PageFlags::PG_DIRTY)
+                && guard.map_count() == 0
+        };
+        // 从最近最少使用的页面开始
+        let victims = self
+            .lru
+            .iter()
+            .rev()
+            .filter(|(_, page)| {
+                let guard = page.read_irqsave();
+                reclaimable(&guard)
+                    && guard
+                        .memcg()
+                        .is_some_and(|c| memory::is_descendant_of(c, memcg))
+            })
+            .take(count)
+            .map(|(paddr, page)| (*paddr, page.clone()))
+            .collect::<Vec<_>>();
+
+        for (paddr, page) in victims {
+            let guard = page.write_irqsave();
+            if !reclaimable(&guard) {
+                continue;
+            }
+            if let PageType::File(info) = guard.page_type() {
+                info.page_cache.lock_irqsave().remove_page(info.index);
+                page_manager_lock_irqsave().remove_page(&paddr);
+                self.remove_page(&paddr);
+                count_vm_event(VmEvent::PgSteal);
             }
         }
     }
@@ -395,7 +451,13 @@ impl PageReclaimer {
             .unwrap();
 
         // 清除标记
-        guard.remove_flags(PageFlags::PG_DIRTY);
+        guard.clear_dirty();
+        count_vm_event(VmEvent::PgWriteback);
+    }
+
+    /// 当前lru中的文件页数量
+    pub fn nr_pages(&self) -> usize {
+        self.lru.len()
     }
 
     /// lru脏页刷新
@@ -409,6 +471,33 @@ impl PageReclaimer {
             }
         }
     }
+
+    /// # 写回属于`bdi`的脏页
+    ///
+    /// ## 参数
+    ///
+    /// - `bdi`: 后备设备
+    /// - `count`: 最多写回的页数
+    ///
+    /// ## 返回值
+    /// 写回的页数
+    pub fn writeback_bdi(&mut self, bdi: &Arc<BackingDevInfo>, count: usize) -> usize {
+        let mut written = 0;
+        for (_paddr, page) in self.lru.iter() {
+            if written >= count {
+                break;
+            }
+            let mut guard = page.write_irqsave();
+            if !guard.flags().contains(PageFlags::PG_DIRTY) {
+                continue;
+            }
+            if guard.bdi().is_some_and(|b| Arc::ptr_eq(&b, bdi)) {
+                Self::page_writeback(&mut guard, false);
+                written += 1;
+            }
+        }
+        written
+    }
 }
 
 bitflags! {
@@ -521,16 +610,23 @@ pub struct InnerPage {
     phys_addr: PhysAddr,
     /// 页面类型
     page_type: PageType,
+    /// 页面计入的memory cgroup
+    memcg: Option<Arc<Cgroup>>,
 }
 
 impl InnerPage {
     pub fn new(phys_addr: PhysAddr, page_type: PageType, flags: PageFlags) -> Self {
-        Self {
+        let page = Self {
             vma_set: HashSet::new(),
             flags,
             phys_addr,
             page_type,
+            memcg: memory::charge_current(),
+        };
+        if flags.contains(PageFlags::PG_DIRTY) {
+            account_page_dirtied(page.bdi().as_deref());
         }
+        page
     }
 
     /// 将vma加入anon_vma
@@ -559,6 +655,11 @@ impl InnerPage {
         }
     }
 
+    /// 页面缓存所属的后备设备
+    pub fn bdi(&self) -> Option<Arc<BackingDevInfo>> {
+        self.page_cache()?.bdi()
+    }
+
     pub fn page_type(&self) -> &PageType {
         &self.page_type
     }
@@ -567,6 +668,11 @@ impl InnerPage {
         self.page_type = page_type;
     }
 
+    /// 页面计入的memory cgroup，内核线程创建的页面不计费
+    pub fn memcg(&self) -> Option<&Arc<Cgroup>> {
+        self.memcg.as_ref()
+    }
+
     #[inline(always)]
     pub fn vma_set(&self) -> &HashSet<Arc<LockedVMA>> {
         &self.vma_set
@@ -597,6 +703,22 @@ impl InnerPage {
         self.flags = self.flags.difference(flags);
     }
 
+    /// 把页面标记为脏页，并计入脏页统计
+    pub fn mark_dirty(&mut self) {
+        if !self.flags.contains(PageFlags::PG_DIRTY) {
+            self.flags.insert(PageFlags::PG_DIRTY);
+            account_page_dirtied(self.bdi().as_deref());
+        }
+    }
+
+    /// 清除脏页标记，并从脏页统计中减去
+    pub fn clear_dirty(&mut self) {
+        if self.flags.contains(PageFlags::PG_DIRTY) {
+            self.flags.remove(PageFlags::PG_DIRTY);
+            account_page_cleaned(self.bdi().as_deref());
+        }
+    }
+
     #[inline(always)]
     fn phys_address(&self) -> PhysAddr {
         self.phys_addr
@@ -652,6 +774,11 @@ impl Drop for InnerPage {
             self.map_count() == 0,
             "page drop when map count is non-zero"
         );
+        // 没有写回就被释放的脏页（如文件被截断）不再计入脏页统计
+        self.clear_dirty();
+        if let Some(memcg) = self.memcg.take() {
+            memory::uncharge(&memcg);
+        }
 
         unsafe {
             deallocate_page_frames(PhysPageFrame::new(self.phys_addr), PageFrameCount::new(1))
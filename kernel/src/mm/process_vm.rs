@@ -0,0 +1,258 @@
+//! process_vm_readv / process_vm_writev：在当前进程与另一个进程的地址空间之间直接拷贝数据
+//!
+//! 目标进程的内存通过它的页表找到物理页，再经由内核的线性映射访问。
+//! 目标页还没有被映射（或者写入时需要写时拷贝）时，以远程缺页的方式为目标进程处理缺页。
+//!
+//! 参考 https://man7.org/linux/man-pages/man2/process_vm_readv.2.html
+
+use alloc::{sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    arch::MMArch,
+    filesystem::vfs::syscall::{IoVec, IoVecs},
+    process::{cred::CAPFlags, Pid, ProcessControlBlock, ProcessManager},
+    syscall::{user_access::UserBufferReader, Syscall},
+};
+
+use super::{
+    fault::{FaultFlags, PageFaultHandler, PageFaultMessage},
+    ucontext::{AddressSpace, InnerAddressSpace},
+    MemoryManagementArch, VirtAddr, VmFaultReason, VmFlags,
+};
+
+/// 一次系统调用中最多的iovec数量
+const UIO_MAXIOV: usize = 1024;
+
+/// # 检查当前进程能否访问目标进程的内存
+///
+/// 与Linux的`ptrace_may_access`相同：当前进程的实际uid、gid必须与目标进程的实际、有效、保存的uid、gid都相同，
+/// 否则需要CAP_SYS_PTRACE
+pub fn may_access(target: &Arc<ProcessControlBlock>) -> bool {
+    let cred = ProcessManager::current_pcb().cred();
+    let tcred = target.cred();
+    let same_user = cred.uid == tcred.uid
+        && cred.uid == tcred.euid
+        && cred.uid == tcred.suid
+        && cred.gid == tcred.gid
+        && cred.gid == tcred.egid
+        && cred.gid == tcred.sgid;
+    same_user || cred.has_capability(CAPFlags::CAP_SYS_PTRACE)
+}
+
+/// # 在目标地址空间中找到`addr`所在的物理页，返回它在内核中的虚拟地址
+///
+/// 页面不存在，或者写入时页面不可写，则为目标进程处理缺页。
+///
+/// 返回的地址只在调用者持有`guard`期间有效：munmap、写时拷贝等释放页面的操作都需要持有地址空间的写锁
+fn remote_page(
+    guard: &mut InnerAddressSpace,
+    addr: VirtAddr,
+    write: bool,
+) -> Result<VirtAddr, SystemError> {
+    let vma = guard.mappings.contains(addr).ok_or(SystemError::EFAULT)?;
+    let vm_flags = *vma.lock_irqsave().vm_flags();
+    let required = if write {
+        VmFlags::VM_WRITE
+    } else {
+        VmFlags::VM_READ
+    };
+    if !vm_flags.contains(required) || vm_flags.intersects(VmFlags::VM_IO | VmFlags::VM_PFNMAP) {
+        return Err(SystemError::EFAULT);
+    }
+
+    let mut faulted = false;
+    loop {
+        if let Some((paddr, flags)) = guard.user_mapper.utable.translate(addr) {
+            if flags.present() && (!write || flags.has_write()) {
+                let kaddr = unsafe { MMArch::phys_2_virt(paddr) }.ok_or(SystemError::EFAULT)?;
+                return Ok(kaddr);
+            }
+        }
+        if faulted {
+            return Err(SystemError::EFAULT);
+        }
+
+        let mut fault_flags = FaultFlags::FAULT_FLAG_REMOTE;
+        if write {
+            fault_flags |= FaultFlags::FAULT_FLAG_WRITE;
+        }
+        let message = PageFaultMessage::new(
+            vma.clone(),
+            addr,
+            fault_flags,
+            &mut guard.user_mapper.utable,
+        );
+        let fault = unsafe { PageFaultHandler::handle_mm_fault(message) };
+        if fault.intersects(VmFaultReason::VM_FAULT_ERROR) {
+            return Err(SystemError::EFAULT);
+        }
+        faulted = true;
+    }
+}
+
+/// # 在`buf`与目标地址空间的`[addr, addr + buf.len())`之间拷贝数据
+///
+/// ## 返回值
+/// - `Ok(usize)`: 拷贝的字节数。遇到无法访问的页面时停止，此时小于`buf.len()`
+/// - `Err(SystemError::EFAULT)`: 第一个页面就无法访问
+fn access_remote(
+    vm: &Arc<AddressSpace>,
+    addr: VirtAddr,
+    buf: &mut [u8],
+    write: bool,
+) -> Result<usize, SystemError> {
+    let mut done = 0;
+    while done < buf.len() {
+        let cur = addr + done;
+        let page_offset = cur.data() & (MMArch::PAGE_SIZE - 1);
+        let len = (MMArch::PAGE_SIZE - page_offset).min(buf.len() - done);
+
+        // 拷贝期间一直持有目标地址空间的锁，防止页面被目标进程并发地释放
+        let mut guard = vm.write();
+        let page = match remote_page(&mut guard, cur - page_offset, write) {
+            Ok(page) => page,
+            Err(e) if done == 0 => return Err(e),
+            Err(_) => break,
+        };
+        let kaddr = (page + page_offset).data() as *mut u8;
+        unsafe {
+            if write {
+                core::ptr::copy_nonoverlapping(buf[done..].as_ptr(), kaddr, len);
+            } else {
+                core::ptr::copy_nonoverlapping(kaddr, buf[done..].as_mut_ptr(), len);
+            }
+        }
+        drop(guard);
+        done += len;
+    }
+    return Ok(done);
+}
+
+/// # process_vm_readv与process_vm_writev的公共实现
+///
+/// `buf`为当前进程一侧的数据，依次与目标进程的各个iovec交换数据，直到`buf`用完、
+/// 目标iovec用完，或者遇到无法访问的地址
+///
+/// ## 返回值
+/// - `Ok(usize)`: 传输的字节数
+/// - `Err(SystemError)`: 一个字节都没有传输时的错误
+fn process_vm_rw(
+    pid: Pid,
+    buf: &mut [u8],
+    rvec: *const IoVec,
+    riovcnt: usize,
+    write: bool,
+) -> Result<usize, SystemError> {
+    let reader = UserBufferReader::new(rvec, riovcnt * core::mem::size_of::<IoVec>(), true)?;
+    let riovs: Vec<IoVec> = reader.read_from_user::<IoVec>(0)?.to_vec();
+
+    let target = ProcessManager::find(pid).ok_or(SystemError::ESRCH)?;
+    if !may_access(&target) {
+        return Err(SystemError::EPERM);
+    }
+    let vm = target.basic().user_vm().ok_or(SystemError::ESRCH)?;
+
+    let mut done = 0;
+    for riov in riovs.iter() {
+        if done == buf.len() {
+            break;
+        }
+        let len = riov.iov_len.min(buf.len() - done);
+        if len == 0 {
+            continue;
+        }
+        let addr = VirtAddr::new(riov.iov_base as usize);
+        if addr
+            .data()
+            .checked_add(len)
+            .map_or(true, |end| end > MMArch::USER_END_VADDR.data())
+        {
+            if done == 0 {
+                return Err(SystemError::EFAULT);
+            }
+            break;
+        }
+        match access_remote(&vm, addr, &mut buf[done..done + len], write) {
+            Ok(n) => {
+                done += n;
+                if n < len {
+                    break;
+                }
+            }
+            Err(e) if done == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    return Ok(done);
+}
+
+/// 检查iovec的数量与标志位
+fn check_args(liovcnt: usize, riovcnt: usize, flags: usize) -> Result<(), SystemError> {
+    if flags != 0 || liovcnt > UIO_MAXIOV || riovcnt > UIO_MAXIOV {
+        return Err(SystemError::EINVAL);
+    }
+    return Ok(());
+}
+
+impl Syscall {
+    /// # 从另一个进程的内存中读取数据
+    ///
+    /// ## 参数
+    /// - `pid`: 目标进程
+    /// - `lvec`, `liovcnt`: 当前进程中用于存放数据的iovec
+    /// - `rvec`, `riovcnt`: 目标进程中要读取的iovec
+    /// - `flags`: 保留，必须为0
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 读取的字节数，可能因为目标进程的某个地址无法访问而小于请求的长度
+    /// - `Err(SystemError::EPERM)`: 没有访问目标进程内存的权限
+    /// - `Err(SystemError::ESRCH)`: 目标进程不存在
+    pub fn process_vm_readv(
+        pid: Pid,
+        lvec: *const IoVec,
+        liovcnt: usize,
+        rvec: *const IoVec,
+        riovcnt: usize,
+        flags: usize,
+    ) -> Result<usize, SystemError> {
+        check_args(liovcnt, riovcnt, flags)?;
+        if liovcnt == 0 || riovcnt == 0 {
+            return Ok(0);
+        }
+        let mut iovecs = unsafe { IoVecs::from_user(lvec, liovcnt, true) }?;
+        let mut buf = iovecs.new_buf(true);
+        let n = process_vm_rw(pid, &mut buf, rvec, riovcnt, false)?;
+        iovecs.scatter(&buf[..n]);
+        return Ok(n);
+    }
+
+    /// # 向另一个进程的内存中写入数据
+    ///
+    /// ## 参数
+    /// - `pid`: 目标进程
+    /// - `lvec`, `liovcnt`: 当前进程中要写入的数据
+    /// - `rvec`, `riovcnt`: 目标进程中被写入的iovec
+    /// - `flags`: 保留，必须为0
+    ///
+    /// ## 返回值
+    /// - `Ok(usize)`: 写入的字节数，可能因为目标进程的某个地址无法访问而小于请求的长度
+    /// - `Err(SystemError::EPERM)`: 没有访问目标进程内存的权限
+    /// - `Err(SystemError::ESRCH)`: 目标进程不存在
+    pub fn process_vm_writev(
+        pid: Pid,
+        lvec: *const IoVec,
+        liovcnt: usize,
+        rvec: *const IoVec,
+        riovcnt: usize,
+        flags: usize,
+    ) -> Result<usize, SystemError> {
+        check_args(liovcnt, riovcnt, flags)?;
+        if liovcnt == 0 || riovcnt == 0 {
+            return Ok(0);
+        }
+        let iovecs = unsafe { IoVecs::from_user(lvec, liovcnt, false) }?;
+        let mut buf = iovecs.gather();
+        return process_vm_rw(pid, &mut buf, rvec, riovcnt, true);
+    }
+}
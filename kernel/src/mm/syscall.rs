@@ -1,12 +1,12 @@
-use core::{intrinsics::unlikely, slice::from_raw_parts};
+use core::intrinsics::unlikely;
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 use log::error;
 use system_error::SystemError;
 
 use crate::{
     arch::MMArch,
-    driver::base::block::SeekFrom,
+    filesystem::vfs::file::File,
     ipc::shm::ShmFlags,
     libs::align::{check_aligned, page_align_up},
     mm::MemoryManagementArch,
@@ -15,6 +15,7 @@ use crate::{
 
 use super::{
     allocator::page_frame::{PageFrameCount, VirtPageFrame},
+    page::{PageFlags, PageReclaimer},
     ucontext::{AddressSpace, DEFAULT_MMAP_MIN_ADDR},
     verify_area, MsFlags, VirtAddr, VmFlags,
 };
@@ -545,9 +546,12 @@ impl Syscall {
     ///
     /// ## 参数
     ///
-    /// - `start`：起始地址(已经对齐到页)
+    /// - `start`：起始地址，必须对齐到页
     /// - `len`：长度(已经对齐到页)
     /// - `flags`：标志
+    ///
+    /// 共享文件映射中被写入的页面会被标记为脏页，MS_SYNC把范围内的脏页写回文件后才返回。
+    /// 脏页会由回写线程定期写回，因此MS_ASYNC不需要额外的操作
     pub fn msync(start: VirtAddr, len: usize, flags: usize) -> Result<usize, SystemError> {
         if !start.check_aligned(MMArch::PAGE_SIZE) || !check_aligned(len, MMArch::PAGE_SIZE) {
             return Err(SystemError::EINVAL);
@@ -556,19 +560,12 @@ impl Syscall {
         if unlikely(verify_area(start, len).is_err()) {
             return Err(SystemError::EINVAL);
         }
-        if unlikely(len == 0) {
-            return Err(SystemError::EINVAL);
-        }
 
         let mut start = start.data();
         let end = start + len;
-        let flags = MsFlags::from_bits_truncate(flags);
+        let flags = MsFlags::from_bits(flags).ok_or(SystemError::EINVAL)?;
         let mut unmapped_error = Ok(0);
 
-        if !flags.intersects(MsFlags::MS_ASYNC | MsFlags::MS_INVALIDATE | MsFlags::MS_SYNC) {
-            return Err(SystemError::EINVAL);
-        }
-
         if flags.contains(MsFlags::MS_ASYNC | MsFlags::MS_SYNC) {
             return Err(SystemError::EINVAL);
         }
@@ -597,7 +594,7 @@ impl Syscall {
                         break;
                     }
                     start = vm_start;
-                    if start >= vm_end {
+                    if start >= end {
                         break;
                     }
                     unmapped_error = Err(SystemError::ENOMEM);
@@ -608,47 +605,59 @@ impl Syscall {
                     break;
                 }
                 let file = guard.vm_file();
-                let fstart = (start - vm_start)
-                    + (guard.file_page_offset().unwrap_or(0) << MMArch::PAGE_SHIFT);
-                let fend = fstart + (core::cmp::min(end, vm_end) - start) - 1;
-                let old_start = start;
+                let file_pgoff = guard.file_page_offset().unwrap_or(0);
+                let start_index = ((start - vm_start) >> MMArch::PAGE_SHIFT) + file_pgoff;
+                let end_index =
+                    ((core::cmp::min(end, vm_end) - vm_start) >> MMArch::PAGE_SHIFT) + file_pgoff;
+                drop(guard);
                 start = vm_end;
-                // log::info!("flags: {:?}", flags);
-                // log::info!("vm_flags: {:?}", vm_flags);
-                // log::info!("file: {:?}", file);
                 if flags.contains(MsFlags::MS_SYNC) && vm_flags.contains(VmFlags::VM_SHARED) {
                     if let Some(file) = file {
-                        let old_pos = file.lseek(SeekFrom::SeekCurrent(0)).unwrap();
-                        file.lseek(SeekFrom::SeekSet(fstart as i64)).unwrap();
-                        err = file.write(len, unsafe {
-                            from_raw_parts(old_start as *mut u8, fend - fstart + 1)
-                        });
-                        file.lseek(SeekFrom::SeekSet(old_pos as i64)).unwrap();
+                        err = Self::msync_file_range(&file, start_index, end_index);
                         if err.is_err() {
                             break;
-                        } else if start >= end {
-                            err = unmapped_error;
-                            break;
                         }
-                        next_vma = current_address_space
-                            .read()
-                            .mappings
-                            .find_nearest(VirtAddr::new(start));
-                    }
-                } else {
-                    if start >= end {
-                        err = unmapped_error;
-                        break;
                     }
-                    next_vma = current_address_space
-                        .read()
-                        .mappings
-                        .find_nearest(VirtAddr::new(vm_end));
                 }
+                if start >= end {
+                    err = unmapped_error;
+                    break;
+                }
+                next_vma = current_address_space
+                    .read()
+                    .mappings
+                    .find_nearest(VirtAddr::new(start));
             } else {
                 return Err(SystemError::ENOMEM);
             }
         }
         return err;
     }
+
+    /// 写回共享文件映射中文件页号在[start_index, end_index)范围内的脏页
+    ///
+    /// 写回时会重新设置页面的写保护，之后的写入会再次把页面标记为脏页
+    fn msync_file_range(
+        file: &Arc<File>,
+        start_index: usize,
+        end_index: usize,
+    ) -> Result<usize, SystemError> {
+        let inode = file.inode();
+        if let Some(page_cache) = inode.page_cache() {
+            let pages = {
+                let guard = page_cache.lock_irqsave();
+                (start_index..end_index)
+                    .filter_map(|index| guard.get_page(index))
+                    .collect::<Vec<_>>()
+            };
+            for page in pages {
+                let mut guard = page.write_irqsave();
+                if guard.flags().contains(PageFlags::PG_DIRTY) {
+                    PageReclaimer::page_writeback(&mut guard, false);
+                }
+            }
+        }
+        inode.sync()?;
+        return Ok(0);
+    }
 }
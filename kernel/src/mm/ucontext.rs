@@ -36,7 +36,7 @@ use super::{
     allocator::page_frame::{
         deallocate_page_frames, PageFrameCount, PhysPageFrame, VirtPageFrame, VirtPageFrameIter,
     },
-    page::{EntryFlags, Flusher, InactiveFlusher, PageFlushAll, PageType},
+    page::{EntryFlags, Flusher, InactiveFlusher, PageFlags, PageFlushAll, PageType},
     syscall::{MadvFlags, MapFlags, MremapFlags, ProtFlags},
     MemoryManagementArch, PageTableKind, VirtAddr, VirtRegion, VmFlags,
 };
@@ -660,7 +660,10 @@ impl InnerAddressSpace {
                 self.mappings.insert_vma(r.clone());
                 return Err(SystemError::EACCES);
             }
-            r_guard.set_vm_flags(VmFlags::from(prot_flags));
+            // 只替换访问权限，保留VM_SHARED等其他标志
+            let vm_access_flags = VmFlags::VM_READ | VmFlags::VM_WRITE | VmFlags::VM_EXEC;
+            let vm_flags = (*r_guard.vm_flags() - vm_access_flags) | VmFlags::from(prot_flags);
+            r_guard.set_vm_flags(vm_flags);
 
             let new_flags: EntryFlags<MMArch> = r_guard
                 .flags()
@@ -1512,12 +1515,23 @@ impl VMA {
         mapper: &mut PageMapper,
         mut flusher: impl Flusher<MMArch>,
     ) -> Result<(), SystemError> {
+        // 共享文件映射中的干净页保持只读，写入时通过写保护异常标记为脏页
+        let track_dirty = self.vm_file.is_some() && self.vm_flags.contains(VmFlags::VM_SHARED);
         for page in self.region.pages() {
             // debug!("remap page {:?}", page.virt_address());
-            if mapper.translate(page.virt_address()).is_some() {
+            if let Some((paddr, _)) = mapper.translate(page.virt_address()) {
+                let mut page_flags = flags;
+                if track_dirty
+                    && flags.has_write()
+                    && !page_manager_lock_irqsave()
+                        .get(&paddr)
+                        .is_some_and(|p| p.read_irqsave().flags().contains(PageFlags::PG_DIRTY))
+                {
+                    page_flags = flags.set_write(false);
+                }
                 let r = unsafe {
                     mapper
-                        .remap(page.virt_address(), flags)
+                        .remap(page.virt_address(), page_flags)
                         .expect("Failed to remap")
                 };
                 flusher.consume(r);
@@ -0,0 +1,106 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use log::info;
+
+use crate::arch::{mm::LockedFrameAllocator, MMArch};
+
+use super::{
+    allocator::{page_frame::FrameAllocator, slab::slab_usage},
+    page::page_reclaimer_lock_irqsave,
+    writeback::global_dirty_pages,
+    MemoryManagementArch,
+};
+
+/// 虚拟内存事件计数器，对应 /proc/vmstat 中的事件统计项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum VmEvent {
+    /// 分配的物理页数
+    PgAlloc = 0,
+    /// 释放的物理页数
+    PgFree,
+    /// 缺页异常次数
+    PgFault,
+    /// 需要进行IO的缺页异常次数
+    PgMajFault,
+    /// 页面回收次数
+    PgSteal,
+    /// 回写的脏页数
+    PgWriteback,
+}
+
+impl VmEvent {
+    const COUNT: usize = 6;
+
+    const ALL: [VmEvent; Self::COUNT] = [
+        VmEvent::PgAlloc,
+        VmEvent::PgFree,
+        VmEvent::PgFault,
+        VmEvent::PgMajFault,
+        VmEvent::PgSteal,
+        VmEvent::PgWriteback,
+    ];
+
+    /// 在 /proc/vmstat 中显示的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            VmEvent::PgAlloc => "pgalloc",
+            VmEvent::PgFree => "pgfree",
+            VmEvent::PgFault => "pgfault",
+            VmEvent::PgMajFault => "pgmajfault",
+            VmEvent::PgSteal => "pgsteal",
+            VmEvent::PgWriteback => "pgwriteback",
+        }
+    }
+
+    pub fn iter() -> impl Iterator<Item = VmEvent> {
+        Self::ALL.into_iter()
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+static VM_EVENTS: [AtomicUsize; VmEvent::COUNT] = [ZERO; VmEvent::COUNT];
+
+/// 记录一次虚拟内存事件
+#[inline]
+pub fn count_vm_event(event: VmEvent) {
+    count_vm_events(event, 1);
+}
+
+/// 记录多次虚拟内存事件
+#[inline]
+pub fn count_vm_events(event: VmEvent, delta: usize) {
+    VM_EVENTS[event as usize].fetch_add(delta, Ordering::Relaxed);
+}
+
+/// 读取虚拟内存事件计数
+#[inline]
+pub fn vm_event_count(event: VmEvent) -> usize {
+    VM_EVENTS[event as usize].load(Ordering::Relaxed)
+}
+
+/// 打印内存的使用情况，用于调试（如sysrq）
+pub fn show_mem() {
+    let usage = unsafe { LockedFrameAllocator.usage() };
+    let slab = unsafe { slab_usage() };
+    let page_kb = MMArch::PAGE_SIZE >> 10;
+
+    info!("Mem-Info:");
+    info!(
+        "total:{}kB free:{}kB used:{}kB",
+        usage.total().data() * page_kb,
+        usage.free().data() * page_kb,
+        usage.used().data() * page_kb
+    );
+    info!(
+        "file:{}kB dirty:{}kB slab:{}kB slab_free:{}kB",
+        page_reclaimer_lock_irqsave().nr_pages() * page_kb,
+        global_dirty_pages() * page_kb,
+        slab.total() >> 10,
+        slab.free() >> 10
+    );
+    for event in VmEvent::iter() {
+        info!("{} {}", event.name(), vm_event_count(event));
+    }
+}
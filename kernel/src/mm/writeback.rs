@@ -0,0 +1,176 @@
+//! 脏页统计与写回限流
+//!
+//! 每个块设备有一个[`BackingDevInfo`]，记录页面缓存中属于这个设备的脏页数。
+//! 页面被标记为脏页、被写回或者被释放时，更新全局与设备的脏页计数。
+//!
+//! 进程通过页面缓存写入文件之后调用[`balance_dirty_pages_ratelimited`]：
+//! - 全局脏页数不超过后台写回阈值与限流阈值的中点时，不做任何事情
+//! - 全局脏页数超过限流阈值，或者设备的脏页数超过设备的阈值时，设备被标记为拥塞，
+//!   写入的进程自己写回这个设备的脏页，直到脏页数回到阈值以下
+//!
+//! 这样大量写入的进程（例如dd）只会拖慢自己，而不会让脏页占满内存，拖慢其它进程的内存分配与页面回收。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/mm/page-writeback.c
+
+use alloc::{string::String, sync::Arc};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use system_error::SystemError;
+
+use crate::{
+    arch::mm::LockedFrameAllocator,
+    filesystem::page_cache::PageCache,
+    time::{sleep::nanosleep, PosixTimeSpec},
+};
+
+use super::{allocator::page_frame::FrameAllocator, page::page_reclaimer_lock_irqsave};
+
+/// 脏页数超过内存的这个百分比时，开始后台写回
+pub const DIRTY_BACKGROUND_RATIO: usize = 10;
+/// 脏页数超过内存的这个百分比时，限制写入的进程
+pub const DIRTY_RATIO: usize = 20;
+
+/// 被限流的进程每轮写回的最大页数
+const WRITEBACK_CHUNK_PAGES: usize = 64;
+/// 被限流的进程最多等待的轮数，避免脏页一直无法写回时永远阻塞
+const MAX_PAUSE_ROUNDS: usize = 100;
+/// 没有可以写回的脏页时，每轮等待的时间（纳秒）
+const PAUSE_NS: i64 = 10_000_000;
+
+/// 全局的脏页数
+static NR_DIRTY: AtomicUsize = AtomicUsize::new(0);
+
+/// 后备设备信息，页面缓存中的脏页最终写入的块设备
+#[derive(Debug)]
+pub struct BackingDevInfo {
+    name: String,
+    /// 属于这个设备的脏页数
+    nr_dirty: AtomicUsize,
+    /// 这个设备最多占用全局限流阈值的百分比
+    max_ratio: AtomicUsize,
+    /// 设备的脏页超过阈值，写入的进程正在被限流
+    congested: AtomicBool,
+}
+
+impl BackingDevInfo {
+    pub fn new(name: String) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            nr_dirty: AtomicUsize::new(0),
+            max_ratio: AtomicUsize::new(100),
+            congested: AtomicBool::new(false),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 属于这个设备的脏页数
+    pub fn nr_dirty(&self) -> usize {
+        self.nr_dirty.load(Ordering::Relaxed)
+    }
+
+    pub fn max_ratio(&self) -> usize {
+        self.max_ratio.load(Ordering::Relaxed)
+    }
+
+    /// # 设置这个设备最多占用全局限流阈值的百分比
+    ///
+    /// 用于限制慢速设备（如U盘）积累的脏页，避免写回它们时长时间占用其它设备的脏页额度
+    pub fn set_max_ratio(&self, ratio: usize) -> Result<(), SystemError> {
+        if ratio == 0 || ratio > 100 {
+            return Err(SystemError::EINVAL);
+        }
+        self.max_ratio.store(ratio, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 设备是否拥塞：写入这个设备的进程正在被限流
+    pub fn congested(&self) -> bool {
+        self.congested.load(Ordering::Relaxed)
+    }
+
+    /// 根据全局限流阈值计算这个设备的阈值
+    fn dirty_limit(&self, thresh: usize) -> usize {
+        thresh * self.max_ratio() / 100
+    }
+}
+
+/// 全局的脏页数
+pub fn global_dirty_pages() -> usize {
+    NR_DIRTY.load(Ordering::Relaxed)
+}
+
+/// # 计算脏页阈值
+///
+/// ## 返回值
+/// (后台写回阈值, 限流阈值)，单位为页
+pub fn dirty_thresholds() -> (usize, usize) {
+    let total = unsafe { LockedFrameAllocator.usage() }.total().data();
+    (
+        total * DIRTY_BACKGROUND_RATIO / 100,
+        total * DIRTY_RATIO / 100,
+    )
+}
+
+/// 页面被标记为脏页时更新计数
+pub(super) fn account_page_dirtied(bdi: Option<&BackingDevInfo>) {
+    NR_DIRTY.fetch_add(1, Ordering::Relaxed);
+    if let Some(bdi) = bdi {
+        bdi.nr_dirty.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// 脏页被写回或者被释放时更新计数
+pub(super) fn account_page_cleaned(bdi: Option<&BackingDevInfo>) {
+    let dec = |v: usize| Some(v.saturating_sub(1));
+    let _ = NR_DIRTY.fetch_update(Ordering::Relaxed, Ordering::Relaxed, dec);
+    if let Some(bdi) = bdi {
+        let _ = bdi
+            .nr_dirty
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, dec);
+    }
+}
+
+/// 写入这个设备的进程是否需要被限流
+fn over_dirty_limit(bdi: &BackingDevInfo) -> bool {
+    let (bg_thresh, thresh) = dirty_thresholds();
+    let nr_dirty = global_dirty_pages();
+    if nr_dirty <= (bg_thresh + thresh) / 2 {
+        return false;
+    }
+    nr_dirty > thresh || bdi.nr_dirty() > bdi.dirty_limit(thresh)
+}
+
+/// # 限制写入`bdi`的进程
+///
+/// 由写入的进程自己写回这个设备的脏页，直到不再超过阈值。超出阈值的脏页属于其它设备时，等待它们被写回
+fn balance_dirty_pages(bdi: &Arc<BackingDevInfo>) {
+    for _ in 0..MAX_PAUSE_ROUNDS {
+        if !over_dirty_limit(bdi) {
+            bdi.congested.store(false, Ordering::Relaxed);
+            return;
+        }
+        bdi.congested.store(true, Ordering::Relaxed);
+        let written = page_reclaimer_lock_irqsave().writeback_bdi(bdi, WRITEBACK_CHUNK_PAGES);
+        if written == 0 {
+            let _ = nanosleep(PosixTimeSpec::new(0, PAUSE_NS));
+        }
+    }
+    log::warn!(
+        "writeback: {} still over dirty limit after throttling",
+        bdi.name()
+    );
+}
+
+/// # 写入页面缓存之后检查脏页数，必要时限制写入的进程
+///
+/// 调用时不能持有页面缓存或者地址空间的锁，限流时会写回脏页
+pub fn balance_dirty_pages_ratelimited(page_cache: &PageCache) {
+    let Some(bdi) = page_cache.bdi() else {
+        return;
+    };
+    if over_dirty_limit(&bdi) {
+        balance_dirty_pages(&bdi);
+    }
+}
@@ -0,0 +1,40 @@
+use alloc::sync::Arc;
+
+use crate::{
+    ipc::{msg::MsgIds, sem::SemIds},
+    libs::spinlock::SpinLock,
+    process::ProcessManager,
+};
+
+/// IPC命名空间，隔离System V信号量和消息队列
+///
+/// 不同命名空间中的进程使用同一个key得到的是不同的对象。共享内存段目前仍然是全局的
+#[derive(Debug)]
+pub struct IpcNamespace {
+    pub sem_ids: SpinLock<SemIds>,
+    pub msg_ids: SpinLock<MsgIds>,
+}
+
+impl Default for IpcNamespace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IpcNamespace {
+    pub fn new() -> Self {
+        Self {
+            sem_ids: SpinLock::new(SemIds::new()),
+            msg_ids: SpinLock::new(MsgIds::new()),
+        }
+    }
+
+    /// 当前进程所在的IPC命名空间
+    pub fn current() -> Arc<Self> {
+        ProcessManager::current_pcb()
+            .get_nsproxy()
+            .read()
+            .ipc_namespace
+            .clone()
+    }
+}
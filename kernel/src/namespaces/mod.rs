@@ -1,4 +1,5 @@
 use alloc::sync::Arc;
+use ipc_namespace::IpcNamespace;
 use mnt_namespace::{FsStruct, MntNamespace};
 use pid_namespace::PidNamespace;
 use system_error::SystemError;
@@ -9,6 +10,7 @@ use crate::{
     process::{fork::CloneFlags, ProcessControlBlock},
 };
 
+pub mod ipc_namespace;
 pub mod mnt_namespace;
 pub mod namespace;
 pub mod pid_namespace;
@@ -27,6 +29,7 @@ pub struct NsSet {
 pub struct NsProxy {
     pub pid_namespace: Arc<PidNamespace>,
     pub mnt_namespace: Arc<MntNamespace>,
+    pub ipc_namespace: Arc<IpcNamespace>,
 }
 impl Default for NsProxy {
     fn default() -> Self {
@@ -39,6 +42,7 @@ impl NsProxy {
         Self {
             pid_namespace: Arc::new(PidNamespace::new()),
             mnt_namespace: Arc::new(MntNamespace::new()),
+            ipc_namespace: Arc::new(IpcNamespace::new()),
         }
     }
     pub fn set_pid_namespace(&mut self, new_pid_ns: Arc<PidNamespace>) {
@@ -48,6 +52,10 @@ impl NsProxy {
     pub fn set_mnt_namespace(&mut self, new_mnt_ns: Arc<MntNamespace>) {
         self.mnt_namespace = new_mnt_ns;
     }
+
+    pub fn set_ipc_namespace(&mut self, new_ipc_ns: Arc<IpcNamespace>) {
+        self.ipc_namespace = new_ipc_ns;
+    }
 }
 
 pub fn create_new_namespaces(
@@ -75,6 +83,14 @@ pub fn create_new_namespaces(
     };
     nsproxy.set_mnt_namespace(new_mnt_ns);
 
+    // ipc_namespace
+    let new_ipc_ns = if clone_flags & CloneFlags::CLONE_NEWIPC.bits() != 0 {
+        Arc::new(IpcNamespace::new())
+    } else {
+        pcb.get_nsproxy().read().ipc_namespace.clone()
+    };
+    nsproxy.set_ipc_namespace(new_ipc_ns);
+
     Ok(nsproxy)
 }
 
@@ -10,7 +10,7 @@ use alloc::boxed::Box;
 use alloc::sync::Arc;
 use system_error::SystemError;
 
-// 目前无credit功能，采用全局静态的user_namespace
+// 初始user namespace，INIT_CRED位于其中
 lazy_static! {
     pub static ref USER_NS: Arc<UserNamespace> = Arc::new(UserNamespace::new());
 }
@@ -88,7 +88,8 @@ pub fn unshare_nsproxy_namespaces(unshare_flags: u64) -> Result<Option<NsProxy>,
     }
     let current = ProcessManager::current_pid();
     let pcb = ProcessManager::find(current).unwrap();
-    let new_nsproxy = create_new_namespaces(unshare_flags, &pcb, USER_NS.clone())?;
+    let user_ns = pcb.cred().user_ns;
+    let new_nsproxy = create_new_namespaces(unshare_flags, &pcb, user_ns)?;
     Ok(Some(new_nsproxy))
 }
 
@@ -102,7 +103,7 @@ pub fn prepare_nsset(flags: u64) -> Result<NsSet, SystemError> {
     Ok(NsSet {
         flags,
         fs: current.fs_struct(),
-        nsproxy: create_new_namespaces(flags, &current, USER_NS.clone())?,
+        nsproxy: create_new_namespaces(flags, &current, current.cred().user_ns)?,
     })
 }
 
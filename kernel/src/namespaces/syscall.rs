@@ -5,8 +5,9 @@ use crate::{
     syscall::Syscall,
 };
 
-use super::namespace::{
-    check_unshare_flags, commit_nsset, prepare_nsset, unshare_nsproxy_namespaces,
+use super::{
+    namespace::{check_unshare_flags, commit_nsset, prepare_nsset, unshare_nsproxy_namespaces},
+    user_namespace::create_user_ns,
 };
 
 impl Syscall {
@@ -30,6 +31,12 @@ impl Syscall {
         let check = check_unshare_flags(unshare_flags)?;
 
         let current = ProcessManager::current_pcb();
+        // 先进入新的user namespace，同时创建的其他namespace属于新的user namespace
+        if unshare_flags & CloneFlags::CLONE_NEWUSER.bits() != 0 {
+            let mut cred = current.cred();
+            create_user_ns(&mut cred)?;
+            current.set_cred(cred);
+        }
         if let Some(nsproxy) = unshare_nsproxy_namespaces(unshare_flags)? {
             *current.get_nsproxy().write() = nsproxy;
         }
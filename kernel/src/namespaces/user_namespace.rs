@@ -3,6 +3,7 @@
 use alloc::boxed::Box;
 
 use crate::libs::rwlock::RwLock;
+use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
 
@@ -11,6 +12,7 @@ use system_error::SystemError;
 
 use crate::namespaces::namespace::NsCommon;
 use crate::namespaces::ucount::UCounts;
+use crate::process::cred::{CAPFlags, Cred, Kgid, Kuid};
 use crate::process::fork::CloneFlags;
 use crate::process::Pid;
 use alloc::sync::Arc;
@@ -20,32 +22,48 @@ use super::ucount::Ucount::Counts;
 
 const UID_GID_MAP_MAX_BASE_EXTENTS: usize = 5;
 const UCOUNT_MAX: u32 = 62636;
+/// user namespace最多嵌套的层数
+const MAX_USER_NS_LEVEL: u32 = 32;
+/// 在当前user namespace中没有映射的uid，显示为这个值
+pub const OVERFLOW_UID: usize = 65534;
+/// 在当前user namespace中没有映射的gid，显示为这个值
+pub const OVERFLOW_GID: usize = 65534;
+
 /// 管理用户ID和组ID的映射
 #[allow(dead_code)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 struct UidGidMap {
-    nr_extents: u32,
     extent: Vec<UidGidExtent>,
 }
 
-///区间映射
+///区间映射：namespace中的[first, first + count)映射到内核中的[lower_first, lower_first + count)
 #[allow(dead_code)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug)]
 struct UidGidExtent {
     first: u32,
     lower_first: u32,
     count: u32,
 }
+
+/// uid_map或gid_map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IdMapType {
+    Uid,
+    Gid,
+}
+
 #[derive(Debug)]
 pub struct UserNamespace {
-    uid_map: UidGidMap,
-    gid_map: UidGidMap,
+    uid_map: RwLock<UidGidMap>,
+    gid_map: RwLock<UidGidMap>,
     progid_map: UidGidMap,
     ///项目ID映射
     parent: Option<Arc<UserNamespace>>,
     level: u32,
-    owner: usize,
-    group: usize,
+    /// 创建者的有效uid
+    owner: Kuid,
+    /// 创建者的有效gid
+    group: Kgid,
     ns_common: Arc<NsCommon>,
     flags: u32,
     pid: Arc<RwLock<Pid>>,
@@ -59,6 +77,16 @@ impl Default for UserNamespace {
         Self::new()
     }
 }
+
+/// 两个user namespace是同一个对象时才相等
+impl PartialEq for UserNamespace {
+    fn eq(&self, other: &Self) -> bool {
+        core::ptr::eq(self, other)
+    }
+}
+
+impl Eq for UserNamespace {}
+
 #[derive(Debug)]
 struct UserNsOperations {
     name: String,
@@ -94,12 +122,36 @@ impl NsOperations for UserNsOperations {
     }
 }
 impl UidGidMap {
+    /// 初始user namespace的映射，所有的id都映射到自身
     pub fn new() -> Self {
         Self {
-            nr_extents: 1,
-            extent: vec![UidGidExtent::new(); UID_GID_MAP_MAX_BASE_EXTENTS],
+            extent: vec![UidGidExtent::new()],
         }
     }
+
+    /// 把namespace中的[id, id + count)映射到内核中的id，区间必须位于同一个映射区间中
+    fn map_range_down(&self, id: u32, count: u32) -> Option<u32> {
+        let last = id.checked_add(count - 1)?;
+        self.extent
+            .iter()
+            .find(|e| id >= e.first && last - e.first < e.count)
+            .map(|e| id - e.first + e.lower_first)
+    }
+
+    /// 把namespace中的id映射到内核中的id
+    fn map_down(&self, id: usize) -> Option<usize> {
+        let id = u32::try_from(id).ok()?;
+        self.map_range_down(id, 1).map(|id| id as usize)
+    }
+
+    /// 把内核中的id映射到namespace中的id
+    fn map_up(&self, id: usize) -> Option<usize> {
+        let id = u32::try_from(id).ok()?;
+        self.extent
+            .iter()
+            .find(|e| id >= e.lower_first && id - e.lower_first < e.count)
+            .map(|e| (id - e.lower_first + e.first) as usize)
+    }
 }
 
 impl UidGidExtent {
@@ -110,17 +162,65 @@ impl UidGidExtent {
             count: u32::MAX,
         }
     }
+
+    /// 两个区间是否重叠
+    fn overlaps(a_first: u32, a_count: u32, b_first: u32, b_count: u32) -> bool {
+        (a_first as u64) < b_first as u64 + b_count as u64
+            && (b_first as u64) < a_first as u64 + a_count as u64
+    }
+}
+
+/// # 解析写入uid_map、gid_map的内容
+///
+/// 每行为`first lower_first count`，其中lower_first是父namespace中的id。
+/// 最多UID_GID_MAP_MAX_BASE_EXTENTS行，各行的两侧区间都不能重叠
+fn parse_id_map(buf: &[u8]) -> Result<Vec<UidGidExtent>, SystemError> {
+    let text = core::str::from_utf8(buf).map_err(|_| SystemError::EINVAL)?;
+    let mut extents: Vec<UidGidExtent> = Vec::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let fields = line
+            .split_whitespace()
+            .map(|f| f.parse::<u32>().map_err(|_| SystemError::EINVAL))
+            .collect::<Result<Vec<u32>, SystemError>>()?;
+        let &[first, lower_first, count] = fields.as_slice() else {
+            return Err(SystemError::EINVAL);
+        };
+        if count == 0
+            || first.checked_add(count).is_none()
+            || lower_first.checked_add(count).is_none()
+        {
+            return Err(SystemError::EINVAL);
+        }
+        if extents.len() == UID_GID_MAP_MAX_BASE_EXTENTS {
+            return Err(SystemError::EINVAL);
+        }
+        if extents.iter().any(|e| {
+            UidGidExtent::overlaps(e.first, e.count, first, count)
+                || UidGidExtent::overlaps(e.lower_first, e.count, lower_first, count)
+        }) {
+            return Err(SystemError::EINVAL);
+        }
+        extents.push(UidGidExtent {
+            first,
+            lower_first,
+            count,
+        });
+    }
+    if extents.is_empty() {
+        return Err(SystemError::EINVAL);
+    }
+    Ok(extents)
 }
 
 impl UserNamespace {
     pub fn new() -> Self {
         Self {
-            uid_map: UidGidMap::new(),
-            gid_map: UidGidMap::new(),
+            uid_map: RwLock::new(UidGidMap::new()),
+            gid_map: RwLock::new(UidGidMap::new()),
             progid_map: UidGidMap::new(),
-            owner: 0,
+            owner: Kuid::new(0),
             level: 0,
-            group: 0,
+            group: Kgid::new(0),
             flags: 1,
             parent: None,
             ns_common: Arc::new(NsCommon::new(Box::new(UserNsOperations::new(
@@ -132,4 +232,211 @@ impl UserNamespace {
             rlimit_max: vec![65535, 10, 32000, 64 * 1024],
         }
     }
+
+    /// 创建子user namespace，新的namespace在写入uid_map、gid_map之前没有任何映射
+    fn create_child(parent: Arc<Self>, owner: Kuid, group: Kgid) -> Result<Self, SystemError> {
+        let level = parent.level + 1;
+        if level > MAX_USER_NS_LEVEL {
+            return Err(SystemError::EUSERS);
+        }
+        // 创建者的uid、gid必须在父namespace中有映射
+        if parent.from_kuid(owner).is_none() || parent.from_kgid(group).is_none() {
+            return Err(SystemError::EPERM);
+        }
+        Ok(Self {
+            uid_map: RwLock::new(UidGidMap::default()),
+            gid_map: RwLock::new(UidGidMap::default()),
+            progid_map: UidGidMap::default(),
+            owner,
+            level,
+            group,
+            flags: 0,
+            ns_common: Arc::new(NsCommon::new(Box::new(UserNsOperations::new(
+                "User".to_string(),
+            )))),
+            pid: Arc::new(RwLock::new(Pid::new(1))),
+            ucount_max: parent.ucount_max.clone(),
+            ucounts: None,
+            rlimit_max: parent.rlimit_max.clone(),
+            parent: Some(parent),
+        })
+    }
+
+    pub fn parent(&self) -> Option<&Arc<UserNamespace>> {
+        self.parent.as_ref()
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    /// 创建这个namespace的进程的有效uid
+    pub fn owner(&self) -> Kuid {
+        self.owner
+    }
+
+    /// 把这个namespace中的uid转换为内核中的uid，没有映射时返回None
+    pub fn make_kuid(&self, uid: usize) -> Option<Kuid> {
+        self.uid_map.read().map_down(uid).map(Kuid::new)
+    }
+
+    /// 把这个namespace中的gid转换为内核中的gid，没有映射时返回None
+    pub fn make_kgid(&self, gid: usize) -> Option<Kgid> {
+        self.gid_map.read().map_down(gid).map(Kgid::new)
+    }
+
+    /// 把内核中的uid转换为这个namespace中的uid，没有映射时返回None
+    pub fn from_kuid(&self, kuid: Kuid) -> Option<usize> {
+        self.uid_map.read().map_up(kuid.data())
+    }
+
+    /// 把内核中的gid转换为这个namespace中的gid，没有映射时返回None
+    pub fn from_kgid(&self, kgid: Kgid) -> Option<usize> {
+        self.gid_map.read().map_up(kgid.data())
+    }
+
+    /// 把内核中的uid转换为这个namespace中的uid，没有映射时返回OVERFLOW_UID
+    pub fn from_kuid_munged(&self, kuid: Kuid) -> usize {
+        self.from_kuid(kuid).unwrap_or(OVERFLOW_UID)
+    }
+
+    /// 把内核中的gid转换为这个namespace中的gid，没有映射时返回OVERFLOW_GID
+    pub fn from_kgid_munged(&self, kgid: Kgid) -> usize {
+        self.from_kgid(kgid).unwrap_or(OVERFLOW_GID)
+    }
+
+    fn id_map(&self, map_type: IdMapType) -> &RwLock<UidGidMap> {
+        match map_type {
+            IdMapType::Uid => &self.uid_map,
+            IdMapType::Gid => &self.gid_map,
+        }
+    }
+
+    /// 把内核中的id转换为父namespace中的id，用于显示映射
+    fn lower_id_in_parent(&self, map_type: IdMapType, id: u32) -> u32 {
+        let Some(parent) = self.parent.as_ref() else {
+            return id;
+        };
+        let id = match map_type {
+            IdMapType::Uid => parent.from_kuid(Kuid::new(id as usize)),
+            IdMapType::Gid => parent.from_kgid(Kgid::new(id as usize)),
+        };
+        id.map(|id| id as u32).unwrap_or(u32::MAX)
+    }
+
+    /// /proc/[pid]/uid_map的内容，每行为`first lower_first count`，lower_first是父namespace中的id
+    pub fn show_uid_map(&self) -> String {
+        self.show_id_map(IdMapType::Uid)
+    }
+
+    /// /proc/[pid]/gid_map的内容
+    pub fn show_gid_map(&self) -> String {
+        self.show_id_map(IdMapType::Gid)
+    }
+
+    fn show_id_map(&self, map_type: IdMapType) -> String {
+        let map = self.id_map(map_type).read();
+        let mut s = String::new();
+        for e in map.extent.iter() {
+            s.push_str(&format!(
+                "{:>10} {:>10} {:>10}\n",
+                e.first,
+                self.lower_id_in_parent(map_type, e.lower_first),
+                e.count
+            ));
+        }
+        s
+    }
+
+    /// 写入/proc/[pid]/uid_map
+    pub fn write_uid_map(&self, buf: &[u8], cred: &Cred) -> Result<usize, SystemError> {
+        self.map_write(IdMapType::Uid, buf, cred)
+    }
+
+    /// 写入/proc/[pid]/gid_map
+    pub fn write_gid_map(&self, buf: &[u8], cred: &Cred) -> Result<usize, SystemError> {
+        self.map_write(IdMapType::Gid, buf, cred)
+    }
+
+    /// # 设置uid、gid映射
+    ///
+    /// 映射只能设置一次，只有这个namespace或者它的父namespace中的进程可以设置。
+    /// 在父namespace中有CAP_SETUID（CAP_SETGID）时可以设置任意映射，
+    /// 否则只能由创建者把自己的有效uid（gid）映射到新namespace中的一个id。
+    ///
+    /// 目前不支持setgroups，因此设置gid_map之前不需要向/proc/[pid]/setgroups写入deny
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/user_namespace.c#897
+    fn map_write(
+        &self,
+        map_type: IdMapType,
+        buf: &[u8],
+        cred: &Cred,
+    ) -> Result<usize, SystemError> {
+        let parent = self.parent.as_ref().ok_or(SystemError::EPERM)?;
+        if !core::ptr::eq(Arc::as_ptr(&cred.user_ns), self) && !Arc::ptr_eq(&cred.user_ns, parent) {
+            return Err(SystemError::EPERM);
+        }
+
+        let mut map = self.id_map(map_type).write();
+        if !map.extent.is_empty() {
+            return Err(SystemError::EPERM);
+        }
+        let mut extents = parse_id_map(buf)?;
+
+        let parent_map = parent.id_map(map_type).read();
+        if !self.idmap_permitted(map_type, &extents, &parent_map, cred) {
+            return Err(SystemError::EPERM);
+        }
+        // 把父namespace中的id转换为内核中的id
+        for e in extents.iter_mut() {
+            e.lower_first = parent_map
+                .map_range_down(e.lower_first, e.count)
+                .ok_or(SystemError::EPERM)?;
+        }
+        drop(parent_map);
+
+        map.extent = extents;
+        Ok(buf.len())
+    }
+
+    /// 检查写入者是否可以设置这些映射
+    fn idmap_permitted(
+        &self,
+        map_type: IdMapType,
+        extents: &[UidGidExtent],
+        parent_map: &UidGidMap,
+        cred: &Cred,
+    ) -> bool {
+        // 创建者把自己的有效id映射到新namespace中
+        if let [e] = extents {
+            let own_id = match map_type {
+                IdMapType::Uid => cred.euid.data(),
+                IdMapType::Gid => cred.egid.data(),
+            };
+            if e.count == 1
+                && cred.euid == self.owner
+                && parent_map.map_down(e.lower_first as usize) == Some(own_id)
+            {
+                return true;
+            }
+        }
+        let cap = match map_type {
+            IdMapType::Uid => CAPFlags::CAP_SETUID,
+            IdMapType::Gid => CAPFlags::CAP_SETGID,
+        };
+        cred.ns_capable(self.parent.as_ref().unwrap(), cap)
+    }
+}
+
+/// # 让凭证进入新的user namespace
+///
+/// 用于unshare或者clone时指定了CLONE_NEWUSER。新namespace的创建者是凭证的有效uid、gid，
+/// 凭证在新的namespace中拥有全部权限，在父namespace中的权限不变
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/user_namespace.c#83
+pub fn create_user_ns(cred: &mut Cred) -> Result<(), SystemError> {
+    let ns = UserNamespace::create_child(cred.user_ns.clone(), cred.euid, cred.egid)?;
+    cred.enter_user_ns(Arc::new(ns));
+    Ok(())
 }
@@ -0,0 +1,213 @@
+//! 内核中的DHCP客户端
+//!
+//! 启动时为默认网卡创建一个smoltcp的dhcpv4套接字，并启动一个内核线程持续轮询它：
+//! 获得租约之后设置网卡的地址和默认路由，续租失败时撤销它们。当前的租约通过debugfs的`/sys/kernel/debug/dhcp`查看。
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt::Write;
+use log::{debug, info, warn};
+use smoltcp::{
+    iface::SocketHandle,
+    socket::dhcpv4,
+    wire::{self, DhcpRepr},
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::net::{NetDevice, Operstate},
+    filesystem::debugfs::{debugfs_create_file, DEBUGFS_MODE_RO},
+    libs::spinlock::SpinLock,
+    net::{netfilter::nat::nf_nat_iface_addr_changed, socket::SOCKET_SET, NET_DEVICES},
+    process::kthread::{KernelThreadClosure, KernelThreadMechanism},
+    time::{sleep::nanosleep, PosixTimeSpec},
+};
+
+/// 启动时等待第一次获得租约的最长时间（秒）
+const DHCP_BOOT_TIMEOUT_SECS: i64 = 10;
+/// 客户端线程轮询套接字的间隔（毫秒）
+const DHCP_POLL_INTERVAL_MS: i64 = 500;
+
+/// 一个网卡当前持有的租约
+#[derive(Debug, Clone)]
+pub struct DhcpLease {
+    pub iface_name: String,
+    pub address: wire::Ipv4Cidr,
+    pub router: Option<wire::Ipv4Address>,
+    pub dns_servers: Vec<wire::Ipv4Address>,
+    /// 分配地址的DHCP服务器
+    pub server: wire::Ipv4Address,
+    /// 租期（秒），服务器没有给出时为None
+    pub lease_secs: Option<u32>,
+}
+
+impl DhcpLease {
+    fn new(net_face: &Arc<dyn NetDevice>, config: &dhcpv4::Config) -> Self {
+        Self {
+            iface_name: net_face.iface_name(),
+            address: config.address,
+            router: config.router,
+            dns_servers: config.dns_servers.iter().copied().collect(),
+            server: config.server.identifier,
+            lease_secs: config
+                .packet
+                .as_ref()
+                .and_then(|p| DhcpRepr::parse(p).ok())
+                .and_then(|r| r.lease_duration),
+        }
+    }
+}
+
+/// 各个网卡的租约，key为网卡id
+static DHCP_LEASES: SpinLock<BTreeMap<usize, DhcpLease>> = SpinLock::new(BTreeMap::new());
+
+/// 获取网卡当前持有的租约
+pub fn dhcp_lease(nic_id: usize) -> Option<DhcpLease> {
+    DHCP_LEASES.lock_irqsave().get(&nic_id).cloned()
+}
+
+/// 生成debugfs中`dhcp`文件的内容，每个租约一段，格式与dhclient的租约文件类似
+fn dhcp_leases_show() -> String {
+    let mut s = String::new();
+    for (nic_id, lease) in DHCP_LEASES.lock_irqsave().iter() {
+        writeln!(s, "lease {{").ok();
+        writeln!(s, "  interface {};", lease.iface_name).ok();
+        writeln!(s, "  ifindex {};", nic_id).ok();
+        writeln!(s, "  fixed-address {};", lease.address).ok();
+        if let Some(router) = lease.router {
+            writeln!(s, "  option routers {};", router).ok();
+        }
+        if !lease.dns_servers.is_empty() {
+            let dns: Vec<String> = lease.dns_servers.iter().map(|a| a.to_string()).collect();
+            writeln!(s, "  option domain-name-servers {};", dns.join(", ")).ok();
+        }
+        writeln!(s, "  option dhcp-server-identifier {};", lease.server).ok();
+        if let Some(secs) = lease.lease_secs {
+            writeln!(s, "  option dhcp-lease-time {};", secs).ok();
+        }
+        writeln!(s, "}}").ok();
+    }
+    s
+}
+
+/// 为默认网卡启动DHCP客户端，并等待第一次获得租约
+///
+/// 等待超时不算失败，客户端线程会继续尝试
+pub fn dhcp_start() -> Result<(), SystemError> {
+    debugfs_create_file(
+        "dhcp",
+        DEBUGFS_MODE_RO,
+        None,
+        Some(Box::new(|| Ok(dhcp_leases_show()))),
+        None,
+    )?;
+
+    // 回环网卡的id最先分配，且地址固定，因此使用id为1的网卡
+    let net_face = NET_DEVICES
+        .read_irqsave()
+        .get(&1)
+        .cloned()
+        .ok_or(SystemError::ENODEV)?;
+    let nic_id = net_face.nic_id();
+
+    let handle = SOCKET_SET.lock_irqsave().add(dhcpv4::Socket::new());
+    let closure = KernelThreadClosure::EmptyClosure((
+        Box::new(move || dhcp_client_thread(net_face.clone(), handle)),
+        (),
+    ));
+    KernelThreadMechanism::create_and_run(closure, format!("dhcp_client{}", nic_id))
+        .ok_or(SystemError::ENOMEM)?;
+
+    let rounds = DHCP_BOOT_TIMEOUT_SECS * 1000 / DHCP_POLL_INTERVAL_MS;
+    for _ in 0..rounds {
+        if let Some(lease) = dhcp_lease(nic_id) {
+            info!("Successfully allocated ip by Dhcpv4! Ip:{}", lease.address);
+            return Ok(());
+        }
+        dhcp_sleep()?;
+    }
+    warn!(
+        "DHCP: no lease after {}s, continue in background",
+        DHCP_BOOT_TIMEOUT_SECS
+    );
+    Ok(())
+}
+
+fn dhcp_sleep() -> Result<(), SystemError> {
+    nanosleep(PosixTimeSpec {
+        tv_sec: 0,
+        tv_nsec: DHCP_POLL_INTERVAL_MS * 1_000_000,
+    })?;
+    Ok(())
+}
+
+fn dhcp_client_thread(net_face: Arc<dyn NetDevice>, handle: SocketHandle) -> i32 {
+    loop {
+        net_face.poll(&mut SOCKET_SET.lock_irqsave()).ok();
+
+        // 在修改网卡之前释放套接字的锁
+        let mut sockets = SOCKET_SET.lock_irqsave();
+        let lease = match sockets.get_mut::<dhcpv4::Socket>(handle).poll() {
+            None => None,
+            Some(dhcpv4::Event::Configured(config)) => {
+                Some(Some(DhcpLease::new(&net_face, &config)))
+            }
+            Some(dhcpv4::Event::Deconfigured) => Some(None),
+        };
+        drop(sockets);
+
+        match lease {
+            Some(Some(lease)) => dhcp_configure(&net_face, lease),
+            Some(None) => dhcp_deconfigure(&net_face),
+            None => {}
+        }
+
+        // 内核线程没有信号需要处理，睡眠被打断时直接继续轮询
+        dhcp_sleep().ok();
+    }
+}
+
+fn dhcp_configure(net_face: &Arc<dyn NetDevice>, lease: DhcpLease) {
+    debug!("DHCP: configured {:?}", lease);
+    net_face
+        .update_ip_addrs(&[wire::IpCidr::Ipv4(lease.address)])
+        .ok();
+    nf_nat_iface_addr_changed(net_face.nic_id(), lease.address.address());
+
+    let mut iface = net_face.inner_iface().lock();
+    match lease.router {
+        Some(router) => {
+            iface.routes_mut().add_default_ipv4_route(router).ok();
+        }
+        None => {
+            iface.routes_mut().remove_default_ipv4_route();
+        }
+    }
+    drop(iface);
+
+    net_face.set_operstate(Operstate::IF_OPER_UP);
+    DHCP_LEASES.lock_irqsave().insert(net_face.nic_id(), lease);
+}
+
+fn dhcp_deconfigure(net_face: &Arc<dyn NetDevice>) {
+    debug!("DHCP: deconfigured {}", net_face.iface_name());
+    DHCP_LEASES.lock_irqsave().remove(&net_face.nic_id());
+    net_face
+        .update_ip_addrs(&[wire::IpCidr::Ipv4(wire::Ipv4Cidr::new(
+            wire::Ipv4Address::UNSPECIFIED,
+            0,
+        ))])
+        .ok();
+    nf_nat_iface_addr_changed(net_face.nic_id(), wire::Ipv4Address::UNSPECIFIED);
+    net_face
+        .inner_iface()
+        .lock()
+        .routes_mut()
+        .remove_default_ipv4_route();
+}
@@ -1,5 +1,4 @@
 use core::{
-    any::Any,
     fmt::Debug,
     sync::atomic::{AtomicBool, Ordering},
 };
@@ -9,7 +8,6 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
-use intertrait::CastFromSync;
 use system_error::SystemError;
 
 use crate::{
@@ -131,9 +129,56 @@ impl EPollItem {
     }
 }
 
-pub trait KernelIoctlData: Send + Sync + Any + Debug + CastFromSync {}
+/// 可被poll的对象上挂接的epitem，相当于Linux中通过`poll_wait`挂到等待队列上的`poll_table`项
+///
+/// 支持epoll的驱动和文件系统持有一个`EPollItems`，在[`IndexNode::add_epitem`]和
+/// [`IndexNode::remove_epitem`]中增删epitem，在有事件到来时调用[`EPollItems::wakeup`]。
+/// 这样新增可被poll的文件时不需要修改epoll的代码
+#[derive(Debug)]
+pub struct EPollItems(SpinLock<LinkedList<Arc<EPollItem>>>);
+
+impl Default for EPollItems {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EPollItems {
+    pub const fn new() -> Self {
+        Self(SpinLock::new(LinkedList::new()))
+    }
+
+    /// 挂接一个epitem
+    pub fn add(&self, epitem: Arc<EPollItem>) {
+        self.0.lock_irqsave().push_back(epitem);
+    }
 
-impl KernelIoctlData for EPollItem {}
+    /// 删除属于`epoll`的epitem，没有找到时返回ENOENT
+    pub fn remove(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
+        let is_remove = !self
+            .0
+            .lock_irqsave()
+            .extract_if(|x| x.epoll().ptr_eq(epoll))
+            .collect::<Vec<_>>()
+            .is_empty();
+
+        if is_remove {
+            return Ok(());
+        }
+
+        Err(SystemError::ENOENT)
+    }
+
+    /// 取出所有的epitem，用于对象被销毁时从各个epoll中删除自己
+    pub fn take_all(&self) -> LinkedList<Arc<EPollItem>> {
+        core::mem::take(&mut *self.0.lock_irqsave())
+    }
+
+    /// 通知挂接的epoll有事件到来。`pollflags`为None时通过文件的poll方法获取事件
+    pub fn wakeup(&self, pollflags: Option<EPollEventType>) -> Result<(), SystemError> {
+        EventPoll::wakeup_epoll(&self.0, pollflags)
+    }
+}
 
 /// ### Epoll文件的私有信息
 #[derive(Debug, Clone)]
@@ -611,7 +656,7 @@ impl EventPoll {
     ) -> Result<(), SystemError> {
         if Self::is_epoll_file(&dst_file) {
             return Err(SystemError::ENOSYS);
-            // TODO：现在的实现先不考虑嵌套其它类型的文件(暂时只针对socket),这里的嵌套指epoll/select/poll
+            // TODO：现在的实现先不考虑嵌套，这里的嵌套指epoll/select/poll
         }
 
         let test_poll = dst_file.poll();
@@ -638,6 +683,7 @@ impl EventPoll {
             return Err(SystemError::ENOSYS);
         }
 
+        // 由文件自己把epitem挂到等待队列上，不支持epoll的文件返回ENOSYS
         dst_file.add_epoll(epitem.clone())?;
         Ok(())
     }
@@ -719,8 +765,8 @@ impl EventPoll {
         self.epoll_wq.wakeup(None);
     }
 
-    /// ### epoll的回调，支持epoll的文件有事件到来时直接调用该方法即可
-    pub fn wakeup_epoll(
+    /// ### epoll的回调，由[`EPollItems::wakeup`]在文件有事件到来时调用
+    fn wakeup_epoll(
         epitems: &SpinLock<LinkedList<Arc<EPollItem>>>,
         pollflags: Option<EPollEventType>,
     ) -> Result<(), SystemError> {
@@ -24,7 +24,7 @@ impl Syscall {
     }
 
     pub fn epoll_create1(flag: usize) -> Result<usize, SystemError> {
-        let flags = FileMode::from_bits_truncate(flag as u32);
+        let flags = FileMode::from_bits(flag as u32).ok_or(SystemError::EINVAL)?;
 
         let ret = EventPoll::do_create_epoll(flags);
         ret
@@ -6,12 +6,16 @@ use core::{
 use alloc::{collections::BTreeMap, sync::Arc};
 
 use crate::{driver::net::NetDevice, libs::rwlock::RwLock};
-use smoltcp::wire::IpEndpoint;
+use smoltcp::wire::{EthernetAddress, IpEndpoint};
 
-use self::socket::SocketInode;
+use self::socket::{netlink::NetlinkEndpoint, unix::UnixEndpoint, xdp::XdpEndpoint, SocketInode};
 
+pub mod dhcp;
 pub mod event_poll;
 pub mod net_core;
+pub mod netfilter;
+pub mod nlmsg;
+pub mod rtnetlink;
 pub mod socket;
 pub mod syscall;
 
@@ -45,14 +49,25 @@ pub enum Endpoint {
     Ip(Option<IpEndpoint>),
     /// inode端点
     Inode(Option<Arc<SocketInode>>),
-    // todo: 增加NetLink机制后，增加NetLink端点
+    /// netlink端点
+    Netlink(NetlinkEndpoint),
+    /// unix域地址
+    Unix(UnixEndpoint),
+    /// AF_XDP端点
+    Xdp(XdpEndpoint),
 }
 
-/// @brief 链路层端点
+/// @brief 链路层端点，对应`struct sockaddr_ll`
 #[derive(Debug, Clone)]
 pub struct LinkLayerEndpoint {
-    /// 网卡的接口号
+    /// 网卡的接口号，为0表示任意网卡
     pub interface: usize,
+    /// 以太网协议号（主机字节序），为0表示未指定
+    pub protocol: u16,
+    /// 数据包的类型，见`socket::packet::PacketType`
+    pub pkttype: u8,
+    /// 硬件地址：接收时为源地址，发送时为目的地址
+    pub addr: Option<EthernetAddress>,
 }
 
 impl LinkLayerEndpoint {
@@ -62,7 +77,12 @@ impl LinkLayerEndpoint {
     ///
     /// @return 返回创建的链路层端点
     pub fn new(interface: usize) -> Self {
-        Self { interface }
+        Self {
+            interface,
+            protocol: 0,
+            pkttype: 0,
+            addr: None,
+        }
     }
 }
 
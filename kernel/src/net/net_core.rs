@@ -1,24 +1,26 @@
 use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
-use log::{debug, info, warn};
-use smoltcp::{socket::dhcpv4, wire};
+use log::warn;
+use smoltcp::wire;
 use system_error::SystemError;
 
 use crate::{
-    driver::net::{NetDevice, Operstate},
-    libs::rwlock::RwLockReadGuard,
-    net::{socket::SocketPollMethod, NET_DEVICES},
-    time::{
-        sleep::nanosleep,
-        timer::{next_n_ms_timer_jiffies, Timer, TimerFunction},
-        PosixTimeSpec,
-    },
+    driver::net::NetDevice,
+    libs::{rwlock::RwLockReadGuard, spinlock::SpinLock},
+    net::{dhcp::dhcp_start, netfilter::nf_flush_rejects, socket::SocketPollMethod, NET_DEVICES},
+    time::timer::{next_n_ms_timer_jiffies, Timer, TimerFunction},
 };
 
 use super::{
-    event_poll::{EPollEventType, EventPoll},
+    event_poll::EPollEventType,
     socket::{handle::GlobalSocketHandle, inet::TcpSocket, HANDLE_MAP, SOCKET_SET},
 };
 
+/// 各个网卡上已经加入的组播组，以及加入该组播组的socket的数量
+///
+/// key为(网卡id, 组播地址)
+static MULTICAST_GROUPS: SpinLock<BTreeMap<(usize, wire::Ipv4Address), usize>> =
+    SpinLock::new(BTreeMap::new());
+
 /// The network poll function, which will be called by timer.
 ///
 /// The main purpose of this function is to poll all network interfaces.
@@ -37,7 +39,7 @@ impl TimerFunction for NetWorkPollFunc {
 }
 
 pub fn net_init() -> Result<(), SystemError> {
-    dhcp_query()?;
+    dhcp_start()?;
     // Init poll timer function
     // let next_time = next_n_ms_timer_jiffies(5);
     // let timer = Timer::new(Box::new(NetWorkPollFunc), next_time);
@@ -45,96 +47,6 @@ pub fn net_init() -> Result<(), SystemError> {
     return Ok(());
 }
 
-fn dhcp_query() -> Result<(), SystemError> {
-    let binding = NET_DEVICES.write_irqsave();
-
-    //由于现在os未实现在用户态为网卡动态分配内存，而lo网卡的id最先分配且ip固定不能被分配
-    //所以特判取用id为1的网卡（也就是virto_net）
-    let net_face = binding.get(&1).ok_or(SystemError::ENODEV)?.clone();
-
-    drop(binding);
-
-    // Create sockets
-    let mut dhcp_socket = dhcpv4::Socket::new();
-
-    // Set a ridiculously short max lease time to show DHCP renews work properly.
-    // This will cause the DHCP client to start renewing after 5 seconds, and give up the
-    // lease after 10 seconds if renew hasn't succeeded.
-    // IMPORTANT: This should be removed in production.
-    dhcp_socket.set_max_lease_duration(Some(smoltcp::time::Duration::from_secs(10)));
-
-    let dhcp_handle = SOCKET_SET.lock_irqsave().add(dhcp_socket);
-
-    const DHCP_TRY_ROUND: u8 = 10;
-    for i in 0..DHCP_TRY_ROUND {
-        debug!("DHCP try round: {}", i);
-        net_face.poll(&mut SOCKET_SET.lock_irqsave()).ok();
-        let mut binding = SOCKET_SET.lock_irqsave();
-        let event = binding.get_mut::<dhcpv4::Socket>(dhcp_handle).poll();
-
-        match event {
-            None => {}
-
-            Some(dhcpv4::Event::Configured(config)) => {
-                // debug!("Find Config!! {config:?}");
-                // debug!("Find ip address: {}", config.address);
-                // debug!("iface.ip_addrs={:?}", net_face.inner_iface.ip_addrs());
-
-                net_face
-                    .update_ip_addrs(&[wire::IpCidr::Ipv4(config.address)])
-                    .ok();
-
-                if let Some(router) = config.router {
-                    net_face
-                        .inner_iface()
-                        .lock()
-                        .routes_mut()
-                        .add_default_ipv4_route(router)
-                        .unwrap();
-                    let cidr = net_face.inner_iface().lock().ip_addrs().first().cloned();
-                    if let Some(cidr) = cidr {
-                        // 这里先在这里将网卡设置为up，后面等netlink实现了再修改
-                        net_face.set_operstate(Operstate::IF_OPER_UP);
-                        info!("Successfully allocated ip by Dhcpv4! Ip:{}", cidr);
-                        return Ok(());
-                    }
-                } else {
-                    net_face
-                        .inner_iface()
-                        .lock()
-                        .routes_mut()
-                        .remove_default_ipv4_route();
-                }
-            }
-
-            Some(dhcpv4::Event::Deconfigured) => {
-                debug!("Dhcp v4 deconfigured");
-                net_face
-                    .update_ip_addrs(&[smoltcp::wire::IpCidr::Ipv4(wire::Ipv4Cidr::new(
-                        wire::Ipv4Address::UNSPECIFIED,
-                        0,
-                    ))])
-                    .ok();
-                net_face
-                    .inner_iface()
-                    .lock()
-                    .routes_mut()
-                    .remove_default_ipv4_route();
-            }
-        }
-        // 在睡眠前释放锁
-        drop(binding);
-
-        let sleep_time = PosixTimeSpec {
-            tv_sec: 5,
-            tv_nsec: 0,
-        };
-        let _ = nanosleep(sleep_time)?;
-    }
-
-    return Err(SystemError::ETIMEDOUT);
-}
-
 pub fn poll_ifaces() {
     let guard: RwLockReadGuard<BTreeMap<usize, Arc<dyn NetDevice>>> = NET_DEVICES.read_irqsave();
     if guard.len() == 0 {
@@ -145,6 +57,8 @@ pub fn poll_ifaces() {
     for (_, iface) in guard.iter() {
         iface.poll(&mut sockets).ok();
     }
+    nf_flush_rejects(&guard);
+    TcpSocket::reap_lingering(&mut sockets);
     let _ = send_event(&sockets);
 }
 
@@ -174,6 +88,8 @@ pub fn poll_ifaces_try_lock(times: u16) -> Result<(), SystemError> {
         for (_, iface) in guard.iter() {
             iface.poll(&mut sockets).ok();
         }
+        nf_flush_rejects(&guard);
+        TcpSocket::reap_lingering(&mut sockets);
         send_event(&sockets)?;
         return Ok(());
     }
@@ -197,10 +113,91 @@ pub fn poll_ifaces_try_lock_onetime() -> Result<(), SystemError> {
     for (_, iface) in guard.iter() {
         iface.poll(&mut sockets).ok();
     }
+    nf_flush_rejects(&guard);
+    TcpSocket::reap_lingering(&mut sockets);
     send_event(&sockets)?;
     return Ok(());
 }
 
+/// 加入组播组
+///
+/// 同一个网卡上的同一个组播组只会向网络发送一次IGMP成员报告，之后只增加引用计数
+pub fn join_multicast_group(
+    iface: &Arc<dyn NetDevice>,
+    group: wire::Ipv4Address,
+) -> Result<(), SystemError> {
+    if !group.is_multicast() {
+        return Err(SystemError::EINVAL);
+    }
+    let mut groups = MULTICAST_GROUPS.lock_irqsave();
+    let key = (iface.nic_id(), group);
+    if let Some(count) = groups.get_mut(&key) {
+        *count += 1;
+        return Ok(());
+    }
+    iface.join_multicast_group(wire::IpAddress::Ipv4(group))?;
+    groups.insert(key, 1);
+    return Ok(());
+}
+
+/// 离开组播组，最后一个socket离开时才会向网络发送IGMP离开报告
+pub fn leave_multicast_group(
+    iface: &Arc<dyn NetDevice>,
+    group: wire::Ipv4Address,
+) -> Result<(), SystemError> {
+    let mut groups = MULTICAST_GROUPS.lock_irqsave();
+    let key = (iface.nic_id(), group);
+    let count = groups.get_mut(&key).ok_or(SystemError::EADDRNOTAVAIL)?;
+    *count -= 1;
+    if *count == 0 {
+        groups.remove(&key);
+        iface.leave_multicast_group(wire::IpAddress::Ipv4(group))?;
+    }
+    return Ok(());
+}
+
+/// 选择发往`dst`的数据包所使用的网卡
+///
+/// 优先使用与目的地址处于同一子网的网卡（127.0.0.0/8和::1因此总是走lo），
+/// 其次是第一个配置了IPv4地址的非环回网卡
+pub fn route_iface(dst: wire::IpAddress) -> Option<Arc<dyn NetDevice>> {
+    let devices = NET_DEVICES.read_irqsave();
+    devices
+        .values()
+        .find(|iface| {
+            iface
+                .inner_iface()
+                .lock()
+                .ip_addrs()
+                .iter()
+                .any(|cidr| cidr.contains_addr(&dst))
+        })
+        .or_else(|| {
+            devices.values().find(|iface| {
+                iface.nic_id() != 0 && iface.inner_iface().lock().ipv4_addr().is_some()
+            })
+        })
+        .cloned()
+}
+
+/// 判断`addr`是否是广播地址（受限广播地址，或者某个网卡所在子网的广播地址）
+pub fn is_broadcast_addr(addr: wire::Ipv4Address) -> bool {
+    if addr.is_broadcast() {
+        return true;
+    }
+    NET_DEVICES.read_irqsave().values().any(|iface| {
+        iface
+            .inner_iface()
+            .lock()
+            .ip_addrs()
+            .iter()
+            .any(|cidr| match cidr {
+                wire::IpCidr::Ipv4(cidr) => cidr.broadcast() == Some(addr),
+                _ => false,
+            })
+    })
+}
+
 /// ### 处理轮询后的事件
 fn send_event(sockets: &smoltcp::iface::SocketSet) -> Result<(), SystemError> {
     for (handle, socket_type) in sockets.iter() {
@@ -243,10 +240,9 @@ fn send_event(sockets: &smoltcp::iface::SocketSet) -> Result<(), SystemError> {
             smoltcp::socket::Socket::Dhcpv4(_) => {}
             smoltcp::socket::Socket::Dns(_) => unimplemented!("Dns socket hasn't unimplemented"),
         }
-        EventPoll::wakeup_epoll(
-            &posix_item.epitems,
-            Some(EPollEventType::from_bits_truncate(events as u32)),
-        )?;
+        posix_item
+            .epitems
+            .wakeup(Some(EPollEventType::from_bits_truncate(events as u32)))?;
         drop(handle_guard);
         // crate::debug!(
         //     "{} send_event {:?}",
@@ -0,0 +1,325 @@
+//! 连接跟踪：记录经过NAT的连接，使同一连接的后续数据包以及回复方向的数据包得到相同的地址转换
+//!
+//! 连接以五元组（源地址、目的地址、协议、源端口、目的端口）为键，每个连接同时以原方向和回复方向的元组登记。
+//! ICMP回显请求和应答以标识符代替端口（请求放在源端口，应答放在目的端口，这样两者互为反转），
+//! 没有端口的协议的端口为0。
+//!
+//! 超时时间参考Linux的默认值，每经过一个数据包就重新计算。过期的连接在查找时被忽略，
+//! 并在登记新连接时被回收。
+
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use smoltcp::wire::{Icmpv4Message, Icmpv4Packet, IpProtocol, Ipv4Address, Ipv4Packet, TcpPacket};
+use system_error::SystemError;
+
+use crate::{
+    libs::spinlock::{SpinLock, SpinLockGuard},
+    time::timekeeping::ktime_get_ns,
+};
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// 连接跟踪表最多容纳多少个连接
+pub const NF_CONNTRACK_MAX: usize = 4096;
+/// 两次回收过期连接之间至少间隔多久
+const NF_CT_GC_INTERVAL: u64 = NSEC_PER_SEC;
+
+const NF_CT_TCP_TIMEOUT_SYN_SENT: u64 = 120 * NSEC_PER_SEC;
+const NF_CT_TCP_TIMEOUT_ESTABLISHED: u64 = 5 * 24 * 3600 * NSEC_PER_SEC;
+const NF_CT_TCP_TIMEOUT_TIME_WAIT: u64 = 120 * NSEC_PER_SEC;
+const NF_CT_TCP_TIMEOUT_CLOSE: u64 = 10 * NSEC_PER_SEC;
+const NF_CT_UDP_TIMEOUT: u64 = 30 * NSEC_PER_SEC;
+/// 双向都有数据的UDP连接
+const NF_CT_UDP_TIMEOUT_STREAM: u64 = 120 * NSEC_PER_SEC;
+const NF_CT_ICMP_TIMEOUT: u64 = 30 * NSEC_PER_SEC;
+const NF_CT_GENERIC_TIMEOUT: u64 = 600 * NSEC_PER_SEC;
+
+/// 连接的五元组
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NfConnTuple {
+    pub src: Ipv4Address,
+    pub dst: Ipv4Address,
+    /// IP协议号
+    pub protocol: u8,
+    pub sport: u16,
+    pub dport: u16,
+}
+
+impl NfConnTuple {
+    /// 从数据包中解析元组
+    ///
+    /// 分片、长度不足的数据包，以及回显之外的ICMP报文返回None，这些数据包不会被跟踪
+    pub fn parse(ip: &Ipv4Packet<&[u8]>) -> Option<Self> {
+        if ip.frag_offset() != 0 || ip.more_frags() {
+            return None;
+        }
+        let payload = ip.payload();
+        let (sport, dport) = match ip.next_header() {
+            IpProtocol::Tcp => {
+                let tcp = TcpPacket::new_checked(payload).ok()?;
+                (tcp.src_port(), tcp.dst_port())
+            }
+            IpProtocol::Udp => {
+                // UDP头部的前4个字节就是源端口和目的端口
+                if payload.len() < 8 {
+                    return None;
+                }
+                (
+                    u16::from_be_bytes([payload[0], payload[1]]),
+                    u16::from_be_bytes([payload[2], payload[3]]),
+                )
+            }
+            IpProtocol::Icmp => {
+                let icmp = Icmpv4Packet::new_checked(payload).ok()?;
+                match icmp.msg_type() {
+                    Icmpv4Message::EchoRequest => (icmp.echo_ident(), 0),
+                    Icmpv4Message::EchoReply => (0, icmp.echo_ident()),
+                    _ => return None,
+                }
+            }
+            _ => (0, 0),
+        };
+        Some(Self {
+            src: ip.src_addr(),
+            dst: ip.dst_addr(),
+            protocol: ip.next_header().into(),
+            sport,
+            dport,
+        })
+    }
+
+    /// 另一个方向的元组
+    pub fn invert(&self) -> Self {
+        Self {
+            src: self.dst,
+            dst: self.src,
+            protocol: self.protocol,
+            sport: self.dport,
+            dport: self.sport,
+        }
+    }
+}
+
+/// 数据包相对于连接的方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NfConnDir {
+    /// 与发起连接的数据包同向
+    Original,
+    Reply,
+}
+
+/// TCP连接的状态，只用来决定超时时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NfConnTcpState {
+    SynSent,
+    Established,
+    /// 已经出现过FIN
+    TimeWait,
+    /// 已经出现过RST
+    Close,
+}
+
+#[derive(Debug, Clone)]
+pub struct NfConn {
+    /// 原方向的元组，即转换之前的第一个数据包
+    pub orig: NfConnTuple,
+    /// 回复方向的元组，即转换之后的第一个数据包的反转
+    pub reply: NfConnTuple,
+    /// 做地址转换的网卡id
+    pub nic_id: usize,
+    /// 是否由masquerade转换，网卡的地址改变时这些连接会被删除
+    pub masquerade: bool,
+    /// 是否收到过回复方向的数据包
+    replied: bool,
+    tcp_state: NfConnTcpState,
+    /// 过期的时刻，单调时间的纳秒数
+    expires: u64,
+}
+
+impl NfConn {
+    pub fn new(orig: NfConnTuple, reply: NfConnTuple, nic_id: usize, masquerade: bool) -> Self {
+        Self {
+            orig,
+            reply,
+            nic_id,
+            masquerade,
+            replied: false,
+            tcp_state: NfConnTcpState::SynSent,
+            expires: 0,
+        }
+    }
+
+    /// 根据经过的数据包更新连接的状态，并重新计算过期时刻
+    fn update(&mut self, dir: NfConnDir, ip: &Ipv4Packet<&[u8]>, now: u64) {
+        if dir == NfConnDir::Reply {
+            self.replied = true;
+        }
+        let protocol = IpProtocol::from(self.orig.protocol);
+        if protocol == IpProtocol::Tcp {
+            if let Ok(tcp) = TcpPacket::new_checked(ip.payload()) {
+                self.tcp_state = match self.tcp_state {
+                    _ if tcp.rst() => NfConnTcpState::Close,
+                    NfConnTcpState::Close => NfConnTcpState::Close,
+                    _ if tcp.fin() => NfConnTcpState::TimeWait,
+                    NfConnTcpState::SynSent if self.replied => NfConnTcpState::Established,
+                    state => state,
+                };
+            }
+        }
+
+        let timeout = match protocol {
+            IpProtocol::Tcp => match self.tcp_state {
+                NfConnTcpState::SynSent => NF_CT_TCP_TIMEOUT_SYN_SENT,
+                NfConnTcpState::Established => NF_CT_TCP_TIMEOUT_ESTABLISHED,
+                NfConnTcpState::TimeWait => NF_CT_TCP_TIMEOUT_TIME_WAIT,
+                NfConnTcpState::Close => NF_CT_TCP_TIMEOUT_CLOSE,
+            },
+            IpProtocol::Udp if self.replied => NF_CT_UDP_TIMEOUT_STREAM,
+            IpProtocol::Udp => NF_CT_UDP_TIMEOUT,
+            IpProtocol::Icmp => NF_CT_ICMP_TIMEOUT,
+            _ => NF_CT_GENERIC_TIMEOUT,
+        };
+        self.expires = now + timeout;
+    }
+
+    #[inline]
+    fn expired(&self, now: u64) -> bool {
+        now >= self.expires
+    }
+}
+
+/// 连接跟踪表
+#[derive(Debug)]
+pub struct NfConnTable {
+    conns: BTreeMap<u64, NfConn>,
+    /// 元组 -> (连接id, 元组的方向)
+    tuples: BTreeMap<NfConnTuple, (u64, NfConnDir)>,
+    next_id: u64,
+    /// 上一次回收过期连接的时刻
+    last_gc: u64,
+}
+
+static NF_CONNTRACK: SpinLock<NfConnTable> = SpinLock::new(NfConnTable {
+    conns: BTreeMap::new(),
+    tuples: BTreeMap::new(),
+    next_id: 0,
+    last_gc: 0,
+});
+
+/// 连接跟踪表中的连接数，为0时收发路径不需要查表
+static NF_CONNTRACK_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 连接跟踪表中的连接数（包括尚未回收的过期连接）
+#[inline]
+pub fn nf_conntrack_count() -> usize {
+    NF_CONNTRACK_COUNT.load(Ordering::Relaxed)
+}
+
+/// 锁住连接跟踪表，用于需要在同一个临界区内完成查找和登记的操作
+pub fn nf_conntrack_lock() -> SpinLockGuard<'static, NfConnTable> {
+    NF_CONNTRACK.lock_irqsave()
+}
+
+/// 查找数据包所属的连接，并根据数据包更新连接的状态
+///
+/// ## 返回值
+///
+/// 数据包属于某个连接时，返回转换之后的数据包应有的元组，即另一个方向的元组的反转
+pub fn nf_conntrack_in(tuple: &NfConnTuple, ip: &Ipv4Packet<&[u8]>) -> Option<NfConnTuple> {
+    if nf_conntrack_count() == 0 {
+        return None;
+    }
+    nf_conntrack_lock().lookup(tuple, ip, ktime_get_ns())
+}
+
+/// 删除某个网卡上由masquerade转换的所有连接
+pub fn nf_conntrack_flush_masquerade(nic_id: usize) {
+    nf_conntrack_lock().retain(|conn| !(conn.masquerade && conn.nic_id == nic_id));
+}
+
+impl NfConnTable {
+    fn lookup(
+        &mut self,
+        tuple: &NfConnTuple,
+        ip: &Ipv4Packet<&[u8]>,
+        now: u64,
+    ) -> Option<NfConnTuple> {
+        let (id, dir) = *self.tuples.get(tuple)?;
+        let conn = self.conns.get_mut(&id)?;
+        if conn.expired(now) {
+            self.remove(id);
+            return None;
+        }
+        conn.update(dir, ip, now);
+        Some(match dir {
+            NfConnDir::Original => conn.reply.invert(),
+            NfConnDir::Reply => conn.orig.invert(),
+        })
+    }
+
+    /// 元组是否已经被某个未过期的连接占用
+    pub fn is_taken(&self, tuple: &NfConnTuple) -> bool {
+        let now = ktime_get_ns();
+        self.tuples
+            .get(tuple)
+            .and_then(|(id, _)| self.conns.get(id))
+            .is_some_and(|conn| !conn.expired(now))
+    }
+
+    /// 登记一个新的连接，`ip`是连接的第一个数据包（转换之前）
+    ///
+    /// ## 错误
+    ///
+    /// - `EEXIST`: 连接的某个元组已经被占用
+    /// - `ENOBUFS`: 回收过期连接之后表仍然是满的
+    pub fn insert(&mut self, mut conn: NfConn, ip: &Ipv4Packet<&[u8]>) -> Result<(), SystemError> {
+        let now = ktime_get_ns();
+        if self.conns.len() >= NF_CONNTRACK_MAX || now >= self.last_gc + NF_CT_GC_INTERVAL {
+            self.last_gc = now;
+            self.retain(|conn| !conn.expired(now));
+        }
+        if self.conns.len() >= NF_CONNTRACK_MAX {
+            return Err(SystemError::ENOBUFS);
+        }
+        if self.is_taken(&conn.orig) || self.is_taken(&conn.reply) {
+            return Err(SystemError::EEXIST);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        conn.update(NfConnDir::Original, ip, now);
+        // 占用这两个元组的过期连接会被覆盖，需要先删掉
+        for tuple in [conn.orig, conn.reply] {
+            if let Some((old, _)) = self.tuples.get(&tuple).copied() {
+                self.remove(old);
+            }
+        }
+        self.tuples.insert(conn.orig, (id, NfConnDir::Original));
+        self.tuples.insert(conn.reply, (id, NfConnDir::Reply));
+        self.conns.insert(id, conn);
+        NF_CONNTRACK_COUNT.store(self.conns.len(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn remove(&mut self, id: u64) {
+        if let Some(conn) = self.conns.remove(&id) {
+            self.tuples.remove(&conn.orig);
+            self.tuples.remove(&conn.reply);
+        }
+        NF_CONNTRACK_COUNT.store(self.conns.len(), Ordering::Relaxed);
+    }
+
+    /// 只保留`f`返回true的连接
+    fn retain<F: FnMut(&NfConn) -> bool>(&mut self, mut f: F) {
+        let tuples = &mut self.tuples;
+        self.conns.retain(|_, conn| {
+            let keep = f(conn);
+            if !keep {
+                tuples.remove(&conn.orig);
+                tuples.remove(&conn.reply);
+            }
+            keep
+        });
+        NF_CONNTRACK_COUNT.store(self.conns.len(), Ordering::Relaxed);
+    }
+}
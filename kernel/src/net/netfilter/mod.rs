@@ -0,0 +1,454 @@
+//! netfilter-lite：一个简化的、nftables风格的防火墙
+//!
+//! 规则组织为 表 -> 链 -> 规则：
+//!
+//! - 表只是链的命名空间；
+//! - 基础链挂在某个钩子上，有优先级（越小越先执行）和默认策略；
+//! - 链中的规则按顺序匹配，第一条匹配的规则决定该链的结果，没有规则匹配时使用链的默认策略。
+//!
+//! 同一个钩子上的多条链按优先级依次执行，任何一条链给出drop/reject时数据包就被丢弃，
+//! 与nftables一样，accept只结束当前链。规则通过`NETLINK_NETFILTER`套接字配置，见[`nfnetlink`]。
+//!
+//! 钩子位于网卡的收发路径上（见`driver::net::packet_tap`）：
+//!
+//! - 接收：PREROUTING -> INPUT
+//! - 发送：OUTPUT -> POSTROUTING
+//!
+//! 协议栈目前不转发数据包，因此FORWARD链可以配置，但不会被执行。
+//! 目前只过滤IPv4数据包，其他数据包（ARP、IPv6等）总是放行。
+//!
+//! POSTROUTING链中的规则还可以给出snat/masquerade结果，对连接做源地址转换，见[`nat`]。
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, Icmpv4DstUnreachable, Icmpv4Message, Icmpv4Packet, IpProtocol,
+    Ipv4Address, Ipv4Cidr, Ipv4Packet, TcpPacket,
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::net::NetDevice,
+    libs::{rwlock::RwLock, spinlock::SpinLock},
+};
+
+use self::{
+    conntrack::nf_conntrack_count,
+    nat::{nf_nat_in, nf_nat_out, NfNatTarget},
+};
+
+pub mod conntrack;
+pub mod nat;
+pub mod nfnetlink;
+
+/// 钩子的数量
+const NF_HOOK_NUM: usize = 5;
+/// 最多缓存多少个待发送的reject回复，超出时直接丢弃
+const NF_MAX_PENDING_REJECTS: usize = 64;
+/// 回复报文的TTL
+const NF_REJECT_TTL: u8 = 64;
+
+/// netfilter的钩子，数值与Linux的`enum nf_inet_hooks`相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum NfHook {
+    PreRouting = 0,
+    LocalIn = 1,
+    Forward = 2,
+    LocalOut = 3,
+    PostRouting = 4,
+}
+
+/// 规则或者链的默认策略给出的结果，数值与Linux的`NF_DROP`、`NF_ACCEPT`相同，reject是扩展
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum NfVerdict {
+    Drop = 0,
+    Accept = 1,
+    /// 丢弃，并向发送方回复TCP RST或者ICMP端口不可达
+    Reject = 2,
+    /// 放行，并把源地址转换为发送网卡的地址，只能用于POSTROUTING链中的规则
+    Masquerade = 3,
+    /// 放行，并把源地址转换为规则指定的地址，只能用于POSTROUTING链中的规则
+    Snat = 4,
+}
+
+impl NfVerdict {
+    /// 是否是地址转换的结果
+    #[inline]
+    pub fn is_nat(&self) -> bool {
+        matches!(self, NfVerdict::Masquerade | NfVerdict::Snat)
+    }
+}
+
+/// 规则的匹配条件，为None的字段匹配任意值
+#[derive(Debug, Clone, Default)]
+pub struct NfRuleMatch {
+    pub src: Option<Ipv4Cidr>,
+    pub dst: Option<Ipv4Cidr>,
+    /// IP协议号
+    pub protocol: Option<u8>,
+    /// 源端口的范围（闭区间），只对TCP、UDP有效
+    pub sport: Option<(u16, u16)>,
+    /// 目的端口的范围（闭区间），只对TCP、UDP有效
+    pub dport: Option<(u16, u16)>,
+    /// 接收数据包的网卡id
+    pub iif: Option<usize>,
+    /// 发送数据包的网卡id
+    pub oif: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NfRule {
+    /// 规则的句柄，在表内唯一，用于删除规则
+    pub handle: u64,
+    pub matches: NfRuleMatch,
+    pub verdict: NfVerdict,
+    /// snat的目标地址，只对`NfVerdict::Snat`有效
+    pub nat_addr: Option<Ipv4Address>,
+}
+
+impl NfRule {
+    /// 规则给出的地址转换目标，不是地址转换的规则返回None
+    pub fn nat_target(&self) -> Option<NfNatTarget> {
+        match self.verdict {
+            NfVerdict::Masquerade => Some(NfNatTarget::Masquerade),
+            NfVerdict::Snat => self.nat_addr.map(NfNatTarget::Snat),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NfChain {
+    pub name: String,
+    pub hook: NfHook,
+    pub priority: i32,
+    pub policy: NfVerdict,
+    pub rules: Vec<NfRule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NfTable {
+    pub name: String,
+    pub chains: Vec<NfChain>,
+    /// 下一条规则的句柄
+    next_handle: u64,
+}
+
+impl NfTable {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            chains: Vec::new(),
+            next_handle: 1,
+        }
+    }
+
+    pub fn chain_mut(&mut self, name: &str) -> Option<&mut NfChain> {
+        self.chains.iter_mut().find(|c| c.name == name)
+    }
+
+    /// 分配一个规则句柄
+    pub fn alloc_handle(&mut self) -> u64 {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        handle
+    }
+}
+
+/// 所有的表
+static NF_TABLES: RwLock<Vec<NfTable>> = RwLock::new(Vec::new());
+
+/// 每个钩子上挂了多少条链，为0时跳过该钩子，不需要获取锁
+static NF_HOOK_CHAINS: [AtomicUsize; NF_HOOK_NUM] = [const { AtomicUsize::new(0) }; NF_HOOK_NUM];
+
+/// 等待发送的reject回复：(网卡id, 以太网帧)
+static NF_PENDING_REJECTS: SpinLock<Vec<(usize, Vec<u8>)>> = SpinLock::new(Vec::new());
+
+/// 修改规则集
+///
+/// 闭包返回之后重新统计每个钩子上的链的数量
+pub fn nf_tables_update<R, F>(f: F) -> Result<R, SystemError>
+where
+    F: FnOnce(&mut Vec<NfTable>) -> Result<R, SystemError>,
+{
+    let mut tables = NF_TABLES.write_irqsave();
+    let result = f(&mut tables);
+
+    let mut counts = [0usize; NF_HOOK_NUM];
+    for chain in tables.iter().flat_map(|t| t.chains.iter()) {
+        counts[chain.hook as usize] += 1;
+    }
+    for (slot, count) in NF_HOOK_CHAINS.iter().zip(counts) {
+        slot.store(count, Ordering::SeqCst);
+    }
+    result
+}
+
+/// 读取规则集
+pub fn nf_tables_read<R, F>(f: F) -> R
+where
+    F: FnOnce(&[NfTable]) -> R,
+{
+    f(&NF_TABLES.read_irqsave())
+}
+
+/// 从IPv4数据包中解析出的、规则匹配需要的信息
+struct NfPacketInfo {
+    src: Ipv4Address,
+    dst: Ipv4Address,
+    protocol: u8,
+    sport: Option<u16>,
+    dport: Option<u16>,
+}
+
+impl NfPacketInfo {
+    fn parse(ip: &Ipv4Packet<&[u8]>) -> Self {
+        let protocol: u8 = ip.next_header().into();
+        let payload = ip.payload();
+        // 只有第一个分片带有端口
+        let (sport, dport) = match ip.next_header() {
+            IpProtocol::Tcp | IpProtocol::Udp if ip.frag_offset() == 0 && payload.len() >= 4 => (
+                Some(u16::from_be_bytes([payload[0], payload[1]])),
+                Some(u16::from_be_bytes([payload[2], payload[3]])),
+            ),
+            _ => (None, None),
+        };
+        Self {
+            src: ip.src_addr(),
+            dst: ip.dst_addr(),
+            protocol,
+            sport,
+            dport,
+        }
+    }
+}
+
+impl NfRuleMatch {
+    fn matches(&self, pkt: &NfPacketInfo, iif: Option<usize>, oif: Option<usize>) -> bool {
+        let in_range = |port: Option<u16>, range: Option<(u16, u16)>| match range {
+            None => true,
+            Some((lo, hi)) => port.is_some_and(|p| p >= lo && p <= hi),
+        };
+        self.src.map_or(true, |c| c.contains_addr(&pkt.src))
+            && self.dst.map_or(true, |c| c.contains_addr(&pkt.dst))
+            && self.protocol.map_or(true, |p| p == pkt.protocol)
+            && in_range(pkt.sport, self.sport)
+            && in_range(pkt.dport, self.dport)
+            && self.iif.map_or(true, |i| iif == Some(i))
+            && self.oif.map_or(true, |o| oif == Some(o))
+    }
+}
+
+/// 在一个钩子上执行所有的链
+///
+/// 地址转换的结果会结束当前链，对于链的执行顺序来说与accept相同。
+/// 返回值的第二项是第一个匹配到的地址转换目标
+fn nf_hook_slow(
+    hook: NfHook,
+    pkt: &NfPacketInfo,
+    iif: Option<usize>,
+    oif: Option<usize>,
+) -> (NfVerdict, Option<NfNatTarget>) {
+    let tables = NF_TABLES.read_irqsave();
+    let mut chains: Vec<&NfChain> = tables
+        .iter()
+        .flat_map(|t| t.chains.iter())
+        .filter(|c| c.hook == hook)
+        .collect();
+    chains.sort_by_key(|c| c.priority);
+
+    let mut nat = None;
+    for chain in chains {
+        let verdict = match chain
+            .rules
+            .iter()
+            .find(|rule| rule.matches.matches(pkt, iif, oif))
+        {
+            Some(rule) if rule.verdict.is_nat() => {
+                nat = nat.or(rule.nat_target());
+                NfVerdict::Accept
+            }
+            Some(rule) => rule.verdict,
+            None => chain.policy,
+        };
+        if verdict != NfVerdict::Accept {
+            return (verdict, None);
+        }
+    }
+    (NfVerdict::Accept, nat)
+}
+
+/// 依次执行`hooks`，返回第一个不是accept的结果，以及放行时的地址转换目标
+fn nf_hooks(
+    hooks: &[NfHook],
+    frame: &[u8],
+    iif: Option<usize>,
+    oif: Option<usize>,
+) -> (NfVerdict, Option<NfNatTarget>) {
+    if hooks
+        .iter()
+        .all(|h| NF_HOOK_CHAINS[*h as usize].load(Ordering::Relaxed) == 0)
+    {
+        return (NfVerdict::Accept, None);
+    }
+    let Ok(eth) = EthernetFrame::new_checked(frame) else {
+        return (NfVerdict::Accept, None);
+    };
+    if eth.ethertype() != EthernetProtocol::Ipv4 {
+        return (NfVerdict::Accept, None);
+    }
+    let Ok(ip) = Ipv4Packet::new_checked(eth.payload()) else {
+        return (NfVerdict::Accept, None);
+    };
+    let pkt = NfPacketInfo::parse(&ip);
+
+    let mut nat = None;
+    for hook in hooks {
+        if NF_HOOK_CHAINS[*hook as usize].load(Ordering::Relaxed) == 0 {
+            continue;
+        }
+        let (verdict, hook_nat) = nf_hook_slow(*hook, &pkt, iif, oif);
+        if verdict != NfVerdict::Accept {
+            return (verdict, None);
+        }
+        nat = nat.or(hook_nat);
+    }
+    (NfVerdict::Accept, nat)
+}
+
+/// 网卡收到一个以太网帧时调用，先还原经过地址转换的连接的回复，再经过PREROUTING和INPUT
+///
+/// ## 返回值
+///
+/// 放行时返回true。reject时会准备好回复，在下一次[`nf_flush_rejects`]时发送
+pub fn nf_hook_rx(nic_id: usize, frame: &mut [u8]) -> bool {
+    nf_nat_in(frame);
+    let (verdict, _) = nf_hooks(
+        &[NfHook::PreRouting, NfHook::LocalIn],
+        frame,
+        Some(nic_id),
+        None,
+    );
+    match verdict {
+        NfVerdict::Accept | NfVerdict::Masquerade | NfVerdict::Snat => true,
+        NfVerdict::Drop => false,
+        NfVerdict::Reject => {
+            if let Some(reply) = nf_build_reject(frame) {
+                let mut pending = NF_PENDING_REJECTS.lock_irqsave();
+                if pending.len() < NF_MAX_PENDING_REJECTS {
+                    pending.push((nic_id, reply));
+                }
+            }
+            false
+        }
+    }
+}
+
+/// 网卡即将发送一个以太网帧时调用，经过OUTPUT和POSTROUTING，放行之后做源地址转换
+///
+/// 本机发出的数据包被reject时，与drop相同
+pub fn nf_hook_tx(nic_id: usize, frame: &mut [u8]) -> bool {
+    let (verdict, nat) = nf_hooks(
+        &[NfHook::LocalOut, NfHook::PostRouting],
+        frame,
+        None,
+        Some(nic_id),
+    );
+    verdict == NfVerdict::Accept && nf_nat_out(nic_id, frame, nat)
+}
+
+/// 发送钩子上是否挂有链，或者有需要转换的连接，都没有时发送路径不需要额外复制数据包
+#[inline]
+pub fn nf_tx_hooks_active() -> bool {
+    NF_HOOK_CHAINS[NfHook::LocalOut as usize].load(Ordering::Relaxed) != 0
+        || NF_HOOK_CHAINS[NfHook::PostRouting as usize].load(Ordering::Relaxed) != 0
+        || nf_conntrack_count() != 0
+}
+
+/// 发送所有等待中的reject回复，在轮询网卡之后调用
+pub fn nf_flush_rejects(devices: &BTreeMap<usize, Arc<dyn NetDevice>>) {
+    let pending = core::mem::take(&mut *NF_PENDING_REJECTS.lock_irqsave());
+    for (nic_id, reply) in pending {
+        if let Some(iface) = devices.get(&nic_id) {
+            iface.poll_xmit(&reply).ok();
+        }
+    }
+}
+
+/// 为被reject的数据包构造回复：TCP回复RST，其他协议回复ICMP端口不可达
+fn nf_build_reject(frame: &[u8]) -> Option<Vec<u8>> {
+    let eth = EthernetFrame::new_checked(frame).ok()?;
+    let ip = Ipv4Packet::new_checked(eth.payload()).ok()?;
+    // 不回复ICMP报文和广播、组播，避免报文风暴
+    if ip.next_header() == IpProtocol::Icmp
+        || ip.dst_addr().is_broadcast()
+        || ip.dst_addr().is_multicast()
+    {
+        return None;
+    }
+
+    let (protocol, payload) = if ip.next_header() == IpProtocol::Tcp {
+        (IpProtocol::Tcp, nf_build_tcp_reset(&ip)?)
+    } else {
+        (IpProtocol::Icmp, nf_build_port_unreachable(&ip))
+    };
+
+    let ip_len = 20 + payload.len();
+    let mut reply = alloc::vec![0u8; 14 + ip_len];
+    let mut reply_eth = EthernetFrame::new_unchecked(&mut reply[..]);
+    reply_eth.set_dst_addr(eth.src_addr());
+    reply_eth.set_src_addr(eth.dst_addr());
+    reply_eth.set_ethertype(EthernetProtocol::Ipv4);
+
+    let mut reply_ip = Ipv4Packet::new_unchecked(reply_eth.payload_mut());
+    reply_ip.set_version(4);
+    reply_ip.set_header_len(20);
+    reply_ip.set_total_len(ip_len as u16);
+    reply_ip.set_dont_frag(true);
+    reply_ip.set_hop_limit(NF_REJECT_TTL);
+    reply_ip.set_next_header(protocol);
+    reply_ip.set_src_addr(ip.dst_addr());
+    reply_ip.set_dst_addr(ip.src_addr());
+    reply_ip.payload_mut().copy_from_slice(&payload);
+    if protocol == IpProtocol::Tcp {
+        let mut tcp = TcpPacket::new_unchecked(reply_ip.payload_mut());
+        tcp.fill_checksum(&ip.dst_addr().into(), &ip.src_addr().into());
+    }
+    reply_ip.fill_checksum();
+    Some(reply)
+}
+
+fn nf_build_tcp_reset(ip: &Ipv4Packet<&[u8]>) -> Option<Vec<u8>> {
+    let tcp = TcpPacket::new_checked(ip.payload()).ok()?;
+    if tcp.rst() {
+        return None;
+    }
+    let mut reply = alloc::vec![0u8; 20];
+    let mut rst = TcpPacket::new_unchecked(&mut reply[..]);
+    rst.set_src_port(tcp.dst_port());
+    rst.set_dst_port(tcp.src_port());
+    rst.set_header_len(20);
+    rst.clear_flags();
+    rst.set_rst(true);
+    if tcp.ack() {
+        rst.set_seq_number(tcp.ack_number());
+    } else {
+        let seg_len = tcp.payload().len() + usize::from(tcp.syn()) + usize::from(tcp.fin());
+        rst.set_ack(true);
+        rst.set_ack_number(tcp.seq_number() + seg_len);
+    }
+    Some(reply)
+}
+
+fn nf_build_port_unreachable(ip: &Ipv4Packet<&[u8]>) -> Vec<u8> {
+    // ICMP差错报文携带原数据包的IP头部以及其后的8个字节
+    let quote_len = core::cmp::min(ip.header_len() as usize + 8, ip.total_len() as usize);
+    let quote = &ip.as_ref()[..quote_len];
+    let mut reply = alloc::vec![0u8; 8 + quote_len];
+    let mut icmp = Icmpv4Packet::new_unchecked(&mut reply[..]);
+    icmp.set_msg_type(Icmpv4Message::DstUnreachable);
+    icmp.set_msg_code(Icmpv4DstUnreachable::PortUnreachable.into());
+    icmp.data_mut().copy_from_slice(quote);
+    icmp.fill_checksum();
+    reply
+}
@@ -0,0 +1,207 @@
+//! 源地址转换（SNAT）与伪装（masquerade）
+//!
+//! POSTROUTING链中的规则可以给出`snat`或`masquerade`结果：前者把源地址改为规则指定的地址，
+//! 后者改为发送网卡的地址。连接的第一个数据包经过规则之后被登记到连接跟踪表（见[`super::conntrack`]），
+//! 同一连接的后续数据包不再查找NAT规则，直接使用登记的转换；回复方向的数据包在经过PREROUTING链之前被还原。
+//! 转换后的元组与已有的连接冲突时，为源端口（ICMP为回显标识符）另选一个空闲的值。
+//!
+//! 协议栈目前不转发数据包，因此只有本机发出的连接会被转换。分片的数据包不做转换。
+
+use alloc::collections::BTreeMap;
+use smoltcp::wire::{
+    EthernetFrame, EthernetProtocol, Icmpv4Message, Icmpv4Packet, IpAddress, IpProtocol,
+    Ipv4Address, Ipv4Packet, TcpPacket, UdpPacket,
+};
+
+use crate::libs::rwlock::RwLock;
+
+use super::conntrack::{
+    nf_conntrack_count, nf_conntrack_flush_masquerade, nf_conntrack_in, nf_conntrack_lock, NfConn,
+    NfConnTuple,
+};
+
+/// 为TCP、UDP另选源端口时使用的范围
+const NF_NAT_PORT_MIN: u16 = 1024;
+const NF_NAT_PORT_MAX: u16 = 65535;
+
+/// 地址转换的目标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NfNatTarget {
+    /// 使用发送网卡的地址
+    Masquerade,
+    /// 使用指定的地址
+    Snat(Ipv4Address),
+}
+
+/// 各个网卡的IPv4地址，masquerade在发送路径上使用
+///
+/// 发送数据包时网卡的`Interface`已经被锁住，不能再从中读取地址，因此在地址改变时把它记录在这里
+static NF_NAT_IFACE_ADDRS: RwLock<BTreeMap<usize, Ipv4Address>> = RwLock::new(BTreeMap::new());
+
+/// 网卡的IPv4地址改变时调用
+///
+/// 与Linux相同，地址改变之后，这个网卡上由masquerade转换的连接都会被删除
+pub fn nf_nat_iface_addr_changed(nic_id: usize, addr: Ipv4Address) {
+    let old = if addr.is_unspecified() {
+        NF_NAT_IFACE_ADDRS.write_irqsave().remove(&nic_id)
+    } else {
+        NF_NAT_IFACE_ADDRS.write_irqsave().insert(nic_id, addr)
+    };
+    if old.is_some_and(|old| old != addr) {
+        nf_conntrack_flush_masquerade(nic_id);
+    }
+}
+
+/// 对发送的数据包做源地址转换，在POSTROUTING链放行之后调用
+///
+/// ## 参数
+///
+/// - `nic_id`: 发送数据包的网卡id
+/// - `frame`: 以太网帧，转换时就地修改
+/// - `target`: POSTROUTING链中匹配到的NAT规则给出的目标
+///
+/// ## 返回值
+///
+/// 数据包需要转换但无法转换（网卡没有地址、没有空闲端口、连接跟踪表已满）时返回false，此时应当丢弃数据包
+pub(super) fn nf_nat_out(nic_id: usize, frame: &mut [u8], target: Option<NfNatTarget>) -> bool {
+    if target.is_none() && nf_conntrack_count() == 0 {
+        return true;
+    }
+    let Some(tuple) = nf_nat_parse(frame) else {
+        return true;
+    };
+
+    // 已经登记的连接直接使用登记的转换，不再查看规则
+    if let Some(new_tuple) = nf_conntrack_in(&tuple, &nf_ipv4_view(frame)) {
+        if new_tuple != tuple {
+            nf_nat_mangle(frame, &new_tuple);
+        }
+        return true;
+    }
+    let Some(target) = target else {
+        return true;
+    };
+
+    let src = match target {
+        NfNatTarget::Snat(addr) => addr,
+        NfNatTarget::Masquerade => match NF_NAT_IFACE_ADDRS.read_irqsave().get(&nic_id) {
+            Some(addr) => *addr,
+            None => return false,
+        },
+    };
+    let mut table = nf_conntrack_lock();
+    let Some(new_tuple) = nf_nat_unique_tuple(&tuple, src, |t| table.is_taken(&t.invert())) else {
+        return false;
+    };
+    let conn = NfConn::new(
+        tuple,
+        new_tuple.invert(),
+        nic_id,
+        target == NfNatTarget::Masquerade,
+    );
+    if table.insert(conn, &nf_ipv4_view(frame)).is_err() {
+        return false;
+    }
+    drop(table);
+
+    if new_tuple != tuple {
+        nf_nat_mangle(frame, &new_tuple);
+    }
+    true
+}
+
+/// 还原接收到的回复方向的数据包，在PREROUTING链之前调用
+pub(super) fn nf_nat_in(frame: &mut [u8]) {
+    if nf_conntrack_count() == 0 {
+        return;
+    }
+    let Some(tuple) = nf_nat_parse(frame) else {
+        return;
+    };
+    if let Some(new_tuple) = nf_conntrack_in(&tuple, &nf_ipv4_view(frame)) {
+        if new_tuple != tuple {
+            nf_nat_mangle(frame, &new_tuple);
+        }
+    }
+}
+
+/// 解析以太网帧中的IPv4数据包的元组，不是IPv4数据包或者不能跟踪时返回None
+fn nf_nat_parse(frame: &[u8]) -> Option<NfConnTuple> {
+    let eth = EthernetFrame::new_checked(frame).ok()?;
+    if eth.ethertype() != EthernetProtocol::Ipv4 {
+        return None;
+    }
+    let ip = Ipv4Packet::new_checked(eth.payload()).ok()?;
+    NfConnTuple::parse(&ip)
+}
+
+/// 以只读的方式查看帧中的IPv4数据包，调用前帧已经通过[`nf_nat_parse`]的检查
+fn nf_ipv4_view(frame: &[u8]) -> Ipv4Packet<&[u8]> {
+    Ipv4Packet::new_unchecked(&frame[EthernetFrame::<&[u8]>::header_len()..])
+}
+
+/// 为转换之后的数据包选择一个回复方向未被占用的元组
+///
+/// 优先保留原来的源端口，被占用时在可用范围内依次尝试。没有端口的协议不能另选，冲突时返回None
+fn nf_nat_unique_tuple<F>(tuple: &NfConnTuple, src: Ipv4Address, taken: F) -> Option<NfConnTuple>
+where
+    F: Fn(&NfConnTuple) -> bool,
+{
+    let mut new_tuple = NfConnTuple { src, ..*tuple };
+    if !taken(&new_tuple) {
+        return Some(new_tuple);
+    }
+
+    let (min, max) = match IpProtocol::from(tuple.protocol) {
+        IpProtocol::Tcp | IpProtocol::Udp => (NF_NAT_PORT_MIN, NF_NAT_PORT_MAX),
+        // 回显请求的标识符可以取任意值
+        IpProtocol::Icmp => (0, u16::MAX),
+        _ => return None,
+    };
+    let range = (max - min) as u32 + 1;
+    // 从一个由元组决定的位置开始查找，避免每次都从头扫描已经被占用的端口
+    let start =
+        (u32::from_be_bytes(tuple.dst.0) ^ ((tuple.dport as u32) << 16) ^ tuple.sport as u32)
+            % range;
+    (0..range).find_map(|i| {
+        new_tuple.sport = min + ((start + i) % range) as u16;
+        (!taken(&new_tuple)).then_some(new_tuple)
+    })
+}
+
+/// 把帧中的IPv4数据包改写为`tuple`所描述的地址和端口，并重新计算校验和
+fn nf_nat_mangle(frame: &mut [u8], tuple: &NfConnTuple) {
+    let mut eth = EthernetFrame::new_unchecked(frame);
+    let mut ip = Ipv4Packet::new_unchecked(eth.payload_mut());
+    ip.set_src_addr(tuple.src);
+    ip.set_dst_addr(tuple.dst);
+    let (src, dst) = (IpAddress::Ipv4(tuple.src), IpAddress::Ipv4(tuple.dst));
+    match IpProtocol::from(tuple.protocol) {
+        IpProtocol::Tcp => {
+            let mut tcp = TcpPacket::new_unchecked(ip.payload_mut());
+            tcp.set_src_port(tuple.sport);
+            tcp.set_dst_port(tuple.dport);
+            tcp.fill_checksum(&src, &dst);
+        }
+        IpProtocol::Udp => {
+            let mut udp = UdpPacket::new_unchecked(ip.payload_mut());
+            udp.set_src_port(tuple.sport);
+            udp.set_dst_port(tuple.dport);
+            // 校验和为0表示发送方没有计算校验和，保持不变
+            if udp.checksum() != 0 {
+                udp.fill_checksum(&src, &dst);
+            }
+        }
+        IpProtocol::Icmp => {
+            let mut icmp = Icmpv4Packet::new_unchecked(ip.payload_mut());
+            match icmp.msg_type() {
+                Icmpv4Message::EchoRequest => icmp.set_echo_ident(tuple.sport),
+                Icmpv4Message::EchoReply => icmp.set_echo_ident(tuple.dport),
+                _ => {}
+            }
+            icmp.fill_checksum();
+        }
+        _ => {}
+    }
+    ip.fill_checksum();
+}
@@ -0,0 +1,505 @@
+//! `NETLINK_NETFILTER`：通过netlink配置netfilter-lite的规则集
+//!
+//! 消息格式与nftables相同：`nlmsghdr`之后是4字节的`nfgenmsg`，再之后是若干属性（`nlattr`），
+//! 消息类型为`(NFNL_SUBSYS_NFTABLES << 8) | NFT_MSG_*`。表、链的属性编号与Linux相同，
+//! 整数属性使用网络字节序。
+//!
+//! 规则不使用nftables的表达式虚拟机，而是用`NFTA_RULE_MATCH`给出匹配条件、
+//! `NFTA_RULE_VERDICT`给出结果，因此不兼容nft命令行工具。结果为snat的规则
+//! 还需要用`NFTA_RULE_NAT_ADDR`给出目标地址。
+
+use alloc::{string::String, vec::Vec};
+use num_traits::FromPrimitive;
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
+use system_error::SystemError;
+
+use crate::{
+    net::nlmsg::{
+        attr_str, find_attr, netlink_rcv, nlmsg_done, parse_attrs, NlMsgBuilder, NlMsgHdr,
+        NLMSG_HDRLEN, NLM_F_EXCL, NLM_F_MULTI,
+    },
+    process::{cred::CAPFlags, ProcessManager},
+};
+
+use super::{
+    nf_tables_read, nf_tables_update, NfChain, NfHook, NfRule, NfRuleMatch, NfTable, NfVerdict,
+};
+
+/// netfilter的netlink协议号
+pub const NETLINK_NETFILTER: u8 = 12;
+
+const NFGENMSG_LEN: usize = 4;
+
+const NFNL_SUBSYS_NFTABLES: u16 = 10;
+/// 批量消息的开始与结束，nftables把一组修改包装在它们之间
+const NFNL_MSG_BATCH_BEGIN: u16 = 0x10;
+const NFNL_MSG_BATCH_END: u16 = 0x11;
+
+const NFT_MSG_NEWTABLE: u16 = 0;
+const NFT_MSG_GETTABLE: u16 = 1;
+const NFT_MSG_DELTABLE: u16 = 2;
+const NFT_MSG_NEWCHAIN: u16 = 3;
+const NFT_MSG_GETCHAIN: u16 = 4;
+const NFT_MSG_DELCHAIN: u16 = 5;
+const NFT_MSG_NEWRULE: u16 = 6;
+const NFT_MSG_GETRULE: u16 = 7;
+const NFT_MSG_DELRULE: u16 = 8;
+
+const NFTA_TABLE_NAME: u16 = 1;
+
+const NFTA_CHAIN_TABLE: u16 = 1;
+const NFTA_CHAIN_NAME: u16 = 3;
+const NFTA_CHAIN_HOOK: u16 = 4;
+const NFTA_CHAIN_POLICY: u16 = 5;
+
+const NFTA_HOOK_HOOKNUM: u16 = 1;
+const NFTA_HOOK_PRIORITY: u16 = 2;
+
+const NFTA_RULE_TABLE: u16 = 1;
+const NFTA_RULE_CHAIN: u16 = 2;
+const NFTA_RULE_HANDLE: u16 = 3;
+/// 规则的结果，u32
+const NFTA_RULE_VERDICT: u16 = 16;
+/// 规则的匹配条件，嵌套`NFTA_MATCH_*`
+const NFTA_RULE_MATCH: u16 = 17;
+/// snat的目标地址，4字节IPv4地址
+const NFTA_RULE_NAT_ADDR: u16 = 18;
+
+/// 源地址：4字节IPv4地址加1字节前缀长度
+const NFTA_MATCH_SRC: u16 = 1;
+/// 目的地址，格式同`NFTA_MATCH_SRC`
+const NFTA_MATCH_DST: u16 = 2;
+/// IP协议号，u8
+const NFTA_MATCH_PROTO: u16 = 3;
+/// 源端口范围：两个u16，最小值与最大值
+const NFTA_MATCH_SPORT: u16 = 4;
+/// 目的端口范围，格式同`NFTA_MATCH_SPORT`
+const NFTA_MATCH_DPORT: u16 = 5;
+/// 接收网卡的ifindex，u32
+const NFTA_MATCH_IIF: u16 = 6;
+/// 发送网卡的ifindex，u32
+const NFTA_MATCH_OIF: u16 = 7;
+
+fn attr_u32(attrs: &[(u16, &[u8])], ty: u16) -> Result<Option<u32>, SystemError> {
+    find_attr(attrs, ty)
+        .map(|v| {
+            v.try_into()
+                .map(u32::from_be_bytes)
+                .map_err(|_| SystemError::EINVAL)
+        })
+        .transpose()
+}
+
+fn attr_u64(attrs: &[(u16, &[u8])], ty: u16) -> Result<Option<u64>, SystemError> {
+    find_attr(attrs, ty)
+        .map(|v| {
+            v.try_into()
+                .map(u64::from_be_bytes)
+                .map_err(|_| SystemError::EINVAL)
+        })
+        .transpose()
+}
+
+fn attr_cidr(attrs: &[(u16, &[u8])], ty: u16) -> Result<Option<Ipv4Cidr>, SystemError> {
+    let Some(v) = find_attr(attrs, ty) else {
+        return Ok(None);
+    };
+    if v.len() != 5 || v[4] > 32 {
+        return Err(SystemError::EINVAL);
+    }
+    Ok(Some(Ipv4Cidr::new(Ipv4Address::from_bytes(&v[..4]), v[4])))
+}
+
+fn attr_ipv4(attrs: &[(u16, &[u8])], ty: u16) -> Result<Option<Ipv4Address>, SystemError> {
+    let Some(v) = find_attr(attrs, ty) else {
+        return Ok(None);
+    };
+    if v.len() != 4 {
+        return Err(SystemError::EINVAL);
+    }
+    Ok(Some(Ipv4Address::from_bytes(v)))
+}
+
+fn attr_port_range(attrs: &[(u16, &[u8])], ty: u16) -> Result<Option<(u16, u16)>, SystemError> {
+    let Some(v) = find_attr(attrs, ty) else {
+        return Ok(None);
+    };
+    if v.len() != 4 {
+        return Err(SystemError::EINVAL);
+    }
+    let lo = u16::from_be_bytes([v[0], v[1]]);
+    let hi = u16::from_be_bytes([v[2], v[3]]);
+    if lo > hi {
+        return Err(SystemError::EINVAL);
+    }
+    Ok(Some((lo, hi)))
+}
+
+fn attr_verdict(attrs: &[(u16, &[u8])], ty: u16) -> Result<Option<NfVerdict>, SystemError> {
+    attr_u32(attrs, ty)?
+        .map(|v| NfVerdict::from_u32(v).ok_or(SystemError::EINVAL))
+        .transpose()
+}
+
+/// 处理用户态发送的一组netlink消息
+///
+/// ## 参数
+///
+/// - `buf`: 用户态写入的数据，可能包含多条消息
+/// - `portid`: 发送者的端口号，作为回复消息的`nlmsg_pid`
+///
+/// ## 返回值
+///
+/// 需要放入发送者的接收队列的回复消息
+pub fn nfnetlink_rcv(buf: &[u8], portid: u32) -> Vec<Vec<u8>> {
+    netlink_rcv(buf, portid, nfnetlink_rcv_msg)
+}
+
+/// 创建一条nftables消息，包含`nfgenmsg`
+fn nft_msg(msg: u16, flags: u16, req: &NlMsgHdr) -> NlMsgBuilder {
+    let mut builder = NlMsgBuilder::reply((NFNL_SUBSYS_NFTABLES << 8) | msg, flags, req);
+    // AF_INET、版本0、res_id为0
+    builder.put_raw(&[2, 0, 0, 0]);
+    builder
+}
+
+fn nfnetlink_rcv_msg(
+    hdr: &NlMsgHdr,
+    msg: &[u8],
+    replies: &mut Vec<Vec<u8>>,
+) -> Result<(), SystemError> {
+    if hdr.ty == NFNL_MSG_BATCH_BEGIN || hdr.ty == NFNL_MSG_BATCH_END {
+        return Ok(());
+    }
+    if hdr.ty >> 8 != NFNL_SUBSYS_NFTABLES {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+    if msg.len() < NLMSG_HDRLEN + NFGENMSG_LEN {
+        return Err(SystemError::EINVAL);
+    }
+    let attrs = parse_attrs(&msg[NLMSG_HDRLEN + NFGENMSG_LEN..])?;
+
+    let msg_type = hdr.ty & 0xff;
+    let is_get = matches!(
+        msg_type,
+        NFT_MSG_GETTABLE | NFT_MSG_GETCHAIN | NFT_MSG_GETRULE
+    );
+    if !is_get
+        && !ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_NET_ADMIN)
+    {
+        return Err(SystemError::EPERM);
+    }
+
+    match msg_type {
+        NFT_MSG_NEWTABLE => nf_new_table(hdr, &attrs),
+        NFT_MSG_DELTABLE => nf_del_table(&attrs),
+        NFT_MSG_NEWCHAIN => nf_new_chain(hdr, &attrs),
+        NFT_MSG_DELCHAIN => nf_del_chain(&attrs),
+        NFT_MSG_NEWRULE => nf_new_rule(&attrs),
+        NFT_MSG_DELRULE => nf_del_rule(&attrs),
+        NFT_MSG_GETTABLE | NFT_MSG_GETCHAIN | NFT_MSG_GETRULE => {
+            nf_get(hdr, msg_type, &attrs, replies)
+        }
+        _ => Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+    }
+}
+
+fn required_str(attrs: &[(u16, &[u8])], ty: u16) -> Result<String, SystemError> {
+    attr_str(attrs, ty)?.ok_or(SystemError::EINVAL)
+}
+
+fn find_table_mut<'a>(
+    tables: &'a mut [NfTable],
+    name: &str,
+) -> Result<&'a mut NfTable, SystemError> {
+    tables
+        .iter_mut()
+        .find(|t| t.name == name)
+        .ok_or(SystemError::ENOENT)
+}
+
+fn nf_new_table(hdr: &NlMsgHdr, attrs: &[(u16, &[u8])]) -> Result<(), SystemError> {
+    let name = required_str(attrs, NFTA_TABLE_NAME)?;
+    nf_tables_update(|tables| {
+        if tables.iter().any(|t| t.name == name) {
+            if hdr.flags & NLM_F_EXCL != 0 {
+                return Err(SystemError::EEXIST);
+            }
+            return Ok(());
+        }
+        tables.push(NfTable::new(name));
+        Ok(())
+    })
+}
+
+fn nf_del_table(attrs: &[(u16, &[u8])]) -> Result<(), SystemError> {
+    let name = required_str(attrs, NFTA_TABLE_NAME)?;
+    nf_tables_update(|tables| {
+        let idx = tables
+            .iter()
+            .position(|t| t.name == name)
+            .ok_or(SystemError::ENOENT)?;
+        tables.remove(idx);
+        Ok(())
+    })
+}
+
+fn nf_new_chain(hdr: &NlMsgHdr, attrs: &[(u16, &[u8])]) -> Result<(), SystemError> {
+    let table = required_str(attrs, NFTA_CHAIN_TABLE)?;
+    let name = required_str(attrs, NFTA_CHAIN_NAME)?;
+    let policy = attr_verdict(attrs, NFTA_CHAIN_POLICY)?;
+    // 只支持基础链，必须指定钩子
+    let hook_attrs = parse_attrs(find_attr(attrs, NFTA_CHAIN_HOOK).ok_or(SystemError::EINVAL)?)?;
+    let hook = attr_u32(&hook_attrs, NFTA_HOOK_HOOKNUM)?
+        .and_then(NfHook::from_u32)
+        .ok_or(SystemError::EINVAL)?;
+    let priority = attr_u32(&hook_attrs, NFTA_HOOK_PRIORITY)?.unwrap_or(0) as i32;
+    // 地址转换需要具体的目标，不能作为默认策略
+    if policy.is_some_and(|p| p.is_nat()) {
+        return Err(SystemError::EINVAL);
+    }
+
+    nf_tables_update(|tables| {
+        let table = find_table_mut(tables, &table)?;
+        if let Some(chain) = table.chain_mut(&name) {
+            if hdr.flags & NLM_F_EXCL != 0 {
+                return Err(SystemError::EEXIST);
+            }
+            // 已经存在的链只能修改默认策略
+            if chain.hook != hook || chain.priority != priority {
+                return Err(SystemError::EBUSY);
+            }
+            if let Some(policy) = policy {
+                chain.policy = policy;
+            }
+            return Ok(());
+        }
+        table.chains.push(NfChain {
+            name,
+            hook,
+            priority,
+            policy: policy.unwrap_or(NfVerdict::Accept),
+            rules: Vec::new(),
+        });
+        Ok(())
+    })
+}
+
+fn nf_del_chain(attrs: &[(u16, &[u8])]) -> Result<(), SystemError> {
+    let table = required_str(attrs, NFTA_CHAIN_TABLE)?;
+    let name = required_str(attrs, NFTA_CHAIN_NAME)?;
+    nf_tables_update(|tables| {
+        let table = find_table_mut(tables, &table)?;
+        let idx = table
+            .chains
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or(SystemError::ENOENT)?;
+        table.chains.remove(idx);
+        Ok(())
+    })
+}
+
+fn nf_new_rule(attrs: &[(u16, &[u8])]) -> Result<(), SystemError> {
+    let table = required_str(attrs, NFTA_RULE_TABLE)?;
+    let chain = required_str(attrs, NFTA_RULE_CHAIN)?;
+    let verdict = attr_verdict(attrs, NFTA_RULE_VERDICT)?.ok_or(SystemError::EINVAL)?;
+    let nat_addr = attr_ipv4(attrs, NFTA_RULE_NAT_ADDR)?;
+    if (verdict == NfVerdict::Snat) != nat_addr.is_some() {
+        return Err(SystemError::EINVAL);
+    }
+    let match_attrs = match find_attr(attrs, NFTA_RULE_MATCH) {
+        Some(v) => parse_attrs(v)?,
+        None => Vec::new(),
+    };
+    let matches = NfRuleMatch {
+        src: attr_cidr(&match_attrs, NFTA_MATCH_SRC)?,
+        dst: attr_cidr(&match_attrs, NFTA_MATCH_DST)?,
+        protocol: find_attr(&match_attrs, NFTA_MATCH_PROTO)
+            .map(|v| match v {
+                [proto] => Ok(*proto),
+                _ => Err(SystemError::EINVAL),
+            })
+            .transpose()?,
+        sport: attr_port_range(&match_attrs, NFTA_MATCH_SPORT)?,
+        dport: attr_port_range(&match_attrs, NFTA_MATCH_DPORT)?,
+        iif: attr_u32(&match_attrs, NFTA_MATCH_IIF)?.map(|i| i as usize),
+        oif: attr_u32(&match_attrs, NFTA_MATCH_OIF)?.map(|i| i as usize),
+    };
+
+    nf_tables_update(|tables| {
+        let table = find_table_mut(tables, &table)?;
+        let handle = table.alloc_handle();
+        let chain = table.chain_mut(&chain).ok_or(SystemError::ENOENT)?;
+        // 与Linux的nat链一样，源地址转换只能在POSTROUTING上进行
+        if verdict.is_nat() && chain.hook != NfHook::PostRouting {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        chain.rules.push(NfRule {
+            handle,
+            matches,
+            verdict,
+            nat_addr,
+        });
+        Ok(())
+    })
+}
+
+fn nf_del_rule(attrs: &[(u16, &[u8])]) -> Result<(), SystemError> {
+    let table = required_str(attrs, NFTA_RULE_TABLE)?;
+    let chain = required_str(attrs, NFTA_RULE_CHAIN)?;
+    let handle = attr_u64(attrs, NFTA_RULE_HANDLE)?;
+    nf_tables_update(|tables| {
+        let chain = find_table_mut(tables, &table)?
+            .chain_mut(&chain)
+            .ok_or(SystemError::ENOENT)?;
+        match handle {
+            // 没有指定句柄时清空整条链
+            None => chain.rules.clear(),
+            Some(handle) => {
+                let idx = chain
+                    .rules
+                    .iter()
+                    .position(|r| r.handle == handle)
+                    .ok_or(SystemError::ENOENT)?;
+                chain.rules.remove(idx);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// 处理GET请求：带`NLM_F_DUMP`时列出所有对象，否则只返回指定的对象
+fn nf_get(
+    hdr: &NlMsgHdr,
+    msg_type: u16,
+    attrs: &[(u16, &[u8])],
+    replies: &mut Vec<Vec<u8>>,
+) -> Result<(), SystemError> {
+    let dump = hdr.is_dump();
+    let flags = if dump { NLM_F_MULTI } else { 0 };
+    // 三种消息中表名的属性编号相同
+    let table_filter = attr_str(attrs, NFTA_TABLE_NAME)?;
+    let chain_filter = match msg_type {
+        NFT_MSG_GETCHAIN => attr_str(attrs, NFTA_CHAIN_NAME)?,
+        NFT_MSG_GETRULE => attr_str(attrs, NFTA_RULE_CHAIN)?,
+        _ => None,
+    };
+    let handle_filter = if msg_type == NFT_MSG_GETRULE {
+        attr_u64(attrs, NFTA_RULE_HANDLE)?
+    } else {
+        None
+    };
+
+    let mut found = Vec::new();
+    nf_tables_read(|tables| {
+        let tables = tables
+            .iter()
+            .filter(|t| table_filter.as_ref().map_or(true, |n| *n == t.name));
+        for table in tables {
+            if msg_type == NFT_MSG_GETTABLE {
+                let mut b = nft_msg(NFT_MSG_NEWTABLE, flags, hdr);
+                b.put_str(NFTA_TABLE_NAME, &table.name);
+                found.push(b.finish());
+                continue;
+            }
+            let chains = table
+                .chains
+                .iter()
+                .filter(|c| chain_filter.as_ref().map_or(true, |n| *n == c.name));
+            for chain in chains {
+                if msg_type == NFT_MSG_GETCHAIN {
+                    found.push(nf_fill_chain(hdr, flags, table, chain));
+                    continue;
+                }
+                let rules = chain
+                    .rules
+                    .iter()
+                    .filter(|r| handle_filter.map_or(true, |h| h == r.handle));
+                for rule in rules {
+                    found.push(nf_fill_rule(hdr, flags, table, chain, rule));
+                }
+            }
+        }
+    });
+
+    if dump {
+        replies.append(&mut found);
+        replies.push(nlmsg_done(hdr));
+        return Ok(());
+    }
+    match found.into_iter().next() {
+        Some(reply) => {
+            replies.push(reply);
+            Ok(())
+        }
+        None => Err(SystemError::ENOENT),
+    }
+}
+
+fn nf_fill_chain(hdr: &NlMsgHdr, flags: u16, table: &NfTable, chain: &NfChain) -> Vec<u8> {
+    let mut b = nft_msg(NFT_MSG_NEWCHAIN, flags, hdr);
+    b.put_str(NFTA_CHAIN_TABLE, &table.name)
+        .put_str(NFTA_CHAIN_NAME, &chain.name)
+        .nest_start(NFTA_CHAIN_HOOK)
+        .put(NFTA_HOOK_HOOKNUM, &(chain.hook as u32).to_be_bytes())
+        .put(NFTA_HOOK_PRIORITY, &(chain.priority as u32).to_be_bytes())
+        .nest_end()
+        .put(NFTA_CHAIN_POLICY, &(chain.policy as u32).to_be_bytes());
+    b.finish()
+}
+
+fn nf_fill_rule(
+    hdr: &NlMsgHdr,
+    flags: u16,
+    table: &NfTable,
+    chain: &NfChain,
+    rule: &NfRule,
+) -> Vec<u8> {
+    let mut b = nft_msg(NFT_MSG_NEWRULE, flags, hdr);
+    b.put_str(NFTA_RULE_TABLE, &table.name)
+        .put_str(NFTA_RULE_CHAIN, &chain.name)
+        .put(NFTA_RULE_HANDLE, &rule.handle.to_be_bytes())
+        .put(NFTA_RULE_VERDICT, &(rule.verdict as u32).to_be_bytes());
+    if let Some(addr) = &rule.nat_addr {
+        b.put(NFTA_RULE_NAT_ADDR, addr.as_bytes());
+    }
+    b.nest_start(NFTA_RULE_MATCH);
+
+    let m = &rule.matches;
+    let cidr = |c: &Ipv4Cidr| {
+        let mut v = Vec::from(c.address().as_bytes());
+        v.push(c.prefix_len());
+        v
+    };
+    let range = |(lo, hi): (u16, u16)| {
+        let mut v = Vec::from(lo.to_be_bytes());
+        v.extend_from_slice(&hi.to_be_bytes());
+        v
+    };
+    if let Some(src) = &m.src {
+        b.put(NFTA_MATCH_SRC, &cidr(src));
+    }
+    if let Some(dst) = &m.dst {
+        b.put(NFTA_MATCH_DST, &cidr(dst));
+    }
+    if let Some(proto) = m.protocol {
+        b.put(NFTA_MATCH_PROTO, &[proto]);
+    }
+    if let Some(sport) = m.sport {
+        b.put(NFTA_MATCH_SPORT, &range(sport));
+    }
+    if let Some(dport) = m.dport {
+        b.put(NFTA_MATCH_DPORT, &range(dport));
+    }
+    if let Some(iif) = m.iif {
+        b.put(NFTA_MATCH_IIF, &(iif as u32).to_be_bytes());
+    }
+    if let Some(oif) = m.oif {
+        b.put(NFTA_MATCH_OIF, &(oif as u32).to_be_bytes());
+    }
+    b.nest_end();
+    b.finish()
+}
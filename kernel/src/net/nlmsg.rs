@@ -0,0 +1,215 @@
+//! netlink消息的解析与构造，供内核中各个netlink协议共用
+//!
+//! 一条消息由`nlmsghdr`、协议自己的固定头部以及若干属性（`nlattr`）组成，
+//! 消息和属性都按4字节对齐，头部中的整数使用本机字节序。
+
+use alloc::{string::String, vec::Vec};
+use system_error::SystemError;
+
+pub const NLMSG_HDRLEN: usize = 16;
+pub const NLA_HDRLEN: usize = 4;
+/// 属性类型中的标志位：嵌套属性、网络字节序
+const NLA_TYPE_MASK: u16 = !(0x8000 | 0x4000);
+pub const NLA_F_NESTED: u16 = 0x8000;
+
+pub const NLMSG_ERROR: u16 = 2;
+pub const NLMSG_DONE: u16 = 3;
+
+pub const NLM_F_REQUEST: u16 = 0x1;
+pub const NLM_F_MULTI: u16 = 0x2;
+pub const NLM_F_ACK: u16 = 0x4;
+pub const NLM_F_REPLACE: u16 = 0x100;
+pub const NLM_F_EXCL: u16 = 0x200;
+pub const NLM_F_CREATE: u16 = 0x400;
+pub const NLM_F_DUMP: u16 = 0x300;
+
+/// 一条netlink消息的头部
+#[derive(Debug, Clone, Copy)]
+pub struct NlMsgHdr {
+    pub len: u32,
+    pub ty: u16,
+    pub flags: u16,
+    pub seq: u32,
+    pub pid: u32,
+}
+
+impl NlMsgHdr {
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < NLMSG_HDRLEN {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_ne_bytes(buf[i..i + 4].try_into().unwrap());
+        let u16_at = |i: usize| u16::from_ne_bytes(buf[i..i + 2].try_into().unwrap());
+        Some(Self {
+            len: u32_at(0),
+            ty: u16_at(4),
+            flags: u16_at(6),
+            seq: u32_at(8),
+            pid: u32_at(12),
+        })
+    }
+
+    /// 是否是dump请求
+    #[inline]
+    pub fn is_dump(&self) -> bool {
+        self.flags & NLM_F_DUMP == NLM_F_DUMP
+    }
+}
+
+#[inline]
+pub fn nla_align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// 解析一串属性，返回(类型, 内容)的列表，格式错误时返回EINVAL
+pub fn parse_attrs(mut buf: &[u8]) -> Result<Vec<(u16, &[u8])>, SystemError> {
+    let mut attrs = Vec::new();
+    while buf.len() >= NLA_HDRLEN {
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        let ty = u16::from_ne_bytes([buf[2], buf[3]]) & NLA_TYPE_MASK;
+        if len < NLA_HDRLEN || len > buf.len() {
+            return Err(SystemError::EINVAL);
+        }
+        attrs.push((ty, &buf[NLA_HDRLEN..len]));
+        buf = &buf[core::cmp::min(nla_align(len), buf.len())..];
+    }
+    Ok(attrs)
+}
+
+pub fn find_attr<'a>(attrs: &[(u16, &'a [u8])], ty: u16) -> Option<&'a [u8]> {
+    attrs.iter().find(|(t, _)| *t == ty).map(|(_, v)| *v)
+}
+
+/// 读取以'\0'结尾的字符串属性，空字符串返回EINVAL
+pub fn attr_str(attrs: &[(u16, &[u8])], ty: u16) -> Result<Option<String>, SystemError> {
+    let Some(value) = find_attr(attrs, ty) else {
+        return Ok(None);
+    };
+    let value = value.split(|b| *b == 0).next().unwrap_or(&[]);
+    let s = core::str::from_utf8(value).map_err(|_| SystemError::EINVAL)?;
+    if s.is_empty() {
+        return Err(SystemError::EINVAL);
+    }
+    Ok(Some(String::from(s)))
+}
+
+/// 构造一条回复消息
+pub struct NlMsgBuilder {
+    buf: Vec<u8>,
+    /// 尚未结束的嵌套属性的起始位置
+    nests: Vec<usize>,
+}
+
+impl NlMsgBuilder {
+    pub fn new(ty: u16, flags: u16, seq: u32, pid: u32) -> Self {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&ty.to_ne_bytes());
+        buf.extend_from_slice(&flags.to_ne_bytes());
+        buf.extend_from_slice(&seq.to_ne_bytes());
+        buf.extend_from_slice(&pid.to_ne_bytes());
+        Self {
+            buf,
+            nests: Vec::new(),
+        }
+    }
+
+    /// 创建一条回复`req`的消息
+    pub fn reply(ty: u16, flags: u16, req: &NlMsgHdr) -> Self {
+        Self::new(ty, flags, req.seq, req.pid)
+    }
+
+    /// 追加协议的固定头部，调用者负责对齐
+    pub fn put_raw(&mut self, value: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub fn put(&mut self, ty: u16, value: &[u8]) -> &mut Self {
+        let len = (NLA_HDRLEN + value.len()) as u16;
+        self.buf.extend_from_slice(&len.to_ne_bytes());
+        self.buf.extend_from_slice(&ty.to_ne_bytes());
+        self.buf.extend_from_slice(value);
+        self.buf.resize(nla_align(self.buf.len()), 0);
+        self
+    }
+
+    pub fn put_str(&mut self, ty: u16, value: &str) -> &mut Self {
+        let mut bytes = Vec::from(value.as_bytes());
+        bytes.push(0);
+        self.put(ty, &bytes)
+    }
+
+    pub fn nest_start(&mut self, ty: u16) -> &mut Self {
+        self.nests.push(self.buf.len());
+        self.put(ty | NLA_F_NESTED, &[])
+    }
+
+    pub fn nest_end(&mut self) -> &mut Self {
+        let start = self.nests.pop().unwrap();
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+        self
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf
+    }
+}
+
+/// 构造`NLMSG_ERROR`消息，`error`为0时表示确认
+pub fn nlmsg_ack(req: &NlMsgHdr, req_buf: &[u8], error: i32) -> Vec<u8> {
+    let mut builder = NlMsgBuilder::reply(NLMSG_ERROR, 0, req);
+    builder
+        .put_raw(&error.to_ne_bytes())
+        .put_raw(&req_buf[..NLMSG_HDRLEN]);
+    builder.finish()
+}
+
+pub fn nlmsg_done(req: &NlMsgHdr) -> Vec<u8> {
+    let mut builder = NlMsgBuilder::reply(NLMSG_DONE, NLM_F_MULTI, req);
+    builder.put_raw(&0i32.to_ne_bytes());
+    builder.finish()
+}
+
+/// 依次处理用户态发送的一组netlink消息，与Linux的`netlink_rcv_skb`相同
+///
+/// ## 参数
+///
+/// - `buf`: 用户态写入的数据，可能包含多条消息
+/// - `portid`: 发送者的端口号，作为回复消息的`nlmsg_pid`
+/// - `handler`: 处理一条请求消息，把回复放入最后一个参数中
+///
+/// ## 返回值
+///
+/// 需要放入发送者的接收队列的回复消息。处理失败或者请求带有`NLM_F_ACK`时，还会附上`NLMSG_ERROR`消息
+pub fn netlink_rcv<F>(mut buf: &[u8], portid: u32, mut handler: F) -> Vec<Vec<u8>>
+where
+    F: FnMut(&NlMsgHdr, &[u8], &mut Vec<Vec<u8>>) -> Result<(), SystemError>,
+{
+    let mut replies = Vec::new();
+    while let Some(mut hdr) = NlMsgHdr::parse(buf) {
+        let len = hdr.len as usize;
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            break;
+        }
+        let msg = &buf[..len];
+        buf = &buf[core::cmp::min(nla_align(len), buf.len())..];
+        hdr.pid = portid;
+
+        if hdr.flags & NLM_F_REQUEST == 0 {
+            continue;
+        }
+        match handler(&hdr, msg, &mut replies) {
+            Ok(()) => {
+                if hdr.flags & NLM_F_ACK != 0 {
+                    replies.push(nlmsg_ack(&hdr, msg, 0));
+                }
+            }
+            Err(e) => replies.push(nlmsg_ack(&hdr, msg, e.to_posix_errno())),
+        }
+    }
+    replies
+}
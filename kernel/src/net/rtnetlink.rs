@@ -0,0 +1,620 @@
+//! `NETLINK_ROUTE`：通过netlink查看和配置网卡、地址与路由
+//!
+//! 消息格式与Linux相同，`ip link`、`ip addr`、`ip route`可以直接使用。网卡的ifindex即网卡id。
+//!
+//! 与Linux的差异：
+//!
+//! - 不能创建、删除或重命名网卡，只能修改网卡的启用状态。启用状态目前只影响上报给用户态的标志，
+//!   关闭的网卡仍然会收发数据包；
+//! - smoltcp的路由只能经过网关，因此只支持添加带有`RTA_GATEWAY`的IPv4路由，
+//!   网卡IPv4地址所在的子网由smoltcp直接送达，在dump时作为内核生成的路由上报；
+//! - 只有一张路由表，`RTA_TABLE`被忽略。
+
+use alloc::{sync::Arc, vec::Vec};
+use smoltcp::{
+    iface::Route,
+    wire::{IpAddress, IpCidr, Ipv4Address, Ipv4Cidr, Ipv6Address},
+};
+use system_error::SystemError;
+
+use crate::{
+    driver::net::{stats::netdev_stats, NetDevice, Operstate},
+    net::{
+        netfilter::nat::nf_nat_iface_addr_changed,
+        nlmsg::{
+            attr_str, find_attr, netlink_rcv, nlmsg_done, parse_attrs, NlMsgBuilder, NlMsgHdr,
+            NLMSG_HDRLEN, NLM_F_CREATE, NLM_F_EXCL, NLM_F_MULTI, NLM_F_REPLACE,
+        },
+        NET_DEVICES,
+    },
+    process::{cred::CAPFlags, ProcessManager},
+};
+
+/// 路由的netlink协议号
+pub const NETLINK_ROUTE: u8 = 0;
+
+const RTM_NEWLINK: u16 = 16;
+const RTM_DELLINK: u16 = 17;
+const RTM_GETLINK: u16 = 18;
+const RTM_SETLINK: u16 = 19;
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+const RTM_GETADDR: u16 = 22;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_DELROUTE: u16 = 25;
+const RTM_GETROUTE: u16 = 26;
+
+const AF_UNSPEC: u8 = 0;
+const AF_INET: u8 = 2;
+const AF_INET6: u8 = 10;
+
+/// `struct ifinfomsg`的长度
+const IFINFOMSG_LEN: usize = 16;
+/// `struct ifaddrmsg`的长度
+const IFADDRMSG_LEN: usize = 8;
+/// `struct rtmsg`的长度
+const RTMSG_LEN: usize = 12;
+
+const IFLA_ADDRESS: u16 = 1;
+const IFLA_BROADCAST: u16 = 2;
+const IFLA_IFNAME: u16 = 3;
+const IFLA_STATS: u16 = 7;
+const IFLA_OPERSTATE: u16 = 16;
+const IFLA_STATS64: u16 = 23;
+
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+const IFA_LABEL: u16 = 3;
+
+const RTA_DST: u16 = 1;
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_PREFSRC: u16 = 7;
+const RTA_TABLE: u16 = 15;
+
+const IFF_UP: u32 = 0x1;
+const IFF_BROADCAST: u32 = 0x2;
+const IFF_LOOPBACK: u32 = 0x8;
+const IFF_RUNNING: u32 = 0x40;
+const IFF_MULTICAST: u32 = 0x1000;
+const IFF_LOWER_UP: u32 = 0x10000;
+
+const RT_SCOPE_UNIVERSE: u8 = 0;
+const RT_SCOPE_LINK: u8 = 253;
+const RT_SCOPE_HOST: u8 = 254;
+
+const RT_TABLE_MAIN: u8 = 254;
+const RTPROT_KERNEL: u8 = 2;
+const RTPROT_BOOT: u8 = 3;
+const RTN_UNICAST: u8 = 1;
+
+/// 处理用户态写入的一组rtnetlink消息，返回需要放入发送者接收队列的回复
+pub fn rtnetlink_rcv(buf: &[u8], portid: u32) -> Vec<Vec<u8>> {
+    netlink_rcv(buf, portid, rtnetlink_rcv_msg)
+}
+
+fn rtnetlink_rcv_msg(
+    hdr: &NlMsgHdr,
+    msg: &[u8],
+    replies: &mut Vec<Vec<u8>>,
+) -> Result<(), SystemError> {
+    let is_get = matches!(hdr.ty, RTM_GETLINK | RTM_GETADDR | RTM_GETROUTE);
+    if !is_get
+        && !ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_NET_ADMIN)
+    {
+        return Err(SystemError::EPERM);
+    }
+
+    let body = &msg[NLMSG_HDRLEN..];
+    match hdr.ty {
+        RTM_GETLINK => rtnl_get_link(hdr, body, replies),
+        RTM_NEWLINK | RTM_SETLINK => rtnl_set_link(hdr, body),
+        RTM_DELLINK => Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+        RTM_GETADDR => rtnl_get_addr(hdr, body, replies),
+        RTM_NEWADDR => rtnl_new_addr(hdr, body),
+        RTM_DELADDR => rtnl_del_addr(body),
+        RTM_GETROUTE => rtnl_get_route(hdr, body, replies),
+        RTM_NEWROUTE => rtnl_new_route(hdr, body),
+        RTM_DELROUTE => rtnl_del_route(body),
+        _ => Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+    }
+}
+
+/// 拆分协议的固定头部与属性。dump请求可能只带有一个字节的family，此时其余字段视为0
+fn split_body(body: &[u8], len: usize, dump: bool) -> Result<([u8; 16], &[u8]), SystemError> {
+    let mut fixed = [0u8; 16];
+    if body.len() < len {
+        if !dump || body.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+        fixed[..body.len()].copy_from_slice(body);
+        return Ok((fixed, &[]));
+    }
+    fixed[..len].copy_from_slice(&body[..len]);
+    Ok((fixed, &body[len..]))
+}
+
+fn attr_u32(attrs: &[(u16, &[u8])], ty: u16) -> Result<Option<u32>, SystemError> {
+    match find_attr(attrs, ty) {
+        None => Ok(None),
+        Some(v) if v.len() == 4 => Ok(Some(u32::from_ne_bytes(v.try_into().unwrap()))),
+        Some(_) => Err(SystemError::EINVAL),
+    }
+}
+
+fn attr_ipv4(attrs: &[(u16, &[u8])], ty: u16) -> Result<Option<Ipv4Address>, SystemError> {
+    match find_attr(attrs, ty) {
+        None => Ok(None),
+        Some(v) if v.len() == 4 => Ok(Some(Ipv4Address::from_bytes(v))),
+        Some(_) => Err(SystemError::EINVAL),
+    }
+}
+
+fn device_by_index(index: u32) -> Result<Arc<dyn NetDevice>, SystemError> {
+    NET_DEVICES
+        .read_irqsave()
+        .get(&(index as usize))
+        .cloned()
+        .ok_or(SystemError::ENODEV)
+}
+
+fn all_devices() -> Vec<Arc<dyn NetDevice>> {
+    NET_DEVICES.read_irqsave().values().cloned().collect()
+}
+
+/// 网卡是否被启用
+///
+/// 驱动初始化时不设置状态（`IF_OPER_UNKNOWN`），与Linux的回环网卡一样视为已启用
+fn device_is_up(dev: &Arc<dyn NetDevice>) -> bool {
+    !matches!(
+        dev.operstate(),
+        Operstate::IF_OPER_DOWN | Operstate::IF_OPER_NOTPRESENT
+    )
+}
+
+fn device_flags(dev: &Arc<dyn NetDevice>) -> u32 {
+    let mut flags = if dev.iface_name() == "lo" {
+        IFF_LOOPBACK
+    } else {
+        IFF_BROADCAST | IFF_MULTICAST
+    };
+    if device_is_up(dev) {
+        flags |= IFF_UP | IFF_RUNNING | IFF_LOWER_UP;
+    }
+    flags
+}
+
+fn rtnl_fill_link(hdr: &NlMsgHdr, flags: u16, dev: &Arc<dyn NetDevice>) -> Vec<u8> {
+    let mut b = NlMsgBuilder::reply(RTM_NEWLINK, flags, hdr);
+    b.put_raw(&[AF_UNSPEC, 0])
+        .put_raw(&dev.net_device_type().to_ne_bytes())
+        .put_raw(&(dev.nic_id() as i32).to_ne_bytes())
+        .put_raw(&device_flags(dev).to_ne_bytes())
+        .put_raw(&0u32.to_ne_bytes())
+        .put_str(IFLA_IFNAME, &dev.iface_name())
+        .put(IFLA_ADDRESS, dev.mac().as_bytes())
+        .put(IFLA_OPERSTATE, &[dev.operstate() as u8]);
+    if device_flags(dev) & IFF_BROADCAST != 0 {
+        b.put(IFLA_BROADCAST, &[0xff; 6]);
+    }
+    // getifaddrs把IFLA_STATS放在ifa_data中，ip -s link优先使用IFLA_STATS64
+    let stats = netdev_stats(dev.nic_id());
+    let stats32: Vec<u8> = stats
+        .to_stats32()
+        .iter()
+        .flat_map(|v| v.to_ne_bytes())
+        .collect();
+    let stats64: Vec<u8> = stats
+        .to_stats64()
+        .iter()
+        .flat_map(|v| v.to_ne_bytes())
+        .collect();
+    b.put(IFLA_STATS, &stats32).put(IFLA_STATS64, &stats64);
+    b.finish()
+}
+
+fn rtnl_get_link(
+    hdr: &NlMsgHdr,
+    body: &[u8],
+    replies: &mut Vec<Vec<u8>>,
+) -> Result<(), SystemError> {
+    let dump = hdr.is_dump();
+    let (fixed, attrs) = split_body(body, IFINFOMSG_LEN, dump)?;
+    if dump {
+        for dev in all_devices() {
+            replies.push(rtnl_fill_link(hdr, NLM_F_MULTI, &dev));
+        }
+        replies.push(nlmsg_done(hdr));
+        return Ok(());
+    }
+
+    let attrs = parse_attrs(attrs)?;
+    let index = i32::from_ne_bytes(fixed[4..8].try_into().unwrap());
+    let dev = if index > 0 {
+        device_by_index(index as u32)?
+    } else {
+        let name = attr_str(&attrs, IFLA_IFNAME)?.ok_or(SystemError::EINVAL)?;
+        all_devices()
+            .into_iter()
+            .find(|d| d.iface_name() == name)
+            .ok_or(SystemError::ENODEV)?
+    };
+    replies.push(rtnl_fill_link(hdr, 0, &dev));
+    Ok(())
+}
+
+/// 修改网卡的状态，目前只支持`IFF_UP`
+fn rtnl_set_link(hdr: &NlMsgHdr, body: &[u8]) -> Result<(), SystemError> {
+    let (fixed, attrs) = split_body(body, IFINFOMSG_LEN, false)?;
+    let attrs = parse_attrs(attrs)?;
+    let index = i32::from_ne_bytes(fixed[4..8].try_into().unwrap());
+    let ifi_flags = u32::from_ne_bytes(fixed[8..12].try_into().unwrap());
+    let change = u32::from_ne_bytes(fixed[12..16].try_into().unwrap());
+
+    let name = attr_str(&attrs, IFLA_IFNAME)?;
+    let dev = if index > 0 {
+        device_by_index(index as u32)?
+    } else {
+        let name = name.as_ref().ok_or(SystemError::EINVAL)?;
+        match all_devices().into_iter().find(|d| d.iface_name() == *name) {
+            Some(dev) => dev,
+            // 不支持创建网卡
+            None if hdr.flags & NLM_F_CREATE != 0 => {
+                return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+            }
+            None => return Err(SystemError::ENODEV),
+        }
+    };
+    if hdr.ty == RTM_NEWLINK && hdr.flags & NLM_F_EXCL != 0 {
+        return Err(SystemError::EEXIST);
+    }
+    if name.is_some_and(|name| name != dev.iface_name()) {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+
+    // change为0时按照旧的习惯视为全部位都需要修改
+    if change & IFF_UP != 0 || change == 0 {
+        if ifi_flags & IFF_UP != 0 {
+            dev.set_operstate(Operstate::IF_OPER_UP);
+        } else {
+            dev.set_operstate(Operstate::IF_OPER_DOWN);
+        }
+    }
+    Ok(())
+}
+
+fn addr_family(addr: &IpCidr) -> u8 {
+    match addr {
+        IpCidr::Ipv4(_) => AF_INET,
+        IpCidr::Ipv6(_) => AF_INET6,
+    }
+}
+
+fn addr_scope(addr: &IpCidr) -> u8 {
+    match addr {
+        IpCidr::Ipv4(cidr) if cidr.address().is_loopback() => RT_SCOPE_HOST,
+        IpCidr::Ipv6(cidr) if cidr.address().is_loopback() => RT_SCOPE_HOST,
+        IpCidr::Ipv6(cidr) if cidr.address().is_link_local() => RT_SCOPE_LINK,
+        _ => RT_SCOPE_UNIVERSE,
+    }
+}
+
+fn addr_is_unspecified(addr: &IpCidr) -> bool {
+    addr.address().is_unspecified()
+}
+
+fn rtnl_fill_addr(hdr: &NlMsgHdr, dev: &Arc<dyn NetDevice>, addr: &IpCidr) -> Vec<u8> {
+    let mut b = NlMsgBuilder::reply(RTM_NEWADDR, NLM_F_MULTI, hdr);
+    b.put_raw(&[addr_family(addr), addr.prefix_len(), 0, addr_scope(addr)])
+        .put_raw(&(dev.nic_id() as u32).to_ne_bytes())
+        .put(IFA_ADDRESS, addr.address().as_bytes());
+    if let IpCidr::Ipv4(_) = addr {
+        b.put(IFA_LOCAL, addr.address().as_bytes())
+            .put_str(IFA_LABEL, &dev.iface_name());
+    }
+    b.finish()
+}
+
+fn rtnl_get_addr(
+    hdr: &NlMsgHdr,
+    body: &[u8],
+    replies: &mut Vec<Vec<u8>>,
+) -> Result<(), SystemError> {
+    if !hdr.is_dump() {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+    let (fixed, _) = split_body(body, IFADDRMSG_LEN, true)?;
+    let family = fixed[0];
+    let index = u32::from_ne_bytes(fixed[4..8].try_into().unwrap());
+
+    for dev in all_devices() {
+        if index != 0 && dev.nic_id() as u32 != index {
+            continue;
+        }
+        let addrs: Vec<IpCidr> = dev.inner_iface().lock().ip_addrs().to_vec();
+        for addr in addrs.iter().filter(|a| !addr_is_unspecified(a)) {
+            if family == AF_UNSPEC || family == addr_family(addr) {
+                replies.push(rtnl_fill_addr(hdr, &dev, addr));
+            }
+        }
+    }
+    replies.push(nlmsg_done(hdr));
+    Ok(())
+}
+
+/// 解析`RTM_NEWADDR`与`RTM_DELADDR`的请求，返回网卡和地址
+fn rtnl_parse_addr(body: &[u8]) -> Result<(Arc<dyn NetDevice>, IpCidr), SystemError> {
+    let (fixed, attrs) = split_body(body, IFADDRMSG_LEN, false)?;
+    let attrs = parse_attrs(attrs)?;
+    let (family, prefix_len) = (fixed[0], fixed[1]);
+    let index = u32::from_ne_bytes(fixed[4..8].try_into().unwrap());
+    let dev = device_by_index(index)?;
+
+    // 点对点链路才需要区分两者，这里优先使用本端地址
+    let addr = find_attr(&attrs, IFA_LOCAL)
+        .or_else(|| find_attr(&attrs, IFA_ADDRESS))
+        .ok_or(SystemError::EINVAL)?;
+    let cidr = match (family, addr.len()) {
+        (AF_INET, 4) if prefix_len <= 32 => {
+            IpCidr::Ipv4(Ipv4Cidr::new(Ipv4Address::from_bytes(addr), prefix_len))
+        }
+        (AF_INET6, 16) if prefix_len <= 128 => {
+            IpCidr::new(IpAddress::Ipv6(Ipv6Address::from_bytes(addr)), prefix_len)
+        }
+        (AF_INET | AF_INET6, _) => return Err(SystemError::EINVAL),
+        _ => return Err(SystemError::EAFNOSUPPORT),
+    };
+    if addr_is_unspecified(&cidr) {
+        return Err(SystemError::EINVAL);
+    }
+    Ok((dev, cidr))
+}
+
+/// 网卡的IPv4地址改变之后，通知需要知道网卡地址的模块
+fn rtnl_addr_changed(dev: &Arc<dyn NetDevice>) {
+    let addr = dev
+        .inner_iface()
+        .lock()
+        .ip_addrs()
+        .iter()
+        .find_map(|addr| match addr {
+            IpCidr::Ipv4(cidr) if !cidr.address().is_unspecified() => Some(cidr.address()),
+            _ => None,
+        })
+        .unwrap_or(Ipv4Address::UNSPECIFIED);
+    nf_nat_iface_addr_changed(dev.nic_id(), addr);
+}
+
+fn rtnl_new_addr(hdr: &NlMsgHdr, body: &[u8]) -> Result<(), SystemError> {
+    let (dev, cidr) = rtnl_parse_addr(body)?;
+
+    let mut result = Ok(());
+    dev.inner_iface().lock().update_ip_addrs(|addrs| {
+        if let Some(old) = addrs.iter_mut().find(|a| a.address() == cidr.address()) {
+            if hdr.flags & NLM_F_EXCL != 0 {
+                result = Err(SystemError::EEXIST);
+            } else {
+                *old = cidr;
+            }
+            return;
+        }
+        // 驱动初始化时放入的未指定地址只是占位，直接替换掉
+        if let Some(old) = addrs
+            .iter_mut()
+            .find(|a| addr_is_unspecified(a) && addr_family(a) == addr_family(&cidr))
+        {
+            *old = cidr;
+            return;
+        }
+        if addrs.push(cidr).is_err() {
+            result = Err(SystemError::ENOSPC);
+        }
+    });
+    result?;
+    rtnl_addr_changed(&dev);
+    Ok(())
+}
+
+fn rtnl_del_addr(body: &[u8]) -> Result<(), SystemError> {
+    let (dev, cidr) = rtnl_parse_addr(body)?;
+
+    let mut found = false;
+    dev.inner_iface().lock().update_ip_addrs(|addrs| {
+        let len = addrs.len();
+        addrs.retain(|a| a.address() != cidr.address());
+        found = addrs.len() != len;
+    });
+    if !found {
+        return Err(SystemError::EADDRNOTAVAIL);
+    }
+    rtnl_addr_changed(&dev);
+    Ok(())
+}
+
+/// 路由表中的一项，网关为None时表示网卡地址所在的子网
+struct RtnlRoute {
+    dst: IpCidr,
+    gateway: Option<IpAddress>,
+    prefsrc: Option<IpAddress>,
+    oif: u32,
+}
+
+fn rtnl_fill_route(hdr: &NlMsgHdr, route: &RtnlRoute) -> Vec<u8> {
+    let family = addr_family(&route.dst);
+    let (protocol, scope) = match route.gateway {
+        Some(_) => (RTPROT_BOOT, RT_SCOPE_UNIVERSE),
+        None => (RTPROT_KERNEL, RT_SCOPE_LINK),
+    };
+    let mut b = NlMsgBuilder::reply(RTM_NEWROUTE, NLM_F_MULTI, hdr);
+    b.put_raw(&[
+        family,
+        route.dst.prefix_len(),
+        0,
+        0,
+        RT_TABLE_MAIN,
+        protocol,
+        scope,
+        RTN_UNICAST,
+    ])
+    .put_raw(&0u32.to_ne_bytes())
+    .put(RTA_TABLE, &(RT_TABLE_MAIN as u32).to_ne_bytes())
+    .put(RTA_OIF, &route.oif.to_ne_bytes());
+    if route.dst.prefix_len() != 0 {
+        b.put(RTA_DST, route.dst.address().as_bytes());
+    }
+    if let Some(gateway) = &route.gateway {
+        b.put(RTA_GATEWAY, gateway.as_bytes());
+    }
+    if let Some(prefsrc) = &route.prefsrc {
+        b.put(RTA_PREFSRC, prefsrc.as_bytes());
+    }
+    b.finish()
+}
+
+/// 收集所有网卡的路由，包括由网卡地址得到的子网路由
+fn rtnl_routes() -> Vec<RtnlRoute> {
+    let mut routes = Vec::new();
+    for dev in all_devices() {
+        let oif = dev.nic_id() as u32;
+        let mut iface = dev.inner_iface().lock();
+        for addr in iface.ip_addrs() {
+            let IpCidr::Ipv4(cidr) = addr else {
+                continue;
+            };
+            if cidr.address().is_unspecified() {
+                continue;
+            }
+            routes.push(RtnlRoute {
+                dst: IpCidr::Ipv4(cidr.network()),
+                gateway: None,
+                prefsrc: Some(IpAddress::Ipv4(cidr.address())),
+                oif,
+            });
+        }
+        iface.routes_mut().update(|table| {
+            for route in table.iter() {
+                routes.push(RtnlRoute {
+                    dst: route.cidr,
+                    gateway: Some(route.via_router),
+                    prefsrc: None,
+                    oif,
+                });
+            }
+        });
+    }
+    routes
+}
+
+fn rtnl_get_route(
+    hdr: &NlMsgHdr,
+    body: &[u8],
+    replies: &mut Vec<Vec<u8>>,
+) -> Result<(), SystemError> {
+    // 不支持查询单个目的地址的路由（`ip route get`）
+    if !hdr.is_dump() {
+        return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+    }
+    let (fixed, _) = split_body(body, RTMSG_LEN, true)?;
+    let family = fixed[0];
+    for route in rtnl_routes() {
+        if family == AF_UNSPEC || family == addr_family(&route.dst) {
+            replies.push(rtnl_fill_route(hdr, &route));
+        }
+    }
+    replies.push(nlmsg_done(hdr));
+    Ok(())
+}
+
+/// 解析`RTM_NEWROUTE`与`RTM_DELROUTE`的请求，返回目的子网、网关与出口网卡
+fn rtnl_parse_route(
+    body: &[u8],
+) -> Result<(Ipv4Cidr, Option<Ipv4Address>, Option<u32>), SystemError> {
+    let (fixed, attrs) = split_body(body, RTMSG_LEN, false)?;
+    let attrs = parse_attrs(attrs)?;
+    let (family, dst_len) = (fixed[0], fixed[1]);
+    if family != AF_INET {
+        return Err(SystemError::EAFNOSUPPORT);
+    }
+    if dst_len > 32 {
+        return Err(SystemError::EINVAL);
+    }
+    let dst = attr_ipv4(&attrs, RTA_DST)?.unwrap_or(Ipv4Address::UNSPECIFIED);
+    let dst = Ipv4Cidr::new(dst, dst_len).network();
+    let gateway = attr_ipv4(&attrs, RTA_GATEWAY)?;
+    let oif = attr_u32(&attrs, RTA_OIF)?;
+    Ok((dst, gateway, oif))
+}
+
+/// 没有指定出口网卡时，使用网关所在子网的网卡
+fn device_for_gateway(gateway: Ipv4Address) -> Result<Arc<dyn NetDevice>, SystemError> {
+    let gateway = IpAddress::Ipv4(gateway);
+    all_devices()
+        .into_iter()
+        .find(|dev| {
+            dev.inner_iface()
+                .lock()
+                .ip_addrs()
+                .iter()
+                .any(|a| !addr_is_unspecified(a) && a.contains_addr(&gateway))
+        })
+        .ok_or(SystemError::ENETUNREACH)
+}
+
+fn rtnl_new_route(hdr: &NlMsgHdr, body: &[u8]) -> Result<(), SystemError> {
+    let (dst, gateway, oif) = rtnl_parse_route(body)?;
+    let gateway = gateway.ok_or(SystemError::EOPNOTSUPP_OR_ENOTSUP)?;
+
+    let dev = match oif {
+        Some(oif) => device_by_index(oif)?,
+        None => device_for_gateway(gateway)?,
+    };
+
+    let route = Route {
+        cidr: IpCidr::Ipv4(dst),
+        via_router: IpAddress::Ipv4(gateway),
+        preferred_until: None,
+        expires_at: None,
+    };
+    let mut result = Ok(());
+    dev.inner_iface().lock().routes_mut().update(|table| {
+        if let Some(old) = table.iter_mut().find(|r| r.cidr == route.cidr) {
+            if hdr.flags & NLM_F_REPLACE == 0 {
+                result = Err(SystemError::EEXIST);
+            } else {
+                *old = route;
+            }
+            return;
+        }
+        if hdr.flags & NLM_F_CREATE == 0 {
+            result = Err(SystemError::ENOENT);
+        } else if table.push(route).is_err() {
+            result = Err(SystemError::ENOSPC);
+        }
+    });
+    result
+}
+
+fn rtnl_del_route(body: &[u8]) -> Result<(), SystemError> {
+    let (dst, gateway, oif) = rtnl_parse_route(body)?;
+    let dst = IpCidr::Ipv4(dst);
+
+    let mut found = false;
+    for dev in all_devices() {
+        if oif.is_some_and(|oif| oif as usize != dev.nic_id()) {
+            continue;
+        }
+        dev.inner_iface().lock().routes_mut().update(|table| {
+            let len = table.len();
+            table.retain(|r| {
+                r.cidr != dst || gateway.is_some_and(|gw| r.via_router != IpAddress::Ipv4(gw))
+            });
+            found |= table.len() != len;
+        });
+    }
+    if !found {
+        return Err(SystemError::ESRCH);
+    }
+    Ok(())
+}
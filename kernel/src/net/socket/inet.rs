@@ -1,25 +1,158 @@
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use log::{error, warn};
 use smoltcp::{
+    iface::{SocketHandle, SocketSet},
     socket::{raw, tcp, udp},
+    time::Duration,
     wire,
 };
 use system_error::SystemError;
 
 use crate::{
     driver::net::NetDevice,
-    libs::rwlock::RwLock,
+    libs::spinlock::SpinLock,
     net::{
-        event_poll::EPollEventType, net_core::poll_ifaces, Endpoint, Protocol, ShutdownType,
-        NET_DEVICES,
+        event_poll::EPollEventType,
+        net_core::{
+            is_broadcast_addr, join_multicast_group, leave_multicast_group, poll_ifaces,
+            route_iface,
+        },
+        syscall::{
+            PosixIpProtocol, PosixIpSocketOptions, PosixIpv6SocketOptions, PosixSocketOption,
+            PosixTcpSocketOptions,
+        },
+        Endpoint, Protocol, ShutdownType, NET_DEVICES,
     },
+    time::timer::{clock, next_n_ms_timer_jiffies},
 };
 
 use super::{
-    handle::GlobalSocketHandle, PosixSocketHandleItem, Socket, SocketHandleItem, SocketMetadata,
-    SocketOptions, SocketPollMethod, SocketType, HANDLE_MAP, PORT_MANAGER, SOCKET_SET,
+    handle::GlobalSocketHandle, sockopt_read_int, sockopt_write_ifname, sockopt_write_int,
+    tcp_info::PosixTcpInfo, PosixLinger, PosixSocketHandleItem, Socket, SocketHandleItem,
+    SocketMetadata, SocketOptions, SocketPollMethod, SocketType, HANDLE_MAP, PORT_MANAGER,
+    SOCKET_SET, SOL_SOCKET,
 };
 
+/// 设置了SO_LINGER、正在后台关闭的tcp socket，以及等待它们关闭的截止时间（jiffies）
+///
+/// 进程关闭socket时不能睡眠等待，因此由轮询网卡的函数在连接关闭或超时后把它们从SOCKET_SET中移除
+static LINGERING_SOCKETS: SpinLock<Vec<(SocketHandle, u64)>> = SpinLock::new(Vec::new());
+
+/// 获取socket的shutdown状态
+fn shutdown_type_of(handle: &GlobalSocketHandle) -> ShutdownType {
+    HANDLE_MAP
+        .read_irqsave()
+        .get(handle)
+        .map(|item| item.shutdown_type())
+        .unwrap_or(ShutdownType::empty())
+}
+
+/// 记录socket的shutdown状态，并唤醒阻塞在这个socket上的进程
+fn inet_shutdown(handle: &GlobalSocketHandle, how: ShutdownType) {
+    let (how, posix_item) = {
+        let mut handle_map = HANDLE_MAP.write_irqsave();
+        let item = handle_map.get_mut(handle).unwrap();
+        item.shutdown_type_writer().insert(how);
+        (item.shutdown_type(), item.posix_item())
+    };
+    if let Some(posix_item) = posix_item {
+        posix_item.wakeup_shutdown(how);
+    }
+}
+
+/// tcp/udp socket的地址族
+///
+/// AF_INET6的socket同时收发IPv6和IPv4的报文：对用户来说IPv4地址表示为IPv4映射地址（::ffff:a.b.c.d），
+/// 在协议栈内部则直接使用IPv4地址。设置了IPV6_V6ONLY的socket只使用IPv6地址。
+///
+/// 端口表不区分地址族，因此即使设置了IPV6_V6ONLY，也不能与IPv4的socket绑定同一个端口
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InetFamily {
+    /// AF_INET
+    V4,
+    /// AF_INET6
+    V6 { v6only: bool },
+}
+
+impl InetFamily {
+    /// 把用户传入的地址转换为协议栈使用的地址
+    fn endpoint_in(self, ip: wire::IpEndpoint) -> Result<wire::IpEndpoint, SystemError> {
+        match (self, ip.addr) {
+            (InetFamily::V4, wire::IpAddress::Ipv4(_)) => Ok(ip),
+            (InetFamily::V6 { v6only }, wire::IpAddress::Ipv6(addr)) => match ipv4_unmapped(addr) {
+                Some(_) if v6only => Err(SystemError::ENETUNREACH),
+                Some(v4) => Ok(wire::IpEndpoint::new(wire::IpAddress::Ipv4(v4), ip.port)),
+                None => Ok(ip),
+            },
+            _ => Err(SystemError::EAFNOSUPPORT),
+        }
+    }
+
+    /// 把协议栈中的地址转换为返回给用户的地址
+    fn endpoint_out(self, ip: wire::IpEndpoint) -> wire::IpEndpoint {
+        match (self, ip.addr) {
+            (InetFamily::V6 { .. }, wire::IpAddress::Ipv4(v4)) => {
+                wire::IpEndpoint::new(wire::IpAddress::Ipv6(ipv4_mapped(v4)), ip.port)
+            }
+            _ => ip,
+        }
+    }
+
+    /// 未指定的本地地址
+    fn unspecified(self) -> wire::IpAddress {
+        match self {
+            InetFamily::V4 => wire::IpAddress::Ipv4(wire::Ipv4Address::UNSPECIFIED),
+            InetFamily::V6 { .. } => wire::IpAddress::Ipv6(wire::Ipv6Address::UNSPECIFIED),
+        }
+    }
+
+    /// 监听所有地址的socket能否接收来自`addr`的连接或报文
+    ///
+    /// smoltcp中只绑定了端口的socket会同时接收IPv4和IPv6的报文，因此需要在socket层过滤
+    fn accepts(self, addr: wire::IpAddress) -> bool {
+        match (self, addr) {
+            (InetFamily::V4, wire::IpAddress::Ipv6(_)) => false,
+            (InetFamily::V6 { v6only }, wire::IpAddress::Ipv4(_)) => !v6only,
+            _ => true,
+        }
+    }
+
+    /// 处理IPV6_V6ONLY选项，socket绑定地址之后不能再修改
+    fn set_v6only(&mut self, optval: &[u8], bound: bool) -> Result<(), SystemError> {
+        let InetFamily::V6 { v6only } = self else {
+            return Err(SystemError::ENOPROTOOPT);
+        };
+        if bound {
+            return Err(SystemError::EINVAL);
+        }
+        *v6only = sockopt_read_int(optval)? != 0;
+        Ok(())
+    }
+
+    fn get_v6only(self, optval: &mut [u8]) -> Result<usize, SystemError> {
+        match self {
+            InetFamily::V6 { v6only } => sockopt_write_int(optval, v6only as i32),
+            InetFamily::V4 => Err(SystemError::ENOPROTOOPT),
+        }
+    }
+}
+
+/// IPv4地址对应的IPv4映射地址
+fn ipv4_mapped(addr: wire::Ipv4Address) -> wire::Ipv6Address {
+    let mut bytes = [0u8; 16];
+    bytes[10] = 0xff;
+    bytes[11] = 0xff;
+    bytes[12..].copy_from_slice(addr.as_bytes());
+    wire::Ipv6Address::from_bytes(&bytes)
+}
+
+/// 如果`addr`是IPv4映射地址，返回它对应的IPv4地址
+fn ipv4_unmapped(addr: wire::Ipv6Address) -> Option<wire::Ipv4Address> {
+    let bytes = addr.as_bytes();
+    let mapped = bytes[..10].iter().all(|&b| b == 0) && bytes[10] == 0xff && bytes[11] == 0xff;
+    mapped.then(|| wire::Ipv4Address::from_bytes(&bytes[12..]))
+}
+
 /// @brief 表示原始的socket。原始套接字绕过传输层协议（如 TCP 或 UDP）并提供对网络层协议（如 IP）的直接访问。
 ///
 /// ref: https://man7.org/linux/man-pages/man7/raw.7.html
@@ -157,8 +290,7 @@ impl Socket for RawSocket {
                 let socket: &mut raw::Socket =
                     socket_set_guard.get_mut::<raw::Socket>(self.handle.smoltcp_handle().unwrap());
 
-                // 暴力解决方案：只考虑0号网卡。 TODO：考虑多网卡的情况！！！
-                let iface = NET_DEVICES.read_irqsave().get(&0).unwrap().clone();
+                let iface = route_iface(endpoint.addr).ok_or(SystemError::ENETUNREACH)?;
 
                 // 构造IP头
                 let ipv4_src_addr: Option<wire::Ipv4Address> =
@@ -245,6 +377,9 @@ pub struct UdpSocket {
     remote_endpoint: Option<Endpoint>, // 记录远程endpoint提供给connect()， 应该使用IP地址。
     metadata: SocketMetadata,
     posix_item: Arc<PosixSocketHandleItem>,
+    /// 通过IP_ADD_MEMBERSHIP加入的组播组：(网卡id, 组播地址)
+    multicast_groups: Vec<(usize, wire::Ipv4Address)>,
+    family: InetFamily,
 }
 
 impl UdpSocket {
@@ -255,12 +390,18 @@ impl UdpSocket {
     /// 默认的发送缓冲区的大小 transmiss
     pub const DEFAULT_TX_BUF_SIZE: usize = 64 * 1024;
 
+    /// socket的地址族
+    pub fn family(&self) -> InetFamily {
+        self.family
+    }
+
     /// @brief 创建一个udp的socket
     ///
     /// @param options socket的选项
+    /// @param family socket的地址族
     ///
     /// @return 返回创建的udp的socket
-    pub fn new(options: SocketOptions) -> Self {
+    pub fn new(options: SocketOptions, family: InetFamily) -> Self {
         let rx_buffer = udp::PacketBuffer::new(
             vec![udp::PacketMetadata::EMPTY; Self::DEFAULT_METADATA_BUF_SIZE],
             vec![0; Self::DEFAULT_RX_BUF_SIZE],
@@ -290,17 +431,25 @@ impl UdpSocket {
             remote_endpoint: None,
             metadata,
             posix_item,
+            multicast_groups: Vec::new(),
+            family,
         };
     }
 
     fn do_bind(&self, socket: &mut udp::Socket, endpoint: Endpoint) -> Result<(), SystemError> {
-        if let Endpoint::Ip(Some(mut ip)) = endpoint {
+        if let Endpoint::Ip(Some(ip)) = endpoint {
+            let mut ip = self.family.endpoint_in(ip)?;
             // 端口为0则分配随机端口
             if ip.port == 0 {
                 ip.port = PORT_MANAGER.get_ephemeral_port(self.metadata.socket_type)?;
             }
             // 检测端口是否已被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, ip.port)?;
+            PORT_MANAGER.bind_port(
+                self.metadata.socket_type,
+                ip.port,
+                self.metadata.options.allows_port_reuse(),
+                self.metadata.bound_device,
+            )?;
 
             let bind_res = if ip.addr.is_unspecified() {
                 socket.bind(ip.port)
@@ -316,6 +465,56 @@ impl UdpSocket {
             return Err(SystemError::EINVAL);
         }
     }
+
+    /// 解析IP_ADD_MEMBERSHIP/IP_DROP_MEMBERSHIP的选项值
+    ///
+    /// 选项值可以是`struct ip_mreq`，也可以是带有网卡序号的`struct ip_mreqn`
+    ///
+    /// ## 返回值
+    ///
+    /// 组播地址，以及在哪个网卡上加入/离开该组播组
+    fn parse_ip_mreq(
+        optval: &[u8],
+    ) -> Result<(wire::Ipv4Address, Arc<dyn NetDevice>), SystemError> {
+        if optval.len() < 8 {
+            return Err(SystemError::EINVAL);
+        }
+        let group = wire::Ipv4Address::from_bytes(&optval[0..4]);
+        let local_addr = wire::Ipv4Address::from_bytes(&optval[4..8]);
+        let ifindex = if optval.len() >= 12 {
+            i32::from_ne_bytes(optval[8..12].try_into().unwrap())
+        } else {
+            0
+        };
+
+        let devices = NET_DEVICES.read_irqsave();
+        let iface = if ifindex > 0 {
+            devices.get(&(ifindex as usize)).cloned()
+        } else if !local_addr.is_unspecified() {
+            devices
+                .values()
+                .find(|iface| iface.inner_iface().lock().has_ip_addr(local_addr))
+                .cloned()
+        } else {
+            // 没有指定网卡时，优先使用非环回网卡（lo网卡的id最先分配，为0）
+            devices
+                .values()
+                .find(|iface| iface.nic_id() != 0)
+                .or_else(|| devices.values().next())
+                .cloned()
+        };
+        return Ok((group, iface.ok_or(SystemError::ENODEV)?));
+    }
+
+    /// 离开所有加入了的组播组
+    fn leave_all_multicast_groups(&mut self) {
+        for (nic_id, group) in self.multicast_groups.drain(..) {
+            let iface = NET_DEVICES.read_irqsave().get(&nic_id).cloned();
+            if let Some(iface) = iface {
+                leave_multicast_group(&iface, group).ok();
+            }
+        }
+    }
 }
 
 impl Socket for UdpSocket {
@@ -331,6 +530,7 @@ impl Socket for UdpSocket {
             sock.close();
         }
         drop(socket_set_guard);
+        self.leave_all_multicast_groups();
         poll_ifaces();
     }
 
@@ -347,13 +547,18 @@ impl Socket for UdpSocket {
 
             if socket.can_recv() {
                 if let Ok((size, metadata)) = socket.recv_slice(buf) {
+                    // 丢弃不属于这个地址族的报文
+                    if !self.family.accepts(metadata.endpoint.addr) {
+                        continue;
+                    }
                     drop(socket_set_guard);
                     poll_ifaces();
-                    return (Ok(size), Endpoint::Ip(Some(metadata.endpoint)));
+                    let endpoint = self.family.endpoint_out(metadata.endpoint);
+                    return (Ok(size), Endpoint::Ip(Some(endpoint)));
                 }
-            } else {
-                // 如果socket没有连接，则忙等
-                // return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
+            } else if shutdown_type_of(&self.socket_handle()).contains(ShutdownType::RCV_SHUTDOWN) {
+                // 读端已经关闭，并且没有剩余的报文
+                return (Ok(0), Endpoint::Ip(None));
             }
             drop(socket_set_guard);
             self.posix_item.sleep(EPollEventType::EPOLLIN.bits() as u64);
@@ -362,10 +567,13 @@ impl Socket for UdpSocket {
 
     fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
         // debug!("udp to send: {:?}, len={}", to, buf.len());
-        let remote_endpoint: &wire::IpEndpoint = {
-            if let Some(Endpoint::Ip(Some(ref endpoint))) = to {
-                endpoint
-            } else if let Some(Endpoint::Ip(Some(ref endpoint))) = self.remote_endpoint {
+        if shutdown_type_of(&self.socket_handle()).contains(ShutdownType::SEND_SHUTDOWN) {
+            return Err(SystemError::EPIPE);
+        }
+        let remote_endpoint: &wire::IpEndpoint = &{
+            if let Some(Endpoint::Ip(Some(endpoint))) = to {
+                self.family.endpoint_in(endpoint)?
+            } else if let Some(Endpoint::Ip(Some(endpoint))) = self.remote_endpoint {
                 endpoint
             } else {
                 return Err(SystemError::ENOTCONN);
@@ -373,6 +581,14 @@ impl Socket for UdpSocket {
         };
         // debug!("udp write: remote = {:?}", remote_endpoint);
 
+        // 没有设置SO_BROADCAST时不允许发送广播报文
+        if let wire::IpAddress::Ipv4(addr) = remote_endpoint.addr {
+            if !self.metadata.options.contains(SocketOptions::BROADCAST) && is_broadcast_addr(addr)
+            {
+                return Err(SystemError::EACCES);
+            }
+        }
+
         let mut socket_set_guard = SOCKET_SET.lock_irqsave();
         let socket = socket_set_guard.get_mut::<udp::Socket>(self.handle.smoltcp_handle().unwrap());
         // debug!("is open()={}", socket.is_open());
@@ -404,6 +620,15 @@ impl Socket for UdpSocket {
         return self.do_bind(socket, endpoint);
     }
 
+    fn shutdown(&mut self, shutdown_type: ShutdownType) -> Result<(), SystemError> {
+        // 和Linux一样，没有连接的socket也会记录shutdown状态（可以用来唤醒等待者），但是返回ENOTCONN
+        inet_shutdown(&self.socket_handle(), shutdown_type);
+        if self.remote_endpoint.is_none() {
+            return Err(SystemError::ENOTCONN);
+        }
+        Ok(())
+    }
+
     fn poll(&self) -> EPollEventType {
         let sockets = SOCKET_SET.lock_irqsave();
         let socket = sockets.get::<udp::Socket>(self.handle.smoltcp_handle().unwrap());
@@ -419,11 +644,17 @@ impl Socket for UdpSocket {
     }
 
     fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
-        if let Endpoint::Ip(_) = endpoint {
-            self.remote_endpoint = Some(endpoint);
-            Ok(())
-        } else {
-            Err(SystemError::EINVAL)
+        match endpoint {
+            Endpoint::Ip(Some(ip)) => {
+                let ip = self.family.endpoint_in(ip)?;
+                self.remote_endpoint = Some(Endpoint::Ip(Some(ip)));
+                Ok(())
+            }
+            Endpoint::Ip(None) => {
+                self.remote_endpoint = Some(endpoint);
+                Ok(())
+            }
+            _ => Err(SystemError::EINVAL),
         }
     }
 
@@ -441,6 +672,112 @@ impl Socket for UdpSocket {
         self.metadata.clone()
     }
 
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level as u8 == SOL_SOCKET {
+            let optname = PosixSocketOption::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            let option = match optname {
+                PosixSocketOption::SO_BROADCAST => SocketOptions::BROADCAST,
+                PosixSocketOption::SO_REUSEADDR => SocketOptions::REUSEADDR,
+                PosixSocketOption::SO_REUSEPORT => SocketOptions::REUSEPORT,
+                PosixSocketOption::SO_BINDTODEVICE => {
+                    let port = match self.endpoint() {
+                        Some(Endpoint::Ip(Some(ip))) => Some(ip.port),
+                        _ => None,
+                    };
+                    return self.metadata.bind_to_device(optval, port);
+                }
+                _ => {
+                    warn!("udp setsockopt: option {:?} is not supported", optname);
+                    return Ok(());
+                }
+            };
+            let enable = sockopt_read_int(optval)? != 0;
+            self.metadata.options.set(option, enable);
+            return Ok(());
+        }
+
+        if level as u16 == u16::from(PosixIpProtocol::IP) {
+            let optname = PosixIpSocketOptions::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            match optname {
+                PosixIpSocketOptions::AddMembership => {
+                    let (group, iface) = Self::parse_ip_mreq(optval)?;
+                    let key = (iface.nic_id(), group);
+                    if self.multicast_groups.contains(&key) {
+                        return Err(SystemError::EADDRINUSE);
+                    }
+                    join_multicast_group(&iface, group)?;
+                    self.multicast_groups.push(key);
+                    poll_ifaces();
+                }
+                PosixIpSocketOptions::DropMembership => {
+                    let (group, iface) = Self::parse_ip_mreq(optval)?;
+                    let pos = self
+                        .multicast_groups
+                        .iter()
+                        .position(|&key| key == (iface.nic_id(), group))
+                        .ok_or(SystemError::EADDRNOTAVAIL)?;
+                    self.multicast_groups.remove(pos);
+                    leave_multicast_group(&iface, group)?;
+                    poll_ifaces();
+                }
+                _ => warn!("udp setsockopt: option {:?} is not supported", optname),
+            }
+            return Ok(());
+        }
+
+        if level as u16 == u16::from(PosixIpProtocol::IPv6) {
+            let optname = PosixIpv6SocketOptions::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            match optname {
+                PosixIpv6SocketOptions::V6Only => {
+                    let bound = self.endpoint().is_some();
+                    self.family.set_v6only(optval, bound)?;
+                }
+                _ => warn!("udp setsockopt: option {:?} is not supported", optname),
+            }
+            return Ok(());
+        }
+
+        warn!("udp setsockopt: level {} is not supported", level);
+        return Ok(());
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level as u16 == u16::from(PosixIpProtocol::IPv6) {
+            return match PosixIpv6SocketOptions::try_from(optname as i32) {
+                Ok(PosixIpv6SocketOptions::V6Only) => self.family.get_v6only(optval),
+                _ => Err(SystemError::ENOPROTOOPT),
+            };
+        }
+        if level as u8 != SOL_SOCKET {
+            return Err(SystemError::ENOPROTOOPT);
+        }
+        let optname =
+            PosixSocketOption::try_from(optname as i32).map_err(|_| SystemError::ENOPROTOOPT)?;
+        let option = match optname {
+            PosixSocketOption::SO_BROADCAST => SocketOptions::BROADCAST,
+            PosixSocketOption::SO_REUSEADDR => SocketOptions::REUSEADDR,
+            PosixSocketOption::SO_REUSEPORT => SocketOptions::REUSEPORT,
+            PosixSocketOption::SO_BINDTODEVICE => {
+                return sockopt_write_ifname(optval, self.metadata.bound_device)
+            }
+            _ => return Err(SystemError::ENOPROTOOPT),
+        };
+        return sockopt_write_int(optval, self.metadata.options.contains(option) as i32);
+    }
+
     fn box_clone(&self) -> Box<dyn Socket> {
         return Box::new(self.clone());
     }
@@ -454,20 +791,21 @@ impl Socket for UdpSocket {
             return None;
         } else {
             // 如果listen_endpoint的address是None，意味着“监听所有的地址”。
-            // 这里假设所有的地址都是ipv4
-            // TODO: 支持ipv6
             let result = wire::IpEndpoint::new(
                 listen_endpoint
                     .addr
-                    .unwrap_or(wire::IpAddress::v4(0, 0, 0, 0)),
+                    .unwrap_or_else(|| self.family.unspecified()),
                 listen_endpoint.port,
             );
-            return Some(Endpoint::Ip(Some(result)));
+            return Some(Endpoint::Ip(Some(self.family.endpoint_out(result))));
         }
     }
 
     fn peer_endpoint(&self) -> Option<Endpoint> {
-        return self.remote_endpoint.clone();
+        return match self.remote_endpoint {
+            Some(Endpoint::Ip(Some(ip))) => Some(Endpoint::Ip(Some(self.family.endpoint_out(ip)))),
+            ref other => other.clone(),
+        };
     }
 
     fn socket_handle(&self) -> GlobalSocketHandle {
@@ -493,6 +831,9 @@ pub struct TcpSocket {
     is_listening: bool,
     metadata: SocketMetadata,
     posix_item: Arc<PosixSocketHandleItem>,
+    /// SO_LINGER设置的等待秒数，None表示未启用
+    linger: Option<u32>,
+    family: InetFamily,
 }
 
 impl TcpSocket {
@@ -507,12 +848,43 @@ impl TcpSocket {
     pub const CAN_CONNECT: u64 = 1u64 << 63;
     pub const CAN_ACCPET: u64 = 1u64 << 62;
 
+    /// 启用SO_KEEPALIVE后，连接空闲多久发送一次保活报文（秒）
+    pub const KEEPALIVE_INTERVAL_SECS: u64 = 75;
+
+    /// socket的地址族
+    pub fn family(&self) -> InetFamily {
+        self.family
+    }
+
+    /// 连接的状态以及发送队列和接收队列中的字节数，用于`/proc/net/tcp`
+    ///
+    /// 正在监听的socket的接收队列长度为等待accept的连接数
+    pub fn queue_state(&self) -> (tcp::State, usize, usize) {
+        let sockets = SOCKET_SET.lock_irqsave();
+        if self.is_listening {
+            let backlog = self
+                .handles
+                .iter()
+                .filter(|handle| {
+                    sockets
+                        .get::<tcp::Socket>(handle.smoltcp_handle().unwrap())
+                        .state()
+                        == tcp::State::Established
+                })
+                .count();
+            return (tcp::State::Listen, 0, backlog);
+        }
+        let socket = sockets.get::<tcp::Socket>(self.handles[0].smoltcp_handle().unwrap());
+        (socket.state(), socket.send_queue(), socket.recv_queue())
+    }
+
     /// @brief 创建一个tcp的socket
     ///
     /// @param options socket的选项
+    /// @param family socket的地址族
     ///
     /// @return 返回创建的tcp的socket
-    pub fn new(options: SocketOptions) -> Self {
+    pub fn new(options: SocketOptions, family: InetFamily) -> Self {
         // 创建handles数组并把socket添加到socket集合中，并得到socket的句柄
         let handles: Vec<GlobalSocketHandle> = vec![GlobalSocketHandle::new_smoltcp_handle(
             SOCKET_SET.lock_irqsave().add(Self::create_new_socket()),
@@ -534,6 +906,8 @@ impl TcpSocket {
             is_listening: false,
             metadata,
             posix_item,
+            linger: None,
+            family,
         };
     }
 
@@ -571,6 +945,74 @@ impl TcpSocket {
         tcp::Socket::new(rx_buffer, tx_buffer)
     }
 
+    /// 把socket选项同步到smoltcp的socket上
+    fn apply_options(options: SocketOptions, socket: &mut tcp::Socket) {
+        socket.set_nagle_enabled(!options.contains(SocketOptions::NODELAY));
+        socket.set_keep_alive(
+            options
+                .contains(SocketOptions::KEEPALIVE)
+                .then_some(Duration::from_secs(Self::KEEPALIVE_INTERVAL_SECS)),
+        );
+    }
+
+    /// 设置或清除一个socket选项，并同步到所有的smoltcp socket上
+    fn update_option(&mut self, option: SocketOptions, enable: bool) {
+        self.metadata.options.set(option, enable);
+        let mut sockets = SOCKET_SET.lock_irqsave();
+        for handle in self.handles.iter() {
+            let socket = sockets.get_mut::<tcp::Socket>(handle.smoltcp_handle().unwrap());
+            Self::apply_options(self.metadata.options, socket);
+        }
+    }
+
+    /// 移除已经关闭或者等待超时的、设置了SO_LINGER的socket
+    ///
+    /// 在轮询网卡之后调用
+    pub fn reap_lingering(sockets: &mut SocketSet<'static>) {
+        let mut lingering = LINGERING_SOCKETS.lock_irqsave();
+        if lingering.is_empty() {
+            return;
+        }
+        let now = clock();
+        lingering.retain(|&(handle, deadline)| {
+            let state = sockets.get::<tcp::Socket>(handle).state();
+            let done = matches!(state, tcp::State::Closed | tcp::State::TimeWait);
+            if done || now >= deadline {
+                sockets.remove(handle);
+                return false;
+            }
+            true
+        });
+    }
+
+    /// 复位监听socket收到的、不属于这个socket地址族的连接
+    ///
+    /// 连接所在的smoltcp socket交给`LINGERING_SOCKETS`，在发出RST之后被移除，原来的位置换成一个新的监听socket
+    fn reject_connection(
+        &mut self,
+        sockets: &mut SocketSet<'static>,
+        index: usize,
+        endpoint: wire::IpEndpoint,
+    ) -> Result<(), SystemError> {
+        let mut socket = Self::create_new_socket();
+        Self::apply_options(self.metadata.options, &mut socket);
+        let new_handle = GlobalSocketHandle::new_smoltcp_handle(sockets.add(socket));
+        let old_handle = core::mem::replace(&mut self.handles[index], new_handle);
+
+        let old = old_handle.smoltcp_handle().unwrap();
+        sockets.get_mut::<tcp::Socket>(old).abort();
+        LINGERING_SOCKETS.lock_irqsave().push((old, clock()));
+
+        let mut handle_guard = HANDLE_MAP.write_irqsave();
+        if let Some(item) = handle_guard.remove(&old_handle) {
+            handle_guard.insert(new_handle, item);
+        }
+        drop(handle_guard);
+
+        let socket = sockets.get_mut::<tcp::Socket>(new_handle.smoltcp_handle().unwrap());
+        return self.do_listen(socket, endpoint);
+    }
+
     /// listening状态的posix socket是需要特殊处理的
     fn tcp_poll_listening(&self) -> EPollEventType {
         let socketset_guard = SOCKET_SET.lock_irqsave();
@@ -599,32 +1041,34 @@ impl Socket for TcpSocket {
 
     fn close(&mut self) {
         for handle in self.handles.iter() {
+            let smoltcp_handle = handle.smoltcp_handle().unwrap();
             {
                 let mut socket_set_guard = SOCKET_SET.lock_irqsave();
-                let smoltcp_handle = handle.smoltcp_handle().unwrap();
-                socket_set_guard
-                    .get_mut::<smoltcp::socket::tcp::Socket>(smoltcp_handle)
-                    .close();
+                let socket =
+                    socket_set_guard.get_mut::<smoltcp::socket::tcp::Socket>(smoltcp_handle);
+                // SO_LINGER的等待时间为0时，直接发送RST复位连接
+                if self.linger == Some(0) {
+                    socket.abort();
+                } else {
+                    socket.close();
+                }
                 drop(socket_set_guard);
             }
             poll_ifaces();
-            SOCKET_SET
-                .lock_irqsave()
-                .remove(handle.smoltcp_handle().unwrap());
+            match self.linger {
+                // 发送缓冲区中剩余的数据在后台继续发送，直到连接关闭或等待超时
+                Some(secs) if secs > 0 => LINGERING_SOCKETS
+                    .lock_irqsave()
+                    .push((smoltcp_handle, next_n_ms_timer_jiffies(secs as u64 * 1000))),
+                _ => {
+                    SOCKET_SET.lock_irqsave().remove(smoltcp_handle);
+                }
+            }
             // debug!("[Socket] [TCP] Close: {:?}", handle);
         }
     }
 
     fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
-        if HANDLE_MAP
-            .read_irqsave()
-            .get(&self.socket_handle())
-            .unwrap()
-            .shutdown_type()
-            .contains(ShutdownType::RCV_SHUTDOWN)
-        {
-            return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
-        }
         // debug!("tcp socket: read, buf len={}", buf.len());
         // debug!("tcp socket:read, socket'len={}",self.handle.len());
         loop {
@@ -634,44 +1078,36 @@ impl Socket for TcpSocket {
             let socket = socket_set_guard
                 .get_mut::<tcp::Socket>(self.handles.first().unwrap().smoltcp_handle().unwrap());
 
-            // 如果socket已经关闭，返回错误
-            if !socket.is_active() {
-                // debug!("Tcp Socket Read Error, socket is closed");
-                return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
-            }
+            // 接收缓冲区中剩余的数据在关闭读端或者收到FIN之后仍然可以读取
+            match socket.recv_slice(buf) {
+                Ok(size) => {
+                    if size > 0 {
+                        let endpoint = if let Some(p) = socket.remote_endpoint() {
+                            p
+                        } else {
+                            return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
+                        };
 
-            if socket.may_recv() {
-                match socket.recv_slice(buf) {
-                    Ok(size) => {
-                        if size > 0 {
-                            let endpoint = if let Some(p) = socket.remote_endpoint() {
-                                p
-                            } else {
-                                return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
-                            };
-
-                            drop(socket_set_guard);
-                            poll_ifaces();
-                            return (Ok(size), Endpoint::Ip(Some(endpoint)));
-                        }
-                    }
-                    Err(tcp::RecvError::InvalidState) => {
-                        warn!("Tcp Socket Read Error, InvalidState");
-                        return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
-                    }
-                    Err(tcp::RecvError::Finished) => {
-                        // 对端写端已关闭，我们应该关闭读端
-                        HANDLE_MAP
-                            .write_irqsave()
-                            .get_mut(&self.socket_handle())
-                            .unwrap()
-                            .shutdown_type_writer()
-                            .insert(ShutdownType::RCV_SHUTDOWN);
-                        return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
+                        drop(socket_set_guard);
+                        poll_ifaces();
+                        let endpoint = self.family.endpoint_out(endpoint);
+                        return (Ok(size), Endpoint::Ip(Some(endpoint)));
                     }
                 }
-            } else {
-                return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
+                Err(tcp::RecvError::InvalidState) => {
+                    return (Err(SystemError::ENOTCONN), Endpoint::Ip(None));
+                }
+                Err(tcp::RecvError::Finished) => {
+                    // 对端写端已关闭，读到文件结束
+                    drop(socket_set_guard);
+                    inet_shutdown(&self.socket_handle(), ShutdownType::RCV_SHUTDOWN);
+                    return (Ok(0), Endpoint::Ip(None));
+                }
+            }
+
+            // 本端关闭了读端，数据已经读完
+            if shutdown_type_of(&self.socket_handle()).contains(ShutdownType::RCV_SHUTDOWN) {
+                return (Ok(0), Endpoint::Ip(None));
             }
             drop(socket_set_guard);
             self.posix_item
@@ -680,14 +1116,8 @@ impl Socket for TcpSocket {
     }
 
     fn write(&self, buf: &[u8], _to: Option<Endpoint>) -> Result<usize, SystemError> {
-        if HANDLE_MAP
-            .read_irqsave()
-            .get(&self.socket_handle())
-            .unwrap()
-            .shutdown_type()
-            .contains(ShutdownType::RCV_SHUTDOWN)
-        {
-            return Err(SystemError::ENOTCONN);
+        if shutdown_type_of(&self.socket_handle()).contains(ShutdownType::SEND_SHUTDOWN) {
+            return Err(SystemError::EPIPE);
         }
         // debug!("tcp socket:write, socket'len={}",self.handle.len());
 
@@ -748,12 +1178,19 @@ impl Socket for TcpSocket {
             sockets.get_mut::<tcp::Socket>(self.handles.first().unwrap().smoltcp_handle().unwrap());
 
         if let Endpoint::Ip(Some(ip)) = endpoint {
+            let ip = self.family.endpoint_in(ip)?;
+            // 源地址取自到达目的地址的网卡，发往127.0.0.0/8的连接因此使用lo上的地址
+            let iface = route_iface(ip.addr).ok_or(SystemError::ENETUNREACH)?;
             let temp_port = PORT_MANAGER.get_ephemeral_port(self.metadata.socket_type)?;
             // 检测端口是否被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, temp_port)?;
+            PORT_MANAGER.bind_port(
+                self.metadata.socket_type,
+                temp_port,
+                false,
+                self.metadata.bound_device,
+            )?;
 
             // debug!("temp_port: {}", temp_port);
-            let iface: Arc<dyn NetDevice> = NET_DEVICES.write_irqsave().get(&0).unwrap().clone();
             let mut inner_iface = iface.inner_iface().lock();
             // debug!("to connect: {ip:?}");
 
@@ -822,8 +1259,10 @@ impl Socket for TcpSocket {
         let socket_handle_item_0 = handle_guard.get_mut(&self.socket_handle()).unwrap();
         socket_handle_item_0.is_posix_listen = true;
 
+        let options = self.metadata.options;
         self.handles.extend((handlen..backlog).map(|_| {
-            let socket = Self::create_new_socket();
+            let mut socket = Self::create_new_socket();
+            Self::apply_options(options, &mut socket);
             let handle = GlobalSocketHandle::new_smoltcp_handle(sockets.add(socket));
             let mut handle_item = SocketHandleItem::new(Arc::downgrade(&self.posix_item));
             handle_item.is_posix_listen = true;
@@ -851,13 +1290,19 @@ impl Socket for TcpSocket {
     }
 
     fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
-        if let Endpoint::Ip(Some(mut ip)) = endpoint {
+        if let Endpoint::Ip(Some(ip)) = endpoint {
+            let mut ip = self.family.endpoint_in(ip)?;
             if ip.port == 0 {
                 ip.port = PORT_MANAGER.get_ephemeral_port(self.metadata.socket_type)?;
             }
 
             // 检测端口是否已被占用
-            PORT_MANAGER.bind_port(self.metadata.socket_type, ip.port)?;
+            PORT_MANAGER.bind_port(
+                self.metadata.socket_type,
+                ip.port,
+                self.metadata.options.allows_port_reuse(),
+                self.metadata.bound_device,
+            )?;
             // debug!("tcp socket:bind, socket'len={}",self.handle.len());
 
             self.local_endpoint = Some(ip);
@@ -868,13 +1313,24 @@ impl Socket for TcpSocket {
         return Err(SystemError::EINVAL);
     }
 
-    fn shutdown(&mut self, shutdown_type: super::ShutdownType) -> Result<(), SystemError> {
-        // TODO：目前只是在表层判断，对端不知晓，后续需使用tcp实现
-        HANDLE_MAP
-            .write_irqsave()
-            .get_mut(&self.socket_handle())
-            .unwrap()
-            .shutdown_type = RwLock::new(shutdown_type);
+    fn shutdown(&mut self, shutdown_type: ShutdownType) -> Result<(), SystemError> {
+        if self.is_listening {
+            return Err(SystemError::ENOTCONN);
+        }
+
+        let mut sockets = SOCKET_SET.lock_irqsave();
+        let socket = sockets.get_mut::<tcp::Socket>(self.handles[0].smoltcp_handle().unwrap());
+        if matches!(socket.state(), tcp::State::Closed | tcp::State::Listen) {
+            return Err(SystemError::ENOTCONN);
+        }
+        // 关闭写端时发送FIN，对端读完数据后读到文件结束。关闭读端只影响本端
+        if shutdown_type.contains(ShutdownType::SEND_SHUTDOWN) {
+            socket.close();
+        }
+        drop(sockets);
+
+        inet_shutdown(&self.socket_handle(), shutdown_type);
+        poll_ifaces();
         return Ok(());
     }
 
@@ -905,20 +1361,27 @@ impl Socket for TcpSocket {
                     .remote_endpoint()
                     .ok_or(SystemError::ENOTCONN)?;
 
-                let tcp_socket = Self::create_new_socket();
+                if !self.family.accepts(remote_ep.addr) {
+                    self.reject_connection(&mut sockset, handle_index, endpoint)?;
+                    continue;
+                }
+
+                let mut tcp_socket = Self::create_new_socket();
+                Self::apply_options(self.metadata.options, &mut tcp_socket);
 
                 let new_handle = GlobalSocketHandle::new_smoltcp_handle(sockset.add(tcp_socket));
 
                 // let handle in TcpSock be the new empty handle, and return the old connected handle
                 let old_handle = core::mem::replace(&mut self.handles[handle_index], new_handle);
 
-                let metadata = SocketMetadata::new(
+                let mut metadata = SocketMetadata::new(
                     SocketType::Tcp,
                     Self::DEFAULT_TX_BUF_SIZE,
                     Self::DEFAULT_RX_BUF_SIZE,
                     Self::DEFAULT_METADATA_BUF_SIZE,
                     self.metadata.options,
                 );
+                metadata.bound_device = self.metadata.bound_device;
 
                 let sock_ret = Box::new(TcpSocket {
                     handles: vec![old_handle],
@@ -926,6 +1389,8 @@ impl Socket for TcpSocket {
                     is_listening: false,
                     metadata,
                     posix_item: Arc::new(PosixSocketHandleItem::new(None)),
+                    linger: self.linger,
+                    family: self.family,
                 });
 
                 {
@@ -952,13 +1417,14 @@ impl Socket for TcpSocket {
                     drop(handle_guard);
                 }
 
+                let remote_ep = self.family.endpoint_out(remote_ep);
                 return Ok((sock_ret, Endpoint::Ip(Some(remote_ep))));
             }
 
             drop(sockset);
 
             // debug!("[TCP] [Accept] sleeping socket with handle: {:?}", self.handles.first().unwrap().smoltcp_handle().unwrap());
-            self.posix_item.sleep(Self::CAN_ACCPET);
+            self.posix_item.sleep_exclusive(Self::CAN_ACCPET);
             // debug!("tcp socket:after sleep, handle_guard'len={}",HANDLE_MAP.write_irqsave().len());
         }
     }
@@ -976,7 +1442,10 @@ impl Socket for TcpSocket {
                 result = Some(Endpoint::Ip(Some(ep)));
             }
         }
-        return result;
+        return result.map(|ep| match ep {
+            Endpoint::Ip(Some(ip)) => Endpoint::Ip(Some(self.family.endpoint_out(ip))),
+            ep => ep,
+        });
     }
 
     fn peer_endpoint(&self) -> Option<Endpoint> {
@@ -985,13 +1454,147 @@ impl Socket for TcpSocket {
 
         let socket =
             sockets.get::<tcp::Socket>(self.handles.first().unwrap().smoltcp_handle().unwrap());
-        return socket.remote_endpoint().map(|x| Endpoint::Ip(Some(x)));
+        return socket
+            .remote_endpoint()
+            .map(|x| Endpoint::Ip(Some(self.family.endpoint_out(x))));
     }
 
     fn metadata(&self) -> SocketMetadata {
         self.metadata.clone()
     }
 
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level as u8 == SOL_SOCKET {
+            let optname = PosixSocketOption::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            match optname {
+                PosixSocketOption::SO_REUSEADDR => {
+                    let enable = sockopt_read_int(optval)? != 0;
+                    self.metadata.options.set(SocketOptions::REUSEADDR, enable);
+                }
+                PosixSocketOption::SO_REUSEPORT => {
+                    let enable = sockopt_read_int(optval)? != 0;
+                    self.metadata.options.set(SocketOptions::REUSEPORT, enable);
+                }
+                PosixSocketOption::SO_KEEPALIVE => {
+                    let enable = sockopt_read_int(optval)? != 0;
+                    self.update_option(SocketOptions::KEEPALIVE, enable);
+                }
+                PosixSocketOption::SO_BINDTODEVICE => {
+                    let port = match self.endpoint() {
+                        Some(Endpoint::Ip(Some(ip))) => Some(ip.port),
+                        _ => None,
+                    };
+                    self.metadata.bind_to_device(optval, port)?;
+                }
+                PosixSocketOption::SO_LINGER => {
+                    let linger = PosixLinger::from_bytes(optval)?;
+                    self.linger = if linger.l_onoff != 0 {
+                        Some(linger.l_linger.max(0) as u32)
+                    } else {
+                        None
+                    };
+                }
+                _ => warn!("tcp setsockopt: option {:?} is not supported", optname),
+            }
+            return Ok(());
+        }
+
+        if level as u16 == u16::from(PosixIpProtocol::TCP) {
+            let optname = PosixTcpSocketOptions::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            match optname {
+                PosixTcpSocketOptions::NoDelay => {
+                    let enable = sockopt_read_int(optval)? != 0;
+                    self.update_option(SocketOptions::NODELAY, enable);
+                }
+                _ => warn!("tcp setsockopt: option {:?} is not supported", optname),
+            }
+            return Ok(());
+        }
+
+        if level as u16 == u16::from(PosixIpProtocol::IPv6) {
+            let optname = PosixIpv6SocketOptions::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            match optname {
+                PosixIpv6SocketOptions::V6Only => {
+                    let bound = self.local_endpoint.is_some();
+                    self.family.set_v6only(optval, bound)?;
+                }
+                _ => warn!("tcp setsockopt: option {:?} is not supported", optname),
+            }
+            return Ok(());
+        }
+
+        warn!("tcp setsockopt: level {} is not supported", level);
+        return Ok(());
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        let flag = |option: SocketOptions| self.metadata.options.contains(option) as i32;
+
+        if level as u8 == SOL_SOCKET {
+            let optname = PosixSocketOption::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            return match optname {
+                PosixSocketOption::SO_REUSEADDR => {
+                    sockopt_write_int(optval, flag(SocketOptions::REUSEADDR))
+                }
+                PosixSocketOption::SO_REUSEPORT => {
+                    sockopt_write_int(optval, flag(SocketOptions::REUSEPORT))
+                }
+                PosixSocketOption::SO_KEEPALIVE => {
+                    sockopt_write_int(optval, flag(SocketOptions::KEEPALIVE))
+                }
+                PosixSocketOption::SO_BINDTODEVICE => {
+                    sockopt_write_ifname(optval, self.metadata.bound_device)
+                }
+                PosixSocketOption::SO_LINGER => PosixLinger {
+                    l_onoff: self.linger.is_some() as i32,
+                    l_linger: self.linger.unwrap_or(0) as i32,
+                }
+                .to_bytes(optval),
+                _ => Err(SystemError::ENOPROTOOPT),
+            };
+        }
+
+        if level as u16 == u16::from(PosixIpProtocol::TCP) {
+            let optname = PosixTcpSocketOptions::try_from(optname as i32)
+                .map_err(|_| SystemError::ENOPROTOOPT)?;
+            return match optname {
+                PosixTcpSocketOptions::NoDelay => {
+                    sockopt_write_int(optval, flag(SocketOptions::NODELAY))
+                }
+                PosixTcpSocketOptions::Info => {
+                    let sockets = SOCKET_SET.lock_irqsave();
+                    let socket =
+                        sockets.get::<tcp::Socket>(self.handles[0].smoltcp_handle().unwrap());
+                    PosixTcpInfo::new(socket).to_bytes(optval)
+                }
+                _ => Err(SystemError::ENOPROTOOPT),
+            };
+        }
+
+        if level as u16 == u16::from(PosixIpProtocol::IPv6) {
+            return match PosixIpv6SocketOptions::try_from(optname as i32) {
+                Ok(PosixIpv6SocketOptions::V6Only) => self.family.get_v6only(optval),
+                _ => Err(SystemError::ENOPROTOOPT),
+            };
+        }
+
+        return Err(SystemError::ENOPROTOOPT);
+    }
+
     fn box_clone(&self) -> Box<dyn Socket> {
         Box::new(self.clone())
     }
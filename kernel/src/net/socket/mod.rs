@@ -1,8 +1,12 @@
-use core::{any::Any, fmt::Debug, sync::atomic::AtomicUsize};
+use core::{
+    any::Any,
+    fmt::Debug,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use alloc::{
     boxed::Box,
-    collections::LinkedList,
+    collections::BTreeMap,
     string::String,
     sync::{Arc, Weak},
     vec::Vec,
@@ -12,38 +16,57 @@ use log::warn;
 use smoltcp::{
     iface::SocketSet,
     socket::{self, raw, tcp, udp},
+    wire,
 };
 use system_error::SystemError;
 
 use crate::{
     arch::rand::rand,
-    filesystem::vfs::{
-        file::FileMode, syscall::ModeType, FilePrivateData, FileSystem, FileType, IndexNode,
-        Metadata,
+    debug::obj_lifetime::{obj_lifetime_release, obj_lifetime_track, ObjKind},
+    filesystem::{
+        page_cache::PageCache,
+        vfs::{
+            core::generate_inode_id, file::FileMode, syscall::ModeType, FilePrivateData,
+            FileSystem, FileType, FsInfo, IndexNode, InodeId, Metadata, SuperBlock,
+        },
     },
     libs::{
         rwlock::{RwLock, RwLockWriteGuard},
         spinlock::{SpinLock, SpinLockGuard},
         wait_queue::EventWaitQueue,
     },
-    process::{Pid, ProcessManager},
+    mm::{
+        fault::{PageFaultHandler, PageFaultMessage},
+        VmFaultReason,
+    },
+    process::{cred::CAPFlags, Pid, ProcessManager},
     sched::{schedule, SchedMode},
 };
 
 use self::{
     handle::GlobalSocketHandle,
-    inet::{RawSocket, TcpSocket, UdpSocket},
-    unix::{SeqpacketSocket, StreamSocket},
+    inet::{InetFamily, RawSocket, TcpSocket, UdpSocket},
+    netlink::NetlinkSocket,
+    packet::PacketSocket,
+    scm::ScmData,
+    unix::{DgramSocket, SeqpacketSocket, StreamSocket},
+    xdp::XdpSocket,
 };
 
 use super::{
-    event_poll::{EPollEventType, EPollItem, EventPoll},
-    Endpoint, Protocol, ShutdownType,
+    event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll},
+    Endpoint, Protocol, ShutdownType, NET_DEVICES,
 };
 
 pub mod handle;
 pub mod inet;
+pub mod netlink;
+pub mod packet;
+pub mod proc;
+pub mod scm;
+pub mod tcp_info;
 pub mod unix;
+pub mod xdp;
 
 lazy_static! {
     /// 所有socket的集合
@@ -60,28 +83,157 @@ lazy_static! {
 // See: linux-5.19.10/include/uapi/asm-generic/socket.h#9
 pub const SOL_SOCKET: u8 = 1;
 
+/// setsockopt(2)中SO_LINGER选项的值
+///
+/// 参考：https://code.dragonos.org.cn/xref/linux-5.19.10/include/linux/socket.h#27
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixLinger {
+    /// 是否启用linger
+    pub l_onoff: i32,
+    /// 关闭时最多等待的秒数
+    pub l_linger: i32,
+}
+
+impl PosixLinger {
+    pub fn from_bytes(optval: &[u8]) -> Result<Self, SystemError> {
+        if optval.len() < core::mem::size_of::<Self>() {
+            return Err(SystemError::EINVAL);
+        }
+        Ok(Self {
+            l_onoff: i32::from_ne_bytes(optval[0..4].try_into().unwrap()),
+            l_linger: i32::from_ne_bytes(optval[4..8].try_into().unwrap()),
+        })
+    }
+
+    /// 写入optval，返回写入的长度
+    pub fn to_bytes(&self, optval: &mut [u8]) -> Result<usize, SystemError> {
+        if optval.len() < core::mem::size_of::<Self>() {
+            return Err(SystemError::EINVAL);
+        }
+        optval[0..4].copy_from_slice(&self.l_onoff.to_ne_bytes());
+        optval[4..8].copy_from_slice(&self.l_linger.to_ne_bytes());
+        Ok(core::mem::size_of::<Self>())
+    }
+}
+
+/// 从setsockopt(2)的optval中读取int类型的选项值
+pub fn sockopt_read_int(optval: &[u8]) -> Result<i32, SystemError> {
+    if optval.len() < core::mem::size_of::<i32>() {
+        return Err(SystemError::EINVAL);
+    }
+    Ok(i32::from_ne_bytes(optval[0..4].try_into().unwrap()))
+}
+
+/// 把int类型的选项值写入getsockopt(2)的optval，返回写入的长度
+pub fn sockopt_write_int(optval: &mut [u8], value: i32) -> Result<usize, SystemError> {
+    if optval.len() < core::mem::size_of::<i32>() {
+        return Err(SystemError::EINVAL);
+    }
+    optval[0..4].copy_from_slice(&value.to_ne_bytes());
+    Ok(core::mem::size_of::<i32>())
+}
+
+/// 网卡名的最大长度，包括结尾的'\0'
+const IFNAMSIZ: usize = 16;
+
+/// 解析SO_BINDTODEVICE的选项值（网卡名），返回网卡id
+///
+/// 网卡名为空时表示解除绑定，返回None
+pub fn sockopt_read_ifname(optval: &[u8]) -> Result<Option<usize>, SystemError> {
+    let name = &optval[..optval.len().min(IFNAMSIZ)];
+    let name = name.split(|b| *b == 0).next().unwrap_or(&[]);
+    if name.is_empty() {
+        return Ok(None);
+    }
+    let name = core::str::from_utf8(name).map_err(|_| SystemError::ENODEV)?;
+    NET_DEVICES
+        .read_irqsave()
+        .values()
+        .find(|iface| iface.iface_name() == name)
+        .map(|iface| Some(iface.nic_id()))
+        .ok_or(SystemError::ENODEV)
+}
+
+/// 把SO_BINDTODEVICE绑定的网卡名写入getsockopt(2)的optval，返回写入的长度，没有绑定时为0
+pub fn sockopt_write_ifname(
+    optval: &mut [u8],
+    device: Option<usize>,
+) -> Result<usize, SystemError> {
+    let Some(device) = device else {
+        return Ok(0);
+    };
+    let name = NET_DEVICES
+        .read_irqsave()
+        .get(&device)
+        .map(|iface| iface.iface_name())
+        .ok_or(SystemError::ENODEV)?;
+    let len = name.len() + 1;
+    if optval.len() < len {
+        return Err(SystemError::EINVAL);
+    }
+    optval[..name.len()].copy_from_slice(name.as_bytes());
+    optval[name.len()] = 0;
+    Ok(len)
+}
+
 /// 根据地址族、socket类型和协议创建socket
-pub(super) fn new_socket(
+///
+/// `protocol`是用户传入的原始值：对于AF_PACKET，它是网络字节序的以太网协议号；
+/// `options`是socket的初始选项，阻塞的socket应当带有`SocketOptions::BLOCK`
+pub(crate) fn new_socket(
     address_family: AddressFamily,
     socket_type: PosixSocketType,
-    protocol: Protocol,
+    protocol: usize,
+    options: SocketOptions,
 ) -> Result<Box<dyn Socket>, SystemError> {
     let socket: Box<dyn Socket> = match address_family {
         AddressFamily::Unix => match socket_type {
-            PosixSocketType::Stream => Box::new(StreamSocket::new(SocketOptions::default())),
-            PosixSocketType::SeqPacket => Box::new(SeqpacketSocket::new(SocketOptions::default())),
+            PosixSocketType::Stream => Box::new(StreamSocket::new(options)),
+            PosixSocketType::SeqPacket => Box::new(SeqpacketSocket::new(options)),
+            PosixSocketType::Datagram => Box::new(DgramSocket::new(options)),
             _ => {
                 return Err(SystemError::EINVAL);
             }
         },
         AddressFamily::INet => match socket_type {
-            PosixSocketType::Stream => Box::new(TcpSocket::new(SocketOptions::default())),
-            PosixSocketType::Datagram => Box::new(UdpSocket::new(SocketOptions::default())),
-            PosixSocketType::Raw => Box::new(RawSocket::new(protocol, SocketOptions::default())),
+            PosixSocketType::Stream => Box::new(TcpSocket::new(options, InetFamily::V4)),
+            PosixSocketType::Datagram => Box::new(UdpSocket::new(options, InetFamily::V4)),
+            PosixSocketType::Raw => {
+                if !ProcessManager::current_pcb()
+                    .cred()
+                    .has_capability(CAPFlags::CAP_NET_RAW)
+                {
+                    return Err(SystemError::EPERM);
+                }
+                Box::new(RawSocket::new(Protocol::from(protocol as u8), options))
+            }
             _ => {
                 return Err(SystemError::EINVAL);
             }
         },
+        AddressFamily::INet6 => {
+            let family = InetFamily::V6 { v6only: false };
+            match socket_type {
+                PosixSocketType::Stream => Box::new(TcpSocket::new(options, family)),
+                PosixSocketType::Datagram => Box::new(UdpSocket::new(options, family)),
+                _ => {
+                    return Err(SystemError::ESOCKTNOSUPPORT);
+                }
+            }
+        }
+        AddressFamily::Netlink => match socket_type {
+            PosixSocketType::Datagram | PosixSocketType::Raw => {
+                Box::new(NetlinkSocket::new(protocol as u8, options)?)
+            }
+            _ => {
+                return Err(SystemError::ESOCKTNOSUPPORT);
+            }
+        },
+        AddressFamily::Packet => {
+            Box::new(PacketSocket::new(socket_type, protocol as u16, options)?)
+        }
+        AddressFamily::Xdp => Box::new(XdpSocket::new(socket_type, protocol, options)?),
         _ => {
             return Err(SystemError::EAFNOSUPPORT);
         }
@@ -111,6 +263,29 @@ pub trait Socket: Sync + Send + Debug + Any {
     /// @return 返回写入的数据的长度
     fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError>;
 
+    /// @brief 从socket中读取数据，同时取出随数据传递的辅助数据，供recvmsg(2)使用
+    ///
+    /// 默认不支持辅助数据，等同于read
+    fn read_msg(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint, ScmData) {
+        let (n, endpoint) = self.read(buf);
+        (n, endpoint, ScmData::default())
+    }
+
+    /// @brief 向socket中写入数据，同时发送辅助数据（SCM_RIGHTS等），供sendmsg(2)使用
+    ///
+    /// 默认不支持辅助数据，带有辅助数据时返回EINVAL
+    fn write_msg(
+        &self,
+        buf: &[u8],
+        to: Option<Endpoint>,
+        scm: ScmData,
+    ) -> Result<usize, SystemError> {
+        if !scm.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+        self.write(buf, to)
+    }
+
     /// @brief 对应于POSIX的connect函数，用于连接到指定的远程服务器端点
     ///
     /// It is used to establish a connection to a remote server.
@@ -223,7 +398,7 @@ pub trait Socket: Sync + Send + Debug + Any {
     ///
     /// @return 返回设置是否成功, 如果不支持该选项，返回ENOSYS
     fn setsockopt(
-        &self,
+        &mut self,
         _level: usize,
         _optname: usize,
         _optval: &[u8],
@@ -232,33 +407,51 @@ pub trait Socket: Sync + Send + Debug + Any {
         Ok(())
     }
 
-    fn socket_handle(&self) -> GlobalSocketHandle;
+    /// @brief 获取socket的选项
+    ///
+    /// @param level 选项的层次
+    /// @param optname 选项的名称
+    /// @param optval 存放选项的值的缓冲区
+    ///
+    /// @return 返回选项的值的长度, 如果不支持该选项，返回ENOPROTOOPT，由调用者按通用的方式处理
+    fn getsockopt(
+        &self,
+        _level: usize,
+        _optname: usize,
+        _optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        Err(SystemError::ENOPROTOOPT)
+    }
 
-    fn write_buffer(&self, _buf: &[u8]) -> Result<usize, SystemError> {
-        todo!()
+    /// @brief 检查能否把socket与用户程序共享的内存（如AF_XDP的环形队列）映射到`offset`处
+    fn mmap(&self, _start: usize, _len: usize, _offset: usize) -> Result<(), SystemError> {
+        Err(SystemError::ENODEV)
     }
 
+    /// @brief 与用户程序共享的内存所在的页缓存，mmap之后的缺页从这里取得页面
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        None
+    }
+
+    fn socket_handle(&self) -> GlobalSocketHandle;
+
     fn as_any_ref(&self) -> &dyn Any;
 
     fn as_any_mut(&mut self) -> &mut dyn Any;
 
     fn add_epoll(&mut self, epitem: Arc<EPollItem>) -> Result<(), SystemError> {
-        let posix_item = self.posix_item();
-        posix_item.add_epoll(epitem);
+        self.posix_item().epitems.add(epitem);
         Ok(())
     }
 
     fn remove_epoll(&mut self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
-        let posix_item = self.posix_item();
-        posix_item.remove_epoll(epoll)?;
-
-        Ok(())
+        self.posix_item().epitems.remove(epoll)
     }
 
     fn clear_epoll(&mut self) -> Result<(), SystemError> {
         let posix_item = self.posix_item();
 
-        for epitem in posix_item.epitems.lock_irqsave().iter() {
+        for epitem in posix_item.epitems.take_all().iter() {
             let epoll = epitem.epoll();
 
             if let Some(epoll) = epoll.upgrade() {
@@ -282,11 +475,39 @@ impl Clone for Box<dyn Socket> {
 
 /// # Socket在文件系统中的inode封装
 #[derive(Debug)]
-pub struct SocketInode(SpinLock<Box<dyn Socket>>, AtomicUsize);
+pub struct SocketInode(SpinLock<Box<dyn Socket>>, AtomicUsize, InodeId);
+
+/// 所有存活的socket inode，key为inode号。用于生成`/proc/net/tcp`等socket列表
+static SOCKET_INODES: SpinLock<BTreeMap<InodeId, Weak<SocketInode>>> =
+    SpinLock::new(BTreeMap::new());
+
+/// 获取所有存活的socket inode，按inode号排序
+pub fn socket_inodes() -> Vec<Arc<SocketInode>> {
+    SOCKET_INODES
+        .lock_irqsave()
+        .values()
+        .filter_map(|inode| inode.upgrade())
+        .collect()
+}
 
 impl SocketInode {
     pub fn new(socket: Box<dyn Socket>) -> Arc<Self> {
-        Arc::new(Self(SpinLock::new(socket), AtomicUsize::new(0)))
+        let inode = Arc::new(Self(
+            SpinLock::new(socket),
+            AtomicUsize::new(0),
+            generate_inode_id(),
+        ));
+        SOCKET_INODES
+            .lock_irqsave()
+            .insert(inode.2, Arc::downgrade(&inode));
+        obj_lifetime_track(ObjKind::Socket, &inode);
+        inode
+    }
+
+    /// socket的inode号，与`/proc/<pid>/fd`中的`socket:[<inode号>]`相同
+    #[inline]
+    pub fn inode_id(&self) -> InodeId {
+        self.2
     }
 
     #[inline]
@@ -302,14 +523,17 @@ impl SocketInode {
         let prev_ref_count = self.1.fetch_sub(1, core::sync::atomic::Ordering::SeqCst);
         if prev_ref_count == 1 {
             // 最后一次关闭，需要释放
+            obj_lifetime_release(self);
             let mut socket = self.0.lock_irqsave();
 
             if socket.metadata().socket_type == SocketType::Unix {
+                socket.close();
                 return Ok(());
             }
 
             if let Some(Endpoint::Ip(Some(ip))) = socket.endpoint() {
-                PORT_MANAGER.unbind_port(socket.metadata().socket_type, ip.port);
+                let metadata = socket.metadata();
+                PORT_MANAGER.unbind_port(metadata.socket_type, ip.port, metadata.bound_device);
             }
 
             socket.clear_epoll()?;
@@ -327,6 +551,7 @@ impl SocketInode {
 
 impl Drop for SocketInode {
     fn drop(&mut self) {
+        SOCKET_INODES.lock_irqsave().remove(&self.2);
         for _ in 0..self.1.load(core::sync::atomic::Ordering::SeqCst) {
             let _ = self.do_close();
         }
@@ -374,8 +599,32 @@ impl IndexNode for SocketInode {
         return Ok(events.bits() as usize);
     }
 
+    fn add_epitem(
+        &self,
+        epitem: Arc<EPollItem>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.inner().add_epoll(epitem)
+    }
+
+    fn remove_epitem(
+        &self,
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<(), SystemError> {
+        self.inner().remove_epoll(epoll)
+    }
+
+    fn mmap(&self, start: usize, len: usize, offset: usize) -> Result<(), SystemError> {
+        self.inner().mmap(start, len, offset)
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.inner().page_cache()
+    }
+
     fn fs(&self) -> Arc<dyn FileSystem> {
-        todo!()
+        Arc::new(SocketFakeFs)
     }
 
     fn as_any_ref(&self) -> &dyn Any {
@@ -388,6 +637,7 @@ impl IndexNode for SocketInode {
 
     fn metadata(&self) -> Result<Metadata, SystemError> {
         let meta = Metadata {
+            inode_id: self.2,
             mode: ModeType::from_bits_truncate(0o755),
             file_type: FileType::Socket,
             ..Default::default()
@@ -401,19 +651,58 @@ impl IndexNode for SocketInode {
     }
 }
 
+/// 用于处理socket共享内存缺页的伪文件系统
+#[derive(Debug)]
+struct SocketFakeFs;
+
+impl FileSystem for SocketFakeFs {
+    fn root_inode(&self) -> Arc<dyn IndexNode> {
+        panic!("SocketFakeFs does not have a root inode")
+    }
+
+    fn info(&self) -> FsInfo {
+        panic!("SocketFakeFs does not have a filesystem info")
+    }
+
+    fn as_any_ref(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "sockfs"
+    }
+
+    fn super_block(&self) -> SuperBlock {
+        panic!("SocketFakeFs does not have a super block")
+    }
+
+    unsafe fn fault(&self, pfm: &mut PageFaultMessage) -> VmFaultReason {
+        PageFaultHandler::filemap_fault(pfm)
+    }
+
+    unsafe fn map_pages(
+        &self,
+        pfm: &mut PageFaultMessage,
+        start_pgoff: usize,
+        end_pgoff: usize,
+    ) -> VmFaultReason {
+        PageFaultHandler::filemap_map_pages(pfm, start_pgoff, end_pgoff)
+    }
+}
+
 #[derive(Debug)]
 pub struct PosixSocketHandleItem {
     /// socket的waitqueue
     wait_queue: Arc<EventWaitQueue>,
 
-    pub epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+    pub epitems: EPollItems,
 }
 
 impl PosixSocketHandleItem {
     pub fn new(wait_queue: Option<Arc<EventWaitQueue>>) -> Self {
         Self {
             wait_queue: wait_queue.unwrap_or(Arc::new(EventWaitQueue::new())),
-            epitems: SpinLock::new(LinkedList::new()),
+            epitems: EPollItems::new(),
         }
     }
     /// ## 在socket的等待队列上睡眠
@@ -426,23 +715,16 @@ impl PosixSocketHandleItem {
         schedule(SchedMode::SM_NONE);
     }
 
-    pub fn add_epoll(&self, epitem: Arc<EPollItem>) {
-        self.epitems.lock_irqsave().push_back(epitem)
-    }
-
-    pub fn remove_epoll(&self, epoll: &Weak<SpinLock<EventPoll>>) -> Result<(), SystemError> {
-        let is_remove = !self
-            .epitems
-            .lock_irqsave()
-            .extract_if(|x| x.epoll().ptr_eq(epoll))
-            .collect::<Vec<_>>()
-            .is_empty();
-
-        if is_remove {
-            return Ok(());
+    /// ## 以独占的方式在socket的等待队列上睡眠
+    ///
+    /// 多个进程等待同一事件（如在同一个socket上accept）时，每次只唤醒其中一个
+    pub fn sleep_exclusive(&self, events: u64) {
+        unsafe {
+            ProcessManager::preempt_disable();
+            self.wait_queue.sleep_without_schedule_exclusive(events);
+            ProcessManager::preempt_enable();
         }
-
-        Err(SystemError::ENOENT)
+        schedule(SchedMode::SM_NONE);
     }
 
     /// ### 唤醒该队列上等待events的进程
@@ -454,6 +736,22 @@ impl PosixSocketHandleItem {
     pub fn wakeup_any(&self, events: u64) {
         self.wait_queue.wakeup_any(events);
     }
+
+    /// socket的读或写被关闭之后，唤醒阻塞在读写上的进程和epoll的等待者
+    pub fn wakeup_shutdown(&self, how: ShutdownType) {
+        let mut events = EPollEventType::EPOLLIN
+            | EPollEventType::EPOLLRDNORM
+            | EPollEventType::EPOLLOUT
+            | EPollEventType::EPOLLWRNORM;
+        if how.contains(ShutdownType::RCV_SHUTDOWN) {
+            events |= EPollEventType::EPOLLRDHUP;
+        }
+        if how == ShutdownType::SHUTDOWN_MASK {
+            events |= EPollEventType::EPOLLHUP;
+        }
+        self.wakeup_any(events.bits() as u64);
+        self.epitems.wakeup(Some(events)).ok();
+    }
 }
 #[derive(Debug)]
 pub struct SocketHandleItem {
@@ -490,13 +788,27 @@ impl SocketHandleItem {
     }
 }
 
+/// 端口的占用情况
+#[derive(Debug)]
+struct PortOwner {
+    /// 最先绑定该端口的进程
+    #[allow(dead_code)]
+    pid: Pid,
+    /// 绑定时是否设置了SO_REUSEADDR或SO_REUSEPORT
+    reuse: bool,
+    /// 绑定到该端口的各个socket通过SO_BINDTODEVICE绑定的网卡，None表示没有绑定网卡
+    devices: Vec<Option<usize>>,
+}
+
 /// # TCP 和 UDP 的端口管理器。
 /// 如果 TCP/UDP 的 socket 绑定了某个端口，它会在对应的表中记录，以检测端口冲突。
 pub struct PortManager {
     // TCP 端口记录表
-    tcp_port_table: SpinLock<HashMap<u16, Pid>>,
+    tcp_port_table: SpinLock<HashMap<u16, PortOwner>>,
     // UDP 端口记录表
-    udp_port_table: SpinLock<HashMap<u16, Pid>>,
+    udp_port_table: SpinLock<HashMap<u16, PortOwner>>,
+    /// 绑定了网卡的socket的数量，为0时收发路径不需要检查
+    bound_devices: AtomicUsize,
 }
 
 impl PortManager {
@@ -504,6 +816,7 @@ impl PortManager {
         return Self {
             tcp_port_table: SpinLock::new(HashMap::new()),
             udp_port_table: SpinLock::new(HashMap::new()),
+            bound_devices: AtomicUsize::new(0),
         };
     }
 
@@ -532,8 +845,8 @@ impl PortManager {
 
             // 使用 ListenTable 检查端口是否被占用
             let listen_table_guard = match socket_type {
-                SocketType::Udp => self.udp_port_table.lock(),
-                SocketType::Tcp => self.tcp_port_table.lock(),
+                SocketType::Udp => self.udp_port_table.lock_irqsave(),
+                SocketType::Tcp => self.tcp_port_table.lock_irqsave(),
                 _ => panic!("{:?} cann't get a port", socket_type),
             };
             if listen_table_guard.get(&port).is_none() {
@@ -547,36 +860,164 @@ impl PortManager {
 
     /// @brief 检测给定端口是否已被占用，如果未被占用则在 TCP/UDP 对应的表中记录
     ///
-    /// TODO: 增加支持端口复用的逻辑
-    pub fn bind_port(&self, socket_type: SocketType, port: u16) -> Result<(), SystemError> {
+    /// 已经绑定该端口的socket与新的socket都允许端口复用（见[`SocketOptions::allows_port_reuse`]）时，
+    /// 也可以绑定成功
+    ///
+    /// @param device socket通过SO_BINDTODEVICE绑定的网卡
+    pub fn bind_port(
+        &self,
+        socket_type: SocketType,
+        port: u16,
+        reuse: bool,
+        device: Option<usize>,
+    ) -> Result<(), SystemError> {
         if port > 0 {
             let mut listen_table_guard = match socket_type {
-                SocketType::Udp => self.udp_port_table.lock(),
-                SocketType::Tcp => self.tcp_port_table.lock(),
+                SocketType::Udp => self.udp_port_table.lock_irqsave(),
+                SocketType::Tcp => self.tcp_port_table.lock_irqsave(),
                 _ => panic!("{:?} cann't bind a port", socket_type),
             };
-            match listen_table_guard.get(&port) {
+            match listen_table_guard.get_mut(&port) {
+                Some(owner) if owner.reuse && reuse => owner.devices.push(device),
                 Some(_) => return Err(SystemError::EADDRINUSE),
-                None => listen_table_guard.insert(port, ProcessManager::current_pid()),
+                None => {
+                    listen_table_guard.insert(
+                        port,
+                        PortOwner {
+                            pid: ProcessManager::current_pid(),
+                            reuse,
+                            devices: vec![device],
+                        },
+                    );
+                }
             };
             drop(listen_table_guard);
+            if device.is_some() {
+                self.bound_devices.fetch_add(1, Ordering::SeqCst);
+            }
         }
         return Ok(());
     }
 
     /// @brief 在对应的端口记录表中将端口和 socket 解绑
     /// should call this function when socket is closed or aborted
-    pub fn unbind_port(&self, socket_type: SocketType, port: u16) {
+    pub fn unbind_port(&self, socket_type: SocketType, port: u16, device: Option<usize>) {
         let mut listen_table_guard = match socket_type {
-            SocketType::Udp => self.udp_port_table.lock(),
-            SocketType::Tcp => self.tcp_port_table.lock(),
+            SocketType::Udp => self.udp_port_table.lock_irqsave(),
+            SocketType::Tcp => self.tcp_port_table.lock_irqsave(),
             _ => {
                 return;
             }
         };
-        listen_table_guard.remove(&port);
+        if let Some(owner) = listen_table_guard.get_mut(&port) {
+            if let Some(pos) = owner.devices.iter().position(|d| *d == device) {
+                owner.devices.remove(pos);
+                if device.is_some() {
+                    self.bound_devices.fetch_sub(1, Ordering::SeqCst);
+                }
+            }
+            if owner.devices.is_empty() {
+                listen_table_guard.remove(&port);
+            }
+        }
         drop(listen_table_guard);
     }
+
+    /// 已经绑定端口的socket修改了SO_BINDTODEVICE时，更新端口记录表
+    pub fn set_port_device(
+        &self,
+        socket_type: SocketType,
+        port: u16,
+        old: Option<usize>,
+        new: Option<usize>,
+    ) {
+        let mut listen_table_guard = match socket_type {
+            SocketType::Udp => self.udp_port_table.lock_irqsave(),
+            SocketType::Tcp => self.tcp_port_table.lock_irqsave(),
+            _ => return,
+        };
+        let Some(owner) = listen_table_guard.get_mut(&port) else {
+            return;
+        };
+        let Some(device) = owner.devices.iter_mut().find(|d| **d == old) else {
+            return;
+        };
+        *device = new;
+        if old.is_some() {
+            self.bound_devices.fetch_sub(1, Ordering::SeqCst);
+        }
+        if new.is_some() {
+            self.bound_devices.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// 是否有socket绑定了网卡
+    #[inline]
+    pub fn has_bound_devices(&self) -> bool {
+        self.bound_devices.load(Ordering::Relaxed) != 0
+    }
+
+    /// 检查经过网卡`nic_id`收发的以太网帧是否属于绑定了其他网卡的socket
+    ///
+    /// 帧中TCP/UDP的本地端口（接收时为目的端口，发送时为源端口）上的socket都绑定了网卡，
+    /// 且没有一个绑定到`nic_id`时返回false，此时应当丢弃这个帧
+    pub fn device_allows_frame(&self, nic_id: usize, frame: &[u8], tx: bool) -> bool {
+        if !self.has_bound_devices() {
+            return true;
+        }
+        let Some((socket_type, src_port, dst_port)) = parse_frame_ports(frame) else {
+            return true;
+        };
+        let port = if tx { src_port } else { dst_port };
+        let listen_table_guard = match socket_type {
+            SocketType::Udp => self.udp_port_table.lock_irqsave(),
+            _ => self.tcp_port_table.lock_irqsave(),
+        };
+        let Some(owner) = listen_table_guard.get(&port) else {
+            return true;
+        };
+        owner
+            .devices
+            .iter()
+            .any(|d| d.map_or(true, |d| d == nic_id))
+    }
+}
+
+/// 解析以太网帧中TCP/UDP数据包的协议与端口，返回(协议, 源端口, 目的端口)
+fn parse_frame_ports(frame: &[u8]) -> Option<(SocketType, u16, u16)> {
+    let eth = wire::EthernetFrame::new_checked(frame).ok()?;
+    let (protocol, payload) = match eth.ethertype() {
+        wire::EthernetProtocol::Ipv4 => {
+            let ip = wire::Ipv4Packet::new_checked(eth.payload()).ok()?;
+            // 只有第一个分片带有传输层头部
+            if ip.frag_offset() != 0 {
+                return None;
+            }
+            (
+                ip.next_header(),
+                &eth.payload()[ip.header_len() as usize..ip.total_len() as usize],
+            )
+        }
+        wire::EthernetProtocol::Ipv6 => {
+            let ip = wire::Ipv6Packet::new_checked(eth.payload()).ok()?;
+            (
+                ip.next_header(),
+                &eth.payload()[wire::IPV6_HEADER_LEN..ip.total_len()],
+            )
+        }
+        _ => return None,
+    };
+    match protocol {
+        wire::IpProtocol::Tcp => {
+            let tcp = wire::TcpPacket::new_checked(payload).ok()?;
+            Some((SocketType::Tcp, tcp.src_port(), tcp.dst_port()))
+        }
+        wire::IpProtocol::Udp => {
+            let udp = wire::UdpPacket::new_checked(payload).ok()?;
+            Some((SocketType::Udp, udp.src_port(), udp.dst_port()))
+        }
+        _ => None,
+    }
 }
 
 /// @brief socket的类型
@@ -590,6 +1031,12 @@ pub enum SocketType {
     Udp,
     /// unix域的 Socket
     Unix,
+    /// netlink的 Socket
+    Netlink,
+    /// AF_PACKET的 Socket
+    Packet,
+    /// AF_XDP的 Socket
+    Xdp,
 }
 
 bitflags! {
@@ -606,6 +1053,19 @@ bitflags! {
         const REUSEADDR = 1 << 3;
         /// 是否允许重用端口
         const REUSEPORT = 1 << 4;
+        /// 是否发送保活报文
+        const KEEPALIVE = 1 << 5;
+        /// 是否禁用Nagle算法（TCP_NODELAY）
+        const NODELAY = 1 << 6;
+        /// 是否接收对端的凭证（SO_PASSCRED）
+        const PASSCRED = 1 << 7;
+    }
+}
+
+impl SocketOptions {
+    /// 绑定端口时是否允许与其他socket共用端口
+    pub fn allows_port_reuse(&self) -> bool {
+        self.intersects(SocketOptions::REUSEADDR | SocketOptions::REUSEPORT)
     }
 }
 
@@ -622,6 +1082,8 @@ pub struct SocketMetadata {
     pub metadata_buf_size: usize,
     /// socket的选项
     pub options: SocketOptions,
+    /// 通过SO_BINDTODEVICE绑定的网卡id
+    pub bound_device: Option<usize>,
 }
 
 impl SocketMetadata {
@@ -638,7 +1100,29 @@ impl SocketMetadata {
             tx_buf_size,
             metadata_buf_size,
             options,
+            bound_device: None,
+        }
+    }
+
+    /// 处理SO_BINDTODEVICE选项
+    ///
+    /// ## 参数
+    ///
+    /// - `optval`: 网卡名，为空时解除绑定
+    /// - `port`: socket已经绑定的本地端口，绑定的网卡会同步到端口记录表中
+    pub fn bind_to_device(&mut self, optval: &[u8], port: Option<u16>) -> Result<(), SystemError> {
+        if !ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_NET_RAW)
+        {
+            return Err(SystemError::EPERM);
+        }
+        let device = sockopt_read_ifname(optval)?;
+        if let Some(port) = port {
+            PORT_MANAGER.set_port_device(self.socket_type, port, self.bound_device, device);
         }
+        self.bound_device = device;
+        Ok(())
     }
 }
 
@@ -814,7 +1298,16 @@ impl SocketPollMethod {
             events.insert(EPollEventType::EPOLLHUP);
         }
 
-        if shutdown.contains(ShutdownType::RCV_SHUTDOWN) {
+        // 本端关闭了读端，或者对端关闭了写端（已经收到FIN）
+        if shutdown.contains(ShutdownType::RCV_SHUTDOWN)
+            || matches!(
+                state,
+                tcp::State::CloseWait
+                    | tcp::State::LastAck
+                    | tcp::State::Closing
+                    | tcp::State::TimeWait
+            )
+        {
             events.insert(
                 EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM | EPollEventType::EPOLLRDHUP,
             );
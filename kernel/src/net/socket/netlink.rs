@@ -0,0 +1,256 @@
+//! AF_NETLINK套接字
+//!
+//! 目前支持三种协议：
+//!
+//! - `NETLINK_ROUTE`：查看和配置网卡、地址与路由（见`net::rtnetlink`），`ip`命令使用这个协议；
+//! - `NETLINK_KOBJECT_UEVENT`：内核把kobject的uevent多播给加入了组1的套接字，
+//!   udevd/mdev等守护进程据此在/dev下创建设备节点；
+//! - `NETLINK_NETFILTER`：配置netfilter-lite的规则集（见`net::netfilter::nfnetlink`），
+//!   内核的回复直接放入发送者的接收队列，`NETLINK_ROUTE`同样如此。
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU32, Ordering};
+use system_error::SystemError;
+
+use crate::{
+    libs::spinlock::SpinLock,
+    net::{
+        event_poll::EPollEventType,
+        netfilter::nfnetlink::{nfnetlink_rcv, NETLINK_NETFILTER},
+        rtnetlink::{rtnetlink_rcv, NETLINK_ROUTE},
+        Endpoint,
+    },
+    process::ProcessManager,
+};
+
+use super::{
+    handle::GlobalSocketHandle, PosixSocketHandleItem, Socket, SocketMetadata, SocketOptions,
+    SocketType,
+};
+
+/// 接收kobject uevent的netlink协议
+pub const NETLINK_KOBJECT_UEVENT: u8 = 15;
+/// uevent所在的多播组
+const UEVENT_GROUP: u32 = 1;
+/// 每个套接字最多缓存的消息数，超出时丢弃新的消息
+const NETLINK_MAX_QUEUED: usize = 256;
+
+/// netlink端点，对应`struct sockaddr_nl`
+#[derive(Debug, Clone, Copy)]
+pub struct NetlinkEndpoint {
+    /// 端口号，内核为0
+    pub portid: u32,
+    /// 多播组的位图
+    pub groups: u32,
+}
+
+/// 所有可能接收uevent的套接字
+static UEVENT_LISTENERS: SpinLock<Vec<Weak<NetlinkQueue>>> = SpinLock::new(Vec::new());
+
+/// 套接字的接收队列，与套接字本身分离，使得内核发送消息时不需要获取套接字的锁
+#[derive(Debug)]
+struct NetlinkQueue {
+    messages: SpinLock<VecDeque<Vec<u8>>>,
+    groups: AtomicU32,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl NetlinkQueue {
+    fn push(&self, msg: &[u8]) {
+        {
+            let mut messages = self.messages.lock_irqsave();
+            if messages.len() >= NETLINK_MAX_QUEUED {
+                return;
+            }
+            messages.push_back(msg.to_vec());
+        }
+        self.posix_item
+            .wakeup_any(EPollEventType::EPOLLIN.bits() as u64);
+        self.posix_item
+            .epitems
+            .wakeup(Some(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM))
+            .ok();
+    }
+}
+
+/// 把一条uevent消息多播给所有加入了uevent组的套接字
+pub fn netlink_broadcast_uevent(msg: &[u8]) {
+    let listeners: Vec<Arc<NetlinkQueue>> = {
+        let mut guard = UEVENT_LISTENERS.lock_irqsave();
+        guard.retain(|q| q.strong_count() > 0);
+        guard.iter().filter_map(|q| q.upgrade()).collect()
+    };
+
+    for queue in listeners {
+        if queue.groups.load(Ordering::SeqCst) & UEVENT_GROUP != 0 {
+            queue.push(msg);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NetlinkSocket {
+    metadata: SocketMetadata,
+    protocol: u8,
+    queue: Arc<NetlinkQueue>,
+    portid: Option<u32>,
+    handle: GlobalSocketHandle,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl NetlinkSocket {
+    /// 默认的元数据缓冲区大小
+    pub const DEFAULT_METADATA_BUF_SIZE: usize = 1024;
+    /// 默认的缓冲区大小
+    pub const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+
+    /// # 创建一个netlink套接字
+    ///
+    /// ## 参数
+    /// - `protocol`: netlink协议，目前支持`NETLINK_ROUTE`、`NETLINK_KOBJECT_UEVENT`和`NETLINK_NETFILTER`
+    /// - `options`: socket选项
+    pub fn new(protocol: u8, options: SocketOptions) -> Result<Self, SystemError> {
+        if !matches!(
+            protocol,
+            NETLINK_ROUTE | NETLINK_KOBJECT_UEVENT | NETLINK_NETFILTER
+        ) {
+            return Err(SystemError::EPROTONOSUPPORT);
+        }
+
+        let metadata = SocketMetadata::new(
+            SocketType::Netlink,
+            Self::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_METADATA_BUF_SIZE,
+            options,
+        );
+        let posix_item = Arc::new(PosixSocketHandleItem::new(None));
+        let queue = Arc::new(NetlinkQueue {
+            messages: SpinLock::new(VecDeque::new()),
+            groups: AtomicU32::new(0),
+            posix_item: posix_item.clone(),
+        });
+        if protocol == NETLINK_KOBJECT_UEVENT {
+            UEVENT_LISTENERS.lock_irqsave().push(Arc::downgrade(&queue));
+        }
+
+        Ok(Self {
+            metadata,
+            protocol,
+            queue,
+            portid: None,
+            handle: GlobalSocketHandle::new_kernel_handle(),
+            posix_item,
+        })
+    }
+}
+
+impl Socket for NetlinkSocket {
+    fn posix_item(&self) -> Arc<PosixSocketHandleItem> {
+        self.posix_item.clone()
+    }
+
+    fn socket_handle(&self) -> GlobalSocketHandle {
+        self.handle
+    }
+
+    fn close(&mut self) {
+        self.queue.groups.store(0, Ordering::SeqCst);
+        self.queue.messages.lock_irqsave().clear();
+    }
+
+    /// 每次读取一条完整的消息，缓冲区不够大时，超出的部分被丢弃
+    fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        let from = Endpoint::Netlink(NetlinkEndpoint {
+            portid: 0,
+            groups: if self.protocol == NETLINK_KOBJECT_UEVENT {
+                UEVENT_GROUP
+            } else {
+                0
+            },
+        });
+        loop {
+            if let Some(msg) = self.queue.messages.lock_irqsave().pop_front() {
+                let len = core::cmp::min(buf.len(), msg.len());
+                buf[..len].copy_from_slice(&msg[..len]);
+                return (Ok(len), from);
+            }
+
+            self.posix_item.sleep(EPollEventType::EPOLLIN.bits() as u64);
+            if ProcessManager::current_pcb().has_pending_signal_fast() {
+                return (Err(SystemError::ERESTARTSYS), from);
+            }
+        }
+    }
+
+    /// 用户态不能向内核发送uevent，只能发送路由和netfilter的配置请求
+    fn write(&self, buf: &[u8], _to: Option<Endpoint>) -> Result<usize, SystemError> {
+        let portid = self
+            .portid
+            .unwrap_or_else(|| ProcessManager::current_pid().data() as u32);
+        let replies = match self.protocol {
+            NETLINK_ROUTE => rtnetlink_rcv(buf, portid),
+            NETLINK_NETFILTER => nfnetlink_rcv(buf, portid),
+            _ => return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+        };
+        for reply in replies {
+            self.queue.push(&reply);
+        }
+        Ok(buf.len())
+    }
+
+    fn connect(&mut self, _endpoint: Endpoint) -> Result<(), SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let Endpoint::Netlink(endpoint) = endpoint else {
+            return Err(SystemError::EINVAL);
+        };
+
+        // portid为0时由内核分配，与Linux一样使用进程号
+        let portid = match endpoint.portid {
+            0 => ProcessManager::current_pid().data() as u32,
+            portid => portid,
+        };
+        self.portid = Some(portid);
+        self.queue.groups.store(endpoint.groups, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        Some(Endpoint::Netlink(NetlinkEndpoint {
+            portid: self.portid.unwrap_or(0),
+            groups: self.queue.groups.load(Ordering::SeqCst),
+        }))
+    }
+
+    fn poll(&self) -> EPollEventType {
+        let mut events = EPollEventType::empty();
+        if !self.queue.messages.lock_irqsave().is_empty() {
+            events.insert(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+        }
+        events
+    }
+
+    fn metadata(&self) -> SocketMetadata {
+        self.metadata.clone()
+    }
+
+    fn box_clone(&self) -> Box<dyn Socket> {
+        Box::new(self.clone())
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn core::any::Any {
+        self
+    }
+}
@@ -0,0 +1,421 @@
+//! AF_PACKET套接字
+//!
+//! 网卡收发的每一个以太网帧都会经过[`packet_rcv`]（见`driver::net::packet_tap`），
+//! 被复制给协议号与网卡都匹配的packet套接字，tcpdump、dhclient等程序依靠它工作。
+//!
+//! - `SOCK_RAW`：收发完整的以太网帧；
+//! - `SOCK_DGRAM`：收发去掉以太网头部的数据，发送时目的地址由`sockaddr_ll`指定。
+//!
+//! 创建packet套接字需要`CAP_NET_RAW`权限。
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+use smoltcp::wire::{EthernetAddress, EthernetFrame, EthernetProtocol};
+use system_error::SystemError;
+
+use crate::{
+    driver::net::{
+        stats::{netdev_stats_tx, netdev_stats_tx_dropped, netdev_stats_tx_error},
+        NetDevice,
+    },
+    libs::spinlock::SpinLock,
+    net::{event_poll::EPollEventType, Endpoint, LinkLayerEndpoint, NET_DEVICES},
+    process::{cred::CAPFlags, ProcessManager},
+};
+
+use super::{
+    handle::GlobalSocketHandle, PosixSocketHandleItem, PosixSocketType, Socket, SocketMetadata,
+    SocketOptions, SocketType,
+};
+
+/// 接收所有协议的以太网帧
+pub const ETH_P_ALL: u16 = 0x0003;
+/// 以太网头部的长度
+const ETH_HLEN: usize = 14;
+/// 不含FCS的以太网帧的最大长度
+const ETH_FRAME_LEN: usize = 1514;
+/// 每个套接字最多缓存的帧数，超出时丢弃新的帧
+const PACKET_MAX_QUEUED: usize = 256;
+/// 网卡忙时，发送一帧最多重试的次数
+const PACKET_XMIT_RETRIES: usize = 1000;
+
+/// 数据包的类型，对应`sockaddr_ll`的`sll_pkttype`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PacketType {
+    /// 发往本机
+    Host = 0,
+    /// 广播
+    Broadcast = 1,
+    /// 组播
+    Multicast = 2,
+    /// 发往其他主机（混杂模式）
+    OtherHost = 3,
+    /// 本机发出
+    Outgoing = 4,
+}
+
+impl PacketType {
+    /// 根据接收到的帧的目的地址判断数据包的类型
+    fn classify(dst: EthernetAddress, mac: EthernetAddress) -> Self {
+        if dst.is_broadcast() {
+            PacketType::Broadcast
+        } else if dst.is_multicast() {
+            PacketType::Multicast
+        } else if dst == mac {
+            PacketType::Host
+        } else {
+            PacketType::OtherHost
+        }
+    }
+}
+
+/// 所有的packet套接字
+static PACKET_LISTENERS: SpinLock<Vec<Weak<PacketQueue>>> = SpinLock::new(Vec::new());
+
+#[derive(Debug)]
+struct PacketFrame {
+    data: Vec<u8>,
+    from: LinkLayerEndpoint,
+}
+
+/// 套接字的接收队列，与套接字本身分离，使得收包路径上不需要获取套接字的锁
+#[derive(Debug)]
+struct PacketQueue {
+    frames: SpinLock<VecDeque<PacketFrame>>,
+    /// 接收的以太网协议号（主机字节序），为0表示不接收任何帧
+    protocol: AtomicU16,
+    /// 绑定的网卡，为0表示所有网卡
+    ifindex: AtomicUsize,
+    /// 是否去掉以太网头部（`SOCK_DGRAM`）
+    cooked: bool,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl PacketQueue {
+    fn accepts(&self, nic_id: usize, protocol: u16) -> bool {
+        let ifindex = self.ifindex.load(Ordering::SeqCst);
+        let want = self.protocol.load(Ordering::SeqCst);
+        (ifindex == 0 || ifindex == nic_id)
+            && (want == ETH_P_ALL || (want != 0 && want == protocol))
+    }
+
+    fn push(&self, frame: &[u8], from: LinkLayerEndpoint) {
+        let data = if self.cooked {
+            frame[ETH_HLEN..].to_vec()
+        } else {
+            frame.to_vec()
+        };
+        {
+            let mut frames = self.frames.lock_irqsave();
+            if frames.len() >= PACKET_MAX_QUEUED {
+                return;
+            }
+            frames.push_back(PacketFrame { data, from });
+        }
+        self.posix_item
+            .wakeup_any(EPollEventType::EPOLLIN.bits() as u64);
+        self.posix_item
+            .epitems
+            .wakeup(Some(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM))
+            .ok();
+    }
+}
+
+/// 把网卡收发的一个以太网帧复制给匹配的packet套接字
+///
+/// ## 参数
+///
+/// - `nic_id`: 网卡的id
+/// - `mac`: 网卡的MAC地址，用于判断接收到的帧的类型
+/// - `frame`: 完整的以太网帧
+/// - `outgoing`: 是否是本机发出的帧
+pub fn packet_rcv(nic_id: usize, mac: EthernetAddress, frame: &[u8], outgoing: bool) {
+    deliver(nic_id, mac, frame, outgoing, None);
+}
+
+fn deliver(
+    nic_id: usize,
+    mac: EthernetAddress,
+    frame: &[u8],
+    outgoing: bool,
+    origin: Option<&Arc<PacketQueue>>,
+) {
+    let listeners: Vec<Arc<PacketQueue>> = {
+        let mut guard = PACKET_LISTENERS.lock_irqsave();
+        if guard.is_empty() {
+            return;
+        }
+        guard.retain(|q| q.strong_count() > 0);
+        guard.iter().filter_map(|q| q.upgrade()).collect()
+    };
+
+    let Ok(eth) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    let protocol: u16 = eth.ethertype().into();
+    let pkttype = if outgoing {
+        PacketType::Outgoing
+    } else {
+        PacketType::classify(eth.dst_addr(), mac)
+    };
+
+    for queue in listeners {
+        // 与Linux一样，自己发出的帧不再交给自己
+        if origin.is_some_and(|origin| Arc::ptr_eq(origin, &queue)) {
+            continue;
+        }
+        if queue.accepts(nic_id, protocol) {
+            queue.push(
+                frame,
+                LinkLayerEndpoint {
+                    interface: nic_id,
+                    protocol,
+                    pkttype: pkttype as u8,
+                    addr: Some(eth.src_addr()),
+                },
+            );
+        }
+    }
+}
+
+/// AF_PACKET套接字
+///
+/// https://man7.org/linux/man-pages/man7/packet.7.html
+#[derive(Debug, Clone)]
+pub struct PacketSocket {
+    metadata: SocketMetadata,
+    queue: Arc<PacketQueue>,
+    handle: GlobalSocketHandle,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl PacketSocket {
+    /// 默认的元数据缓冲区大小
+    pub const DEFAULT_METADATA_BUF_SIZE: usize = 1024;
+    /// 默认的缓冲区大小
+    pub const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+
+    /// # 创建一个packet套接字
+    ///
+    /// ## 参数
+    /// - `socket_type`: `SOCK_RAW`或`SOCK_DGRAM`
+    /// - `protocol`: 接收的以太网协议号（网络字节序），为0时在bind之前不接收任何帧
+    /// - `options`: socket选项
+    pub fn new(
+        socket_type: PosixSocketType,
+        protocol: u16,
+        options: SocketOptions,
+    ) -> Result<Self, SystemError> {
+        if !ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_NET_RAW)
+        {
+            return Err(SystemError::EPERM);
+        }
+        let cooked = match socket_type {
+            PosixSocketType::Raw => false,
+            PosixSocketType::Datagram => true,
+            _ => return Err(SystemError::ESOCKTNOSUPPORT),
+        };
+
+        let metadata = SocketMetadata::new(
+            SocketType::Packet,
+            Self::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_METADATA_BUF_SIZE,
+            options,
+        );
+        let posix_item = Arc::new(PosixSocketHandleItem::new(None));
+        let queue = Arc::new(PacketQueue {
+            frames: SpinLock::new(VecDeque::new()),
+            protocol: AtomicU16::new(u16::from_be(protocol)),
+            ifindex: AtomicUsize::new(0),
+            cooked,
+            posix_item: posix_item.clone(),
+        });
+        PACKET_LISTENERS.lock_irqsave().push(Arc::downgrade(&queue));
+
+        Ok(Self {
+            metadata,
+            queue,
+            handle: GlobalSocketHandle::new_kernel_handle(),
+            posix_item,
+        })
+    }
+
+    /// 网卡忙时重试几次，仍然失败则返回`ENOBUFS`
+    fn xmit(iface: &Arc<dyn NetDevice>, frame: &[u8]) -> Result<(), SystemError> {
+        let nic_id = iface.nic_id();
+        for _ in 0..PACKET_XMIT_RETRIES {
+            match iface.poll_xmit(frame) {
+                Err(SystemError::EAGAIN_OR_EWOULDBLOCK) => core::hint::spin_loop(),
+                Ok(()) => {
+                    netdev_stats_tx(nic_id, frame.len());
+                    return Ok(());
+                }
+                Err(e) => {
+                    netdev_stats_tx_error(nic_id);
+                    return Err(e);
+                }
+            }
+        }
+        netdev_stats_tx_dropped(nic_id);
+        Err(SystemError::ENOBUFS)
+    }
+}
+
+impl Socket for PacketSocket {
+    fn posix_item(&self) -> Arc<PosixSocketHandleItem> {
+        self.posix_item.clone()
+    }
+
+    fn socket_handle(&self) -> GlobalSocketHandle {
+        self.handle
+    }
+
+    fn close(&mut self) {
+        self.queue.protocol.store(0, Ordering::SeqCst);
+        self.queue.frames.lock_irqsave().clear();
+    }
+
+    /// 每次读取一个完整的帧，缓冲区不够大时，超出的部分被丢弃
+    fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        loop {
+            if let Some(frame) = self.queue.frames.lock_irqsave().pop_front() {
+                let len = core::cmp::min(buf.len(), frame.data.len());
+                buf[..len].copy_from_slice(&frame.data[..len]);
+                return (Ok(len), Endpoint::LinkLayer(frame.from));
+            }
+
+            self.posix_item.sleep(EPollEventType::EPOLLIN.bits() as u64);
+            if ProcessManager::current_pcb().has_pending_signal_fast() {
+                return (
+                    Err(SystemError::ERESTARTSYS),
+                    Endpoint::LinkLayer(LinkLayerEndpoint::new(0)),
+                );
+            }
+        }
+    }
+
+    fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
+        let to = match to {
+            Some(Endpoint::LinkLayer(endpoint)) => Some(endpoint),
+            Some(_) => return Err(SystemError::EINVAL),
+            None => None,
+        };
+        let ifindex = match to.as_ref().map(|to| to.interface) {
+            Some(ifindex) if ifindex != 0 => ifindex,
+            _ => self.queue.ifindex.load(Ordering::SeqCst),
+        };
+        if ifindex == 0 {
+            return Err(SystemError::ENXIO);
+        }
+        let iface = NET_DEVICES
+            .read_irqsave()
+            .get(&ifindex)
+            .cloned()
+            .ok_or(SystemError::ENODEV)?;
+
+        let mac = iface.mac();
+        let frame = if self.queue.cooked {
+            // 目的地址与协议号只能从sockaddr_ll中获得
+            let to = to.ok_or(SystemError::EDESTADDRREQ)?;
+            let dst = to.addr.ok_or(SystemError::EINVAL)?;
+            let protocol = match to.protocol {
+                0 => self.queue.protocol.load(Ordering::SeqCst),
+                protocol => protocol,
+            };
+            if buf.len() + ETH_HLEN > ETH_FRAME_LEN {
+                return Err(SystemError::EMSGSIZE);
+            }
+            let mut frame = alloc::vec![0u8; ETH_HLEN + buf.len()];
+            let mut eth = EthernetFrame::new_unchecked(&mut frame);
+            eth.set_dst_addr(dst);
+            eth.set_src_addr(mac);
+            eth.set_ethertype(EthernetProtocol::from(protocol));
+            eth.payload_mut().copy_from_slice(buf);
+            frame
+        } else {
+            if buf.len() < ETH_HLEN {
+                return Err(SystemError::EINVAL);
+            }
+            if buf.len() > ETH_FRAME_LEN {
+                return Err(SystemError::EMSGSIZE);
+            }
+            buf.to_vec()
+        };
+
+        Self::xmit(&iface, &frame)?;
+        deliver(ifindex, mac, &frame, true, Some(&self.queue));
+        Ok(buf.len())
+    }
+
+    fn connect(&mut self, _endpoint: Endpoint) -> Result<(), SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let Endpoint::LinkLayer(endpoint) = endpoint else {
+            return Err(SystemError::EINVAL);
+        };
+        if endpoint.interface != 0 && !NET_DEVICES.read_irqsave().contains_key(&endpoint.interface)
+        {
+            return Err(SystemError::ENODEV);
+        }
+
+        self.queue
+            .ifindex
+            .store(endpoint.interface, Ordering::SeqCst);
+        // 协议号为0时保持创建时指定的协议号
+        if endpoint.protocol != 0 {
+            self.queue
+                .protocol
+                .store(endpoint.protocol, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        let ifindex = self.queue.ifindex.load(Ordering::SeqCst);
+        let addr = NET_DEVICES
+            .read_irqsave()
+            .get(&ifindex)
+            .map(|iface| iface.mac());
+        Some(Endpoint::LinkLayer(LinkLayerEndpoint {
+            interface: ifindex,
+            protocol: self.queue.protocol.load(Ordering::SeqCst),
+            pkttype: PacketType::Host as u8,
+            addr,
+        }))
+    }
+
+    fn poll(&self) -> EPollEventType {
+        let mut events = EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        if !self.queue.frames.lock_irqsave().is_empty() {
+            events.insert(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+        }
+        events
+    }
+
+    fn metadata(&self) -> SocketMetadata {
+        self.metadata.clone()
+    }
+
+    fn box_clone(&self) -> Box<dyn Socket> {
+        Box::new(self.clone())
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn core::any::Any {
+        self
+    }
+}
@@ -0,0 +1,202 @@
+//! `/proc/net`下的socket列表
+//!
+//! 格式与Linux的`/proc/net/tcp`、`/proc/net/udp`和`/proc/net/unix`相同。其中的inode号
+//! 与`/proc/<pid>/fd`中的`socket:[<inode号>]`一致，`ss`和`netstat -p`据此找到socket所属的进程。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/net/ipv4/tcp_ipv4.c#get_tcp4_sock
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+use smoltcp::wire;
+
+use crate::net::Endpoint;
+
+use super::{
+    inet::{InetFamily, TcpSocket, UdpSocket},
+    socket_inodes,
+    tcp_info::PosixTcpInfo,
+    unix::{DgramSocket, SeqpacketSocket, StreamSocket, UnixEndpoint},
+};
+
+/// Linux的`TCP_ESTABLISHED`，已连接的UDP socket也显示为这个状态
+const TCP_ESTABLISHED: u8 = 1;
+/// Linux的`TCP_CLOSE`，未连接的UDP socket显示为这个状态
+const TCP_CLOSE: u8 = 7;
+
+/// unix socket的`SS_UNCONNECTED`和`SS_CONNECTED`
+const SS_UNCONNECTED: u8 = 1;
+const SS_CONNECTED: u8 = 3;
+
+/// `/proc/net/tcp`和`/proc/net/udp`中的一行
+struct InetEntry {
+    local: wire::IpEndpoint,
+    remote: Option<wire::IpEndpoint>,
+    state: u8,
+    tx_queue: usize,
+    rx_queue: usize,
+    inode: usize,
+}
+
+fn ip_endpoint(ep: Option<Endpoint>) -> Option<wire::IpEndpoint> {
+    match ep {
+        Some(Endpoint::Ip(Some(ip))) => Some(ip),
+        _ => None,
+    }
+}
+
+/// 按照地址在内存中的字节序输出十六进制的地址和端口，与Linux相同
+fn write_ip_endpoint(s: &mut String, ep: Option<wire::IpEndpoint>, ipv6: bool) {
+    let port = ep.map(|ep| ep.port).unwrap_or(0);
+    match ep.map(|ep| ep.addr) {
+        Some(wire::IpAddress::Ipv4(addr)) => {
+            write!(s, "{:08X}", u32::from_ne_bytes(addr.0)).ok();
+        }
+        Some(wire::IpAddress::Ipv6(addr)) => {
+            for word in addr.0.chunks_exact(4) {
+                write!(s, "{:08X}", u32::from_ne_bytes(word.try_into().unwrap())).ok();
+            }
+        }
+        None => s.push_str(&"0".repeat(if ipv6 { 32 } else { 8 })),
+    }
+    write!(s, ":{:04X}", port).ok();
+}
+
+fn inet_show(header: &str, ipv6: bool, entries: Vec<InetEntry>) -> String {
+    let mut s = String::from(header);
+    for (i, entry) in entries.iter().enumerate() {
+        write!(s, "{:4}: ", i).ok();
+        write_ip_endpoint(&mut s, Some(entry.local), ipv6);
+        s.push(' ');
+        write_ip_endpoint(&mut s, entry.remote, ipv6);
+        writeln!(
+            s,
+            " {:02X} {:08X}:{:08X} 00:00000000 00000000 {:5} {:8} {}",
+            entry.state, entry.tx_queue, entry.rx_queue, 0, 0, entry.inode
+        )
+        .ok();
+    }
+    s
+}
+
+fn is_ipv6(family: InetFamily) -> bool {
+    matches!(family, InetFamily::V6 { .. })
+}
+
+/// 生成`/proc/net/tcp`（`ipv6`为false）或`/proc/net/tcp6`的内容
+///
+/// 只列出已经绑定了本地地址的socket
+pub fn tcp_procfs_show(ipv6: bool) -> String {
+    let mut entries = Vec::new();
+    for inode in socket_inodes() {
+        let socket = inode.inner();
+        let Some(tcp) = socket.as_any_ref().downcast_ref::<TcpSocket>() else {
+            continue;
+        };
+        if is_ipv6(tcp.family()) != ipv6 {
+            continue;
+        }
+        let Some(local) = ip_endpoint(tcp.endpoint()) else {
+            continue;
+        };
+        let (state, tx_queue, rx_queue) = tcp.queue_state();
+        entries.push(InetEntry {
+            local,
+            remote: ip_endpoint(tcp.peer_endpoint()),
+            state: PosixTcpInfo::linux_state(state),
+            tx_queue,
+            rx_queue,
+            inode: inode.inode_id().into(),
+        });
+    }
+
+    let header = if ipv6 {
+        "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n"
+    } else {
+        "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n"
+    };
+    inet_show(header, ipv6, entries)
+}
+
+/// 生成`/proc/net/udp`（`ipv6`为false）或`/proc/net/udp6`的内容
+///
+/// 只列出已经绑定了端口的socket，已连接的socket状态为`ESTABLISHED`
+pub fn udp_procfs_show(ipv6: bool) -> String {
+    let mut entries = Vec::new();
+    for inode in socket_inodes() {
+        let socket = inode.inner();
+        let Some(udp) = socket.as_any_ref().downcast_ref::<UdpSocket>() else {
+            continue;
+        };
+        if is_ipv6(udp.family()) != ipv6 {
+            continue;
+        }
+        let Some(local) = ip_endpoint(udp.endpoint()) else {
+            continue;
+        };
+        let remote = ip_endpoint(udp.peer_endpoint());
+        entries.push(InetEntry {
+            local,
+            remote,
+            state: if remote.is_some() {
+                TCP_ESTABLISHED
+            } else {
+                TCP_CLOSE
+            },
+            tx_queue: 0,
+            rx_queue: 0,
+            inode: inode.inode_id().into(),
+        });
+    }
+
+    let header = if ipv6 {
+        "  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n"
+    } else {
+        "   sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n"
+    };
+    inet_show(header, ipv6, entries)
+}
+
+/// 生成`/proc/net/unix`的内容
+///
+/// 抽象命名空间中的地址以`@`开头，没有名字的socket不显示路径
+pub fn unix_procfs_show() -> String {
+    let mut s = String::from("Num       RefCount Protocol Flags    Type St Inode Path\n");
+    for inode in socket_inodes() {
+        let socket = inode.inner();
+        let any = socket.as_any_ref();
+        // Linux的SOCK_STREAM、SOCK_DGRAM和SOCK_SEQPACKET
+        let ty: u16 = if any.is::<StreamSocket>() {
+            1
+        } else if any.is::<DgramSocket>() {
+            2
+        } else if any.is::<SeqpacketSocket>() {
+            5
+        } else {
+            continue;
+        };
+        let ino: usize = inode.inode_id().into();
+        let state = if socket.peer_endpoint().is_some() {
+            SS_CONNECTED
+        } else {
+            SS_UNCONNECTED
+        };
+        write!(
+            s,
+            "0000000000000000: 00000002 00000000 00000000 {:04X} {:02X} {:5}",
+            ty, state, ino
+        )
+        .ok();
+        match socket.endpoint() {
+            Some(Endpoint::Unix(UnixEndpoint::Path(path))) => {
+                write!(s, " {}", path).ok();
+            }
+            Some(Endpoint::Unix(UnixEndpoint::Abstract(name))) => {
+                write!(s, " @{}", String::from_utf8_lossy(&name)).ok();
+            }
+            _ => {}
+        }
+        s.push('\n');
+    }
+    s
+}
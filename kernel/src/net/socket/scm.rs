@@ -0,0 +1,354 @@
+//! unix域socket的辅助数据（控制消息）
+//!
+//! sendmsg(2)时从`msg_control`中解析出SCM_RIGHTS和SCM_CREDENTIALS，随数据一起放入对端的接收缓冲区；
+//! recvmsg(2)时把传递过来的文件安装到接收进程的文件描述符表中，再把控制消息写回用户空间。
+//!
+//! 参考：https://man7.org/linux/man-pages/man7/unix.7.html
+
+use core::mem::size_of;
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::{
+        file::{File, FileDescriptorVec},
+        IndexNode,
+    },
+    libs::spinlock::SpinLock,
+    net::syscall::MsgHdr,
+    process::{cred::CAPFlags, ProcessManager},
+    syscall::user_access::{UserBufferReader, UserBufferWriter},
+};
+
+use super::SOL_SOCKET;
+
+/// 传递文件描述符
+pub const SCM_RIGHTS: i32 = 1;
+/// 传递进程凭证
+pub const SCM_CREDENTIALS: i32 = 2;
+/// 一条消息最多能携带的文件描述符数量
+pub const SCM_MAX_FD: usize = 253;
+
+/// 每个用户在途（已发送但尚未被接收）的文件数量上限，与RLIMIT_NOFILE相同
+const USER_INFLIGHT_MAX: usize = FileDescriptorVec::PROCESS_MAX_FD;
+
+/// 每个用户（按实际uid）在途的文件数量
+static USER_INFLIGHT: SpinLock<BTreeMap<usize, usize>> = SpinLock::new(BTreeMap::new());
+
+/// recvmsg(2)返回的标志：控制消息因为缓冲区不足被截断
+pub const MSG_CTRUNC: u32 = 0x8;
+/// recvmsg(2)的标志：为收到的文件描述符设置close-on-exec
+pub const MSG_CMSG_CLOEXEC: u32 = 0x4000_0000;
+
+/// 控制消息头，对应Linux的`struct cmsghdr`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct CMsgHdr {
+    /// 包括消息头在内的长度
+    cmsg_len: usize,
+    cmsg_level: i32,
+    cmsg_type: i32,
+}
+
+const CMSG_HDR_LEN: usize = size_of::<CMsgHdr>();
+
+/// 控制消息按`long`对齐
+const fn cmsg_align(len: usize) -> usize {
+    (len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1)
+}
+
+/// SCM_CREDENTIALS携带的凭证，对应Linux的`struct ucred`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UCred {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl UCred {
+    /// 当前进程的凭证
+    pub fn current() -> Self {
+        let pcb = ProcessManager::current_pcb();
+        let cred = pcb.cred();
+        Self {
+            pid: pcb.tgid().data() as i32,
+            uid: cred.euid.data() as u32,
+            gid: cred.egid.data() as u32,
+        }
+    }
+
+    /// 检查当前进程是否有权发送这个凭证
+    ///
+    /// 没有特权的进程只能发送自己的pid，以及自己的实际、有效或保存的uid/gid
+    fn check(&self) -> Result<(), SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        let cred = pcb.cred();
+
+        let pid_ok =
+            self.pid as usize == pcb.tgid().data() || cred.has_capability(CAPFlags::CAP_SYS_ADMIN);
+        let uid_ok = [cred.uid, cred.euid, cred.suid]
+            .iter()
+            .any(|uid| uid.data() == self.uid as usize)
+            || cred.has_capability(CAPFlags::CAP_SETUID);
+        let gid_ok = [cred.gid, cred.egid, cred.sgid]
+            .iter()
+            .any(|gid| gid.data() == self.gid as usize)
+            || cred.has_capability(CAPFlags::CAP_SETGID);
+
+        if pid_ok && uid_ok && gid_ok {
+            Ok(())
+        } else {
+            Err(SystemError::EPERM)
+        }
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self, SystemError> {
+        if data.len() != size_of::<Self>() {
+            return Err(SystemError::EINVAL);
+        }
+        let word = |i: usize| data[i * 4..i * 4 + 4].try_into().unwrap();
+        Ok(Self {
+            pid: i32::from_ne_bytes(word(0)),
+            uid: u32::from_ne_bytes(word(1)),
+            gid: u32::from_ne_bytes(word(2)),
+        })
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(size_of::<Self>());
+        bytes.extend_from_slice(&self.pid.to_ne_bytes());
+        bytes.extend_from_slice(&self.uid.to_ne_bytes());
+        bytes.extend_from_slice(&self.gid.to_ne_bytes());
+        bytes
+    }
+}
+
+/// SCM_RIGHTS传递的在途文件
+///
+/// 文件计入发送者的在途文件数，直到被接收或随消息一起被丢弃。
+/// 对应Linux的`too_many_unix_fds()`，防止用户把大量文件藏在socket的接收缓冲区中
+#[derive(Debug, Default)]
+pub struct InflightFiles {
+    files: Vec<File>,
+    /// 被计数的用户
+    uid: usize,
+}
+
+impl InflightFiles {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// 是否包含`inode`对应的文件
+    pub fn contains_inode(&self, inode: &Arc<dyn IndexNode>) -> bool {
+        let target = Arc::as_ptr(inode) as *const ();
+        self.files
+            .iter()
+            .any(|file| Arc::as_ptr(&file.inode()) as *const () == target)
+    }
+
+    /// 加入发送进程的一个文件，超出在途文件数上限时返回ETOOMANYREFS
+    fn push(&mut self, file: File) -> Result<(), SystemError> {
+        let cred = ProcessManager::current_pcb().cred();
+        let uid = cred.uid.data();
+        let privileged = cred.has_capability(CAPFlags::CAP_SYS_RESOURCE)
+            || cred.has_capability(CAPFlags::CAP_SYS_ADMIN);
+
+        let mut inflight = USER_INFLIGHT.lock_irqsave();
+        let count = inflight.entry(uid).or_insert(0);
+        if *count >= USER_INFLIGHT_MAX && !privileged {
+            if *count == 0 {
+                inflight.remove(&uid);
+            }
+            return Err(SystemError::ETOOMANYREFS);
+        }
+        *count += 1;
+        drop(inflight);
+
+        self.uid = uid;
+        self.files.push(file);
+        Ok(())
+    }
+
+    /// 取出所有文件，它们不再处于在途状态
+    fn take(&mut self) -> Vec<File> {
+        let files = core::mem::take(&mut self.files);
+        uncharge_inflight(self.uid, files.len());
+        files
+    }
+}
+
+impl Drop for InflightFiles {
+    fn drop(&mut self) {
+        uncharge_inflight(self.uid, self.files.len());
+    }
+}
+
+fn uncharge_inflight(uid: usize, count: usize) {
+    if count == 0 {
+        return;
+    }
+    let mut inflight = USER_INFLIGHT.lock_irqsave();
+    if let Some(used) = inflight.get_mut(&uid) {
+        *used -= count;
+        if *used == 0 {
+            inflight.remove(&uid);
+        }
+    }
+}
+
+/// 随一条消息传递的辅助数据
+#[derive(Debug, Default)]
+pub struct ScmData {
+    /// SCM_RIGHTS传递的文件，发送时从发送进程的文件描述符表中复制
+    pub files: InflightFiles,
+    /// SCM_CREDENTIALS传递的凭证
+    pub creds: Option<UCred>,
+}
+
+impl ScmData {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.creds.is_none()
+    }
+
+    /// 从用户空间的`msg_control`中解析控制消息
+    ///
+    /// 不是SOL_SOCKET层的控制消息交给具体的协议处理，这里跳过它们
+    pub fn from_user(control: *const u8, controllen: usize) -> Result<Self, SystemError> {
+        let mut scm = Self::default();
+        if control.is_null() || controllen == 0 {
+            return Ok(scm);
+        }
+
+        let reader = UserBufferReader::new(control, controllen, true)?;
+        let buf = reader.read_from_user::<u8>(0)?;
+
+        let mut offset = 0;
+        while offset + CMSG_HDR_LEN <= buf.len() {
+            let hdr = unsafe { (buf[offset..].as_ptr() as *const CMsgHdr).read_unaligned() };
+            if hdr.cmsg_len < CMSG_HDR_LEN || hdr.cmsg_len > buf.len() - offset {
+                return Err(SystemError::EINVAL);
+            }
+            let data = &buf[offset + CMSG_HDR_LEN..offset + hdr.cmsg_len];
+
+            if hdr.cmsg_level == SOL_SOCKET as i32 {
+                match hdr.cmsg_type {
+                    SCM_RIGHTS => scm.add_rights(data)?,
+                    SCM_CREDENTIALS => {
+                        let creds = UCred::from_bytes(data)?;
+                        creds.check()?;
+                        scm.creds = Some(creds);
+                    }
+                    _ => return Err(SystemError::EINVAL),
+                }
+            }
+
+            offset += cmsg_align(hdr.cmsg_len);
+        }
+
+        Ok(scm)
+    }
+
+    /// 复制SCM_RIGHTS中的每个文件描述符对应的文件
+    fn add_rights(&mut self, data: &[u8]) -> Result<(), SystemError> {
+        if data.len() % size_of::<i32>() != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let count = data.len() / size_of::<i32>();
+        if self.files.len() + count > SCM_MAX_FD {
+            return Err(SystemError::EINVAL);
+        }
+
+        let fd_table = ProcessManager::current_pcb().fd_table();
+        let fd_table_guard = fd_table.read();
+        for chunk in data.chunks_exact(size_of::<i32>()) {
+            let fd = i32::from_ne_bytes(chunk.try_into().unwrap());
+            let file = fd_table_guard
+                .get_file_by_fd(fd)
+                .and_then(|file| file.try_clone())
+                .ok_or(SystemError::EBADF)?;
+            self.files.push(file)?;
+        }
+        Ok(())
+    }
+
+    /// 把控制消息写回用户空间的`msg_control`，并更新`msg_controllen`和`msg_flags`
+    ///
+    /// 传递过来的文件在这里被安装到当前进程的文件描述符表中。只有接收端设置了SO_PASSCRED时才返回凭证。
+    /// `msg_control`放不下的部分被丢弃（对应的文件被关闭），并在`msg_flags`中设置MSG_CTRUNC
+    pub fn write_to_user(
+        mut self,
+        msg: &mut MsgHdr,
+        passcred: bool,
+        cloexec: bool,
+    ) -> Result<(), SystemError> {
+        let space = if msg.msg_control.is_null() {
+            0
+        } else {
+            msg.msg_controllen
+        };
+        let mut out: Vec<u8> = Vec::new();
+        let mut truncated = false;
+
+        if let (true, Some(creds)) = (passcred, self.creds) {
+            truncated |= !put_cmsg(&mut out, space, SCM_CREDENTIALS, &creds.to_bytes());
+        }
+
+        if !self.files.is_empty() {
+            let avail = space.saturating_sub(out.len() + CMSG_HDR_LEN) / size_of::<i32>();
+            truncated |= avail < self.files.len();
+
+            let fd_table = ProcessManager::current_pcb().fd_table();
+            let mut fd_table_guard = fd_table.write();
+            let mut fds: Vec<u8> = Vec::new();
+            for file in self.files.take().into_iter().take(avail) {
+                file.set_close_on_exec(cloexec);
+                match fd_table_guard.alloc_fd(file, None) {
+                    Ok(fd) => fds.extend_from_slice(&fd.to_ne_bytes()),
+                    Err(_) => {
+                        truncated = true;
+                        break;
+                    }
+                }
+            }
+            drop(fd_table_guard);
+
+            if !fds.is_empty() {
+                put_cmsg(&mut out, space, SCM_RIGHTS, &fds);
+            }
+        }
+
+        if !out.is_empty() {
+            let mut writer = UserBufferWriter::new(msg.msg_control, out.len(), true)?;
+            writer.copy_to_user(&out, 0)?;
+        }
+        msg.msg_controllen = out.len();
+        if truncated {
+            msg.msg_flags |= MSG_CTRUNC;
+        }
+        Ok(())
+    }
+}
+
+/// 在`out`后追加一条控制消息，超出`space`时不追加并返回false
+fn put_cmsg(out: &mut Vec<u8>, space: usize, cmsg_type: i32, data: &[u8]) -> bool {
+    let cmsg_len = CMSG_HDR_LEN + data.len();
+    if out.len() + cmsg_len > space {
+        return false;
+    }
+
+    out.extend_from_slice(&cmsg_len.to_ne_bytes());
+    out.extend_from_slice(&(SOL_SOCKET as i32).to_ne_bytes());
+    out.extend_from_slice(&cmsg_type.to_ne_bytes());
+    out.extend_from_slice(data);
+    // 最后一条消息的填充可以超出缓冲区，此时不填充
+    let padded = core::cmp::min(cmsg_align(out.len()), space);
+    out.resize(padded, 0);
+    true
+}
@@ -0,0 +1,488 @@
+//! TCP连接的统计信息（TCP_INFO）
+//!
+//! smoltcp不公开RTT估计、重传次数等内部状态，因此网卡收发的每个TCP报文段都经过[`tcp_info_rcv`]
+//! （见`driver::net::packet_tap`），按连接统计序号、确认号和时间，自己估计RTT（RFC 6298）和重传次数。
+//!
+//! 统计从看到SYN开始，收到RST或者双方都发送了FIN之后结束。
+//! smoltcp没有启用拥塞控制，发送窗口只受对端接收窗口的限制，
+//! 因此`tcpi_snd_cwnd`报告为对端窗口能容纳的报文段个数。
+
+use alloc::collections::BTreeMap;
+use smoltcp::{
+    socket::tcp,
+    wire::{
+        EthernetFrame, EthernetProtocol, IpAddress, IpEndpoint, IpProtocol, Ipv4Packet, Ipv6Packet,
+        TcpPacket,
+    },
+};
+use system_error::SystemError;
+
+use crate::{libs::spinlock::SpinLock, time::Instant};
+
+/// 最多统计的连接数，超出时不再统计新的连接
+const TCP_INFO_FLOWS_MAX: usize = 1024;
+/// 还没有RTT样本时的重传超时（微秒），RFC 6298
+const TCP_TIMEOUT_INIT_US: u32 = 1_000_000;
+/// 重传超时的下限（微秒），与Linux的`TCP_RTO_MIN`相同
+const TCP_RTO_MIN_US: u32 = 200_000;
+/// 没有慢启动阈值时报告的值，与Linux的`TCP_INFINITE_SSTHRESH`相同
+const TCP_INFINITE_SSTHRESH: u32 = 0x7fff_ffff;
+
+const TCPI_OPT_TIMESTAMPS: u8 = 1;
+const TCPI_OPT_SACK: u8 = 2;
+const TCPI_OPT_WSCALE: u8 = 4;
+
+/// 连接的统计信息，key为(本地端点, 远程端点)
+static TCP_FLOWS: SpinLock<BTreeMap<(IpEndpoint, IpEndpoint), TcpFlowStats>> =
+    SpinLock::new(BTreeMap::new());
+
+/// SYN报文段中协商的选项
+#[derive(Debug, Default, Clone, Copy)]
+struct SynOptions {
+    mss: u16,
+    wscale: Option<u8>,
+    sack: bool,
+    timestamps: bool,
+}
+
+impl SynOptions {
+    fn parse(mut options: &[u8]) -> Self {
+        let mut result = Self::default();
+        while let Some(&kind) = options.first() {
+            match kind {
+                0 => break,
+                1 => {
+                    options = &options[1..];
+                    continue;
+                }
+                _ => {}
+            }
+            let len = match options.get(1) {
+                Some(&len) if len >= 2 && len as usize <= options.len() => len as usize,
+                _ => break,
+            };
+            match (kind, len) {
+                (2, 4) => result.mss = u16::from_be_bytes([options[2], options[3]]),
+                (3, 3) => result.wscale = Some(options[2].min(14)),
+                (4, 2) => result.sack = true,
+                (8, 10) => result.timestamps = true,
+                _ => {}
+            }
+            options = &options[len..];
+        }
+        result
+    }
+}
+
+/// `a`在`b`之后
+fn seq_after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+#[derive(Debug, Default)]
+struct TcpFlowStats {
+    /// 本端和对端SYN中的选项
+    tx_syn: Option<SynOptions>,
+    rx_syn: Option<SynOptions>,
+    /// 已经发送的最大序号
+    snd_max: Option<u32>,
+    /// 对端确认到的序号
+    snd_una: u32,
+    /// 期望从对端收到的下一个序号
+    rcv_nxt: Option<u32>,
+    /// 对端通告的接收窗口（已经按窗口扩大因子放大）
+    snd_wnd: u32,
+    /// 正在测量RTT的报文段：(结束序号, 发送时间)
+    rtt_sample: Option<(u32, Instant)>,
+    srtt_us: u32,
+    rttvar_us: u32,
+    min_rtt_us: u32,
+    /// 自上次确认推进以来连续重传的次数
+    retransmits: u8,
+    total_retrans: u32,
+    bytes_acked: u64,
+    bytes_received: u64,
+    segs_out: u32,
+    segs_in: u32,
+    data_segs_out: u32,
+    data_segs_in: u32,
+    last_data_sent: Option<Instant>,
+    last_ack_sent: Option<Instant>,
+    last_data_recv: Option<Instant>,
+    last_ack_recv: Option<Instant>,
+    fin_tx: bool,
+    fin_rx: bool,
+}
+
+impl TcpFlowStats {
+    /// 双方的SYN都带有窗口扩大选项时，窗口扩大才生效
+    fn wscale(&self) -> Option<(u8, u8)> {
+        match (self.tx_syn?.wscale, self.rx_syn?.wscale) {
+            (Some(rcv), Some(snd)) => Some((snd, rcv)),
+            _ => None,
+        }
+    }
+
+    fn snd_mss(&self) -> u32 {
+        match self.rx_syn.map(|syn| syn.mss) {
+            Some(mss) if mss != 0 => mss as u32,
+            // 对端没有通告MSS时使用RFC 9293规定的默认值
+            _ => 536,
+        }
+    }
+
+    fn update_rtt(&mut self, rtt_us: u32) {
+        let rtt_us = rtt_us.max(1);
+        if self.srtt_us == 0 {
+            self.srtt_us = rtt_us;
+            self.rttvar_us = rtt_us / 2;
+            self.min_rtt_us = rtt_us;
+        } else {
+            let delta = self.srtt_us.abs_diff(rtt_us);
+            self.rttvar_us = ((3 * self.rttvar_us as u64 + delta as u64) / 4) as u32;
+            self.srtt_us = ((7 * self.srtt_us as u64 + rtt_us as u64) / 8) as u32;
+            self.min_rtt_us = self.min_rtt_us.min(rtt_us);
+        }
+    }
+
+    fn rto_us(&self) -> u32 {
+        if self.srtt_us == 0 {
+            return TCP_TIMEOUT_INIT_US;
+        }
+        self.srtt_us
+            .saturating_add(4 * self.rttvar_us)
+            .max(TCP_RTO_MIN_US)
+    }
+
+    fn on_send(&mut self, tcp: &TcpPacket<&[u8]>, now: Instant) {
+        let payload = tcp.payload().len() as u32;
+        let seq = tcp.seq_number().0 as u32;
+        let seg_len = payload + tcp.syn() as u32 + tcp.fin() as u32;
+        let end = seq.wrapping_add(seg_len);
+
+        self.segs_out += 1;
+        if payload > 0 {
+            self.data_segs_out += 1;
+            self.last_data_sent = Some(now);
+        }
+        if tcp.ack() {
+            self.last_ack_sent = Some(now);
+        }
+        if tcp.syn() {
+            self.tx_syn = Some(SynOptions::parse(tcp.options()));
+        }
+        self.fin_tx |= tcp.fin();
+        if seg_len == 0 {
+            return;
+        }
+
+        match self.snd_max {
+            None => {
+                self.snd_una = seq;
+                self.snd_max = Some(end);
+                self.rtt_sample = Some((end, now));
+            }
+            Some(snd_max) if seq_after(end, snd_max) => {
+                self.snd_max = Some(end);
+                if self.rtt_sample.is_none() {
+                    self.rtt_sample = Some((end, now));
+                }
+            }
+            Some(_) => {
+                self.retransmits = self.retransmits.saturating_add(1);
+                self.total_retrans += 1;
+                // Karn算法：不用重传过的报文段测量RTT
+                if self
+                    .rtt_sample
+                    .is_some_and(|(sample_end, _)| seq_after(sample_end, seq))
+                {
+                    self.rtt_sample = None;
+                }
+            }
+        }
+    }
+
+    fn on_receive(&mut self, tcp: &TcpPacket<&[u8]>, now: Instant) {
+        let payload = tcp.payload().len() as u32;
+        let seq = tcp.seq_number().0 as u32;
+
+        self.segs_in += 1;
+        if tcp.syn() {
+            self.rx_syn = Some(SynOptions::parse(tcp.options()));
+            self.rcv_nxt = Some(seq.wrapping_add(1));
+        }
+        if payload > 0 {
+            self.data_segs_in += 1;
+            self.last_data_recv = Some(now);
+            let start = seq.wrapping_add(tcp.syn() as u32);
+            let end = start.wrapping_add(payload);
+            if let Some(rcv_nxt) = self.rcv_nxt {
+                // 只统计新的数据，重复收到的部分不计入
+                if seq_after(end, rcv_nxt) {
+                    let from = if seq_after(start, rcv_nxt) {
+                        start
+                    } else {
+                        rcv_nxt
+                    };
+                    self.bytes_received += end.wrapping_sub(from) as u64;
+                    self.rcv_nxt = Some(end);
+                }
+            }
+        }
+        self.fin_rx |= tcp.fin();
+
+        if !tcp.ack() {
+            return;
+        }
+        self.last_ack_recv = Some(now);
+
+        let window = tcp.window_len() as u32;
+        self.snd_wnd = match (tcp.syn(), self.wscale()) {
+            // SYN中的窗口不按扩大因子放大
+            (false, Some((snd_wscale, _))) => window << snd_wscale,
+            _ => window,
+        };
+
+        let ack = tcp.ack_number().0 as u32;
+        if self.snd_max.is_some() && seq_after(ack, self.snd_una) {
+            self.bytes_acked += ack.wrapping_sub(self.snd_una) as u64;
+            self.snd_una = ack;
+            self.retransmits = 0;
+        }
+        if let Some((sample_end, sent)) = self.rtt_sample {
+            if !seq_after(sample_end, ack) {
+                self.rtt_sample = None;
+                self.update_rtt((now - sent).total_micros() as u32);
+            }
+        }
+    }
+}
+
+/// 统计网卡收发的一个以太网帧，不是TCP报文段的帧被忽略
+///
+/// ## 参数
+///
+/// - `frame`: 完整的以太网帧
+/// - `outgoing`: 是否是本机发出的帧
+pub fn tcp_info_rcv(frame: &[u8], outgoing: bool) {
+    let Ok(eth) = EthernetFrame::new_checked(frame) else {
+        return;
+    };
+    let (src, dst, payload): (IpAddress, IpAddress, &[u8]) = match eth.ethertype() {
+        EthernetProtocol::Ipv4 => {
+            let Ok(ip) = Ipv4Packet::new_checked(eth.payload()) else {
+                return;
+            };
+            if ip.next_header() != IpProtocol::Tcp || ip.more_frags() || ip.frag_offset() != 0 {
+                return;
+            }
+            let hdr_len = ip.header_len() as usize;
+            let total_len = ip.total_len() as usize;
+            let payload = &eth.payload()[hdr_len..total_len];
+            (ip.src_addr().into(), ip.dst_addr().into(), payload)
+        }
+        EthernetProtocol::Ipv6 => {
+            let Ok(ip) = Ipv6Packet::new_checked(eth.payload()) else {
+                return;
+            };
+            // 不解析扩展头部
+            if ip.next_header() != IpProtocol::Tcp {
+                return;
+            }
+            let payload = &eth.payload()[40..40 + ip.payload_len() as usize];
+            (ip.src_addr().into(), ip.dst_addr().into(), payload)
+        }
+        _ => return,
+    };
+    let Ok(tcp) = TcpPacket::new_checked(payload) else {
+        return;
+    };
+
+    let src = IpEndpoint::new(src, tcp.src_port());
+    let dst = IpEndpoint::new(dst, tcp.dst_port());
+    let key = if outgoing { (src, dst) } else { (dst, src) };
+    let now = Instant::now();
+
+    let mut flows = TCP_FLOWS.lock_irqsave();
+    // 新连接的第一个SYN重新开始统计
+    if tcp.syn() && !tcp.ack() {
+        flows.remove(&key);
+    }
+    if !flows.contains_key(&key) {
+        if !tcp.syn() || flows.len() >= TCP_INFO_FLOWS_MAX {
+            return;
+        }
+        flows.insert(key, TcpFlowStats::default());
+    }
+    let stats = flows.get_mut(&key).unwrap();
+
+    if outgoing {
+        stats.on_send(&tcp, now);
+    } else {
+        stats.on_receive(&tcp, now);
+    }
+    if tcp.rst() || (stats.fin_tx && stats.fin_rx) {
+        flows.remove(&key);
+    }
+}
+
+/// 对应Linux的`struct tcp_info`，只包含到`tcpi_data_segs_out`为止的字段
+///
+/// 参考：https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/tcp.h#214
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PosixTcpInfo {
+    pub tcpi_state: u8,
+    pub tcpi_ca_state: u8,
+    pub tcpi_retransmits: u8,
+    pub tcpi_probes: u8,
+    pub tcpi_backoff: u8,
+    pub tcpi_options: u8,
+    /// 低4位为tcpi_snd_wscale，高4位为tcpi_rcv_wscale
+    pub tcpi_wscale: u8,
+    pub tcpi_flags: u8,
+
+    pub tcpi_rto: u32,
+    pub tcpi_ato: u32,
+    pub tcpi_snd_mss: u32,
+    pub tcpi_rcv_mss: u32,
+
+    pub tcpi_unacked: u32,
+    pub tcpi_sacked: u32,
+    pub tcpi_lost: u32,
+    pub tcpi_retrans: u32,
+    pub tcpi_fackets: u32,
+
+    pub tcpi_last_data_sent: u32,
+    pub tcpi_last_ack_sent: u32,
+    pub tcpi_last_data_recv: u32,
+    pub tcpi_last_ack_recv: u32,
+
+    pub tcpi_pmtu: u32,
+    pub tcpi_rcv_ssthresh: u32,
+    pub tcpi_rtt: u32,
+    pub tcpi_rttvar: u32,
+    pub tcpi_snd_ssthresh: u32,
+    pub tcpi_snd_cwnd: u32,
+    pub tcpi_advmss: u32,
+    pub tcpi_reordering: u32,
+
+    pub tcpi_rcv_rtt: u32,
+    pub tcpi_rcv_space: u32,
+
+    pub tcpi_total_retrans: u32,
+
+    pub tcpi_pacing_rate: u64,
+    pub tcpi_max_pacing_rate: u64,
+    pub tcpi_bytes_acked: u64,
+    pub tcpi_bytes_received: u64,
+    pub tcpi_segs_out: u32,
+    pub tcpi_segs_in: u32,
+
+    pub tcpi_notsent_bytes: u32,
+    pub tcpi_min_rtt: u32,
+    pub tcpi_data_segs_in: u32,
+    pub tcpi_data_segs_out: u32,
+}
+
+impl PosixTcpInfo {
+    /// 生成smoltcp的TCP socket的统计信息
+    pub fn new(socket: &tcp::Socket) -> Self {
+        let mut info = Self {
+            tcpi_state: Self::linux_state(socket.state()),
+            tcpi_snd_ssthresh: TCP_INFINITE_SSTHRESH,
+            tcpi_rcv_space: socket.recv_capacity() as u32,
+            tcpi_rcv_ssthresh: socket.recv_capacity() as u32,
+            tcpi_reordering: 3,
+            tcpi_rto: TCP_TIMEOUT_INIT_US,
+            ..Default::default()
+        };
+
+        let (Some(local), Some(remote)) = (socket.local_endpoint(), socket.remote_endpoint())
+        else {
+            return info;
+        };
+        let flows = TCP_FLOWS.lock_irqsave();
+        let Some(stats) = flows.get(&(local, remote)) else {
+            return info;
+        };
+
+        let now = Instant::now();
+        let since = |t: Option<Instant>| t.map_or(0, |t| (now - t).total_millis() as u32);
+        let snd_mss = stats.snd_mss();
+        let inflight = stats
+            .snd_max
+            .map_or(0, |snd_max| snd_max.wrapping_sub(stats.snd_una));
+
+        if stats
+            .tx_syn
+            .zip(stats.rx_syn)
+            .is_some_and(|(tx, rx)| tx.timestamps && rx.timestamps)
+        {
+            info.tcpi_options |= TCPI_OPT_TIMESTAMPS;
+        }
+        if stats
+            .tx_syn
+            .zip(stats.rx_syn)
+            .is_some_and(|(tx, rx)| tx.sack && rx.sack)
+        {
+            info.tcpi_options |= TCPI_OPT_SACK;
+        }
+        if let Some((snd_wscale, rcv_wscale)) = stats.wscale() {
+            info.tcpi_options |= TCPI_OPT_WSCALE;
+            info.tcpi_wscale = (snd_wscale & 0xf) | (rcv_wscale << 4);
+        }
+
+        info.tcpi_retransmits = stats.retransmits;
+        info.tcpi_rto = stats.rto_us();
+        info.tcpi_snd_mss = snd_mss;
+        info.tcpi_rcv_mss = stats.tx_syn.map_or(0, |syn| syn.mss as u32);
+        info.tcpi_advmss = info.tcpi_rcv_mss;
+        info.tcpi_unacked = inflight.div_ceil(snd_mss);
+        info.tcpi_last_data_sent = since(stats.last_data_sent);
+        info.tcpi_last_ack_sent = since(stats.last_ack_sent);
+        info.tcpi_last_data_recv = since(stats.last_data_recv);
+        info.tcpi_last_ack_recv = since(stats.last_ack_recv);
+        info.tcpi_rtt = stats.srtt_us;
+        info.tcpi_rttvar = stats.rttvar_us;
+        info.tcpi_min_rtt = stats.min_rtt_us;
+        info.tcpi_snd_cwnd = (stats.snd_wnd / snd_mss).max(1);
+        info.tcpi_total_retrans = stats.total_retrans;
+        info.tcpi_bytes_acked = stats.bytes_acked;
+        info.tcpi_bytes_received = stats.bytes_received;
+        info.tcpi_segs_out = stats.segs_out;
+        info.tcpi_segs_in = stats.segs_in;
+        info.tcpi_data_segs_out = stats.data_segs_out;
+        info.tcpi_data_segs_in = stats.data_segs_in;
+        info.tcpi_notsent_bytes = (socket.send_queue() as u32).saturating_sub(inflight);
+        info
+    }
+
+    /// smoltcp的连接状态对应的Linux的`TCP_*`状态
+    pub fn linux_state(state: tcp::State) -> u8 {
+        match state {
+            tcp::State::Established => 1,
+            tcp::State::SynSent => 2,
+            tcp::State::SynReceived => 3,
+            tcp::State::FinWait1 => 4,
+            tcp::State::FinWait2 => 5,
+            tcp::State::TimeWait => 6,
+            tcp::State::Closed => 7,
+            tcp::State::CloseWait => 8,
+            tcp::State::LastAck => 9,
+            tcp::State::Listen => 10,
+            tcp::State::Closing => 11,
+        }
+    }
+
+    /// 写入optval，返回写入的长度
+    pub fn to_bytes(&self, optval: &mut [u8]) -> Result<usize, SystemError> {
+        let len = core::mem::size_of::<Self>();
+        if optval.len() < len {
+            return Err(SystemError::EINVAL);
+        }
+        let bytes = unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, len) };
+        optval[..len].copy_from_slice(bytes);
+        Ok(len)
+    }
+}
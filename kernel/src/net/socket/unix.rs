@@ -1,18 +1,502 @@
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
+//! unix域socket
+//!
+//! - `SOCK_STREAM`、`SOCK_SEQPACKET`：目前只能通过socketpair创建一对相连的socket，可以绑定地址但还不能监听。
+//!   shutdown(2)关闭写端之后，对端读完已有的数据会读到文件结束；
+//! - `SOCK_DGRAM`：可以绑定文件系统路径或者抽象命名空间中的名字，按地址发送报文。
+//!   绑定时只给出地址族的地址，或者设置了SO_PASSCRED的socket在未绑定时发送或连接，会自动绑定一个抽象地址。
+//!
+//! 各种类型的socket共用同一个地址空间，抽象地址的名字可以包含'\0'，由地址长度决定名字的长度。
+//!
+//! 路径地址目前只记录在内核的地址表中，不会在文件系统中创建socket文件。
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU32, Ordering};
+use log::warn;
 use system_error::SystemError;
 
-use crate::{libs::spinlock::SpinLock, net::Endpoint};
+use crate::{
+    libs::spinlock::SpinLock,
+    net::{event_poll::EPollEventType, syscall::PosixSocketOption, Endpoint, ShutdownType},
+    process::ProcessManager,
+};
 
 use super::{
-    handle::GlobalSocketHandle, PosixSocketHandleItem, Socket, SocketInode, SocketMetadata,
-    SocketOptions, SocketType,
+    handle::GlobalSocketHandle,
+    scm::{ScmData, UCred},
+    sockopt_read_int, sockopt_write_int, AddressFamily, PosixSocketHandleItem, PosixSocketType,
+    Socket, SocketMetadata, SocketOptions, SocketType, SOL_SOCKET,
 };
 
+/// unix域地址，对应`struct sockaddr_un`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UnixEndpoint {
+    /// 没有名字的地址
+    Unnamed,
+    /// 文件系统路径
+    Path(String),
+    /// 抽象命名空间中的名字，不含开头的'\0'。名字可以包含'\0'，长度由地址的长度决定
+    Abstract(Vec<u8>),
+}
+
+impl UnixEndpoint {
+    /// `sun_path`的最大长度
+    const SUN_PATH_MAX: usize = 108;
+
+    /// 对应的`struct sockaddr_un`的长度，即getsockname(2)等返回的addrlen
+    pub fn sockaddr_len(&self) -> usize {
+        let path_len = match self {
+            UnixEndpoint::Unnamed => 0,
+            // 路径包含结尾的'\0'
+            UnixEndpoint::Path(path) => path.len() + 1,
+            // 开头的'\0'加上名字，没有结尾的'\0'
+            UnixEndpoint::Abstract(name) => name.len() + 1,
+        };
+        core::mem::size_of::<u16>() + path_len.min(Self::SUN_PATH_MAX)
+    }
+}
+
+/// unix socket的接收缓冲区
+///
+/// 每次写入的数据作为一条消息记录下来，辅助数据挂在它所属的消息上。
+/// 流式socket读取时可以跨越消息，但不会越过携带文件的消息，也不会把凭证不同的数据合并到一次读取中
+#[derive(Debug)]
+struct UnixBuffer {
+    data: VecDeque<u8>,
+    /// 各条消息的长度和辅助数据，按写入顺序排列
+    msgs: VecDeque<(usize, ScmData)>,
+    capacity: usize,
+    /// 这一端的读写是否已经关闭
+    shutdown: ShutdownType,
+}
+
+impl UnixBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            data: VecDeque::new(),
+            msgs: VecDeque::new(),
+            capacity,
+            shutdown: ShutdownType::empty(),
+        }
+    }
+
+    /// 写入一条消息
+    ///
+    /// 流式socket不保存空的消息，对应的辅助数据被丢弃
+    fn push(&mut self, buf: &[u8], mut scm: ScmData, packet: bool) -> Result<usize, SystemError> {
+        if self.capacity - self.data.len() < buf.len() {
+            return Err(SystemError::ENOBUFS);
+        }
+        if buf.is_empty() && !packet {
+            return Ok(0);
+        }
+
+        // 发送者没有显式指定凭证时，附上发送进程的凭证，供设置了SO_PASSCRED的接收者使用
+        scm.creds.get_or_insert_with(UCred::current);
+        self.data.extend(buf);
+        self.msgs.push_back((buf.len(), scm));
+        Ok(buf.len())
+    }
+
+    /// 读取数据，没有可读的消息时返回None
+    ///
+    /// `packet`为true时一次只读取一条消息，缓冲区放不下的部分被丢弃
+    fn pop(&mut self, buf: &mut [u8], packet: bool) -> Option<(usize, ScmData)> {
+        if packet {
+            let (len, scm) = self.msgs.pop_front()?;
+            let read = core::cmp::min(len, buf.len());
+            for (dst, src) in buf.iter_mut().zip(self.data.drain(..len)) {
+                *dst = src;
+            }
+            return Some((read, scm));
+        }
+
+        if self.msgs.is_empty() {
+            return None;
+        }
+        let mut out = ScmData::default();
+        let mut read = 0;
+        while read < buf.len() {
+            let Some((len, scm)) = self.msgs.front_mut() else {
+                break;
+            };
+            if read > 0 && (!scm.files.is_empty() || scm.creds != out.creds) {
+                break;
+            }
+            if read == 0 {
+                out.creds = scm.creds;
+                out.files = core::mem::take(&mut scm.files);
+            }
+
+            let n = core::cmp::min(*len, buf.len() - read);
+            for (dst, src) in buf[read..read + n].iter_mut().zip(self.data.drain(..n)) {
+                *dst = src;
+            }
+            read += n;
+            *len -= n;
+            if *len == 0 {
+                self.msgs.pop_front();
+            }
+
+            // 携带文件的数据单独返回
+            if !out.files.is_empty() {
+                break;
+            }
+        }
+        Some((read, out))
+    }
+}
+
+/// 处理unix socket通用的SOL_SOCKET层选项
+fn unix_setsockopt(
+    metadata: &mut SocketMetadata,
+    level: usize,
+    optname: usize,
+    optval: &[u8],
+) -> Result<(), SystemError> {
+    if level as u8 != SOL_SOCKET {
+        warn!("unix setsockopt: level {} is not supported", level);
+        return Ok(());
+    }
+    let optname =
+        PosixSocketOption::try_from(optname as i32).map_err(|_| SystemError::ENOPROTOOPT)?;
+    match optname {
+        PosixSocketOption::SO_PASSCRED => {
+            let enable = sockopt_read_int(optval)? != 0;
+            metadata.options.set(SocketOptions::PASSCRED, enable);
+        }
+        _ => warn!("unix setsockopt: option {:?} is not supported", optname),
+    }
+    Ok(())
+}
+
+/// 连接建立时记录的对端凭证，通过SO_PEERCRED和SO_PEERGROUPS获取
+///
+/// 目前只有socketpair(2)创建的socket有对端，两端的凭证都是创建者的凭证
+#[derive(Debug, Clone)]
+struct PeerCred {
+    ucred: UCred,
+    groups: Vec<u32>,
+}
+
+impl PeerCred {
+    fn current() -> Self {
+        let groups = ProcessManager::current_pcb()
+            .cred()
+            .group_info
+            .as_ref()
+            .map(|info| info.gids.iter().map(|gid| gid.data() as u32).collect())
+            .unwrap_or_default();
+        Self {
+            ucred: UCred::current(),
+            groups,
+        }
+    }
+}
+
+fn unix_getsockopt(
+    metadata: &SocketMetadata,
+    socket_type: PosixSocketType,
+    peer_cred: Option<&PeerCred>,
+    level: usize,
+    optname: usize,
+    optval: &mut [u8],
+) -> Result<usize, SystemError> {
+    if level as u8 != SOL_SOCKET {
+        return Err(SystemError::ENOPROTOOPT);
+    }
+    let optname =
+        PosixSocketOption::try_from(optname as i32).map_err(|_| SystemError::ENOPROTOOPT)?;
+    match optname {
+        PosixSocketOption::SO_PASSCRED => sockopt_write_int(
+            optval,
+            metadata.options.contains(SocketOptions::PASSCRED) as i32,
+        ),
+        PosixSocketOption::SO_TYPE => sockopt_write_int(optval, socket_type as i32),
+        PosixSocketOption::SO_DOMAIN => sockopt_write_int(optval, AddressFamily::Unix as i32),
+        PosixSocketOption::SO_PROTOCOL => sockopt_write_int(optval, 0),
+        // 还不支持监听
+        PosixSocketOption::SO_ACCEPTCONN => sockopt_write_int(optval, 0),
+        PosixSocketOption::SO_PEERCRED => {
+            // 没有对端时和Linux一样返回pid为0，uid和gid为-1的凭证
+            let ucred = peer_cred.map(|cred| cred.ucred).unwrap_or(UCred {
+                pid: 0,
+                uid: u32::MAX,
+                gid: u32::MAX,
+            });
+            let bytes = ucred.to_bytes();
+            let len = core::cmp::min(optval.len(), bytes.len());
+            optval[..len].copy_from_slice(&bytes[..len]);
+            Ok(len)
+        }
+        PosixSocketOption::SO_PEERGROUPS => {
+            let groups = &peer_cred.ok_or(SystemError::ENODATA)?.groups;
+            let len = groups.len() * core::mem::size_of::<u32>();
+            if optval.len() < len {
+                return Err(SystemError::ERANGE);
+            }
+            for (chunk, gid) in optval.chunks_exact_mut(4).zip(groups) {
+                chunk.copy_from_slice(&gid.to_ne_bytes());
+            }
+            Ok(len)
+        }
+        _ => Err(SystemError::ENOPROTOOPT),
+    }
+}
+
+/// 流式或seqpacket socket的一端
+///
+/// 对端直接持有本端的接收缓冲区和等待队列，写入时不需要获取本端socket的锁，
+/// 因此一端阻塞在读取中时，另一端仍然可以写入、关闭
+#[derive(Debug)]
+struct UnixConnEnd {
+    buffer: SpinLock<UnixBuffer>,
+    /// 绑定的地址
+    addr: SpinLock<Option<UnixEndpoint>>,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl UnixConnEnd {
+    fn endpoint(&self) -> UnixEndpoint {
+        self.addr
+            .lock_irqsave()
+            .clone()
+            .unwrap_or(UnixEndpoint::Unnamed)
+    }
+
+    /// 关闭这一端的读或写，并唤醒等待者
+    fn shutdown(&self, how: ShutdownType) {
+        let how = {
+            let mut buffer = self.buffer.lock_irqsave();
+            buffer.shutdown.insert(how);
+            buffer.shutdown
+        };
+        self.posix_item.wakeup_shutdown(how);
+    }
+
+    fn wakeup(&self, events: EPollEventType) {
+        self.posix_item.wakeup_any(events.bits() as u64);
+        self.posix_item.epitems.wakeup(Some(events)).ok();
+    }
+}
+
+/// 流式和seqpacket socket共用的连接状态
+#[derive(Debug, Clone)]
+struct UnixConn {
+    local: Arc<UnixConnEnd>,
+    peer: Option<Arc<UnixConnEnd>>,
+    peer_cred: Option<PeerCred>,
+    /// 是否保留消息边界（seqpacket）
+    packet: bool,
+}
+
+impl UnixConn {
+    fn new(capacity: usize, packet: bool, posix_item: Arc<PosixSocketHandleItem>) -> Self {
+        Self {
+            local: Arc::new(UnixConnEnd {
+                buffer: SpinLock::new(UnixBuffer::new(capacity)),
+                addr: SpinLock::new(None),
+                posix_item,
+            }),
+            peer: None,
+            peer_cred: None,
+            packet,
+        }
+    }
+
+    fn peer_addr(&self) -> UnixEndpoint {
+        self.peer
+            .as_ref()
+            .map(|peer| peer.endpoint())
+            .unwrap_or(UnixEndpoint::Unnamed)
+    }
+
+    /// 读取数据，没有数据时按`options`阻塞等待，直到有数据或者读端被关闭
+    fn read_msg(
+        &self,
+        buf: &mut [u8],
+        options: SocketOptions,
+    ) -> (Result<usize, SystemError>, Endpoint, ScmData) {
+        let endpoint = Endpoint::Unix(self.peer_addr());
+        if buf.is_empty() && !self.packet {
+            return (Ok(0), endpoint, ScmData::default());
+        }
+
+        loop {
+            let mut buffer = self.local.buffer.lock_irqsave();
+            if let Some((len, scm)) = buffer.pop(buf, self.packet) {
+                return (Ok(len), endpoint, scm);
+            }
+            // 读端被关闭并且数据已经读完，返回文件结束
+            if buffer.shutdown.contains(ShutdownType::RCV_SHUTDOWN) {
+                return (Ok(0), endpoint, ScmData::default());
+            }
+            drop(buffer);
+
+            if self.peer.is_none() {
+                return (Err(SystemError::ENOTCONN), endpoint, ScmData::default());
+            }
+            if !options.contains(SocketOptions::BLOCK) {
+                return (
+                    Err(SystemError::EAGAIN_OR_EWOULDBLOCK),
+                    endpoint,
+                    ScmData::default(),
+                );
+            }
+
+            self.local.posix_item.sleep(
+                (EPollEventType::EPOLLIN | EPollEventType::EPOLLRDHUP | EPollEventType::EPOLLHUP)
+                    .bits() as u64,
+            );
+            if ProcessManager::current_pcb().has_pending_signal_fast() {
+                return (Err(SystemError::ERESTARTSYS), endpoint, ScmData::default());
+            }
+        }
+    }
+
+    /// 把数据写入对端的接收缓冲区
+    fn write_msg(&self, buf: &[u8], scm: ScmData) -> Result<usize, SystemError> {
+        if self
+            .local
+            .buffer
+            .lock_irqsave()
+            .shutdown
+            .contains(ShutdownType::SEND_SHUTDOWN)
+        {
+            return Err(SystemError::EPIPE);
+        }
+        let peer = self.peer.as_ref().ok_or(SystemError::ENOTCONN)?;
+
+        let mut peer_buffer = peer.buffer.lock_irqsave();
+        // 对端关闭了读端
+        if peer_buffer.shutdown.contains(ShutdownType::RCV_SHUTDOWN) {
+            return Err(SystemError::EPIPE);
+        }
+        let len = peer_buffer.push(buf, scm, self.packet)?;
+        drop(peer_buffer);
+
+        peer.wakeup(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+        Ok(len)
+    }
+
+    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        if self.peer.is_some() {
+            return Err(SystemError::EISCONN);
+        }
+
+        match endpoint {
+            // socketpair
+            Endpoint::Inode(Some(inode)) => {
+                let peer = inode.inner();
+                let peer = peer.as_any_ref();
+                let peer = peer
+                    .downcast_ref::<StreamSocket>()
+                    .map(|peer| &peer.conn)
+                    .or_else(|| {
+                        peer.downcast_ref::<SeqpacketSocket>()
+                            .map(|peer| &peer.conn)
+                    })
+                    .filter(|peer| peer.packet == self.packet)
+                    .ok_or(SystemError::EPROTOTYPE)?;
+                self.peer = Some(peer.local.clone());
+                self.peer_cred = Some(PeerCred::current());
+                Ok(())
+            }
+            Endpoint::Unix(addr) => match unix_lookup(&addr)? {
+                // 还不支持监听地址的流式socket，只能通过socketpair连接
+                UnixBound::Connection(_) => Err(SystemError::ECONNREFUSED),
+                UnixBound::Dgram(_) => Err(SystemError::EPROTOTYPE),
+            },
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+
+    fn bind(&self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let Endpoint::Unix(addr) = endpoint else {
+            return Err(SystemError::EINVAL);
+        };
+        let mut local_addr = self.local.addr.lock_irqsave();
+        if local_addr.is_some() {
+            return Err(SystemError::EINVAL);
+        }
+        *local_addr = Some(unix_bind(
+            addr,
+            UnixBound::Connection(Arc::downgrade(&self.local)),
+        )?);
+        Ok(())
+    }
+
+    /// 关闭读端时，本端不再接收数据；关闭写端时，对端读完已有的数据后读到文件结束
+    fn shutdown(&self, how: ShutdownType) -> Result<(), SystemError> {
+        self.local.shutdown(how);
+
+        if let Some(peer) = self.peer.as_ref() {
+            let mut peer_how = ShutdownType::empty();
+            if how.contains(ShutdownType::RCV_SHUTDOWN) {
+                peer_how |= ShutdownType::SEND_SHUTDOWN;
+            }
+            if how.contains(ShutdownType::SEND_SHUTDOWN) {
+                peer_how |= ShutdownType::RCV_SHUTDOWN;
+            }
+            peer.shutdown(peer_how);
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        if let Some(addr) = self.local.addr.lock_irqsave().take() {
+            unix_unbind(&addr, &UnixBound::Connection(Arc::downgrade(&self.local)));
+        }
+        // 对端读完已有的数据后读到文件结束，再写入时得到EPIPE
+        if let Some(peer) = self.peer.take() {
+            peer.shutdown(ShutdownType::SHUTDOWN_MASK);
+        }
+        self.local.shutdown(ShutdownType::SHUTDOWN_MASK);
+        let mut buffer = self.local.buffer.lock_irqsave();
+        buffer.data.clear();
+        buffer.msgs.clear();
+    }
+
+    fn poll(&self) -> EPollEventType {
+        let mut events = EPollEventType::empty();
+        let shutdown = {
+            let buffer = self.local.buffer.lock_irqsave();
+            if !buffer.msgs.is_empty() {
+                events |= EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM;
+            }
+            buffer.shutdown
+        };
+
+        if shutdown.contains(ShutdownType::RCV_SHUTDOWN) {
+            events |=
+                EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM | EPollEventType::EPOLLRDHUP;
+        }
+        if shutdown == ShutdownType::SHUTDOWN_MASK || self.peer.is_none() {
+            events |= EPollEventType::EPOLLHUP;
+        }
+
+        if let Some(peer) = self.peer.as_ref() {
+            let peer_buffer = peer.buffer.lock_irqsave();
+            if !shutdown.contains(ShutdownType::SEND_SHUTDOWN)
+                && peer_buffer.data.len() < peer_buffer.capacity
+            {
+                events |= EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+            }
+        }
+        events
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct StreamSocket {
     metadata: SocketMetadata,
-    buffer: Arc<SpinLock<Vec<u8>>>,
-    peer_inode: Option<Arc<SocketInode>>,
+    conn: UnixConn,
     handle: GlobalSocketHandle,
     posix_item: Arc<PosixSocketHandleItem>,
 }
@@ -28,8 +512,6 @@ impl StreamSocket {
     /// ## 参数
     /// - `options`: socket选项
     pub fn new(options: SocketOptions) -> Self {
-        let buffer = Arc::new(SpinLock::new(Vec::with_capacity(Self::DEFAULT_BUF_SIZE)));
-
         let metadata = SocketMetadata::new(
             SocketType::Unix,
             Self::DEFAULT_BUF_SIZE,
@@ -42,8 +524,7 @@ impl StreamSocket {
 
         Self {
             metadata,
-            buffer,
-            peer_inode: None,
+            conn: UnixConn::new(Self::DEFAULT_BUF_SIZE, false, posix_item.clone()),
             handle: GlobalSocketHandle::new_kernel_handle(),
             posix_item,
         }
@@ -54,56 +535,88 @@ impl Socket for StreamSocket {
     fn posix_item(&self) -> Arc<PosixSocketHandleItem> {
         self.posix_item.clone()
     }
+
     fn socket_handle(&self) -> GlobalSocketHandle {
         self.handle
     }
 
-    fn close(&mut self) {}
+    fn close(&mut self) {
+        self.conn.close();
+    }
 
     fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
-        let mut buffer = self.buffer.lock_irqsave();
+        // 通过read读取时，随数据传递的文件被直接关闭
+        let (n, endpoint, _) = self.read_msg(buf);
+        (n, endpoint)
+    }
 
-        let len = core::cmp::min(buf.len(), buffer.len());
-        buf[..len].copy_from_slice(&buffer[..len]);
+    fn read_msg(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint, ScmData) {
+        self.conn.read_msg(buf, self.metadata.options)
+    }
 
-        let _ = buffer.split_off(len);
+    fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
+        self.write_msg(buf, to, ScmData::default())
+    }
 
-        (Ok(len), Endpoint::Inode(self.peer_inode.clone()))
+    fn write_msg(
+        &self,
+        buf: &[u8],
+        _to: Option<Endpoint>,
+        scm: ScmData,
+    ) -> Result<usize, SystemError> {
+        self.conn.write_msg(buf, scm)
     }
 
-    fn write(&self, buf: &[u8], _to: Option<Endpoint>) -> Result<usize, SystemError> {
-        if self.peer_inode.is_none() {
-            return Err(SystemError::ENOTCONN);
-        }
+    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        self.conn.connect(endpoint)
+    }
 
-        let peer_inode = self.peer_inode.clone().unwrap();
-        let len = peer_inode.inner().write_buffer(buf)?;
-        Ok(len)
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        self.conn.bind(endpoint)
     }
 
-    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
-        if self.peer_inode.is_some() {
-            return Err(SystemError::EISCONN);
-        }
+    fn shutdown(&mut self, shutdown_type: ShutdownType) -> Result<(), SystemError> {
+        self.conn.shutdown(shutdown_type)
+    }
 
-        if let Endpoint::Inode(inode) = endpoint {
-            self.peer_inode = inode;
-            Ok(())
-        } else {
-            Err(SystemError::EINVAL)
-        }
+    fn endpoint(&self) -> Option<Endpoint> {
+        Some(Endpoint::Unix(self.conn.local.endpoint()))
     }
 
-    fn write_buffer(&self, buf: &[u8]) -> Result<usize, SystemError> {
-        let mut buffer = self.buffer.lock_irqsave();
+    fn peer_endpoint(&self) -> Option<Endpoint> {
+        self.conn
+            .peer
+            .as_ref()
+            .map(|peer| Endpoint::Unix(peer.endpoint()))
+    }
 
-        let len = buf.len();
-        if buffer.capacity() - buffer.len() < len {
-            return Err(SystemError::ENOBUFS);
-        }
-        buffer.extend_from_slice(buf);
+    fn poll(&self) -> EPollEventType {
+        self.conn.poll()
+    }
 
-        Ok(len)
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        unix_setsockopt(&mut self.metadata, level, optname, optval)
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        unix_getsockopt(
+            &self.metadata,
+            PosixSocketType::Stream,
+            self.conn.peer_cred.as_ref(),
+            level,
+            optname,
+            optval,
+        )
     }
 
     fn metadata(&self) -> SocketMetadata {
@@ -126,8 +639,7 @@ impl Socket for StreamSocket {
 #[derive(Debug, Clone)]
 pub struct SeqpacketSocket {
     metadata: SocketMetadata,
-    buffer: Arc<SpinLock<Vec<u8>>>,
-    peer_inode: Option<Arc<SocketInode>>,
+    conn: UnixConn,
     handle: GlobalSocketHandle,
     posix_item: Arc<PosixSocketHandleItem>,
 }
@@ -143,8 +655,6 @@ impl SeqpacketSocket {
     /// ## 参数
     /// - `options`: socket选项
     pub fn new(options: SocketOptions) -> Self {
-        let buffer = Arc::new(SpinLock::new(Vec::with_capacity(Self::DEFAULT_BUF_SIZE)));
-
         let metadata = SocketMetadata::new(
             SocketType::Unix,
             Self::DEFAULT_BUF_SIZE,
@@ -157,8 +667,7 @@ impl SeqpacketSocket {
 
         Self {
             metadata,
-            buffer,
-            peer_inode: None,
+            conn: UnixConn::new(Self::DEFAULT_BUF_SIZE, true, posix_item.clone()),
             handle: GlobalSocketHandle::new_kernel_handle(),
             posix_item,
         }
@@ -169,58 +678,489 @@ impl Socket for SeqpacketSocket {
     fn posix_item(&self) -> Arc<PosixSocketHandleItem> {
         self.posix_item.clone()
     }
-    fn close(&mut self) {}
+
+    fn socket_handle(&self) -> GlobalSocketHandle {
+        self.handle
+    }
+
+    fn close(&mut self) {
+        self.conn.close();
+    }
 
     fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
-        let mut buffer = self.buffer.lock_irqsave();
+        // 通过read读取时，随数据传递的文件被直接关闭
+        let (n, endpoint, _) = self.read_msg(buf);
+        (n, endpoint)
+    }
+
+    fn read_msg(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint, ScmData) {
+        self.conn.read_msg(buf, self.metadata.options)
+    }
+
+    fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
+        self.write_msg(buf, to, ScmData::default())
+    }
+
+    fn write_msg(
+        &self,
+        buf: &[u8],
+        _to: Option<Endpoint>,
+        scm: ScmData,
+    ) -> Result<usize, SystemError> {
+        self.conn.write_msg(buf, scm)
+    }
+
+    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        self.conn.connect(endpoint)
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        self.conn.bind(endpoint)
+    }
+
+    fn shutdown(&mut self, shutdown_type: ShutdownType) -> Result<(), SystemError> {
+        self.conn.shutdown(shutdown_type)
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        Some(Endpoint::Unix(self.conn.local.endpoint()))
+    }
+
+    fn peer_endpoint(&self) -> Option<Endpoint> {
+        self.conn
+            .peer
+            .as_ref()
+            .map(|peer| Endpoint::Unix(peer.endpoint()))
+    }
+
+    fn poll(&self) -> EPollEventType {
+        self.conn.poll()
+    }
+
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        unix_setsockopt(&mut self.metadata, level, optname, optval)
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        unix_getsockopt(
+            &self.metadata,
+            PosixSocketType::SeqPacket,
+            self.conn.peer_cred.as_ref(),
+            level,
+            optname,
+            optval,
+        )
+    }
+
+    fn metadata(&self) -> SocketMetadata {
+        self.metadata.clone()
+    }
+
+    fn box_clone(&self) -> Box<dyn Socket> {
+        Box::new(self.clone())
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn core::any::Any {
+        self
+    }
+}
 
-        let len = core::cmp::min(buf.len(), buffer.len());
-        buf[..len].copy_from_slice(&buffer[..len]);
+/// 一个数据报socket最多缓存的报文数，对应Linux的`net.unix.max_dgram_qlen`
+const UNIX_DGRAM_MAX_QLEN: usize = 512;
+/// 自动绑定的抽象地址由5个十六进制数字组成
+const UNIX_AUTOBIND_MAX: u32 = 0xfffff;
 
-        let _ = buffer.split_off(len);
+/// 所有绑定了地址的unix socket，各种类型的socket共用同一个地址空间
+static UNIX_BOUND: SpinLock<BTreeMap<UnixEndpoint, UnixBound>> = SpinLock::new(BTreeMap::new());
 
-        (Ok(len), Endpoint::Inode(self.peer_inode.clone()))
+/// 绑定了地址的socket
+#[derive(Debug, Clone)]
+enum UnixBound {
+    Dgram(Weak<DgramQueue>),
+    /// 流式和seqpacket socket。还不支持监听，绑定只用于占用地址
+    Connection(Weak<UnixConnEnd>),
+}
+
+impl UnixBound {
+    fn is_alive(&self) -> bool {
+        match self {
+            UnixBound::Dgram(queue) => queue.strong_count() > 0,
+            UnixBound::Connection(buffer) => buffer.strong_count() > 0,
+        }
     }
 
-    fn write(&self, buf: &[u8], _to: Option<Endpoint>) -> Result<usize, SystemError> {
-        if self.peer_inode.is_none() {
-            return Err(SystemError::ENOTCONN);
+    fn ptr_eq(&self, other: &UnixBound) -> bool {
+        match (self, other) {
+            (UnixBound::Dgram(a), UnixBound::Dgram(b)) => a.ptr_eq(b),
+            (UnixBound::Connection(a), UnixBound::Connection(b)) => a.ptr_eq(b),
+            _ => false,
         }
+    }
+}
 
-        let peer_inode = self.peer_inode.clone().unwrap();
-        let len = peer_inode.inner().write_buffer(buf)?;
-        Ok(len)
+/// 把socket绑定到`addr`，`addr`为`Unnamed`时自动选择一个抽象地址，返回绑定的地址
+fn unix_bind(addr: UnixEndpoint, socket: UnixBound) -> Result<UnixEndpoint, SystemError> {
+    let mut bound = UNIX_BOUND.lock_irqsave();
+    bound.retain(|_, socket| socket.is_alive());
+
+    let addr = match addr {
+        UnixEndpoint::Unnamed => unix_autobind_addr(&bound)?,
+        addr => {
+            if bound.contains_key(&addr) {
+                return Err(SystemError::EADDRINUSE);
+            }
+            addr
+        }
+    };
+    bound.insert(addr.clone(), socket);
+    Ok(addr)
+}
+
+fn unix_autobind_addr(
+    bound: &BTreeMap<UnixEndpoint, UnixBound>,
+) -> Result<UnixEndpoint, SystemError> {
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    for _ in 0..=UNIX_AUTOBIND_MAX {
+        let n = NEXT.fetch_add(1, Ordering::SeqCst) & UNIX_AUTOBIND_MAX;
+        let addr = UnixEndpoint::Abstract(format!("{:05x}", n).into_bytes());
+        if !bound.contains_key(&addr) {
+            return Ok(addr);
+        }
     }
+    Err(SystemError::ENOSPC)
+}
 
-    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
-        if self.peer_inode.is_some() {
-            return Err(SystemError::EISCONN);
+/// 释放`socket`绑定的地址
+fn unix_unbind(addr: &UnixEndpoint, socket: &UnixBound) {
+    let mut bound = UNIX_BOUND.lock_irqsave();
+    if bound.get(addr).is_some_and(|b| b.ptr_eq(socket)) {
+        bound.remove(addr);
+    }
+}
+
+/// 查找绑定到`addr`的socket
+fn unix_lookup(addr: &UnixEndpoint) -> Result<UnixBound, SystemError> {
+    let socket = UNIX_BOUND
+        .lock_irqsave()
+        .get(addr)
+        .filter(|socket| socket.is_alive())
+        .cloned();
+    match (socket, addr) {
+        (Some(socket), _) => Ok(socket),
+        (None, UnixEndpoint::Path(_)) => Err(SystemError::ENOENT),
+        (None, _) => Err(SystemError::ECONNREFUSED),
+    }
+}
+
+#[derive(Debug)]
+struct DgramMsg {
+    data: Vec<u8>,
+    from: UnixEndpoint,
+    scm: ScmData,
+}
+
+/// 数据报socket的接收队列和地址
+///
+/// 发送者直接把报文放进接收者的队列，不需要获取接收者的socket的锁
+#[derive(Debug)]
+struct DgramQueue {
+    msgs: SpinLock<VecDeque<DgramMsg>>,
+    /// 绑定的地址
+    addr: SpinLock<Option<UnixEndpoint>>,
+    /// 连接的对端，连接之后只接收来自对端的报文
+    peer: SpinLock<Option<Weak<DgramQueue>>>,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl DgramQueue {
+    /// 把队列绑定到`addr`，`addr`为`Unnamed`时自动选择一个抽象地址
+    fn bind(self: &Arc<Self>, addr: UnixEndpoint) -> Result<(), SystemError> {
+        let mut self_addr = self.addr.lock_irqsave();
+        if self_addr.is_some() {
+            return Err(SystemError::EINVAL);
         }
+        *self_addr = Some(unix_bind(addr, UnixBound::Dgram(Arc::downgrade(self)))?);
+        Ok(())
+    }
 
-        if let Endpoint::Inode(inode) = endpoint {
-            self.peer_inode = inode;
-            Ok(())
-        } else {
-            Err(SystemError::EINVAL)
+    /// 设置了SO_PASSCRED的socket在发送或连接之前自动绑定，使得对端能够回复
+    fn autobind_if_needed(self: &Arc<Self>, options: SocketOptions) -> Result<(), SystemError> {
+        if options.contains(SocketOptions::PASSCRED) && self.addr.lock_irqsave().is_none() {
+            self.bind(UnixEndpoint::Unnamed)?;
         }
+        Ok(())
     }
 
-    fn write_buffer(&self, buf: &[u8]) -> Result<usize, SystemError> {
-        let mut buffer = self.buffer.lock_irqsave();
+    fn unbind(self: &Arc<Self>) {
+        if let Some(addr) = self.addr.lock_irqsave().take() {
+            unix_unbind(&addr, &UnixBound::Dgram(Arc::downgrade(self)));
+        }
+    }
 
-        let len = buf.len();
-        if buffer.capacity() - buffer.len() < len {
-            return Err(SystemError::ENOBUFS);
+    fn lookup(addr: &UnixEndpoint) -> Result<Arc<DgramQueue>, SystemError> {
+        match unix_lookup(addr)? {
+            UnixBound::Dgram(queue) => queue.upgrade().ok_or(SystemError::ECONNREFUSED),
+            UnixBound::Connection(_) => Err(SystemError::EPROTOTYPE),
         }
-        buffer.extend_from_slice(buf);
+    }
 
-        Ok(len)
+    fn endpoint(&self) -> UnixEndpoint {
+        self.addr
+            .lock_irqsave()
+            .clone()
+            .unwrap_or(UnixEndpoint::Unnamed)
+    }
+
+    /// 由`from`发来一个报文
+    fn push(&self, from: &Arc<DgramQueue>, data: &[u8], scm: ScmData) -> Result<(), SystemError> {
+        // 已经连接的socket只接收来自对端的报文
+        if let Some(peer) = self.peer.lock_irqsave().as_ref() {
+            if !peer.ptr_eq(&Arc::downgrade(from)) {
+                return Err(SystemError::EPERM);
+            }
+        }
+        {
+            let mut msgs = self.msgs.lock_irqsave();
+            if msgs.len() >= UNIX_DGRAM_MAX_QLEN {
+                return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+            }
+            msgs.push_back(DgramMsg {
+                data: data.to_vec(),
+                from: from.endpoint(),
+                scm,
+            });
+        }
+        self.posix_item
+            .wakeup_any(EPollEventType::EPOLLIN.bits() as u64);
+        self.posix_item
+            .epitems
+            .wakeup(Some(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM))
+            .ok();
+        Ok(())
+    }
+}
+
+/// unix域数据报socket
+///
+/// https://man7.org/linux/man-pages/man7/unix.7.html
+#[derive(Debug, Clone)]
+pub struct DgramSocket {
+    metadata: SocketMetadata,
+    queue: Arc<DgramQueue>,
+    peer_cred: Option<PeerCred>,
+    handle: GlobalSocketHandle,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl DgramSocket {
+    /// 默认的元数据缓冲区大小
+    pub const DEFAULT_METADATA_BUF_SIZE: usize = 1024;
+    /// 默认的缓冲区大小，也是一个报文的最大长度
+    pub const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+
+    /// # 创建一个 Datagram Socket
+    ///
+    /// ## 参数
+    /// - `options`: socket选项
+    pub fn new(options: SocketOptions) -> Self {
+        let metadata = SocketMetadata::new(
+            SocketType::Unix,
+            Self::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_METADATA_BUF_SIZE,
+            options,
+        );
+
+        let posix_item = Arc::new(PosixSocketHandleItem::new(None));
+        let queue = Arc::new(DgramQueue {
+            msgs: SpinLock::new(VecDeque::new()),
+            addr: SpinLock::new(None),
+            peer: SpinLock::new(None),
+            posix_item: posix_item.clone(),
+        });
+
+        Self {
+            metadata,
+            queue,
+            peer_cred: None,
+            handle: GlobalSocketHandle::new_kernel_handle(),
+            posix_item,
+        }
+    }
+}
+
+impl Socket for DgramSocket {
+    fn posix_item(&self) -> Arc<PosixSocketHandleItem> {
+        self.posix_item.clone()
     }
 
     fn socket_handle(&self) -> GlobalSocketHandle {
         self.handle
     }
 
+    fn close(&mut self) {
+        self.queue.unbind();
+        self.queue.peer.lock_irqsave().take();
+        self.queue.msgs.lock_irqsave().clear();
+    }
+
+    fn read(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        let (n, endpoint, _) = self.read_msg(buf);
+        (n, endpoint)
+    }
+
+    /// 每次读取一个完整的报文，缓冲区不够大时，超出的部分被丢弃
+    fn read_msg(&self, buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint, ScmData) {
+        loop {
+            if let Some(msg) = self.queue.msgs.lock_irqsave().pop_front() {
+                let len = core::cmp::min(buf.len(), msg.data.len());
+                buf[..len].copy_from_slice(&msg.data[..len]);
+                return (Ok(len), Endpoint::Unix(msg.from), msg.scm);
+            }
+            if !self.metadata.options.contains(SocketOptions::BLOCK) {
+                return (
+                    Err(SystemError::EAGAIN_OR_EWOULDBLOCK),
+                    Endpoint::Unix(UnixEndpoint::Unnamed),
+                    ScmData::default(),
+                );
+            }
+
+            self.posix_item.sleep(EPollEventType::EPOLLIN.bits() as u64);
+            if ProcessManager::current_pcb().has_pending_signal_fast() {
+                return (
+                    Err(SystemError::ERESTARTSYS),
+                    Endpoint::Unix(UnixEndpoint::Unnamed),
+                    ScmData::default(),
+                );
+            }
+        }
+    }
+
+    fn write(&self, buf: &[u8], to: Option<Endpoint>) -> Result<usize, SystemError> {
+        self.write_msg(buf, to, ScmData::default())
+    }
+
+    fn write_msg(
+        &self,
+        buf: &[u8],
+        to: Option<Endpoint>,
+        mut scm: ScmData,
+    ) -> Result<usize, SystemError> {
+        if buf.len() > Self::DEFAULT_BUF_SIZE {
+            return Err(SystemError::EMSGSIZE);
+        }
+        let target = match to {
+            Some(Endpoint::Unix(UnixEndpoint::Unnamed)) | None => self
+                .queue
+                .peer
+                .lock_irqsave()
+                .as_ref()
+                .and_then(|peer| peer.upgrade())
+                .ok_or(SystemError::ENOTCONN)?,
+            Some(Endpoint::Unix(addr)) => DgramQueue::lookup(&addr)?,
+            Some(_) => return Err(SystemError::EINVAL),
+        };
+
+        self.queue.autobind_if_needed(self.metadata.options)?;
+        scm.creds.get_or_insert_with(UCred::current);
+        target.push(&self.queue, buf, scm)?;
+        Ok(buf.len())
+    }
+
+    fn connect(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let peer = match endpoint {
+            // 断开连接
+            Endpoint::Unix(UnixEndpoint::Unnamed) => {
+                self.queue.peer.lock_irqsave().take();
+                return Ok(());
+            }
+            Endpoint::Unix(addr) => DgramQueue::lookup(&addr)?,
+            // socketpair
+            Endpoint::Inode(Some(inode)) => {
+                let peer = inode
+                    .inner()
+                    .as_any_ref()
+                    .downcast_ref::<DgramSocket>()
+                    .map(|peer| peer.queue.clone())
+                    .ok_or(SystemError::EPROTOTYPE)?;
+                self.peer_cred = Some(PeerCred::current());
+                peer
+            }
+            _ => return Err(SystemError::EINVAL),
+        };
+
+        self.queue.autobind_if_needed(self.metadata.options)?;
+        *self.queue.peer.lock_irqsave() = Some(Arc::downgrade(&peer));
+        Ok(())
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        match endpoint {
+            Endpoint::Unix(addr) => self.queue.bind(addr),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        Some(Endpoint::Unix(self.queue.endpoint()))
+    }
+
+    fn peer_endpoint(&self) -> Option<Endpoint> {
+        self.queue
+            .peer
+            .lock_irqsave()
+            .as_ref()
+            .and_then(|peer| peer.upgrade())
+            .map(|peer| Endpoint::Unix(peer.endpoint()))
+    }
+
+    fn poll(&self) -> EPollEventType {
+        let mut events = EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM;
+        if !self.queue.msgs.lock_irqsave().is_empty() {
+            events.insert(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+        }
+        events
+    }
+
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        unix_setsockopt(&mut self.metadata, level, optname, optval)
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        unix_getsockopt(
+            &self.metadata,
+            PosixSocketType::Datagram,
+            self.peer_cred.as_ref(),
+            level,
+            optname,
+            optval,
+        )
+    }
+
     fn metadata(&self) -> SocketMetadata {
         self.metadata.clone()
     }
@@ -0,0 +1,951 @@
+//! AF_XDP套接字
+//!
+//! 一个简化的XDP socket：用户程序注册一块UMEM，创建与内核共享的四个环形队列，
+//! 然后把套接字绑定到网卡的一个队列上，之后收发帧都不再需要在内核与用户程序之间拷贝：
+//!
+//! - 填充队列（fill）：用户程序把空闲的UMEM帧交给内核；
+//! - RX队列：内核把收到的帧写入从填充队列取出的UMEM帧，再把描述符放入RX队列；
+//! - TX队列：用户程序在UMEM帧中构造好要发送的帧，放入TX队列之后调用sendto通知内核发送；
+//! - 完成队列（completion）：发送完毕的UMEM帧由内核归还给用户程序。
+//!
+//! 与Linux不同的地方：
+//!
+//! - UMEM由内核分配，注册时`addr`必须为0，用户程序在[`XDP_UMEM_PGOFF_UMEM`]处mmap得到它。
+//!   收包时无法访问注册者的地址空间，因此不能使用用户程序自己的内存；
+//! - 没有XDP程序：绑定之后，网卡收到的所有帧都交给套接字，不再经过抓包点、防火墙和协议栈，
+//!   相当于一个总是返回`XDP_REDIRECT`的XDP程序（见`driver::net::packet_tap`）；
+//! - 网卡驱动不支持零拷贝，收到的帧从驱动的缓冲区复制一次到UMEM（即`XDP_COPY`模式），
+//!   发送时直接把UMEM中的帧交给驱动；
+//! - 网卡只有一个队列，`queue_id`只能为0，也不支持`XDP_SHARED_UMEM`。
+//!
+//! 创建AF_XDP套接字需要`CAP_NET_RAW`权限。
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use system_error::SystemError;
+
+use crate::{
+    arch::{mm::LockedFrameAllocator, MMArch},
+    driver::net::NetDevice,
+    filesystem::page_cache::PageCache,
+    libs::{align::page_align_up, spinlock::SpinLock},
+    mm::{
+        allocator::page_frame::PageFrameCount,
+        page::{page_manager_lock_irqsave, Page, PageFlags, PageType},
+        MemoryManagementArch, VirtAddr,
+    },
+    net::{event_poll::EPollEventType, net_core::poll_ifaces, Endpoint, NET_DEVICES},
+    process::{cred::CAPFlags, ProcessManager},
+};
+
+use super::{
+    handle::GlobalSocketHandle, sockopt_read_int, PosixSocketHandleItem, PosixSocketType, Socket,
+    SocketMetadata, SocketOptions, SocketType,
+};
+
+/// AF_XDP套接字选项的层次
+pub const SOL_XDP: usize = 283;
+
+/// 获取各个环形队列中生产者、消费者索引以及描述符数组的偏移量
+const XDP_MMAP_OFFSETS: usize = 1;
+/// 设置RX队列的长度
+const XDP_RX_RING: usize = 2;
+/// 设置TX队列的长度
+const XDP_TX_RING: usize = 3;
+/// 注册UMEM
+const XDP_UMEM_REG: usize = 4;
+/// 设置填充队列的长度
+const XDP_UMEM_FILL_RING: usize = 5;
+/// 设置完成队列的长度
+const XDP_UMEM_COMPLETION_RING: usize = 6;
+/// 获取丢包等统计信息
+const XDP_STATISTICS: usize = 7;
+
+/// mmap时，RX队列对应的偏移量
+pub const XDP_PGOFF_RX_RING: usize = 0;
+/// mmap时，TX队列对应的偏移量
+pub const XDP_PGOFF_TX_RING: usize = 0x80000000;
+/// mmap时，填充队列对应的偏移量
+pub const XDP_UMEM_PGOFF_FILL_RING: usize = 0x100000000;
+/// mmap时，完成队列对应的偏移量
+pub const XDP_UMEM_PGOFF_COMPLETION_RING: usize = 0x180000000;
+/// mmap时，UMEM对应的偏移量（Linux中UMEM是用户程序自己的内存，没有这个偏移量）
+pub const XDP_UMEM_PGOFF_UMEM: usize = 0x200000000;
+
+/// 环形队列中，生产者索引的偏移量
+const XSK_RING_PRODUCER: usize = 0;
+/// 环形队列中，消费者索引的偏移量
+const XSK_RING_CONSUMER: usize = 64;
+/// 环形队列中，标志的偏移量
+const XSK_RING_FLAGS: usize = 128;
+/// 环形队列中，描述符数组的偏移量
+const XSK_RING_DESC: usize = 192;
+
+/// 环形队列的最大长度
+const XSK_RING_MAX_ENTRIES: u32 = 16384;
+/// UMEM的最大长度
+const XDP_UMEM_MAX_SIZE: usize = 16 * 1024 * 1024;
+/// UMEM中帧的最小长度
+const XDP_UMEM_MIN_CHUNK_SIZE: usize = 2048;
+
+/// 以太网头部的长度
+const ETH_HLEN: usize = 14;
+/// 不含FCS的以太网帧的最大长度
+const ETH_FRAME_LEN: usize = 1514;
+/// 网卡忙时，发送一帧最多重试的次数
+const XSK_XMIT_RETRIES: usize = 1000;
+
+/// 套接字还没有绑定到网卡（网卡id从0开始）
+const XSK_UNBOUND: usize = usize::MAX;
+
+/// 环形队列的标志：内核需要用户程序调用sendto或recvfrom才会处理该队列
+const XDP_RING_NEED_WAKEUP: u32 = 1;
+
+bitflags! {
+    /// bind时`sockaddr_xdp`中的标志
+    pub struct XdpBindFlags: u16 {
+        /// 与另一个套接字共享UMEM
+        const XDP_SHARED_UMEM = 1 << 0;
+        /// 强制使用拷贝模式
+        const XDP_COPY = 1 << 1;
+        /// 强制使用零拷贝模式
+        const XDP_ZEROCOPY = 1 << 2;
+        /// 在队列的标志中指出是否需要用户程序通知内核
+        const XDP_USE_NEED_WAKEUP = 1 << 3;
+    }
+}
+
+/// AF_XDP端点，对应`struct sockaddr_xdp`
+#[derive(Debug, Clone, Copy)]
+pub struct XdpEndpoint {
+    /// 绑定标志，见[`XdpBindFlags`]
+    pub flags: u16,
+    /// 网卡的接口号
+    pub ifindex: u32,
+    /// 网卡的队列号
+    pub queue_id: u32,
+    /// 共享UMEM时，另一个套接字的文件描述符
+    pub shared_umem_fd: u32,
+}
+
+/// 把`#[repr(C)]`的选项值写入getsockopt(2)的optval，返回写入的长度
+fn sockopt_write_struct<T>(optval: &mut [u8], value: &T) -> Result<usize, SystemError> {
+    let len = core::mem::size_of::<T>();
+    if optval.len() < len {
+        return Err(SystemError::EINVAL);
+    }
+    let bytes = unsafe { core::slice::from_raw_parts(value as *const T as *const u8, len) };
+    optval[..len].copy_from_slice(bytes);
+    Ok(len)
+}
+
+/// `XDP_UMEM_REG`的选项值，对应`struct xdp_umem_reg`
+#[derive(Debug, Clone, Copy)]
+struct XdpUmemReg {
+    addr: u64,
+    len: u64,
+    chunk_size: u32,
+    headroom: u32,
+    flags: u32,
+}
+
+impl XdpUmemReg {
+    /// 旧版本的`struct xdp_umem_reg`没有`flags`等字段
+    const MIN_SIZE: usize = 24;
+
+    fn from_bytes(optval: &[u8]) -> Result<Self, SystemError> {
+        if optval.len() < Self::MIN_SIZE {
+            return Err(SystemError::EINVAL);
+        }
+        let u64_at = |off: usize| u64::from_ne_bytes(optval[off..off + 8].try_into().unwrap());
+        let u32_at = |off: usize| u32::from_ne_bytes(optval[off..off + 4].try_into().unwrap());
+        Ok(Self {
+            addr: u64_at(0),
+            len: u64_at(8),
+            chunk_size: u32_at(16),
+            headroom: u32_at(20),
+            flags: if optval.len() >= 28 { u32_at(24) } else { 0 },
+        })
+    }
+}
+
+/// 环形队列中各个字段的偏移量，对应`struct xdp_ring_offset`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct XdpRingOffset {
+    producer: u64,
+    consumer: u64,
+    desc: u64,
+    flags: u64,
+}
+
+/// `XDP_MMAP_OFFSETS`的结果，对应`struct xdp_mmap_offsets`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct XdpMmapOffsets {
+    rx: XdpRingOffset,
+    tx: XdpRingOffset,
+    fr: XdpRingOffset,
+    cr: XdpRingOffset,
+}
+
+impl XdpMmapOffsets {
+    fn new() -> Self {
+        // 所有队列的布局相同
+        let off = XdpRingOffset {
+            producer: XSK_RING_PRODUCER as u64,
+            consumer: XSK_RING_CONSUMER as u64,
+            desc: XSK_RING_DESC as u64,
+            flags: XSK_RING_FLAGS as u64,
+        };
+        Self {
+            rx: off,
+            tx: off,
+            fr: off,
+            cr: off,
+        }
+    }
+}
+
+/// `XDP_STATISTICS`的结果，对应`struct xdp_statistics`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct XdpStatistics {
+    /// 因为各种原因被丢弃的接收帧
+    rx_dropped: u64,
+    /// 填充队列中无效的描述符
+    rx_invalid_descs: u64,
+    /// TX队列中无效的描述符
+    tx_invalid_descs: u64,
+    /// RX队列已满时丢弃的帧
+    rx_ring_full: u64,
+    /// 填充队列为空时丢弃的帧
+    rx_fill_ring_empty_descs: u64,
+    /// 通知内核发送时TX队列为空的次数
+    tx_ring_empty_descs: u64,
+}
+
+/// RX、TX队列中的描述符，对应`struct xdp_desc`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct XdpDesc {
+    /// 帧数据在UMEM中的地址
+    addr: u64,
+    len: u32,
+    options: u32,
+}
+
+/// 一段供mmap到用户态的连续物理页
+///
+/// 页面由区域、页缓存与页面管理器共同引用，物理页在最后一个引用消失时才释放
+#[derive(Debug)]
+struct XskRegion {
+    vaddr: VirtAddr,
+    pages: Vec<Arc<Page>>,
+}
+
+impl XskRegion {
+    /// 分配`len`字节的内存，并从页偏移量`pgoff`开始加入页缓存
+    fn new(page_cache: &Arc<PageCache>, len: usize, pgoff: usize) -> Result<Self, SystemError> {
+        let page_count = PageFrameCount::new(page_align_up(len) / MMArch::PAGE_SIZE);
+        let mut page_manager_guard = page_manager_lock_irqsave();
+        let (phys_addr, pages) = page_manager_guard.create_pages(
+            PageType::Normal,
+            PageFlags::PG_UNEVICTABLE,
+            &mut LockedFrameAllocator,
+            page_count,
+        )?;
+        drop(page_manager_guard);
+
+        let mut cache_guard = page_cache.lock_irqsave();
+        for (i, page) in pages.iter().enumerate() {
+            cache_guard.add_page(pgoff + i, page);
+        }
+        drop(cache_guard);
+
+        let vaddr = unsafe { MMArch::phys_2_virt(phys_addr) }.ok_or(SystemError::EFAULT)?;
+        Ok(Self { vaddr, pages })
+    }
+
+    fn len(&self) -> usize {
+        self.pages.len() * MMArch::PAGE_SIZE
+    }
+}
+
+impl Drop for XskRegion {
+    fn drop(&mut self) {
+        let mut page_manager_guard = page_manager_lock_irqsave();
+        for page in self.pages.iter() {
+            let mut page_guard = page.write_irqsave();
+            if page_guard.map_count() == 0 {
+                page_manager_guard.remove_page(&page.phys_address());
+            } else {
+                // 仍被映射的页面交给解除映射的流程从页面管理器中移除
+                page_guard.remove_flags(PageFlags::PG_UNEVICTABLE);
+            }
+        }
+    }
+}
+
+/// 与用户程序共享的环形队列
+///
+/// 内核只作为其中的一端：RX队列和完成队列的生产者，填充队列和TX队列的消费者
+#[derive(Debug)]
+struct XskRing {
+    region: XskRegion,
+    entries: u32,
+    desc_size: usize,
+}
+
+impl XskRing {
+    fn new(
+        page_cache: &Arc<PageCache>,
+        entries: u32,
+        desc_size: usize,
+        pgoff: usize,
+    ) -> Result<Self, SystemError> {
+        let region = XskRegion::new(
+            page_cache,
+            XSK_RING_DESC + entries as usize * desc_size,
+            pgoff,
+        )?;
+        Ok(Self {
+            region,
+            entries,
+            desc_size,
+        })
+    }
+
+    fn field(&self, offset: usize) -> &AtomicU32 {
+        unsafe { &*((self.region.vaddr.data() + offset) as *const AtomicU32) }
+    }
+
+    fn desc_ptr(&self, idx: u32) -> usize {
+        self.region.vaddr.data()
+            + XSK_RING_DESC
+            + (idx & (self.entries - 1)) as usize * self.desc_size
+    }
+
+    /// 队列中尚未被消费的描述符数量
+    fn len(&self) -> u32 {
+        let prod = self.field(XSK_RING_PRODUCER).load(Ordering::Acquire);
+        let cons = self.field(XSK_RING_CONSUMER).load(Ordering::Acquire);
+        prod.wrapping_sub(cons)
+    }
+
+    /// 作为消费者，获取下一个描述符的索引，队列为空时返回None
+    fn peek(&self) -> Option<u32> {
+        let cons = self.field(XSK_RING_CONSUMER).load(Ordering::Relaxed);
+        let prod = self.field(XSK_RING_PRODUCER).load(Ordering::Acquire);
+        (cons != prod).then_some(cons)
+    }
+
+    /// 作为消费者，释放[`Self::peek`]得到的描述符
+    fn release(&self, cons: u32) {
+        self.field(XSK_RING_CONSUMER)
+            .store(cons.wrapping_add(1), Ordering::Release);
+    }
+
+    /// 作为生产者，获取下一个空闲描述符的索引，队列已满时返回None
+    fn reserve(&self) -> Option<u32> {
+        let prod = self.field(XSK_RING_PRODUCER).load(Ordering::Relaxed);
+        let cons = self.field(XSK_RING_CONSUMER).load(Ordering::Acquire);
+        (prod.wrapping_sub(cons) < self.entries).then_some(prod)
+    }
+
+    /// 作为生产者，提交[`Self::reserve`]得到的描述符
+    fn submit(&self, prod: u32) {
+        self.field(XSK_RING_PRODUCER)
+            .store(prod.wrapping_add(1), Ordering::Release);
+    }
+
+    fn set_flags(&self, flags: u32) {
+        self.field(XSK_RING_FLAGS).store(flags, Ordering::Release);
+    }
+
+    /// 读取填充队列中的UMEM地址
+    fn read_addr(&self, idx: u32) -> u64 {
+        unsafe { core::ptr::read_volatile(self.desc_ptr(idx) as *const u64) }
+    }
+
+    /// 向完成队列写入UMEM地址
+    fn write_addr(&self, idx: u32, addr: u64) {
+        unsafe { core::ptr::write_volatile(self.desc_ptr(idx) as *mut u64, addr) }
+    }
+
+    fn read_desc(&self, idx: u32) -> XdpDesc {
+        unsafe { core::ptr::read_volatile(self.desc_ptr(idx) as *const XdpDesc) }
+    }
+
+    fn write_desc(&self, idx: u32, desc: XdpDesc) {
+        unsafe { core::ptr::write_volatile(self.desc_ptr(idx) as *mut XdpDesc, desc) }
+    }
+}
+
+/// 注册的UMEM，被划分为长度相同的帧
+#[derive(Debug)]
+struct XdpUmem {
+    region: XskRegion,
+    size: usize,
+    chunk_size: usize,
+    headroom: usize,
+}
+
+impl XdpUmem {
+    /// 接收的帧写入`addr`所在的帧中，跳过headroom之后的位置
+    fn rx_addr(&self, addr: u64) -> u64 {
+        (addr & !(self.chunk_size as u64 - 1)) + self.headroom as u64
+    }
+
+    /// 从`addr`开始、长度为`len`的数据在内核中的地址，数据越过了帧的边界时返回None
+    fn data_ptr(&self, addr: u64, len: usize) -> Option<*mut u8> {
+        let addr = usize::try_from(addr)
+            .ok()
+            .filter(|addr| *addr < self.size)?;
+        let chunk_end = (addr & !(self.chunk_size - 1)) + self.chunk_size;
+        if addr + len > chunk_end {
+            return None;
+        }
+        Some((self.region.vaddr.data() + addr) as *mut u8)
+    }
+}
+
+/// 收包路径使用的队列，与发包路径的队列分开加锁
+#[derive(Debug, Default)]
+struct XskRxQueues {
+    umem: Option<Arc<XdpUmem>>,
+    rx: Option<XskRing>,
+    fill: Option<XskRing>,
+    stats: XdpStatistics,
+}
+
+/// 发包路径使用的队列
+#[derive(Debug, Default)]
+struct XskTxQueues {
+    umem: Option<Arc<XdpUmem>>,
+    tx: Option<XskRing>,
+    comp: Option<XskRing>,
+    stats: XdpStatistics,
+}
+
+/// 绑定到各个网卡的AF_XDP套接字，key为网卡id
+static XSK_MAP: SpinLock<BTreeMap<usize, Weak<XdpSock>>> = SpinLock::new(BTreeMap::new());
+
+/// 把网卡收到的帧交给绑定到该网卡的AF_XDP套接字
+///
+/// 返回true表示帧已经被套接字接收或者丢弃，不应再交给协议栈
+pub fn xsk_rcv(nic_id: usize, frame: &[u8]) -> bool {
+    let xs = {
+        let guard = XSK_MAP.lock_irqsave();
+        if guard.is_empty() {
+            return false;
+        }
+        match guard.get(&nic_id).and_then(|xs| xs.upgrade()) {
+            Some(xs) => xs,
+            None => return false,
+        }
+    };
+    xs.rcv(frame);
+    true
+}
+
+/// 套接字的状态，与套接字本身分离，使得收包路径上不需要获取套接字的锁
+#[derive(Debug)]
+struct XdpSock {
+    /// 绑定的网卡，为[`XSK_UNBOUND`]表示还没有绑定
+    ifindex: AtomicUsize,
+    rx: SpinLock<XskRxQueues>,
+    tx: SpinLock<XskTxQueues>,
+    /// 环形队列和UMEM所在的页，mmap时映射给用户程序
+    page_cache: Arc<PageCache>,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl XdpSock {
+    fn bound_ifindex(&self) -> Option<usize> {
+        match self.ifindex.load(Ordering::SeqCst) {
+            XSK_UNBOUND => None,
+            ifindex => Some(ifindex),
+        }
+    }
+
+    /// 把一个帧写入UMEM，并放入RX队列
+    fn rcv(&self, frame: &[u8]) {
+        {
+            let mut guard = self.rx.lock_irqsave();
+            let queues = &mut *guard;
+            let stats = &mut queues.stats;
+            let (Some(umem), Some(fill)) = (queues.umem.as_ref(), queues.fill.as_ref()) else {
+                return;
+            };
+            let Some(rx) = queues.rx.as_ref() else {
+                stats.rx_dropped += 1;
+                return;
+            };
+            let Some(prod) = rx.reserve() else {
+                stats.rx_ring_full += 1;
+                stats.rx_dropped += 1;
+                return;
+            };
+            let Some(cons) = fill.peek() else {
+                stats.rx_fill_ring_empty_descs += 1;
+                stats.rx_dropped += 1;
+                return;
+            };
+            let addr = fill.read_addr(cons);
+            fill.release(cons);
+
+            if addr >= umem.size as u64 {
+                stats.rx_invalid_descs += 1;
+                stats.rx_dropped += 1;
+                return;
+            }
+            let addr = umem.rx_addr(addr);
+            // 帧放不进一个UMEM帧时丢弃，用户程序取回的填充描述符已经丢失
+            let Some(data) = umem.data_ptr(addr, frame.len()) else {
+                stats.rx_dropped += 1;
+                return;
+            };
+            unsafe { core::ptr::copy_nonoverlapping(frame.as_ptr(), data, frame.len()) };
+            rx.write_desc(
+                prod,
+                XdpDesc {
+                    addr,
+                    len: frame.len() as u32,
+                    options: 0,
+                },
+            );
+            rx.submit(prod);
+        }
+
+        self.posix_item
+            .wakeup_any(EPollEventType::EPOLLIN.bits() as u64);
+        self.posix_item
+            .epitems
+            .wakeup(Some(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM))
+            .ok();
+    }
+
+    /// 网卡忙时重试几次，仍然失败则返回`ENOBUFS`
+    fn xmit_frame(iface: &Arc<dyn NetDevice>, frame: &[u8]) -> Result<(), SystemError> {
+        for _ in 0..XSK_XMIT_RETRIES {
+            match iface.poll_xmit(frame) {
+                Err(SystemError::EAGAIN_OR_EWOULDBLOCK) => core::hint::spin_loop(),
+                r => return r,
+            }
+        }
+        Err(SystemError::ENOBUFS)
+    }
+
+    /// 发送TX队列中的帧，发送完毕的帧放入完成队列，返回发送的帧数
+    ///
+    /// 完成队列已满或者网卡忙时，剩下的帧留在TX队列中，等待下一次sendto
+    fn xmit(&self) -> Result<usize, SystemError> {
+        let ifindex = self.bound_ifindex().ok_or(SystemError::ENXIO)?;
+        let iface = NET_DEVICES
+            .read_irqsave()
+            .get(&ifindex)
+            .cloned()
+            .ok_or(SystemError::ENETDOWN)?;
+
+        let mut guard = self.tx.lock_irqsave();
+        let queues = &mut *guard;
+        let stats = &mut queues.stats;
+        let (Some(umem), Some(tx), Some(comp)) = (
+            queues.umem.as_ref(),
+            queues.tx.as_ref(),
+            queues.comp.as_ref(),
+        ) else {
+            return Err(SystemError::ENOBUFS);
+        };
+
+        if tx.peek().is_none() {
+            stats.tx_ring_empty_descs += 1;
+            return Ok(0);
+        }
+        let mut sent = 0;
+        while let Some(cons) = tx.peek() {
+            let Some(prod) = comp.reserve() else {
+                break;
+            };
+            let desc = tx.read_desc(cons);
+            let len = desc.len as usize;
+            let data = if (ETH_HLEN..=ETH_FRAME_LEN).contains(&len) {
+                umem.data_ptr(desc.addr, len)
+            } else {
+                None
+            };
+            let Some(data) = data else {
+                stats.tx_invalid_descs += 1;
+                tx.release(cons);
+                continue;
+            };
+
+            let frame = unsafe { core::slice::from_raw_parts(data as *const u8, len) };
+            if let Err(e) = Self::xmit_frame(&iface, frame) {
+                if sent == 0 {
+                    return Err(e);
+                }
+                break;
+            }
+            tx.release(cons);
+            comp.write_addr(prod, desc.addr);
+            comp.submit(prod);
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    fn reg_umem(&self, reg: XdpUmemReg) -> Result<(), SystemError> {
+        if reg.addr != 0 || reg.flags != 0 {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        let chunk_size = reg.chunk_size as usize;
+        if !chunk_size.is_power_of_two()
+            || !(XDP_UMEM_MIN_CHUNK_SIZE..=MMArch::PAGE_SIZE).contains(&chunk_size)
+            || reg.headroom as usize >= chunk_size
+        {
+            return Err(SystemError::EINVAL);
+        }
+        let size = usize::try_from(reg.len).map_err(|_| SystemError::EINVAL)?;
+        if size == 0 || size % MMArch::PAGE_SIZE != 0 || size > XDP_UMEM_MAX_SIZE {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut rx = self.rx.lock_irqsave();
+        let mut tx = self.tx.lock_irqsave();
+        if rx.umem.is_some() {
+            return Err(SystemError::EBUSY);
+        }
+        let umem = Arc::new(XdpUmem {
+            region: XskRegion::new(
+                &self.page_cache,
+                size,
+                XDP_UMEM_PGOFF_UMEM >> MMArch::PAGE_SHIFT,
+            )?,
+            size,
+            chunk_size,
+            headroom: reg.headroom as usize,
+        });
+        rx.umem = Some(umem.clone());
+        tx.umem = Some(umem);
+        Ok(())
+    }
+
+    fn create_ring(&self, optname: usize, entries: u32) -> Result<(), SystemError> {
+        let mut rx_guard = self.rx.lock_irqsave();
+        let mut tx_guard = self.tx.lock_irqsave();
+        let (rx, tx) = (&mut *rx_guard, &mut *tx_guard);
+        // 填充队列和完成队列属于UMEM，需要先注册UMEM
+        if matches!(optname, XDP_UMEM_FILL_RING | XDP_UMEM_COMPLETION_RING) && rx.umem.is_none() {
+            return Err(SystemError::EINVAL);
+        }
+        let desc_size = core::mem::size_of::<XdpDesc>();
+        let addr_size = core::mem::size_of::<u64>();
+        let (slot, desc_size, offset) = match optname {
+            XDP_RX_RING => (&mut rx.rx, desc_size, XDP_PGOFF_RX_RING),
+            XDP_TX_RING => (&mut tx.tx, desc_size, XDP_PGOFF_TX_RING),
+            XDP_UMEM_FILL_RING => (&mut rx.fill, addr_size, XDP_UMEM_PGOFF_FILL_RING),
+            XDP_UMEM_COMPLETION_RING => (&mut tx.comp, addr_size, XDP_UMEM_PGOFF_COMPLETION_RING),
+            _ => return Err(SystemError::ENOPROTOOPT),
+        };
+        if slot.is_some() {
+            return Err(SystemError::EINVAL);
+        }
+        *slot = Some(XskRing::new(
+            &self.page_cache,
+            entries,
+            desc_size,
+            offset >> MMArch::PAGE_SHIFT,
+        )?);
+        Ok(())
+    }
+
+    /// 检查绑定所需的UMEM和队列，成功时返回要绑定的网卡id
+    fn check_bind(&self, endpoint: &XdpEndpoint) -> Result<usize, SystemError> {
+        let flags = XdpBindFlags::from_bits(endpoint.flags).ok_or(SystemError::EINVAL)?;
+        if flags.contains(XdpBindFlags::XDP_COPY | XdpBindFlags::XDP_ZEROCOPY) {
+            return Err(SystemError::EINVAL);
+        }
+        if flags.intersects(XdpBindFlags::XDP_SHARED_UMEM | XdpBindFlags::XDP_ZEROCOPY) {
+            return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP);
+        }
+        if self.bound_ifindex().is_some() {
+            return Err(SystemError::EINVAL);
+        }
+        let ifindex = endpoint.ifindex as usize;
+        if !NET_DEVICES.read_irqsave().contains_key(&ifindex) {
+            return Err(SystemError::ENODEV);
+        }
+        if endpoint.queue_id != 0 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let rx = self.rx.lock_irqsave();
+        let tx = self.tx.lock_irqsave();
+        if rx.umem.is_none() || rx.fill.is_none() || tx.comp.is_none() {
+            return Err(SystemError::EINVAL);
+        }
+        if rx.rx.is_none() && tx.tx.is_none() {
+            return Err(SystemError::EINVAL);
+        }
+        // 只有调用sendto时才会发送，接收则由网卡中断驱动
+        if flags.contains(XdpBindFlags::XDP_USE_NEED_WAKEUP) {
+            if let Some(ring) = tx.tx.as_ref() {
+                ring.set_flags(XDP_RING_NEED_WAKEUP);
+            }
+        }
+        Ok(ifindex)
+    }
+
+    /// `offset`处可以mmap的长度
+    fn mmap_len(&self, offset: usize) -> Option<usize> {
+        let rx = self.rx.lock_irqsave();
+        let tx = self.tx.lock_irqsave();
+        let region = match offset {
+            XDP_PGOFF_RX_RING => rx.rx.as_ref().map(|ring| &ring.region),
+            XDP_PGOFF_TX_RING => tx.tx.as_ref().map(|ring| &ring.region),
+            XDP_UMEM_PGOFF_FILL_RING => rx.fill.as_ref().map(|ring| &ring.region),
+            XDP_UMEM_PGOFF_COMPLETION_RING => tx.comp.as_ref().map(|ring| &ring.region),
+            XDP_UMEM_PGOFF_UMEM => rx.umem.as_ref().map(|umem| &umem.region),
+            _ => None,
+        };
+        region.map(|region| region.len())
+    }
+
+    fn statistics(&self) -> XdpStatistics {
+        let mut stats = self.rx.lock_irqsave().stats;
+        let tx = self.tx.lock_irqsave().stats;
+        stats.tx_invalid_descs = tx.tx_invalid_descs;
+        stats.tx_ring_empty_descs = tx.tx_ring_empty_descs;
+        stats
+    }
+}
+
+/// AF_XDP套接字
+///
+/// https://docs.kernel.org/networking/af_xdp.html
+#[derive(Debug, Clone)]
+pub struct XdpSocket {
+    metadata: SocketMetadata,
+    sock: Arc<XdpSock>,
+    handle: GlobalSocketHandle,
+    posix_item: Arc<PosixSocketHandleItem>,
+}
+
+impl XdpSocket {
+    /// 默认的元数据缓冲区大小
+    pub const DEFAULT_METADATA_BUF_SIZE: usize = 1024;
+    /// 默认的缓冲区大小
+    pub const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+
+    /// # 创建一个AF_XDP套接字
+    ///
+    /// ## 参数
+    /// - `socket_type`: 只能为`SOCK_RAW`
+    /// - `protocol`: 只能为0
+    /// - `options`: socket选项
+    pub fn new(
+        socket_type: PosixSocketType,
+        protocol: usize,
+        options: SocketOptions,
+    ) -> Result<Self, SystemError> {
+        if !ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_NET_RAW)
+        {
+            return Err(SystemError::EPERM);
+        }
+        if socket_type != PosixSocketType::Raw {
+            return Err(SystemError::ESOCKTNOSUPPORT);
+        }
+        if protocol != 0 {
+            return Err(SystemError::EPROTONOSUPPORT);
+        }
+
+        let metadata = SocketMetadata::new(
+            SocketType::Xdp,
+            Self::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_BUF_SIZE,
+            Self::DEFAULT_METADATA_BUF_SIZE,
+            options,
+        );
+        let posix_item = Arc::new(PosixSocketHandleItem::new(None));
+        let sock = Arc::new(XdpSock {
+            ifindex: AtomicUsize::new(XSK_UNBOUND),
+            rx: SpinLock::new(XskRxQueues::default()),
+            tx: SpinLock::new(XskTxQueues::default()),
+            page_cache: PageCache::new(None),
+            posix_item: posix_item.clone(),
+        });
+
+        Ok(Self {
+            metadata,
+            sock,
+            handle: GlobalSocketHandle::new_kernel_handle(),
+            posix_item,
+        })
+    }
+}
+
+impl Socket for XdpSocket {
+    fn posix_item(&self) -> Arc<PosixSocketHandleItem> {
+        self.posix_item.clone()
+    }
+
+    fn socket_handle(&self) -> GlobalSocketHandle {
+        self.handle
+    }
+
+    fn close(&mut self) {
+        let ifindex = self.sock.ifindex.swap(XSK_UNBOUND, Ordering::SeqCst);
+        if ifindex == XSK_UNBOUND {
+            return;
+        }
+        let mut guard = XSK_MAP.lock_irqsave();
+        if guard
+            .get(&ifindex)
+            .is_some_and(|xs| xs.as_ptr() == Arc::as_ptr(&self.sock))
+        {
+            guard.remove(&ifindex);
+        }
+    }
+
+    /// 接收的帧通过RX队列交给用户程序，recvfrom只用来让内核立即收取网卡中的帧
+    fn read(&self, _buf: &mut [u8]) -> (Result<usize, SystemError>, Endpoint) {
+        let endpoint = self.endpoint().unwrap();
+        if self.sock.bound_ifindex().is_none() {
+            return (Err(SystemError::ENXIO), endpoint);
+        }
+        poll_ifaces();
+        (Ok(0), endpoint)
+    }
+
+    /// 通知内核发送TX队列中的帧，`buf`被忽略
+    fn write(&self, _buf: &[u8], _to: Option<Endpoint>) -> Result<usize, SystemError> {
+        self.sock.xmit()?;
+        // 环回网卡发出的帧要在轮询之后才会被接收
+        poll_ifaces();
+        Ok(0)
+    }
+
+    fn connect(&mut self, _endpoint: Endpoint) -> Result<(), SystemError> {
+        Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+    }
+
+    fn bind(&mut self, endpoint: Endpoint) -> Result<(), SystemError> {
+        let Endpoint::Xdp(endpoint) = endpoint else {
+            return Err(SystemError::EINVAL);
+        };
+        let ifindex = self.sock.check_bind(&endpoint)?;
+
+        let mut guard = XSK_MAP.lock_irqsave();
+        if guard.get(&ifindex).is_some_and(|xs| xs.strong_count() > 0) {
+            return Err(SystemError::EBUSY);
+        }
+        guard.insert(ifindex, Arc::downgrade(&self.sock));
+        self.sock.ifindex.store(ifindex, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn endpoint(&self) -> Option<Endpoint> {
+        Some(Endpoint::Xdp(XdpEndpoint {
+            flags: 0,
+            ifindex: self.sock.bound_ifindex().unwrap_or(0) as u32,
+            queue_id: 0,
+            shared_umem_fd: 0,
+        }))
+    }
+
+    fn poll(&self) -> EPollEventType {
+        let mut events = EPollEventType::empty();
+        if let Some(rx) = self.sock.rx.lock_irqsave().rx.as_ref() {
+            if rx.len() > 0 {
+                events.insert(EPollEventType::EPOLLIN | EPollEventType::EPOLLRDNORM);
+            }
+        }
+        if let Some(tx) = self.sock.tx.lock_irqsave().tx.as_ref() {
+            if tx.len() < tx.entries {
+                events.insert(EPollEventType::EPOLLOUT | EPollEventType::EPOLLWRNORM);
+            }
+        }
+        events
+    }
+
+    fn setsockopt(
+        &mut self,
+        level: usize,
+        optname: usize,
+        optval: &[u8],
+    ) -> Result<(), SystemError> {
+        if level != SOL_XDP {
+            return Err(SystemError::ENOPROTOOPT);
+        }
+        // 绑定之后不能再修改UMEM和队列
+        if self.sock.bound_ifindex().is_some() {
+            return Err(SystemError::EBUSY);
+        }
+        match optname {
+            XDP_UMEM_REG => self.sock.reg_umem(XdpUmemReg::from_bytes(optval)?),
+            XDP_RX_RING | XDP_TX_RING | XDP_UMEM_FILL_RING | XDP_UMEM_COMPLETION_RING => {
+                let entries = sockopt_read_int(optval)?;
+                if entries <= 0
+                    || entries as u32 > XSK_RING_MAX_ENTRIES
+                    || !(entries as u32).is_power_of_two()
+                {
+                    return Err(SystemError::EINVAL);
+                }
+                self.sock.create_ring(optname, entries as u32)
+            }
+            _ => Err(SystemError::ENOPROTOOPT),
+        }
+    }
+
+    fn getsockopt(
+        &self,
+        level: usize,
+        optname: usize,
+        optval: &mut [u8],
+    ) -> Result<usize, SystemError> {
+        if level != SOL_XDP {
+            return Err(SystemError::ENOPROTOOPT);
+        }
+        match optname {
+            XDP_MMAP_OFFSETS => sockopt_write_struct(optval, &XdpMmapOffsets::new()),
+            XDP_STATISTICS => sockopt_write_struct(optval, &self.sock.statistics()),
+            _ => Err(SystemError::ENOPROTOOPT),
+        }
+    }
+
+    fn mmap(&self, _start: usize, len: usize, offset: usize) -> Result<(), SystemError> {
+        match self.sock.mmap_len(offset) {
+            Some(region_len) if len <= region_len => Ok(()),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+
+    fn page_cache(&self) -> Option<Arc<PageCache>> {
+        Some(self.sock.page_cache.clone())
+    }
+
+    fn metadata(&self) -> SocketMetadata {
+        self.metadata.clone()
+    }
+
+    fn box_clone(&self) -> Box<dyn Socket> {
+        Box::new(self.clone())
+    }
+
+    fn as_any_ref(&self) -> &dyn core::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn core::any::Any {
+        self
+    }
+}
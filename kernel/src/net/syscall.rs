@@ -1,6 +1,6 @@
-use core::{cmp::min, ffi::CStr};
+use core::cmp::min;
 
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{boxed::Box, string::String, sync::Arc};
 use num_traits::{FromPrimitive, ToPrimitive};
 use smoltcp::wire;
 use system_error::SystemError;
@@ -9,18 +9,27 @@ use crate::{
     filesystem::vfs::{
         file::{File, FileMode},
         syscall::{IoVec, IoVecs},
-        FileType,
+        IndexNode,
     },
     libs::spinlock::SpinLockGuard,
     mm::{verify_area, VirtAddr},
-    net::socket::{AddressFamily, SOL_SOCKET},
+    net::socket::{netlink::NetlinkEndpoint, AddressFamily, SOL_SOCKET},
     process::ProcessManager,
-    syscall::Syscall,
+    syscall::{
+        user_access::{UserPtr, UserSlice},
+        Syscall,
+    },
 };
 
 use super::{
-    socket::{new_socket, PosixSocketType, Socket, SocketInode},
-    Endpoint, Protocol, ShutdownType,
+    socket::{
+        new_socket,
+        scm::{ScmData, MSG_CMSG_CLOEXEC},
+        unix::UnixEndpoint,
+        xdp::XdpEndpoint,
+        PosixSocketType, Socket, SocketInode, SocketOptions,
+    },
+    Endpoint, LinkLayerEndpoint, ShutdownType,
 };
 
 /// Flags for socket, socketpair, accept4
@@ -39,13 +48,13 @@ impl Syscall {
         protocol: usize,
     ) -> Result<usize, SystemError> {
         let address_family = AddressFamily::try_from(address_family as u16)?;
+        let (file_mode, options) = Self::socket_type_flags(socket_type)?;
         let socket_type = PosixSocketType::try_from((socket_type & 0xf) as u8)?;
-        let protocol = Protocol::from(protocol as u8);
 
-        let socket = new_socket(address_family, socket_type, protocol)?;
+        let socket = new_socket(address_family, socket_type, protocol, options)?;
 
         let socketinode: Arc<SocketInode> = SocketInode::new(socket);
-        let f = File::new(socketinode, FileMode::O_RDWR)?;
+        let f = File::new(socketinode, file_mode)?;
         // 把socket添加到当前进程的文件描述符表中
         let binding = ProcessManager::current_pcb().fd_table();
         let mut fd_table_guard = binding.write();
@@ -68,15 +77,15 @@ impl Syscall {
         fds: &mut [i32],
     ) -> Result<usize, SystemError> {
         let address_family = AddressFamily::try_from(address_family as u16)?;
+        let (file_mode, options) = Self::socket_type_flags(socket_type)?;
         let socket_type = PosixSocketType::try_from((socket_type & 0xf) as u8)?;
-        let protocol = Protocol::from(protocol as u8);
 
         let binding = ProcessManager::current_pcb().fd_table();
         let mut fd_table_guard = binding.write();
 
         // 创建一对socket
-        let inode0 = SocketInode::new(new_socket(address_family, socket_type, protocol)?);
-        let inode1 = SocketInode::new(new_socket(address_family, socket_type, protocol)?);
+        let inode0 = SocketInode::new(new_socket(address_family, socket_type, protocol, options)?);
+        let inode1 = SocketInode::new(new_socket(address_family, socket_type, protocol, options)?);
 
         // 进行pair
         unsafe {
@@ -88,13 +97,40 @@ impl Syscall {
                 .connect(Endpoint::Inode(Some(inode0.clone())))?;
         }
 
-        fds[0] = fd_table_guard.alloc_fd(File::new(inode0, FileMode::O_RDWR)?, None)?;
-        fds[1] = fd_table_guard.alloc_fd(File::new(inode1, FileMode::O_RDWR)?, None)?;
+        let file0 = File::new(inode0, file_mode)?;
+        let file1 = File::new(inode1, file_mode)?;
+        fds[0] = fd_table_guard.alloc_fd(file0, None)?;
+        fds[1] = match fd_table_guard.alloc_fd(file1, None) {
+            Ok(fd) => fd,
+            Err(e) => {
+                // 第二个fd分配失败时，回收已经分配的第一个fd
+                fd_table_guard.drop_fd(fds[0]).ok();
+                return Err(e);
+            }
+        };
 
         drop(fd_table_guard);
         Ok(0)
     }
 
+    /// 解析socket(2)和socketpair(2)的`type`参数中与类型一起传入的SOCK_CLOEXEC和SOCK_NONBLOCK
+    ///
+    /// @return 新文件的打开模式和socket的初始选项
+    fn socket_type_flags(socket_type: usize) -> Result<(FileMode, SocketOptions), SystemError> {
+        let flags = (socket_type & !0xf) as u32;
+        if flags & !(SOCK_CLOEXEC | SOCK_NONBLOCK).bits() != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let flags = FileMode::from_bits_truncate(flags);
+
+        let options = if flags.contains(SOCK_NONBLOCK) {
+            SocketOptions::empty()
+        } else {
+            SocketOptions::BLOCK
+        };
+        Ok((FileMode::O_RDWR | flags, options))
+    }
+
     /// @brief sys_setsockopt系统调用的实际执行函数
     ///
     /// @param fd 文件描述符
@@ -112,7 +148,7 @@ impl Syscall {
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
         // 获取内层的socket（真正的数据）
-        let socket: SpinLockGuard<Box<dyn Socket>> = socket_inode.inner();
+        let mut socket: SpinLockGuard<Box<dyn Socket>> = socket_inode.inner();
         return socket.setsockopt(level, optname, optval).map(|_| 0);
     }
 
@@ -133,12 +169,40 @@ impl Syscall {
         optlen: *mut u32,
     ) -> Result<usize, SystemError> {
         // 获取socket
-        let optval = optval as *mut u32;
         let binding: Arc<SocketInode> = ProcessManager::current_pcb()
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
         let socket = binding.inner();
 
+        // 先交给具体的socket处理，它不认识的选项再走下面的通用处理
+        // 足够容纳最大的选项值（TCP_INFO）
+        let mut kbuf = [0u8; 256];
+        match socket.getsockopt(level, optname, &mut kbuf) {
+            Ok(len) => {
+                drop(socket);
+                let optlen = UserPtr::<u32>::from_ptr(optlen);
+                let user_len = optlen.read()? as usize;
+                // SO_PEERGROUPS的结果不能截断，缓冲区不够时返回ERANGE和需要的长度
+                if level as u8 == SOL_SOCKET
+                    && optname == PosixSocketOption::SO_PEERGROUPS as usize
+                    && len > user_len
+                {
+                    optlen.write(&(len as u32))?;
+                    return Err(SystemError::ERANGE);
+                }
+                let len = min(len, user_len);
+                UserSlice::new(VirtAddr::new(optval as usize), len)?
+                    .writer()
+                    .write_raw(&kbuf[..len])?;
+                optlen.write(&(len as u32))?;
+                return Ok(0);
+            }
+            Err(SystemError::ENOPROTOOPT) => {}
+            Err(e) => return Err(e),
+        }
+
+        let optval = optval as *mut u32;
+
         if level as u8 == SOL_SOCKET {
             let optname = PosixSocketOption::try_from(optname as i32)
                 .map_err(|_| SystemError::ENOPROTOOPT)?;
@@ -278,22 +342,58 @@ impl Syscall {
 
         // 如果有地址信息，将地址信息写入用户空间
         if !addr.is_null() {
-            let sockaddr_in = SockAddr::from(endpoint);
             unsafe {
-                sockaddr_in.write_to_user(addr, addrlen)?;
+                SockAddr::write_endpoint_to_user(endpoint, addr, addrlen)?;
             }
         }
         return Ok(n);
     }
 
-    /// @brief sys_recvmsg系统调用的实际执行函数
+    /// @brief sys_sendmsg系统调用的实际执行函数
     ///
     /// @param fd 文件描述符
     /// @param msg MsgHdr
     /// @param flags 标志，暂时未使用
     ///
+    /// @return 成功返回发送的字节数，失败返回错误码
+    pub fn sendmsg(fd: usize, msg: &MsgHdr, _flags: u32) -> Result<usize, SystemError> {
+        // 检查每个缓冲区地址是否合法，生成iovecs
+        let iovs = unsafe { IoVecs::from_user(msg.msg_iov, msg.msg_iovlen, false)? };
+        let buf = iovs.gather();
+
+        let endpoint = if msg.msg_name.is_null() {
+            None
+        } else {
+            Some(SockAddr::to_endpoint(
+                msg.msg_name,
+                msg.msg_namelen as usize,
+            )?)
+        };
+        // 解析辅助数据，SCM_RIGHTS中的文件在这里被复制
+        let scm = ScmData::from_user(msg.msg_control, msg.msg_controllen)?;
+
+        let socket: Arc<SocketInode> = ProcessManager::current_pcb()
+            .get_socket(fd as i32)
+            .ok_or(SystemError::EBADF)?;
+        // socket不能通过它自己发送：它会被对端接收缓冲区中的自身引用保持存活，关闭后无法释放
+        if scm
+            .files
+            .contains_inode(&(socket.clone() as Arc<dyn IndexNode>))
+        {
+            return Err(SystemError::EINVAL);
+        }
+        let socket = unsafe { socket.inner_no_preempt() };
+        return socket.write_msg(&buf, endpoint, scm);
+    }
+
+    /// @brief sys_recvmsg系统调用的实际执行函数
+    ///
+    /// @param fd 文件描述符
+    /// @param msg MsgHdr
+    /// @param flags 标志，目前只支持MSG_CMSG_CLOEXEC
+    ///
     /// @return 成功返回接收的字节数，失败返回错误码
-    pub fn recvmsg(fd: usize, msg: &mut MsgHdr, _flags: u32) -> Result<usize, SystemError> {
+    pub fn recvmsg(fd: usize, msg: &mut MsgHdr, flags: u32) -> Result<usize, SystemError> {
         // 检查每个缓冲区地址是否合法，生成iovecs
         let mut iovs = unsafe { IoVecs::from_user(msg.msg_iov, msg.msg_iovlen, true)? };
 
@@ -301,10 +401,11 @@ impl Syscall {
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
         let socket = unsafe { socket.inner_no_preempt() };
+        let passcred = socket.metadata().options.contains(SocketOptions::PASSCRED);
 
         let mut buf = iovs.new_buf(true);
         // 从socket中读取数据
-        let (n, endpoint) = socket.read(&mut buf);
+        let (n, endpoint, scm) = socket.read_msg(&mut buf);
         drop(socket);
 
         let n: usize = n?;
@@ -312,9 +413,14 @@ impl Syscall {
         // 将数据写入用户空间的iovecs
         iovs.scatter(&buf[..n]);
 
-        let sockaddr_in = SockAddr::from(endpoint);
-        unsafe {
-            sockaddr_in.write_to_user(msg.msg_name, &mut msg.msg_namelen)?;
+        // 安装传递过来的文件，并写回控制消息
+        msg.msg_flags = 0;
+        scm.write_to_user(msg, passcred, flags & MSG_CMSG_CLOEXEC != 0)?;
+
+        if !msg.msg_name.is_null() {
+            unsafe {
+                SockAddr::write_endpoint_to_user(endpoint, msg.msg_name, &mut msg.msg_namelen)?;
+            }
         }
         return Ok(n);
     }
@@ -344,6 +450,10 @@ impl Syscall {
         let socket: Arc<SocketInode> = ProcessManager::current_pcb()
             .get_socket(fd as i32)
             .ok_or(SystemError::EBADF)?;
+        // SHUT_RD、SHUT_WR、SHUT_RDWR分别为0、1、2，加1之后就是对应的ShutdownType
+        if how > 2 {
+            return Err(SystemError::EINVAL);
+        }
         let mut socket = unsafe { socket.inner_no_preempt() };
         socket.shutdown(ShutdownType::from_bits_truncate((how + 1) as u8))?;
         return Ok(0);
@@ -422,19 +532,20 @@ impl Syscall {
             file_mode |= FileMode::O_CLOEXEC;
         }
 
-        let new_fd = ProcessManager::current_pcb()
-            .fd_table()
-            .write()
-            .alloc_fd(File::new(new_socket, file_mode)?, None)?;
-        // debug!("accept: new_fd={}", new_fd);
+        let new_file = File::new(new_socket, file_mode)?;
         if !addr.is_null() {
             // debug!("accept: write remote_endpoint to user");
-            // 将对端地址写入用户空间
-            let sockaddr_in = SockAddr::from(remote_endpoint);
+            // 将对端地址写入用户空间。
+            // 必须在安装fd之前完成，否则写入失败时新的fd已经对用户可见，却没有返回给用户，从而泄漏
             unsafe {
-                sockaddr_in.write_to_user(addr, addrlen)?;
+                SockAddr::write_endpoint_to_user(remote_endpoint, addr, addrlen)?;
             }
         }
+        let new_fd = ProcessManager::current_pcb()
+            .fd_table()
+            .write()
+            .alloc_fd(new_file, None)?;
+        // debug!("accept: new_fd={}", new_fd);
         return Ok(new_fd as usize);
     }
 
@@ -463,9 +574,8 @@ impl Syscall {
         let endpoint: Endpoint = socket.endpoint().ok_or(SystemError::EINVAL)?;
         drop(socket);
 
-        let sockaddr_in = SockAddr::from(endpoint);
         unsafe {
-            sockaddr_in.write_to_user(addr, addrlen)?;
+            SockAddr::write_endpoint_to_user(endpoint, addr, addrlen)?;
         }
         return Ok(0);
     }
@@ -493,9 +603,8 @@ impl Syscall {
         let endpoint: Endpoint = socket.peer_endpoint().ok_or(SystemError::EINVAL)?;
         drop(socket);
 
-        let sockaddr_in = SockAddr::from(endpoint);
         unsafe {
-            sockaddr_in.write_to_user(addr, addrlen)?;
+            SockAddr::write_endpoint_to_user(endpoint, addr, addrlen)?;
         }
         return Ok(0);
     }
@@ -511,6 +620,17 @@ pub struct SockAddrIn {
     pub sin_zero: [u8; 8],
 }
 
+// 参考资料： https://pubs.opengroup.org/onlinepubs/9699919799/basedefs/netinet_in.h.html#tag_13_32
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockAddrIn6 {
+    pub sin6_family: u16,
+    pub sin6_port: u16,
+    pub sin6_flowinfo: u32,
+    pub sin6_addr: [u8; 16],
+    pub sin6_scope_id: u32,
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct SockAddrUn {
@@ -518,6 +638,11 @@ pub struct SockAddrUn {
     pub sun_path: [u8; 108],
 }
 
+/// 以太网硬件地址的长度
+const ETHERNET_ADDR_LEN: usize = 6;
+/// 以太网的硬件类型，目前所有网卡都工作在以太网模式下
+const ARPHRD_ETHER: u16 = 1;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct SockAddrLl {
@@ -539,6 +664,16 @@ pub struct SockAddrNl {
     nl_groups: u32,
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockAddrXdp {
+    pub sxdp_family: u16,
+    pub sxdp_flags: u16,
+    pub sxdp_ifindex: u32,
+    pub sxdp_queue_id: u32,
+    pub sxdp_shared_umem_fd: u32,
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct SockAddrPlaceholder {
@@ -551,9 +686,11 @@ pub struct SockAddrPlaceholder {
 pub union SockAddr {
     pub family: u16,
     pub addr_in: SockAddrIn,
+    pub addr_in6: SockAddrIn6,
     pub addr_un: SockAddrUn,
     pub addr_ll: SockAddrLl,
     pub addr_nl: SockAddrNl,
+    pub addr_xdp: SockAddrXdp,
     pub addr_ph: SockAddrPlaceholder,
 }
 
@@ -583,35 +720,84 @@ impl SockAddr {
 
                     return Ok(Endpoint::Ip(Some(wire::IpEndpoint::new(ip, port))));
                 }
-                AddressFamily::Unix => {
-                    let addr_un: SockAddrUn = addr.addr_un;
-
-                    let path = CStr::from_bytes_until_nul(&addr_un.sun_path)
-                        .map_err(|_| SystemError::EINVAL)?
-                        .to_str()
-                        .map_err(|_| SystemError::EINVAL)?;
-
-                    let fd = Syscall::open(path.as_ptr(), FileMode::O_RDWR.bits(), 0o755, true)?;
+                AddressFamily::INet6 => {
+                    if len < addr.len()? {
+                        return Err(SystemError::EINVAL);
+                    }
+                    let addr_in6: SockAddrIn6 = addr.addr_in6;
 
-                    let binding = ProcessManager::current_pcb().fd_table();
-                    let fd_table_guard = binding.read();
+                    // smoltcp不区分链路本地地址的作用域，sin6_scope_id被忽略
+                    let ip =
+                        wire::IpAddress::Ipv6(wire::Ipv6Address::from_bytes(&addr_in6.sin6_addr));
+                    let port = u16::from_be(addr_in6.sin6_port);
 
-                    let file = fd_table_guard.get_file_by_fd(fd as i32).unwrap();
-                    if file.file_type() != FileType::Socket {
-                        return Err(SystemError::ENOTSOCK);
-                    }
-                    let inode = file.inode();
-                    let socketinode = inode.as_any_ref().downcast_ref::<Arc<SocketInode>>();
+                    return Ok(Endpoint::Ip(Some(wire::IpEndpoint::new(ip, port))));
+                }
+                AddressFamily::Unix => {
+                    let addr_un: SockAddrUn = addr.addr_un;
+                    let path_len = len
+                        .checked_sub(core::mem::size_of::<u16>())
+                        .ok_or(SystemError::EINVAL)?;
+                    let path = &addr_un.sun_path[..min(path_len, addr_un.sun_path.len())];
+
+                    let endpoint = match path.first() {
+                        // 只有地址族的地址用于自动绑定
+                        None => UnixEndpoint::Unnamed,
+                        // 以'\0'开头的是抽象命名空间中的名字，名字的长度由addrlen决定
+                        Some(0) => UnixEndpoint::Abstract(path[1..].to_vec()),
+                        Some(_) => {
+                            let end = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+                            let path = core::str::from_utf8(&path[..end])
+                                .map_err(|_| SystemError::EINVAL)?;
+                            UnixEndpoint::Path(String::from(path))
+                        }
+                    };
 
-                    return Ok(Endpoint::Inode(socketinode.cloned()));
+                    return Ok(Endpoint::Unix(endpoint));
                 }
                 AddressFamily::Packet => {
-                    // TODO: support packet socket
-                    return Err(SystemError::EINVAL);
+                    if len < addr.len()? {
+                        return Err(SystemError::EINVAL);
+                    }
+
+                    let addr_ll: SockAddrLl = addr.addr_ll;
+                    let hw_addr = if addr_ll.sll_halen as usize == ETHERNET_ADDR_LEN {
+                        Some(wire::EthernetAddress::from_bytes(
+                            &addr_ll.sll_addr[..ETHERNET_ADDR_LEN],
+                        ))
+                    } else {
+                        None
+                    };
+                    return Ok(Endpoint::LinkLayer(LinkLayerEndpoint {
+                        interface: addr_ll.sll_ifindex as usize,
+                        protocol: u16::from_be(addr_ll.sll_protocol),
+                        pkttype: addr_ll.sll_pkttype,
+                        addr: hw_addr,
+                    }));
                 }
                 AddressFamily::Netlink => {
-                    // TODO: support netlink socket
-                    return Err(SystemError::EINVAL);
+                    if len < addr.len()? {
+                        return Err(SystemError::EINVAL);
+                    }
+
+                    let addr_nl: SockAddrNl = addr.addr_nl;
+                    return Ok(Endpoint::Netlink(NetlinkEndpoint {
+                        portid: addr_nl.nl_pid,
+                        groups: addr_nl.nl_groups,
+                    }));
+                }
+                AddressFamily::Xdp => {
+                    if len < addr.len()? {
+                        return Err(SystemError::EINVAL);
+                    }
+
+                    let addr_xdp: SockAddrXdp = addr.addr_xdp;
+                    return Ok(Endpoint::Xdp(XdpEndpoint {
+                        flags: addr_xdp.sxdp_flags,
+                        ifindex: addr_xdp.sxdp_ifindex,
+                        queue_id: addr_xdp.sxdp_queue_id,
+                        shared_umem_fd: addr_xdp.sxdp_shared_umem_fd,
+                    }));
                 }
                 _ => {
                     return Err(SystemError::EINVAL);
@@ -624,9 +810,22 @@ impl SockAddr {
     pub fn len(&self) -> Result<usize, SystemError> {
         let ret = match AddressFamily::try_from(unsafe { self.family })? {
             AddressFamily::INet => Ok(core::mem::size_of::<SockAddrIn>()),
+            AddressFamily::INet6 => Ok(core::mem::size_of::<SockAddrIn6>()),
             AddressFamily::Packet => Ok(core::mem::size_of::<SockAddrLl>()),
             AddressFamily::Netlink => Ok(core::mem::size_of::<SockAddrNl>()),
-            AddressFamily::Unix => Err(SystemError::EINVAL),
+            AddressFamily::Xdp => Ok(core::mem::size_of::<SockAddrXdp>()),
+            AddressFamily::Unix => {
+                let sun_path = unsafe { self.addr_un.sun_path };
+                let path_len = match sun_path.first() {
+                    // 抽象地址的名字不以'\0'结尾，以最后一个非零字节作为结尾
+                    Some(0) => sun_path.iter().rposition(|&c| c != 0).map_or(0, |i| i + 1),
+                    _ => sun_path
+                        .iter()
+                        .position(|&c| c == 0)
+                        .map_or(sun_path.len(), |i| i + 1),
+                };
+                Ok(core::mem::size_of::<u16>() + path_len)
+            }
             _ => Err(SystemError::EINVAL),
         };
 
@@ -643,6 +842,38 @@ impl SockAddr {
         &self,
         addr: *mut SockAddr,
         addr_len: *mut u32,
+    ) -> Result<usize, SystemError> {
+        self.write_to_user_with_len(self.len()?, addr, addr_len)
+    }
+
+    /// @brief 把端点的地址写入用户空间
+    ///
+    /// unix域抽象地址的名字可以包含'\0'，无法从SockAddr中得出它的长度，因此地址的长度由端点决定
+    ///
+    /// @return 成功返回写入的长度，失败返回错误码
+    pub unsafe fn write_endpoint_to_user(
+        endpoint: Endpoint,
+        addr: *mut SockAddr,
+        addr_len: *mut u32,
+    ) -> Result<usize, SystemError> {
+        let unix_len = match &endpoint {
+            Endpoint::Unix(unix_endpoint) => Some(unix_endpoint.sockaddr_len()),
+            _ => None,
+        };
+        let sockaddr = SockAddr::from(endpoint);
+        let len = match unix_len {
+            Some(len) => len,
+            None => sockaddr.len()?,
+        };
+        sockaddr.write_to_user_with_len(len, addr, addr_len)
+    }
+
+    /// @brief 把SockAddr的前`len`个字节写入用户空间，并把`len`写入`addr_len`
+    unsafe fn write_to_user_with_len(
+        &self,
+        len: usize,
+        addr: *mut SockAddr,
+        addr_len: *mut u32,
     ) -> Result<usize, SystemError> {
         // 当用户传入的地址或者长度为空时，直接返回0
         if addr.is_null() || addr_len.is_null() {
@@ -662,7 +893,7 @@ impl SockAddr {
         )
         .map_err(|_| SystemError::EFAULT)?;
 
-        let to_write = min(self.len()?, *addr_len as usize);
+        let to_write = min(len, *addr_len as usize);
         if to_write > 0 {
             let buf = core::slice::from_raw_parts_mut(addr as *mut u8, to_write);
             buf.copy_from_slice(core::slice::from_raw_parts(
@@ -670,7 +901,7 @@ impl SockAddr {
                 to_write,
             ));
         }
-        *addr_len = self.len()? as u32;
+        *addr_len = len as u32;
         return Ok(to_write);
     }
 }
@@ -701,29 +932,89 @@ impl From<Endpoint> for SockAddr {
 
                         return SockAddr { addr_in };
                     }
-                    _ => {
-                        unimplemented!("not support ipv6");
+                    wire::IpAddress::Ipv6(ipv6_addr) => {
+                        let addr_in6 = SockAddrIn6 {
+                            sin6_family: AddressFamily::INet6 as u16,
+                            sin6_port: ip_endpoint.port.to_be(),
+                            sin6_flowinfo: 0,
+                            sin6_addr: ipv6_addr.0,
+                            sin6_scope_id: 0,
+                        };
+
+                        return SockAddr { addr_in6 };
                     }
                 }
             }
 
             Endpoint::LinkLayer(link_endpoint) => {
+                let mut sll_addr = [0u8; 8];
+                let mut sll_halen = 0;
+                if let Some(hw_addr) = link_endpoint.addr {
+                    sll_addr[..ETHERNET_ADDR_LEN].copy_from_slice(hw_addr.as_bytes());
+                    sll_halen = ETHERNET_ADDR_LEN as u8;
+                }
                 let addr_ll = SockAddrLl {
                     sll_family: AddressFamily::Packet as u16,
-                    sll_protocol: 0,
+                    sll_protocol: link_endpoint.protocol.to_be(),
                     sll_ifindex: link_endpoint.interface as u32,
-                    sll_hatype: 0,
-                    sll_pkttype: 0,
-                    sll_halen: 0,
-                    sll_addr: [0; 8],
+                    sll_hatype: ARPHRD_ETHER,
+                    sll_pkttype: link_endpoint.pkttype,
+                    sll_halen,
+                    sll_addr,
                 };
 
                 return SockAddr { addr_ll };
             }
 
-            _ => {
-                // todo: support other endpoint, like Netlink...
-                unimplemented!("not support {value:?}");
+            Endpoint::Netlink(netlink_endpoint) => {
+                let addr_nl = SockAddrNl {
+                    nl_family: AddressFamily::Netlink as u16,
+                    nl_pad: 0,
+                    nl_pid: netlink_endpoint.portid,
+                    nl_groups: netlink_endpoint.groups,
+                };
+
+                return SockAddr { addr_nl };
+            }
+
+            Endpoint::Xdp(xdp_endpoint) => {
+                let addr_xdp = SockAddrXdp {
+                    sxdp_family: AddressFamily::Xdp as u16,
+                    sxdp_flags: xdp_endpoint.flags,
+                    sxdp_ifindex: xdp_endpoint.ifindex,
+                    sxdp_queue_id: xdp_endpoint.queue_id,
+                    sxdp_shared_umem_fd: xdp_endpoint.shared_umem_fd,
+                };
+
+                return SockAddr { addr_xdp };
+            }
+
+            // socketpair创建的socket没有名字
+            Endpoint::Inode(_) => {
+                return SockAddr::from(Endpoint::Unix(UnixEndpoint::Unnamed));
+            }
+
+            Endpoint::Unix(unix_endpoint) => {
+                let mut sun_path = [0u8; 108];
+                // 保留结尾的'\0'
+                let max = sun_path.len() - 1;
+                match unix_endpoint {
+                    UnixEndpoint::Unnamed => {}
+                    UnixEndpoint::Path(path) => {
+                        let n = min(path.len(), max);
+                        sun_path[..n].copy_from_slice(&path.as_bytes()[..n]);
+                    }
+                    UnixEndpoint::Abstract(name) => {
+                        let n = min(name.len(), max);
+                        sun_path[1..n + 1].copy_from_slice(&name[..n]);
+                    }
+                }
+                let addr_un = SockAddrUn {
+                    sun_family: AddressFamily::Unix as u16,
+                    sun_path,
+                };
+
+                return SockAddr { addr_un };
             }
         }
     }
@@ -1032,3 +1323,87 @@ impl From<PosixTcpSocketOptions> for i32 {
         <PosixTcpSocketOptions as ToPrimitive>::to_i32(&val).unwrap()
     }
 }
+
+/// IP层（level为`PosixIpProtocol::IP`）的socket选项
+///
+/// 参考：https://code.dragonos.org.cn/xref/linux-5.19.10/include/uapi/linux/in.h#96
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum PosixIpSocketOptions {
+    Tos = 1,
+    Ttl = 2,
+    HdrIncl = 3,
+    Options = 4,
+    RecvOpts = 6,
+    RetOpts = 7,
+    PktInfo = 8,
+    MtuDiscover = 10,
+    RecvErr = 11,
+    RecvTtl = 12,
+    RecvTos = 13,
+    Mtu = 14,
+    FreeBind = 15,
+    /// 发送组播报文使用的网卡
+    MulticastIf = 32,
+    /// 发送组播报文的TTL
+    MulticastTtl = 33,
+    /// 是否把发送的组播报文回送给本机
+    MulticastLoop = 34,
+    /// 加入组播组
+    AddMembership = 35,
+    /// 离开组播组
+    DropMembership = 36,
+    /// 是否接收所有组播组的报文
+    MulticastAll = 49,
+}
+
+impl TryFrom<i32> for PosixIpSocketOptions {
+    type Error = SystemError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match <Self as FromPrimitive>::from_i32(value) {
+            Some(p) => Ok(p),
+            None => Err(SystemError::EINVAL),
+        }
+    }
+}
+
+impl From<PosixIpSocketOptions> for i32 {
+    fn from(val: PosixIpSocketOptions) -> Self {
+        <PosixIpSocketOptions as ToPrimitive>::to_i32(&val).unwrap()
+    }
+}
+
+/// IPv6层（level为`PosixIpProtocol::IPv6`）的socket选项
+///
+/// 参考：https://code.dragonos.org.cn/xref/linux-5.19.10/include/uapi/linux/in6.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+pub enum PosixIpv6SocketOptions {
+    AddrForm = 1,
+    UnicastHops = 16,
+    MulticastIf = 17,
+    MulticastHops = 18,
+    MulticastLoop = 19,
+    JoinGroup = 20,
+    LeaveGroup = 21,
+    /// 只使用IPv6通信，不接受IPv4映射地址
+    V6Only = 26,
+    RecvPktInfo = 49,
+    TClass = 67,
+}
+
+impl TryFrom<i32> for PosixIpv6SocketOptions {
+    type Error = SystemError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match <Self as FromPrimitive>::from_i32(value) {
+            Some(p) => Ok(p),
+            None => Err(SystemError::EINVAL),
+        }
+    }
+}
+
+impl From<PosixIpv6SocketOptions> for i32 {
+    fn from(val: PosixIpv6SocketOptions) -> Self {
+        <PosixIpv6SocketOptions as ToPrimitive>::to_i32(&val).unwrap()
+    }
+}
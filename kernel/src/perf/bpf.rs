@@ -11,12 +11,13 @@ use crate::libs::spinlock::{SpinLock, SpinLockGuard};
 use crate::mm::allocator::page_frame::{PageFrameCount, PhysPageFrame};
 use crate::mm::page::{page_manager_lock_irqsave, PageFlags, PageType};
 use crate::mm::{MemoryManagementArch, PhysAddr};
-use crate::perf::util::{LostSamples, PerfProbeArgs, PerfSample, SampleHeader};
+use crate::perf::util::PerfProbeArgs;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::any::Any;
 use core::fmt::Debug;
+use ring_buffer::{Ring, RingControl, RingCore, RingMode, RECORD_HEADER_SIZE};
 use system_error::SystemError;
 const PAGE_SIZE: usize = MMArch::PAGE_SIZE;
 #[derive(Debug)]
@@ -38,17 +39,21 @@ pub struct RingPage {
     size: usize,
     ptr: usize,
     data_region_size: usize,
-    lost: usize,
+    /// 生产者的状态。读写位置是mmap页中的`data_head`与`data_tail`，用户态读取数据后更新`data_tail`
+    core: RingCore,
     phys_addr: PhysAddr,
 }
 
+// 环形缓冲区的记录头与perf_event_header的布局相同
+const _: () = assert!(size_of::<perf_event_header>() == RECORD_HEADER_SIZE);
+
 impl RingPage {
     pub fn empty() -> Self {
         RingPage {
             ptr: 0,
             size: 0,
             data_region_size: 0,
-            lost: 0,
+            core: RingCore::new(RingMode::NoOverwrite),
             phys_addr: PhysAddr::new(0),
         }
     }
@@ -75,155 +80,50 @@ impl RingPage {
             ptr: ptr as usize,
             size,
             data_region_size: size - PAGE_SIZE,
-            lost: 0,
+            core: RingCore::new(RingMode::NoOverwrite),
             phys_addr,
         }
     }
 
-    #[inline]
-    fn can_write(&self, data_size: usize, data_tail: usize, data_head: usize) -> bool {
-        let capacity = self.data_region_size - data_head + data_tail;
-        data_size <= capacity
-    }
-
-    pub fn write_event(&mut self, data: &[u8]) -> Result<()> {
-        let data_tail = unsafe { &mut (*(self.ptr as *mut perf_event_mmap_page)).data_tail };
-        let data_head = unsafe { &mut (*(self.ptr as *mut perf_event_mmap_page)).data_head };
-
-        // user lib will update the tail after read the data,but it will not % data_region_size
-        let perf_header_size = size_of::<perf_event_header>();
-        let can_write_perf_header =
-            self.can_write(perf_header_size, *data_tail as usize, *data_head as usize);
-
-        if can_write_perf_header {
-            let can_write_lost_record = self.can_write(
-                size_of::<LostSamples>(),
-                *data_tail as usize,
-                *data_head as usize,
-            );
-            // if there is lost record, we need to write the lost record first
-            if self.lost > 0 && can_write_lost_record {
-                let new_data_head = self.write_lost(*data_head as usize)?;
-                *data_head = new_data_head as u64;
-                // log::info!(
-                //     "Write lost record: {}, data_tail: {}, new_data_head: {}",
-                //     self.lost,
-                //     *data_tail,
-                //     *data_head
-                // );
-                self.lost = 0;
-                // try to write the event again
-                return self.write_event(data);
-            }
-            let sample_size = PerfSample::calculate_size(data.len());
-            let can_write_sample =
-                self.can_write(sample_size, *data_tail as usize, *data_head as usize);
-            // log::error!(
-            //     "can_write_sample: {}, data_tail: {}, data_head: {}, data.len(): {}, region_size: {}",
-            //     can_write_sample,
-            //     *data_tail,
-            //     *data_head,
-            //     data.len(),
-            //     self.data_region_size
-            // );
-            if can_write_sample {
-                let new_data_head = self.write_sample(data, *data_head as usize)?;
-                *data_head = new_data_head as u64;
-                // log::info!(
-                //     "Write sample record, data_tail: {}, new_data_head: {}",
-                //     *data_tail,
-                //     *data_head
-                // );
-            } else {
-                self.lost += 1;
-            }
-        } else {
-            self.lost += 1;
-        }
-        Ok(())
-    }
-
-    /// Write any data to the page.
-    ///
-    /// Return the new data_head
-    fn write_any(&mut self, data: &[u8], data_head: usize) -> Result<()> {
-        let data_region_len = self.data_region_size;
-        let data_region = self.as_mut_slice()[PAGE_SIZE..].as_mut();
-        let data_len = data.len();
-        let start = data_head % data_region_len;
-        let end = (data_head + data_len) % data_region_len;
-        if start < end {
-            data_region[start..end].copy_from_slice(data);
-        } else {
-            let first_len = data_region_len - start;
-            data_region[start..start + first_len].copy_from_slice(&data[..first_len]);
-            data_region[0..end].copy_from_slice(&data[first_len..]);
+    /// The data region after the first page, with data_head/data_tail of the mmap page as its positions.
+    fn ring(&self) -> Result<Ring<'_>> {
+        if self.ptr == 0 {
+            return Err(SystemError::EINVAL);
         }
-        Ok(())
-    }
-    #[inline]
-    fn fill_size(&self, data_head_mod: usize) -> usize {
-        if self.data_region_size - data_head_mod < size_of::<perf_event_header>() {
-            // The remaining space is not enough to write the perf_event_header
-            // We need to fill the remaining space with 0
-            self.data_region_size - data_head_mod
-        } else {
-            0
+        unsafe {
+            let page = self.ptr as *mut perf_event_mmap_page;
+            let control = &*(core::ptr::addr_of!((*page).data_head) as *const RingControl);
+            Ok(Ring::from_raw(
+                &self.core,
+                control,
+                (self.ptr + PAGE_SIZE) as *mut u8,
+                self.data_region_size,
+            ))
         }
     }
 
-    /// Write a sample to the page.
-    fn write_sample(&mut self, data: &[u8], data_head: usize) -> Result<usize> {
-        let sample_size = PerfSample::calculate_size(data.len());
-        let maybe_end = (data_head + sample_size) % self.data_region_size;
-        let fill_size = self.fill_size(maybe_end);
-        let perf_sample = PerfSample {
-            s_hdr: SampleHeader {
-                header: perf_event_header {
-                    type_: perf_event_type::PERF_RECORD_SAMPLE as u32,
-                    misc: 0,
-                    size: size_of::<SampleHeader>() as u16 + data.len() as u16 + fill_size as u16,
-                },
-                size: data.len() as u32,
-            },
-            value: data,
-        };
-        self.write_any(perf_sample.s_hdr.as_bytes(), data_head)?;
-        self.write_any(perf_sample.value, data_head + size_of::<SampleHeader>())?;
-        Ok(data_head + sample_size + fill_size)
-    }
-
-    /// Write a lost record to the page.
-    ///
-    /// Return the new data_head
-    fn write_lost(&mut self, data_head: usize) -> Result<usize> {
-        let maybe_end = (data_head + size_of::<LostSamples>()) % self.data_region_size;
-        let fill_size = self.fill_size(maybe_end);
-        let lost = LostSamples {
-            header: perf_event_header {
-                type_: perf_event_type::PERF_RECORD_LOST as u32,
-                misc: 0,
-                size: size_of::<LostSamples>() as u16 + fill_size as u16,
-            },
-            id: 0,
-            count: self.lost as u64,
-        };
-        self.write_any(lost.as_bytes(), data_head)?;
-        Ok(data_head + size_of::<LostSamples>() + fill_size)
+    pub fn write_event(&self, data: &[u8]) -> Result<()> {
+        let ring = self.ring()?;
+        // if there is lost record, we need to write the lost record first
+        ring.write_lost(perf_event_type::PERF_RECORD_LOST as u32, 0, |count| {
+            // struct { u64 id; u64 lost; }
+            let mut lost = [0u8; 16];
+            lost[8..].copy_from_slice(&count.to_ne_bytes());
+            lost
+        })
+        .ok();
+        // A sample that doesn't fit is dropped and counted as lost by the ring buffer.
+        ring.write(
+            perf_event_type::PERF_RECORD_SAMPLE as u32,
+            0,
+            &[&(data.len() as u32).to_ne_bytes(), data],
+        )
+        .ok();
+        Ok(())
     }
 
     pub fn readable(&self) -> bool {
-        let data_tail = unsafe { &(*(self.ptr as *mut perf_event_mmap_page)).data_tail };
-        let data_head = unsafe { &(*(self.ptr as *mut perf_event_mmap_page)).data_head };
-        data_tail != data_head
-    }
-
-    #[allow(dead_code)]
-    pub fn as_slice(&self) -> &[u8] {
-        unsafe { core::slice::from_raw_parts(self.ptr as *const u8, self.size) }
-    }
-    pub fn as_mut_slice(&mut self) -> &mut [u8] {
-        unsafe { core::slice::from_raw_parts_mut(self.ptr as *mut u8, self.size) }
+        self.ring().is_ok_and(|ring| !ring.is_empty())
     }
 }
 
@@ -262,7 +162,7 @@ impl BpfPerfEvent {
     }
 
     pub fn write_event(&self, data: &[u8]) -> Result<()> {
-        let mut inner_data = self.data.lock();
+        let inner_data = self.data.lock();
         inner_data.mmap_page.write_event(data)?;
         Ok(())
     }
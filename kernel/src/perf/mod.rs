@@ -15,14 +15,13 @@ use crate::libs::casting::DowncastArc;
 use crate::libs::spinlock::{SpinLock, SpinLockGuard};
 use crate::mm::fault::{PageFaultHandler, PageFaultMessage};
 use crate::mm::VmFaultReason;
-use crate::net::event_poll::{EPollEventType, EPollItem, EventPoll, KernelIoctlData};
+use crate::net::event_poll::{EPollEventType, EPollItem, EPollItems, EventPoll};
 use crate::perf::bpf::BpfPerfEvent;
 use crate::perf::util::{PerfEventIoc, PerfEventOpenFlags, PerfProbeArgs};
-use crate::process::ProcessManager;
+use crate::process::{cred::CAPFlags, ProcessManager};
 use crate::syscall::user_access::UserBufferReader;
 use crate::syscall::Syscall;
 use alloc::boxed::Box;
-use alloc::collections::LinkedList;
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
@@ -57,31 +56,16 @@ pub trait PerfEventOps: Send + Sync + Debug + CastFromSync + CastFrom + IndexNod
 #[derive(Debug)]
 pub struct PerfEventInode {
     event: Box<dyn PerfEventOps>,
-    epitems: SpinLock<LinkedList<Arc<EPollItem>>>,
+    epitems: EPollItems,
 }
 
 impl PerfEventInode {
     pub fn new(event: Box<dyn PerfEventOps>) -> Self {
         Self {
             event,
-            epitems: SpinLock::new(LinkedList::new()),
+            epitems: EPollItems::new(),
         }
     }
-    pub fn remove_epoll(
-        &self,
-        epoll: &Weak<SpinLock<EventPoll>>,
-    ) -> core::result::Result<(), SystemError> {
-        let is_remove = !self
-            .epitems
-            .lock_irqsave()
-            .extract_if(|x| x.epoll().ptr_eq(epoll))
-            .collect::<Vec<_>>()
-            .is_empty();
-        if is_remove {
-            return Ok(());
-        }
-        Err(SystemError::ENOENT)
-    }
     fn do_poll(&self) -> Result<usize> {
         let mut events = EPollEventType::empty();
         if self.event.readable() {
@@ -92,7 +76,7 @@ impl PerfEventInode {
     fn epoll_callback(&self) -> Result<()> {
         let pollflag = EPollEventType::from_bits_truncate(self.do_poll()? as u32);
         // 唤醒epoll中等待的进程
-        EventPoll::wakeup_epoll(&self.epitems, Some(pollflag))
+        self.epitems.wakeup(Some(pollflag))
     }
 }
 
@@ -177,17 +161,17 @@ impl IndexNode for PerfEventInode {
         }
     }
 
-    fn kernel_ioctl(
+    fn add_epitem(&self, epitem: Arc<EPollItem>, _private_data: &FilePrivateData) -> Result<()> {
+        self.epitems.add(epitem);
+        Ok(())
+    }
+
+    fn remove_epitem(
         &self,
-        arg: Arc<dyn KernelIoctlData>,
-        _data: &FilePrivateData,
-    ) -> core::result::Result<usize, SystemError> {
-        let epitem = arg
-            .arc_any()
-            .downcast::<EPollItem>()
-            .map_err(|_| SystemError::EFAULT)?;
-        self.epitems.lock().push_back(epitem);
-        Ok(0)
+        epoll: &Weak<SpinLock<EventPoll>>,
+        _private_data: &FilePrivateData,
+    ) -> Result<()> {
+        self.epitems.remove(epoll)
     }
 
     fn fs(&self) -> Arc<dyn FileSystem> {
@@ -250,6 +234,12 @@ impl Syscall {
         group_fd: i32,
         flags: u32,
     ) -> Result<usize> {
+        let cred = ProcessManager::current_pcb().cred();
+        if !cred.has_capability(CAPFlags::CAP_PERFMON)
+            && !cred.has_capability(CAPFlags::CAP_SYS_ADMIN)
+        {
+            return Err(SystemError::EACCES);
+        }
         let buf = UserBufferReader::new(
             attr as *const perf_event_attr,
             size_of::<perf_event_attr>(),
@@ -0,0 +1,416 @@
+//! binfmt_misc：按文件开头的魔数或者文件扩展名，为可执行文件指定解释器
+//!
+//! 用户通过`/proc/sys/fs/binfmt_misc/register`注册格式，格式为：
+//!
+//! `:name:type:offset:magic:mask:interpreter:flags`
+//!
+//! - `type`为`M`时按`offset`处的`magic`（与`mask`按位与之后）匹配，为`E`时按文件扩展名`magic`匹配；
+//! - `magic`和`mask`中可以用`\xHH`表示任意字节；
+//! - `flags`支持`P`（保留原来的argv[0]）和`F`（注册时检查解释器是否存在）。
+//!   `O`和`C`会被记录下来，但目前还没有实现对应的功能。
+//!
+//! execve(2)匹配到某个格式时，以`interpreter 文件路径 参数...`的形式改为执行解释器。
+//!
+//! 参考：https://docs.kernel.org/admin-guide/binfmt-misc.html
+
+use alloc::{
+    ffi::CString,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use system_error::SystemError;
+
+use crate::{
+    filesystem::vfs::{
+        file::{File, FileMode},
+        ROOT_INODE,
+    },
+    libs::rwlock::RwLock,
+};
+
+use super::{cred::CAPFlags, ProcessManager};
+
+/// 匹配魔数时读取的文件头部的长度，魔数不能超出这个范围
+const BINPRM_BUF_SIZE: usize = 256;
+/// 解释器本身也可以由binfmt_misc处理，最多嵌套这么多层
+const BINPRM_MAX_RECURSION: usize = 4;
+
+static BINFMT_MISC: RwLock<BinfmtMisc> = RwLock::new(BinfmtMisc {
+    enabled: true,
+    entries: Vec::new(),
+});
+
+#[derive(Debug)]
+struct BinfmtMisc {
+    /// 为false时不处理任何格式
+    enabled: bool,
+    entries: Vec<BinfmtEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BinfmtMatch {
+    /// 文件`offset`处的内容与`mask`按位与之后等于`magic`
+    Magic {
+        offset: usize,
+        magic: Vec<u8>,
+        mask: Option<Vec<u8>>,
+    },
+    /// 文件名的扩展名（不含'.'）
+    Extension(String),
+}
+
+bitflags! {
+    struct BinfmtFlags: u8 {
+        /// 保留原来的argv[0]，文件路径作为额外的参数传给解释器
+        const PRESERVE_ARGV0 = 1 << 0;
+        /// 通过文件描述符把可执行文件交给解释器（未实现）
+        const OPEN_BINARY = 1 << 1;
+        /// 按可执行文件而不是解释器计算新进程的凭证（未实现）
+        const CREDENTIALS = 1 << 2;
+        /// 注册时检查解释器
+        const FIX_BINARY = 1 << 3;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BinfmtEntry {
+    name: String,
+    enabled: bool,
+    matcher: BinfmtMatch,
+    interpreter: String,
+    flags: BinfmtFlags,
+}
+
+impl BinfmtEntry {
+    /// 解析写入register文件的一行
+    fn parse(line: &[u8]) -> Result<Self, SystemError> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        // 第一个字符是分隔符
+        let (&delim, rest) = line.split_first().ok_or(SystemError::EINVAL)?;
+        let fields: Vec<&[u8]> = rest.split(|&c| c == delim).collect();
+        if fields.len() < 6 || fields.len() > 7 {
+            return Err(SystemError::EINVAL);
+        }
+
+        let name = core::str::from_utf8(fields[0]).map_err(|_| SystemError::EINVAL)?;
+        if name.is_empty()
+            || name.contains('/')
+            || matches!(name, "." | ".." | "register" | "status")
+        {
+            return Err(SystemError::EINVAL);
+        }
+
+        let offset = fields[2];
+        let magic = unescape(fields[3])?;
+        let mask = fields[4];
+        let matcher = match fields[1] {
+            b"M" => {
+                let offset = if offset.is_empty() {
+                    0
+                } else {
+                    core::str::from_utf8(offset)
+                        .ok()
+                        .and_then(|s| s.parse::<usize>().ok())
+                        .ok_or(SystemError::EINVAL)?
+                };
+                let mask = if mask.is_empty() {
+                    None
+                } else {
+                    Some(unescape(mask)?)
+                };
+                if magic.is_empty()
+                    || offset + magic.len() > BINPRM_BUF_SIZE
+                    || mask.as_ref().is_some_and(|m| m.len() != magic.len())
+                {
+                    return Err(SystemError::EINVAL);
+                }
+                BinfmtMatch::Magic {
+                    offset,
+                    magic,
+                    mask,
+                }
+            }
+            b"E" => {
+                if !offset.is_empty() || !mask.is_empty() || magic.is_empty() {
+                    return Err(SystemError::EINVAL);
+                }
+                let ext = String::from_utf8(magic).map_err(|_| SystemError::EINVAL)?;
+                if ext.contains('/') {
+                    return Err(SystemError::EINVAL);
+                }
+                BinfmtMatch::Extension(ext)
+            }
+            _ => return Err(SystemError::EINVAL),
+        };
+
+        let interpreter = core::str::from_utf8(fields[5]).map_err(|_| SystemError::EINVAL)?;
+        if interpreter.is_empty() {
+            return Err(SystemError::EINVAL);
+        }
+
+        let mut flags = BinfmtFlags::empty();
+        for &c in fields.get(6).copied().unwrap_or_default() {
+            flags |= match c {
+                b'P' => BinfmtFlags::PRESERVE_ARGV0,
+                b'O' => BinfmtFlags::OPEN_BINARY,
+                b'C' => BinfmtFlags::CREDENTIALS | BinfmtFlags::OPEN_BINARY,
+                b'F' => BinfmtFlags::FIX_BINARY,
+                _ => return Err(SystemError::EINVAL),
+            };
+        }
+        if flags.contains(BinfmtFlags::FIX_BINARY) {
+            ROOT_INODE().lookup(interpreter)?;
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            enabled: true,
+            matcher,
+            interpreter: interpreter.to_string(),
+            flags,
+        })
+    }
+
+    fn matches(&self, path: &str, head: &[u8]) -> bool {
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                let Some(data) = head.get(*offset..*offset + magic.len()) else {
+                    return false;
+                };
+                match mask {
+                    Some(mask) => data
+                        .iter()
+                        .zip(mask)
+                        .zip(magic)
+                        .all(|((d, m), x)| d & m == *x),
+                    None => data == magic.as_slice(),
+                }
+            }
+            BinfmtMatch::Extension(ext) => {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                name.rsplit_once('.').is_some_and(|(_, e)| e == ext)
+            }
+        }
+    }
+
+    /// 格式文件的内容
+    fn show(&self) -> String {
+        let mut s = String::from(if self.enabled {
+            "enabled\n"
+        } else {
+            "disabled\n"
+        });
+        s += &format!("interpreter {}\n", self.interpreter);
+
+        let mut flags = String::new();
+        for (flag, c) in [
+            (BinfmtFlags::PRESERVE_ARGV0, 'P'),
+            (BinfmtFlags::OPEN_BINARY, 'O'),
+            (BinfmtFlags::CREDENTIALS, 'C'),
+            (BinfmtFlags::FIX_BINARY, 'F'),
+        ] {
+            if self.flags.contains(flag) {
+                flags.push(c);
+            }
+        }
+        s += &format!("flags: {}\n", flags);
+
+        match &self.matcher {
+            BinfmtMatch::Magic {
+                offset,
+                magic,
+                mask,
+            } => {
+                s += &format!("offset {}\nmagic {}\n", offset, hex(magic));
+                if let Some(mask) = mask {
+                    s += &format!("mask {}\n", hex(mask));
+                }
+            }
+            BinfmtMatch::Extension(ext) => s += &format!("extension .{}\n", ext),
+        }
+        s
+    }
+}
+
+/// 处理`\xHH`转义
+fn unescape(s: &[u8]) -> Result<Vec<u8>, SystemError> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == b'\\' && s.get(i + 1) == Some(&b'x') {
+            let hex = s.get(i + 2..i + 4).ok_or(SystemError::EINVAL)?;
+            let hex = core::str::from_utf8(hex).map_err(|_| SystemError::EINVAL)?;
+            out.push(u8::from_str_radix(hex, 16).map_err(|_| SystemError::EINVAL)?);
+            i += 4;
+        } else {
+            out.push(s[i]);
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 解析写入status文件或者格式文件的命令：1启用，0禁用，-1删除
+fn parse_command(buf: &[u8]) -> Result<Option<bool>, SystemError> {
+    let buf = buf.strip_suffix(b"\n").unwrap_or(buf);
+    match buf {
+        b"1" => Ok(Some(true)),
+        b"0" => Ok(Some(false)),
+        b"-1" => Ok(None),
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+fn check_permission() -> Result<(), SystemError> {
+    if ProcessManager::current_pcb()
+        .cred()
+        .has_capability(CAPFlags::CAP_SYS_ADMIN)
+    {
+        Ok(())
+    } else {
+        Err(SystemError::EPERM)
+    }
+}
+
+/// 注册一个格式
+pub fn binfmt_misc_register(line: &[u8]) -> Result<(), SystemError> {
+    check_permission()?;
+    let entry = BinfmtEntry::parse(line)?;
+
+    let mut binfmt = BINFMT_MISC.write();
+    if binfmt.entries.iter().any(|e| e.name == entry.name) {
+        return Err(SystemError::EEXIST);
+    }
+    binfmt.entries.push(entry);
+    Ok(())
+}
+
+/// 已经注册的格式的名字
+pub fn binfmt_misc_names() -> Vec<String> {
+    BINFMT_MISC
+        .read()
+        .entries
+        .iter()
+        .map(|e| e.name.clone())
+        .collect()
+}
+
+/// status文件的内容
+pub fn binfmt_misc_status() -> String {
+    if BINFMT_MISC.read().enabled {
+        String::from("enabled\n")
+    } else {
+        String::from("disabled\n")
+    }
+}
+
+/// 写status文件：启用、禁用binfmt_misc，或者删除所有格式
+pub fn binfmt_misc_write_status(buf: &[u8]) -> Result<(), SystemError> {
+    check_permission()?;
+    let command = parse_command(buf)?;
+
+    let mut binfmt = BINFMT_MISC.write();
+    match command {
+        Some(enabled) => binfmt.enabled = enabled,
+        None => binfmt.entries.clear(),
+    }
+    Ok(())
+}
+
+/// 格式文件的内容
+pub fn binfmt_misc_show(name: &str) -> Result<String, SystemError> {
+    BINFMT_MISC
+        .read()
+        .entries
+        .iter()
+        .find(|e| e.name == name)
+        .map(|e| e.show())
+        .ok_or(SystemError::ENOENT)
+}
+
+/// 写格式文件：启用、禁用或者删除这个格式
+pub fn binfmt_misc_write_entry(name: &str, buf: &[u8]) -> Result<(), SystemError> {
+    check_permission()?;
+    let command = parse_command(buf)?;
+
+    let mut binfmt = BINFMT_MISC.write();
+    let index = binfmt
+        .entries
+        .iter()
+        .position(|e| e.name == name)
+        .ok_or(SystemError::ENOENT)?;
+    match command {
+        Some(enabled) => binfmt.entries[index].enabled = enabled,
+        None => {
+            binfmt.entries.remove(index);
+        }
+    }
+    Ok(())
+}
+
+/// 查找处理`path`的格式，返回它的解释器和标志
+fn binfmt_misc_match(path: &str) -> Result<Option<(String, BinfmtFlags)>, SystemError> {
+    {
+        let binfmt = BINFMT_MISC.read();
+        if !binfmt.enabled || binfmt.entries.iter().all(|e| !e.enabled) {
+            return Ok(None);
+        }
+    }
+
+    let inode = ROOT_INODE().lookup(path)?;
+    let file = File::new(inode, FileMode::O_RDONLY)?;
+    let mut head = [0u8; BINPRM_BUF_SIZE];
+    let len = file.read(BINPRM_BUF_SIZE, &mut head)?;
+
+    let binfmt = BINFMT_MISC.read();
+    if !binfmt.enabled {
+        return Ok(None);
+    }
+    Ok(binfmt
+        .entries
+        .iter()
+        .find(|e| e.enabled && e.matches(path, &head[..len]))
+        .map(|e| (e.interpreter.clone(), e.flags)))
+}
+
+/// execve(2)时确定真正要加载的文件
+///
+/// 如果`path`被某个格式处理，就改为执行它的解释器，并相应地改写参数。
+///
+/// ## 返回值
+///
+/// 要加载的文件路径和新的参数
+pub fn binfmt_misc_resolve(
+    mut path: String,
+    mut argv: Vec<CString>,
+) -> Result<(String, Vec<CString>), SystemError> {
+    for _ in 0..BINPRM_MAX_RECURSION {
+        let Some((interpreter, flags)) = binfmt_misc_match(&path)? else {
+            return Ok((path, argv));
+        };
+
+        // 解释器的参数：解释器 文件路径 [原来的argv[0]] 原来的其他参数
+        let skip = if flags.contains(BinfmtFlags::PRESERVE_ARGV0) {
+            0
+        } else {
+            1
+        };
+        let mut new_argv = Vec::with_capacity(argv.len() + 2);
+        new_argv.push(CString::new(interpreter.as_str()).map_err(|_| SystemError::EINVAL)?);
+        new_argv.push(CString::new(path.as_str()).map_err(|_| SystemError::EINVAL)?);
+        new_argv.extend(argv.into_iter().skip(skip));
+
+        path = interpreter;
+        argv = new_argv;
+    }
+    Err(SystemError::ELOOP)
+}
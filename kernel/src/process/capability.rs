@@ -0,0 +1,251 @@
+//! 进程权限集（capabilities）
+//!
+//! 每个进程的凭证中有permitted、effective、inheritable、bounding和ambient五个权限集，
+//! 内核通过[`Cred::has_capability`](super::cred::Cred::has_capability)检查effective权限集，
+//! 而不是检查uid是否为0。uid改变和execve时按照与Linux相同的规则调整权限集。
+//!
+//! 参考 https://man7.org/linux/man-pages/man7/capabilities.7.html
+
+use core::mem::size_of;
+
+use system_error::SystemError;
+
+use crate::syscall::{
+    user_access::{UserBufferReader, UserBufferWriter},
+    Syscall,
+};
+
+use super::{cred::CAPFlags, Pid, ProcessManager};
+
+/// capget、capset的版本号
+const LINUX_CAPABILITY_VERSION_1: u32 = 0x1998_0330;
+const LINUX_CAPABILITY_VERSION_2: u32 = 0x2007_1026;
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+/// PR_CAP_AMBIENT的子操作
+const PR_CAP_AMBIENT_IS_SET: usize = 1;
+const PR_CAP_AMBIENT_RAISE: usize = 2;
+const PR_CAP_AMBIENT_LOWER: usize = 3;
+const PR_CAP_AMBIENT_CLEAR_ALL: usize = 4;
+
+/// struct __user_cap_header_struct
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CapUserHeader {
+    pub version: u32,
+    pub pid: i32,
+}
+
+/// struct __user_cap_data_struct，64位的权限集分为低32位与高32位两项
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapUserData {
+    pub effective: u32,
+    pub permitted: u32,
+    pub inheritable: u32,
+}
+
+/// # 检查用户传入的版本号
+///
+/// ## 返回值
+/// - `Ok(usize)`: 用户缓冲区中`CapUserData`的数量
+/// - `Err(SystemError::EINVAL)`: 不支持的版本号，此时把内核支持的版本号写回`header`
+fn validate_version(header: *mut CapUserHeader) -> Result<usize, SystemError> {
+    let reader = UserBufferReader::new(header, size_of::<CapUserHeader>(), true)?;
+    let mut h = *reader.read_one_from_user::<CapUserHeader>(0)?;
+    match h.version {
+        LINUX_CAPABILITY_VERSION_1 => Ok(1),
+        LINUX_CAPABILITY_VERSION_2 | LINUX_CAPABILITY_VERSION_3 => Ok(2),
+        _ => {
+            h.version = LINUX_CAPABILITY_VERSION_3;
+            let mut writer = UserBufferWriter::new(header, size_of::<CapUserHeader>(), true)?;
+            writer.copy_one_to_user(&h, 0)?;
+            Err(SystemError::EINVAL)
+        }
+    }
+}
+
+/// 把用户传入的低32位与高32位合并为权限集
+fn merge_caps(data: &[CapUserData], f: impl Fn(&CapUserData) -> u32) -> CAPFlags {
+    let bits = data
+        .iter()
+        .enumerate()
+        .fold(0u64, |acc, (i, d)| acc | ((f(d) as u64) << (32 * i)));
+    CAPFlags::from_bits_truncate(bits)
+}
+
+/// 检查cap是否为合法的权限编号
+fn cap_from_arg(cap: usize) -> Result<CAPFlags, SystemError> {
+    CAPFlags::from_cap(cap).ok_or(SystemError::EINVAL)
+}
+
+/// # prctl(PR_CAPBSET_READ)
+///
+/// 返回当前进程的capability bounding set是否包含`cap`
+pub fn prctl_capbset_read(cap: usize) -> Result<usize, SystemError> {
+    let cap = cap_from_arg(cap)?;
+    let cred = ProcessManager::current_pcb().cred();
+    Ok(cred.cap_bset.contains(cap) as usize)
+}
+
+/// # prctl(PR_CAPBSET_DROP)
+///
+/// 从当前进程的capability bounding set中删除`cap`，需要CAP_SETPCAP。删除之后不能再加回
+pub fn prctl_capbset_drop(cap: usize) -> Result<usize, SystemError> {
+    let cap = cap_from_arg(cap)?;
+    let pcb = ProcessManager::current_pcb();
+    let mut cred = pcb.cred.lock();
+    if !cred.ns_capable(&cred.user_ns, CAPFlags::CAP_SETPCAP) {
+        return Err(SystemError::EPERM);
+    }
+    cred.cap_bset.remove(cap);
+    Ok(0)
+}
+
+/// # prctl(PR_CAP_AMBIENT)
+///
+/// 查询、加入或者删除ambient权限集中的权限。加入的权限必须同时在permitted和inheritable权限集中
+pub fn prctl_cap_ambient(
+    op: usize,
+    cap: usize,
+    arg4: usize,
+    arg5: usize,
+) -> Result<usize, SystemError> {
+    if arg4 != 0 || arg5 != 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let pcb = ProcessManager::current_pcb();
+    let mut cred = pcb.cred.lock();
+    if op == PR_CAP_AMBIENT_CLEAR_ALL {
+        if cap != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        cred.cap_ambient = CAPFlags::CAP_EMPTY_SET;
+        return Ok(0);
+    }
+
+    let cap = cap_from_arg(cap)?;
+    match op {
+        PR_CAP_AMBIENT_IS_SET => Ok(cred.cap_ambient.contains(cap) as usize),
+        PR_CAP_AMBIENT_RAISE => {
+            if !cred.cap_permitted.contains(cap) || !cred.cap_inheritable.contains(cap) {
+                return Err(SystemError::EPERM);
+            }
+            cred.cap_ambient.insert(cap);
+            Ok(0)
+        }
+        PR_CAP_AMBIENT_LOWER => {
+            cred.cap_ambient.remove(cap);
+            Ok(0)
+        }
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+impl Syscall {
+    /// # 获取进程的权限集
+    ///
+    /// ## 参数
+    /// - `header`: 版本号与目标进程，pid为0时表示当前进程
+    /// - `data`: 用于返回权限集，为空时只检查版本号
+    ///
+    /// ## 返回值
+    /// - `Ok(0)`: 成功
+    /// - `Err(SystemError::EINVAL)`: 不支持的版本号，内核支持的版本号被写回`header`
+    /// - `Err(SystemError::ESRCH)`: 目标进程不存在
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/capget.2.html
+    pub fn capget(
+        header: *mut CapUserHeader,
+        data: *mut CapUserData,
+    ) -> Result<usize, SystemError> {
+        let tocopy = match validate_version(header) {
+            Ok(tocopy) => tocopy,
+            // 用户可以传入空的data来查询内核支持的版本号
+            Err(SystemError::EINVAL) if data.is_null() => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        if data.is_null() {
+            return Ok(0);
+        }
+
+        let reader = UserBufferReader::new(header, size_of::<CapUserHeader>(), true)?;
+        let pid = reader.read_one_from_user::<CapUserHeader>(0)?.pid;
+        if pid < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = if pid == 0 {
+            ProcessManager::current_pcb()
+        } else {
+            ProcessManager::find(Pid::new(pid as usize)).ok_or(SystemError::ESRCH)?
+        };
+        let cred = pcb.cred();
+
+        let mut kdata = [CapUserData::default(); 2];
+        for (i, d) in kdata.iter_mut().enumerate() {
+            let shift = 32 * i;
+            d.effective = (cred.cap_effective.bits() >> shift) as u32;
+            d.permitted = (cred.cap_permitted.bits() >> shift) as u32;
+            d.inheritable = (cred.cap_inheritable.bits() >> shift) as u32;
+        }
+        let mut writer = UserBufferWriter::new(data, tocopy * size_of::<CapUserData>(), true)?;
+        writer.copy_to_user(&kdata[..tocopy], 0)?;
+        Ok(0)
+    }
+
+    /// # 设置当前进程的权限集
+    ///
+    /// 新的权限集需要满足：
+    /// - permitted权限集不能增加新的权限
+    /// - effective权限集是新的permitted权限集的子集
+    /// - inheritable权限集是原来的inheritable与bounding set的并集的子集，
+    ///   没有CAP_SETPCAP时还必须是原来的inheritable与permitted权限集的并集的子集
+    ///
+    /// ## 参数
+    /// - `header`: 版本号与目标进程，只能设置当前进程
+    /// - `data`: 新的权限集
+    ///
+    /// ## 返回值
+    /// - `Ok(0)`: 成功
+    /// - `Err(SystemError::EPERM)`: 目标不是当前进程，或者新的权限集不满足上述条件
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/capset.2.html
+    pub fn capset(
+        header: *mut CapUserHeader,
+        data: *const CapUserData,
+    ) -> Result<usize, SystemError> {
+        let tocopy = validate_version(header)?;
+        let reader = UserBufferReader::new(header, size_of::<CapUserHeader>(), true)?;
+        let pid = reader.read_one_from_user::<CapUserHeader>(0)?.pid;
+        let pcb = ProcessManager::current_pcb();
+        if pid != 0 && pid as usize != pcb.pid().data() {
+            return Err(SystemError::EPERM);
+        }
+
+        let reader = UserBufferReader::new(data, tocopy * size_of::<CapUserData>(), true)?;
+        let kdata = reader.read_from_user::<CapUserData>(0)?;
+        let effective = merge_caps(kdata, |d| d.effective);
+        let permitted = merge_caps(kdata, |d| d.permitted);
+        let inheritable = merge_caps(kdata, |d| d.inheritable);
+
+        let mut cred = pcb.cred.lock();
+        if !(cred.cap_inheritable | cred.cap_permitted).contains(inheritable)
+            && !cred.ns_capable(&cred.user_ns, CAPFlags::CAP_SETPCAP)
+        {
+            return Err(SystemError::EPERM);
+        }
+        if !(cred.cap_inheritable | cred.cap_bset).contains(inheritable)
+            || !cred.cap_permitted.contains(permitted)
+            || !permitted.contains(effective)
+        {
+            return Err(SystemError::EPERM);
+        }
+
+        cred.cap_effective = effective;
+        cred.cap_permitted = permitted;
+        cred.cap_inheritable = inheritable;
+        // ambient权限集必须是permitted与inheritable权限集的子集
+        cred.cap_ambient &= permitted & inheritable;
+        Ok(0)
+    }
+}
@@ -1,10 +1,15 @@
 use core::sync::atomic::AtomicUsize;
 
-use alloc::vec::Vec;
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::namespaces::{namespace::USER_NS, user_namespace::UserNamespace};
 
 const GLOBAL_ROOT_UID: Kuid = Kuid(0);
 const GLOBAL_ROOT_GID: Kgid = Kgid(0);
-pub static INIT_CRED: Cred = Cred::init();
+
+lazy_static! {
+    pub static ref INIT_CRED: Cred = Cred::init();
+}
 
 int_like!(Kuid, AtomicKuid, usize, AtomicUsize);
 int_like!(Kgid, AtomicKgid, usize, AtomicUsize);
@@ -12,7 +17,114 @@ int_like!(Kgid, AtomicKgid, usize, AtomicUsize);
 bitflags! {
     pub struct CAPFlags:u64{
         const CAP_EMPTY_SET = 0;
-        const CAP_FULL_SET = (1 << 41) - 1;
+        /// 任意修改文件的uid和gid
+        const CAP_CHOWN = 1 << 0;
+        /// 绕过文件的读、写、执行权限检查
+        const CAP_DAC_OVERRIDE = 1 << 1;
+        /// 绕过文件的读权限检查，以及目录的读和执行权限检查
+        const CAP_DAC_READ_SEARCH = 1 << 2;
+        /// 绕过要求进程fsuid与文件uid相同的权限检查
+        const CAP_FOWNER = 1 << 3;
+        /// 修改文件时不清除set-user-ID和set-group-ID位
+        const CAP_FSETID = 1 << 4;
+        /// 向任意进程发送信号
+        const CAP_KILL = 1 << 5;
+        /// 任意设置进程的gid，以及在SCM_CREDENTIALS中伪造gid
+        const CAP_SETGID = 1 << 6;
+        /// 任意设置进程的uid，以及在SCM_CREDENTIALS中伪造uid
+        const CAP_SETUID = 1 << 7;
+        /// 修改进程的权限集，以及从capability bounding set中删除权限
+        const CAP_SETPCAP = 1 << 8;
+        /// 设置文件的FS_APPEND_FL和FS_IMMUTABLE_FL标志
+        const CAP_LINUX_IMMUTABLE = 1 << 9;
+        /// 绑定小于1024的端口
+        const CAP_NET_BIND_SERVICE = 1 << 10;
+        /// 发送广播和监听多播
+        const CAP_NET_BROADCAST = 1 << 11;
+        /// 配置网络接口、路由表等
+        const CAP_NET_ADMIN = 1 << 12;
+        /// 使用原始套接字与packet套接字
+        const CAP_NET_RAW = 1 << 13;
+        /// 锁定内存
+        const CAP_IPC_LOCK = 1 << 14;
+        /// 绕过System V IPC对象的权限检查
+        const CAP_IPC_OWNER = 1 << 15;
+        /// 加载和卸载内核模块
+        const CAP_SYS_MODULE = 1 << 16;
+        /// 进行I/O端口操作
+        const CAP_SYS_RAWIO = 1 << 17;
+        /// 使用chroot
+        const CAP_SYS_CHROOT = 1 << 18;
+        /// 访问任意进程的内存，如process_vm_readv、process_vm_writev
+        const CAP_SYS_PTRACE = 1 << 19;
+        /// 使用acct
+        const CAP_SYS_PACCT = 1 << 20;
+        /// 系统管理操作，包括挂载文件系统、在SCM_CREDENTIALS中伪造pid
+        const CAP_SYS_ADMIN = 1 << 21;
+        /// 重启系统
+        const CAP_SYS_BOOT = 1 << 22;
+        /// 降低进程的nice值，以及修改其它用户的进程的nice值
+        const CAP_SYS_NICE = 1 << 23;
+        /// 超过资源限制，以及设置进程地址空间的边界
+        const CAP_SYS_RESOURCE = 1 << 24;
+        /// 设置系统时钟
+        const CAP_SYS_TIME = 1 << 25;
+        /// 配置终端
+        const CAP_SYS_TTY_CONFIG = 1 << 26;
+        /// 使用mknod创建设备文件
+        const CAP_MKNOD = 1 << 27;
+        /// 为任意文件设置租约
+        const CAP_LEASE = 1 << 28;
+        /// 写入审计日志
+        const CAP_AUDIT_WRITE = 1 << 29;
+        /// 配置审计
+        const CAP_AUDIT_CONTROL = 1 << 30;
+        /// 设置文件的权限集
+        const CAP_SETFCAP = 1 << 31;
+        /// 绕过强制访问控制
+        const CAP_MAC_OVERRIDE = 1 << 32;
+        /// 配置强制访问控制
+        const CAP_MAC_ADMIN = 1 << 33;
+        /// 读取和清除内核日志
+        const CAP_SYSLOG = 1 << 34;
+        /// 设置唤醒系统的定时器
+        const CAP_WAKE_ALARM = 1 << 35;
+        /// 阻止系统挂起
+        const CAP_BLOCK_SUSPEND = 1 << 36;
+        /// 读取审计日志
+        const CAP_AUDIT_READ = 1 << 37;
+        /// 使用性能监控
+        const CAP_PERFMON = 1 << 38;
+        /// 使用bpf系统调用加载程序、创建映射
+        const CAP_BPF = 1 << 39;
+        /// 检查点与恢复相关的操作
+        const CAP_CHECKPOINT_RESTORE = 1 << 40;
+        const CAP_FULL_SET = (1 << (CAP_LAST_CAP + 1)) - 1;
+    }
+}
+
+/// 编号最大的权限
+pub const CAP_LAST_CAP: u32 = 40;
+
+/// fsuid在0与非0之间变化时，随之获得或者失去的权限
+const CAP_FS_MASK: CAPFlags = CAPFlags::from_bits_truncate(
+    CAPFlags::CAP_CHOWN.bits()
+        | CAPFlags::CAP_MKNOD.bits()
+        | CAPFlags::CAP_DAC_OVERRIDE.bits()
+        | CAPFlags::CAP_DAC_READ_SEARCH.bits()
+        | CAPFlags::CAP_FOWNER.bits()
+        | CAPFlags::CAP_FSETID.bits()
+        | CAPFlags::CAP_LINUX_IMMUTABLE.bits()
+        | CAPFlags::CAP_MAC_OVERRIDE.bits(),
+);
+
+impl CAPFlags {
+    /// 根据编号得到权限，编号超出范围时返回None
+    pub fn from_cap(cap: usize) -> Option<Self> {
+        if cap > CAP_LAST_CAP as usize {
+            return None;
+        }
+        Some(Self::from_bits_truncate(1 << cap))
     }
 }
 
@@ -51,12 +163,16 @@ pub struct Cred {
     pub cap_bset: CAPFlags,
     /// Ambient capability set
     pub cap_ambient: CAPFlags,
+    /// 为true时，uid从0变为非0不会清除permitted权限集（PR_SET_KEEPCAPS）
+    pub keep_caps: bool,
     /// supplementary groups for euid/fsgid
     pub group_info: Option<GroupInfo>,
+    /// 凭证所在的user namespace，uid、gid与权限集都是相对于它的
+    pub user_ns: Arc<UserNamespace>,
 }
 
 impl Cred {
-    pub const fn init() -> Self {
+    pub fn init() -> Self {
         Self {
             uid: GLOBAL_ROOT_UID,
             gid: GLOBAL_ROOT_GID,
@@ -70,11 +186,127 @@ impl Cred {
             cap_permitted: CAPFlags::CAP_FULL_SET,
             cap_effective: CAPFlags::CAP_FULL_SET,
             cap_bset: CAPFlags::CAP_FULL_SET,
-            cap_ambient: CAPFlags::CAP_FULL_SET,
+            cap_ambient: CAPFlags::CAP_EMPTY_SET,
+            keep_caps: false,
             group_info: None,
+            user_ns: USER_NS.clone(),
         }
     }
 
+    /// 判断凭证在初始user namespace中是否拥有指定的权限
+    pub fn has_capability(&self, cap: CAPFlags) -> bool {
+        self.ns_capable(&USER_NS, cap)
+    }
+
+    /// # 判断凭证在user namespace `ns`中是否拥有指定的权限
+    ///
+    /// - `ns`就是凭证所在的namespace时，检查有效权限集
+    /// - `ns`是凭证所在namespace的后代时，如果凭证的有效uid创建了`ns`的某个祖先，
+    ///   并且这个祖先的父namespace就是凭证所在的namespace，则拥有全部权限
+    /// - 否则没有权限
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/security/commoncap.c#68
+    pub fn ns_capable(&self, ns: &Arc<UserNamespace>, cap: CAPFlags) -> bool {
+        let mut ns = ns;
+        loop {
+            if Arc::ptr_eq(ns, &self.user_ns) {
+                return self.cap_effective.contains(cap);
+            }
+            if ns.level() <= self.user_ns.level() {
+                return false;
+            }
+            let parent = ns.parent().unwrap();
+            if Arc::ptr_eq(parent, &self.user_ns) && ns.owner() == self.euid {
+                return true;
+            }
+            ns = parent;
+        }
+    }
+
+    /// 判断凭证是否可以对uid、gid为`uid`、`gid`的文件使用指定的权限：
+    /// 在自己的user namespace中拥有该权限，并且文件的uid、gid在这个namespace中有映射
+    pub fn capable_wrt_inode_uidgid(&self, uid: usize, gid: usize, cap: CAPFlags) -> bool {
+        self.ns_capable(&self.user_ns, cap)
+            && self.user_ns.from_kuid(Kuid::new(uid)).is_some()
+            && self.user_ns.from_kgid(Kgid::new(gid)).is_some()
+    }
+
+    /// # 进入新的user namespace
+    ///
+    /// 在新的namespace中拥有全部权限，inheritable与ambient权限集被清空
+    pub fn enter_user_ns(&mut self, ns: Arc<UserNamespace>) {
+        self.user_ns = ns;
+        self.cap_inheritable = CAPFlags::CAP_EMPTY_SET;
+        self.cap_permitted = CAPFlags::CAP_FULL_SET;
+        self.cap_effective = CAPFlags::CAP_FULL_SET;
+        self.cap_bset = CAPFlags::CAP_FULL_SET;
+        self.cap_ambient = CAPFlags::CAP_EMPTY_SET;
+        self.keep_caps = false;
+    }
+
+    /// # uid改变之后调整权限集
+    ///
+    /// 与Linux相同（uid 0指凭证所在user namespace中的uid 0）：
+    /// - 实际、有效、保存的uid原来有一个为0，改变之后都不为0时，清除permitted、effective权限集
+    ///   （设置了keep_caps时保留permitted权限集），并清除ambient权限集
+    /// - 有效uid从0变为非0时，清除effective权限集；从非0变为0时，effective权限集恢复为permitted权限集
+    /// - fsuid从0变为非0时，从effective权限集中去掉文件系统相关的权限；从非0变为0时恢复这些权限
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/security/commoncap.c#1052
+    pub fn fixup_setuid(&mut self, old: &Cred) {
+        // user namespace中的root，没有映射时没有任何uid被当作root
+        let root_uid = self.user_ns.make_kuid(0);
+        let root = |uid: Kuid| Some(uid) == root_uid;
+        let was_root = root(old.uid) || root(old.euid) || root(old.suid);
+        let is_root = root(self.uid) || root(self.euid) || root(self.suid);
+        if was_root && !is_root {
+            if !self.keep_caps {
+                self.cap_permitted = CAPFlags::CAP_EMPTY_SET;
+                self.cap_effective = CAPFlags::CAP_EMPTY_SET;
+            }
+            self.cap_ambient = CAPFlags::CAP_EMPTY_SET;
+        }
+        if root(old.euid) && !root(self.euid) {
+            self.cap_effective = CAPFlags::CAP_EMPTY_SET;
+        }
+        if !root(old.euid) && root(self.euid) {
+            self.cap_effective = self.cap_permitted;
+        }
+
+        if root(old.fsuid) && !root(self.fsuid) {
+            self.cap_effective.remove(CAP_FS_MASK);
+        }
+        if !root(old.fsuid) && root(self.fsuid) {
+            self.cap_effective
+                .insert(self.cap_permitted.intersection(CAP_FS_MASK));
+        }
+    }
+
+    /// # execve时计算新的权限集
+    ///
+    /// 目前不支持文件权限集和set-user-ID程序，相当于Linux中执行没有文件权限集的普通程序：
+    /// - 实际或有效uid为user namespace中的0时，permitted权限集为inheritable权限集与bounding set的并集，
+    ///   有效uid为0时effective权限集与permitted权限集相同
+    /// - 否则permitted、effective权限集都等于ambient权限集
+    ///
+    /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/security/commoncap.c#887
+    pub fn exec_transform(&mut self) {
+        let root = self.user_ns.make_kuid(0);
+        // ambient权限集必须是permitted与inheritable权限集的子集
+        self.cap_ambient &= self.cap_permitted & self.cap_inheritable;
+        if root == Some(self.uid) || root == Some(self.euid) {
+            self.cap_permitted = (self.cap_inheritable | self.cap_bset) | self.cap_ambient;
+        } else {
+            self.cap_permitted = self.cap_ambient;
+        }
+        self.cap_effective = if root == Some(self.euid) {
+            self.cap_permitted
+        } else {
+            self.cap_ambient
+        };
+        self.keep_caps = false;
+    }
+
     #[allow(dead_code)]
     /// Compare two credentials with respect to filesystem access.
     pub fn fscmp(&self, other: Cred) -> CredFsCmp {
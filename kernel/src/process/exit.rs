@@ -5,7 +5,8 @@ use log::warn;
 use system_error::SystemError;
 
 use crate::{
-    arch::ipc::signal::{SigChildCode, Signal},
+    arch::ipc::signal::SigChildCode,
+    filesystem::{pidfd::pidfd_get_pcb, vfs::file::FileMode},
     sched::{schedule, SchedMode},
     syscall::user_access::UserBufferWriter,
     time::{sleep::nanosleep, Duration},
@@ -102,6 +103,93 @@ pub fn kernel_wait4(
     return Ok(r);
 }
 
+/// waitid的idtype：等待任意子进程
+pub const P_ALL: i32 = 0;
+/// waitid的idtype：等待指定pid的子进程
+pub const P_PID: i32 = 1;
+/// waitid的idtype：等待指定进程组的子进程
+pub const P_PGID: i32 = 2;
+/// waitid的idtype：等待pidfd指向的子进程
+pub const P_PIDFD: i32 = 3;
+
+/// # waitid的内核实现
+///
+/// ## 参数
+/// - `which`: P_ALL、P_PID、P_PGID 或者 P_PIDFD
+/// - `upid`: 进程号或者 pidfd，含义由`which`决定
+/// - `options`: 至少包含 WEXITED、WSTOPPED、WCONTINUED 之一
+///
+/// ## 返回值
+/// - `Ok(Some(info))`: 状态发生变化的子进程的信息
+/// - `Ok(None)`: 设置了 WNOHANG，并且没有子进程改变状态
+/// - `Err(SystemError::EAGAIN_OR_EWOULDBLOCK)`: pidfd 是非阻塞的，并且子进程没有改变状态
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/exit.c#1654
+pub fn kernel_waitid(
+    which: i32,
+    upid: i32,
+    mut options: WaitOption,
+    rusage_buf: Option<&mut RUsage>,
+) -> Result<Option<WaitIdInfo>, SystemError> {
+    let valid = WaitOption::WNOHANG
+        | WaitOption::WEXITED
+        | WaitOption::WSTOPPED
+        | WaitOption::WCONTINUED
+        | WaitOption::WNOWAIT
+        | WaitOption::WNOTHREAD
+        | WaitOption::WALL
+        | WaitOption::WCLONE;
+    if !valid.contains(options)
+        || !options.intersects(WaitOption::WEXITED | WaitOption::WSTOPPED | WaitOption::WCONTINUED)
+    {
+        return Err(SystemError::EINVAL);
+    }
+
+    let mut nonblock = false;
+    let (pid_type, pid) = match which {
+        P_ALL => (PidType::MAX, Pid(0)),
+        P_PID => {
+            if upid <= 0 {
+                return Err(SystemError::EINVAL);
+            }
+            (PidType::PID, Pid(upid as usize))
+        }
+        P_PIDFD => {
+            if upid < 0 {
+                return Err(SystemError::EINVAL);
+            }
+            let (pcb, mode) = pidfd_get_pcb(upid)?;
+            if mode.contains(FileMode::O_NONBLOCK) {
+                nonblock = true;
+                options.insert(WaitOption::WNOHANG);
+            }
+            (PidType::PID, pcb.pid())
+        }
+        P_PGID => {
+            // todo: 对于pgid的处理
+            warn!("kernel_waitid: currently not support P_PGID");
+            return Err(SystemError::EINVAL);
+        }
+        _ => return Err(SystemError::EINVAL),
+    };
+
+    let mut kwo = KernelWaitOption::new(pid_type, pid, options);
+    kwo.ret_info = Some(WaitIdInfo {
+        pid: Pid(0),
+        status: 0,
+        cause: 0,
+    });
+    kwo.ret_rusage = rusage_buf;
+
+    do_wait(&mut kwo)?;
+
+    let info = kwo.ret_info.filter(|info| info.pid != Pid(0));
+    if nonblock && info.is_none() {
+        return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
+    }
+    return Ok(info);
+}
+
 /// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/exit.c#1573
 fn do_wait(kwo: &mut KernelWaitOption) -> Result<usize, SystemError> {
     let mut retval: Result<usize, SystemError>;
@@ -161,21 +249,37 @@ fn do_wait(kwo: &mut KernelWaitOption) -> Result<usize, SystemError> {
                     break;
                 }
                 for pid in rd_childen.iter() {
-                    let pcb = ProcessManager::find(*pid).ok_or(SystemError::ECHILD)?;
+                    // 已经被回收、但pcb仍被pidfd等引用的子进程还在children中，跳过它
+                    let Some(pcb) = ProcessManager::find(*pid) else {
+                        continue;
+                    };
                     let sched_guard = pcb.sched_info().inner_lock_read_irqsave();
                     let state = sched_guard.state();
                     if state.is_exited() {
                         kwo.ret_status = state.exit_code().unwrap() as i32;
                         kwo.no_task_error = None;
+                        if let Some(infop) = &mut kwo.ret_info {
+                            *infop = WaitIdInfo {
+                                pid: *pid,
+                                status: kwo.ret_status,
+                                cause: SigChildCode::Exited.into(),
+                            };
+                        }
                         // 由于pcb的drop方法里面要获取父进程的children字段的写锁，所以这里不能直接drop pcb，
                         // 而是要先break到外层循环，以便释放父进程的children字段的锁,才能drop pcb。
                         // 否则会死锁。
                         tmp_child_pcb = Some(pcb.clone());
-                        unsafe { ProcessManager::release(*pid) };
+                        if !kwo.options.contains(WaitOption::WNOWAIT) {
+                            unsafe { ProcessManager::release(*pid) };
+                        }
                         retval = Ok((*pid).into());
                         break 'outer;
                     }
                 }
+                if kwo.options.contains(WaitOption::WNOHANG) {
+                    retval = Ok(0);
+                    break 'outer;
+                }
                 nanosleep(Duration::from_millis(100).into())?;
             }
         } else {
@@ -210,24 +314,19 @@ fn do_waitpid(
     let state = child_pcb.sched_info().inner_lock_read_irqsave().state();
     // 获取退出码
     match state {
-        ProcessState::Runnable => {
-            if kwo.options.contains(WaitOption::WNOHANG)
-                || kwo.options.contains(WaitOption::WNOWAIT)
-            {
-                if let Some(info) = &mut kwo.ret_info {
-                    *info = WaitIdInfo {
-                        pid: child_pcb.pid(),
-                        status: Signal::SIGCONT as i32,
-                        cause: SigChildCode::Continued.into(),
-                    };
-                } else {
+        // 子进程在睡眠时并没有发生状态变化，与运行时一样继续等待
+        ProcessState::Runnable | ProcessState::Blocked(_) => {
+            // WNOWAIT只表示不回收子进程，仍需阻塞等待其状态改变
+            if kwo.options.contains(WaitOption::WNOHANG) {
+                // waitid通过ret_info中的pid为0表示没有子进程改变状态
+                if kwo.ret_info.is_none() {
                     kwo.ret_status = 0xffff;
                 }
 
                 return Some(Ok(0));
             }
         }
-        ProcessState::Blocked(_) | ProcessState::Stopped => {
+        ProcessState::Stopped => {
             // todo: 在stopped里面，添加code字段，表示停止的原因
             let exitcode = 0;
             // 由于目前不支持ptrace，因此这个值为false
@@ -272,8 +371,11 @@ fn do_waitpid(
             kwo.ret_status = status as i32;
 
             drop(child_pcb);
-            // debug!("wait4: to release {pid:?}");
-            unsafe { ProcessManager::release(pid) };
+            // WNOWAIT：保留子进程，之后还能再次等待它
+            if !kwo.options.contains(WaitOption::WNOWAIT) {
+                // debug!("wait4: to release {pid:?}");
+                unsafe { ProcessManager::release(pid) };
+            }
             return Some(Ok(pid.into()));
         }
     };
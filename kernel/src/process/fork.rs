@@ -7,11 +7,12 @@ use system_error::SystemError;
 
 use crate::{
     arch::{interrupt::TrapFrame, ipc::signal::Signal},
+    cgroup::cgroup_post_fork,
     filesystem::procfs::procfs_register_pid,
     ipc::signal::flush_signal_handlers,
     libs::rwlock::RwLock,
     mm::VirtAddr,
-    namespaces::{create_new_namespaces, namespace::USER_NS, pid_namespace::PidStrcut},
+    namespaces::{create_new_namespaces, pid_namespace::PidStrcut, user_namespace::create_user_ns},
     process::ProcessFlags,
     sched::{sched_cgroup_fork, sched_fork},
     smp::core::smp_get_processor_id,
@@ -281,11 +282,24 @@ impl ProcessManager {
             return Err(SystemError::EINVAL);
         }
 
-        let new_nsproxy = create_new_namespaces(clone_flags.bits(), current_pcb, USER_NS.clone())?;
+        let user_ns = new_pcb.cred().user_ns;
+        let new_nsproxy = create_new_namespaces(clone_flags.bits(), current_pcb, user_ns)?;
         *new_pcb.nsproxy.write() = new_nsproxy;
         Ok(())
     }
 
+    /// 子进程使用新的user namespace时，让它的凭证进入新的namespace
+    #[inline(never)]
+    fn copy_creds(
+        clone_flags: &CloneFlags,
+        new_pcb: &Arc<ProcessControlBlock>,
+    ) -> Result<(), SystemError> {
+        if clone_flags.contains(CloneFlags::CLONE_NEWUSER) {
+            create_user_ns(&mut new_pcb.cred.lock())?;
+        }
+        Ok(())
+    }
+
     #[inline(never)]
     fn copy_files(
         clone_flags: &CloneFlags,
@@ -386,7 +400,7 @@ impl ProcessManager {
         }
 
         if clone_flags.contains(CloneFlags::CLONE_PIDFD)
-            && clone_flags.contains(CloneFlags::CLONE_DETACHED | CloneFlags::CLONE_THREAD)
+            && clone_flags.intersects(CloneFlags::CLONE_DETACHED | CloneFlags::CLONE_THREAD)
         {
             return Err(SystemError::EINVAL);
         }
@@ -425,6 +439,9 @@ impl ProcessManager {
             writer.copy_one_to_user(&(pcb.pid().0 as i32), 0)?;
         }
 
+        // 拷贝凭证，必须在创建其他namespace之前完成
+        Self::copy_creds(&clone_flags, pcb)?;
+
         sched_fork(pcb).unwrap_or_else(|e| {
             panic!(
                 "fork: Failed to set sched info from current process, current pid: [{:?}], new pid: [{:?}]. Error: {:?}",
@@ -529,6 +546,7 @@ impl ProcessManager {
 
         // todo: 增加线程组相关的逻辑。 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/kernel/fork.c#2437
 
+        cgroup_post_fork(pcb);
         sched_cgroup_fork(pcb);
 
         Ok(())
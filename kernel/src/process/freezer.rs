@@ -0,0 +1,161 @@
+//! 进程冻结
+//!
+//! 被冻结的进程停在返回用户态之前的安全点上，不会持有内核中的锁，也不会在系统调用的中途停下。
+//! 冻结一个进程时，标记它需要冻结，并像发送信号一样唤醒可中断的睡眠、让正在运行的进程陷入内核；
+//! 被打断的系统调用在解冻之后重新执行。与SIGSTOP不同，冻结对进程不可见，父进程也收不到通知。
+//!
+//! 进程需要冻结的条件有两个：系统正在挂起（[`freeze_processes`]），或者进程所在的cgroup
+//! 及其祖先之一设置了`cgroup.freeze`（见[`crate::cgroup::freezer`]）。
+//! 内核线程和带有[`ProcessFlags::NOFREEZE`]的进程不会被冻结，收到SIGKILL的进程也不会停留在冻结状态。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/freezer.c
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{sync::Arc, vec::Vec};
+use log::warn;
+use system_error::SystemError;
+
+use crate::{
+    arch::ipc::signal::Signal,
+    cgroup::freezer::cgroup_freezing,
+    libs::wait_queue::WaitQueue,
+    sched::SchedMode,
+    time::{sleep::nanosleep, PosixTimeSpec},
+};
+
+use super::{ProcessControlBlock, ProcessFlags, ProcessManager};
+
+/// 系统是否正在冻结所有用户进程
+static SYSTEM_FREEZING: AtomicBool = AtomicBool::new(false);
+
+/// 被冻结的进程在这个等待队列上睡眠
+static FREEZER_WAIT: WaitQueue = WaitQueue::default();
+
+/// 等待所有进程被冻结的最长时间（ms）
+const FREEZE_TIMEOUT_MS: i64 = 20_000;
+/// 等待进程被冻结时，每次睡眠的时间（ms）
+const FREEZE_POLL_MS: i64 = 10;
+
+/// # 进程当前是否应该被冻结
+pub fn freezing(pcb: &Arc<ProcessControlBlock>) -> bool {
+    let flags = *pcb.flags();
+    if flags.intersects(ProcessFlags::KTHREAD | ProcessFlags::NOFREEZE | ProcessFlags::EXITING) {
+        return false;
+    }
+    if !SYSTEM_FREEZING.load(Ordering::SeqCst) && !cgroup_freezing(&pcb.task_cgroup().cgroup()) {
+        return false;
+    }
+    // 被杀死的进程需要尽快退出，释放资源
+    return !Signal::fatal_signal_pending(pcb);
+}
+
+/// # 进程是否已经停在冻结点上
+///
+/// 被SIGSTOP停止的进程不会再运行，同样视为已经冻结
+pub fn frozen(pcb: &Arc<ProcessControlBlock>) -> bool {
+    if pcb.flags().contains(ProcessFlags::FROZEN) {
+        return true;
+    }
+    pcb.sched_info()
+        .inner_lock_read_irqsave()
+        .state()
+        .is_stopped()
+}
+
+/// # 让进程尽快进入冻结状态
+///
+/// 进程在下一次返回用户态之前停下。
+///
+/// ## 返回值
+///
+/// 进程不需要冻结时返回false
+pub fn freeze_task(pcb: &Arc<ProcessControlBlock>) -> bool {
+    if !freezing(pcb) {
+        return false;
+    }
+    if pcb.flags().contains(ProcessFlags::FROZEN) {
+        return true;
+    }
+    pcb.flags().insert(ProcessFlags::FREEZE_PENDING);
+
+    // 像信号一样打断可中断的睡眠，被打断的系统调用在解冻之后重新执行
+    let state = pcb.sched_info().inner_lock_read_irqsave().state();
+    pcb.flags().insert(ProcessFlags::HAS_PENDING_SIGNAL);
+    if state.is_blocked_interruptable() {
+        let _ = ProcessManager::wakeup(pcb);
+    }
+    ProcessManager::kick(pcb);
+    return true;
+}
+
+/// # 唤醒被冻结的进程
+///
+/// 不再需要冻结的进程离开冻结状态，仍然需要冻结的进程继续睡眠
+pub fn thaw() {
+    FREEZER_WAIT.wakeup_all(None);
+}
+
+/// # 返回用户态之前，如果当前进程需要冻结，就停在这里直到解冻
+pub fn refrigerator() {
+    let pcb = ProcessManager::current_pcb();
+    pcb.flags().remove(ProcessFlags::FREEZE_PENDING);
+    // 去掉冻结时为了打断睡眠而设置的标志，保留真正的信号
+    pcb.recalc_sigpending(None);
+
+    if !freezing(&pcb) {
+        return;
+    }
+    pcb.flags().insert(ProcessFlags::FROZEN);
+    let r = wq_wait_event_interruptible!(FREEZER_WAIT, !freezing(&pcb), {});
+    pcb.flags().remove(ProcessFlags::FROZEN);
+
+    if r.is_err() && freezing(&pcb) {
+        // 被信号打断：先处理信号，再重新进入冻结状态
+        pcb.flags().insert(ProcessFlags::FREEZE_PENDING);
+    }
+}
+
+/// # 冻结所有用户进程
+///
+/// 供挂起到内存使用。等待所有用户进程停在冻结点上，超时则解冻所有进程并返回EBUSY
+#[allow(dead_code)]
+pub fn freeze_processes() -> Result<(), SystemError> {
+    SYSTEM_FREEZING.store(true, Ordering::SeqCst);
+
+    // 发起挂起的进程自己不冻结
+    let current = ProcessManager::current_pcb();
+    let mut waited = 0;
+    loop {
+        let busy = ProcessManager::all_processes()
+            .into_iter()
+            .filter(|pcb| !Arc::ptr_eq(pcb, &current))
+            .filter(|pcb| freeze_task(pcb) && !frozen(pcb))
+            .collect::<Vec<_>>();
+        if busy.is_empty() {
+            return Ok(());
+        }
+        if waited >= FREEZE_TIMEOUT_MS {
+            for pcb in busy {
+                warn!(
+                    "Freezing of tasks failed: {} ({}) refused to freeze",
+                    pcb.pid().data(),
+                    pcb.basic().name()
+                );
+            }
+            thaw_processes();
+            return Err(SystemError::EBUSY);
+        }
+        let _ = nanosleep(PosixTimeSpec::new(0, FREEZE_POLL_MS * 1_000_000));
+        waited += FREEZE_POLL_MS;
+    }
+}
+
+/// # 解冻[`freeze_processes`]冻结的进程
+///
+/// 所在cgroup仍然被冻结的进程继续保持冻结
+#[allow(dead_code)]
+pub fn thaw_processes() {
+    SYSTEM_FREEZING.store(false, Ordering::SeqCst);
+    thaw();
+}
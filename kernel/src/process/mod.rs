@@ -4,7 +4,9 @@ use core::{
     hint::spin_loop,
     intrinsics::{likely, unlikely},
     mem::ManuallyDrop,
-    sync::atomic::{compiler_fence, fence, AtomicBool, AtomicUsize, Ordering},
+    sync::atomic::{
+        compiler_fence, fence, AtomicBool, AtomicIsize, AtomicU16, AtomicUsize, Ordering,
+    },
 };
 
 use alloc::{
@@ -20,14 +22,16 @@ use system_error::SystemError;
 
 use crate::{
     arch::{
-        cpu::current_cpu_id,
         ipc::signal::{AtomicSignal, SigSet, Signal},
         process::ArchPCBInfo,
-        CurrentIrqArch,
+        CurrentIrqArch, MMArch,
     },
-    driver::tty::tty_core::TtyCore,
+    cgroup::{cgroup_exit, cgroup_root, TaskCgroup},
+    debug::latency_tracer::{trace_preempt_off, trace_preempt_on},
+    driver::{base::block::ioprio::IoPrio, tty::tty_core::TtyCore},
     exception::InterruptArch,
     filesystem::{
+        pidfd::pidfd_notify_exit,
         procfs::procfs_unregister_pid,
         vfs::{file::FileDescriptorVec, FileType},
     },
@@ -35,6 +39,7 @@ use crate::{
     libs::{
         align::AlignedBox,
         casting::DowncastArc,
+        cpumask::CpuMask,
         futex::{
             constant::{FutexFlag, FUTEX_BITSET_MATCH_ANY},
             futex::{Futex, RobustListHead},
@@ -45,16 +50,20 @@ use crate::{
         wait_queue::WaitQueue,
     },
     mm::{
+        kernel_mapper::KernelMapper,
+        mmio_buddy::{mmio_pool, MMIOSpaceGuard},
+        page::EntryFlags,
         percpu::{PerCpu, PerCpuVar},
         set_IDLE_PROCESS_ADDRESS_SPACE,
         ucontext::AddressSpace,
-        VirtAddr,
+        MemoryManagementArch, VirtAddr,
     },
     namespaces::{mnt_namespace::FsStruct, pid_namespace::PidStrcut, NsProxy},
     net::socket::SocketInode,
     sched::{
-        completion::Completion, cpu_rq, fair::FairSchedEntity, prio::MAX_PRIO, DequeueFlag,
-        EnqueueFlag, OnRq, SchedMode, WakeupFlags, __schedule,
+        completion::Completion, cpu_rq, fair::FairSchedEntity, preempt::preempt_check_resched,
+        prio::DEFAULT_PRIO, select_wakeup_cpu, DequeueFlag, EnqueueFlag, OnRq, SchedMode,
+        WakeupFlags, __schedule,
     },
     smp::{
         core::smp_get_processor_id,
@@ -63,20 +72,33 @@ use crate::{
     },
     syscall::{user_access::clear_user, Syscall},
 };
+
+#[cfg(feature = "debug_atomic_sleep")]
+use crate::sched::preempt::record_preempt_off;
 use timer::AlarmTimer;
 
-use self::{cred::Cred, kthread::WorkerPrivate};
+use self::{
+    cred::Cred,
+    kthread::WorkerPrivate,
+    seccomp::{Seccomp, SeccompMode},
+};
 
 pub mod abi;
+pub mod binfmt_misc;
 pub mod c_adapter;
+pub mod capability;
 pub mod cred;
 pub mod exec;
 pub mod exit;
 pub mod fork;
+pub mod freezer;
 pub mod idle;
 pub mod kthread;
 pub mod pid;
+pub mod prctl;
 pub mod resource;
+pub mod seccomp;
+pub mod stack_overflow;
 pub mod stdio;
 pub mod syscall;
 pub mod timer;
@@ -179,6 +201,7 @@ impl ProcessManager {
 
     /// 增加当前进程的锁持有计数
     #[inline(always)]
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn preempt_disable() {
         if likely(unsafe { __PROCESS_MANAGEMENT_INIT_DONE }) {
             ProcessManager::current_pcb().preempt_disable();
@@ -186,8 +209,21 @@ impl ProcessManager {
     }
 
     /// 减少当前进程的锁持有计数
+    ///
+    /// 启用了内核抢占时，计数减为0后如果当前进程需要被调度，会立即进行调度
     #[inline(always)]
     pub fn preempt_enable() {
+        if likely(unsafe { __PROCESS_MANAGEMENT_INIT_DONE }) {
+            ProcessManager::current_pcb().preempt_enable();
+            preempt_check_resched();
+        }
+    }
+
+    /// 减少当前进程的锁持有计数，但是不检查是否需要调度
+    ///
+    /// 用于随后马上会开中断或者调度的场景，由调用者负责在合适的时候检查
+    #[inline(always)]
+    pub fn preempt_enable_no_resched() {
         if likely(unsafe { __PROCESS_MANAGEMENT_INIT_DONE }) {
             ProcessManager::current_pcb().preempt_enable();
         }
@@ -223,6 +259,47 @@ impl ProcessManager {
             .insert(pcb.pid(), pcb.clone());
     }
 
+    /// 系统中所有的进程
+    pub fn all_processes() -> Vec<Arc<ProcessControlBlock>> {
+        ALL_PROCESS
+            .lock_irqsave()
+            .as_ref()
+            .map(|all| all.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// # 打印进程的状态，用于调试（如sysrq）
+    ///
+    /// ## 参数
+    ///
+    /// - `filter`: 只打印状态满足条件的进程
+    pub fn show_state_filter(filter: impl Fn(&ProcessState) -> bool) {
+        let mut pcbs = Self::all_processes();
+        pcbs.sort_by_key(|pcb| pcb.pid());
+
+        info!("  PID  TGID  PPID CPU S NAME");
+        for pcb in pcbs {
+            let state = pcb.sched_info().inner_lock_read_irqsave().state();
+            if !filter(&state) {
+                continue;
+            }
+            let cpu = pcb
+                .sched_info()
+                .on_cpu()
+                .map(|cpu| cpu.data().to_string())
+                .unwrap_or_else(|| "-".to_string());
+            info!(
+                "{:>5} {:>5} {:>5} {:>3} {} {}",
+                pcb.pid().data(),
+                pcb.tgid().data(),
+                pcb.basic().ppid().data(),
+                cpu,
+                state.state_char(),
+                pcb.basic().name()
+            );
+        }
+    }
+
     /// 唤醒一个进程
     pub fn wakeup(pcb: &Arc<ProcessControlBlock>) -> Result<(), SystemError> {
         let _guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
@@ -237,8 +314,7 @@ impl ProcessManager {
                 // avoid deadlock
                 drop(writer);
 
-                let rq =
-                    cpu_rq(pcb.sched_info().on_cpu().unwrap_or(current_cpu_id()).data() as usize);
+                let rq = cpu_rq(select_wakeup_cpu(pcb).data() as usize);
 
                 let (rq, _guard) = rq.self_lock();
                 rq.update_rq_clock();
@@ -275,12 +351,7 @@ impl ProcessManager {
                 // avoid deadlock
                 drop(writer);
 
-                let rq = cpu_rq(
-                    pcb.sched_info()
-                        .on_cpu()
-                        .unwrap_or(smp_get_processor_id())
-                        .data() as usize,
-                );
+                let rq = cpu_rq(select_wakeup_cpu(pcb).data() as usize);
 
                 let (rq, _guard) = rq.self_lock();
                 rq.update_rq_clock();
@@ -398,6 +469,7 @@ impl ProcessManager {
                 .set_state(ProcessState::Exited(exit_code));
             pcb.wait_queue.mark_dead();
             pcb.wait_queue.wakeup_all(Some(ProcessState::Blocked(true)));
+            pidfd_notify_exit(pid);
 
             let rq = cpu_rq(smp_get_processor_id().data() as usize);
             let (rq, guard) = rq.self_lock();
@@ -406,6 +478,7 @@ impl ProcessManager {
                 DequeueFlag::DEQUEUE_SLEEP | DequeueFlag::DEQUEUE_NOCLOCK,
             );
             drop(guard);
+            cgroup_exit(&pcb);
 
             // 进行进程退出后的工作
             let thread = pcb.thread.write_irqsave();
@@ -512,7 +585,6 @@ impl ProcessManager {
     /// ## 参数
     ///
     /// - `pcb` : 进程的pcb
-    #[allow(dead_code)]
     pub fn kick(pcb: &Arc<ProcessControlBlock>) {
         ProcessManager::current_pcb().preempt_disable();
         let cpu_id = pcb.sched_info().on_cpu();
@@ -564,6 +636,17 @@ pub enum ProcessState {
 
 #[allow(dead_code)]
 impl ProcessState {
+    /// 与Linux中`ps`显示的相同的单字符状态
+    pub fn state_char(&self) -> char {
+        match self {
+            ProcessState::Runnable => 'R',
+            ProcessState::Blocked(true) => 'S',
+            ProcessState::Blocked(false) => 'D',
+            ProcessState::Stopped => 'T',
+            ProcessState::Exited(_) => 'Z',
+        }
+    }
+
     #[inline(always)]
     pub fn is_runnable(&self) -> bool {
         return matches!(self, ProcessState::Runnable);
@@ -629,12 +712,27 @@ bitflags! {
         const HAS_PENDING_SIGNAL = 1 << 9;
         /// 进程需要恢复之前保存的信号掩码
         const RESTORE_SIG_MASK = 1 << 10;
+        /// 进程所在的cgroup超出了cpu.max的限制，返回用户态之前需要睡眠
+        const CPU_THROTTLED = 1 << 11;
+        /// 进程所在的cgroup超出了memory.max的限制，返回用户态之前需要回收内存
+        const MEMCG_OVER_LIMIT = 1 << 12;
+        /// 进程需要冻结，返回用户态之前停下
+        const FREEZE_PENDING = 1 << 13;
+        /// 进程已经停在冻结点上
+        const FROZEN = 1 << 14;
     }
 }
 
 impl ProcessFlags {
     pub const fn exit_to_user_mode_work(&self) -> Self {
-        Self::from_bits_truncate(self.bits & (Self::HAS_PENDING_SIGNAL.bits))
+        Self::from_bits_truncate(
+            self.bits
+                & (Self::HAS_PENDING_SIGNAL.bits
+                    | Self::CPU_THROTTLED.bits
+                    | Self::MEMCG_OVER_LIMIT.bits
+                    | Self::FREEZE_PENDING.bits
+                    | Self::NEED_MIGRATE.bits),
+        )
     }
 
     /// 测试并清除标志位
@@ -711,6 +809,15 @@ pub struct ProcessControlBlock {
 
     /// 进程作为主体的凭证集
     cred: SpinLock<Cred>,
+
+    /// 进程的seccomp模式与过滤器
+    seccomp: SpinLock<Seccomp>,
+    /// 设置之后，execve不能再获得新的权限，并且不能清除
+    no_new_privs: AtomicBool,
+    /// 进程的IO优先级，子进程继承父进程的IO优先级
+    ioprio: AtomicU16,
+    /// 进程所属的cgroup
+    cgroup: SpinLock<TaskCgroup>,
     self_ref: Weak<ProcessControlBlock>,
 }
 
@@ -750,17 +857,43 @@ impl ProcessControlBlock {
 
     #[inline(never)]
     fn do_create_pcb(name: String, kstack: KernelStack, is_idle: bool) -> Arc<Self> {
-        let (pid, ppid, cwd, cred, tty) = if is_idle {
+        let (pid, ppid, cwd, cred, tty, seccomp, no_new_privs, ioprio) = if is_idle {
             let cred = INIT_CRED.clone();
-            (Pid(0), Pid(0), "/".to_string(), cred, None)
+            (
+                Pid(0),
+                Pid(0),
+                "/".to_string(),
+                cred,
+                None,
+                Seccomp::default(),
+                false,
+                IoPrio::DEFAULT,
+            )
         } else {
             let ppid = ProcessManager::current_pcb().pid();
-            let mut cred = ProcessManager::current_pcb().cred();
-            cred.cap_permitted = cred.cap_ambient;
-            cred.cap_effective = cred.cap_ambient;
+            let cred = ProcessManager::current_pcb().cred();
             let cwd = ProcessManager::current_pcb().basic().cwd();
             let tty = ProcessManager::current_pcb().sig_info_irqsave().tty();
-            (Self::generate_pid(), ppid, cwd, cred, tty)
+            let seccomp = ProcessManager::current_pcb().seccomp.lock_irqsave().clone();
+            let no_new_privs = ProcessManager::current_pcb().no_new_privs();
+            let ioprio = ProcessManager::current_pcb().ioprio();
+            (
+                Self::generate_pid(),
+                ppid,
+                cwd,
+                cred,
+                tty,
+                seccomp,
+                no_new_privs,
+                ioprio,
+            )
+        };
+
+        // 子进程与父进程在同一个cgroup中
+        let cgroup = if is_idle {
+            cgroup_root().clone()
+        } else {
+            ProcessManager::current_pcb().task_cgroup().cgroup()
         };
 
         let basic_info = ProcessBasicInfo::new(Pid(0), ppid, Pid(0), name, cwd, None);
@@ -798,6 +931,10 @@ impl ProcessControlBlock {
             robust_list: RwLock::new(None),
             nsproxy: Arc::new(RwLock::new(NsProxy::new())),
             cred: SpinLock::new(cred),
+            seccomp: SpinLock::new(seccomp),
+            no_new_privs: AtomicBool::new(no_new_privs),
+            ioprio: AtomicU16::new(ioprio.bits()),
+            cgroup: SpinLock::new(TaskCgroup::new(cgroup)),
             self_ref: Weak::new(),
         };
 
@@ -859,13 +996,21 @@ impl ProcessControlBlock {
 
     /// 增加当前进程的锁持有计数
     #[inline(always)]
+    #[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
     pub fn preempt_disable(&self) {
-        self.preempt_count.fetch_add(1, Ordering::SeqCst);
+        if self.preempt_count.fetch_add(1, Ordering::SeqCst) == 0 {
+            trace_preempt_off();
+            #[cfg(feature = "debug_atomic_sleep")]
+            record_preempt_off(core::panic::Location::caller());
+        }
     }
 
     /// 减少当前进程的锁持有计数
     #[inline(always)]
     pub fn preempt_enable(&self) {
+        if self.preempt_count.load(Ordering::SeqCst) == 1 {
+            trace_preempt_on();
+        }
         self.preempt_count.fetch_sub(1, Ordering::SeqCst);
     }
 
@@ -924,6 +1069,10 @@ impl ProcessControlBlock {
         self.kernel_stack.force_get_ref()
     }
 
+    pub unsafe fn syscall_stack_force_ref(&self) -> &KernelStack {
+        self.syscall_stack.force_get_ref()
+    }
+
     #[inline(always)]
     #[allow(dead_code)]
     pub fn kernel_stack_mut(&self) -> RwLockWriteGuard<KernelStack> {
@@ -971,6 +1120,42 @@ impl ProcessControlBlock {
         self.cred.lock().clone()
     }
 
+    /// 替换进程的凭证
+    pub fn set_cred(&self, cred: Cred) {
+        *self.cred.lock() = cred;
+    }
+
+    /// 获取seccomp模式
+    pub fn seccomp_mode(&self) -> SeccompMode {
+        self.seccomp.lock_irqsave().mode()
+    }
+
+    #[inline(always)]
+    pub fn no_new_privs(&self) -> bool {
+        self.no_new_privs.load(Ordering::SeqCst)
+    }
+
+    /// 进程所属的cgroup
+    #[inline(always)]
+    pub fn task_cgroup(&self) -> SpinLockGuard<TaskCgroup> {
+        self.cgroup.lock_irqsave()
+    }
+
+    /// 设置no_new_privs，设置之后不能清除
+    pub fn set_no_new_privs(&self) {
+        self.no_new_privs.store(true, Ordering::SeqCst);
+    }
+
+    /// 进程通过ioprio_set设置的IO优先级，没有设置时为`IoPrio::DEFAULT`
+    #[inline(always)]
+    pub fn ioprio(&self) -> IoPrio {
+        IoPrio::from_bits(self.ioprio.load(Ordering::SeqCst))
+    }
+
+    pub fn set_ioprio(&self, ioprio: IoPrio) {
+        self.ioprio.store(ioprio.bits(), Ordering::SeqCst);
+    }
+
     /// 根据文件描述符序号，获取socket对象的Arc指针
     ///
     /// ## 参数
@@ -1052,10 +1237,10 @@ impl ProcessControlBlock {
         return None;
     }
 
-    /// 判断当前进程是否有未处理的信号
+    /// 判断当前进程是否有未被屏蔽、未处理的信号
     pub fn has_pending_signal(&self) -> bool {
         let sig_info = self.sig_info_irqsave();
-        let has_pending = sig_info.sig_pending().has_pending();
+        let has_pending = !(sig_info.sig_pending().signal() & !*sig_info.sig_blocked()).is_empty();
         drop(sig_info);
         return has_pending;
     }
@@ -1271,16 +1456,20 @@ pub struct ProcessSchedulerInfo {
     // priority: SchedPriority,
     /// 当前进程的虚拟运行时间
     // virtual_runtime: AtomicIsize,
-    /// 由实时调度器管理的时间片
-    // rt_time_slice: AtomicIsize,
+    /// SCHED_RR进程剩余的时间片（tick数）
+    pub rt_time_slice: AtomicIsize,
     pub sched_stat: RwLock<SchedInfo>,
     /// 调度策略
     pub sched_policy: RwLock<crate::sched::SchedPolicy>,
+    /// 由CFS调度时，用户设置的调度策略
+    pub fair_policy: RwLock<crate::sched::FairPolicy>,
     /// cfs调度实体
     pub sched_entity: Arc<FairSchedEntity>,
     pub on_rq: SpinLock<OnRq>,
 
     pub prio_data: RwLock<PrioData>,
+    /// 进程允许运行的cpu，由sched_setaffinity设置，子进程继承父进程的设置
+    cpus_allowed: RwLock<CpuMask>,
 }
 
 #[derive(Debug, Default)]
@@ -1294,6 +1483,10 @@ pub struct SchedInfo {
     pub last_arrival: u64,
     /// 记录任务上次被加入到运行队列中的时间戳
     pub last_queued: u64,
+    /// 记录任务被迁移到其他 CPU 的次数
+    pub nr_migrations: usize,
+    /// 记录任务在运行队列上的最长一次等待时间
+    pub wait_max: u64,
 }
 
 #[derive(Debug)]
@@ -1307,9 +1500,9 @@ pub struct PrioData {
 impl Default for PrioData {
     fn default() -> Self {
         Self {
-            prio: MAX_PRIO - 20,
-            static_prio: MAX_PRIO - 20,
-            normal_prio: MAX_PRIO - 20,
+            prio: DEFAULT_PRIO,
+            static_prio: DEFAULT_PRIO,
+            normal_prio: DEFAULT_PRIO,
         }
     }
 }
@@ -1356,16 +1549,27 @@ impl ProcessSchedulerInfo {
                 sleep: false,
             }),
             // virtual_runtime: AtomicIsize::new(0),
-            // rt_time_slice: AtomicIsize::new(0),
+            rt_time_slice: AtomicIsize::new(crate::sched::rt::RR_TIMESLICE),
             // priority: SchedPriority::new(100).unwrap(),
             sched_stat: RwLock::new(SchedInfo::default()),
             sched_policy: RwLock::new(crate::sched::SchedPolicy::CFS),
+            fair_policy: RwLock::new(crate::sched::FairPolicy::Normal),
             sched_entity: FairSchedEntity::new(),
             on_rq: SpinLock::new(OnRq::None),
             prio_data: RwLock::new(PrioData::default()),
+            cpus_allowed: RwLock::new(CpuMask::new_full()),
         };
     }
 
+    /// 进程允许运行的cpu
+    pub fn cpus_allowed(&self) -> CpuMask {
+        self.cpus_allowed.read_irqsave().clone()
+    }
+
+    pub fn set_cpus_allowed(&self, mask: CpuMask) {
+        *self.cpus_allowed.write_irqsave() = mask;
+    }
+
     pub fn sched_entity(&self) -> Arc<FairSchedEntity> {
         return self.sched_entity.clone();
     }
@@ -1466,11 +1670,26 @@ impl ProcessSchedulerInfo {
     pub fn policy(&self) -> crate::sched::SchedPolicy {
         return *self.sched_policy.read_irqsave();
     }
+
+    pub fn fair_policy(&self) -> crate::sched::FairPolicy {
+        return *self.fair_policy.read_irqsave();
+    }
 }
 
-#[derive(Debug, Clone)]
+type KernelStackMem = AlignedBox<[u8; KernelStack::SIZE], { KernelStack::ALIGN }>;
+
+/// 已经释放、留待复用的带保护区域的内核栈
+///
+/// 目前没有跨CPU的TLB刷新，取消映射后其他CPU上可能还残留着旧的表项，
+/// 因此映射一旦建立就不再取消，内核栈释放时连同映射一起放回这里。
+static GUARDED_STACK_POOL: SpinLock<Vec<(KernelStackMem, MMIOSpaceGuard)>> =
+    SpinLock::new(Vec::new());
+
+#[derive(Debug)]
 pub struct KernelStack {
-    stack: Option<AlignedBox<[u8; KernelStack::SIZE], { KernelStack::ALIGN }>>,
+    stack: Option<KernelStackMem>,
+    /// 带保护区域的映射，为None时直接通过线性映射区访问`stack`
+    guarded: Option<MMIOSpaceGuard>,
     /// 标记该内核栈是否可以被释放
     can_be_freed: bool,
 }
@@ -1478,16 +1697,52 @@ pub struct KernelStack {
 impl KernelStack {
     pub const SIZE: usize = 0x4000;
     pub const ALIGN: usize = 0x4000;
+    /// 栈下方不映射的保护区域的大小
+    ///
+    /// 与栈一样大，这样整段映射按两倍的栈大小对齐，栈本身仍然按`ALIGN`对齐
+    pub const GUARD_SIZE: usize = Self::SIZE;
 
     pub fn new() -> Result<Self, SystemError> {
+        let reused = GUARDED_STACK_POOL.lock_irqsave().pop();
+        if let Some((stack, guarded)) = reused {
+            let r = Self {
+                stack: Some(stack),
+                guarded: Some(guarded),
+                can_be_freed: true,
+            };
+            unsafe { core::ptr::write_bytes(r.start_address().data() as *mut u8, 0, Self::SIZE) };
+            return Ok(r);
+        }
+
+        let stack = KernelStackMem::new_zeroed()?;
+        let guarded = Self::map_with_guard(&stack)?;
         return Ok(Self {
-            stack: Some(
-                AlignedBox::<[u8; KernelStack::SIZE], { KernelStack::ALIGN }>::new_zeroed()?,
-            ),
+            stack: Some(stack),
+            guarded: Some(guarded),
             can_be_freed: true,
         });
     }
 
+    /// 把栈所在的物理页重新映射到一段新的虚拟地址，并在其下方留出不映射的保护区域
+    ///
+    /// 栈溢出时访问保护区域会触发缺页，而不是悄悄地破坏相邻的内存
+    fn map_with_guard(stack: &KernelStackMem) -> Result<MMIOSpaceGuard, SystemError> {
+        let space = mmio_pool().create_mmio(Self::GUARD_SIZE + Self::SIZE)?;
+        let paddr = unsafe { MMArch::virt_2_phys(VirtAddr::new(stack.as_ptr() as usize)) }
+            .ok_or(SystemError::EFAULT)?;
+        let flags = EntryFlags::new().set_write(true).set_page_global(true);
+        unsafe {
+            KernelMapper::lock().map_phys_with_size(
+                space.vaddr() + Self::GUARD_SIZE,
+                paddr,
+                Self::SIZE,
+                flags,
+                true,
+            )?;
+        }
+        return Ok(space);
+    }
+
     /// 根据已有的空间，构造一个内核栈结构体
     ///
     /// 仅仅用于BSP启动时，为idle进程构造内核栈。其他时候使用这个函数，很可能造成错误！
@@ -1497,23 +1752,25 @@ impl KernelStack {
         }
 
         return Ok(Self {
-            stack: Some(
-                AlignedBox::<[u8; KernelStack::SIZE], { KernelStack::ALIGN }>::new_unchecked(
-                    base.data() as *mut [u8; KernelStack::SIZE],
-                ),
-            ),
+            stack: Some(KernelStackMem::new_unchecked(
+                base.data() as *mut [u8; KernelStack::SIZE]
+            )),
+            guarded: None,
             can_be_freed: false,
         });
     }
 
     /// 返回内核栈的起始虚拟地址(低地址)
     pub fn start_address(&self) -> VirtAddr {
+        if let Some(guarded) = self.guarded.as_ref() {
+            return guarded.vaddr() + Self::GUARD_SIZE;
+        }
         return VirtAddr::new(self.stack.as_ref().unwrap().as_ptr() as usize);
     }
 
     /// 返回内核栈的结束虚拟地址(高地址)(不包含该地址)
     pub fn stack_max_address(&self) -> VirtAddr {
-        return VirtAddr::new(self.stack.as_ref().unwrap().as_ptr() as usize + Self::SIZE);
+        return self.start_address() + Self::SIZE;
     }
 
     pub unsafe fn set_pcb(&mut self, pcb: Weak<ProcessControlBlock>) -> Result<(), SystemError> {
@@ -1557,7 +1814,7 @@ impl KernelStack {
     #[allow(dead_code)]
     pub unsafe fn pcb(&self) -> Option<Arc<ProcessControlBlock>> {
         // 从内核栈的最低地址处取出pcb的地址
-        let p = self.stack.as_ref().unwrap().as_ptr() as *const *const ProcessControlBlock;
+        let p = self.start_address().data() as *const *const ProcessControlBlock;
         if unlikely(unsafe { (*p).is_null() }) {
             return None;
         }
@@ -1574,7 +1831,7 @@ impl KernelStack {
 impl Drop for KernelStack {
     fn drop(&mut self) {
         if self.stack.is_some() {
-            let ptr = self.stack.as_ref().unwrap().as_ptr() as *const *const ProcessControlBlock;
+            let ptr = self.start_address().data() as *const *const ProcessControlBlock;
             if unsafe { !(*ptr).is_null() } {
                 let pcb_ptr: Weak<ProcessControlBlock> = unsafe { Weak::from_raw(*ptr) };
                 drop(pcb_ptr);
@@ -1584,6 +1841,11 @@ impl Drop for KernelStack {
         if !self.can_be_freed {
             let bx = self.stack.take();
             core::mem::forget(bx);
+            return;
+        }
+
+        if let (Some(stack), Some(guarded)) = (self.stack.take(), self.guarded.take()) {
+            GUARDED_STACK_POOL.lock_irqsave().push((stack, guarded));
         }
     }
 }
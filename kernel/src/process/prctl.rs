@@ -0,0 +1,63 @@
+use num_traits::FromPrimitive;
+use system_error::SystemError;
+
+/// 进程名的最大长度（包括结尾的'\0'）
+pub const TASK_COMM_LEN: usize = 16;
+
+/// prctl系统调用的选项
+///
+/// 参考 https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/prctl.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum PrctlOption {
+    /// 获取keep_caps
+    GetKeepCaps = 7,
+    /// 设置keep_caps：uid从0变为非0时是否保留permitted权限集
+    SetKeepCaps = 8,
+    /// 设置进程名
+    SetName = 15,
+    /// 获取进程名
+    GetName = 16,
+    /// 获取seccomp模式
+    GetSeccomp = 21,
+    /// 进入seccomp严格模式，或者安装seccomp过滤器
+    SetSeccomp = 22,
+    /// 查询capability bounding set是否包含某个权限
+    CapBsetRead = 23,
+    /// 从capability bounding set中删除某个权限
+    CapBsetDrop = 24,
+    /// 设置进程地址空间的边界，用于恢复进程检查点
+    SetMm = 35,
+    /// 设置no_new_privs
+    SetNoNewPrivs = 38,
+    /// 获取no_new_privs
+    GetNoNewPrivs = 39,
+    /// 查询、修改ambient权限集
+    CapAmbient = 47,
+}
+
+impl TryFrom<usize> for PrctlOption {
+    type Error = SystemError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        <Self as FromPrimitive>::from_usize(value).ok_or(SystemError::EINVAL)
+    }
+}
+
+/// `prctl(PR_SET_MM)`的子选项
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum PrSetMmOption {
+    StartCode = 1,
+    EndCode = 2,
+    StartData = 3,
+    EndData = 4,
+    StartBrk = 6,
+    Brk = 7,
+}
+
+impl TryFrom<usize> for PrSetMmOption {
+    type Error = SystemError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        <Self as FromPrimitive>::from_usize(value).ok_or(SystemError::EINVAL)
+    }
+}
@@ -0,0 +1,366 @@
+//! seccomp：限制进程可以使用的系统调用
+//!
+//! 严格模式下只能使用read、write、exit和rt_sigreturn，调用其他系统调用的进程会被SIGKILL终止。
+//!
+//! 过滤模式下，每次系统调用之前以[`SeccompData`]为输入执行进程的所有过滤器，根据其中优先级最高的返回值
+//! 允许执行、返回错误码或者终止进程。过滤器是经典BPF程序，安装时被转换为eBPF程序，由内核的eBPF虚拟机执行。
+//!
+//! 过滤器只能增加不能删除，fork、clone时被子进程继承，execve之后仍然保留。为了避免借助setuid程序提权，
+//! 安装过滤器之前需要设置no_new_privs，或者拥有CAP_SYS_ADMIN。
+//!
+//! 参考 https://man7.org/linux/man-pages/man2/seccomp.2.html
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{fmt::Debug, mem::size_of};
+use rbpf::EbpfVmRawOwned;
+use system_error::SystemError;
+
+use crate::{
+    arch::{
+        ipc::signal::{SigCode, Signal},
+        syscall::nr::{SYS_EXIT, SYS_READ, SYS_RT_SIGRETURN, SYS_WRITE},
+    },
+    bpf::classic::{convert_classic, SockFilter, SockFprog, BPF_MAXINSNS},
+    ipc::signal_types::{SigInfo, SigType},
+    syscall::{user_access::UserBufferReader, Syscall},
+};
+
+use super::{cred::CAPFlags, ProcessManager};
+
+/// seccomp系统调用的操作
+const SECCOMP_SET_MODE_STRICT: u32 = 0;
+const SECCOMP_SET_MODE_FILTER: u32 = 1;
+const SECCOMP_GET_ACTION_AVAIL: u32 = 2;
+
+/// 过滤器的返回值，高16位为动作，低16位为附带的数据。数值越小（按有符号数比较）优先级越高
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_USER_NOTIF: u32 = 0x7fc0_0000;
+const SECCOMP_RET_TRACE: u32 = 0x7ff0_0000;
+const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+const SECCOMP_RET_ACTION_FULL: u32 = 0xffff_0000;
+const SECCOMP_RET_DATA: u32 = 0x0000_ffff;
+
+/// 一个进程的所有过滤器的指令数之和的上限，每个过滤器额外计4条指令
+const MAX_INSNS_PER_PATH: usize = 32768;
+
+/// SECCOMP_RET_ERRNO能返回的最大错误码
+const MAX_ERRNO: u32 = 4095;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH_CURRENT: u32 = 0xc000_003e;
+#[cfg(target_arch = "riscv64")]
+const AUDIT_ARCH_CURRENT: u32 = 0xc000_00f3;
+
+/// 过滤器的输入，即struct seccomp_data
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeccompData {
+    pub nr: i32,
+    pub arch: u32,
+    pub instruction_pointer: u64,
+    pub args: [u64; 6],
+}
+
+/// seccomp模式，数值与prctl(PR_GET_SECCOMP)的返回值相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeccompMode {
+    #[default]
+    Disabled = 0,
+    Strict = 1,
+    Filter = 2,
+}
+
+/// 一个seccomp过滤器，以及在它之前安装的过滤器
+pub struct SeccompFilter {
+    vm: EbpfVmRawOwned,
+    /// 经典BPF指令数
+    len: usize,
+    prev: Option<Arc<SeccompFilter>>,
+}
+
+impl Debug for SeccompFilter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SeccompFilter")
+            .field("len", &self.len)
+            .field("prev", &self.prev)
+            .finish()
+    }
+}
+
+impl SeccompFilter {
+    /// 执行过滤器，出错时按照SECCOMP_RET_KILL_PROCESS处理
+    fn run(&self, data: &SeccompData) -> u32 {
+        let mut data = *data;
+        let mem = unsafe {
+            core::slice::from_raw_parts_mut(
+                &mut data as *mut SeccompData as *mut u8,
+                size_of::<SeccompData>(),
+            )
+        };
+        match self.vm.execute_program(mem) {
+            Ok(ret) => ret as u32,
+            Err(e) => {
+                log::error!("seccomp: failed to run filter: {:?}", e);
+                SECCOMP_RET_KILL_PROCESS
+            }
+        }
+    }
+
+    /// 执行所有过滤器，返回优先级最高的结果
+    fn run_all(self: &Arc<Self>, data: &SeccompData) -> u32 {
+        let mut ret = SECCOMP_RET_ALLOW;
+        let mut filter = Some(self);
+        while let Some(f) = filter {
+            let cur = f.run(data);
+            if ((cur & SECCOMP_RET_ACTION_FULL) as i32) < ((ret & SECCOMP_RET_ACTION_FULL) as i32) {
+                ret = cur;
+            }
+            filter = f.prev.as_ref();
+        }
+        ret
+    }
+
+    /// 过滤器链的指令数，用于限制过滤器的总长度
+    fn path_len(self: &Arc<Self>) -> usize {
+        let mut total = 0;
+        let mut filter = Some(self);
+        while let Some(f) = filter {
+            total += f.len + 4;
+            filter = f.prev.as_ref();
+        }
+        total
+    }
+}
+
+/// 进程的seccomp状态
+#[derive(Debug, Clone, Default)]
+pub struct Seccomp {
+    mode: SeccompMode,
+    /// 最后安装的过滤器
+    filter: Option<Arc<SeccompFilter>>,
+}
+
+impl Seccomp {
+    pub fn mode(&self) -> SeccompMode {
+        self.mode
+    }
+}
+
+/// 系统调用被seccomp拒绝之后的处理
+enum SeccompAction {
+    /// 跳过系统调用，返回这个值
+    Return(usize),
+    /// 以这个信号终止当前线程
+    Kill(Signal),
+}
+
+/// 严格模式下允许使用的系统调用
+fn strict_allowed(nr: usize) -> bool {
+    matches!(nr, SYS_READ | SYS_WRITE | SYS_EXIT | SYS_RT_SIGRETURN)
+}
+
+/// 系统调用返回错误码
+fn errno_return(errno: u32) -> usize {
+    (-(errno as isize)) as usize
+}
+
+/// 执行过滤器，决定如何处理系统调用
+fn filter_syscall(nr: usize, args: &[usize; 6], ip: usize) -> Option<SeccompAction> {
+    let pcb = ProcessManager::current_pcb();
+    let filter = {
+        let seccomp = pcb.seccomp.lock_irqsave();
+        match seccomp.mode {
+            SeccompMode::Disabled => return None,
+            SeccompMode::Strict => {
+                drop(seccomp);
+                if strict_allowed(nr) {
+                    return None;
+                }
+                log::warn!(
+                    "seccomp: pid {:?} killed by strict mode, syscall {}",
+                    pcb.pid(),
+                    nr
+                );
+                return Some(SeccompAction::Kill(Signal::SIGKILL));
+            }
+            SeccompMode::Filter => seccomp.filter.clone()?,
+        }
+    };
+
+    let data = SeccompData {
+        nr: nr as i32,
+        arch: AUDIT_ARCH_CURRENT,
+        instruction_pointer: ip as u64,
+        args: args.map(|a| a as u64),
+    };
+    let ret = filter.run_all(&data);
+    let errno_data = (ret & SECCOMP_RET_DATA).min(MAX_ERRNO);
+    match ret & SECCOMP_RET_ACTION_FULL {
+        SECCOMP_RET_ALLOW => None,
+        SECCOMP_RET_LOG => {
+            log::info!("seccomp: pid {:?} syscall {} logged", pcb.pid(), nr);
+            None
+        }
+        SECCOMP_RET_ERRNO => Some(SeccompAction::Return(errno_return(errno_data))),
+        SECCOMP_RET_TRAP => {
+            let mut info = SigInfo::new(
+                Signal::SIGSYS,
+                errno_data as i32,
+                SigCode::SysSeccomp,
+                SigType::Kill(pcb.pid()),
+            );
+            Signal::SIGSYS
+                .send_signal_info_to_pcb(Some(&mut info), pcb.clone())
+                .ok();
+            Some(SeccompAction::Return(
+                SystemError::ENOSYS.to_posix_errno() as usize
+            ))
+        }
+        SECCOMP_RET_KILL_THREAD | SECCOMP_RET_KILL_PROCESS => {
+            Some(SeccompAction::Kill(Signal::SIGSYS))
+        }
+        // 不支持ptrace和用户态通知，与Linux没有tracer、监听者时相同，返回ENOSYS
+        SECCOMP_RET_TRACE | SECCOMP_RET_USER_NOTIF => Some(SeccompAction::Return(
+            SystemError::ENOSYS.to_posix_errno() as usize,
+        )),
+        // 未知的动作按照SECCOMP_RET_KILL_PROCESS处理
+        _ => Some(SeccompAction::Kill(Signal::SIGSYS)),
+    }
+}
+
+/// # 系统调用入口的seccomp检查
+///
+/// 在执行系统调用之前调用。需要终止进程时不会返回
+///
+/// ## 参数
+/// - `nr`: 系统调用号
+/// - `args`: 系统调用的参数
+/// - `ip`: 系统调用指令的地址
+///
+/// ## 返回值
+/// - `None`: 允许执行系统调用
+/// - `Some(usize)`: 跳过系统调用，以这个值作为系统调用的返回值
+pub fn secure_computing(nr: usize, args: &[usize; 6], ip: usize) -> Option<usize> {
+    match filter_syscall(nr, args, ip)? {
+        SeccompAction::Return(ret) => Some(ret),
+        SeccompAction::Kill(sig) => ProcessManager::exit(sig as usize),
+    }
+}
+
+/// 进入严格模式
+fn set_mode_strict() -> Result<usize, SystemError> {
+    let pcb = ProcessManager::current_pcb();
+    let mut seccomp = pcb.seccomp.lock_irqsave();
+    if seccomp.mode == SeccompMode::Filter {
+        return Err(SystemError::EINVAL);
+    }
+    seccomp.mode = SeccompMode::Strict;
+    return Ok(0);
+}
+
+/// 从用户空间读取经典BPF程序，安装为新的过滤器
+fn set_mode_filter(flags: u32, uprog: *const SockFprog) -> Result<usize, SystemError> {
+    if flags != 0 {
+        return Err(SystemError::EINVAL);
+    }
+    let reader = UserBufferReader::new(uprog, size_of::<SockFprog>(), true)?;
+    let fprog = *reader.read_one_from_user::<SockFprog>(0)?;
+    let len = fprog.len as usize;
+    if len == 0 || len > BPF_MAXINSNS {
+        return Err(SystemError::EINVAL);
+    }
+    let reader = UserBufferReader::new(fprog.filter, len * size_of::<SockFilter>(), true)?;
+    let insns: Vec<SockFilter> = reader.read_from_user::<SockFilter>(0)?.to_vec();
+
+    let pcb = ProcessManager::current_pcb();
+    if !pcb.no_new_privs() && !pcb.cred().has_capability(CAPFlags::CAP_SYS_ADMIN) {
+        return Err(SystemError::EACCES);
+    }
+
+    let prog = convert_classic(&insns, size_of::<SeccompData>() as u32)?;
+    let vm = EbpfVmRawOwned::new(Some(prog)).map_err(|e| {
+        log::error!("seccomp: failed to load filter: {:?}", e);
+        SystemError::EINVAL
+    })?;
+
+    let mut seccomp = pcb.seccomp.lock_irqsave();
+    if seccomp.mode == SeccompMode::Strict {
+        return Err(SystemError::EINVAL);
+    }
+    let prev = seccomp.filter.clone();
+    if prev.as_ref().map_or(0, |f| f.path_len()) + len + 4 > MAX_INSNS_PER_PATH {
+        return Err(SystemError::ENOMEM);
+    }
+    seccomp.filter = Some(Arc::new(SeccompFilter { vm, len, prev }));
+    seccomp.mode = SeccompMode::Filter;
+    return Ok(0);
+}
+
+/// 检查内核是否支持过滤器的某个返回动作
+fn get_action_avail(uaction: *const u32) -> Result<usize, SystemError> {
+    let reader = UserBufferReader::new(uaction, size_of::<u32>(), true)?;
+    let action = *reader.read_one_from_user::<u32>(0)?;
+    match action {
+        SECCOMP_RET_KILL_PROCESS
+        | SECCOMP_RET_KILL_THREAD
+        | SECCOMP_RET_TRAP
+        | SECCOMP_RET_ERRNO
+        | SECCOMP_RET_LOG
+        | SECCOMP_RET_ALLOW => Ok(0),
+        _ => Err(SystemError::EOPNOTSUPP_OR_ENOTSUP),
+    }
+}
+
+/// # prctl(PR_SET_SECCOMP)
+///
+/// `mode`为[`SeccompMode::Strict`]或[`SeccompMode::Filter`]，后者的`filter`指向用户空间的sock_fprog
+pub fn prctl_set_seccomp(mode: usize, filter: usize) -> Result<usize, SystemError> {
+    match mode {
+        m if m == SeccompMode::Strict as usize => {
+            if filter != 0 {
+                return Err(SystemError::EINVAL);
+            }
+            set_mode_strict()
+        }
+        m if m == SeccompMode::Filter as usize => set_mode_filter(0, filter as *const SockFprog),
+        _ => Err(SystemError::EINVAL),
+    }
+}
+
+impl Syscall {
+    /// # 设置当前线程的seccomp模式
+    ///
+    /// ## 参数
+    /// - `op`: SECCOMP_SET_MODE_STRICT、SECCOMP_SET_MODE_FILTER或SECCOMP_GET_ACTION_AVAIL
+    /// - `flags`: 目前不支持任何标志，必须为0
+    /// - `uargs`: SECCOMP_SET_MODE_FILTER时指向sock_fprog，SECCOMP_GET_ACTION_AVAIL时指向要检查的动作
+    ///
+    /// ## 返回值
+    /// - `Ok(0)`: 成功
+    /// - `Err(SystemError::EACCES)`: 没有设置no_new_privs，也没有CAP_SYS_ADMIN
+    /// - `Err(SystemError::EINVAL)`: 参数或者过滤器不合法
+    ///
+    /// See: https://man7.org/linux/man-pages/man2/seccomp.2.html
+    pub fn sys_seccomp(op: u32, flags: u32, uargs: usize) -> Result<usize, SystemError> {
+        match op {
+            SECCOMP_SET_MODE_STRICT => {
+                if flags != 0 || uargs != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                set_mode_strict()
+            }
+            SECCOMP_SET_MODE_FILTER => set_mode_filter(flags, uargs as *const SockFprog),
+            SECCOMP_GET_ACTION_AVAIL => {
+                if flags != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                get_action_avail(uargs as *const u32)
+            }
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! 内核栈溢出检测
+//!
+//! 每个内核栈的下方都有一段不映射的保护区域（见[`KernelStack`]），栈溢出时访问保护区域会触发缺页。
+//! 溢出发生时原来的栈已经不能再用来保存现场，因此每个CPU还有一个独立的溢出栈：
+//!
+//! - x86_64上，往保护区域压栈失败会升级为双重错误，双重错误通过TSS的IST切换到溢出栈；
+//! - riscv64在内核态陷入的入口处检查栈指针，发现即将越过当前内核栈时切换到溢出栈。
+//!
+//! 两种情况最终都调用[`kernel_stack_overflow`]打印诊断信息并panic。
+
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use log::error;
+use system_error::SystemError;
+
+use crate::{
+    arch::interrupt::TrapFrame,
+    mm::{percpu::PerCpu, VirtAddr},
+    smp::{
+        core::smp_get_processor_id,
+        cpu::{smp_cpu_manager, ProcessorId},
+    },
+};
+
+use super::{KernelStack, ProcessControlBlock, ProcessManager};
+
+/// 每个进程最多有两个内核栈：内核栈与系统调用栈
+const NR_TASK_STACKS: usize = 2;
+
+struct CpuStackInfo {
+    /// 当前进程的各个内核栈的最低地址，为0表示未知
+    task_stacks: [AtomicUsize; NR_TASK_STACKS],
+    /// 溢出栈的最低地址，为0表示还未分配
+    overflow_stack: AtomicUsize,
+}
+
+impl CpuStackInfo {
+    const fn new() -> Self {
+        Self {
+            task_stacks: [const { AtomicUsize::new(0) }; NR_TASK_STACKS],
+            overflow_stack: AtomicUsize::new(0),
+        }
+    }
+}
+
+static CPU_STACK_INFO: [CpuStackInfo; PerCpu::MAX_CPU_NUM as usize] =
+    [const { CpuStackInfo::new() }; PerCpu::MAX_CPU_NUM as usize];
+
+/// 为所有可能的CPU分配溢出栈
+///
+/// 需要在`prepare_cpus`之后、启动AP之前调用，分配好之后再由架构相关的代码把溢出栈交给硬件
+pub fn stack_overflow_init() -> Result<(), SystemError> {
+    for cpu in smp_cpu_manager().possible_cpus().iter_cpu() {
+        let info = &CPU_STACK_INFO[cpu.data() as usize];
+        if info.overflow_stack.load(Ordering::SeqCst) != 0 {
+            continue;
+        }
+        let stack = KernelStack::new()?;
+        info.overflow_stack
+            .store(stack.start_address().data(), Ordering::SeqCst);
+        // 溢出栈伴随CPU一直存在，不会被释放
+        core::mem::forget(stack);
+    }
+    return Ok(());
+}
+
+/// 获取指定CPU的溢出栈的栈顶（高地址），还未分配时返回None
+pub fn overflow_stack_top(cpu: ProcessorId) -> Option<VirtAddr> {
+    let base = CPU_STACK_INFO[cpu.data() as usize]
+        .overflow_stack
+        .load(Ordering::SeqCst);
+    if base == 0 {
+        return None;
+    }
+    return Some(VirtAddr::new(base + KernelStack::SIZE));
+}
+
+/// 记录即将在当前CPU上运行的进程的内核栈
+///
+/// 在切换进程时、真正切换栈之前调用
+pub unsafe fn set_current_task_stacks(next: &ProcessControlBlock) {
+    let info = &CPU_STACK_INFO[smp_get_processor_id().data() as usize];
+    let stacks = [
+        next.kernel_stack_force_ref().start_address(),
+        next.syscall_stack_force_ref().start_address(),
+    ];
+    for (slot, stack) in info.task_stacks.iter().zip(stacks) {
+        slot.store(stack.data(), Ordering::SeqCst);
+    }
+}
+
+/// 判断栈指针`sp`是否已经越过（或即将越过）当前进程的某个内核栈的底部
+///
+/// 栈指针离栈底不足一个中断栈帧时也认为溢出了，因为此时压入异常现场就会落入保护区域。
+///
+/// ## 返回值
+///
+/// 溢出时返回那个内核栈的最低地址
+pub fn stack_guard_hit(sp: usize) -> Option<usize> {
+    let info = &CPU_STACK_INFO[smp_get_processor_id().data() as usize];
+    info.task_stacks
+        .iter()
+        .map(|slot| slot.load(Ordering::SeqCst))
+        .find(|&bottom| {
+            bottom != 0
+                && sp >= bottom - KernelStack::GUARD_SIZE
+                && sp < bottom + size_of::<TrapFrame>()
+        })
+}
+
+/// `base`是否是当前CPU上某个已知的栈（当前进程的内核栈、系统调用栈，或者本CPU的溢出栈）的最低地址
+pub fn is_cpu_stack(base: usize) -> bool {
+    if base == 0 {
+        return false;
+    }
+    let info = &CPU_STACK_INFO[smp_get_processor_id().data() as usize];
+    info.task_stacks
+        .iter()
+        .chain(core::iter::once(&info.overflow_stack))
+        .any(|slot| slot.load(Ordering::SeqCst) == base)
+}
+
+/// 当前是否正运行在本CPU的溢出栈上
+///
+/// ## 返回值
+///
+/// 是的话返回当前进程的内核栈的最低地址（未知时为0）
+pub fn on_overflow_stack() -> Option<usize> {
+    let info = &CPU_STACK_INFO[smp_get_processor_id().data() as usize];
+    let base = info.overflow_stack.load(Ordering::SeqCst);
+    let sp = &base as *const usize as usize;
+    if base == 0 || sp < base || sp >= base + KernelStack::SIZE {
+        return None;
+    }
+    return Some(info.task_stacks[0].load(Ordering::SeqCst));
+}
+
+/// 报告内核栈溢出，不会返回
+///
+/// ## 参数
+///
+/// - `stack_bottom`: 溢出的内核栈的最低地址，为0表示未知
+/// - `sp`: 出错时的栈指针
+/// - `pc`: 出错时的指令地址
+pub unsafe fn kernel_stack_overflow(stack_bottom: usize, sp: usize, pc: usize) -> ! {
+    // 溢出栈底部没有pcb指针，把出错的内核栈的复制过来，这样后面仍然可以获取当前进程
+    if stack_bottom != 0 {
+        inherit_pcb_slot(stack_bottom);
+    }
+
+    error!(
+        "Kernel stack overflow detected! CPU: {}, pid: {:?}, sp: {:#x}, pc: {:#x}",
+        smp_get_processor_id().data(),
+        if stack_bottom != 0 {
+            Some(ProcessManager::current_pid())
+        } else {
+            None
+        },
+        sp,
+        pc
+    );
+    if stack_bottom != 0 {
+        error!(
+            "Kernel stack: [{:#x}, {:#x}), guard: [{:#x}, {:#x}), overflowed by {} bytes",
+            stack_bottom,
+            stack_bottom + KernelStack::SIZE,
+            stack_bottom - KernelStack::GUARD_SIZE,
+            stack_bottom,
+            stack_bottom.saturating_sub(sp)
+        );
+    }
+    panic!("Kernel stack overflow");
+}
+
+/// 把内核栈`stack_bottom`底部的pcb指针复制到当前所在的栈的底部
+///
+/// 在IST栈、溢出栈上运行时，通过栈指针无法找到当前进程，需要先调用这个函数
+pub unsafe fn inherit_pcb_slot(stack_bottom: usize) {
+    let marker = 0usize;
+    let current_bottom = (&marker as *const usize as usize) & !(KernelStack::ALIGN - 1);
+    if current_bottom == stack_bottom {
+        return;
+    }
+    let from = stack_bottom as *const *const ProcessControlBlock;
+    let to = current_bottom as *mut *const ProcessControlBlock;
+    to.write_volatile(from.read_volatile());
+}
+
+/// 获取当前CPU上正在运行的进程的内核栈的最低地址，未知时返回0
+pub fn current_kernel_stack_bottom() -> usize {
+    CPU_STACK_INFO[smp_get_processor_id().data() as usize].task_stacks[0].load(Ordering::SeqCst)
+}
@@ -11,17 +11,22 @@ use system_error::SystemError;
 
 use super::{
     abi::WaitOption,
-    cred::{Kgid, Kuid},
+    binfmt_misc::binfmt_misc_resolve,
+    capability::{prctl_cap_ambient, prctl_capbset_drop, prctl_capbset_read},
+    cred::CAPFlags,
     exec::{load_binary_file, ExecParam, ExecParamFlags},
-    exit::kernel_wait4,
+    exit::{kernel_wait4, kernel_waitid},
     fork::{CloneFlags, KernelCloneArgs},
     resource::{RLimit64, RLimitID, RUsage, RUsageWho},
+    seccomp::prctl_set_seccomp,
     KernelStack, Pid, ProcessManager,
 };
 use crate::{
-    arch::{interrupt::TrapFrame, CurrentIrqArch, MMArch},
+    arch::{interrupt::TrapFrame, ipc::signal::Signal, CurrentIrqArch, MMArch},
+    debug::exec_fd_audit::exec_fd_audit,
     exception::InterruptArch,
     filesystem::{
+        pidfd::pidfd_create,
         procfs::procfs_register_pid,
         vfs::{file::FileDescriptorVec, MAX_PATHLEN},
     },
@@ -29,7 +34,10 @@ use crate::{
         ucontext::{AddressSpace, UserStack},
         verify_area, MemoryManagementArch, VirtAddr,
     },
-    process::ProcessControlBlock,
+    process::{
+        prctl::{PrSetMmOption, PrctlOption, TASK_COMM_LEN},
+        ProcessControlBlock,
+    },
     sched::completion::Completion,
     syscall::{
         user_access::{check_and_clone_cstr, check_and_clone_cstr_array, UserBufferWriter},
@@ -37,6 +45,9 @@ use crate::{
     },
 };
 
+/// waitid返回的siginfo_t的大小
+const WAITID_SIGINFO_SIZE: usize = 128;
+
 //参考资料：https://code.dragonos.org.cn/xref/linux-6.1.9/include/uapi/linux/utsname.h#17
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -138,9 +149,13 @@ impl Syscall {
 
         Self::do_execve(path, argv, envp, frame)?;
 
+        // 按照执行新程序的规则重新计算权限集
+        ProcessManager::current_pcb().cred.lock().exec_transform();
+
         // 关闭设置了O_CLOEXEC的文件描述符
         let fd_table = ProcessManager::current_pcb().fd_table();
         fd_table.write().close_on_exec();
+        exec_fd_audit(&fd_table.read());
         // debug!(
         //     "after execve: strong count: {}",
         //     Arc::strong_count(&ProcessManager::current_pcb())
@@ -155,6 +170,9 @@ impl Syscall {
         envp: Vec<CString>,
         regs: &mut TrapFrame,
     ) -> Result<(), SystemError> {
+        // 由binfmt_misc处理的文件改为执行它的解释器
+        let (path, argv) = binfmt_misc_resolve(path, argv)?;
+
         let address_space = AddressSpace::new(true).expect("Failed to create new address space");
         // debug!("to load binary file");
         let mut param = ExecParam::new(path.as_str(), address_space.clone(), ExecParamFlags::EXEC)?;
@@ -234,6 +252,65 @@ impl Syscall {
         return Ok(r);
     }
 
+    /// # 等待子进程改变状态
+    ///
+    /// ## 参数
+    ///
+    /// - `which`: P_ALL、P_PID、P_PGID 或者 P_PIDFD
+    /// - `upid`: 进程号或者 pidfd
+    /// - `infop`: 用户空间的 siginfo_t，用于返回子进程的信息。没有子进程改变状态时各字段被清零
+    /// - `options`: 等待选项
+    /// - `rusage`: 用户空间的 rusage，可以为空
+    pub fn waitid(
+        which: i32,
+        upid: i32,
+        infop: *mut c_void,
+        options: i32,
+        rusage: *mut c_void,
+    ) -> Result<usize, SystemError> {
+        let options = WaitOption::from_bits(options as u32).ok_or(SystemError::EINVAL)?;
+
+        let mut info_buf = if infop.is_null() {
+            None
+        } else {
+            Some(UserBufferWriter::new(
+                infop as *mut i32,
+                WAITID_SIGINFO_SIZE,
+                true,
+            )?)
+        };
+
+        let mut tmp_rusage = if rusage.is_null() {
+            None
+        } else {
+            Some(RUsage::default())
+        };
+
+        let info = kernel_waitid(which, upid, options, tmp_rusage.as_mut())?;
+
+        if let Some(info_buf) = info_buf.as_mut() {
+            // 按照siginfo_t中SIGCHLD的布局填写：si_signo、si_errno、si_code，之后是si_pid、si_uid、si_status
+            let mut siginfo = [0i32; WAITID_SIGINFO_SIZE / core::mem::size_of::<i32>()];
+            if let Some(info) = info {
+                siginfo[0] = Signal::SIGCHLD as i32;
+                siginfo[2] = info.cause;
+                siginfo[4] = info.pid.data() as i32;
+                siginfo[6] = info.status;
+            }
+            info_buf.copy_to_user(&siginfo, 0)?;
+        }
+
+        if !rusage.is_null() {
+            let mut rusage_buf = UserBufferWriter::new::<RUsage>(
+                rusage as *mut RUsage,
+                core::mem::size_of::<RUsage>(),
+                true,
+            )?;
+            rusage_buf.copy_one_to_user(&tmp_rusage.unwrap(), 0)?;
+        }
+        return Ok(0);
+    }
+
     /// # 退出进程
     ///
     /// ## 参数
@@ -291,6 +368,17 @@ impl Syscall {
             return Err(SystemError::EINVAL);
         }
 
+        // CLONE_PIDFD：新进程的pidfd写到这个地址。先检查地址，避免子进程创建之后才失败
+        let mut pidfd_writer = if flags.contains(CloneFlags::CLONE_PIDFD) {
+            Some(UserBufferWriter::new(
+                clone_args.pidfd.as_ptr::<i32>(),
+                core::mem::size_of::<i32>(),
+                true,
+            )?)
+        } else {
+            None
+        };
+
         let current_pcb = ProcessManager::current_pcb();
         let new_kstack = KernelStack::new()?;
         let name = current_pcb.basic().name().to_string();
@@ -319,6 +407,11 @@ impl Syscall {
             writer.copy_one_to_user(&(pcb.pid().data() as i32), 0)?;
         }
 
+        if let Some(writer) = pidfd_writer.as_mut() {
+            let pidfd = pidfd_create(pcb.clone(), 0)?;
+            writer.copy_one_to_user(&(pidfd as i32), 0)?;
+        }
+
         ProcessManager::wakeup(&pcb).unwrap_or_else(|e| {
             panic!(
                 "fork: Failed to wakeup new process, pid: [{:?}]. Error: {:?}",
@@ -351,38 +444,46 @@ impl Syscall {
     }
 
     pub fn getuid() -> Result<usize, SystemError> {
-        let pcb = ProcessManager::current_pcb();
-        return Ok(pcb.cred.lock().uid.data());
+        let cred = ProcessManager::current_pcb().cred();
+        return Ok(cred.user_ns.from_kuid_munged(cred.uid));
     }
 
     pub fn getgid() -> Result<usize, SystemError> {
-        let pcb = ProcessManager::current_pcb();
-        return Ok(pcb.cred.lock().gid.data());
+        let cred = ProcessManager::current_pcb().cred();
+        return Ok(cred.user_ns.from_kgid_munged(cred.gid));
     }
 
     pub fn geteuid() -> Result<usize, SystemError> {
-        let pcb = ProcessManager::current_pcb();
-        return Ok(pcb.cred.lock().euid.data());
+        let cred = ProcessManager::current_pcb().cred();
+        return Ok(cred.user_ns.from_kuid_munged(cred.euid));
     }
 
     pub fn getegid() -> Result<usize, SystemError> {
-        let pcb = ProcessManager::current_pcb();
-        return Ok(pcb.cred.lock().egid.data());
+        let cred = ProcessManager::current_pcb().cred();
+        return Ok(cred.user_ns.from_kgid_munged(cred.egid));
     }
 
+    /// 有CAP_SETUID时设置实际、有效、保存的uid，否则只能把有效uid设为实际或保存的uid
+    ///
+    /// `uid`是当前user namespace中的uid，没有映射时返回EINVAL
     pub fn setuid(uid: usize) -> Result<usize, SystemError> {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
-
-        if guard.uid.data() == 0 {
-            guard.setuid(uid);
-            guard.seteuid(uid);
-            guard.setsuid(uid);
-        } else if uid == guard.uid.data() || uid == guard.suid.data() {
-            guard.seteuid(uid);
+        let old = guard.clone();
+        let kuid = old.user_ns.make_kuid(uid).ok_or(SystemError::EINVAL)?;
+
+        if old.ns_capable(&old.user_ns, CAPFlags::CAP_SETUID) {
+            guard.setuid(kuid.data());
+            guard.seteuid(kuid.data());
+            guard.setsuid(kuid.data());
+            guard.setfsuid(kuid.data());
+        } else if kuid == old.uid || kuid == old.suid {
+            guard.seteuid(kuid.data());
+            guard.setfsuid(kuid.data());
         } else {
             return Err(SystemError::EPERM);
         }
+        guard.fixup_setuid(&old);
 
         return Ok(0);
     }
@@ -390,15 +491,16 @@ impl Syscall {
     pub fn setgid(gid: usize) -> Result<usize, SystemError> {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
-
-        if guard.egid.data() == 0 {
-            guard.setgid(gid);
-            guard.setegid(gid);
-            guard.setsgid(gid);
-            guard.setfsgid(gid);
-        } else if guard.gid.data() == gid || guard.sgid.data() == gid {
-            guard.setegid(gid);
-            guard.setfsgid(gid);
+        let kgid = guard.user_ns.make_kgid(gid).ok_or(SystemError::EINVAL)?;
+
+        if guard.ns_capable(&guard.user_ns, CAPFlags::CAP_SETGID) {
+            guard.setgid(kgid.data());
+            guard.setegid(kgid.data());
+            guard.setsgid(kgid.data());
+            guard.setfsgid(kgid.data());
+        } else if kgid == guard.gid || kgid == guard.sgid {
+            guard.setegid(kgid.data());
+            guard.setfsgid(kgid.data());
         } else {
             return Err(SystemError::EPERM);
         }
@@ -406,68 +508,98 @@ impl Syscall {
         return Ok(0);
     }
 
+    /// 没有CAP_SETUID时，只能把有效uid设为实际、有效或保存的uid。`euid`为-1时不修改
     pub fn seteuid(euid: usize) -> Result<usize, SystemError> {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
+        let old = guard.clone();
 
-        if euid == usize::MAX || (euid == guard.euid.data() && euid == guard.fsuid.data()) {
+        if euid as u32 == u32::MAX {
+            return Ok(0);
+        }
+        let keuid = old.user_ns.make_kuid(euid).ok_or(SystemError::EINVAL)?;
+        if keuid == old.euid && keuid == old.fsuid {
             return Ok(0);
         }
 
-        if euid != usize::MAX {
-            guard.seteuid(euid);
+        let allowed = keuid == old.uid || keuid == old.euid || keuid == old.suid;
+        if !allowed && !old.ns_capable(&old.user_ns, CAPFlags::CAP_SETUID) {
+            return Err(SystemError::EPERM);
         }
 
-        let euid = guard.euid.data();
-        guard.setfsuid(euid);
+        guard.seteuid(keuid.data());
+        guard.setfsuid(keuid.data());
+        guard.fixup_setuid(&old);
 
         return Ok(0);
     }
 
+    /// 没有CAP_SETGID时，只能把有效gid设为实际、有效或保存的gid。`egid`为-1时不修改
     pub fn setegid(egid: usize) -> Result<usize, SystemError> {
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
 
-        if egid == usize::MAX || (egid == guard.egid.data() && egid == guard.fsgid.data()) {
+        if egid as u32 == u32::MAX {
+            return Ok(0);
+        }
+        let kegid = guard.user_ns.make_kgid(egid).ok_or(SystemError::EINVAL)?;
+        if kegid == guard.egid && kegid == guard.fsgid {
             return Ok(0);
         }
 
-        if egid != usize::MAX {
-            guard.setegid(egid);
+        let allowed = kegid == guard.gid || kegid == guard.egid || kegid == guard.sgid;
+        if !allowed && !guard.ns_capable(&guard.user_ns, CAPFlags::CAP_SETGID) {
+            return Err(SystemError::EPERM);
         }
 
-        let egid = guard.egid.data();
-        guard.setfsgid(egid);
+        guard.setegid(kegid.data());
+        guard.setfsgid(kegid.data());
 
         return Ok(0);
     }
 
+    /// 返回原来的fsuid。`fsuid`没有映射或者没有权限时不修改
     pub fn setfsuid(fsuid: usize) -> Result<usize, SystemError> {
-        let fsuid = Kuid::new(fsuid);
-
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
-        let old_fsuid = guard.fsuid;
+        let old = guard.clone();
+        let old_fsuid = old.user_ns.from_kuid_munged(old.fsuid);
 
-        if fsuid == guard.uid || fsuid == guard.euid || fsuid == guard.suid {
-            guard.setfsuid(fsuid.data());
+        let Some(kfsuid) = old.user_ns.make_kuid(fsuid) else {
+            return Ok(old_fsuid);
+        };
+        if kfsuid == old.uid
+            || kfsuid == old.euid
+            || kfsuid == old.suid
+            || kfsuid == old.fsuid
+            || old.ns_capable(&old.user_ns, CAPFlags::CAP_SETUID)
+        {
+            guard.setfsuid(kfsuid.data());
+            guard.fixup_setuid(&old);
         }
 
-        Ok(old_fsuid.data())
+        Ok(old_fsuid)
     }
 
+    /// 返回原来的fsgid。`fsgid`没有映射或者没有权限时不修改
     pub fn setfsgid(fsgid: usize) -> Result<usize, SystemError> {
-        let fsgid = Kgid::new(fsgid);
-
         let pcb = ProcessManager::current_pcb();
         let mut guard = pcb.cred.lock();
-        let old_fsgid = guard.fsgid;
+        let old_fsgid = guard.user_ns.from_kgid_munged(guard.fsgid);
 
-        if fsgid == guard.gid || fsgid == guard.egid || fsgid == guard.sgid {
-            guard.setfsgid(fsgid.data());
+        let Some(kfsgid) = guard.user_ns.make_kgid(fsgid) else {
+            return Ok(old_fsgid);
+        };
+        if kfsgid == guard.gid
+            || kfsgid == guard.egid
+            || kfsgid == guard.sgid
+            || kfsgid == guard.fsgid
+            || guard.ns_capable(&guard.user_ns, CAPFlags::CAP_SETGID)
+        {
+            guard.setfsgid(kfsgid.data());
         }
 
-        Ok(old_fsgid.data())
+        Ok(old_fsgid)
     }
 
     pub fn get_rusage(who: i32, rusage: *mut RUsage) -> Result<usize, SystemError> {
@@ -556,6 +688,108 @@ impl Syscall {
 
         return Ok(0);
     }
+
+    /// # prctl系统调用
+    ///
+    /// 目前支持：
+    /// - PR_SET_NAME/PR_GET_NAME: 设置/获取当前进程的名字
+    /// - PR_SET_MM: 设置当前进程代码段、数据段与堆的边界，用于恢复进程检查点。需要CAP_SYS_RESOURCE
+    /// - PR_SET_SECCOMP/PR_GET_SECCOMP: 设置/获取seccomp模式
+    /// - PR_SET_NO_NEW_PRIVS/PR_GET_NO_NEW_PRIVS: 设置/获取no_new_privs
+    /// - PR_SET_KEEPCAPS/PR_GET_KEEPCAPS: 设置/获取keep_caps
+    /// - PR_CAPBSET_READ/PR_CAPBSET_DROP: 查询capability bounding set/从中删除权限
+    /// - PR_CAP_AMBIENT: 查询、修改ambient权限集
+    pub fn prctl(
+        option: usize,
+        arg2: usize,
+        arg3: usize,
+        arg4: usize,
+        arg5: usize,
+    ) -> Result<usize, SystemError> {
+        let pcb = ProcessManager::current_pcb();
+        match PrctlOption::try_from(option)? {
+            PrctlOption::SetName => {
+                let name = check_and_clone_cstr(arg2 as *const u8, Some(TASK_COMM_LEN - 1))?;
+                pcb.set_name(name.to_string_lossy().into_owned());
+            }
+            PrctlOption::GetName => {
+                let mut comm = [0u8; TASK_COMM_LEN];
+                let basic = pcb.basic();
+                let name = basic.name().as_bytes();
+                let len = name.len().min(TASK_COMM_LEN - 1);
+                comm[..len].copy_from_slice(&name[..len]);
+                drop(basic);
+
+                let mut writer = UserBufferWriter::new(arg2 as *mut u8, TASK_COMM_LEN, true)?;
+                writer.copy_to_user(&comm, 0)?;
+            }
+            PrctlOption::GetKeepCaps => return Ok(pcb.cred().keep_caps as usize),
+            PrctlOption::SetKeepCaps => {
+                if arg2 > 1 {
+                    return Err(SystemError::EINVAL);
+                }
+                pcb.cred.lock().keep_caps = arg2 == 1;
+            }
+            PrctlOption::CapBsetRead => return prctl_capbset_read(arg2),
+            PrctlOption::CapBsetDrop => return prctl_capbset_drop(arg2),
+            PrctlOption::CapAmbient => return prctl_cap_ambient(arg2, arg3, arg4, arg5),
+            PrctlOption::GetSeccomp => return Ok(pcb.seccomp_mode() as usize),
+            PrctlOption::SetSeccomp => return prctl_set_seccomp(arg2, arg3),
+            PrctlOption::SetNoNewPrivs => {
+                if arg2 != 1 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                pcb.set_no_new_privs();
+            }
+            PrctlOption::GetNoNewPrivs => {
+                if arg2 != 0 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                return Ok(pcb.no_new_privs() as usize);
+            }
+            PrctlOption::SetMm => {
+                if arg4 != 0 || arg5 != 0 {
+                    return Err(SystemError::EINVAL);
+                }
+                if !pcb.cred().has_capability(CAPFlags::CAP_SYS_RESOURCE) {
+                    return Err(SystemError::EPERM);
+                }
+                let addr = VirtAddr::new(arg3);
+                if addr >= MMArch::USER_END_VADDR {
+                    return Err(SystemError::EINVAL);
+                }
+
+                let user_vm = AddressSpace::current()?;
+                let mut vm = user_vm.write();
+                match PrSetMmOption::try_from(arg2)? {
+                    PrSetMmOption::StartCode => vm.start_code = addr,
+                    PrSetMmOption::EndCode => vm.end_code = addr,
+                    PrSetMmOption::StartData => vm.start_data = addr,
+                    PrSetMmOption::EndData => vm.end_data = addr,
+                    // 堆的边界必须按页对齐，brk系统调用会从这里开始映射新的页面
+                    PrSetMmOption::StartBrk | PrSetMmOption::Brk
+                        if !addr.check_aligned(MMArch::PAGE_SIZE) =>
+                    {
+                        return Err(SystemError::EINVAL);
+                    }
+                    PrSetMmOption::StartBrk => {
+                        if addr > vm.brk {
+                            return Err(SystemError::EINVAL);
+                        }
+                        vm.brk_start = addr;
+                    }
+                    PrSetMmOption::Brk => {
+                        if addr < vm.brk_start {
+                            return Err(SystemError::EINVAL);
+                        }
+                        vm.brk = addr;
+                    }
+                }
+            }
+        }
+
+        return Ok(0);
+    }
 }
 
 /// 切换用户虚拟内存空间
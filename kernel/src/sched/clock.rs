@@ -1,25 +1,16 @@
 //! 这个文件实现的是调度过程中涉及到的时钟
 //!
-use crate::{arch::CurrentTimeArch, smp::cpu::ProcessorId, time::TimeArch};
+//! 调度器的时钟就是内核的单调时间（见`time::timekeeping::ktime_get`），
+//! 这样运行时间的统计与定时器、日志中的时间戳使用同一个时间基准。
+use crate::{smp::cpu::ProcessorId, time::timekeeping::ktime_get_ns};
 
 pub struct SchedClock;
 
 impl SchedClock {
+    /// 获取调度器的时钟（纳秒），timekeeping初始化之前为0
     #[inline]
     pub fn sched_clock_cpu(_cpu: ProcessorId) -> u64 {
-        #[cfg(target_arch = "x86_64")]
-        {
-            if crate::arch::driver::tsc::TSCManager::cpu_khz() == 0 {
-                // TSC not calibrated yet
-                return 0;
-            }
-            return CurrentTimeArch::cycles2ns(CurrentTimeArch::get_cycles()) as u64;
-        }
-
-        #[cfg(target_arch = "riscv64")]
-        {
-            return CurrentTimeArch::cycles2ns(CurrentTimeArch::get_cycles()) as u64;
-        }
+        ktime_get_ns()
     }
 }
 
@@ -35,7 +35,7 @@ impl Completion {
             //loop break 类似 do while 保证进行一次信号检测
             loop {
                 //检查当前线程是否有未处理的信号
-                if pcb.sig_info_irqsave().sig_pending().has_pending() {
+                if pcb.has_pending_signal() {
                     return Err(SystemError::ERESTARTSYS);
                 }
 
@@ -101,6 +101,11 @@ impl Completion {
         // 脱离生命周期，自动释放guard
     }
 
+    /// @brief 清除之前残留的done，准备下一轮等待
+    pub fn reinit(&self) {
+        self.inner.lock_irqsave().done = 0;
+    }
+
     /// @brief 永久标记done为Complete_All，并从wait_queue中删除所有节点
     pub fn complete_all(&self) {
         let mut inner = self.inner.lock_irqsave();
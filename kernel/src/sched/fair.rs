@@ -4,6 +4,7 @@ use core::mem::swap;
 use core::sync::atomic::fence;
 use core::sync::atomic::{AtomicU64, Ordering};
 
+use crate::cgroup;
 use crate::libs::rbtree::RBTree;
 use crate::libs::spinlock::SpinLock;
 use crate::process::ProcessControlBlock;
@@ -18,9 +19,10 @@ use alloc::sync::{Arc, Weak};
 
 use super::idle::IdleScheduler;
 use super::pelt::{add_positive, sub_positive, SchedulerAvg, UpdateAvgFlags, PELT_MIN_DIVIDER};
+use super::rt::RealTimeScheduler;
 use super::{
-    CpuRunQueue, DequeueFlag, EnqueueFlag, LoadWeight, OnRq, SchedPolicy, Scheduler, TaskGroup,
-    WakeupFlags, SCHED_CAPACITY_SHIFT,
+    CpuRunQueue, DequeueFlag, EnqueueFlag, FairPolicy, LoadWeight, OnRq, SchedPolicy, Scheduler,
+    TaskGroup, WakeupFlags, SCHED_CAPACITY_SHIFT,
 };
 
 /// 用于设置 CPU-bound 任务的最小抢占粒度的参数。
@@ -92,7 +94,10 @@ impl FairSchedEntity {
             my_cfs_rq: None,
             on_rq: OnRq::None,
             slice: SYSCTL_SHCED_BASE_SLICE.load(Ordering::SeqCst),
-            load: Default::default(),
+            load: LoadWeight {
+                weight: LoadWeight::NICE_0_LOAD,
+                inv_weight: 0,
+            },
             deadline: Default::default(),
             min_deadline: Default::default(),
             exec_start: Default::default(),
@@ -184,11 +189,11 @@ impl FairSchedEntity {
     }
 
     pub fn calculate_delta_fair(&self, delta: u64) -> u64 {
-        if unlikely(self.load.weight != LoadWeight::NICE_0_LOAD_SHIFT as u64) {
+        if unlikely(self.load.weight != LoadWeight::NICE_0_LOAD) {
             return self
                 .force_mut()
                 .load
-                .calculate_delta(delta, LoadWeight::NICE_0_LOAD_SHIFT as u64);
+                .calculate_delta(delta, LoadWeight::NICE_0_LOAD);
         };
 
         delta
@@ -591,7 +596,13 @@ impl CfsRunQueue {
         curr.sum_exec_runtime += delta_exec;
 
         // 根据实际运行时长加权增加虚拟运行时长
-        curr.vruntime += curr.calculate_delta_fair(delta_exec);
+        let mut delta_fair = curr.calculate_delta_fair(delta_exec);
+        if let Some(pcb) = curr.pcb.upgrade() {
+            // 按照进程所在cgroup的cpu.weight缩放，并计入cgroup的cpu.max限额
+            delta_fair = cgroup::cpu::scale_delta_fair(&pcb, delta_fair);
+            cgroup::cpu::account_runtime(&pcb, delta_exec);
+        }
+        curr.vruntime += delta_fair;
         fence(Ordering::SeqCst);
         self.update_deadline(&curr.self_arc());
         self.update_min_vruntime();
@@ -1463,6 +1474,7 @@ impl Scheduler for CompletelyFairScheduler {
             });
         }
 
+        pcb.task_cgroup().set_runnable(true);
         rq.add_nr_running(1);
     }
 
@@ -1532,6 +1544,7 @@ impl Scheduler for CompletelyFairScheduler {
             });
         }
 
+        pcb.task_cgroup().set_runnable(false);
         rq.sub_nr_running(1);
 
         if unlikely(!was_sched_idle && rq.sched_idle_rq()) {
@@ -1607,7 +1620,17 @@ impl Scheduler for CompletelyFairScheduler {
             return;
         }
 
+        // SCHED_IDLE的进程总是让位于其他CFS进程
+        if unlikely(curr.sched_info().fair_policy() == FairPolicy::Idle)
+            && pcb.sched_info().fair_policy() != FairPolicy::Idle
+        {
+            rq.resched_current();
+            return;
+        }
+
+        // SCHED_BATCH、SCHED_IDLE的进程被唤醒时不抢占当前进程
         if unlikely(pcb.sched_info().policy() != SchedPolicy::CFS)
+            || pcb.sched_info().fair_policy() != FairPolicy::Normal
             || !SCHED_FEATURES.contains(SchedFeature::WAKEUP_PREEMPTION)
         {
             return;
@@ -1718,8 +1741,9 @@ impl Scheduler for CompletelyFairScheduler {
         {
             if let Some(prev) = prev {
                 match prev.sched_info().policy() {
-                    SchedPolicy::RT => todo!(),
-                    SchedPolicy::FIFO => todo!(),
+                    SchedPolicy::RT | SchedPolicy::FIFO => {
+                        RealTimeScheduler::put_prev_task(rq, prev)
+                    }
                     SchedPolicy::CFS => todo!(),
                     SchedPolicy::IDLE => IdleScheduler::put_prev_task(rq, prev),
                 }
@@ -1810,4 +1834,16 @@ impl Scheduler for CompletelyFairScheduler {
             return (true, true);
         });
     }
+
+    fn set_next_task(rq: &mut CpuRunQueue, pcb: Arc<ProcessControlBlock>) {
+        let mut se = pcb.sched_info().sched_entity();
+        se.force_mut().exec_start = rq.clock_task();
+
+        FairSchedEntity::for_each_in_group(&mut se, |se| {
+            let cfs = se.cfs_rq();
+            cfs.force_mut().set_next_entity(&se);
+
+            return (true, true);
+        });
+    }
 }
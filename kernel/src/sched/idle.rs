@@ -64,4 +64,10 @@ impl Scheduler for IdleScheduler {
     ) {
         // Nothing todo
     }
+
+    fn set_next_task(
+        _rq: &mut super::CpuRunQueue,
+        _pcb: alloc::sync::Arc<crate::process::ProcessControlBlock>,
+    ) {
+    }
 }
@@ -4,7 +4,10 @@ pub mod cputime;
 pub mod fair;
 pub mod idle;
 pub mod pelt;
+pub mod preempt;
 pub mod prio;
+pub mod rt;
+pub mod stats;
 pub mod syscall;
 
 use core::{
@@ -18,6 +21,7 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
+use log::info;
 use system_error::SystemError;
 
 use crate::{
@@ -27,21 +31,30 @@ use crate::{
         InterruptArch,
     },
     libs::{
+        cpumask::CpuMask,
         lazy_init::Lazy,
         spinlock::{SpinLock, SpinLockGuard},
     },
     mm::percpu::{PerCpu, PerCpuVar},
     process::{ProcessControlBlock, ProcessFlags, ProcessManager, ProcessState, SchedInfo},
     sched::idle::IdleScheduler,
-    smp::{core::smp_get_processor_id, cpu::ProcessorId},
-    time::{clocksource::HZ, timer::clock},
+    smp::{
+        core::smp_get_processor_id,
+        cpu::{smp_cpu_manager, ProcessorId},
+    },
+    time::{
+        clocksource::HZ,
+        timer::{clock, schedule_timeout},
+    },
 };
 
 use self::{
     clock::{ClockUpdataFlag, SchedClock},
     cputime::{irq_time_read, CpuTimeFunc, IrqTime},
     fair::{CfsRunQueue, CompletelyFairScheduler, FairSchedEntity},
-    prio::PrioUtil,
+    prio::{PrioUtil, MAX_RT_PRIO, NICE_WIDTH, SCHED_PRIO_TO_WEIGHT, WEIGHT_IDLEPRIO},
+    rt::{RealTimeScheduler, RtRunQueue, RR_TIMESLICE},
+    stats::RqSchedStat,
 };
 
 static mut CPU_IRQ_TIME: Option<Vec<&'static mut IrqTime>> = None;
@@ -128,6 +141,9 @@ pub trait Scheduler {
     fn task_fork(pcb: Arc<ProcessControlBlock>);
 
     fn put_prev_task(rq: &mut CpuRunQueue, prev: Arc<ProcessControlBlock>);
+
+    /// ## 把正在运行的任务设置为该调度类的当前任务，用于修改正在运行的任务的调度类或者优先级之后
+    fn set_next_task(rq: &mut CpuRunQueue, pcb: Arc<ProcessControlBlock>);
 }
 
 /// 调度策略
@@ -144,6 +160,26 @@ pub enum SchedPolicy {
     IDLE,
 }
 
+impl SchedPolicy {
+    /// 是否由实时调度类调度
+    #[inline]
+    pub fn is_rt(&self) -> bool {
+        matches!(self, SchedPolicy::RT | SchedPolicy::FIFO)
+    }
+}
+
+/// 由CFS调度的进程在用户态可见的调度策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FairPolicy {
+    /// SCHED_NORMAL（SCHED_OTHER）
+    #[default]
+    Normal,
+    /// SCHED_BATCH，被唤醒时不抢占当前进程
+    Batch,
+    /// SCHED_IDLE，使用最低的权重，几乎只在cpu空闲时运行
+    Idle,
+}
+
 #[allow(dead_code)]
 pub struct TaskGroup {
     /// CFS管理的调度实体，percpu的
@@ -172,6 +208,8 @@ impl LoadWeight {
     pub const WMULT_CONST: u32 = !0;
 
     pub const NICE_0_LOAD_SHIFT: u32 = Self::SCHED_FIXEDPOINT_SHIFT + Self::SCHED_FIXEDPOINT_SHIFT;
+    /// nice值为0的进程的权重
+    pub const NICE_0_LOAD: u64 = 1 << Self::NICE_0_LOAD_SHIFT;
 
     pub fn update_load_add(&mut self, inc: u64) {
         self.weight += inc;
@@ -263,7 +301,6 @@ impl LoadWeight {
         weight
     }
 
-    #[allow(dead_code)]
     pub const fn scale_load(weight: u64) -> u64 {
         weight << Self::SCHED_FIXEDPOINT_SHIFT
     }
@@ -313,6 +350,9 @@ pub struct CpuRunQueue {
     /// CFS调度器
     cfs: Arc<CfsRunQueue>,
 
+    /// 实时调度器
+    rt: RtRunQueue,
+
     clock_pelt: u64,
     lost_idle_time: u64,
     clock_idle: u64,
@@ -321,6 +361,8 @@ pub struct CpuRunQueue {
 
     /// 最近一次的调度信息
     sched_info: SchedInfo,
+    /// 调度统计信息
+    sched_stat: RqSchedStat,
 
     /// 当前在运行队列上执行的进程
     current: Weak<ProcessControlBlock>,
@@ -345,11 +387,13 @@ impl CpuRunQueue {
             cala_load_update: (clock() + (5 * HZ + 1)) as usize,
             cala_load_active: 0,
             cfs: Arc::new(CfsRunQueue::new()),
+            rt: RtRunQueue::new(),
             clock_pelt: 0,
             lost_idle_time: 0,
             clock_idle: 0,
             cfs_tasks: LinkedList::new(),
             sched_info: SchedInfo::default(),
+            sched_stat: RqSchedStat::default(),
             current: Weak::new(),
             idle: Weak::new(),
         }
@@ -410,8 +454,7 @@ impl CpuRunQueue {
 
         match pcb.sched_info().policy() {
             SchedPolicy::CFS => CompletelyFairScheduler::enqueue(self, pcb, flags),
-            SchedPolicy::FIFO => todo!(),
-            SchedPolicy::RT => todo!(),
+            SchedPolicy::FIFO | SchedPolicy::RT => RealTimeScheduler::enqueue(self, pcb, flags),
             SchedPolicy::IDLE => IdleScheduler::enqueue(self, pcb, flags),
         }
 
@@ -441,8 +484,7 @@ impl CpuRunQueue {
 
         match pcb.sched_info().policy() {
             SchedPolicy::CFS => CompletelyFairScheduler::dequeue(self, pcb, flags),
-            SchedPolicy::FIFO => todo!(),
-            SchedPolicy::RT => todo!(),
+            SchedPolicy::FIFO | SchedPolicy::RT => RealTimeScheduler::dequeue(self, pcb, flags),
             SchedPolicy::IDLE => IdleScheduler::dequeue(self, pcb, flags),
         }
     }
@@ -453,10 +495,7 @@ impl CpuRunQueue {
             flags |= EnqueueFlag::ENQUEUE_MIGRATED;
         }
 
-        if flags.contains(EnqueueFlag::ENQUEUE_MIGRATED) {
-            todo!()
-        }
-
+        self.sched_info_activate(pcb, flags.contains(EnqueueFlag::ENQUEUE_WAKEUP));
         self.enqueue_task(pcb.clone(), flags);
 
         *pcb.sched_info().on_rq.lock_irqsave() = OnRq::Queued;
@@ -466,16 +505,19 @@ impl CpuRunQueue {
     /// 检查对应的task是否可以抢占当前运行的task
     #[allow(clippy::comparison_chain)]
     pub fn check_preempt_currnet(&mut self, pcb: &Arc<ProcessControlBlock>, flags: WakeupFlags) {
-        if pcb.sched_info().policy() == self.current().sched_info().policy() {
-            match self.current().sched_info().policy() {
+        let policy = pcb.sched_info().policy();
+        let curr_policy = self.current().sched_info().policy();
+        if policy == curr_policy || (policy.is_rt() && curr_policy.is_rt()) {
+            match curr_policy {
                 SchedPolicy::CFS => {
                     CompletelyFairScheduler::check_preempt_currnet(self, pcb, flags)
                 }
-                SchedPolicy::FIFO => todo!(),
-                SchedPolicy::RT => todo!(),
+                SchedPolicy::FIFO | SchedPolicy::RT => {
+                    RealTimeScheduler::check_preempt_currnet(self, pcb, flags)
+                }
                 SchedPolicy::IDLE => IdleScheduler::check_preempt_currnet(self, pcb, flags),
             }
-        } else if pcb.sched_info().policy() < self.current().sched_info().policy() {
+        } else if policy < curr_policy && !(policy.is_rt() && self.rt.throttled()) {
             // 调度优先级更高
             self.resched_current();
         }
@@ -657,6 +699,24 @@ impl CpuRunQueue {
         send_resched_ipi(cpu);
     }
 
+    /// 把正在运行的任务放回它的调度类
+    fn put_prev_task(&mut self, prev: Arc<ProcessControlBlock>) {
+        match prev.sched_info().policy() {
+            SchedPolicy::FIFO | SchedPolicy::RT => RealTimeScheduler::put_prev_task(self, prev),
+            SchedPolicy::CFS => CompletelyFairScheduler::put_prev_task(self, prev),
+            SchedPolicy::IDLE => IdleScheduler::put_prev_task(self, prev),
+        }
+    }
+
+    /// 把正在运行的任务重新设置为它的调度类的当前任务
+    fn set_next_task(&mut self, pcb: Arc<ProcessControlBlock>) {
+        match pcb.sched_info().policy() {
+            SchedPolicy::FIFO | SchedPolicy::RT => RealTimeScheduler::set_next_task(self, pcb),
+            SchedPolicy::CFS => CompletelyFairScheduler::set_next_task(self, pcb),
+            SchedPolicy::IDLE => IdleScheduler::set_next_task(self, pcb),
+        }
+    }
+
     /// 选择下一个task
     pub fn pick_next_task(&mut self, prev: Arc<ProcessControlBlock>) -> Arc<ProcessControlBlock> {
         if likely(prev.sched_info().policy() >= SchedPolicy::CFS)
@@ -675,18 +735,21 @@ impl CpuRunQueue {
                 //         .map(|x| x.1.pid)
                 //         .collect::<Vec<_>>()
                 // );
-                match prev.sched_info().policy() {
-                    SchedPolicy::FIFO => todo!(),
-                    SchedPolicy::RT => todo!(),
-                    SchedPolicy::CFS => CompletelyFairScheduler::put_prev_task(self, prev),
-                    SchedPolicy::IDLE => IdleScheduler::put_prev_task(self, prev),
-                }
+                self.put_prev_task(prev);
                 // 选择idle
                 return self.idle.upgrade().unwrap();
             }
         }
 
-        todo!()
+        // 有实时进程，或者上一个进程是实时进程：按照调度类的优先级依次选择
+        self.put_prev_task(prev);
+        if let Some(pcb) = RealTimeScheduler::pick_next_task(self, None) {
+            return pcb;
+        }
+        if let Some(pcb) = CompletelyFairScheduler::pick_next_task(self, None) {
+            return pcb;
+        }
+        return self.idle.upgrade().unwrap();
     }
 }
 
@@ -803,10 +866,11 @@ pub fn scheduler_tick() {
     // 更新请求队列时钟
     rq.update_rq_clock();
 
+    rt::sched_rt_period_tick(rq);
+
     match current.sched_info().policy() {
         SchedPolicy::CFS => CompletelyFairScheduler::tick(rq, current, false),
-        SchedPolicy::FIFO => todo!(),
-        SchedPolicy::RT => todo!(),
+        SchedPolicy::FIFO | SchedPolicy::RT => RealTimeScheduler::tick(rq, current, false),
         SchedPolicy::IDLE => IdleScheduler::tick(rq, current, false),
     }
 
@@ -821,7 +885,7 @@ pub fn scheduler_tick() {
 #[inline]
 pub fn schedule(sched_mod: SchedMode) {
     let _guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
-    assert_eq!(ProcessManager::current_pcb().preempt_count(), 0);
+    preempt::schedule_debug();
     __schedule(sched_mod);
 }
 
@@ -885,6 +949,7 @@ pub fn __schedule(sched_mod: SchedMode) {
     }
 
     let next = rq.pick_next_task(prev.clone());
+    rq.sched_info_switch(&prev, &next);
 
     // kBUG!(
     //     "after cfs rq pcbs {:?}\nvruntimes {:?}\n",
@@ -938,17 +1003,35 @@ pub fn sched_fork(pcb: &Arc<ProcessControlBlock>) -> Result<(), SystemError> {
     let mut prio_guard = pcb.sched_info().prio_data.write_irqsave();
     let current = ProcessManager::current_pcb();
 
-    prio_guard.prio = current.sched_info().prio_data.read_irqsave().normal_prio;
+    // 子进程继承父进程的nice值
+    let parent_prio = current.sched_info().prio_data.read_irqsave();
+    prio_guard.static_prio = parent_prio.static_prio;
+    prio_guard.normal_prio = parent_prio.normal_prio;
+    prio_guard.prio = parent_prio.normal_prio;
+    drop(parent_prio);
 
     if PrioUtil::dl_prio(prio_guard.prio) {
         return Err(SystemError::EAGAIN_OR_EWOULDBLOCK);
     } else if PrioUtil::rt_prio(prio_guard.prio) {
+        // 子进程继承父进程的SCHED_FIFO或者SCHED_RR策略，不继承优先级继承得到的实时调度类
         let policy = &pcb.sched_info().sched_policy;
-        *policy.write_irqsave() = SchedPolicy::RT;
+        *policy.write_irqsave() = current.sched_info().policy();
+        pcb.sched_info()
+            .rt_time_slice
+            .store(RR_TIMESLICE, Ordering::SeqCst);
     } else {
         let policy = &pcb.sched_info().sched_policy;
         *policy.write_irqsave() = SchedPolicy::CFS;
     }
+    drop(prio_guard);
+
+    // 子进程继承父进程的SCHED_NORMAL、SCHED_BATCH或者SCHED_IDLE策略
+    *pcb.sched_info().fair_policy.write_irqsave() = current.sched_info().fair_policy();
+    set_load_weight(pcb, false);
+
+    // 子进程继承父进程允许运行的cpu
+    pcb.sched_info()
+        .set_cpus_allowed(current.sched_info().cpus_allowed());
 
     pcb.sched_info()
         .sched_entity()
@@ -958,16 +1041,278 @@ pub fn sched_fork(pcb: &Arc<ProcessControlBlock>) -> Result<(), SystemError> {
     Ok(())
 }
 
+/// # 根据进程的nice值与调度策略计算它的CFS权重
+pub fn task_load_weight(pcb: &Arc<ProcessControlBlock>) -> u64 {
+    if pcb.sched_info().fair_policy() == FairPolicy::Idle {
+        return LoadWeight::scale_load(WEIGHT_IDLEPRIO);
+    }
+    let static_prio = pcb.sched_info().prio_data.read_irqsave().static_prio;
+    let idx = (static_prio - MAX_RT_PRIO).clamp(0, NICE_WIDTH - 1) as usize;
+    LoadWeight::scale_load(SCHED_PRIO_TO_WEIGHT[idx])
+}
+
+/// # nice值或者调度策略改变之后，更新进程的CFS权重
+///
+/// ## 参数
+///
+/// - `update_load`: 进程可能已经在运行队列中，需要持有运行队列的锁，连同队列的负载与虚拟运行时间一起更新
+pub fn set_load_weight(pcb: &Arc<ProcessControlBlock>, update_load: bool) {
+    let weight = task_load_weight(pcb);
+    let se = pcb.sched_info().sched_entity();
+    if !update_load || pcb.sched_info().policy() != SchedPolicy::CFS {
+        se.force_mut().load.update_load_set(weight);
+        return;
+    }
+
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let rq = cpu_rq(
+        pcb.sched_info()
+            .on_cpu()
+            .unwrap_or(smp_get_processor_id())
+            .data() as usize,
+    );
+    let (rq, guard) = rq.self_lock();
+    rq.update_rq_clock();
+    se.cfs_rq().force_mut().reweight_entity(se.clone(), weight);
+    drop(guard);
+    drop(irq_guard);
+}
+
+/// # 设置进程因优先级继承而得到的优先级
+///
+/// `pi_prio`为等待该进程所持有的PI锁的进程中最高的优先级（数值最小），
+/// 进程的优先级取它与normal_prio中较高的一个；为None时恢复为normal_prio
+///
+/// 被提升到实时优先级的普通进程由实时调度类按照SCHED_FIFO调度，恢复之后回到CFS
+pub fn rt_mutex_setprio(pcb: &Arc<ProcessControlBlock>, pi_prio: Option<i32>) {
+    let prio_guard = pcb.sched_info().prio_data.read_irqsave();
+    let prio = match pi_prio {
+        Some(prio) => prio.min(prio_guard.normal_prio),
+        None => prio_guard.normal_prio,
+    };
+    if prio == prio_guard.prio {
+        return;
+    }
+    drop(prio_guard);
+
+    sched_change(pcb, || {
+        pcb.sched_info().prio_data.write_irqsave().prio = prio;
+        let mut policy = pcb.sched_info().sched_policy.write_irqsave();
+        if !PrioUtil::rt_prio(prio) {
+            *policy = SchedPolicy::CFS;
+        } else if !policy.is_rt() {
+            *policy = SchedPolicy::FIFO;
+        }
+    });
+}
+
+/// # 修改进程的调度类或者优先级
+///
+/// 进程可能正在运行或者在运行队列中：先把它从原来的调度类中取出，调用`change`修改之后，
+/// 再放回新的调度类，最后检查是否需要抢占
+pub fn sched_change<F: FnOnce()>(pcb: &Arc<ProcessControlBlock>, change: F) {
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let rq = cpu_rq(
+        pcb.sched_info()
+            .on_cpu()
+            .unwrap_or(smp_get_processor_id())
+            .data() as usize,
+    );
+    let (rq, guard) = rq.self_lock();
+    rq.update_rq_clock();
+
+    let queued = *pcb.sched_info().on_rq.lock_irqsave() == OnRq::Queued;
+    let running = Arc::ptr_eq(&rq.current(), pcb);
+    if queued {
+        rq.dequeue_task(
+            pcb.clone(),
+            DequeueFlag::DEQUEUE_SAVE | DequeueFlag::DEQUEUE_MOVE | DequeueFlag::DEQUEUE_NOCLOCK,
+        );
+    }
+    if running {
+        rq.put_prev_task(pcb.clone());
+    }
+
+    change();
+
+    if queued {
+        rq.enqueue_task(
+            pcb.clone(),
+            EnqueueFlag::ENQUEUE_RESTORE | EnqueueFlag::ENQUEUE_MOVE | EnqueueFlag::ENQUEUE_NOCLOCK,
+        );
+    }
+    if running {
+        rq.set_next_task(pcb.clone());
+        // 优先级可能降低了，重新选择
+        rq.resched_current();
+    } else if queued {
+        rq.check_preempt_currnet(pcb, WakeupFlags::empty());
+    }
+
+    drop(guard);
+    drop(irq_guard);
+}
+
 pub fn sched_cgroup_fork(pcb: &Arc<ProcessControlBlock>) {
     __set_task_cpu(pcb, smp_get_processor_id());
     match pcb.sched_info().policy() {
-        SchedPolicy::RT => todo!(),
-        SchedPolicy::FIFO => todo!(),
+        SchedPolicy::RT | SchedPolicy::FIFO => RealTimeScheduler::task_fork(pcb.clone()),
         SchedPolicy::CFS => CompletelyFairScheduler::task_fork(pcb.clone()),
         SchedPolicy::IDLE => todo!(),
     }
 }
 
+/// 进程实际可以运行的cpu：允许运行的cpu中在线的cpu
+pub fn task_allowed_cpus(pcb: &Arc<ProcessControlBlock>) -> CpuMask {
+    &pcb.sched_info().cpus_allowed() & &smp_cpu_manager().online_cpus()
+}
+
+/// # 为被唤醒或者新创建的进程选择cpu
+///
+/// 上次运行的cpu仍然被允许时留在原来的cpu上，否则选择允许的cpu中运行队列最短的
+pub fn select_task_rq(pcb: &Arc<ProcessControlBlock>) -> ProcessorId {
+    let prev = pcb.sched_info().on_cpu().unwrap_or(smp_get_processor_id());
+    // 空闲进程固定在自己的cpu上
+    if pcb.sched_info().policy() == SchedPolicy::IDLE {
+        return prev;
+    }
+    let mut allowed = task_allowed_cpus(pcb);
+    if allowed.is_empty() {
+        allowed = select_fallback_cpus(pcb, prev);
+    }
+    if allowed.get(prev).unwrap_or(false) {
+        return prev;
+    }
+    allowed
+        .iter_cpu()
+        .min_by_key(|cpu| cpu_rq(cpu.data() as usize).nr_running)
+        .unwrap_or(prev)
+}
+
+/// # 进程允许运行的cpu都已经离线时，与Linux相同，允许进程在所有cpu上运行
+fn select_fallback_cpus(pcb: &Arc<ProcessControlBlock>, prev: ProcessorId) -> CpuMask {
+    info!(
+        "process {} ({}) no longer affine to cpu{}",
+        pcb.pid().data(),
+        pcb.basic().name(),
+        prev.data()
+    );
+    pcb.sched_info()
+        .set_cpus_allowed(smp_cpu_manager().possible_cpus().clone());
+    smp_cpu_manager().online_cpus()
+}
+
+/// # 唤醒进程时选择运行队列
+///
+/// 进程需要离开原来的cpu时，先获取原来的运行队列的锁，等待原来的cpu把进程切换出去：
+/// 如果进程还在原来的cpu上运行（标记了睡眠但还没有调用schedule），仍然在原来的cpu上唤醒；
+/// 否则进程的上下文已经保存好，可以放到新的cpu的运行队列中
+pub fn select_wakeup_cpu(pcb: &Arc<ProcessControlBlock>) -> ProcessorId {
+    let cpu = select_task_rq(pcb);
+    let prev = match pcb.sched_info().on_cpu() {
+        Some(prev) if prev != cpu => prev,
+        _ => return cpu,
+    };
+
+    let rq = cpu_rq(prev.data() as usize);
+    let (rq, guard) = rq.self_lock();
+    if Arc::ptr_eq(&rq.current(), pcb) {
+        return prev;
+    }
+    drop(guard);
+
+    __set_task_cpu(pcb, cpu);
+    cpu
+}
+
+/// # 修改进程允许运行的cpu
+///
+/// 进程当前所在的cpu不再被允许时，把它迁移到允许的cpu上，见`migrate_task`
+pub fn set_cpus_allowed(pcb: &Arc<ProcessControlBlock>, mask: CpuMask) -> Result<(), SystemError> {
+    let mask = &mask & smp_cpu_manager().possible_cpus();
+    if (&mask & &smp_cpu_manager().online_cpus()).is_empty() {
+        return Err(SystemError::EINVAL);
+    }
+    pcb.sched_info().set_cpus_allowed(mask);
+    migrate_task(pcb);
+    Ok(())
+}
+
+/// # 进程所在的cpu不再被允许（或者已经离线）时，把它迁移到其他cpu
+///
+/// 在运行队列中等待的进程直接迁移到新的cpu；正在运行的进程在返回用户态之前迁移（见`migrate_current`）；
+/// 睡眠的进程在被唤醒时迁移
+fn migrate_task(pcb: &Arc<ProcessControlBlock>) {
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    let prev = match pcb.sched_info().on_cpu() {
+        Some(prev) if !task_allowed_cpus(pcb).get(prev).unwrap_or(false) => prev,
+        _ => return,
+    };
+
+    let rq = cpu_rq(prev.data() as usize);
+    let (rq, guard) = rq.self_lock();
+    if Arc::ptr_eq(&rq.current(), pcb) {
+        pcb.flags().insert(ProcessFlags::NEED_MIGRATE);
+        drop(guard);
+        drop(irq_guard);
+        if !Arc::ptr_eq(pcb, &ProcessManager::current_pcb()) {
+            ProcessManager::kick(pcb);
+        }
+        return;
+    }
+    if *pcb.sched_info().on_rq.lock_irqsave() != OnRq::Queued {
+        return;
+    }
+
+    rq.update_rq_clock();
+    rq.deactivate_task(pcb.clone(), DequeueFlag::DEQUEUE_NOCLOCK);
+    drop(guard);
+
+    let cpu = select_task_rq(pcb);
+    __set_task_cpu(pcb, cpu);
+    let rq = cpu_rq(cpu.data() as usize);
+    let (rq, guard) = rq.self_lock();
+    rq.update_rq_clock();
+    rq.activate_task(pcb, EnqueueFlag::ENQUEUE_NOCLOCK);
+    rq.check_preempt_currnet(pcb, WakeupFlags::empty());
+    drop(guard);
+    drop(irq_guard);
+}
+
+/// # 当前进程所在的cpu不再被允许时，迁移到允许的cpu上
+///
+/// 当前进程不能直接把自己放到其他cpu的运行队列中（在切换出去之前就可能被其他cpu选中运行），
+/// 因此先睡眠，切换完成之后由定时器唤醒，唤醒时选择新的cpu
+pub fn migrate_current() {
+    let pcb = ProcessManager::current_pcb();
+    pcb.flags().remove(ProcessFlags::NEED_MIGRATE);
+    if task_allowed_cpus(&pcb)
+        .get(smp_get_processor_id())
+        .unwrap_or(false)
+    {
+        return;
+    }
+    schedule_timeout(1).ok();
+}
+
+/// # cpu上线的最后一步：调度器可以把进程放到该cpu上
+pub fn sched_cpu_activate(cpu: ProcessorId) -> Result<(), SystemError> {
+    smp_cpu_manager().set_cpu_online(cpu, true);
+    Ok(())
+}
+
+/// # cpu离线的第一步：调度器不再把进程放到该cpu上，并把该cpu上的进程迁移到其他cpu
+pub fn sched_cpu_deactivate(cpu: ProcessorId) -> Result<(), SystemError> {
+    smp_cpu_manager().set_cpu_online(cpu, false);
+    for pcb in ProcessManager::all_processes() {
+        if pcb.sched_info().on_cpu() == Some(cpu) && pcb.sched_info().policy() != SchedPolicy::IDLE
+        {
+            migrate_task(&pcb);
+        }
+    }
+    Ok(())
+}
+
 fn __set_task_cpu(pcb: &Arc<ProcessControlBlock>, cpu: ProcessorId) {
     // TODO: Fixme There is not implement group sched;
     let se = pcb.sched_info().sched_entity();
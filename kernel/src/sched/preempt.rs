@@ -0,0 +1,203 @@
+//! 内核抢占
+//!
+//! 进程的`preempt_count`不为0时（持有自旋锁、正在处理软中断等），当前进程不能被抢占。
+//!
+//! 无论是否启用`preempt` feature，从中断返回时（不管返回用户态还是内核态），
+//! 只要`preempt_count`为0，并且这是时钟中断或者当前进程需要被调度，就会进行调度。
+//!
+//! `preempt` feature（相当于Linux的CONFIG_PREEMPT）默认不启用。启用之后，
+//! `preempt_count`减为0时，如果当前进程需要被调度并且中断是开启的，还会立即进行调度，
+//! 而不必等到下一次中断。
+//!
+//! 启用`debug_atomic_sleep` feature之后，会记录每个CPU上最近一次关闭抢占的位置，
+//! 在原子上下文中调度时把它与调用栈一起打印出来，便于找到没有释放的锁。
+//! 同时，可能睡眠的函数（互斥锁、等待队列等）会通过[`might_sleep`]检查调用者是否处于原子上下文，
+//! 这样即使这一次没有真正睡眠，也能尽早发现问题。
+
+#[cfg(feature = "debug_atomic_sleep")]
+use core::{
+    panic::Location,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use core::intrinsics::likely;
+
+#[cfg(feature = "debug_atomic_sleep")]
+use log::error;
+
+#[cfg(feature = "debug_atomic_sleep")]
+use crate::{
+    debug::traceback::dump_stack, mm::percpu::PerCpu, process::ProcessManager,
+    smp::core::smp_get_processor_id,
+};
+
+use crate::{
+    arch::CurrentIrqArch,
+    exception::InterruptArch,
+    process::{
+        utils::{current_pcb_flags, current_pcb_preempt_count},
+        ProcessFlags,
+    },
+};
+
+use super::{SchedMode, __schedule};
+
+/// 每个CPU上最近一次把`preempt_count`从0变为1的位置
+#[cfg(feature = "debug_atomic_sleep")]
+static PREEMPT_OFF_LOCATION: [AtomicPtr<Location<'static>>; PerCpu::MAX_CPU_NUM as usize] =
+    [const { AtomicPtr::new(core::ptr::null_mut()) }; PerCpu::MAX_CPU_NUM as usize];
+
+/// 系统启动完成、开始调度之前，中断是关闭的，这时不检查是否在原子上下文中睡眠
+#[cfg(feature = "debug_atomic_sleep")]
+static MIGHT_SLEEP_CHECK_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 最多报告多少次在原子上下文中睡眠，避免刷屏
+#[cfg(feature = "debug_atomic_sleep")]
+const MAX_ATOMIC_SLEEP_REPORTS: usize = 16;
+
+#[cfg(feature = "debug_atomic_sleep")]
+static ATOMIC_SLEEP_REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+/// 当前进程能否被抢占
+#[inline(always)]
+pub fn preemptible() -> bool {
+    current_pcb_preempt_count() == 0 && CurrentIrqArch::is_irq_enabled()
+}
+
+/// 在`preempt_count`减为0之后调用，当前进程需要被调度时进行调度
+///
+/// 未启用`preempt` feature时什么也不做
+#[inline(always)]
+pub fn preempt_check_resched() {
+    #[cfg(feature = "preempt")]
+    if core::intrinsics::unlikely(current_pcb_flags().contains(ProcessFlags::NEED_SCHEDULE))
+        && preemptible()
+    {
+        preempt_schedule();
+    }
+}
+
+#[cfg(feature = "preempt")]
+#[inline(never)]
+fn preempt_schedule() {
+    super::schedule(SchedMode::SM_PREEMPT);
+}
+
+/// 中断处理完毕、即将返回被中断的现场时调用，判断是否需要调度
+///
+/// ## 参数
+///
+/// - `tick`: 是否是时钟中断，时钟中断总是尝试调度
+pub fn irq_exit_resched(tick: bool) {
+    if current_pcb_preempt_count() > 0 {
+        return;
+    }
+    if tick || current_pcb_flags().contains(ProcessFlags::NEED_SCHEDULE) {
+        __schedule(SchedMode::SM_PREEMPT);
+    }
+}
+
+/// 记录关闭抢占的位置
+///
+/// 在`preempt_count`从0变为1时调用
+#[cfg(feature = "debug_atomic_sleep")]
+#[inline(always)]
+pub fn record_preempt_off(location: &'static Location<'static>) {
+    PREEMPT_OFF_LOCATION[smp_get_processor_id().data() as usize]
+        .store(location as *const _ as *mut _, Ordering::Relaxed);
+}
+
+/// 开始检查是否在原子上下文中睡眠
+///
+/// 在启动过程的最后、开启调度时调用
+pub fn might_sleep_check_enable() {
+    #[cfg(feature = "debug_atomic_sleep")]
+    MIGHT_SLEEP_CHECK_ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// 声明调用者可能会睡眠
+///
+/// 启用`debug_atomic_sleep` feature时，如果当前关闭了中断或者抢占，就打印调用位置、
+/// 关闭抢占的位置以及调用栈。未启用时什么也不做
+#[inline(always)]
+#[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
+pub fn might_sleep() {
+    #[cfg(feature = "debug_atomic_sleep")]
+    __might_sleep(0, true, Location::caller());
+}
+
+/// 与[`might_sleep`]相同，但是允许调用者持有`preempt_offset`把锁
+///
+/// 用于“释放锁并睡眠”的函数：调用者持有的锁会在真正睡眠之前释放。
+/// 这些锁可能关闭了中断，因此不检查中断是否开启
+#[inline(always)]
+#[cfg_attr(feature = "debug_atomic_sleep", track_caller)]
+pub fn might_sleep_offset(_preempt_offset: usize) {
+    #[cfg(feature = "debug_atomic_sleep")]
+    __might_sleep(_preempt_offset, false, Location::caller());
+}
+
+#[cfg(feature = "debug_atomic_sleep")]
+#[inline(never)]
+fn __might_sleep(preempt_offset: usize, check_irq: bool, location: &'static Location<'static>) {
+    if !MIGHT_SLEEP_CHECK_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let count = current_pcb_preempt_count();
+    let irq_enabled = CurrentIrqArch::is_irq_enabled();
+    if likely(count <= preempt_offset && (irq_enabled || !check_irq)) {
+        return;
+    }
+    if ATOMIC_SLEEP_REPORTS.fetch_add(1, Ordering::Relaxed) >= MAX_ATOMIC_SLEEP_REPORTS {
+        return;
+    }
+
+    let pcb = ProcessManager::current_pcb();
+    error!(
+        "BUG: sleeping function called from invalid context at {}",
+        location
+    );
+    error!(
+        "in_atomic(): {}, irqs_disabled(): {}, pid: {:?}, name: {}, preempt_count: {}",
+        count > preempt_offset,
+        !irq_enabled,
+        pcb.pid(),
+        pcb.basic().name(),
+        count
+    );
+    if count > preempt_offset {
+        let off =
+            PREEMPT_OFF_LOCATION[smp_get_processor_id().data() as usize].load(Ordering::Relaxed);
+        if !off.is_null() {
+            error!("Preemption disabled at: {}", unsafe { &*off });
+        }
+    }
+    dump_stack();
+}
+
+/// 调度之前的检查：不能在关闭了抢占的情况下调度
+pub(super) fn schedule_debug() {
+    let count = current_pcb_preempt_count();
+    if likely(count == 0) {
+        return;
+    }
+
+    #[cfg(feature = "debug_atomic_sleep")]
+    {
+        let pcb = ProcessManager::current_pcb();
+        error!(
+            "BUG: scheduling while atomic: {}/{:?}, preempt_count: {}",
+            pcb.basic().name(),
+            pcb.pid(),
+            count
+        );
+        let location =
+            PREEMPT_OFF_LOCATION[smp_get_processor_id().data() as usize].load(Ordering::Relaxed);
+        if !location.is_null() {
+            error!("Preemption disabled at: {}", unsafe { &*location });
+        }
+        dump_stack();
+    }
+
+    panic!("schedule() called with preempt_count {}", count);
+}
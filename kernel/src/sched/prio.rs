@@ -1,13 +1,26 @@
-pub const MAX_NICE: i32 = 20;
+pub const MAX_NICE: i32 = 19;
 pub const MIN_NICE: i32 = -20;
 pub const NICE_WIDTH: i32 = MAX_NICE - MIN_NICE + 1;
 
 pub const MAX_RT_PRIO: i32 = 100;
 pub const MAX_PRIO: i32 = MAX_RT_PRIO + NICE_WIDTH;
-#[allow(dead_code)]
 pub const DEFAULT_PRIO: i32 = MAX_RT_PRIO + NICE_WIDTH / 2;
 
 pub const MAX_DL_PRIO: i32 = 0;
+
+/// # nice值-20..19对应的CFS权重
+///
+/// nice值每增加1，进程得到的cpu时间约减少10%，相邻两项的比值约为1.25，nice 0对应1024。
+/// 下标为`static_prio - MAX_RT_PRIO`
+pub const SCHED_PRIO_TO_WEIGHT: [u64; NICE_WIDTH as usize] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110, 87,
+    70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// SCHED_IDLE进程的权重，远小于nice 19
+pub const WEIGHT_IDLEPRIO: u64 = 3;
+
 pub struct PrioUtil;
 #[allow(dead_code)]
 impl PrioUtil {
@@ -0,0 +1,306 @@
+//! 实时调度类（SCHED_FIFO与SCHED_RR）
+//!
+//! 每个cpu有一个实时运行队列，优先级0..99（数值越小越高，对应用户态的sched_priority 99..1）各有一个队列，
+//! 总是运行优先级最高的非空队列头部的进程，正在运行的进程仍然留在队列中。
+//! SCHED_FIFO的进程一直运行到阻塞、主动让出或者被更高优先级的进程抢占；
+//! SCHED_RR的进程用完时间片之后排到同优先级队列的尾部。
+//!
+//! 为了避免实时进程饿死其他进程，每个周期（`sched_rt_period_us`）内，一个cpu上的实时进程
+//! 最多运行`sched_rt_runtime_us`，超出之后被限流，直到下一个周期开始。`sched_rt_runtime_us`为-1时不限流。
+//!
+//! 参考 https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/sched/rt.c
+
+use core::sync::atomic::{AtomicI64, Ordering};
+
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec::Vec};
+use system_error::SystemError;
+
+use crate::{
+    process::ProcessControlBlock,
+    time::{clocksource::HZ, NSEC_PER_USEC},
+};
+
+use super::{
+    prio::MAX_RT_PRIO, CpuRunQueue, DequeueFlag, EnqueueFlag, SchedPolicy, Scheduler, WakeupFlags,
+};
+
+/// SCHED_RR的时间片（tick数），100ms
+pub const RR_TIMESLICE: isize = (100 * HZ / 1000) as isize;
+
+/// 实时进程限流的周期（us）
+static SCHED_RT_PERIOD_US: AtomicI64 = AtomicI64::new(1_000_000);
+/// 每个周期内实时进程最多运行的时间（us），-1表示不限流
+static SCHED_RT_RUNTIME_US: AtomicI64 = AtomicI64::new(950_000);
+
+/// 每个cpu的实时运行队列
+#[derive(Debug)]
+pub struct RtRunQueue {
+    /// 每个优先级的进程队列，下标为进程的prio
+    queues: Vec<VecDeque<Arc<ProcessControlBlock>>>,
+    /// 第i位为1表示优先级为i的队列非空
+    bitmap: u128,
+    /// 队列中的进程数
+    rt_nr_running: usize,
+    /// 本周期内实时进程已经运行的时间（ns）
+    rt_time: u64,
+    /// 本周期开始的时间（ns）
+    period_start: u64,
+    /// 是否因为运行时间超出限额而被限流
+    rt_throttled: bool,
+}
+
+impl RtRunQueue {
+    pub fn new() -> Self {
+        Self {
+            queues: (0..MAX_RT_PRIO).map(|_| VecDeque::new()).collect(),
+            bitmap: 0,
+            rt_nr_running: 0,
+            rt_time: 0,
+            period_start: 0,
+            rt_throttled: false,
+        }
+    }
+
+    #[inline]
+    pub fn throttled(&self) -> bool {
+        self.rt_throttled
+    }
+
+    fn enqueue(&mut self, pcb: Arc<ProcessControlBlock>, prio: usize) {
+        self.queues[prio].push_back(pcb);
+        self.bitmap |= 1 << prio;
+        self.rt_nr_running += 1;
+    }
+
+    fn dequeue(&mut self, pcb: &Arc<ProcessControlBlock>, prio: usize) {
+        let queue = &mut self.queues[prio];
+        if let Some(idx) = queue.iter().position(|p| Arc::ptr_eq(p, pcb)) {
+            queue.remove(idx);
+            self.rt_nr_running -= 1;
+        }
+        if queue.is_empty() {
+            self.bitmap &= !(1 << prio);
+        }
+    }
+
+    /// 把进程移到同优先级队列的尾部
+    fn requeue(&mut self, pcb: &Arc<ProcessControlBlock>, prio: usize) {
+        let queue = &mut self.queues[prio];
+        if let Some(idx) = queue.iter().position(|p| Arc::ptr_eq(p, pcb)) {
+            let pcb = queue.remove(idx).unwrap();
+            queue.push_back(pcb);
+        }
+    }
+
+    /// 优先级最高的非空队列的头部
+    fn first(&self) -> Option<Arc<ProcessControlBlock>> {
+        if self.bitmap == 0 {
+            return None;
+        }
+        let prio = self.bitmap.trailing_zeros() as usize;
+        self.queues[prio].front().cloned()
+    }
+}
+
+impl Default for RtRunQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct RealTimeScheduler;
+
+impl RealTimeScheduler {
+    /// 进程在实时运行队列中的下标
+    fn task_prio(pcb: &Arc<ProcessControlBlock>) -> usize {
+        let prio = pcb.sched_info().prio_data.read_irqsave().prio;
+        prio.clamp(0, MAX_RT_PRIO - 1) as usize
+    }
+
+    /// 更新当前实时进程的运行时间，并计入本周期的实时运行时间
+    fn update_curr(rq: &mut CpuRunQueue) {
+        let curr = rq.current();
+        if !curr.sched_info().policy().is_rt() {
+            return;
+        }
+
+        let now = rq.clock_task();
+        let se = curr.sched_info().sched_entity();
+        if now <= se.exec_start {
+            return;
+        }
+        let delta_exec = now - se.exec_start;
+        let se = se.force_mut();
+        se.exec_start = now;
+        se.sum_exec_runtime += delta_exec;
+
+        let runtime = SCHED_RT_RUNTIME_US.load(Ordering::SeqCst);
+        if runtime < 0 {
+            return;
+        }
+        rq.rt.rt_time += delta_exec;
+        if !rq.rt.rt_throttled && rq.rt.rt_time > runtime as u64 * NSEC_PER_USEC as u64 {
+            rq.rt.rt_throttled = true;
+            rq.resched_current();
+        }
+    }
+}
+
+impl Scheduler for RealTimeScheduler {
+    fn enqueue(rq: &mut CpuRunQueue, pcb: Arc<ProcessControlBlock>, _flags: EnqueueFlag) {
+        let prio = Self::task_prio(&pcb);
+        rq.rt.enqueue(pcb, prio);
+        rq.add_nr_running(1);
+    }
+
+    fn dequeue(rq: &mut CpuRunQueue, pcb: Arc<ProcessControlBlock>, _flags: DequeueFlag) {
+        Self::update_curr(rq);
+        let prio = Self::task_prio(&pcb);
+        rq.rt.dequeue(&pcb, prio);
+        rq.sub_nr_running(1);
+    }
+
+    fn yield_task(rq: &mut CpuRunQueue) {
+        let curr = rq.current();
+        let prio = Self::task_prio(&curr);
+        rq.rt.requeue(&curr, prio);
+    }
+
+    fn check_preempt_currnet(
+        rq: &mut CpuRunQueue,
+        pcb: &Arc<ProcessControlBlock>,
+        _flags: WakeupFlags,
+    ) {
+        if Self::task_prio(pcb) < Self::task_prio(&rq.current()) {
+            rq.resched_current();
+        }
+    }
+
+    fn pick_task(rq: &mut CpuRunQueue) -> Option<Arc<ProcessControlBlock>> {
+        if rq.rt.rt_throttled {
+            return None;
+        }
+        rq.rt.first()
+    }
+
+    fn pick_next_task(
+        rq: &mut CpuRunQueue,
+        _pcb: Option<Arc<ProcessControlBlock>>,
+    ) -> Option<Arc<ProcessControlBlock>> {
+        let next = Self::pick_task(rq)?;
+        Self::set_next_task(rq, next.clone());
+        Some(next)
+    }
+
+    fn tick(rq: &mut CpuRunQueue, pcb: Arc<ProcessControlBlock>, _queued: bool) {
+        Self::update_curr(rq);
+
+        // SCHED_FIFO没有时间片
+        if pcb.sched_info().policy() != SchedPolicy::RT {
+            return;
+        }
+        let slice = &pcb.sched_info().rt_time_slice;
+        if slice.fetch_sub(1, Ordering::SeqCst) > 1 {
+            return;
+        }
+        slice.store(RR_TIMESLICE, Ordering::SeqCst);
+
+        // 同优先级还有其他进程时，排到队列尾部
+        let prio = Self::task_prio(&pcb);
+        if rq.rt.queues[prio].len() > 1 {
+            rq.rt.requeue(&pcb, prio);
+            rq.resched_current();
+        }
+    }
+
+    fn task_fork(_pcb: Arc<ProcessControlBlock>) {
+        // 时间片已经在sched_fork中重置
+    }
+
+    fn put_prev_task(rq: &mut CpuRunQueue, _prev: Arc<ProcessControlBlock>) {
+        Self::update_curr(rq);
+    }
+
+    fn set_next_task(rq: &mut CpuRunQueue, pcb: Arc<ProcessControlBlock>) {
+        pcb.sched_info().sched_entity().force_mut().exec_start = rq.clock_task();
+    }
+}
+
+/// # 时钟tick时检查实时限流的周期是否结束
+///
+/// 新周期开始时，已经运行的时间减去经过的周期数对应的限额，不再超额的运行队列解除限流
+pub fn sched_rt_period_tick(rq: &mut CpuRunQueue) {
+    let period = SCHED_RT_PERIOD_US.load(Ordering::SeqCst) as u64 * NSEC_PER_USEC as u64;
+    let elapsed = rq.clock.saturating_sub(rq.rt.period_start);
+    if elapsed < period {
+        return;
+    }
+    let overrun = elapsed / period;
+    rq.rt.period_start += overrun * period;
+
+    let runtime = SCHED_RT_RUNTIME_US.load(Ordering::SeqCst);
+    if runtime < 0 {
+        rq.rt.rt_time = 0;
+    } else {
+        let runtime = runtime as u64 * NSEC_PER_USEC as u64;
+        rq.rt.rt_time = rq
+            .rt
+            .rt_time
+            .saturating_sub(overrun.saturating_mul(runtime));
+        if rq.rt.rt_time >= runtime {
+            return;
+        }
+    }
+
+    if rq.rt.rt_throttled {
+        rq.rt.rt_throttled = false;
+        if rq.rt.rt_nr_running > 0 {
+            rq.resched_current();
+        }
+    }
+}
+
+/// `/proc/sys/kernel/sched_rt_period_us`的内容
+pub fn sched_rt_period_show() -> String {
+    alloc::format!("{}\n", SCHED_RT_PERIOD_US.load(Ordering::SeqCst))
+}
+
+/// `/proc/sys/kernel/sched_rt_runtime_us`的内容
+pub fn sched_rt_runtime_show() -> String {
+    alloc::format!("{}\n", SCHED_RT_RUNTIME_US.load(Ordering::SeqCst))
+}
+
+fn parse_sysctl(buf: &[u8]) -> Result<i64, SystemError> {
+    core::str::from_utf8(buf)
+        .map_err(|_| SystemError::EINVAL)?
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| SystemError::EINVAL)
+}
+
+/// # 写入`/proc/sys/kernel/sched_rt_period_us`
+///
+/// 周期必须为正数，并且不小于当前的`sched_rt_runtime_us`
+pub fn write_sched_rt_period(buf: &[u8]) -> Result<usize, SystemError> {
+    let period = parse_sysctl(buf)?;
+    if period <= 0 || period > i32::MAX as i64 {
+        return Err(SystemError::EINVAL);
+    }
+    if SCHED_RT_RUNTIME_US.load(Ordering::SeqCst) > period {
+        return Err(SystemError::EINVAL);
+    }
+    SCHED_RT_PERIOD_US.store(period, Ordering::SeqCst);
+    Ok(buf.len())
+}
+
+/// # 写入`/proc/sys/kernel/sched_rt_runtime_us`
+///
+/// 限额为-1（不限流）或者0到`sched_rt_period_us`之间的数
+pub fn write_sched_rt_runtime(buf: &[u8]) -> Result<usize, SystemError> {
+    let runtime = parse_sysctl(buf)?;
+    if runtime < -1 || runtime > SCHED_RT_PERIOD_US.load(Ordering::SeqCst) {
+        return Err(SystemError::EINVAL);
+    }
+    SCHED_RT_RUNTIME_US.store(runtime, Ordering::SeqCst);
+    Ok(buf.len())
+}
@@ -0,0 +1,196 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+};
+
+use crate::{
+    process::ProcessControlBlock,
+    smp::{core::smp_get_processor_id, cpu::smp_cpu_manager},
+    time::timer::clock,
+};
+
+use super::{cpu_rq, syscall::user_policy, CpuRunQueue, OnRq, SchedPolicy};
+
+/// /proc/schedstat的格式版本，与Linux一致
+pub const SCHEDSTAT_VERSION: u32 = 15;
+
+/// 等待时间直方图的桶数
+///
+/// 第0个桶统计小于1us的等待，第i个桶统计[2^(i-1), 2^i)us的等待，最后一个桶统计更长的等待
+pub const WAIT_HIST_BUCKETS: usize = 16;
+
+/// 运行队列的调度统计信息
+#[derive(Debug, Default)]
+pub struct RqSchedStat {
+    /// schedule()被调用的次数
+    pub sched_count: u64,
+    /// schedule()选择了idle进程的次数
+    pub sched_goidle: u64,
+    /// 唤醒到这个运行队列上的次数
+    pub ttwu_count: u64,
+    /// 由本cpu发起的唤醒次数
+    pub ttwu_local: u64,
+    /// 从其他cpu迁移到这个运行队列上的次数
+    pub nr_migrations: u64,
+    /// 进程在这个cpu上运行的总时间（ns）
+    pub rq_cpu_time: u64,
+    /// 进程在运行队列上等待时间的直方图
+    pub wait_hist: [u64; WAIT_HIST_BUCKETS],
+}
+
+impl RqSchedStat {
+    /// 记录一次等待时间（ns）
+    fn record_wait(&mut self, delta: u64) {
+        let us = delta / 1000;
+        let bucket = if us == 0 {
+            0
+        } else {
+            (64 - us.leading_zeros() as usize).min(WAIT_HIST_BUCKETS - 1)
+        };
+        self.wait_hist[bucket] += 1;
+    }
+}
+
+impl CpuRunQueue {
+    /// 进程被唤醒并加入运行队列时的统计
+    pub(super) fn sched_info_activate(&mut self, pcb: &Arc<ProcessControlBlock>, wakeup: bool) {
+        if wakeup {
+            self.sched_stat.ttwu_count += 1;
+            if self.cpu == smp_get_processor_id() {
+                self.sched_stat.ttwu_local += 1;
+            }
+        }
+
+        if pcb.sched_info().on_cpu().is_some_and(|cpu| cpu != self.cpu) {
+            self.sched_stat.nr_migrations += 1;
+            pcb.sched_info().sched_stat.write_irqsave().nr_migrations += 1;
+        }
+    }
+
+    /// # 进程切换时的统计
+    ///
+    /// 统计prev在cpu上运行的时间，以及next在运行队列上等待的时间。
+    /// prev被抢占而仍在运行队列上时，从现在开始计算它的等待时间
+    pub(super) fn sched_info_switch(
+        &mut self,
+        prev: &Arc<ProcessControlBlock>,
+        next: &Arc<ProcessControlBlock>,
+    ) {
+        self.sched_stat.sched_count += 1;
+        if next.sched_info().policy() == SchedPolicy::IDLE {
+            self.sched_stat.sched_goidle += 1;
+        }
+        if Arc::ptr_eq(prev, next) {
+            return;
+        }
+
+        if prev.sched_info().policy() != SchedPolicy::IDLE {
+            let mut stat = prev.sched_info().sched_stat.write_irqsave();
+            self.sched_stat.rq_cpu_time += self.clock.saturating_sub(stat.last_arrival);
+            if *prev.sched_info().on_rq.lock_irqsave() == OnRq::Queued && stat.last_queued == 0 {
+                stat.last_queued = self.clock;
+            }
+        }
+
+        if next.sched_info().policy() != SchedPolicy::IDLE {
+            let mut stat = next.sched_info().sched_stat.write_irqsave();
+            if stat.last_queued > 0 {
+                let delta = self.clock.saturating_sub(stat.last_queued);
+                stat.last_queued = 0;
+                stat.run_delay += delta as usize;
+                stat.wait_max = stat.wait_max.max(delta);
+                self.sched_info.run_delay += delta as usize;
+                self.sched_stat.record_wait(delta);
+            }
+            stat.last_arrival = self.clock;
+            stat.pcount += 1;
+            self.sched_info.pcount += 1;
+        }
+    }
+}
+
+/// # 生成/proc/schedstat的内容
+///
+/// 每个cpu一行，前9个字段与Linux的第15版格式相同：
+/// `cpu<N> yld_count 0 sched_count sched_goidle ttwu_count ttwu_local rq_cpu_time run_delay pcount`，
+/// 之后依次为nr_running、nr_uninterruptible和nr_migrations。
+/// 每个cpu行之后跟一行`wait_hist_us`，为等待时间直方图
+pub fn schedstat_procfs_show() -> String {
+    let mut s = format!("version {}\ntimestamp {}\n", SCHEDSTAT_VERSION, clock());
+    for cpu in smp_cpu_manager().present_cpus().iter_cpu() {
+        let rq = cpu_rq(cpu.data() as usize);
+        let stat = &rq.sched_stat;
+        s.push_str(&format!(
+            "cpu{} 0 0 {} {} {} {} {} {} {} {} {} {}\n",
+            cpu.data(),
+            stat.sched_count,
+            stat.sched_goidle,
+            stat.ttwu_count,
+            stat.ttwu_local,
+            stat.rq_cpu_time,
+            rq.sched_info.run_delay,
+            rq.sched_info.pcount,
+            rq.nr_running,
+            rq.nr_uninterruptible,
+            stat.nr_migrations,
+        ));
+        s.push_str("wait_hist_us");
+        for count in stat.wait_hist.iter() {
+            s.push_str(&format!(" {}", count));
+        }
+        s.push('\n');
+    }
+    s
+}
+
+/// 以毫秒为单位输出纳秒，保留6位小数，与Linux的/proc/[pid]/sched相同
+fn fmt_nsec(name: &str, nsec: u64) -> String {
+    format!(
+        "{:<45}:{:>14}.{:06}\n",
+        name,
+        nsec / 1_000_000,
+        nsec % 1_000_000
+    )
+}
+
+fn fmt_int(name: &str, val: impl ToString) -> String {
+    format!("{:<45}:{:>21}\n", name, val.to_string())
+}
+
+/// 生成/proc/[pid]/sched的内容
+pub fn sched_procfs_show(pcb: &Arc<ProcessControlBlock>) -> String {
+    let sched_info = pcb.sched_info();
+    let se = sched_info.sched_entity();
+    let stat = sched_info.sched_stat.read_irqsave();
+    let prio = sched_info.prio_data.read_irqsave().prio;
+
+    let mut s = format!("{} ({})\n", pcb.basic().name(), pcb.pid().data());
+    s.push_str(&"-".repeat(67));
+    s.push('\n');
+    s.push_str(&fmt_nsec("se.exec_start", se.exec_start));
+    s.push_str(&fmt_nsec("se.vruntime", se.vruntime));
+    s.push_str(&fmt_nsec("se.sum_exec_runtime", se.sum_exec_runtime));
+    s.push_str(&fmt_nsec("se.slice", se.slice));
+    s.push_str(&fmt_nsec("se.deadline", se.deadline));
+    s.push_str(&fmt_int("se.vlag", se.vlag));
+    s.push_str(&fmt_int("se.nr_migrations", stat.nr_migrations));
+    s.push_str(&fmt_int("nr_switches", stat.pcount));
+    s.push_str(&fmt_nsec("sched_info.run_delay", stat.run_delay as u64));
+    s.push_str(&fmt_nsec("sched_info.wait_max", stat.wait_max));
+    s.push_str(&fmt_nsec("sched_info.last_arrival", stat.last_arrival));
+    s.push_str(&fmt_int("se.load.weight", se.load.weight));
+    s.push_str(&fmt_int("se.avg.load_avg", se.avg.load_avg));
+    s.push_str(&fmt_int("se.avg.runnable_avg", se.avg.runnable_avg));
+    s.push_str(&fmt_int("se.avg.util_avg", se.avg.util_avg));
+    s.push_str(&fmt_int("policy", user_policy(pcb)));
+    s.push_str(&fmt_int("prio", prio));
+    s.push_str(&fmt_int(
+        "on_cpu",
+        sched_info
+            .on_cpu()
+            .map(|cpu| cpu.data() as i64)
+            .unwrap_or(-1),
+    ));
+    s
+}
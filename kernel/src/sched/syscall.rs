@@ -1,14 +1,81 @@
+use core::sync::atomic::Ordering;
+
+use alloc::sync::Arc;
 use system_error::SystemError;
 
 use crate::arch::cpu::current_cpu_id;
+use crate::arch::MMArch;
 use crate::exception::InterruptArch;
-use crate::process::ProcessManager;
+use crate::libs::cpumask::CpuMask;
+use crate::mm::{MemoryManagementArch, VirtAddr};
+use crate::process::cred::CAPFlags;
+use crate::process::{Pid, ProcessControlBlock, ProcessManager};
 use crate::sched::CurrentIrqArch;
 use crate::sched::Scheduler;
+use crate::smp::cpu::smp_cpu_manager;
+use crate::syscall::user_access::{UserBufferReader, UserBufferWriter, UserPtr, UserSlice};
 use crate::syscall::Syscall;
+use crate::time::{clocksource::HZ, PosixTimeSpec, NSEC_PER_SEC};
 
 use super::fair::CompletelyFairScheduler;
-use super::{cpu_rq, schedule, SchedMode};
+use super::prio::{PrioUtil, MAX_NICE, MAX_RT_PRIO, MIN_NICE};
+use super::rt::{RealTimeScheduler, RR_TIMESLICE};
+use super::{
+    cpu_rq, sched_change, schedule, set_cpus_allowed, set_load_weight, task_allowed_cpus,
+    FairPolicy, SchedMode, SchedPolicy,
+};
+
+/// getpriority、setpriority的which参数：目标是一个进程
+const PRIO_PROCESS: i32 = 0;
+
+/// 用户态可见的调度策略
+const SCHED_NORMAL: i32 = 0;
+const SCHED_FIFO: i32 = 1;
+const SCHED_RR: i32 = 2;
+const SCHED_BATCH: i32 = 3;
+const SCHED_IDLE: i32 = 5;
+
+/// sched_setscheduler等系统调用使用的调度参数
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SchedParam {
+    pub sched_priority: i32,
+}
+
+/// `struct sched_attr`最初版本的大小
+const SCHED_ATTR_SIZE_VER0: usize = 48;
+
+/// sched_setattr、sched_getattr使用的调度属性
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchedAttr {
+    pub size: u32,
+    pub sched_policy: u32,
+    pub sched_flags: u64,
+    pub sched_nice: i32,
+    pub sched_priority: u32,
+    /// 以下三项用于SCHED_DEADLINE
+    pub sched_runtime: u64,
+    pub sched_deadline: u64,
+    pub sched_period: u64,
+    /// 利用率钳制，目前不支持
+    pub sched_util_min: u32,
+    pub sched_util_max: u32,
+}
+
+/// 根据which与who找到getpriority、setpriority的目标进程，目前只支持PRIO_PROCESS
+fn prio_target(which: i32, who: i32) -> Result<Arc<ProcessControlBlock>, SystemError> {
+    if which != PRIO_PROCESS {
+        return Err(SystemError::EINVAL);
+    }
+    if who < 0 {
+        return Err(SystemError::ESRCH);
+    }
+    if who == 0 {
+        return Ok(ProcessManager::current_pcb());
+    }
+    ProcessManager::find(Pid::new(who as usize)).ok_or(SystemError::ESRCH)
+}
 
 impl Syscall {
     pub fn do_sched_yield() -> Result<usize, SystemError> {
@@ -21,7 +88,11 @@ impl Syscall {
 
         // TODO: schedstat_inc(rq->yld_count);
 
-        CompletelyFairScheduler::yield_task(rq);
+        if pcb.sched_info().policy().is_rt() {
+            RealTimeScheduler::yield_task(rq);
+        } else {
+            CompletelyFairScheduler::yield_task(rq);
+        }
 
         pcb.preempt_disable();
 
@@ -34,4 +105,426 @@ impl Syscall {
 
         Ok(0)
     }
+
+    /// # 获取进程的nice值
+    ///
+    /// 为了避免返回负数，与Linux的系统调用相同，返回`20 - nice`，由C库转换为nice值
+    pub fn getpriority(which: i32, who: i32) -> Result<usize, SystemError> {
+        let pcb = prio_target(which, who)?;
+        let nice = PrioUtil::prio_to_nice(pcb.sched_info().prio_data.read_irqsave().static_prio);
+        Ok((20 - nice) as usize)
+    }
+
+    /// # 设置进程的nice值
+    ///
+    /// 只能修改uid或者有效uid与当前进程的有效uid相同的进程，降低nice值（提高优先级）需要CAP_SYS_NICE。
+    /// 进程的CFS权重随之改变
+    pub fn setpriority(which: i32, who: i32, nice: i32) -> Result<usize, SystemError> {
+        let pcb = prio_target(which, who)?;
+        let nice = nice.clamp(MIN_NICE, MAX_NICE);
+
+        let can_nice = check_same_owner(&pcb)?;
+
+        let mut prio_guard = pcb.sched_info().prio_data.write_irqsave();
+        if nice < PrioUtil::prio_to_nice(prio_guard.static_prio) && !can_nice {
+            return Err(SystemError::EACCES);
+        }
+        let prio = PrioUtil::nice_to_prio(nice);
+        prio_guard.static_prio = prio;
+        // 实时进程的优先级与nice值无关，优先级继承得到的优先级也不受影响
+        if !PrioUtil::rt_prio(prio_guard.normal_prio) {
+            if prio_guard.prio == prio_guard.normal_prio {
+                prio_guard.prio = prio;
+            }
+            prio_guard.normal_prio = prio;
+        }
+        drop(prio_guard);
+
+        set_load_weight(&pcb, true);
+        Ok(0)
+    }
+
+    /// # 设置进程的调度策略与参数
+    ///
+    /// SCHED_FIFO与SCHED_RR的`sched_priority`为1..99，SCHED_NORMAL、SCHED_BATCH与SCHED_IDLE的必须为0。
+    /// 成为实时进程、提高实时优先级以及离开SCHED_IDLE需要CAP_SYS_NICE
+    pub fn sched_setscheduler(
+        pid: i32,
+        policy: i32,
+        param: *const SchedParam,
+    ) -> Result<usize, SystemError> {
+        if policy < 0 {
+            return Err(SystemError::EINVAL);
+        }
+        do_sched_setscheduler(pid, Some(policy), param)
+    }
+
+    /// # 设置进程的调度参数，调度策略不变
+    pub fn sched_setparam(pid: i32, param: *const SchedParam) -> Result<usize, SystemError> {
+        do_sched_setscheduler(pid, None, param)
+    }
+
+    /// # 获取进程的调度策略
+    pub fn sched_getscheduler(pid: i32) -> Result<usize, SystemError> {
+        let pcb = sched_target(pid)?;
+        Ok(user_policy(&pcb) as usize)
+    }
+
+    /// # 获取进程的调度参数
+    ///
+    /// 对于由CFS调度的进程，`sched_priority`总是0
+    pub fn sched_getparam(pid: i32, param: *mut SchedParam) -> Result<usize, SystemError> {
+        if param.is_null() {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = sched_target(pid)?;
+        let sched_priority = rt_priority(&pcb);
+
+        let mut writer = UserBufferWriter::new(param, core::mem::size_of::<SchedParam>(), true)?;
+        writer.copy_one_to_user(&SchedParam { sched_priority }, 0)?;
+        Ok(0)
+    }
+
+    /// # 设置进程的调度策略、nice值与实时优先级
+    ///
+    /// `attr`是可扩展的结构体，`size`字段为0时按照最初的版本处理。
+    /// 用户程序传入的结构体比内核认识的大时，多出来的部分必须为0，否则把内核认识的大小写回`size`并返回E2BIG。
+    /// 不支持SCHED_DEADLINE与任何`sched_flags`
+    pub fn sched_setattr(pid: i32, attr: *mut SchedAttr, flags: u32) -> Result<usize, SystemError> {
+        if attr.is_null() || pid < 0 || flags != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let attr = read_sched_attr(attr)?;
+        if attr.sched_flags != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let policy = i32::try_from(attr.sched_policy).map_err(|_| SystemError::EINVAL)?;
+        let sched_priority = i32::try_from(attr.sched_priority).map_err(|_| SystemError::EINVAL)?;
+
+        let pcb = sched_target(pid)?;
+        if matches!(policy, SCHED_NORMAL | SCHED_BATCH | SCHED_IDLE) {
+            let nice = attr.sched_nice.clamp(MIN_NICE, MAX_NICE);
+            let static_prio = pcb.sched_info().prio_data.read_irqsave().static_prio;
+            if nice < PrioUtil::prio_to_nice(static_prio) && !check_same_owner(&pcb)? {
+                return Err(SystemError::EPERM);
+            }
+        }
+        __sched_setscheduler(&pcb, policy, sched_priority)?;
+        if matches!(policy, SCHED_NORMAL | SCHED_BATCH | SCHED_IDLE) {
+            Self::setpriority(PRIO_PROCESS, pid, attr.sched_nice)?;
+        }
+        Ok(0)
+    }
+
+    /// # 获取进程的调度属性
+    ///
+    /// 写入`size`与内核认识的结构体大小中较小的那一部分
+    pub fn sched_getattr(
+        pid: i32,
+        attr: *mut SchedAttr,
+        size: usize,
+        flags: u32,
+    ) -> Result<usize, SystemError> {
+        if attr.is_null()
+            || pid < 0
+            || flags != 0
+            || size < SCHED_ATTR_SIZE_VER0
+            || size > MMArch::PAGE_SIZE
+        {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = sched_target(pid)?;
+        let len = size.min(core::mem::size_of::<SchedAttr>());
+        let kattr = SchedAttr {
+            size: len as u32,
+            sched_policy: user_policy(&pcb) as u32,
+            sched_nice: PrioUtil::prio_to_nice(
+                pcb.sched_info().prio_data.read_irqsave().static_prio,
+            ),
+            sched_priority: rt_priority(&pcb) as u32,
+            ..Default::default()
+        };
+
+        let bytes =
+            unsafe { core::slice::from_raw_parts(&kattr as *const SchedAttr as *const u8, len) };
+        UserSlice::new(VirtAddr::new(attr as usize), len)?
+            .writer()
+            .write_raw(bytes)?;
+        Ok(0)
+    }
+
+    /// # 获取进程的时间片长度
+    ///
+    /// SCHED_FIFO的进程没有时间片，返回0
+    pub fn sched_rr_get_interval(
+        pid: i32,
+        interval: *mut PosixTimeSpec,
+    ) -> Result<usize, SystemError> {
+        let pcb = sched_target(pid)?;
+        let nsec = match pcb.sched_info().policy() {
+            SchedPolicy::RT => RR_TIMESLICE as u64 * NSEC_PER_SEC as u64 / HZ,
+            SchedPolicy::FIFO | SchedPolicy::IDLE => 0,
+            SchedPolicy::CFS => pcb.sched_info().sched_entity().slice,
+        };
+        let ts = PosixTimeSpec::new(
+            (nsec / NSEC_PER_SEC as u64) as i64,
+            (nsec % NSEC_PER_SEC as u64) as i64,
+        );
+
+        let mut writer =
+            UserBufferWriter::new(interval, core::mem::size_of::<PosixTimeSpec>(), true)?;
+        writer.copy_one_to_user(&ts, 0)?;
+        Ok(0)
+    }
+
+    /// # 获取进程允许运行的cpu
+    ///
+    /// `len`必须是8的倍数，并且能容纳所有可能的cpu，返回写入用户缓冲区的字节数
+    pub fn sched_getaffinity(
+        pid: i32,
+        len: usize,
+        user_mask: *mut u8,
+    ) -> Result<usize, SystemError> {
+        let size = cpumask_size();
+        if len * 8 < smp_cpu_manager().nr_cpu_ids() || len % core::mem::size_of::<usize>() != 0 {
+            return Err(SystemError::EINVAL);
+        }
+        let pcb = prio_target(PRIO_PROCESS, pid)?;
+        let len = len.min(size);
+        let bytes = task_allowed_cpus(&pcb).to_bytes(len);
+
+        let mut writer = UserBufferWriter::new(user_mask, len, true)?;
+        writer.copy_to_user(&bytes, 0)?;
+        Ok(len)
+    }
+
+    /// # 设置进程允许运行的cpu
+    ///
+    /// 只能修改uid或者有效uid与当前进程的有效uid相同的进程，除非有CAP_SYS_NICE。
+    /// 掩码中没有已经启动的cpu时返回EINVAL
+    pub fn sched_setaffinity(
+        pid: i32,
+        len: usize,
+        user_mask: *const u8,
+    ) -> Result<usize, SystemError> {
+        let pcb = prio_target(PRIO_PROCESS, pid)?;
+        check_same_owner(&pcb)?;
+
+        let len = len.min(cpumask_size());
+        let reader = UserBufferReader::new(user_mask, len, true)?;
+        let mask = CpuMask::from_bytes(reader.read_from_user::<u8>(0)?);
+        set_cpus_allowed(&pcb, mask)?;
+        Ok(0)
+    }
+
+    /// # 获取当前进程所在的cpu与NUMA节点
+    ///
+    /// 目前不支持NUMA，节点总是0
+    pub fn getcpu(cpu: *mut u32, node: *mut u32) -> Result<usize, SystemError> {
+        let cpu_id = current_cpu_id().data();
+        if !cpu.is_null() {
+            let mut writer = UserBufferWriter::new(cpu, core::mem::size_of::<u32>(), true)?;
+            writer.copy_one_to_user(&cpu_id, 0)?;
+        }
+        if !node.is_null() {
+            let mut writer = UserBufferWriter::new(node, core::mem::size_of::<u32>(), true)?;
+            writer.copy_one_to_user(&0u32, 0)?;
+        }
+        Ok(0)
+    }
+
+    /// # 获取调度策略允许的最大`sched_priority`
+    pub fn sched_get_priority_max(policy: i32) -> Result<usize, SystemError> {
+        match policy {
+            SCHED_FIFO | SCHED_RR => Ok((MAX_RT_PRIO - 1) as usize),
+            SCHED_NORMAL | SCHED_BATCH | SCHED_IDLE => Ok(0),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+
+    /// # 获取调度策略允许的最小`sched_priority`
+    pub fn sched_get_priority_min(policy: i32) -> Result<usize, SystemError> {
+        match policy {
+            SCHED_FIFO | SCHED_RR => Ok(1),
+            SCHED_NORMAL | SCHED_BATCH | SCHED_IDLE => Ok(0),
+            _ => Err(SystemError::EINVAL),
+        }
+    }
+}
+
+/// 检查当前进程能否修改`pcb`的调度属性
+///
+/// ## 返回值
+///
+/// - Ok(bool): 当前进程是否有CAP_SYS_NICE
+/// - Err(EPERM): `pcb`的uid与有效uid都不等于当前进程的有效uid，并且当前进程没有CAP_SYS_NICE
+fn check_same_owner(pcb: &Arc<ProcessControlBlock>) -> Result<bool, SystemError> {
+    let cred = ProcessManager::current_pcb().cred();
+    let target_cred = pcb.cred();
+    let can_nice = cred.has_capability(CAPFlags::CAP_SYS_NICE);
+    if target_cred.uid != cred.euid && target_cred.euid != cred.euid && !can_nice {
+        return Err(SystemError::EPERM);
+    }
+    Ok(can_nice)
+}
+
+/// 内核中cpu掩码的字节数，按照`unsigned long`对齐
+fn cpumask_size() -> usize {
+    let bits = usize::BITS as usize;
+    smp_cpu_manager().nr_cpu_ids().div_ceil(bits) * bits / 8
+}
+
+/// 根据pid找到sched_*系统调用的目标进程，pid为0表示当前进程
+fn sched_target(pid: i32) -> Result<Arc<ProcessControlBlock>, SystemError> {
+    if pid < 0 {
+        return Err(SystemError::EINVAL);
+    }
+    prio_target(PRIO_PROCESS, pid)
+}
+
+/// 进程在用户态可见的调度策略
+///
+/// 被优先级继承提升到实时调度类的普通进程，仍然报告它自己的调度策略
+pub(super) fn user_policy(pcb: &Arc<ProcessControlBlock>) -> i32 {
+    let normal_prio = pcb.sched_info().prio_data.read_irqsave().normal_prio;
+    match pcb.sched_info().policy() {
+        SchedPolicy::FIFO if PrioUtil::rt_prio(normal_prio) => SCHED_FIFO,
+        SchedPolicy::RT if PrioUtil::rt_prio(normal_prio) => SCHED_RR,
+        SchedPolicy::IDLE => SCHED_IDLE,
+        _ => match pcb.sched_info().fair_policy() {
+            FairPolicy::Normal => SCHED_NORMAL,
+            FairPolicy::Batch => SCHED_BATCH,
+            FairPolicy::Idle => SCHED_IDLE,
+        },
+    }
+}
+
+/// 进程的实时优先级（用户态的`sched_priority`），普通进程为0
+fn rt_priority(pcb: &Arc<ProcessControlBlock>) -> i32 {
+    let prio = pcb.sched_info().prio_data.read_irqsave().normal_prio;
+    if PrioUtil::rt_prio(prio) {
+        MAX_RT_PRIO - 1 - prio
+    } else {
+        0
+    }
+}
+
+/// 从用户空间读取`struct sched_attr`
+fn read_sched_attr(uattr: *mut SchedAttr) -> Result<SchedAttr, SystemError> {
+    let size_ptr = UserPtr::<u32>::from_ptr(uattr as *const u32);
+    let size = match size_ptr.read()? as usize {
+        0 => SCHED_ATTR_SIZE_VER0,
+        size => size,
+    };
+    if size < SCHED_ATTR_SIZE_VER0 || size > MMArch::PAGE_SIZE {
+        size_ptr.write(&(core::mem::size_of::<SchedAttr>() as u32))?;
+        return Err(SystemError::E2BIG);
+    }
+
+    let mut attr = SchedAttr::default();
+    let len = size.min(core::mem::size_of::<SchedAttr>());
+    let bytes =
+        unsafe { core::slice::from_raw_parts_mut(&mut attr as *mut SchedAttr as *mut u8, len) };
+    let mut reader = UserSlice::new(VirtAddr::new(uattr as usize), size)?.reader();
+    reader.read_raw(bytes)?;
+    // 新版本用户程序传入的更大的结构体中，本内核不认识的字段必须为0
+    if !reader.rest_is_zeroed()? {
+        size_ptr.write(&(core::mem::size_of::<SchedAttr>() as u32))?;
+        return Err(SystemError::E2BIG);
+    }
+    Ok(attr)
+}
+
+/// sched_setscheduler与sched_setparam的公共部分，`policy`为None时保持原来的调度策略
+fn do_sched_setscheduler(
+    pid: i32,
+    policy: Option<i32>,
+    param: *const SchedParam,
+) -> Result<usize, SystemError> {
+    if param.is_null() {
+        return Err(SystemError::EINVAL);
+    }
+    let reader = UserBufferReader::new(param, core::mem::size_of::<SchedParam>(), true)?;
+    let sched_priority = reader.read_one_from_user::<SchedParam>(0)?.sched_priority;
+
+    let pcb = sched_target(pid)?;
+    let policy = policy.unwrap_or_else(|| user_policy(&pcb));
+    __sched_setscheduler(&pcb, policy, sched_priority)?;
+    Ok(0)
+}
+
+/// # 修改进程的调度策略与实时优先级
+///
+/// 进程在实时调度类与CFS之间移动时，会被移出原来的运行队列再放入新的运行队列
+fn __sched_setscheduler(
+    pcb: &Arc<ProcessControlBlock>,
+    policy: i32,
+    sched_priority: i32,
+) -> Result<(), SystemError> {
+    let rt = match policy {
+        SCHED_FIFO | SCHED_RR => true,
+        SCHED_NORMAL | SCHED_BATCH | SCHED_IDLE => false,
+        _ => return Err(SystemError::EINVAL),
+    };
+    if rt && !(1..MAX_RT_PRIO).contains(&sched_priority) {
+        return Err(SystemError::EINVAL);
+    }
+    if !rt && sched_priority != 0 {
+        return Err(SystemError::EINVAL);
+    }
+
+    let can_nice = check_same_owner(pcb)?;
+    if pcb.sched_info().policy() == SchedPolicy::IDLE {
+        return Err(SystemError::EPERM);
+    }
+    let old_policy = user_policy(pcb);
+    let old_priority = rt_priority(pcb);
+    if !can_nice {
+        // 没有CAP_SYS_NICE时，实时进程只能降低自己的实时优先级
+        if rt && (old_policy != policy || sched_priority > old_priority) {
+            return Err(SystemError::EPERM);
+        }
+        if old_policy == SCHED_IDLE && policy != SCHED_IDLE {
+            return Err(SystemError::EPERM);
+        }
+    }
+    if old_policy == policy && old_priority == sched_priority {
+        return Ok(());
+    }
+
+    sched_change(pcb, || {
+        let mut prio_guard = pcb.sched_info().prio_data.write_irqsave();
+        let normal_prio = if rt {
+            MAX_RT_PRIO - 1 - sched_priority
+        } else {
+            prio_guard.static_prio
+        };
+        // 优先级继承得到的更高的优先级不受影响
+        prio_guard.prio = if prio_guard.prio == prio_guard.normal_prio {
+            normal_prio
+        } else {
+            prio_guard.prio.min(normal_prio)
+        };
+        prio_guard.normal_prio = normal_prio;
+        let boosted = PrioUtil::rt_prio(prio_guard.prio);
+        drop(prio_guard);
+
+        let fair_policy = match policy {
+            SCHED_BATCH => FairPolicy::Batch,
+            SCHED_IDLE => FairPolicy::Idle,
+            _ => FairPolicy::Normal,
+        };
+        *pcb.sched_info().fair_policy.write_irqsave() = fair_policy;
+        *pcb.sched_info().sched_policy.write_irqsave() = match policy {
+            SCHED_FIFO => SchedPolicy::FIFO,
+            SCHED_RR => SchedPolicy::RT,
+            _ if boosted => SchedPolicy::FIFO,
+            _ => SchedPolicy::CFS,
+        };
+        pcb.sched_info()
+            .rt_time_slice
+            .store(RR_TIMESLICE, Ordering::SeqCst);
+        set_load_weight(pcb, false);
+    });
+    Ok(())
 }
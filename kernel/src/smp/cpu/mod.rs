@@ -1,18 +1,20 @@
-use core::sync::atomic::AtomicU32;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
 use alloc::{sync::Arc, vec::Vec};
 use log::{debug, error, info};
 use system_error::SystemError;
 
 use crate::{
-    arch::CurrentSMPArch,
-    libs::cpumask::CpuMask,
+    arch::{CurrentIrqArch, CurrentSMPArch},
+    exception::InterruptArch,
+    libs::{cpumask::CpuMask, mutex::Mutex, rwlock::RwLock},
     mm::percpu::{PerCpu, PerCpuVar},
     process::{ProcessControlBlock, ProcessManager},
-    sched::completion::Completion,
+    sched::{completion::Completion, sched_cpu_activate, sched_cpu_deactivate},
+    time::timer::timers_dead_cpu,
 };
 
-use super::{core::smp_get_processor_id, SMPArch};
+use super::{core::smp_get_processor_id, kick_cpu, SMPArch};
 
 int_like!(ProcessorId, AtomicProcessorId, u32, AtomicU32);
 
@@ -35,10 +37,66 @@ pub enum CpuHpState {
     /// 该CPU是离线的
     Offline,
 
+    /// 该CPU的定时器基座可以使用，离线时在这一步把剩下的定时器迁移到其他CPU
+    TimersPrepare,
+
+    /// 该CPU已经启动（或者从停靠中恢复）并开启了本地时钟中断
+    BringupCpu,
+
+    /// 调度器可以把进程放到该CPU上
+    SchedActive,
+
     /// 该CPU是在线的
     Online,
 }
 
+impl CpuHpState {
+    fn next(&self) -> Option<Self> {
+        match self {
+            CpuHpState::ThresholdBringUp => Some(CpuHpState::Offline),
+            CpuHpState::Offline => Some(CpuHpState::TimersPrepare),
+            CpuHpState::TimersPrepare => Some(CpuHpState::BringupCpu),
+            CpuHpState::BringupCpu => Some(CpuHpState::SchedActive),
+            CpuHpState::SchedActive => Some(CpuHpState::Online),
+            CpuHpState::Online => None,
+        }
+    }
+
+    fn prev(&self) -> Option<Self> {
+        match self {
+            CpuHpState::ThresholdBringUp => None,
+            CpuHpState::Offline => Some(CpuHpState::ThresholdBringUp),
+            CpuHpState::TimersPrepare => Some(CpuHpState::Offline),
+            CpuHpState::BringupCpu => Some(CpuHpState::TimersPrepare),
+            CpuHpState::SchedActive => Some(CpuHpState::BringupCpu),
+            CpuHpState::Online => Some(CpuHpState::SchedActive),
+        }
+    }
+
+    /// 进入这个状态时执行的回调与离开这个状态时执行的回调
+    ///
+    /// 回调都在发起热插拔的CPU上执行
+    fn callbacks(&self) -> (Option<CpuHpCallback>, Option<CpuHpCallback>) {
+        match self {
+            CpuHpState::TimersPrepare => (None, Some(timers_dead_cpu)),
+            CpuHpState::BringupCpu => (Some(bringup_cpu), Some(takedown_cpu)),
+            CpuHpState::SchedActive => (Some(sched_cpu_activate), Some(sched_cpu_deactivate)),
+            _ => (None, None),
+        }
+    }
+}
+
+/// 热插拔状态机的回调，参数为被热插拔的CPU
+type CpuHpCallback = fn(ProcessorId) -> Result<(), SystemError>;
+
+fn bringup_cpu(cpu_id: ProcessorId) -> Result<(), SystemError> {
+    smp_cpu_manager().do_cpuhp_kick_ap(cpu_id)
+}
+
+fn takedown_cpu(cpu_id: ProcessorId) -> Result<(), SystemError> {
+    smp_cpu_manager().do_cpuhp_park_ap(cpu_id)
+}
+
 /// Per-Cpu Cpu的热插拔状态
 pub struct CpuHpCpuState {
     /// 当前状态
@@ -52,6 +110,12 @@ pub struct CpuHpCpuState {
     bringup: bool,
     /// 启动完成的信号
     comp_done_up: Completion,
+    /// 停靠完成的信号
+    comp_done_down: Completion,
+    /// 该CPU是否已经启动过。启动过的CPU离线时停靠在空闲进程中，重新上线时不需要再次启动
+    booted: AtomicBool,
+    /// 该CPU需要停靠（离线）
+    should_park: AtomicBool,
 }
 
 impl CpuHpCpuState {
@@ -62,6 +126,9 @@ impl CpuHpCpuState {
             thread: None,
             bringup: false,
             comp_done_up: Completion::new(),
+            comp_done_down: Completion::new(),
+            booted: AtomicBool::new(false),
+            should_park: AtomicBool::new(false),
         }
     }
 
@@ -80,8 +147,12 @@ pub struct SmpCpuManager {
     present_cnt: AtomicU32,
     /// 可用的CPU的数量
     possible_cnt: AtomicU32,
+    /// 在线的CPU，调度器只会把进程放到这些CPU上
+    online_cpus: RwLock<CpuMask>,
     /// CPU的状态
     cpuhp_state: PerCpuVar<CpuHpCpuState>,
+    /// 串行化CPU的上线与离线
+    hotplug_lock: Mutex<()>,
 }
 
 impl SmpCpuManager {
@@ -99,9 +170,11 @@ impl SmpCpuManager {
         Self {
             possible_cpus,
             present_cpus,
+            online_cpus: RwLock::new(CpuMask::new()),
             cpuhp_state,
             present_cnt: AtomicU32::new(0),
             possible_cnt: AtomicU32::new(0),
+            hotplug_lock: Mutex::new(()),
         }
     }
 
@@ -138,6 +211,13 @@ impl SmpCpuManager {
         self.possible_cnt.load(core::sync::atomic::Ordering::SeqCst)
     }
 
+    /// 可能存在的cpu的编号上限，即最大的cpu编号加1
+    pub fn nr_cpu_ids(&self) -> usize {
+        self.possible_cpus
+            .last()
+            .map_or(1, |cpu| cpu.data() as usize + 1)
+    }
+
     pub fn present_cpus_count(&self) -> u32 {
         self.present_cnt.load(core::sync::atomic::Ordering::SeqCst)
     }
@@ -185,8 +265,31 @@ impl SmpCpuManager {
         return old_state;
     }
 
+    /// 把已经在运行的CPU（启动CPU）直接标记为在线
     pub fn set_online_cpu(&self, cpu_id: ProcessorId) {
-        unsafe { self.set_cpuhp_state(cpu_id, CpuHpState::Online) };
+        let st = self.cpuhp_state_mut(cpu_id);
+        st.state = CpuHpState::Online;
+        st.target_state = CpuHpState::Online;
+        st.booted.store(true, Ordering::SeqCst);
+        self.set_cpu_online(cpu_id, true);
+    }
+
+    /// 获取在线的CPU
+    pub fn online_cpus(&self) -> CpuMask {
+        self.online_cpus.read_irqsave().clone()
+    }
+
+    pub fn online_cpus_count(&self) -> u32 {
+        self.online_cpus.read_irqsave().weight() as u32
+    }
+
+    pub fn cpu_online(&self, cpu_id: ProcessorId) -> bool {
+        self.online_cpus.read_irqsave().get(cpu_id).unwrap_or(false)
+    }
+
+    /// 修改在线的CPU，只能在热插拔的流程中调用
+    pub fn set_cpu_online(&self, cpu_id: ProcessorId, online: bool) {
+        self.online_cpus.write_irqsave().set(cpu_id, online);
     }
 
     /// 获取出现在系统中的CPU
@@ -213,10 +316,13 @@ impl SmpCpuManager {
     }
 
     fn cpu_up(&self, cpu_id: ProcessorId, target_state: CpuHpState) -> Result<(), SystemError> {
-        if !self.possible_cpus().get(cpu_id).unwrap_or(false) {
+        if !self.possible_cpus().get(cpu_id).unwrap_or(false)
+            || !self.present_cpus().get(cpu_id).unwrap_or(false)
+        {
             return Err(SystemError::EINVAL);
         }
 
+        let _guard = self.hotplug_lock.lock();
         let cpu_state = self.cpuhp_state(cpu_id).state;
         debug!(
             "cpu_up: cpu_id: {}, cpu_state: {:?}, target_state: {:?}",
@@ -229,15 +335,50 @@ impl SmpCpuManager {
             return Ok(());
         }
 
-        unsafe { self.set_cpuhp_state(cpu_id, target_state) };
+        return self.cpuhp_kick_ap(cpu_id, target_state);
+    }
+
+    /// # 让CPU离线
+    ///
+    /// 离线的CPU上的进程与定时器被迁移到其他CPU，然后CPU停止本地时钟中断，停靠在空闲进程中。
+    /// CPU0负责更新jiffies，不能离线；最后一个在线的CPU也不能离线
+    fn cpu_down(&self, cpu_id: ProcessorId, target_state: CpuHpState) -> Result<(), SystemError> {
+        if !self.present_cpus().get(cpu_id).unwrap_or(false) {
+            return Err(SystemError::EINVAL);
+        }
+        if cpu_id == ProcessorId::new(0) {
+            return Err(SystemError::EBUSY);
+        }
+
+        let _guard = self.hotplug_lock.lock();
         let cpu_state = self.cpuhp_state(cpu_id).state;
-        if cpu_state > CpuHpState::ThresholdBringUp {
-            self.cpuhp_kick_ap(cpu_id, target_state)?;
+        debug!(
+            "cpu_down: cpu_id: {}, cpu_state: {:?}, target_state: {:?}",
+            cpu_id.data(),
+            cpu_state,
+            target_state
+        );
+        if cpu_state <= target_state {
+            return Ok(());
+        }
+        if self.cpu_online(cpu_id) && self.online_cpus_count() <= 1 {
+            return Err(SystemError::EBUSY);
         }
 
-        return Ok(());
+        return self.cpuhp_kick_ap(cpu_id, target_state);
+    }
+
+    /// 让CPU上线，用于sysfs的`cpuN/online`
+    pub fn cpu_device_up(&self, cpu_id: ProcessorId) -> Result<(), SystemError> {
+        self.cpu_up(cpu_id, CpuHpState::Online)
     }
 
+    /// 让CPU离线，用于sysfs的`cpuN/online`
+    pub fn cpu_device_down(&self, cpu_id: ProcessorId) -> Result<(), SystemError> {
+        self.cpu_down(cpu_id, CpuHpState::Offline)
+    }
+
+    /// 把CPU推进到目标状态，失败时回到原来的状态
     fn cpuhp_kick_ap(
         &self,
         cpu_id: ProcessorId,
@@ -245,9 +386,9 @@ impl SmpCpuManager {
     ) -> Result<(), SystemError> {
         let prev_state = unsafe { self.set_cpuhp_state(cpu_id, target_state) };
         let hpstate = self.cpuhp_state_mut(cpu_id);
-        if let Err(e) = self.do_cpuhp_kick_ap(hpstate) {
+        if let Err(e) = self.cpuhp_invoke_callback_range(cpu_id, hpstate) {
             self.cpuhp_reset_state(hpstate, prev_state);
-            self.do_cpuhp_kick_ap(hpstate).ok();
+            self.cpuhp_invoke_callback_range(cpu_id, hpstate).ok();
 
             return Err(e);
         }
@@ -255,40 +396,118 @@ impl SmpCpuManager {
         return Ok(());
     }
 
-    fn do_cpuhp_kick_ap(&self, cpu_state: &mut CpuHpCpuState) -> Result<(), SystemError> {
-        let pcb = cpu_state.thread.as_ref().ok_or(SystemError::EINVAL)?;
-        let cpu_id = pcb.sched_info().on_cpu().ok_or(SystemError::EINVAL)?;
+    /// 逐个状态地走向目标状态：上线时执行每个状态的启动回调，离线时逆序执行每个状态的拆除回调
+    fn cpuhp_invoke_callback_range(
+        &self,
+        cpu_id: ProcessorId,
+        st: &mut CpuHpCpuState,
+    ) -> Result<(), SystemError> {
+        while st.state != st.target_state {
+            let (state, callback) = if st.bringup {
+                let next = st.state.next().ok_or(SystemError::EINVAL)?;
+                (next, next.callbacks().0)
+            } else {
+                let prev = st.state.prev().ok_or(SystemError::EINVAL)?;
+                (prev, st.state.callbacks().1)
+            };
+
+            if let Some(callback) = callback {
+                callback(cpu_id).inspect_err(|e| {
+                    error!(
+                        "cpuhp: cpu {} failed to {} {:?}: {:?}",
+                        cpu_id.data(),
+                        if st.bringup { "enter" } else { "leave" },
+                        if st.bringup { state } else { st.state },
+                        e
+                    )
+                })?;
+            }
+            st.state = state;
+        }
 
-        // todo: 等待CPU启动完成
+        return Ok(());
+    }
+
+    /// 启动AP，或者让停靠的AP恢复运行，然后等待它完成上线
+    ///
+    /// 等待失败时让AP重新停靠，由`cpuhp_kick_ap`把状态机退回到原来的状态
+    fn do_cpuhp_kick_ap(&self, cpu_id: ProcessorId) -> Result<(), SystemError> {
+        let cpu_state = self.cpuhp_state(cpu_id);
+        let pcb = cpu_state.thread.as_ref().ok_or(SystemError::EINVAL)?;
+        if pcb.sched_info().on_cpu() != Some(cpu_id) {
+            return Err(SystemError::EINVAL);
+        }
 
-        ProcessManager::wakeup(cpu_state.thread.as_ref().unwrap())?;
+        cpu_state.comp_done_up.reinit();
+        if cpu_state.booted.load(Ordering::SeqCst) {
+            cpu_state.should_park.store(false, Ordering::SeqCst);
+            if let Err(e) = kick_cpu(cpu_id) {
+                cpu_state.should_park.store(true, Ordering::SeqCst);
+                return Err(e);
+            }
+        } else {
+            ProcessManager::wakeup(pcb)?;
 
-        CurrentSMPArch::start_cpu(cpu_id, cpu_state)?;
+            CurrentSMPArch::start_cpu(cpu_id, cpu_state)?;
+            cpu_state.booted.store(true, Ordering::SeqCst);
+        }
         assert_eq!(ProcessManager::current_pcb().preempt_count(), 0);
-        self.wait_for_ap_thread(cpu_state, cpu_state.bringup);
+        if let Err(e) = self.wait_for_ap_thread(cpu_state, true) {
+            // AP已经被唤醒，无法撤回，让它在空闲进程的下一次循环中重新停靠
+            cpu_state.should_park.store(true, Ordering::SeqCst);
+            return Err(e);
+        }
 
         return Ok(());
     }
 
-    fn wait_for_ap_thread(&self, cpu_state: &mut CpuHpCpuState, bringup: bool) {
-        if bringup {
-            cpu_state
-                .comp_done_up
-                .wait_for_completion()
-                .expect("failed to wait ap thread");
-        } else {
-            todo!("wait_for_ap_thread")
+    /// 通知AP停靠，然后等待它停止本地时钟中断
+    ///
+    /// 等待失败时取消停靠，由`cpuhp_kick_ap`把状态机退回到原来的状态
+    fn do_cpuhp_park_ap(&self, cpu_id: ProcessorId) -> Result<(), SystemError> {
+        let cpu_state = self.cpuhp_state(cpu_id);
+        cpu_state.comp_done_down.reinit();
+        cpu_state.should_park.store(true, Ordering::SeqCst);
+        let r = kick_cpu(cpu_id).and_then(|_| self.wait_for_ap_thread(cpu_state, false));
+        if r.is_err() {
+            // 若AP已经停靠，清除标志后它会自行恢复运行
+            cpu_state.should_park.store(false, Ordering::SeqCst);
+            kick_cpu(cpu_id).ok();
         }
+
+        return r;
     }
 
-    /// 完成AP的启动
+    /// 等待AP完成启动或者停靠
+    ///
+    /// 发起热插拔的可能是写`cpuN/online`的用户进程，有待处理的信号时等待会失败
+    fn wait_for_ap_thread(
+        &self,
+        cpu_state: &CpuHpCpuState,
+        bringup: bool,
+    ) -> Result<(), SystemError> {
+        let comp = if bringup {
+            &cpu_state.comp_done_up
+        } else {
+            &cpu_state.comp_done_down
+        };
+        comp.wait_for_completion().map(|_| ()).inspect_err(|e| {
+            error!(
+                "cpuhp: failed to wait for cpu {}: {:?}",
+                if bringup { "bringup" } else { "park" },
+                e
+            )
+        })
+    }
+
+    /// 完成AP的启动或者停靠
     pub fn complete_ap_thread(&self, bringup: bool) {
         let cpu_id = smp_get_processor_id();
-        let cpu_state = self.cpuhp_state_mut(cpu_id);
+        let cpu_state = self.cpuhp_state(cpu_id);
         if bringup {
             cpu_state.comp_done_up.complete();
         } else {
-            todo!("complete_ap_thread")
+            cpu_state.comp_done_down.complete();
         }
     }
 
@@ -298,6 +517,34 @@ impl SmpCpuManager {
 
         st.bringup = bringup;
     }
+
+    /// # 在空闲进程中检查当前CPU是否需要离线
+    ///
+    /// 需要离线时，停止本地时钟中断并通知发起离线的CPU，然后停靠在这里，直到重新上线
+    pub fn cpuhp_idle_park(&self) {
+        let cpu_id = smp_get_processor_id();
+        let cpu_state = self.cpuhp_state(cpu_id);
+        if !cpu_state.should_park.load(Ordering::SeqCst) {
+            return;
+        }
+
+        ProcessManager::preempt_disable();
+        let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+        CurrentSMPArch::cpu_disable();
+        info!("CPU {} is offline", cpu_id.data());
+        self.complete_ap_thread(false);
+
+        while cpu_state.should_park.load(Ordering::SeqCst) {
+            CurrentSMPArch::cpu_park_wait();
+        }
+
+        CurrentSMPArch::cpu_enable();
+        info!("CPU {} is online", cpu_id.data());
+        drop(irq_guard);
+        self.complete_ap_thread(true);
+        // 由空闲进程的循环负责检查是否需要调度
+        ProcessManager::preempt_enable_no_resched();
+    }
 }
 
 pub fn smp_cpu_manager_init(boot_cpu: ProcessorId) {
@@ -307,6 +554,7 @@ pub fn smp_cpu_manager_init(boot_cpu: ProcessorId) {
 
     unsafe { smp_cpu_manager().set_possible_cpu(boot_cpu, true) };
     unsafe { smp_cpu_manager().set_present_cpu(boot_cpu, true) };
+    smp_cpu_manager().set_online_cpu(boot_cpu);
 
     SmpCpuManager::arch_init(boot_cpu);
 }
@@ -13,7 +13,6 @@ use self::{
 pub mod core;
 pub mod cpu;
 pub mod init;
-mod syscall;
 
 pub fn kick_cpu(cpu_id: ProcessorId) -> Result<(), SystemError> {
     // todo: 增加对cpu_id的有效性检查
@@ -39,6 +38,21 @@ pub trait SMPArch {
     ///
     /// 如果目标CPU已经启动，返回Ok。
     fn start_cpu(cpu_id: ProcessorId, hp_state: &CpuHpCpuState) -> Result<(), SystemError>;
+
+    /// 停止当前CPU的本地时钟中断
+    ///
+    /// 在即将离线的CPU上、关中断的情况下调用
+    fn cpu_disable();
+
+    /// 重新开启当前CPU的本地时钟中断
+    ///
+    /// 在重新上线的CPU上、关中断的情况下调用
+    fn cpu_enable();
+
+    /// 离线的CPU等待下一个中断到来（用于在重新上线时被唤醒）
+    ///
+    /// 调用前后中断都是关闭的，等待期间到来的中断需要被处理
+    fn cpu_park_wait();
 }
 
 /// 早期SMP初始化
@@ -5,17 +5,33 @@ use core::{
 
 use crate::{
     arch::{ipc::signal::SigSet, syscall::nr::*},
-    filesystem::vfs::syscall::{PosixStatfs, PosixStatx},
-    ipc::shm::{ShmCtlCmd, ShmFlags, ShmId, ShmKey},
-    libs::{futex::constant::FutexFlag, rand::GRandFlags},
+    filesystem::{
+        timerfd::PosixItimerspec,
+        vfs::syscall::{IoVec, PosixOpenHow, PosixStatfs, PosixStatx},
+    },
+    ipc::{
+        mqueue::{PosixMqAttr, PosixSigevent},
+        sem::PosixSembuf,
+        shm::{ShmCtlCmd, ShmFlags, ShmId, ShmKey},
+    },
+    libs::{
+        futex::constant::{FutexArg, FutexFlag},
+        rand::GRandFlags,
+    },
     mm::{page::PAGE_4K_SIZE, syscall::MremapFlags},
     net::syscall::MsgHdr,
     process::{
+        capability::{CapUserData, CapUserHeader},
+        cred::CAPFlags,
         fork::KernelCloneArgs,
         resource::{RLimit64, RUsage},
         ProcessFlags, ProcessManager,
     },
-    sched::{schedule, SchedMode},
+    sched::{
+        schedule,
+        syscall::{SchedAttr, SchedParam},
+        SchedMode,
+    },
     syscall::user_access::check_and_clone_cstr,
 };
 
@@ -25,6 +41,7 @@ use system_error::SystemError;
 
 use crate::{
     arch::{cpu::cpu_reset, interrupt::TrapFrame, MMArch},
+    debug::obj_lifetime::obj_lifetime_report,
     filesystem::vfs::{
         fcntl::{AtFlags, FcntlCommand},
         file::FileMode,
@@ -150,6 +167,14 @@ impl Syscall {
 
                 Self::openat(dirfd, path, flags, mode, true)
             }
+            SYS_OPENAT2 => {
+                let dirfd = args[0] as i32;
+                let path = args[1] as *const u8;
+                let how = args[2] as *const PosixOpenHow;
+                let size = args[3];
+
+                Self::openat2(dirfd, path, how, size)
+            }
             SYS_CLOSE => {
                 let fd = args[0];
                 Self::close(fd)
@@ -210,6 +235,24 @@ impl Syscall {
                 Self::pwrite(fd, buf, len, offset)
             }
 
+            SYS_SENDFILE => {
+                let out_fd = args[0] as i32;
+                let in_fd = args[1] as i32;
+                let offset = args[2] as *mut i64;
+                let count = args[3];
+                Self::sys_sendfile(out_fd, in_fd, offset, count)
+            }
+
+            SYS_SPLICE => {
+                let fd_in = args[0] as i32;
+                let off_in = args[1] as *mut i64;
+                let fd_out = args[2] as i32;
+                let off_out = args[3] as *mut i64;
+                let len = args[4];
+                let flags = args[5] as u32;
+                Self::sys_splice(fd_in, off_in, fd_out, off_out, len, flags)
+            }
+
             SYS_IOCTL => {
                 let fd = args[0];
                 let cmd = args[1];
@@ -300,6 +343,14 @@ impl Syscall {
                 Self::wait4(pid.into(), wstatus, options, rusage)
             }
 
+            SYS_WAITID => Self::waitid(
+                args[0] as i32,
+                args[1] as i32,
+                args[2] as *mut c_void,
+                args[3] as c_int,
+                args[4] as *mut c_void,
+            ),
+
             SYS_EXIT => {
                 let exit_code = args[0];
                 Self::exit(exit_code)
@@ -351,8 +402,10 @@ impl Syscall {
                 if pipefd.is_null() {
                     Err(SystemError::EFAULT)
                 } else {
-                    let flags = FileMode::from_bits_truncate(arg1 as u32);
-                    Self::pipe2(pipefd, flags)
+                    match FileMode::from_bits(arg1 as u32) {
+                        Some(flags) => Self::pipe2(pipefd, flags),
+                        None => Err(SystemError::EINVAL),
+                    }
                 }
             }
 
@@ -523,7 +576,12 @@ impl Syscall {
                     // 地址空间超出了用户空间的范围，不合法
                     Err(SystemError::EFAULT)
                 } else {
-                    let data: &[u8] = unsafe { core::slice::from_raw_parts(buf, len) };
+                    // AF_XDP用sendto(fd, NULL, 0, ...)通知内核发送
+                    let data: &[u8] = if len == 0 {
+                        &[]
+                    } else {
+                        unsafe { core::slice::from_raw_parts(buf, len) }
+                    };
                     Self::sendto(args[0], data, flags, addr, addrlen)
                 }
             }
@@ -560,11 +618,28 @@ impl Syscall {
                 if let Err(e) = r {
                     Err(e)
                 } else {
-                    let buf = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+                    let buf: &mut [u8] = if len == 0 {
+                        &mut []
+                    } else {
+                        unsafe { core::slice::from_raw_parts_mut(buf, len) }
+                    };
                     Self::recvfrom(args[0], buf, flags, addr, addrlen as *mut u32)
                 }
             }
 
+            SYS_SENDMSG => {
+                let msg = args[1] as *const MsgHdr;
+                let flags = args[2] as u32;
+
+                let user_buffer_reader = UserBufferReader::new(
+                    msg,
+                    core::mem::size_of::<MsgHdr>(),
+                    frame.is_from_user(),
+                )?;
+                let msg = user_buffer_reader.read_one_from_user::<MsgHdr>(0)?;
+                Self::sendmsg(args[0], msg, flags)
+            }
+
             SYS_RECVMSG => {
                 let msg = args[1] as *mut MsgHdr;
                 let flags = args[2] as u32;
@@ -727,6 +802,10 @@ impl Syscall {
                 clone_args.parent_tid = parent_tid;
                 clone_args.child_tid = child_tid;
                 clone_args.tls = args[4];
+                // clone系统调用没有单独的pidfd参数，与Linux相同，pidfd写到parent_tid的位置
+                if clone_args.flags.contains(CloneFlags::CLONE_PIDFD) {
+                    clone_args.pidfd = parent_tid;
+                }
                 Self::clone(frame, clone_args)
             }
 
@@ -739,7 +818,10 @@ impl Syscall {
                 let val3 = args[5] as u32;
 
                 let mut timespec = None;
-                if utime != 0 && operation.contains(FutexFlag::FLAGS_HAS_TIMEOUT) {
+                let has_timeout =
+                    FutexArg::from_bits(operation.bits() & FutexFlag::FUTEX_CMD_MASK.bits())
+                        .is_some_and(|cmd| cmd.has_timeout());
+                if utime != 0 && has_timeout {
                     let reader = UserBufferReader::new(
                         utime as *const PosixTimeSpec,
                         core::mem::size_of::<PosixTimeSpec>(),
@@ -774,6 +856,8 @@ impl Syscall {
 
             SYS_READV => Self::readv(args[0] as i32, args[1], args[2]),
             SYS_WRITEV => Self::writev(args[0] as i32, args[1], args[2]),
+            SYS_PREADV => Self::preadv(args[0] as i32, args[1], args[2], args[3]),
+            SYS_PWRITEV => Self::pwritev(args[0] as i32, args[1], args[2], args[3]),
 
             SYS_SET_TID_ADDRESS => Self::set_tid_address(args[0]),
 
@@ -915,6 +999,23 @@ impl Syscall {
                 }
             }
 
+            SYS_PROCESS_VM_READV => Self::process_vm_readv(
+                Pid::new(args[0]),
+                args[1] as *const IoVec,
+                args[2],
+                args[3] as *const IoVec,
+                args[4],
+                args[5],
+            ),
+            SYS_PROCESS_VM_WRITEV => Self::process_vm_writev(
+                Pid::new(args[0]),
+                args[1] as *const IoVec,
+                args[2],
+                args[3] as *const IoVec,
+                args[4],
+                args[5],
+            ),
+
             SYS_GETTID => Self::gettid().map(|tid| tid.into()),
 
             SYS_SYSLOG => {
@@ -942,6 +1043,16 @@ impl Syscall {
             SYS_SETFSUID => Self::setfsuid(args[0]),
             SYS_SETFSGID => Self::setfsgid(args[0]),
 
+            SYS_CAPGET => Self::capget(args[0] as *mut CapUserHeader, args[1] as *mut CapUserData),
+            SYS_CAPSET => {
+                Self::capset(args[0] as *mut CapUserHeader, args[1] as *const CapUserData)
+            }
+
+            SYS_GETPRIORITY => Self::getpriority(args[0] as i32, args[1] as i32),
+            SYS_SETPRIORITY => Self::setpriority(args[0] as i32, args[1] as i32, args[2] as i32),
+            SYS_IOPRIO_SET => Self::ioprio_set(args[0] as i32, args[1] as i32, args[2] as i32),
+            SYS_IOPRIO_GET => Self::ioprio_get(args[0] as i32, args[1] as i32),
+
             SYS_SETSID => {
                 warn!("SYS_SETSID has not yet been implemented");
                 Ok(0)
@@ -1006,6 +1117,20 @@ impl Syscall {
                 Self::clock_gettime(clockid, timespec)
             }
 
+            SYS_CLOCK_GETRES => {
+                let clockid = args[0] as i32;
+                let res = args[1] as *mut PosixTimeSpec;
+                Self::clock_getres(clockid, res)
+            }
+
+            SYS_CLOCK_NANOSLEEP => {
+                let clockid = args[0] as i32;
+                let flags = args[1] as i32;
+                let request = args[2] as *const PosixTimeSpec;
+                let remain = args[3] as *mut PosixTimeSpec;
+                Self::clock_nanosleep(clockid, flags, request, remain)
+            }
+
             SYS_SYSINFO => {
                 let info = args[0] as *mut SysInfo;
                 Self::sysinfo(info)
@@ -1045,10 +1170,7 @@ impl Syscall {
                 Self::fchownat(dirfd, pathname, uid, gid, flag)
             }
 
-            SYS_FSYNC => {
-                warn!("SYS_FSYNC has not yet been implemented");
-                Ok(0)
-            }
+            SYS_FSYNC | SYS_FDATASYNC => Self::fsync(args[0] as i32),
 
             SYS_RSEQ => {
                 warn!("SYS_RSEQ has not yet been implemented");
@@ -1075,17 +1197,38 @@ impl Syscall {
 
             SYS_SCHED_YIELD => Self::do_sched_yield(),
 
-            SYS_SCHED_GETAFFINITY => {
-                let pid = args[0] as i32;
-                let size = args[1];
-                let set_vaddr = args[2];
-
-                let mut user_buffer_writer =
-                    UserBufferWriter::new(set_vaddr as *mut u8, size, frame.is_from_user())?;
-                let set: &mut [u8] = user_buffer_writer.buffer(0)?;
+            SYS_SCHED_SETSCHEDULER => Self::sched_setscheduler(
+                args[0] as i32,
+                args[1] as i32,
+                args[2] as *const SchedParam,
+            ),
+            SYS_SCHED_GETSCHEDULER => Self::sched_getscheduler(args[0] as i32),
+            SYS_SCHED_SETPARAM => {
+                Self::sched_setparam(args[0] as i32, args[1] as *const SchedParam)
+            }
+            SYS_SCHED_GETPARAM => Self::sched_getparam(args[0] as i32, args[1] as *mut SchedParam),
+            SYS_SCHED_GET_PRIORITY_MAX => Self::sched_get_priority_max(args[0] as i32),
+            SYS_SCHED_GET_PRIORITY_MIN => Self::sched_get_priority_min(args[0] as i32),
+            SYS_SCHED_SETATTR => {
+                Self::sched_setattr(args[0] as i32, args[1] as *mut SchedAttr, args[2] as u32)
+            }
+            SYS_SCHED_GETATTR => Self::sched_getattr(
+                args[0] as i32,
+                args[1] as *mut SchedAttr,
+                args[2],
+                args[3] as u32,
+            ),
+            SYS_SCHED_RR_GET_INTERVAL => {
+                Self::sched_rr_get_interval(args[0] as i32, args[1] as *mut PosixTimeSpec)
+            }
 
-                Self::getaffinity(pid, set)
+            SYS_SCHED_GETAFFINITY => {
+                Self::sched_getaffinity(args[0] as i32, args[1], args[2] as *mut u8)
+            }
+            SYS_SCHED_SETAFFINITY => {
+                Self::sched_setaffinity(args[0] as i32, args[1], args[2] as *const u8)
             }
+            SYS_GETCPU => Self::getcpu(args[0] as *mut u32, args[1] as *mut u32),
 
             #[cfg(target_arch = "x86_64")]
             SYS_GETRLIMIT => {
@@ -1100,11 +1243,12 @@ impl Syscall {
                 )
             }
 
-            SYS_FADVISE64 => {
-                // todo: 这个系统调用还没有实现
-
-                Err(SystemError::ENOSYS)
-            }
+            SYS_FADVISE64 => Self::fadvise64(
+                args[0] as i32,
+                args[1] as i64,
+                args[2] as i64,
+                args[3] as i32,
+            ),
 
             SYS_MOUNT => {
                 let source = args[0] as *const u8;
@@ -1133,11 +1277,8 @@ impl Syscall {
                 let name = args[0] as *mut PosixOldUtsName;
                 Self::uname(name)
             }
-            SYS_PRCTL => {
-                // todo: 这个系统调用还没有实现
-
-                Err(SystemError::EINVAL)
-            }
+            SYS_PRCTL => Self::prctl(args[0], args[1], args[2], args[3], args[4]),
+            SYS_SECCOMP => Self::sys_seccomp(args[0] as u32, args[1] as u32, args[2]),
 
             #[cfg(target_arch = "x86_64")]
             SYS_ALARM => {
@@ -1171,8 +1312,37 @@ impl Syscall {
 
                 Self::shmctl(id, cmd, user_buf, from_user)
             }
+            SYS_SEMGET => Self::sys_semget(args[0] as i32, args[1] as i32, args[2] as u32),
+            SYS_SEMOP => Self::sys_semtimedop(
+                args[0] as i32,
+                args[1] as *const PosixSembuf,
+                args[2],
+                core::ptr::null(),
+            ),
+            SYS_SEMTIMEDOP => Self::sys_semtimedop(
+                args[0] as i32,
+                args[1] as *const PosixSembuf,
+                args[2],
+                args[3] as *const PosixTimeSpec,
+            ),
+            SYS_SEMCTL => Self::sys_semctl(args[0] as i32, args[1] as i32, args[2] as i32, args[3]),
+            SYS_MSGGET => Self::sys_msgget(args[0] as i32, args[1] as u32),
+            SYS_MSGSND => Self::sys_msgsnd(
+                args[0] as i32,
+                args[1] as *const u8,
+                args[2],
+                args[3] as u32,
+            ),
+            SYS_MSGRCV => Self::sys_msgrcv(
+                args[0] as i32,
+                args[1] as *mut u8,
+                args[2],
+                args[3] as i64,
+                args[4] as u32,
+            ),
+            SYS_MSGCTL => Self::sys_msgctl(args[0] as i32, args[1] as i32, args[2]),
             SYS_MSYNC => {
-                let start = page_align_up(args[0]);
+                let start = args[0];
                 let len = page_align_up(args[1]);
                 let flags = args[2];
                 Self::msync(VirtAddr::new(start), len, flags)
@@ -1205,6 +1375,58 @@ impl Syscall {
                 let flags = args[1] as u32;
                 Self::sys_eventfd(initval, flags)
             }
+            SYS_PIDFD_OPEN => Self::sys_pidfd_open(args[0] as i32, args[1] as u32),
+            SYS_PIDFD_SEND_SIGNAL => Self::sys_pidfd_send_signal(
+                args[0] as i32,
+                args[1] as i32,
+                args[2] as *const c_void,
+                args[3] as u32,
+            ),
+            #[cfg(target_arch = "x86_64")]
+            SYS_SIGNALFD => Self::sys_signalfd4(args[0] as i32, args[1] as *const u64, args[2], 0),
+            SYS_SIGNALFD4 => Self::sys_signalfd4(
+                args[0] as i32,
+                args[1] as *const u64,
+                args[2],
+                args[3] as u32,
+            ),
+            SYS_TIMERFD_CREATE => Self::sys_timerfd_create(args[0] as i32, args[1] as u32),
+            SYS_TIMERFD_SETTIME => Self::sys_timerfd_settime(
+                args[0] as i32,
+                args[1] as u32,
+                args[2] as *const PosixItimerspec,
+                args[3] as *mut PosixItimerspec,
+            ),
+            SYS_TIMERFD_GETTIME => {
+                Self::sys_timerfd_gettime(args[0] as i32, args[1] as *mut PosixItimerspec)
+            }
+            SYS_MQ_OPEN => Self::sys_mq_open(
+                args[0] as *const u8,
+                args[1] as u32,
+                args[2] as u32,
+                args[3] as *const PosixMqAttr,
+            ),
+            SYS_MQ_UNLINK => Self::sys_mq_unlink(args[0] as *const u8),
+            SYS_MQ_TIMEDSEND => Self::sys_mq_timedsend(
+                args[0] as i32,
+                args[1] as *const u8,
+                args[2],
+                args[3] as u32,
+                args[4] as *const PosixTimeSpec,
+            ),
+            SYS_MQ_TIMEDRECEIVE => Self::sys_mq_timedreceive(
+                args[0] as i32,
+                args[1] as *mut u8,
+                args[2],
+                args[3] as *mut u32,
+                args[4] as *const PosixTimeSpec,
+            ),
+            SYS_MQ_NOTIFY => Self::sys_mq_notify(args[0] as i32, args[1] as *const PosixSigevent),
+            SYS_MQ_GETSETATTR => Self::sys_mq_getsetattr(
+                args[0] as i32,
+                args[1] as *const PosixMqAttr,
+                args[2] as *mut PosixMqAttr,
+            ),
             SYS_UNSHARE => Self::sys_unshare(args[0] as u64),
             SYS_BPF => {
                 let cmd = args[0] as u32;
@@ -1220,6 +1442,35 @@ impl Syscall {
                 let flags = args[4] as u32;
                 Self::sys_perf_event_open(attr, pid, cpu, group_fd, flags)
             }
+            SYS_IO_URING_SETUP => {
+                let entries = args[0] as u32;
+                let params = args[1] as *mut crate::filesystem::io_uring::IoUringParams;
+                Self::sys_io_uring_setup(entries, params)
+            }
+            SYS_IO_URING_ENTER => {
+                let fd = args[0] as i32;
+                let to_submit = args[1] as u32;
+                let min_complete = args[2] as u32;
+                let flags = args[3] as u32;
+                Self::sys_io_uring_enter(fd, to_submit, min_complete, flags)
+            }
+            SYS_IO_URING_REGISTER => {
+                let fd = args[0] as i32;
+                let opcode = args[1] as u32;
+                let arg = args[2];
+                let nr_args = args[3] as u32;
+                Self::sys_io_uring_register(fd, opcode, arg, nr_args)
+            }
+            SYS_DRAGONOS_CHANNEL_CREATE => {
+                let params = args[0] as *mut crate::ipc::channel::ChannelParams;
+                let fds = args[1] as *mut i32;
+                Self::sys_channel_create(params, fds)
+            }
+            SYS_DRAGONOS_CHANNEL_CTL => {
+                let fd = args[0] as i32;
+                let op = args[1] as u32;
+                Self::sys_channel_ctl(fd, op, args[2], args[3])
+            }
             SYS_SETRLIMIT => Ok(0),
             SYS_RESTART_SYSCALL => Self::restart_syscall(),
             _ => panic!("Unsupported syscall ID: {}", syscall_num),
@@ -1255,6 +1506,13 @@ impl Syscall {
     }
 
     pub fn reboot() -> Result<usize, SystemError> {
+        if !ProcessManager::current_pcb()
+            .cred()
+            .has_capability(CAPFlags::CAP_SYS_BOOT)
+        {
+            return Err(SystemError::EPERM);
+        }
+        obj_lifetime_report();
         unsafe { cpu_reset() };
     }
 }
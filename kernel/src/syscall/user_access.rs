@@ -1,14 +1,18 @@
 //! 这个文件用于放置一些内核态访问用户态数据的函数
 
 use core::{
-    mem::size_of,
+    marker::PhantomData,
+    mem::{size_of, size_of_val, MaybeUninit},
     num::NonZero,
     slice::{from_raw_parts, from_raw_parts_mut},
 };
 
-use alloc::{ffi::CString, vec::Vec};
+use alloc::{ffi::CString, vec, vec::Vec};
 
-use crate::mm::{verify_area, VirtAddr};
+use crate::{
+    arch::mm::extable::{__clear_user, __copy_user},
+    mm::{verify_area, VirtAddr},
+};
 
 use super::SystemError;
 
@@ -29,32 +33,46 @@ use super::SystemError;
 pub unsafe fn clear_user(dest: VirtAddr, len: usize) -> Result<usize, SystemError> {
     verify_area(dest, len).map_err(|_| SystemError::EFAULT)?;
 
-    let p = dest.data() as *mut u8;
-    // 清空用户空间的数据
-    p.write_bytes(0, len);
+    // 清空用户空间的数据，访问出错时由异常表修复，返回未清空的字节数
+    if __clear_user(dest.data() as *mut u8, len) != 0 {
+        return Err(SystemError::EFAULT);
+    }
     return Ok(len);
 }
 
+/// 从内核空间拷贝数据到用户空间
+///
+/// 用户地址未映射或不可写时返回`EFAULT`，不会导致内核panic
 pub unsafe fn copy_to_user(dest: VirtAddr, src: &[u8]) -> Result<usize, SystemError> {
     verify_area(dest, src.len()).map_err(|_| SystemError::EFAULT)?;
 
-    let p = dest.data() as *mut u8;
     // 拷贝数据
-    p.copy_from_nonoverlapping(src.as_ptr(), src.len());
+    copy_user_raw(dest.data() as *mut u8, src.as_ptr(), src.len())?;
     return Ok(src.len());
 }
 
 /// 从用户空间拷贝数据到内核空间
+///
+/// 用户地址未映射时返回`EFAULT`，不会导致内核panic
 pub unsafe fn copy_from_user(dst: &mut [u8], src: VirtAddr) -> Result<usize, SystemError> {
     verify_area(src, dst.len()).map_err(|_| SystemError::EFAULT)?;
 
-    let src: &[u8] = core::slice::from_raw_parts(src.data() as *const u8, dst.len());
     // 拷贝数据
-    dst.copy_from_slice(src);
+    copy_user_raw(dst.as_mut_ptr(), src.data() as *const u8, dst.len())?;
 
     return Ok(dst.len());
 }
 
+/// 在两段不重叠的内存之间拷贝`len`字节，其中至少一段可能位于用户空间
+///
+/// 访问出错时由异常表修复，返回`EFAULT`
+unsafe fn copy_user_raw(dst: *mut u8, src: *const u8, len: usize) -> Result<(), SystemError> {
+    if __copy_user(dst, src, len) != 0 {
+        return Err(SystemError::EFAULT);
+    }
+    return Ok(());
+}
+
 /// 检查并从用户态拷贝一个 C 字符串。
 ///
 /// 一旦遇到非法地址，就会返回错误
@@ -208,8 +226,17 @@ impl UserBufferReader<'_> {
         dst: &mut [T],
         offset: usize,
     ) -> Result<usize, SystemError> {
-        let data = self.convert_with_offset(self.buffer, offset)?;
-        dst.copy_from_slice(data);
+        let data = self.convert_with_offset::<T>(self.buffer, offset)?;
+        if data.len() != dst.len() {
+            return Err(SystemError::EINVAL);
+        }
+        unsafe {
+            copy_user_raw(
+                dst.as_mut_ptr() as *mut u8,
+                data.as_ptr() as *const u8,
+                size_of_val(dst),
+            )?
+        };
         return Ok(dst.len());
     }
 
@@ -224,7 +251,13 @@ impl UserBufferReader<'_> {
         offset: usize,
     ) -> Result<(), SystemError> {
         let data = self.convert_one_with_offset::<T>(self.buffer, offset)?;
-        dst.clone_from(data);
+        unsafe {
+            copy_user_raw(
+                dst as *mut T as *mut u8,
+                data as *const T as *const u8,
+                size_of::<T>(),
+            )?
+        };
         return Ok(());
     }
 
@@ -300,8 +333,17 @@ impl<'a> UserBufferWriter<'a> {
         src: &[T],
         offset: usize,
     ) -> Result<usize, SystemError> {
-        let dst = Self::convert_with_offset(self.buffer, offset)?;
-        dst.copy_from_slice(src);
+        let dst = Self::convert_with_offset::<T>(self.buffer, offset)?;
+        if dst.len() != src.len() {
+            return Err(SystemError::EINVAL);
+        }
+        unsafe {
+            copy_user_raw(
+                dst.as_mut_ptr() as *mut u8,
+                src.as_ptr() as *const u8,
+                size_of_val(src),
+            )?
+        };
         return Ok(src.len());
     }
 
@@ -317,7 +359,13 @@ impl<'a> UserBufferWriter<'a> {
         offset: usize,
     ) -> Result<(), SystemError> {
         let dst = Self::convert_one_with_offset::<T>(self.buffer, offset)?;
-        dst.clone_from(src);
+        unsafe {
+            copy_user_raw(
+                dst as *mut T as *mut u8,
+                src as *const T as *const u8,
+                size_of::<T>(),
+            )?
+        };
         return Ok(());
     }
 
@@ -354,3 +402,309 @@ impl<'a> UserBufferWriter<'a> {
         return Ok(data);
     }
 }
+
+/// 检查`[addr, addr + len)`是否完全位于用户地址空间内
+///
+/// 不检查这段内存是否已经映射，未映射的页面在实际拷贝时才会被发现
+#[inline]
+pub fn access_ok(addr: VirtAddr, len: usize) -> Result<(), SystemError> {
+    verify_area(addr, len).map_err(|_| SystemError::EFAULT)
+}
+
+/// 可以按字节在内核与用户空间之间拷贝的类型
+///
+/// 用户空间与内核的字节序相同，所以按字节拷贝得到的就是用户程序看到的值。
+/// 以网络字节序保存的字段应当通过[`UserSliceReader::read_u16_be`]等方法读取。
+///
+/// # Safety
+///
+/// 实现者必须是基本类型或`#[repr(C)]`的结构体，且任意位模式都是合法的值
+/// （不能含有引用、`bool`、枚举等）。写回用户空间时会连同填充字节一起拷贝，
+/// 所以写回的结构体应当先用`Default`完整初始化，避免泄露内核栈上的数据。
+pub unsafe trait UserPod: Copy {}
+
+macro_rules! impl_user_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl UserPod for $t {})*
+    };
+}
+
+impl_user_pod!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+unsafe impl<T: UserPod, const N: usize> UserPod for [T; N] {}
+
+/// 指向用户空间中一个`T`的指针
+///
+/// 每次读写都重新检查地址范围，并把数据拷贝到内核中，而不是在内核中持有对用户内存的引用，
+/// 因此用户程序在系统调用执行期间修改这块内存也不会破坏内核的数据。
+/// 指针不要求按`T`对齐。
+pub struct UserPtr<T> {
+    addr: VirtAddr,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T> core::fmt::Debug for UserPtr<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "UserPtr({:#x})", self.addr.data())
+    }
+}
+
+impl<T: UserPod> UserPtr<T> {
+    pub fn new(addr: usize) -> Self {
+        Self {
+            addr: VirtAddr::new(addr),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn from_ptr(ptr: *const T) -> Self {
+        Self::new(ptr as usize)
+    }
+
+    #[inline]
+    pub fn addr(&self) -> VirtAddr {
+        self.addr
+    }
+
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        self.addr.is_null()
+    }
+
+    /// 指向之后第`count`个元素的指针，地址溢出时返回`EFAULT`
+    pub fn add(&self, count: usize) -> Result<Self, SystemError> {
+        count
+            .checked_mul(size_of::<T>())
+            .and_then(|off| self.addr.data().checked_add(off))
+            .map(Self::new)
+            .ok_or(SystemError::EFAULT)
+    }
+
+    /// 从用户空间读取一个`T`
+    pub fn read(&self) -> Result<T, SystemError> {
+        let mut val = MaybeUninit::<T>::uninit();
+        let bytes = unsafe { from_raw_parts_mut(val.as_mut_ptr() as *mut u8, size_of::<T>()) };
+        UserSlice::new(self.addr, size_of::<T>())?.read_raw(bytes)?;
+        // 所有字节都已被写入，且`T: UserPod`保证任意位模式都合法
+        Ok(unsafe { val.assume_init() })
+    }
+
+    /// 指针为空时返回`None`，否则读取一个`T`
+    pub fn read_opt(&self) -> Result<Option<T>, SystemError> {
+        if self.is_null() {
+            return Ok(None);
+        }
+        self.read().map(Some)
+    }
+
+    /// 把`val`写入用户空间
+    pub fn write(&self, val: &T) -> Result<(), SystemError> {
+        let bytes = unsafe { from_raw_parts(val as *const T as *const u8, size_of::<T>()) };
+        UserSlice::new(self.addr, size_of::<T>())?.write_raw(bytes)
+    }
+}
+
+/// 用户空间中的一段内存
+///
+/// 创建时检查整段内存都位于用户地址空间内，之后通过[`UserSliceReader`]与[`UserSliceWriter`]
+/// 顺序地读写。所有对用户内存的实际访问都集中在`copy_from_user`/`copy_to_user`中。
+#[derive(Debug, Clone, Copy)]
+pub struct UserSlice {
+    addr: VirtAddr,
+    len: usize,
+}
+
+impl UserSlice {
+    pub fn new(addr: VirtAddr, len: usize) -> Result<Self, SystemError> {
+        access_ok(addr, len)?;
+        Ok(Self { addr, len })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 把整段内存拷贝到`dst`中，`dst`的长度必须与这段内存相同
+    fn read_raw(&self, dst: &mut [u8]) -> Result<(), SystemError> {
+        if dst.len() != self.len {
+            return Err(SystemError::EINVAL);
+        }
+        unsafe { copy_from_user(dst, self.addr)? };
+        Ok(())
+    }
+
+    /// 把`src`写入整段内存，`src`的长度必须与这段内存相同
+    fn write_raw(&self, src: &[u8]) -> Result<(), SystemError> {
+        if src.len() != self.len {
+            return Err(SystemError::EINVAL);
+        }
+        unsafe { copy_to_user(self.addr, src)? };
+        Ok(())
+    }
+
+    /// 把整段内存拷贝到一个新的`Vec`中
+    pub fn read_all(&self) -> Result<Vec<u8>, SystemError> {
+        let mut buf = vec![0u8; self.len];
+        self.read_raw(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn reader(&self) -> UserSliceReader {
+        UserSliceReader {
+            slice: *self,
+            pos: 0,
+        }
+    }
+
+    pub fn writer(&self) -> UserSliceWriter {
+        UserSliceWriter {
+            slice: *self,
+            pos: 0,
+        }
+    }
+
+    /// 从`pos`开始，长度为`len`的子段，超出范围时返回`EFAULT`
+    fn sub(&self, pos: usize, len: usize) -> Result<UserSlice, SystemError> {
+        if pos.checked_add(len).is_none_or(|end| end > self.len) {
+            return Err(SystemError::EFAULT);
+        }
+        Ok(UserSlice {
+            addr: self.addr + pos,
+            len,
+        })
+    }
+}
+
+/// 顺序地从[`UserSlice`]中读取数据
+#[derive(Debug)]
+pub struct UserSliceReader {
+    slice: UserSlice,
+    pos: usize,
+}
+
+impl UserSliceReader {
+    /// 尚未读取的字节数
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.slice.len - self.pos
+    }
+
+    /// 读取`dst.len()`字节，剩下的数据不足时返回`EFAULT`
+    pub fn read_raw(&mut self, dst: &mut [u8]) -> Result<(), SystemError> {
+        self.slice.sub(self.pos, dst.len())?.read_raw(dst)?;
+        self.pos += dst.len();
+        Ok(())
+    }
+
+    /// 读取一个`T`
+    pub fn read<T: UserPod>(&mut self) -> Result<T, SystemError> {
+        let val =
+            UserPtr::<T>::new(self.slice.sub(self.pos, size_of::<T>())?.addr.data()).read()?;
+        self.pos += size_of::<T>();
+        Ok(val)
+    }
+
+    /// 读取`count`个`T`
+    pub fn read_vec<T: UserPod>(&mut self, count: usize) -> Result<Vec<T>, SystemError> {
+        (0..count).map(|_| self.read::<T>()).collect()
+    }
+
+    /// 以大端序（网络字节序）读取一个`u16`
+    pub fn read_u16_be(&mut self) -> Result<u16, SystemError> {
+        self.read::<[u8; 2]>().map(u16::from_be_bytes)
+    }
+
+    /// 以大端序（网络字节序）读取一个`u32`
+    pub fn read_u32_be(&mut self) -> Result<u32, SystemError> {
+        self.read::<[u8; 4]>().map(u32::from_be_bytes)
+    }
+
+    /// 跳过`len`字节
+    pub fn skip(&mut self, len: usize) -> Result<(), SystemError> {
+        self.slice.sub(self.pos, len)?;
+        self.pos += len;
+        Ok(())
+    }
+
+    /// 剩下的数据是否全为0
+    ///
+    /// 用于可扩展的结构体（如`open_how`）：新版本用户程序传入的更大的结构体中，
+    /// 内核不认识的字段必须为0
+    pub fn rest_is_zeroed(&mut self) -> Result<bool, SystemError> {
+        let mut buf = [0u8; 64];
+        while self.remaining() > 0 {
+            let n = self.remaining().min(buf.len());
+            self.read_raw(&mut buf[..n])?;
+            if buf[..n].iter().any(|b| *b != 0) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// 顺序地向[`UserSlice`]中写入数据
+#[derive(Debug)]
+pub struct UserSliceWriter {
+    slice: UserSlice,
+    pos: usize,
+}
+
+impl UserSliceWriter {
+    /// 尚未写入的字节数
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.slice.len - self.pos
+    }
+
+    /// 写入`src`，剩下的空间不足时返回`EFAULT`
+    pub fn write_raw(&mut self, src: &[u8]) -> Result<(), SystemError> {
+        self.slice.sub(self.pos, src.len())?.write_raw(src)?;
+        self.pos += src.len();
+        Ok(())
+    }
+
+    /// 写入一个`T`
+    pub fn write<T: UserPod>(&mut self, val: &T) -> Result<(), SystemError> {
+        UserPtr::<T>::new(self.slice.sub(self.pos, size_of::<T>())?.addr.data()).write(val)?;
+        self.pos += size_of::<T>();
+        Ok(())
+    }
+
+    /// 写入一组`T`
+    pub fn write_slice<T: UserPod>(&mut self, vals: &[T]) -> Result<(), SystemError> {
+        vals.iter().try_for_each(|val| self.write(val))
+    }
+
+    /// 以大端序（网络字节序）写入一个`u16`
+    pub fn write_u16_be(&mut self, val: u16) -> Result<(), SystemError> {
+        self.write(&val.to_be_bytes())
+    }
+
+    /// 以大端序（网络字节序）写入一个`u32`
+    pub fn write_u32_be(&mut self, val: u32) -> Result<(), SystemError> {
+        self.write(&val.to_be_bytes())
+    }
+
+    /// 把接下来的`len`字节清零
+    pub fn clear(&mut self, len: usize) -> Result<(), SystemError> {
+        let sub = self.slice.sub(self.pos, len)?;
+        unsafe { clear_user(sub.addr, len)? };
+        self.pos += len;
+        Ok(())
+    }
+}
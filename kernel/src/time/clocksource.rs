@@ -29,6 +29,7 @@ use crate::{
 
 use super::{
     jiffies::clocksource_default_clock,
+    timekeeping::timekeeping_notify,
     timer::{clock, Timer, TimerFunction},
     NSEC_PER_SEC, NSEC_PER_USEC,
 };
@@ -1002,22 +1003,24 @@ pub fn clocksource_select() {
             info!("Switching to the clocksource {:?}\n", best_name);
             drop(cur_clocksource);
             CUR_CLOCKSOURCE.lock().replace(best.clone());
-            // TODO 通知timerkeeping 切换了时间源
+            timekeeping_notify(best.clone());
         }
     } else {
         // 当前时钟源为空
         CUR_CLOCKSOURCE.lock().replace(best.clone());
+        timekeeping_notify(best.clone());
     }
     debug!("clocksource_select finish, CUR_CLOCKSOURCE = {best:?}");
 }
 
 /// # clocksource模块加载完成
 pub fn clocksource_boot_finish() {
-    let mut cur_clocksource = CUR_CLOCKSOURCE.lock();
-    cur_clocksource.replace(clocksource_default_clock());
+    CUR_CLOCKSOURCE.lock().replace(clocksource_default_clock());
     FINISHED_BOOTING.store(true, Ordering::Relaxed);
     // 清除不稳定的时钟源
     __clocksource_watchdog_kthread();
+    // 启动过程中注册的时钟源还没有被选择过，这里选出最好的一个交给timekeeping
+    clocksource_select();
     debug!("clocksource_boot_finish");
 }
 
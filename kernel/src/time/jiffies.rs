@@ -10,7 +10,7 @@ use crate::{arch::time::CLOCK_TICK_RATE, libs::spinlock::SpinLock};
 use super::{
     clocksource::{Clocksource, ClocksourceData, ClocksourceFlags, ClocksourceMask, CycleNum, HZ},
     timer::clock,
-    NSEC_PER_SEC,
+    NSEC_PER_MSEC, NSEC_PER_SEC, NSEC_PER_USEC,
 };
 lazy_static! {
     pub static ref DEFAULT_CLOCK: Arc<ClocksourceJiffies> = ClocksourceJiffies::new();
@@ -26,6 +26,38 @@ pub const fn sh_div(nom: u32, den: u32, lsh: u32) -> u32 {
     (((nom) / (den)) << (lsh)) + ((((nom) % (den)) << (lsh)) + (den) / 2) / (den)
 }
 
+/// 把纳秒数换算为jiffies数
+///
+/// 向上取整，保证以此设置的定时器不会提前到期
+#[inline]
+pub const fn nsecs_to_jiffies(ns: u64) -> u64 {
+    ns.div_ceil(NSEC_PER_JIFFY as u64)
+}
+
+/// 把微秒数换算为jiffies数，向上取整
+#[inline]
+pub const fn usecs_to_jiffies(us: u64) -> u64 {
+    nsecs_to_jiffies(us * NSEC_PER_USEC as u64)
+}
+
+/// 把毫秒数换算为jiffies数，向上取整
+#[inline]
+pub const fn msecs_to_jiffies(ms: u64) -> u64 {
+    nsecs_to_jiffies(ms * NSEC_PER_MSEC as u64)
+}
+
+/// 把jiffies数换算为纳秒数
+#[inline]
+pub const fn jiffies_to_nsecs(jiffies: u64) -> u64 {
+    jiffies * NSEC_PER_JIFFY as u64
+}
+
+/// 把jiffies数换算为毫秒数
+#[inline]
+pub const fn jiffies_to_msecs(jiffies: u64) -> u64 {
+    jiffies_to_nsecs(jiffies) / NSEC_PER_MSEC as u64
+}
+
 #[derive(Debug)]
 pub struct ClocksourceJiffies(SpinLock<InnerJiffies>);
 
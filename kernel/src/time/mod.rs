@@ -1,13 +1,11 @@
 use core::{
     fmt,
-    intrinsics::unlikely,
     ops::{self, Sub},
 };
 
-use crate::arch::CurrentTimeArch;
 use crate::time::syscall::PosixTimeval;
 
-use self::timekeeping::getnstimeofday;
+use self::timekeeping::{getnstimeofday, ktime_get};
 
 pub mod clocksource;
 pub mod jiffies;
@@ -71,27 +69,6 @@ impl PosixTimeSpec {
         getnstimeofday()
     }
 
-    /// 获取当前CPU时间(使用CPU时钟周期计算，会存在回绕问题)
-    pub fn now_cpu_time() -> Self {
-        #[cfg(target_arch = "x86_64")]
-        {
-            use crate::arch::driver::tsc::TSCManager;
-            let khz = TSCManager::cpu_khz();
-            if unlikely(khz == 0) {
-                return PosixTimeSpec::default();
-            } else {
-                return Self::from(Duration::from_millis(
-                    CurrentTimeArch::get_cycles() as u64 / khz,
-                ));
-            }
-        }
-
-        #[cfg(target_arch = "riscv64")]
-        {
-            return PosixTimeSpec::new(0, 0);
-        }
-    }
-
     /// 换算成纳秒
     pub fn total_nanos(&self) -> i64 {
         self.tv_sec * 1000000000 + self.tv_nsec
@@ -100,10 +77,10 @@ impl PosixTimeSpec {
 
 impl Sub for PosixTimeSpec {
     type Output = Duration;
+    /// 两个时间点的差值，`rhs`晚于`self`时为0
     fn sub(self, rhs: Self) -> Self::Output {
-        let sec = self.tv_sec.checked_sub(rhs.tv_sec).unwrap_or(0);
-        let nsec = self.tv_nsec.checked_sub(rhs.tv_nsec).unwrap_or(0);
-        Duration::from_micros((sec * 1000000 + nsec / 1000) as u64)
+        let nsecs = self.total_nanos().saturating_sub(rhs.total_nanos()).max(0);
+        Duration::from_micros(nsecs as u64 / 1000)
     }
 }
 
@@ -137,6 +114,9 @@ impl From<PosixTimeSpec> for Duration {
 /// represents a number of microseconds, monotonically increasing
 /// since an arbitrary moment in time, such as system startup.
 ///
+/// [`Instant::now`] 返回系统启动以来的单调时间（见[`timekeeping::ktime_get`]），
+/// 不受修改墙上时间的影响，适合用来计算超时与时间间隔。
+///
 /// * A value of `0` is inherently arbitrary.
 /// * A value less than `0` indicates a time before the starting
 ///   point.
@@ -236,10 +216,9 @@ impl Instant {
         }
     }
 
-    /// Create a new `Instant` from the current time
+    /// Create a new `Instant` from the current monotonic time
     pub fn now() -> Instant {
-        let tm = getnstimeofday();
-        Self::from_micros(tm.tv_sec * 1000000 + tm.tv_nsec / 1000)
+        Self::from_micros(ktime_get() / NSEC_PER_USEC as i64)
     }
 
     /// The fractional number of milliseconds that have passed
@@ -8,7 +8,7 @@ use crate::{
     exception::InterruptArch,
     process::ProcessManager,
     sched::{schedule, SchedMode},
-    time::timekeeping::getnstimeofday,
+    time::timekeeping::ktime_get_ts64,
 };
 
 use super::{
@@ -49,13 +49,13 @@ pub fn nanosleep(sleep_time: PosixTimeSpec) -> Result<PosixTimeSpec, SystemError
         unsafe { CurrentIrqArch::save_and_disable_irq() };
     ProcessManager::mark_sleep(true).ok();
 
-    let start_time = getnstimeofday();
+    let start_time = ktime_get_ts64();
     timer.activate();
 
     drop(irq_guard);
     schedule(SchedMode::SM_NONE);
 
-    let end_time = getnstimeofday();
+    let end_time = ktime_get_ts64();
     // 返回正确的剩余时间
     let real_sleep_time = end_time - start_time;
     let rm_time: PosixTimeSpec = (sleep_time - real_sleep_time.into()).into();
@@ -7,11 +7,17 @@ use system_error::SystemError;
 
 use crate::{
     process::{timer::AlarmTimer, ProcessManager},
-    syscall::{user_access::UserBufferWriter, Syscall},
+    syscall::{
+        user_access::{UserBufferReader, UserBufferWriter},
+        Syscall,
+    },
     time::{sleep::nanosleep, PosixTimeSpec},
 };
 
-use super::timekeeping::{do_gettimeofday, getnstimeofday};
+use super::jiffies::NSEC_PER_JIFFY;
+use super::timekeeping::{
+    do_gettimeofday, getnstimeofday, ktime_get_boottime_ts64, ktime_get_ts64,
+};
 
 pub type PosixTimeT = c_longlong;
 pub type PosixSusecondsT = c_int;
@@ -136,9 +142,6 @@ impl Syscall {
 
     pub fn clock_gettime(clock_id: c_int, tp: *mut PosixTimeSpec) -> Result<usize, SystemError> {
         let clock_id = PosixClockID::try_from(clock_id)?;
-        if clock_id != PosixClockID::Realtime {
-            // warn!("clock_gettime: currently only support Realtime clock, but got {:?}. Defaultly return realtime!!!\n", clock_id);
-        }
         if tp.is_null() {
             return Err(SystemError::EFAULT);
         }
@@ -148,12 +151,112 @@ impl Syscall {
             true,
         )?;
 
-        let timespec = getnstimeofday();
+        let timespec = match clock_id {
+            PosixClockID::Monotonic
+            | PosixClockID::MonotonicRaw
+            | PosixClockID::MonotonicCoarse => ktime_get_ts64(),
+            PosixClockID::Boottime | PosixClockID::BoottimeAlarm => ktime_get_boottime_ts64(),
+            // TODO: 进程、线程的CPU时间，暂时返回墙上时间
+            _ => getnstimeofday(),
+        };
 
         tp_buf.copy_one_to_user(&timespec, 0)?;
 
         return Ok(0);
     }
+    /// # clock_getres系统调用
+    ///
+    /// 获取时钟的精度。高精度时钟的精度为1ns，粗粒度时钟的精度为一个jiffy
+    ///
+    /// ## 参数
+    ///
+    /// - `clock_id`: 时钟id
+    /// - `res`: 用于返回精度的用户缓冲区，可以为空
+    pub fn clock_getres(clock_id: c_int, res: *mut PosixTimeSpec) -> Result<usize, SystemError> {
+        let clock_id = PosixClockID::try_from(clock_id)?;
+        if res.is_null() {
+            return Ok(0);
+        }
+        let mut res_buf = UserBufferWriter::new::<PosixTimeSpec>(
+            res,
+            core::mem::size_of::<PosixTimeSpec>(),
+            true,
+        )?;
+
+        let nsec = match clock_id {
+            PosixClockID::RealtimeCoarse | PosixClockID::MonotonicCoarse => NSEC_PER_JIFFY as i64,
+            _ => 1,
+        };
+        res_buf.copy_one_to_user(
+            &PosixTimeSpec {
+                tv_sec: 0,
+                tv_nsec: nsec,
+            },
+            0,
+        )?;
+
+        return Ok(0);
+    }
+
+    /// # clock_nanosleep系统调用
+    ///
+    /// 在指定的时钟上休眠。设置了`TIMER_ABSTIME`时`request`为绝对时间，否则为相对时间
+    ///
+    /// ## 参数
+    ///
+    /// - `clock_id`: 时钟id，不支持进程、线程的CPU时钟
+    /// - `flags`: 0或者`TIMER_ABSTIME`
+    /// - `request`: 休眠的时间
+    /// - `remain`: 相对休眠被信号打断时，用于返回剩余的时间，可以为空
+    pub fn clock_nanosleep(
+        clock_id: c_int,
+        flags: c_int,
+        request: *const PosixTimeSpec,
+        remain: *mut PosixTimeSpec,
+    ) -> Result<usize, SystemError> {
+        const TIMER_ABSTIME: c_int = 1;
+
+        let clock_id = PosixClockID::try_from(clock_id)?;
+        let now = match clock_id {
+            PosixClockID::Realtime | PosixClockID::RealtimeAlarm => getnstimeofday(),
+            PosixClockID::Monotonic => ktime_get_ts64(),
+            PosixClockID::Boottime | PosixClockID::BoottimeAlarm => ktime_get_boottime_ts64(),
+            PosixClockID::ProcessCPUTimeID | PosixClockID::ThreadCPUTimeID => {
+                return Err(SystemError::EOPNOTSUPP_OR_ENOTSUP)
+            }
+            _ => return Err(SystemError::EINVAL),
+        };
+        let request = *UserBufferReader::new(request, core::mem::size_of::<PosixTimeSpec>(), true)?
+            .read_one_from_user::<PosixTimeSpec>(0)?;
+        if request.tv_sec < 0 || request.tv_nsec < 0 || request.tv_nsec >= 1_000_000_000 {
+            return Err(SystemError::EINVAL);
+        }
+
+        if flags & TIMER_ABSTIME == 0 {
+            let rm_time = nanosleep(request)?;
+            if !remain.is_null() && (rm_time.tv_sec > 0 || rm_time.tv_nsec > 0) {
+                UserBufferWriter::new(remain, core::mem::size_of::<PosixTimeSpec>(), true)?
+                    .copy_one_to_user(&rm_time, 0)?;
+            }
+            return Ok(0);
+        }
+
+        // 绝对时间已经过去时立即返回
+        let (req_ns, now_ns) = (
+            request.tv_sec as i128 * 1_000_000_000 + request.tv_nsec as i128,
+            now.tv_sec as i128 * 1_000_000_000 + now.tv_nsec as i128,
+        );
+        if req_ns <= now_ns {
+            return Ok(0);
+        }
+        let delta = (req_ns - now_ns) as i64;
+        nanosleep(PosixTimeSpec {
+            tv_sec: delta / 1_000_000_000,
+            tv_nsec: delta % 1_000_000_000,
+        })?;
+        return Ok(0);
+    }
+
     /// # alarm函数功能
     ///  
     /// 设置alarm（单位：秒）
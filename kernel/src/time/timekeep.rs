@@ -15,26 +15,28 @@ const KTIME_SEC_MAX: i64 = KTIME_MAX / NSEC_PER_SEC as i64;
 #[allow(non_camel_case_types)]
 pub type ktime_t = i64;
 
-// @brief 将ktime_t类型转换为纳秒类型
+/// 将ktime_t类型转换为纳秒类型
 #[inline]
-fn ktime_to_ns(kt: ktime_t) -> i64 {
+pub fn ktime_to_ns(kt: ktime_t) -> i64 {
     return kt;
 }
 
 /// @brief 从RTC获取当前时间，然后计算时间戳。
 /// 时间戳为从UTC+0 1970-01-01 00:00到当前UTC+0时间，所经过的纳秒数。
 /// 注意，由于当前未引入时区，因此本函数默认时区为UTC+8来计算
-fn ktime_get_real() -> Result<ktime_t, SystemError> {
+fn rtc_read_real() -> Result<ktime_t, SystemError> {
     let rtc_time = rtc_read_time_default()?;
     let time_spec: PosixTimeSpec = rtc_time.into();
     let r = time_spec.tv_sec * 1_000_000_000 + time_spec.tv_nsec;
     return Ok(r);
 }
 
-/// @brief 暴露给外部使用的接口，返回一个时间戳
+/// @brief 从RTC读取墙上时间，只在初始化timekeeping时使用。
+///
+/// 其他地方获取时间应使用`timekeeping::ktime_get_real`
 #[inline]
-pub fn ktime_get_real_ns() -> i64 {
-    let kt: ktime_t = ktime_get_real().unwrap_or(0);
+pub fn rtc_read_real_ns() -> i64 {
+    let kt: ktime_t = rtc_read_real().unwrap_or(0);
     return ktime_to_ns(kt);
 }
 
@@ -59,3 +61,10 @@ fn ktime_set(secs: i64, nsecs: u64) -> ktime_t {
 pub fn timespec_to_ktime(ts: PosixTimeSpec) -> ktime_t {
     return ktime_set(ts.tv_sec, ts.tv_nsec as u64);
 }
+
+/// # 将ktime_t转换成PosixTimeSpec
+#[inline(always)]
+pub fn ktime_to_timespec(kt: ktime_t) -> PosixTimeSpec {
+    let nsec_per_sec = NSEC_PER_SEC as i64;
+    return PosixTimeSpec::new(kt.div_euclid(nsec_per_sec), kt.rem_euclid(nsec_per_sec));
+}
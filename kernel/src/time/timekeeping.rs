@@ -1,32 +1,36 @@
+//! 内核时间
+//!
+//! timekeeper以当前的时钟源为基础维护三种时间：
+//!
+//! - 墙上时间（`CLOCK_REALTIME`）：1970-01-01以来的时间，可以被`settimeofday`修改；
+//! - 单调时间（`CLOCK_MONOTONIC`）：系统启动以来的时间，不受修改墙上时间的影响；
+//! - 启动时间（`CLOCK_BOOTTIME`）：单调时间加上系统休眠的时间。
+//!
+//! 内核中需要时间戳、计算时间间隔的地方都应该使用这里的`ktime_get*`系列函数，
+//! 而不是直接读取TSC等计数器或者用jiffies换算。
+//!
+//! 每个时钟中断（[`update_wall_time`]）把自上次以来时钟源走过的周期数累积到墙上时间中，
+//! 读取时间时再加上尚未累积的部分，因此读到的时间精度与时钟源一致。
+
 use alloc::sync::Arc;
-use core::intrinsics::{likely, unlikely};
+use core::intrinsics::unlikely;
 use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
-use log::{debug, info, warn};
+use log::{info, warn};
 use system_error::SystemError;
 
 use crate::{
     arch::CurrentIrqArch,
     exception::InterruptArch,
-    libs::rwlock::RwLock,
+    libs::rwlock::{RwLock, RwLockReadGuard},
     time::{
         jiffies::{clocksource_default_clock, jiffies_init},
-        timekeep::ktime_get_real_ns,
+        timekeep::rtc_read_real_ns,
         PosixTimeSpec,
     },
 };
 
-use super::timekeep::{ktime_t, timespec_to_ktime};
-use super::{
-    clocksource::{clocksource_cyc2ns, Clocksource, CycleNum, HZ},
-    syscall::PosixTimeval,
-    NSEC_PER_SEC,
-};
-/// NTP周期频率
-pub const NTP_INTERVAL_FREQ: u64 = HZ;
-/// NTP周期长度
-pub const NTP_INTERVAL_LENGTH: u64 = NSEC_PER_SEC as u64 / NTP_INTERVAL_FREQ;
-/// NTP转换比例
-pub const NTP_SCALE_SHIFT: u32 = 32;
+use super::timekeep::{ktime_t, ktime_to_timespec, timespec_to_ktime};
+use super::{clocksource::Clocksource, syscall::PosixTimeval, NSEC_PER_SEC};
 
 /// timekeeping休眠标志，false为未休眠
 pub static TIMEKEEPING_SUSPENDED: AtomicBool = AtomicBool::new(false);
@@ -41,65 +45,88 @@ pub struct Timekeeper {
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct TimekeeperData {
-    /// 用于计时的当前时钟源。
+    /// 用于计时的当前时钟源
     clock: Option<Arc<dyn Clocksource>>,
-    /// 当前时钟源的移位值。
-    shift: i32,
-    /// 一个NTP间隔中的时钟周期数。
-    cycle_interval: CycleNum,
-    /// 一个NTP间隔中时钟移位的纳秒数。
-    xtime_interval: u64,
-    xtime_remainder: i64,
-    /// 每个NTP间隔累积的原始纳米秒
-    raw_interval: i64,
-    /// 时钟移位纳米秒余数
-    xtime_nsec: u64,
-    /// 积累时间和ntp时间在ntp位移纳秒量上的差距
-    ntp_error: i64,
-    /// 用于转换时钟偏移纳秒和ntp偏移纳秒的偏移量
-    ntp_error_shift: i32,
-    /// NTP调整时钟乘法器
+    /// 上一次累积时时钟源的读数
+    cycle_last: u64,
+    /// 时钟源读数的掩码
+    mask: u64,
+    /// 周期数转换为（左移了shift位的）纳秒数的乘数
     mult: u32,
-    raw_time: PosixTimeSpec,
-    wall_to_monotonic: PosixTimeSpec,
-    total_sleep_time: PosixTimeSpec,
+    shift: u32,
+    /// 墙上时间的纳秒部分，左移了shift位
+    xtime_nsec: u64,
+    /// 墙上时间，tv_nsec为xtime_nsec右移shift位的结果
     xtime: PosixTimeSpec,
-    /// 单调时间和实时时间的偏移量
+    /// 单调时间与墙上时间的差值（单调时间 = 墙上时间 + wall_to_monotonic）
+    wall_to_monotonic: ktime_t,
+    /// 系统休眠的总时间
+    total_sleep_time: ktime_t,
+    /// 墙上时间与单调时间的差值
     real_time_offset: ktime_t,
 }
+
 impl TimekeeperData {
     pub fn new() -> Self {
         Self {
             clock: None,
-            shift: Default::default(),
-            cycle_interval: CycleNum::new(0),
-            xtime_interval: Default::default(),
-            xtime_remainder: Default::default(),
-            raw_interval: Default::default(),
-            xtime_nsec: Default::default(),
-            ntp_error: Default::default(),
-            ntp_error_shift: Default::default(),
-            mult: Default::default(),
-            xtime: PosixTimeSpec {
-                tv_nsec: 0,
-                tv_sec: 0,
-            },
-            wall_to_monotonic: PosixTimeSpec {
-                tv_nsec: 0,
-                tv_sec: 0,
-            },
-            total_sleep_time: PosixTimeSpec {
-                tv_nsec: 0,
-                tv_sec: 0,
-            },
-            raw_time: PosixTimeSpec {
-                tv_nsec: 0,
-                tv_sec: 0,
-            },
+            cycle_last: 0,
+            mask: 0,
+            mult: 0,
+            shift: 0,
+            xtime_nsec: 0,
+            xtime: PosixTimeSpec::default(),
+            wall_to_monotonic: 0,
+            total_sleep_time: 0,
             real_time_offset: 0,
         }
     }
+
+    /// 距离上一次累积，时钟源走过的周期数
+    #[inline]
+    fn cycle_delta(&self) -> u64 {
+        let Some(clock) = self.clock.as_ref() else {
+            return 0;
+        };
+        let delta = clock.read().data().wrapping_sub(self.cycle_last) & self.mask;
+        // 各个CPU上的计数器可能不完全同步，读数比cycle_last略小时视为没有前进，而不是回绕
+        if delta > self.mask >> 1 {
+            return 0;
+        }
+        delta
+    }
+
+    /// 当前的墙上时间，包括尚未累积的部分
+    fn xtime_now(&self) -> PosixTimeSpec {
+        let nsec = (self.xtime_nsec + self.cycle_delta() * self.mult as u64) >> self.shift;
+        PosixTimeSpec::new(
+            self.xtime.tv_sec + (nsec / NSEC_PER_SEC as u64) as i64,
+            (nsec % NSEC_PER_SEC as u64) as i64,
+        )
+    }
+
+    /// 把自上次以来时钟源走过的周期全部累积到墙上时间中
+    fn accumulate(&mut self) {
+        let delta = self.cycle_delta();
+        self.cycle_last = self.cycle_last.wrapping_add(delta) & self.mask;
+        self.xtime_nsec += delta * self.mult as u64;
+
+        let nsecps = (NSEC_PER_SEC as u64) << self.shift;
+        if self.xtime_nsec >= nsecps {
+            self.xtime.tv_sec += (self.xtime_nsec / nsecps) as i64;
+            self.xtime_nsec %= nsecps;
+            // TODO: 处理闰秒
+        }
+        self.xtime.tv_nsec = (self.xtime_nsec >> self.shift) as i64;
+    }
+
+    /// 设置墙上时间，`tv_nsec`必须已经规范化
+    fn set_xtime(&mut self, ts: PosixTimeSpec) {
+        self.xtime = ts;
+        self.xtime_nsec = (ts.tv_nsec as u64) << self.shift;
+    }
 }
+
 impl Timekeeper {
     fn new() -> Self {
         Self {
@@ -107,197 +134,104 @@ impl Timekeeper {
         }
     }
 
-    /// # 设置timekeeper的参数
+    /// # 设置timekeeper使用的时钟源
+    ///
+    /// 切换之前先用旧的时钟源把时间累积完，切换之后的时间从新时钟源当前的读数开始计算
     ///
     /// ## 参数
     ///
     /// * 'clock' - 指定的时钟实际类型。初始为ClocksourceJiffies
     pub fn timekeeper_setup_internals(&self, clock: Arc<dyn Clocksource>) {
-        let mut timekeeper = self.inner.write_irqsave();
-        // 更新clock
-        let mut clock_data = clock.clocksource_data();
-        clock_data.cycle_last = clock.read();
-        if clock.update_clocksource_data(clock_data).is_err() {
-            debug!("timekeeper_setup_internals:update_clocksource_data run failed");
-        }
-        timekeeper.clock.replace(clock.clone());
+        let mut tk = self.inner.write_irqsave();
+        tk.accumulate();
 
         let clock_data = clock.clocksource_data();
-        let mut temp = NTP_INTERVAL_LENGTH << clock_data.shift;
-        let ntpinterval = temp;
-        temp += (clock_data.mult / 2) as u64;
-        // do div
-
-        timekeeper.cycle_interval = CycleNum::new(temp);
-        timekeeper.xtime_interval = temp * clock_data.mult as u64;
-        // 这里可能存在下界溢出问题，debug模式下会报错panic
-        timekeeper.xtime_remainder = (ntpinterval - timekeeper.xtime_interval) as i64;
-        timekeeper.raw_interval = (timekeeper.xtime_interval >> clock_data.shift) as i64;
-        timekeeper.xtime_nsec = 0;
-        timekeeper.shift = clock_data.shift as i32;
-
-        timekeeper.ntp_error = 0;
-        timekeeper.ntp_error_shift = (NTP_SCALE_SHIFT - clock_data.shift) as i32;
-
-        timekeeper.mult = clock_data.mult;
+        let nsec = tk.xtime_nsec >> tk.shift;
+        tk.cycle_last = clock.read().data() & clock_data.mask.bits();
+        tk.mask = clock_data.mask.bits();
+        tk.mult = clock_data.mult;
+        tk.shift = clock_data.shift;
+        tk.xtime_nsec = nsec << tk.shift;
+        tk.clock.replace(clock);
     }
 
+    /// 距离上一次累积经过的纳秒数
     pub fn timekeeping_get_ns(&self) -> i64 {
-        let timekeeper = self.inner.read_irqsave();
-        let clock = timekeeper.clock.clone().unwrap();
-
-        let cycle_now = clock.read();
-        let clock_data = clock.clocksource_data();
-        let cycle_delta = (cycle_now.div(clock_data.cycle_last)).data() & clock_data.mask.bits();
-
-        return clocksource_cyc2ns(
-            CycleNum::new(cycle_delta),
-            timekeeper.mult,
-            timekeeper.shift as u32,
-        ) as i64;
+        let tk = timekeeping_read_lock(self);
+        return ((tk.cycle_delta() * tk.mult as u64) >> tk.shift) as i64;
     }
+}
 
-    /// # 处理大幅度调整
-    pub fn timekeeping_bigadjust(&self, error: i64, interval: i64, offset: i64) -> (i64, i64, i32) {
-        let mut error = error;
-        let mut interval = interval;
-        let mut offset = offset;
-
-        // TODO: 计算look_head并调整ntp误差
-
-        let tmp = interval;
-        let mut mult = 1;
-        let mut adj = 0;
-        if error < 0 {
-            error = -error;
-            interval = -interval;
-            offset = -offset;
-            mult = -1;
-        }
-        while error > tmp {
-            adj += 1;
-            error >>= 1;
-        }
-
-        interval <<= adj;
-        offset <<= adj;
-        mult <<= adj;
-
-        return (interval, offset, mult);
-    }
+#[inline(always)]
+pub fn timekeeper() -> &'static Timekeeper {
+    let r = unsafe { __TIMEKEEPER.as_ref().unwrap() };
 
-    /// # 调整时钟的mult减少ntp_error
-    pub fn timekeeping_adjust(&self, offset: i64) -> i64 {
-        let mut timekeeper = self.inner.write_irqsave();
-        let mut interval = timekeeper.cycle_interval.data() as i64;
-        let mut offset = offset;
-        let adj: i32;
-
-        // 计算误差
-        let mut error = timekeeper.ntp_error >> (timekeeper.ntp_error_shift - 1);
-
-        // 误差超过一个interval，就要进行调整
-        if error >= 0 {
-            if error > interval {
-                error >>= 2;
-                if likely(error <= interval) {
-                    adj = 1;
-                } else {
-                    (interval, offset, adj) = self.timekeeping_bigadjust(error, interval, offset);
-                }
-            } else {
-                // 不需要校准
-                return offset;
-            }
-        } else if -error > interval {
-            if likely(-error <= interval) {
-                adj = -1;
-                interval = -interval;
-                offset = -offset;
-            } else {
-                (interval, offset, adj) = self.timekeeping_bigadjust(error, interval, offset);
-            }
-        } else {
-            // 不需要校准
-            return offset;
-        }
+    return r;
+}
 
-        // 检查最大调整值，确保调整值不会超过时钟源允许的最大值
-        let clock_data = timekeeper.clock.clone().unwrap().clocksource_data();
-        if unlikely(
-            clock_data.maxadj != 0
-                && (timekeeper.mult as i32 + adj
-                    > clock_data.mult as i32 + clock_data.maxadj as i32),
-        ) {
-            warn!(
-                "Adjusting {:?} more than ({} vs {})",
-                clock_data.name,
-                timekeeper.mult as i32 + adj,
-                clock_data.mult as i32 + clock_data.maxadj as i32
-            );
-        }
+pub fn timekeeper_init() {
+    unsafe { __TIMEKEEPER = Some(Timekeeper::new()) };
+}
 
-        if error > 0 {
-            timekeeper.mult += adj as u32;
-            timekeeper.xtime_interval += interval as u64;
-            timekeeper.xtime_nsec -= offset as u64;
-        } else {
-            timekeeper.mult -= adj as u32;
-            timekeeper.xtime_interval -= interval as u64;
-            timekeeper.xtime_nsec += offset as u64;
+/// 获取timekeeper的读锁
+///
+/// 时钟中断会获取写锁，这里不能在关中断的情况下一直等待，因此使用try_read
+fn timekeeping_read_lock(tk: &Timekeeper) -> RwLockReadGuard<'_, TimekeeperData> {
+    loop {
+        if let Some(guard) = tk.inner.try_read_irqsave() {
+            return guard;
         }
-        timekeeper.ntp_error -= (interval - offset) << timekeeper.ntp_error_shift;
-
-        return offset;
+        core::hint::spin_loop();
     }
-    /// # 用于累积时间间隔，并将其转换为纳秒时间
-    pub fn logarithmic_accumulation(&self, offset: u64, shift: i32) -> u64 {
-        let mut timekeeper = self.inner.write_irqsave();
-        let clock = timekeeper.clock.clone().unwrap();
-        let clock_data = clock.clocksource_data();
-        let nsecps = (NSEC_PER_SEC as u64) << timekeeper.shift;
-        let mut offset = offset;
-
-        // 检查offset是否小于一个NTP周期间隔
-        if offset < timekeeper.cycle_interval.data() << shift {
-            return offset;
-        }
-
-        // 累积一个移位的interval
-        offset -= timekeeper.cycle_interval.data() << shift;
-        clock_data
-            .cycle_last
-            .add(CycleNum::new(timekeeper.cycle_interval.data() << shift));
-        if clock.update_clocksource_data(clock_data).is_err() {
-            debug!("logarithmic_accumulation:update_clocksource_data run failed");
-        }
-        timekeeper.clock.replace(clock.clone());
+}
 
-        // 更新xime_nsec
-        timekeeper.xtime_nsec += timekeeper.xtime_interval << shift;
-        while timekeeper.xtime_nsec >= nsecps {
-            timekeeper.xtime_nsec -= nsecps;
-            timekeeper.xtime.tv_sec += 1;
-            // TODO: 处理闰秒
-        }
+/// 在timekeeper的读锁下执行`f`，timekeeping还未初始化时返回None
+#[inline]
+fn timekeeping_read<R>(f: impl FnOnce(&TimekeeperData) -> R) -> Option<R> {
+    let tk = unsafe { __TIMEKEEPER.as_ref() }?;
+    Some(f(&timekeeping_read_lock(tk)))
+}
 
-        // TODO：更新raw_time
+/// # 获取单调时间
+///
+/// 系统启动以来经过的纳秒数，不受修改墙上时间的影响。timekeeping初始化之前返回0
+pub fn ktime_get() -> ktime_t {
+    timekeeping_read(|tk| timespec_to_ktime(tk.xtime_now()) + tk.wall_to_monotonic).unwrap_or(0)
+}
 
-        // TODO：计算ntp_error
+/// 与[`ktime_get`]相同，以u64返回，便于计算时间间隔
+#[inline]
+pub fn ktime_get_ns() -> u64 {
+    ktime_get() as u64
+}
 
-        return offset;
-    }
+/// # 获取墙上时间
+///
+/// 1970-01-01 00:00:00 UTC以来经过的纳秒数
+pub fn ktime_get_real() -> ktime_t {
+    timekeeping_read(|tk| timespec_to_ktime(tk.xtime_now())).unwrap_or(0)
 }
 
-#[inline(always)]
-pub fn timekeeper() -> &'static Timekeeper {
-    let r = unsafe { __TIMEKEEPER.as_ref().unwrap() };
+/// # 获取启动时间
+///
+/// 单调时间加上系统休眠的时间
+pub fn ktime_get_boottime() -> ktime_t {
+    timekeeping_read(|tk| {
+        timespec_to_ktime(tk.xtime_now()) + tk.wall_to_monotonic + tk.total_sleep_time
+    })
+    .unwrap_or(0)
+}
 
-    return r;
+/// 以`PosixTimeSpec`的形式获取单调时间
+#[inline]
+pub fn ktime_get_ts64() -> PosixTimeSpec {
+    ktime_to_timespec(ktime_get())
 }
 
-pub fn timekeeper_init() {
-    unsafe { __TIMEKEEPER = Some(Timekeeper::new()) };
+/// 以`PosixTimeSpec`的形式获取启动时间
+#[inline]
+pub fn ktime_get_boottime_ts64() -> PosixTimeSpec {
+    ktime_to_timespec(ktime_get_boottime())
 }
 
 /// # 获取1970.1.1至今的UTC时间戳(最小单位:nsec)
@@ -306,32 +240,7 @@ pub fn timekeeper_init() {
 ///
 /// * 'TimeSpec' - 时间戳
 pub fn getnstimeofday() -> PosixTimeSpec {
-    // debug!("enter getnstimeofday");
-
-    let nsecs;
-    let mut xtime: PosixTimeSpec;
-    loop {
-        match timekeeper().inner.try_read_irqsave() {
-            None => continue,
-            Some(tk) => {
-                xtime = tk.xtime;
-                drop(tk);
-
-                nsecs = timekeeper().timekeeping_get_ns();
-
-                // TODO 不同架构可能需要加上不同的偏移量
-                break;
-            }
-        }
-    }
-    xtime.tv_nsec += nsecs;
-    xtime.tv_sec += xtime.tv_nsec / NSEC_PER_SEC as i64;
-    xtime.tv_nsec %= NSEC_PER_SEC as i64;
-    // debug!("getnstimeofday: xtime = {:?}, nsecs = {:}", xtime, nsecs);
-
-    // TODO 将xtime和当前时间源的时间相加
-
-    return xtime;
+    timekeeping_read(|tk| tk.xtime_now()).unwrap_or_default()
 }
 
 /// # 获取1970.1.1至今的UTC时间戳(最小单位:usec)
@@ -347,13 +256,42 @@ pub fn do_gettimeofday() -> PosixTimeval {
     };
 }
 
+/// # 设置墙上时间
+///
+/// 单调时间保持连续：墙上时间跳变多少，wall_to_monotonic就反向调整多少
 pub fn do_settimeofday64(time: PosixTimeSpec) -> Result<(), SystemError> {
-    timekeeper().inner.write_irqsave().xtime = time;
-    // todo: 模仿linux，实现时间误差校准。
-    // https://code.dragonos.org.cn/xref/linux-6.6.21/kernel/time/timekeeping.c?fi=do_settimeofday64#1312
+    if time.tv_nsec < 0 || time.tv_nsec >= NSEC_PER_SEC as i64 {
+        return Err(SystemError::EINVAL);
+    }
+    let mut tk = timekeeper().inner.write_irqsave();
+    tk.accumulate();
+    let delta = timespec_to_ktime(time) - timespec_to_ktime(tk.xtime);
+    tk.wall_to_monotonic -= delta;
+    tk.set_xtime(time);
+    drop(tk);
+
+    timekeeping_update();
     return Ok(());
 }
 
+/// # 通知timekeeping切换时钟源
+///
+/// 由`clocksource_select`在选出了更好的时钟源之后调用
+pub fn timekeeping_notify(clock: Arc<dyn Clocksource>) {
+    if unsafe { __TIMEKEEPER.as_ref() }.is_none() {
+        return;
+    }
+    if let Err(e) = clock.enable() {
+        warn!(
+            "timekeeping_notify: failed to enable clocksource {:?}: {:?}",
+            clock.clocksource_data().name,
+            e
+        );
+        return;
+    }
+    timekeeper().timekeeper_setup_internals(clock);
+}
+
 /// # 初始化timekeeping模块
 #[inline(never)]
 pub fn timekeeping_init() {
@@ -361,22 +299,19 @@ pub fn timekeeping_init() {
     let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
     timekeeper_init();
 
-    // TODO 有ntp模块后 在此初始化ntp模块
-
     let clock = clocksource_default_clock();
     clock
         .enable()
         .expect("clocksource_default_clock enable failed");
     timekeeper().timekeeper_setup_internals(clock);
-    // 暂时不支持其他架构平台对时间的设置 所以使用x86平台对应值初始化
+
     let mut timekeeper = timekeeper().inner.write_irqsave();
-    timekeeper.xtime.tv_nsec = ktime_get_real_ns();
+    let now = ktime_to_timespec(rtc_read_real_ns());
+    timekeeper.set_xtime(now);
 
     //参考https://elixir.bootlin.com/linux/v4.4/source/kernel/time/timekeeping.c#L1251 对wtm进行初始化
-    (
-        timekeeper.wall_to_monotonic.tv_nsec,
-        timekeeper.wall_to_monotonic.tv_sec,
-    ) = (-timekeeper.xtime.tv_nsec, -timekeeper.xtime.tv_sec);
+    timekeeper.wall_to_monotonic = -timespec_to_ktime(now);
+    timekeeper.real_time_offset = timespec_to_ktime(now);
 
     drop(irq_guard);
     drop(timekeeper);
@@ -385,74 +320,24 @@ pub fn timekeeping_init() {
 }
 
 /// # 使用当前时钟源增加wall time
-/// 参考：https://code.dragonos.org.cn/xref/linux-3.4.99/kernel/time/timekeeping.c#1041
+///
+/// 每个时钟中断调用一次
 pub fn update_wall_time() {
-    // debug!("enter update_wall_time, stack_use = {:}",stack_use);
     compiler_fence(Ordering::SeqCst);
-    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
     // 如果在休眠那就不更新
-    if TIMEKEEPING_SUSPENDED.load(Ordering::SeqCst) {
-        return;
-    }
-
-    let mut tk = timekeeper().inner.write_irqsave();
-    // 获取当前时钟源
-    let clock = tk.clock.clone().unwrap();
-    let clock_data = clock.clocksource_data();
-    // 计算从上一次更新周期以来经过的时钟周期数
-    let mut offset = (clock.read().div(clock_data.cycle_last).data()) & clock_data.mask.bits();
-    // 检查offset是否达到了一个NTP周期间隔
-    if offset < tk.cycle_interval.data() {
+    if unlikely(TIMEKEEPING_SUSPENDED.load(Ordering::SeqCst)) {
         return;
     }
 
-    // 将纳秒部分转换为更高精度的格式
-    tk.xtime_nsec = (tk.xtime.tv_nsec as u64) << tk.shift;
-
-    let mut shift = (offset.ilog2() - tk.cycle_interval.data().ilog2()) as i32;
-    shift = shift.max(0);
-    // let max_shift = (64 - (ntp_tick_length().ilog2()+1)) - 1;
-    // shift = min(shift, max_shift)
-    while offset >= tk.cycle_interval.data() {
-        offset = timekeeper().logarithmic_accumulation(offset, shift);
-        if offset < tk.cycle_interval.data() << shift {
-            shift -= 1;
-        }
-    }
-
-    timekeeper().timekeeping_adjust(offset as i64);
-
-    // 处理xtime_nsec下溢问题，并对NTP误差进行调整
-    if unlikely((tk.xtime_nsec as i64) < 0) {
-        let neg = -(tk.xtime_nsec as i64);
-        tk.xtime_nsec = 0;
-        tk.ntp_error += neg << tk.ntp_error_shift;
-    }
-
-    // 将纳秒部分舍入后存储在xtime.tv_nsec中
-    tk.xtime.tv_nsec = ((tk.xtime_nsec as i64) >> tk.shift) + 1;
-    tk.xtime_nsec -= (tk.xtime.tv_nsec as u64) << tk.shift;
-
-    // 确保经过舍入后的xtime.tv_nsec不会大于NSEC_PER_SEC，并在超过1秒的情况下进行适当的调整
-    if unlikely(tk.xtime.tv_nsec >= NSEC_PER_SEC.into()) {
-        tk.xtime.tv_nsec -= NSEC_PER_SEC as i64;
-        tk.xtime.tv_sec += 1;
-        // TODO: 处理闰秒
-    }
+    timekeeper().inner.write_irqsave().accumulate();
 
     // 更新时间的相关信息
     timekeeping_update();
-
-    compiler_fence(Ordering::SeqCst);
-    drop(irq_guard);
     compiler_fence(Ordering::SeqCst);
 }
-// TODO wall_to_monotic
 
 /// 参考：https://code.dragonos.org.cn/xref/linux-3.4.99/kernel/time/timekeeping.c#190
 pub fn timekeeping_update() {
-    // TODO：如果clearntp为true，则会清除NTP错误并调用ntp_clear()
-
     // 更新实时时钟偏移量，用于跟踪硬件时钟与系统时间的差异，以便进行时间校正
     update_rt_offset();
 }
@@ -460,9 +345,5 @@ pub fn timekeeping_update() {
 /// # 更新实时偏移量(墙上之间与单调时间的差值)
 pub fn update_rt_offset() {
     let mut timekeeper = timekeeper().inner.write_irqsave();
-    let ts = PosixTimeSpec::new(
-        -timekeeper.wall_to_monotonic.tv_sec,
-        -timekeeper.wall_to_monotonic.tv_nsec,
-    );
-    timekeeper.real_time_offset = timespec_to_ktime(ts);
+    timekeeper.real_time_offset = -timekeeper.wall_to_monotonic;
 }
@@ -1,7 +1,21 @@
+//! 定时器
+//!
+//! 每个CPU有一个自己的定时器基座（`TimerBase`），定时器被激活时放入当前CPU的基座，
+//! 由该CPU的定时器软中断执行，因此大量定时器不会在同一把锁上竞争。
+//!
+//! 基座使用分级时间轮组织定时器：共[`TIMER_WHEEL_DEPTH`]级，每级64个槽，第n级的一个槽覆盖64^n个jiffies。
+//! 定时器按照距离到期的时间放入能容纳它的最低一级。第0级的槽在对应的jiffies到期时执行，
+//! 更高级的槽在时间轮转到它时才把其中的定时器重新放入低一级的槽（惰性级联），
+//! 因此激活、取消定时器以及每个时钟节拍的开销都与定时器的数量无关。
+//! 超出最高一级范围的定时器先按照最长的超时放入最高一级，级联时再重新计算位置。
+//!
+//! 定时器可以设置松弛时间（slack），允许推迟执行。放入时间轮时到期时刻被对齐到松弛范围内最“整”的时刻，
+//! 这样到期时刻相近的定时器会落入同一个槽，在同一次软中断中一起执行，减少软中断的次数。
+
 use core::{
     fmt::Debug,
     intrinsics::unlikely,
-    sync::atomic::{compiler_fence, AtomicBool, AtomicU64, Ordering},
+    sync::atomic::{compiler_fence, AtomicU64, Ordering},
     time::Duration,
 };
 
@@ -10,7 +24,7 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 use system_error::SystemError;
 
 use crate::{
@@ -19,19 +33,39 @@ use crate::{
         softirq::{softirq_vectors, SoftirqNumber, SoftirqVec},
         InterruptArch,
     },
-    libs::spinlock::{SpinLock, SpinLockGuard},
+    libs::{
+        lazy_init::Lazy,
+        spinlock::{SpinLock, SpinLockGuard},
+    },
+    mm::percpu::{PerCpu, PerCpuVar},
     process::{ProcessControlBlock, ProcessManager},
     sched::{schedule, SchedMode},
+    smp::{core::smp_get_processor_id, cpu::ProcessorId},
 };
 
-use super::{jiffies::NSEC_PER_JIFFY, timekeeping::update_wall_time};
+use super::{
+    jiffies::{jiffies_to_nsecs, msecs_to_jiffies, nsecs_to_jiffies, usecs_to_jiffies},
+    timekeeping::update_wall_time,
+};
 
 const MAX_TIMEOUT: i64 = i64::MAX;
-const TIMER_RUN_CYCLE_THRESHOLD: usize = 20;
 static TIMER_JIFFIES: AtomicU64 = AtomicU64::new(0);
 
-lazy_static! {
-    pub static ref TIMER_LIST: SpinLock<Vec<(u64, Arc<Timer>)>> = SpinLock::new(Vec::new());
+/// 时间轮每一级的槽数为2^TIMER_WHEEL_BITS
+const TIMER_WHEEL_BITS: usize = 6;
+const TIMER_WHEEL_SIZE: usize = 1 << TIMER_WHEEL_BITS;
+const TIMER_WHEEL_MASK: u64 = TIMER_WHEEL_SIZE as u64 - 1;
+/// 时间轮的级数
+pub const TIMER_WHEEL_DEPTH: usize = 5;
+/// 时间轮能够直接容纳的最长超时（jiffies）
+const TIMER_WHEEL_MAX_DELTA: u64 = (1 << (TIMER_WHEEL_BITS * TIMER_WHEEL_DEPTH)) - 1;
+
+/// 每个CPU的定时器基座
+static TIMER_BASES: Lazy<PerCpuVar<SpinLock<TimerBase>>> = PerCpuVar::define_lazy();
+
+#[inline]
+fn timer_base(cpu: ProcessorId) -> &'static SpinLock<TimerBase> {
+    unsafe { TIMER_BASES.get().force_get(cpu) }
 }
 
 /// 定时器要执行的函数的特征
@@ -61,9 +95,7 @@ impl From<Jiffies> for Duration {
     ///
     /// Duration： 这段时间的Duration形式
     fn from(jiffies: Jiffies) -> Self {
-        let ms = jiffies.data() / 1_000_000 * NSEC_PER_JIFFY as u64;
-        let result = Duration::from_millis(ms);
-        result
+        Duration::from_nanos(jiffies_to_nsecs(jiffies.data()))
     }
 }
 
@@ -78,9 +110,7 @@ impl From<Duration> for Jiffies {
     ///
     /// Jiffies结构体： 这段时间的Jiffies数
     fn from(ms: Duration) -> Self {
-        let jiffies = ms.as_millis() as u64 * 1_000_000 / NSEC_PER_JIFFY as u64;
-        let result = Jiffies::new(jiffies);
-        result
+        Jiffies::new(nsecs_to_jiffies(ms.as_nanos() as u64))
     }
 }
 
@@ -123,6 +153,8 @@ impl Timer {
                 timer_func: Some(timer_func),
                 self_ref: Weak::default(),
                 triggered: false,
+                slot: None,
+                slack: 0,
             }),
         });
 
@@ -135,41 +167,27 @@ impl Timer {
         return self.inner.lock_irqsave();
     }
 
-    /// @brief 将定时器插入到定时器链表中
+    /// ## 设置定时器的松弛时间
+    ///
+    /// 定时器可以被推迟至多`slack`个jiffies执行，以便与到期时刻相近的定时器合并。在激活之前设置
+    pub fn set_slack(&self, slack: u64) {
+        self.inner().slack = slack;
+    }
+
+    /// @brief 将定时器放入当前CPU的时间轮
     pub fn activate(&self) {
-        let mut timer_list = TIMER_LIST.lock_irqsave();
-        let inner_guard = self.inner();
-
-        // 链表为空，则直接插入
-        if timer_list.is_empty() {
-            // FIXME push_timer
-            timer_list.push((
-                inner_guard.expire_jiffies,
-                inner_guard.self_ref.upgrade().unwrap(),
-            ));
-
-            drop(inner_guard);
-            drop(timer_list);
-            compiler_fence(Ordering::SeqCst);
-
-            return;
+        if self.inner().slot.is_some() {
+            warn!("Timer already in list");
+            self.cancel();
         }
-        let expire_jiffies = inner_guard.expire_jiffies;
+
+        let mut base = timer_base(smp_get_processor_id()).lock_irqsave();
+        let mut inner_guard = self.inner();
         let self_arc = inner_guard.self_ref.upgrade().unwrap();
+        base.enqueue(&self_arc, &mut inner_guard);
         drop(inner_guard);
-        let mut split_pos: usize = 0;
-        for (pos, elt) in timer_list.iter().enumerate() {
-            if Arc::ptr_eq(&self_arc, &elt.1) {
-                warn!("Timer already in list");
-            }
-            if elt.0 > expire_jiffies {
-                split_pos = pos;
-                break;
-            }
-        }
-        timer_list.insert(split_pos, (expire_jiffies, self_arc));
-
-        drop(timer_list);
+        drop(base);
+        compiler_fence(Ordering::SeqCst);
     }
 
     #[inline]
@@ -194,15 +212,35 @@ impl Timer {
 
     /// ## 取消定时器任务
     pub fn cancel(&self) -> bool {
-        let this_arc = self.inner().self_ref.upgrade().unwrap();
-        TIMER_LIST
-            .lock_irqsave()
-            .extract_if(|x| Arc::ptr_eq(&this_arc, &x.1))
-            .for_each(drop);
+        // 定时器可能同时被迁移到别的槽，因此在锁住基座之后需要再次确认它的位置
+        loop {
+            // 先释放定时器的锁，再按照先基座、后定时器的顺序加锁
+            let slot = self.inner().slot;
+            let Some(slot) = slot else {
+                break;
+            };
+            let mut base = timer_base(slot.cpu).lock_irqsave();
+            let mut inner_guard = self.inner();
+            if inner_guard.slot != Some(slot) {
+                continue;
+            }
+            let this_arc = inner_guard.self_ref.upgrade().unwrap();
+            base.dequeue(&this_arc, slot);
+            inner_guard.slot = None;
+            break;
+        }
         true
     }
 }
 
+/// 定时器在时间轮中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TimerSlot {
+    cpu: ProcessorId,
+    level: usize,
+    index: usize,
+}
+
 /// 定时器类型
 #[derive(Debug)]
 pub struct InnerTimer {
@@ -214,67 +252,208 @@ pub struct InnerTimer {
     self_ref: Weak<Timer>,
     /// 判断该计时器是否触发
     triggered: bool,
+    /// 定时器在时间轮中的位置，不在时间轮中时为None
+    slot: Option<TimerSlot>,
+    /// 允许推迟执行的jiffies数
+    slack: u64,
 }
 
+impl InnerTimer {
+    /// 考虑松弛时间之后的到期时刻
+    ///
+    /// 与Linux的`apply_slack`相同：在`[expire_jiffies, expire_jiffies + slack]`中，
+    /// 选取低位为0的位数最多的时刻
+    fn slack_expires(&self) -> u64 {
+        let expires = self.expire_jiffies;
+        let limit = expires.saturating_add(self.slack);
+        let mask = expires ^ limit;
+        if mask == 0 {
+            return expires;
+        }
+        let bit = 63 - mask.leading_zeros();
+        limit & !((1u64 << bit) - 1)
+    }
+}
+
+/// 一个CPU的定时器基座
 #[derive(Debug)]
-pub struct DoTimerSoftirq {
-    running: AtomicBool,
+struct TimerBase {
+    cpu: ProcessorId,
+    /// 下一个要处理的jiffies，小于它的时刻都已经处理完毕
+    clk: u64,
+    /// 下一个需要处理的时刻（有定时器到期或者需要级联），只保证不晚于真实值
+    next_expiry: u64,
+    /// 时间轮中定时器的数量
+    pending: usize,
+    /// 时间轮，第`level`级的第`index`个槽位于`level * TIMER_WHEEL_SIZE + index`，第一次使用时才分配
+    wheel: Vec<Vec<Arc<Timer>>>,
+    /// 每一级中不为空的槽的位图
+    occupied: [u64; TIMER_WHEEL_DEPTH],
 }
 
-impl DoTimerSoftirq {
-    pub fn new() -> Self {
-        return DoTimerSoftirq {
-            running: AtomicBool::new(false),
-        };
+impl TimerBase {
+    fn new(cpu: ProcessorId) -> Self {
+        Self {
+            cpu,
+            clk: TIMER_JIFFIES.load(Ordering::SeqCst),
+            next_expiry: u64::MAX,
+            pending: 0,
+            wheel: Vec::new(),
+            occupied: [0; TIMER_WHEEL_DEPTH],
+        }
     }
 
-    fn set_run(&self) -> bool {
-        let x = self
-            .running
-            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed);
-        return x.is_ok();
+    /// 计算在`expire_jiffies`到期的定时器应当放在哪个槽
+    fn calc_slot(&self, expire_jiffies: u64) -> (usize, usize) {
+        // 已经过期的定时器放在下一个要处理的槽
+        let delta = expire_jiffies
+            .saturating_sub(self.clk)
+            .min(TIMER_WHEEL_MAX_DELTA);
+        let expires = self.clk + delta;
+        let mut level = 0;
+        while level + 1 < TIMER_WHEEL_DEPTH && delta >> (TIMER_WHEEL_BITS * (level + 1)) != 0 {
+            level += 1;
+        }
+        let index = ((expires >> (TIMER_WHEEL_BITS * level)) & TIMER_WHEEL_MASK) as usize;
+        (level, index)
     }
 
-    fn clear_run(&self) {
-        self.running.store(false, Ordering::Release);
+    /// 第`level`级中下一个不为空的槽在什么时刻被处理，调用前需要确认这一级不为空
+    fn level_next_time(&self, level: usize) -> u64 {
+        let shift = TIMER_WHEEL_BITS * level;
+        // 不早于clk的、这一级的第一个槽的边界
+        let unit = (self.clk + (1 << shift) - 1) >> shift;
+        let start = (unit & TIMER_WHEEL_MASK) as u32;
+        let offset = self.occupied[level].rotate_right(start).trailing_zeros() as u64;
+        (unit + offset) << shift
     }
-}
-impl SoftirqVec for DoTimerSoftirq {
-    fn run(&self) {
-        if !self.set_run() {
-            return;
+
+    fn update_next_expiry(&mut self) {
+        self.next_expiry = (0..TIMER_WHEEL_DEPTH)
+            .filter(|level| self.occupied[*level] != 0)
+            .map(|level| self.level_next_time(level))
+            .min()
+            .unwrap_or(u64::MAX);
+    }
+
+    fn enqueue(&mut self, timer: &Arc<Timer>, inner: &mut InnerTimer) {
+        if self.wheel.is_empty() {
+            self.wheel
+                .resize_with(TIMER_WHEEL_DEPTH * TIMER_WHEEL_SIZE, Vec::new);
         }
-        // 最多只处理TIMER_RUN_CYCLE_THRESHOLD个计时器
-        for _ in 0..TIMER_RUN_CYCLE_THRESHOLD {
-            // debug!("DoTimerSoftirq run");
-            let timer_list = TIMER_LIST.try_lock_irqsave();
-            if timer_list.is_err() {
-                continue;
-            }
-            let mut timer_list = timer_list.unwrap();
+        // 时间轮为空时软中断不会推进clk，先把它追上当前时刻，让定时器落在更精确的槽里
+        if self.pending == 0 {
+            self.clk = self.clk.max(TIMER_JIFFIES.load(Ordering::SeqCst));
+        }
+        let (level, index) = self.calc_slot(inner.slack_expires());
+        self.wheel[level * TIMER_WHEEL_SIZE + index].push(timer.clone());
+        self.occupied[level] |= 1 << index;
+        self.pending += 1;
+        inner.slot = Some(TimerSlot {
+            cpu: self.cpu,
+            level,
+            index,
+        });
+        self.update_next_expiry();
+    }
 
-            if timer_list.is_empty() {
+    fn dequeue(&mut self, timer: &Arc<Timer>, slot: TimerSlot) {
+        let bucket = &mut self.wheel[slot.level * TIMER_WHEEL_SIZE + slot.index];
+        if let Some(pos) = bucket.iter().position(|t| Arc::ptr_eq(t, timer)) {
+            bucket.swap_remove(pos);
+            self.pending -= 1;
+        }
+        if bucket.is_empty() {
+            self.occupied[slot.level] &= !(1 << slot.index);
+        }
+    }
+
+    /// 取出一个槽中的所有定时器
+    fn take_bucket(&mut self, level: usize, index: usize) -> Vec<Arc<Timer>> {
+        self.occupied[level] &= !(1 << index);
+        let bucket = core::mem::take(&mut self.wheel[level * TIMER_WHEEL_SIZE + index]);
+        self.pending -= bucket.len();
+        bucket
+    }
+
+    /// 处理所有不晚于`jiffies`的时刻，返回到期的定时器
+    ///
+    /// 返回的定时器已经从时间轮中移除，需要在释放基座的锁之后执行
+    fn collect_expired(&mut self, jiffies: u64) -> Vec<Arc<Timer>> {
+        let mut expired = Vec::new();
+        while self.clk <= jiffies {
+            if self.pending == 0 {
+                self.clk = jiffies + 1;
                 break;
             }
+            // 跳过既没有定时器到期、也不需要级联的时刻
+            if self.next_expiry > self.clk {
+                self.clk = self.next_expiry.min(jiffies + 1);
+                continue;
+            }
 
-            let (front_jiffies, timer_list_front) = timer_list.first().unwrap().clone();
-            // debug!("to lock timer_list_front");
+            let clk = self.clk;
+            // 先级联高级的槽，这样落入低一级当前槽的定时器也能在这一轮被级联
+            for level in (1..TIMER_WHEEL_DEPTH).rev() {
+                let shift = TIMER_WHEEL_BITS * level;
+                if clk & ((1 << shift) - 1) != 0 {
+                    continue;
+                }
+                let index = ((clk >> shift) & TIMER_WHEEL_MASK) as usize;
+                for timer in self.take_bucket(level, index) {
+                    let mut inner = timer.inner();
+                    self.enqueue(&timer, &mut inner);
+                }
+            }
 
-            if front_jiffies >= TIMER_JIFFIES.load(Ordering::SeqCst) {
-                break;
+            let index = (clk & TIMER_WHEEL_MASK) as usize;
+            for timer in self.take_bucket(0, index) {
+                let mut inner = timer.inner();
+                if inner.expire_jiffies > clk {
+                    // 超出时间轮范围的定时器，重新计算位置
+                    self.enqueue(&timer, &mut inner);
+                    continue;
+                }
+                inner.slot = None;
+                drop(inner);
+                expired.push(timer);
             }
-            timer_list.remove(0);
-            drop(timer_list);
-            timer_list_front.run();
+            self.clk += 1;
+            self.update_next_expiry();
         }
+        expired
+    }
+}
+
+#[derive(Debug)]
+pub struct DoTimerSoftirq;
 
-        self.clear_run();
+impl DoTimerSoftirq {
+    pub fn new() -> Self {
+        return DoTimerSoftirq;
+    }
+}
+
+impl SoftirqVec for DoTimerSoftirq {
+    /// 执行当前CPU上所有到期的定时器
+    fn run(&self) {
+        let mut base = timer_base(smp_get_processor_id()).lock_irqsave();
+        let expired = base.collect_expired(TIMER_JIFFIES.load(Ordering::SeqCst));
+        drop(base);
+        for timer in expired {
+            timer.run();
+        }
     }
 }
 
 /// 初始化系统定时器
 #[inline(never)]
 pub fn timer_init() {
+    let bases = (0..PerCpu::MAX_CPU_NUM)
+        .map(|cpu| SpinLock::new(TimerBase::new(ProcessorId::new(cpu))))
+        .collect::<Vec<_>>();
+    TIMER_BASES.init(PerCpuVar::new(bases).unwrap());
+
     // FIXME 调用register_trap
     let do_timer_softirq = Arc::new(DoTimerSoftirq::new());
     softirq_vectors()
@@ -283,13 +462,53 @@ pub fn timer_init() {
     info!("timer initialized successfully");
 }
 
+/// # 把离线的CPU上剩下的定时器迁移到当前CPU
+///
+/// 在离线的CPU停止本地时钟中断之后，由发起离线的CPU调用
+pub fn timers_dead_cpu(cpu: ProcessorId) -> Result<(), SystemError> {
+    let this_cpu = smp_get_processor_id();
+    if cpu == this_cpu {
+        return Err(SystemError::EINVAL);
+    }
+
+    let irq_guard = unsafe { CurrentIrqArch::save_and_disable_irq() };
+    // 只有热插拔的流程会同时持有两个基座的锁，而热插拔是串行的，因此不会死锁
+    let mut old_base = timer_base(cpu).lock();
+    let mut new_base = timer_base(this_cpu).lock();
+    let mut migrated = 0;
+    for level in 0..TIMER_WHEEL_DEPTH {
+        while old_base.occupied[level] != 0 {
+            let index = old_base.occupied[level].trailing_zeros() as usize;
+            for timer in old_base.take_bucket(level, index) {
+                let mut inner = timer.inner();
+                new_base.enqueue(&timer, &mut inner);
+                migrated += 1;
+            }
+        }
+    }
+    old_base.update_next_expiry();
+    drop(new_base);
+    drop(old_base);
+    drop(irq_guard);
+
+    if migrated != 0 {
+        debug!(
+            "migrated {} timers from cpu {} to cpu {}",
+            migrated,
+            cpu.data(),
+            this_cpu.data()
+        );
+    }
+    return Ok(());
+}
+
 /// 计算接下来n毫秒对应的定时器时间片
 pub fn next_n_ms_timer_jiffies(expire_ms: u64) -> u64 {
-    return TIMER_JIFFIES.load(Ordering::SeqCst) + expire_ms * 1000000 / NSEC_PER_JIFFY as u64;
+    return TIMER_JIFFIES.load(Ordering::SeqCst) + msecs_to_jiffies(expire_ms);
 }
 /// 计算接下来n微秒对应的定时器时间片
 pub fn next_n_us_timer_jiffies(expire_us: u64) -> u64 {
-    return TIMER_JIFFIES.load(Ordering::SeqCst) + expire_us * 1000 / NSEC_PER_JIFFY as u64;
+    return TIMER_JIFFIES.load(Ordering::SeqCst) + usecs_to_jiffies(expire_us);
 }
 
 /// @brief 让pcb休眠timeout个jiffies
@@ -335,22 +554,19 @@ pub fn schedule_timeout(mut timeout: i64) -> Result<i64, SystemError> {
     }
 }
 
+/// 获取当前CPU上下一个需要处理的时刻，没有定时器时返回`u64::MAX`
+///
+/// 由于级联也需要在软中断中进行，返回的时刻可能早于最早的定时器的到期时刻
 pub fn timer_get_first_expire() -> Result<u64, SystemError> {
-    // FIXME
-    // debug!("rs_timer_get_first_expire,timer_jif = {:?}", TIMER_JIFFIES);
+    let base = timer_base(smp_get_processor_id());
     for _ in 0..10 {
-        match TIMER_LIST.try_lock_irqsave() {
-            Ok(timer_list) => {
-                // debug!("rs_timer_get_first_expire TIMER_LIST lock successfully");
-                if timer_list.is_empty() {
-                    // debug!("timer_list is empty");
-                    return Ok(0);
-                } else {
-                    // debug!("timer_list not empty");
-                    return Ok(timer_list.first().unwrap().0);
+        match base.try_lock_irqsave() {
+            Ok(base) => {
+                if base.pending == 0 {
+                    return Ok(u64::MAX);
                 }
+                return Ok(base.next_expiry);
             }
-            // 加锁失败返回啥？？
             Err(_) => continue,
         }
     }
@@ -359,6 +575,7 @@ pub fn timer_get_first_expire() -> Result<u64, SystemError> {
 
 /// 检查是否需要触发定时器软中断，如果需要则触发
 pub fn try_raise_timer_softirq() {
+    // 时间轮为空时得到u64::MAX，不会触发软中断；加锁失败时等下一个时钟节拍再检查
     if let Ok(first_expire) = timer_get_first_expire() {
         if first_expire <= clock() {
             softirq_vectors().raise_softirq(SoftirqNumber::TIMER);
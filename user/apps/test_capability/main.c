@@ -0,0 +1,241 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/capability.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/prctl.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static int capget_self(struct __user_cap_data_struct data[2]) {
+    struct __user_cap_header_struct header = {.version = _LINUX_CAPABILITY_VERSION_3, .pid = 0};
+    return syscall(SYS_capget, &header, data);
+}
+
+static int capset_self(struct __user_cap_data_struct data[2]) {
+    struct __user_cap_header_struct header = {.version = _LINUX_CAPABILITY_VERSION_3, .pid = 0};
+    return syscall(SYS_capset, &header, data);
+}
+
+static int has_effective(int cap) {
+    struct __user_cap_data_struct data[2];
+    if (capget_self(data) != 0) {
+        return -1;
+    }
+    return (data[CAP_TO_INDEX(cap)].effective & CAP_TO_MASK(cap)) != 0;
+}
+
+static int run_child(void (*fn)(void)) {
+    fflush(stdout);
+    pid_t pid = fork();
+    if (pid == 0) {
+        fn();
+        _exit(0);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    return WIFEXITED(status) ? WEXITSTATUS(status) : -1;
+}
+
+/* 从effective权限集中删除CAP_SYS_ADMIN之后不能挂载，permitted中仍有时可以恢复 */
+static void child_drop_effective(void) {
+    struct __user_cap_data_struct data[2];
+    if (capget_self(data) != 0) {
+        _exit(1);
+    }
+    data[CAP_TO_INDEX(CAP_SYS_ADMIN)].effective &= ~CAP_TO_MASK(CAP_SYS_ADMIN);
+    if (capset_self(data) != 0) {
+        _exit(2);
+    }
+    errno = 0;
+    if (mount("none", "/tmp", "tmpfs", 0, NULL) != -1 || errno != EPERM) {
+        _exit(3);
+    }
+    data[CAP_TO_INDEX(CAP_SYS_ADMIN)].effective |= CAP_TO_MASK(CAP_SYS_ADMIN);
+    if (capset_self(data) != 0 || has_effective(CAP_SYS_ADMIN) != 1) {
+        _exit(4);
+    }
+    _exit(0);
+}
+
+/* 从permitted权限集中删除的权限不能再加回 */
+static void child_drop_permitted(void) {
+    struct __user_cap_data_struct data[2];
+    if (capget_self(data) != 0) {
+        _exit(1);
+    }
+    data[CAP_TO_INDEX(CAP_SYS_NICE)].effective &= ~CAP_TO_MASK(CAP_SYS_NICE);
+    data[CAP_TO_INDEX(CAP_SYS_NICE)].permitted &= ~CAP_TO_MASK(CAP_SYS_NICE);
+    if (capset_self(data) != 0) {
+        _exit(2);
+    }
+    data[CAP_TO_INDEX(CAP_SYS_NICE)].permitted |= CAP_TO_MASK(CAP_SYS_NICE);
+    errno = 0;
+    if (capset_self(data) != -1 || errno != EPERM) {
+        _exit(3);
+    }
+    /* 没有CAP_SYS_NICE时可以提高nice值，但不能降低 */
+    if (setpriority(PRIO_PROCESS, 0, 10) != 0 || getpriority(PRIO_PROCESS, 0) != 10) {
+        _exit(4);
+    }
+    errno = 0;
+    if (setpriority(PRIO_PROCESS, 0, 5) != -1 || errno != EACCES) {
+        _exit(5);
+    }
+    _exit(0);
+}
+
+/* 删除bounding set中的权限 */
+static void child_capbset(void) {
+    if (prctl(PR_CAPBSET_READ, CAP_NET_RAW, 0, 0, 0) != 1) {
+        _exit(1);
+    }
+    if (prctl(PR_CAPBSET_DROP, CAP_NET_RAW, 0, 0, 0) != 0) {
+        _exit(2);
+    }
+    if (prctl(PR_CAPBSET_READ, CAP_NET_RAW, 0, 0, 0) != 0) {
+        _exit(3);
+    }
+    errno = 0;
+    if (prctl(PR_CAPBSET_READ, CAP_LAST_CAP + 1, 0, 0, 0) != -1 || errno != EINVAL) {
+        _exit(4);
+    }
+    /* bounding set中没有的权限不能加入inheritable权限集 */
+    struct __user_cap_data_struct data[2];
+    if (capget_self(data) != 0) {
+        _exit(5);
+    }
+    data[CAP_TO_INDEX(CAP_NET_RAW)].inheritable |= CAP_TO_MASK(CAP_NET_RAW);
+    data[CAP_TO_INDEX(CAP_SETPCAP)].effective &= ~CAP_TO_MASK(CAP_SETPCAP);
+    errno = 0;
+    if (capset_self(data) != -1 || errno != EPERM) {
+        _exit(6);
+    }
+    _exit(0);
+}
+
+/* setuid到非root用户之后清空权限集 */
+static void child_setuid(void) {
+    if (setuid(1000) != 0) {
+        _exit(1);
+    }
+    struct __user_cap_data_struct data[2];
+    if (capget_self(data) != 0) {
+        _exit(2);
+    }
+    if (data[0].effective || data[0].permitted || data[1].effective || data[1].permitted) {
+        _exit(3);
+    }
+    errno = 0;
+    if (setuid(0) != -1 || errno != EPERM) {
+        _exit(4);
+    }
+    errno = 0;
+    if (mount("none", "/tmp", "tmpfs", 0, NULL) != -1 || errno != EPERM) {
+        _exit(5);
+    }
+    _exit(0);
+}
+
+/* 设置了keep_caps时，setuid之后保留permitted权限集 */
+static void child_keepcaps(void) {
+    if (prctl(PR_SET_KEEPCAPS, 1, 0, 0, 0) != 0 || prctl(PR_GET_KEEPCAPS, 0, 0, 0, 0) != 1) {
+        _exit(1);
+    }
+    if (setuid(1000) != 0) {
+        _exit(2);
+    }
+    struct __user_cap_data_struct data[2];
+    if (capget_self(data) != 0) {
+        _exit(3);
+    }
+    if (data[0].effective != 0 || !(data[CAP_TO_INDEX(CAP_SYS_ADMIN)].permitted & CAP_TO_MASK(CAP_SYS_ADMIN))) {
+        _exit(4);
+    }
+    /* 重新把权限加入effective权限集 */
+    data[CAP_TO_INDEX(CAP_SYS_ADMIN)].effective |= CAP_TO_MASK(CAP_SYS_ADMIN);
+    if (capset_self(data) != 0 || has_effective(CAP_SYS_ADMIN) != 1) {
+        _exit(5);
+    }
+    _exit(0);
+}
+
+/* ambient权限集中的权限必须同时在permitted与inheritable权限集中 */
+static void child_ambient(void) {
+    errno = 0;
+    if (prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, CAP_NET_BIND_SERVICE, 0, 0) != -1 || errno != EPERM) {
+        _exit(1);
+    }
+    struct __user_cap_data_struct data[2];
+    if (capget_self(data) != 0) {
+        _exit(2);
+    }
+    data[CAP_TO_INDEX(CAP_NET_BIND_SERVICE)].inheritable |= CAP_TO_MASK(CAP_NET_BIND_SERVICE);
+    if (capset_self(data) != 0) {
+        _exit(3);
+    }
+    if (prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_RAISE, CAP_NET_BIND_SERVICE, 0, 0) != 0 ||
+        prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_IS_SET, CAP_NET_BIND_SERVICE, 0, 0) != 1) {
+        _exit(4);
+    }
+    /* 从inheritable权限集中删除时，ambient权限集中的权限也被删除 */
+    data[CAP_TO_INDEX(CAP_NET_BIND_SERVICE)].inheritable &= ~CAP_TO_MASK(CAP_NET_BIND_SERVICE);
+    if (capset_self(data) != 0 || prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_IS_SET, CAP_NET_BIND_SERVICE, 0, 0) != 0) {
+        _exit(5);
+    }
+    if (prctl(PR_CAP_AMBIENT, PR_CAP_AMBIENT_CLEAR_ALL, 0, 0, 0) != 0) {
+        _exit(6);
+    }
+    _exit(0);
+}
+
+int main() {
+    if (getuid() != 0) {
+        printf("test_capability must be run as root\n");
+        return 1;
+    }
+
+    struct __user_cap_header_struct header = {.version = 0x12345678, .pid = 0};
+    check("capget with NULL data reports supported version",
+          syscall(SYS_capget, &header, NULL) == 0 && header.version == _LINUX_CAPABILITY_VERSION_3);
+    header.version = 0x12345678;
+    struct __user_cap_data_struct data[2];
+    errno = 0;
+    check("capget with unknown version returns EINVAL",
+          syscall(SYS_capget, &header, data) == -1 && errno == EINVAL);
+
+    check("root has CAP_SYS_ADMIN", has_effective(CAP_SYS_ADMIN) == 1);
+    check("root has CAP_SETUID", has_effective(CAP_SETUID) == 1);
+
+    header.version = _LINUX_CAPABILITY_VERSION_3;
+    header.pid = getppid();
+    check("capget of another process", syscall(SYS_capget, &header, data) == 0);
+    errno = 0;
+    check("capset of another process is rejected", syscall(SYS_capset, &header, data) == -1 && errno == EPERM);
+
+    check("dropping effective capability", run_child(child_drop_effective) == 0);
+    check("dropping permitted capability", run_child(child_drop_permitted) == 0);
+    check("PR_CAPBSET_READ/PR_CAPBSET_DROP", run_child(child_capbset) == 0);
+    check("setuid clears capabilities", run_child(child_setuid) == 0);
+    check("PR_SET_KEEPCAPS keeps permitted capabilities", run_child(child_keepcaps) == 0);
+    check("PR_CAP_AMBIENT", run_child(child_ambient) == 0);
+
+    check("parent keeps CAP_NET_RAW in bounding set", prctl(PR_CAPBSET_READ, CAP_NET_RAW, 0, 0, 0) == 1);
+
+    if (failures == 0) {
+        printf("All tests passed\n");
+    }
+    return failures == 0 ? 0 : 1;
+}
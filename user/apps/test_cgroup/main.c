@@ -0,0 +1,179 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CGROUP_ROOT "/sys/fs/cgroup"
+#define TEST_CGROUP CGROUP_ROOT "/test_cgroup"
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 写入接口文件，成功返回0，失败返回errno */
+static int write_file(const char *path, const char *buf) {
+    int fd = open(path, O_WRONLY);
+    if (fd < 0) {
+        return errno;
+    }
+    int ret = 0;
+    if (write(fd, buf, strlen(buf)) < 0) {
+        ret = errno;
+    }
+    close(fd);
+    return ret;
+}
+
+static int read_file(const char *path, char *buf, size_t size) {
+    int fd = open(path, O_RDONLY);
+    if (fd < 0) {
+        return -1;
+    }
+    ssize_t len = read(fd, buf, size - 1);
+    close(fd);
+    if (len < 0) {
+        return -1;
+    }
+    buf[len] = '\0';
+    return 0;
+}
+
+/* 从cpu.stat中读取一项 */
+static long read_stat(const char *key) {
+    char buf[512];
+    if (read_file(TEST_CGROUP "/cpu.stat", buf, sizeof(buf)) != 0) {
+        return -1;
+    }
+    char *p = strstr(buf, key);
+    if (p == NULL) {
+        return -1;
+    }
+    return atol(p + strlen(key) + 1);
+}
+
+static long now_ms(void) {
+    struct timespec ts;
+    clock_gettime(CLOCK_MONOTONIC, &ts);
+    return ts.tv_sec * 1000 + ts.tv_nsec / 1000000;
+}
+
+int main() {
+    char buf[512];
+
+    if (access(CGROUP_ROOT "/cgroup.controllers", F_OK) != 0) {
+        mkdir(CGROUP_ROOT, 0755);
+        if (mount("none", CGROUP_ROOT, "cgroup2", 0, NULL) != 0) {
+            printf("failed to mount cgroup2: %s\n", strerror(errno));
+            return 1;
+        }
+    }
+
+    check("cgroup.controllers lists cpu",
+          read_file(CGROUP_ROOT "/cgroup.controllers", buf, sizeof(buf)) == 0 &&
+              strstr(buf, "cpu") != NULL);
+
+    rmdir(TEST_CGROUP);
+    check("mkdir creates a cgroup", mkdir(TEST_CGROUP, 0755) == 0);
+    check("cgroup.procs of a new cgroup is empty",
+          read_file(TEST_CGROUP "/cgroup.procs", buf, sizeof(buf)) == 0 && buf[0] == '\0');
+    check("cpu.weight absent before enabling cpu",
+          access(TEST_CGROUP "/cpu.weight", F_OK) != 0);
+
+    check("unknown controller is rejected with EINVAL",
+          write_file(CGROUP_ROOT "/cgroup.subtree_control", "+foo") == EINVAL);
+    check("enable cpu in root subtree_control",
+          write_file(CGROUP_ROOT "/cgroup.subtree_control", "+cpu") == 0);
+    check("subtree_control shows cpu",
+          read_file(CGROUP_ROOT "/cgroup.subtree_control", buf, sizeof(buf)) == 0 &&
+              strstr(buf, "cpu") != NULL);
+    check("child cgroup.controllers lists cpu",
+          read_file(TEST_CGROUP "/cgroup.controllers", buf, sizeof(buf)) == 0 &&
+              strstr(buf, "cpu") != NULL);
+
+    check("cpu.weight defaults to 100",
+          read_file(TEST_CGROUP "/cpu.weight", buf, sizeof(buf)) == 0 && strcmp(buf, "100\n") == 0);
+    check("cpu.weight 0 fails with ERANGE", write_file(TEST_CGROUP "/cpu.weight", "0") == ERANGE);
+    check("cpu.weight 10001 fails with ERANGE",
+          write_file(TEST_CGROUP "/cpu.weight", "10001") == ERANGE);
+    check("cpu.weight accepts 200", write_file(TEST_CGROUP "/cpu.weight", "200") == 0);
+    check("cpu.weight reads back 200",
+          read_file(TEST_CGROUP "/cpu.weight", buf, sizeof(buf)) == 0 && strcmp(buf, "200\n") == 0);
+
+    check("cpu.max defaults to max 100000",
+          read_file(TEST_CGROUP "/cpu.max", buf, sizeof(buf)) == 0 &&
+              strcmp(buf, "max 100000\n") == 0);
+    check("cpu.max rejects tiny quota", write_file(TEST_CGROUP "/cpu.max", "500") == EINVAL);
+    check("cpu.max rejects garbage", write_file(TEST_CGROUP "/cpu.max", "abc 100000") == EINVAL);
+    check("cpu.max accepts 20000 100000",
+          write_file(TEST_CGROUP "/cpu.max", "20000 100000") == 0);
+    check("cpu.max reads back",
+          read_file(TEST_CGROUP "/cpu.max", buf, sizeof(buf)) == 0 &&
+              strcmp(buf, "20000 100000\n") == 0);
+
+    int pipefd[2];
+    if (pipe(pipefd) != 0) {
+        printf("pipe failed\n");
+        return 1;
+    }
+    pid_t pid = fork();
+    if (pid == 0) {
+        char c;
+        close(pipefd[1]);
+        read(pipefd[0], &c, 1);
+        /* 在cpu.max的限制下忙等500ms */
+        long start = now_ms();
+        volatile unsigned long x = 0;
+        while (now_ms() - start < 500) {
+            x++;
+        }
+        _exit(0);
+    }
+    close(pipefd[0]);
+
+    char pidbuf[32];
+    snprintf(pidbuf, sizeof(pidbuf), "%d", pid);
+    check("attach child through cgroup.procs", write_file(TEST_CGROUP "/cgroup.procs", pidbuf) == 0);
+    snprintf(pidbuf, sizeof(pidbuf), "%d\n", pid);
+    check("cgroup.procs lists the child",
+          read_file(TEST_CGROUP "/cgroup.procs", buf, sizeof(buf)) == 0 && strcmp(buf, pidbuf) == 0);
+    check("attach nonexistent pid fails with ESRCH",
+          write_file(TEST_CGROUP "/cgroup.procs", "999999") == ESRCH);
+    check("rmdir busy cgroup fails with EBUSY", rmdir(TEST_CGROUP) != 0 && errno == EBUSY);
+    check("enabling controllers in a populated cgroup fails with EBUSY",
+          write_file(TEST_CGROUP "/cgroup.subtree_control", "+cpu") == EBUSY);
+
+    write(pipefd[1], "x", 1);
+    close(pipefd[1]);
+    int status;
+    waitpid(pid, &status, 0);
+    check("child exited normally", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    check("cpu.stat usage_usec is positive", read_stat("usage_usec") > 0);
+    check("cpu.stat nr_periods is positive", read_stat("nr_periods") > 0);
+    check("cpu.stat nr_throttled is positive", read_stat("nr_throttled") > 0);
+    check("cpu.stat throttled_usec is positive", read_stat("throttled_usec") > 0);
+
+    check("cgroup.procs empty after child exits",
+          read_file(TEST_CGROUP "/cgroup.procs", buf, sizeof(buf)) == 0 && buf[0] == '\0');
+    check("rmdir empty cgroup", rmdir(TEST_CGROUP) == 0);
+    check("disable cpu in root subtree_control",
+          write_file(CGROUP_ROOT "/cgroup.subtree_control", "-cpu") == 0);
+
+    if (failures) {
+        printf("%d tests failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
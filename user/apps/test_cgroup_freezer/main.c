@@ -0,0 +1,185 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CGROUP_ROOT "/sys/fs/cgroup"
+#define TEST_CGROUP CGROUP_ROOT "/test_freezer"
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 写入接口文件，成功返回0，失败返回errno */
+static int write_file(const char *path, const char *buf) {
+    int fd = open(path, O_WRONLY);
+    if (fd < 0) {
+        return errno;
+    }
+    int ret = 0;
+    if (write(fd, buf, strlen(buf)) < 0) {
+        ret = errno;
+    }
+    close(fd);
+    return ret;
+}
+
+static int read_file(const char *path, char *buf, size_t size) {
+    int fd = open(path, O_RDONLY);
+    if (fd < 0) {
+        return -1;
+    }
+    ssize_t len = read(fd, buf, size - 1);
+    close(fd);
+    if (len < 0) {
+        return -1;
+    }
+    buf[len] = '\0';
+    return 0;
+}
+
+/* 从cgroup.events中读取一项 */
+static long read_event(const char *key) {
+    char buf[256];
+    if (read_file(TEST_CGROUP "/cgroup.events", buf, sizeof(buf)) != 0) {
+        return -1;
+    }
+    char *p = buf;
+    size_t len = strlen(key);
+    while (p != NULL && *p != '\0') {
+        if (strncmp(p, key, len) == 0 && p[len] == ' ') {
+            return atol(p + len + 1);
+        }
+        p = strchr(p, '\n');
+        if (p != NULL) {
+            p++;
+        }
+    }
+    return -1;
+}
+
+/* 等待cgroup.events中的frozen变为expected，最多等待2秒 */
+static int wait_frozen(long expected) {
+    for (int i = 0; i < 200; i++) {
+        if (read_event("frozen") == expected) {
+            return 1;
+        }
+        usleep(10000);
+    }
+    return 0;
+}
+
+/* 把进程迁移到cgroup目录dir中 */
+static int attach(const char *dir, pid_t pid) {
+    char path[128], buf[32];
+    snprintf(path, sizeof(path), "%s/cgroup.procs", dir);
+    snprintf(buf, sizeof(buf), "%d", pid);
+    return write_file(path, buf);
+}
+
+/* 子进程不断增加计数器；sleeper为真时每次增加之前睡眠，用于测试冻结可中断睡眠中的进程 */
+static pid_t spawn_counter(volatile long *counter, int sleeper) {
+    pid_t pid = fork();
+    if (pid == 0) {
+        for (;;) {
+            if (sleeper) {
+                usleep(10000);
+            }
+            (*counter)++;
+        }
+    }
+    return pid;
+}
+
+/* 计数器在一段时间内是否变化 */
+static int counter_moves(volatile long *counter) {
+    long before = *counter;
+    usleep(200000);
+    return *counter != before;
+}
+
+int main() {
+    char buf[256];
+
+    if (access(CGROUP_ROOT "/cgroup.controllers", F_OK) != 0) {
+        mkdir(CGROUP_ROOT, 0755);
+        if (mount("none", CGROUP_ROOT, "cgroup2", 0, NULL) != 0) {
+            printf("failed to mount cgroup2: %s\n", strerror(errno));
+            return 1;
+        }
+    }
+
+    check("root cgroup has no cgroup.freeze", access(CGROUP_ROOT "/cgroup.freeze", F_OK) != 0);
+    rmdir(TEST_CGROUP);
+    check("mkdir creates a cgroup", mkdir(TEST_CGROUP, 0755) == 0);
+    check("cgroup.freeze defaults to 0",
+          read_file(TEST_CGROUP "/cgroup.freeze", buf, sizeof(buf)) == 0 && strcmp(buf, "0\n") == 0);
+    check("empty cgroup is not populated", read_event("populated") == 0);
+    check("cgroup.freeze rejects 2", write_file(TEST_CGROUP "/cgroup.freeze", "2") == EINVAL);
+    check("cgroup.events is read-only", write_file(TEST_CGROUP "/cgroup.events", "0") == EACCES);
+
+    volatile long *counters = mmap(NULL, 4096, PROT_READ | PROT_WRITE,
+                                   MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    if (counters == MAP_FAILED) {
+        printf("mmap failed: %s\n", strerror(errno));
+        return 1;
+    }
+    pid_t busy = spawn_counter(&counters[0], 0);
+    pid_t sleeper = spawn_counter(&counters[1], 1);
+    check("attach busy child", attach(TEST_CGROUP, busy) == 0);
+    check("attach sleeping child", attach(TEST_CGROUP, sleeper) == 0);
+    check("cgroup is populated", read_event("populated") == 1);
+    check("busy child runs before freezing", counter_moves(&counters[0]));
+
+    check("write 1 to cgroup.freeze", write_file(TEST_CGROUP "/cgroup.freeze", "1") == 0);
+    check("cgroup.freeze reads 1",
+          read_file(TEST_CGROUP "/cgroup.freeze", buf, sizeof(buf)) == 0 && strcmp(buf, "1\n") == 0);
+    check("cgroup becomes frozen", wait_frozen(1));
+    check("busy child stops while frozen", !counter_moves(&counters[0]));
+    check("sleeping child stops while frozen", !counter_moves(&counters[1]));
+    check("frozen child is not reported as exited", waitpid(busy, NULL, WNOHANG) == 0);
+
+    pid_t late = spawn_counter(&counters[2], 0);
+    check("attach child to a frozen cgroup", attach(TEST_CGROUP, late) == 0);
+    check("child attached later is frozen too", wait_frozen(1) && !counter_moves(&counters[2]));
+    check("move child back to the root cgroup", attach(CGROUP_ROOT, late) == 0);
+    check("child leaving a frozen cgroup is thawed", counter_moves(&counters[2]));
+    kill(late, SIGKILL);
+    waitpid(late, NULL, 0);
+
+    check("write 0 to cgroup.freeze", write_file(TEST_CGROUP "/cgroup.freeze", "0") == 0);
+    check("cgroup is thawed", wait_frozen(0));
+    check("busy child runs after thawing", counter_moves(&counters[0]));
+    check("sleeping child runs after thawing", counter_moves(&counters[1]));
+
+    check("freeze again", write_file(TEST_CGROUP "/cgroup.freeze", "1") == 0 && wait_frozen(1));
+    int status = 0;
+    kill(busy, SIGKILL);
+    check("frozen child can be killed",
+          waitpid(busy, &status, 0) == busy && WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    kill(sleeper, SIGKILL);
+    waitpid(sleeper, NULL, 0);
+
+    check("killing all children leaves the cgroup unpopulated", read_event("populated") == 0);
+    check("thaw the empty cgroup", write_file(TEST_CGROUP "/cgroup.freeze", "0") == 0);
+    check("rmdir empty cgroup", rmdir(TEST_CGROUP) == 0);
+
+    if (failures) {
+        printf("%d tests failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
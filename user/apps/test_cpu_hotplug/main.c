@@ -0,0 +1,212 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CPU_DIR "/sys/devices/system/cpu"
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static int read_file(const char *path, char *buf, size_t len) {
+    int fd = open(path, O_RDONLY);
+    if (fd < 0) {
+        return -1;
+    }
+    ssize_t n = read(fd, buf, len - 1);
+    close(fd);
+    if (n < 0) {
+        return -1;
+    }
+    buf[n] = '\0';
+    return 0;
+}
+
+/* 写入成功返回0，失败返回-1并保留errno */
+static int write_file(const char *path, const char *s) {
+    int fd = open(path, O_WRONLY);
+    if (fd < 0) {
+        return -1;
+    }
+    ssize_t n = write(fd, s, strlen(s));
+    int saved = errno;
+    close(fd);
+    errno = saved;
+    return n == (ssize_t)strlen(s) ? 0 : -1;
+}
+
+/* 判断cpu是否在形如"0-3,5"的列表中 */
+static int cpu_in_list(const char *list, int cpu) {
+    const char *p = list;
+    for (;;) {
+        char *end;
+        long start = strtol(p, &end, 10);
+        if (end == p) {
+            return 0;
+        }
+        long last = start;
+        if (*end == '-') {
+            last = strtol(end + 1, &end, 10);
+        }
+        if (cpu >= start && cpu <= last) {
+            return 1;
+        }
+        if (*end != ',') {
+            return 0;
+        }
+        p = end + 1;
+    }
+}
+
+static int cpu_online_in_list(int cpu) {
+    char buf[256];
+    if (read_file(CPU_DIR "/online", buf, sizeof(buf)) != 0) {
+        return -1;
+    }
+    return cpu_in_list(buf, cpu);
+}
+
+static int read_cpu_online(int cpu) {
+    char path[128], buf[16];
+    snprintf(path, sizeof(path), CPU_DIR "/cpu%d/online", cpu);
+    if (read_file(path, buf, sizeof(buf)) != 0) {
+        return -1;
+    }
+    return buf[0] == '1';
+}
+
+static int set_cpu_online(int cpu, const char *value) {
+    char path[128];
+    snprintf(path, sizeof(path), CPU_DIR "/cpu%d/online", cpu);
+    return write_file(path, value);
+}
+
+/* 找一个可以热插拔的cpu，即存在cpuN/online的cpu */
+static int find_hotplug_cpu(void) {
+    for (int cpu = 1; cpu < CPU_SETSIZE; cpu++) {
+        char path[128];
+        snprintf(path, sizeof(path), CPU_DIR "/cpu%d/online", cpu);
+        if (access(path, F_OK) == 0) {
+            return cpu;
+        }
+    }
+    return -1;
+}
+
+static void pin(int cpu) {
+    cpu_set_t one;
+    CPU_ZERO(&one);
+    CPU_SET(cpu, &one);
+    sched_setaffinity(0, sizeof(one), &one);
+}
+
+static void test_sysfs_layout(void) {
+    char buf[256];
+    check("cpu/online is readable", read_file(CPU_DIR "/online", buf, sizeof(buf)) == 0);
+    check("cpu 0 is online", cpu_online_in_list(0) == 1);
+    check("cpu0 directory exists", access(CPU_DIR "/cpu0", F_OK) == 0);
+    check("cpu0 cannot be offlined", access(CPU_DIR "/cpu0/online", F_OK) != 0);
+}
+
+static void test_offline_online(int cpu) {
+    char what[96];
+
+    check("cpu starts online", read_cpu_online(cpu) == 1 && cpu_online_in_list(cpu) == 1);
+
+    /* 子进程固定在要离线的cpu上，不停地记录自己所在的cpu */
+    volatile int *child_cpu =
+        mmap(NULL, sizeof(int), PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    *child_cpu = -1;
+    pin(cpu);
+    pid_t child = fork();
+    if (child == 0) {
+        for (;;) {
+            *child_cpu = sched_getcpu();
+            usleep(10 * 1000);
+        }
+    }
+    for (int i = 0; i < 100 && *child_cpu != cpu; i++) {
+        usleep(10 * 1000);
+    }
+    check("child runs on the cpu", *child_cpu == cpu);
+
+    /* 在将要离线的cpu上发起离线 */
+    snprintf(what, sizeof(what), "offline cpu %d", cpu);
+    check(what, set_cpu_online(cpu, "0") == 0);
+    check("cpuN/online reads 0", read_cpu_online(cpu) == 0);
+    check("cpu removed from online list", cpu_online_in_list(cpu) == 0);
+    check("writer left the offline cpu", sched_getcpu() != cpu);
+
+    cpu_set_t got;
+    CPU_ZERO(&got);
+    sched_getaffinity(0, sizeof(got), &got);
+    check("affinity is widened when its only cpu goes offline", CPU_COUNT(&got) > 1);
+
+    usleep(200 * 1000);
+    int last = *child_cpu;
+    check("pinned child keeps running", last >= 0);
+    check("pinned child moved off the offline cpu", last != cpu);
+
+    cpu_set_t one;
+    CPU_ZERO(&one);
+    CPU_SET(cpu, &one);
+    errno = 0;
+    check("cannot pin to an offline cpu",
+          sched_setaffinity(0, sizeof(one), &one) == -1 && errno == EINVAL);
+    check("offlining again is a no-op", set_cpu_online(cpu, "0") == 0);
+
+    errno = 0;
+    check("invalid value is EINVAL", set_cpu_online(cpu, "2") == -1 && errno == EINVAL);
+
+    snprintf(what, sizeof(what), "online cpu %d", cpu);
+    check(what, set_cpu_online(cpu, "1") == 0);
+    check("cpuN/online reads 1", read_cpu_online(cpu) == 1);
+    check("cpu back in online list", cpu_online_in_list(cpu) == 1);
+
+    check("can pin to the cpu again", sched_setaffinity(0, sizeof(one), &one) == 0);
+    for (volatile int i = 0; i < 10000000; i++) {
+    }
+    check("running on the onlined cpu", sched_getcpu() == cpu);
+
+    kill(child, SIGKILL);
+    waitpid(child, NULL, 0);
+    munmap((void *)child_cpu, sizeof(int));
+}
+
+int main(void) {
+    cpu_set_t all;
+    CPU_ZERO(&all);
+    sched_getaffinity(0, sizeof(all), &all);
+
+    test_sysfs_layout();
+
+    int cpu = find_hotplug_cpu();
+    if (cpu < 0) {
+        printf("SKIP: no hotpluggable cpu\n");
+    } else if (geteuid() != 0) {
+        printf("SKIP: need root to offline cpus\n");
+    } else {
+        test_offline_online(cpu);
+    }
+    sched_setaffinity(0, sizeof(all), &all);
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
@@ -7,6 +7,13 @@ int main()
 {
     printf("Current uid: %d, euid: %d, gid: %d, egid: %d\n\n", getuid(), geteuid(), getgid(), getegid());
 
+    // 测试gid。setuid之后失去CAP_SETGID，因此先修改gid
+    printf("Set gid 1000\n");
+    setgid(1000);
+    int gid = getgid();
+    assert(gid == 1000);
+    printf("Current gid:%d\n\n", gid);
+
     // 测试uid
     printf("Set uid 1000\n");
     setuid(1000);
@@ -14,13 +21,6 @@ int main()
     assert(uid == 1000);
     printf("Current uid:%d\n\n", uid);
 
-    // 测试gid
-    printf("Set gid 1000\n");
-    setgid(1000);
-    int gid = getgid();
-    assert(gid == 1000);
-    printf("Current gid:%d\n\n", gid);
-
     // 测试euid
     printf("Setg euid 1000\n");
     seteuid(1000);
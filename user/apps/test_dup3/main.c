@@ -1,6 +1,33 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
 #include <stdio.h>
+#include <sys/epoll.h>
+#include <sys/socket.h>
 #include <unistd.h>
-#include <fcntl.h>
+
+static int failures = 0;
+
+/* 检查系统调用以指定的错误码失败 */
+static void expect_errno(const char *what, int ret, int err) {
+    if (ret >= 0 || errno != err) {
+        printf("FAIL: %s: ret=%d errno=%d, expected errno=%d\n", what, ret, errno, err);
+        failures++;
+    } else {
+        printf("PASS: %s\n", what);
+    }
+}
+
+/* 检查fd是否设置了close-on-exec */
+static void expect_cloexec(const char *what, int fd, int expected) {
+    int flags = fcntl(fd, F_GETFD);
+    if (flags < 0 || !!(flags & FD_CLOEXEC) != expected) {
+        printf("FAIL: %s: F_GETFD=%d, expected cloexec=%d\n", what, flags, expected);
+        failures++;
+    } else {
+        printf("PASS: %s\n", what);
+    }
+}
 
 int main() {
     int fd = open("/history_commands.txt", O_RDONLY);
@@ -23,8 +50,48 @@ int main() {
     }
 
     printf("Data:\n %.*s\n", bytes_read, buffer);
+    expect_cloexec("dup3(O_CLOEXEC)", new_fd, 1);
+
+    /* 未知的标志位必须返回EINVAL */
+    expect_errno("dup3 with unknown flags", dup3(fd, 778, 0x40000000), EINVAL);
+    expect_errno("dup3 with oldfd == newfd", dup3(fd, fd, 0), EINVAL);
+    int pipefd[2];
+    expect_errno("pipe2 with unknown flags", pipe2(pipefd, 0x40000000), EINVAL);
+    expect_errno("epoll_create1 with unknown flags", epoll_create1(0x40000000), EINVAL);
+    expect_errno("socket with unknown type flags",
+                 socket(AF_INET, SOCK_STREAM | 0x40000000, 0), EINVAL);
+
+    /* oldfd无效时不能关闭newfd */
+    expect_errno("dup3 with bad oldfd", dup3(12345, new_fd, 0), EBADF);
+    expect_cloexec("newfd survives dup3 with bad oldfd", new_fd, 1);
+
+    /* F_DUPFD_CLOEXEC返回不小于arg的最小空闲fd */
+    int dupfd = fcntl(fd, F_DUPFD_CLOEXEC, 100);
+    if (dupfd < 100) {
+        printf("FAIL: F_DUPFD_CLOEXEC returned %d\n", dupfd);
+        failures++;
+    } else {
+        expect_cloexec("F_DUPFD_CLOEXEC", dupfd, 1);
+        close(dupfd);
+    }
+    dupfd = fcntl(fd, F_DUPFD, 100);
+    expect_cloexec("F_DUPFD", dupfd, 0);
+    close(dupfd);
+    expect_errno("F_DUPFD with negative arg", fcntl(fd, F_DUPFD, -1), EINVAL);
+
+    /* F_SETFL不能清除close-on-exec标志 */
+    fcntl(new_fd, F_SETFL, O_NONBLOCK);
+    expect_cloexec("F_SETFL keeps FD_CLOEXEC", new_fd, 1);
+
+    if (pipe2(pipefd, O_CLOEXEC) == 0) {
+        expect_cloexec("pipe2(O_CLOEXEC) read end", pipefd[0], 1);
+        expect_cloexec("pipe2(O_CLOEXEC) write end", pipefd[1], 1);
+        close(pipefd[0]);
+        close(pipefd[1]);
+    }
 
     close(fd);
     close(new_fd);
-    return 0;
-}
\ No newline at end of file
+    printf("%s\n", failures ? "Some tests failed" : "All tests passed");
+    return failures ? 1 : 0;
+}
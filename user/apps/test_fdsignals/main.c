@@ -0,0 +1,143 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/epoll.h>
+#include <sys/eventfd.h>
+#include <sys/signalfd.h>
+#include <sys/timerfd.h>
+#include <time.h>
+#include <unistd.h>
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 用epoll等待fd可读，返回就绪的fd数量 */
+static int wait_readable(int fd, int timeout_ms) {
+    int epfd = epoll_create1(0);
+    struct epoll_event ev = {.events = EPOLLIN, .data.fd = fd};
+    epoll_ctl(epfd, EPOLL_CTL_ADD, fd, &ev);
+    int n = epoll_wait(epfd, &ev, 1, timeout_ms);
+    close(epfd);
+    return n;
+}
+
+static void test_eventfd_semaphore(void) {
+    int efd = eventfd(3, EFD_SEMAPHORE | EFD_NONBLOCK);
+    uint64_t v = 0;
+    int ok = 1;
+    for (int i = 0; i < 3; i++) {
+        ok &= read(efd, &v, sizeof(v)) == sizeof(v) && v == 1;
+    }
+    check("EFD_SEMAPHORE reads decrement by one", ok);
+    check("EFD_SEMAPHORE read on zero counter returns EAGAIN",
+          read(efd, &v, sizeof(v)) < 0 && errno == EAGAIN);
+    check("eventfd with zero counter is not readable", wait_readable(efd, 0) == 0);
+    v = 2;
+    write(efd, &v, sizeof(v));
+    check("eventfd is readable after write", wait_readable(efd, 0) == 1);
+    close(efd);
+}
+
+static void test_signalfd(void) {
+    sigset_t mask;
+    sigemptyset(&mask);
+    sigaddset(&mask, SIGUSR1);
+    sigprocmask(SIG_BLOCK, &mask, NULL);
+
+    int sfd = signalfd(-1, &mask, SFD_NONBLOCK | SFD_CLOEXEC);
+    check("signalfd", sfd >= 0);
+    struct signalfd_siginfo info;
+    check("signalfd read without pending signal returns EAGAIN",
+          read(sfd, &info, sizeof(info)) < 0 && errno == EAGAIN);
+
+    kill(getpid(), SIGUSR1);
+    check("signalfd is readable after the signal is sent", wait_readable(sfd, 1000) == 1);
+    memset(&info, 0, sizeof(info));
+    check("signalfd read returns one siginfo", read(sfd, &info, sizeof(info)) == sizeof(info));
+    check("siginfo has SIGUSR1", info.ssi_signo == SIGUSR1);
+    check("siginfo has the sender pid", info.ssi_pid == (uint32_t)getpid());
+
+    sigset_t pending;
+    sigpending(&pending);
+    check("signal is no longer pending", !sigismember(&pending, SIGUSR1));
+    check("signalfd read with a small buffer returns EINVAL",
+          read(sfd, &info, sizeof(info) - 1) < 0 && errno == EINVAL);
+
+    close(sfd);
+    sigprocmask(SIG_UNBLOCK, &mask, NULL);
+}
+
+static void test_timerfd(void) {
+    int tfd = timerfd_create(CLOCK_MONOTONIC, TFD_NONBLOCK);
+    check("timerfd_create(CLOCK_MONOTONIC)", tfd >= 0);
+    uint64_t ticks = 0;
+    check("disarmed timerfd read returns EAGAIN",
+          read(tfd, &ticks, sizeof(ticks)) < 0 && errno == EAGAIN);
+
+    /* 50ms后第一次到期，之后每50ms到期一次 */
+    struct itimerspec its = {
+        .it_value = {.tv_sec = 0, .tv_nsec = 50 * 1000 * 1000},
+        .it_interval = {.tv_sec = 0, .tv_nsec = 50 * 1000 * 1000},
+    };
+    check("timerfd_settime", timerfd_settime(tfd, 0, &its, NULL) == 0);
+    struct itimerspec cur;
+    check("timerfd_gettime", timerfd_gettime(tfd, &cur) == 0);
+    check("timerfd_gettime reports the interval", cur.it_interval.tv_nsec == 50 * 1000 * 1000);
+    check("timerfd is readable after expiring", wait_readable(tfd, 1000) == 1);
+
+    usleep(200 * 1000);
+    check("interval timer counts every expiration",
+          read(tfd, &ticks, sizeof(ticks)) == sizeof(ticks) && ticks >= 3);
+    printf("  ticks=%llu\n", (unsigned long long)ticks);
+
+    /* 停止定时器 */
+    memset(&its, 0, sizeof(its));
+    timerfd_settime(tfd, 0, &its, NULL);
+    timerfd_gettime(tfd, &cur);
+    check("disarmed timer has zero it_value", cur.it_value.tv_sec == 0 && cur.it_value.tv_nsec == 0);
+    close(tfd);
+
+    tfd = timerfd_create(CLOCK_REALTIME, 0);
+    struct timespec now;
+    clock_gettime(CLOCK_REALTIME, &now);
+    its.it_value.tv_sec = now.tv_sec;
+    its.it_value.tv_nsec = now.tv_nsec + 100 * 1000 * 1000;
+    if (its.it_value.tv_nsec >= 1000000000) {
+        its.it_value.tv_sec++;
+        its.it_value.tv_nsec -= 1000000000;
+    }
+    check("timerfd_settime(TFD_TIMER_ABSTIME)", timerfd_settime(tfd, TFD_TIMER_ABSTIME, &its, NULL) == 0);
+    check("blocking read of an absolute timer",
+          read(tfd, &ticks, sizeof(ticks)) == sizeof(ticks) && ticks == 1);
+    struct timespec after;
+    clock_gettime(CLOCK_REALTIME, &after);
+    check("absolute timer does not expire early",
+          after.tv_sec > its.it_value.tv_sec ||
+              (after.tv_sec == its.it_value.tv_sec && after.tv_nsec >= its.it_value.tv_nsec));
+    close(tfd);
+
+    check("timerfd_create with an unsupported clock returns EINVAL",
+          timerfd_create(CLOCK_PROCESS_CPUTIME_ID, 0) < 0 && errno == EINVAL);
+}
+
+int main() {
+    test_eventfd_semaphore();
+    test_signalfd();
+    test_timerfd();
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
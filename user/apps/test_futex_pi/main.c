@@ -0,0 +1,164 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/futex.h>
+#include <pthread.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <time.h>
+#include <unistd.h>
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static long futex(uint32_t *uaddr, int op, uint32_t val, const struct timespec *timeout,
+                  uint32_t *uaddr2, uint32_t val3) {
+    return syscall(SYS_futex, uaddr, op, val, timeout, uaddr2, val3);
+}
+
+static pid_t gettid_raw(void) { return syscall(SYS_gettid); }
+
+static uint32_t pi_word = 0;
+
+static void *pi_contender(void *arg) {
+    (void)arg;
+    /* 锁被主线程持有，这里会阻塞直到主线程FUTEX_UNLOCK_PI把锁交过来 */
+    long ret = futex(&pi_word, FUTEX_LOCK_PI_PRIVATE, 0, NULL, NULL, 0);
+    int owned = ret == 0 && (pi_word & FUTEX_TID_MASK) == (uint32_t)gettid_raw();
+    futex(&pi_word, FUTEX_UNLOCK_PI_PRIVATE, 0, NULL, NULL, 0);
+    return (void *)(intptr_t)owned;
+}
+
+static void test_lock_pi(void) {
+    pid_t tid = gettid_raw();
+    check("FUTEX_LOCK_PI on a free word takes it",
+          futex(&pi_word, FUTEX_LOCK_PI_PRIVATE, 0, NULL, NULL, 0) == 0 &&
+              pi_word == (uint32_t)tid);
+    check("FUTEX_LOCK_PI by the owner returns EDEADLK",
+          futex(&pi_word, FUTEX_LOCK_PI_PRIVATE, 0, NULL, NULL, 0) < 0 && errno == EDEADLK);
+
+    pthread_t th;
+    pthread_create(&th, NULL, pi_contender, NULL);
+    /* 等待竞争者设置FUTEX_WAITERS */
+    for (int i = 0; i < 200 && !(pi_word & FUTEX_WAITERS); i++) {
+        usleep(10000);
+    }
+    check("blocked waiter sets FUTEX_WAITERS", (pi_word & FUTEX_WAITERS) != 0);
+
+    check("FUTEX_UNLOCK_PI hands the lock over",
+          futex(&pi_word, FUTEX_UNLOCK_PI_PRIVATE, 0, NULL, NULL, 0) == 0);
+    void *owned = NULL;
+    pthread_join(th, &owned);
+    check("waiter owns the word after handoff", owned != NULL);
+    check("word is free after the waiter unlocks", pi_word == 0);
+
+    check("FUTEX_UNLOCK_PI by a non-owner returns EPERM",
+          futex(&pi_word, FUTEX_UNLOCK_PI_PRIVATE, 0, NULL, NULL, 0) < 0 && errno == EPERM);
+
+    uint32_t taken = 1;
+    check("FUTEX_TRYLOCK_PI on a held word returns EAGAIN",
+          futex(&taken, FUTEX_TRYLOCK_PI_PRIVATE, 0, NULL, NULL, 0) < 0 && errno == EAGAIN);
+}
+
+static void test_lock_pi_timeout(void) {
+    /* 持有者（init进程）不会释放锁，等待应超时 */
+    uint32_t word = 1;
+    struct timespec ts;
+    clock_gettime(CLOCK_REALTIME, &ts);
+    ts.tv_nsec += 50 * 1000 * 1000;
+    if (ts.tv_nsec >= 1000000000) {
+        ts.tv_sec++;
+        ts.tv_nsec -= 1000000000;
+    }
+    long ret = futex(&word, FUTEX_LOCK_PI_PRIVATE, 0, &ts, NULL, 0);
+    check("FUTEX_LOCK_PI times out at the absolute deadline", ret < 0 && errno == ETIMEDOUT);
+}
+
+static uint32_t bitset_word = 0;
+static volatile int bitset_woken[2];
+
+struct bitset_arg {
+    int index;
+    uint32_t bitset;
+};
+
+static void *bitset_waiter(void *p) {
+    struct bitset_arg *arg = p;
+    struct timespec ts;
+    clock_gettime(CLOCK_MONOTONIC, &ts);
+    ts.tv_sec += 1;
+    long ret = futex(&bitset_word, FUTEX_WAIT_BITSET_PRIVATE, 0, &ts, NULL, arg->bitset);
+    bitset_woken[arg->index] = ret == 0 ? 1 : -1;
+    return NULL;
+}
+
+static void test_wait_bitset(void) {
+    struct bitset_arg args[2] = {{0, 0x1}, {1, 0x2}};
+    pthread_t th[2];
+    for (int i = 0; i < 2; i++) {
+        pthread_create(&th[i], NULL, bitset_waiter, &args[i]);
+    }
+    usleep(100000);
+
+    long n = futex(&bitset_word, FUTEX_WAKE_BITSET_PRIVATE, 2, NULL, NULL, 0x2);
+    check("FUTEX_WAKE_BITSET wakes only matching waiters", n == 1);
+    usleep(50000);
+    check("matching waiter was woken", bitset_woken[1] == 1);
+    check("non-matching waiter is still asleep", bitset_woken[0] == 0);
+
+    for (int i = 0; i < 2; i++) {
+        pthread_join(th[i], NULL);
+    }
+    check("non-matching waiter times out at the monotonic deadline", bitset_woken[0] == -1);
+    check("FUTEX_WAIT_BITSET with empty bitset returns EINVAL",
+          futex(&bitset_word, FUTEX_WAIT_BITSET_PRIVATE, 0, NULL, NULL, 0) < 0 &&
+              errno == EINVAL);
+}
+
+static pthread_mutex_t pi_mutex;
+static pthread_cond_t cond = PTHREAD_COND_INITIALIZER;
+static int ready = 0;
+
+static void *cond_signaler(void *arg) {
+    (void)arg;
+    pthread_mutex_lock(&pi_mutex);
+    ready = 1;
+    pthread_cond_signal(&cond);
+    pthread_mutex_unlock(&pi_mutex);
+    return NULL;
+}
+
+static void test_pthread_prio_inherit(void) {
+    pthread_mutexattr_t attr;
+    pthread_mutexattr_init(&attr);
+    check("PTHREAD_PRIO_INHERIT is accepted",
+          pthread_mutexattr_setprotocol(&attr, PTHREAD_PRIO_INHERIT) == 0);
+    pthread_mutex_init(&pi_mutex, &attr);
+
+    pthread_mutex_lock(&pi_mutex);
+    pthread_t th;
+    pthread_create(&th, NULL, cond_signaler, NULL);
+    while (!ready) {
+        pthread_cond_wait(&cond, &pi_mutex);
+    }
+    pthread_mutex_unlock(&pi_mutex);
+    pthread_join(th, NULL);
+    check("condvar with a PI mutex", ready == 1);
+    pthread_mutex_destroy(&pi_mutex);
+}
+
+int main(void) {
+    test_lock_pi();
+    test_lock_pi_timeout();
+    test_wait_bitset();
+    test_pthread_prio_inherit();
+
+    printf("%s\n", failures == 0 ? "All tests passed" : "Some tests failed");
+    return failures == 0 ? 0 : 1;
+}
@@ -0,0 +1,281 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <time.h>
+#include <unistd.h>
+
+/* 与内核的定义保持一致，不依赖<linux/io_uring.h> */
+#ifndef SYS_io_uring_setup
+#define SYS_io_uring_setup 425
+#define SYS_io_uring_enter 426
+#define SYS_io_uring_register 427
+#endif
+
+#define IORING_OFF_SQ_RING_ 0ULL
+#define IORING_OFF_SQES_ 0x10000000ULL
+
+#define IORING_FEAT_SINGLE_MMAP_ (1U << 0)
+#define IORING_ENTER_GETEVENTS_ (1U << 0)
+#define IOSQE_FIXED_FILE_ (1U << 0)
+#define IORING_REGISTER_FILES_ 2
+#define IORING_UNREGISTER_FILES_ 3
+
+#define OP_NOP 0
+#define OP_READ 22
+#define OP_WRITE 23
+
+#define ENTRIES 8
+#define TEST_FILE "/tmp/test_io_uring"
+
+struct sqring_offsets {
+    uint32_t head, tail, ring_mask, ring_entries, flags, dropped, array, resv1;
+    uint64_t user_addr;
+};
+
+struct cqring_offsets {
+    uint32_t head, tail, ring_mask, ring_entries, overflow, cqes, flags, resv1;
+    uint64_t user_addr;
+};
+
+struct uring_params {
+    uint32_t sq_entries, cq_entries, flags, sq_thread_cpu, sq_thread_idle, features, wq_fd;
+    uint32_t resv[3];
+    struct sqring_offsets sq_off;
+    struct cqring_offsets cq_off;
+};
+
+struct uring_sqe {
+    uint8_t opcode;
+    uint8_t flags;
+    uint16_t ioprio;
+    int32_t fd;
+    uint64_t off;
+    uint64_t addr;
+    uint32_t len;
+    uint32_t rw_flags;
+    uint64_t user_data;
+    uint16_t buf_index;
+    uint16_t personality;
+    int32_t splice_fd_in;
+    uint64_t addr3;
+    uint64_t pad2;
+};
+
+struct uring_cqe {
+    uint64_t user_data;
+    int32_t res;
+    uint32_t flags;
+};
+
+struct ring {
+    int fd;
+    struct uring_params p;
+    void *ring_ptr;
+    size_t ring_len;
+    struct uring_sqe *sqes;
+    volatile uint32_t *sq_head, *sq_tail, *sq_array, *cq_head, *cq_tail;
+    struct uring_cqe *cqes;
+};
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static int ring_enter(struct ring *r, unsigned to_submit, unsigned min_complete, unsigned flags) {
+    return syscall(SYS_io_uring_enter, r->fd, to_submit, min_complete, flags, NULL, 0);
+}
+
+static int ring_init(struct ring *r) {
+    memset(r, 0, sizeof(*r));
+    r->fd = syscall(SYS_io_uring_setup, ENTRIES, &r->p);
+    if (r->fd < 0) {
+        return -1;
+    }
+    if (!(r->p.features & IORING_FEAT_SINGLE_MMAP_)) {
+        return -1;
+    }
+
+    /* SQ与CQ共用一次mmap */
+    size_t sq_len = r->p.sq_off.array + r->p.sq_entries * sizeof(uint32_t);
+    size_t cq_len = r->p.cq_off.cqes + r->p.cq_entries * sizeof(struct uring_cqe);
+    r->ring_len = sq_len > cq_len ? sq_len : cq_len;
+    r->ring_ptr = mmap(NULL, r->ring_len, PROT_READ | PROT_WRITE, MAP_SHARED, r->fd,
+                       IORING_OFF_SQ_RING_);
+    if (r->ring_ptr == MAP_FAILED) {
+        return -1;
+    }
+    r->sqes = mmap(NULL, r->p.sq_entries * sizeof(struct uring_sqe), PROT_READ | PROT_WRITE,
+                   MAP_SHARED, r->fd, IORING_OFF_SQES_);
+    if (r->sqes == MAP_FAILED) {
+        return -1;
+    }
+
+    char *base = r->ring_ptr;
+    r->sq_head = (uint32_t *)(base + r->p.sq_off.head);
+    r->sq_tail = (uint32_t *)(base + r->p.sq_off.tail);
+    r->sq_array = (uint32_t *)(base + r->p.sq_off.array);
+    r->cq_head = (uint32_t *)(base + r->p.cq_off.head);
+    r->cq_tail = (uint32_t *)(base + r->p.cq_off.tail);
+    r->cqes = (struct uring_cqe *)(base + r->p.cq_off.cqes);
+    return 0;
+}
+
+/* 在SQ中放入一个请求，返回对应的SQE */
+static struct uring_sqe *queue_sqe(struct ring *r, uint8_t opcode, int fd, uint64_t off,
+                                   void *buf, uint32_t len, uint64_t user_data) {
+    uint32_t tail = *r->sq_tail;
+    uint32_t idx = tail & (r->p.sq_entries - 1);
+    struct uring_sqe *sqe = &r->sqes[idx];
+    memset(sqe, 0, sizeof(*sqe));
+    sqe->opcode = opcode;
+    sqe->fd = fd;
+    sqe->off = off;
+    sqe->addr = (uint64_t)(uintptr_t)buf;
+    sqe->len = len;
+    sqe->user_data = user_data;
+    r->sq_array[idx] = idx;
+    __atomic_store_n(r->sq_tail, tail + 1, __ATOMIC_RELEASE);
+    return sqe;
+}
+
+/* 取出一个CQE，CQ为空时返回-1 */
+static int pop_cqe(struct ring *r, struct uring_cqe *out) {
+    uint32_t head = *r->cq_head;
+    if (head == __atomic_load_n(r->cq_tail, __ATOMIC_ACQUIRE)) {
+        return -1;
+    }
+    *out = r->cqes[head & (r->p.cq_entries - 1)];
+    __atomic_store_n(r->cq_head, head + 1, __ATOMIC_RELEASE);
+    return 0;
+}
+
+/* 提交一个请求并等待它完成 */
+static int submit_and_wait(struct ring *r, struct uring_cqe *cqe) {
+    if (ring_enter(r, 1, 1, IORING_ENTER_GETEVENTS_) != 1) {
+        return -1;
+    }
+    return pop_cqe(r, cqe);
+}
+
+static void test_nop(struct ring *r) {
+    struct uring_cqe cqe;
+    queue_sqe(r, OP_NOP, -1, 0, NULL, 0, 0x1234);
+    check("nop completes", submit_and_wait(r, &cqe) == 0);
+    check("nop cqe", cqe.user_data == 0x1234 && cqe.res == 0);
+
+    queue_sqe(r, 0xff, -1, 0, NULL, 0, 7);
+    check("unknown opcode completes", submit_and_wait(r, &cqe) == 0);
+    check("unknown opcode is EINVAL", cqe.user_data == 7 && cqe.res == -EINVAL);
+}
+
+static void test_read_write(struct ring *r, int file) {
+    struct uring_cqe cqe;
+    const char msg[] = "hello io_uring";
+    char buf[64];
+
+    queue_sqe(r, OP_WRITE, file, 0, (void *)msg, sizeof(msg), 1);
+    check("write completes", submit_and_wait(r, &cqe) == 0);
+    check("write cqe", cqe.user_data == 1 && cqe.res == (int)sizeof(msg));
+
+    memset(buf, 0, sizeof(buf));
+    queue_sqe(r, OP_READ, file, 0, buf, sizeof(buf), 2);
+    check("read completes", submit_and_wait(r, &cqe) == 0);
+    check("read cqe", cqe.user_data == 2 && cqe.res == (int)sizeof(msg));
+    check("read data", memcmp(buf, msg, sizeof(msg)) == 0);
+
+    /* 只提交不等待，完成事件应当由内核直接写入CQ，而不是等到下一次io_uring_enter */
+    memset(buf, 0, sizeof(buf));
+    queue_sqe(r, OP_READ, file, 6, buf, 8, 3);
+    check("submit read without waiting", ring_enter(r, 1, 0, 0) == 1);
+    int got = -1;
+    for (int i = 0; i < 1000 && got != 0; i++) {
+        got = pop_cqe(r, &cqe);
+        if (got != 0) {
+            struct timespec ts = {0, 1000000};
+            nanosleep(&ts, NULL);
+        }
+    }
+    check("read cqe posted without io_uring_enter", got == 0);
+    check("polled read cqe", cqe.user_data == 3 && cqe.res == 8);
+    check("polled read data", memcmp(buf, "io_uring", 8) == 0);
+
+    /* 偏移量为-1时使用文件当前的读写位置 */
+    lseek(file, 0, SEEK_SET);
+    memset(buf, 0, sizeof(buf));
+    queue_sqe(r, OP_READ, file, (uint64_t)-1, buf, 5, 4);
+    check("read at current position", submit_and_wait(r, &cqe) == 0 && cqe.res == 5 &&
+                                          memcmp(buf, "hello", 5) == 0);
+    check("file position advanced", lseek(file, 0, SEEK_CUR) == 5);
+
+    queue_sqe(r, OP_READ, 12345, 0, buf, sizeof(buf), 5);
+    check("bad fd is EBADF", submit_and_wait(r, &cqe) == 0 && cqe.res == -EBADF);
+}
+
+static void test_fixed_files(struct ring *r, int file) {
+    struct uring_cqe cqe;
+    char buf[16];
+    int fds[2];
+
+    fds[0] = r->fd;
+    check("registering the ring itself is EBADF",
+          syscall(SYS_io_uring_register, r->fd, IORING_REGISTER_FILES_, fds, 1) == -1 &&
+              errno == EBADF);
+
+    fds[0] = -1;
+    fds[1] = file;
+    check("register files",
+          syscall(SYS_io_uring_register, r->fd, IORING_REGISTER_FILES_, fds, 2) == 0);
+
+    memset(buf, 0, sizeof(buf));
+    struct uring_sqe *sqe = queue_sqe(r, OP_READ, 1, 0, buf, 5, 6);
+    sqe->flags = IOSQE_FIXED_FILE_;
+    check("read fixed file", submit_and_wait(r, &cqe) == 0 && cqe.res == 5 &&
+                                 memcmp(buf, "hello", 5) == 0);
+
+    sqe = queue_sqe(r, OP_READ, 0, 0, buf, 5, 7);
+    sqe->flags = IOSQE_FIXED_FILE_;
+    check("empty fixed slot is EBADF", submit_and_wait(r, &cqe) == 0 && cqe.res == -EBADF);
+
+    check("unregister files",
+          syscall(SYS_io_uring_register, r->fd, IORING_UNREGISTER_FILES_, NULL, 0) == 0);
+}
+
+int main() {
+    struct ring r;
+    if (ring_init(&r) != 0) {
+        printf("FAIL: io_uring setup: %s\n", strerror(errno));
+        return 1;
+    }
+
+    int file = open(TEST_FILE, O_RDWR | O_CREAT | O_TRUNC, 0644);
+    check("open test file", file >= 0);
+    if (file < 0) {
+        return 1;
+    }
+
+    test_nop(&r);
+    test_read_write(&r, file);
+    test_fixed_files(&r, file);
+
+    close(file);
+    unlink(TEST_FILE);
+    munmap(r.sqes, r.p.sq_entries * sizeof(struct uring_sqe));
+    munmap(r.ring_ptr, r.ring_len);
+    close(r.fd);
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
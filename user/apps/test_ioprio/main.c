@@ -0,0 +1,180 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define IOPRIO_CLASS_SHIFT 13
+#define IOPRIO_PRIO_VALUE(class, data) (((class) << IOPRIO_CLASS_SHIFT) | (data))
+#define IOPRIO_PRIO_CLASS(ioprio) ((ioprio) >> IOPRIO_CLASS_SHIFT)
+
+#define IOPRIO_CLASS_NONE 0
+#define IOPRIO_CLASS_RT 1
+#define IOPRIO_CLASS_BE 2
+#define IOPRIO_CLASS_IDLE 3
+
+#define IOPRIO_WHO_PROCESS 1
+#define IOPRIO_WHO_PGRP 2
+#define IOPRIO_WHO_USER 3
+
+#define BLOCK_SIZE 4096
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static int ioprio_set(int which, int who, int ioprio) {
+    return syscall(SYS_ioprio_set, which, who, ioprio);
+}
+
+static int ioprio_get(int which, int who) {
+    return syscall(SYS_ioprio_get, which, who);
+}
+
+/* 写入一个块并等待落盘 */
+static int write_sync(int fd, const char *buf) {
+    if (pwrite(fd, buf, BLOCK_SIZE, 0) != BLOCK_SIZE) {
+        return -1;
+    }
+    return fsync(fd);
+}
+
+static void test_set_get(void) {
+    check("default ioprio is NONE", ioprio_get(IOPRIO_WHO_PROCESS, 0) == 0);
+
+    int be7 = IOPRIO_PRIO_VALUE(IOPRIO_CLASS_BE, 7);
+    check("set BE level 7", ioprio_set(IOPRIO_WHO_PROCESS, 0, be7) == 0);
+    check("get BE level 7", ioprio_get(IOPRIO_WHO_PROCESS, 0) == be7);
+    check("get by own pid", ioprio_get(IOPRIO_WHO_PROCESS, getpid()) == be7);
+
+    errno = 0;
+    check("BE level 8 is rejected",
+          ioprio_set(IOPRIO_WHO_PROCESS, 0, IOPRIO_PRIO_VALUE(IOPRIO_CLASS_BE, 8)) == -1 &&
+              errno == EINVAL);
+    errno = 0;
+    check("unknown class is rejected",
+          ioprio_set(IOPRIO_WHO_PROCESS, 0, IOPRIO_PRIO_VALUE(4, 0)) == -1 && errno == EINVAL);
+    errno = 0;
+    check("NONE with level is rejected",
+          ioprio_set(IOPRIO_WHO_PROCESS, 0, IOPRIO_PRIO_VALUE(IOPRIO_CLASS_NONE, 1)) == -1 &&
+              errno == EINVAL);
+    errno = 0;
+    check("unknown which is rejected", ioprio_get(42, 0) == -1 && errno == EINVAL);
+    errno = 0;
+    check("missing pid is ESRCH", ioprio_get(IOPRIO_WHO_PROCESS, 999999) == -1 && errno == ESRCH);
+
+    int idle = IOPRIO_PRIO_VALUE(IOPRIO_CLASS_IDLE, 0);
+    check("set IDLE", ioprio_set(IOPRIO_WHO_PROCESS, 0, idle) == 0);
+    check("get IDLE", IOPRIO_PRIO_CLASS(ioprio_get(IOPRIO_WHO_PROCESS, 0)) == IOPRIO_CLASS_IDLE);
+
+    int rt0 = IOPRIO_PRIO_VALUE(IOPRIO_CLASS_RT, 0);
+    if (geteuid() == 0) {
+        check("root can set RT", ioprio_set(IOPRIO_WHO_PROCESS, 0, rt0) == 0 &&
+                                     ioprio_get(IOPRIO_WHO_PROCESS, 0) == rt0);
+    }
+
+    check("reset to NONE", ioprio_set(IOPRIO_WHO_PROCESS, 0, 0) == 0 &&
+                               ioprio_get(IOPRIO_WHO_PROCESS, 0) == 0);
+}
+
+static void test_inherit_and_groups(void) {
+    int be2 = IOPRIO_PRIO_VALUE(IOPRIO_CLASS_BE, 2);
+    int be6 = IOPRIO_PRIO_VALUE(IOPRIO_CLASS_BE, 6);
+    ioprio_set(IOPRIO_WHO_PROCESS, 0, be6);
+
+    int pipefd[2];
+    pipe(pipefd);
+    pid_t child = fork();
+    if (child == 0) {
+        close(pipefd[1]);
+        char c;
+        /* 等待父进程检查完进程组 */
+        read(pipefd[0], &c, 1);
+        _exit(0);
+    }
+    close(pipefd[0]);
+
+    check("child inherits ioprio", ioprio_get(IOPRIO_WHO_PROCESS, child) == be6);
+    check("set child ioprio by pid", ioprio_set(IOPRIO_WHO_PROCESS, child, be2) == 0);
+    check("get child ioprio by pid", ioprio_get(IOPRIO_WHO_PROCESS, child) == be2);
+    check("process group reports highest ioprio", ioprio_get(IOPRIO_WHO_PGRP, 0) == be2);
+    check("user reports highest ioprio", ioprio_get(IOPRIO_WHO_USER, 0) != -1);
+
+    write(pipefd[1], "x", 1);
+    close(pipefd[1]);
+    waitpid(child, NULL, 0);
+
+    check("set process group", ioprio_set(IOPRIO_WHO_PGRP, 0, 0) == 0 &&
+                                   ioprio_get(IOPRIO_WHO_PROCESS, 0) == 0);
+}
+
+/* IDLE类的进程不停地写入时，其他进程的写入仍然能完成；其他进程停止之后，IDLE类的进程也能继续写入 */
+static void test_idle_io(const char *dir) {
+    char idle_path[256], be_path[256];
+    snprintf(idle_path, sizeof(idle_path), "%s/test_ioprio_idle.dat", dir);
+    snprintf(be_path, sizeof(be_path), "%s/test_ioprio_be.dat", dir);
+
+    volatile long *idle_done =
+        mmap(NULL, sizeof(long), PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    *idle_done = 0;
+
+    pid_t child = fork();
+    if (child == 0) {
+        ioprio_set(IOPRIO_WHO_PROCESS, 0, IOPRIO_PRIO_VALUE(IOPRIO_CLASS_IDLE, 0));
+        int fd = open(idle_path, O_CREAT | O_TRUNC | O_RDWR, 0644);
+        char buf[BLOCK_SIZE];
+        memset(buf, 'i', sizeof(buf));
+        while (fd >= 0 && write_sync(fd, buf) == 0) {
+            (*idle_done)++;
+        }
+        _exit(1);
+    }
+
+    int fd = open(be_path, O_CREAT | O_TRUNC | O_RDWR, 0644);
+    char buf[BLOCK_SIZE];
+    memset(buf, 'b', sizeof(buf));
+    int ok = fd >= 0;
+    for (int i = 0; ok && i < 20; i++) {
+        ok = write_sync(fd, buf) == 0;
+    }
+    check("BE writes complete while IDLE writer runs", ok);
+    if (fd >= 0) {
+        close(fd);
+    }
+
+    long before = *idle_done;
+    for (int i = 0; i < 50 && *idle_done == before; i++) {
+        usleep(100 * 1000);
+    }
+    check("IDLE writer makes progress once the disk is idle", *idle_done > before);
+
+    kill(child, SIGKILL);
+    waitpid(child, NULL, 0);
+    unlink(idle_path);
+    unlink(be_path);
+}
+
+int main(int argc, char **argv) {
+    const char *dir = argc > 1 ? argv[1] : ".";
+
+    test_set_get();
+    test_inherit_and_groups();
+    test_idle_io(dir);
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
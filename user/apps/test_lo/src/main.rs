@@ -1,7 +1,9 @@
-use std::net::UdpSocket;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
 use std::str;
+use std::thread;
 
-fn main() -> std::io::Result<()> {
+fn test_udp() -> std::io::Result<()> {
     let socket = UdpSocket::bind("127.0.0.1:34254")?;
     socket.connect("127.0.0.1:34254")?;
 
@@ -23,3 +25,54 @@ fn main() -> std::io::Result<()> {
 
     Ok(())
 }
+
+/// 127.0.0.0/8中的任意地址都应当属于本机，而不仅仅是127.0.0.1
+fn test_udp_any_loopback_addr() -> std::io::Result<()> {
+    let receiver = UdpSocket::bind("127.0.0.2:34255")?;
+    let sender = UdpSocket::bind("127.0.0.1:0")?;
+
+    let msg = "Hello, 127.0.0.2!";
+    sender.send_to(msg.as_bytes(), "127.0.0.2:34255")?;
+
+    let mut buf = [0; 1024];
+    let (amt, src) = receiver.recv_from(&mut buf)?;
+    let received_msg = str::from_utf8(&buf[..amt]).expect("Could not read buffer as UTF-8");
+    println!("Received from {}: {}", src, received_msg);
+
+    assert_eq!(msg, received_msg, "The message sent to 127.0.0.2 was lost!");
+    Ok(())
+}
+
+/// 在lo上完成TCP三次握手，并双向传输数据
+fn test_tcp() -> std::io::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:34256")?;
+    let server = thread::spawn(move || -> std::io::Result<()> {
+        let (mut stream, peer) = listener.accept()?;
+        println!("Accepted connection from {}", peer);
+        let mut buf = [0; 1024];
+        let amt = stream.read(&mut buf)?;
+        stream.write_all(&buf[..amt])?;
+        Ok(())
+    });
+
+    let mut stream = TcpStream::connect("127.0.0.1:34256")?;
+    let msg = "Hello, tcp over loopback!";
+    stream.write_all(msg.as_bytes())?;
+
+    let mut buf = [0; 1024];
+    let amt = stream.read(&mut buf)?;
+    let received_msg = str::from_utf8(&buf[..amt]).expect("Could not read buffer as UTF-8");
+    println!("Echoed: {}", received_msg);
+
+    server.join().unwrap()?;
+    assert_eq!(msg, received_msg, "The echoed message does not match!");
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    test_udp()?;
+    test_udp_any_loopback_addr()?;
+    test_tcp()?;
+    println!("All loopback tests passed");
+    Ok(())
+}
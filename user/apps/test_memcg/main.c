@@ -0,0 +1,171 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CGROUP_ROOT "/sys/fs/cgroup"
+#define TEST_CGROUP CGROUP_ROOT "/test_memcg"
+
+#define LIMIT (4 * 1024 * 1024)
+#define PAGE_SIZE 4096
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 写入接口文件，成功返回0，失败返回errno */
+static int write_file(const char *path, const char *buf) {
+    int fd = open(path, O_WRONLY);
+    if (fd < 0) {
+        return errno;
+    }
+    int ret = 0;
+    if (write(fd, buf, strlen(buf)) < 0) {
+        ret = errno;
+    }
+    close(fd);
+    return ret;
+}
+
+static int read_file(const char *path, char *buf, size_t size) {
+    int fd = open(path, O_RDONLY);
+    if (fd < 0) {
+        return -1;
+    }
+    ssize_t len = read(fd, buf, size - 1);
+    close(fd);
+    if (len < 0) {
+        return -1;
+    }
+    buf[len] = '\0';
+    return 0;
+}
+
+static long read_number(const char *path) {
+    char buf[64];
+    if (read_file(path, buf, sizeof(buf)) != 0) {
+        return -1;
+    }
+    return atol(buf);
+}
+
+/* 从memory.events中读取一项 */
+static long read_event(const char *key) {
+    char buf[256];
+    if (read_file(TEST_CGROUP "/memory.events", buf, sizeof(buf)) != 0) {
+        return -1;
+    }
+    char *p = buf;
+    size_t len = strlen(key);
+    while (p != NULL && *p != '\0') {
+        if (strncmp(p, key, len) == 0 && p[len] == ' ') {
+            return atol(p + len + 1);
+        }
+        p = strchr(p, '\n');
+        if (p != NULL) {
+            p++;
+        }
+    }
+    return -1;
+}
+
+/* 在子进程中加入测试cgroup并访问size字节的匿名内存，返回wait得到的状态 */
+static int run_child(size_t size) {
+    pid_t pid = fork();
+    if (pid == 0) {
+        char pidbuf[32];
+        snprintf(pidbuf, sizeof(pidbuf), "%d", getpid());
+        if (write_file(TEST_CGROUP "/cgroup.procs", pidbuf) != 0) {
+            _exit(2);
+        }
+        char *p = mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+        if (p == MAP_FAILED) {
+            _exit(3);
+        }
+        for (size_t i = 0; i < size; i += PAGE_SIZE) {
+            p[i] = 1;
+        }
+        _exit(0);
+    }
+    int status = 0;
+    waitpid(pid, &status, 0);
+    return status;
+}
+
+int main() {
+    char buf[256];
+
+    if (access(CGROUP_ROOT "/cgroup.controllers", F_OK) != 0) {
+        mkdir(CGROUP_ROOT, 0755);
+        if (mount("none", CGROUP_ROOT, "cgroup2", 0, NULL) != 0) {
+            printf("failed to mount cgroup2: %s\n", strerror(errno));
+            return 1;
+        }
+    }
+
+    check("cgroup.controllers lists memory",
+          read_file(CGROUP_ROOT "/cgroup.controllers", buf, sizeof(buf)) == 0 &&
+              strstr(buf, "memory") != NULL);
+
+    rmdir(TEST_CGROUP);
+    check("mkdir creates a cgroup", mkdir(TEST_CGROUP, 0755) == 0);
+    check("memory.max absent before enabling memory",
+          access(TEST_CGROUP "/memory.max", F_OK) != 0);
+    check("enable memory in root subtree_control",
+          write_file(CGROUP_ROOT "/cgroup.subtree_control", "+memory") == 0);
+
+    check("memory.max defaults to max",
+          read_file(TEST_CGROUP "/memory.max", buf, sizeof(buf)) == 0 && strcmp(buf, "max\n") == 0);
+    check("memory.current of an empty cgroup is 0", read_number(TEST_CGROUP "/memory.current") == 0);
+    check("memory.max rejects garbage", write_file(TEST_CGROUP "/memory.max", "abc") == EINVAL);
+    check("memory.max is rounded up to pages",
+          write_file(TEST_CGROUP "/memory.max", "1") == 0 &&
+              read_number(TEST_CGROUP "/memory.max") == PAGE_SIZE);
+    check("memory.current is read-only",
+          write_file(TEST_CGROUP "/memory.current", "0") == EACCES);
+
+    snprintf(buf, sizeof(buf), "%d", LIMIT);
+    check("memory.max accepts 4M", write_file(TEST_CGROUP "/memory.max", buf) == 0);
+    check("memory.max reads back 4M", read_number(TEST_CGROUP "/memory.max") == LIMIT);
+
+    int status = run_child(LIMIT / 4);
+    check("child within the limit exits normally", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    check("memory.events oom_kill is 0", read_event("oom_kill") == 0);
+
+    status = run_child(LIMIT * 8);
+    check("child over the limit is killed by SIGKILL",
+          WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    check("memory.events max is positive", read_event("max") > 0);
+    check("memory.events oom_kill is 1", read_event("oom_kill") == 1);
+
+    long current = read_number(TEST_CGROUP "/memory.current");
+    check("memory.current is within the limit after the child exits",
+          current >= 0 && current <= LIMIT);
+
+    check("memory.max accepts max", write_file(TEST_CGROUP "/memory.max", "max") == 0);
+    status = run_child(LIMIT * 2);
+    check("child exits normally without a limit", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    check("rmdir empty cgroup", rmdir(TEST_CGROUP) == 0);
+    check("disable memory in root subtree_control",
+          write_file(CGROUP_ROOT "/cgroup.subtree_control", "-memory") == 0);
+
+    if (failures) {
+        printf("%d tests failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
@@ -0,0 +1,180 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <mqueue.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/epoll.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define QUEUE_NAME "/test_mqueue"
+
+static int failures = 0;
+static volatile sig_atomic_t notified = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 非特权进程只能使用默认上限以内的属性，创建的队列占用的字节数也有上限 */
+static int test_unprivileged_limits(void) {
+    if (setgid(65534) < 0 || setuid(65534) < 0) {
+        return 1;
+    }
+    struct mq_attr big_maxmsg = {.mq_maxmsg = 11, .mq_msgsize = 64};
+    struct mq_attr big_msgsize = {.mq_maxmsg = 4, .mq_msgsize = 8193};
+    if (mq_open("/test_mqueue_limit", O_RDWR | O_CREAT, 0600, &big_maxmsg) != (mqd_t)-1 || errno != EINVAL) {
+        return 2;
+    }
+    if (mq_open("/test_mqueue_limit", O_RDWR | O_CREAT, 0600, &big_msgsize) != (mqd_t)-1 || errno != EINVAL) {
+        return 3;
+    }
+
+    char name[32];
+    int created = 0;
+    int ret = 4;
+    for (; created < 32; created++) {
+        snprintf(name, sizeof(name), "/test_mqueue_limit%d", created);
+        if (mq_open(name, O_RDWR | O_CREAT, 0600, NULL) == (mqd_t)-1) {
+            ret = errno == EMFILE ? 0 : 5;
+            break;
+        }
+    }
+    for (int i = 0; i < created; i++) {
+        snprintf(name, sizeof(name), "/test_mqueue_limit%d", i);
+        mq_unlink(name);
+    }
+    return ret;
+}
+
+static void notify_handler(int sig) {
+    (void)sig;
+    notified = 1;
+}
+
+/* 用epoll等待fd就绪，返回就绪的fd数量 */
+static int wait_events(int fd, unsigned int events, int timeout_ms) {
+    int epfd = epoll_create1(0);
+    struct epoll_event ev = {.events = events, .data.fd = fd};
+    epoll_ctl(epfd, EPOLL_CTL_ADD, fd, &ev);
+    int n = epoll_wait(epfd, &ev, 1, timeout_ms);
+    close(epfd);
+    return n;
+}
+
+static int queue_listed(void) {
+    DIR *dir = opendir("/dev/mqueue");
+    if (dir == NULL) {
+        return 0;
+    }
+    int found = 0;
+    struct dirent *ent;
+    while ((ent = readdir(dir)) != NULL) {
+        if (strcmp(ent->d_name, QUEUE_NAME + 1) == 0) {
+            found = 1;
+        }
+    }
+    closedir(dir);
+    return found;
+}
+
+int main() {
+    struct mq_attr attr = {.mq_maxmsg = 4, .mq_msgsize = 64};
+    mq_unlink(QUEUE_NAME);
+    mqd_t mq = mq_open(QUEUE_NAME, O_RDWR | O_CREAT | O_EXCL | O_NONBLOCK, 0600, &attr);
+    check("mq_open(O_CREAT | O_EXCL)", mq != (mqd_t)-1);
+    check("mq_open(O_EXCL) on an existing queue returns EEXIST",
+          mq_open(QUEUE_NAME, O_RDWR | O_CREAT | O_EXCL, 0600, &attr) == (mqd_t)-1 && errno == EEXIST);
+    check("queue is listed in /dev/mqueue", queue_listed());
+
+    struct mq_attr cur;
+    mq_getattr(mq, &cur);
+    check("mq_getattr reports the attributes",
+          cur.mq_maxmsg == 4 && cur.mq_msgsize == 64 && cur.mq_curmsgs == 0 && (cur.mq_flags & O_NONBLOCK));
+
+    char buf[64];
+    unsigned int prio = 0;
+    check("nonblocking receive on an empty queue returns EAGAIN",
+          mq_receive(mq, buf, sizeof(buf), &prio) < 0 && errno == EAGAIN);
+    check("empty queue is writable but not readable",
+          wait_events(mq, EPOLLOUT, 0) == 1 && wait_events(mq, EPOLLIN, 0) == 0);
+
+    /* 按优先级接收，相同优先级先进先出 */
+    mq_send(mq, "low", 4, 1);
+    mq_send(mq, "high", 5, 10);
+    mq_send(mq, "mid1", 5, 5);
+    mq_send(mq, "mid2", 5, 5);
+    check("queue is readable after send", wait_events(mq, EPOLLIN, 0) == 1);
+    check("nonblocking send on a full queue returns EAGAIN",
+          mq_send(mq, "x", 2, 0) < 0 && errno == EAGAIN);
+    check("send larger than mq_msgsize returns EMSGSIZE",
+          mq_send(mq, buf, sizeof(buf) + 1, 0) < 0 && errno == EMSGSIZE);
+
+    const char *expected[] = {"high", "mid1", "mid2", "low"};
+    unsigned int expected_prio[] = {10, 5, 5, 1};
+    int ok = 1;
+    for (int i = 0; i < 4; i++) {
+        ssize_t n = mq_receive(mq, buf, sizeof(buf), &prio);
+        ok &= n == (ssize_t)strlen(expected[i]) + 1 && strcmp(buf, expected[i]) == 0 && prio == expected_prio[i];
+    }
+    check("messages are received in priority order", ok);
+    check("receive with a small buffer returns EMSGSIZE",
+          mq_receive(mq, buf, sizeof(buf) - 1, &prio) < 0 && errno == EMSGSIZE);
+
+    /* 切换为阻塞模式，测试超时 */
+    struct mq_attr newattr = {.mq_flags = 0};
+    mq_setattr(mq, &newattr, NULL);
+    mq_getattr(mq, &cur);
+    check("mq_setattr clears O_NONBLOCK", !(cur.mq_flags & O_NONBLOCK));
+    struct timespec ts;
+    clock_gettime(CLOCK_REALTIME, &ts);
+    ts.tv_nsec += 100 * 1000 * 1000;
+    if (ts.tv_nsec >= 1000000000) {
+        ts.tv_sec++;
+        ts.tv_nsec -= 1000000000;
+    }
+    check("mq_timedreceive on an empty queue returns ETIMEDOUT",
+          mq_timedreceive(mq, buf, sizeof(buf), &prio, &ts) < 0 && errno == ETIMEDOUT);
+    ts.tv_nsec = 1000000000;
+    check("mq_timedreceive with an invalid timeout returns EINVAL",
+          mq_timedreceive(mq, buf, sizeof(buf), &prio, &ts) < 0 && errno == EINVAL);
+
+    /* 空队列收到消息时通知 */
+    signal(SIGUSR1, notify_handler);
+    struct sigevent sev = {.sigev_notify = SIGEV_SIGNAL, .sigev_signo = SIGUSR1};
+    check("mq_notify", mq_notify(mq, &sev) == 0);
+    check("second mq_notify returns EBUSY", mq_notify(mq, &sev) == 0 || errno == EBUSY);
+    mq_send(mq, "ping", 5, 0);
+    usleep(10 * 1000);
+    check("mq_notify delivers the signal", notified);
+    check("blocking receive returns the pending message",
+          mq_receive(mq, buf, sizeof(buf), &prio) == 5 && strcmp(buf, "ping") == 0);
+
+    mq_close(mq);
+    check("mq_unlink", mq_unlink(QUEUE_NAME) == 0);
+    check("queue is removed from /dev/mqueue", !queue_listed());
+    check("mq_open without O_CREAT after unlink returns ENOENT",
+          mq_open(QUEUE_NAME, O_RDWR) == (mqd_t)-1 && errno == ENOENT);
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        _exit(test_unprivileged_limits());
+    }
+    int status = 0;
+    waitpid(pid, &status, 0);
+    check("unprivileged mq_open is limited", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
@@ -0,0 +1,88 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define TEST_FILE "/tmp/test_msync.dat"
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 不经过映射，直接从文件中读取内容 */
+static int file_contains(int fd, off_t offset, const char *expected) {
+    char buf[64];
+    size_t len = strlen(expected);
+    if (pread(fd, buf, len, offset) != (ssize_t)len) {
+        return 0;
+    }
+    return memcmp(buf, expected, len) == 0;
+}
+
+int main() {
+    long page_size = sysconf(_SC_PAGESIZE);
+    size_t len = page_size * 4;
+
+    int fd = open(TEST_FILE, O_RDWR | O_CREAT | O_TRUNC, 0644);
+    if (fd < 0 || ftruncate(fd, len) != 0) {
+        printf("failed to create %s\n", TEST_FILE);
+        return 1;
+    }
+
+    char *map = mmap(NULL, len, PROT_READ | PROT_WRITE, MAP_SHARED, fd, 0);
+    check("mmap shared file", map != MAP_FAILED);
+    if (map == MAP_FAILED) {
+        return 1;
+    }
+
+    /* 先读取使页面以只读方式映射，之后的写入应被记录为脏页 */
+    volatile char c = map[page_size];
+    (void)c;
+    memcpy(map + page_size, "hello msync", 11);
+    check("MS_SYNC writes back a page dirtied after a read fault",
+          msync(map, len, MS_SYNC) == 0 && file_contains(fd, page_size, "hello msync"));
+
+    /* 写回之后页面重新被写保护，再次写入同样需要写回 */
+    memcpy(map + page_size, "second write", 12);
+    check("MS_SYNC writes back a page written again after writeback",
+          msync(map + page_size, page_size, MS_SYNC) == 0 && file_contains(fd, page_size, "second write"));
+
+    memcpy(map + 3 * page_size, "async", 5);
+    check("MS_ASYNC succeeds", msync(map, len, MS_ASYNC) == 0);
+    check("flags 0 is accepted", msync(map, len, 0) == 0);
+    check("MS_SYNC after MS_ASYNC", msync(map, len, MS_SYNC) == 0 && file_contains(fd, 3 * page_size, "async"));
+
+    /* mprotect之后仍然能够跟踪写入 */
+    check("mprotect read-only", mprotect(map, len, PROT_READ) == 0);
+    check("mprotect read-write", mprotect(map, len, PROT_READ | PROT_WRITE) == 0);
+    memcpy(map, "after mprotect", 14);
+    check("MS_SYNC after mprotect", msync(map, page_size, MS_SYNC) == 0 && file_contains(fd, 0, "after mprotect"));
+
+    errno = 0;
+    check("unaligned address returns EINVAL", msync(map + 1, page_size, MS_SYNC) == -1 && errno == EINVAL);
+    errno = 0;
+    check("MS_SYNC|MS_ASYNC returns EINVAL", msync(map, len, MS_SYNC | MS_ASYNC) == -1 && errno == EINVAL);
+    errno = 0;
+    check("unknown flags return EINVAL", msync(map, len, 0x100) == -1 && errno == EINVAL);
+
+    /* 范围内包含未映射的区域 */
+    munmap(map + 2 * page_size, page_size);
+    errno = 0;
+    check("range with a hole returns ENOMEM", msync(map, len, MS_SYNC) == -1 && errno == ENOMEM);
+
+    munmap(map, len);
+    close(fd);
+    unlink(TEST_FILE);
+
+    if (failures == 0) {
+        printf("All tests passed\n");
+    }
+    return failures == 0 ? 0 : 1;
+}
@@ -0,0 +1,163 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/epoll.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#ifndef P_PIDFD
+#define P_PIDFD 3
+#endif
+
+#ifndef CLONE_PIDFD
+#define CLONE_PIDFD 0x1000
+#endif
+
+#ifndef PIDFD_NONBLOCK
+#define PIDFD_NONBLOCK O_NONBLOCK
+#endif
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static int pidfd_open(pid_t pid, unsigned int flags) {
+    return syscall(SYS_pidfd_open, pid, flags);
+}
+
+static int pidfd_send_signal(int pidfd, int sig, siginfo_t *info, unsigned int flags) {
+    return syscall(SYS_pidfd_send_signal, pidfd, sig, info, flags);
+}
+
+/* 用epoll等待fd可读，返回就绪的fd数量 */
+static int wait_readable(int fd, int timeout_ms) {
+    int epfd = epoll_create1(0);
+    struct epoll_event ev = {.events = EPOLLIN, .data.fd = fd};
+    epoll_ctl(epfd, EPOLL_CTL_ADD, fd, &ev);
+    int n = epoll_wait(epfd, &ev, 1, timeout_ms);
+    close(epfd);
+    return n;
+}
+
+static void test_pidfd_open_wait(void) {
+    pid_t pid = fork();
+    if (pid == 0) {
+        usleep(200 * 1000);
+        _exit(7);
+    }
+
+    int pidfd = pidfd_open(pid, 0);
+    check("pidfd_open on a child succeeds", pidfd >= 0);
+    check("pidfd is not readable while the child is running", wait_readable(pidfd, 0) == 0);
+
+    int nbfd = pidfd_open(pid, PIDFD_NONBLOCK);
+    siginfo_t info;
+    check("waitid on a nonblocking pidfd returns EAGAIN while the child is running",
+          waitid(P_PIDFD, nbfd, &info, WEXITED) < 0 && errno == EAGAIN);
+    close(nbfd);
+
+    check("pidfd becomes readable when the child exits", wait_readable(pidfd, 2000) == 1);
+
+    memset(&info, 0, sizeof(info));
+    int r = waitid(P_PIDFD, pidfd, &info, WEXITED | WNOWAIT);
+    check("waitid(P_PIDFD, WNOWAIT) reports the child", r == 0 && info.si_pid == pid);
+
+    memset(&info, 0, sizeof(info));
+    r = waitid(P_PIDFD, pidfd, &info, WEXITED);
+    check("waitid(P_PIDFD) reaps the child",
+          r == 0 && info.si_signo == SIGCHLD && info.si_pid == pid &&
+              info.si_code == CLD_EXITED && info.si_status == 7);
+
+    check("pidfd_send_signal to a reaped process returns ESRCH",
+          pidfd_send_signal(pidfd, SIGTERM, NULL, 0) < 0 && errno == ESRCH);
+    check("waitid on a reaped process returns ECHILD",
+          waitid(P_PIDFD, pidfd, &info, WEXITED) < 0 && errno == ECHILD);
+    close(pidfd);
+}
+
+static void test_wnowait_blocks(void) {
+    pid_t pid = fork();
+    if (pid == 0) {
+        usleep(200 * 1000);
+        _exit(3);
+    }
+
+    int pidfd = pidfd_open(pid, 0);
+    siginfo_t info;
+    memset(&info, 0, sizeof(info));
+    int r = waitid(P_PIDFD, pidfd, &info, WEXITED | WNOWAIT);
+    check("waitid(WNOWAIT) blocks until the running child exits",
+          r == 0 && info.si_pid == pid && info.si_code == CLD_EXITED && info.si_status == 3);
+
+    memset(&info, 0, sizeof(info));
+    r = waitid(P_PIDFD, pidfd, &info, WEXITED);
+    check("child waited with WNOWAIT can still be reaped", r == 0 && info.si_pid == pid);
+    close(pidfd);
+}
+
+static void test_clone_pidfd(void) {
+    int pidfd = -1;
+    /* 不指定新栈的clone与fork相同 */
+    long pid = syscall(SYS_clone, CLONE_PIDFD | SIGCHLD, 0, &pidfd, 0, 0);
+    if (pid == 0) {
+        for (;;) {
+            pause();
+        }
+    }
+    check("clone(CLONE_PIDFD) returns a pidfd", pid > 0 && pidfd >= 0);
+    check("pidfd from clone is close-on-exec", (fcntl(pidfd, F_GETFD) & FD_CLOEXEC) != 0);
+
+    check("pidfd_send_signal with signal 0 checks the process",
+          pidfd_send_signal(pidfd, 0, NULL, 0) == 0);
+    check("pidfd_send_signal with flags returns EINVAL",
+          pidfd_send_signal(pidfd, SIGKILL, NULL, 0x100) < 0 && errno == EINVAL);
+    check("pidfd_send_signal SIGKILL succeeds", pidfd_send_signal(pidfd, SIGKILL, NULL, 0) == 0);
+
+    siginfo_t info;
+    memset(&info, 0, sizeof(info));
+    int r = waitid(P_PIDFD, pidfd, &info, WEXITED);
+    check("waitid(P_PIDFD) reaps the killed child",
+          r == 0 && info.si_pid == pid && info.si_status == SIGKILL);
+    close(pidfd);
+}
+
+static void test_errors(void) {
+    int pidfd = pidfd_open(getpid(), 0);
+    check("pidfd_open on self succeeds", pidfd >= 0);
+    check("pidfd_open with unknown flags returns EINVAL",
+          pidfd_open(getpid(), 1) < 0 && errno == EINVAL);
+    check("pidfd_open on an invalid pid returns EINVAL", pidfd_open(-1, 0) < 0 && errno == EINVAL);
+    check("pidfd_send_signal on a non-pidfd returns EBADF or EINVAL",
+          pidfd_send_signal(0, 0, NULL, 0) < 0 && (errno == EBADF || errno == EINVAL));
+
+    siginfo_t info;
+    check("waitid(P_ALL, WNOHANG) without children returns ECHILD",
+          waitid(P_ALL, 0, &info, WEXITED | WNOHANG) < 0 && errno == ECHILD);
+    check("waitid without WEXITED, WSTOPPED or WCONTINUED returns EINVAL",
+          waitid(P_PIDFD, pidfd, &info, WNOHANG) < 0 && errno == EINVAL);
+    close(pidfd);
+}
+
+int main() {
+    test_pidfd_open_wait();
+    test_wnowait_blocks();
+    test_clone_pidfd();
+    test_errors();
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
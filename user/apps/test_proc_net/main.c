@@ -0,0 +1,126 @@
+#define _GNU_SOURCE
+#include <arpa/inet.h>
+#include <netinet/in.h>
+#include <netinet/tcp.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/un.h>
+#include <unistd.h>
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 从/proc/self/fd/<fd>的链接中取出socket的inode号 */
+static unsigned long socket_inode(int fd) {
+    char path[64], link[64];
+    snprintf(path, sizeof(path), "/proc/self/fd/%d", fd);
+    ssize_t len = readlink(path, link, sizeof(link) - 1);
+    if (len < 0) {
+        perror("readlink");
+        return 0;
+    }
+    link[len] = '\0';
+    unsigned long ino = 0;
+    if (sscanf(link, "socket:[%lu]", &ino) != 1) {
+        printf("unexpected fd link: %s\n", link);
+    }
+    return ino;
+}
+
+/* 在/proc/net下的列表中查找包含inode号和指定内容的行 */
+static int table_has(const char *table, unsigned long ino, const char *needle) {
+    FILE *f = fopen(table, "r");
+    if (f == NULL) {
+        perror(table);
+        return 0;
+    }
+    char line[256], ino_str[32];
+    snprintf(ino_str, sizeof(ino_str), " %lu", ino);
+    int found = 0;
+    while (fgets(line, sizeof(line), f) != NULL) {
+        if (strstr(line, ino_str) != NULL && strstr(line, needle) != NULL) {
+            printf("  %s", line);
+            found = 1;
+        }
+    }
+    fclose(f);
+    return found;
+}
+
+int main() {
+    struct sockaddr_in addr = {
+        .sin_family = AF_INET,
+        .sin_port = htons(12345),
+        .sin_addr.s_addr = htonl(INADDR_LOOPBACK),
+    };
+
+    int listener = socket(AF_INET, SOCK_STREAM, 0);
+    if (listener < 0 || bind(listener, (struct sockaddr *)&addr, sizeof(addr)) < 0 ||
+        listen(listener, 4) < 0) {
+        perror("tcp listen");
+        return 1;
+    }
+    unsigned long ino = socket_inode(listener);
+    check("listening socket has an inode number", ino != 0);
+    /* 0100007F:3039 是 127.0.0.1:12345，0A 是 LISTEN */
+    check("listening socket in /proc/net/tcp", table_has("/proc/net/tcp", ino, "0100007F:3039 00000000:0000 0A"));
+
+    int client = socket(AF_INET, SOCK_STREAM, 0);
+    if (client < 0 || connect(client, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        perror("tcp connect");
+        return 1;
+    }
+    int conn = accept(listener, NULL, NULL);
+    check("accept", conn >= 0);
+
+    struct tcp_info info;
+    socklen_t len = sizeof(info);
+    memset(&info, 0, sizeof(info));
+    check("getsockopt(TCP_INFO)", getsockopt(client, IPPROTO_TCP, TCP_INFO, &info, &len) == 0);
+    check("TCP_INFO state is ESTABLISHED", info.tcpi_state == TCP_ESTABLISHED);
+    printf("  rtt=%u cwnd=%u\n", info.tcpi_rtt, info.tcpi_snd_cwnd);
+    check("connected socket in /proc/net/tcp",
+          table_has("/proc/net/tcp", socket_inode(client), "0100007F:3039 01"));
+
+    int udp = socket(AF_INET, SOCK_DGRAM, 0);
+    addr.sin_port = htons(12346);
+    if (udp < 0 || bind(udp, (struct sockaddr *)&addr, sizeof(addr)) < 0) {
+        perror("udp bind");
+        return 1;
+    }
+    /* 未连接的UDP socket状态为07 */
+    check("udp socket in /proc/net/udp", table_has("/proc/net/udp", socket_inode(udp), "0100007F:303A 00000000:0000 07"));
+
+    int unix_fd = socket(AF_UNIX, SOCK_DGRAM, 0);
+    struct sockaddr_un un = {.sun_family = AF_UNIX};
+    strcpy(un.sun_path, "/tmp/test_proc_net.sock");
+    unlink(un.sun_path);
+    if (unix_fd < 0 || bind(unix_fd, (struct sockaddr *)&un, sizeof(un)) < 0) {
+        perror("unix bind");
+        return 1;
+    }
+    check("unix socket in /proc/net/unix",
+          table_has("/proc/net/unix", socket_inode(unix_fd), "/tmp/test_proc_net.sock"));
+
+    close(unix_fd);
+    unlink(un.sun_path);
+    close(udp);
+    close(conn);
+    close(client);
+    close(listener);
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
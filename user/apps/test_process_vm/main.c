@@ -0,0 +1,94 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/uio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static char shared_buf[64] = "hello from the child";
+
+int main() {
+    /* 子进程从未访问过的匿名页，读取时需要为子进程处理缺页 */
+    char *untouched = mmap(NULL, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    int to_child[2], to_parent[2];
+    pipe(to_child);
+    pipe(to_parent);
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        char c;
+        strcpy(shared_buf, "hello from the child");
+        write(to_parent[1], "r", 1);
+        read(to_child[0], &c, 1);
+        /* 父进程写入之后，检查自己的内存 */
+        int ok = strcmp(shared_buf, "written by the parent") == 0;
+        _exit(ok ? 0 : 1);
+    }
+
+    char c;
+    read(to_parent[0], &c, 1);
+
+    char local[64] = {0};
+    struct iovec liov = {.iov_base = local, .iov_len = sizeof(local)};
+    struct iovec riov = {.iov_base = shared_buf, .iov_len = sizeof(shared_buf)};
+    ssize_t n = process_vm_readv(pid, &liov, 1, &riov, 1, 0);
+    check("process_vm_readv reads the child's memory",
+          n == sizeof(shared_buf) && strcmp(local, "hello from the child") == 0);
+
+    /* 分散到两个本地缓冲区 */
+    char a[6] = {0}, b[16] = {0};
+    struct iovec lsplit[2] = {{.iov_base = a, .iov_len = 5}, {.iov_base = b, .iov_len = 15}};
+    n = process_vm_readv(pid, lsplit, 2, &riov, 1, 0);
+    check("process_vm_readv scatters into several local iovecs",
+          n == 20 && strcmp(a, "hello") == 0 && strcmp(b, " from the child") == 0);
+
+    memset(local, 0x55, sizeof(local));
+    struct iovec runtouched = {.iov_base = untouched, .iov_len = 16};
+    n = process_vm_readv(pid, &liov, 1, &runtouched, 1, 0);
+    check("process_vm_readv reads a page the child never touched", n == 16 && local[0] == 0);
+
+    struct iovec rparts[2] = {{.iov_base = shared_buf, .iov_len = 8}, {.iov_base = (void *)8, .iov_len = 8}};
+    n = process_vm_readv(pid, &liov, 1, rparts, 2, 0);
+    check("process_vm_readv stops at an unmapped remote iovec", n == 8);
+
+    struct iovec rbad = {.iov_base = (void *)8, .iov_len = 8};
+    check("process_vm_readv on an unmapped address returns EFAULT",
+          process_vm_readv(pid, &liov, 1, &rbad, 1, 0) < 0 && errno == EFAULT);
+    check("process_vm_readv with flags returns EINVAL",
+          process_vm_readv(pid, &liov, 1, &riov, 1, 1) < 0 && errno == EINVAL);
+
+    char msg[] = "written by the parent";
+    struct iovec lw = {.iov_base = msg, .iov_len = sizeof(msg)};
+    struct iovec rw = {.iov_base = shared_buf, .iov_len = sizeof(msg)};
+    n = process_vm_writev(pid, &lw, 1, &rw, 1, 0);
+    check("process_vm_writev writes the child's memory", n == sizeof(msg));
+    check("process_vm_writev does not change the parent's copy of a COW page",
+          strcmp(shared_buf, "hello from the child") == 0);
+
+    write(to_child[1], "w", 1);
+    int status;
+    waitpid(pid, &status, 0);
+    check("the child sees the data written by process_vm_writev",
+          WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    check("process_vm_readv on a reaped process returns ESRCH",
+          process_vm_readv(pid, &liov, 1, &riov, 1, 0) < 0 && errno == ESRCH);
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
@@ -0,0 +1,88 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/statvfs.h>
+#include <unistd.h>
+
+#define MNT "/tmp/test_remount"
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static int is_readonly(void) {
+    struct statvfs st;
+    if (statvfs(MNT, &st) != 0) {
+        return -1;
+    }
+    return (st.f_flag & ST_RDONLY) != 0;
+}
+
+int main() {
+    mkdir("/tmp", 0755);
+    mkdir(MNT, 0755);
+    if (mount("tmpfs", MNT, "tmpfs", 0, NULL) != 0) {
+        perror("mount");
+        return 1;
+    }
+
+    int fd = open(MNT "/file", O_CREAT | O_RDWR, 0644);
+    check("create a file on a read-write mount", fd >= 0);
+    check("write on a read-write mount", write(fd, "hello", 5) == 5);
+    close(fd);
+    check("statfs does not report ST_RDONLY", is_readonly() == 0);
+
+    check("remount read-only",
+          mount(NULL, MNT, NULL, MS_REMOUNT | MS_RDONLY, NULL) == 0);
+    check("statfs reports ST_RDONLY", is_readonly() == 1);
+
+    errno = 0;
+    check("creating a file returns EROFS",
+          open(MNT "/new", O_CREAT | O_WRONLY, 0644) < 0 && errno == EROFS);
+    errno = 0;
+    check("opening a file for writing returns EROFS",
+          open(MNT "/file", O_WRONLY) < 0 && errno == EROFS);
+    errno = 0;
+    check("mkdir returns EROFS", mkdir(MNT "/dir", 0755) < 0 && errno == EROFS);
+    errno = 0;
+    check("unlink returns EROFS", unlink(MNT "/file") < 0 && errno == EROFS);
+    errno = 0;
+    check("rename returns EROFS",
+          rename(MNT "/file", MNT "/file2") < 0 && errno == EROFS);
+    errno = 0;
+    check("chmod returns EROFS", chmod(MNT "/file", 0600) < 0 && errno == EROFS);
+
+    char buf[8] = {0};
+    fd = open(MNT "/file", O_RDONLY);
+    check("reading still works on a read-only mount",
+          fd >= 0 && read(fd, buf, sizeof(buf)) == 5 && memcmp(buf, "hello", 5) == 0);
+    close(fd);
+
+    errno = 0;
+    check("remount of a path that is not a mount root returns EINVAL",
+          mount(NULL, MNT "/file", NULL, MS_REMOUNT, NULL) < 0 && errno == EINVAL);
+
+    check("remount read-write", mount(NULL, MNT, NULL, MS_REMOUNT, NULL) == 0);
+    check("statfs no longer reports ST_RDONLY", is_readonly() == 0);
+    fd = open(MNT "/file", O_WRONLY | O_APPEND);
+    check("write after remounting read-write", fd >= 0 && write(fd, "!", 1) == 1);
+    close(fd);
+    check("unlink after remounting read-write", unlink(MNT "/file") == 0);
+
+    umount(MNT);
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
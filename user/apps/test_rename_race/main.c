@@ -0,0 +1,92 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define DIR_PATH "/tmp/test_rename_race"
+#define TARGET DIR_PATH "/sub/target"
+#define ITERATIONS 2000
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 反复创建临时文件并用rename原子地替换target */
+static void replace_loop(const char *tmp) {
+    for (int i = 0; i < ITERATIONS; i++) {
+        int fd = open(tmp, O_CREAT | O_WRONLY | O_TRUNC, 0644);
+        if (fd < 0) {
+            _exit(1);
+        }
+        close(fd);
+        if (rename(tmp, TARGET) != 0) {
+            _exit(2);
+        }
+    }
+    _exit(0);
+}
+
+/* 反复创建并删除与target同目录的其他文件 */
+static void unlink_loop(const char *name) {
+    for (int i = 0; i < ITERATIONS; i++) {
+        int fd = open(name, O_CREAT | O_WRONLY, 0644);
+        if (fd >= 0) {
+            close(fd);
+        }
+        unlink(name);
+    }
+    _exit(0);
+}
+
+int main() {
+    mkdir(DIR_PATH, 0755);
+    mkdir(DIR_PATH "/sub", 0755);
+    int fd = open(TARGET, O_CREAT | O_WRONLY, 0644);
+    check("create target", fd >= 0);
+    close(fd);
+
+    pid_t renamer = fork();
+    if (renamer == 0) {
+        replace_loop(DIR_PATH "/sub/tmp");
+    }
+    pid_t unlinker = fork();
+    if (unlinker == 0) {
+        unlink_loop(DIR_PATH "/sub/other");
+    }
+
+    /* rename是原子的，任何时刻target都应当存在 */
+    int lookups = 0, missing = 0, done = 0;
+    while (done < 2) {
+        struct stat st;
+        if (stat(TARGET, &st) != 0 && errno == ENOENT) {
+            missing++;
+        }
+        lookups++;
+        int status;
+        while (waitpid(-1, &status, WNOHANG) > 0) {
+            done++;
+        }
+    }
+    printf("%d lookups, %d returned ENOENT\n", lookups, missing);
+    check("target never disappears while being replaced by rename", missing == 0);
+
+    unlink(TARGET);
+    rmdir(DIR_PATH "/sub");
+    rmdir(DIR_PATH);
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
@@ -0,0 +1,145 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static long sys_getaffinity(pid_t pid, size_t len, void *mask) {
+    return syscall(SYS_sched_getaffinity, pid, len, mask);
+}
+
+static long sys_setaffinity(pid_t pid, size_t len, const void *mask) {
+    return syscall(SYS_sched_setaffinity, pid, len, mask);
+}
+
+/* 在当前cpu上空转一会儿，给内核迁移的机会 */
+static void spin(void) {
+    for (volatile int i = 0; i < 10000000; i++) {
+    }
+}
+
+static void test_get(cpu_set_t *all) {
+    CPU_ZERO(all);
+    long ret = sys_getaffinity(0, sizeof(*all), all);
+    check("getaffinity returns mask size", ret > 0 && ret <= (long)sizeof(*all) && ret % 8 == 0);
+    check("cpu 0 is allowed", CPU_ISSET(0, all));
+    check("at least one cpu", CPU_COUNT(all) >= 1);
+
+    cpu_set_t by_pid;
+    CPU_ZERO(&by_pid);
+    sys_getaffinity(getpid(), sizeof(by_pid), &by_pid);
+    check("get by own pid", CPU_EQUAL(all, &by_pid));
+
+    unsigned long small;
+    errno = 0;
+    check("len not multiple of long is EINVAL",
+          sys_getaffinity(0, sizeof(small) - 1, &small) == -1 && errno == EINVAL);
+    errno = 0;
+    check("missing pid is ESRCH", sys_getaffinity(999999, sizeof(by_pid), &by_pid) == -1 &&
+                                      errno == ESRCH);
+}
+
+static void test_pin(const cpu_set_t *all) {
+    for (int cpu = 0; cpu < CPU_SETSIZE; cpu++) {
+        if (!CPU_ISSET(cpu, all)) {
+            continue;
+        }
+        cpu_set_t one;
+        CPU_ZERO(&one);
+        CPU_SET(cpu, &one);
+        char what[64];
+        snprintf(what, sizeof(what), "pin to cpu %d", cpu);
+        check(what, sys_setaffinity(0, sizeof(one), &one) == 0);
+
+        cpu_set_t got;
+        CPU_ZERO(&got);
+        sys_getaffinity(0, sizeof(got), &got);
+        snprintf(what, sizeof(what), "mask is cpu %d only", cpu);
+        check(what, CPU_EQUAL(&one, &got));
+
+        spin();
+        snprintf(what, sizeof(what), "running on cpu %d", cpu);
+        check(what, sched_getcpu() == cpu);
+    }
+
+    cpu_set_t empty;
+    CPU_ZERO(&empty);
+    errno = 0;
+    check("empty mask is EINVAL", sys_setaffinity(0, sizeof(empty), &empty) == -1 &&
+                                      errno == EINVAL);
+
+    check("restore full mask", sys_setaffinity(0, sizeof(*all), all) == 0);
+}
+
+static void test_inherit(const cpu_set_t *all) {
+    int first = -1;
+    for (int cpu = 0; cpu < CPU_SETSIZE && first < 0; cpu++) {
+        if (CPU_ISSET(cpu, all)) {
+            first = cpu;
+        }
+    }
+    cpu_set_t one;
+    CPU_ZERO(&one);
+    CPU_SET(first, &one);
+    sys_setaffinity(0, sizeof(one), &one);
+
+    pid_t child = fork();
+    if (child == 0) {
+        cpu_set_t got;
+        CPU_ZERO(&got);
+        sys_getaffinity(0, sizeof(got), &got);
+        _exit(CPU_EQUAL(&one, &got) && sched_getcpu() == first ? 0 : 1);
+    }
+    int status = 0;
+    waitpid(child, &status, 0);
+    check("child inherits mask", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    sys_setaffinity(0, sizeof(*all), all);
+}
+
+static void test_proc_status(void) {
+    FILE *f = fopen("/proc/self/status", "r");
+    int has_mask = 0, has_list = 0;
+    char line[256];
+    while (f && fgets(line, sizeof(line), f)) {
+        if (strncmp(line, "Cpus_allowed:", 13) == 0) {
+            has_mask = 1;
+        } else if (strncmp(line, "Cpus_allowed_list:", 18) == 0) {
+            has_list = 1;
+        }
+    }
+    if (f) {
+        fclose(f);
+    }
+    check("status shows Cpus_allowed", has_mask);
+    check("status shows Cpus_allowed_list", has_list);
+}
+
+int main(void) {
+    cpu_set_t all;
+
+    test_get(&all);
+    test_pin(&all);
+    test_inherit(&all);
+    test_proc_status();
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
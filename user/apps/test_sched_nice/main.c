@@ -0,0 +1,184 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#ifndef SCHED_BATCH
+#define SCHED_BATCH 3
+#endif
+#ifndef SCHED_IDLE
+#define SCHED_IDLE 5
+#endif
+
+/* nice 0、5、19与SCHED_IDLE的权重，与内核一样左移10位 */
+#define WEIGHT_NICE_0 (1024L << 10)
+#define WEIGHT_NICE_5 (335L << 10)
+#define WEIGHT_NICE_19 (15L << 10)
+#define WEIGHT_IDLE (3L << 10)
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* musl没有实现这几个函数（直接返回ENOSYS），因此直接使用系统调用 */
+static int sys_sched_setscheduler(pid_t pid, int policy, const struct sched_param *param) {
+    return syscall(SYS_sched_setscheduler, pid, policy, param);
+}
+
+static int sys_sched_getscheduler(pid_t pid) {
+    return syscall(SYS_sched_getscheduler, pid);
+}
+
+static int sys_sched_setparam(pid_t pid, const struct sched_param *param) {
+    return syscall(SYS_sched_setparam, pid, param);
+}
+
+static int sys_sched_getparam(pid_t pid, struct sched_param *param) {
+    return syscall(SYS_sched_getparam, pid, param);
+}
+
+static int read_sched(char *buf, size_t size) {
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/%d/sched", getpid());
+    FILE *f = fopen(path, "r");
+    if (f == NULL) {
+        return -1;
+    }
+    size_t n = fread(buf, 1, size - 1, f);
+    buf[n] = '\0';
+    fclose(f);
+    return (int)n;
+}
+
+/* 读取/proc/[pid]/sched中的一项，时间以毫秒为单位 */
+static double sched_field(const char *name) {
+    char buf[4096];
+    if (read_sched(buf, sizeof(buf)) < 0) {
+        return -1;
+    }
+    const char *p = strstr(buf, name);
+    if (p == NULL) {
+        return -1;
+    }
+    p = strchr(p, ':');
+    return p ? strtod(p + 1, NULL) : -1;
+}
+
+static void busy_loop(long ms) {
+    struct timespec start, now;
+    clock_gettime(CLOCK_MONOTONIC, &start);
+    do {
+        clock_gettime(CLOCK_MONOTONIC, &now);
+    } while ((now.tv_sec - start.tv_sec) * 1000 + (now.tv_nsec - start.tv_nsec) / 1000000 < ms);
+}
+
+/* 忙等一段时间，返回虚拟运行时间的增量与实际运行时间的增量之比 */
+static double vruntime_ratio(void) {
+    double vruntime = sched_field("se.vruntime");
+    double exec = sched_field("se.sum_exec_runtime");
+    busy_loop(200);
+    double dv = sched_field("se.vruntime") - vruntime;
+    double de = sched_field("se.sum_exec_runtime") - exec;
+    return de > 0 ? dv / de : -1;
+}
+
+static void test_priority_range(void) {
+    check("SCHED_OTHER max priority is 0", sched_get_priority_max(SCHED_OTHER) == 0);
+    check("SCHED_OTHER min priority is 0", sched_get_priority_min(SCHED_OTHER) == 0);
+    check("SCHED_BATCH max priority is 0", sched_get_priority_max(SCHED_BATCH) == 0);
+    check("SCHED_IDLE min priority is 0", sched_get_priority_min(SCHED_IDLE) == 0);
+    check("SCHED_FIFO max priority is 99", sched_get_priority_max(SCHED_FIFO) == 99);
+    check("SCHED_RR min priority is 1", sched_get_priority_min(SCHED_RR) == 1);
+    errno = 0;
+    check("unknown policy is rejected", sched_get_priority_max(42) == -1 && errno == EINVAL);
+}
+
+static void test_nice_weight(void) {
+    errno = 0;
+    check("default nice is 0", getpriority(PRIO_PROCESS, 0) == 0 && errno == 0);
+    check("nice 0 has weight 1024", (long)sched_field("se.load.weight") == WEIGHT_NICE_0);
+    double ratio = vruntime_ratio();
+    check("vruntime advances at wall speed at nice 0", ratio > 0.5 && ratio < 2);
+
+    check("setpriority to nice 5", setpriority(PRIO_PROCESS, 0, 5) == 0);
+    check("getpriority returns 5", getpriority(PRIO_PROCESS, 0) == 5);
+    check("nice 5 has weight 335", (long)sched_field("se.load.weight") == WEIGHT_NICE_5);
+    ratio = vruntime_ratio();
+    check("vruntime advances faster at nice 5", ratio > 2);
+
+    check("setpriority clamps to nice 19", setpriority(PRIO_PROCESS, 0, 100) == 0);
+    check("getpriority returns 19", getpriority(PRIO_PROCESS, 0) == 19);
+    check("nice 19 has weight 15", (long)sched_field("se.load.weight") == WEIGHT_NICE_19);
+
+    check("restore nice 0", setpriority(PRIO_PROCESS, 0, 0) == 0);
+    check("weight restored to 1024", (long)sched_field("se.load.weight") == WEIGHT_NICE_0);
+}
+
+static void test_policy(void) {
+    struct sched_param param = {.sched_priority = 0};
+    check("default policy is SCHED_OTHER", sys_sched_getscheduler(0) == SCHED_OTHER);
+    param.sched_priority = -1;
+    check("sched_getparam succeeds", sys_sched_getparam(0, &param) == 0);
+    check("SCHED_OTHER priority is 0", param.sched_priority == 0);
+
+    param.sched_priority = 1;
+    errno = 0;
+    check("nonzero priority is rejected for SCHED_OTHER",
+          sys_sched_setparam(0, &param) == -1 && errno == EINVAL);
+    errno = 0;
+    check("negative pid is rejected", sys_sched_getscheduler(-1) == -1 && errno == EINVAL);
+    errno = 0;
+    check("unknown policy is rejected for setscheduler",
+          sys_sched_setscheduler(0, 42, &param) == -1 && errno == EINVAL);
+
+    param.sched_priority = 0;
+    check("switch to SCHED_BATCH", sys_sched_setscheduler(0, SCHED_BATCH, &param) == 0);
+    check("policy is SCHED_BATCH", sys_sched_getscheduler(0) == SCHED_BATCH);
+    check("SCHED_BATCH keeps the nice weight",
+          (long)sched_field("se.load.weight") == WEIGHT_NICE_0);
+    check("sched_setparam keeps SCHED_BATCH",
+          sys_sched_setparam(0, &param) == 0 && sys_sched_getscheduler(0) == SCHED_BATCH);
+
+    check("switch to SCHED_IDLE", sys_sched_setscheduler(0, SCHED_IDLE, &param) == 0);
+    check("policy is SCHED_IDLE", sys_sched_getscheduler(0) == SCHED_IDLE);
+    check("/proc/[pid]/sched reports SCHED_IDLE", (long)sched_field("policy") == SCHED_IDLE);
+    check("SCHED_IDLE has weight 3", (long)sched_field("se.load.weight") == WEIGHT_IDLE);
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        _exit(sys_sched_getscheduler(0) == SCHED_IDLE ? 0 : 1);
+    }
+    int status = 0;
+    waitpid(pid, &status, 0);
+    check("child inherits SCHED_IDLE", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    check("switch back to SCHED_OTHER", sys_sched_setscheduler(0, SCHED_OTHER, &param) == 0);
+    check("policy is SCHED_OTHER again", sys_sched_getscheduler(0) == SCHED_OTHER);
+    check("weight restored after SCHED_IDLE",
+          (long)sched_field("se.load.weight") == WEIGHT_NICE_0);
+}
+
+int main() {
+    test_priority_range();
+    test_nice_weight();
+    test_policy();
+
+    if (failures) {
+        printf("%d tests failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
@@ -0,0 +1,331 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define RT_PERIOD "/proc/sys/kernel/sched_rt_period_us"
+#define RT_RUNTIME "/proc/sys/kernel/sched_rt_runtime_us"
+
+#define SCHED_ATTR_SIZE_VER0 48
+#define SCHED_ATTR_SIZE_VER1 56
+
+/* 与内核的struct sched_attr相同，避免与新版本C库中的定义冲突 */
+struct test_sched_attr {
+    uint32_t size;
+    uint32_t sched_policy;
+    uint64_t sched_flags;
+    int32_t sched_nice;
+    uint32_t sched_priority;
+    uint64_t sched_runtime;
+    uint64_t sched_deadline;
+    uint64_t sched_period;
+    uint32_t sched_util_min;
+    uint32_t sched_util_max;
+};
+
+/* 子进程与父进程共享的数据 */
+struct shared {
+    volatile long counters[2];
+    volatile int finished;
+    volatile int len;
+    volatile char order[8];
+};
+
+static struct shared *shm;
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* musl没有实现这几个函数（直接返回ENOSYS），因此直接使用系统调用 */
+static int sys_sched_setscheduler(pid_t pid, int policy, const struct sched_param *param) {
+    return syscall(SYS_sched_setscheduler, pid, policy, param);
+}
+
+static int sys_sched_getscheduler(pid_t pid) {
+    return syscall(SYS_sched_getscheduler, pid);
+}
+
+static int sys_sched_setparam(pid_t pid, const struct sched_param *param) {
+    return syscall(SYS_sched_setparam, pid, param);
+}
+
+static int sys_sched_getparam(pid_t pid, struct sched_param *param) {
+    return syscall(SYS_sched_getparam, pid, param);
+}
+
+static int set_policy(pid_t pid, int policy, int prio) {
+    struct sched_param param = {.sched_priority = prio};
+    return sys_sched_setscheduler(pid, policy, &param);
+}
+
+static int setattr(struct test_sched_attr *attr) {
+    return syscall(SYS_sched_setattr, 0, attr, 0);
+}
+
+static int getattr(struct test_sched_attr *attr, unsigned int size, unsigned int flags) {
+    return syscall(SYS_sched_getattr, 0, attr, size, flags);
+}
+
+static int read_file(const char *path, char *buf, size_t size) {
+    int fd = open(path, O_RDONLY);
+    if (fd < 0) {
+        return -1;
+    }
+    ssize_t len = read(fd, buf, size - 1);
+    close(fd);
+    if (len < 0) {
+        return -1;
+    }
+    buf[len] = '\0';
+    return 0;
+}
+
+/* 写入接口文件，成功返回0，失败返回errno */
+static int write_file(const char *path, const char *buf) {
+    int fd = open(path, O_WRONLY);
+    if (fd < 0) {
+        return errno;
+    }
+    int ret = 0;
+    if (write(fd, buf, strlen(buf)) < 0) {
+        ret = errno;
+    }
+    close(fd);
+    return ret;
+}
+
+static long elapsed_ms(const struct timespec *start) {
+    struct timespec now;
+    clock_gettime(CLOCK_MONOTONIC, &now);
+    return (now.tv_sec - start->tv_sec) * 1000 + (now.tv_nsec - start->tv_nsec) / 1000000;
+}
+
+static void mark(char who) {
+    shm->order[shm->len++] = who;
+}
+
+static pid_t spawn_counter(int idx) {
+    pid_t pid = fork();
+    if (pid == 0) {
+        for (;;) {
+            shm->counters[idx]++;
+        }
+    }
+    return pid;
+}
+
+static void reap(pid_t pid) {
+    kill(pid, SIGKILL);
+    waitpid(pid, NULL, 0);
+}
+
+static void test_policy(void) {
+    struct sched_param param;
+    struct timespec ts;
+
+    errno = 0;
+    check("SCHED_FIFO priority 0 is rejected", set_policy(0, SCHED_FIFO, 0) == -1 && errno == EINVAL);
+    errno = 0;
+    check("SCHED_RR priority 100 is rejected", set_policy(0, SCHED_RR, 100) == -1 && errno == EINVAL);
+
+    check("switch to SCHED_FIFO 50", set_policy(0, SCHED_FIFO, 50) == 0);
+    check("policy is SCHED_FIFO", sys_sched_getscheduler(0) == SCHED_FIFO);
+    check("sched_getparam returns 50", sys_sched_getparam(0, &param) == 0 && param.sched_priority == 50);
+    check("SCHED_FIFO has no time slice",
+          sched_rr_get_interval(0, &ts) == 0 && ts.tv_sec == 0 && ts.tv_nsec == 0);
+
+    param.sched_priority = 60;
+    check("sched_setparam keeps SCHED_FIFO",
+          sys_sched_setparam(0, &param) == 0 && sys_sched_getscheduler(0) == SCHED_FIFO);
+    check("sched_getparam returns 60", sys_sched_getparam(0, &param) == 0 && param.sched_priority == 60);
+
+    check("switch to SCHED_RR 10", set_policy(0, SCHED_RR, 10) == 0);
+    check("policy is SCHED_RR", sys_sched_getscheduler(0) == SCHED_RR);
+    check("SCHED_RR time slice is 100ms",
+          sched_rr_get_interval(0, &ts) == 0 && ts.tv_sec == 0 && ts.tv_nsec == 100000000);
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        _exit(sys_sched_getscheduler(0) == SCHED_RR && sys_sched_getparam(0, &param) == 0 &&
+                      param.sched_priority == 10
+                  ? 0
+                  : 1);
+    }
+    int status = 0;
+    waitpid(pid, &status, 0);
+    check("child inherits SCHED_RR 10", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    check("switch back to SCHED_OTHER", set_policy(0, SCHED_OTHER, 0) == 0);
+    check("policy is SCHED_OTHER again", sys_sched_getscheduler(0) == SCHED_OTHER);
+    check("SCHED_OTHER priority is 0", sys_sched_getparam(0, &param) == 0 && param.sched_priority == 0);
+}
+
+static void test_attr(void) {
+    struct test_sched_attr attr;
+
+    memset(&attr, 0, sizeof(attr));
+    attr.size = SCHED_ATTR_SIZE_VER1;
+    attr.sched_policy = SCHED_RR;
+    attr.sched_priority = 5;
+    check("sched_setattr SCHED_RR 5", setattr(&attr) == 0);
+    memset(&attr, 0xff, sizeof(attr));
+    check("sched_getattr succeeds", getattr(&attr, sizeof(attr), 0) == 0);
+    check("sched_getattr reports SCHED_RR 5",
+          attr.size == SCHED_ATTR_SIZE_VER1 && attr.sched_policy == SCHED_RR &&
+              attr.sched_priority == 5 && attr.sched_flags == 0);
+    check("sched_getattr with the first version size",
+          getattr(&attr, SCHED_ATTR_SIZE_VER0, 0) == 0 && attr.size == SCHED_ATTR_SIZE_VER0);
+    errno = 0;
+    check("sched_getattr rejects flags", getattr(&attr, sizeof(attr), 1) == -1 && errno == EINVAL);
+    errno = 0;
+    check("sched_getattr rejects a small size",
+          getattr(&attr, SCHED_ATTR_SIZE_VER0 - 8, 0) == -1 && errno == EINVAL);
+
+    /* size为0时按照最初的版本处理 */
+    memset(&attr, 0, sizeof(attr));
+    attr.sched_policy = SCHED_OTHER;
+    attr.sched_nice = 3;
+    check("sched_setattr SCHED_OTHER with nice 3", setattr(&attr) == 0);
+    check("policy is SCHED_OTHER", sys_sched_getscheduler(0) == SCHED_OTHER);
+    check("nice is 3", getpriority(PRIO_PROCESS, 0) == 3);
+    check("restore nice 0", setpriority(PRIO_PROCESS, 0, 0) == 0);
+
+    unsigned char big[SCHED_ATTR_SIZE_VER1 + 8];
+    memset(big, 0, sizeof(big));
+    struct test_sched_attr *battr = (struct test_sched_attr *)big;
+    battr->size = sizeof(big);
+    battr->sched_policy = SCHED_OTHER;
+    check("larger zero-padded sched_attr is accepted", setattr(battr) == 0);
+    big[sizeof(big) - 1] = 1;
+    errno = 0;
+    check("unknown nonzero fields are rejected", setattr(battr) == -1 && errno == E2BIG);
+    check("kernel size is written back", battr->size == SCHED_ATTR_SIZE_VER1);
+
+    memset(&attr, 0, sizeof(attr));
+    attr.size = SCHED_ATTR_SIZE_VER1;
+    attr.sched_policy = 6;
+    errno = 0;
+    check("SCHED_DEADLINE is rejected", setattr(&attr) == -1 && errno == EINVAL);
+}
+
+static void test_preempt(void) {
+    check("switch to SCHED_FIFO 10", set_policy(0, SCHED_FIFO, 10) == 0);
+
+    /* 同优先级的SCHED_FIFO子进程不抢占父进程 */
+    shm->len = 0;
+    pid_t pid = fork();
+    if (pid == 0) {
+        mark('c');
+        _exit(0);
+    }
+    mark('p');
+    waitpid(pid, NULL, 0);
+    check("equal priority child runs after the parent", shm->order[0] == 'p' && shm->order[1] == 'c');
+
+    /* 提高子进程的优先级之后，子进程立即抢占父进程 */
+    shm->len = 0;
+    pid = fork();
+    if (pid == 0) {
+        mark('c');
+        _exit(0);
+    }
+    check("raise the child to SCHED_FIFO 20", set_policy(pid, SCHED_FIFO, 20) == 0);
+    mark('p');
+    waitpid(pid, NULL, 0);
+    check("higher priority child preempts the parent", shm->order[0] == 'c' && shm->order[1] == 'p');
+
+    check("switch back to SCHED_OTHER", set_policy(0, SCHED_OTHER, 0) == 0);
+}
+
+/* 两个同优先级的忙等子进程，返回第二个子进程是否得到了运行 */
+static int second_runs(int policy) {
+    shm->counters[0] = 0;
+    shm->counters[1] = 0;
+    pid_t first = spawn_counter(0);
+    set_policy(first, policy, 10);
+    pid_t second = spawn_counter(1);
+    set_policy(second, policy, 10);
+
+    usleep(500000);
+    long first_count = shm->counters[0];
+    long second_count = shm->counters[1];
+    reap(first);
+    reap(second);
+    return first_count > 0 && second_count > 0;
+}
+
+static void test_round_robin(void) {
+    check("switch to SCHED_FIFO 90", set_policy(0, SCHED_FIFO, 90) == 0);
+    check("SCHED_RR processes take turns", second_runs(SCHED_RR));
+    check("SCHED_FIFO process keeps the cpu", !second_runs(SCHED_FIFO));
+    check("switch back to SCHED_OTHER", set_policy(0, SCHED_OTHER, 0) == 0);
+}
+
+static void test_throttle(void) {
+    char buf[64];
+    check("sched_rt_period_us defaults to 1000000",
+          read_file(RT_PERIOD, buf, sizeof(buf)) == 0 && strcmp(buf, "1000000\n") == 0);
+    check("sched_rt_runtime_us defaults to 950000",
+          read_file(RT_RUNTIME, buf, sizeof(buf)) == 0 && strcmp(buf, "950000\n") == 0);
+    check("runtime longer than the period is rejected", write_file(RT_RUNTIME, "2000000") == EINVAL);
+    check("period shorter than the runtime is rejected", write_file(RT_PERIOD, "500000") == EINVAL);
+    check("runtime below -1 is rejected", write_file(RT_RUNTIME, "-2") == EINVAL);
+    check("write sched_rt_runtime_us", write_file(RT_RUNTIME, "900000") == 0);
+    check("sched_rt_runtime_us reads back",
+          read_file(RT_RUNTIME, buf, sizeof(buf)) == 0 && strcmp(buf, "900000\n") == 0);
+    check("restore sched_rt_runtime_us", write_file(RT_RUNTIME, "950000") == 0);
+
+    /* 忙等3秒的实时子进程不能饿死父进程 */
+    check("switch to SCHED_FIFO 10", set_policy(0, SCHED_FIFO, 10) == 0);
+    shm->finished = 0;
+    pid_t pid = fork();
+    if (pid == 0) {
+        struct timespec start;
+        clock_gettime(CLOCK_MONOTONIC, &start);
+        while (elapsed_ms(&start) < 3000) {
+        }
+        shm->finished = 1;
+        _exit(0);
+    }
+    check("parent drops to SCHED_OTHER", set_policy(0, SCHED_OTHER, 0) == 0);
+    int finished = shm->finished;
+    reap(pid);
+    check("SCHED_OTHER parent runs while a SCHED_FIFO task spins", !finished);
+}
+
+int main() {
+    shm = mmap(NULL, sizeof(*shm), PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+    if (shm == MAP_FAILED) {
+        printf("mmap failed: %s\n", strerror(errno));
+        return 1;
+    }
+
+    test_policy();
+    test_attr();
+    test_preempt();
+    test_round_robin();
+    test_throttle();
+
+    if (failures) {
+        printf("%d tests failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
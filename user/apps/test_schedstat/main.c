@@ -0,0 +1,78 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+static int read_file(const char *path, char *buf, size_t size) {
+    FILE *f = fopen(path, "r");
+    if (f == NULL) {
+        return -1;
+    }
+    size_t n = fread(buf, 1, size - 1, f);
+    buf[n] = '\0';
+    fclose(f);
+    return (int)n;
+}
+
+/* 读取/proc/[pid]/sched中某一项的整数部分 */
+static long sched_field(const char *buf, const char *name) {
+    const char *p = strstr(buf, name);
+    if (p == NULL) {
+        return -1;
+    }
+    p = strchr(p, ':');
+    return p ? strtol(p + 1, NULL, 10) : -1;
+}
+
+static void test_schedstat(void) {
+    char buf[8192];
+    check("/proc/schedstat is readable", read_file("/proc/schedstat", buf, sizeof(buf)) > 0);
+    check("schedstat reports version 15", strncmp(buf, "version 15\n", 11) == 0);
+
+    char *line = strstr(buf, "\ncpu0 ");
+    unsigned long long v[12];
+    int n = line ? sscanf(line + 1,
+                          "cpu0 %llu %llu %llu %llu %llu %llu %llu %llu %llu %llu %llu %llu", &v[0],
+                          &v[1], &v[2], &v[3], &v[4], &v[5], &v[6], &v[7], &v[8], &v[9], &v[10],
+                          &v[11])
+                 : 0;
+    check("cpu0 line has 12 fields", n == 12);
+    check("cpu0 has scheduled at least once", n == 12 && v[2] > 0 && v[8] > 0);
+    check("wait time histogram is present", strstr(buf, "wait_hist_us ") != NULL);
+}
+
+static void test_pid_sched(void) {
+    char path[64], before[4096], after[4096];
+    snprintf(path, sizeof(path), "/proc/%d/sched", getpid());
+    check("/proc/[pid]/sched is readable", read_file(path, before, sizeof(before)) > 0);
+    char *header_end = strchr(before, '\n');
+    check("sched header names the task",
+          header_end != NULL && strstr(before, "test_schedstat") < header_end &&
+              strstr(before, "test_schedstat") != NULL);
+    check("sched reports vruntime", strstr(before, "se.vruntime") != NULL);
+
+    for (int i = 0; i < 5; i++) {
+        usleep(10000);
+    }
+    read_file(path, after, sizeof(after));
+    check("nr_switches grows after sleeping",
+          sched_field(after, "nr_switches") > sched_field(before, "nr_switches"));
+    check("policy is SCHED_NORMAL", sched_field(after, "policy") == 0);
+}
+
+int main(void) {
+    test_schedstat();
+    test_pid_sched();
+
+    printf("%s\n", failures == 0 ? "All tests passed" : "Some tests failed");
+    return failures == 0 ? 0 : 1;
+}
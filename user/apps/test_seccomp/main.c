@@ -0,0 +1,197 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/audit.h>
+#include <linux/filter.h>
+#include <linux/seccomp.h>
+#include <signal.h>
+#include <stddef.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#if defined(__x86_64__)
+#define CURRENT_AUDIT_ARCH AUDIT_ARCH_X86_64
+#elif defined(__riscv)
+#define CURRENT_AUDIT_ARCH AUDIT_ARCH_RISCV64
+#endif
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 系统调用号为nr时返回action，否则允许 */
+static int install_filter(int nr, unsigned int action) {
+    struct sock_filter filter[] = {
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, arch)),
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, CURRENT_AUDIT_ARCH, 1, 0),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS),
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, nr)),
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, nr, 0, 1),
+        BPF_STMT(BPF_RET | BPF_K, action),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW),
+    };
+    struct sock_fprog prog = {
+        .len = sizeof(filter) / sizeof(filter[0]),
+        .filter = filter,
+    };
+    return syscall(SYS_seccomp, SECCOMP_SET_MODE_FILTER, 0, &prog);
+}
+
+static pid_t run_child(void (*fn)(void)) {
+    fflush(stdout);
+    pid_t pid = fork();
+    if (pid == 0) {
+        fn();
+        _exit(0);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    return status;
+}
+
+static void child_errno(void) {
+    if (prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 || install_filter(SYS_getpid, SECCOMP_RET_ERRNO | EDOM) != 0) {
+        _exit(1);
+    }
+    if (prctl(PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) != 1 || prctl(PR_GET_SECCOMP) != 2) {
+        _exit(2);
+    }
+    errno = 0;
+    if (syscall(SYS_getpid) != -1 || errno != EDOM) {
+        _exit(3);
+    }
+    /* 其他系统调用不受影响 */
+    if (syscall(SYS_getppid) <= 0) {
+        _exit(4);
+    }
+    /* 过滤器被子进程继承 */
+    pid_t pid = fork();
+    if (pid == 0) {
+        errno = 0;
+        _exit(syscall(SYS_getpid) == -1 && errno == EDOM ? 0 : 1);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    if (!WIFEXITED(status) || WEXITSTATUS(status) != 0) {
+        _exit(5);
+    }
+    _exit(0);
+}
+
+static void child_kill(void) {
+    prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0);
+    if (install_filter(SYS_getppid, SECCOMP_RET_KILL_PROCESS) != 0) {
+        _exit(1);
+    }
+    syscall(SYS_getppid);
+    _exit(2);
+}
+
+static volatile sig_atomic_t trapped = 0;
+
+static void sigsys_handler(int sig) {
+    (void)sig;
+    trapped = 1;
+}
+
+static void child_trap(void) {
+    signal(SIGSYS, sigsys_handler);
+    prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0);
+    if (install_filter(SYS_getuid, SECCOMP_RET_TRAP) != 0) {
+        _exit(1);
+    }
+    syscall(SYS_getuid);
+    _exit(trapped ? 0 : 2);
+}
+
+static void child_strict_exit(void) {
+    if (prctl(PR_SET_SECCOMP, SECCOMP_MODE_STRICT, 0, 0, 0) != 0) {
+        _exit(1);
+    }
+    const char msg[] = "write in strict mode\n";
+    if (write(STDOUT_FILENO, msg, sizeof(msg) - 1) != sizeof(msg) - 1) {
+        syscall(SYS_exit, 2);
+    }
+    /* exit_group不在允许的范围内，需要直接使用exit */
+    syscall(SYS_exit, 7);
+}
+
+static void child_strict_kill(void) {
+    if (syscall(SYS_seccomp, SECCOMP_SET_MODE_STRICT, 0, NULL) != 0) {
+        _exit(1);
+    }
+    syscall(SYS_getpid);
+    syscall(SYS_exit, 2);
+}
+
+static void child_strict_then_filter(void) {
+    prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0);
+    if (install_filter(SYS_getpid, SECCOMP_RET_ERRNO | EDOM) != 0) {
+        _exit(1);
+    }
+    /* 已经安装了过滤器，不能再进入严格模式 */
+    errno = 0;
+    if (prctl(PR_SET_SECCOMP, SECCOMP_MODE_STRICT, 0, 0, 0) != -1 || errno != EINVAL) {
+        _exit(2);
+    }
+    _exit(0);
+}
+
+int main() {
+    check("seccomp is disabled initially", prctl(PR_GET_SECCOMP) == 0);
+    check("no_new_privs is not set initially", prctl(PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) == 0);
+
+    unsigned int action = SECCOMP_RET_ALLOW;
+    check("SECCOMP_RET_ALLOW is available", syscall(SYS_seccomp, SECCOMP_GET_ACTION_AVAIL, 0, &action) == 0);
+    action = 0x12340000;
+    errno = 0;
+    check("unknown action is not available",
+          syscall(SYS_seccomp, SECCOMP_GET_ACTION_AVAIL, 0, &action) == -1 && errno == EOPNOTSUPP);
+
+    struct sock_filter bad[] = {
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, nr)),
+    };
+    struct sock_fprog bad_prog = {.len = 1, .filter = bad};
+    errno = 0;
+    check("filter without return is rejected",
+          syscall(SYS_seccomp, SECCOMP_SET_MODE_FILTER, 0, &bad_prog) == -1 && errno == EINVAL);
+
+    if (getuid() != 0) {
+        errno = 0;
+        check("filter without no_new_privs needs CAP_SYS_ADMIN",
+              install_filter(SYS_getpid, SECCOMP_RET_ERRNO | EDOM) == -1 && errno == EACCES);
+    }
+
+    int status = run_child(child_errno);
+    check("SECCOMP_RET_ERRNO filter", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    status = run_child(child_kill);
+    check("SECCOMP_RET_KILL_PROCESS kills with SIGSYS", WIFSIGNALED(status) && WTERMSIG(status) == SIGSYS);
+
+    status = run_child(child_trap);
+    check("SECCOMP_RET_TRAP sends SIGSYS", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    status = run_child(child_strict_exit);
+    check("strict mode allows write and exit", WIFEXITED(status) && WEXITSTATUS(status) == 7);
+
+    status = run_child(child_strict_kill);
+    check("strict mode kills with SIGKILL", WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+
+    status = run_child(child_strict_then_filter);
+    check("strict mode after filter is rejected", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    check("parent is not filtered", syscall(SYS_getpid) == getpid() && prctl(PR_GET_SECCOMP) == 0);
+
+    if (failures == 0) {
+        printf("All tests passed\n");
+    }
+    return failures == 0 ? 0 : 1;
+}
@@ -1,6 +1,20 @@
 use libc::syscall;
 use libc::AT_FDCWD;
 use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::{symlink, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const STATX_BASIC_STATS: u32 = 0x7ff;
+const STATX_BTIME: u32 = 0x800;
+const STATX_RESERVED: u32 = 0x8000_0000;
+
+const AT_EMPTY_PATH: i32 = 0x1000;
+const AT_STATX_FORCE_SYNC: i32 = 0x2000;
+const AT_STATX_DONT_SYNC: i32 = 0x4000;
+
+const STATX_ATTR_MOUNT_ROOT: u64 = 0x2000;
 
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
@@ -39,25 +53,205 @@ pub struct StatxTimestamp {
     pub __statx_timestamp_pad1: [i32; 1],
 }
 
-fn main() {
-    let path = CString::new("/bin/about.elf").expect("Failed to create CString");
+/// 调用statx，失败时返回errno
+fn statx(dirfd: i32, path: &str, flags: i32, mask: u32) -> Result<Statx, i32> {
+    let path = CString::new(path).expect("Failed to create CString");
     let mut statxbuf: Statx = unsafe { std::mem::zeroed() };
-    let x = sc::nr::STATX as i64;
-
     let result = unsafe {
         syscall(
-            x,
-            AT_FDCWD,
+            sc::nr::STATX as i64,
+            dirfd,
             path.as_ptr(),
-            libc::AT_SYMLINK_NOFOLLOW,
-            0x7ff,
+            flags,
+            mask,
             &mut statxbuf,
         )
     };
-
-    if result != -1 {
-        println!("statx succeeded: {:?}", statxbuf);
+    if result == -1 {
+        Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(0))
     } else {
-        eprintln!("statx failed");
+        Ok(statxbuf)
+    }
+}
+
+struct Checker {
+    failures: usize,
+}
+
+impl Checker {
+    fn check(&mut self, what: &str, ok: bool) {
+        println!("{}: {}", if ok { "PASS" } else { "FAIL" }, what);
+        if !ok {
+            self.failures += 1;
+        }
+    }
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn test_basic(c: &mut Checker, file: &str) {
+    let meta = fs::metadata(file).expect("stat failed");
+    match statx(AT_FDCWD, file, 0, STATX_BASIC_STATS) {
+        Ok(stx) => {
+            c.check(
+                "basic stats are reported",
+                stx.stx_mask & STATX_BASIC_STATS == STATX_BASIC_STATS,
+            );
+            c.check("size matches stat", stx.stx_size == meta.size());
+            c.check("inode matches stat", stx.stx_ino == meta.ino());
+            c.check("mode matches stat", stx.stx_mode as u32 == meta.mode());
+            c.check("mtime matches stat", stx.stx_mtime.tv_sec == meta.mtime());
+            c.check("ctime matches stat", stx.stx_ctime.tv_sec == meta.ctime());
+            c.check(
+                "btime is not reported unless requested",
+                stx.stx_mask & STATX_BTIME == 0,
+            );
+        }
+        Err(e) => c.check(&format!("statx succeeds (errno {})", e), false),
+    }
+}
+
+fn test_btime(c: &mut Checker, file: &str, created: i64) {
+    match statx(AT_FDCWD, file, 0, STATX_BASIC_STATS | STATX_BTIME) {
+        Ok(stx) if stx.stx_mask & STATX_BTIME != 0 => {
+            // FAT以2秒为单位记录创建时间
+            c.check(
+                "btime is the creation time",
+                stx.stx_btime.tv_sec >= created - 2 && stx.stx_btime.tv_sec <= now_secs() + 1,
+            );
+        }
+        Ok(_) => println!("SKIP: filesystem does not record btime"),
+        Err(e) => c.check(&format!("statx with STATX_BTIME (errno {})", e), false),
+    }
+}
+
+fn test_flags(c: &mut Checker, dir: &str, file: &str) {
+    let f = fs::File::open(file).expect("open failed");
+    let ino = fs::metadata(file).map(|m| m.ino()).unwrap_or(0);
+    c.check(
+        "AT_EMPTY_PATH stats the fd",
+        statx(f.as_raw_fd(), "", AT_EMPTY_PATH, STATX_BASIC_STATS)
+            .map(|stx| stx.stx_ino == ino)
+            .unwrap_or(false),
+    );
+    c.check(
+        "empty path without AT_EMPTY_PATH is ENOENT",
+        statx(AT_FDCWD, "", 0, STATX_BASIC_STATS).err() == Some(libc::ENOENT),
+    );
+
+    let dirf = fs::File::open(dir).expect("open dir failed");
+    let name = file.rsplit('/').next().unwrap_or(file);
+    c.check(
+        "path relative to dirfd",
+        statx(dirf.as_raw_fd(), name, 0, STATX_BASIC_STATS)
+            .map(|stx| stx.stx_ino == ino)
+            .unwrap_or(false),
+    );
+
+    c.check(
+        "AT_STATX_DONT_SYNC succeeds",
+        statx(AT_FDCWD, file, AT_STATX_DONT_SYNC, STATX_BASIC_STATS).is_ok(),
+    );
+    c.check(
+        "AT_STATX_FORCE_SYNC succeeds",
+        statx(AT_FDCWD, file, AT_STATX_FORCE_SYNC, STATX_BASIC_STATS).is_ok(),
+    );
+    c.check(
+        "both sync flags is EINVAL",
+        statx(
+            AT_FDCWD,
+            file,
+            AT_STATX_FORCE_SYNC | AT_STATX_DONT_SYNC,
+            STATX_BASIC_STATS,
+        )
+        .err()
+            == Some(libc::EINVAL),
+    );
+    c.check(
+        "unknown flag is EINVAL",
+        statx(AT_FDCWD, file, 0x10_0000, STATX_BASIC_STATS).err() == Some(libc::EINVAL),
+    );
+    c.check(
+        "STATX_RESERVED is EINVAL",
+        statx(AT_FDCWD, file, 0, STATX_RESERVED).err() == Some(libc::EINVAL),
+    );
+    c.check(
+        "missing file is ENOENT",
+        statx(AT_FDCWD, "/nonexistent_statx_file", 0, STATX_BASIC_STATS).err()
+            == Some(libc::ENOENT),
+    );
+}
+
+fn test_symlink(c: &mut Checker, file: &str, link: &str) {
+    let _ = fs::remove_file(link);
+    if symlink(file, link).is_err() {
+        println!("SKIP: symlink not supported");
+        return;
+    }
+    c.check(
+        "AT_SYMLINK_NOFOLLOW stats the link",
+        statx(AT_FDCWD, link, libc::AT_SYMLINK_NOFOLLOW, STATX_BASIC_STATS)
+            .map(|stx| stx.stx_mode as u32 & libc::S_IFMT == libc::S_IFLNK)
+            .unwrap_or(false),
+    );
+    c.check(
+        "symlink is followed by default",
+        statx(AT_FDCWD, link, 0, STATX_BASIC_STATS)
+            .map(|stx| stx.stx_mode as u32 & libc::S_IFMT == libc::S_IFREG)
+            .unwrap_or(false),
+    );
+    let _ = fs::remove_file(link);
+}
+
+fn test_mount_root(c: &mut Checker, file: &str) {
+    match statx(AT_FDCWD, "/", 0, STATX_BASIC_STATS) {
+        Ok(stx) => {
+            c.check(
+                "MOUNT_ROOT is in attributes mask",
+                stx.stx_attributes_mask & STATX_ATTR_MOUNT_ROOT != 0,
+            );
+            c.check(
+                "/ is a mount root",
+                stx.stx_attributes & STATX_ATTR_MOUNT_ROOT != 0,
+            );
+        }
+        Err(e) => c.check(&format!("statx / (errno {})", e), false),
+    }
+    c.check(
+        "regular file is not a mount root",
+        statx(AT_FDCWD, file, 0, STATX_BASIC_STATS)
+            .map(|stx| stx.stx_attributes & STATX_ATTR_MOUNT_ROOT == 0)
+            .unwrap_or(false),
+    );
+}
+
+fn main() {
+    let dir = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/tmp".to_string());
+    let file = format!("{}/test_statx.dat", dir);
+    let link = format!("{}/test_statx.lnk", dir);
+
+    let created = now_secs();
+    fs::write(&file, b"hello statx").expect("Failed to create test file");
+
+    let mut c = Checker { failures: 0 };
+    test_basic(&mut c, &file);
+    test_btime(&mut c, &file, created);
+    test_flags(&mut c, &dir, &file);
+    test_symlink(&mut c, &file, &link);
+    test_mount_root(&mut c, &file);
+
+    let _ = fs::remove_file(&file);
+
+    if c.failures != 0 {
+        println!("{} test(s) failed", c.failures);
+        std::process::exit(1);
     }
+    println!("All tests passed");
 }
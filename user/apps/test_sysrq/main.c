@@ -0,0 +1,51 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define SYSRQ_TRIGGER "/proc/sysrq-trigger"
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+int main() {
+    struct stat st;
+    check("sysrq-trigger exists", stat(SYSRQ_TRIGGER, &st) == 0);
+    check("sysrq-trigger is write-only for root", (st.st_mode & 0777) == 0200);
+
+    int fd = open(SYSRQ_TRIGGER, O_WRONLY);
+    check("open sysrq-trigger for writing", fd >= 0);
+    /* 未登记的按键只打印帮助信息，不能触发'g'，否则会停在调试器中 */
+    check("write unregistered key", write(fd, "?", 1) == 1);
+    check("write only uses the first character", write(fd, "?xyz", 4) == 4);
+    check("empty write succeeds", write(fd, "", 0) == 0);
+    /* 只触发不会影响系统运行的内置按键 */
+    check("show memory (m)", write(fd, "m", 1) == 1);
+    check("show task states (t)", write(fd, "t", 1) == 1);
+    check("show blocked tasks (w)", write(fd, "w", 1) == 1);
+    check("emergency sync (s)", write(fd, "s", 1) == 1);
+    check("keys are case-insensitive", write(fd, "M", 1) == 1);
+    close(fd);
+
+    char buf[16];
+    fd = open(SYSRQ_TRIGGER, O_RDONLY);
+    if (fd >= 0) {
+        check("read returns nothing", read(fd, buf, sizeof(buf)) <= 0);
+        close(fd);
+    }
+
+    if (failures) {
+        printf("%d tests failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
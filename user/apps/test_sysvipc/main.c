@@ -0,0 +1,210 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/ipc.h>
+#include <sys/msg.h>
+#include <sys/sem.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+union semun {
+    int val;
+    struct semid_ds *buf;
+    unsigned short *array;
+};
+
+struct test_msg {
+    long mtype;
+    char mtext[64];
+};
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 检查/proc/sysvipc/<name>中是否有id对应的行 */
+static int proc_has_id(const char *name, int id) {
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/sysvipc/%s", name);
+    FILE *f = fopen(path, "r");
+    if (f == NULL) {
+        return 0;
+    }
+    char line[256];
+    int found = 0;
+    /* 跳过表头 */
+    fgets(line, sizeof(line), f);
+    while (fgets(line, sizeof(line), f) != NULL) {
+        int key, lid;
+        if (sscanf(line, "%d %d", &key, &lid) == 2 && lid == id) {
+            found = 1;
+        }
+    }
+    fclose(f);
+    return found;
+}
+
+static void test_sem(void) {
+    key_t key = 0x5e3a;
+    int semid = semget(key, 2, IPC_CREAT | IPC_EXCL | 0600);
+    check("semget(IPC_CREAT | IPC_EXCL)", semid >= 0);
+    check("semget of an existing key returns the same id", semget(key, 0, 0) == semid);
+    check("semget(IPC_EXCL) of an existing key returns EEXIST",
+          semget(key, 2, IPC_CREAT | IPC_EXCL | 0600) < 0 && errno == EEXIST);
+    check("semget asking for more semaphores returns EINVAL", semget(key, 3, 0) < 0 && errno == EINVAL);
+
+    unsigned short init[2] = {1, 0};
+    union semun arg = {.array = init};
+    check("semctl(SETALL)", semctl(semid, 0, SETALL, arg) == 0);
+    check("semctl(GETVAL)", semctl(semid, 0, GETVAL) == 1 && semctl(semid, 1, GETVAL) == 0);
+
+    struct semid_ds ds;
+    arg.buf = &ds;
+    check("semctl(IPC_STAT)", semctl(semid, 0, IPC_STAT, arg) == 0 && ds.sem_nsems == 2 &&
+                                  (ds.sem_perm.mode & 0777) == 0600);
+
+    /* 原子操作：第二个操作无法完成时，第一个操作也不生效 */
+    struct sembuf ops[2] = {{0, -1, 0}, {1, -1, IPC_NOWAIT}};
+    check("semop that would block with IPC_NOWAIT returns EAGAIN",
+          semop(semid, ops, 2) < 0 && errno == EAGAIN);
+    check("failed semop changes no semaphore", semctl(semid, 0, GETVAL) == 1);
+
+    struct timespec ts = {.tv_sec = 0, .tv_nsec = 100 * 1000 * 1000};
+    struct sembuf wait_op = {1, -1, 0};
+    check("semtimedop times out with EAGAIN", semtimedop(semid, &wait_op, 1, &ts) < 0 && errno == EAGAIN);
+    check("semncnt is zero after the timeout", semctl(semid, 1, GETNCNT) == 0);
+
+    /* 子进程等待信号量，父进程释放 */
+    pid_t pid = fork();
+    if (pid == 0) {
+        struct sembuf op = {1, -1, 0};
+        _exit(semop(semid, &op, 1) == 0 ? 0 : 1);
+    }
+    usleep(100 * 1000);
+    check("waiting process is counted in semncnt", semctl(semid, 1, GETNCNT) == 1);
+    struct sembuf post = {1, 1, 0};
+    semop(semid, &post, 1);
+    int status = 0;
+    waitpid(pid, &status, 0);
+    check("blocked semop completes after the semaphore is posted",
+          WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    check("sempid is the last process that operated", semctl(semid, 1, GETPID) == pid);
+
+    check("/proc/sysvipc/sem lists the semaphore set", proc_has_id("sem", semid));
+    check("semctl(IPC_RMID)", semctl(semid, 0, IPC_RMID) == 0);
+    check("removed semaphore set is invalid", semctl(semid, 0, GETVAL) < 0 && errno == EINVAL);
+    check("/proc/sysvipc/sem no longer lists it", !proc_has_id("sem", semid));
+}
+
+static void test_msg(void) {
+    int msqid = msgget(IPC_PRIVATE, 0600);
+    check("msgget(IPC_PRIVATE)", msqid >= 0);
+
+    struct test_msg msg;
+    long types[] = {3, 1, 2};
+    for (int i = 0; i < 3; i++) {
+        msg.mtype = types[i];
+        snprintf(msg.mtext, sizeof(msg.mtext), "type %ld", types[i]);
+        msgsnd(msqid, &msg, strlen(msg.mtext) + 1, 0);
+    }
+
+    struct msqid_ds ds;
+    check("msgctl(IPC_STAT) counts messages", msgctl(msqid, IPC_STAT, &ds) == 0 && ds.msg_qnum == 3);
+    check("/proc/sysvipc/msg lists the queue", proc_has_id("msg", msqid));
+
+    check("msgrcv by type",
+          msgrcv(msqid, &msg, sizeof(msg.mtext), 2, 0) == 7 && msg.mtype == 2 && strcmp(msg.mtext, "type 2") == 0);
+    check("msgrcv with a negative type takes the lowest type",
+          msgrcv(msqid, &msg, sizeof(msg.mtext), -3, 0) > 0 && msg.mtype == 1);
+    check("msgrcv with a small buffer returns E2BIG", msgrcv(msqid, &msg, 2, 0, 0) < 0 && errno == E2BIG);
+    check("msgrcv with MSG_NOERROR truncates", msgrcv(msqid, &msg, 2, 0, MSG_NOERROR) == 2 && msg.mtype == 3);
+    check("msgrcv on an empty queue with IPC_NOWAIT returns ENOMSG",
+          msgrcv(msqid, &msg, sizeof(msg.mtext), 0, IPC_NOWAIT) < 0 && errno == ENOMSG);
+
+    msg.mtype = 0;
+    check("msgsnd with mtype 0 returns EINVAL", msgsnd(msqid, &msg, 1, 0) < 0 && errno == EINVAL);
+
+    /* 阻塞的接收者在队列被删除时返回EIDRM */
+    pid_t pid = fork();
+    if (pid == 0) {
+        struct test_msg m;
+        _exit(msgrcv(msqid, &m, sizeof(m.mtext), 0, 0) < 0 && errno == EIDRM ? 0 : 1);
+    }
+    usleep(100 * 1000);
+    check("msgctl(IPC_RMID)", msgctl(msqid, IPC_RMID, NULL) == 0);
+    int status = 0;
+    waitpid(pid, &status, 0);
+    check("blocked msgrcv returns EIDRM after the queue is removed",
+          WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+/* 非特权进程只能按照权限位访问，不能删除或者修改别人的对象，也不能调大qbytes */
+static void test_perm(void) {
+    int msqid = msgget(IPC_PRIVATE, IPC_CREAT | 0600);
+    int semid = semget(IPC_PRIVATE, 1, IPC_CREAT | 0644);
+    check("create objects for the permission test", msqid >= 0 && semid >= 0);
+
+    pid_t pid = fork();
+    if (pid == 0) {
+        if (setgid(65534) < 0 || setuid(65534) < 0)
+            _exit(1);
+        struct test_msg m = {.mtype = 1};
+        struct sembuf op = {.sem_num = 0, .sem_op = 1, .sem_flg = IPC_NOWAIT};
+        struct msqid_ds ds = {0};
+        int ok = msgsnd(msqid, &m, 1, IPC_NOWAIT) < 0 && errno == EACCES;
+        ok = ok && msgrcv(msqid, &m, sizeof(m.mtext), 0, IPC_NOWAIT) < 0 && errno == EACCES;
+        ok = ok && msgctl(msqid, IPC_STAT, &ds) < 0 && errno == EACCES;
+        ok = ok && msgctl(msqid, IPC_SET, &ds) < 0 && errno == EPERM;
+        ok = ok && msgctl(msqid, IPC_RMID, NULL) < 0 && errno == EPERM;
+        ok = ok && semop(semid, &op, 1) < 0 && errno == EACCES;
+        ok = ok && semctl(semid, 0, GETVAL) == 0;
+        ok = ok && semctl(semid, 0, IPC_RMID) < 0 && errno == EPERM;
+        _exit(ok ? 0 : 2);
+    }
+    int status = 0;
+    waitpid(pid, &status, 0);
+    check("unprivileged caller is limited by the permission bits", WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    pid = fork();
+    if (pid == 0) {
+        if (setgid(65534) < 0 || setuid(65534) < 0)
+            _exit(1);
+        int q = msgget(IPC_PRIVATE, IPC_CREAT | 0600);
+        struct msqid_ds ds;
+        if (q < 0 || msgctl(q, IPC_STAT, &ds) < 0)
+            _exit(2);
+        ds.msg_qbytes /= 2;
+        int ok = msgctl(q, IPC_SET, &ds) == 0;
+        ds.msg_qbytes = ds.msg_qbytes * 4;
+        ok = ok && msgctl(q, IPC_SET, &ds) < 0 && errno == EPERM;
+        msgctl(q, IPC_RMID, NULL);
+        _exit(ok ? 0 : 3);
+    }
+    waitpid(pid, &status, 0);
+    check("unprivileged owner cannot raise qbytes above MSGMNB",
+          WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+    msgctl(msqid, IPC_RMID, NULL);
+    semctl(semid, 0, IPC_RMID);
+}
+
+int main() {
+    test_sem();
+    test_msg();
+    test_perm();
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
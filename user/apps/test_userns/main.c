@@ -0,0 +1,248 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/capability.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define UNPRIV_UID 1000
+#define UNPRIV_GID 1000
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 向/proc/[pid]/下的文件写入内容，成功返回0，失败返回errno */
+static int write_proc(pid_t pid, const char *name, const char *content) {
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/%d/%s", pid, name);
+    int fd = open(path, O_WRONLY);
+    if (fd < 0) {
+        return errno;
+    }
+    int ret = 0;
+    if (write(fd, content, strlen(content)) != (ssize_t)strlen(content)) {
+        ret = errno;
+    }
+    close(fd);
+    return ret;
+}
+
+static int read_proc(pid_t pid, const char *name, char *buf, size_t len) {
+    char path[64];
+    snprintf(path, sizeof(path), "/proc/%d/%s", pid, name);
+    int fd = open(path, O_RDONLY);
+    if (fd < 0) {
+        return -1;
+    }
+    ssize_t n = read(fd, buf, len - 1);
+    close(fd);
+    if (n < 0) {
+        return -1;
+    }
+    buf[n] = '\0';
+    return 0;
+}
+
+static int has_effective(int cap) {
+    struct __user_cap_header_struct header = {.version = _LINUX_CAPABILITY_VERSION_3, .pid = 0};
+    struct __user_cap_data_struct data[2];
+    if (syscall(SYS_capget, &header, data) != 0) {
+        return -1;
+    }
+    return (data[CAP_TO_INDEX(cap)].effective & CAP_TO_MASK(cap)) != 0;
+}
+
+static int drop_root(void) {
+    if (setgid(UNPRIV_GID) != 0 || setuid(UNPRIV_UID) != 0) {
+        return -1;
+    }
+    /* Linux中setuid之后进程不可dump，/proc/[pid]下的文件属于root，不支持时忽略 */
+    prctl(PR_SET_DUMPABLE, 1, 0, 0, 0);
+    return 0;
+}
+
+static int run_child(void (*fn)(void)) {
+    fflush(stdout);
+    pid_t pid = fork();
+    if (pid == 0) {
+        fn();
+        _exit(0);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    return WIFEXITED(status) ? WEXITSTATUS(status) : -1;
+}
+
+/* 普通用户创建user namespace，并把自己映射为namespace中的root */
+static void child_unprivileged(void) {
+    if (drop_root() != 0) {
+        _exit(1);
+    }
+    if (unshare(CLONE_NEWUSER) != 0) {
+        _exit(2);
+    }
+    /* 没有映射时显示为overflow uid，但在新namespace中拥有全部权限 */
+    if (getuid() != 65534 || getgid() != 65534 || has_effective(CAP_SYS_ADMIN) != 1) {
+        _exit(3);
+    }
+    pid_t self = getpid();
+    /* 不能映射别人的uid */
+    if (write_proc(self, "uid_map", "0 0 1\n") != EPERM) {
+        _exit(4);
+    }
+    char map[64];
+    snprintf(map, sizeof(map), "0 %d 1\n", UNPRIV_UID);
+    if (write_proc(self, "uid_map", map) != 0) {
+        _exit(5);
+    }
+    /* 映射只能写入一次 */
+    if (write_proc(self, "uid_map", map) != EPERM) {
+        _exit(6);
+    }
+    /* Linux要求先禁用setgroups，不支持时忽略 */
+    write_proc(self, "setgroups", "deny");
+    snprintf(map, sizeof(map), "0 %d 1\n", UNPRIV_GID);
+    if (write_proc(self, "gid_map", map) != 0) {
+        _exit(7);
+    }
+    if (getuid() != 0 || geteuid() != 0 || getgid() != 0) {
+        _exit(8);
+    }
+    char buf[128];
+    unsigned int first, lower, count;
+    if (read_proc(self, "uid_map", buf, sizeof(buf)) != 0 || sscanf(buf, "%u %u %u", &first, &lower, &count) != 3 ||
+        first != 0 || lower != UNPRIV_UID || count != 1) {
+        _exit(9);
+    }
+    /* 映射之外的uid不能使用 */
+    errno = 0;
+    if (setuid(5) != -1 || errno != EINVAL) {
+        _exit(10);
+    }
+    if (setuid(0) != 0) {
+        _exit(11);
+    }
+    /* 初始namespace中root的文件显示为overflow uid */
+    struct stat st;
+    if (stat("/", &st) != 0 || st.st_uid != 65534) {
+        _exit(12);
+    }
+    _exit(0);
+}
+
+/* 父namespace中有CAP_SETUID的进程可以为子进程设置任意映射 */
+static void child_privileged_map(void) {
+    int to_parent[2], to_child[2];
+    if (pipe(to_parent) != 0 || pipe(to_child) != 0) {
+        _exit(1);
+    }
+    pid_t pid = fork();
+    if (pid == 0) {
+        char c = 0;
+        if (unshare(CLONE_NEWUSER) != 0) {
+            _exit(2);
+        }
+        write(to_parent[1], &c, 1);
+        read(to_child[0], &c, 1);
+        _exit(getuid() == 0 && geteuid() == 0 && getgid() == 0 ? 0 : 3);
+    }
+    char c;
+    if (read(to_parent[0], &c, 1) != 1) {
+        _exit(4);
+    }
+    int err_uid = write_proc(pid, "uid_map", "0 0 65536\n");
+    write_proc(pid, "setgroups", "deny");
+    int err_gid = write_proc(pid, "gid_map", "0 0 65536\n");
+    write(to_child[1], &c, 1);
+    int status;
+    waitpid(pid, &status, 0);
+    if (err_uid != 0 || err_gid != 0) {
+        _exit(5);
+    }
+    _exit(WIFEXITED(status) ? WEXITSTATUS(status) : 6);
+}
+
+/* 有重叠区间或者格式错误的映射被拒绝 */
+static void child_bad_map(void) {
+    int to_parent[2], to_child[2];
+    if (pipe(to_parent) != 0 || pipe(to_child) != 0) {
+        _exit(1);
+    }
+    pid_t pid = fork();
+    if (pid == 0) {
+        char c = 0;
+        if (unshare(CLONE_NEWUSER) != 0) {
+            _exit(2);
+        }
+        write(to_parent[1], &c, 1);
+        read(to_child[0], &c, 1);
+        _exit(0);
+    }
+    char c;
+    read(to_parent[0], &c, 1);
+    int ret = 0;
+    if (write_proc(pid, "uid_map", "0 100000 10\n5 200000 10\n") != EINVAL) {
+        ret = 3;
+    } else if (write_proc(pid, "uid_map", "0 100000\n") != EINVAL) {
+        ret = 4;
+    } else if (write_proc(pid, "uid_map", "0 100000 0\n") != EINVAL) {
+        ret = 5;
+    }
+    write(to_child[1], &c, 1);
+    waitpid(pid, NULL, 0);
+    _exit(ret);
+}
+
+/* clone时指定CLONE_NEWUSER */
+static void child_clone_newuser(void) {
+    pid_t pid = syscall(SYS_clone, CLONE_NEWUSER | SIGCHLD, 0, 0, 0, 0);
+    if (pid == 0) {
+        _exit(getuid() == 65534 && has_effective(CAP_SETUID) == 1 ? 0 : 1);
+    }
+    if (pid < 0) {
+        _exit(2);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    _exit(WIFEXITED(status) ? WEXITSTATUS(status) : 3);
+}
+
+int main() {
+    if (getuid() != 0) {
+        printf("test_userns must be run as root\n");
+        return 1;
+    }
+
+    char buf[128];
+    unsigned int first, lower, count;
+    check("initial uid_map is identity",
+          read_proc(getpid(), "uid_map", buf, sizeof(buf)) == 0 && sscanf(buf, "%u %u %u", &first, &lower, &count) == 3 &&
+              first == 0 && lower == 0 && count == 4294967295u);
+    errno = 0;
+    check("initial uid_map cannot be written", write_proc(getpid(), "uid_map", "0 0 1\n") == EPERM);
+
+    check("unprivileged user namespace", run_child(child_unprivileged) == 0);
+    check("privileged parent writes child's map", run_child(child_privileged_map) == 0);
+    check("invalid maps are rejected", run_child(child_bad_map) == 0);
+    check("clone with CLONE_NEWUSER", run_child(child_clone_newuser) == 0);
+
+    check("parent is still root", getuid() == 0 && has_effective(CAP_SYS_ADMIN) == 1);
+
+    if (failures == 0) {
+        printf("All tests passed\n");
+    }
+    return failures == 0 ? 0 : 1;
+}
@@ -0,0 +1,82 @@
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define CHUNK_SIZE (1024 * 1024)
+#define TOTAL_SIZE (128L * 1024 * 1024)
+#define PAGE_SIZE 4096
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 从/proc/vmstat中读取一项，不存在时返回-1 */
+static long vmstat(const char *name) {
+    FILE *f = fopen("/proc/vmstat", "r");
+    if (f == NULL) {
+        return -1;
+    }
+    char key[64];
+    long value;
+    long ret = -1;
+    while (fscanf(f, "%63s %ld", key, &value) == 2) {
+        if (strcmp(key, name) == 0) {
+            ret = value;
+            break;
+        }
+    }
+    fclose(f);
+    return ret;
+}
+
+int main(int argc, char *argv[]) {
+    const char *path = argc > 1 ? argv[1] : "test_writeback.dat";
+
+    long thresh = vmstat("nr_dirty_threshold");
+    long bg_thresh = vmstat("nr_dirty_background_threshold");
+    check("vmstat reports dirty thresholds", thresh > 0 && bg_thresh > 0 && bg_thresh <= thresh);
+    check("vmstat reports nr_dirty", vmstat("nr_dirty") >= 0);
+
+    int fd = open(path, O_CREAT | O_TRUNC | O_WRONLY, 0644);
+    check("create file", fd >= 0);
+    if (fd < 0) {
+        return 1;
+    }
+
+    char *buf = malloc(CHUNK_SIZE);
+    memset(buf, 0x5a, CHUNK_SIZE);
+
+    /* 大量写入时脏页数被限制在阈值附近，最多超出一次写入的页数 */
+    long max_dirty = 0;
+    int write_ok = 1;
+    for (long done = 0; done < TOTAL_SIZE; done += CHUNK_SIZE) {
+        if (write(fd, buf, CHUNK_SIZE) != CHUNK_SIZE) {
+            write_ok = 0;
+            break;
+        }
+        long dirty = vmstat("nr_dirty");
+        if (dirty > max_dirty) {
+            max_dirty = dirty;
+        }
+    }
+    check("write file", write_ok);
+    printf("max nr_dirty %ld, threshold %ld\n", max_dirty, thresh);
+    check("dirty pages stay under the threshold", max_dirty <= thresh + CHUNK_SIZE / PAGE_SIZE);
+
+    check("fsync", fsync(fd) == 0);
+    close(fd);
+    free(buf);
+    unlink(path);
+
+    if (failures == 0) {
+        printf("All tests passed\n");
+    }
+    return failures == 0 ? 0 : 1;
+}
@@ -0,0 +1,344 @@
+#include <errno.h>
+#include <poll.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+/* 与内核的定义保持一致，不依赖<linux/if_xdp.h> */
+#define AF_XDP_ 44
+#define SOL_XDP_ 283
+
+#define XDP_MMAP_OFFSETS_ 1
+#define XDP_RX_RING_ 2
+#define XDP_TX_RING_ 3
+#define XDP_UMEM_REG_ 4
+#define XDP_UMEM_FILL_RING_ 5
+#define XDP_UMEM_COMPLETION_RING_ 6
+#define XDP_STATISTICS_ 7
+
+#define XDP_PGOFF_RX_RING_ 0
+#define XDP_PGOFF_TX_RING_ 0x80000000ULL
+#define XDP_UMEM_PGOFF_FILL_RING_ 0x100000000ULL
+#define XDP_UMEM_PGOFF_COMPLETION_RING_ 0x180000000ULL
+#define XDP_UMEM_PGOFF_UMEM_ 0x200000000ULL
+
+#define XDP_ZEROCOPY_ (1 << 2)
+
+#define NUM_FRAMES 64
+#define FRAME_SIZE 2048
+#define RING_SIZE 64
+#define UMEM_SIZE (NUM_FRAMES * FRAME_SIZE)
+/* 发送用的帧，其余的帧交给内核接收 */
+#define TX_FRAME 32
+
+struct umem_reg {
+    uint64_t addr;
+    uint64_t len;
+    uint32_t chunk_size;
+    uint32_t headroom;
+    uint32_t flags;
+    uint32_t tx_metadata_len;
+};
+
+struct ring_offset {
+    uint64_t producer;
+    uint64_t consumer;
+    uint64_t desc;
+    uint64_t flags;
+};
+
+struct mmap_offsets {
+    struct ring_offset rx, tx, fr, cr;
+};
+
+struct xdp_desc_ {
+    uint64_t addr;
+    uint32_t len;
+    uint32_t options;
+};
+
+struct xdp_stats {
+    uint64_t rx_dropped;
+    uint64_t rx_invalid_descs;
+    uint64_t tx_invalid_descs;
+    uint64_t rx_ring_full;
+    uint64_t rx_fill_ring_empty_descs;
+    uint64_t tx_ring_empty_descs;
+};
+
+struct sockaddr_xdp_ {
+    uint16_t sxdp_family;
+    uint16_t sxdp_flags;
+    uint32_t sxdp_ifindex;
+    uint32_t sxdp_queue_id;
+    uint32_t sxdp_shared_umem_fd;
+};
+
+struct ring {
+    void *map;
+    size_t map_len;
+    uint32_t *producer;
+    uint32_t *consumer;
+    void *desc;
+};
+
+static int failures = 0;
+
+static void check(const char *what, int ok) {
+    printf("%s: %s\n", ok ? "PASS" : "FAIL", what);
+    if (!ok) {
+        failures++;
+    }
+}
+
+/* 通过sysfs按名字查询网卡的ifindex */
+static int ifindex_by_name(const char *name) {
+    char path[64];
+    snprintf(path, sizeof(path), "/sys/class/net/%s/ifindex", name);
+    FILE *fp = fopen(path, "r");
+    if (fp == NULL) {
+        return -1;
+    }
+    int index = -1;
+    if (fscanf(fp, "%d", &index) != 1) {
+        index = -1;
+    }
+    fclose(fp);
+    return index;
+}
+
+static int set_int(int fd, int opt, int value) {
+    return setsockopt(fd, SOL_XDP_, opt, &value, sizeof(value));
+}
+
+static int reg_umem(int fd, uint64_t addr, uint32_t chunk_size) {
+    struct umem_reg reg = {
+        .addr = addr,
+        .len = UMEM_SIZE,
+        .chunk_size = chunk_size,
+        .headroom = 0,
+    };
+    return setsockopt(fd, SOL_XDP_, XDP_UMEM_REG_, &reg, sizeof(reg));
+}
+
+static int bind_xdp(int fd, int ifindex, int queue_id, int flags) {
+    struct sockaddr_xdp_ addr = {
+        .sxdp_family = AF_XDP_,
+        .sxdp_flags = flags,
+        .sxdp_ifindex = ifindex,
+        .sxdp_queue_id = queue_id,
+    };
+    return bind(fd, (struct sockaddr *)&addr, sizeof(addr));
+}
+
+/* 注册UMEM并创建全部四个队列 */
+static int setup(int fd) {
+    if (reg_umem(fd, 0, FRAME_SIZE) != 0) {
+        return -1;
+    }
+    if (set_int(fd, XDP_UMEM_FILL_RING_, RING_SIZE) != 0 ||
+        set_int(fd, XDP_UMEM_COMPLETION_RING_, RING_SIZE) != 0 ||
+        set_int(fd, XDP_RX_RING_, RING_SIZE) != 0 || set_int(fd, XDP_TX_RING_, RING_SIZE) != 0) {
+        return -1;
+    }
+    return 0;
+}
+
+static int map_ring(int fd, const struct ring_offset *off, uint64_t pgoff, size_t desc_size,
+                    struct ring *ring) {
+    size_t len = off->desc + RING_SIZE * desc_size;
+    char *p = mmap(NULL, len, PROT_READ | PROT_WRITE, MAP_SHARED, fd, pgoff);
+    if (p == MAP_FAILED) {
+        return -1;
+    }
+    ring->map = p;
+    ring->map_len = len;
+    ring->producer = (uint32_t *)(p + off->producer);
+    ring->consumer = (uint32_t *)(p + off->consumer);
+    ring->desc = p + off->desc;
+    return 0;
+}
+
+static void test_errors(int lo) {
+    errno = 0;
+    check("SOCK_DGRAM is not supported",
+          socket(AF_XDP_, SOCK_DGRAM, 0) < 0 && errno == ESOCKTNOSUPPORT);
+
+    int fd = socket(AF_XDP_, SOCK_RAW, 0);
+
+    errno = 0;
+    check("bind without umem is EINVAL", bind_xdp(fd, lo, 0, 0) == -1 && errno == EINVAL);
+    errno = 0;
+    check("fill ring without umem is EINVAL",
+          set_int(fd, XDP_UMEM_FILL_RING_, RING_SIZE) == -1 && errno == EINVAL);
+    errno = 0;
+    check("user memory umem is not supported",
+          reg_umem(fd, 0x10000000, FRAME_SIZE) == -1 && errno == EOPNOTSUPP);
+    errno = 0;
+    check("bad chunk size is EINVAL", reg_umem(fd, 0, 1000) == -1 && errno == EINVAL);
+    check("register umem", reg_umem(fd, 0, FRAME_SIZE) == 0);
+    errno = 0;
+    check("second umem is EBUSY", reg_umem(fd, 0, FRAME_SIZE) == -1 && errno == EBUSY);
+    errno = 0;
+    check("ring size must be a power of two",
+          set_int(fd, XDP_RX_RING_, 3) == -1 && errno == EINVAL);
+    check("create rings", set_int(fd, XDP_UMEM_FILL_RING_, RING_SIZE) == 0 &&
+                              set_int(fd, XDP_UMEM_COMPLETION_RING_, RING_SIZE) == 0 &&
+                              set_int(fd, XDP_RX_RING_, RING_SIZE) == 0);
+    errno = 0;
+    check("ring can only be created once",
+          set_int(fd, XDP_RX_RING_, RING_SIZE) == -1 && errno == EINVAL);
+    errno = 0;
+    check("only queue 0 exists", bind_xdp(fd, lo, 1, 0) == -1 && errno == EINVAL);
+    errno = 0;
+    check("zero-copy driver mode is not supported",
+          bind_xdp(fd, lo, 0, XDP_ZEROCOPY_) == -1 && errno == EOPNOTSUPP);
+    errno = 0;
+    check("unknown device is ENODEV", bind_xdp(fd, 12345, 0, 0) == -1 && errno == ENODEV);
+    errno = 0;
+    check("sendto before bind is ENXIO",
+          sendto(fd, NULL, 0, MSG_DONTWAIT, NULL, 0) == -1 && errno == ENXIO);
+    close(fd);
+}
+
+static void test_rx_tx(int lo) {
+    int fd = socket(AF_XDP_, SOCK_RAW, 0);
+    check("setup umem and rings", setup(fd) == 0);
+
+    struct mmap_offsets off;
+    socklen_t optlen = sizeof(off);
+    check("get mmap offsets",
+          getsockopt(fd, SOL_XDP_, XDP_MMAP_OFFSETS_, &off, &optlen) == 0 &&
+              optlen == sizeof(off));
+
+    struct ring fill, comp, rx, tx;
+    check("mmap fill ring",
+          map_ring(fd, &off.fr, XDP_UMEM_PGOFF_FILL_RING_, sizeof(uint64_t), &fill) == 0);
+    check("mmap completion ring",
+          map_ring(fd, &off.cr, XDP_UMEM_PGOFF_COMPLETION_RING_, sizeof(uint64_t), &comp) == 0);
+    check("mmap rx ring",
+          map_ring(fd, &off.rx, XDP_PGOFF_RX_RING_, sizeof(struct xdp_desc_), &rx) == 0);
+    check("mmap tx ring",
+          map_ring(fd, &off.tx, XDP_PGOFF_TX_RING_, sizeof(struct xdp_desc_), &tx) == 0);
+    unsigned char *umem =
+        mmap(NULL, UMEM_SIZE, PROT_READ | PROT_WRITE, MAP_SHARED, fd, XDP_UMEM_PGOFF_UMEM_);
+    check("mmap umem", umem != MAP_FAILED);
+
+    check("bind to lo queue 0", bind_xdp(fd, lo, 0, 0) == 0);
+
+    int other = socket(AF_XDP_, SOCK_RAW, 0);
+    errno = 0;
+    check("queue is already bound",
+          setup(other) == 0 && bind_xdp(other, lo, 0, 0) == -1 && errno == EBUSY);
+
+    /* 把前一半的帧交给内核接收 */
+    uint64_t *fill_addrs = fill.desc;
+    for (int i = 0; i < TX_FRAME; i++) {
+        fill_addrs[i] = (uint64_t)i * FRAME_SIZE;
+    }
+    __atomic_store_n(fill.producer, TX_FRAME, __ATOMIC_RELEASE);
+
+    /* 在UMEM中构造一个以太网帧，目的地址为环回网卡的地址 */
+    unsigned char *frame = umem + TX_FRAME * FRAME_SIZE;
+    memset(frame, 0, 12);
+    frame[12] = 0x88;
+    frame[13] = 0xb5;
+    for (int i = 14; i < 100; i++) {
+        frame[i] = (unsigned char)i;
+    }
+    struct xdp_desc_ *tx_descs = tx.desc;
+    tx_descs[0].addr = TX_FRAME * FRAME_SIZE;
+    tx_descs[0].len = 100;
+    tx_descs[0].options = 0;
+    __atomic_store_n(tx.producer, 1, __ATOMIC_RELEASE);
+
+    check("sendto kicks tx", sendto(fd, NULL, 0, MSG_DONTWAIT, NULL, 0) == 0);
+    check("tx ring consumed", __atomic_load_n(tx.consumer, __ATOMIC_ACQUIRE) == 1);
+    uint64_t *comp_addrs = comp.desc;
+    check("frame completed",
+          __atomic_load_n(comp.producer, __ATOMIC_ACQUIRE) == 1 &&
+              comp_addrs[0] == TX_FRAME * FRAME_SIZE);
+
+    for (int i = 0; i < 100 && __atomic_load_n(rx.producer, __ATOMIC_ACQUIRE) == 0; i++) {
+        recvfrom(fd, NULL, 0, MSG_DONTWAIT, NULL, NULL);
+        usleep(10 * 1000);
+    }
+    check("frame looped back into rx ring", __atomic_load_n(rx.producer, __ATOMIC_ACQUIRE) == 1);
+    check("one fill entry consumed", __atomic_load_n(fill.consumer, __ATOMIC_ACQUIRE) == 1);
+
+    struct xdp_desc_ *rx_descs = rx.desc;
+    check("rx desc points into a filled frame",
+          rx_descs[0].len == 100 && rx_descs[0].addr < TX_FRAME * FRAME_SIZE);
+    check("rx payload is the sent frame",
+          rx_descs[0].addr < UMEM_SIZE && memcmp(umem + rx_descs[0].addr, frame, 100) == 0);
+
+    struct pollfd pfd = {.fd = fd, .events = POLLIN | POLLOUT};
+    check("poll reports rx and tx", poll(&pfd, 1, 0) == 1 &&
+                                        (pfd.revents & (POLLIN | POLLOUT)) == (POLLIN | POLLOUT));
+    __atomic_store_n(rx.consumer, 1, __ATOMIC_RELEASE);
+    pfd.revents = 0;
+    check("poll has no rx after consuming", poll(&pfd, 1, 0) == 1 && !(pfd.revents & POLLIN));
+
+    /* 无效的发送描述符被跳过并计数 */
+    tx_descs[1].addr = UMEM_SIZE;
+    tx_descs[1].len = 100;
+    __atomic_store_n(tx.producer, 2, __ATOMIC_RELEASE);
+    check("sendto with an invalid desc", sendto(fd, NULL, 0, MSG_DONTWAIT, NULL, 0) == 0);
+
+    struct xdp_stats stats;
+    optlen = sizeof(stats);
+    check("get statistics", getsockopt(fd, SOL_XDP_, XDP_STATISTICS_, &stats, &optlen) == 0);
+    check("no rx drops", stats.rx_dropped == 0);
+    check("invalid tx desc counted", stats.tx_invalid_descs == 1);
+
+    errno = 0;
+    check("cannot change rings after bind",
+          set_int(fd, XDP_TX_RING_, RING_SIZE) == -1 && errno == EBUSY);
+
+    /* 映射持有套接字的引用，全部解除之后套接字才会真正关闭 */
+    munmap(fill.map, fill.map_len);
+    munmap(comp.map, comp.map_len);
+    munmap(rx.map, rx.map_len);
+    munmap(tx.map, tx.map_len);
+    munmap(umem, UMEM_SIZE);
+    close(fd);
+    check("queue is free after close", bind_xdp(other, lo, 0, 0) == 0);
+    close(other);
+}
+
+int main(void) {
+    if (geteuid() != 0) {
+        printf("SKIP: need CAP_NET_RAW\n");
+        return 0;
+    }
+    int fd = socket(AF_XDP_, SOCK_RAW, 0);
+    if (fd < 0) {
+        printf("SKIP: AF_XDP is not supported\n");
+        return 0;
+    }
+    /* Linux的UMEM是用户程序自己的内存，不支持由内核分配 */
+    int ok = reg_umem(fd, 0, FRAME_SIZE) == 0;
+    close(fd);
+    if (!ok) {
+        printf("SKIP: kernel allocated umem is not supported\n");
+        return 0;
+    }
+
+    int lo = ifindex_by_name("lo");
+    check("find lo", lo >= 0);
+    if (lo < 0) {
+        return 1;
+    }
+    test_errors(lo);
+    test_rx_tx(lo);
+
+    if (failures) {
+        printf("%d test(s) failed\n", failures);
+        return 1;
+    }
+    printf("All tests passed\n");
+    return 0;
+}
@@ -0,0 +1,63 @@
+;; 不带参数运行wasmtime_lite时执行的示例程序
+;;
+;; 打印时钟精度、单调时钟的时间和一个随机数，用到了clock_res_get、clock_time_get、random_get和fd_write
+(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_res_get"
+    (func $clock_res_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "clock_time_get"
+    (func $clock_time_get (param i32 i64 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "random_get"
+    (func $random_get (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit"
+    (func $proc_exit (param i32)))
+
+  (memory (export "memory") 1)
+
+  (data (i32.const 0) "Hello from WASI on DragonOS!\n")
+  (data (i32.const 64) "clock resolution (ns): ")
+  (data (i32.const 96) "monotonic time (ns): ")
+  (data (i32.const 128) "random u64: ")
+  (data (i32.const 160) "\n")
+
+  ;; 200: iovec，208: 写入的字节数，216: 系统调用返回的u64，256~288: 数字的十进制字符串
+
+  (func $print (param $ptr i32) (param $len i32)
+    (i32.store (i32.const 200) (local.get $ptr))
+    (i32.store (i32.const 204) (local.get $len))
+    (call $check (call $fd_write (i32.const 1) (i32.const 200) (i32.const 1) (i32.const 208))))
+
+  (func $print_u64 (param $n i64)
+    (local $pos i32)
+    (local.set $pos (i32.const 288))
+    (loop $digits
+      (local.set $pos (i32.sub (local.get $pos) (i32.const 1)))
+      (i64.store8 (local.get $pos)
+        (i64.add (i64.rem_u (local.get $n) (i64.const 10)) (i64.const 48)))
+      (local.set $n (i64.div_u (local.get $n) (i64.const 10)))
+      (br_if $digits (i64.ne (local.get $n) (i64.const 0))))
+    (call $print (local.get $pos) (i32.sub (i32.const 288) (local.get $pos)))
+    (call $print (i32.const 160) (i32.const 1)))
+
+  ;; 出错时以错误码退出
+  (func $check (param $errno i32)
+    (if (local.get $errno)
+      (then (call $proc_exit (local.get $errno)))))
+
+  (func (export "_start")
+    (call $print (i32.const 0) (i32.const 29))
+
+    (call $print (i32.const 64) (i32.const 23))
+    (call $check (call $clock_res_get (i32.const 1) (i32.const 216)))
+    (call $print_u64 (i64.load (i32.const 216)))
+
+    (call $print (i32.const 96) (i32.const 21))
+    (call $check (call $clock_time_get (i32.const 1) (i64.const 1) (i32.const 216)))
+    (call $print_u64 (i64.load (i32.const 216)))
+
+    (call $print (i32.const 128) (i32.const 12))
+    (call $check (call $random_get (i32.const 216) (i32.const 8)))
+    (call $print_u64 (i64.load (i32.const 216)))
+
+    (call $proc_exit (i32.const 0))))
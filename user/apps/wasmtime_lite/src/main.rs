@@ -0,0 +1,93 @@
+//! wasmtime_lite: 一个最小的WASI（preview1）运行时
+//!
+//! 用法：`wasmtime_lite [--dir <目录>]... [<module.wasm|module.wat> [参数]...]`
+//!
+//! 不指定模块时运行内置的示例程序。`--dir`把一个宿主目录预打开给模块，模块只能通过预打开的目录访问文件。
+
+mod wasi;
+
+use std::process::exit;
+
+use wasmi::{Engine, Linker, Module, Store};
+
+use wasi::WasiCtx;
+
+/// 内置的示例程序
+const DEMO: &str = include_str!("demo.wat");
+
+fn usage() -> ! {
+    eprintln!("usage: wasmtime_lite [--dir <dir>]... [<module.wasm|module.wat> [args]...]");
+    exit(2);
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut dirs = Vec::new();
+    while args.peek().map(String::as_str) == Some("--dir") {
+        args.next();
+        dirs.push(args.next().unwrap_or_else(|| usage()));
+    }
+    let guest_args: Vec<String> = args.collect();
+
+    let (name, wasm) = match guest_args.first() {
+        Some(path) => match std::fs::read(path) {
+            Ok(bytes) => (path.clone(), bytes),
+            Err(e) => {
+                eprintln!("wasmtime_lite: failed to read {}: {}", path, e);
+                exit(1);
+            }
+        },
+        None => ("demo.wat".to_string(), DEMO.as_bytes().to_vec()),
+    };
+    // 文本格式的模块先转换为二进制格式
+    let wasm = match wat::parse_bytes(&wasm) {
+        Ok(wasm) => wasm.into_owned(),
+        Err(e) => {
+            eprintln!("wasmtime_lite: {}: {}", name, e);
+            exit(1);
+        }
+    };
+
+    let mut ctx = WasiCtx::new(guest_args, std::env::vars().collect());
+    for dir in dirs {
+        if let Err(e) = ctx.preopen(&dir) {
+            eprintln!("wasmtime_lite: failed to open {}: {}", dir, e);
+            exit(1);
+        }
+    }
+
+    let engine = Engine::default();
+    let module = match Module::new(&engine, &wasm[..]) {
+        Ok(module) => module,
+        Err(e) => {
+            eprintln!("wasmtime_lite: {}: {}", name, e);
+            exit(1);
+        }
+    };
+    let mut store = Store::new(&engine, ctx);
+    let mut linker = <Linker<WasiCtx>>::new(&engine);
+    wasi::add_to_linker(&mut linker).expect("failed to define WASI functions");
+
+    let instance = match linker
+        .instantiate(&mut store, &module)
+        .and_then(|pre| pre.start(&mut store))
+    {
+        Ok(instance) => instance,
+        Err(e) => {
+            eprintln!("wasmtime_lite: failed to instantiate {}: {}", name, e);
+            exit(1);
+        }
+    };
+    let start = match instance.get_typed_func::<(), ()>(&store, "_start") {
+        Ok(start) => start,
+        Err(e) => {
+            eprintln!("wasmtime_lite: {}: no _start function: {}", name, e);
+            exit(1);
+        }
+    };
+    // 模块调用proc_exit时直接退出进程，不会返回到这里
+    if let Err(e) = start.call(&mut store, ()) {
+        eprintln!("wasmtime_lite: {}: {}", name, e);
+        exit(1);
+    }
+}
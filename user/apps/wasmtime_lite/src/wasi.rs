@@ -0,0 +1,767 @@
+//! WASI preview1（`wasi_snapshot_preview1`）的一个子集
+//!
+//! 模块看到的文件描述符就是宿主进程的文件描述符，路径也直接交给宿主的`*at`系统调用解析，
+//! 因此这里不做沙箱隔离，只用于演示和测试系统调用。
+//!
+//! 参考 https://github.com/WebAssembly/WASI/blob/main/legacy/preview1/docs.md
+
+use std::ffi::CString;
+use std::os::fd::IntoRawFd;
+
+use wasmi::{Caller, Extern, Linker};
+
+/// WASI的错误码
+type Errno = i32;
+
+const ERRNO_SUCCESS: Errno = 0;
+const ERRNO_2BIG: Errno = 1;
+const ERRNO_ACCES: Errno = 2;
+const ERRNO_AGAIN: Errno = 6;
+const ERRNO_BADF: Errno = 8;
+const ERRNO_BUSY: Errno = 10;
+const ERRNO_EXIST: Errno = 20;
+const ERRNO_FAULT: Errno = 21;
+const ERRNO_FBIG: Errno = 22;
+const ERRNO_INTR: Errno = 27;
+const ERRNO_INVAL: Errno = 28;
+const ERRNO_IO: Errno = 29;
+const ERRNO_ISDIR: Errno = 31;
+const ERRNO_LOOP: Errno = 32;
+const ERRNO_MFILE: Errno = 33;
+const ERRNO_NAMETOOLONG: Errno = 37;
+const ERRNO_NFILE: Errno = 41;
+const ERRNO_NODEV: Errno = 43;
+const ERRNO_NOENT: Errno = 44;
+const ERRNO_NOMEM: Errno = 48;
+const ERRNO_NOSPC: Errno = 51;
+const ERRNO_NOSYS: Errno = 52;
+const ERRNO_NOTDIR: Errno = 54;
+const ERRNO_NOTEMPTY: Errno = 55;
+const ERRNO_NOTSUP: Errno = 58;
+const ERRNO_NOTTY: Errno = 59;
+const ERRNO_NXIO: Errno = 60;
+const ERRNO_OVERFLOW: Errno = 61;
+const ERRNO_PERM: Errno = 63;
+const ERRNO_PIPE: Errno = 64;
+const ERRNO_RANGE: Errno = 68;
+const ERRNO_ROFS: Errno = 69;
+const ERRNO_SPIPE: Errno = 70;
+const ERRNO_XDEV: Errno = 75;
+
+const FILETYPE_UNKNOWN: u8 = 0;
+const FILETYPE_BLOCK_DEVICE: u8 = 1;
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+const FILETYPE_DIRECTORY: u8 = 3;
+const FILETYPE_REGULAR_FILE: u8 = 4;
+const FILETYPE_SOCKET_STREAM: u8 = 6;
+const FILETYPE_SYMBOLIC_LINK: u8 = 7;
+
+const FDFLAGS_APPEND: u16 = 1 << 0;
+const FDFLAGS_DSYNC: u16 = 1 << 1;
+const FDFLAGS_NONBLOCK: u16 = 1 << 2;
+const FDFLAGS_SYNC: u16 = 1 << 4;
+
+const OFLAGS_CREAT: u16 = 1 << 0;
+const OFLAGS_DIRECTORY: u16 = 1 << 1;
+const OFLAGS_EXCL: u16 = 1 << 2;
+const OFLAGS_TRUNC: u16 = 1 << 3;
+
+const LOOKUPFLAGS_SYMLINK_FOLLOW: u32 = 1 << 0;
+
+const RIGHTS_FD_READ: u64 = 1 << 1;
+const RIGHTS_FD_WRITE: u64 = 1 << 6;
+/// 模块拥有所有权限
+const RIGHTS_ALL: u64 = (1 << 30) - 1;
+
+const ADVICE_SEQUENTIAL: i32 = 1;
+const ADVICE_RANDOM: i32 = 2;
+
+const EVENTTYPE_CLOCK: u8 = 0;
+const SUBCLOCKFLAGS_ABSTIME: u16 = 1 << 0;
+/// `subscription`的大小
+const SUBSCRIPTION_SIZE: u32 = 48;
+/// `event`的大小
+const EVENT_SIZE: u32 = 32;
+
+/// 模块的运行环境
+pub struct WasiCtx {
+    args: Vec<String>,
+    env: Vec<String>,
+    /// 预打开的目录，(文件描述符, 路径)
+    preopens: Vec<(i32, String)>,
+}
+
+impl WasiCtx {
+    pub fn new(args: Vec<String>, env: Vec<(String, String)>) -> Self {
+        Self {
+            args,
+            env: env
+                .into_iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect(),
+            preopens: Vec::new(),
+        }
+    }
+
+    /// 把宿主的目录预打开给模块
+    pub fn preopen(&mut self, dir: &str) -> std::io::Result<()> {
+        let file = std::fs::File::open(dir)?;
+        if !file.metadata()?.is_dir() {
+            return Err(std::io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+        self.preopens.push((file.into_raw_fd(), dir.to_string()));
+        Ok(())
+    }
+}
+
+/// 把宿主的errno转换为WASI的错误码
+fn errno_from_host(errno: i32) -> Errno {
+    match errno {
+        0 => ERRNO_SUCCESS,
+        libc::E2BIG => ERRNO_2BIG,
+        libc::EACCES => ERRNO_ACCES,
+        libc::EAGAIN => ERRNO_AGAIN,
+        libc::EBADF => ERRNO_BADF,
+        libc::EBUSY => ERRNO_BUSY,
+        libc::EEXIST => ERRNO_EXIST,
+        libc::EFAULT => ERRNO_FAULT,
+        libc::EFBIG => ERRNO_FBIG,
+        libc::EINTR => ERRNO_INTR,
+        libc::EINVAL => ERRNO_INVAL,
+        libc::EISDIR => ERRNO_ISDIR,
+        libc::ELOOP => ERRNO_LOOP,
+        libc::EMFILE => ERRNO_MFILE,
+        libc::ENAMETOOLONG => ERRNO_NAMETOOLONG,
+        libc::ENFILE => ERRNO_NFILE,
+        libc::ENODEV => ERRNO_NODEV,
+        libc::ENOENT => ERRNO_NOENT,
+        libc::ENOMEM => ERRNO_NOMEM,
+        libc::ENOSPC => ERRNO_NOSPC,
+        libc::ENOSYS => ERRNO_NOSYS,
+        libc::ENOTDIR => ERRNO_NOTDIR,
+        libc::ENOTEMPTY => ERRNO_NOTEMPTY,
+        libc::EOPNOTSUPP => ERRNO_NOTSUP,
+        libc::ENOTTY => ERRNO_NOTTY,
+        libc::ENXIO => ERRNO_NXIO,
+        libc::EOVERFLOW => ERRNO_OVERFLOW,
+        libc::EPERM => ERRNO_PERM,
+        libc::EPIPE => ERRNO_PIPE,
+        libc::ERANGE => ERRNO_RANGE,
+        libc::EROFS => ERRNO_ROFS,
+        libc::ESPIPE => ERRNO_SPIPE,
+        libc::EXDEV => ERRNO_XDEV,
+        _ => ERRNO_IO,
+    }
+}
+
+/// 最近一次失败的系统调用对应的WASI错误码
+fn last_errno() -> Errno {
+    errno_from_host(std::io::Error::last_os_error().raw_os_error().unwrap_or(0))
+}
+
+/// 系统调用的返回值小于0时返回对应的WASI错误码
+fn check<T: Default + PartialOrd>(ret: T) -> Result<T, Errno> {
+    if ret < T::default() {
+        Err(last_errno())
+    } else {
+        Ok(ret)
+    }
+}
+
+/// 模块内存中`[ptr, ptr + len)`的范围，越界时返回`ERRNO_FAULT`
+fn range(mem: &[u8], ptr: u32, len: u32) -> Result<std::ops::Range<usize>, Errno> {
+    let start = ptr as usize;
+    let end = start.checked_add(len as usize).ok_or(ERRNO_FAULT)?;
+    if end > mem.len() {
+        return Err(ERRNO_FAULT);
+    }
+    Ok(start..end)
+}
+
+fn read_bytes<const N: usize>(mem: &[u8], ptr: u32) -> Result<[u8; N], Errno> {
+    Ok(mem[range(mem, ptr, N as u32)?].try_into().unwrap())
+}
+
+fn read_u16(mem: &[u8], ptr: u32) -> Result<u16, Errno> {
+    read_bytes(mem, ptr).map(u16::from_le_bytes)
+}
+
+fn read_u32(mem: &[u8], ptr: u32) -> Result<u32, Errno> {
+    read_bytes(mem, ptr).map(u32::from_le_bytes)
+}
+
+fn read_u64(mem: &[u8], ptr: u32) -> Result<u64, Errno> {
+    read_bytes(mem, ptr).map(u64::from_le_bytes)
+}
+
+fn write_bytes(mem: &mut [u8], ptr: u32, bytes: &[u8]) -> Result<(), Errno> {
+    let r = range(mem, ptr, bytes.len() as u32)?;
+    mem[r].copy_from_slice(bytes);
+    Ok(())
+}
+
+fn write_u32(mem: &mut [u8], ptr: u32, val: u32) -> Result<(), Errno> {
+    write_bytes(mem, ptr, &val.to_le_bytes())
+}
+
+fn write_u64(mem: &mut [u8], ptr: u32, val: u64) -> Result<(), Errno> {
+    write_bytes(mem, ptr, &val.to_le_bytes())
+}
+
+/// 读取模块传入的路径
+fn read_path(mem: &[u8], ptr: u32, len: u32) -> Result<CString, Errno> {
+    CString::new(&mem[range(mem, ptr, len)?]).map_err(|_| ERRNO_INVAL)
+}
+
+/// 把模块内存中的`iovec`数组转换为宿主的`iovec`数组，指向模块内存
+fn host_iovecs(mem: &mut [u8], iovs: u32, iovs_len: u32) -> Result<Vec<libc::iovec>, Errno> {
+    let mut result = Vec::with_capacity(iovs_len as usize);
+    for i in 0..iovs_len {
+        let iov = i
+            .checked_mul(8)
+            .and_then(|off| iovs.checked_add(off))
+            .ok_or(ERRNO_FAULT)?;
+        let raw: [u8; 8] = read_bytes(mem, iov)?;
+        let buf = u32::from_le_bytes(raw[..4].try_into().unwrap());
+        let buf_len = u32::from_le_bytes(raw[4..].try_into().unwrap());
+        let r = range(mem, buf, buf_len)?;
+        result.push(libc::iovec {
+            iov_base: mem[r].as_mut_ptr().cast(),
+            iov_len: buf_len as usize,
+        });
+    }
+    Ok(result)
+}
+
+/// 在模块的内存上执行`f`，返回WASI错误码
+fn with_memory(
+    caller: &mut Caller<'_, WasiCtx>,
+    f: impl FnOnce(&mut [u8], &mut WasiCtx) -> Result<(), Errno>,
+) -> Errno {
+    let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+        return ERRNO_NOSYS;
+    };
+    let (mem, ctx) = memory.data_and_store_mut(caller);
+    match f(mem, ctx) {
+        Ok(()) => ERRNO_SUCCESS,
+        Err(errno) => errno,
+    }
+}
+
+/// 把一组字符串（参数或环境变量）写入模块内存
+fn write_strings(
+    mem: &mut [u8],
+    strings: &[String],
+    mut ptrs: u32,
+    mut buf: u32,
+) -> Result<(), Errno> {
+    for s in strings {
+        write_u32(mem, ptrs, buf)?;
+        write_bytes(mem, buf, s.as_bytes())?;
+        write_bytes(mem, buf + s.len() as u32, &[0])?;
+        ptrs += 4;
+        buf += s.len() as u32 + 1;
+    }
+    Ok(())
+}
+
+fn write_sizes(mem: &mut [u8], strings: &[String], count: u32, size: u32) -> Result<(), Errno> {
+    write_u32(mem, count, strings.len() as u32)?;
+    write_u32(mem, size, strings.iter().map(|s| s.len() as u32 + 1).sum())
+}
+
+fn filetype_from_mode(mode: libc::mode_t) -> u8 {
+    match mode & libc::S_IFMT {
+        libc::S_IFBLK => FILETYPE_BLOCK_DEVICE,
+        libc::S_IFCHR => FILETYPE_CHARACTER_DEVICE,
+        libc::S_IFDIR => FILETYPE_DIRECTORY,
+        libc::S_IFREG => FILETYPE_REGULAR_FILE,
+        libc::S_IFSOCK => FILETYPE_SOCKET_STREAM,
+        libc::S_IFLNK => FILETYPE_SYMBOLIC_LINK,
+        _ => FILETYPE_UNKNOWN,
+    }
+}
+
+/// 把`stat`写成WASI的`filestat`
+fn write_filestat(mem: &mut [u8], buf: u32, st: &libc::stat) -> Result<(), Errno> {
+    let nsec = |sec: i64, nsec: i64| (sec as u64) * 1_000_000_000 + nsec as u64;
+    let mut filestat = [0u8; 64];
+    filestat[0..8].copy_from_slice(&st.st_dev.to_le_bytes());
+    filestat[8..16].copy_from_slice(&st.st_ino.to_le_bytes());
+    filestat[16] = filetype_from_mode(st.st_mode);
+    filestat[24..32].copy_from_slice(&(st.st_nlink as u64).to_le_bytes());
+    filestat[32..40].copy_from_slice(&(st.st_size as u64).to_le_bytes());
+    filestat[40..48].copy_from_slice(&nsec(st.st_atime, st.st_atime_nsec).to_le_bytes());
+    filestat[48..56].copy_from_slice(&nsec(st.st_mtime, st.st_mtime_nsec).to_le_bytes());
+    filestat[56..64].copy_from_slice(&nsec(st.st_ctime, st.st_ctime_nsec).to_le_bytes());
+    write_bytes(mem, buf, &filestat)
+}
+
+fn fd_fdstat_get(fd: i32, mem: &mut [u8], buf: u32) -> Result<(), Errno> {
+    let mut st: libc::stat = unsafe { core::mem::zeroed() };
+    check(unsafe { libc::fstat(fd, &mut st) })?;
+    let fl = check(unsafe { libc::fcntl(fd, libc::F_GETFL) })?;
+
+    let mut flags = 0;
+    if fl & libc::O_APPEND != 0 {
+        flags |= FDFLAGS_APPEND;
+    }
+    if fl & libc::O_NONBLOCK != 0 {
+        flags |= FDFLAGS_NONBLOCK;
+    }
+    let mut fdstat = [0u8; 24];
+    fdstat[0] = filetype_from_mode(st.st_mode);
+    fdstat[2..4].copy_from_slice(&flags.to_le_bytes());
+    fdstat[8..16].copy_from_slice(&RIGHTS_ALL.to_le_bytes());
+    fdstat[16..24].copy_from_slice(&RIGHTS_ALL.to_le_bytes());
+    write_bytes(mem, buf, &fdstat)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn path_open(
+    mem: &mut [u8],
+    dirfd: i32,
+    dirflags: u32,
+    path: u32,
+    path_len: u32,
+    oflags: u16,
+    rights: u64,
+    fdflags: u16,
+    fd_ptr: u32,
+) -> Result<(), Errno> {
+    let path = read_path(mem, path, path_len)?;
+    let mut flags = libc::O_CLOEXEC;
+    let (read, write) = (rights & RIGHTS_FD_READ != 0, rights & RIGHTS_FD_WRITE != 0);
+    flags |= match (read, write) {
+        (_, false) => libc::O_RDONLY,
+        (false, true) => libc::O_WRONLY,
+        (true, true) => libc::O_RDWR,
+    };
+    if dirflags & LOOKUPFLAGS_SYMLINK_FOLLOW == 0 {
+        flags |= libc::O_NOFOLLOW;
+    }
+    for (wasi, host) in [
+        (OFLAGS_CREAT, libc::O_CREAT),
+        (OFLAGS_DIRECTORY, libc::O_DIRECTORY),
+        (OFLAGS_EXCL, libc::O_EXCL),
+        (OFLAGS_TRUNC, libc::O_TRUNC),
+    ] {
+        if oflags & wasi != 0 {
+            flags |= host;
+        }
+    }
+    for (wasi, host) in [
+        (FDFLAGS_APPEND, libc::O_APPEND),
+        (FDFLAGS_DSYNC, libc::O_DSYNC),
+        (FDFLAGS_NONBLOCK, libc::O_NONBLOCK),
+        (FDFLAGS_SYNC, libc::O_SYNC),
+    ] {
+        if fdflags & wasi != 0 {
+            flags |= host;
+        }
+    }
+
+    let fd = check(unsafe { libc::openat(dirfd, path.as_ptr(), flags, 0o644) })?;
+    write_u32(mem, fd_ptr, fd as u32)
+}
+
+/// 只支持时钟订阅：休眠到最早的时钟到期。有文件描述符订阅时认为它们都已就绪，不休眠
+fn poll_oneoff(mem: &mut [u8], input: u32, output: u32, nsubs: u32) -> Result<u32, Errno> {
+    if nsubs == 0 {
+        return Err(ERRNO_INVAL);
+    }
+    let mut earliest: Option<(u32, u64, u32, u16)> = None;
+    let mut fd_subs = Vec::new();
+    for i in 0..nsubs {
+        let sub = input.wrapping_add(i.wrapping_mul(SUBSCRIPTION_SIZE));
+        range(mem, sub, SUBSCRIPTION_SIZE)?;
+        if mem[(sub + 8) as usize] == EVENTTYPE_CLOCK {
+            let id = read_u32(mem, sub + 16)?;
+            let timeout = read_u64(mem, sub + 24)?;
+            let flags = read_u16(mem, sub + 40)?;
+            if earliest.map_or(true, |(_, t, _, _)| timeout < t) {
+                earliest = Some((sub, timeout, id, flags));
+            }
+        } else {
+            fd_subs.push(sub);
+        }
+    }
+
+    let mut events = fd_subs;
+    if events.is_empty() {
+        let (sub, timeout, id, flags) = earliest.unwrap();
+        let ts = libc::timespec {
+            tv_sec: (timeout / 1_000_000_000) as libc::time_t,
+            tv_nsec: (timeout % 1_000_000_000) as libc::c_long,
+        };
+        let flags = if flags & SUBCLOCKFLAGS_ABSTIME != 0 {
+            libc::TIMER_ABSTIME
+        } else {
+            0
+        };
+        let ret = unsafe {
+            libc::clock_nanosleep(id as libc::clockid_t, flags, &ts, core::ptr::null_mut())
+        };
+        if ret != 0 {
+            return Err(errno_from_host(ret));
+        }
+        events.push(sub);
+    }
+
+    for (i, sub) in events.iter().enumerate() {
+        let mut event = [0u8; EVENT_SIZE as usize];
+        event[0..8].copy_from_slice(&read_u64(mem, *sub)?.to_le_bytes());
+        event[10] = mem[(*sub + 8) as usize];
+        write_bytes(mem, output.wrapping_add(i as u32 * EVENT_SIZE), &event)?;
+    }
+    Ok(events.len() as u32)
+}
+
+/// 把WASI函数加入链接器
+pub fn add_to_linker(linker: &mut Linker<WasiCtx>) -> Result<(), wasmi::Error> {
+    const M: &str = "wasi_snapshot_preview1";
+
+    linker.func_wrap(
+        M,
+        "args_sizes_get",
+        |mut c: Caller<'_, WasiCtx>, argc: i32, size: i32| {
+            with_memory(&mut c, |mem, ctx| {
+                write_sizes(mem, &ctx.args, argc as u32, size as u32)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "args_get",
+        |mut c: Caller<'_, WasiCtx>, argv: i32, buf: i32| {
+            with_memory(&mut c, |mem, ctx| {
+                write_strings(mem, &ctx.args, argv as u32, buf as u32)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "environ_sizes_get",
+        |mut c: Caller<'_, WasiCtx>, count: i32, size: i32| {
+            with_memory(&mut c, |mem, ctx| {
+                write_sizes(mem, &ctx.env, count as u32, size as u32)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "environ_get",
+        |mut c: Caller<'_, WasiCtx>, env: i32, buf: i32| {
+            with_memory(&mut c, |mem, ctx| {
+                write_strings(mem, &ctx.env, env as u32, buf as u32)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        M,
+        "clock_res_get",
+        |mut c: Caller<'_, WasiCtx>, id: i32, res: i32| {
+            with_memory(&mut c, |mem, _| {
+                let mut ts: libc::timespec = unsafe { core::mem::zeroed() };
+                check(unsafe { libc::clock_getres(id as libc::clockid_t, &mut ts) })?;
+                let ns = ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64;
+                write_u64(mem, res as u32, ns)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "clock_time_get",
+        |mut c: Caller<'_, WasiCtx>, id: i32, _precision: i64, time: i32| {
+            with_memory(&mut c, |mem, _| {
+                let mut ts: libc::timespec = unsafe { core::mem::zeroed() };
+                check(unsafe { libc::clock_gettime(id as libc::clockid_t, &mut ts) })?;
+                let ns = ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64;
+                write_u64(mem, time as u32, ns)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "random_get",
+        |mut c: Caller<'_, WasiCtx>, buf: i32, len: i32| {
+            with_memory(&mut c, |mem, _| {
+                let r = range(mem, buf as u32, len as u32)?;
+                let mut buf = &mut mem[r];
+                while !buf.is_empty() {
+                    let n = check(unsafe {
+                        libc::syscall(libc::SYS_getrandom, buf.as_mut_ptr(), buf.len(), 0)
+                    })?;
+                    buf = &mut buf[n as usize..];
+                }
+                Ok(())
+            })
+        },
+    )?;
+    linker.func_wrap(M, "sched_yield", || -> i32 {
+        unsafe { libc::sched_yield() };
+        ERRNO_SUCCESS
+    })?;
+    linker.func_wrap(
+        M,
+        "poll_oneoff",
+        |mut c: Caller<'_, WasiCtx>, input: i32, output: i32, nsubs: i32, nevents: i32| {
+            with_memory(&mut c, |mem, _| {
+                let n = poll_oneoff(mem, input as u32, output as u32, nsubs as u32)?;
+                write_u32(mem, nevents as u32, n)
+            })
+        },
+    )?;
+    linker.func_wrap(M, "proc_exit", |code: i32| {
+        std::process::exit(code);
+    })?;
+
+    linker.func_wrap(M, "fd_close", |mut c: Caller<'_, WasiCtx>, fd: i32| {
+        with_memory(&mut c, |_, ctx| {
+            check(unsafe { libc::close(fd) })?;
+            ctx.preopens.retain(|(pfd, _)| *pfd != fd);
+            Ok(())
+        })
+    })?;
+    linker.func_wrap(M, "fd_sync", |fd: i32| {
+        match check(unsafe { libc::fsync(fd) }) {
+            Ok(_) => ERRNO_SUCCESS,
+            Err(errno) => errno,
+        }
+    })?;
+    linker.func_wrap(M, "fd_datasync", |fd: i32| {
+        match check(unsafe { libc::fdatasync(fd) }) {
+            Ok(_) => ERRNO_SUCCESS,
+            Err(errno) => errno,
+        }
+    })?;
+    linker.func_wrap(
+        M,
+        "fd_advise",
+        |fd: i32, offset: i64, len: i64, advice: i32| {
+            // WASI与Linux的SEQUENTIAL和RANDOM的取值相反
+            let advice = match advice {
+                ADVICE_SEQUENTIAL => libc::POSIX_FADV_SEQUENTIAL,
+                ADVICE_RANDOM => libc::POSIX_FADV_RANDOM,
+                advice => advice,
+            };
+            errno_from_host(unsafe { libc::posix_fadvise(fd, offset, len, advice) })
+        },
+    )?;
+    linker.func_wrap(M, "fd_allocate", |_fd: i32, _offset: i64, _len: i64| {
+        ERRNO_NOTSUP
+    })?;
+    linker.func_wrap(
+        M,
+        "fd_fdstat_get",
+        |mut c: Caller<'_, WasiCtx>, fd: i32, buf: i32| {
+            with_memory(&mut c, |mem, _| fd_fdstat_get(fd, mem, buf as u32))
+        },
+    )?;
+    linker.func_wrap(M, "fd_fdstat_set_flags", |fd: i32, flags: i32| {
+        let mut fl = 0;
+        if flags as u16 & FDFLAGS_APPEND != 0 {
+            fl |= libc::O_APPEND;
+        }
+        if flags as u16 & FDFLAGS_NONBLOCK != 0 {
+            fl |= libc::O_NONBLOCK;
+        }
+        match check(unsafe { libc::fcntl(fd, libc::F_SETFL, fl) }) {
+            Ok(_) => ERRNO_SUCCESS,
+            Err(errno) => errno,
+        }
+    })?;
+    linker.func_wrap(
+        M,
+        "fd_filestat_get",
+        |mut c: Caller<'_, WasiCtx>, fd: i32, buf: i32| {
+            with_memory(&mut c, |mem, _| {
+                let mut st: libc::stat = unsafe { core::mem::zeroed() };
+                check(unsafe { libc::fstat(fd, &mut st) })?;
+                write_filestat(mem, buf as u32, &st)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "fd_prestat_get",
+        |mut c: Caller<'_, WasiCtx>, fd: i32, buf: i32| {
+            with_memory(&mut c, |mem, ctx| {
+                let (_, path) = ctx
+                    .preopens
+                    .iter()
+                    .find(|(pfd, _)| *pfd == fd)
+                    .ok_or(ERRNO_BADF)?;
+                // tag为0表示目录
+                write_u32(mem, buf as u32, 0)?;
+                write_u32(mem, buf as u32 + 4, path.len() as u32)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "fd_prestat_dir_name",
+        |mut c: Caller<'_, WasiCtx>, fd: i32, buf: i32, len: i32| {
+            with_memory(&mut c, |mem, ctx| {
+                let (_, path) = ctx
+                    .preopens
+                    .iter()
+                    .find(|(pfd, _)| *pfd == fd)
+                    .ok_or(ERRNO_BADF)?;
+                let n = path.len().min(len as usize);
+                write_bytes(mem, buf as u32, &path.as_bytes()[..n])
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "fd_read",
+        |mut c: Caller<'_, WasiCtx>, fd: i32, iovs: i32, iovs_len: i32, nread: i32| {
+            with_memory(&mut c, |mem, _| {
+                let iov = host_iovecs(mem, iovs as u32, iovs_len as u32)?;
+                let n = check(unsafe { libc::readv(fd, iov.as_ptr(), iov.len() as i32) })?;
+                write_u32(mem, nread as u32, n as u32)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "fd_write",
+        |mut c: Caller<'_, WasiCtx>, fd: i32, iovs: i32, iovs_len: i32, nwritten: i32| {
+            with_memory(&mut c, |mem, _| {
+                let iov = host_iovecs(mem, iovs as u32, iovs_len as u32)?;
+                let n = check(unsafe { libc::writev(fd, iov.as_ptr(), iov.len() as i32) })?;
+                write_u32(mem, nwritten as u32, n as u32)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "fd_pread",
+        |mut c: Caller<'_, WasiCtx>, fd: i32, iovs: i32, iovs_len: i32, offset: i64, nread: i32| {
+            with_memory(&mut c, |mem, _| {
+                let iov = host_iovecs(mem, iovs as u32, iovs_len as u32)?;
+                let n = check(unsafe { libc::preadv(fd, iov.as_ptr(), iov.len() as i32, offset) })?;
+                write_u32(mem, nread as u32, n as u32)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "fd_pwrite",
+        |mut c: Caller<'_, WasiCtx>,
+         fd: i32,
+         iovs: i32,
+         iovs_len: i32,
+         offset: i64,
+         nwritten: i32| {
+            with_memory(&mut c, |mem, _| {
+                let iov = host_iovecs(mem, iovs as u32, iovs_len as u32)?;
+                let n =
+                    check(unsafe { libc::pwritev(fd, iov.as_ptr(), iov.len() as i32, offset) })?;
+                write_u32(mem, nwritten as u32, n as u32)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "fd_seek",
+        |mut c: Caller<'_, WasiCtx>, fd: i32, offset: i64, whence: i32, newoffset: i32| {
+            with_memory(&mut c, |mem, _| {
+                // WASI与Linux的whence取值相同
+                let pos = check(unsafe { libc::lseek(fd, offset, whence) })?;
+                write_u64(mem, newoffset as u32, pos as u64)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "fd_tell",
+        |mut c: Caller<'_, WasiCtx>, fd: i32, offset: i32| {
+            with_memory(&mut c, |mem, _| {
+                let pos = check(unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) })?;
+                write_u64(mem, offset as u32, pos as u64)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        M,
+        "path_open",
+        |mut c: Caller<'_, WasiCtx>,
+         dirfd: i32,
+         dirflags: i32,
+         path: i32,
+         path_len: i32,
+         oflags: i32,
+         rights_base: i64,
+         _rights_inheriting: i64,
+         fdflags: i32,
+         fd: i32| {
+            with_memory(&mut c, |mem, _| {
+                path_open(
+                    mem,
+                    dirfd,
+                    dirflags as u32,
+                    path as u32,
+                    path_len as u32,
+                    oflags as u16,
+                    rights_base as u64,
+                    fdflags as u16,
+                    fd as u32,
+                )
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "path_filestat_get",
+        |mut c: Caller<'_, WasiCtx>, dirfd: i32, flags: i32, path: i32, path_len: i32, buf: i32| {
+            with_memory(&mut c, |mem, _| {
+                let path = read_path(mem, path as u32, path_len as u32)?;
+                let at_flags = if flags as u32 & LOOKUPFLAGS_SYMLINK_FOLLOW == 0 {
+                    libc::AT_SYMLINK_NOFOLLOW
+                } else {
+                    0
+                };
+                let mut st: libc::stat = unsafe { core::mem::zeroed() };
+                check(unsafe { libc::fstatat(dirfd, path.as_ptr(), &mut st, at_flags) })?;
+                write_filestat(mem, buf as u32, &st)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "path_create_directory",
+        |mut c: Caller<'_, WasiCtx>, dirfd: i32, path: i32, path_len: i32| {
+            with_memory(&mut c, |mem, _| {
+                let path = read_path(mem, path as u32, path_len as u32)?;
+                check(unsafe { libc::mkdirat(dirfd, path.as_ptr(), 0o755) }).map(|_| ())
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "path_unlink_file",
+        |mut c: Caller<'_, WasiCtx>, dirfd: i32, path: i32, path_len: i32| {
+            with_memory(&mut c, |mem, _| {
+                let path = read_path(mem, path as u32, path_len as u32)?;
+                check(unsafe { libc::unlinkat(dirfd, path.as_ptr(), 0) }).map(|_| ())
+            })
+        },
+    )?;
+    linker.func_wrap(
+        M,
+        "path_remove_directory",
+        |mut c: Caller<'_, WasiCtx>, dirfd: i32, path: i32, path_len: i32| {
+            with_memory(&mut c, |mem, _| {
+                let path = read_path(mem, path as u32, path_len as u32)?;
+                check(unsafe { libc::unlinkat(dirfd, path.as_ptr(), libc::AT_REMOVEDIR) })
+                    .map(|_| ())
+            })
+        },
+    )?;
+
+    Ok(())
+}